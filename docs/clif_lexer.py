@@ -33,6 +33,8 @@ class CraneliftLexer(RegexLexer):
             (r';.*?$', Comment.Single),
             # Strings are prefixed by % or # with hex.
             (r'%\w+|#[0-9a-fA-F]*', String),
+            # Quoted symbol names.
+            (r'"(\\.|[^"\\\n])*"', String),
             # Numbers.
             (r'[-+]?0[xX][0-9a-fA-F_]+', Number.Hex),
             (r'[-+]?0[xX][0-9a-fA-F_]*\.[0-9a-fA-F_]*([pP]\d+)?', Number.Hex),
@@ -0,0 +1,10 @@
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate cranelift_codegen;
+use cranelift_codegen::ir::Function;
+
+fuzz_target!(|data: &[u8]| if let Ok(func) = Function::read_binary(data) {
+    // Successfully decoded functions must be printable.
+    let _ = func.to_string();
+});
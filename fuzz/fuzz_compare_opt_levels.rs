@@ -0,0 +1,164 @@
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate cranelift_codegen;
+extern crate cranelift_fuzzgen;
+extern crate cranelift_interpreter;
+extern crate cranelift_module;
+extern crate cranelift_native;
+extern crate cranelift_simplejit;
+use cranelift_codegen::cursor::{Cursor, FuncCursor};
+use cranelift_codegen::ir::condcodes::IntCC;
+use cranelift_codegen::ir::{
+    types, AbiParam, ExternalName, Function, InstBuilder, MemFlags, Signature, Type,
+};
+use cranelift_codegen::settings::{self, Configurable};
+use cranelift_codegen::Context;
+use cranelift_fuzzgen::generate_function;
+use cranelift_interpreter::{DataValue, Interpreter, Outcome};
+use cranelift_module::{FuncId, Linkage, Module};
+use cranelift_simplejit::{SimpleJITBackend, SimpleJITBuilder};
+use std::mem;
+
+/// The maximum number of instructions to interpret.
+const STEP_LIMIT: u64 = 1_000_000;
+
+fuzz_target!(|data: &[u8]| {
+    let func = generate_function(data);
+    let args = arguments(&func.signature, data);
+
+    // The interpreter runs the function without optimizing it, so it provides the expected
+    // results. Generated functions don't trap and always terminate, but they may run for too long.
+    let mut interpreter = Interpreter::new();
+    interpreter.set_step_limit(Some(STEP_LIMIT));
+    let expected = match interpreter.call(&func, &args) {
+        Ok(Outcome::Return(values)) => values,
+        Ok(Outcome::Trap(_)) | Err(_) => return,
+    };
+
+    for opt_level in &["fastest", "best"] {
+        let actual = run_native(&func, opt_level, &args);
+        assert!(
+            same(&expected, &actual),
+            "opt_level={}: {:?}: interpreter returned {:?}, native code returned {:?}\n{}",
+            opt_level,
+            args,
+            expected,
+            actual,
+            func.display(None)
+        );
+    }
+});
+
+/// Get the arguments for a function with signature `sig` from the last bytes of `data`.
+fn arguments(sig: &Signature, data: &[u8]) -> Vec<DataValue> {
+    let mut bytes = data.iter().rev();
+    sig.params
+        .iter()
+        .map(|param| {
+            let bits = (0..8).fold(0, |bits, i| {
+                bits | (u64::from(*bytes.next().unwrap_or(&0)) << (i * 8))
+            });
+            DataValue::from_bits(param.value_type, bits).unwrap()
+        }).collect()
+}
+
+/// Is `actual` the same as `expected`, considering all NaNs to be equal?
+fn same(expected: &[DataValue], actual: &[DataValue]) -> bool {
+    expected.len() == actual.len() && expected
+        .iter()
+        .zip(actual)
+        .all(|(e, a)| e == a || (e.is_nan() && a.is_nan()))
+}
+
+/// Compile `func` for the host with `opt_level`, and call it with `args`.
+fn run_native(func: &Function, opt_level: &str, args: &[DataValue]) -> Vec<DataValue> {
+    let (mut flag_builder, isa_builder) = cranelift_native::builders().unwrap();
+    flag_builder.enable("enable_verifier").unwrap();
+    flag_builder.set("opt_level", opt_level).unwrap();
+    // Functions with large stack frames would call a probestack function, which simplejit can't
+    // resolve.
+    flag_builder.set("probestack_enabled", "false").unwrap();
+    let isa = isa_builder.finish(settings::Flags::new(flag_builder));
+    let mut module: Module<SimpleJITBackend> = Module::new(SimpleJITBuilder::with_isa(isa));
+
+    let func_id = module
+        .declare_function("func", Linkage::Local, &func.signature)
+        .unwrap();
+    let mut ctx = Context::for_function(func.clone());
+    module.define_function(func_id, &mut ctx).unwrap();
+
+    let mut ctx = module.make_context();
+    ctx.func = make_trampoline(&module, func_id, &func.signature);
+    let trampoline_id = module
+        .declare_function("trampoline", Linkage::Local, &ctx.func.signature)
+        .unwrap();
+    module.define_function(trampoline_id, &mut ctx).unwrap();
+    module.finalize_definitions();
+
+    let code = module.get_finalized_function(trampoline_id);
+    let trampoline: extern "C" fn(*mut u64) = unsafe { mem::transmute(code) };
+    let returns: Vec<Type> = func
+        .signature
+        .returns
+        .iter()
+        .map(|p| p.value_type)
+        .collect();
+
+    let mut slots: Vec<u64> = args.iter().map(|arg| arg.to_bits()).collect();
+    slots.resize(args.len().max(returns.len()).max(1), 0);
+    trampoline(slots.as_mut_ptr());
+    returns
+        .iter()
+        .zip(slots)
+        .map(|(&ty, slot)| {
+            let mask = !0 >> (64 - 8 * ty.bytes());
+            DataValue::from_bits(ty, slot & mask).unwrap()
+        }).collect()
+}
+
+/// Build a trampoline with the signature `(values)` that calls `callee` with arguments loaded
+/// from the array of 8-byte slots at `values`, and stores its results back into the slots.
+fn make_trampoline(module: &Module<SimpleJITBackend>, callee: FuncId, sig: &Signature) -> Function {
+    let pointer_type = module.pointer_type();
+    let mut tsig = module.make_signature();
+    tsig.params.push(AbiParam::new(pointer_type));
+    let mut func = Function::with_name_signature(ExternalName::testcase("trampoline"), tsig);
+    let callee = module.declare_func_in_func(callee, &mut func);
+
+    let ebb = func.dfg.make_ebb();
+    let values = func.dfg.append_ebb_param(ebb, pointer_type);
+    let mut pos = FuncCursor::new(&mut func);
+    pos.insert_ebb(ebb);
+
+    let mut flags = MemFlags::new();
+    flags.set_notrap();
+    flags.set_aligned();
+
+    let args: Vec<_> = sig
+        .params
+        .iter()
+        .enumerate()
+        .map(|(i, param)| {
+            let offset = (i * 8) as i32;
+            if param.value_type == types::B1 {
+                let byte = pos.ins().uload8(types::I32, flags, values, offset);
+                pos.ins().icmp_imm(IntCC::NotEqual, byte, 0)
+            } else {
+                pos.ins().load(param.value_type, flags, values, offset)
+            }
+        }).collect();
+    let call = pos.ins().call(callee, &args);
+    let results = pos.func.dfg.inst_results(call).to_vec();
+    for (i, &result) in results.iter().enumerate() {
+        let offset = (i * 8) as i32;
+        if pos.func.dfg.value_type(result) == types::B1 {
+            let byte = pos.ins().bint(types::I32, result);
+            pos.ins().istore8(flags, byte, values, offset);
+        } else {
+            pos.ins().store(flags, result, values, offset);
+        }
+    }
+    pos.ins().return_(&[]);
+    func
+}
@@ -0,0 +1,34 @@
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate cranelift_codegen;
+extern crate cranelift_fuzzgen;
+#[macro_use]
+extern crate target_lexicon;
+use cranelift_codegen::settings::{self, Configurable};
+use cranelift_codegen::{isa, Context};
+use cranelift_fuzzgen::generate_function;
+use std::str::FromStr;
+
+fuzz_target!(|data: &[u8]| {
+    let func = generate_function(data);
+
+    // Compile the function at every optimization level, verifying the IR after each pass.
+    for opt_level in &["fastest", "default", "best"] {
+        let mut flag_builder = settings::builder();
+        flag_builder.enable("enable_verifier").unwrap();
+        flag_builder.set("opt_level", opt_level).unwrap();
+        let isa = isa::lookup(triple!("x86_64"))
+            .unwrap()
+            .finish(settings::Flags::new(flag_builder));
+
+        let mut ctx = Context::for_function(func.clone());
+        if let Err(err) = ctx.compile(&*isa) {
+            panic!(
+                "opt_level={}: {}",
+                opt_level,
+                cranelift_codegen::print_errors::pretty_error(&ctx.func, Some(&*isa), err)
+            );
+        }
+    }
+});
@@ -0,0 +1,805 @@
+/* Generated by cbindgen from lib/c-api. Don't edit this file by hand. */
+
+#ifndef CRANELIFT_H
+#define CRANELIFT_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * A boolean, as produced by comparisons.
+ */
+#define CRANELIFT_TYPE_B1 1
+
+/**
+ * An 8-bit integer.
+ */
+#define CRANELIFT_TYPE_I8 2
+
+/**
+ * A 16-bit integer.
+ */
+#define CRANELIFT_TYPE_I16 3
+
+/**
+ * A 32-bit integer.
+ */
+#define CRANELIFT_TYPE_I32 4
+
+/**
+ * A 64-bit integer.
+ */
+#define CRANELIFT_TYPE_I64 5
+
+/**
+ * A 32-bit floating point number.
+ */
+#define CRANELIFT_TYPE_F32 6
+
+/**
+ * A 64-bit floating point number.
+ */
+#define CRANELIFT_TYPE_F64 7
+
+/**
+ * Defined outside of the module.
+ */
+#define CRANELIFT_LINKAGE_IMPORT 1
+
+/**
+ * Defined inside the module, but not visible outside it.
+ */
+#define CRANELIFT_LINKAGE_LOCAL 2
+
+/**
+ * Defined inside the module, visible outside it, and may be preempted.
+ */
+#define CRANELIFT_LINKAGE_PREEMPTIBLE 3
+
+/**
+ * Defined inside the module, and visible outside it.
+ */
+#define CRANELIFT_LINKAGE_EXPORT 4
+
+/**
+ * Wrapping integer addition: `iadd`.
+ */
+#define CRANELIFT_BINARY_IADD 1
+
+/**
+ * Wrapping integer subtraction: `isub`.
+ */
+#define CRANELIFT_BINARY_ISUB 2
+
+/**
+ * Wrapping integer multiplication: `imul`.
+ */
+#define CRANELIFT_BINARY_IMUL 3
+
+/**
+ * Unsigned integer division: `udiv`.
+ */
+#define CRANELIFT_BINARY_UDIV 4
+
+/**
+ * Signed integer division: `sdiv`.
+ */
+#define CRANELIFT_BINARY_SDIV 5
+
+/**
+ * Unsigned integer remainder: `urem`.
+ */
+#define CRANELIFT_BINARY_UREM 6
+
+/**
+ * Signed integer remainder: `srem`.
+ */
+#define CRANELIFT_BINARY_SREM 7
+
+/**
+ * Bitwise and: `band`.
+ */
+#define CRANELIFT_BINARY_BAND 8
+
+/**
+ * Bitwise or: `bor`.
+ */
+#define CRANELIFT_BINARY_BOR 9
+
+/**
+ * Bitwise exclusive or: `bxor`.
+ */
+#define CRANELIFT_BINARY_BXOR 10
+
+/**
+ * Shift left: `ishl`.
+ */
+#define CRANELIFT_BINARY_ISHL 11
+
+/**
+ * Logical shift right: `ushr`.
+ */
+#define CRANELIFT_BINARY_USHR 12
+
+/**
+ * Arithmetic shift right: `sshr`.
+ */
+#define CRANELIFT_BINARY_SSHR 13
+
+/**
+ * Floating point addition: `fadd`.
+ */
+#define CRANELIFT_BINARY_FADD 14
+
+/**
+ * Floating point subtraction: `fsub`.
+ */
+#define CRANELIFT_BINARY_FSUB 15
+
+/**
+ * Floating point multiplication: `fmul`.
+ */
+#define CRANELIFT_BINARY_FMUL 16
+
+/**
+ * Floating point division: `fdiv`.
+ */
+#define CRANELIFT_BINARY_FDIV 17
+
+/**
+ * Zero-extend an integer to a wider type: `uextend`.
+ */
+#define CRANELIFT_CONVERT_UEXTEND 1
+
+/**
+ * Sign-extend an integer to a wider type: `sextend`.
+ */
+#define CRANELIFT_CONVERT_SEXTEND 2
+
+/**
+ * Truncate an integer to a narrower type: `ireduce`.
+ */
+#define CRANELIFT_CONVERT_IREDUCE 3
+
+/**
+ * Convert a boolean to the integer 0 or 1: `bint`.
+ */
+#define CRANELIFT_CONVERT_BINT 4
+
+/**
+ * Convert `f32` to `f64`: `fpromote`.
+ */
+#define CRANELIFT_CONVERT_FPROMOTE 5
+
+/**
+ * Convert `f64` to `f32`: `fdemote`.
+ */
+#define CRANELIFT_CONVERT_FDEMOTE 6
+
+/**
+ * Convert a signed integer to floating point: `fcvt_from_sint`.
+ */
+#define CRANELIFT_CONVERT_FCVT_FROM_SINT 7
+
+/**
+ * Convert an unsigned integer to floating point: `fcvt_from_uint`.
+ */
+#define CRANELIFT_CONVERT_FCVT_FROM_UINT 8
+
+/**
+ * Convert floating point to a signed integer, trapping if it doesn't fit: `fcvt_to_sint`.
+ */
+#define CRANELIFT_CONVERT_FCVT_TO_SINT 9
+
+/**
+ * Convert floating point to an unsigned integer, trapping if it doesn't fit: `fcvt_to_uint`.
+ */
+#define CRANELIFT_CONVERT_FCVT_TO_UINT 10
+
+/**
+ * `==`.
+ */
+#define CRANELIFT_INTCC_EQUAL 1
+
+/**
+ * `!=`.
+ */
+#define CRANELIFT_INTCC_NOT_EQUAL 2
+
+/**
+ * Signed `<`.
+ */
+#define CRANELIFT_INTCC_SIGNED_LESS_THAN 3
+
+/**
+ * Signed `>=`.
+ */
+#define CRANELIFT_INTCC_SIGNED_GREATER_THAN_OR_EQUAL 4
+
+/**
+ * Signed `>`.
+ */
+#define CRANELIFT_INTCC_SIGNED_GREATER_THAN 5
+
+/**
+ * Signed `<=`.
+ */
+#define CRANELIFT_INTCC_SIGNED_LESS_THAN_OR_EQUAL 6
+
+/**
+ * Unsigned `<`.
+ */
+#define CRANELIFT_INTCC_UNSIGNED_LESS_THAN 7
+
+/**
+ * Unsigned `>=`.
+ */
+#define CRANELIFT_INTCC_UNSIGNED_GREATER_THAN_OR_EQUAL 8
+
+/**
+ * Unsigned `>`.
+ */
+#define CRANELIFT_INTCC_UNSIGNED_GREATER_THAN 9
+
+/**
+ * Unsigned `<=`.
+ */
+#define CRANELIFT_INTCC_UNSIGNED_LESS_THAN_OR_EQUAL 10
+
+/**
+ * `==`.
+ */
+#define CRANELIFT_FLOATCC_EQUAL 1
+
+/**
+ * `!=`.
+ */
+#define CRANELIFT_FLOATCC_NOT_EQUAL 2
+
+/**
+ * `<`.
+ */
+#define CRANELIFT_FLOATCC_LESS_THAN 3
+
+/**
+ * `<=`.
+ */
+#define CRANELIFT_FLOATCC_LESS_THAN_OR_EQUAL 4
+
+/**
+ * `>`.
+ */
+#define CRANELIFT_FLOATCC_GREATER_THAN 5
+
+/**
+ * `>=`.
+ */
+#define CRANELIFT_FLOATCC_GREATER_THAN_OR_EQUAL 6
+
+/**
+ * The result of a call to a fallible function.
+ *
+ * When a function returns anything but `CRANELIFT_STATUS_OK`, a message describing the failure
+ * is available from `cranelift_last_error`, and none of its output parameters have been written.
+ */
+typedef enum {
+  /**
+   * The call succeeded.
+   */
+  CRANELIFT_STATUS_OK = 0,
+  /**
+   * A required pointer argument was null.
+   */
+  CRANELIFT_STATUS_NULL_POINTER,
+  /**
+   * An argument was invalid, for example a string that isn't UTF-8 or an unknown entity.
+   */
+  CRANELIFT_STATUS_INVALID_ARGUMENT,
+  /**
+   * The target isn't supported by this build of Cranelift.
+   */
+  CRANELIFT_STATUS_UNSUPPORTED_TARGET,
+  /**
+   * A setting name or value was rejected.
+   */
+  CRANELIFT_STATUS_INVALID_SETTING,
+  /**
+   * A declaration or definition was rejected by the module.
+   */
+  CRANELIFT_STATUS_MODULE,
+  /**
+   * Compiling a function failed, usually because it didn't pass the verifier.
+   */
+  CRANELIFT_STATUS_COMPILATION,
+  /**
+   * The operation isn't supported by this kind of module.
+   */
+  CRANELIFT_STATUS_UNSUPPORTED,
+  /**
+   * Writing an output file failed.
+   */
+  CRANELIFT_STATUS_IO,
+  /**
+   * Cranelift panicked. The handles passed to the call must not be used again, except to free
+   * them.
+   */
+  CRANELIFT_STATUS_PANIC,
+} CraneliftStatus;
+
+/**
+ * A target ISA with its settings, used to create a module.
+ */
+typedef struct CraneliftIsa CraneliftIsa;
+
+/**
+ * A module of functions and data objects.
+ */
+typedef struct CraneliftModule CraneliftModule;
+
+/**
+ * A function being built.
+ */
+typedef struct CraneliftBuilder CraneliftBuilder;
+
+/**
+ * A reference to an SSA value in the function being built.
+ */
+typedef uint32_t CraneliftValue;
+
+/**
+ * A reference to an extended basic block in the function being built.
+ */
+typedef uint32_t CraneliftEbb;
+
+/**
+ * A variable of the function being built, chosen by the frontend.
+ */
+typedef uint32_t CraneliftVariable;
+
+/**
+ * A function declared in a module.
+ */
+typedef uint32_t CraneliftFuncId;
+
+/**
+ * A data object declared in a module.
+ */
+typedef uint32_t CraneliftDataId;
+
+/**
+ * The type of a value, one of the `CRANELIFT_TYPE_*` constants.
+ */
+typedef uint32_t CraneliftType;
+
+/**
+ * Where a function or data object is defined and who can see it, one of the
+ * `CRANELIFT_LINKAGE_*` constants.
+ */
+typedef uint32_t CraneliftLinkage;
+
+/**
+ * An instruction taking two operands of the same type and producing a result of that type, one of
+ * the `CRANELIFT_BINARY_*` constants.
+ */
+typedef uint32_t CraneliftBinaryOp;
+
+/**
+ * An instruction converting its operand to another type, one of the `CRANELIFT_CONVERT_*`
+ * constants.
+ */
+typedef uint32_t CraneliftConvertOp;
+
+/**
+ * An integer comparison, one of the `CRANELIFT_INTCC_*` constants.
+ */
+typedef uint32_t CraneliftIntCC;
+
+/**
+ * A floating point comparison, one of the `CRANELIFT_FLOATCC_*` constants.
+ *
+ * These are all false when either operand is NaN, except `CRANELIFT_FLOATCC_NOT_EQUAL`.
+ */
+typedef uint32_t CraneliftFloatCC;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Get the message describing the last failed call on the current thread.
+ *
+ * Returns null if no call has failed on this thread. The string is owned by the library and is
+ * valid until the next failed call on the same thread.
+ */
+const char *cranelift_last_error(void);
+
+/**
+ * Create a target ISA for `triple`, configured by the `flags_len` strings in `flags`.
+ *
+ * A null `triple` selects the host, with the features of the host CPU enabled. This is usually
+ * what's wanted for simplejit modules, since it also selects the host's calling convention.
+ *
+ * Each flag is either `name=value` to set a setting, or just `name` to enable a boolean setting
+ * or apply a preset. Both shared settings like `opt_level` and settings specific to the ISA
+ * are accepted.
+ *
+ * On success, the new ISA is written to `isa_out` and must be passed to a function creating a
+ * module, or freed with `cranelift_isa_free`.
+ */
+CraneliftStatus cranelift_isa_new(const char *triple,
+                                  const char *const *flags,
+                                  uintptr_t flags_len,
+                                  CraneliftIsa **isa_out);
+
+/**
+ * Free an ISA that hasn't been passed to a function creating a module.
+ *
+ * Does nothing if `isa` is null.
+ */
+void cranelift_isa_free(CraneliftIsa *isa);
+
+/**
+ * Create a module that compiles functions to memory, so they can be called by the host.
+ *
+ * Takes ownership of `isa`, even if this fails. The target must be the host; use a null triple to
+ * create it.
+ *
+ * On success, the new module is written to `module_out` and must be freed with
+ * `cranelift_module_free`.
+ */
+CraneliftStatus cranelift_module_new_simplejit(CraneliftIsa *isa, CraneliftModule **module_out);
+
+/**
+ * Create a module that compiles functions to an object file named `name`, to be written with
+ * `cranelift_module_write_object`.
+ *
+ * Takes ownership of `isa`, even if this fails. The ISA must have the `is_pic` setting enabled.
+ *
+ * On success, the new module is written to `module_out` and must be freed with
+ * `cranelift_module_free`, or by writing the object file.
+ */
+CraneliftStatus cranelift_module_new_faerie(CraneliftIsa *isa,
+                                            const char *name,
+                                            CraneliftModule **module_out);
+
+/**
+ * Free a module.
+ *
+ * Code and data compiled by a simplejit module stay valid after it's freed. Does nothing if
+ * `module` is null.
+ */
+void cranelift_module_free(CraneliftModule *module);
+
+/**
+ * Declare a function called `name`, taking parameters of the `params_len` types in `params` and
+ * returning the `returns_len` types in `returns`.
+ *
+ * The function uses the default calling convention of the module's ISA. Declaring a name again
+ * returns the same function, as long as the signature is the same.
+ *
+ * On success, the function's identifier is written to `func_out`.
+ */
+CraneliftStatus cranelift_module_declare_function(CraneliftModule *module,
+                                                  const char *name,
+                                                  CraneliftLinkage linkage,
+                                                  const CraneliftType *params,
+                                                  uintptr_t params_len,
+                                                  const CraneliftType *returns,
+                                                  uintptr_t returns_len,
+                                                  CraneliftFuncId *func_out);
+
+/**
+ * Declare a data object called `name`, which is writable if `writable` is true.
+ *
+ * On success, the data object's identifier is written to `data_out`.
+ */
+CraneliftStatus cranelift_module_declare_data(CraneliftModule *module,
+                                              const char *name,
+                                              CraneliftLinkage linkage,
+                                              bool writable,
+                                              CraneliftDataId *data_out);
+
+/**
+ * Define the data object `data` with the `size` bytes at `contents`, or with `size` zero bytes if
+ * `contents` is null.
+ */
+CraneliftStatus cranelift_module_define_data(CraneliftModule *module,
+                                             CraneliftDataId data,
+                                             const uint8_t *contents,
+                                             uintptr_t size);
+
+/**
+ * Compile the function built by `builder`, and define it in `module`.
+ *
+ * Takes ownership of `builder`, even if this fails. When the function doesn't pass the verifier,
+ * the error message lists the verifier errors in the function.
+ */
+CraneliftStatus cranelift_module_define_function(CraneliftModule *module,
+                                                 CraneliftBuilder *builder);
+
+/**
+ * Finalize all functions and data objects defined since the last call, resolving the references
+ * between them.
+ *
+ * All the functions and data objects they reference must be defined, or be imports that can be
+ * resolved.
+ */
+CraneliftStatus cranelift_module_finalize(CraneliftModule *module);
+
+/**
+ * Get the address of the finalized code of the function `func` in a simplejit module.
+ *
+ * On success, the address is written to `code_out`.
+ */
+CraneliftStatus cranelift_module_get_function(CraneliftModule *module,
+                                              CraneliftFuncId func,
+                                              const void **code_out);
+
+/**
+ * Get the address and size of the finalized data object `data` in a simplejit module.
+ *
+ * On success, the address is written to `data_out` and the size in bytes to `size_out`.
+ */
+CraneliftStatus cranelift_module_get_data(CraneliftModule *module,
+                                          CraneliftDataId data,
+                                          void **data_out,
+                                          uintptr_t *size_out);
+
+/**
+ * Write the object file containing the functions and data objects defined in a faerie module to
+ * the file at `path`.
+ *
+ * Takes ownership of `module`, even if this fails.
+ */
+CraneliftStatus cranelift_module_write_object(CraneliftModule *module, const char *path);
+
+/**
+ * Start building the function `func` declared in `module`.
+ *
+ * On success, the new builder is written to `builder_out`, and must be passed to
+ * `cranelift_module_define_function` once the function is complete, or freed with
+ * `cranelift_builder_free`.
+ */
+CraneliftStatus cranelift_builder_new(CraneliftModule *module,
+                                      CraneliftFuncId func,
+                                      CraneliftBuilder **builder_out);
+
+/**
+ * Free a builder without defining its function.
+ *
+ * Does nothing if `builder` is null.
+ */
+void cranelift_builder_free(CraneliftBuilder *builder);
+
+/**
+ * Create a new EBB, and write it to `ebb_out`.
+ */
+CraneliftStatus cranelift_builder_create_ebb(CraneliftBuilder *builder, CraneliftEbb *ebb_out);
+
+/**
+ * Append parameters to `ebb` matching the parameters of the function.
+ *
+ * This should be done for the entry EBB, before getting the parameters with
+ * `cranelift_builder_ebb_param`.
+ */
+CraneliftStatus cranelift_builder_append_ebb_params_for_function_params(CraneliftBuilder *builder,
+                                                                        CraneliftEbb ebb);
+
+/**
+ * Append a parameter of type `ty` to `ebb`, and write it to `value_out`.
+ */
+CraneliftStatus cranelift_builder_append_ebb_param(CraneliftBuilder *builder,
+                                                   CraneliftEbb ebb,
+                                                   CraneliftType ty,
+                                                   CraneliftValue *value_out);
+
+/**
+ * Write the parameter number `index` of `ebb` to `value_out`.
+ */
+CraneliftStatus cranelift_builder_ebb_param(CraneliftBuilder *builder,
+                                            CraneliftEbb ebb,
+                                            uintptr_t index,
+                                            CraneliftValue *value_out);
+
+/**
+ * Insert the following instructions at the end of `ebb`.
+ */
+CraneliftStatus cranelift_builder_switch_to_block(CraneliftBuilder *builder, CraneliftEbb ebb);
+
+/**
+ * Declare that all the branches to `ebb` have been inserted.
+ *
+ * Every EBB must be sealed before the function is defined.
+ */
+CraneliftStatus cranelift_builder_seal_block(CraneliftBuilder *builder, CraneliftEbb ebb);
+
+/**
+ * Seal all the EBBs of the function.
+ *
+ * This is simpler than sealing each EBB as soon as all the branches to it are known, but slower.
+ */
+CraneliftStatus cranelift_builder_seal_all_blocks(CraneliftBuilder *builder);
+
+/**
+ * Declare the variable `var` with type `ty`.
+ *
+ * Variables are numbered by the frontend, and must be declared before they are used or defined.
+ */
+CraneliftStatus cranelift_builder_declare_var(CraneliftBuilder *builder,
+                                              CraneliftVariable var,
+                                              CraneliftType ty);
+
+/**
+ * Write the current value of the variable `var` to `value_out`.
+ */
+CraneliftStatus cranelift_builder_use_var(CraneliftBuilder *builder,
+                                          CraneliftVariable var,
+                                          CraneliftValue *value_out);
+
+/**
+ * Assign `value` to the variable `var`.
+ */
+CraneliftStatus cranelift_builder_def_var(CraneliftBuilder *builder,
+                                          CraneliftVariable var,
+                                          CraneliftValue value);
+
+/**
+ * Insert an `iconst` instruction materializing the integer `imm` with type `ty`.
+ */
+CraneliftStatus cranelift_ins_iconst(CraneliftBuilder *builder,
+                                     CraneliftType ty,
+                                     int64_t imm,
+                                     CraneliftValue *result_out);
+
+/**
+ * Insert an `f32const` instruction materializing `imm`.
+ */
+CraneliftStatus cranelift_ins_f32const(CraneliftBuilder *builder,
+                                       float imm,
+                                       CraneliftValue *result_out);
+
+/**
+ * Insert an `f64const` instruction materializing `imm`.
+ */
+CraneliftStatus cranelift_ins_f64const(CraneliftBuilder *builder,
+                                       double imm,
+                                       CraneliftValue *result_out);
+
+/**
+ * Insert the binary instruction `op` with the operands `x` and `y`.
+ */
+CraneliftStatus cranelift_ins_binary(CraneliftBuilder *builder,
+                                     CraneliftBinaryOp op,
+                                     CraneliftValue x,
+                                     CraneliftValue y,
+                                     CraneliftValue *result_out);
+
+/**
+ * Insert the conversion instruction `op` converting `x` to the type `ty`.
+ */
+CraneliftStatus cranelift_ins_convert(CraneliftBuilder *builder,
+                                      CraneliftConvertOp op,
+                                      CraneliftType ty,
+                                      CraneliftValue x,
+                                      CraneliftValue *result_out);
+
+/**
+ * Insert an `icmp` instruction comparing the integers `x` and `y` with `cc`.
+ */
+CraneliftStatus cranelift_ins_icmp(CraneliftBuilder *builder,
+                                   CraneliftIntCC cc,
+                                   CraneliftValue x,
+                                   CraneliftValue y,
+                                   CraneliftValue *result_out);
+
+/**
+ * Insert an `fcmp` instruction comparing the floating point numbers `x` and `y` with `cc`.
+ */
+CraneliftStatus cranelift_ins_fcmp(CraneliftBuilder *builder,
+                                   CraneliftFloatCC cc,
+                                   CraneliftValue x,
+                                   CraneliftValue y,
+                                   CraneliftValue *result_out);
+
+/**
+ * Insert a `select` instruction choosing `x` if the boolean `c` is true, and `y` otherwise.
+ */
+CraneliftStatus cranelift_ins_select(CraneliftBuilder *builder,
+                                     CraneliftValue c,
+                                     CraneliftValue x,
+                                     CraneliftValue y,
+                                     CraneliftValue *result_out);
+
+/**
+ * Insert a `load` instruction loading a value of type `ty` from `addr + offset`.
+ */
+CraneliftStatus cranelift_ins_load(CraneliftBuilder *builder,
+                                   CraneliftType ty,
+                                   CraneliftValue addr,
+                                   int32_t offset,
+                                   CraneliftValue *result_out);
+
+/**
+ * Insert a `store` instruction storing `value` to `addr + offset`.
+ */
+CraneliftStatus cranelift_ins_store(CraneliftBuilder *builder,
+                                    CraneliftValue value,
+                                    CraneliftValue addr,
+                                    int32_t offset);
+
+/**
+ * Insert a `jump` instruction to `ebb`, passing the `args_len` values in `args` to its
+ * parameters.
+ */
+CraneliftStatus cranelift_ins_jump(CraneliftBuilder *builder,
+                                   CraneliftEbb ebb,
+                                   const CraneliftValue *args,
+                                   uintptr_t args_len);
+
+/**
+ * Insert a `brz` instruction branching to `ebb` if `c` is zero or false, passing the `args_len`
+ * values in `args` to its parameters.
+ */
+CraneliftStatus cranelift_ins_brz(CraneliftBuilder *builder,
+                                  CraneliftValue c,
+                                  CraneliftEbb ebb,
+                                  const CraneliftValue *args,
+                                  uintptr_t args_len);
+
+/**
+ * Insert a `brnz` instruction branching to `ebb` if `c` is non-zero or true, passing the
+ * `args_len` values in `args` to its parameters.
+ */
+CraneliftStatus cranelift_ins_brnz(CraneliftBuilder *builder,
+                                   CraneliftValue c,
+                                   CraneliftEbb ebb,
+                                   const CraneliftValue *args,
+                                   uintptr_t args_len);
+
+/**
+ * Insert a `return` instruction returning the `values_len` values in `values`.
+ */
+CraneliftStatus cranelift_ins_return(CraneliftBuilder *builder,
+                                     const CraneliftValue *values,
+                                     uintptr_t values_len);
+
+/**
+ * Insert a `call` instruction calling the function `callee` declared in `module`, with the
+ * `args_len` values in `args`.
+ *
+ * The results are written to `results_out`, whose length `results_len` must be the number of
+ * values returned by `callee`.
+ */
+CraneliftStatus cranelift_ins_call(CraneliftBuilder *builder,
+                                   CraneliftModule *module,
+                                   CraneliftFuncId callee,
+                                   const CraneliftValue *args,
+                                   uintptr_t args_len,
+                                   CraneliftValue *results_out,
+                                   uintptr_t results_len);
+
+/**
+ * Insert a `func_addr` instruction getting the address of the function `func` declared in
+ * `module`.
+ */
+CraneliftStatus cranelift_ins_func_addr(CraneliftBuilder *builder,
+                                        CraneliftModule *module,
+                                        CraneliftFuncId func,
+                                        CraneliftValue *result_out);
+
+/**
+ * Insert a `symbol_value` instruction getting the address of the data object `data` declared in
+ * `module`.
+ */
+CraneliftStatus cranelift_ins_data_addr(CraneliftBuilder *builder,
+                                        CraneliftModule *module,
+                                        CraneliftDataId data,
+                                        CraneliftValue *result_out);
+
+#ifdef __cplusplus
+} // extern "C"
+#endif // __cplusplus
+
+#endif /* CRANELIFT_H */
@@ -0,0 +1,302 @@
+//! Building a function with `cranelift_frontend`.
+
+use constants::{
+    value_type, CraneliftEbb, CraneliftFuncId, CraneliftType, CraneliftValue, CraneliftVariable,
+};
+use cranelift_codegen::entity::EntityRef;
+use cranelift_codegen::ir::{Ebb, ExternalName, Function, Type, Value};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext, Variable};
+use error::{handle, output, wrap, CraneliftStatus, Error, Result};
+use module::CraneliftModule;
+use std::collections::HashMap;
+use std::mem::{self, ManuallyDrop};
+
+/// A function being built.
+pub struct CraneliftBuilder {
+    /// The function being defined.
+    id: CraneliftFuncId,
+    /// The builder, which borrows the function and context that are allocated below. They are
+    /// freed in `drop`, after the builder.
+    pub(crate) bcx: ManuallyDrop<FunctionBuilder<'static>>,
+    func: *mut Function,
+    func_ctx: *mut FunctionBuilderContext,
+    /// The types of the declared variables.
+    variables: HashMap<CraneliftVariable, Type>,
+}
+
+impl CraneliftBuilder {
+    fn new(id: CraneliftFuncId, func: Function) -> Self {
+        let func = Box::into_raw(Box::new(func));
+        let func_ctx = Box::into_raw(Box::new(FunctionBuilderContext::new()));
+        let bcx = unsafe { FunctionBuilder::new(&mut *func, &mut *func_ctx) };
+        Self {
+            id,
+            bcx: ManuallyDrop::new(bcx),
+            func,
+            func_ctx,
+            variables: HashMap::new(),
+        }
+    }
+
+    /// Finish building the function, returning it and the identifier of its declaration.
+    pub(crate) fn finish(mut self: Box<Self>) -> (CraneliftFuncId, Function) {
+        self.bcx.finalize();
+        let func = mem::replace(self.bcx.func, Function::new());
+        (self.id, func)
+    }
+
+    /// Get the value `value` of the function, checking that it exists.
+    pub(crate) fn value(&self, value: CraneliftValue) -> Result<Value> {
+        let v = Value::new(value as usize);
+        if self.bcx.func.dfg.value_is_valid(v) {
+            Ok(v)
+        } else {
+            Err(Error::invalid(format!("unknown value {}", value)))
+        }
+    }
+
+    /// Get the values `values` of the function, checking that they exist.
+    pub(crate) fn values(&self, values: &[CraneliftValue]) -> Result<Vec<Value>> {
+        values.iter().map(|&v| self.value(v)).collect()
+    }
+
+    /// Get the EBB `ebb` of the function, checking that it exists.
+    pub(crate) fn ebb(&self, ebb: CraneliftEbb) -> Result<Ebb> {
+        let e = Ebb::new(ebb as usize);
+        if self.bcx.func.dfg.ebb_is_valid(e) {
+            Ok(e)
+        } else {
+            Err(Error::invalid(format!("unknown EBB {}", ebb)))
+        }
+    }
+
+    fn variable(&self, var: CraneliftVariable) -> Result<(Variable, Type)> {
+        match self.variables.get(&var) {
+            Some(&ty) => Ok((Variable::with_u32(var), ty)),
+            None => Err(Error::invalid(format!("undeclared variable {}", var))),
+        }
+    }
+}
+
+impl Drop for CraneliftBuilder {
+    fn drop(&mut self) {
+        unsafe {
+            ManuallyDrop::drop(&mut self.bcx);
+            drop(Box::from_raw(self.func));
+            drop(Box::from_raw(self.func_ctx));
+        }
+    }
+}
+
+/// Start building the function `func` declared in `module`.
+///
+/// On success, the new builder is written to `builder_out`, and must be passed to
+/// `cranelift_module_define_function` once the function is complete, or freed with
+/// `cranelift_builder_free`.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_builder_new(
+    module: *mut CraneliftModule,
+    func: CraneliftFuncId,
+    builder_out: *mut *mut CraneliftBuilder,
+) -> CraneliftStatus {
+    wrap(|| {
+        let module = handle(module, "module")?;
+        let builder_out = output(builder_out, "builder_out")?;
+        let sig = module.signature(func)?.clone();
+        let name = ExternalName::user(0, func);
+        let builder = CraneliftBuilder::new(func, Function::with_name_signature(name, sig));
+        *builder_out = Box::into_raw(Box::new(builder));
+        Ok(())
+    })
+}
+
+/// Free a builder without defining its function.
+///
+/// Does nothing if `builder` is null.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_builder_free(builder: *mut CraneliftBuilder) {
+    if !builder.is_null() {
+        drop(Box::from_raw(builder));
+    }
+}
+
+/// Create a new EBB, and write it to `ebb_out`.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_builder_create_ebb(
+    builder: *mut CraneliftBuilder,
+    ebb_out: *mut CraneliftEbb,
+) -> CraneliftStatus {
+    wrap(|| {
+        let builder = handle(builder, "builder")?;
+        let ebb_out = output(ebb_out, "ebb_out")?;
+        *ebb_out = builder.bcx.create_ebb().index() as CraneliftEbb;
+        Ok(())
+    })
+}
+
+/// Append parameters to `ebb` matching the parameters of the function.
+///
+/// This should be done for the entry EBB, before getting the parameters with
+/// `cranelift_builder_ebb_param`.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_builder_append_ebb_params_for_function_params(
+    builder: *mut CraneliftBuilder,
+    ebb: CraneliftEbb,
+) -> CraneliftStatus {
+    wrap(|| {
+        let builder = handle(builder, "builder")?;
+        let ebb = builder.ebb(ebb)?;
+        builder.bcx.append_ebb_params_for_function_params(ebb);
+        Ok(())
+    })
+}
+
+/// Append a parameter of type `ty` to `ebb`, and write it to `value_out`.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_builder_append_ebb_param(
+    builder: *mut CraneliftBuilder,
+    ebb: CraneliftEbb,
+    ty: CraneliftType,
+    value_out: *mut CraneliftValue,
+) -> CraneliftStatus {
+    wrap(|| {
+        let builder = handle(builder, "builder")?;
+        let ebb = builder.ebb(ebb)?;
+        let ty = value_type(ty)?;
+        let value_out = output(value_out, "value_out")?;
+        *value_out = builder.bcx.append_ebb_param(ebb, ty).index() as CraneliftValue;
+        Ok(())
+    })
+}
+
+/// Write the parameter number `index` of `ebb` to `value_out`.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_builder_ebb_param(
+    builder: *mut CraneliftBuilder,
+    ebb: CraneliftEbb,
+    index: usize,
+    value_out: *mut CraneliftValue,
+) -> CraneliftStatus {
+    wrap(|| {
+        let builder = handle(builder, "builder")?;
+        let ebb = builder.ebb(ebb)?;
+        let value_out = output(value_out, "value_out")?;
+        let value = match builder.bcx.ebb_params(ebb).get(index) {
+            Some(&value) => value,
+            None => {
+                return Err(Error::invalid(format!(
+                    "{} doesn't have a parameter {}",
+                    ebb, index
+                )))
+            }
+        };
+        *value_out = value.index() as CraneliftValue;
+        Ok(())
+    })
+}
+
+/// Insert the following instructions at the end of `ebb`.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_builder_switch_to_block(
+    builder: *mut CraneliftBuilder,
+    ebb: CraneliftEbb,
+) -> CraneliftStatus {
+    wrap(|| {
+        let builder = handle(builder, "builder")?;
+        let ebb = builder.ebb(ebb)?;
+        builder.bcx.switch_to_block(ebb);
+        Ok(())
+    })
+}
+
+/// Declare that all the branches to `ebb` have been inserted.
+///
+/// Every EBB must be sealed before the function is defined.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_builder_seal_block(
+    builder: *mut CraneliftBuilder,
+    ebb: CraneliftEbb,
+) -> CraneliftStatus {
+    wrap(|| {
+        let builder = handle(builder, "builder")?;
+        let ebb = builder.ebb(ebb)?;
+        builder.bcx.seal_block(ebb);
+        Ok(())
+    })
+}
+
+/// Seal all the EBBs of the function.
+///
+/// This is simpler than sealing each EBB as soon as all the branches to it are known, but slower.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_builder_seal_all_blocks(
+    builder: *mut CraneliftBuilder,
+) -> CraneliftStatus {
+    wrap(|| {
+        let builder = handle(builder, "builder")?;
+        builder.bcx.seal_all_blocks();
+        Ok(())
+    })
+}
+
+/// Declare the variable `var` with type `ty`.
+///
+/// Variables are numbered by the frontend, and must be declared before they are used or defined.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_builder_declare_var(
+    builder: *mut CraneliftBuilder,
+    var: CraneliftVariable,
+    ty: CraneliftType,
+) -> CraneliftStatus {
+    wrap(|| {
+        let builder = handle(builder, "builder")?;
+        let ty = value_type(ty)?;
+        if builder.variables.insert(var, ty).is_some() {
+            return Err(Error::invalid(format!(
+                "variable {} was already declared",
+                var
+            )));
+        }
+        builder.bcx.declare_var(Variable::with_u32(var), ty);
+        Ok(())
+    })
+}
+
+/// Write the current value of the variable `var` to `value_out`.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_builder_use_var(
+    builder: *mut CraneliftBuilder,
+    var: CraneliftVariable,
+    value_out: *mut CraneliftValue,
+) -> CraneliftStatus {
+    wrap(|| {
+        let builder = handle(builder, "builder")?;
+        let (v, _) = builder.variable(var)?;
+        let value_out = output(value_out, "value_out")?;
+        *value_out = builder.bcx.use_var(v).index() as CraneliftValue;
+        Ok(())
+    })
+}
+
+/// Assign `value` to the variable `var`.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_builder_def_var(
+    builder: *mut CraneliftBuilder,
+    var: CraneliftVariable,
+    value: CraneliftValue,
+) -> CraneliftStatus {
+    wrap(|| {
+        let builder = handle(builder, "builder")?;
+        let (v, ty) = builder.variable(var)?;
+        let value = builder.value(value)?;
+        let value_ty = builder.bcx.func.dfg.value_type(value);
+        if value_ty != ty {
+            return Err(Error::invalid(format!(
+                "variable {} has type {}, but {} has type {}",
+                var, ty, value, value_ty
+            )));
+        }
+        builder.bcx.def_var(v, value);
+        Ok(())
+    })
+}
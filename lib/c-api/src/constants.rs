@@ -0,0 +1,222 @@
+//! Entity references and the constants for types, linkages, opcodes, and condition codes.
+//!
+//! Each group of constants has a type alias for `u32` that documents which group a parameter
+//! expects, and a function translating a constant into the Cranelift value it stands for.
+
+use cranelift_codegen::ir::condcodes::{FloatCC, IntCC};
+use cranelift_codegen::ir::{types, Type};
+use cranelift_module::Linkage;
+use error::{Error, Result};
+
+/// A reference to an SSA value in the function being built.
+pub type CraneliftValue = u32;
+
+/// A reference to an extended basic block in the function being built.
+pub type CraneliftEbb = u32;
+
+/// A variable of the function being built, chosen by the frontend.
+pub type CraneliftVariable = u32;
+
+/// A function declared in a module.
+pub type CraneliftFuncId = u32;
+
+/// A data object declared in a module.
+pub type CraneliftDataId = u32;
+
+/// The type of a value, one of the `CRANELIFT_TYPE_*` constants.
+pub type CraneliftType = u32;
+
+/// A boolean, as produced by comparisons.
+pub const CRANELIFT_TYPE_B1: CraneliftType = 1;
+/// An 8-bit integer.
+pub const CRANELIFT_TYPE_I8: CraneliftType = 2;
+/// A 16-bit integer.
+pub const CRANELIFT_TYPE_I16: CraneliftType = 3;
+/// A 32-bit integer.
+pub const CRANELIFT_TYPE_I32: CraneliftType = 4;
+/// A 64-bit integer.
+pub const CRANELIFT_TYPE_I64: CraneliftType = 5;
+/// A 32-bit floating point number.
+pub const CRANELIFT_TYPE_F32: CraneliftType = 6;
+/// A 64-bit floating point number.
+pub const CRANELIFT_TYPE_F64: CraneliftType = 7;
+
+pub(crate) fn value_type(ty: CraneliftType) -> Result<Type> {
+    Ok(match ty {
+        CRANELIFT_TYPE_B1 => types::B1,
+        CRANELIFT_TYPE_I8 => types::I8,
+        CRANELIFT_TYPE_I16 => types::I16,
+        CRANELIFT_TYPE_I32 => types::I32,
+        CRANELIFT_TYPE_I64 => types::I64,
+        CRANELIFT_TYPE_F32 => types::F32,
+        CRANELIFT_TYPE_F64 => types::F64,
+        _ => return Err(Error::invalid(format!("unknown type {}", ty))),
+    })
+}
+
+/// Where a function or data object is defined and who can see it, one of the
+/// `CRANELIFT_LINKAGE_*` constants.
+pub type CraneliftLinkage = u32;
+
+/// Defined outside of the module.
+pub const CRANELIFT_LINKAGE_IMPORT: CraneliftLinkage = 1;
+/// Defined inside the module, but not visible outside it.
+pub const CRANELIFT_LINKAGE_LOCAL: CraneliftLinkage = 2;
+/// Defined inside the module, visible outside it, and may be preempted.
+pub const CRANELIFT_LINKAGE_PREEMPTIBLE: CraneliftLinkage = 3;
+/// Defined inside the module, and visible outside it.
+pub const CRANELIFT_LINKAGE_EXPORT: CraneliftLinkage = 4;
+
+pub(crate) fn module_linkage(linkage: CraneliftLinkage) -> Result<Linkage> {
+    Ok(match linkage {
+        CRANELIFT_LINKAGE_IMPORT => Linkage::Import,
+        CRANELIFT_LINKAGE_LOCAL => Linkage::Local,
+        CRANELIFT_LINKAGE_PREEMPTIBLE => Linkage::Preemptible,
+        CRANELIFT_LINKAGE_EXPORT => Linkage::Export,
+        _ => return Err(Error::invalid(format!("unknown linkage {}", linkage))),
+    })
+}
+
+/// An instruction taking two operands of the same type and producing a result of that type, one of
+/// the `CRANELIFT_BINARY_*` constants.
+pub type CraneliftBinaryOp = u32;
+
+/// Wrapping integer addition: `iadd`.
+pub const CRANELIFT_BINARY_IADD: CraneliftBinaryOp = 1;
+/// Wrapping integer subtraction: `isub`.
+pub const CRANELIFT_BINARY_ISUB: CraneliftBinaryOp = 2;
+/// Wrapping integer multiplication: `imul`.
+pub const CRANELIFT_BINARY_IMUL: CraneliftBinaryOp = 3;
+/// Unsigned integer division: `udiv`.
+pub const CRANELIFT_BINARY_UDIV: CraneliftBinaryOp = 4;
+/// Signed integer division: `sdiv`.
+pub const CRANELIFT_BINARY_SDIV: CraneliftBinaryOp = 5;
+/// Unsigned integer remainder: `urem`.
+pub const CRANELIFT_BINARY_UREM: CraneliftBinaryOp = 6;
+/// Signed integer remainder: `srem`.
+pub const CRANELIFT_BINARY_SREM: CraneliftBinaryOp = 7;
+/// Bitwise and: `band`.
+pub const CRANELIFT_BINARY_BAND: CraneliftBinaryOp = 8;
+/// Bitwise or: `bor`.
+pub const CRANELIFT_BINARY_BOR: CraneliftBinaryOp = 9;
+/// Bitwise exclusive or: `bxor`.
+pub const CRANELIFT_BINARY_BXOR: CraneliftBinaryOp = 10;
+/// Shift left: `ishl`.
+pub const CRANELIFT_BINARY_ISHL: CraneliftBinaryOp = 11;
+/// Logical shift right: `ushr`.
+pub const CRANELIFT_BINARY_USHR: CraneliftBinaryOp = 12;
+/// Arithmetic shift right: `sshr`.
+pub const CRANELIFT_BINARY_SSHR: CraneliftBinaryOp = 13;
+/// Floating point addition: `fadd`.
+pub const CRANELIFT_BINARY_FADD: CraneliftBinaryOp = 14;
+/// Floating point subtraction: `fsub`.
+pub const CRANELIFT_BINARY_FSUB: CraneliftBinaryOp = 15;
+/// Floating point multiplication: `fmul`.
+pub const CRANELIFT_BINARY_FMUL: CraneliftBinaryOp = 16;
+/// Floating point division: `fdiv`.
+pub const CRANELIFT_BINARY_FDIV: CraneliftBinaryOp = 17;
+
+/// An instruction converting its operand to another type, one of the `CRANELIFT_CONVERT_*`
+/// constants.
+pub type CraneliftConvertOp = u32;
+
+/// Zero-extend an integer to a wider type: `uextend`.
+pub const CRANELIFT_CONVERT_UEXTEND: CraneliftConvertOp = 1;
+/// Sign-extend an integer to a wider type: `sextend`.
+pub const CRANELIFT_CONVERT_SEXTEND: CraneliftConvertOp = 2;
+/// Truncate an integer to a narrower type: `ireduce`.
+pub const CRANELIFT_CONVERT_IREDUCE: CraneliftConvertOp = 3;
+/// Convert a boolean to the integer 0 or 1: `bint`.
+pub const CRANELIFT_CONVERT_BINT: CraneliftConvertOp = 4;
+/// Convert `f32` to `f64`: `fpromote`.
+pub const CRANELIFT_CONVERT_FPROMOTE: CraneliftConvertOp = 5;
+/// Convert `f64` to `f32`: `fdemote`.
+pub const CRANELIFT_CONVERT_FDEMOTE: CraneliftConvertOp = 6;
+/// Convert a signed integer to floating point: `fcvt_from_sint`.
+pub const CRANELIFT_CONVERT_FCVT_FROM_SINT: CraneliftConvertOp = 7;
+/// Convert an unsigned integer to floating point: `fcvt_from_uint`.
+pub const CRANELIFT_CONVERT_FCVT_FROM_UINT: CraneliftConvertOp = 8;
+/// Convert floating point to a signed integer, trapping if it doesn't fit: `fcvt_to_sint`.
+pub const CRANELIFT_CONVERT_FCVT_TO_SINT: CraneliftConvertOp = 9;
+/// Convert floating point to an unsigned integer, trapping if it doesn't fit: `fcvt_to_uint`.
+pub const CRANELIFT_CONVERT_FCVT_TO_UINT: CraneliftConvertOp = 10;
+
+/// An integer comparison, one of the `CRANELIFT_INTCC_*` constants.
+pub type CraneliftIntCC = u32;
+
+/// `==`.
+pub const CRANELIFT_INTCC_EQUAL: CraneliftIntCC = 1;
+/// `!=`.
+pub const CRANELIFT_INTCC_NOT_EQUAL: CraneliftIntCC = 2;
+/// Signed `<`.
+pub const CRANELIFT_INTCC_SIGNED_LESS_THAN: CraneliftIntCC = 3;
+/// Signed `>=`.
+pub const CRANELIFT_INTCC_SIGNED_GREATER_THAN_OR_EQUAL: CraneliftIntCC = 4;
+/// Signed `>`.
+pub const CRANELIFT_INTCC_SIGNED_GREATER_THAN: CraneliftIntCC = 5;
+/// Signed `<=`.
+pub const CRANELIFT_INTCC_SIGNED_LESS_THAN_OR_EQUAL: CraneliftIntCC = 6;
+/// Unsigned `<`.
+pub const CRANELIFT_INTCC_UNSIGNED_LESS_THAN: CraneliftIntCC = 7;
+/// Unsigned `>=`.
+pub const CRANELIFT_INTCC_UNSIGNED_GREATER_THAN_OR_EQUAL: CraneliftIntCC = 8;
+/// Unsigned `>`.
+pub const CRANELIFT_INTCC_UNSIGNED_GREATER_THAN: CraneliftIntCC = 9;
+/// Unsigned `<=`.
+pub const CRANELIFT_INTCC_UNSIGNED_LESS_THAN_OR_EQUAL: CraneliftIntCC = 10;
+
+pub(crate) fn int_cc(cc: CraneliftIntCC) -> Result<IntCC> {
+    Ok(match cc {
+        CRANELIFT_INTCC_EQUAL => IntCC::Equal,
+        CRANELIFT_INTCC_NOT_EQUAL => IntCC::NotEqual,
+        CRANELIFT_INTCC_SIGNED_LESS_THAN => IntCC::SignedLessThan,
+        CRANELIFT_INTCC_SIGNED_GREATER_THAN_OR_EQUAL => IntCC::SignedGreaterThanOrEqual,
+        CRANELIFT_INTCC_SIGNED_GREATER_THAN => IntCC::SignedGreaterThan,
+        CRANELIFT_INTCC_SIGNED_LESS_THAN_OR_EQUAL => IntCC::SignedLessThanOrEqual,
+        CRANELIFT_INTCC_UNSIGNED_LESS_THAN => IntCC::UnsignedLessThan,
+        CRANELIFT_INTCC_UNSIGNED_GREATER_THAN_OR_EQUAL => IntCC::UnsignedGreaterThanOrEqual,
+        CRANELIFT_INTCC_UNSIGNED_GREATER_THAN => IntCC::UnsignedGreaterThan,
+        CRANELIFT_INTCC_UNSIGNED_LESS_THAN_OR_EQUAL => IntCC::UnsignedLessThanOrEqual,
+        _ => {
+            return Err(Error::invalid(format!(
+                "unknown integer condition code {}",
+                cc
+            )))
+        }
+    })
+}
+
+/// A floating point comparison, one of the `CRANELIFT_FLOATCC_*` constants.
+///
+/// These are all false when either operand is NaN, except `CRANELIFT_FLOATCC_NOT_EQUAL`.
+pub type CraneliftFloatCC = u32;
+
+/// `==`.
+pub const CRANELIFT_FLOATCC_EQUAL: CraneliftFloatCC = 1;
+/// `!=`.
+pub const CRANELIFT_FLOATCC_NOT_EQUAL: CraneliftFloatCC = 2;
+/// `<`.
+pub const CRANELIFT_FLOATCC_LESS_THAN: CraneliftFloatCC = 3;
+/// `<=`.
+pub const CRANELIFT_FLOATCC_LESS_THAN_OR_EQUAL: CraneliftFloatCC = 4;
+/// `>`.
+pub const CRANELIFT_FLOATCC_GREATER_THAN: CraneliftFloatCC = 5;
+/// `>=`.
+pub const CRANELIFT_FLOATCC_GREATER_THAN_OR_EQUAL: CraneliftFloatCC = 6;
+
+pub(crate) fn float_cc(cc: CraneliftFloatCC) -> Result<FloatCC> {
+    Ok(match cc {
+        CRANELIFT_FLOATCC_EQUAL => FloatCC::Equal,
+        CRANELIFT_FLOATCC_NOT_EQUAL => FloatCC::NotEqual,
+        CRANELIFT_FLOATCC_LESS_THAN => FloatCC::LessThan,
+        CRANELIFT_FLOATCC_LESS_THAN_OR_EQUAL => FloatCC::LessThanOrEqual,
+        CRANELIFT_FLOATCC_GREATER_THAN => FloatCC::GreaterThan,
+        CRANELIFT_FLOATCC_GREATER_THAN_OR_EQUAL => FloatCC::GreaterThanOrEqual,
+        _ => {
+            return Err(Error::invalid(format!(
+                "unknown float condition code {}",
+                cc
+            )))
+        }
+    })
+}
@@ -0,0 +1,177 @@
+//! Status codes, the last error message, and checking of arguments passed from C.
+
+use std::any::Any;
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+use std::string::String;
+
+/// The result of a call to a fallible function.
+///
+/// When a function returns anything but `CRANELIFT_STATUS_OK`, a message describing the failure
+/// is available from `cranelift_last_error`, and none of its output parameters have been written.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CraneliftStatus {
+    /// The call succeeded.
+    Ok = 0,
+    /// A required pointer argument was null.
+    NullPointer,
+    /// An argument was invalid, for example a string that isn't UTF-8 or an unknown entity.
+    InvalidArgument,
+    /// The target isn't supported by this build of Cranelift.
+    UnsupportedTarget,
+    /// A setting name or value was rejected.
+    InvalidSetting,
+    /// A declaration or definition was rejected by the module.
+    Module,
+    /// Compiling a function failed, usually because it didn't pass the verifier.
+    Compilation,
+    /// The operation isn't supported by this kind of module.
+    Unsupported,
+    /// Writing an output file failed.
+    Io,
+    /// Cranelift panicked. The handles passed to the call must not be used again, except to free
+    /// them.
+    Panic,
+}
+
+/// An error to be reported to C as a status code and a message.
+pub(crate) struct Error {
+    status: CraneliftStatus,
+    message: String,
+}
+
+impl Error {
+    pub fn new<S: Into<String>>(status: CraneliftStatus, message: S) -> Self {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+
+    pub fn invalid<S: Into<String>>(message: S) -> Self {
+        Self::new(CraneliftStatus::InvalidArgument, message)
+    }
+}
+
+/// A convenient alias for a `Result` that uses `Error` as the error type.
+pub(crate) type Result<T> = ::std::result::Result<T, Error>;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = RefCell::new(None);
+}
+
+/// Get the message describing the last failed call on the current thread.
+///
+/// Returns null if no call has failed on this thread. The string is owned by the library and is
+/// valid until the next failed call on the same thread.
+#[no_mangle]
+pub extern "C" fn cranelift_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| match *last.borrow() {
+        Some(ref message) => message.as_ptr(),
+        None => ptr::null(),
+    })
+}
+
+/// Run the body of an exported function, turning its result into a status code.
+///
+/// Panics must not unwind into C, so they are caught and reported as `CraneliftStatus::Panic`.
+pub(crate) fn wrap<F>(body: F) -> CraneliftStatus
+where
+    F: FnOnce() -> Result<()>,
+{
+    let result = match panic::catch_unwind(AssertUnwindSafe(body)) {
+        Ok(result) => result,
+        Err(payload) => Err(Error::new(CraneliftStatus::Panic, panic_message(&*payload))),
+    };
+    match result {
+        Ok(()) => CraneliftStatus::Ok,
+        Err(err) => {
+            // The message is returned as a C string, so it can't contain NUL characters.
+            let message = CString::new(err.message.replace('\0', "")).unwrap();
+            LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+            err.status
+        }
+    }
+}
+
+fn panic_message(payload: &(Any + Send)) -> String {
+    let message = if let Some(message) = payload.downcast_ref::<&str>() {
+        *message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.as_str()
+    } else {
+        "unknown panic"
+    };
+    format!("Cranelift panicked: {}", message)
+}
+
+fn null_pointer(name: &str) -> Error {
+    Error::new(
+        CraneliftStatus::NullPointer,
+        format!("`{}` must not be null", name),
+    )
+}
+
+/// Borrow the object behind the handle `ptr`, the argument called `name`.
+pub(crate) unsafe fn handle<'a, T>(ptr: *mut T, name: &str) -> Result<&'a mut T> {
+    ptr.as_mut().ok_or_else(|| null_pointer(name))
+}
+
+/// Take ownership of the object behind the handle `ptr`, the argument called `name`.
+pub(crate) unsafe fn take_handle<T>(ptr: *mut T, name: &str) -> Result<Box<T>> {
+    if ptr.is_null() {
+        Err(null_pointer(name))
+    } else {
+        Ok(Box::from_raw(ptr))
+    }
+}
+
+/// Get the output parameter `ptr`, the argument called `name`.
+pub(crate) unsafe fn output<'a, T>(ptr: *mut T, name: &str) -> Result<&'a mut T> {
+    ptr.as_mut().ok_or_else(|| null_pointer(name))
+}
+
+/// Get the NUL-terminated UTF-8 string `ptr`, the argument called `name`.
+pub(crate) unsafe fn string<'a>(ptr: *const c_char, name: &str) -> Result<&'a str> {
+    if ptr.is_null() {
+        return Err(null_pointer(name));
+    }
+    CStr::from_ptr(ptr)
+        .to_str()
+        .map_err(|_| Error::invalid(format!("`{}` is not valid UTF-8", name)))
+}
+
+/// Get the array of `len` elements at `ptr`, the argument called `name`.
+///
+/// The pointer may be null when `len` is zero.
+pub(crate) unsafe fn array<'a, T>(ptr: *const T, len: usize, name: &str) -> Result<&'a [T]> {
+    if len == 0 {
+        Ok(&[])
+    } else if ptr.is_null() {
+        Err(null_pointer(name))
+    } else {
+        Ok(slice::from_raw_parts(ptr, len))
+    }
+}
+
+/// Get the output array of `len` elements at `ptr`, the argument called `name`.
+///
+/// The pointer may be null when `len` is zero.
+pub(crate) unsafe fn output_array<'a, T>(
+    ptr: *mut T,
+    len: usize,
+    name: &str,
+) -> Result<&'a mut [T]> {
+    if len == 0 {
+        Ok(&mut [])
+    } else if ptr.is_null() {
+        Err(null_pointer(name))
+    } else {
+        Ok(slice::from_raw_parts_mut(ptr, len))
+    }
+}
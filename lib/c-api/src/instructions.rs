@@ -0,0 +1,365 @@
+//! Inserting instructions with a builder.
+//!
+//! Each function inserts one instruction at the builder's current position, and writes its result
+//! to `result_out` if it has one. The operand types aren't checked when the instruction is
+//! inserted, but by the verifier when the function is defined.
+
+use builder::CraneliftBuilder;
+use constants::*;
+use cranelift_codegen::entity::EntityRef;
+use cranelift_codegen::ir::immediates::{Ieee32, Ieee64};
+use cranelift_codegen::ir::{InstBuilder, MemFlags, Value};
+use error::{array, handle, output, output_array, wrap, CraneliftStatus, Error, Result};
+use module::CraneliftModule;
+
+/// Run the body of a function inserting an instruction with a single result, and write the result
+/// to `result_out`.
+unsafe fn insert<F>(
+    builder: *mut CraneliftBuilder,
+    result_out: *mut CraneliftValue,
+    body: F,
+) -> CraneliftStatus
+where
+    F: FnOnce(&mut CraneliftBuilder) -> Result<Value>,
+{
+    wrap(|| {
+        let builder = handle(builder, "builder")?;
+        let result_out = output(result_out, "result_out")?;
+        *result_out = body(builder)?.index() as CraneliftValue;
+        Ok(())
+    })
+}
+
+/// Insert an `iconst` instruction materializing the integer `imm` with type `ty`.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_ins_iconst(
+    builder: *mut CraneliftBuilder,
+    ty: CraneliftType,
+    imm: i64,
+    result_out: *mut CraneliftValue,
+) -> CraneliftStatus {
+    insert(builder, result_out, |b| {
+        let ty = value_type(ty)?;
+        Ok(b.bcx.ins().iconst(ty, imm))
+    })
+}
+
+/// Insert an `f32const` instruction materializing `imm`.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_ins_f32const(
+    builder: *mut CraneliftBuilder,
+    imm: f32,
+    result_out: *mut CraneliftValue,
+) -> CraneliftStatus {
+    insert(builder, result_out, |b| {
+        Ok(b.bcx.ins().f32const(Ieee32::with_float(imm)))
+    })
+}
+
+/// Insert an `f64const` instruction materializing `imm`.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_ins_f64const(
+    builder: *mut CraneliftBuilder,
+    imm: f64,
+    result_out: *mut CraneliftValue,
+) -> CraneliftStatus {
+    insert(builder, result_out, |b| {
+        Ok(b.bcx.ins().f64const(Ieee64::with_float(imm)))
+    })
+}
+
+/// Insert the binary instruction `op` with the operands `x` and `y`.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_ins_binary(
+    builder: *mut CraneliftBuilder,
+    op: CraneliftBinaryOp,
+    x: CraneliftValue,
+    y: CraneliftValue,
+    result_out: *mut CraneliftValue,
+) -> CraneliftStatus {
+    insert(builder, result_out, |b| {
+        let x = b.value(x)?;
+        let y = b.value(y)?;
+        let ins = b.bcx.ins();
+        Ok(match op {
+            CRANELIFT_BINARY_IADD => ins.iadd(x, y),
+            CRANELIFT_BINARY_ISUB => ins.isub(x, y),
+            CRANELIFT_BINARY_IMUL => ins.imul(x, y),
+            CRANELIFT_BINARY_UDIV => ins.udiv(x, y),
+            CRANELIFT_BINARY_SDIV => ins.sdiv(x, y),
+            CRANELIFT_BINARY_UREM => ins.urem(x, y),
+            CRANELIFT_BINARY_SREM => ins.srem(x, y),
+            CRANELIFT_BINARY_BAND => ins.band(x, y),
+            CRANELIFT_BINARY_BOR => ins.bor(x, y),
+            CRANELIFT_BINARY_BXOR => ins.bxor(x, y),
+            CRANELIFT_BINARY_ISHL => ins.ishl(x, y),
+            CRANELIFT_BINARY_USHR => ins.ushr(x, y),
+            CRANELIFT_BINARY_SSHR => ins.sshr(x, y),
+            CRANELIFT_BINARY_FADD => ins.fadd(x, y),
+            CRANELIFT_BINARY_FSUB => ins.fsub(x, y),
+            CRANELIFT_BINARY_FMUL => ins.fmul(x, y),
+            CRANELIFT_BINARY_FDIV => ins.fdiv(x, y),
+            _ => return Err(Error::invalid(format!("unknown binary operation {}", op))),
+        })
+    })
+}
+
+/// Insert the conversion instruction `op` converting `x` to the type `ty`.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_ins_convert(
+    builder: *mut CraneliftBuilder,
+    op: CraneliftConvertOp,
+    ty: CraneliftType,
+    x: CraneliftValue,
+    result_out: *mut CraneliftValue,
+) -> CraneliftStatus {
+    insert(builder, result_out, |b| {
+        let ty = value_type(ty)?;
+        let x = b.value(x)?;
+        let ins = b.bcx.ins();
+        Ok(match op {
+            CRANELIFT_CONVERT_UEXTEND => ins.uextend(ty, x),
+            CRANELIFT_CONVERT_SEXTEND => ins.sextend(ty, x),
+            CRANELIFT_CONVERT_IREDUCE => ins.ireduce(ty, x),
+            CRANELIFT_CONVERT_BINT => ins.bint(ty, x),
+            CRANELIFT_CONVERT_FPROMOTE => ins.fpromote(ty, x),
+            CRANELIFT_CONVERT_FDEMOTE => ins.fdemote(ty, x),
+            CRANELIFT_CONVERT_FCVT_FROM_SINT => ins.fcvt_from_sint(ty, x),
+            CRANELIFT_CONVERT_FCVT_FROM_UINT => ins.fcvt_from_uint(ty, x),
+            CRANELIFT_CONVERT_FCVT_TO_SINT => ins.fcvt_to_sint(ty, x),
+            CRANELIFT_CONVERT_FCVT_TO_UINT => ins.fcvt_to_uint(ty, x),
+            _ => return Err(Error::invalid(format!("unknown conversion {}", op))),
+        })
+    })
+}
+
+/// Insert an `icmp` instruction comparing the integers `x` and `y` with `cc`.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_ins_icmp(
+    builder: *mut CraneliftBuilder,
+    cc: CraneliftIntCC,
+    x: CraneliftValue,
+    y: CraneliftValue,
+    result_out: *mut CraneliftValue,
+) -> CraneliftStatus {
+    insert(builder, result_out, |b| {
+        let cc = int_cc(cc)?;
+        let x = b.value(x)?;
+        let y = b.value(y)?;
+        Ok(b.bcx.ins().icmp(cc, x, y))
+    })
+}
+
+/// Insert an `fcmp` instruction comparing the floating point numbers `x` and `y` with `cc`.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_ins_fcmp(
+    builder: *mut CraneliftBuilder,
+    cc: CraneliftFloatCC,
+    x: CraneliftValue,
+    y: CraneliftValue,
+    result_out: *mut CraneliftValue,
+) -> CraneliftStatus {
+    insert(builder, result_out, |b| {
+        let cc = float_cc(cc)?;
+        let x = b.value(x)?;
+        let y = b.value(y)?;
+        Ok(b.bcx.ins().fcmp(cc, x, y))
+    })
+}
+
+/// Insert a `select` instruction choosing `x` if the boolean `c` is true, and `y` otherwise.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_ins_select(
+    builder: *mut CraneliftBuilder,
+    c: CraneliftValue,
+    x: CraneliftValue,
+    y: CraneliftValue,
+    result_out: *mut CraneliftValue,
+) -> CraneliftStatus {
+    insert(builder, result_out, |b| {
+        let c = b.value(c)?;
+        let x = b.value(x)?;
+        let y = b.value(y)?;
+        Ok(b.bcx.ins().select(c, x, y))
+    })
+}
+
+/// Insert a `load` instruction loading a value of type `ty` from `addr + offset`.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_ins_load(
+    builder: *mut CraneliftBuilder,
+    ty: CraneliftType,
+    addr: CraneliftValue,
+    offset: i32,
+    result_out: *mut CraneliftValue,
+) -> CraneliftStatus {
+    insert(builder, result_out, |b| {
+        let ty = value_type(ty)?;
+        let addr = b.value(addr)?;
+        Ok(b.bcx.ins().load(ty, MemFlags::new(), addr, offset))
+    })
+}
+
+/// Insert a `store` instruction storing `value` to `addr + offset`.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_ins_store(
+    builder: *mut CraneliftBuilder,
+    value: CraneliftValue,
+    addr: CraneliftValue,
+    offset: i32,
+) -> CraneliftStatus {
+    wrap(|| {
+        let builder = handle(builder, "builder")?;
+        let value = builder.value(value)?;
+        let addr = builder.value(addr)?;
+        builder
+            .bcx
+            .ins()
+            .store(MemFlags::new(), value, addr, offset);
+        Ok(())
+    })
+}
+
+/// Insert a `jump` instruction to `ebb`, passing the `args_len` values in `args` to its
+/// parameters.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_ins_jump(
+    builder: *mut CraneliftBuilder,
+    ebb: CraneliftEbb,
+    args: *const CraneliftValue,
+    args_len: usize,
+) -> CraneliftStatus {
+    wrap(|| {
+        let builder = handle(builder, "builder")?;
+        let ebb = builder.ebb(ebb)?;
+        let args = builder.values(array(args, args_len, "args")?)?;
+        builder.bcx.ins().jump(ebb, &args);
+        Ok(())
+    })
+}
+
+/// Insert a `brz` instruction branching to `ebb` if `c` is zero or false, passing the `args_len`
+/// values in `args` to its parameters.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_ins_brz(
+    builder: *mut CraneliftBuilder,
+    c: CraneliftValue,
+    ebb: CraneliftEbb,
+    args: *const CraneliftValue,
+    args_len: usize,
+) -> CraneliftStatus {
+    wrap(|| {
+        let builder = handle(builder, "builder")?;
+        let c = builder.value(c)?;
+        let ebb = builder.ebb(ebb)?;
+        let args = builder.values(array(args, args_len, "args")?)?;
+        builder.bcx.ins().brz(c, ebb, &args);
+        Ok(())
+    })
+}
+
+/// Insert a `brnz` instruction branching to `ebb` if `c` is non-zero or true, passing the
+/// `args_len` values in `args` to its parameters.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_ins_brnz(
+    builder: *mut CraneliftBuilder,
+    c: CraneliftValue,
+    ebb: CraneliftEbb,
+    args: *const CraneliftValue,
+    args_len: usize,
+) -> CraneliftStatus {
+    wrap(|| {
+        let builder = handle(builder, "builder")?;
+        let c = builder.value(c)?;
+        let ebb = builder.ebb(ebb)?;
+        let args = builder.values(array(args, args_len, "args")?)?;
+        builder.bcx.ins().brnz(c, ebb, &args);
+        Ok(())
+    })
+}
+
+/// Insert a `return` instruction returning the `values_len` values in `values`.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_ins_return(
+    builder: *mut CraneliftBuilder,
+    values: *const CraneliftValue,
+    values_len: usize,
+) -> CraneliftStatus {
+    wrap(|| {
+        let builder = handle(builder, "builder")?;
+        let values = builder.values(array(values, values_len, "values")?)?;
+        builder.bcx.ins().return_(&values);
+        Ok(())
+    })
+}
+
+/// Insert a `call` instruction calling the function `callee` declared in `module`, with the
+/// `args_len` values in `args`.
+///
+/// The results are written to `results_out`, whose length `results_len` must be the number of
+/// values returned by `callee`.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_ins_call(
+    builder: *mut CraneliftBuilder,
+    module: *mut CraneliftModule,
+    callee: CraneliftFuncId,
+    args: *const CraneliftValue,
+    args_len: usize,
+    results_out: *mut CraneliftValue,
+    results_len: usize,
+) -> CraneliftStatus {
+    wrap(|| {
+        let builder = handle(builder, "builder")?;
+        let module = handle(module, "module")?;
+        let args = builder.values(array(args, args_len, "args")?)?;
+        let results_out = output_array(results_out, results_len, "results_out")?;
+        let returns = module.signature(callee)?.returns.len();
+        if returns != results_len {
+            return Err(Error::invalid(format!(
+                "function {} returns {} values, but `results_len` is {}",
+                callee, returns, results_len
+            )));
+        }
+
+        let callee = module.declare_func_in_func(callee, builder.bcx.func)?;
+        let call = builder.bcx.ins().call(callee, &args);
+        for (out, result) in results_out.iter_mut().zip(builder.bcx.inst_results(call)) {
+            *out = result.index() as CraneliftValue;
+        }
+        Ok(())
+    })
+}
+
+/// Insert a `func_addr` instruction getting the address of the function `func` declared in
+/// `module`.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_ins_func_addr(
+    builder: *mut CraneliftBuilder,
+    module: *mut CraneliftModule,
+    func: CraneliftFuncId,
+    result_out: *mut CraneliftValue,
+) -> CraneliftStatus {
+    insert(builder, result_out, |b| {
+        let module = handle(module, "module")?;
+        let pointer_type = module.pointer_type();
+        let func = module.declare_func_in_func(func, b.bcx.func)?;
+        Ok(b.bcx.ins().func_addr(pointer_type, func))
+    })
+}
+
+/// Insert a `symbol_value` instruction getting the address of the data object `data` declared in
+/// `module`.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_ins_data_addr(
+    builder: *mut CraneliftBuilder,
+    module: *mut CraneliftModule,
+    data: CraneliftDataId,
+    result_out: *mut CraneliftValue,
+) -> CraneliftStatus {
+    insert(builder, result_out, |b| {
+        let module = handle(module, "module")?;
+        let pointer_type = module.pointer_type();
+        let data = module.declare_data_in_func(data, b.bcx.func)?;
+        Ok(b.bcx.ins().symbol_value(pointer_type, data))
+    })
+}
@@ -0,0 +1,98 @@
+//! Creating a target ISA.
+
+use cranelift_codegen::isa::{self, TargetIsa};
+use cranelift_codegen::settings::{self, Configurable, SetError};
+use cranelift_native;
+use error::{array, output, string, wrap, CraneliftStatus, Error, Result};
+use std::os::raw::c_char;
+use std::str::FromStr;
+use target_lexicon::Triple;
+
+/// A target ISA with its settings, used to create a module.
+pub struct CraneliftIsa {
+    pub(crate) isa: Box<TargetIsa>,
+}
+
+/// Create a target ISA for `triple`, configured by the `flags_len` strings in `flags`.
+///
+/// A null `triple` selects the host, with the features of the host CPU enabled. This is usually
+/// what's wanted for simplejit modules, since it also selects the host's calling convention.
+///
+/// Each flag is either `name=value` to set a setting, or just `name` to enable a boolean setting
+/// or apply a preset. Both shared settings like `opt_level` and settings specific to the ISA
+/// are accepted.
+///
+/// On success, the new ISA is written to `isa_out` and must be passed to a function creating a
+/// module, or freed with `cranelift_isa_free`.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_isa_new(
+    triple: *const c_char,
+    flags: *const *const c_char,
+    flags_len: usize,
+    isa_out: *mut *mut CraneliftIsa,
+) -> CraneliftStatus {
+    wrap(|| {
+        let isa_out = output(isa_out, "isa_out")?;
+        let (mut flag_builder, mut isa_builder) = if triple.is_null() {
+            cranelift_native::builders()
+                .map_err(|msg| Error::new(CraneliftStatus::UnsupportedTarget, msg))?
+        } else {
+            let triple = string(triple, "triple")?;
+            let parsed = Triple::from_str(triple)
+                .map_err(|_| Error::invalid(format!("invalid target triple '{}'", triple)))?;
+            let isa_builder = isa::lookup(parsed).map_err(|err| {
+                Error::new(
+                    CraneliftStatus::UnsupportedTarget,
+                    format!("{}: {}", triple, err),
+                )
+            })?;
+            (settings::builder(), isa_builder)
+        };
+        for &flag in array(flags, flags_len, "flags")? {
+            apply_flag(
+                &mut flag_builder,
+                &mut isa_builder,
+                string(flag, "flags element")?,
+            )?;
+        }
+        let isa = isa_builder.finish(settings::Flags::new(flag_builder));
+        *isa_out = Box::into_raw(Box::new(CraneliftIsa { isa }));
+        Ok(())
+    })
+}
+
+/// Apply `flag` to the shared settings, or to the ISA settings if there's no shared setting by
+/// that name.
+fn apply_flag(
+    flag_builder: &mut settings::Builder,
+    isa_builder: &mut isa::Builder,
+    flag: &str,
+) -> Result<()> {
+    let mut parts = flag.splitn(2, '=');
+    let name = parts.next().unwrap();
+    let value = parts.next();
+    let configure = |builder: &mut Configurable| match value {
+        Some(value) => builder.set(name, value),
+        None => builder.enable(name),
+    };
+    let result = match configure(flag_builder) {
+        Err(SetError::BadName(_)) => configure(isa_builder),
+        result => result,
+    };
+    result.map_err(|err| {
+        Error::new(
+            CraneliftStatus::InvalidSetting,
+            format!("{}: {}", flag, err),
+        )
+    })
+}
+
+/// Free an ISA that hasn't been passed to a function creating a module.
+///
+/// Does nothing if `isa` is null.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_isa_free(isa: *mut CraneliftIsa) {
+    if !isa.is_null() {
+        drop(Box::from_raw(isa));
+    }
+}
@@ -0,0 +1,66 @@
+//! C bindings for Cranelift.
+//!
+//! This crate exposes an `extern "C"` interface for frontends that aren't written in Rust. It
+//! covers creating a target ISA, declaring and defining functions and data in a module backed by
+//! simplejit or faerie, building functions with a wrapper around `cranelift_frontend`'s
+//! `FunctionBuilder`, and getting the finalized code.
+//!
+//! Objects are passed across the interface as pointers to opaque handles, and functions that can
+//! fail return a `CraneliftStatus`. The message describing the last failure on the current thread
+//! is available from `cranelift_last_error`. Entities like values and EBBs are passed as `u32`
+//! indices, and enumerations like types and condition codes as `u32` constants, so that an invalid
+//! value from C is reported as an error rather than causing undefined behavior.
+//!
+//! The header in `include/cranelift.h` is generated from this crate with cbindgen.
+
+#![deny(
+    missing_docs,
+    trivial_numeric_casts,
+    unused_extern_crates,
+    unstable_features
+)]
+#![warn(unused_import_braces)]
+#![cfg_attr(feature = "clippy", plugin(clippy(conf_file = "../../clippy.toml")))]
+#![cfg_attr(
+    feature = "cargo-clippy",
+    allow(new_without_default, new_without_default_derive)
+)]
+#![cfg_attr(
+    feature = "cargo-clippy",
+    warn(
+        mut_mut,
+        nonminimal_bool,
+        option_map_unwrap_or,
+        option_map_unwrap_or_else,
+        print_stdout,
+        unicode_not_nfc,
+        use_self
+    )
+)]
+
+extern crate cranelift_codegen;
+#[cfg(feature = "faerie")]
+extern crate cranelift_faerie;
+extern crate cranelift_frontend;
+extern crate cranelift_module;
+extern crate cranelift_native;
+#[cfg(feature = "simplejit")]
+extern crate cranelift_simplejit;
+extern crate target_lexicon;
+
+#[cfg(not(any(feature = "simplejit", feature = "faerie")))]
+compile_error!("cranelift-c-api needs at least one of the `simplejit` and `faerie` features");
+
+mod builder;
+mod constants;
+mod error;
+mod instructions;
+mod isa;
+mod module;
+
+pub use builder::*;
+pub use constants::*;
+pub use error::*;
+pub use instructions::*;
+pub use isa::*;
+pub use module::*;
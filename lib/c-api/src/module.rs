@@ -0,0 +1,477 @@
+//! Modules of functions and data objects, backed by simplejit or faerie.
+
+use builder::CraneliftBuilder;
+use constants::{
+    module_linkage, value_type, CraneliftDataId, CraneliftFuncId, CraneliftLinkage, CraneliftType,
+};
+use cranelift_codegen::entity::EntityRef;
+use cranelift_codegen::ir::{self, AbiParam};
+use cranelift_codegen::print_errors::pretty_error;
+use cranelift_codegen::Context;
+#[cfg(feature = "faerie")]
+use cranelift_faerie::{FaerieBackend, FaerieBuilder, FaerieTrapCollection};
+use cranelift_module::{DataContext, DataId, FuncId, Module, ModuleError};
+#[cfg(feature = "simplejit")]
+use cranelift_simplejit::{SimpleJITBackend, SimpleJITBuilder};
+use error::{array, handle, output, string, take_handle, wrap, CraneliftStatus, Error, Result};
+use isa::CraneliftIsa;
+use std::collections::HashMap;
+#[cfg(feature = "faerie")]
+use std::fs::File;
+use std::os::raw::{c_char, c_void};
+use std::slice;
+
+/// The backend of a module.
+enum Backend {
+    #[cfg(feature = "simplejit")]
+    SimpleJIT(Module<SimpleJITBackend>),
+    #[cfg(feature = "faerie")]
+    Faerie(Module<FaerieBackend>),
+}
+
+/// Evaluate `$body` with `$module` bound to the `Module` of a `Backend`, whatever its type.
+macro_rules! with_module {
+    ($backend:expr, $module:ident => $body:expr) => {
+        match $backend {
+            #[cfg(feature = "simplejit")]
+            Backend::SimpleJIT(ref mut $module) => $body,
+            #[cfg(feature = "faerie")]
+            Backend::Faerie(ref mut $module) => $body,
+        }
+    };
+}
+
+/// How far along the definition of a function or data object is.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum State {
+    Declared,
+    Defined,
+    Finalized,
+}
+
+/// A module of functions and data objects.
+pub struct CraneliftModule {
+    backend: Backend,
+    /// The declared functions, with their signatures.
+    functions: HashMap<FuncId, (ir::Signature, State)>,
+    /// The declared data objects.
+    data_objects: HashMap<DataId, State>,
+}
+
+impl CraneliftModule {
+    fn new(backend: Backend) -> Self {
+        Self {
+            backend,
+            functions: HashMap::new(),
+            data_objects: HashMap::new(),
+        }
+    }
+
+    fn func_id(&self, id: CraneliftFuncId) -> Result<FuncId> {
+        let id = FuncId::new(id as usize);
+        if self.functions.contains_key(&id) {
+            Ok(id)
+        } else {
+            Err(Error::invalid(format!("undeclared function {}", id)))
+        }
+    }
+
+    fn data_id(&self, id: CraneliftDataId) -> Result<DataId> {
+        let id = DataId::new(id as usize);
+        if self.data_objects.contains_key(&id) {
+            Ok(id)
+        } else {
+            Err(Error::invalid(format!("undeclared data object {}", id)))
+        }
+    }
+
+    /// Get the signature of the declared function `id`.
+    pub(crate) fn signature(&self, id: CraneliftFuncId) -> Result<&ir::Signature> {
+        let id = self.func_id(id)?;
+        Ok(&self.functions[&id].0)
+    }
+
+    /// Get the type of pointers for this module's target.
+    pub(crate) fn pointer_type(&mut self) -> ir::Type {
+        with_module!(self.backend, module => module.pointer_type())
+    }
+
+    /// Declare the function `id` for use in `func`.
+    pub(crate) fn declare_func_in_func(
+        &mut self,
+        id: CraneliftFuncId,
+        func: &mut ir::Function,
+    ) -> Result<ir::FuncRef> {
+        let id = self.func_id(id)?;
+        Ok(with_module!(self.backend, module => module.declare_func_in_func(id, func)))
+    }
+
+    /// Declare the data object `id` for use in `func`.
+    pub(crate) fn declare_data_in_func(
+        &mut self,
+        id: CraneliftDataId,
+        func: &mut ir::Function,
+    ) -> Result<ir::GlobalValue> {
+        let id = self.data_id(id)?;
+        Ok(with_module!(self.backend, module => module.declare_data_in_func(id, func)))
+    }
+
+    fn finalized_function(&mut self, id: CraneliftFuncId) -> Result<FuncId> {
+        let id = self.func_id(id)?;
+        if self.functions[&id].1 != State::Finalized {
+            return Err(Error::invalid(format!(
+                "function {} hasn't been defined and finalized",
+                id
+            )));
+        }
+        Ok(id)
+    }
+
+    fn finalized_data(&mut self, id: CraneliftDataId) -> Result<DataId> {
+        let id = self.data_id(id)?;
+        if self.data_objects[&id] != State::Finalized {
+            return Err(Error::invalid(format!(
+                "data object {} hasn't been defined and finalized",
+                id
+            )));
+        }
+        Ok(id)
+    }
+}
+
+fn module_error(err: ModuleError) -> Error {
+    Error::new(CraneliftStatus::Module, err.to_string())
+}
+
+fn unsupported(what: &str) -> Error {
+    Error::new(
+        CraneliftStatus::Unsupported,
+        format!("{} isn't supported by this module", what),
+    )
+}
+
+/// Create a module that compiles functions to memory, so they can be called by the host.
+///
+/// Takes ownership of `isa`, even if this fails. The target must be the host; use a null triple to
+/// create it.
+///
+/// On success, the new module is written to `module_out` and must be freed with
+/// `cranelift_module_free`.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_module_new_simplejit(
+    isa: *mut CraneliftIsa,
+    module_out: *mut *mut CraneliftModule,
+) -> CraneliftStatus {
+    wrap(|| {
+        let isa = take_handle(isa, "isa")?;
+        let module_out = output(module_out, "module_out")?;
+        *module_out = Box::into_raw(Box::new(CraneliftModule::new(simplejit(isa)?)));
+        Ok(())
+    })
+}
+
+#[cfg(feature = "simplejit")]
+fn simplejit(isa: Box<CraneliftIsa>) -> Result<Backend> {
+    Ok(Backend::SimpleJIT(Module::new(SimpleJITBuilder::with_isa(
+        isa.isa,
+    ))))
+}
+
+#[cfg(not(feature = "simplejit"))]
+fn simplejit(_isa: Box<CraneliftIsa>) -> Result<Backend> {
+    Err(Error::new(
+        CraneliftStatus::Unsupported,
+        "cranelift-c-api was built without simplejit",
+    ))
+}
+
+/// Create a module that compiles functions to an object file named `name`, to be written with
+/// `cranelift_module_write_object`.
+///
+/// Takes ownership of `isa`, even if this fails. The ISA must have the `is_pic` setting enabled.
+///
+/// On success, the new module is written to `module_out` and must be freed with
+/// `cranelift_module_free`, or by writing the object file.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_module_new_faerie(
+    isa: *mut CraneliftIsa,
+    name: *const c_char,
+    module_out: *mut *mut CraneliftModule,
+) -> CraneliftStatus {
+    wrap(|| {
+        let isa = take_handle(isa, "isa")?;
+        let name = string(name, "name")?;
+        let module_out = output(module_out, "module_out")?;
+        *module_out = Box::into_raw(Box::new(CraneliftModule::new(faerie(isa, name)?)));
+        Ok(())
+    })
+}
+
+#[cfg(feature = "faerie")]
+fn faerie(isa: Box<CraneliftIsa>, name: &str) -> Result<Backend> {
+    let builder = FaerieBuilder::new(
+        isa.isa,
+        name.to_owned(),
+        FaerieTrapCollection::Disabled,
+        FaerieBuilder::default_libcall_names(),
+    )
+    .map_err(module_error)?;
+    Ok(Backend::Faerie(Module::new(builder)))
+}
+
+#[cfg(not(feature = "faerie"))]
+fn faerie(_isa: Box<CraneliftIsa>, _name: &str) -> Result<Backend> {
+    Err(Error::new(
+        CraneliftStatus::Unsupported,
+        "cranelift-c-api was built without faerie",
+    ))
+}
+
+/// Free a module.
+///
+/// Code and data compiled by a simplejit module stay valid after it's freed. Does nothing if
+/// `module` is null.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_module_free(module: *mut CraneliftModule) {
+    if !module.is_null() {
+        drop(Box::from_raw(module));
+    }
+}
+
+/// Declare a function called `name`, taking parameters of the `params_len` types in `params` and
+/// returning the `returns_len` types in `returns`.
+///
+/// The function uses the default calling convention of the module's ISA. Declaring a name again
+/// returns the same function, as long as the signature is the same.
+///
+/// On success, the function's identifier is written to `func_out`.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_module_declare_function(
+    module: *mut CraneliftModule,
+    name: *const c_char,
+    linkage: CraneliftLinkage,
+    params: *const CraneliftType,
+    params_len: usize,
+    returns: *const CraneliftType,
+    returns_len: usize,
+    func_out: *mut CraneliftFuncId,
+) -> CraneliftStatus {
+    wrap(|| {
+        let module = handle(module, "module")?;
+        let name = string(name, "name")?;
+        let linkage = module_linkage(linkage)?;
+        let params = array(params, params_len, "params")?;
+        let returns = array(returns, returns_len, "returns")?;
+        let func_out = output(func_out, "func_out")?;
+
+        let mut sig = with_module!(module.backend, m => m.make_signature());
+        for &ty in params {
+            sig.params.push(AbiParam::new(value_type(ty)?));
+        }
+        for &ty in returns {
+            sig.returns.push(AbiParam::new(value_type(ty)?));
+        }
+        let id = with_module!(module.backend, m => m.declare_function(name, linkage, &sig))
+            .map_err(module_error)?;
+        module.functions.entry(id).or_insert((sig, State::Declared));
+        *func_out = id.index() as CraneliftFuncId;
+        Ok(())
+    })
+}
+
+/// Declare a data object called `name`, which is writable if `writable` is true.
+///
+/// On success, the data object's identifier is written to `data_out`.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_module_declare_data(
+    module: *mut CraneliftModule,
+    name: *const c_char,
+    linkage: CraneliftLinkage,
+    writable: bool,
+    data_out: *mut CraneliftDataId,
+) -> CraneliftStatus {
+    wrap(|| {
+        let module = handle(module, "module")?;
+        let name = string(name, "name")?;
+        let linkage = module_linkage(linkage)?;
+        let data_out = output(data_out, "data_out")?;
+
+        let id = with_module!(module.backend, m => m.declare_data(name, linkage, writable))
+            .map_err(module_error)?;
+        module.data_objects.entry(id).or_insert(State::Declared);
+        *data_out = id.index() as CraneliftDataId;
+        Ok(())
+    })
+}
+
+/// Define the data object `data` with the `size` bytes at `contents`, or with `size` zero bytes if
+/// `contents` is null.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_module_define_data(
+    module: *mut CraneliftModule,
+    data: CraneliftDataId,
+    contents: *const u8,
+    size: usize,
+) -> CraneliftStatus {
+    wrap(|| {
+        let module = handle(module, "module")?;
+        let id = module.data_id(data)?;
+
+        let mut data_ctx = DataContext::new();
+        if contents.is_null() {
+            data_ctx.define_zeroinit(size);
+        } else {
+            data_ctx.define(
+                slice::from_raw_parts(contents, size)
+                    .to_vec()
+                    .into_boxed_slice(),
+            );
+        }
+        with_module!(module.backend, m => m.define_data(id, &data_ctx)).map_err(module_error)?;
+        module.data_objects.insert(id, State::Defined);
+        Ok(())
+    })
+}
+
+/// Compile the function built by `builder`, and define it in `module`.
+///
+/// Takes ownership of `builder`, even if this fails. When the function doesn't pass the verifier,
+/// the error message lists the verifier errors in the function.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_module_define_function(
+    module: *mut CraneliftModule,
+    builder: *mut CraneliftBuilder,
+) -> CraneliftStatus {
+    wrap(|| {
+        let builder = take_handle(builder, "builder")?;
+        let module = handle(module, "module")?;
+
+        let (id, func) = builder.finish();
+        let id = module.func_id(id)?;
+        let mut ctx = Context::for_function(func);
+        match with_module!(module.backend, m => m.define_function(id, &mut ctx)) {
+            Ok(()) => {}
+            Err(ModuleError::Compilation(err)) => {
+                return Err(Error::new(
+                    CraneliftStatus::Compilation,
+                    pretty_error(&ctx.func, None, err),
+                ))
+            }
+            Err(err) => return Err(module_error(err)),
+        }
+        module.functions.get_mut(&id).unwrap().1 = State::Defined;
+        Ok(())
+    })
+}
+
+/// Finalize all functions and data objects defined since the last call, resolving the references
+/// between them.
+///
+/// All the functions and data objects they reference must be defined, or be imports that can be
+/// resolved.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_module_finalize(
+    module: *mut CraneliftModule,
+) -> CraneliftStatus {
+    wrap(|| {
+        let module = handle(module, "module")?;
+        with_module!(module.backend, m => m.finalize_definitions());
+        for state in module
+            .functions
+            .values_mut()
+            .map(|entry| &mut entry.1)
+            .chain(module.data_objects.values_mut())
+        {
+            if *state == State::Defined {
+                *state = State::Finalized;
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Get the address of the finalized code of the function `func` in a simplejit module.
+///
+/// On success, the address is written to `code_out`.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_module_get_function(
+    module: *mut CraneliftModule,
+    func: CraneliftFuncId,
+    code_out: *mut *const c_void,
+) -> CraneliftStatus {
+    wrap(|| {
+        let module = handle(module, "module")?;
+        let code_out = output(code_out, "code_out")?;
+        let id = module.finalized_function(func)?;
+        *code_out = match module.backend {
+            #[cfg(feature = "simplejit")]
+            Backend::SimpleJIT(ref mut m) => m.get_finalized_function(id) as *const c_void,
+            #[cfg(feature = "faerie")]
+            Backend::Faerie(_) => return Err(unsupported("getting finalized code")),
+        };
+        Ok(())
+    })
+}
+
+/// Get the address and size of the finalized data object `data` in a simplejit module.
+///
+/// On success, the address is written to `data_out` and the size in bytes to `size_out`.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_module_get_data(
+    module: *mut CraneliftModule,
+    data: CraneliftDataId,
+    data_out: *mut *mut c_void,
+    size_out: *mut usize,
+) -> CraneliftStatus {
+    wrap(|| {
+        let module = handle(module, "module")?;
+        let data_out = output(data_out, "data_out")?;
+        let size_out = output(size_out, "size_out")?;
+        let id = module.finalized_data(data)?;
+        let (ptr, size) = match module.backend {
+            #[cfg(feature = "simplejit")]
+            Backend::SimpleJIT(ref mut m) => m.get_finalized_data(id),
+            #[cfg(feature = "faerie")]
+            Backend::Faerie(_) => return Err(unsupported("getting finalized data")),
+        };
+        *data_out = ptr as *mut c_void;
+        *size_out = size;
+        Ok(())
+    })
+}
+
+/// Write the object file containing the functions and data objects defined in a faerie module to
+/// the file at `path`.
+///
+/// Takes ownership of `module`, even if this fails.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_module_write_object(
+    module: *mut CraneliftModule,
+    path: *const c_char,
+) -> CraneliftStatus {
+    wrap(|| {
+        let module = take_handle(module, "module")?;
+        let path = string(path, "path")?;
+        write_object(module.backend, path)
+    })
+}
+
+#[cfg(feature = "faerie")]
+fn write_object(backend: Backend, path: &str) -> Result<()> {
+    match backend {
+        #[cfg(feature = "simplejit")]
+        Backend::SimpleJIT(_) => Err(unsupported("writing an object file")),
+        Backend::Faerie(m) => {
+            let io_error = |err| Error::new(CraneliftStatus::Io, format!("{}: {}", path, err));
+            let file = File::create(path).map_err(|err| io_error(err.to_string()))?;
+            m.finish()
+                .write(file)
+                .map_err(|err| io_error(err.to_string()))
+        }
+    }
+}
+
+#[cfg(not(feature = "faerie"))]
+fn write_object(_backend: Backend, _path: &str) -> Result<()> {
+    Err(unsupported("writing an object file"))
+}
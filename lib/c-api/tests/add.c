@@ -0,0 +1,69 @@
+/*
+ * Build an `add` function through the C interface, compile it with simplejit, and call it.
+ *
+ * From the root of the repository:
+ *
+ *     cargo build -p cranelift-c-api
+ *     cc -I lib/c-api/include lib/c-api/tests/add.c target/debug/libcranelift_c_api.a \
+ *         -lpthread -ldl -lm -o add
+ *     ./add
+ */
+
+#include <stdio.h>
+#include <stdlib.h>
+
+#include "cranelift.h"
+
+/* Exit with the last error message if `call` fails. */
+#define CHECK(call)                                                                   \
+    do {                                                                              \
+        CraneliftStatus status = (call);                                              \
+        if (status != CRANELIFT_STATUS_OK) {                                          \
+            fprintf(stderr, "%s failed with status %d: %s\n", #call, (int)status,     \
+                    cranelift_last_error());                                          \
+            exit(1);                                                                  \
+        }                                                                             \
+    } while (0)
+
+int main(void) {
+    const char *flags[] = {"opt_level=best"};
+    CraneliftIsa *isa;
+    CraneliftModule *module;
+    CraneliftBuilder *builder;
+    CraneliftFuncId add;
+    CraneliftEbb ebb;
+    CraneliftValue x, y, sum;
+    const void *code;
+    int32_t (*add_ptr)(int32_t, int32_t);
+    const CraneliftType params[] = {CRANELIFT_TYPE_I32, CRANELIFT_TYPE_I32};
+    const CraneliftType returns[] = {CRANELIFT_TYPE_I32};
+
+    /* A null triple selects the host. */
+    CHECK(cranelift_isa_new(NULL, flags, 1, &isa));
+    CHECK(cranelift_module_new_simplejit(isa, &module));
+    CHECK(cranelift_module_declare_function(module, "add", CRANELIFT_LINKAGE_EXPORT, params, 2,
+                                            returns, 1, &add));
+
+    CHECK(cranelift_builder_new(module, add, &builder));
+    CHECK(cranelift_builder_create_ebb(builder, &ebb));
+    CHECK(cranelift_builder_append_ebb_params_for_function_params(builder, ebb));
+    CHECK(cranelift_builder_switch_to_block(builder, ebb));
+    CHECK(cranelift_builder_seal_block(builder, ebb));
+    CHECK(cranelift_builder_ebb_param(builder, ebb, 0, &x));
+    CHECK(cranelift_builder_ebb_param(builder, ebb, 1, &y));
+    CHECK(cranelift_ins_binary(builder, CRANELIFT_BINARY_IADD, x, y, &sum));
+    CHECK(cranelift_ins_return(builder, &sum, 1));
+    CHECK(cranelift_module_define_function(module, builder));
+
+    CHECK(cranelift_module_finalize(module));
+    CHECK(cranelift_module_get_function(module, add, &code));
+    add_ptr = (int32_t(*)(int32_t, int32_t))code;
+
+    if (add_ptr(40, 2) != 42 || add_ptr(-1, 1) != 0) {
+        fprintf(stderr, "add returned the wrong result\n");
+        return 1;
+    }
+    cranelift_module_free(module);
+    printf("ok\n");
+    return 0;
+}
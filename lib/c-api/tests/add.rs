@@ -0,0 +1,294 @@
+//! Build, compile, and call functions with simplejit through the C interface.
+
+extern crate cranelift_c_api;
+
+use cranelift_c_api::*;
+use std::ffi::{CStr, CString};
+use std::mem;
+use std::os::raw::{c_char, c_void};
+use std::ptr;
+
+fn check(status: CraneliftStatus) {
+    if status != CraneliftStatus::Ok {
+        panic!("{:?}: {}", status, last_error());
+    }
+}
+
+fn last_error() -> String {
+    unsafe { CStr::from_ptr(cranelift_last_error()) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+unsafe fn host_module() -> *mut CraneliftModule {
+    let opt_level = CString::new("opt_level=best").unwrap();
+    let flags = [opt_level.as_ptr()];
+    let mut isa = ptr::null_mut();
+    check(cranelift_isa_new(
+        ptr::null(),
+        flags.as_ptr(),
+        flags.len(),
+        &mut isa,
+    ));
+    let mut module = ptr::null_mut();
+    check(cranelift_module_new_simplejit(isa, &mut module));
+    module
+}
+
+unsafe fn declare(
+    module: *mut CraneliftModule,
+    name: &str,
+    params: &[CraneliftType],
+    returns: &[CraneliftType],
+) -> CraneliftFuncId {
+    let name = CString::new(name).unwrap();
+    let mut func = 0;
+    check(cranelift_module_declare_function(
+        module,
+        name.as_ptr(),
+        CRANELIFT_LINKAGE_EXPORT,
+        params.as_ptr(),
+        params.len(),
+        returns.as_ptr(),
+        returns.len(),
+        &mut func,
+    ));
+    func
+}
+
+/// Start building `func`, with the builder positioned in its entry EBB.
+unsafe fn start(
+    module: *mut CraneliftModule,
+    func: CraneliftFuncId,
+) -> (*mut CraneliftBuilder, CraneliftEbb) {
+    let mut builder = ptr::null_mut();
+    check(cranelift_builder_new(module, func, &mut builder));
+    let mut ebb = 0;
+    check(cranelift_builder_create_ebb(builder, &mut ebb));
+    check(cranelift_builder_append_ebb_params_for_function_params(
+        builder, ebb,
+    ));
+    check(cranelift_builder_switch_to_block(builder, ebb));
+    (builder, ebb)
+}
+
+unsafe fn param(builder: *mut CraneliftBuilder, ebb: CraneliftEbb, index: usize) -> CraneliftValue {
+    let mut value = 0;
+    check(cranelift_builder_ebb_param(builder, ebb, index, &mut value));
+    value
+}
+
+unsafe fn finalized(module: *mut CraneliftModule, func: CraneliftFuncId) -> *const c_void {
+    let mut code = ptr::null();
+    check(cranelift_module_get_function(module, func, &mut code));
+    code
+}
+
+#[test]
+fn add() {
+    unsafe {
+        let module = host_module();
+        let func = declare(
+            module,
+            "add",
+            &[CRANELIFT_TYPE_I32, CRANELIFT_TYPE_I32],
+            &[CRANELIFT_TYPE_I32],
+        );
+        let (builder, ebb) = start(module, func);
+        let x = param(builder, ebb, 0);
+        let y = param(builder, ebb, 1);
+        let mut sum = 0;
+        check(cranelift_ins_binary(
+            builder,
+            CRANELIFT_BINARY_IADD,
+            x,
+            y,
+            &mut sum,
+        ));
+        check(cranelift_ins_return(builder, &sum, 1));
+        check(cranelift_builder_seal_all_blocks(builder));
+        check(cranelift_module_define_function(module, builder));
+        check(cranelift_module_finalize(module));
+
+        let add: extern "C" fn(i32, i32) -> i32 = mem::transmute(finalized(module, func));
+        assert_eq!(add(40, 2), 42);
+        assert_eq!(add(-1, 1), 0);
+        cranelift_module_free(module);
+    }
+}
+
+/// Sum the integers below `n` in a loop, with variables, and call the function from another one
+/// that adds a constant loaded from a data object.
+#[test]
+fn loop_and_call() {
+    unsafe {
+        let module = host_module();
+        let i64s = [CRANELIFT_TYPE_I64];
+        let sum = declare(module, "sum", &i64s, &i64s);
+        let caller = declare(module, "caller", &i64s, &i64s);
+
+        let name = CString::new("offset").unwrap();
+        let mut data = 0;
+        check(cranelift_module_declare_data(
+            module,
+            name.as_ptr(),
+            CRANELIFT_LINKAGE_LOCAL,
+            false,
+            &mut data,
+        ));
+        // 1000, in little-endian byte order.
+        let contents: [u8; 8] = [0xe8, 0x03, 0, 0, 0, 0, 0, 0];
+        check(cranelift_module_define_data(
+            module,
+            data,
+            contents.as_ptr(),
+            contents.len(),
+        ));
+
+        // sum(n): i = 0; total = 0; while i != n { total += i; i += 1 }; return total
+        let (b, entry) = start(module, sum);
+        check(cranelift_builder_seal_block(b, entry));
+        let n = param(b, entry, 0);
+        let (i, total) = (0, 1);
+        check(cranelift_builder_declare_var(b, i, CRANELIFT_TYPE_I64));
+        check(cranelift_builder_declare_var(b, total, CRANELIFT_TYPE_I64));
+        let mut zero = 0;
+        check(cranelift_ins_iconst(b, CRANELIFT_TYPE_I64, 0, &mut zero));
+        check(cranelift_builder_def_var(b, i, zero));
+        check(cranelift_builder_def_var(b, total, zero));
+        let (mut header, mut body, mut exit) = (0, 0, 0);
+        check(cranelift_builder_create_ebb(b, &mut header));
+        check(cranelift_builder_create_ebb(b, &mut body));
+        check(cranelift_builder_create_ebb(b, &mut exit));
+        check(cranelift_ins_jump(b, header, ptr::null(), 0));
+
+        check(cranelift_builder_switch_to_block(b, header));
+        let mut v = 0;
+        check(cranelift_builder_use_var(b, i, &mut v));
+        let mut done = 0;
+        check(cranelift_ins_icmp(
+            b,
+            CRANELIFT_INTCC_EQUAL,
+            v,
+            n,
+            &mut done,
+        ));
+        check(cranelift_ins_brnz(b, done, exit, ptr::null(), 0));
+        check(cranelift_ins_jump(b, body, ptr::null(), 0));
+
+        check(cranelift_builder_switch_to_block(b, body));
+        check(cranelift_builder_seal_block(b, body));
+        let (mut t, mut one, mut next) = (0, 0, 0);
+        check(cranelift_builder_use_var(b, total, &mut t));
+        check(cranelift_builder_use_var(b, i, &mut v));
+        check(cranelift_ins_binary(b, CRANELIFT_BINARY_IADD, t, v, &mut t));
+        check(cranelift_builder_def_var(b, total, t));
+        check(cranelift_ins_iconst(b, CRANELIFT_TYPE_I64, 1, &mut one));
+        check(cranelift_ins_binary(
+            b,
+            CRANELIFT_BINARY_IADD,
+            v,
+            one,
+            &mut next,
+        ));
+        check(cranelift_builder_def_var(b, i, next));
+        check(cranelift_ins_jump(b, header, ptr::null(), 0));
+        check(cranelift_builder_seal_block(b, header));
+
+        check(cranelift_builder_switch_to_block(b, exit));
+        check(cranelift_builder_seal_block(b, exit));
+        check(cranelift_builder_use_var(b, total, &mut t));
+        check(cranelift_ins_return(b, &t, 1));
+        check(cranelift_module_define_function(module, b));
+
+        // caller(n): return sum(n) + offset
+        let (b, entry) = start(module, caller);
+        let n = param(b, entry, 0);
+        let mut result = 0;
+        check(cranelift_ins_call(b, module, sum, &n, 1, &mut result, 1));
+        let (mut addr, mut offset) = (0, 0);
+        check(cranelift_ins_data_addr(b, module, data, &mut addr));
+        check(cranelift_ins_load(
+            b,
+            CRANELIFT_TYPE_I64,
+            addr,
+            0,
+            &mut offset,
+        ));
+        check(cranelift_ins_binary(
+            b,
+            CRANELIFT_BINARY_IADD,
+            result,
+            offset,
+            &mut result,
+        ));
+        check(cranelift_ins_return(b, &result, 1));
+        check(cranelift_builder_seal_all_blocks(b));
+        check(cranelift_module_define_function(module, b));
+
+        check(cranelift_module_finalize(module));
+        let caller: extern "C" fn(i64) -> i64 = mem::transmute(finalized(module, caller));
+        assert_eq!(caller(0), 1000);
+        assert_eq!(caller(10), 1045);
+        cranelift_module_free(module);
+    }
+}
+
+#[test]
+fn errors() {
+    unsafe {
+        let mut isa = ptr::null_mut();
+        let triple = CString::new("pdp11").unwrap();
+        let status = cranelift_isa_new(triple.as_ptr(), ptr::null(), 0, &mut isa);
+        assert_eq!(status, CraneliftStatus::InvalidArgument);
+        assert_eq!(last_error(), "invalid target triple 'pdp11'");
+        assert!(isa.is_null());
+
+        let flag = CString::new("opt_level=fastest-ever").unwrap();
+        let flags: [*const c_char; 1] = [flag.as_ptr()];
+        let status = cranelift_isa_new(ptr::null(), flags.as_ptr(), 1, &mut isa);
+        assert_eq!(status, CraneliftStatus::InvalidSetting);
+        assert!(last_error().starts_with("opt_level=fastest-ever: "));
+
+        let status = cranelift_isa_new(ptr::null(), ptr::null(), 0, ptr::null_mut());
+        assert_eq!(status, CraneliftStatus::NullPointer);
+        assert_eq!(last_error(), "`isa_out` must not be null");
+
+        // Returning an `i64` from a function returning `i32` is caught by the verifier.
+        let module = host_module();
+        let func = declare(module, "bad", &[CRANELIFT_TYPE_I64], &[CRANELIFT_TYPE_I32]);
+        let (builder, ebb) = start(module, func);
+        let x = param(builder, ebb, 0);
+        assert_eq!(
+            cranelift_builder_ebb_param(builder, ebb, 1, &mut 0),
+            CraneliftStatus::InvalidArgument
+        );
+        assert_eq!(
+            cranelift_ins_binary(builder, 99, x, x, &mut 0),
+            CraneliftStatus::InvalidArgument
+        );
+        assert_eq!(last_error(), "unknown binary operation 99");
+        assert_eq!(
+            cranelift_ins_return(builder, &1234, 1),
+            CraneliftStatus::InvalidArgument
+        );
+        check(cranelift_ins_return(builder, &x, 1));
+        check(cranelift_builder_seal_all_blocks(builder));
+        assert_eq!(
+            cranelift_module_define_function(module, builder),
+            CraneliftStatus::Compilation
+        );
+        assert!(last_error().contains("return v0"), "{}", last_error());
+
+        let mut code = ptr::null();
+        assert_eq!(
+            cranelift_module_get_function(module, func, &mut code),
+            CraneliftStatus::InvalidArgument
+        );
+        let path = CString::new("bad.o").unwrap();
+        assert_eq!(
+            cranelift_module_write_object(module, path.as_ptr()),
+            CraneliftStatus::Unsupported
+        );
+    }
+}
@@ -0,0 +1,168 @@
+//! Compile a corpus of functions on several threads that share a single `TargetIsa`.
+//!
+//! Usage: `cargo run --release --example parallel -- [threads] [files or directories...]`
+//!
+//! The functions are read from the x86 `test compile` files among the given `.clif` files, or
+//! among the filetests of this repository by default. The corpus is compiled once with a fresh
+//! `Context` for each function, and once with a `Context` per thread which is cleared and reused
+//! between functions, and the time taken by each is printed.
+
+extern crate cranelift_codegen;
+extern crate cranelift_reader;
+extern crate target_lexicon;
+
+use cranelift_codegen::ir::Function;
+use cranelift_codegen::isa::{self, TargetIsa};
+use cranelift_codegen::settings::{self, Configurable};
+use cranelift_codegen::Context;
+use cranelift_reader::parse_test;
+use std::env;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use target_lexicon::Triple;
+
+/// The number of times the corpus is compiled by each method.
+const ROUNDS: usize = 20;
+
+fn main() {
+    let mut args = env::args().skip(1).peekable();
+    let threads = match args.peek().and_then(|arg| arg.parse().ok()) {
+        Some(threads) => {
+            args.next();
+            threads
+        }
+        None => 4,
+    };
+    let mut paths: Vec<PathBuf> = args.map(PathBuf::from).collect();
+    if paths.is_empty() {
+        paths.push(Path::new(env!("CARGO_MANIFEST_DIR")).join("../../filetests"));
+    }
+
+    let mut flag_builder = settings::builder();
+    flag_builder.set("opt_level", "best").unwrap();
+    let isa_builder = isa::lookup(Triple::from_str("x86_64").unwrap()).unwrap();
+    let isa: Arc<TargetIsa> = Arc::from(isa_builder.finish(settings::Flags::new(flag_builder)));
+
+    let mut files = Vec::new();
+    for path in &paths {
+        collect_files(path, &mut files);
+    }
+    let corpus = Arc::new(read_corpus(&files, &*isa));
+    println!(
+        "Compiling {} functions {} times on {} threads",
+        corpus.len(),
+        ROUNDS,
+        threads
+    );
+
+    let (fresh_time, fresh_size) = run(&isa, &corpus, threads, compile_fresh);
+    println!("Fresh contexts:  {}", format_duration(fresh_time));
+    let (reused_time, reused_size) = run(&isa, &corpus, threads, compile_reused);
+    println!("Reused contexts: {}", format_duration(reused_time));
+    assert_eq!(
+        fresh_size, reused_size,
+        "Reusing a context changed the code"
+    );
+}
+
+/// Add the `.clif` files in `path` to `files`.
+fn collect_files(path: &Path, files: &mut Vec<PathBuf>) {
+    if path.is_dir() {
+        let mut entries: Vec<PathBuf> = fs::read_dir(path)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        entries.sort();
+        for entry in entries {
+            collect_files(&entry, files);
+        }
+    } else if path.extension().map_or(false, |ext| ext == "clif") {
+        files.push(path.to_path_buf());
+    }
+}
+
+/// Read the functions of the x86 `test compile` files in `files` that compile for `isa`.
+fn read_corpus(files: &[PathBuf], isa: &TargetIsa) -> Vec<Function> {
+    let mut corpus = Vec::new();
+    for path in files {
+        let mut text = String::new();
+        File::open(path).unwrap().read_to_string(&mut text).unwrap();
+        let file = match parse_test(&text, None, None) {
+            Ok(file) => file,
+            Err(_) => continue,
+        };
+        let is_x86 = file
+            .isa_spec
+            .unique_isa()
+            .map_or(false, |isa| isa.name() == "x86");
+        if !is_x86 || !file.commands.iter().any(|cmd| cmd.command == "compile") {
+            continue;
+        }
+        for (func, _) in file.functions {
+            // Some of the tests are expected to fail.
+            if Context::for_function(func.clone()).compile(isa).is_ok() {
+                corpus.push(func);
+            }
+        }
+    }
+    corpus
+}
+
+/// Compile `corpus` `ROUNDS` times with `compile` on `threads` threads.
+///
+/// Returns the time taken and the total size of the generated code.
+fn run(
+    isa: &Arc<TargetIsa>,
+    corpus: &Arc<Vec<Function>>,
+    threads: usize,
+    compile: fn(&TargetIsa, &[Function]) -> usize,
+) -> (Duration, usize) {
+    let chunk_size = (corpus.len() + threads - 1) / threads;
+    let start = Instant::now();
+    let handles: Vec<_> = (0..threads)
+        .map(|thread| {
+            let isa = Arc::clone(isa);
+            let corpus = Arc::clone(corpus);
+            thread::spawn(move || {
+                let begin = (thread * chunk_size).min(corpus.len());
+                let end = (begin + chunk_size).min(corpus.len());
+                (0..ROUNDS)
+                    .map(|_| compile(&*isa, &corpus[begin..end]))
+                    .sum::<usize>()
+            })
+        })
+        .collect();
+    let size = handles.into_iter().map(|h| h.join().unwrap()).sum();
+    (start.elapsed(), size)
+}
+
+/// Compile each function in a new `Context`.
+fn compile_fresh(isa: &TargetIsa, funcs: &[Function]) -> usize {
+    let mut size = 0;
+    for func in funcs {
+        let mut ctx = Context::for_function(func.clone());
+        size += ctx.compile(isa).unwrap().total_size as usize;
+    }
+    size
+}
+
+/// Compile all the functions in one `Context`, which is cleared between them.
+fn compile_reused(isa: &TargetIsa, funcs: &[Function]) -> usize {
+    let mut size = 0;
+    let mut ctx = Context::new();
+    for func in funcs {
+        ctx.clear();
+        ctx.func = func.clone();
+        size += ctx.compile(isa).unwrap().total_size as usize;
+    }
+    size
+}
+
+fn format_duration(dur: Duration) -> String {
+    format!("{}.{:03} s", dur.as_secs(), dur.subsec_nanos() / 1_000_000)
+}
@@ -63,6 +63,11 @@ Load = InstructionFormat(memflags, VALUE, offset32)
 LoadComplex = InstructionFormat(memflags, VARIABLE_ARGS, offset32)
 Store = InstructionFormat(memflags, VALUE, VALUE, offset32)
 StoreComplex = InstructionFormat(memflags, VALUE, VARIABLE_ARGS, offset32)
+LoadSymbol = InstructionFormat(
+        memflags, VALUE, entities.global_value, offset32)
+StoreSymbol = InstructionFormat(
+        memflags, VARIABLE_ARGS, entities.global_value, offset32)
+LoadGlobal = InstructionFormat(memflags, entities.global_value, offset32)
 
 StackLoad = InstructionFormat(stack_slot, offset32)
 StackStore = InstructionFormat(VALUE, stack_slot, offset32)
@@ -80,10 +85,11 @@ RegSpill = InstructionFormat(
 RegFill = InstructionFormat(
         VALUE, ('src', entities.stack_slot), ('dst', regunit))
 
-Trap = InstructionFormat(trapcode)
-CondTrap = InstructionFormat(VALUE, trapcode)
-IntCondTrap = InstructionFormat(intcc, VALUE, trapcode)
-FloatCondTrap = InstructionFormat(floatcc, VALUE, trapcode)
+Trap = InstructionFormat(trapcode, ('payload', uimm32))
+CondTrap = InstructionFormat(VALUE, trapcode, ('payload', uimm32))
+IntCondTrap = InstructionFormat(intcc, VALUE, trapcode, ('payload', uimm32))
+FloatCondTrap = InstructionFormat(
+        floatcc, VALUE, trapcode, ('payload', uimm32))
 
 # Finally extract the names of global values in this module.
 InstructionFormat.extract_names(globals())
@@ -8,7 +8,7 @@ from __future__ import absolute_import
 from cdsl.operands import Operand, VARIABLE_ARGS
 from cdsl.typevar import TypeVar
 from cdsl.instructions import Instruction, InstructionGroup
-from base.types import f32, f64, b1, iflags, fflags
+from base.types import i64, f32, f64, b1, iflags, fflags
 from base.immediates import imm64, uimm8, uimm32, ieee32, ieee64, offset32
 from base.immediates import boolean, intcc, floatcc, memflags, regunit
 from base.immediates import trapcode
@@ -34,7 +34,7 @@ Any = TypeVar(
         ints=True, floats=True, bools=True, scalars=True, simd=True)
 Mem = TypeVar(
         'Mem', 'Any type that can be stored in memory',
-        ints=True, floats=True, simd=True)
+        ints=True, floats=True, bools=(1, 1), simd=True)
 MemTo = TypeVar(
         'MemTo', 'Any type that can be stored in memory',
         ints=True, floats=True, simd=True)
@@ -138,8 +138,8 @@ br_table = Instruction(
         Indirect branch via jump table.
 
         Use ``x`` as an unsigned index into the jump table ``JT``. If a jump
-        table entry is found, branch to the corresponding EBB. If no entry was
-        found or the index is out-of-bounds, branch to the given default EBB.
+        table entry is found, branch to the corresponding EBB. If the entry is
+        a hole or the index is out-of-bounds, branch to the given default EBB.
 
         Note that this branch instruction can't pass arguments to the targeted
         blocks. Split critical edges as needed to work around this.
@@ -158,7 +158,7 @@ jump_table_entry = Instruction(
     Currently, the only type supported is entries which are relative to the
     base of the jump table.
     """,
-    ins=(x, addr, Size, JT), outs=entry)
+    ins=(x, addr, Size, JT), outs=entry, can_load=True)
 
 jump_table_base = Instruction(
     'jump_table_base', r"""
@@ -182,11 +182,17 @@ indirect_jump_table_br = Instruction(
     is_branch=True, is_indirect_branch=True, is_terminator=True)
 
 code = Operand('code', trapcode)
+payload = Operand(
+        'payload', uimm32, doc='Value recorded with the trap, or 0 for none')
 trap = Instruction(
         'trap', r"""
         Terminate execution unconditionally.
+
+        The ``payload`` isn't encoded in the instruction. It is passed to the
+        trap sink along with ``code``, so the runtime can tell apart traps
+        with the same code, like different assertions.
         """,
-        ins=code, is_terminator=True, can_trap=True)
+        ins=(code, payload), is_terminator=True, can_trap=True)
 
 trapz = Instruction(
         'trapz', r"""
@@ -194,7 +200,7 @@ trapz = Instruction(
 
         if ``c`` is non-zero, execution continues at the following instruction.
         """,
-        ins=(c, code), can_trap=True)
+        ins=(c, code, payload), can_trap=True)
 
 trapnz = Instruction(
         'trapnz', r"""
@@ -202,7 +208,7 @@ trapnz = Instruction(
 
         if ``c`` is zero, execution continues at the following instruction.
         """,
-        ins=(c, code), can_trap=True)
+        ins=(c, code, payload), can_trap=True)
 
 Cond = Operand('Cond', intcc)
 f = Operand('f', iflags)
@@ -211,7 +217,7 @@ trapif = Instruction(
         'trapif', r"""
         Trap when condition is true in integer CPU flags.
         """,
-        ins=(Cond, f, code), can_trap=True)
+        ins=(Cond, f, code, payload), can_trap=True)
 
 Cond = Operand('Cond', floatcc)
 f = Operand('f', fflags)
@@ -220,7 +226,7 @@ trapff = Instruction(
         'trapff', r"""
         Trap when condition is true in floating point CPU flags.
         """,
-        ins=(Cond, f, code), can_trap=True)
+        ins=(Cond, f, code, payload), can_trap=True)
 
 rvals = Operand('rvals', VARIABLE_ARGS, doc='return values')
 
@@ -667,6 +673,22 @@ nop = Instruction(
         Note: this doesn't compile to a machine code nop
         """)
 
+a = Operand('a', i64, doc='The current value of the cycle counter')
+
+read_cycle_counter = Instruction(
+        'read_cycle_counter', r"""
+        Read the cycle counter of the processor.
+
+        The counter increases monotonically, at a rate that depends on the
+        target. It is only meant for measuring elapsed time, like for
+        self-profiling code. Targets without a cheap counter instruction call
+        the ``CycleCounter`` library routine instead.
+
+        This instruction is non-deterministic, so it is only allowed when the
+        ``enable_cycle_counter`` setting is enabled.
+        """,
+        outs=a, other_side_effects=True)
+
 c = Operand('c', Testable, doc='Controlling value to test')
 x = Operand('x', Any, doc='Value to use when `c` is true')
 y = Operand('y', Any, doc='Value to use when `c` is false')
@@ -690,6 +712,19 @@ selectif = Instruction(
         """,
         ins=(cc, flags, x, y), outs=a)
 
+c = Operand('c', Any, doc='Controlling value to test')
+bitselect = Instruction(
+        'bitselect', r"""
+        Conditional select of bits.
+
+        For each bit in ``c``, select the corresponding bit of ``x`` if the
+        bit is 1 and the corresponding bit of ``y`` if it is 0. All the
+        operands have the same type, so a boolean vector must be converted
+        with :inst:`bmask` before it can control the selection of integer
+        lanes.
+        """,
+        ins=(c, x, y), outs=a)
+
 x = Operand('x', Any)
 
 copy = Instruction(
@@ -876,7 +911,9 @@ vselect = Instruction(
         Vector lane select.
 
         Select lanes from ``x`` or ``y`` controlled by the lanes of the boolean
-        vector ``c``.
+        vector ``c``. The lanes of ``c`` have the same width as the lanes of
+        ``x`` and ``y``, like the result of comparing ``x`` and ``y`` with
+        :inst:`icmp` or :inst:`fcmp`.
         """,
         ins=(c, x, y), outs=a)
 
@@ -890,6 +927,18 @@ splat = Instruction(
         """,
         ins=x, outs=a)
 
+scalar_to_vector = Instruction(
+        'scalar_to_vector', r"""
+        Scalar to vector.
+
+        Return a vector whose lane 0 is ``x``. The other lanes are undefined.
+
+        Targets that hold scalars in the low lane of their vector registers
+        can implement this without any code, so it is cheaper than
+        :inst:`splat` or :inst:`insertlane` when only lane 0 is used.
+        """,
+        ins=x, outs=a)
+
 x = Operand('x', TxN, doc='SIMD vector to modify')
 y = Operand('y', TxN.lane_of(), doc='New lane value')
 Idx = Operand('Idx', uimm8, doc='Lane index')
@@ -943,7 +992,9 @@ icmp = Instruction(
         ====== ======== =========
 
         When this instruction compares integer vectors, it returns a boolean
-        vector of lane-wise comparisons.
+        vector of lane-wise comparisons. The boolean lanes have the same width
+        as the compared lanes, so comparing two :type:`i32x4` values produces
+        a :type:`b32x4`.
         """,
         ins=(Cond, x, y), outs=a)
 
@@ -1074,6 +1125,42 @@ srem = Instruction(
         """,
         ins=(x, y), outs=a, can_trap=True)
 
+imin = Instruction(
+        'imin', r"""
+        Signed integer minimum: :math:`a := x < y ? x : y`.
+
+        The preopt pass forms this instruction from a :inst:`select` of the
+        operands of a signed :inst:`icmp`.
+        """,
+        ins=(x, y), outs=a)
+
+imax = Instruction(
+        'imax', r"""
+        Signed integer maximum: :math:`a := x > y ? x : y`.
+        """,
+        ins=(x, y), outs=a)
+
+umin = Instruction(
+        'umin', r"""
+        Unsigned integer minimum: :math:`a := x < y ? x : y`.
+        """,
+        ins=(x, y), outs=a)
+
+umax = Instruction(
+        'umax', r"""
+        Unsigned integer maximum: :math:`a := x > y ? x : y`.
+        """,
+        ins=(x, y), outs=a)
+
+iabs = Instruction(
+        'iabs', r"""
+        Integer absolute value: :math:`a := x < 0 ? -x : x`.
+
+        The result wraps around like :inst:`isub`, so the absolute value of
+        :math:`-2^{B-1}` is :math:`-2^{B-1}`.
+        """,
+        ins=x, outs=a)
+
 a = Operand('a', iB)
 x = Operand('x', iB)
 Y = Operand('Y', imm64)
@@ -1155,6 +1242,8 @@ c_in = Operand('c_in', b1, doc="Input carry flag")
 c_out = Operand('c_out', b1, doc="Output carry flag")
 b_in = Operand('b_in', b1, doc="Input borrow flag")
 b_out = Operand('b_out', b1, doc="Output borrow flag")
+c_if_out = Operand('c_out', iflags, doc="Output carry flag")
+b_if_out = Operand('b_out', iflags, doc="Output borrow flag")
 
 iadd_cin = Instruction(
         'iadd_cin', r"""
@@ -1187,6 +1276,21 @@ iadd_cout = Instruction(
         """,
         ins=(x, y), outs=(a, c_out))
 
+iadd_ifcout = Instruction(
+        'iadd_ifcout', r"""
+        Add integers with carry out in the CPU flags.
+
+        Same as :inst:`iadd_cout`, but the carry output is a flags value. The
+        carry is tested with the ``ult`` condition code, so the flags can be
+        used directly by :inst:`brif` and :inst:`trapif` instead of being
+        materialized as a boolean. The other condition codes are not
+        meaningful for these flags.
+
+        Polymorphic over all scalar integer types, but does not support vector
+        types.
+        """,
+        ins=(x, y), outs=(a, c_if_out))
+
 iadd_carry = Instruction(
         'iadd_carry', r"""
         Add integers with carry in and out.
@@ -1234,6 +1338,21 @@ isub_bout = Instruction(
         """,
         ins=(x, y), outs=(a, b_out))
 
+isub_ifbout = Instruction(
+        'isub_ifbout', r"""
+        Subtract integers with borrow out in the CPU flags.
+
+        Same as :inst:`isub_bout`, but the borrow output is a flags value. The
+        borrow is tested with the ``ult`` condition code, so the flags can be
+        used directly by :inst:`brif` and :inst:`trapif` instead of being
+        materialized as a boolean. The other condition codes are not
+        meaningful for these flags.
+
+        Polymorphic over all scalar integer types, but does not support vector
+        types.
+        """,
+        ins=(x, y), outs=(a, b_if_out))
+
 isub_borrow = Instruction(
         'isub_borrow', r"""
         Subtract integers with borrow in and out.
@@ -1687,6 +1806,30 @@ fmax = Instruction(
         """,
         ins=(x, y), outs=a)
 
+a = Operand('a', Float, 'The smaller of ``x`` and ``y``')
+
+fmin_pseudo = Instruction(
+        'fmin_pseudo', r"""
+        Floating point minimum as :math:`a := x < y ? x : y`.
+
+        Unlike :inst:`fmin`, this returns ``y`` when the operands are
+        unordered or when both are zeroes, so the result depends on the order
+        of the operands. It is the :inst:`select` of an :inst:`fcmp` that the
+        preopt pass replaces, and it is a single instruction on x86.
+        """,
+        ins=(x, y), outs=a)
+
+a = Operand('a', Float, 'The larger of ``x`` and ``y``')
+
+fmax_pseudo = Instruction(
+        'fmax_pseudo', r"""
+        Floating point maximum as :math:`a := x > y ? x : y`.
+
+        Like :inst:`fmin_pseudo`, this returns ``y`` when the operands are
+        unordered or when both are zeroes.
+        """,
+        ins=(x, y), outs=a)
+
 a = Operand('a', Float, '``x`` rounded to integral value')
 
 ceil = Instruction(
@@ -7,20 +7,21 @@ patterns that describe how base instructions can be transformed to other base
 instructions that are legal.
 """
 from __future__ import absolute_import
-from .immediates import intcc, imm64, ieee32, ieee64
+from .immediates import intcc, floatcc, imm64, ieee32, ieee64
 from . import instructions as insts
 from . import types
 from .instructions import uextend, sextend, ireduce
 from .instructions import iadd, iadd_cout, iadd_cin, iadd_carry, iadd_imm
 from .instructions import isub, isub_bin, isub_bout, isub_borrow, irsub_imm
-from .instructions import imul, imul_imm
+from .instructions import imul, imul_imm, umulhi
 from .instructions import sdiv, sdiv_imm, udiv, udiv_imm
 from .instructions import srem, srem_imm, urem, urem_imm
 from .instructions import band, bor, bxor, isplit, iconcat
 from .instructions import bnot, band_not, bor_not, bxor_not
 from .instructions import band_imm, bor_imm, bxor_imm
 from .instructions import icmp, icmp_imm, ifcmp, ifcmp_imm
-from .instructions import iconst, bint, select
+from .instructions import iconst, bint, bextend, select, bitselect
+from .instructions import vsplit, vconcat, vselect
 from .instructions import ishl, ishl_imm, sshr, sshr_imm, ushr, ushr_imm
 from .instructions import rotl, rotl_imm, rotr, rotr_imm
 from .instructions import f32const, f64const
@@ -85,14 +86,37 @@ expand.custom_legalize(insts.trapnz, 'expand_cond_trap')
 expand.custom_legalize(insts.br_table, 'expand_br_table')
 expand.custom_legalize(insts.select, 'expand_select')
 
+# Custom expansion for lane-wise selects, which select bits with a mask of the
+# same type as the selected values.
+expand.custom_legalize(insts.vselect, 'expand_vselect')
+
+# Custom expansions for conditional branches on integer types that are too
+# small or too large for the target. The branch is rewritten to test an
+# extended or reduced value instead.
+for group in [narrow, widen]:
+    group.custom_legalize(insts.brz, 'expand_cond_branch')
+    group.custom_legalize(insts.brnz, 'expand_cond_branch')
+
 # Custom expansions for floating point constants.
 # These expansions require bit-casting or creating constant pool entries.
 expand.custom_legalize(insts.f32const, 'expand_fconst')
 expand.custom_legalize(insts.f64const, 'expand_fconst')
 
 # Custom expansions for stack memory accesses.
-expand.custom_legalize(insts.stack_load, 'expand_stack_load')
-expand.custom_legalize(insts.stack_store, 'expand_stack_store')
+# Small integer types are legalized by widening, which needs the same
+# expansions.
+for group in [expand, widen]:
+    group.custom_legalize(insts.stack_load, 'expand_stack_load')
+    group.custom_legalize(insts.stack_store, 'expand_stack_store')
+
+# Custom widening of instructions with an immediate operand that is
+# zero-extended along with the argument. Immediates are stored sign-extended
+# from the type, which an Rtl can't change.
+for inst in [
+        insts.iadd_imm, insts.imul_imm, insts.udiv_imm, insts.urem_imm,
+        insts.irsub_imm, insts.band_imm, insts.bor_imm, insts.bxor_imm,
+        insts.icmp_imm]:
+    widen.custom_legalize(inst, 'widen_imm')
 
 x = Var('x')
 y = Var('y')
@@ -110,6 +134,7 @@ b4 = Var('b4')
 b_in = Var('b_in')
 b_int = Var('b_int')
 c = Var('c')
+p = Var('p')
 c1 = Var('c1')
 c2 = Var('c2')
 c3 = Var('c3')
@@ -135,6 +160,8 @@ yl = Var('yl')
 yh = Var('yh')
 al = Var('al')
 ah = Var('ah')
+cl = Var('cl')
+ch = Var('ch')
 cc = Var('cc')
 ptr = Var('ptr')
 flags = Var('flags')
@@ -161,6 +188,23 @@ narrow.legalize(
             a << iconcat(al, ah)
         ))
 
+# The high half of the product is the high half of the double-width product of
+# the low halves, plus the low halves of the cross products. `xh * yh` only
+# contributes to the bits above the result.
+narrow.legalize(
+        a << imul(x, y),
+        Rtl(
+            (xl, xh) << isplit(x),
+            (yl, yh) << isplit(y),
+            a1 << imul(xh, yl),
+            a2 << imul(xl, yh),
+            a3 << iadd(a1, a2),
+            a4 << umulhi(xl, yl),
+            ah << iadd(a3, a4),
+            al << imul(xl, yl),
+            a << iconcat(al, ah)
+        ))
+
 for bitop in [band, bor, bxor]:
     narrow.legalize(
             a << bitop(x, y),
@@ -182,6 +226,60 @@ narrow.legalize(
             a << iconcat(al, ah)
         ))
 
+# Lane-wise operations on vectors are narrowed by operating on the two halves
+# of the vectors, until the halves are scalars. The scalar comparisons produce
+# `b1` values that are extended to the width of the boolean lanes. The type
+# inference can't tell the two cases apart, so the comparisons are narrowed
+# for each vector type of up to 256 bits.
+for lane_ty in [types.i8, types.i16, types.i32, types.i64]:
+    lanes = 2
+    while lanes * lane_ty.bits <= 256:
+        if lanes > 2:
+            narrow.legalize(
+                    a << icmp.bind(lane_ty.by(lanes))(cc, x, y),
+                    Rtl(
+                        (xl, xh) << vsplit(x),
+                        (yl, yh) << vsplit(y),
+                        al << icmp(cc, xl, yl),
+                        ah << icmp(cc, xh, yh),
+                        a << vconcat(al, ah)
+                    ))
+        else:
+            narrow.legalize(
+                    a << icmp.bind(lane_ty.by(lanes))(cc, x, y),
+                    Rtl(
+                        (xl, xh) << vsplit(x),
+                        (yl, yh) << vsplit(y),
+                        b1 << icmp(cc, xl, yl),
+                        b2 << icmp(cc, xh, yh),
+                        al << bextend(b1),
+                        ah << bextend(b2),
+                        a << vconcat(al, ah)
+                    ))
+        lanes *= 2
+
+narrow.legalize(
+        a << vselect(c, x, y),
+        Rtl(
+            (cl, ch) << vsplit(c),
+            (xl, xh) << vsplit(x),
+            (yl, yh) << vsplit(y),
+            al << vselect(cl, xl, yl),
+            ah << vselect(ch, xh, yh),
+            a << vconcat(al, ah)
+        ))
+
+narrow.legalize(
+        a << vselect(c, x, y),
+        Rtl(
+            (cl, ch) << vsplit(c),
+            (xl, xh) << vsplit(x),
+            (yl, yh) << vsplit(y),
+            al << select(cl, xl, yl),
+            ah << select(ch, xh, yh),
+            a << vconcat(al, ah)
+        ))
+
 
 def widen_one_arg(signed, op):
     # type: (bool, Instruction) -> None
@@ -227,41 +325,32 @@ def widen_two_arg(signed, op):
                 ))
 
 
-def widen_imm(signed, op):
-    # type: (bool, Instruction) -> None
+def widen_signed_imm(op):
+    # type: (Instruction) -> None
     for int_ty in [types.i8, types.i16]:
-        if signed:
-            widen.legalize(
-                a << op.bind(int_ty)(b, c),
-                Rtl(
-                    x << sextend.i32(b),
-                    z << op.i32(x, c),
-                    a << ireduce.bind(int_ty)(z)
-                ))
-        else:
-            widen.legalize(
-                a << op.bind(int_ty)(b, c),
-                Rtl(
-                    x << uextend.i32(b),
-                    z << op.i32(x, c),
-                    a << ireduce.bind(int_ty)(z)
-                ))
+        widen.legalize(
+            a << op.bind(int_ty)(b, c),
+            Rtl(
+                x << sextend.i32(b),
+                z << op.i32(x, c),
+                a << ireduce.bind(int_ty)(z)
+            ))
 
 
 # int ops
 for binop in [iadd, isub, imul, udiv, urem]:
     widen_two_arg(False, binop)
 
-for binop in [sdiv, srem]:
+for binop in [sdiv, srem, insts.imin, insts.imax]:
     widen_two_arg(True, binop)
 
-for binop in [iadd_imm, imul_imm, udiv_imm, urem_imm]:
-    widen_imm(False, binop)
+for binop in [insts.umin, insts.umax]:
+    widen_two_arg(False, binop)
 
-for binop in [sdiv_imm, srem_imm]:
-    widen_imm(True, binop)
+widen_one_arg(True, insts.iabs)
 
-widen_imm(False, irsub_imm)
+for binop in [sdiv_imm, srem_imm]:
+    widen_signed_imm(binop)
 
 # bit ops
 widen_one_arg(False, bnot)
@@ -269,9 +358,6 @@ widen_one_arg(False, bnot)
 for binop in [band, bor, bxor, band_not, bor_not, bxor_not]:
     widen_two_arg(False, binop)
 
-for binop in [band_imm, bor_imm, bxor_imm]:
-    widen_imm(False, binop)
-
 widen_one_arg(False, insts.popcnt)
 
 for (int_ty, num) in [(types.i8, 24), (types.i16, 16)]:
@@ -356,6 +442,32 @@ widen.legalize(
         a << ireduce(b)
     ))
 
+# Each bit of the result comes from `x` where the mask is set and from `y`
+# where it is clear.
+expand.legalize(
+        a << bitselect(c, x, y),
+        Rtl(
+            a1 << band(x, c),
+            a2 << band_not(y, c),
+            a << bor(a1, a2)
+        ))
+
+# A `b1` is stored in memory as a byte that is 1 for true and 0 for false.
+# Any non-zero byte is loaded as true.
+expand.legalize(
+    store.b1(flags, a, ptr, offset),
+    Rtl(
+        b << bint.i32(a),
+        insts.istore8(flags, b, ptr, offset)
+    ))
+
+expand.legalize(
+    a << load.b1(flags, ptr, offset),
+    Rtl(
+        b << insts.uload8.i32(flags, ptr, offset),
+        a << icmp_imm(intcc.ne, b, imm64(0))
+    ))
+
 for int_ty in [types.i8, types.i16]:
     widen.legalize(
         br_table.bind(int_ty)(x, y, z),
@@ -374,6 +486,17 @@ for int_ty in [types.i8, types.i16]:
         )
     )
 
+for int_ty in [types.i8, types.i16]:
+    widen.legalize(
+        a << select.bind(int_ty)(c, b, d),
+        Rtl(
+            x << uextend.i32(b),
+            y << uextend.i32(d),
+            z << select.i32(c, x, y),
+            a << ireduce.bind(int_ty)(z)
+        )
+    )
+
 for int_ty in [types.i8, types.i16]:
     for op in [ushr_imm, ishl_imm]:
         widen.legalize(
@@ -411,12 +534,6 @@ for int_ty in [types.i8, types.i16]:
     for w_cc in [
         intcc.eq, intcc.ne, intcc.ugt, intcc.ult, intcc.uge, intcc.ule
     ]:
-        widen.legalize(
-            a << insts.icmp_imm.bind(int_ty)(w_cc, b, c),
-            Rtl(
-                x << uextend.i32(b),
-                a << insts.icmp_imm(w_cc, x, c)
-            ))
         widen.legalize(
             a << insts.icmp.bind(int_ty)(w_cc, b, c),
             Rtl(
@@ -425,12 +542,6 @@ for int_ty in [types.i8, types.i16]:
                 a << insts.icmp.i32(w_cc, x, y)
             ))
     for w_cc in [intcc.sgt, intcc.slt, intcc.sge, intcc.sle]:
-        widen.legalize(
-            a << insts.icmp_imm.bind(int_ty)(w_cc, b, c),
-            Rtl(
-                x << sextend.i32(b),
-                a << insts.icmp_imm(w_cc, x, c)
-            ))
         widen.legalize(
             a << insts.icmp.bind(int_ty)(w_cc, b, c),
             Rtl(
@@ -515,7 +626,8 @@ expand.legalize(
         a << isub(a1, y)
     ))
 
-# Rotates and shifts.
+# Rotates and shifts. The immediate amount is already reduced modulo the type
+# width, so it always fits in an i32.
 for inst_imm,      inst in [
         (rotl_imm, rotl),
         (rotr_imm, rotr),
@@ -556,6 +668,14 @@ expand.legalize(
             a << bxor(x, y)
         ))
 
+# A `b1` is inverted by testing it as an integer.
+expand.legalize(
+        a << bnot.b1(x),
+        Rtl(
+            y << bint.i32(x),
+            a << icmp_imm(intcc.eq, y, imm64(0))
+        ))
+
 # Expand bitrev
 # Adapted from Stack Overflow.
 # https://stackoverflow.com/questions/746171/most-efficient-algorithm-for-bit-reversal-from-msb-lsb-to-lsb-msb-in-c
@@ -570,7 +690,7 @@ widen.legalize(
             b1 << band_imm(b, imm64(0xcc)),
             b2 << ushr_imm(b1, imm64(2)),
             b3 << band_imm(b, imm64(0x33)),
-            b4 << ushr_imm(b3, imm64(2)),
+            b4 << ishl_imm(b3, imm64(2)),
             c << bor(b2, b4),
             c1 << band_imm(c, imm64(0xf0)),
             c2 << ushr_imm(c1, imm64(4)),
@@ -590,7 +710,7 @@ widen.legalize(
             b1 << band_imm(b, imm64(0xcccc)),
             b2 << ushr_imm(b1, imm64(2)),
             b3 << band_imm(b, imm64(0x3333)),
-            b4 << ushr_imm(b3, imm64(2)),
+            b4 << ishl_imm(b3, imm64(2)),
             c << bor(b2, b4),
             c1 << band_imm(c, imm64(0xf0f0)),
             c2 << ushr_imm(c1, imm64(4)),
@@ -615,7 +735,7 @@ expand.legalize(
             b1 << band_imm(b, imm64(0xcccccccc)),
             b2 << ushr_imm(b1, imm64(2)),
             b3 << band_imm(b, imm64(0x33333333)),
-            b4 << ushr_imm(b3, imm64(2)),
+            b4 << ishl_imm(b3, imm64(2)),
             c << bor(b2, b4),
             c1 << band_imm(c, imm64(0xf0f0f0f0)),
             c2 << ushr_imm(c1, imm64(4)),
@@ -643,7 +763,7 @@ expand.legalize(
             b1 << band_imm(b, imm64(0xcccccccccccccccc)),
             b2 << ushr_imm(b1, imm64(2)),
             b3 << band_imm(b, imm64(0x3333333333333333)),
-            b4 << ushr_imm(b3, imm64(2)),
+            b4 << ishl_imm(b3, imm64(2)),
             c << bor(b2, b4),
             c1 << band_imm(c, imm64(0xf0f0f0f0f0f0f0f0)),
             c2 << ushr_imm(c1, imm64(4)),
@@ -692,17 +812,84 @@ for ty,             minus_zero in [
 
 expand.custom_legalize(insts.br_icmp, 'expand_br_icmp')
 
+# Minimum, maximum, and absolute value as a select of a comparison. Integers
+# too wide for the target are narrowed after the expansion.
+for group in [narrow, expand]:
+    for inst,           cond in [
+            (insts.imin, intcc.slt),
+            (insts.imax, intcc.sgt),
+            (insts.umin, intcc.ult),
+            (insts.umax, intcc.ugt)]:
+        group.legalize(
+                a << inst(x, y),
+                Rtl(
+                    c << icmp(cond, x, y),
+                    a << select(c, x, y)
+                ))
+    group.legalize(
+            a << insts.iabs(x),
+            Rtl(
+                b << irsub_imm(x, imm64(0)),
+                c << icmp_imm(intcc.slt, x, imm64(0)),
+                a << select(c, b, x)
+            ))
+
+for inst,                  cond in [
+        (insts.fmin_pseudo, floatcc.lt),
+        (insts.fmax_pseudo, floatcc.gt)]:
+    expand.legalize(
+            a << inst(x, y),
+            Rtl(
+                c << insts.fcmp(cond, x, y),
+                a << select(c, x, y)
+            ))
+
 # Expansions using CPU flags.
 
 expand_flags.legalize(
-    insts.trapnz(x, c),
+    insts.trapnz(x, c, p),
     Rtl(
         a << insts.ifcmp_imm(x, imm64(0)),
-        insts.trapif(intcc.ne, a, c)
+        insts.trapif(intcc.ne, a, c, p)
     ))
 expand_flags.legalize(
-    insts.trapz(x, c),
+    insts.trapz(x, c, p),
     Rtl(
         a << insts.ifcmp_imm(x, imm64(0)),
-        insts.trapif(intcc.eq, a, c)
+        insts.trapif(intcc.eq, a, c, p)
+    ))
+
+# Minimum, maximum, and absolute value as a conditional move.
+for inst,           cond in [
+        (insts.imin, intcc.slt),
+        (insts.imax, intcc.sgt),
+        (insts.umin, intcc.ult),
+        (insts.umax, intcc.ugt)]:
+    expand_flags.legalize(
+            a << inst(x, y),
+            Rtl(
+                f << ifcmp(x, y),
+                a << insts.selectif(cond, f, x, y)
+            ))
+expand_flags.legalize(
+        a << insts.iabs(x),
+        Rtl(
+            b << irsub_imm(x, imm64(0)),
+            f << ifcmp_imm(x, imm64(0)),
+            a << insts.selectif(intcc.slt, f, b, x)
+        ))
+
+# The carry of an add and the borrow of a subtract as CPU flags, for
+# architectures without encodings that set them.
+expand_flags.legalize(
+    (a, f) << insts.iadd_ifcout(x, y),
+    Rtl(
+        a << iadd(x, y),
+        f << ifcmp(a, x)
+    ))
+expand_flags.legalize(
+    (a, f) << insts.isub_ifbout(x, y),
+    Rtl(
+        a << isub(x, y),
+        f << ifcmp(x, y)
     ))
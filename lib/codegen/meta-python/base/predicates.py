@@ -29,10 +29,10 @@ class IsColocatedData(FieldPredicate):
     colocated.
     """
 
-    def __init__(self):
-        # type: () -> None
+    def __init__(self, field=UnaryGlobalValue.global_value):
+        # type: (FormatField) -> None
         super(IsColocatedData, self).__init__(
-            UnaryGlobalValue.global_value, 'is_colocated_data', ('func',))
+            field, 'is_colocated_data', ('func',))
 
 
 class LengthEquals(FieldPredicate):
@@ -38,6 +38,9 @@ call_conv = EnumSetting(
                             x64 and ARM
         - baldrdash: SpiderMonkey WebAssembly convention
         - probestack: specialized convention for the probestack function
+        - raw: no convention, for testing. The locations of the parameters
+               and return values are given by the signature, and they are
+               not checked against any other convention.
 
         The default calling convention may be overridden by individual
         functions.
@@ -48,7 +51,8 @@ call_conv = EnumSetting(
         'system_v',
         'windows_fastcall',
         'baldrdash',
-        'probestack'
+        'probestack',
+        'raw'
 )
 
 # Note that Cranelift doesn't currently need an is_pie flag, because PIE is
@@ -56,6 +60,29 @@ call_conv = EnumSetting(
 # `colocated` flag on external functions and global values.
 is_pic = BoolSetting("Enable Position-Independent Code generation")
 
+code_model = EnumSetting(
+        """
+        Code model, which limits the distance between the generated code and
+        the symbols it refers to:
+
+        - large: Symbols can be anywhere in the address space, so their
+          addresses are materialized as 64-bit absolute constants, and calls
+          to them are made indirectly.
+        - medium: Functions are within 2 GB of the code, so they can be called
+          and addressed PC-relatively, but data symbols can be anywhere.
+        - small: Both functions and data symbols are within 2 GB of the code,
+          in the low 2 GB of the address space, so the absolute address of a
+          data symbol fits in a 32-bit displacement.
+
+        Colocated symbols are always addressed PC-relatively. When
+        ``is_pic`` is enabled, the symbols that aren't colocated are accessed
+        through the GOT and PLT with 32-bit PC-relative offsets, which is the
+        small PIC code model, regardless of this setting.
+
+        This setting only affects 64-bit targets.
+        """,
+        'large', 'medium', 'small')
+
 colocated_libcalls = BoolSetting(
         """
         Use colocated libcalls.
@@ -73,6 +100,11 @@ avoid_div_traps = BoolSetting(
         This is primarily used by SpiderMonkey which doesn't install a signal
         handler for SIGFPE, but expects a SIGILL trap for division by zero.
 
+        When this is not set, the native division instructions are used, and
+        the trap code of their trap site is `int_divz`, including for the
+        overflow of a signed division. `srem` is always expanded to produce 0
+        for `INT_MIN % -1`, which traps natively on x86.
+
         On ISAs like ARM where the native division instructions don't trap,
         this setting has no effect - explicit checks are always inserted.
         """)
@@ -81,7 +113,8 @@ enable_float = BoolSetting(
         """
         Enable the use of floating-point instructions
 
-        Disabling use of floating-point instructions is not yet implemented.
+        When this is disabled, the verifier and the legalizer reject functions
+        with values of floating point types.
         """,
         default=True)
 
@@ -96,13 +129,28 @@ enable_nan_canonicalization = BoolSetting(
         default=False)
 
 enable_simd = BoolSetting(
-        """Enable the use of SIMD instructions.""",
+        """
+        Enable the use of SIMD instructions.
+
+        When this is disabled, the verifier and the legalizer reject functions
+        with values of vector types.
+        """,
         default=True)
 
 enable_atomics = BoolSetting(
         """Enable the use of atomic instructions""",
         default=True)
 
+enable_cycle_counter = BoolSetting(
+        """
+        Enable the use of the `read_cycle_counter` instruction.
+
+        The cycle counter makes the behavior of the generated code
+        non-deterministic. When this is disabled, the verifier and the
+        legalizer reject functions using it.
+        """,
+        default=False)
+
 #
 # Settings specific to the `baldrdash` calling convention.
 #
@@ -165,4 +213,87 @@ jump_tables_enabled = BoolSetting(
         """,
         default=True)
 
+#
+# Heap options.
+#
+emit_trap_tables_for_guarded_heaps = BoolSetting(
+        """
+        Record the heap accesses that rely on guard pages in the trap tables.
+
+        When a `heap_addr` is legalized without an explicit bounds check, an
+        out-of-bounds access shows up as a fault in the guard pages. With this
+        setting, the loads and stores using such an address lose their
+        `notrap` flag, so code emission reports them to the `TrapSink` with
+        the `heap_oob` trap code. A signal handler can then use the trap
+        tables to turn the fault into a trap.
+        """)
+
+check_heap_alignment = BoolSetting(
+        """
+        Check that all heap accesses are naturally aligned.
+
+        WebAssembly translation marks the heaps it accesses with the
+        `check_alignment` flag, so every load and store traps with the
+        `heap_misaligned` trap code when its address isn't a multiple of its
+        size. This is useful for finding undefined behavior in the guest.
+        """)
+
+hoist_global_value_loads = BoolSetting(
+        """
+        Load each `readonly` global value once per function.
+
+        Before legalization, all the `global_value` instructions of a load
+        global value whose chain of loads is `readonly` are replaced with a
+        single instruction at the common dominator of their uses, so the loads
+        aren't repeated in every block using the global value. Global values
+        loaded without the `readonly` flag, like the bound of a dynamic heap,
+        are still loaded at each use.
+        """)
+
+#
+# Code size options.
+#
+merge_returns = BoolSetting(
+        """
+        Merge the returns of a function into a single epilogue.
+
+        Before legalization, the `return` instructions of a function returning
+        at most two values are replaced with jumps to a single EBB holding the
+        only `return`, so the function gets one epilogue instead of one per
+        return. This makes functions with many early returns smaller, at the
+        cost of a jump at each of them.
+        """)
+
+#
+# Memory operation options.
+#
+inline_memory_ops_limit = NumSetting(
+        """
+        The largest size in bytes of an inline memory copy or fill.
+
+        The legalizer replaces calls to the `memcpy`, `memmove`, and `memset`
+        library routines with a constant size up to this limit by a sequence
+        of loads and stores. A value of 0 disables the expansion.
+        """,
+        default=64)
+
+#
+# Register allocation options.
+#
+callee_saved_strategy = EnumSetting(
+        """
+        How the register allocator uses callee-saved registers.
+
+        Every callee-saved register used by a function must be saved by its
+        prologue and restored by its epilogue:
+
+        - auto: Callee-saved registers are allocated like any other register,
+          in the order of the target ISA's register classes.
+        - minimize: Callee-saved registers are allocated last, so they are
+          only used, and saved, when the other registers are exhausted.
+        - prefer: In functions that contain calls, callee-saved registers are
+          allocated first. Leaf functions allocate them last like `minimize`.
+        """,
+        'auto', 'minimize', 'prefer')
+
 group.close(globals())
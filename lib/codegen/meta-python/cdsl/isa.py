@@ -309,10 +309,19 @@ class EncRecipe(object):
     branch instructions. It is an `(origin, bits)` tuple describing the exact
     range that can be encoded in a branch instruction.
 
+    The `stack_disp_bits` argument must be provided for recipes that address
+    stack slots with a signed displacement narrower than 32 bits. Such
+    encodings can only be used once the stack frame is laid out and the
+    displacement is known to fit, so they are picked by the shrinking pass.
+
     For ISAs that use CPU flags in `iflags` and `fflags` value types, the
     `clobbers_flags` is used to indicate instruction encodings that clobbers
     the CPU flags, so they can't be used where a flag value is live.
 
+    On 64-bit ISAs, the `zero_extends` argument indicates recipes that write
+    the whole register of their 32-bit results, with the upper half cleared.
+    Extending such a result to 64 bits doesn't need any code.
+
     :param name: Short mnemonic name for this recipe.
     :param format: All encoded instructions must have this
             :py:class:`InstructionFormat`.
@@ -321,7 +330,9 @@ class EncRecipe(object):
     :param ins: Tuple of register constraints for value operands.
     :param outs: Tuple of register constraints for results.
     :param branch_range: `(origin, bits)` range for branches.
+    :param stack_disp_bits: Number of bits in stack slot displacements.
     :param clobbers_flags: This instruction clobbers `iflags` and `fflags`.
+    :param zero_extends: This instruction zero-extends its 32-bit results.
     :param instp: Instruction predicate.
     :param isap: ISA predicate.
     :param emit: Rust code for binary emission.
@@ -336,7 +347,9 @@ class EncRecipe(object):
             outs,                     # type: ConstraintSeq
             compute_size=None,        # type: str
             branch_range=None,        # type: BranchRange
+            stack_disp_bits=None,     # type: int
             clobbers_flags=True,      # type: bool
+            zero_extends=False,       # type: bool
             instp=None,               # type: PredNode
             isap=None,                # type: PredNode
             emit=None                 # type: str
@@ -349,7 +362,9 @@ class EncRecipe(object):
         self.compute_size = compute_size if compute_size is not None \
             else 'base_size'
         self.branch_range = branch_range
+        self.stack_disp_bits = stack_disp_bits
         self.clobbers_flags = clobbers_flags
+        self.zero_extends = zero_extends
         self.instp = instp
         self.isap = isap
         self.emit = emit
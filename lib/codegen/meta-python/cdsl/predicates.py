@@ -30,13 +30,13 @@ try:
     if TYPE_CHECKING:
         from .formats import InstructionFormat, InstructionContext, FormatField  # noqa
         from .instructions import Instruction  # noqa
-        from .settings import BoolSetting, SettingGroup  # noqa
+        from .settings import BoolSetting, EnumPredicate, SettingGroup  # noqa
         from .types import ValueType  # noqa
         from .typevar import TypeVar  # noqa
         PredContext = Union[SettingGroup, InstructionFormat,
                             InstructionContext]
-        PredLeaf = Union[BoolSetting, 'FieldPredicate', 'TypePredicate',
-                         'CtrlTypePredicate']
+        PredLeaf = Union[BoolSetting, EnumPredicate, 'FieldPredicate',
+                         'TypePredicate', 'CtrlTypePredicate']
         PredNode = Union[PredLeaf, 'Predicate']
         # A predicate key is a (recursive) tuple of primitive types that
         # uniquely describes a predicate. It is used for interning.
@@ -341,6 +341,51 @@ class IsUnsignedInt(FieldPredicate):
         assert scale >= 0 and scale < width
 
 
+class IsBitmaskImm(FieldPredicate):
+    """
+    Instruction predicate that checks if an immediate instruction format field
+    can be encoded as an ARM64 logical immediate.
+
+    :param field: `FormatField` to be checked.
+    :param width: Width of the operation, 32 or 64 bits.
+
+    The predicate is true if the low `width` bits of the field are a repeating
+    pattern of 2, 4, 8, 16, 32, or 64-bit elements, each of which is a rotated
+    run of ones. The values with all bits clear or all bits set aren't
+    encodable.
+    """
+
+    def __init__(self, field, width):
+        # type: (FormatField, int) -> None
+        super(IsBitmaskImm, self).__init__(
+                field, 'is_bitmask_imm', (width,))
+        self.width = width
+        assert width == 32 or width == 64
+
+
+class IsThumbImm(FieldPredicate):
+    """
+    Instruction predicate that checks if an immediate instruction format field
+    can be encoded as a Thumb-2 modified immediate.
+
+    :param field: `FormatField` to be checked.
+    :param negated: Check the negated value of the field instead.
+    :param inverted: Check the bitwise inverse of the field instead.
+
+    The predicate is true if the low 32 bits of the value are an 8-bit value
+    shifted left by any amount, or an 8-bit value repeated in every byte,
+    every even byte, or every odd byte.
+    """
+
+    def __init__(self, field, negated=False, inverted=False):
+        # type: (FormatField, bool, bool) -> None
+        super(IsThumbImm, self).__init__(
+                field, 'is_thumb_imm',
+                ('true' if negated else 'false',
+                 'true' if inverted else 'false'))
+        assert not (negated and inverted)
+
+
 class TypePredicate(object):
     """
     An instruction predicate that checks the type of an SSA argument value.
@@ -1,6 +1,7 @@
 """Classes for describing settings and groups of settings."""
 from __future__ import absolute_import
 from collections import OrderedDict
+from . import camel_case
 from .predicates import Predicate
 
 try:
@@ -166,6 +167,64 @@ class EnumSetting(Setting):
         # type: (Any) -> int
         return self.values.index(value)
 
+    def is_value(self, value):
+        # type: (str) -> EnumPredicate
+        """
+        Get a predicate that is true when this setting has the value `value`.
+        """
+        return EnumPredicate(self, value)
+
+
+class EnumPredicate(object):
+    """
+    A leaf predicate that is true when an `EnumSetting` has a given value.
+
+    :param setting: The enum setting.
+    :param value: One of the values of `setting`.
+    """
+
+    def __init__(self, setting, value):
+        # type: (EnumSetting, str) -> None
+        assert value in setting.values, \
+            "{} is not a value of {}".format(value, setting)
+        self.setting = setting
+        self.value = value
+
+    def __str__(self):
+        # type: () -> str
+        return '{}={}'.format(self.setting, self.value)
+
+    def predicate_context(self):
+        # type: () -> SettingGroup
+        return self.setting.group
+
+    def predicate_key(self):
+        # type: () -> PredKey
+        assert self.setting.name, "Can't compute key before setting is named"
+        return ('enum', self.setting.group.name, self.setting.name,
+                self.value)
+
+    def predicate_leafs(self, leafs):
+        # type: (Set[PredLeaf]) -> None
+        leafs.add(self)
+
+    def rust_predicate(self, prec):
+        # type: (int) -> str
+        """
+        Return the Rust code comparing the setting to the value.
+
+        The emitted code assumes that the setting group exists as a local
+        variable.
+        """
+        group = self.setting.group
+        s = '{}.{}() == ::{}::{}::{}'.format(
+                group.name, self.setting.name, group.qual_mod,
+                camel_case(self.setting.name), camel_case(self.value))
+        # The `!` operator binds tighter than `==`.
+        if prec > 2:
+            s = '({})'.format(s)
+        return s
+
 
 class SettingGroup(object):
     """
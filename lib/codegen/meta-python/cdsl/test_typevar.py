@@ -186,10 +186,11 @@ class TestTypeVar(TestCase):
         with self.assertRaises(AssertionError):
             x2.double_width()
         self.assertEqual(str(x2.half_width()), '`half_width(x2)`')
-        self.assertEqual(x2.half_width().rust_expr(), 'x2.half_width()')
+        self.assertEqual(
+                x2.half_width().rust_expr(), 'x2.half_width().unwrap()')
         self.assertEqual(
                 x2.half_width().double_width().rust_expr(),
-                'x2.half_width().double_width()')
+                'x2.half_width().unwrap().double_width().unwrap()')
 
         x3 = TypeVar('x3', 'up to i32', ints=(8, 32))
         self.assertEqual(str(x3.double_width()), '`double_width(x3)`')
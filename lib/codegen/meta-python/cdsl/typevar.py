@@ -25,6 +25,17 @@ MAX_LANES = 256
 MAX_BITS = 64
 MAX_BITVEC = MAX_BITS * MAX_LANES
 
+# Rust expressions applying each derived function to an `ir::Type`. The
+# functions that can fail return an `Option` in Rust, but a type variable is
+# only derived where the function applies.
+RUST_DERIVED_FUNCS = {
+        'lane_of': 'lane_type()',
+        'as_bool': 'as_bool()',
+        'half_width': 'half_width().unwrap()',
+        'double_width': 'double_width().unwrap()',
+        'half_vector': 'half_vector().unwrap()',
+        'double_vector': 'by(2).unwrap()'}
+
 
 def int_log2(x):
     # type: (int) -> int
@@ -855,8 +866,8 @@ class TypeVar(object):
         Get a Rust expression that computes the type of this type variable.
         """
         if self.is_derived:
-            return '{}.{}()'.format(
-                    self.base.rust_expr(), self.derived_func)
+            func = RUST_DERIVED_FUNCS[self.derived_func]
+            return '{}.{}'.format(self.base.rust_expr(), func)
         elif self.singleton_type():
             return self.singleton_type().rust_name()
         else:
@@ -89,7 +89,7 @@ def gen_recipe(recipe, fmt):
                     recipe.name.lower(), args)
         else:
             fmt.multi_line(recipe.emit)
-            fmt.line('return;')
+            fmt.line('return Ok(());')
 
 
 def unwrap_values(args, prefix, values, fmt):
@@ -130,6 +130,9 @@ def gen_isa(isa, fmt):
     fmt.doc_comment(
             '''
             Emit binary machine code for `inst` for the {} ISA.
+
+            Returns an error if a displacement doesn't fit in the encoding of
+            `inst`.
             '''.format(isa.name))
     if len(isa.all_recipes) == 0:
         # No encoding recipes: Emit a stub.
@@ -138,7 +141,7 @@ def gen_isa(isa, fmt):
             fmt.line('inst: Inst,')
             fmt.line('_divert: &mut RegDiversions,')
             fmt.line('_sink: &mut CS,')
-        with fmt.indented(') {', '}'):
+        with fmt.indented(') -> CodegenResult<()> {', '}'):
             fmt.line('bad_encoding(func, inst)')
     else:
         fmt.line('#[allow(unused_variables, unreachable_code)]')
@@ -147,7 +150,7 @@ def gen_isa(isa, fmt):
             fmt.line('inst: Inst,')
             fmt.line('divert: &mut RegDiversions,')
             fmt.line('sink: &mut CS,')
-        with fmt.indented(') {', '}'):
+        with fmt.indented(') -> CodegenResult<()> {', '}'):
             fmt.line('let encoding = func.encodings[inst];')
             fmt.line('let bits = encoding.bits();')
             with fmt.indented('match func.encodings[inst].recipe() {', '}'):
@@ -160,6 +163,7 @@ def gen_isa(isa, fmt):
             # Verifier checks the details.
             with fmt.indented('if encoding.is_legal() {', '}'):
                 fmt.line('bad_encoding(func, inst);')
+            fmt.line('Ok(())')
 
 
 def generate(isas, out_dir):
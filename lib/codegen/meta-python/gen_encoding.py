@@ -770,6 +770,14 @@ def emit_recipe_constraints(isa, fmt):
                 fmt.format(
                         'clobbers_flags: {},',
                         str(bool(r.clobbers_flags)).lower())
+                fmt.format(
+                        'zero_extends: {},',
+                        str(bool(r.zero_extends)).lower())
+                if r.stack_disp_bits is None:
+                    fmt.line('stack_disp_bits: None,')
+                else:
+                    fmt.format(
+                            'stack_disp_bits: Some({}),', r.stack_disp_bits)
 
 
 def emit_operand_constraints(
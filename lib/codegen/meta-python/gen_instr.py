@@ -412,6 +412,15 @@ def gen_opcodes(groups, fmt):
                     i.format.name, i.name)
     fmt.line()
 
+    # Generate a private table of all opcodes in numerical order.
+    with fmt.indented(
+            'const OPCODE_LIST: [Opcode; {}] = ['
+            .format(len(instrs)),
+            '];'):
+        for i in instrs:
+            fmt.format('Opcode::{},', i.camel_name)
+    fmt.line()
+
     # Generate a private opcode_name function.
     with fmt.indented('fn opcode_name(opc: Opcode) -> &\'static str {', '}'):
         m = srcgen.Match('opc')
@@ -433,9 +442,38 @@ def gen_opcodes(groups, fmt):
             else:
                 fmt.format('Some(Opcode::{}),', i.camel_name)
     fmt.line()
+
+    fmt.doc_comment('''
+                    A hash of the instruction set descriptor.
+
+                    This changes whenever an opcode is added, removed, renumbered,
+                    or changes its instruction format, so serialized IR can be
+                    checked against it.
+                    ''')
+    fmt.line(
+            'pub const INSTRUCTION_SET_HASH: u64 = 0x{:016x};'
+            .format(instruction_set_hash(instrs)))
+    fmt.line()
     return instrs
 
 
+def instruction_set_hash(instrs):
+    # type: (Sequence[Instruction]) -> int
+    """
+    Compute a 64-bit FNV-1a hash of the opcode numbering and the operand
+    layout of each instruction.
+    """
+    h = 0xcbf29ce484222325
+    for i in instrs:
+        desc = '{} {} {} {} {} {};'.format(
+                i.number, i.name, i.format.name,
+                ','.join(f.kind.name for f in i.format.imm_fields),
+                len(i.value_opnums), len(i.value_results))
+        for b in bytearray(desc.encode('utf-8')):
+            h = ((h ^ b) * 0x100000001b3) & 0xffffffffffffffff
+    return h
+
+
 def get_constraint(op, ctrl_typevar, type_sets):
     # type: (Operand, TypeVar, UniqueTable) -> str
     """
@@ -608,16 +646,26 @@ def gen_format_constructor(iform, fmt):
     proto = '{}({})'.format(iform.name, ', '.join(args))
     proto += " -> (Inst, &'f mut ir::DataFlowGraph)"
 
+    # Integer immediates are stored sign-extended from the width of the
+    # controlling type, whatever the signedness of the number passed in.
+    has_imm64 = any(f.kind.name == 'imm64' for f in iform.imm_fields)
+
     fmt.doc_comment(str(iform))
     fmt.line('#[allow(non_snake_case)]')
     with fmt.indented('fn {} {{'.format(proto), '}'):
         # Generate the instruction data.
         with fmt.indented(
-                'let data = ir::InstructionData::{} {{'.format(iform.name),
+                'let {}data = ir::InstructionData::{} {{'.format(
+                    'mut ' if has_imm64 else '', iform.name),
                 '};'):
             fmt.line('opcode,')
             gen_member_inits(iform, fmt)
 
+        if has_imm64:
+            fmt.line('let fits = data.sign_extend_imm(ctrl_typevar);')
+            fmt.line(
+                'debug_assert!(fits, "immediate out of range for {}", '
+                'ctrl_typevar);')
         fmt.line('self.build(data, ctrl_typevar)')
 
 
@@ -763,6 +811,45 @@ def gen_inst_builder(inst, fmt):
             'results[{}]'.format(i) for i in range(len(inst.value_results))))
 
 
+def gen_native_constants(fmt):
+    # type: (srcgen.Formatter) -> None
+    """
+    Emit methods for building constants from native Rust numbers.
+
+    The controlling type of the constant is implied by the Rust type of the
+    number, so the number is always in range.
+    """
+    for bits in (8, 16, 32, 64):
+        ctrl = 'types::I{}'.format(bits)
+        for rust_ty in ('i{}'.format(bits), 'u{}'.format(bits)):
+            signed = 'signed' if rust_ty[0] == 'i' else 'unsigned'
+            fmt.doc_comment(
+                    '`a = iconst.i{} N` for the {} number `N`.'
+                    .format(bits, signed))
+            fmt.line('#[allow(non_snake_case)]')
+            with fmt.indented(
+                    'fn iconst_{}(self, N: {}) -> Value {{'
+                    .format(rust_ty, rust_ty), '}'):
+                if rust_ty == 'i64':
+                    fmt.line('self.iconst({}, N)'.format(ctrl))
+                elif rust_ty == 'u64':
+                    # The bits are the same as an `i64`.
+                    fmt.line('self.iconst({}, N as i64)'.format(ctrl))
+                else:
+                    fmt.line('self.iconst({}, i64::from(N))'.format(ctrl))
+
+    for bits in (32, 64):
+        fmt.doc_comment(
+                '`a = f{}const N` for the number `N`.'.format(bits))
+        fmt.line('#[allow(non_snake_case)]')
+        with fmt.indented(
+                'fn f{}const_from(self, N: f{}) -> Value {{'
+                .format(bits, bits), '}'):
+            fmt.line(
+                'self.f{}const(ir::immediates::Ieee{}::with_float(N))'
+                .format(bits, bits))
+
+
 def gen_builder(insts, fmt):
     # type: (Sequence[Instruction], srcgen.Formatter) -> None
     """
@@ -782,11 +869,16 @@ def gen_builder(insts, fmt):
 
             There is also a method per instruction format. These methods all
             return an `Inst`.
+
+            The `iconst_*` and `f*const_from` methods build constants from
+            native Rust numbers, with the type implied by the type of the
+            number.
             """)
     with fmt.indented(
             "pub trait InstBuilder<'f>: InstBuilderBase<'f> {",  '}'):
         for inst in insts:
             gen_inst_builder(inst, fmt)
+        gen_native_constants(fmt)
         for f in InstructionFormat.all_formats:
             gen_format_constructor(f, fmt)
 
@@ -357,7 +357,9 @@ def gen_xform(xform, fmt, type_sets):
 def gen_xform_group(xgrp, fmt, type_sets):
     # type: (XFormGroup, Formatter, UniqueTable) -> None
     fmt.doc_comment("Legalize `inst`.")
-    fmt.line('#[allow(unused_variables,unused_assignments,non_snake_case)]')
+    fmt.line(
+        '#[allow(unused_variables,unused_assignments,unused_imports,'
+        'non_snake_case)]')
     with fmt.indented('pub fn {}('.format(xgrp.name)):
         fmt.line('inst: ir::Inst,')
         fmt.line('func: &mut ir::Function,')
@@ -50,7 +50,10 @@ def emit_types(fmt):
         emit_type(spec, fmt)
     for ty in ValueType.all_lane_types:
         emit_type(ty, fmt)
-    # Emit vector definitions for common SIMD sizes.
+    # Emit vector definitions for common SIMD sizes, and the smaller vectors
+    # that narrowing them produces.
+    emit_vectors(16, fmt)
+    emit_vectors(32, fmt)
     emit_vectors(64, fmt)
     emit_vectors(128, fmt)
     emit_vectors(256, fmt)
@@ -6,9 +6,12 @@ Commonly used definitions.
 from __future__ import absolute_import
 from cdsl.isa import TargetISA, CPUMode
 import base.instructions
+from base.immediates import floatcc
 from base.legalize import narrow
+from . import instructions as arm32
 
-ISA = TargetISA('arm32', [base.instructions.GROUP])  # type: TargetISA
+ISA = TargetISA(
+        'arm32', [base.instructions.GROUP, arm32.GROUP])  # type: TargetISA
 
 # CPU modes for 32-bit ARM and Thumb2.
 A32 = CPUMode('A32', ISA)
@@ -16,4 +19,20 @@ T32 = CPUMode('T32', ISA)
 
 # TODO: Refine these.
 A32.legalize_type(narrow)
-T32.legalize_type(narrow)
+
+# The floating point condition codes that can be tested with a single
+# condition after copying the `vcmp` flags to the `nzcv` register. The
+# remaining codes are handled by legalization patterns.
+supported_floatccs = [
+        floatcc.ord,
+        floatcc.uno,
+        floatcc.eq,
+        floatcc.ne,
+        floatcc.lt,
+        floatcc.le,
+        floatcc.gt,
+        floatcc.ge,
+        floatcc.ult,
+        floatcc.ule,
+        floatcc.ugt,
+        floatcc.uge]
@@ -0,0 +1,264 @@
+"""
+ARM32 Encodings.
+"""
+from __future__ import absolute_import
+from base import instructions as base
+from base import types
+from base.legalize import widen, expand_flags
+from .defs import T32
+from .recipes import VFP
+from . import recipes as r
+from . import instructions as arm32
+from .legalize import arm32_expand, arm32_narrow
+from .settings import use_vfp3
+
+T32.legalize_monomorphic(expand_flags)
+T32.legalize_type(
+    default=arm32_narrow,
+    b1=expand_flags,
+    i8=widen,
+    i16=widen,
+    i32=arm32_expand,
+    f32=arm32_expand,
+    f64=arm32_expand)
+
+# The condition codes used as encoding bits by `brzw` and `brzn`.
+EQ = 0b0000
+NE = 0b0001
+
+#
+# Integer arithmetic.
+#
+# The 32-bit data processing instructions don't set the flags unless the `S`
+# bit is set, so the register allocator can insert them between a flags
+# producer and its user.
+#
+
+for inst,               template in [
+        (base.iadd,     0xeb00),
+        (base.isub,     0xeba0),
+        (base.band,     0xea00),
+        (base.bor,      0xea40),
+        (base.bxor,     0xea80),
+        (base.band_not, 0xea20),
+        (base.bor_not,  0xea60)]:
+    T32.enc(inst.i32, r.dpr, template)
+
+T32.enc(base.band.b1, r.dpr, 0xea00)
+T32.enc(base.bor.b1, r.dpr, 0xea40)
+T32.enc(base.bxor.b1, r.dpr, 0xea80)
+
+# Double-width additions and subtractions, see `legalize.py`.
+T32.enc(arm32.adds, r.dprs, 0xeb10)
+T32.enc(arm32.adc, r.dprc, 0xeb40)
+T32.enc(arm32.subs, r.dprs, 0xebb0)
+T32.enc(arm32.sbc, r.dprc, 0xeb60)
+
+# Immediates that aren't modified immediates may still be encodable after
+# negating or inverting them for the complementary instruction.
+T32.enc(base.iadd_imm.i32, r.dpi, 0xf100)
+T32.enc(base.iadd_imm.i32, r.dpineg, 0xf1a0)
+T32.enc(base.iadd_imm.i32, r.dpi12, 0xf200)
+T32.enc(base.band_imm.i32, r.dpi, 0xf000)
+T32.enc(base.band_imm.i32, r.dpinot, 0xf020)
+T32.enc(base.bor_imm.i32, r.dpi, 0xf040)
+T32.enc(base.bor_imm.i32, r.dpinot, 0xf060)
+T32.enc(base.bxor_imm.i32, r.dpi, 0xf080)
+T32.enc(base.irsub_imm.i32, r.dpi, 0xf1c0)
+
+T32.enc(base.imul.i32, r.rrr, 0xfb00)
+T32.enc(arm32.umull, r.rrrr, 0xfba0)
+
+# The register-controlled shifts take the low byte of the shift amount, see
+# `legalize.py`. Only `ror` uses the amount modulo 32.
+T32.enc(arm32.lsl, r.rrr, 0xfa00)
+T32.enc(arm32.lsr, r.rrr, 0xfa20)
+T32.enc(arm32.asr, r.rrr, 0xfa40)
+T32.enc(base.rotr.i32.i32, r.rrr, 0xfa60)
+
+# Immediate shifts. The encoding bits are the shift type.
+T32.enc(base.ishl_imm.i32, r.shi, 0)
+T32.enc(base.ushr_imm.i32, r.shi, 1)
+T32.enc(base.sshr_imm.i32, r.shi, 2)
+T32.enc(base.rotr_imm.i32, r.shi, 3)
+
+# Integer constants. Use the modified immediates when possible, then `movw`,
+# and `movw` followed by `movt` for anything else.
+T32.enc(base.iconst.i32, r.movi, 0xf04f)
+T32.enc(base.iconst.i32, r.mvni, 0xf06f)
+T32.enc(base.iconst.i32, r.movw, 0xf240)
+T32.enc(base.iconst.i32, r.movwt, 0xf240)
+T32.enc(base.bconst.b1, r.movb, 0xf04f)
+
+#
+# Conversions.
+#
+
+T32.enc(base.uextend.i32.i8, r.ext, 0xfa5f)
+T32.enc(base.uextend.i32.i16, r.ext, 0xfa1f)
+T32.enc(base.sextend.i32.i8, r.ext, 0xfa4f)
+T32.enc(base.sextend.i32.i16, r.ext, 0xfa0f)
+
+# Reducing an integer is a no-op.
+T32.enc(base.ireduce.i8.i16, r.null, 0)
+T32.enc(base.ireduce.i8.i32, r.null, 0)
+T32.enc(base.ireduce.i16.i32, r.null, 0)
+
+# A `b1` value is represented as 0 or 1 in a register. Extract the low byte
+# anyway with `uxtb`.
+T32.enc(base.bint.i32.b1, r.ext, 0xfa5f)
+
+#
+# Register copies.
+#
+
+for ty in [types.i32, types.b1]:
+    T32.enc(base.copy.bind(ty), r.copy, 0x4600)
+    T32.enc(base.regmove.bind(ty), r.rmov, 0x4600)
+
+T32.enc(base.copy_special, r.copysp, 0x4600)
+
+#
+# Comparisons.
+#
+
+T32.enc(base.ifcmp.i32, r.cmp, 0xebb0)
+T32.enc(base.ifcmp_imm.i32, r.cmpi, 0xf1b0)
+T32.enc(base.ifcmp_imm.i32, r.cmni, 0xf110)
+T32.enc(base.icmp.i32, r.icmp, 0xebb0)
+T32.enc(base.icmp_imm.i32, r.icmpi, 0xf1b0)
+
+T32.enc(base.trueif, r.cset, 0)
+
+T32.enc(base.selectif.i32, r.csel, 0)
+# Compare the controlling `b1` value with `cmp rc, #0`.
+T32.enc(base.select.i32.b1, r.sel, 0xf1b0)
+T32.enc(base.select.b1.b1, r.sel, 0xf1b0)
+
+#
+# Control flow.
+#
+# The 16-bit branches are listed last. The shrinking pass picks them when the
+# registers allow it, and branch relaxation goes back to the longer ones when
+# the destination is out of range.
+#
+
+T32.enc(base.jump, r.bw, 0x9000)
+T32.enc(base.jump, r.bn, 0xe000)
+T32.enc(base.brif, r.bcondw, 0xf000)
+T32.enc(base.brif, r.bcondn, 0xd000)
+
+for ty in [types.i32, types.b1]:
+    T32.enc(base.brz.bind(ty), r.brzw, EQ)
+    T32.enc(base.brnz.bind(ty), r.brzw, NE)
+    T32.enc(base.brz.bind(ty), r.brzn, EQ)
+    T32.enc(base.brnz.bind(ty), r.brzn, NE)
+    T32.enc(base.brz.bind(ty), r.cb, 0xb100)
+    T32.enc(base.brnz.bind(ty), r.cb, 0xb900)
+
+# Calls and returns use `lr` as the link register. It is saved by the
+# prologue, see `abi.rs`.
+T32.enc(base.call, r.bl, 0xd000)
+T32.enc(base.call_indirect.i32, r.blx, 0x4780)
+T32.enc(base.x_return, r.ret, 0x4770)
+
+# Traps use `udf #0`.
+T32.enc(base.trap, r.trap, 0xde00)
+T32.enc(base.trapif, r.trapif, 0)
+
+#
+# Loads and stores.
+#
+
+# Loads and stores with a positive 12-bit offset, and with a small offset that
+# can be negative.
+for inst,              imm12,  imm8 in [
+        (base.load,    0xf8d0, 0xf850),
+        (base.uload8,  0xf890, 0xf810),
+        (base.sload8,  0xf990, 0xf910),
+        (base.uload16, 0xf8b0, 0xf830),
+        (base.sload16, 0xf9b0, 0xf930)]:
+    T32.enc(inst.i32.any, r.ld, imm12)
+    T32.enc(inst.i32.any, r.ld8, imm8)
+
+for inst,               imm12,  imm8 in [
+        (base.store,    0xf8c0, 0xf840),
+        (base.istore8,  0xf880, 0xf800),
+        (base.istore16, 0xf8a0, 0xf820)]:
+    T32.enc(inst.i32.any, r.st, imm12)
+    T32.enc(inst.i32.any, r.st8, imm8)
+
+# Spill slots are addressed relative to the stack pointer. Use a 32-bit access
+# for `b1`, `i8` and `i16` like the 32-bit values.
+for ty in [types.b1, types.i8, types.i16, types.i32]:
+    T32.enc(base.spill.bind(ty), r.spill, 0xf8c0)
+    T32.enc(base.regspill.bind(ty), r.regspill, 0xf8c0)
+    T32.enc(base.fill.bind(ty), r.fill, 0xf8d0)
+    T32.enc(base.regfill.bind(ty), r.regfill, 0xf8d0)
+
+#
+# Stack pointer manipulation, used by the prologue and epilogue.
+#
+
+T32.enc(base.adjust_sp_down_imm, r.adjustsp12, 0xf2a0)
+T32.enc(base.adjust_sp_down_imm, r.adjustspi, 0xf1a0)
+T32.enc(base.adjust_sp_down_imm, r.adjustsp7, 0xb080)
+T32.enc(base.adjust_sp_up_imm, r.adjustsp12, 0xf200)
+T32.enc(base.adjust_sp_up_imm, r.adjustspi, 0xf100)
+T32.enc(base.adjust_sp_up_imm, r.adjustsp7, 0xb000)
+
+T32.enc(arm32.push, r.push, 0xf84d)
+T32.enc(arm32.push, r.push16, 0xb400)
+T32.enc(arm32.push, r.pushlr, 0xb500)
+T32.enc(arm32.pop, r.pop, 0xf85d)
+T32.enc(arm32.pop, r.pop16, 0xbc00)
+
+#
+# Floating point.
+#
+# These all need VFPv3. Single precision values are only moved around, which
+# is enough to pass them in integer registers, see `abi.rs`.
+#
+
+for inst,           hw1,    hw2 in [
+        (base.fadd, 0xee30, 0x0b00),
+        (base.fsub, 0xee30, 0x0b40),
+        (base.fmul, 0xee20, 0x0b00),
+        (base.fdiv, 0xee80, 0x0b00)]:
+    T32.enc(inst.f64, r.vrrr, VFP(hw1, hw2), isap=use_vfp3)
+
+for inst,           hw1,    hw2 in [
+        (base.sqrt, 0xeeb1, 0x0bc0),
+        (base.fneg, 0xeeb1, 0x0b40),
+        (base.fabs, 0xeeb0, 0x0bc0),
+        (base.copy, 0xeeb0, 0x0b40)]:
+    T32.enc(inst.f64, r.vrr, VFP(hw1, hw2), isap=use_vfp3)
+
+T32.enc(base.regmove.f64, r.vrmov, VFP(0xeeb0, 0x0b40), isap=use_vfp3)
+T32.enc(base.copy.f32, r.vrrs, VFP(0xeeb0, 0x0a40), isap=use_vfp3)
+T32.enc(base.regmove.f32, r.vrmovs, VFP(0xeeb0, 0x0a40), isap=use_vfp3)
+
+T32.enc(base.ffcmp.f64, r.vcmp, VFP(0xeeb4, 0x0b40), isap=use_vfp3)
+T32.enc(base.fcmp.f64, r.vcmpset, VFP(0xeeb4, 0x0b40), isap=use_vfp3)
+T32.enc(base.trueff, r.fcset, 0, isap=use_vfp3)
+T32.enc(base.brff, r.bfcondw, 0xf000, isap=use_vfp3)
+T32.enc(base.brff, r.bfcondn, 0xd000, isap=use_vfp3)
+
+T32.enc(arm32.vmovdrr, r.vmovdrr, 0xec40, isap=use_vfp3)
+T32.enc(arm32.vmovrrd, r.vmovrrd, 0xec50, isap=use_vfp3)
+T32.enc(base.bitcast.f32.i32, r.vmovsr, 0xee00, isap=use_vfp3)
+T32.enc(base.bitcast.i32.f32, r.vmovrs, 0xee10, isap=use_vfp3)
+
+T32.enc(base.load.f64.any, r.vld, 0xed10, isap=use_vfp3)
+T32.enc(base.store.f64.any, r.vst, 0xed00, isap=use_vfp3)
+T32.enc(base.load.f32.any, r.vlds, 0xed10, isap=use_vfp3)
+T32.enc(base.store.f32.any, r.vsts, 0xed00, isap=use_vfp3)
+
+T32.enc(base.spill.f64, r.vspill, 0xed00, isap=use_vfp3)
+T32.enc(base.regspill.f64, r.vregspill, 0xed00, isap=use_vfp3)
+T32.enc(base.fill.f64, r.vfill, 0xed10, isap=use_vfp3)
+T32.enc(base.regfill.f64, r.vregfill, 0xed10, isap=use_vfp3)
+T32.enc(base.spill.f32, r.vspills, 0xed00, isap=use_vfp3)
+T32.enc(base.regspill.f32, r.vregspills, 0xed00, isap=use_vfp3)
+T32.enc(base.fill.f32, r.vfills, 0xed10, isap=use_vfp3)
+T32.enc(base.regfill.f32, r.vregfills, 0xed10, isap=use_vfp3)
@@ -0,0 +1,132 @@
+"""
+Supplementary instruction definitions for ARM32.
+
+This module defines additional instructions that are useful only to the ARM32
+target ISA.
+"""
+
+from base.types import i32, f64, iflags
+from cdsl.operands import Operand
+from cdsl.instructions import Instruction, InstructionGroup
+
+
+GROUP = InstructionGroup("arm32", "ARM32-specific instruction set")
+
+x = Operand('x', i32)
+y = Operand('y', i32)
+a = Operand('a', i32)
+
+push = Instruction(
+    'arm32_push', r"""
+    Pushes a value onto the stack.
+
+    Decrements the stack pointer by 4 and stores the specified value at the
+    new top of the stack.
+    """,
+    ins=x, can_store=True, other_side_effects=True)
+
+pop = Instruction(
+    'arm32_pop', r"""
+    Pops a value from the stack.
+
+    Loads a value from the top of the stack and then increments the stack
+    pointer by 4.
+    """,
+    outs=x, can_load=True, other_side_effects=True)
+
+c_in = Operand('c_in', iflags, doc='Flags holding the input carry')
+c_out = Operand('c_out', iflags, doc='Flags holding the output carry')
+
+adds = Instruction(
+    'arm32_adds', r"""
+    Add and set the flags.
+
+    Like `iadd`, but also produce the carry out of the addition in the `C`
+    flag. This is the low half of a double-width addition.
+    """,
+    ins=(x, y), outs=(a, c_out))
+
+adc = Instruction(
+    'arm32_adc', r"""
+    Add with carry.
+
+    Add `x`, `y`, and the `C` flag of `c_in`. This is the high half of a
+    double-width addition.
+    """,
+    ins=(x, y, c_in), outs=a)
+
+subs = Instruction(
+    'arm32_subs', r"""
+    Subtract and set the flags.
+
+    Like `isub`, but also produce the inverted borrow out of the subtraction
+    in the `C` flag. This is the low half of a double-width subtraction.
+    """,
+    ins=(x, y), outs=(a, c_out))
+
+sbc = Instruction(
+    'arm32_sbc', r"""
+    Subtract with carry.
+
+    Subtract `y` and the inverted `C` flag of `c_in` from `x`. This is the
+    high half of a double-width subtraction.
+    """,
+    ins=(x, y, c_in), outs=a)
+
+lo = Operand('lo', i32, doc='Low half of the product')
+hi = Operand('hi', i32, doc='High half of the product')
+
+umull = Instruction(
+    'arm32_umull', r"""
+    Unsigned integer multiplication, producing a double-length result.
+    """,
+    ins=(x, y), outs=(lo, hi))
+
+# The register-controlled shifts only use the low byte of the shift amount, so
+# shifting by 32 or more doesn't wrap around like the `ishl` family does.
+
+lsl = Instruction(
+    'arm32_lsl', r"""
+    Logical shift left by the low byte of `y`.
+
+    Unlike `ishl`, the result is 0 when the shift amount is at least 32.
+    """,
+    ins=(x, y), outs=a)
+
+lsr = Instruction(
+    'arm32_lsr', r"""
+    Logical shift right by the low byte of `y`.
+
+    Unlike `ushr`, the result is 0 when the shift amount is at least 32.
+    """,
+    ins=(x, y), outs=a)
+
+asr = Instruction(
+    'arm32_asr', r"""
+    Arithmetic shift right by the low byte of `y`.
+
+    Unlike `sshr`, every bit of the result is a copy of the sign bit when the
+    shift amount is at least 32.
+    """,
+    ins=(x, y), outs=a)
+
+d = Operand('d', f64)
+
+vmovdrr = Instruction(
+    'arm32_vmovdrr', r"""
+    Move two integer registers into a double-precision register.
+
+    The bits of `x` become the low half of `d`, and the bits of `y` become
+    the high half.
+    """,
+    ins=(x, y), outs=d)
+
+vmovrrd = Instruction(
+    'arm32_vmovrrd', r"""
+    Move a double-precision register into two integer registers.
+
+    This is the inverse of `arm32_vmovdrr`.
+    """,
+    ins=d, outs=(x, y))
+
+GROUP.close()
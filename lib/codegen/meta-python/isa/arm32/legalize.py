@@ -0,0 +1,155 @@
+"""
+Custom legalization patterns for ARM32.
+"""
+from __future__ import absolute_import
+from cdsl.ast import Var
+from cdsl.xform import Rtl, XFormGroup
+from base.immediates import imm64, floatcc
+from base import legalize as shared
+from base import instructions as insts
+from . import instructions as arm32
+from .defs import ISA
+
+arm32_expand = XFormGroup(
+        'arm32_expand',
+        """
+        Legalize instructions by expansion.
+
+        Use ARM32-specific instructions if needed.
+        """,
+        isa=ISA, chain=shared.expand_flags)
+
+arm32_narrow = XFormGroup(
+        'arm32_narrow',
+        """
+        Legalize instructions by narrowing.
+
+        Use ARM32-specific instructions if needed.
+        """,
+        isa=ISA, chain=shared.narrow)
+
+a = Var('a')
+b = Var('b')
+c = Var('c')
+x = Var('x')
+y = Var('y')
+a1 = Var('a1')
+a2 = Var('a2')
+xl = Var('xl')
+xh = Var('xh')
+yl = Var('yl')
+yh = Var('yh')
+al = Var('al')
+ah = Var('ah')
+lo = Var('lo')
+
+# The register-controlled shifts don't take the shift amount modulo 32, so
+# mask it explicitly.
+for inst,           shift in [
+        (insts.ishl, arm32.lsl),
+        (insts.ushr, arm32.lsr),
+        (insts.sshr, arm32.asr)]:
+    arm32_expand.legalize(
+            a << inst(x, y),
+            Rtl(
+                a1 << insts.band_imm(y, imm64(31)),
+                a << shift(x, a1)
+            ))
+
+# There is no rotate left instruction, but rotating right by the negated
+# amount is equivalent since `ror` takes the amount modulo 32.
+arm32_expand.legalize(
+        a << insts.rotl(x, y),
+        Rtl(
+            a1 << insts.irsub_imm(y, imm64(0)),
+            a << insts.rotr(x, a1)
+        ))
+
+arm32_expand.legalize(
+        a << insts.umulhi(x, y),
+        Rtl(
+            (lo, a) << arm32.umull(x, y)
+        ))
+
+# Floating point condition codes that can't be tested with a single condition.
+arm32_expand.legalize(
+        a << insts.fcmp(floatcc.one, x, y),
+        Rtl(
+            a1 << insts.fcmp(floatcc.ord, x, y),
+            a2 << insts.fcmp(floatcc.ne, x, y),
+            a << insts.band(a1, a2)
+        ))
+arm32_expand.legalize(
+        a << insts.fcmp(floatcc.ueq, x, y),
+        Rtl(
+            a1 << insts.fcmp(floatcc.uno, x, y),
+            a2 << insts.fcmp(floatcc.eq, x, y),
+            a << insts.bor(a1, a2)
+        ))
+
+# An `f64` is passed in a pair of integer registers in the soft-float calling
+# convention, see `abi.rs`.
+arm32_expand.legalize(
+        a << insts.bitcast.f64.i64(x),
+        Rtl(
+            (xl, xh) << insts.isplit(x),
+            a << arm32.vmovdrr(xl, xh)
+        ))
+arm32_narrow.legalize(
+        a << insts.bitcast.i64.f64(x),
+        Rtl(
+            (al, ah) << arm32.vmovrrd(x),
+            a << insts.iconcat(al, ah)
+        ))
+
+# Use the carry flag for double-width additions and subtractions.
+arm32_narrow.legalize(
+        a << insts.iadd(x, y),
+        Rtl(
+            (xl, xh) << insts.isplit(x),
+            (yl, yh) << insts.isplit(y),
+            (al, c) << arm32.adds(xl, yl),
+            ah << arm32.adc(xh, yh, c),
+            a << insts.iconcat(al, ah)
+        ))
+arm32_narrow.legalize(
+        a << insts.isub(x, y),
+        Rtl(
+            (xl, xh) << insts.isplit(x),
+            (yl, yh) << insts.isplit(y),
+            (al, b) << arm32.subs(xl, yl),
+            ah << arm32.sbc(xh, yh, b),
+            a << insts.iconcat(al, ah)
+        ))
+
+# Extending from 32 bits computes the high half, and reducing takes the low
+# half. The controlling type of `ireduce` is the narrow result type.
+arm32_narrow.legalize(
+        a << insts.uextend.i64.i32(x),
+        Rtl(
+            ah << insts.iconst.i32(imm64(0)),
+            a << insts.iconcat(x, ah)
+        ))
+arm32_narrow.legalize(
+        a << insts.sextend.i64.i32(x),
+        Rtl(
+            ah << insts.sshr_imm(x, imm64(31)),
+            a << insts.iconcat(x, ah)
+        ))
+arm32_expand.legalize(
+        a << insts.ireduce.i32.i64(x),
+        Rtl(
+            (al, ah) << insts.isplit(x),
+            a << insts.copy(al)
+        ))
+
+# The constant and the memory offset of the high half need to be computed.
+arm32_narrow.custom_legalize(insts.iconst, 'narrow_iconst')
+arm32_narrow.custom_legalize(insts.load, 'narrow_load')
+arm32_narrow.custom_legalize(insts.store, 'narrow_store')
+
+# Memory offsets that don't fit the load and store encodings are added to the
+# address instead.
+for inst in [insts.load, insts.uload8, insts.sload8, insts.uload16,
+             insts.sload16, insts.store, insts.istore8, insts.istore16]:
+    arm32_expand.custom_legalize(inst, 'expand_mem_offset')
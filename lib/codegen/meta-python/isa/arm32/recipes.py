@@ -0,0 +1,851 @@
+"""
+ARM32 Encoding recipes.
+
+The encoding recipes defined here correspond to the Thumb-2 (T32) instruction
+encodings described in the reference:
+
+    ARM Architecture Reference Manual
+    ARMv7-A and ARMv7-R edition
+    Chapter A6: Thumb Instruction Set Encoding
+
+Thumb-2 instructions are either one or two 16-bit halfwords. Unless noted
+otherwise, the encoding bits of a recipe are the first halfword of the
+instruction with the operand fields cleared, and the emitter in `binemit.rs`
+fills in the operands and the second halfword.
+"""
+from __future__ import absolute_import
+from cdsl.isa import EncRecipe
+from cdsl.predicates import IsSignedInt, IsUnsignedInt, IsThumbImm
+from cdsl.predicates import IsEqual, Or
+from cdsl.registers import Stack
+from base.formats import Unary, UnaryImm, UnaryBool, Binary, BinaryImm
+from base.formats import Ternary, MultiAry, NullAry, IntCompare
+from base.formats import IntCompareImm, IntCond, IntSelect, IntCondTrap
+from base.formats import FloatCompare, FloatCond, BranchFloat
+from base.formats import Trap, Jump, Branch, BranchInt, Call, CallIndirect
+from base.formats import Load, Store, RegMove, RegSpill, RegFill
+from base.formats import CopySpecial
+from .defs import supported_floatccs
+from .registers import GPR, GPR8, S, D, FLAG
+
+try:
+    from typing import TYPE_CHECKING  # noqa
+    if TYPE_CHECKING:
+        from cdsl.instructions import InstructionFormat  # noqa
+        from cdsl.predicates import PredNode  # noqa
+except ImportError:
+    pass
+
+
+def VFP(hw1, hw2):
+    # type: (int, int) -> int
+    """
+    Compute the encoding bits for a VFP data-processing instruction.
+
+    The instruction is `hw1` followed by `hw2`, with the register fields
+    cleared. The high bytes are the same for all these instructions, except
+    for the `sz` bit which the emitter gets from the registers. The encoding
+    bits hold the low byte of `hw1` in the low byte and the low byte of `hw2`
+    in the high byte.
+    """
+    assert hw1 & 0xff00 == 0xee00, "0x{:04x} isn't a VFP template".format(hw1)
+    assert hw2 & 0xfe00 == 0x0a00, "0x{:04x} isn't a VFP template".format(hw2)
+    return (hw1 & 0xff) | ((hw2 & 0xff) << 8)
+
+
+def floatccs(iform):
+    # type: (InstructionFormat) -> PredNode
+    """
+    Return an instruction predicate that checks if `iform.cond` is one of the
+    directly supported floating point condition codes.
+    """
+    return Or(*(IsEqual(iform.cond, cc) for cc in supported_floatccs))
+
+
+#
+# Data processing.
+#
+
+# Data processing with a register operand and a zero shift.
+#
+#   hw1: 11101 01 op S Rn
+#   hw2: 0 imm3 Rd imm2 type Rm
+dpr = EncRecipe(
+        'dpr', Binary, base_size=4, ins=(GPR, GPR), outs=GPR,
+        clobbers_flags=False,
+        emit='put_dpr(bits, in_reg0, in_reg1, out_reg0, sink);')
+
+# The flag-setting variant producing the carry.
+dprs = EncRecipe(
+        'dprs', Binary, base_size=4, ins=(GPR, GPR), outs=(GPR, FLAG.nzcv),
+        emit='put_dpr(bits, in_reg0, in_reg1, out_reg0, sink);')
+
+# The variant consuming the carry.
+dprc = EncRecipe(
+        'dprc', Ternary, base_size=4, ins=(GPR, GPR, FLAG.nzcv), outs=GPR,
+        clobbers_flags=False,
+        emit='put_dpr(bits, in_reg0, in_reg1, out_reg0, sink);')
+
+# Data processing with a modified immediate.
+#
+#   hw1: 11110 i 0 op S Rn
+#   hw2: 0 imm3 Rd imm8
+dpi = EncRecipe(
+        'dpi', BinaryImm, base_size=4, ins=GPR, outs=GPR,
+        clobbers_flags=False,
+        instp=IsThumbImm(BinaryImm.imm),
+        emit='put_dpi(bits, in_reg0, imm.into(), out_reg0, sink);')
+
+# Data processing with the negated immediate, like `sub` for `iadd_imm`.
+dpineg = EncRecipe(
+        'dpineg', BinaryImm, base_size=4, ins=GPR, outs=GPR,
+        clobbers_flags=False,
+        instp=IsThumbImm(BinaryImm.imm, negated=True),
+        emit='''
+        let imm: i64 = imm.into();
+        put_dpi(bits, in_reg0, imm.wrapping_neg(), out_reg0, sink);
+        ''')
+
+# Data processing with the inverted immediate, like `bic` for `band_imm`.
+dpinot = EncRecipe(
+        'dpinot', BinaryImm, base_size=4, ins=GPR, outs=GPR,
+        clobbers_flags=False,
+        instp=IsThumbImm(BinaryImm.imm, inverted=True),
+        emit='''
+        let imm: i64 = imm.into();
+        put_dpi(bits, in_reg0, !imm, out_reg0, sink);
+        ''')
+
+# Add or subtract a plain 12-bit immediate: `addw` or `subw`.
+#
+#   hw1: 11110 i 1 op Rn
+#   hw2: 0 imm3 Rd imm8
+dpi12 = EncRecipe(
+        'dpi12', BinaryImm, base_size=4, ins=GPR, outs=GPR,
+        clobbers_flags=False,
+        instp=IsUnsignedInt(BinaryImm.imm, 12),
+        emit='put_dpi12(bits, in_reg0, imm.into(), out_reg0, sink);')
+
+# Register-controlled shifts and multiplications.
+#
+#   hw1: 11111 op Rn
+#   hw2: 1111 Rd op Rm
+rrr = EncRecipe(
+        'rrr', Binary, base_size=4, ins=(GPR, GPR), outs=GPR,
+        clobbers_flags=False,
+        emit='put_rrr(bits, in_reg0, in_reg1, out_reg0, sink);')
+
+# Long multiplication: `umull rdlo, rdhi, rn, rm`.
+#
+#   hw1: 11111 op Rn
+#   hw2: RdLo RdHi op Rm
+rrrr = EncRecipe(
+        'rrrr', Binary, base_size=4, ins=(GPR, GPR), outs=(GPR, GPR),
+        clobbers_flags=False,
+        emit='put_umull(bits, in_reg0, in_reg1, out_reg0, out_reg1, sink);')
+
+# Shift by an immediate, as an alias of `mov` with a shifted register.
+# The encoding bits are the shift type.
+#
+#   hw1: 11101 01 0010 0 1111
+#   hw2: 0 imm3 Rd imm2 type Rm
+shi = EncRecipe(
+        'shi', BinaryImm, base_size=4, ins=GPR, outs=GPR,
+        clobbers_flags=False,
+        emit='put_shi(bits, in_reg0, imm.into(), out_reg0, sink);')
+
+# Sign or zero extension.
+#
+#   hw1: 11111 010 0 op 1111
+#   hw2: 1111 Rd 1 0 rotate Rm
+ext = EncRecipe(
+        'ext', Unary, base_size=4, ins=GPR, outs=GPR,
+        clobbers_flags=False,
+        emit='put_ext(bits, in_reg0, out_reg0, sink);')
+
+# A null unary instruction that takes a GPR register. Can be used for identity
+# copies and no-op conversions.
+null = EncRecipe('null', Unary, base_size=0, ins=GPR, outs=0, emit='')
+
+#
+# Moves and constants.
+#
+
+# Register copy with the 16-bit `mov`, which can use any register and doesn't
+# set the flags. The encoding bits are the 16-bit instruction.
+#
+#   0100 0110 D Rm Rd
+copy = EncRecipe(
+        'copy', Unary, base_size=2, ins=GPR, outs=GPR,
+        clobbers_flags=False,
+        emit='put_mov(bits, in_reg0, out_reg0, sink);')
+
+rmov = EncRecipe(
+        'rmov', RegMove, base_size=2, ins=GPR, outs=(),
+        clobbers_flags=False,
+        emit='put_mov(bits, src, dst, sink);')
+
+# Copy to or from the stack pointer.
+copysp = EncRecipe(
+        'copysp', CopySpecial, base_size=2, ins=(), outs=(),
+        clobbers_flags=False,
+        emit='put_mov(bits, src, dst, sink);')
+
+# Integer constant that is a modified immediate: `mov` or `mvn`.
+movi = EncRecipe(
+        'movi', UnaryImm, base_size=4, ins=(), outs=GPR,
+        clobbers_flags=False,
+        instp=IsThumbImm(UnaryImm.imm),
+        emit='put_dpi(bits, PC, imm.into(), out_reg0, sink);')
+
+mvni = EncRecipe(
+        'mvni', UnaryImm, base_size=4, ins=(), outs=GPR,
+        clobbers_flags=False,
+        instp=IsThumbImm(UnaryImm.imm, inverted=True),
+        emit='''
+        let imm: i64 = imm.into();
+        put_dpi(bits, PC, !imm, out_reg0, sink);
+        ''')
+
+# Integer constant with a 16-bit immediate: `movw`.
+#
+#   hw1: 11110 i 10 0 1 0 0 imm4
+#   hw2: 0 imm3 Rd imm8
+movw = EncRecipe(
+        'movw', UnaryImm, base_size=4, ins=(), outs=GPR,
+        clobbers_flags=False,
+        instp=IsUnsignedInt(UnaryImm.imm, 16),
+        emit='''
+        let imm: i64 = imm.into();
+        put_movw(bits, imm as u32, out_reg0, sink);
+        ''')
+
+# Any 32-bit integer constant: `movw` followed by `movt`.
+movwt = EncRecipe(
+        'movwt', UnaryImm, base_size=8, ins=(), outs=GPR,
+        clobbers_flags=False,
+        emit='''
+        let imm: i64 = imm.into();
+        put_movw(bits, imm as u32 & 0xffff, out_reg0, sink);
+        put_movw(MOVT, imm as u32 >> 16, out_reg0, sink);
+        ''')
+
+# Boolean constant.
+movb = EncRecipe(
+        'movb', UnaryBool, base_size=4, ins=(), outs=GPR,
+        clobbers_flags=False,
+        emit='put_dpi(bits, PC, imm as i64, out_reg0, sink);')
+
+#
+# Comparisons and flags.
+#
+# The conditional instructions are materialized with an `it` block. The
+# instructions in the block don't set the flags.
+#
+
+# Compare two registers, as an alias of `subs` writing the `pc`.
+cmp = EncRecipe(
+        'cmp', Binary, base_size=4, ins=(GPR, GPR), outs=FLAG.nzcv,
+        emit='put_dpr(bits, in_reg0, in_reg1, PC, sink);')
+
+# Compare with a modified immediate: `cmp`, or `cmn` with the negated
+# immediate.
+cmpi = EncRecipe(
+        'cmpi', BinaryImm, base_size=4, ins=GPR, outs=FLAG.nzcv,
+        instp=IsThumbImm(BinaryImm.imm),
+        emit='put_dpi(bits, in_reg0, imm.into(), PC, sink);')
+
+cmni = EncRecipe(
+        'cmni', BinaryImm, base_size=4, ins=GPR, outs=FLAG.nzcv,
+        instp=IsThumbImm(BinaryImm.imm, negated=True),
+        emit='''
+        let imm: i64 = imm.into();
+        put_dpi(bits, in_reg0, imm.wrapping_neg(), PC, sink);
+        ''')
+
+# Materialize a condition as a `b1` value: `ite cond; mov rd, #1; mov rd, #0`.
+cset = EncRecipe(
+        'cset', IntCond, base_size=10, ins=FLAG.nzcv, outs=GPR,
+        clobbers_flags=False,
+        emit='put_cset(icc2cond(cond), out_reg0, sink);')
+
+# Integer comparison with the result in a register: `cmp` followed by the
+# `cset` sequence.
+icmp = EncRecipe(
+        'icmp', IntCompare, base_size=14, ins=(GPR, GPR), outs=GPR,
+        emit='''
+        put_dpr(bits, in_reg0, in_reg1, PC, sink);
+        put_cset(icc2cond(cond), out_reg0, sink);
+        ''')
+
+icmpi = EncRecipe(
+        'icmpi', IntCompareImm, base_size=14, ins=GPR, outs=GPR,
+        instp=IsThumbImm(IntCompareImm.imm),
+        emit='''
+        put_dpi(bits, in_reg0, imm.into(), PC, sink);
+        put_cset(icc2cond(cond), out_reg0, sink);
+        ''')
+
+# Conditional select on the flags: `ite cond; mov rd, rn; mov rd, rm`.
+csel = EncRecipe(
+        'csel', IntSelect, base_size=6, ins=(FLAG.nzcv, GPR, GPR), outs=GPR,
+        clobbers_flags=False,
+        emit='''
+        put_ite(icc2cond(cond), sink);
+        put_mov(MOV, in_reg1, out_reg0, sink);
+        put_mov(MOV, in_reg2, out_reg0, sink);
+        ''')
+
+# Select on a `b1` value: `cmp rc, #0` followed by the `csel` sequence.
+sel = EncRecipe(
+        'sel', Ternary, base_size=10, ins=(GPR, GPR, GPR), outs=GPR,
+        emit='''
+        put_dpi(bits, in_reg0, 0, PC, sink);
+        put_ite(COND_NE, sink);
+        put_mov(MOV, in_reg1, out_reg0, sink);
+        put_mov(MOV, in_reg2, out_reg0, sink);
+        ''')
+
+#
+# Control flow.
+#
+# The branch displacements are relative to the address of the branch
+# instruction plus 4.
+#
+
+# Unconditional branches: `b.w` and the 16-bit `b`. The encoding bits of `bw`
+# are the second halfword, which distinguishes `b.w` from `bl`.
+bw = EncRecipe(
+        'bw', Jump, base_size=4, ins=(), outs=(),
+        branch_range=(4, 25),
+        clobbers_flags=False,
+        emit='''
+        let disp = branch_disp(func, inst, destination, sink.offset() + 4, |d| {
+            is_signed_int(d, 25, 1)
+        })?;
+        put_bw(bits, disp, sink);
+        ''')
+
+bn = EncRecipe(
+        'bn', Jump, base_size=2, ins=(), outs=(),
+        branch_range=(4, 12),
+        clobbers_flags=False,
+        emit='''
+        let disp = branch_disp(func, inst, destination, sink.offset() + 4, |d| {
+            is_signed_int(d, 12, 1)
+        })?;
+        put_bn(bits, disp, sink);
+        ''')
+
+# Conditional branches on the flags: `b<c>.w` and the 16-bit `b<c>`.
+bcondw = EncRecipe(
+        'bcondw', BranchInt, base_size=4, ins=FLAG.nzcv, outs=(),
+        branch_range=(4, 21),
+        clobbers_flags=False,
+        emit='''
+        let disp = branch_disp(func, inst, destination, sink.offset() + 4, |d| {
+            is_signed_int(d, 21, 1)
+        })?;
+        put_bcondw(bits, icc2cond(cond), disp, sink);
+        ''')
+
+bcondn = EncRecipe(
+        'bcondn', BranchInt, base_size=2, ins=FLAG.nzcv, outs=(),
+        branch_range=(4, 9),
+        clobbers_flags=False,
+        emit='''
+        let disp = branch_disp(func, inst, destination, sink.offset() + 4, |d| {
+            is_signed_int(d, 9, 1)
+        })?;
+        put_bcondn(bits, icc2cond(cond), disp, sink);
+        ''')
+
+# Conditional branches on the `vcmp` flags.
+bfcondw = EncRecipe(
+        'bfcondw', BranchFloat, base_size=4, ins=FLAG.nzcv, outs=(),
+        branch_range=(4, 21),
+        clobbers_flags=False,
+        instp=floatccs(BranchFloat),
+        emit='''
+        let disp = branch_disp(func, inst, destination, sink.offset() + 4, |d| {
+            is_signed_int(d, 21, 1)
+        })?;
+        put_bcondw(bits, fcc2cond(cond), disp, sink);
+        ''')
+
+bfcondn = EncRecipe(
+        'bfcondn', BranchFloat, base_size=2, ins=FLAG.nzcv, outs=(),
+        branch_range=(4, 9),
+        clobbers_flags=False,
+        instp=floatccs(BranchFloat),
+        emit='''
+        let disp = branch_disp(func, inst, destination, sink.offset() + 4, |d| {
+            is_signed_int(d, 9, 1)
+        })?;
+        put_bcondn(bits, fcc2cond(cond), disp, sink);
+        ''')
+
+# Compare a register with zero and branch: `cmp rn, #0` followed by `beq.w` or
+# `bne.w`. The encoding bits are the branch condition.
+brzw = EncRecipe(
+        'brzw', Branch, base_size=8, ins=GPR, outs=(),
+        branch_range=(8, 21),
+        emit='''
+        put_dpi(CMP_IMM, in_reg0, 0, PC, sink);
+        let disp = branch_disp(func, inst, destination, sink.offset() + 4, |d| {
+            is_signed_int(d, 21, 1)
+        })?;
+        put_bcondw(BCOND_W, bits, disp, sink);
+        ''')
+
+# The same with the 16-bit instructions.
+brzn = EncRecipe(
+        'brzn', Branch, base_size=4, ins=GPR8, outs=(),
+        branch_range=(6, 9),
+        emit='''
+        put_cmp16(in_reg0, sink);
+        let disp = branch_disp(func, inst, destination, sink.offset() + 4, |d| {
+            is_signed_int(d, 9, 1)
+        })?;
+        put_bcondn(BCOND_N, bits, disp, sink);
+        ''')
+
+# Compare and branch on zero: `cbz` or `cbnz`. The encoding bits are the
+# 16-bit instruction.
+#
+# These can only branch forward by 0 to 126 bytes from the address of the
+# branch instruction plus 4. Moving the origin of the 7-bit signed range
+# forward by 64 bytes covers exactly these displacements.
+cb = EncRecipe(
+        'cb', Branch, base_size=2, ins=GPR8, outs=(),
+        branch_range=(68, 7),
+        clobbers_flags=False,
+        emit='''
+        let disp = branch_disp(func, inst, destination, sink.offset() + 4, |d| {
+            is_unsigned_int(d, 7, 1)
+        })?;
+        put_cb(bits, in_reg0, disp, sink);
+        ''')
+
+# Direct call with a relocation for the callee. The encoding bits are the
+# second halfword like for `bw`.
+bl = EncRecipe(
+        'bl', Call, base_size=4, ins=(), outs=(),
+        emit='''
+        sink.reloc_external(Reloc::Arm32Call,
+                            &func.dfg.ext_funcs[func_ref].name,
+                            0);
+        put_bw(bits, 0, sink);
+        ''')
+
+# Indirect call through a register: `blx rm`. The encoding bits are the 16-bit
+# instruction.
+blx = EncRecipe(
+        'blx', CallIndirect, base_size=2, ins=GPR, outs=(),
+        emit='sink.put2(bits | (reg(in_reg0) << 3));')
+
+# Return to the address in the link register: `bx lr`.
+# The variable return values are not encoded.
+ret = EncRecipe(
+        'ret', MultiAry, base_size=2, ins=(), outs=(),
+        emit='sink.put2(bits);')
+
+# Trap with a permanently undefined instruction: `udf #0`.
+trap = EncRecipe(
+        'trap', Trap, base_size=2, ins=(), outs=(),
+        clobbers_flags=False,
+        emit='''
+        sink.trap(code, func.srclocs[inst], payload.into());
+        sink.put2(bits);
+        ''')
+
+# Macro: conditional branch over an undefined instruction.
+trapif = EncRecipe(
+        'trapif', IntCondTrap, base_size=4, ins=FLAG.nzcv, outs=(),
+        clobbers_flags=False,
+        emit='''
+        // Branch over the 2-byte `udf`.
+        put_bcondn(BCOND_N, icc2cond(cond.inverse()), 0, sink);
+        sink.trap(code, func.srclocs[inst], payload.into());
+        sink.put2(UDF);
+        ''')
+
+#
+# Memory accesses.
+#
+
+# Load with a positive 12-bit offset.
+#
+#   hw1: 11111 00 0 op 1 Rn
+#   hw2: Rt imm12
+ld = EncRecipe(
+        'ld', Load, base_size=4, ins=GPR, outs=GPR,
+        clobbers_flags=False,
+        instp=IsUnsignedInt(Load.offset, 12),
+        emit='''
+        if !flags.notrap() {
+            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst], 0);
+        }
+        put_ldst12(bits, out_reg0, in_reg0, offset.into(), sink);
+        ''')
+
+# Load with an 8-bit offset that is added or subtracted.
+#
+#   hw1: 11111 00 0 op 1 Rn
+#   hw2: Rt 1 P U W imm8
+#
+# The offset can be -255 to 255, but this predicate only accepts the 8-bit
+# signed offsets.
+ld8 = EncRecipe(
+        'ld8', Load, base_size=4, ins=GPR, outs=GPR,
+        clobbers_flags=False,
+        instp=IsSignedInt(Load.offset, 8),
+        emit='''
+        if !flags.notrap() {
+            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst], 0);
+        }
+        put_ldst8(bits, out_reg0, in_reg0, offset.into(), sink);
+        ''')
+
+st = EncRecipe(
+        'st', Store, base_size=4, ins=(GPR, GPR), outs=(),
+        clobbers_flags=False,
+        instp=IsUnsignedInt(Store.offset, 12),
+        emit='''
+        if !flags.notrap() {
+            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst], 0);
+        }
+        put_ldst12(bits, in_reg0, in_reg1, offset.into(), sink);
+        ''')
+
+st8 = EncRecipe(
+        'st8', Store, base_size=4, ins=(GPR, GPR), outs=(),
+        clobbers_flags=False,
+        instp=IsSignedInt(Store.offset, 8),
+        emit='''
+        if !flags.notrap() {
+            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst], 0);
+        }
+        put_ldst8(bits, in_reg0, in_reg1, offset.into(), sink);
+        ''')
+
+# Spill and fill relative to the stack pointer, with a 12-bit offset.
+spill = EncRecipe(
+        'spill', Unary, base_size=4, ins=GPR, outs=Stack(GPR),
+        clobbers_flags=False,
+        emit='''
+        sink.trap(TrapCode::StackOverflow, func.srclocs[inst], 0);
+        let base = stk_base(out_stk0.base);
+        put_ldst12(bits, in_reg0, base, out_stk0.offset.into(), sink);
+        ''')
+
+fill = EncRecipe(
+        'fill', Unary, base_size=4, ins=Stack(GPR), outs=GPR,
+        clobbers_flags=False,
+        emit='''
+        let base = stk_base(in_stk0.base);
+        put_ldst12(bits, out_reg0, base, in_stk0.offset.into(), sink);
+        ''')
+
+regspill = EncRecipe(
+        'regspill', RegSpill, base_size=4, ins=GPR, outs=(),
+        clobbers_flags=False,
+        emit='''
+        sink.trap(TrapCode::StackOverflow, func.srclocs[inst], 0);
+        let dst = StackRef::sp(dst, &func.stack_slots);
+        let base = stk_base(dst.base);
+        put_ldst12(bits, src, base, dst.offset.into(), sink);
+        ''')
+
+regfill = EncRecipe(
+        'regfill', RegFill, base_size=4, ins=Stack(GPR), outs=(),
+        clobbers_flags=False,
+        emit='''
+        let src = StackRef::sp(src, &func.stack_slots);
+        let base = stk_base(src.base);
+        put_ldst12(bits, dst, base, src.offset.into(), sink);
+        ''')
+
+# Push a register with a pre-indexed store: `str rt, [sp, #-4]!`.
+push = EncRecipe(
+        'push', Unary, base_size=4, ins=GPR, outs=(),
+        clobbers_flags=False,
+        emit='''
+        sink.trap(TrapCode::StackOverflow, func.srclocs[inst], 0);
+        put_t32(bits, (reg(in_reg0) << 12) | 0x0d04, sink);
+        ''')
+
+# Pop a register with a post-indexed load: `ldr rt, [sp], #4`.
+pop = EncRecipe(
+        'pop', NullAry, base_size=4, ins=(), outs=GPR,
+        clobbers_flags=False,
+        emit='put_t32(bits, (reg(out_reg0) << 12) | 0x0b04, sink);')
+
+# The 16-bit `push` and `pop` with a single low register. The encoding bits
+# are the 16-bit instruction with an empty register list.
+push16 = EncRecipe(
+        'push16', Unary, base_size=2, ins=GPR8, outs=(),
+        clobbers_flags=False,
+        emit='''
+        sink.trap(TrapCode::StackOverflow, func.srclocs[inst], 0);
+        sink.put2(bits | (1 << reg(in_reg0)));
+        ''')
+
+pop16 = EncRecipe(
+        'pop16', NullAry, base_size=2, ins=(), outs=GPR8,
+        clobbers_flags=False,
+        emit='sink.put2(bits | (1 << reg(out_reg0)));')
+
+# The 16-bit `push {lr}`.
+pushlr = EncRecipe(
+        'pushlr', Unary, base_size=2, ins=GPR.r14, outs=(),
+        clobbers_flags=False,
+        emit='''
+        sink.trap(TrapCode::StackOverflow, func.srclocs[inst], 0);
+        sink.put2(bits);
+        ''')
+
+# Adjust the stack pointer: `addw` or `subw` with a 12-bit immediate, `add` or
+# `sub` with a modified immediate, and the 16-bit `add` or `sub` with a
+# 7-bit immediate scaled by 4.
+adjustsp12 = EncRecipe(
+        'adjustsp12', UnaryImm, base_size=4, ins=(), outs=(),
+        clobbers_flags=False,
+        instp=IsUnsignedInt(UnaryImm.imm, 12),
+        emit='put_dpi12(bits, SP, imm.into(), SP, sink);')
+
+adjustspi = EncRecipe(
+        'adjustspi', UnaryImm, base_size=4, ins=(), outs=(),
+        clobbers_flags=False,
+        instp=IsThumbImm(UnaryImm.imm),
+        emit='put_dpi(bits, SP, imm.into(), SP, sink);')
+
+adjustsp7 = EncRecipe(
+        'adjustsp7', UnaryImm, base_size=2, ins=(), outs=(),
+        clobbers_flags=False,
+        instp=IsUnsignedInt(UnaryImm.imm, 9, 2),
+        emit='''
+        let imm: i64 = imm.into();
+        sink.put2(bits | (imm >> 2) as u16);
+        ''')
+
+#
+# Floating point.
+#
+# The VFP register fields are a 4-bit field and an extra bit elsewhere in the
+# instruction. The emitter gets both from `dreg()` or `sreg()`, together with
+# the `sz` bit selecting double precision.
+#
+
+# VFP data processing with three registers.
+#
+#   hw1: 11101110 op D op Vn
+#   hw2: Vd 101 sz N op M 0 Vm
+vrrr = EncRecipe(
+        'vrrr', Binary, base_size=4, ins=(D, D), outs=D,
+        clobbers_flags=False,
+        emit='''
+        put_vfp(bits, dreg(out_reg0), dreg(in_reg0), dreg(in_reg1), sink);
+        ''')
+
+# VFP data processing with two registers, where the `Vn` field holds more
+# opcode bits.
+vrr = EncRecipe(
+        'vrr', Unary, base_size=4, ins=D, outs=D,
+        clobbers_flags=False,
+        emit='put_vfp2(bits, dreg(out_reg0), dreg(in_reg0), sink);')
+
+vrmov = EncRecipe(
+        'vrmov', RegMove, base_size=4, ins=D, outs=(),
+        clobbers_flags=False,
+        emit='put_vfp2(bits, dreg(dst), dreg(src), sink);')
+
+# Single-precision register copies.
+vrrs = EncRecipe(
+        'vrrs', Unary, base_size=4, ins=S, outs=S,
+        clobbers_flags=False,
+        emit='put_vfp2(bits, sreg(out_reg0), sreg(in_reg0), sink);')
+
+vrmovs = EncRecipe(
+        'vrmovs', RegMove, base_size=4, ins=S, outs=(),
+        clobbers_flags=False,
+        emit='put_vfp2(bits, sreg(dst), sreg(src), sink);')
+
+# Compare with `vcmp` and copy the flags with `vmrs APSR_nzcv, fpscr`.
+vcmp = EncRecipe(
+        'vcmp', Binary, base_size=8, ins=(D, D), outs=FLAG.nzcv,
+        emit='''
+        put_vfp2(bits, dreg(in_reg0), dreg(in_reg1), sink);
+        put_t32(VMRS_HW1, VMRS_HW2, sink);
+        ''')
+
+# Floating point comparison with the result in a register.
+vcmpset = EncRecipe(
+        'vcmpset', FloatCompare, base_size=18, ins=(D, D), outs=GPR,
+        instp=floatccs(FloatCompare),
+        emit='''
+        put_vfp2(bits, dreg(in_reg0), dreg(in_reg1), sink);
+        put_t32(VMRS_HW1, VMRS_HW2, sink);
+        put_cset(fcc2cond(cond), out_reg0, sink);
+        ''')
+
+# Materialize a floating point condition as a `b1` value.
+fcset = EncRecipe(
+        'fcset', FloatCond, base_size=10, ins=FLAG.nzcv, outs=GPR,
+        clobbers_flags=False,
+        instp=floatccs(FloatCond),
+        emit='put_cset(fcc2cond(cond), out_reg0, sink);')
+
+# Move between a double-precision register and two integer registers.
+#
+#   hw1: 11101100 010 op Rt2
+#   hw2: Rt 1011 00 M 1 Vm
+vmovdrr = EncRecipe(
+        'vmovdrr', Binary, base_size=4, ins=(GPR, GPR), outs=D,
+        clobbers_flags=False,
+        emit='put_vmov(bits, dreg(out_reg0), in_reg0, in_reg1, sink);')
+
+vmovrrd = EncRecipe(
+        'vmovrrd', Unary, base_size=4, ins=D, outs=(GPR, GPR),
+        clobbers_flags=False,
+        emit='put_vmov(bits, dreg(in_reg0), out_reg0, out_reg1, sink);')
+
+# Move between a single-precision register and an integer register.
+#
+#   hw1: 11101110 000 op Vn
+#   hw2: Rt 1010 N 00 1 0000
+vmovsr = EncRecipe(
+        'vmovsr', Unary, base_size=4, ins=GPR, outs=S,
+        clobbers_flags=False,
+        emit='put_vmovs(bits, sreg(out_reg0), in_reg0, sink);')
+
+vmovrs = EncRecipe(
+        'vmovrs', Unary, base_size=4, ins=S, outs=GPR,
+        clobbers_flags=False,
+        emit='put_vmovs(bits, sreg(in_reg0), out_reg0, sink);')
+
+# Load and store with an 8-bit offset scaled by 4.
+#
+#   hw1: 1110110 1 U D 0 op Rn
+#   hw2: Vd 101 sz imm8
+#
+# The offset can be up to 1020 bytes in either direction, but this predicate
+# only accepts the 10-bit signed offsets.
+vld = EncRecipe(
+        'vld', Load, base_size=4, ins=GPR, outs=D,
+        clobbers_flags=False,
+        instp=IsSignedInt(Load.offset, 10, 2),
+        emit='''
+        if !flags.notrap() {
+            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst], 0);
+        }
+        put_vldst(bits, dreg(out_reg0), in_reg0, offset.into(), sink);
+        ''')
+
+vst = EncRecipe(
+        'vst', Store, base_size=4, ins=(D, GPR), outs=(),
+        clobbers_flags=False,
+        instp=IsSignedInt(Store.offset, 10, 2),
+        emit='''
+        if !flags.notrap() {
+            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst], 0);
+        }
+        put_vldst(bits, dreg(in_reg0), in_reg1, offset.into(), sink);
+        ''')
+
+vlds = EncRecipe(
+        'vlds', Load, base_size=4, ins=GPR, outs=S,
+        clobbers_flags=False,
+        instp=IsSignedInt(Load.offset, 10, 2),
+        emit='''
+        if !flags.notrap() {
+            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst], 0);
+        }
+        put_vldst(bits, sreg(out_reg0), in_reg0, offset.into(), sink);
+        ''')
+
+vsts = EncRecipe(
+        'vsts', Store, base_size=4, ins=(S, GPR), outs=(),
+        clobbers_flags=False,
+        instp=IsSignedInt(Store.offset, 10, 2),
+        emit='''
+        if !flags.notrap() {
+            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst], 0);
+        }
+        put_vldst(bits, sreg(in_reg0), in_reg1, offset.into(), sink);
+        ''')
+
+# Spill and fill relative to the stack pointer. The offset must fit the
+# scaled 8-bit immediate, so the slots need to be within 1020 bytes of the
+# stack pointer.
+vspill = EncRecipe(
+        'vspill', Unary, base_size=4, ins=D, outs=Stack(D),
+        clobbers_flags=False,
+        emit='''
+        sink.trap(TrapCode::StackOverflow, func.srclocs[inst], 0);
+        let base = stk_base(out_stk0.base);
+        put_vldst(bits, dreg(in_reg0), base, out_stk0.offset.into(), sink);
+        ''')
+
+vfill = EncRecipe(
+        'vfill', Unary, base_size=4, ins=Stack(D), outs=D,
+        clobbers_flags=False,
+        emit='''
+        let base = stk_base(in_stk0.base);
+        put_vldst(bits, dreg(out_reg0), base, in_stk0.offset.into(), sink);
+        ''')
+
+vregspill = EncRecipe(
+        'vregspill', RegSpill, base_size=4, ins=D, outs=(),
+        clobbers_flags=False,
+        emit='''
+        sink.trap(TrapCode::StackOverflow, func.srclocs[inst], 0);
+        let dst = StackRef::sp(dst, &func.stack_slots);
+        let base = stk_base(dst.base);
+        put_vldst(bits, dreg(src), base, dst.offset.into(), sink);
+        ''')
+
+vregfill = EncRecipe(
+        'vregfill', RegFill, base_size=4, ins=Stack(D), outs=(),
+        clobbers_flags=False,
+        emit='''
+        let src = StackRef::sp(src, &func.stack_slots);
+        let base = stk_base(src.base);
+        put_vldst(bits, dreg(dst), base, src.offset.into(), sink);
+        ''')
+
+vspills = EncRecipe(
+        'vspills', Unary, base_size=4, ins=S, outs=Stack(S),
+        clobbers_flags=False,
+        emit='''
+        sink.trap(TrapCode::StackOverflow, func.srclocs[inst], 0);
+        let base = stk_base(out_stk0.base);
+        put_vldst(bits, sreg(in_reg0), base, out_stk0.offset.into(), sink);
+        ''')
+
+vfills = EncRecipe(
+        'vfills', Unary, base_size=4, ins=Stack(S), outs=S,
+        clobbers_flags=False,
+        emit='''
+        let base = stk_base(in_stk0.base);
+        put_vldst(bits, sreg(out_reg0), base, in_stk0.offset.into(), sink);
+        ''')
+
+vregspills = EncRecipe(
+        'vregspills', RegSpill, base_size=4, ins=S, outs=(),
+        clobbers_flags=False,
+        emit='''
+        sink.trap(TrapCode::StackOverflow, func.srclocs[inst], 0);
+        let dst = StackRef::sp(dst, &func.stack_slots);
+        let base = stk_base(dst.base);
+        put_vldst(bits, sreg(src), base, dst.offset.into(), sink);
+        ''')
+
+vregfills = EncRecipe(
+        'vregfills', RegFill, base_size=4, ins=Stack(S), outs=(),
+        clobbers_flags=False,
+        emit='''
+        let src = StackRef::sp(src, &func.stack_slots);
+        let base = stk_base(src.base);
+        put_vldst(bits, sreg(dst), base, src.offset.into(), sink);
+        ''')
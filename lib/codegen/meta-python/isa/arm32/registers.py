@@ -37,6 +37,9 @@ FlagRegs = RegBank(
         names=['nzcv'])
 
 GPR = RegClass(IntRegs)
+# The low registers `r0-r7` that fit in the 3-bit register fields of most
+# 16-bit Thumb instructions.
+GPR8 = GPR[0:8]
 S = RegClass(FloatRegs, count=32)
 D = RegClass(FloatRegs, width=2)
 Q = RegClass(FloatRegs, width=4)
@@ -2,10 +2,16 @@
 ARM32 settings.
 """
 from __future__ import absolute_import
-from cdsl.settings import SettingGroup
+from cdsl.settings import SettingGroup, BoolSetting
+from cdsl.predicates import And
 import base.settings as shared
 from .defs import ISA
 
 ISA.settings = SettingGroup('arm32', parent=shared.group)
 
+has_vfp3 = BoolSetting(
+        "CPU supports VFPv3 with at least 16 double-precision registers")
+
+use_vfp3 = And(has_vfp3, shared.enable_float)
+
 ISA.settings.close(globals())
@@ -7,7 +7,7 @@ ARMv8 CPUs running the Aarch64 architecture.
 
 from __future__ import absolute_import
 from . import defs
-from . import settings, registers  # noqa
+from . import encodings, settings, registers  # noqa
 from cdsl.isa import TargetISA  # noqa
 
 # Re-export the primary target ISA definition.
@@ -6,10 +6,8 @@ Commonly used definitions.
 from __future__ import absolute_import
 from cdsl.isa import TargetISA, CPUMode
 import base.instructions
-from base.legalize import narrow
+from . import instructions as arm64
 
-ISA = TargetISA('arm64', [base.instructions.GROUP])  # type: TargetISA
+ISA = TargetISA(
+        'arm64', [base.instructions.GROUP, arm64.GROUP])  # type: TargetISA
 A64 = CPUMode('A64', ISA)
-
-# TODO: Refine these
-A64.legalize_type(narrow)
@@ -0,0 +1,268 @@
+"""
+ARM64 Encodings.
+"""
+from __future__ import absolute_import
+from cdsl.predicates import IsBitmaskImm, IsUnsignedInt
+from base import instructions as base
+from base import types
+from base.formats import BinaryImm, Load, Store
+from base.legalize import narrow, widen, expand_flags
+from .defs import A64
+from .recipes import OP, SF
+from . import recipes as r
+from . import instructions as arm64
+from .legalize import arm64_expand
+
+try:
+    from typing import TYPE_CHECKING, Any  # noqa
+    if TYPE_CHECKING:
+        from cdsl.instructions import MaybeBoundInst  # noqa
+        from cdsl.isa import EncRecipe  # noqa
+except ImportError:
+    pass
+
+
+A64.legalize_monomorphic(expand_flags)
+A64.legalize_type(
+    default=narrow,
+    b1=expand_flags,
+    i8=widen,
+    i16=widen,
+    i32=arm64_expand,
+    i64=arm64_expand,
+    f32=arm64_expand,
+    f64=arm64_expand)
+
+# The `N` bit of the bitfield instructions must match `sf`.
+N = 0x00400000
+
+
+def enc_i32_i64(inst, recipe, template, **kwargs):
+    # type: (MaybeBoundInst, EncRecipe, int, **Any) -> None
+    """
+    Add encodings for `inst.i32` and `inst.i64`, using the 32-bit instruction
+    `template` and its 64-bit variant with the `sf` bit set.
+    """
+    A64.enc(inst.i32, recipe, OP(template), **kwargs)
+    A64.enc(inst.i64, recipe, OP(template | SF), **kwargs)
+
+
+#
+# Integer arithmetic.
+#
+
+# Add/subtract and logical instructions with a shifted register operand, using
+# a zero shift amount.
+for inst,               template in [
+        (base.iadd,     0x0b000000),
+        (base.isub,     0x4b000000),
+        (base.band,     0x0a000000),
+        (base.bor,      0x2a000000),
+        (base.bxor,     0x4a000000),
+        (base.band_not, 0x0a200000),
+        (base.bor_not,  0x2a200000),
+        (base.bxor_not, 0x4a200000)]:
+    enc_i32_i64(inst, r.rrr, template)
+
+# Also add `b1` encodings for the logic instructions.
+A64.enc(base.band.b1, r.rrr, OP(0x0a000000))
+A64.enc(base.bor.b1, r.rrr, OP(0x2a000000))
+A64.enc(base.bxor.b1, r.rrr, OP(0x4a000000))
+
+# `mul` is an alias of `madd` with the zero register as the addend.
+enc_i32_i64(base.imul, r.rrr, 0x1b007c00)
+A64.enc(base.umulhi.i64, r.rrr, OP(0x9bc07c00))
+A64.enc(base.smulhi.i64, r.rrr, OP(0x9b407c00))
+
+# Add with an immediate, optionally shifted left by 12.
+enc_i32_i64(base.iadd_imm, r.rri12, 0x11000000)
+enc_i32_i64(base.iadd_imm, r.rri12s, 0x11400000)
+
+# Logical instructions with a bitmask immediate.
+for inst,               template in [
+        (base.band_imm, 0x12000000),
+        (base.bor_imm,  0x32000000),
+        (base.bxor_imm, 0x52000000)]:
+    A64.enc(inst.i32, r.rrl, OP(template),
+            instp=IsBitmaskImm(BinaryImm.imm, 32))
+    A64.enc(inst.i64, r.rrl, OP(template | SF),
+            instp=IsBitmaskImm(BinaryImm.imm, 64))
+
+# Dynamic shifts use the shift amount modulo the type width, like the clif
+# instructions.
+for inst,           template in [
+        (base.ishl, 0x1ac02000),
+        (base.ushr, 0x1ac02400),
+        (base.sshr, 0x1ac02800),
+        (base.rotr, 0x1ac02c00)]:
+    A64.enc(inst.i32.i32, r.rrr, OP(template))
+    A64.enc(inst.i32.i64, r.rrr, OP(template))
+    A64.enc(inst.i64.i64, r.rrr, OP(template | SF))
+    A64.enc(inst.i64.i32, r.rrr, OP(template | SF))
+
+# Immediate shifts are aliases of the bitfield and extract instructions.
+for inst,               recipe, template in [
+        (base.ishl_imm, r.lsli, 0x53000000),
+        (base.ushr_imm, r.rshi, 0x53000000),
+        (base.sshr_imm, r.rshi, 0x13000000),
+        (base.rotr_imm, r.rori, 0x13800000),
+        (base.rotl_imm, r.roli, 0x13800000)]:
+    A64.enc(inst.i32, recipe, OP(template))
+    A64.enc(inst.i64, recipe, OP(template | SF | N))
+
+# Integer constants.
+enc_i32_i64(base.iconst, r.movimm, 0x52800000)
+A64.enc(base.bconst.b1, r.movb, OP(0x52800000))
+
+#
+# Conversions.
+#
+
+# Writing a 32-bit register clears the high bits of the 64-bit register, so
+# zero extensions to i64 can use the 32-bit instructions.
+for ty in [types.i32, types.i64]:
+    A64.enc(base.uextend.bind(ty, types.i8), r.ext, OP(0x53001c00))
+    A64.enc(base.uextend.bind(ty, types.i16), r.ext, OP(0x53003c00))
+A64.enc(base.uextend.i64.i32, r.copy, OP(0x2a000000))
+
+A64.enc(base.sextend.i32.i8, r.ext, OP(0x13001c00))
+A64.enc(base.sextend.i32.i16, r.ext, OP(0x13003c00))
+A64.enc(base.sextend.i64.i8, r.ext, OP(0x93401c00))
+A64.enc(base.sextend.i64.i16, r.ext, OP(0x93403c00))
+A64.enc(base.sextend.i64.i32, r.ext, OP(0x93407c00))
+
+# Reducing an integer is a no-op.
+A64.enc(base.ireduce.i8.i16, r.null, 0)
+A64.enc(base.ireduce.i8.i32, r.null, 0)
+A64.enc(base.ireduce.i16.i32, r.null, 0)
+A64.enc(base.ireduce.i8.i64, r.null, 0)
+A64.enc(base.ireduce.i16.i64, r.null, 0)
+A64.enc(base.ireduce.i32.i64, r.null, 0)
+
+# A `b1` value is represented as 0 or 1 in a 32-bit register. Extract the low
+# bit anyway, like `ubfx wd, wn, #0, #1`.
+A64.enc(base.bint.i32.b1, r.ext, OP(0x53000000))
+A64.enc(base.bint.i64.b1, r.ext, OP(0x53000000))
+
+#
+# Register copies.
+#
+
+A64.enc(base.copy.i32, r.copy, OP(0x2a000000))
+A64.enc(base.copy.i64, r.copy, OP(0xaa000000))
+A64.enc(base.copy.b1, r.copy, OP(0x2a000000))
+A64.enc(base.regmove.i32, r.rmov, OP(0x2a000000))
+A64.enc(base.regmove.i64, r.rmov, OP(0xaa000000))
+A64.enc(base.regmove.b1, r.rmov, OP(0x2a000000))
+
+# Copies involving the stack pointer use `add`.
+A64.enc(base.copy_special, r.copysp, OP(0x91000000))
+
+#
+# Comparisons.
+#
+
+enc_i32_i64(base.ifcmp, r.rcmp, 0x6b000000)
+enc_i32_i64(base.ifcmp_imm, r.rcmpi, 0x71000000)
+enc_i32_i64(base.icmp, r.icmp, 0x6b000000)
+enc_i32_i64(base.icmp_imm, r.icmpi, 0x71000000)
+
+A64.enc(base.trueif, r.cset, OP(0x1a800400))
+
+enc_i32_i64(base.selectif, r.csel, 0x1a800000)
+A64.enc(base.select.i32.b1, r.sel, OP(0x1a800000))
+A64.enc(base.select.i64.b1, r.sel, OP(0x1a800000 | SF))
+A64.enc(base.select.b1.b1, r.sel, OP(0x1a800000))
+
+#
+# Control flow.
+#
+
+A64.enc(base.jump, r.b, OP(0x14000000))
+A64.enc(base.brif, r.bcond, OP(0x54000000))
+
+enc_i32_i64(base.brz, r.cb, 0x34000000)
+enc_i32_i64(base.brnz, r.cb, 0x35000000)
+A64.enc(base.brz.b1, r.cb, OP(0x34000000))
+A64.enc(base.brnz.b1, r.cb, OP(0x35000000))
+
+# Calls and returns use `x30` as the link register. It is saved in the frame
+# record by the prologue, see `abi.rs`.
+A64.enc(base.call, r.bl, OP(0x94000000))
+A64.enc(base.call_indirect.i64, r.blr, OP(0xd6200000))
+A64.enc(base.x_return, r.ret, OP(0xd6400000))
+
+# Traps use `brk #0`.
+A64.enc(base.trap, r.trap, OP(0xd4200000))
+A64.enc(base.trapif, r.trapif, OP(0x54000000))
+
+# The cycle counter is the virtual count register, read with `mrs`.
+A64.enc(base.read_cycle_counter, r.mrs_cntvct, OP(0xd5200000))
+
+#
+# Loads and stores.
+#
+
+# Loads with an unsigned offset scaled by the access size, and with a signed
+# unscaled offset.
+for inst,              ty,        ldr,        ldur,       scale in [
+        (base.load,    types.i32, 0xb9400000, 0xb8400000, 2),
+        (base.load,    types.i64, 0xf9400000, 0xf8400000, 3),
+        (base.uload8,  types.i32, 0x39400000, 0x38400000, 0),
+        (base.uload8,  types.i64, 0x39400000, 0x38400000, 0),
+        (base.sload8,  types.i32, 0x39c00000, 0x38c00000, 0),
+        (base.sload8,  types.i64, 0x39800000, 0x38800000, 0),
+        (base.uload16, types.i32, 0x79400000, 0x78400000, 1),
+        (base.uload16, types.i64, 0x79400000, 0x78400000, 1),
+        (base.sload16, types.i32, 0x79c00000, 0x78c00000, 1),
+        (base.sload16, types.i64, 0x79800000, 0x78800000, 1)]:
+    A64.enc(inst.bind(ty).any, r.ld, OP(ldr),
+            instp=IsUnsignedInt(Load.offset, 12 + scale, scale))
+    A64.enc(inst.bind(ty).any, r.ldu, OP(ldur))
+
+# The 32-bit extending loads only produce `i64` values, so their type variable
+# is the address type.
+for inst,              ldr,        ldur in [
+        (base.uload32, 0xb9400000, 0xb8400000),
+        (base.sload32, 0xb9800000, 0xb8800000)]:
+    A64.enc(inst.i64, r.ld, OP(ldr),
+            instp=IsUnsignedInt(Load.offset, 14, 2))
+    A64.enc(inst.i64, r.ldu, OP(ldur))
+
+for inst,               ty,        st,         stur,       scale in [
+        (base.store,    types.i32, 0xb9000000, 0xb8000000, 2),
+        (base.store,    types.i64, 0xf9000000, 0xf8000000, 3),
+        (base.istore8,  types.i32, 0x39000000, 0x38000000, 0),
+        (base.istore8,  types.i64, 0x39000000, 0x38000000, 0),
+        (base.istore16, types.i32, 0x79000000, 0x78000000, 1),
+        (base.istore16, types.i64, 0x79000000, 0x78000000, 1),
+        (base.istore32, types.i64, 0xb9000000, 0xb8000000, 2)]:
+    A64.enc(inst.bind(ty).any, r.st, OP(st),
+            instp=IsUnsignedInt(Store.offset, 12 + scale, scale))
+    A64.enc(inst.bind(ty).any, r.stu, OP(stur))
+
+# Spill slots are addressed relative to the stack pointer. Use a 32-bit access
+# for `b1`, `i8` and `i16` like the 32-bit values.
+for ty in [types.b1, types.i8, types.i16, types.i32]:
+    A64.enc(base.spill.bind(ty), r.spill, OP(0xb9000000))
+    A64.enc(base.regspill.bind(ty), r.regspill, OP(0xb9000000))
+    A64.enc(base.fill.bind(ty), r.fill, OP(0xb9400000))
+    A64.enc(base.regfill.bind(ty), r.regfill, OP(0xb9400000))
+A64.enc(base.spill.i64, r.spill, OP(0xf9000000))
+A64.enc(base.regspill.i64, r.regspill, OP(0xf9000000))
+A64.enc(base.fill.i64, r.fill, OP(0xf9400000))
+A64.enc(base.regfill.i64, r.regfill, OP(0xf9400000))
+
+#
+# Stack pointer manipulation, used by the prologue and epilogue.
+#
+
+A64.enc(base.adjust_sp_down_imm, r.adjustsp, OP(0xd1000000))
+A64.enc(base.adjust_sp_down_imm, r.adjustsp12, OP(0xd1400000))
+A64.enc(base.adjust_sp_up_imm, r.adjustsp, OP(0x91000000))
+A64.enc(base.adjust_sp_up_imm, r.adjustsp12, OP(0x91400000))
+
+A64.enc(arm64.push.i64, r.push, OP(0xf8000c00))
+A64.enc(arm64.pop.i64, r.pop, OP(0xf8400400))
+A64.enc(arm64.push2.i64, r.push2, OP(0xa9800000))
+A64.enc(arm64.pop2.i64, r.pop2, OP(0xa8c00000))
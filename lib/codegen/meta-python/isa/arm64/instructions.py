@@ -0,0 +1,65 @@
+"""
+Supplementary instruction definitions for ARM64.
+
+This module defines additional instructions that are useful only to the ARM64
+target ISA.
+"""
+
+from cdsl.operands import Operand
+from cdsl.typevar import TypeVar
+from cdsl.instructions import Instruction, InstructionGroup
+
+
+GROUP = InstructionGroup("arm64", "ARM64-specific instruction set")
+
+iWord = TypeVar('iWord', 'A scalar integer machine word', ints=(32, 64))
+
+x = Operand('x', iWord)
+y = Operand('y', iWord)
+
+push = Instruction(
+    'arm64_push', r"""
+    Pushes a value onto the stack.
+
+    Decrements the stack pointer by 16 and stores the specified value at the
+    new top of the stack. The stack pointer must stay 16-byte aligned, so the
+    other 8 bytes of the slot are unused.
+
+    This is polymorphic in i32 and i64, but it is only implemented for i64.
+    """,
+    ins=x, can_store=True, other_side_effects=True)
+
+pop = Instruction(
+    'arm64_pop', r"""
+    Pops a value from the stack.
+
+    Loads a value from the top of the stack and then increments the stack
+    pointer by 16.
+
+    This is polymorphic in i32 and i64, but it is only implemented for i64.
+    """,
+    outs=x, can_load=True, other_side_effects=True)
+
+push2 = Instruction(
+    'arm64_push2', r"""
+    Pushes a pair of values onto the stack.
+
+    Decrements the stack pointer by 16 and stores `x` and `y` at the new top
+    of the stack, with `x` at the lower address.
+
+    This is polymorphic in i32 and i64, but it is only implemented for i64.
+    """,
+    ins=(x, y), can_store=True, other_side_effects=True)
+
+pop2 = Instruction(
+    'arm64_pop2', r"""
+    Pops a pair of values from the stack.
+
+    Loads `x` and `y` from the top of the stack, with `x` at the lower
+    address, and then increments the stack pointer by 16.
+
+    This is polymorphic in i32 and i64, but it is only implemented for i64.
+    """,
+    outs=(x, y), can_load=True, other_side_effects=True)
+
+GROUP.close()
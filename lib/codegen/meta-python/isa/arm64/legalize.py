@@ -0,0 +1,33 @@
+"""
+Custom legalization patterns for ARM64.
+"""
+from __future__ import absolute_import
+from cdsl.ast import Var
+from cdsl.xform import Rtl, XFormGroup
+from base.immediates import imm64
+from base import legalize as shared
+from base import instructions as insts
+from .defs import ISA
+
+arm64_expand = XFormGroup(
+        'arm64_expand',
+        """
+        Legalize instructions by expansion.
+
+        Use ARM64-specific instructions if needed.
+        """,
+        isa=ISA, chain=shared.expand_flags)
+
+a = Var('a')
+x = Var('x')
+y = Var('y')
+a1 = Var('a1')
+
+# There is no rotate left instruction, but rotating right by the negated
+# amount is equivalent since the amount is taken modulo the type width.
+arm64_expand.legalize(
+        a << insts.rotl(x, y),
+        Rtl(
+            a1 << insts.irsub_imm(y, imm64(0)),
+            a << insts.rotr(x, a1)
+        ))
@@ -0,0 +1,446 @@
+"""
+ARM64 Encoding recipes.
+
+The encoding recipes defined here correspond to the A64 instruction classes
+described in the reference:
+
+    ARM Architecture Reference Manual
+    ARMv8, for ARMv8-A architecture profile
+    Chapter C4: A64 Instruction Set Encoding
+
+All A64 instructions are 32 bits wide. The encoding bits of a recipe hold the
+fixed fields of the instruction that aren't operands of the recipe, and the
+emitter in `binemit.rs` fills in the registers and immediates.
+"""
+from __future__ import absolute_import
+from cdsl.isa import EncRecipe
+from cdsl.predicates import IsSignedInt, IsUnsignedInt
+from cdsl.registers import Stack
+from base.formats import Unary, UnaryImm, UnaryBool, Binary, BinaryImm
+from base.formats import Ternary, MultiAry, NullAry, IntCompare
+from base.formats import IntCompareImm, IntCond, IntSelect, IntCondTrap
+from base.formats import Trap, Jump, Branch, BranchInt, Call, CallIndirect
+from base.formats import Load, Store, RegMove, RegSpill, RegFill
+from base.formats import CopySpecial
+from .registers import GPR, FLAG
+
+# The `sf` bit selects the 64-bit variant of most instruction classes.
+SF = 0x80000000
+
+
+def OP(inst):
+    # type: (int) -> int
+    """
+    Compute the encoding bits for the 32-bit instruction template `inst`.
+
+    The encoding bits are `inst[31:21] | (inst[14:10] << 11)`. The other bits
+    of the template must be zero, they are filled in by the recipes. This
+    covers the fixed fields of all the instruction classes used here: The
+    opcode in the high bits, and the opcode or `Ra` fields of the
+    data-processing register instructions in bits 14-10.
+    """
+    assert inst & 0x001f83ff == 0, "0x{:08x} isn't a template".format(inst)
+    return (inst >> 21) | (((inst >> 10) & 0x1f) << 11)
+
+
+# Three-register data processing instructions: `rd = op(rn, rm)`.
+# This covers the add/sub and logical (shifted register) classes with a zero
+# shift, and the data-processing (2 source) and (3 source) classes.
+rrr = EncRecipe(
+        'rrr', Binary, base_size=4, ins=(GPR, GPR), outs=GPR,
+        clobbers_flags=False,
+        emit='put_rrr(bits, in_reg0, in_reg1, out_reg0, sink);')
+
+# Add/subtract with a 12-bit unsigned immediate.
+rri12 = EncRecipe(
+        'rri12', BinaryImm, base_size=4, ins=GPR, outs=GPR,
+        clobbers_flags=False,
+        instp=IsUnsignedInt(BinaryImm.imm, 12),
+        emit='put_rri12(bits, in_reg0, imm.into(), out_reg0, sink);')
+
+# Add/subtract with a 12-bit unsigned immediate shifted left by 12.
+rri12s = EncRecipe(
+        'rri12s', BinaryImm, base_size=4, ins=GPR, outs=GPR,
+        clobbers_flags=False,
+        instp=IsUnsignedInt(BinaryImm.imm, 24, 12),
+        emit='''
+        let imm: i64 = imm.into();
+        put_rri12(bits, in_reg0, imm >> 12, out_reg0, sink);
+        ''')
+
+# Logical instructions with a bitmask immediate. The encodings provide an
+# `IsBitmaskImm` predicate for the width of the operation.
+rrl = EncRecipe(
+        'rrl', BinaryImm, base_size=4, ins=GPR, outs=GPR,
+        clobbers_flags=False,
+        emit='put_logical_imm(bits, in_reg0, imm.into(), out_reg0, sink);')
+
+# Shift left by an immediate, as an alias of `ubfm`.
+lsli = EncRecipe(
+        'lsli', BinaryImm, base_size=4, ins=GPR, outs=GPR,
+        clobbers_flags=False,
+        emit='''
+        let size = reg_size(bits);
+        let imm: i64 = imm.into();
+        let shift = imm as u32 & (size - 1);
+        put_bitfield(
+            bits,
+            in_reg0,
+            shift.wrapping_neg() & (size - 1),
+            size - 1 - shift,
+            out_reg0,
+            sink,
+        );
+        ''')
+
+# Shift right by an immediate, as an alias of `ubfm` or `sbfm`.
+rshi = EncRecipe(
+        'rshi', BinaryImm, base_size=4, ins=GPR, outs=GPR,
+        clobbers_flags=False,
+        emit='''
+        let size = reg_size(bits);
+        let imm: i64 = imm.into();
+        let shift = imm as u32 & (size - 1);
+        put_bitfield(bits, in_reg0, shift, size - 1, out_reg0, sink);
+        ''')
+
+# Rotate right by an immediate, as an alias of `extr`.
+rori = EncRecipe(
+        'rori', BinaryImm, base_size=4, ins=GPR, outs=GPR,
+        clobbers_flags=False,
+        emit='''
+        let size = reg_size(bits);
+        let imm: i64 = imm.into();
+        let shift = imm as u32 & (size - 1);
+        put_extr(bits, in_reg0, in_reg0, shift, out_reg0, sink);
+        ''')
+
+# Rotate left by an immediate, as a rotate right by the negated amount.
+roli = EncRecipe(
+        'roli', BinaryImm, base_size=4, ins=GPR, outs=GPR,
+        clobbers_flags=False,
+        emit='''
+        let size = reg_size(bits);
+        let imm: i64 = imm.into();
+        let shift = (imm as u32).wrapping_neg() & (size - 1);
+        put_extr(bits, in_reg0, in_reg0, shift, out_reg0, sink);
+        ''')
+
+# Sign or zero extension, as an alias of `sbfm` or `ubfm`. The `immr` and
+# `imms` fields are part of the encoding bits.
+ext = EncRecipe(
+        'ext', Unary, base_size=4, ins=GPR, outs=GPR,
+        clobbers_flags=False,
+        emit='put_rrr(bits, in_reg0, 0, out_reg0, sink);')
+
+# A null unary instruction that takes a GPR register. Can be used for identity
+# copies and no-op conversions.
+null = EncRecipe('null', Unary, base_size=0, ins=GPR, outs=0, emit='')
+
+# Integer constant materialized by `movz` or `movn` followed by the needed
+# `movk` instructions.
+movimm = EncRecipe(
+        'movimm', UnaryImm, base_size=4, ins=(), outs=GPR,
+        clobbers_flags=False,
+        compute_size='size_movimm',
+        emit='put_movimm(bits, imm.into(), out_reg0, sink);')
+
+# Boolean constant materialized by `movz`.
+movb = EncRecipe(
+        'movb', UnaryBool, base_size=4, ins=(), outs=GPR,
+        clobbers_flags=False,
+        emit='put_movewide(bits, 0, imm as u32, out_reg0, sink);')
+
+# Register copy, as an alias of `orr` with the zero register.
+copy = EncRecipe(
+        'copy', Unary, base_size=4, ins=GPR, outs=GPR,
+        clobbers_flags=False,
+        emit='put_rrr(bits, 31, in_reg0, out_reg0, sink);')
+
+rmov = EncRecipe(
+        'rmov', RegMove, base_size=4, ins=GPR, outs=(),
+        clobbers_flags=False,
+        emit='put_rrr(bits, 31, src, dst, sink);')
+
+# Copy to or from the stack pointer, as an alias of `add` with a zero
+# immediate. The `orr` alias of a register copy reads `x31` as the zero
+# register.
+copysp = EncRecipe(
+        'copysp', CopySpecial, base_size=4, ins=(), outs=(),
+        clobbers_flags=False,
+        emit='put_rri12(bits, src, 0, dst, sink);')
+
+# Adjust the stack pointer by a 12-bit unsigned immediate.
+adjustsp = EncRecipe(
+        'adjustsp', UnaryImm, base_size=4, ins=(), outs=(),
+        clobbers_flags=False,
+        instp=IsUnsignedInt(UnaryImm.imm, 12),
+        emit='put_rri12(bits, 31, imm.into(), 31, sink);')
+
+# Adjust the stack pointer by a 12-bit unsigned immediate shifted left by 12.
+adjustsp12 = EncRecipe(
+        'adjustsp12', UnaryImm, base_size=4, ins=(), outs=(),
+        clobbers_flags=False,
+        instp=IsUnsignedInt(UnaryImm.imm, 24, 12),
+        emit='''
+        let imm: i64 = imm.into();
+        put_rri12(bits, 31, imm >> 12, 31, sink);
+        ''')
+
+#
+# Comparisons and flags.
+#
+
+# Compare two registers, as an alias of `subs` writing the zero register.
+rcmp = EncRecipe(
+        'rcmp', Binary, base_size=4, ins=(GPR, GPR), outs=FLAG.nzcv,
+        emit='put_rrr(bits, in_reg0, in_reg1, 31, sink);')
+
+# Compare a register with a 12-bit unsigned immediate.
+rcmpi = EncRecipe(
+        'rcmpi', BinaryImm, base_size=4, ins=GPR, outs=FLAG.nzcv,
+        instp=IsUnsignedInt(BinaryImm.imm, 12),
+        emit='put_rri12(bits, in_reg0, imm.into(), 31, sink);')
+
+# Materialize a condition as a `b1` value: `cset rd, cond`.
+cset = EncRecipe(
+        'cset', IntCond, base_size=4, ins=FLAG.nzcv, outs=GPR,
+        clobbers_flags=False,
+        emit='put_cset(bits, cond, out_reg0, sink);')
+
+# Integer comparison with the result in a register: `cmp` followed by
+# `cset`.
+icmp = EncRecipe(
+        'icmp', IntCompare, base_size=8, ins=(GPR, GPR), outs=GPR,
+        emit='''
+        put_rrr(bits, in_reg0, in_reg1, 31, sink);
+        put_cset(CSET_BITS, cond, out_reg0, sink);
+        ''')
+
+icmpi = EncRecipe(
+        'icmpi', IntCompareImm, base_size=8, ins=GPR, outs=GPR,
+        instp=IsUnsignedInt(IntCompareImm.imm, 12),
+        emit='''
+        put_rri12(bits, in_reg0, imm.into(), 31, sink);
+        put_cset(CSET_BITS, cond, out_reg0, sink);
+        ''')
+
+# Conditional select on the flags.
+csel = EncRecipe(
+        'csel', IntSelect, base_size=4, ins=(FLAG.nzcv, GPR, GPR), outs=GPR,
+        clobbers_flags=False,
+        emit='put_csel(bits, in_reg1, in_reg2, cond, out_reg0, sink);')
+
+# Select on a `b1` value: `cmp wc, #0` followed by `csel`.
+sel = EncRecipe(
+        'sel', Ternary, base_size=8, ins=(GPR, GPR, GPR), outs=GPR,
+        emit='''
+        put_rri12(CMP_W_IMM_BITS, in_reg0, 0, 31, sink);
+        put_csel(bits, in_reg1, in_reg2, IntCC::NotEqual, out_reg0, sink);
+        ''')
+
+#
+# Control flow.
+#
+
+# Conditional branch on the flags: `b.cond`.
+bcond = EncRecipe(
+        'bcond', BranchInt, base_size=4, ins=FLAG.nzcv, outs=(),
+        branch_range=(0, 21),
+        clobbers_flags=False,
+        emit='''
+        let disp = branch_disp(func, inst, destination, sink.offset(), |d| {
+            is_signed_int(d, 21, 2)
+        })?;
+        put_bcond(bits, cond, disp, sink);
+        ''')
+
+# Compare a register with zero and branch: `cbz` or `cbnz`.
+cb = EncRecipe(
+        'cb', Branch, base_size=4, ins=GPR, outs=(),
+        branch_range=(0, 21),
+        clobbers_flags=False,
+        emit='''
+        let disp = branch_disp(func, inst, destination, sink.offset(), |d| {
+            is_signed_int(d, 21, 2)
+        })?;
+        put_cb(bits, in_reg0, disp, sink);
+        ''')
+
+# Unconditional branch.
+b = EncRecipe(
+        'b', Jump, base_size=4, ins=(), outs=(),
+        branch_range=(0, 28),
+        clobbers_flags=False,
+        emit='''
+        let disp = branch_disp(func, inst, destination, sink.offset(), |d| {
+            is_signed_int(d, 28, 2)
+        })?;
+        put_b(bits, disp, sink);
+        ''')
+
+# Direct call with a relocation for the callee.
+bl = EncRecipe(
+        'bl', Call, base_size=4, ins=(), outs=(),
+        emit='''
+        sink.reloc_external(Reloc::Arm64Call,
+                            &func.dfg.ext_funcs[func_ref].name,
+                            0);
+        put_b(bits, 0, sink);
+        ''')
+
+# Indirect call through a register.
+blr = EncRecipe(
+        'blr', CallIndirect, base_size=4, ins=GPR, outs=(),
+        emit='put_rrr(bits, in_reg0, 31, 0, sink);')
+
+# Return to the address in the link register `x30`.
+# The variable return values are not encoded.
+ret = EncRecipe(
+        'ret', MultiAry, base_size=4, ins=(), outs=(),
+        emit='put_rrr(bits, 30, 31, 0, sink);')
+
+# Trap with a breakpoint instruction.
+trap = EncRecipe(
+        'trap', Trap, base_size=4, ins=(), outs=(),
+        clobbers_flags=False,
+        emit='''
+        sink.trap(code, func.srclocs[inst], payload.into());
+        sink.put4(op(bits));
+        ''')
+
+# Macro: conditional branch over a breakpoint instruction.
+trapif = EncRecipe(
+        'trapif', IntCondTrap, base_size=8, ins=FLAG.nzcv, outs=(),
+        clobbers_flags=False,
+        emit='''
+        // Branch over the 4-byte `brk`.
+        put_bcond(bits, cond.inverse(), 8, sink);
+        sink.trap(code, func.srclocs[inst], payload.into());
+        sink.put4(BRK);
+        ''')
+
+#
+# Memory accesses.
+#
+
+# Load with an unsigned offset scaled by the access size. The encodings
+# provide a predicate for the offset range of the access size.
+ld = EncRecipe(
+        'ld', Load, base_size=4, ins=GPR, outs=GPR,
+        clobbers_flags=False,
+        emit='''
+        if !flags.notrap() {
+            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst], 0);
+        }
+        put_ldst_uimm(bits, out_reg0, in_reg0, offset.into(), sink);
+        ''')
+
+# Load with a signed unscaled 9-bit offset: `ldur` and friends.
+ldu = EncRecipe(
+        'ldu', Load, base_size=4, ins=GPR, outs=GPR,
+        clobbers_flags=False,
+        instp=IsSignedInt(Load.offset, 9),
+        emit='''
+        if !flags.notrap() {
+            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst], 0);
+        }
+        put_ldst_simm9(bits, out_reg0, in_reg0, offset.into(), sink);
+        ''')
+
+# Store with an unsigned offset scaled by the access size.
+st = EncRecipe(
+        'st', Store, base_size=4, ins=(GPR, GPR), outs=(),
+        clobbers_flags=False,
+        emit='''
+        if !flags.notrap() {
+            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst], 0);
+        }
+        put_ldst_uimm(bits, in_reg0, in_reg1, offset.into(), sink);
+        ''')
+
+# Store with a signed unscaled 9-bit offset: `stur` and friends.
+stu = EncRecipe(
+        'stu', Store, base_size=4, ins=(GPR, GPR), outs=(),
+        clobbers_flags=False,
+        instp=IsSignedInt(Store.offset, 9),
+        emit='''
+        if !flags.notrap() {
+            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst], 0);
+        }
+        put_ldst_simm9(bits, in_reg0, in_reg1, offset.into(), sink);
+        ''')
+
+# Spill and fill relative to the stack pointer, with a scaled unsigned offset.
+spill = EncRecipe(
+        'spill', Unary, base_size=4, ins=GPR, outs=Stack(GPR),
+        clobbers_flags=False,
+        emit='''
+        sink.trap(TrapCode::StackOverflow, func.srclocs[inst], 0);
+        let base = stk_base(out_stk0.base);
+        put_ldst_uimm(bits, in_reg0, base, out_stk0.offset.into(), sink);
+        ''')
+
+fill = EncRecipe(
+        'fill', Unary, base_size=4, ins=Stack(GPR), outs=GPR,
+        clobbers_flags=False,
+        emit='''
+        let base = stk_base(in_stk0.base);
+        put_ldst_uimm(bits, out_reg0, base, in_stk0.offset.into(), sink);
+        ''')
+
+regspill = EncRecipe(
+        'regspill', RegSpill, base_size=4, ins=GPR, outs=(),
+        clobbers_flags=False,
+        emit='''
+        sink.trap(TrapCode::StackOverflow, func.srclocs[inst], 0);
+        let dst = StackRef::sp(dst, &func.stack_slots);
+        let base = stk_base(dst.base);
+        put_ldst_uimm(bits, src, base, dst.offset.into(), sink);
+        ''')
+
+regfill = EncRecipe(
+        'regfill', RegFill, base_size=4, ins=Stack(GPR), outs=(),
+        clobbers_flags=False,
+        emit='''
+        let src = StackRef::sp(src, &func.stack_slots);
+        let base = stk_base(src.base);
+        put_ldst_uimm(bits, dst, base, src.offset.into(), sink);
+        ''')
+
+# Push a register with a pre-indexed store: `str xt, [sp, #-16]!`.
+push = EncRecipe(
+        'push', Unary, base_size=4, ins=GPR, outs=(),
+        clobbers_flags=False,
+        emit='''
+        sink.trap(TrapCode::StackOverflow, func.srclocs[inst], 0);
+        put_ldst_simm9(bits, in_reg0, 31, -16, sink);
+        ''')
+
+# Pop a register with a post-indexed load: `ldr xt, [sp], #16`.
+pop = EncRecipe(
+        'pop', NullAry, base_size=4, ins=(), outs=GPR,
+        clobbers_flags=False,
+        emit='put_ldst_simm9(bits, out_reg0, 31, 16, sink);')
+
+# Read the virtual count register: `mrs xt, cntvct_el0`. The encoding bits are
+# the system register move class, and the emitter adds the register number.
+mrs_cntvct = EncRecipe(
+        'mrs_cntvct', NullAry, base_size=4, ins=(), outs=GPR,
+        clobbers_flags=False,
+        emit='sink.put4(op(bits) | CNTVCT_EL0 | reg(out_reg0));')
+
+# Push a pair of registers: `stp xt1, xt2, [sp, #-16]!`.
+push2 = EncRecipe(
+        'push2', Binary, base_size=4, ins=(GPR, GPR), outs=(),
+        clobbers_flags=False,
+        emit='''
+        sink.trap(TrapCode::StackOverflow, func.srclocs[inst], 0);
+        put_ldst_pair(bits, in_reg0, in_reg1, 31, -16, sink);
+        ''')
+
+# Pop a pair of registers: `ldp xt1, xt2, [sp], #16`.
+pop2 = EncRecipe(
+        'pop2', NullAry, base_size=4, ins=(), outs=(GPR, GPR),
+        clobbers_flags=False,
+        emit='put_ldst_pair(bits, out_reg0, out_reg1, 31, 16, sink);')
@@ -6,8 +6,10 @@ Commonly used definitions.
 from __future__ import absolute_import
 from cdsl.isa import TargetISA, CPUMode
 import base.instructions
+from . import instructions as riscv
 
-ISA = TargetISA('riscv', [base.instructions.GROUP])  # type: TargetISA
+ISA = TargetISA(
+        'riscv', [base.instructions.GROUP, riscv.GROUP])  # type: TargetISA
 
 # CPU modes for 32-bit and 64-bit operation.
 RV32 = CPUMode('RV32', ISA)
@@ -6,25 +6,31 @@ from base import instructions as base
 from base.immediates import intcc
 from .defs import RV32, RV64
 from .recipes import OPIMM, OPIMM32, OP, OP32, LUI, BRANCH, JALR, JAL
-from .recipes import LOAD, STORE
+from .recipes import LOAD, STORE, SYSTEM, C3, C4
 from .recipes import R, Rshamt, Ricmp, Ii, Iz, Iicmp, Iret, Icall, Icopy
-from .recipes import U, UJ, UJcall, SB, SBzero, GPsp, GPfi, Irmov
-from .settings import use_m
+from .recipes import U, Ili, UJ, UJcall, SB, SBzero, GPsp, GPfi, Irmov, Itrap
+from .recipes import Iload, S, Iuext8
+from . import recipes as r
+from .settings import use_m, use_c_ext
+from cdsl.predicates import IsUnsignedInt
+from base.formats import Load, Store
+from . import instructions as riscv
+from .legalize import riscv_expand
 from cdsl.ast import Var
 from base.legalize import narrow, expand
 
 RV32.legalize_monomorphic(expand)
 RV32.legalize_type(
         default=narrow,
-        i32=expand,
+        i32=riscv_expand,
         f32=expand,
         f64=expand)
 
 RV64.legalize_monomorphic(expand)
 RV64.legalize_type(
         default=narrow,
-        i32=expand,
-        i64=expand,
+        i32=riscv_expand,
+        i64=riscv_expand,
         f32=expand,
         f64=expand)
 
@@ -53,6 +59,10 @@ for inst,           inst_imm,      f3,    f7 in [
 # 32-bit ops in RV64.
 RV64.enc(base.iadd.i32, R, OP32(0b000, 0b0000000))
 RV64.enc(base.isub.i32, R, OP32(0b000, 0b0100000))
+# The bitwise operations preserve the sign extension of 32-bit values.
+RV64.enc(base.bxor.i32, R, OP(0b100, 0b0000000))
+RV64.enc(base.bor.i32, R, OP(0b110, 0b0000000))
+RV64.enc(base.band.i32, R, OP(0b111, 0b0000000))
 # There are no andiw/oriw/xoriw variations.
 RV64.enc(base.iadd_imm.i32, Ii, OPIMM32(0b000))
 
@@ -96,12 +106,35 @@ RV32.enc(base.iconst.i32, U, LUI())
 RV64.enc(base.iconst.i32, U, LUI())
 RV64.enc(base.iconst.i64, U, LUI())
 
+# Any other integer constant is materialized by a sequence of instructions.
+# TODO: Load the constants which need long sequences from a constant pool.
+RV32.enc(base.iconst.i32, Ili, OPIMM(0b000))
+RV64.enc(base.iconst.i32, Ili, OPIMM32(0b000))
+RV64.enc(base.iconst.i64, Ili, OPIMM32(0b000))
+
 # "M" Standard Extension for Integer Multiplication and Division.
 # Gated by the `use_m` flag.
 RV32.enc(base.imul.i32, R, OP(0b000, 0b0000001), isap=use_m)
 RV64.enc(base.imul.i64, R, OP(0b000, 0b0000001), isap=use_m)
 RV64.enc(base.imul.i32, R, OP32(0b000, 0b0000001), isap=use_m)
 
+# There are no 'w' variants of the high multiplications.
+RV32.enc(base.smulhi.i32, R, OP(0b001, 0b0000001), isap=use_m)
+RV64.enc(base.smulhi.i64, R, OP(0b001, 0b0000001), isap=use_m)
+RV32.enc(base.umulhi.i32, R, OP(0b011, 0b0000001), isap=use_m)
+RV64.enc(base.umulhi.i64, R, OP(0b011, 0b0000001), isap=use_m)
+
+# The base division instructions are legalized to these non-trapping ones.
+for inst,            f3 in [
+        (riscv.div,  0b100),
+        (riscv.divu, 0b101),
+        (riscv.rem,  0b110),
+        (riscv.remu, 0b111)
+        ]:
+    RV32.enc(inst.i32, R, OP(f3, 0b0000001), isap=use_m)
+    RV64.enc(inst.i64, R, OP(f3, 0b0000001), isap=use_m)
+    RV64.enc(inst.i32, R, OP32(f3, 0b0000001), isap=use_m)
+
 # Control flow.
 
 # Unconditional branches.
@@ -128,6 +161,7 @@ for inst,           f3 in [
         ]:
     RV32.enc(inst.i32, SBzero, BRANCH(f3))
     RV64.enc(inst.i64, SBzero, BRANCH(f3))
+    RV64.enc(inst.i32, SBzero, BRANCH(f3))
     RV32.enc(inst.b1, SBzero, BRANCH(f3))
     RV64.enc(inst.b1, SBzero, BRANCH(f3))
 
@@ -139,6 +173,18 @@ RV64.enc(base.x_return, Iret, JALR())
 RV32.enc(base.call_indirect.i32, Icall, JALR())
 RV64.enc(base.call_indirect.i64, Icall, JALR())
 
+# Traps.
+RV32.enc(base.trap, Itrap, SYSTEM(0b001))
+RV64.enc(base.trap, Itrap, SYSTEM(0b001))
+
+# Loads and stores.
+RV32.enc(base.load.i32.any, Iload, LOAD(0b010))
+RV64.enc(base.load.i32.any, Iload, LOAD(0b010))
+RV64.enc(base.load.i64.any, Iload, LOAD(0b011))
+RV32.enc(base.store.i32.any, S, STORE(0b010))
+RV64.enc(base.store.i32.any, S, STORE(0b010))
+RV64.enc(base.store.i64.any, S, STORE(0b011))
+
 # Spill and fill.
 RV32.enc(base.spill.i32, GPsp, STORE(0b010))
 RV64.enc(base.spill.i32, GPsp, STORE(0b010))
@@ -152,6 +198,17 @@ RV32.enc(base.copy.i32, Icopy, OPIMM(0b000))
 RV64.enc(base.copy.i64, Icopy, OPIMM(0b000))
 RV64.enc(base.copy.i32, Icopy, OPIMM32(0b000))
 
+# Byte zero-extensions.
+RV32.enc(base.uextend.i32.i8, Iuext8, OPIMM(0b111))
+RV64.enc(base.uextend.i32.i8, Iuext8, OPIMM(0b111))
+RV64.enc(base.uextend.i64.i8, Iuext8, OPIMM(0b111))
+
+# Register moves are inserted by the register allocator with the first legal
+# encoding, and `c.mv` doesn't constrain the registers.
+RV32.enc(base.regmove.i32, r.CRrmov, C4(0b1000, 0b10), isap=use_c_ext)
+RV64.enc(base.regmove.i64, r.CRrmov, C4(0b1000, 0b10), isap=use_c_ext)
+RV64.enc(base.regmove.i32, r.CRrmov, C4(0b1000, 0b10), isap=use_c_ext)
+
 RV32.enc(base.regmove.i32, Irmov, OPIMM(0b000))
 RV64.enc(base.regmove.i64, Irmov, OPIMM(0b000))
 RV64.enc(base.regmove.i32, Irmov, OPIMM32(0b000))
@@ -160,3 +217,46 @@ RV32.enc(base.copy.b1, Icopy, OPIMM(0b000))
 RV64.enc(base.copy.b1, Icopy, OPIMM(0b000))
 RV32.enc(base.regmove.b1, Irmov, OPIMM(0b000))
 RV64.enc(base.regmove.b1, Irmov, OPIMM(0b000))
+
+# A `b1` in a register is already 0 or 1.
+RV32.enc(base.bint.i32.b1, Icopy, OPIMM(0b000))
+RV64.enc(base.bint.i64.b1, Icopy, OPIMM(0b000))
+RV64.enc(base.bint.i32.b1, Icopy, OPIMM(0b000))
+
+# "C" Standard Extension for Compressed Instructions.
+# Gated by the `use_c_ext` flag.
+for cpu in [RV32, RV64]:
+    cpu.enc(base.iconst.i32, r.CIz, C3(0b010, 0b01), isap=use_c_ext)
+    cpu.enc(base.copy.i32, r.CRcopy, C4(0b1000, 0b10), isap=use_c_ext)
+    cpu.enc(base.copy.b1, r.CRcopy, C4(0b1000, 0b10), isap=use_c_ext)
+    cpu.enc(
+            base.load.i32.any, r.CL, C3(0b010, 0b00),
+            instp=IsUnsignedInt(Load.offset, 7, 2), isap=use_c_ext)
+    cpu.enc(
+            base.store.i32.any, r.CS, C3(0b110, 0b00),
+            instp=IsUnsignedInt(Store.offset, 7, 2), isap=use_c_ext)
+    cpu.enc(base.jump, r.CJ, C3(0b101, 0b01), isap=use_c_ext)
+    for inst,           f3 in [
+            (base.brz,  0b110),
+            (base.brnz, 0b111)
+            ]:
+        cpu.enc(inst.i32, r.CBzero, C3(f3, 0b01), isap=use_c_ext)
+        cpu.enc(inst.b1, r.CBzero, C3(f3, 0b01), isap=use_c_ext)
+
+RV32.enc(base.iadd.i32, r.CR, C4(0b1001, 0b10), isap=use_c_ext)
+RV32.enc(base.iadd_imm.i32, r.CIi, C3(0b000, 0b01), isap=use_c_ext)
+
+RV64.enc(base.iadd.i64, r.CR, C4(0b1001, 0b10), isap=use_c_ext)
+RV64.enc(base.iadd_imm.i64, r.CIi, C3(0b000, 0b01), isap=use_c_ext)
+# In RV64, the encoding of RV32's `c.jal` is `c.addiw`.
+RV64.enc(base.iadd_imm.i32, r.CIi, C3(0b001, 0b01), isap=use_c_ext)
+RV64.enc(base.iconst.i64, r.CIz, C3(0b010, 0b01), isap=use_c_ext)
+RV64.enc(base.copy.i64, r.CRcopy, C4(0b1000, 0b10), isap=use_c_ext)
+RV64.enc(
+        base.load.i64.any, r.CL, C3(0b011, 0b00),
+        instp=IsUnsignedInt(Load.offset, 8, 3), isap=use_c_ext)
+RV64.enc(
+        base.store.i64.any, r.CS, C3(0b111, 0b00),
+        instp=IsUnsignedInt(Store.offset, 8, 3), isap=use_c_ext)
+RV64.enc(base.brz.i64, r.CBzero, C3(0b110, 0b01), isap=use_c_ext)
+RV64.enc(base.brnz.i64, r.CBzero, C3(0b111, 0b01), isap=use_c_ext)
@@ -0,0 +1,58 @@
+"""
+Supplementary instruction definitions for RISC-V.
+
+This module defines additional instructions that are useful only to the RISC-V
+target ISA.
+"""
+
+from cdsl.operands import Operand
+from cdsl.typevar import TypeVar
+from cdsl.instructions import Instruction, InstructionGroup
+
+
+GROUP = InstructionGroup("riscv", "RISC-V-specific instruction set")
+
+iWord = TypeVar('iWord', 'A scalar integer machine word', ints=(32, 64))
+
+x = Operand('x', iWord, doc='Numerator')
+y = Operand('y', iWord, doc='Denominator')
+q = Operand('q', iWord, doc='Quotient')
+r = Operand('r', iWord, doc='Remainder')
+
+# The RISC-V division instructions never trap. The results for a zero divisor
+# and for the single overflowing signed division are defined instead.
+
+div = Instruction(
+        'riscv_div', r"""
+        Signed integer division without traps.
+
+        Like `sdiv`, but the quotient is -1 when `y` is zero and `x` when
+        `x` is the smallest negative number and `y` is -1.
+        """,
+        ins=(x, y), outs=q)
+
+divu = Instruction(
+        'riscv_divu', r"""
+        Unsigned integer division without traps.
+
+        Like `udiv`, but the quotient has all bits set when `y` is zero.
+        """,
+        ins=(x, y), outs=q)
+
+rem = Instruction(
+        'riscv_rem', r"""
+        Signed integer remainder without traps.
+
+        Like `srem`, but the remainder is `x` when `y` is zero.
+        """,
+        ins=(x, y), outs=r)
+
+remu = Instruction(
+        'riscv_remu', r"""
+        Unsigned integer remainder without traps.
+
+        Like `urem`, but the remainder is `x` when `y` is zero.
+        """,
+        ins=(x, y), outs=r)
+
+GROUP.close()
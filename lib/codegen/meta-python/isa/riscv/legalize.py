@@ -0,0 +1,64 @@
+"""
+Custom legalization patterns for RISC-V.
+"""
+from __future__ import absolute_import
+from cdsl.ast import Var
+from cdsl.xform import Rtl, XFormGroup
+from base import legalize as shared
+from base import instructions as insts
+from base.immediates import intcc, imm64
+from .defs import ISA
+
+riscv_expand = XFormGroup(
+        'riscv_expand',
+        """
+        Legalize instructions by expansion.
+
+        Use RISC-V-specific instructions if needed.
+        """,
+        isa=ISA, chain=shared.expand)
+
+# The division instructions don't trap, so the division traps are checked
+# explicitly.
+riscv_expand.custom_legalize(insts.sdiv, 'expand_sdivrem')
+riscv_expand.custom_legalize(insts.srem, 'expand_sdivrem')
+riscv_expand.custom_legalize(insts.udiv, 'expand_udivrem')
+riscv_expand.custom_legalize(insts.urem, 'expand_udivrem')
+
+a = Var('a')
+b = Var('b')
+x = Var('x')
+y = Var('y')
+zero = Var('zero')
+
+# There are only less-than comparisons, so equality is tested by comparing
+# the exclusive or of the operands with zero.
+riscv_expand.legalize(
+    a << insts.icmp(intcc.eq, x, y),
+    Rtl(
+        b << insts.bxor(x, y),
+        a << insts.icmp_imm(intcc.ult, b, imm64(1))
+    ))
+
+riscv_expand.legalize(
+    a << insts.icmp(intcc.ne, x, y),
+    Rtl(
+        b << insts.bxor(x, y),
+        zero << insts.iconst(imm64(0)),
+        a << insts.icmp(intcc.ult, zero, b)
+    ))
+
+riscv_expand.legalize(
+    a << insts.icmp_imm(intcc.eq, x, y),
+    Rtl(
+        b << insts.bxor_imm(x, y),
+        a << insts.icmp_imm(intcc.ult, b, imm64(1))
+    ))
+
+riscv_expand.legalize(
+    a << insts.icmp_imm(intcc.ne, x, y),
+    Rtl(
+        b << insts.bxor_imm(x, y),
+        zero << insts.iconst(imm64(0)),
+        a << insts.icmp(intcc.ult, zero, b)
+    ))
@@ -14,8 +14,8 @@ from cdsl.predicates import IsSignedInt
 from cdsl.registers import Stack
 from base.formats import Binary, BinaryImm, MultiAry, IntCompare, IntCompareImm
 from base.formats import Unary, UnaryImm, BranchIcmp, Branch, Jump
-from base.formats import Call, CallIndirect, RegMove
-from .registers import GPR
+from base.formats import Call, CallIndirect, RegMove, Trap, Load, Store
+from .registers import GPR, GPRC
 
 # The low 7 bits of a RISC-V instruction is the base opcode. All 32-bit
 # instructions have 11 as the two low bits, with bits 6:2 determining the base
@@ -90,6 +90,31 @@ def LUI():
     return 0b01101
 
 
+def SYSTEM(funct3):
+    # type: (int) -> int
+    assert funct3 <= 0b111
+    return 0b11100 | (funct3 << 5)
+
+
+# The 16-bit compressed instructions have a 2-bit opcode in the low bits and
+# a `funct3` or `funct4` field in the high bits. Their encbits are the fixed
+# bits of the instruction.
+
+
+def C3(funct3, op):
+    # type: (int, int) -> int
+    assert funct3 <= 0b111
+    assert op <= 0b10
+    return (funct3 << 13) | op
+
+
+def C4(funct4, op):
+    # type: (int, int) -> int
+    assert funct4 <= 0b1111
+    assert op <= 0b10
+    return (funct4 << 12) | op
+
+
 # R-type 32-bit instructions: These are mostly binary arithmetic instructions.
 # The encbits are `opcode[6:2] | (funct3 << 5) | (funct7 << 8)
 R = EncRecipe(
@@ -160,6 +185,11 @@ Icopy = EncRecipe(
         'Icopy', Unary, base_size=4, ins=GPR, outs=GPR,
         emit='put_i(bits, in_reg0, 0, out_reg0, sink);')
 
+# Zero-extension of a byte is implemented as andi x, 0xff.
+Iuext8 = EncRecipe(
+        'Iuext8', Unary, base_size=4, ins=GPR, outs=GPR,
+        emit='put_i(bits, in_reg0, 0xff, out_reg0, sink);')
+
 # Same for a GPR regmove.
 Irmov = EncRecipe(
         'Irmov', RegMove, base_size=4, ins=GPR, outs=(),
@@ -171,12 +201,24 @@ U = EncRecipe(
         instp=IsSignedInt(UnaryImm.imm, 32, 12),
         emit='put_u(bits, imm.into(), out_reg0, sink);')
 
+# Integer constant materialized by `lui` and `addi` followed by the needed
+# `slli` and `addi` pairs for the high bits. The encoding bits are the `addi`
+# variant used after the `lui`: `addiw` in RV64 and `addi` in RV32.
+Ili = EncRecipe(
+        'Ili', UnaryImm, base_size=4, ins=(), outs=GPR,
+        compute_size='size_li',
+        emit='''
+        let ty = func.dfg.ctrl_typevar(inst);
+        put_li(bits, imm.into(), ty, out_reg0, sink);
+        ''')
+
 # UJ-type unconditional branch instructions.
 UJ = EncRecipe(
         'UJ', Jump, base_size=4, ins=(), outs=(), branch_range=(0, 21),
         emit='''
-        let dest = i64::from(func.offsets[destination]);
-        let disp = dest - i64::from(sink.offset());
+        let disp = branch_disp(func, inst, destination, sink.offset(), |d| {
+            is_signed_int(d, 21, 1)
+        })?;
         put_uj(bits, disp, 0, sink);
         ''')
 
@@ -196,8 +238,9 @@ SB = EncRecipe(
         ins=(GPR, GPR), outs=(),
         branch_range=(0, 13),
         emit='''
-        let dest = i64::from(func.offsets[destination]);
-        let disp = dest - i64::from(sink.offset());
+        let disp = branch_disp(func, inst, destination, sink.offset(), |d| {
+            is_signed_int(d, 13, 1)
+        })?;
         put_sb(bits, disp, in_reg0, in_reg1, sink);
         ''')
 
@@ -207,11 +250,43 @@ SBzero = EncRecipe(
         ins=(GPR), outs=(),
         branch_range=(0, 13),
         emit='''
-        let dest = i64::from(func.offsets[destination]);
-        let disp = dest - i64::from(sink.offset());
+        let disp = branch_disp(func, inst, destination, sink.offset(), |d| {
+            is_signed_int(d, 13, 1)
+        })?;
         put_sb(bits, disp, in_reg0, 0, sink);
         ''')
 
+# Trap with the canonical illegal instruction `unimp`, which is an alias of
+# `csrrw x0, cycle, x0`: Writing the read-only `cycle` CSR is illegal.
+Itrap = EncRecipe(
+        'Itrap', Trap, base_size=4, ins=(), outs=(),
+        emit='''
+        sink.trap(code, func.srclocs[inst], payload.into());
+        put_i(bits, 0, 0xc00, 0, sink);
+        ''')
+
+# I-type loads.
+Iload = EncRecipe(
+        'Iload', Load, base_size=4, ins=GPR, outs=GPR,
+        instp=IsSignedInt(Load.offset, 12),
+        emit='''
+        if !flags.notrap() {
+            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst], 0);
+        }
+        put_i(bits, in_reg0, offset.into(), out_reg0, sink);
+        ''')
+
+# S-type stores.
+S = EncRecipe(
+        'S', Store, base_size=4, ins=(GPR, GPR), outs=(),
+        instp=IsSignedInt(Store.offset, 12),
+        emit='''
+        if !flags.notrap() {
+            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst], 0);
+        }
+        put_s(bits, in_reg1, in_reg0, offset.into(), sink);
+        ''')
+
 # Spill of a GPR.
 GPsp = EncRecipe(
         'GPsp', Unary, base_size=4,
@@ -223,3 +298,78 @@ GPfi = EncRecipe(
         'GPfi', Unary, base_size=4,
         ins=Stack(GPR), outs=GPR,
         emit='unimplemented!();')
+
+
+# Compressed instructions from the 'C' extension.
+#
+# Their encodings come after the 32-bit ones so they don't constrain the
+# register allocator. The instruction shrinking pass substitutes them when the
+# allocated registers and the immediates fit.
+
+# CI-type `c.addi`, which has a tied register operand.
+CIi = EncRecipe(
+        'CIi', BinaryImm, base_size=2, ins=GPR, outs=0,
+        instp=IsSignedInt(BinaryImm.imm, 6),
+        emit='put_ci(bits, in_reg0, imm.into(), sink);')
+
+# CI-type `c.li`.
+CIz = EncRecipe(
+        'CIz', UnaryImm, base_size=2, ins=(), outs=GPR,
+        instp=IsSignedInt(UnaryImm.imm, 6),
+        emit='put_ci(bits, out_reg0, imm.into(), sink);')
+
+# CR-type `c.add`, with the first operand tied to the result.
+CR = EncRecipe(
+        'CR', Binary, base_size=2, ins=(GPR, GPR), outs=0,
+        emit='put_cr(bits, in_reg0, in_reg1, sink);')
+
+# CR-type `c.mv` used for a copy.
+CRcopy = EncRecipe(
+        'CRcopy', Unary, base_size=2, ins=GPR, outs=GPR,
+        emit='put_cr(bits, out_reg0, in_reg0, sink);')
+
+# CR-type `c.mv` used for a regmove.
+CRrmov = EncRecipe(
+        'CRrmov', RegMove, base_size=2, ins=GPR, outs=(),
+        emit='put_cr(bits, dst, src, sink);')
+
+# CL-type loads with an unsigned offset scaled by the access size.
+CL = EncRecipe(
+        'CL', Load, base_size=2, ins=GPRC, outs=GPRC,
+        emit='''
+        if !flags.notrap() {
+            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst], 0);
+        }
+        put_cls(bits, in_reg0, out_reg0, offset.into(), sink);
+        ''')
+
+# CS-type stores with an unsigned offset scaled by the access size.
+CS = EncRecipe(
+        'CS', Store, base_size=2, ins=(GPRC, GPRC), outs=(),
+        emit='''
+        if !flags.notrap() {
+            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst], 0);
+        }
+        put_cls(bits, in_reg1, in_reg0, offset.into(), sink);
+        ''')
+
+# CJ-type `c.j`.
+CJ = EncRecipe(
+        'CJ', Jump, base_size=2, ins=(), outs=(), branch_range=(0, 12),
+        emit='''
+        let disp = branch_disp(func, inst, destination, sink.offset(), |d| {
+            is_signed_int(d, 12, 1)
+        })?;
+        put_cj(bits, disp, sink);
+        ''')
+
+# CB-type `c.beqz` and `c.bnez`.
+CBzero = EncRecipe(
+        'CBzero', Branch, base_size=2, ins=GPRC, outs=(),
+        branch_range=(0, 9),
+        emit='''
+        let disp = branch_disp(func, inst, destination, sink.offset(), |d| {
+            is_signed_int(d, 9, 1)
+        })?;
+        put_cb(bits, disp, in_reg0, sink);
+        ''')
@@ -18,6 +18,8 @@ FloatRegs = RegBank(
         units=32, prefix='f')
 
 GPR = RegClass(IntRegs)
+# The compressed instructions with 3-bit register fields can only use %x8-%x15.
+GPRC = GPR[8:16]
 FPR = RegClass(FloatRegs)
 
 RegClass.extract_names(globals())
@@ -13,11 +13,17 @@ supports_m = BoolSetting("CPU supports the 'M' extension (mul/div)")
 supports_a = BoolSetting("CPU supports the 'A' extension (atomics)")
 supports_f = BoolSetting("CPU supports the 'F' extension (float)")
 supports_d = BoolSetting("CPU supports the 'D' extension (double)")
+supports_c = BoolSetting(
+        "CPU supports the 'C' extension (compressed instructions)")
 
 enable_m = BoolSetting(
         "Enable the use of 'M' instructions if available",
         default=True)
 
+enable_c = BoolSetting(
+        "Enable the use of 'C' instructions if available",
+        default=True)
+
 enable_e = BoolSetting(
         "Enable the 'RV32E' instruction set with only 16 registers")
 
@@ -25,6 +31,7 @@ use_m = And(supports_m, enable_m)
 use_a = And(supports_a, shared.enable_atomics)
 use_f = And(supports_f, shared.enable_float)
 use_d = And(supports_d, shared.enable_float)
+use_c_ext = And(supports_c, enable_c)
 
 full_float = And(shared.enable_simd, supports_f, supports_d)
 
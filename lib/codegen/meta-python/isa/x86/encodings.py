@@ -3,31 +3,34 @@ x86 Encodings.
 """
 from __future__ import absolute_import
 from cdsl.predicates import IsZero32BitFloat, IsZero64BitFloat
-from cdsl.predicates import IsUnsignedInt, Not, And
+from cdsl.predicates import IsUnsignedInt, IsEqual, Not, And
 from base.predicates import IsColocatedFunc, IsColocatedData, LengthEquals
 from base import instructions as base
 from base import types
 from base.formats import UnaryIeee32, UnaryIeee64, UnaryImm
 from base.formats import FuncAddr, Call, LoadComplex, StoreComplex
+from base.formats import LoadSymbol, LoadGlobal, IntCompare, ExtractLane
+from base.immediates import intcc
 from .defs import X86_64, X86_32
 from . import recipes as r
 from . import settings as cfg
 from . import instructions as x86
 from .legalize import x86_expand
 from base.legalize import narrow, widen, expand_flags
-from base.settings import allones_funcaddrs, is_pic
-from .settings import use_sse41
+from base.settings import allones_funcaddrs, code_model, is_pic
+from .settings import use_sse41, use_sse42
 
 try:
-    from typing import TYPE_CHECKING, Any  # noqa
+    from typing import TYPE_CHECKING, Any, Tuple  # noqa
     if TYPE_CHECKING:
         from cdsl.instructions import MaybeBoundInst  # noqa
-        from cdsl.predicates import FieldPredicate # noqa
+        from cdsl.predicates import FieldPredicate, PredNode # noqa
+        from cdsl.types import ValueType  # noqa
 except ImportError:
     pass
 
 
-X86_32.legalize_monomorphic(expand_flags)
+X86_32.legalize_monomorphic(x86_expand)
 X86_32.legalize_type(
     default=narrow,
     b1=expand_flags,
@@ -37,7 +40,7 @@ X86_32.legalize_type(
     f32=x86_expand,
     f64=x86_expand)
 
-X86_64.legalize_monomorphic(expand_flags)
+X86_64.legalize_monomorphic(x86_expand)
 X86_64.legalize_type(
     default=narrow,
     b1=expand_flags,
@@ -48,6 +51,19 @@ X86_64.legalize_type(
     f32=x86_expand,
     f64=x86_expand)
 
+# The 128-bit vector types are held in the XMM registers. Vector operations
+# that don't have encodings for them are legalized by expansion, while the
+# other vector types are narrowed.
+INT_VECTOR_TYPES = [
+        types.i8.by(16), types.i16.by(8), types.i32.by(4), types.i64.by(2)]
+FLOAT_VECTOR_TYPES = [types.f32.by(4), types.f64.by(2)]
+BOOL_VECTOR_TYPES = [
+        types.b8.by(16), types.b16.by(8), types.b32.by(4), types.b64.by(2)]
+VECTOR_TYPES = INT_VECTOR_TYPES + FLOAT_VECTOR_TYPES + BOOL_VECTOR_TYPES
+
+for mode in [X86_32, X86_64]:
+    mode.legalize_type(**{ty.name: x86_expand for ty in VECTOR_TYPES})
+
 
 #
 # Helper functions for generating encodings.
@@ -89,6 +105,20 @@ def enc_both_instp(inst, recipe, instp, *args, **kwargs):
     enc_x86_64_instp(inst, recipe, instp, *args, **kwargs)
 
 
+def enc_avx(inst, recipe, *args, **kwargs):
+    # type: (MaybeBoundInst, r.TailRecipe, *int, **int) -> None
+    """
+    Add VEX encodings for `inst` to both X86_32 and X86_64 when AVX is
+    available.
+
+    The VEX prefix holds the high bits of the register numbers, so a single
+    encoding covers all the registers. These encodings must be added before
+    the legacy SSE encodings of `inst` so they are preferred.
+    """
+    X86_32.enc(inst, *recipe.vex(*args, **kwargs), isap=cfg.use_avx)
+    X86_64.enc(inst, *recipe.vex(*args, **kwargs), isap=cfg.use_avx)
+
+
 def enc_i32_i64(inst, recipe, *args, **kwargs):
     # type: (MaybeBoundInst, r.TailRecipe, *int, **int) -> None
     """
@@ -155,6 +185,22 @@ for inst,           opc in [
         (base.bxor, 0x31)]:
     enc_i32_i64(inst, r.rr, opc)
 
+# The same instructions with the carry or borrow in the CPU flags.
+enc_i32_i64(base.iadd_ifcout, r.rout, 0x01)
+enc_i32_i64(base.isub_ifbout, r.rout, 0x29)
+
+# The 8-bit and 16-bit versions of the same instructions. Without a REX prefix,
+# the byte registers other than ABCD are not addressable, so the byte encodings
+# fall back to ABCD. The 16-bit encodings use the operand-size prefix.
+for inst,           opc in [
+        (base.iadd, 0x00),
+        (base.isub, 0x28),
+        (base.band, 0x20),
+        (base.bor,  0x08),
+        (base.bxor, 0x30)]:
+    enc_both(inst.i8, r.rr_abcd, opc)
+    enc_both(inst.i16, r.rr, 0x66, opc + 1)
+
 # x86 has a bitwise not instruction NOT.
 enc_i32_i64(base.bnot, r.ur, 0xf7, rrr=2)
 
@@ -172,6 +218,15 @@ enc_i32_i64(x86.udivmodx, r.div, 0xf7, rrr=6)
 enc_i32_i64(x86.smulx, r.mulx, 0xf7, rrr=5)
 enc_i32_i64(x86.umulx, r.mulx, 0xf7, rrr=4)
 
+# BMI2 `mulx` computes the high half of an unsigned product without clobbering
+# %rax or the flags. Without BMI2, `umulhi` is legalized to `x86_umulx`.
+X86_32.enc(base.umulhi.i32, *r.vmulhi.vex(0xf2, 0x0f, 0x38, 0xf6),
+           isap=cfg.use_bmi2)
+X86_64.enc(base.umulhi.i64, *r.vmulhi.vex(0xf2, 0x0f, 0x38, 0xf6, w=1),
+           isap=cfg.use_bmi2)
+X86_64.enc(base.umulhi.i32, *r.vmulhi.vex(0xf2, 0x0f, 0x38, 0xf6),
+           isap=cfg.use_bmi2)
+
 enc_i32_i64(base.copy, r.umr, 0x89)
 for ty in [types.b1, types.i8, types.i16]:
     enc_both(base.copy.bind(ty), r.umr, 0x89)
@@ -213,12 +268,38 @@ X86_64.enc(base.iconst.i64, *r.u_id.rex(0xc7, rrr=0, w=1))
 # Finally, the 0xb8 opcode takes an 8-byte immediate with a REX.W prefix.
 X86_64.enc(base.iconst.i64, *r.pu_iq.rex(0xb8, w=1))
 
+# Zero constants can use the `xor r, r` idiom instead. It clobbers the flags,
+# so the encodings above are preferred, and the post-legalization pass switches
+# to these where no flags are live. The 32-bit xor also zeroes the high bits.
+X86_32.enc(base.iconst.i32, *r.u_id_z(0x31))
+X86_64.enc(base.iconst.i32, *r.u_id_z.rex(0x31))
+X86_64.enc(base.iconst.i32, *r.u_id_z(0x31))
+X86_64.enc(base.iconst.i64, *r.u_id_z.rex(0x31))
+X86_64.enc(base.iconst.i64, *r.u_id_z(0x31))
+
 # bool constants.
 enc_both(base.bconst.b1, r.pu_id_bool, 0xb8)
+for ty in [types.b8, types.b16, types.b32]:
+    enc_both(base.bconst.bind(ty), r.u_id_bmask, 0xc7)
+X86_64.enc(base.bconst.b64, *r.u_id_bmask.rex(0xc7, w=1))
 
 # Shifts and rotates.
 # Note that the dynamic shift amount is only masked by 5 or 6 bits; the 8-bit
 # and 16-bit shifts would need explicit masking.
+
+# BMI2 shifts take the shift amount in any register instead of %cl, and don't
+# affect the flags, so they are preferred when available.
+for inst,           prefix in [
+        (base.ishl, 0x66),
+        (base.ushr, 0xf2),
+        (base.sshr, 0xf3)]:
+    X86_32.enc(inst.i32.any, *r.vrr.vex(prefix, 0x0f, 0x38, 0xf7),
+               isap=cfg.use_bmi2)
+    X86_64.enc(inst.i64.any, *r.vrr.vex(prefix, 0x0f, 0x38, 0xf7, w=1),
+               isap=cfg.use_bmi2)
+    X86_64.enc(inst.i32.any, *r.vrr.vex(prefix, 0x0f, 0x38, 0xf7),
+               isap=cfg.use_bmi2)
+
 for inst,           rrr in [
         (base.rotl, 0),
         (base.rotr, 1),
@@ -238,6 +319,18 @@ for inst,           rrr in [
         (base.sshr_imm, 7)]:
     enc_i32_i64(inst, r.r_ib, 0xc1, rrr=rrr)
 
+# BMI2 rotates by an immediate. Without BMI2, these are legalized to rotates by
+# a register.
+for inst,           recipe in [
+        (base.rotr_imm, r.vr_ib),
+        (base.rotl_imm, r.vr_nib)]:
+    X86_32.enc(inst.i32, *recipe.vex(0xf2, 0x0f, 0x3a, 0xf0),
+               isap=cfg.use_bmi2)
+    X86_64.enc(inst.i64, *recipe.vex(0xf2, 0x0f, 0x3a, 0xf0, w=1),
+               isap=cfg.use_bmi2)
+    X86_64.enc(inst.i32, *recipe.vex(0xf2, 0x0f, 0x3a, 0xf0),
+               isap=cfg.use_bmi2)
+
 # Population count.
 X86_32.enc(base.popcnt.i32, *r.urm(0xf3, 0x0f, 0xb8), isap=cfg.use_popcnt)
 X86_64.enc(base.popcnt.i64, *r.urm.rex(0xf3, 0x0f, 0xb8, w=1),
@@ -291,6 +384,7 @@ for recipe in [r.st, r.stDisp8, r.stDisp32]:
     enc_i32_i64_ld_st(base.store, True, recipe, 0x89)
     enc_x86_64(base.istore32.i64.any, recipe, 0x89)
     enc_i32_i64_ld_st(base.istore16, False, recipe, 0x66, 0x89)
+    enc_both(base.store.i16.any, recipe, 0x66, 0x89)
 
 # Byte stores are more complicated because the registers they can address
 # depends of the presence of a REX prefix. The st*_abcd recipes fall back to
@@ -298,8 +392,10 @@ for recipe in [r.st, r.stDisp8, r.stDisp32]:
 for recipe in [r.st_abcd, r.stDisp8_abcd, r.stDisp32_abcd]:
     enc_both(base.istore8.i32.any, recipe, 0x88)
     enc_x86_64(base.istore8.i64.any, recipe, 0x88)
+    enc_both(base.store.i8.any, recipe, 0x88)
 
 enc_i32_i64(base.spill, r.spillSib32, 0x89)
+enc_i32_i64(base.spill, r.spillSib8, 0x89)
 enc_i32_i64(base.regspill, r.regspill32, 0x89)
 
 # Use a 32-bit write for spilling `b1`, `i8` and `i16` to avoid
@@ -307,6 +403,7 @@ enc_i32_i64(base.regspill, r.regspill32, 0x89)
 # See MIN_SPILL_SLOT_SIZE which makes this safe.
 for ty in [types.b1, types.i8, types.i16]:
     enc_both(base.spill.bind(ty), r.spillSib32, 0x89)
+    enc_both(base.spill.bind(ty), r.spillSib8, 0x89)
     enc_both(base.regspill.bind(ty), r.regspill32, 0x89)
 
 for recipe in [r.ld, r.ldDisp8, r.ldDisp32]:
@@ -319,13 +416,26 @@ for recipe in [r.ld, r.ldDisp8, r.ldDisp32]:
     enc_i32_i64_ld_st(base.sload8, True, recipe, 0x0f, 0xbe)
 
 enc_i32_i64(base.fill, r.fillSib32, 0x8b)
+enc_i32_i64(base.fill, r.fillSib8, 0x8b)
 enc_i32_i64(base.regfill, r.regfill32, 0x8b)
 
 # Load 32 bits from `b1`, `i8` and `i16` spill slots. See `spill.b1` above.
 for ty in [types.b1, types.i8, types.i16]:
     enc_both(base.fill.bind(ty), r.fillSib32, 0x8b)
+    enc_both(base.fill.bind(ty), r.fillSib8, 0x8b)
     enc_both(base.regfill.bind(ty), r.regfill32, 0x8b)
 
+# Arithmetic with a memory operand, formed by the postopt pass. These use the
+# `op r, r/m` opcodes, so the memory operand is the second one.
+for recipe in [r.ldop, r.ldopDisp8, r.ldopDisp32]:
+    for inst,               opc in [
+            (x86.iadd_load, 0x03),
+            (x86.isub_load, 0x2b),
+            (x86.band_load, 0x23),
+            (x86.bor_load,  0x0b),
+            (x86.bxor_load, 0x33)]:
+        enc_i32_i64_ld_st(inst, True, recipe, opc)
+
 # Push and Pop
 X86_32.enc(x86.push.i32, *r.pushq(0x50))
 enc_x86_64(x86.push.i64, r.pushq, 0x50)
@@ -333,6 +443,10 @@ enc_x86_64(x86.push.i64, r.pushq, 0x50)
 X86_32.enc(x86.pop.i32, *r.popq(0x58))
 enc_x86_64(x86.pop.i64, r.popq, 0x58)
 
+# Time stamp counter.
+X86_32.enc(x86.rdtsc.i32, *r.rdtsc(0x0f, 0x31))
+X86_64.enc(x86.rdtsc.i64, *r.rdtsc(0x0f, 0x31))
+
 # Copy Special
 # For x86-64, only define REX forms for now, since we can't describe the
 # special regunit immediate operands with the current constraint language.
@@ -392,36 +506,54 @@ enc_both(base.store_complex.f64, r.fstWithIndexDisp8, 0xf2, 0x0f, 0x11)
 enc_both(base.store_complex.f64, r.fstWithIndexDisp32, 0xf2, 0x0f, 0x11)
 
 enc_both(base.fill.f32, r.ffillSib32, 0xf3, 0x0f, 0x10)
+enc_both(base.fill.f32, r.ffillSib8, 0xf3, 0x0f, 0x10)
 enc_both(base.regfill.f32, r.fregfill32, 0xf3, 0x0f, 0x10)
 enc_both(base.fill.f64, r.ffillSib32, 0xf2, 0x0f, 0x10)
+enc_both(base.fill.f64, r.ffillSib8, 0xf2, 0x0f, 0x10)
 enc_both(base.regfill.f64, r.fregfill32, 0xf2, 0x0f, 0x10)
 
 enc_both(base.spill.f32, r.fspillSib32, 0xf3, 0x0f, 0x11)
+enc_both(base.spill.f32, r.fspillSib8, 0xf3, 0x0f, 0x11)
 enc_both(base.regspill.f32, r.fregspill32, 0xf3, 0x0f, 0x11)
 enc_both(base.spill.f64, r.fspillSib32, 0xf2, 0x0f, 0x11)
+enc_both(base.spill.f64, r.fspillSib8, 0xf2, 0x0f, 0x11)
 enc_both(base.regspill.f64, r.fregspill32, 0xf2, 0x0f, 0x11)
 
 #
 # Function addresses.
 #
 
-# Non-PIC, all-ones funcaddresses.
-X86_32.enc(base.func_addr.i32, *r.fnaddr4(0xb8),
-           isap=And(Not(allones_funcaddrs), Not(is_pic)))
-X86_64.enc(base.func_addr.i64, *r.fnaddr8.rex(0xb8, w=1),
-           isap=And(Not(allones_funcaddrs), Not(is_pic)))
+# The code models where functions are within 2 GB of the code, and the code
+# model where data symbols are within 2 GB of the code too. These only apply
+# to non-PIC code; PIC code uses the GOT and PLT for symbols that aren't
+# colocated.
+near_funcs = And(Not(is_pic), Not(code_model.is_value('large')))
+near_data = And(Not(is_pic), code_model.is_value('small'))
 
-# Non-PIC, all-zeros funcaddresses.
+# Non-PIC, all-ones funcaddresses. These are patched by BaldrMonkey, so they
+# are used even for colocated functions.
 X86_32.enc(base.func_addr.i32, *r.allones_fnaddr4(0xb8),
            isap=And(allones_funcaddrs, Not(is_pic)))
 X86_64.enc(base.func_addr.i64, *r.allones_fnaddr8.rex(0xb8, w=1),
            isap=And(allones_funcaddrs, Not(is_pic)))
 
+# 32-bit, non-PIC.
+X86_32.enc(base.func_addr.i32, *r.fnaddr4(0xb8),
+           isap=And(Not(allones_funcaddrs), Not(is_pic)))
+
 # 64-bit, colocated, both PIC and non-PIC. Use the lea instruction's
 # pc-relative field.
 X86_64.enc(base.func_addr.i64, *r.pcrel_fnaddr8.rex(0x8d, w=1),
            instp=IsColocatedFunc(FuncAddr.func_ref))
 
+# 64-bit, non-colocated, non-PIC, small and medium code models.
+X86_64.enc(base.func_addr.i64, *r.pcrel_fnaddr8.rex(0x8d, w=1),
+           isap=near_funcs)
+
+# 64-bit, non-colocated, non-PIC, large code model.
+X86_64.enc(base.func_addr.i64, *r.fnaddr8.rex(0xb8, w=1),
+           isap=And(Not(allones_funcaddrs), Not(is_pic)))
+
 # 64-bit, non-colocated, PIC.
 X86_64.enc(base.func_addr.i64, *r.got_fnaddr8.rex(0x8b, w=1),
            isap=is_pic)
@@ -430,21 +562,76 @@ X86_64.enc(base.func_addr.i64, *r.got_fnaddr8.rex(0x8b, w=1),
 # Global addresses.
 #
 
-# Non-PIC
+# 32-bit, non-PIC.
 X86_32.enc(base.symbol_value.i32, *r.gvaddr4(0xb8),
            isap=Not(is_pic))
-X86_64.enc(base.symbol_value.i64, *r.gvaddr8.rex(0xb8, w=1),
-           isap=Not(is_pic))
 
-# PIC, colocated
+# 64-bit, colocated, both PIC and non-PIC.
 X86_64.enc(base.symbol_value.i64, *r.pcrel_gvaddr8.rex(0x8d, w=1),
-           isap=is_pic,
            instp=IsColocatedData())
 
-# PIC, non-colocated
+# 64-bit, non-colocated, non-PIC, small code model.
+X86_64.enc(base.symbol_value.i64, *r.pcrel_gvaddr8.rex(0x8d, w=1),
+           isap=near_data)
+
+# 64-bit, non-colocated, non-PIC, medium and large code models.
+X86_64.enc(base.symbol_value.i64, *r.gvaddr8.rex(0xb8, w=1),
+           isap=Not(is_pic))
+
+# 64-bit, non-colocated, PIC.
 X86_64.enc(base.symbol_value.i64, *r.got_gvaddr8.rex(0x8b, w=1),
            isap=is_pic)
 
+#
+# Loads and stores at symbol addresses.
+#
+
+# The absolute address of the symbol is used as a 32-bit displacement, so the
+# symbol must be in the low 2 GB of the address space. That's the case for
+# 32-bit non-PIC code and for 64-bit non-PIC code in the small code model.
+for inst,               recipe,     opc in [
+        (x86.load_symbol,  r.ldSymbol, 0x8b),
+        (x86.store_symbol, r.stSymbol, 0x89)]:
+    X86_32.enc(inst.i32.i32, *recipe(opc), isap=Not(is_pic))
+    X86_64.enc(inst.i32.i64, *recipe.rex(opc), isap=near_data)
+    X86_64.enc(inst.i32.i64, *recipe(opc), isap=near_data)
+    X86_64.enc(inst.i64.i64, *recipe.rex(opc, w=1), isap=near_data)
+
+for inst,               recipe,      opc in [
+        (x86.load_symbol,  r.fldSymbol, 0x10),
+        (x86.store_symbol, r.fstSymbol, 0x11)]:
+    for ty,         prefix in [(types.f32, 0xf3), (types.f64, 0xf2)]:
+        X86_32.enc(inst.bind(ty).i32, *recipe(prefix, 0x0f, opc),
+                   isap=Not(is_pic))
+        X86_64.enc(inst.bind(ty).i64, *recipe.rex(prefix, 0x0f, opc),
+                   isap=near_data)
+        X86_64.enc(inst.bind(ty).i64, *recipe(prefix, 0x0f, opc),
+                   isap=near_data)
+
+# 64-bit code can address symbols within 2 GB of the code relative to the
+# instruction pointer. That's the case for colocated symbols, and for all
+# symbols in non-PIC code in the small code model.
+for inst,              recipe,     opc,  fmt in [
+        (x86.load_pcrel,  r.ldPCRel, 0x8b, LoadGlobal),
+        (x86.store_pcrel, r.stPCRel, 0x89, LoadSymbol)]:
+    colocated = IsColocatedData(fmt.global_value)
+    for bound, rrr in [
+            (inst.i32, recipe.rex(opc)),
+            (inst.i32, recipe(opc)),
+            (inst.i64, recipe.rex(opc, w=1))]:
+        X86_64.enc(bound, *rrr, instp=colocated)
+        X86_64.enc(bound, *rrr, isap=near_data)
+
+for inst,              recipe,      opc,  fmt in [
+        (x86.load_pcrel,  r.fldPCRel, 0x10, LoadGlobal),
+        (x86.store_pcrel, r.fstPCRel, 0x11, LoadSymbol)]:
+    colocated = IsColocatedData(fmt.global_value)
+    for ty,         prefix in [(types.f32, 0xf3), (types.f64, 0xf2)]:
+        for rrr in [recipe.rex(prefix, 0x0f, opc),
+                    recipe(prefix, 0x0f, opc)]:
+            X86_64.enc(inst.bind(ty), *rrr, instp=colocated)
+            X86_64.enc(inst.bind(ty), *rrr, isap=near_data)
+
 #
 # Stack addresses.
 #
@@ -452,7 +639,9 @@ X86_64.enc(base.symbol_value.i64, *r.got_gvaddr8.rex(0x8b, w=1),
 # don't get legalized to stack_addr + load/store.
 #
 X86_32.enc(base.stack_addr.i32, *r.spaddr4_id(0x8d))
+X86_32.enc(base.stack_addr.i32, *r.spaddr4_ib(0x8d))
 X86_64.enc(base.stack_addr.i64, *r.spaddr8_id.rex(0x8d, w=1))
+X86_64.enc(base.stack_addr.i64, *r.spaddr8_ib.rex(0x8d, w=1))
 
 #
 # Call/return
@@ -466,11 +655,14 @@ X86_32.enc(base.call, *r.call_id(0xe8))
 X86_64.enc(base.call, *r.call_id(0xe8),
            instp=IsColocatedFunc(Call.func_ref))
 
-# 64-bit, non-colocated, PIC. There is no 64-bit non-colocated non-PIC version,
-# since non-PIC is currently using the large model, which requires calls be
-# lowered to func_addr+call_indirect.
+# 64-bit, non-colocated, PIC.
 X86_64.enc(base.call, *r.call_plt_id(0xe8), isap=is_pic)
 
+# 64-bit, non-colocated, non-PIC, small and medium code models. There is no
+# version for the large code model, which requires calls to be lowered to
+# func_addr+call_indirect.
+X86_64.enc(base.call, *r.call_id(0xe8), isap=near_funcs)
+
 X86_32.enc(base.call_indirect.i32, *r.call_r(0xff, rrr=2))
 X86_64.enc(base.call_indirect.i64, *r.call_r.rex(0xff, rrr=2))
 X86_64.enc(base.call_indirect.i64, *r.call_r(0xff, rrr=2))
@@ -537,12 +729,21 @@ X86_64.enc(base.trapff, r.trapff, 0)
 # Comparisons
 #
 enc_i32_i64(base.icmp, r.icscc, 0x39)
+# Comparisons with zero use TEST, which sets the flags the same way.
+enc_i32_i64(base.icmp_imm, r.icscc_z, 0x85)
 enc_i32_i64(base.icmp_imm, r.icscc_ib, 0x83, rrr=7)
 enc_i32_i64(base.icmp_imm, r.icscc_id, 0x81, rrr=7)
 enc_i32_i64(base.ifcmp, r.rcmp, 0x39)
+enc_i32_i64(base.ifcmp_imm, r.rcmp_z, 0x85)
 enc_i32_i64(base.ifcmp_imm, r.rcmp_ib, 0x83, rrr=7)
 enc_i32_i64(base.ifcmp_imm, r.rcmp_id, 0x81, rrr=7)
-# TODO: We could special-case ifcmp_imm(x, 0) to TEST(x, x).
+
+# Byte and word compares set the flags from the narrow values, so they don't
+# need the operands to be extended first.
+enc_both(base.icmp.i8, r.icscc_abcd, 0x38)
+enc_both(base.icmp.i16, r.icscc, 0x66, 0x39)
+enc_both(base.ifcmp.i8, r.rcmp_abcd, 0x38)
+enc_both(base.ifcmp.i16, r.rcmp, 0x66, 0x39)
 
 X86_32.enc(base.ifcmp_sp.i32, *r.rcmp_sp(0x39))
 X86_64.enc(base.ifcmp_sp.i64, *r.rcmp_sp.rex(0x39, w=1))
@@ -577,6 +778,28 @@ X86_64.enc(base.bint.i64.b1, *r.urm_noflags.rex(0x0f, 0xb6))
 X86_64.enc(base.bint.i64.b1, *r.urm_noflags_abcd(0x0f, 0xb6))
 X86_64.enc(base.bint.i32.b1, *r.urm_noflags.rex(0x0f, 0xb6))
 X86_64.enc(base.bint.i32.b1, *r.urm_noflags_abcd(0x0f, 0xb6))
+for ty in [types.i8, types.i16]:
+    X86_32.enc(base.bint.bind(ty, types.b1),
+               *r.urm_noflags_abcd(0x0f, 0xb6))
+    X86_64.enc(base.bint.bind(ty, types.b1), *r.urm_noflags.rex(0x0f, 0xb6))
+    X86_64.enc(base.bint.bind(ty, types.b1), *r.urm_noflags_abcd(0x0f, 0xb6))
+
+# Convert bool to mask with a `neg` of the 0 or 1 from `bint`. The larger bool
+# types are masks too, so extending a `b1` works the same way.
+for inst in [base.bmask.i8, base.bmask.i16, base.bmask.i32,
+             base.bextend.b8, base.bextend.b16, base.bextend.b32]:
+    X86_32.enc(inst.b1, *r.bmask_abcd(0xf7, rrr=3))
+    enc_x86_64(inst.b1, r.bmask_abcd, 0xf7, rrr=3)
+X86_64.enc(base.bmask.i64.b1, *r.bmask.rex(0xf7, rrr=3, w=1))
+X86_64.enc(base.bextend.b64.b1, *r.bmask.rex(0xf7, rrr=3, w=1))
+
+# Extending a larger bool sign-extends its bits, like `sextend`.
+enc_both(base.bextend.b16.b8, r.urm_noflags_abcd, 0x0f, 0xbe)
+enc_both(base.bextend.b32.b8, r.urm_noflags_abcd, 0x0f, 0xbe)
+enc_both(base.bextend.b32.b16, r.urm_noflags, 0x0f, 0xbf)
+X86_64.enc(base.bextend.b64.b8, *r.urm_noflags.rex(0x0f, 0xbe, w=1))
+X86_64.enc(base.bextend.b64.b16, *r.urm_noflags.rex(0x0f, 0xbf, w=1))
+X86_64.enc(base.bextend.b64.b32, *r.urm_noflags.rex(0x63, w=1))
 
 # Numerical conversions.
 
@@ -636,13 +859,21 @@ X86_64.enc(base.uextend.i64.i16, *r.urm_noflags(0x0f, 0xb7))
 X86_64.enc(base.uextend.i64.i32, *r.umr.rex(0x89))
 X86_64.enc(base.uextend.i64.i32, *r.umr(0x89))
 
+# The result of an instruction with a `zero_extends` recipe is already
+# zero-extended, so the extension needs no code. The post-opt pass picks this
+# encoding when it applies, so it must come after the copies.
+X86_64.enc(base.uextend.i64.i32, r.null, 0)
+
 
 #
 # Floating point
 #
 
 # floating-point constants equal to 0.0 can be encoded using either
-# `xorps` or `xorpd`, for 32-bit and 64-bit floats respectively.
+# `xorps` or `xorpd`, for 32-bit and 64-bit floats respectively. With AVX, the
+# `vxorps` zero idiom is used for both.
+enc_avx(base.f32const, r.vf32imm_z, 0x0f, 0x57)
+enc_avx(base.f64const, r.vf64imm_z, 0x0f, 0x57)
 X86_32.enc(base.f32const, *r.f32imm_z(0x0f, 0x57),
            instp=IsZero32BitFloat(UnaryIeee32.imm))
 X86_32.enc(base.f64const, *r.f64imm_z(0x66, 0x0f, 0x57),
@@ -653,6 +884,14 @@ enc_x86_64_instp(base.f32const, r.f32imm_z,
 enc_x86_64_instp(base.f64const, r.f64imm_z,
                  IsZero64BitFloat(UnaryIeee64.imm), 0x66, 0x0f, 0x57)
 
+# Other floating-point constants are loaded from the constant pool with
+# `movss` or `movsd`. This needs rip-relative addressing, so 32-bit x86 uses
+# an integer constant and a bitcast instead.
+enc_x86_64_instp(base.f32const, r.f32imm_pool,
+                 Not(IsZero32BitFloat(UnaryIeee32.imm)), 0xf3, 0x0f, 0x10)
+enc_x86_64_instp(base.f64const, r.f64imm_pool,
+                 Not(IsZero64BitFloat(UnaryIeee64.imm)), 0xf2, 0x0f, 0x10)
+
 # movd
 enc_both(base.bitcast.f32.i32, r.frurm, 0x66, 0x0f, 0x6e)
 enc_both(base.bitcast.i32.f32, r.rfumr, 0x66, 0x0f, 0x7e)
@@ -675,11 +914,25 @@ X86_64.enc(base.regmove.f32, *r.frmov.rex(0x0f, 0x28))
 X86_32.enc(base.regmove.f64, *r.frmov(0x0f, 0x28))
 X86_64.enc(base.regmove.f64, *r.frmov.rex(0x0f, 0x28))
 
-# cvtsi2ss
-enc_i32_i64(base.fcvt_from_sint.f32, r.frurm, 0xf3, 0x0f, 0x2a)
-
-# cvtsi2sd
-enc_i32_i64(base.fcvt_from_sint.f64, r.frurm, 0xf2, 0x0f, 0x2a)
+# With AVX, the integer to float conversions break the dependency on the old
+# value of the output register. The shorter SSE encodings don't, so they are
+# disabled to prevent instruction shrinking from picking them.
+for ty,         prefix in [
+        (types.f32, 0xf3),   # vcvtsi2ss / cvtsi2ss
+        (types.f64, 0xf2)]:  # vcvtsi2sd / cvtsi2sd
+    inst = base.fcvt_from_sint.bind(ty)
+    enc_avx(inst.i32, r.vfrurm, prefix, 0x0f, 0x2a)
+    X86_64.enc(inst.i64, *r.vfrurm.vex(prefix, 0x0f, 0x2a, w=1),
+               isap=cfg.use_avx)
+
+    X86_32.enc(inst.i32, *r.frurm(prefix, 0x0f, 0x2a),
+               isap=Not(cfg.use_avx))
+    X86_64.enc(inst.i32, *r.frurm.rex(prefix, 0x0f, 0x2a),
+               isap=Not(cfg.use_avx))
+    X86_64.enc(inst.i32, *r.frurm(prefix, 0x0f, 0x2a),
+               isap=Not(cfg.use_avx))
+    X86_64.enc(inst.i64, *r.frurm.rex(prefix, 0x0f, 0x2a, w=1),
+               isap=Not(cfg.use_avx))
 
 # cvtss2sd
 enc_both(base.fpromote.f64.f32, r.furm, 0xf3, 0x0f, 0x5a)
@@ -717,6 +970,8 @@ for inst,           opc in [
         (base.fdiv, 0x5e),
         (x86.fmin,  0x5d),
         (x86.fmax,  0x5f)]:
+    enc_avx(inst.f32, r.vfa, 0xf3, 0x0f, opc)
+    enc_avx(inst.f64, r.vfa, 0xf2, 0x0f, opc)
     enc_both(inst.f32, r.fa, 0xf3, 0x0f, opc)
     enc_both(inst.f64, r.fa, 0xf2, 0x0f, opc)
 
@@ -725,10 +980,14 @@ for inst,               opc in [
         (base.band,     0x54),
         (base.bor,      0x56),
         (base.bxor,     0x57)]:
+    enc_avx(inst.f32, r.vfa, 0x0f, opc)
+    enc_avx(inst.f64, r.vfa, 0x0f, opc)
     enc_both(inst.f32, r.fa, 0x0f, opc)
     enc_both(inst.f64, r.fa, 0x0f, opc)
 
 # The `andnps(x,y)` instruction computes `~x&y`, while band_not(x,y)` is `x&~y.
+enc_avx(base.band_not.f32, r.vfax, 0x0f, 0x55)
+enc_avx(base.band_not.f64, r.vfax, 0x0f, 0x55)
 enc_both(base.band_not.f32, r.fax, 0x0f, 0x55)
 enc_both(base.band_not.f64, r.fax, 0x0f, 0x55)
 
@@ -741,3 +1000,119 @@ enc_both(base.fcmp.f64, r.fcscc, 0x66, 0x0f, 0x2e)
 
 enc_both(base.ffcmp.f32, r.fcmp, 0x0f, 0x2e)
 enc_both(base.ffcmp.f64, r.fcmp, 0x66, 0x0f, 0x2e)
+
+
+#
+# SIMD
+#
+
+# Copies, register moves, spills, and fills of whole vectors use `movaps` and
+# `movups`. Stack slots are not 16-byte aligned, so memory accesses use the
+# unaligned form.
+for ty in VECTOR_TYPES:
+    enc_both(base.copy.bind(ty), r.furm, 0x0f, 0x28)
+    X86_32.enc(base.regmove.bind(ty), *r.frmov(0x0f, 0x28))
+    X86_64.enc(base.regmove.bind(ty), *r.frmov.rex(0x0f, 0x28))
+
+    enc_both(base.fill.bind(ty), r.ffillSib32, 0x0f, 0x10)
+    enc_both(base.fill.bind(ty), r.ffillSib8, 0x0f, 0x10)
+    enc_both(base.regfill.bind(ty), r.fregfill32, 0x0f, 0x10)
+    enc_both(base.spill.bind(ty), r.fspillSib32, 0x0f, 0x11)
+    enc_both(base.spill.bind(ty), r.fspillSib8, 0x0f, 0x11)
+    enc_both(base.regspill.bind(ty), r.fregspill32, 0x0f, 0x11)
+
+for ty in INT_VECTOR_TYPES + FLOAT_VECTOR_TYPES:
+    enc_both(base.load.bind(ty).any, r.fld, 0x0f, 0x10)
+    enc_both(base.load.bind(ty).any, r.fldDisp8, 0x0f, 0x10)
+    enc_both(base.load.bind(ty).any, r.fldDisp32, 0x0f, 0x10)
+    enc_both(base.store.bind(ty).any, r.fst, 0x0f, 0x11)
+    enc_both(base.store.bind(ty).any, r.fstDisp8, 0x0f, 0x11)
+    enc_both(base.store.bind(ty).any, r.fstDisp32, 0x0f, 0x11)
+
+# The lanes of a boolean vector are all ones or all zeros, which is the same
+# as the integer mask. All the integer and float vectors are held in the same
+# XMM registers, so a bitcast between them doesn't need any code.
+for bool_ty, int_ty in zip(BOOL_VECTOR_TYPES, INT_VECTOR_TYPES):
+    X86_32.enc(base.bmask.bind(int_ty, bool_ty), r.null_fpr, 0)
+    X86_64.enc(base.bmask.bind(int_ty, bool_ty), r.null_fpr, 0)
+
+for to_ty in INT_VECTOR_TYPES + FLOAT_VECTOR_TYPES:
+    for from_ty in INT_VECTOR_TYPES + FLOAT_VECTOR_TYPES:
+        if from_ty != to_ty:
+            for mode in [X86_32, X86_64]:
+                mode.enc(base.bitcast.bind(to_ty, from_ty), r.null_fpr, 0)
+
+# A scalar float is held in lane 0 of its XMM register, so moving it into or
+# out of lane 0 of a vector doesn't need any code when the register allocator
+# assigns the scalar and the vector the same register. Otherwise it inserts a
+# copy. Integer scalars are moved between the GPRs and lane 0 with movd/movq.
+lane0 = IsEqual(ExtractLane.lane, 0)
+for ty in FLOAT_VECTOR_TYPES:
+    for mode in [X86_32, X86_64]:
+        mode.enc(base.scalar_to_vector.bind(ty), r.null_fpr, 0)
+        mode.enc(base.extractlane.bind(ty), r.null_fpr_lane, 0, instp=lane0)
+
+for ty in INT_VECTOR_TYPES[:3]:
+    enc_both(base.scalar_to_vector.bind(ty), r.frurm, 0x66, 0x0f, 0x6e)
+    enc_both_instp(base.extractlane.bind(ty), r.rfumr_lane, lane0,
+                   0x66, 0x0f, 0x7e)
+
+i64x2 = types.i64.by(2)
+X86_64.enc(base.scalar_to_vector.bind(i64x2),
+           *r.frurm.rex(0x66, 0x0f, 0x6e, w=1))
+X86_64.enc(base.extractlane.bind(i64x2),
+           *r.rfumr_lane.rex(0x66, 0x0f, 0x7e, w=1), instp=lane0)
+
+# The `pshufd` instruction copies the low lane of a scalar float to all the
+# lanes of the vector.
+for ty in FLOAT_VECTOR_TYPES:
+    enc_both(base.splat.bind(ty), r.furmi_splat, 0x66, 0x0f, 0x70)
+
+# Binary bitwise ops: pand, por, pxor.
+for inst,               opc in [
+        (base.band,     0xdb),
+        (base.bor,      0xeb),
+        (base.bxor,     0xef)]:
+    for ty in VECTOR_TYPES:
+        enc_both(inst.bind(ty), r.fa, 0x66, 0x0f, opc)
+
+# The `pandn(x,y)` instruction computes `~x&y`, while band_not(x,y)` is `x&~y.
+for ty in VECTOR_TYPES:
+    enc_both(base.band_not.bind(ty), r.fax, 0x66, 0x0f, 0xdf)
+
+
+def enc_vector_icmp(ty, cc, ops, isap=None):
+    # type: (ValueType, Any, Tuple[int, ...], PredNode) -> None
+    """
+    Add encodings for comparing integer vectors of type `ty` with the
+    condition code `cc` to both X86_32 and X86_64.
+    """
+    inst = base.icmp.bind(ty)
+    instp = IsEqual(IntCompare.cond, cc)
+    X86_32.enc(inst, *r.icscc_fpr(*ops), instp=instp, isap=isap)
+    X86_64.enc(inst, *r.icscc_fpr.rex(*ops), instp=instp, isap=isap)
+    X86_64.enc(inst, *r.icscc_fpr(*ops), instp=instp, isap=isap)
+
+
+# Comparisons: pcmpeq and pcmpgt. The other condition codes are handled by
+# legalization patterns.
+for ty,                 eq_op,                  gt_op in [
+        (types.i8.by(16), (0x66, 0x0f, 0x74), (0x66, 0x0f, 0x64)),
+        (types.i16.by(8), (0x66, 0x0f, 0x75), (0x66, 0x0f, 0x65)),
+        (types.i32.by(4), (0x66, 0x0f, 0x76), (0x66, 0x0f, 0x66))]:
+    enc_vector_icmp(ty, intcc.eq, eq_op)
+    enc_vector_icmp(ty, intcc.sgt, gt_op)
+
+enc_vector_icmp(types.i64.by(2), intcc.eq, (0x66, 0x0f, 0x38, 0x29),
+                isap=use_sse41)
+enc_vector_icmp(types.i64.by(2), intcc.sgt, (0x66, 0x0f, 0x38, 0x37),
+                isap=use_sse42)
+
+# The `pblendvb` instruction selects bytes, which works for all lane sizes
+# because the lanes of the mask are all ones or all zeros. Without SSE 4.1,
+# `vselect` is expanded into bitwise operations.
+for ty in VECTOR_TYPES:
+    inst = base.vselect.bind(ty)
+    X86_32.enc(inst, *r.blend(0x66, 0x0f, 0x38, 0x10), isap=use_sse41)
+    X86_64.enc(inst, *r.blend.rex(0x66, 0x0f, 0x38, 0x10), isap=use_sse41)
+    X86_64.enc(inst, *r.blend(0x66, 0x0f, 0x38, 0x10), isap=use_sse41)
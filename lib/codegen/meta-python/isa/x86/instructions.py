@@ -6,6 +6,8 @@ target ISA.
 """
 
 from base.types import iflags
+from base.immediates import memflags, offset32
+from base import entities
 from cdsl.operands import Operand
 from cdsl.typevar import TypeVar
 from cdsl.instructions import Instruction, InstructionGroup
@@ -170,4 +172,108 @@ bsf = Instruction(
     """,
     ins=x, outs=(y, rflags))
 
+lo = Operand('lo', iWord, doc='Low 32 bits of the counter')
+hi = Operand('hi', iWord, doc='High 32 bits of the counter')
+
+rdtsc = Instruction(
+    'x86_rdtsc', r"""
+    Read the time stamp counter.
+
+    Returns the low and high 32 bits of the 64-bit counter, zero-extended to
+    the word size.
+
+    This is polymorphic in i32 and i64. However, it is only implemented for i64
+    in 64-bit mode, and only for i32 in 32-bit mode.
+    """,
+    outs=(lo, hi), other_side_effects=True)
+
+
+iAddr = TypeVar('iAddr', 'An integer address type', ints=(32, 64))
+x = Operand('x', iWord)
+p = Operand('p', iAddr)
+MemFlags = Operand('MemFlags', memflags)
+Offset = Operand('Offset', offset32, 'Byte offset from base address')
+a = Operand('a', iWord)
+
+iadd_load = Instruction(
+    'x86_iadd_load', r"""
+    Add ``x`` and the value loaded from memory at ``p + Offset``.
+
+    This is the ``add r, [m]`` form of the x86 add instruction. The postopt
+    pass folds a ``load`` into the ``iadd`` using its only result.
+    """,
+    ins=(MemFlags, x, p, Offset), outs=a, can_load=True)
+
+isub_load = Instruction(
+    'x86_isub_load', r"""
+    Subtract the value loaded from memory at ``p + Offset`` from ``x``.
+    """,
+    ins=(MemFlags, x, p, Offset), outs=a, can_load=True)
+
+band_load = Instruction(
+    'x86_band_load', r"""
+    Bitwise and of ``x`` and the value loaded from memory at ``p + Offset``.
+    """,
+    ins=(MemFlags, x, p, Offset), outs=a, can_load=True)
+
+bor_load = Instruction(
+    'x86_bor_load', r"""
+    Bitwise or of ``x`` and the value loaded from memory at ``p + Offset``.
+    """,
+    ins=(MemFlags, x, p, Offset), outs=a, can_load=True)
+
+bxor_load = Instruction(
+    'x86_bxor_load', r"""
+    Bitwise xor of ``x`` and the value loaded from memory at ``p + Offset``.
+    """,
+    ins=(MemFlags, x, p, Offset), outs=a, can_load=True)
+
+Mem = TypeVar(
+        'Mem', 'A scalar integer or float type that can be loaded',
+        ints=(32, 64), floats=True)
+GV = Operand('GV', entities.global_value, doc='A symbol global value')
+x = Operand('x', Mem, doc='Value to be stored')
+a = Operand('a', Mem, doc='Value loaded')
+
+load_symbol = Instruction(
+    'x86_load_symbol', r"""
+    Load from the address of the symbol ``GV`` plus ``p + Offset``.
+
+    This is the ``mov r, [p + sym + disp32]`` form of the x86 load with no base
+    register, where the absolute address of the symbol is added to the
+    displacement by a relocation. The postopt pass forms it from the ``load``
+    of a heap whose base is a symbol, so the address of the symbol isn't
+    materialized in a register.
+    """,
+    ins=(MemFlags, p, GV, Offset), outs=a, can_load=True)
+
+store_symbol = Instruction(
+    'x86_store_symbol', r"""
+    Store ``x`` to the address of the symbol ``GV`` plus ``p + Offset``.
+
+    This is the store version of :inst:`x86_load_symbol`.
+    """,
+    ins=(MemFlags, x, p, GV, Offset), can_store=True)
+
+load_pcrel = Instruction(
+    'x86_load_pcrel', r"""
+    Load from the address of the symbol ``GV`` plus ``Offset``.
+
+    This is the ``mov r, [rip + disp32]`` form of the x86-64 load, where the
+    displacement from the end of the instruction to the symbol is filled in by
+    a PC-relative relocation. The symbol must be within 2 GB of the code, so
+    it is colocated or the code model is small. The postopt pass forms it from
+    the ``load`` of a single-use ``symbol_value``, so the address of the symbol
+    isn't materialized in a register.
+    """,
+    ins=(MemFlags, GV, Offset), outs=a, can_load=True)
+
+store_pcrel = Instruction(
+    'x86_store_pcrel', r"""
+    Store ``x`` to the address of the symbol ``GV`` plus ``Offset``.
+
+    This is the store version of :inst:`x86_load_pcrel`.
+    """,
+    ins=(MemFlags, x, GV, Offset), can_store=True)
+
 GROUP.close()
@@ -88,9 +88,143 @@ for cc,               rev_cc in [
                 a << insts.fcmp(rev_cc, y, x)
             ))
 
-# We need to modify the CFG for min/max legalization.
-x86_expand.custom_legalize(insts.fmin, 'expand_minmax')
-x86_expand.custom_legalize(insts.fmax, 'expand_minmax')
+# The operands can't be reversed when testing the flags from an `ffcmp`, so
+# the remaining condition codes are combined from two supported ones instead.
+f = Var('f')
+c = Var('c')
+p = Var('p')
+
+# These condition codes are false when the operands are unordered.
+for cc,               ord_cc in [
+        (floatcc.eq,  floatcc.ueq),
+        (floatcc.lt,  floatcc.ult),
+        (floatcc.le,  floatcc.ule)]:
+    x86_expand.legalize(
+            a << insts.trueff(cc, f),
+            Rtl(
+                a1 << insts.trueff(floatcc.ord, f),
+                a2 << insts.trueff(ord_cc, f),
+                a << insts.band(a1, a2)
+            ))
+    x86_expand.legalize(
+            insts.trapff(cc, f, c, p),
+            Rtl(
+                a << insts.trueff(cc, f),
+                insts.trapnz(a, c, p)
+            ))
+
+# These condition codes are true when the operands are unordered.
+for cc,               ord_cc in [
+        (floatcc.ne,  floatcc.one),
+        (floatcc.ugt, floatcc.gt),
+        (floatcc.uge, floatcc.ge)]:
+    x86_expand.legalize(
+            a << insts.trueff(cc, f),
+            Rtl(
+                a1 << insts.trueff(floatcc.uno, f),
+                a2 << insts.trueff(ord_cc, f),
+                a << insts.bor(a1, a2)
+            ))
+    x86_expand.legalize(
+            insts.trapff(cc, f, c, p),
+            Rtl(
+                insts.trapff(floatcc.uno, f, c, p),
+                insts.trapff(ord_cc, f, c, p)
+            ))
+
+# Branches can't be combined like this, see `expand_brff`.
+x86_expand.custom_legalize(insts.brff, 'expand_brff')
+
+# The carry and borrow of `add` and `sub` are in the CPU flags, so they are
+# read from there instead of comparing the result with an operand.
+x86_expand.legalize(
+        (a, c) << insts.iadd_cout(x, y),
+        Rtl(
+            (a, f) << insts.iadd_ifcout(x, y),
+            c << insts.trueif(intcc.ult, f)
+        ))
+x86_expand.legalize(
+        (a, c) << insts.isub_bout(x, y),
+        Rtl(
+            (a, f) << insts.isub_ifbout(x, y),
+            c << insts.trueif(intcc.ult, f)
+        ))
+
+# The x86 min/max instructions return their second operand when the operands
+# are unordered or both zero, so they are computed with the operands in both
+# orders and the results are combined:
+#
+# - For `fmin`, `bor` picks -0.0 over +0.0 and produces a NaN when either
+#   result is a NaN.
+# - For `fmax`, `bxor` finds the bits where the results differ, which are the
+#   sign bit of zeros of different signs or the bits of a NaN. The `bor` keeps
+#   a NaN result, and subtracting the difference turns -0.0 into +0.0 and
+#   quiets NaNs.
+#
+# When the results are the same, the difference is +0.0 and the subtraction
+# doesn't change the result.
+diff = Var('diff')
+merged = Var('merged')
+x86_expand.legalize(
+        a << insts.fmin(x, y),
+        Rtl(
+            a1 << x86.fmin(x, y),
+            a2 << x86.fmin(y, x),
+            a << insts.bor(a1, a2)
+        ))
+x86_expand.legalize(
+        a << insts.fmax(x, y),
+        Rtl(
+            a1 << x86.fmax(x, y),
+            a2 << x86.fmax(y, x),
+            diff << insts.bxor(a1, a2),
+            merged << insts.bor(a1, diff),
+            a << insts.fsub(merged, diff)
+        ))
+
+# The pseudo min/max instructions have the semantics of the x86 instructions.
+x86_expand.legalize(
+        a << insts.fmin_pseudo(x, y),
+        Rtl(
+            a << x86.fmin(x, y)
+        ))
+x86_expand.legalize(
+        a << insts.fmax_pseudo(x, y),
+        Rtl(
+            a << x86.fmax(x, y)
+        ))
+
+# Integer vector comparisons.
+#
+# SSE only compares integer vectors for `eq` and `sgt`. The other signed
+# condition codes reverse the operands, or flip the bits of the result by
+# xoring them with the all-ones lanes from comparing a value to itself. There
+# are no patterns for the unsigned condition codes.
+x86_expand.legalize(
+        a << insts.icmp(intcc.slt, x, y),
+        Rtl(
+            a << insts.icmp(intcc.sgt, y, x)
+        ))
+
+for cc,               inv_cc,      swap in [
+        (intcc.ne,    intcc.eq,    False),
+        (intcc.sle,   intcc.sgt,   False),
+        (intcc.sge,   intcc.sgt,   True)]:
+    x86_expand.legalize(
+            a << insts.icmp(cc, x, y),
+            Rtl(
+                a1 << (insts.icmp(inv_cc, y, x) if swap
+                       else insts.icmp(inv_cc, x, y)),
+                a2 << insts.icmp(intcc.eq, x, x),
+                a << insts.bxor(a1, a2)
+            ))
+
+# Without SSE 4.1, rounding is done inline or with library calls, depending
+# on the optimization level.
+x86_expand.custom_legalize(insts.ceil, 'expand_round')
+x86_expand.custom_legalize(insts.floor, 'expand_round')
+x86_expand.custom_legalize(insts.trunc, 'expand_round')
+x86_expand.custom_legalize(insts.nearest, 'expand_round')
 
 # Conversions from unsigned need special handling.
 x86_expand.custom_legalize(insts.fcvt_from_uint, 'expand_fcvt_from_uint')
@@ -100,6 +234,11 @@ x86_expand.custom_legalize(insts.fcvt_to_uint, 'expand_fcvt_to_uint')
 x86_expand.custom_legalize(insts.fcvt_to_sint_sat, 'expand_fcvt_to_sint_sat')
 x86_expand.custom_legalize(insts.fcvt_to_uint_sat, 'expand_fcvt_to_uint_sat')
 
+# The cycle counter is read with rdtsc, which returns the counter in two
+# halves.
+x86_expand.custom_legalize(
+        insts.read_cycle_counter, 'expand_read_cycle_counter')
+
 # Count leading and trailing zeroes, for baseline x86_64
 c_minus_one = Var('c_minus_one')
 c_thirty_one = Var('c_thirty_one')
@@ -3,7 +3,7 @@ x86 Encoding recipes.
 """
 from __future__ import absolute_import
 from cdsl.isa import EncRecipe
-from cdsl.predicates import IsSignedInt, IsEqual, Or
+from cdsl.predicates import IsSignedInt, IsEqual, Or, Not
 from cdsl.predicates import IsZero32BitFloat, IsZero64BitFloat
 from cdsl.registers import RegClass
 from base.formats import Unary, UnaryIeee32, UnaryIeee64, UnaryImm, UnaryBool
@@ -15,9 +15,10 @@ from base.formats import IntCond, FloatCond
 from base.formats import IntSelect, IntCondTrap, FloatCondTrap
 from base.formats import Jump, Branch, BranchInt, BranchFloat
 from base.formats import BranchTableEntry, BranchTableBase, IndirectJump
-from base.formats import Ternary, FuncAddr, UnaryGlobalValue
+from base.formats import Ternary, FuncAddr, UnaryGlobalValue, ExtractLane
 from base.formats import RegMove, RegSpill, RegFill, CopySpecial
-from base.formats import LoadComplex, StoreComplex
+from base.formats import LoadComplex, StoreComplex, LoadSymbol, StoreSymbol
+from base.formats import LoadGlobal
 from base.formats import StackLoad
 from .registers import GPR, ABCD, FPR
 from .registers import GPR8, FPR8, FLAG
@@ -61,7 +62,8 @@ OPCODE_PREFIX = {
         }
 
 # The table above does not include the REX prefix which goes after the
-# mandatory prefix. VEX/XOP and EVEX prefixes are not yet supported. Encodings
+# mandatory prefix. The VEX prefix replaces the mandatory prefix, the REX prefix
+# and the opcode map. XOP and EVEX prefixes are not yet supported. Encodings
 # using any of these prefixes are represented by separate recipes.
 #
 # The encoding bits are:
@@ -81,7 +83,7 @@ OPCODE_PREFIX = {
 # 15:    REX.W bit (or VEX.W/E)
 #
 # There is some redundancy between bits 8-11 and the recipe names, but we have
-# enough bits, and the pp+mm format is ready for supporting VEX prefixes.
+# enough bits, and the pp+mm format is the same as in the VEX prefix.
 
 
 def decode_ops(ops, rrr=0, w=0):
@@ -158,7 +160,9 @@ class TailRecipe:
             ins,                    # type: ConstraintSeq
             outs,                   # type: ConstraintSeq
             branch_range=None,      # type: int
+            stack_disp_bits=None,   # type: int
             clobbers_flags=True,    # type: bool
+            zero_extends=False,     # type: bool
             instp=None,             # type: PredNode
             isap=None,              # type: PredNode
             when_prefixed=None,     # type: TailRecipe
@@ -173,7 +177,9 @@ class TailRecipe:
         self.ins = ins
         self.outs = outs
         self.branch_range = branch_range
+        self.stack_disp_bits = stack_disp_bits
         self.clobbers_flags = clobbers_flags
+        self.zero_extends = zero_extends
         self.instp = instp
         self.isap = isap
         self.when_prefixed = when_prefixed
@@ -209,7 +215,9 @@ class TailRecipe:
                 ins=self.ins,
                 outs=self.outs,
                 branch_range=branch_range,
+                stack_disp_bits=self.stack_disp_bits,
                 clobbers_flags=self.clobbers_flags,
+                zero_extends=self.zero_extends,
                 instp=self.instp,
                 isap=self.isap,
                 emit=replace_put_op(self.emit, name),
@@ -253,7 +261,9 @@ class TailRecipe:
                 ins=self.ins,
                 outs=self.outs,
                 branch_range=branch_range,
+                stack_disp_bits=self.stack_disp_bits,
                 clobbers_flags=self.clobbers_flags,
+                zero_extends=self.zero_extends,
                 instp=self.instp,
                 isap=self.isap,
                 emit=replace_put_op(self.emit, name),
@@ -262,6 +272,43 @@ class TailRecipe:
 
         return (self.recipes[name], bits)
 
+    def vex(self, *ops, **kwargs):
+        # type: (*int, **int) -> Tuple[EncRecipe, int]
+        """
+        Create a VEX encoding recipe and encoding bits for the opcode bytes in
+        `ops`.
+
+        The recipe always generates a three-byte VEX prefix, which encodes the
+        mandatory prefix and the opcode map of `ops`, followed by the last
+        opcode byte. The vector length VEX.L is always 0.
+
+        The `PUT_OP` function used by VEX recipes takes the register to encode
+        in the VEX.vvvv field as an extra argument before the code sink.
+        """
+        w = kwargs.get('w', 0)
+        name, bits = decode_ops(ops, 0, w)
+        assert len(ops) > 1, "VEX encodings require an opcode map"
+        name = 'Vex' + name
+        base_size = 4 + self.base_size
+
+        if name not in self.recipes:
+            recipe = EncRecipe(
+                name + self.name,
+                self.format,
+                base_size,
+                ins=self.ins,
+                outs=self.outs,
+                stack_disp_bits=self.stack_disp_bits,
+                clobbers_flags=self.clobbers_flags,
+                zero_extends=self.zero_extends,
+                instp=self.instp,
+                isap=self.isap,
+                emit=replace_put_op(self.emit, 'Vex'),
+                compute_size=self.compute_size)
+            self.recipes[name] = recipe
+
+        return (self.recipes[name], bits)
+
     @staticmethod
     def check_names(globs):
         # type: (Dict[str, Any]) -> None
@@ -294,12 +341,19 @@ def valid_scale(iform):
 # A null unary instruction that takes a GPR register. Can be used for identity
 # copies and no-op conversions.
 null = EncRecipe('null', Unary, base_size=0, ins=GPR, outs=0, emit='')
+null_fpr = EncRecipe(
+        'null_fpr', Unary, base_size=0, ins=FPR, outs=0, emit='')
+
+# A null instruction that reads lane 0 of a vector in an FPR register. The
+# scalar result is the low lane of the same register.
+null_fpr_lane = EncRecipe(
+        'null_fpr_lane', ExtractLane, base_size=0, ins=FPR, outs=0, emit='')
 
 # XX opcode, no ModR/M.
 trap = TailRecipe(
         'trap', Trap, base_size=0, ins=(), outs=(),
         emit='''
-        sink.trap(code, func.srclocs[inst]);
+        sink.trap(code, func.srclocs[inst], payload.into());
         PUT_OP(bits, BASE_REX, sink);
         ''')
 
@@ -312,7 +366,7 @@ trapif = EncRecipe(
         sink.put1(0x70 | (icc2opc(cond.inverse()) as u8));
         sink.put1(2);
         // ud2.
-        sink.trap(code, func.srclocs[inst]);
+        sink.trap(code, func.srclocs[inst], payload.into());
         sink.put1(0x0f);
         sink.put1(0x0b);
         ''')
@@ -326,7 +380,7 @@ trapff = EncRecipe(
         sink.put1(0x70 | (fcc2opc(cond.inverse()) as u8));
         sink.put1(2);
         // ud2.
-        sink.trap(code, func.srclocs[inst]);
+        sink.trap(code, func.srclocs[inst], payload.into());
         sink.put1(0x0f);
         sink.put1(0x0b);
         ''')
@@ -335,6 +389,28 @@ trapff = EncRecipe(
 # XX /r
 rr = TailRecipe(
         'rr', Binary, base_size=1, ins=(GPR, GPR), outs=0,
+        zero_extends=True,
+        emit='''
+        PUT_OP(bits, rex2(in_reg0, in_reg1), sink);
+        modrm_rr(in_reg0, in_reg1, sink);
+        ''')
+
+# XX /r with a flags output. Same as rr, but the flags set by the instruction
+# are a result.
+rout = TailRecipe(
+        'rout', Binary, base_size=1, ins=(GPR, GPR), outs=(0, FLAG.rflags),
+        zero_extends=True,
+        emit='''
+        PUT_OP(bits, rex2(in_reg0, in_reg1), sink);
+        modrm_rr(in_reg0, in_reg1, sink);
+        ''')
+
+# XX /r. Same as rr, but limited to ABCD. This is for byte operations with no
+# REX prefix.
+rr_abcd = TailRecipe(
+        'rr_abcd', Binary, base_size=1, ins=(ABCD, ABCD), outs=0,
+        zero_extends=True,
+        when_prefixed=rr,
         emit='''
         PUT_OP(bits, rex2(in_reg0, in_reg1), sink);
         modrm_rr(in_reg0, in_reg1, sink);
@@ -343,6 +419,7 @@ rr = TailRecipe(
 # XX /r with operands swapped. (RM form).
 rrx = TailRecipe(
         'rrx', Binary, base_size=1, ins=(GPR, GPR), outs=0,
+        zero_extends=True,
         emit='''
         PUT_OP(bits, rex2(in_reg1, in_reg0), sink);
         modrm_rr(in_reg1, in_reg0, sink);
@@ -356,6 +433,27 @@ fa = TailRecipe(
         modrm_rr(in_reg1, in_reg0, sink);
         ''')
 
+# XX /r with FPR ins and outs, for the lane-wise integer vector comparisons.
+# The opcode determines the condition code.
+icscc_fpr = TailRecipe(
+        'icscc_fpr', IntCompare, base_size=1, ins=(FPR, FPR), outs=0,
+        clobbers_flags=False,
+        emit='''
+        PUT_OP(bits, rex2(in_reg1, in_reg0), sink);
+        modrm_rr(in_reg1, in_reg0, sink);
+        ''')
+
+# XX /r with the mask in %xmm0, for the variable blend instructions. The bytes
+# of the first value operand are selected where the mask is set, and the
+# output is tied to the second value operand.
+blend = TailRecipe(
+        'blend', Ternary, base_size=1, ins=(FPR.xmm0, FPR, FPR), outs=2,
+        clobbers_flags=False,
+        emit='''
+        PUT_OP(bits, rex2(in_reg1, in_reg2), sink);
+        modrm_rr(in_reg1, in_reg2, sink);
+        ''')
+
 # XX /r with FPR ins and outs. A form with input operands swapped.
 fax = TailRecipe(
         'fax', Binary, base_size=1, ins=(FPR, FPR), outs=1,
@@ -364,9 +462,27 @@ fax = TailRecipe(
         modrm_rr(in_reg0, in_reg1, sink);
         ''')
 
+# VEX XX /r with FPR ins and outs. The three-operand form of `fa` with the
+# first operand in VEX.vvvv, so the output doesn't need to be tied to it.
+vfa = TailRecipe(
+        'vfa', Binary, base_size=1, ins=(FPR, FPR), outs=FPR,
+        emit='''
+        PUT_OP(bits, rex2(in_reg1, out_reg0), in_reg0, sink);
+        modrm_rr(in_reg1, out_reg0, sink);
+        ''')
+
+# VEX XX /r with FPR ins and outs. The three-operand form of `fax`.
+vfax = TailRecipe(
+        'vfax', Binary, base_size=1, ins=(FPR, FPR), outs=FPR,
+        emit='''
+        PUT_OP(bits, rex2(in_reg0, out_reg0), in_reg1, sink);
+        modrm_rr(in_reg0, out_reg0, sink);
+        ''')
+
 # XX /n for a unary operation with extension bits.
 ur = TailRecipe(
         'ur', Unary, base_size=1, ins=GPR, outs=0,
+        zero_extends=True,
         emit='''
         PUT_OP(bits, rex1(in_reg0), sink);
         modrm_r_bits(in_reg0, bits, sink);
@@ -376,6 +492,7 @@ ur = TailRecipe(
 # copies. MR form, preserving flags.
 umr = TailRecipe(
         'umr', Unary, base_size=1, ins=GPR, outs=GPR,
+        zero_extends=True,
         clobbers_flags=False,
         emit='''
         PUT_OP(bits, rex2(out_reg0, in_reg0), sink);
@@ -395,6 +512,7 @@ rfumr = TailRecipe(
 # RM form. Clobbers FLAGS.
 urm = TailRecipe(
         'urm', Unary, base_size=1, ins=GPR, outs=GPR,
+        zero_extends=True,
         emit='''
         PUT_OP(bits, rex2(in_reg0, out_reg0), sink);
         modrm_rr(in_reg0, out_reg0, sink);
@@ -403,6 +521,7 @@ urm = TailRecipe(
 # XX /r. Same as urm, but doesn't clobber FLAGS.
 urm_noflags = TailRecipe(
         'urm_noflags', Unary, base_size=1, ins=GPR, outs=GPR,
+        zero_extends=True,
         clobbers_flags=False,
         emit='''
         PUT_OP(bits, rex2(in_reg0, out_reg0), sink);
@@ -412,6 +531,7 @@ urm_noflags = TailRecipe(
 # XX /r. Same as urm_noflags, but input limited to ABCD.
 urm_noflags_abcd = TailRecipe(
         'urm_noflags_abcd', Unary, base_size=1, ins=ABCD, outs=GPR,
+        zero_extends=True,
         when_prefixed=urm_noflags,
         clobbers_flags=False,
         emit='''
@@ -419,6 +539,36 @@ urm_noflags_abcd = TailRecipe(
         modrm_rr(in_reg0, out_reg0, sink);
         ''')
 
+# XX /n for converting a `b1` to a mask in place. The `b1` is only defined in
+# the low byte of its register, so a `movzbl` clears the other bits first, and
+# then XX /n negates the 0 or 1 into all zeros or all ones.
+bmask = TailRecipe(
+        'bmask', Unary, base_size=5, ins=GPR, outs=0,
+        requires_prefix=True,
+        zero_extends=True,
+        emit='''
+        // The REX prefix of `movzbl` selects the low byte of any register.
+        sink.put1(rex2(in_reg0, in_reg0));
+        sink.put1(0x0f);
+        sink.put1(0xb6);
+        modrm_rr(in_reg0, in_reg0, sink);
+        PUT_OP(bits, rex1(in_reg0), sink);
+        modrm_r_bits(in_reg0, bits, sink);
+        ''')
+
+# XX /n. Same as bmask, but input limited to ABCD, so `movzbl` needs no REX.
+bmask_abcd = TailRecipe(
+        'bmask_abcd', Unary, base_size=4, ins=ABCD, outs=0,
+        when_prefixed=bmask,
+        zero_extends=True,
+        emit='''
+        sink.put1(0x0f);
+        sink.put1(0xb6);
+        modrm_rr(in_reg0, in_reg0, sink);
+        PUT_OP(bits, rex1(in_reg0), sink);
+        modrm_r_bits(in_reg0, bits, sink);
+        ''')
+
 # XX /r, RM form, FPR -> FPR.
 furm = TailRecipe(
         'furm', Unary, base_size=1, ins=FPR, outs=FPR,
@@ -437,6 +587,30 @@ frurm = TailRecipe(
         modrm_rr(in_reg0, out_reg0, sink);
         ''')
 
+# XX /r, RM form, FPR -> GPR, reading lane 0 of a vector.
+rfumr_lane = TailRecipe(
+        'rfumr_lane', ExtractLane, base_size=1, ins=FPR, outs=GPR,
+        clobbers_flags=False,
+        emit='''
+        PUT_OP(bits, rex2(out_reg0, in_reg0), sink);
+        modrm_rr(out_reg0, in_reg0, sink);
+        ''')
+
+# VEX XX /r, RM form, GPR -> FPR, for the integer to float conversions. These
+# merge the result into the upper bits of the VEX.vvvv register, so the output
+# register is zeroed with `vxorps` first to break the dependency on its old
+# value.
+vfrurm = TailRecipe(
+        'vfrurm', Unary, base_size=6, ins=GPR, outs=FPR,
+        clobbers_flags=False,
+        emit='''
+        // vxorps out_reg0, out_reg0, out_reg0
+        PUT_OP(0x0457, rex2(out_reg0, out_reg0), out_reg0, sink);
+        modrm_rr(out_reg0, out_reg0, sink);
+        PUT_OP(bits, rex2(in_reg0, out_reg0), out_reg0, sink);
+        modrm_rr(in_reg0, out_reg0, sink);
+        ''')
+
 # XX /r, RM form, FPR -> GPR.
 rfurm = TailRecipe(
         'rfurm', Unary, base_size=1, ins=FPR, outs=GPR,
@@ -462,6 +636,21 @@ furmi_rnd = TailRecipe(
         });
         ''')
 
+# XX /r ib with FPR ins and outs, for `pshufd` copying lane 0 of the input to
+# all the lanes of the output.
+furmi_splat = TailRecipe(
+        'furmi_splat', Unary, base_size=2, ins=FPR, outs=FPR,
+        clobbers_flags=False,
+        emit='''
+        PUT_OP(bits, rex2(in_reg0, out_reg0), sink);
+        modrm_rr(in_reg0, out_reg0, sink);
+        sink.put1(match func.dfg.ctrl_typevar(inst).lane_bits() {
+            32 => 0x00,
+            64 => 0x44,
+            x => panic!("{}-bit lanes unexpected for furmi_splat", x),
+        });
+        ''')
+
 # XX /r, for regmove instructions.
 rmov = TailRecipe(
         'rmov', RegMove, base_size=1, ins=GPR, outs=(),
@@ -483,6 +672,7 @@ frmov = TailRecipe(
 # XX /n with one arg in %rcx, for shifts.
 rc = TailRecipe(
         'rc', Binary, base_size=1, ins=(GPR, GPR.rcx), outs=0,
+        zero_extends=True,
         emit='''
         PUT_OP(bits, rex1(in_reg0), sink);
         modrm_r_bits(in_reg0, bits, sink);
@@ -492,8 +682,9 @@ rc = TailRecipe(
 div = TailRecipe(
         'div', Ternary, base_size=1,
         ins=(GPR.rax, GPR.rdx, GPR), outs=(GPR.rax, GPR.rdx),
+        zero_extends=True,
         emit='''
-        sink.trap(TrapCode::IntegerDivisionByZero, func.srclocs[inst]);
+        sink.trap(TrapCode::IntegerDivisionByZero, func.srclocs[inst], 0);
         PUT_OP(bits, rex1(in_reg2), sink);
         modrm_r_bits(in_reg2, bits, sink);
         ''')
@@ -502,14 +693,69 @@ div = TailRecipe(
 mulx = TailRecipe(
         'mulx', Binary, base_size=1,
         ins=(GPR.rax, GPR), outs=(GPR.rax, GPR.rdx),
+        zero_extends=True,
         emit='''
         PUT_OP(bits, rex1(in_reg1), sink);
         modrm_r_bits(in_reg1, bits, sink);
         ''')
 
+# XX for rdtsc. Outputs in %rdx(hi):%rax(lo).
+rdtsc = TailRecipe(
+        'rdtsc', NullAry, base_size=0, ins=(), outs=(GPR.rax, GPR.rdx),
+        clobbers_flags=False,
+        emit='''
+        PUT_OP(bits, BASE_REX, sink);
+        ''')
+
+# VEX XX /r with the second operand in VEX.vvvv. Used for the BMI2 shifts,
+# which take the shift amount in any register and don't affect the flags.
+vrr = TailRecipe(
+        'vrr', Binary, base_size=1, ins=(GPR, GPR), outs=GPR,
+        clobbers_flags=False,
+        emit='''
+        PUT_OP(bits, rex2(in_reg0, out_reg0), in_reg1, sink);
+        modrm_rr(in_reg0, out_reg0, sink);
+        ''')
+
+# VEX XX /r ib with an 8-bit immediate. Used for `rorx`.
+vr_ib = TailRecipe(
+        'vr_ib', BinaryImm, base_size=2, ins=GPR, outs=GPR,
+        clobbers_flags=False,
+        emit='''
+        PUT_OP(bits, rex2(in_reg0, out_reg0), 0, sink);
+        modrm_rr(in_reg0, out_reg0, sink);
+        let imm: i64 = imm.into();
+        sink.put1(imm as u8);
+        ''')
+
+# VEX XX /r ib with a negated 8-bit immediate. Used for `rorx` implementing
+# a left rotate, since rotating right by `-imm` modulo the operand size is the
+# same as rotating left by `imm`.
+vr_nib = TailRecipe(
+        'vr_nib', BinaryImm, base_size=2, ins=GPR, outs=GPR,
+        clobbers_flags=False,
+        emit='''
+        PUT_OP(bits, rex2(in_reg0, out_reg0), 0, sink);
+        modrm_rr(in_reg0, out_reg0, sink);
+        let imm: i64 = imm.into();
+        let mask = if (bits & 0x8000) != 0 { 63 } else { 31 };
+        sink.put1((imm as u8).wrapping_neg() & mask);
+        ''')
+
+# VEX XX /r for `mulx` computing the high half of `%rdx * r`. Both halves of
+# the product are written to the output register, which leaves the high half.
+vmulhi = TailRecipe(
+        'vmulhi', Binary, base_size=1, ins=(GPR.rdx, GPR), outs=GPR,
+        clobbers_flags=False,
+        emit='''
+        PUT_OP(bits, rex2(in_reg1, out_reg0), out_reg0, sink);
+        modrm_rr(in_reg1, out_reg0, sink);
+        ''')
+
 # XX /n ib with 8-bit immediate sign-extended.
 r_ib = TailRecipe(
         'r_ib', BinaryImm, base_size=2, ins=GPR, outs=0,
+        zero_extends=True,
         instp=IsSignedInt(BinaryImm.imm, 8),
         emit='''
         PUT_OP(bits, rex1(in_reg0), sink);
@@ -521,6 +767,7 @@ r_ib = TailRecipe(
 # XX /n id with 32-bit immediate sign-extended.
 r_id = TailRecipe(
         'r_id', BinaryImm, base_size=5, ins=GPR, outs=0,
+        zero_extends=True,
         instp=IsSignedInt(BinaryImm.imm, 32),
         emit='''
         PUT_OP(bits, rex1(in_reg0), sink);
@@ -532,6 +779,8 @@ r_id = TailRecipe(
 # XX /n id with 32-bit immediate sign-extended. UnaryImm version.
 u_id = TailRecipe(
         'u_id', UnaryImm, base_size=5, ins=(), outs=GPR,
+        zero_extends=True,
+        clobbers_flags=False,
         instp=IsSignedInt(UnaryImm.imm, 32),
         emit='''
         PUT_OP(bits, rex1(out_reg0), sink);
@@ -543,17 +792,21 @@ u_id = TailRecipe(
 # XX+rd id unary with 32-bit immediate. Note no recipe predicate.
 pu_id = TailRecipe(
         'pu_id', UnaryImm, base_size=4, ins=(), outs=GPR,
+        zero_extends=True,
+        clobbers_flags=False,
         emit='''
         // The destination register is encoded in the low bits of the opcode.
         // No ModR/M.
         PUT_OP(bits | (out_reg0 & 7), rex1(out_reg0), sink);
         let imm: i64 = imm.into();
+        patch_point(func, inst, 4, sink);
         sink.put4(imm as u32);
         ''')
 
 # XX+rd id unary with bool immediate. Note no recipe predicate.
 pu_id_bool = TailRecipe(
         'pu_id_bool', UnaryBool, base_size=4, ins=(), outs=GPR,
+        clobbers_flags=False,
         emit='''
         // The destination register is encoded in the low bits of the opcode.
         // No ModR/M.
@@ -562,15 +815,41 @@ pu_id_bool = TailRecipe(
         sink.put4(imm);
         ''')
 
+# XX /n id unary with a bool immediate for the larger bool types, which are all
+# zeros or all ones. The immediate is sign-extended to 64 bits with REX.W.
+u_id_bmask = TailRecipe(
+        'u_id_bmask', UnaryBool, base_size=5, ins=(), outs=GPR,
+        clobbers_flags=False,
+        emit='''
+        PUT_OP(bits, rex1(out_reg0), sink);
+        modrm_r_bits(out_reg0, bits, sink);
+        let imm: u32 = if imm { 0xffff_ffff } else { 0 };
+        sink.put4(imm);
+        ''')
+
 # XX+rd iq unary with 64-bit immediate.
 pu_iq = TailRecipe(
         'pu_iq', UnaryImm, base_size=8, ins=(), outs=GPR,
+        clobbers_flags=False,
         emit='''
         PUT_OP(bits | (out_reg0 & 7), rex1(out_reg0), sink);
         let imm: i64 = imm.into();
+        patch_point(func, inst, 8, sink);
         sink.put8(imm as u64);
         ''')
 
+# XX /r unary with an integer immediate equal to zero, using the same register
+# for both operands. This is the `xor r, r` zeroing idiom, which is smaller than
+# a move, but it clobbers the flags.
+u_id_z = TailRecipe(
+        'u_id_z', UnaryImm, base_size=1, ins=(), outs=GPR,
+        zero_extends=True,
+        instp=IsEqual(UnaryImm.imm, 0),
+        emit='''
+        PUT_OP(bits, rex2(out_reg0, out_reg0), sink);
+        modrm_rr(out_reg0, out_reg0, sink);
+        ''')
+
 # XX /n Unary with floating point 32-bit immediate equal to zero.
 f32imm_z = TailRecipe(
     'f32imm_z', UnaryIeee32, base_size=1, ins=(), outs=FPR,
@@ -589,10 +868,56 @@ f64imm_z = TailRecipe(
         modrm_rr(out_reg0, out_reg0, sink);
     ''')
 
+# VEX XX /r zero idiom for a floating point 32-bit immediate equal to zero.
+vf32imm_z = TailRecipe(
+    'vf32imm_z', UnaryIeee32, base_size=1, ins=(), outs=FPR,
+    instp=IsZero32BitFloat(UnaryIeee32.imm),
+    emit='''
+        PUT_OP(bits, rex2(out_reg0, out_reg0), out_reg0, sink);
+        modrm_rr(out_reg0, out_reg0, sink);
+    ''')
+
+# VEX XX /r zero idiom for a floating point 64-bit immediate equal to zero.
+vf64imm_z = TailRecipe(
+    'vf64imm_z', UnaryIeee64, base_size=1, ins=(), outs=FPR,
+    instp=IsZero64BitFloat(UnaryIeee64.imm),
+    emit='''
+        PUT_OP(bits, rex2(out_reg0, out_reg0), out_reg0, sink);
+        modrm_rr(out_reg0, out_reg0, sink);
+    ''')
+
+# XX /r with a rip-relative load of a floating point 32-bit immediate from
+# the constant pool.
+f32imm_pool = TailRecipe(
+    'f32imm_pool', UnaryIeee32, base_size=5, ins=(), outs=FPR,
+    instp=Not(IsZero32BitFloat(UnaryIeee32.imm)),
+    clobbers_flags=False,
+    # rex2 gets passed 0 for r/m register because the upper bit of
+    # r/m doesnt get decoded when in rip-relative addressing mode.
+    emit='''
+        PUT_OP(bits, rex2(0, out_reg0), sink);
+        modrm_riprel(out_reg0, sink);
+        // No reloc is needed here as the constant pool is emitted after the
+        // function body, or at a known displacement from it.
+        const_disp4(u64::from(imm.bits()), 4, func, inst, sink)?;
+    ''')
+
+# XX /r with a rip-relative load of a floating point 64-bit immediate from
+# the constant pool.
+f64imm_pool = TailRecipe(
+    'f64imm_pool', UnaryIeee64, base_size=5, ins=(), outs=FPR,
+    instp=Not(IsZero64BitFloat(UnaryIeee64.imm)),
+    clobbers_flags=False,
+    emit='''
+        PUT_OP(bits, rex2(0, out_reg0), sink);
+        modrm_riprel(out_reg0, sink);
+        const_disp4(imm.bits(), 8, func, inst, sink)?;
+    ''')
+
 pushq = TailRecipe(
     'pushq', Unary, base_size=0, ins=GPR, outs=(),
     emit='''
-    sink.trap(TrapCode::StackOverflow, func.srclocs[inst]);
+    sink.trap(TrapCode::StackOverflow, func.srclocs[inst], 0);
     PUT_OP(bits | (in_reg0 & 7), rex1(in_reg0), sink);
     ''')
 
@@ -723,7 +1048,7 @@ gvaddr4 = TailRecipe(
         PUT_OP(bits | (out_reg0 & 7), rex1(out_reg0), sink);
         sink.reloc_external(Reloc::Abs4,
                             &func.global_values[global_value].symbol_name(),
-                            0);
+                            func.global_values[global_value].symbol_offset());
         sink.put4(0);
         ''')
 
@@ -734,7 +1059,7 @@ gvaddr8 = TailRecipe(
         PUT_OP(bits | (out_reg0 & 7), rex1(out_reg0), sink);
         sink.reloc_external(Reloc::Abs8,
                             &func.global_values[global_value].symbol_name(),
-                            0);
+                            func.global_values[global_value].symbol_offset());
         sink.put8(0);
         ''')
 
@@ -748,7 +1073,7 @@ pcrel_gvaddr8 = TailRecipe(
         // instruction and the beginning of the immediate field.
         sink.reloc_external(Reloc::X86PCRel4,
                             &func.global_values[global_value].symbol_name(),
-                            -4);
+                            func.global_values[global_value].symbol_offset() - 4);
         sink.put4(0);
         ''')
 
@@ -769,7 +1094,8 @@ got_gvaddr8 = TailRecipe(
 #
 # Stack addresses.
 #
-# TODO: Alternative forms for 8-bit immediates, when applicable.
+# The 8-bit displacement forms are only valid when the stack frame is laid
+# out, so they are picked by the shrinking pass.
 #
 
 spaddr4_id = TailRecipe(
@@ -777,8 +1103,8 @@ spaddr4_id = TailRecipe(
         emit='''
         let sp = StackRef::sp(stack_slot, &func.stack_slots);
         let base = stk_base(sp.base);
-        PUT_OP(bits, rex2(out_reg0, base), sink);
-        modrm_sib_disp8(out_reg0, sink);
+        PUT_OP(bits, rex2(base, out_reg0), sink);
+        modrm_sib_disp32(out_reg0, sink);
         sib_noindex(base, sink);
         let imm : i32 = offset.into();
         sink.put4(sp.offset.checked_add(imm).unwrap() as u32);
@@ -796,6 +1122,32 @@ spaddr8_id = TailRecipe(
         sink.put4(sp.offset.checked_add(imm).unwrap() as u32);
         ''')
 
+spaddr4_ib = TailRecipe(
+        'spaddr4_ib', StackLoad, base_size=3, ins=(), outs=GPR,
+        stack_disp_bits=8,
+        emit='''
+        let sp = StackRef::sp(stack_slot, &func.stack_slots);
+        let base = stk_base(sp.base);
+        PUT_OP(bits, rex2(base, out_reg0), sink);
+        modrm_sib_disp8(out_reg0, sink);
+        sib_noindex(base, sink);
+        let imm : i32 = offset.into();
+        sink.put1(sp.offset.checked_add(imm).unwrap() as u8);
+        ''')
+
+spaddr8_ib = TailRecipe(
+        'spaddr8_ib', StackLoad, base_size=3, ins=(), outs=GPR,
+        stack_disp_bits=8,
+        emit='''
+        let sp = StackRef::sp(stack_slot, &func.stack_slots);
+        let base = stk_base(sp.base);
+        PUT_OP(bits, rex2(base, out_reg0), sink);
+        modrm_sib_disp8(out_reg0, sink);
+        sib_noindex(base, sink);
+        let imm : i32 = offset.into();
+        sink.put1(sp.offset.checked_add(imm).unwrap() as u8);
+        ''')
+
 
 #
 # Store recipes.
@@ -809,7 +1161,7 @@ st = TailRecipe(
         compute_size="size_plus_maybe_offset_for_in_reg_1",
         emit='''
         if !flags.notrap() {
-            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst], 0);
         }
         PUT_OP(bits, rex2(in_reg1, in_reg0), sink);
         if needs_offset(in_reg1) {
@@ -830,7 +1182,7 @@ stWithIndex = TailRecipe(
     compute_size="size_plus_maybe_offset_for_in_reg_1",
     emit='''
     if !flags.notrap() {
-        sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+        sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst], 0);
     }
     PUT_OP(bits, rex3(in_reg1, in_reg0, in_reg2), sink);
     if needs_offset(in_reg1) {
@@ -852,7 +1204,7 @@ st_abcd = TailRecipe(
         clobbers_flags=False,
         emit='''
         if !flags.notrap() {
-            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst], 0);
         }
         PUT_OP(bits, rex2(in_reg1, in_reg0), sink);
         modrm_rm(in_reg1, in_reg0, sink);
@@ -869,7 +1221,7 @@ stWithIndex_abcd = TailRecipe(
     compute_size="size_plus_maybe_offset_for_in_reg_1",
     emit='''
     if !flags.notrap() {
-        sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+        sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst], 0);
     }
     PUT_OP(bits, rex3(in_reg1, in_reg0, in_reg2), sink);
     if needs_offset(in_reg1) {
@@ -890,7 +1242,7 @@ fst = TailRecipe(
         compute_size="size_plus_maybe_offset_for_in_reg_1",
         emit='''
         if !flags.notrap() {
-            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst], 0);
         }
         PUT_OP(bits, rex2(in_reg1, in_reg0), sink);
         if needs_offset(in_reg1) {
@@ -909,7 +1261,7 @@ fstWithIndex = TailRecipe(
         compute_size="size_plus_maybe_offset_for_in_reg_1",
         emit='''
         if !flags.notrap() {
-            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst], 0);
         }
         PUT_OP(bits, rex3(in_reg1, in_reg0, in_reg2), sink);
         if needs_offset(in_reg1) {
@@ -930,7 +1282,7 @@ stDisp8 = TailRecipe(
         compute_size="size_plus_maybe_sib_for_in_reg_1",
         emit='''
         if !flags.notrap() {
-            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst], 0);
         }
         PUT_OP(bits, rex2(in_reg1, in_reg0), sink);
         if needs_sib_byte(in_reg1) {
@@ -952,7 +1304,7 @@ stWithIndexDisp8 = TailRecipe(
     clobbers_flags=False,
     emit='''
     if !flags.notrap() {
-        sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+        sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst], 0);
     }
     PUT_OP(bits, rex3(in_reg1, in_reg0, in_reg2), sink);
     modrm_sib_disp8(in_reg0, sink);
@@ -970,7 +1322,7 @@ stDisp8_abcd = TailRecipe(
         clobbers_flags=False,
         emit='''
         if !flags.notrap() {
-            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst], 0);
         }
         PUT_OP(bits, rex2(in_reg1, in_reg0), sink);
         modrm_disp8(in_reg1, in_reg0, sink);
@@ -988,7 +1340,7 @@ stWithIndexDisp8_abcd = TailRecipe(
     clobbers_flags=False,
     emit='''
     if !flags.notrap() {
-        sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+        sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst], 0);
     }
     PUT_OP(bits, rex3(in_reg1, in_reg0, in_reg2), sink);
     modrm_sib_disp8(in_reg0, sink);
@@ -1005,7 +1357,7 @@ fstDisp8 = TailRecipe(
         compute_size='size_plus_maybe_sib_for_in_reg_1',
         emit='''
         if !flags.notrap() {
-            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst], 0);
         }
         PUT_OP(bits, rex2(in_reg1, in_reg0), sink);
         if needs_sib_byte(in_reg1) {
@@ -1027,7 +1379,7 @@ fstWithIndexDisp8 = TailRecipe(
     clobbers_flags=False,
     emit='''
     if !flags.notrap() {
-        sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+        sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst], 0);
     }
     PUT_OP(bits, rex3(in_reg1, in_reg0, in_reg2), sink);
     modrm_sib_disp8(in_reg0, sink);
@@ -1043,7 +1395,7 @@ stDisp32 = TailRecipe(
         compute_size='size_plus_maybe_sib_for_in_reg_1',
         emit='''
         if !flags.notrap() {
-            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst], 0);
         }
         PUT_OP(bits, rex2(in_reg1, in_reg0), sink);
         if needs_sib_byte(in_reg1) {
@@ -1065,7 +1417,7 @@ stWithIndexDisp32 = TailRecipe(
     clobbers_flags=False,
     emit='''
     if !flags.notrap() {
-        sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+        sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst], 0);
     }
     PUT_OP(bits, rex3(in_reg1, in_reg0, in_reg2), sink);
     modrm_sib_disp32(in_reg0, sink);
@@ -1082,7 +1434,7 @@ stDisp32_abcd = TailRecipe(
         clobbers_flags=False,
         emit='''
         if !flags.notrap() {
-            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst], 0);
         }
         PUT_OP(bits, rex2(in_reg1, in_reg0), sink);
         modrm_disp32(in_reg1, in_reg0, sink);
@@ -1100,7 +1452,7 @@ stWithIndexDisp32_abcd = TailRecipe(
     clobbers_flags=False,
     emit='''
     if !flags.notrap() {
-        sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+        sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst], 0);
     }
     PUT_OP(bits, rex3(in_reg1, in_reg0, in_reg2), sink);
     modrm_sib_disp32(in_reg0, sink);
@@ -1116,7 +1468,7 @@ fstDisp32 = TailRecipe(
         compute_size='size_plus_maybe_sib_for_in_reg_1',
         emit='''
         if !flags.notrap() {
-            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst], 0);
         }
         PUT_OP(bits, rex2(in_reg1, in_reg0), sink);
         if needs_sib_byte(in_reg1) {
@@ -1138,7 +1490,7 @@ fstWithIndexDisp32 = TailRecipe(
     clobbers_flags=False,
     emit='''
     if !flags.notrap() {
-        sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+        sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst], 0);
     }
     PUT_OP(bits, rex3(in_reg1, in_reg0, in_reg2), sink);
     modrm_sib_disp32(in_reg0, sink);
@@ -1152,7 +1504,7 @@ spillSib32 = TailRecipe(
         'spillSib32', Unary, base_size=6, ins=GPR, outs=StackGPR32,
         clobbers_flags=False,
         emit='''
-        sink.trap(TrapCode::StackOverflow, func.srclocs[inst]);
+        sink.trap(TrapCode::StackOverflow, func.srclocs[inst], 0);
         let base = stk_base(out_stk0.base);
         PUT_OP(bits, rex2(base, in_reg0), sink);
         modrm_sib_disp32(in_reg0, sink);
@@ -1160,12 +1512,26 @@ spillSib32 = TailRecipe(
         sink.put4(out_stk0.offset as u32);
         ''')
 
+# Unary spill with SIB and 8-bit displacement.
+spillSib8 = TailRecipe(
+        'spillSib8', Unary, base_size=3, ins=GPR, outs=StackGPR32,
+        stack_disp_bits=8,
+        clobbers_flags=False,
+        emit='''
+        sink.trap(TrapCode::StackOverflow, func.srclocs[inst], 0);
+        let base = stk_base(out_stk0.base);
+        PUT_OP(bits, rex2(base, in_reg0), sink);
+        modrm_sib_disp8(in_reg0, sink);
+        sib_noindex(base, sink);
+        sink.put1(out_stk0.offset as u8);
+        ''')
+
 # Like spillSib32, but targeting an FPR rather than a GPR.
 fspillSib32 = TailRecipe(
         'fspillSib32', Unary, base_size=6, ins=FPR, outs=StackFPR32,
         clobbers_flags=False,
         emit='''
-        sink.trap(TrapCode::StackOverflow, func.srclocs[inst]);
+        sink.trap(TrapCode::StackOverflow, func.srclocs[inst], 0);
         let base = stk_base(out_stk0.base);
         PUT_OP(bits, rex2(base, in_reg0), sink);
         modrm_sib_disp32(in_reg0, sink);
@@ -1173,12 +1539,26 @@ fspillSib32 = TailRecipe(
         sink.put4(out_stk0.offset as u32);
         ''')
 
+# Like spillSib8, but targeting an FPR rather than a GPR.
+fspillSib8 = TailRecipe(
+        'fspillSib8', Unary, base_size=3, ins=FPR, outs=StackFPR32,
+        stack_disp_bits=8,
+        clobbers_flags=False,
+        emit='''
+        sink.trap(TrapCode::StackOverflow, func.srclocs[inst], 0);
+        let base = stk_base(out_stk0.base);
+        PUT_OP(bits, rex2(base, in_reg0), sink);
+        modrm_sib_disp8(in_reg0, sink);
+        sib_noindex(base, sink);
+        sink.put1(out_stk0.offset as u8);
+        ''')
+
 # Regspill using RSP-relative addressing.
 regspill32 = TailRecipe(
         'regspill32', RegSpill, base_size=6, ins=GPR, outs=(),
         clobbers_flags=False,
         emit='''
-        sink.trap(TrapCode::StackOverflow, func.srclocs[inst]);
+        sink.trap(TrapCode::StackOverflow, func.srclocs[inst], 0);
         let dst = StackRef::sp(dst, &func.stack_slots);
         let base = stk_base(dst.base);
         PUT_OP(bits, rex2(base, src), sink);
@@ -1192,7 +1572,7 @@ fregspill32 = TailRecipe(
         'fregspill32', RegSpill, base_size=6, ins=FPR, outs=(),
         clobbers_flags=False,
         emit='''
-        sink.trap(TrapCode::StackOverflow, func.srclocs[inst]);
+        sink.trap(TrapCode::StackOverflow, func.srclocs[inst], 0);
         let dst = StackRef::sp(dst, &func.stack_slots);
         let base = stk_base(dst.base);
         PUT_OP(bits, rex2(base, src), sink);
@@ -1208,12 +1588,13 @@ fregspill32 = TailRecipe(
 # XX /r load with no offset.
 ld = TailRecipe(
         'ld', Load, base_size=1, ins=(GPR), outs=(GPR),
+        zero_extends=True,
         instp=IsEqual(Load.offset, 0),
         clobbers_flags=False,
         compute_size="size_plus_maybe_offset_for_in_reg_0",
         emit='''
         if !flags.notrap() {
-            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst], 0);
         }
         PUT_OP(bits, rex2(in_reg0, out_reg0), sink);
         if needs_offset(in_reg0) {
@@ -1229,12 +1610,13 @@ ldWithIndex = TailRecipe(
     'ldWithIndex', LoadComplex, base_size=2,
     ins=(GPR, GPR),
     outs=(GPR),
+    zero_extends=True,
     instp=IsEqual(LoadComplex.offset, 0),
     clobbers_flags=False,
     compute_size="size_plus_maybe_offset_for_in_reg_0",
     emit='''
     if !flags.notrap() {
-        sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+        sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst], 0);
     }
     PUT_OP(bits, rex3(in_reg0, out_reg0, in_reg1), sink);
     if needs_offset(in_reg0) {
@@ -1255,7 +1637,7 @@ fld = TailRecipe(
         compute_size="size_plus_maybe_offset_for_in_reg_0",
         emit='''
         if !flags.notrap() {
-            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst], 0);
         }
         PUT_OP(bits, rex2(in_reg0, out_reg0), sink);
         if needs_offset(in_reg0) {
@@ -1276,7 +1658,7 @@ fldWithIndex = TailRecipe(
     compute_size="size_plus_maybe_offset_for_in_reg_0",
     emit='''
     if !flags.notrap() {
-        sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+        sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst], 0);
     }
     PUT_OP(bits, rex3(in_reg0, out_reg0, in_reg1), sink);
     if needs_offset(in_reg0) {
@@ -1292,12 +1674,13 @@ fldWithIndex = TailRecipe(
 # XX /r load with 8-bit offset.
 ldDisp8 = TailRecipe(
         'ldDisp8', Load, base_size=2, ins=(GPR), outs=(GPR),
+        zero_extends=True,
         instp=IsSignedInt(Load.offset, 8),
         clobbers_flags=False,
         compute_size="size_plus_maybe_sib_for_in_reg_0",
         emit='''
         if !flags.notrap() {
-            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst], 0);
         }
         PUT_OP(bits, rex2(in_reg0, out_reg0), sink);
         if needs_sib_byte(in_reg0) {
@@ -1315,11 +1698,12 @@ ldWithIndexDisp8 = TailRecipe(
     'ldWithIndexDisp8', LoadComplex, base_size=3,
     ins=(GPR, GPR),
     outs=(GPR),
+    zero_extends=True,
     instp=IsSignedInt(LoadComplex.offset, 8),
     clobbers_flags=False,
     emit='''
     if !flags.notrap() {
-        sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+        sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst], 0);
     }
     PUT_OP(bits, rex3(in_reg0, out_reg0, in_reg1), sink);
     modrm_sib_disp8(out_reg0, sink);
@@ -1336,7 +1720,7 @@ fldDisp8 = TailRecipe(
         compute_size="size_plus_maybe_sib_for_in_reg_0",
         emit='''
         if !flags.notrap() {
-            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst], 0);
         }
         PUT_OP(bits, rex2(in_reg0, out_reg0), sink);
         if needs_sib_byte(in_reg0) {
@@ -1358,7 +1742,7 @@ fldWithIndexDisp8 = TailRecipe(
     clobbers_flags=False,
     emit='''
     if !flags.notrap() {
-        sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+        sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst], 0);
     }
     PUT_OP(bits, rex3(in_reg0, out_reg0, in_reg1), sink);
     modrm_sib_disp8(out_reg0, sink);
@@ -1370,12 +1754,13 @@ fldWithIndexDisp8 = TailRecipe(
 # XX /r load with 32-bit offset.
 ldDisp32 = TailRecipe(
         'ldDisp32', Load, base_size=5, ins=(GPR), outs=(GPR),
+        zero_extends=True,
         instp=IsSignedInt(Load.offset, 32),
         clobbers_flags=False,
         compute_size='size_plus_maybe_sib_for_in_reg_0',
         emit='''
         if !flags.notrap() {
-            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst], 0);
         }
         PUT_OP(bits, rex2(in_reg0, out_reg0), sink);
         if needs_sib_byte(in_reg0) {
@@ -1393,11 +1778,12 @@ ldWithIndexDisp32 = TailRecipe(
     'ldWithIndexDisp32', LoadComplex, base_size=6,
     ins=(GPR, GPR),
     outs=(GPR),
+    zero_extends=True,
     instp=IsSignedInt(LoadComplex.offset, 32),
     clobbers_flags=False,
     emit='''
     if !flags.notrap() {
-        sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+        sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst], 0);
     }
     PUT_OP(bits, rex3(in_reg0, out_reg0, in_reg1), sink);
     modrm_sib_disp32(out_reg0, sink);
@@ -1414,7 +1800,7 @@ fldDisp32 = TailRecipe(
         compute_size="size_plus_maybe_sib_for_in_reg_0",
         emit='''
         if !flags.notrap() {
-            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst], 0);
         }
         PUT_OP(bits, rex2(in_reg0, out_reg0), sink);
         if needs_sib_byte(in_reg0) {
@@ -1436,7 +1822,7 @@ fldWithIndexDisp32 = TailRecipe(
     clobbers_flags=False,
     emit='''
     if !flags.notrap() {
-        sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+        sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst], 0);
     }
     PUT_OP(bits, rex3(in_reg0, out_reg0, in_reg1), sink);
     modrm_sib_disp32(out_reg0, sink);
@@ -1445,9 +1831,260 @@ fldWithIndexDisp32 = TailRecipe(
     sink.put4(offset as u32);
     ''')
 
+#
+# Memory accesses at a symbol address
+#
+# These use a SIB byte with an index register and no base register, so the
+# address is the index plus a 32-bit displacement. The displacement is the
+# absolute address of the symbol plus the offset, filled in by an Abs4
+# relocation.
+
+# XX /r load with index and symbol displacement.
+ldSymbol = TailRecipe(
+    'ldSymbol', LoadSymbol, base_size=6,
+    ins=(GPR),
+    outs=(GPR),
+    zero_extends=True,
+    clobbers_flags=False,
+    emit='''
+    if !flags.notrap() {
+        sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst], 0);
+    }
+    PUT_OP(bits, rex3(0, out_reg0, in_reg0), sink);
+    modrm_sib(out_reg0, sink);
+    sib(0, in_reg0, 0b101, sink);
+    let offset: i32 = offset.into();
+    sink.reloc_external(Reloc::Abs4,
+                        &func.global_values[global_value].symbol_name(),
+                        func.global_values[global_value].symbol_offset()
+                            + i64::from(offset));
+    sink.put4(0);
+    ''')
+
+# XX /r float load with index and symbol displacement.
+fldSymbol = TailRecipe(
+    'fldSymbol', LoadSymbol, base_size=6,
+    ins=(GPR),
+    outs=(FPR),
+    clobbers_flags=False,
+    emit='''
+    if !flags.notrap() {
+        sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst], 0);
+    }
+    PUT_OP(bits, rex3(0, out_reg0, in_reg0), sink);
+    modrm_sib(out_reg0, sink);
+    sib(0, in_reg0, 0b101, sink);
+    let offset: i32 = offset.into();
+    sink.reloc_external(Reloc::Abs4,
+                        &func.global_values[global_value].symbol_name(),
+                        func.global_values[global_value].symbol_offset()
+                            + i64::from(offset));
+    sink.put4(0);
+    ''')
+
+# XX /r store with index and symbol displacement.
+stSymbol = TailRecipe(
+    'stSymbol', StoreSymbol, base_size=6,
+    ins=(GPR, GPR),
+    outs=(),
+    clobbers_flags=False,
+    emit='''
+    if !flags.notrap() {
+        sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst], 0);
+    }
+    PUT_OP(bits, rex3(0, in_reg0, in_reg1), sink);
+    modrm_sib(in_reg0, sink);
+    sib(0, in_reg1, 0b101, sink);
+    let offset: i32 = offset.into();
+    sink.reloc_external(Reloc::Abs4,
+                        &func.global_values[global_value].symbol_name(),
+                        func.global_values[global_value].symbol_offset()
+                            + i64::from(offset));
+    sink.put4(0);
+    ''')
+
+# XX /r float store with index and symbol displacement.
+fstSymbol = TailRecipe(
+    'fstSymbol', StoreSymbol, base_size=6,
+    ins=(FPR, GPR),
+    outs=(),
+    clobbers_flags=False,
+    emit='''
+    if !flags.notrap() {
+        sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst], 0);
+    }
+    PUT_OP(bits, rex3(0, in_reg0, in_reg1), sink);
+    modrm_sib(in_reg0, sink);
+    sib(0, in_reg1, 0b101, sink);
+    let offset: i32 = offset.into();
+    sink.reloc_external(Reloc::Abs4,
+                        &func.global_values[global_value].symbol_name(),
+                        func.global_values[global_value].symbol_offset()
+                            + i64::from(offset));
+    sink.put4(0);
+    ''')
+
+#
+# Memory accesses relative to the instruction pointer
+#
+# These use the RIP-relative addressing of x86-64, so the address is the end of
+# the instruction plus a 32-bit displacement. The displacement is filled in by
+# a PC-relative relocation, whose addend adjusts for the difference between the
+# end of the instruction and the beginning of the displacement.
+
+# XX /r load with a RIP-relative symbol displacement.
+ldPCRel = TailRecipe(
+    'ldPCRel', LoadGlobal, base_size=5,
+    ins=(),
+    outs=(GPR),
+    zero_extends=True,
+    clobbers_flags=False,
+    emit='''
+    if !flags.notrap() {
+        sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst], 0);
+    }
+    PUT_OP(bits, rex2(0, out_reg0), sink);
+    modrm_riprel(out_reg0, sink);
+    let offset: i32 = offset.into();
+    sink.reloc_external(Reloc::X86PCRel4,
+                        &func.global_values[global_value].symbol_name(),
+                        func.global_values[global_value].symbol_offset()
+                            + i64::from(offset) - 4);
+    sink.put4(0);
+    ''')
+
+# XX /r float load with a RIP-relative symbol displacement.
+fldPCRel = TailRecipe(
+    'fldPCRel', LoadGlobal, base_size=5,
+    ins=(),
+    outs=(FPR),
+    clobbers_flags=False,
+    emit='''
+    if !flags.notrap() {
+        sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst], 0);
+    }
+    PUT_OP(bits, rex2(0, out_reg0), sink);
+    modrm_riprel(out_reg0, sink);
+    let offset: i32 = offset.into();
+    sink.reloc_external(Reloc::X86PCRel4,
+                        &func.global_values[global_value].symbol_name(),
+                        func.global_values[global_value].symbol_offset()
+                            + i64::from(offset) - 4);
+    sink.put4(0);
+    ''')
+
+# XX /r store with a RIP-relative symbol displacement.
+stPCRel = TailRecipe(
+    'stPCRel', LoadSymbol, base_size=5,
+    ins=(GPR),
+    outs=(),
+    clobbers_flags=False,
+    emit='''
+    if !flags.notrap() {
+        sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst], 0);
+    }
+    PUT_OP(bits, rex2(0, in_reg0), sink);
+    modrm_riprel(in_reg0, sink);
+    let offset: i32 = offset.into();
+    sink.reloc_external(Reloc::X86PCRel4,
+                        &func.global_values[global_value].symbol_name(),
+                        func.global_values[global_value].symbol_offset()
+                            + i64::from(offset) - 4);
+    sink.put4(0);
+    ''')
+
+# XX /r float store with a RIP-relative symbol displacement.
+fstPCRel = TailRecipe(
+    'fstPCRel', LoadSymbol, base_size=5,
+    ins=(FPR),
+    outs=(),
+    clobbers_flags=False,
+    emit='''
+    if !flags.notrap() {
+        sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst], 0);
+    }
+    PUT_OP(bits, rex2(0, in_reg0), sink);
+    modrm_riprel(in_reg0, sink);
+    let offset: i32 = offset.into();
+    sink.reloc_external(Reloc::X86PCRel4,
+                        &func.global_values[global_value].symbol_name(),
+                        func.global_values[global_value].symbol_offset()
+                            + i64::from(offset) - 4);
+    sink.put4(0);
+    ''')
+
+#
+# Arithmetic with a memory operand
+#
+
+# XX /r with a register-indirect memory operand and no offset. The result is
+# tied to the register operand.
+ldop = TailRecipe(
+        'ldop', Store, base_size=1, ins=(GPR, GPR), outs=0,
+        zero_extends=True,
+        instp=IsEqual(Store.offset, 0),
+        compute_size="size_plus_maybe_sib_or_offset_for_in_reg_1",
+        emit='''
+        if !flags.notrap() {
+            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst], 0);
+        }
+        PUT_OP(bits, rex2(in_reg1, in_reg0), sink);
+        if needs_sib_byte(in_reg1) {
+            modrm_sib(in_reg0, sink);
+            sib_noindex(in_reg1, sink);
+        } else if needs_offset(in_reg1) {
+            modrm_disp8(in_reg1, in_reg0, sink);
+            sink.put1(0);
+        } else {
+            modrm_rm(in_reg1, in_reg0, sink);
+        }
+        ''')
+
+# XX /r with a register-indirect memory operand and an 8-bit offset.
+ldopDisp8 = TailRecipe(
+        'ldopDisp8', Store, base_size=2, ins=(GPR, GPR), outs=0,
+        zero_extends=True,
+        instp=IsSignedInt(Store.offset, 8),
+        compute_size="size_plus_maybe_sib_for_in_reg_1",
+        emit='''
+        if !flags.notrap() {
+            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst], 0);
+        }
+        PUT_OP(bits, rex2(in_reg1, in_reg0), sink);
+        if needs_sib_byte(in_reg1) {
+            modrm_sib_disp8(in_reg0, sink);
+            sib_noindex(in_reg1, sink);
+        } else {
+            modrm_disp8(in_reg1, in_reg0, sink);
+        }
+        let offset: i32 = offset.into();
+        sink.put1(offset as u8);
+        ''')
+
+# XX /r with a register-indirect memory operand and a 32-bit offset.
+ldopDisp32 = TailRecipe(
+        'ldopDisp32', Store, base_size=5, ins=(GPR, GPR), outs=0,
+        zero_extends=True,
+        compute_size="size_plus_maybe_sib_for_in_reg_1",
+        emit='''
+        if !flags.notrap() {
+            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst], 0);
+        }
+        PUT_OP(bits, rex2(in_reg1, in_reg0), sink);
+        if needs_sib_byte(in_reg1) {
+            modrm_sib_disp32(in_reg0, sink);
+            sib_noindex(in_reg1, sink);
+        } else {
+            modrm_disp32(in_reg1, in_reg0, sink);
+        }
+        let offset: i32 = offset.into();
+        sink.put4(offset as u32);
+        ''')
+
 # Unary fill with SIB and 32-bit displacement.
 fillSib32 = TailRecipe(
         'fillSib32', Unary, base_size=6, ins=StackGPR32, outs=GPR,
+        zero_extends=True,
         clobbers_flags=False,
         emit='''
         let base = stk_base(in_stk0.base);
@@ -1457,6 +2094,20 @@ fillSib32 = TailRecipe(
         sink.put4(in_stk0.offset as u32);
         ''')
 
+# Unary fill with SIB and 8-bit displacement.
+fillSib8 = TailRecipe(
+        'fillSib8', Unary, base_size=3, ins=StackGPR32, outs=GPR,
+        zero_extends=True,
+        stack_disp_bits=8,
+        clobbers_flags=False,
+        emit='''
+        let base = stk_base(in_stk0.base);
+        PUT_OP(bits, rex2(base, out_reg0), sink);
+        modrm_sib_disp8(out_reg0, sink);
+        sib_noindex(base, sink);
+        sink.put1(in_stk0.offset as u8);
+        ''')
+
 # Like fillSib32, but targeting an FPR rather than a GPR.
 ffillSib32 = TailRecipe(
         'ffillSib32', Unary, base_size=6, ins=StackFPR32, outs=FPR,
@@ -1469,6 +2120,19 @@ ffillSib32 = TailRecipe(
         sink.put4(in_stk0.offset as u32);
         ''')
 
+# Like fillSib8, but targeting an FPR rather than a GPR.
+ffillSib8 = TailRecipe(
+        'ffillSib8', Unary, base_size=3, ins=StackFPR32, outs=FPR,
+        stack_disp_bits=8,
+        clobbers_flags=False,
+        emit='''
+        let base = stk_base(in_stk0.base);
+        PUT_OP(bits, rex2(base, out_reg0), sink);
+        modrm_sib_disp8(out_reg0, sink);
+        sib_noindex(base, sink);
+        sink.put1(in_stk0.offset as u8);
+        ''')
+
 # Regfill with RSP-relative 32-bit displacement.
 regfill32 = TailRecipe(
         'regfill32', RegFill, base_size=6, ins=StackGPR32, outs=(),
@@ -1501,31 +2165,33 @@ fregfill32 = TailRecipe(
 call_id = TailRecipe(
         'call_id', Call, base_size=4, ins=(), outs=(),
         emit='''
-        sink.trap(TrapCode::StackOverflow, func.srclocs[inst]);
+        sink.trap(TrapCode::StackOverflow, func.srclocs[inst], 0);
         PUT_OP(bits, BASE_REX, sink);
         // The addend adjusts for the difference between the end of the
         // instruction and the beginning of the immediate field.
         sink.reloc_external(Reloc::X86CallPCRel4,
                             &func.dfg.ext_funcs[func_ref].name,
                             -4);
+        patch_point(func, inst, 4, sink);
         sink.put4(0);
         ''')
 
 call_plt_id = TailRecipe(
         'call_plt_id', Call, base_size=4, ins=(), outs=(),
         emit='''
-        sink.trap(TrapCode::StackOverflow, func.srclocs[inst]);
+        sink.trap(TrapCode::StackOverflow, func.srclocs[inst], 0);
         PUT_OP(bits, BASE_REX, sink);
         sink.reloc_external(Reloc::X86CallPLTRel4,
                             &func.dfg.ext_funcs[func_ref].name,
                             -4);
+        patch_point(func, inst, 4, sink);
         sink.put4(0);
         ''')
 
 call_r = TailRecipe(
         'call_r', CallIndirect, base_size=1, ins=GPR, outs=(),
         emit='''
-        sink.trap(TrapCode::StackOverflow, func.srclocs[inst]);
+        sink.trap(TrapCode::StackOverflow, func.srclocs[inst], 0);
         PUT_OP(bits, rex1(in_reg0), sink);
         modrm_r_bits(in_reg0, bits, sink);
         ''')
@@ -1545,7 +2211,7 @@ jmpb = TailRecipe(
         clobbers_flags=False,
         emit='''
         PUT_OP(bits, BASE_REX, sink);
-        disp1(destination, func, sink);
+        disp1(destination, func, inst, sink)?;
         ''')
 
 jmpd = TailRecipe(
@@ -1554,7 +2220,7 @@ jmpd = TailRecipe(
         clobbers_flags=False,
         emit='''
         PUT_OP(bits, BASE_REX, sink);
-        disp4(destination, func, sink);
+        disp4(destination, func, inst, sink)?;
         ''')
 
 brib = TailRecipe(
@@ -1563,7 +2229,7 @@ brib = TailRecipe(
         clobbers_flags=False,
         emit='''
         PUT_OP(bits | icc2opc(cond), BASE_REX, sink);
-        disp1(destination, func, sink);
+        disp1(destination, func, inst, sink)?;
         ''')
 
 brid = TailRecipe(
@@ -1572,7 +2238,7 @@ brid = TailRecipe(
         clobbers_flags=False,
         emit='''
         PUT_OP(bits | icc2opc(cond), BASE_REX, sink);
-        disp4(destination, func, sink);
+        disp4(destination, func, inst, sink)?;
         ''')
 
 brfb = TailRecipe(
@@ -1582,7 +2248,7 @@ brfb = TailRecipe(
         instp=floatccs(BranchFloat),
         emit='''
         PUT_OP(bits | fcc2opc(cond), BASE_REX, sink);
-        disp1(destination, func, sink);
+        disp1(destination, func, inst, sink)?;
         ''')
 
 brfd = TailRecipe(
@@ -1592,7 +2258,7 @@ brfd = TailRecipe(
         instp=floatccs(BranchFloat),
         emit='''
         PUT_OP(bits | fcc2opc(cond), BASE_REX, sink);
-        disp4(destination, func, sink);
+        disp4(destination, func, inst, sink)?;
         ''')
 
 indirect_jmp = TailRecipe(
@@ -1630,8 +2296,8 @@ jt_base = TailRecipe(
         modrm_riprel(out_reg0, sink);
 
         // No reloc is needed here as the jump table is emitted directly after
-        // the function body.
-        jt_disp4(table, func, sink);
+        // the function body, or at a known displacement from it.
+        jt_disp4(table, func, inst, sink)?;
         ''')
 
 #
@@ -1665,6 +2331,7 @@ setf = TailRecipe(
         'setf', FloatCond, base_size=1, ins=FLAG.rflags, outs=GPR,
         requires_prefix=True,
         clobbers_flags=False,
+        instp=floatccs(FloatCond),
         emit='''
         PUT_OP(bits | fcc2opc(cond), rex1(out_reg0), sink);
         modrm_r_bits(out_reg0, bits, sink);
@@ -1673,6 +2340,7 @@ setf_abcd = TailRecipe(
         'setf_abcd', FloatCond, base_size=1, ins=FLAG.rflags, outs=ABCD,
         when_prefixed=setf,
         clobbers_flags=False,
+        instp=floatccs(FloatCond),
         emit='''
         PUT_OP(bits | fcc2opc(cond), rex1(out_reg0), sink);
         modrm_r_bits(out_reg0, bits, sink);
@@ -1685,6 +2353,7 @@ setf_abcd = TailRecipe(
 #
 cmov = TailRecipe(
         'cmov', IntSelect, base_size=1, ins=(FLAG.rflags, GPR, GPR), outs=2,
+        zero_extends=True,
         requires_prefix=False,
         clobbers_flags=False,
         emit='''
@@ -1716,6 +2385,17 @@ rcmp = TailRecipe(
         modrm_rr(in_reg0, in_reg1, sink);
         ''')
 
+# XX /r, MR form. Same as rcmp, but limited to ABCD. This is for byte compares
+# with no REX prefix.
+rcmp_abcd = TailRecipe(
+        'rcmp_abcd', Binary, base_size=1, ins=(ABCD, ABCD),
+        outs=FLAG.rflags,
+        when_prefixed=rcmp,
+        emit='''
+        PUT_OP(bits, rex2(in_reg0, in_reg1), sink);
+        modrm_rr(in_reg0, in_reg1, sink);
+        ''')
+
 # XX /r, RM form. Compare two FPR registers and set flags.
 fcmp = TailRecipe(
         'fcmp', Binary, base_size=1, ins=(FPR, FPR), outs=FLAG.rflags,
@@ -1735,6 +2415,16 @@ rcmp_ib = TailRecipe(
         sink.put1(imm as u8);
         ''')
 
+# XX /r, MR form with the same register for both operands. Compare with zero
+# using TEST, which sets the flags like a compare with a zero immediate.
+rcmp_z = TailRecipe(
+        'rcmp_z', BinaryImm, base_size=1, ins=GPR, outs=FLAG.rflags,
+        instp=IsEqual(BinaryImm.imm, 0),
+        emit='''
+        PUT_OP(bits, rex2(in_reg0, in_reg0), sink);
+        modrm_rr(in_reg0, in_reg0, sink);
+        ''')
+
 # XX /n, MI form with imm32.
 rcmp_id = TailRecipe(
         'rcmp_id', BinaryImm, base_size=5, ins=GPR, outs=FLAG.rflags,
@@ -1776,7 +2466,7 @@ tjccb = TailRecipe(
         modrm_rr(in_reg0, in_reg0, sink);
         // Jcc instruction.
         sink.put1(bits as u8);
-        disp1(destination, func, sink);
+        disp1(destination, func, inst, sink)?;
         ''')
 
 tjccd = TailRecipe(
@@ -1789,7 +2479,7 @@ tjccd = TailRecipe(
         // Jcc instruction.
         sink.put1(0x0f);
         sink.put1(bits as u8);
-        disp4(destination, func, sink);
+        disp4(destination, func, inst, sink)?;
         ''')
 
 # 8-bit test-and-branch.
@@ -1806,7 +2496,7 @@ t8jccb = TailRecipe(
         modrm_rr(in_reg0, in_reg0, sink);
         // Jcc instruction.
         sink.put1(bits as u8);
-        disp1(destination, func, sink);
+        disp1(destination, func, inst, sink)?;
         ''')
 t8jccb_abcd = TailRecipe(
         't8jccb_abcd', Branch, base_size=1 + 2, ins=ABCD, outs=(),
@@ -1818,7 +2508,7 @@ t8jccb_abcd = TailRecipe(
         modrm_rr(in_reg0, in_reg0, sink);
         // Jcc instruction.
         sink.put1(bits as u8);
-        disp1(destination, func, sink);
+        disp1(destination, func, inst, sink)?;
         ''')
 
 t8jccd = TailRecipe(
@@ -1832,7 +2522,7 @@ t8jccd = TailRecipe(
         // Jcc instruction.
         sink.put1(0x0f);
         sink.put1(bits as u8);
-        disp4(destination, func, sink);
+        disp4(destination, func, inst, sink)?;
         ''')
 t8jccd_abcd = TailRecipe(
         't8jccd_abcd', Branch, base_size=1 + 6, ins=ABCD, outs=(),
@@ -1845,7 +2535,7 @@ t8jccd_abcd = TailRecipe(
         // Jcc instruction.
         sink.put1(0x0f);
         sink.put1(bits as u8);
-        disp4(destination, func, sink);
+        disp4(destination, func, inst, sink)?;
         ''')
 
 # Worst case test-and-branch recipe for brz.b1 and brnz.b1 in 32-bit mode.
@@ -1864,7 +2554,7 @@ t8jccd_long = TailRecipe(
         // Jcc instruction.
         sink.put1(0x0f);
         sink.put1(bits as u8);
-        disp4(destination, func, sink);
+        disp4(destination, func, inst, sink)?;
         ''')
 
 # Comparison that produces a `b1` result in a GPR.
@@ -1909,6 +2599,62 @@ icscc = TailRecipe(
         modrm_rr(out_reg0, 0, sink);
         ''')
 
+# Same as icscc, but the compared values are limited to ABCD too. This is for
+# byte compares with no REX prefix.
+icscc_abcd = TailRecipe(
+        'icscc_abcd', IntCompare, base_size=1 + 3, ins=(ABCD, ABCD),
+        outs=ABCD,
+        when_prefixed=icscc,
+        emit='''
+        // Comparison instruction.
+        PUT_OP(bits, rex2(in_reg0, in_reg1), sink);
+        modrm_rr(in_reg0, in_reg1, sink);
+        // `setCC` instruction, no REX.
+        use ir::condcodes::IntCC::*;
+        let setcc = match cond {
+            Equal => 0x94,
+            NotEqual => 0x95,
+            SignedLessThan => 0x9c,
+            SignedGreaterThanOrEqual => 0x9d,
+            SignedGreaterThan => 0x9f,
+            SignedLessThanOrEqual => 0x9e,
+            UnsignedLessThan => 0x92,
+            UnsignedGreaterThanOrEqual => 0x93,
+            UnsignedGreaterThan => 0x97,
+            UnsignedLessThanOrEqual => 0x96,
+        };
+        sink.put1(0x0f);
+        sink.put1(setcc);
+        modrm_rr(out_reg0, 0, sink);
+        ''')
+
+# Same as icscc, but comparing with zero using TEST.
+icscc_z = TailRecipe(
+        'icscc_z', IntCompareImm, base_size=1 + 3, ins=GPR, outs=ABCD,
+        instp=IsEqual(IntCompareImm.imm, 0),
+        emit='''
+        // Comparison instruction.
+        PUT_OP(bits, rex2(in_reg0, in_reg0), sink);
+        modrm_rr(in_reg0, in_reg0, sink);
+        // `setCC` instruction, no REX.
+        use ir::condcodes::IntCC::*;
+        let setcc = match cond {
+            Equal => 0x94,
+            NotEqual => 0x95,
+            SignedLessThan => 0x9c,
+            SignedGreaterThanOrEqual => 0x9d,
+            SignedGreaterThan => 0x9f,
+            SignedLessThanOrEqual => 0x9e,
+            UnsignedLessThan => 0x92,
+            UnsignedGreaterThanOrEqual => 0x93,
+            UnsignedGreaterThan => 0x97,
+            UnsignedLessThanOrEqual => 0x96,
+        };
+        sink.put1(0x0f);
+        sink.put1(setcc);
+        modrm_rr(out_reg0, 0, sink);
+        ''')
+
 icscc_ib = TailRecipe(
         'icscc_ib', IntCompareImm, base_size=2 + 3, ins=GPR, outs=ABCD,
         instp=IsSignedInt(IntCompareImm.imm, 8),
@@ -33,7 +33,9 @@ use_sse41 = And(has_sse41)
 use_sse42 = And(has_sse42, use_sse41)
 use_popcnt = And(has_popcnt, has_sse42)
 use_bmi1 = And(has_bmi1)
+use_bmi2 = And(has_bmi2)
 use_lzcnt = And(has_lzcnt)
+use_avx = And(has_avx)
 
 # Presets corresponding to x86 CPUs.
 
@@ -3,8 +3,9 @@
 //! This module provides functions and data structures that are useful for implementing the
 //! `TargetIsa::legalize_signature()` method.
 
-use ir::{AbiParam, ArgumentExtension, ArgumentLoc, Type};
-use std::cmp::Ordering;
+use ir::{AbiParam, ArgumentExtension, ArgumentLoc, ArgumentPurpose, Type};
+use settings::CallConv;
+use std::cmp::{max, Ordering};
 use std::vec::Vec;
 
 /// Legalization action to perform on a single argument or return value when converting a
@@ -19,8 +20,8 @@ pub enum ArgAction {
 
     /// Convert the argument, then call again.
     ///
-    /// This action can split an integer type into two smaller integer arguments, or it can split a
-    /// SIMD vector into halves.
+    /// This action can split an integer type into two smaller integer arguments, it can split a
+    /// SIMD vector into halves, or it can convert a boolean to an integer.
     Convert(ValueConversion),
 }
 
@@ -53,6 +54,11 @@ pub enum ValueConversion {
 
     /// Unsigned zero-extend value to the required type.
     Uext(Type),
+
+    /// Convert a boolean to an integer of the required type, using `bint`.
+    ///
+    /// The reverse conversion tests the integer against zero.
+    BoolToInt(Type),
 }
 
 impl ValueConversion {
@@ -62,7 +68,9 @@ impl ValueConversion {
             ValueConversion::IntSplit => ty.half_width().expect("Integer type too small to split"),
             ValueConversion::VectorSplit => ty.half_vector().expect("Not a vector"),
             ValueConversion::IntBits => Type::int(ty.bits()).expect("Bad integer size"),
-            ValueConversion::Sext(nty) | ValueConversion::Uext(nty) => nty,
+            ValueConversion::Sext(nty)
+            | ValueConversion::Uext(nty)
+            | ValueConversion::BoolToInt(nty) => nty,
         }
     }
 
@@ -75,6 +83,22 @@ impl ValueConversion {
     }
 }
 
+/// Get the integer type used to pass a boolean of type `ty` in the `call_conv` calling
+/// convention.
+///
+/// Booleans have no defined representation outside a function, so they are converted to integers
+/// at ABI boundaries: true is passed as 1 and false as 0. WebAssembly has no integer types smaller
+/// than `i32`, so SpiderMonkey expects `i32` values. The other calling conventions use at least
+/// an `i8`, like C's `bool`.
+pub fn bool_abi_type(ty: Type, call_conv: CallConv) -> Type {
+    debug_assert!(ty.is_bool(), "Expected a boolean: {}", ty);
+    let min_bits = match call_conv {
+        CallConv::Baldrdash => 32,
+        _ => 8,
+    };
+    Type::int(max(ty.bits(), min_bits)).expect("Bad boolean size")
+}
+
 /// Common trait for assigning arguments to registers or stack locations.
 ///
 /// This will be implemented by individual ISAs.
@@ -118,6 +142,55 @@ pub fn legalize_args<AA: ArgAssigner>(args: &mut Vec<AbiParam>, aa: &mut AA) {
     }
 }
 
+/// Get the number of `word`-sized parts needed to hold a struct argument of `size` bytes.
+pub fn struct_words(size: u32, word: Type) -> usize {
+    let word_bytes = word.bytes();
+    ((size + word_bytes - 1) / word_bytes) as usize
+}
+
+/// Expand the struct arguments in `params` into the values that are actually passed.
+///
+/// An unlegalized `StructArgument(size)` parameter is a pointer to `size` bytes that are passed by
+/// value. If `by_reference(size)` returns true, the caller passes a pointer to a private copy of
+/// the bytes instead, and the parameter is flagged as `legalized_to_pointer`. Otherwise the
+/// parameter is replaced by one `word`-typed parameter per part of the struct. The parts keep the
+/// `StructArgument` purpose so the argument assigner can place them together.
+///
+/// This should be called before `legalize_args`.
+pub fn expand_struct_args<F>(params: &mut Vec<AbiParam>, word: Type, by_reference: F)
+where
+    F: Fn(u32) -> bool,
+{
+    let mut argno = 0;
+    while let Some(arg) = params.get(argno).cloned() {
+        argno += 1;
+        let size = match arg.purpose {
+            ArgumentPurpose::StructArgument(size) => size,
+            _ => continue,
+        };
+        if arg.location.is_assigned() || arg.legalized_to_pointer {
+            continue;
+        }
+        debug_assert!(size > 0, "Empty struct argument");
+
+        if by_reference(size) {
+            params[argno - 1].legalized_to_pointer = true;
+            continue;
+        }
+
+        let words = struct_words(size, word);
+        let part = AbiParam {
+            value_type: word,
+            ..arg
+        };
+        params[argno - 1] = part;
+        for _ in 1..words {
+            params.insert(argno, part);
+            argno += 1;
+        }
+    }
+}
+
 /// Determine the right action to take when passing a `have` value type to a call signature where
 /// the next argument is `arg` which has a different value type.
 ///
@@ -144,6 +217,15 @@ pub fn legalize_abi_value(have: Type, arg: &AbiParam) -> ValueConversion {
     let have_bits = have.bits();
     let arg_bits = arg.value_type.bits();
 
+    // Booleans are converted directly to the ABI integer type, unless it needs to be split.
+    if have.is_bool() && !have.is_vector() && arg.value_type.is_int() {
+        return ValueConversion::BoolToInt(if have_bits <= arg_bits {
+            arg.value_type
+        } else {
+            Type::int(have_bits).expect("Bad boolean size")
+        });
+    }
+
     match have_bits.cmp(&arg_bits) {
         // We have fewer bits than the ABI argument.
         Ordering::Less => {
@@ -218,5 +300,44 @@ mod tests {
             legalize_abi_value(types::F64, &arg),
             ValueConversion::IntBits
         );
+
+        // Booleans are converted to the ABI integer type, or split if they are too large.
+        assert_eq!(
+            legalize_abi_value(types::B1, &arg),
+            ValueConversion::BoolToInt(types::I32)
+        );
+        assert_eq!(
+            legalize_abi_value(types::B64, &arg),
+            ValueConversion::BoolToInt(types::I64)
+        );
+    }
+
+    #[test]
+    fn bool_type() {
+        assert_eq!(bool_abi_type(types::B1, CallConv::SystemV), types::I8);
+        assert_eq!(bool_abi_type(types::B1, CallConv::Baldrdash), types::I32);
+        assert_eq!(bool_abi_type(types::B16, CallConv::Fast), types::I16);
+        assert_eq!(bool_abi_type(types::B64, CallConv::Baldrdash), types::I64);
+    }
+
+    #[test]
+    fn struct_args() {
+        let mut params = vec![
+            AbiParam::new(types::I32),
+            AbiParam::special(types::I64, ArgumentPurpose::StructArgument(12)),
+            AbiParam::special(types::I64, ArgumentPurpose::StructArgument(24)),
+        ];
+        assert_eq!(struct_words(12, types::I64), 2);
+        assert_eq!(struct_words(8, types::I64), 1);
+        assert_eq!(struct_words(12, types::I32), 3);
+
+        expand_struct_args(&mut params, types::I64, |size| size > 16);
+        let sarg12 = AbiParam::special(types::I64, ArgumentPurpose::StructArgument(12));
+        let mut sarg24 = AbiParam::special(types::I64, ArgumentPurpose::StructArgument(24));
+        sarg24.legalized_to_pointer = true;
+        assert_eq!(
+            params,
+            vec![AbiParam::new(types::I32), sarg12, sarg12, sarg24]
+        );
     }
 }
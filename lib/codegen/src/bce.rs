@@ -0,0 +1,479 @@
+//! Bounds check elimination for heap accesses.
+//!
+//! A `heap_addr` instruction checks that its offset is in bounds before computing an address. In
+//! a loop like `for i in 0..n { heap[i] }`, the loop condition often already proves that the
+//! offset is in bounds, and an access to `heap[i + 4]` is in bounds if a dominating access
+//! checked `heap[i .. i + 8]`. This pass finds such redundant checks and rewrites the `heap_addr`
+//! instructions into the unchecked address computation.
+//!
+//! The analysis keeps track of three kinds of facts in the dominator tree:
+//!
+//! - An upper bound of a value, derived from a branch on an `icmp_imm` comparison. The bounds of
+//!   other values are computed from these facts through `iconst` and `iadd_imm` instructions.
+//! - That a value isn't zero, derived from a branch or a conditional trap on the value or on a
+//!   comparison of it.
+//! - A range of bytes that a dominating `heap_addr` checked.
+//!
+//! The same facts tell the legalizer when a division can't divide by zero or by -1, so it can
+//! leave out the guards that `avoid_div_traps` requires. See `divisor_facts`.
+//!
+//! A dynamic heap may be resized by a call, so the checks made by dominating `heap_addr`
+//! instructions for a dynamic heap are only used in functions without calls, unless its bound is
+//! a `readonly` load. The bound of a static heap never changes.
+
+use dominator_tree::DominatorTree;
+use flowgraph::ControlFlowGraph;
+use ir::condcodes::{CondCode, IntCC};
+use ir::immediates::Imm64;
+use ir::{
+    DataFlowGraph, Ebb, Function, GlobalValueData, Heap, HeapStyle, Inst, InstructionData, Opcode,
+    Value, ValueDef,
+};
+use known_bits::type_mask;
+use legalizer::expand_unchecked_heap_addr;
+use std::vec::Vec;
+use timing;
+
+/// A fact that holds at some point of the function.
+#[derive(Clone, Copy, Debug)]
+enum Fact {
+    /// The value is unsigned less than or equal to the limit.
+    AtMost(Value, u64),
+    /// The value is not zero.
+    NonZero(Value),
+    /// The bytes `offset .. offset + size` of the heap are in bounds.
+    Checked(Heap, Value, u64),
+}
+
+/// Get the upper bound implied for `value` by knowing that `value cond imm` is `holds`.
+fn comparison_bound(cond: IntCC, imm: i64, holds: bool) -> Option<u64> {
+    let cond = if holds { cond } else { cond.inverse() };
+    match cond {
+        IntCC::UnsignedLessThan if imm != 0 => Some(imm as u64 - 1),
+        IntCC::UnsignedLessThanOrEqual | IntCC::Equal => Some(imm as u64),
+        _ => None,
+    }
+}
+
+/// Does knowing that `value cond imm` is `holds` prove that `value` isn't zero?
+///
+/// The immediate is sign-extended from the width of the value, whose type has the mask `mask`.
+fn excludes_zero(cond: IntCC, imm: i64, mask: u64, holds: bool) -> bool {
+    let cond = if holds { cond } else { cond.inverse() };
+    let uimm = imm as u64 & mask;
+    match cond {
+        IntCC::Equal | IntCC::UnsignedGreaterThanOrEqual => uimm != 0,
+        IntCC::NotEqual => uimm == 0,
+        IntCC::UnsignedGreaterThan => true,
+        IntCC::SignedGreaterThan => imm >= 0,
+        IntCC::SignedGreaterThanOrEqual => imm > 0,
+        IntCC::SignedLessThan => imm <= 0,
+        IntCC::SignedLessThanOrEqual => imm < 0,
+        _ => false,
+    }
+}
+
+/// Add the facts established by knowing that `arg cond imm` is `holds` to `facts`.
+fn comparison_facts(
+    dfg: &DataFlowGraph,
+    cond: IntCC,
+    arg: Value,
+    imm: Imm64,
+    holds: bool,
+    facts: &mut Vec<Fact>,
+) {
+    let imm: i64 = imm.into();
+    let mask = type_mask(dfg.value_type(arg));
+    if let Some(limit) = comparison_bound(cond, imm, holds) {
+        facts.push(Fact::AtMost(arg, limit & mask));
+    }
+    if excludes_zero(cond, imm, mask, holds) {
+        facts.push(Fact::NonZero(arg));
+    }
+}
+
+/// Add the facts established by knowing that the boolean or integer `cond` is `holds` to `facts`.
+///
+/// An integer condition holds when it isn't zero.
+fn condition_facts(dfg: &DataFlowGraph, cond: Value, holds: bool, facts: &mut Vec<Fact>) {
+    if dfg.value_type(cond).is_int() {
+        facts.push(if holds {
+            Fact::NonZero(cond)
+        } else {
+            Fact::AtMost(cond, 0)
+        });
+        return;
+    }
+    let inst = match dfg.value_def(cond) {
+        ValueDef::Result(inst, _) => inst,
+        ValueDef::Param(_, _) => return,
+    };
+    if let InstructionData::IntCompareImm {
+        opcode: Opcode::IcmpImm,
+        cond,
+        arg,
+        imm,
+    } = dfg[inst]
+    {
+        comparison_facts(dfg, cond, arg, imm, holds, facts);
+    }
+}
+
+/// Add the facts established by knowing that the condition `cond` of the integer flags `flags` is
+/// `holds` to `facts`.
+fn flags_facts(dfg: &DataFlowGraph, cond: IntCC, flags: Value, holds: bool, facts: &mut Vec<Fact>) {
+    let inst = match dfg.value_def(flags) {
+        ValueDef::Result(inst, _) => inst,
+        ValueDef::Param(_, _) => return,
+    };
+    if let InstructionData::BinaryImm {
+        opcode: Opcode::IfcmpImm,
+        arg,
+        imm,
+    } = dfg[inst]
+    {
+        comparison_facts(dfg, cond, arg, imm, holds, facts);
+    }
+}
+
+/// Add the facts established when the conditional branch `inst` is taken or not to `facts`.
+fn branch_facts(dfg: &DataFlowGraph, inst: Inst, taken: bool, facts: &mut Vec<Fact>) {
+    match dfg[inst] {
+        InstructionData::Branch {
+            opcode, ref args, ..
+        } => {
+            let cond = match args.first(&dfg.value_lists) {
+                Some(cond) => cond,
+                None => return,
+            };
+            match opcode {
+                Opcode::Brnz => condition_facts(dfg, cond, taken, facts),
+                Opcode::Brz => condition_facts(dfg, cond, !taken, facts),
+                _ => {}
+            }
+        }
+        InstructionData::BranchInt {
+            opcode: Opcode::Brif,
+            cond,
+            ref args,
+            ..
+        } => {
+            if let Some(flags) = args.first(&dfg.value_lists) {
+                flags_facts(dfg, cond, flags, taken, facts);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Add the facts established when the conditional trap `inst` doesn't trap to `facts`.
+fn trap_facts(dfg: &DataFlowGraph, inst: Inst, facts: &mut Vec<Fact>) {
+    match dfg[inst] {
+        InstructionData::CondTrap {
+            opcode: Opcode::Trapz,
+            arg,
+            ..
+        } => condition_facts(dfg, arg, true, facts),
+        InstructionData::CondTrap {
+            opcode: Opcode::Trapnz,
+            arg,
+            ..
+        } => condition_facts(dfg, arg, false, facts),
+        InstructionData::IntCondTrap {
+            opcode: Opcode::Trapif,
+            cond,
+            arg,
+            ..
+        } => flags_facts(dfg, cond, arg, false, facts),
+        _ => {}
+    }
+}
+
+/// Get an upper bound of the unsigned value of `value`, if it is known.
+///
+/// The values of patchable instructions are never known.
+fn upper_bound(func: &Function, value: Value, facts: &[Fact]) -> Option<u64> {
+    let dfg = &func.dfg;
+    let known = facts
+        .iter()
+        .filter_map(|&fact| match fact {
+            Fact::AtMost(v, limit) if v == value => Some(limit),
+            _ => None,
+        })
+        .min();
+    let mask = type_mask(dfg.value_type(value));
+    let computed = match dfg.value_def(value) {
+        ValueDef::Result(inst, _) if func.is_patchable(inst) => None,
+        ValueDef::Result(inst, _) => match dfg[inst] {
+            InstructionData::UnaryImm {
+                opcode: Opcode::Iconst,
+                imm,
+            } => {
+                let imm: i64 = imm.into();
+                Some(imm as u64 & mask)
+            }
+            InstructionData::BinaryImm {
+                opcode: Opcode::IaddImm,
+                arg,
+                imm,
+            } => {
+                let imm: i64 = imm.into();
+                if imm < 0 {
+                    None
+                } else {
+                    upper_bound(func, arg, facts)
+                        .and_then(|bound| bound.checked_add(imm as u64))
+                        .filter(|&bound| bound <= mask)
+                }
+            }
+            _ => None,
+        },
+        ValueDef::Param(_, _) => None,
+    };
+    match (known, computed) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+/// Is `value` known not to be zero?
+///
+/// The values of patchable instructions are never known.
+fn is_nonzero(func: &Function, value: Value, facts: &[Fact]) -> bool {
+    let dfg = &func.dfg;
+    if facts.iter().any(|&fact| match fact {
+        Fact::NonZero(v) => v == value,
+        _ => false,
+    }) {
+        return true;
+    }
+    let mask = type_mask(dfg.value_type(value));
+    match dfg.value_def(value) {
+        ValueDef::Result(inst, _) if func.is_patchable(inst) => false,
+        ValueDef::Result(inst, _) => match dfg[inst] {
+            InstructionData::UnaryImm {
+                opcode: Opcode::Iconst,
+                imm,
+            }
+            | InstructionData::BinaryImm {
+                opcode: Opcode::BorImm,
+                imm,
+                ..
+            } => {
+                let imm: i64 = imm.into();
+                imm as u64 & mask != 0
+            }
+            _ => false,
+        },
+        ValueDef::Param(_, _) => false,
+    }
+}
+
+/// Split `value` into a base value and a constant added to it by `iadd_imm` instructions.
+///
+/// The constant is less than half the range of the type, so `base + added` wraps at most once.
+fn split_offset(dfg: &DataFlowGraph, mut value: Value) -> (Value, u64) {
+    let half = type_mask(dfg.value_type(value)) >> 1;
+    let mut added = 0u64;
+    while let ValueDef::Result(inst, _) = dfg.value_def(value) {
+        match dfg[inst] {
+            InstructionData::BinaryImm {
+                opcode: Opcode::IaddImm,
+                arg,
+                imm,
+            } => {
+                let imm: i64 = imm.into();
+                match added.checked_add(imm as u64) {
+                    Some(sum) if imm >= 0 && sum <= half => added = sum,
+                    _ => break,
+                }
+                value = arg;
+            }
+            _ => break,
+        }
+    }
+    (value, added)
+}
+
+/// Is the access of `size` bytes at `offset` in `heap` known to be in bounds?
+fn is_in_bounds(
+    func: &Function,
+    heap: Heap,
+    offset: Value,
+    size: u64,
+    facts: &[Fact],
+    use_checks: bool,
+) -> bool {
+    let heap_data = &func.heaps[heap];
+    let (limit, use_checks): (i64, bool) = match heap_data.style {
+        HeapStyle::Static { bound } => (bound.into(), true),
+        HeapStyle::Dynamic { bound_gv } => {
+            let readonly = match func.global_values[bound_gv] {
+                GlobalValueData::Load { readonly, .. } => readonly,
+                _ => false,
+            };
+            (heap_data.min_size.into(), use_checks || readonly)
+        }
+    };
+    if let Some(bound) = upper_bound(func, offset, facts) {
+        if bound
+            .checked_add(size)
+            .map_or(false, |end| end <= limit as u64)
+        {
+            return true;
+        }
+    }
+    if !use_checks {
+        return false;
+    }
+
+    // Both offsets are computed from the same base. If only the later one wraps around, it is
+    // smaller than the checked one, so it's enough that its bytes fit in the checked range.
+    let (base, added) = split_offset(&func.dfg, offset);
+    facts.iter().any(|&fact| match fact {
+        Fact::Checked(h, v, checked) if h == heap => {
+            let (checked_base, checked_added) = split_offset(&func.dfg, v);
+            checked_base == base
+                && checked_added <= added
+                && (added - checked_added)
+                    .checked_add(size)
+                    .map_or(false, |end| end <= checked)
+        }
+        _ => false,
+    })
+}
+
+/// Add the facts known on entry to `ebb` from the branch that is its only predecessor to `facts`.
+///
+/// Returns the branch, if there is one.
+fn entry_facts(
+    func: &Function,
+    cfg: &ControlFlowGraph,
+    ebb: Ebb,
+    facts: &mut Vec<Fact>,
+) -> Option<Inst> {
+    let mut preds = cfg.pred_iter(ebb);
+    let pred = preds.next()?;
+    if preds.next().is_some() {
+        return None;
+    }
+    // A conditional branch is not the last instruction of its EBB, so this can't be a branch that
+    // also falls through to `ebb`.
+    branch_facts(&func.dfg, pred.inst, true, facts);
+    Some(pred.inst)
+}
+
+/// What is known about the divisor of a division.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DivisorFacts {
+    /// The divisor is never zero, so the division can't trap with `int_divz`.
+    pub nonzero: bool,
+    /// The divisor is never -1, so a signed division can't overflow.
+    pub not_minus_one: bool,
+}
+
+/// Get what is known about the divisor `divisor` of the division `inst`.
+///
+/// This doesn't need a dominator tree, so the legalizer can use it: the facts come from the
+/// branches and traps that precede `inst` in its EBB, and from the chain of single predecessors
+/// of the EBB, which all dominate `inst`.
+pub fn divisor_facts(
+    func: &Function,
+    cfg: &ControlFlowGraph,
+    inst: Inst,
+    divisor: Value,
+) -> DivisorFacts {
+    let dfg = &func.dfg;
+    let mut facts = Vec::new();
+    let mut pos = inst;
+    // Unreachable EBBs can form a cycle of single predecessors, so the walk is bounded.
+    for _ in 0..dfg.num_ebbs() {
+        let mut prev = func.layout.prev_inst(pos);
+        while let Some(inst) = prev {
+            if dfg[inst].opcode().is_branch() {
+                branch_facts(dfg, inst, false, &mut facts);
+            } else {
+                trap_facts(dfg, inst, &mut facts);
+            }
+            prev = func.layout.prev_inst(inst);
+        }
+        match func
+            .layout
+            .inst_ebb(pos)
+            .and_then(|ebb| entry_facts(func, cfg, ebb, &mut facts))
+        {
+            Some(branch) => pos = branch,
+            None => break,
+        }
+    }
+
+    let mask = type_mask(dfg.value_type(divisor));
+    DivisorFacts {
+        nonzero: is_nonzero(func, divisor, &facts),
+        not_minus_one: upper_bound(func, divisor, &facts).map_or(false, |bound| bound < mask),
+    }
+}
+
+/// Eliminate the bounds checks of the `heap_addr` instructions that are dominated by checks or
+/// conditions that prove them in bounds.
+pub fn do_bce(func: &mut Function, cfg: &ControlFlowGraph, domtree: &DominatorTree) {
+    let _tt = timing::bce();
+    debug_assert!(domtree.is_valid());
+
+    let has_calls = func
+        .layout
+        .ebbs()
+        .any(|ebb| func.layout.ebb_insts(ebb).any(|inst| func.dfg[inst].opcode().is_call()));
+
+    // The facts known at the current point. Each scope is represented by the instruction that
+    // starts it and the number of facts that were known before it.
+    let mut facts: Vec<Fact> = Vec::new();
+    let mut scope_stack: Vec<(Inst, usize)> = Vec::new();
+    let mut unchecked = Vec::new();
+
+    for &ebb in domtree.cfg_postorder().iter().rev() {
+        // Pop any scopes that we just exited.
+        while let Some(&(start, len)) = scope_stack.last() {
+            if domtree.dominates(start, ebb, &func.layout) {
+                break;
+            }
+            scope_stack.pop();
+            facts.truncate(len);
+        }
+
+        scope_stack.push((func.layout.first_inst(ebb).unwrap(), facts.len()));
+        entry_facts(func, cfg, ebb, &mut facts);
+
+        for inst in func.layout.ebb_insts(ebb) {
+            let opcode = func.dfg[inst].opcode();
+
+            // The rest of the EBB is only reached when the branch isn't taken.
+            if opcode.is_branch() && !opcode.is_terminator() {
+                let next = func.layout.next_inst(inst).unwrap();
+                scope_stack.push((next, facts.len()));
+                branch_facts(&func.dfg, inst, false, &mut facts);
+            }
+            trap_facts(&func.dfg, inst, &mut facts);
+
+            if let InstructionData::HeapAddr {
+                heap, arg, imm, ..
+            } = func.dfg[inst]
+            {
+                let size: u32 = imm.into();
+                let size = u64::from(size);
+                if is_in_bounds(func, heap, arg, size, &facts, !has_calls) {
+                    unchecked.push(inst);
+                }
+                facts.push(Fact::Checked(heap, arg, size));
+            }
+        }
+    }
+
+    for inst in unchecked {
+        debug!(
+            "Eliminating bounds check of {}",
+            func.dfg.display_inst(inst, None)
+        );
+        expand_unchecked_heap_addr(inst, func);
+    }
+}
@@ -0,0 +1,38 @@
+//! Address maps from the machine code of a compiled function back to its source.
+//!
+//! A profiler that samples program counters needs to know where each sample came from. The
+//! address map lists the code offset, size, and source location of every emitted instruction, and
+//! it marks where the code of each EBB starts, so samples can be attributed to EBBs as well.
+
+use binemit::CodeOffset;
+use ir::{Ebb, Function, SourceLoc};
+use isa::TargetIsa;
+
+/// A trait for receiving the address map of a compiled function.
+///
+/// The offsets are in increasing order. An EBB start marker comes before the instructions of the
+/// EBB, and it may have the same offset as the marker of the next EBB if the EBB has no code.
+pub trait AddrMapSink {
+    /// The code of an EBB starts at the given offset.
+    fn ebb(&mut self, CodeOffset, Ebb);
+
+    /// The code of an instruction with the given source location starts at the given offset, and
+    /// it is the given number of bytes long.
+    fn inst(&mut self, CodeOffset, CodeOffset, SourceLoc);
+}
+
+/// Send the address map of `func` to `sink`.
+///
+/// Instructions that don't emit any code are left out. The code layout must have been computed
+/// by `relax_branches()`, so this can be used after `Context::compile()`.
+pub fn emit_address_map(func: &Function, isa: &TargetIsa, sink: &mut AddrMapSink) {
+    let encinfo = isa.encoding_info();
+    for (ebb, ebb_offset) in func.ebb_offsets() {
+        sink.ebb(ebb_offset, ebb);
+        for (offset, inst, size) in func.inst_offsets(ebb, &encinfo) {
+            if size > 0 {
+                sink.inst(offset, size, func.srclocs[inst]);
+            }
+        }
+    }
+}
@@ -0,0 +1,339 @@
+//! Ready-made relocation and trap sinks.
+//!
+//! Most embedders want the relocations and trap sites of a function as a list they can process
+//! once the function has been emitted. `CollectedRelocs` and `CollectedTraps` record them with
+//! owned values, and `TeeSink` forwards them to two sinks, so a sink can record them while
+//! another one handles them as they are emitted.
+
+use super::{Addend, CodeOffset, Reloc, RelocSink, TrapSink};
+use ir::{ExternalName, JumpTable, SourceLoc, TrapCode};
+use std::vec::Vec;
+
+/// What a relocation refers to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RelocTarget {
+    /// The EBB at this offset from the start of the function.
+    Ebb(CodeOffset),
+    /// An external function or data object.
+    External(ExternalName),
+    /// A jump table of the function.
+    JumpTable(JumpTable),
+}
+
+/// A relocation in the code of a function.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RelocRecord {
+    /// The offset of the relocated bytes from the start of the function.
+    pub offset: CodeOffset,
+    /// The kind of relocation.
+    pub reloc: Reloc,
+    /// What the relocation refers to.
+    pub target: RelocTarget,
+    /// The addend to add to the address of `target`. It is 0 unless `target` is external.
+    pub addend: Addend,
+}
+
+/// A trap site in the code of a function.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TrapSite {
+    /// The offset of the trapping instruction from the start of the function.
+    pub offset: CodeOffset,
+    /// The source location of the trapping instruction.
+    pub srcloc: SourceLoc,
+    /// The reason for the trap.
+    pub code: TrapCode,
+    /// The payload of the trap instruction, or 0 if it has none.
+    pub payload: u32,
+}
+
+/// A `RelocSink` that records the relocations of a function in code order.
+#[derive(Clone, Debug, Default)]
+pub struct CollectedRelocs {
+    /// The recorded relocations.
+    pub relocs: Vec<RelocRecord>,
+}
+
+impl CollectedRelocs {
+    /// Create an empty sink.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl RelocSink for CollectedRelocs {
+    fn reloc_ebb(&mut self, offset: CodeOffset, reloc: Reloc, ebb_offset: CodeOffset) {
+        self.relocs.push(RelocRecord {
+            offset,
+            reloc,
+            target: RelocTarget::Ebb(ebb_offset),
+            addend: 0,
+        });
+    }
+
+    fn reloc_external(
+        &mut self,
+        offset: CodeOffset,
+        reloc: Reloc,
+        name: &ExternalName,
+        addend: Addend,
+    ) {
+        self.relocs.push(RelocRecord {
+            offset,
+            reloc,
+            target: RelocTarget::External(name.clone()),
+            addend,
+        });
+    }
+
+    fn reloc_jt(&mut self, offset: CodeOffset, reloc: Reloc, jt: JumpTable) {
+        self.relocs.push(RelocRecord {
+            offset,
+            reloc,
+            target: RelocTarget::JumpTable(jt),
+            addend: 0,
+        });
+    }
+}
+
+/// A `TrapSink` that records the trap sites of a function in code order.
+#[derive(Clone, Debug, Default)]
+pub struct CollectedTraps {
+    /// The recorded trap sites.
+    pub sites: Vec<TrapSite>,
+}
+
+impl CollectedTraps {
+    /// Create an empty sink.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl TrapSink for CollectedTraps {
+    fn trap(&mut self, offset: CodeOffset, srcloc: SourceLoc, code: TrapCode, payload: u32) {
+        self.sites.push(TrapSite {
+            offset,
+            srcloc,
+            code,
+            payload,
+        });
+    }
+}
+
+/// A `RelocSink` implementation that does nothing, which is convenient when the code is never
+/// linked, for example when it is only disassembled.
+pub struct NullRelocSink {}
+
+impl RelocSink for NullRelocSink {
+    fn reloc_ebb(&mut self, _: CodeOffset, _: Reloc, _: CodeOffset) {}
+    fn reloc_external(&mut self, _: CodeOffset, _: Reloc, _: &ExternalName, _: Addend) {}
+    fn reloc_jt(&mut self, _: CodeOffset, _: Reloc, _: JumpTable) {}
+}
+
+/// A sink that forwards everything it receives to two sinks, `first` and then `second`.
+///
+/// The sinks are often mutable references, for example to record the relocations of a function
+/// in a `CollectedRelocs` while another sink applies them.
+pub struct TeeSink<A, B> {
+    /// The sink that receives everything first.
+    pub first: A,
+    /// The sink that receives everything second.
+    pub second: B,
+}
+
+impl<A, B> TeeSink<A, B> {
+    /// Create a sink that forwards to `first` and `second`.
+    pub fn new(first: A, second: B) -> Self {
+        Self { first, second }
+    }
+}
+
+impl<A: RelocSink, B: RelocSink> RelocSink for TeeSink<A, B> {
+    fn reloc_ebb(&mut self, offset: CodeOffset, reloc: Reloc, ebb_offset: CodeOffset) {
+        self.first.reloc_ebb(offset, reloc, ebb_offset);
+        self.second.reloc_ebb(offset, reloc, ebb_offset);
+    }
+
+    fn reloc_external(
+        &mut self,
+        offset: CodeOffset,
+        reloc: Reloc,
+        name: &ExternalName,
+        addend: Addend,
+    ) {
+        self.first.reloc_external(offset, reloc, name, addend);
+        self.second.reloc_external(offset, reloc, name, addend);
+    }
+
+    fn reloc_jt(&mut self, offset: CodeOffset, reloc: Reloc, jt: JumpTable) {
+        self.first.reloc_jt(offset, reloc, jt);
+        self.second.reloc_jt(offset, reloc, jt);
+    }
+}
+
+impl<A: TrapSink, B: TrapSink> TrapSink for TeeSink<A, B> {
+    fn trap(&mut self, offset: CodeOffset, srcloc: SourceLoc, code: TrapCode, payload: u32) {
+        self.first.trap(offset, srcloc, code, payload);
+        self.second.trap(offset, srcloc, code, payload);
+    }
+}
+
+impl<S: RelocSink + ?Sized> RelocSink for &mut S {
+    fn reloc_ebb(&mut self, offset: CodeOffset, reloc: Reloc, ebb_offset: CodeOffset) {
+        (**self).reloc_ebb(offset, reloc, ebb_offset);
+    }
+
+    fn reloc_external(
+        &mut self,
+        offset: CodeOffset,
+        reloc: Reloc,
+        name: &ExternalName,
+        addend: Addend,
+    ) {
+        (**self).reloc_external(offset, reloc, name, addend);
+    }
+
+    fn reloc_jt(&mut self, offset: CodeOffset, reloc: Reloc, jt: JumpTable) {
+        (**self).reloc_jt(offset, reloc, jt);
+    }
+}
+
+impl<S: TrapSink + ?Sized> TrapSink for &mut S {
+    fn trap(&mut self, offset: CodeOffset, srcloc: SourceLoc, code: TrapCode, payload: u32) {
+        (**self).trap(offset, srcloc, code, payload);
+    }
+}
+
+#[cfg(test)]
+#[cfg(build_x86)]
+mod tests {
+    use super::*;
+    use binemit::NullTrapSink;
+    use cursor::{Cursor, FuncCursor};
+    use ir::{
+        types, AbiParam, ArgumentPurpose, ExtFuncData, Function, GlobalValueData, HeapData,
+        HeapStyle, InstBuilder, JumpTableData, MemFlags, Signature,
+    };
+    use isa;
+    use settings::{self, CallConv};
+    use std::str::FromStr;
+    use Context;
+
+    /// Compile a function with a heap access, a call, and a jump table.
+    ///
+    /// The relocations and traps are sent to `relocs` and `traps`.
+    fn compile(relocs: &mut RelocSink, traps: &mut TrapSink) {
+        let isa = isa::lookup(triple!("x86_64"))
+            .unwrap()
+            .finish(settings::Flags::new(settings::builder()));
+
+        let mut func = Function::new();
+        func.signature.params.push(AbiParam::new(types::I32));
+        func.signature
+            .params
+            .push(AbiParam::special(types::I64, ArgumentPurpose::VMContext));
+        let vmctx = func.create_global_value(GlobalValueData::VMContext);
+        let bound_gv = func.create_global_value(GlobalValueData::Load {
+            base: vmctx,
+            offset: 8.into(),
+            global_type: types::I32,
+            readonly: false,
+        });
+        let heap = func.create_heap(HeapData {
+            base: vmctx,
+            min_size: 0.into(),
+            guard_size: 0.into(),
+            style: HeapStyle::Dynamic { bound_gv },
+            index_type: types::I32,
+            check_alignment: false,
+        });
+        let sig = func.import_signature(Signature::new(CallConv::SystemV));
+        let callee = func.import_function(ExtFuncData {
+            name: ExternalName::testcase("callee"),
+            signature: sig,
+            colocated: true,
+        });
+        let ebb0 = func.dfg.make_ebb();
+        let ebb1 = func.dfg.make_ebb();
+        let ebb2 = func.dfg.make_ebb();
+        let mut jt_data = JumpTableData::new();
+        jt_data.push_entry(ebb1);
+        jt_data.push_entry(ebb2);
+        let jt = func.create_jump_table(jt_data);
+        {
+            let mut pos = FuncCursor::new(&mut func);
+            pos.insert_ebb(ebb0);
+            let x = pos.func.dfg.append_ebb_param(ebb0, types::I32);
+            pos.func.dfg.append_ebb_param(ebb0, types::I64);
+            pos.set_srcloc(SourceLoc::new(7));
+            let addr = pos.ins().heap_addr(types::I64, heap, x, 4);
+            pos.set_srcloc(SourceLoc::new(8));
+            pos.ins().store(MemFlags::new(), x, addr, 0);
+            pos.set_srcloc(SourceLoc::default());
+            pos.ins().call(callee, &[]);
+            pos.ins().br_table(x, ebb2, jt);
+            pos.insert_ebb(ebb1);
+            pos.ins().return_(&[]);
+            pos.insert_ebb(ebb2);
+            pos.ins().return_(&[]);
+        }
+
+        let mut ctx = Context::for_function(func);
+        ctx.compile_and_emit(&*isa, &mut Vec::new(), relocs, traps)
+            .unwrap();
+    }
+
+    #[test]
+    fn collected() {
+        let mut relocs = CollectedRelocs::new();
+        let mut traps = CollectedTraps::new();
+        compile(&mut relocs, &mut traps);
+
+        // The jump table is addressed relative to the code, so only the call is relocated.
+        assert_eq!(relocs.relocs.len(), 1);
+        let call = &relocs.relocs[0];
+        assert_eq!(call.reloc, Reloc::X86CallPCRel4);
+        assert_eq!(
+            call.target,
+            RelocTarget::External(ExternalName::testcase("callee"))
+        );
+        assert_eq!(call.addend, -4);
+
+        // The bounds check and the store trap with the source locations of their instructions,
+        // before the call.
+        let mut heap_srclocs: Vec<_> = traps
+            .sites
+            .iter()
+            .filter(|site| site.code == TrapCode::HeapOutOfBounds)
+            .map(|site| {
+                assert!(site.offset < call.offset);
+                site.srcloc
+            }).collect();
+        heap_srclocs.dedup();
+        assert_eq!(heap_srclocs, [SourceLoc::new(7), SourceLoc::new(8)]);
+    }
+
+    #[test]
+    fn tee() {
+        let mut relocs = CollectedRelocs::new();
+        let mut traps = CollectedTraps::new();
+        compile(&mut relocs, &mut traps);
+
+        let (mut relocs1, mut relocs2) = (CollectedRelocs::new(), CollectedRelocs::new());
+        let (mut traps1, mut traps2) = (CollectedTraps::new(), CollectedTraps::new());
+        compile(
+            &mut TeeSink::new(&mut relocs1, &mut relocs2),
+            &mut TeeSink::new(&mut traps1, &mut traps2),
+        );
+        assert_eq!(relocs1.relocs, relocs.relocs);
+        assert_eq!(relocs2.relocs, relocs.relocs);
+        assert_eq!(traps1.sites, traps.sites);
+        assert_eq!(traps2.sites, traps.sites);
+
+        compile(
+            &mut NullRelocSink {},
+            &mut TeeSink::new(NullTrapSink {}, &mut traps1),
+        );
+        assert_eq!(traps1.sites.len(), 2 * traps.sites.len());
+    }
+}
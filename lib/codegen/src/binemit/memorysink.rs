@@ -14,26 +14,36 @@
 //! relocations to a `RelocSink` trait object. Relocations are less frequent than the
 //! `CodeSink::put*` methods, so the performance impact of the virtual callbacks is less severe.
 
-use super::{Addend, CodeOffset, CodeSink, Reloc};
-use ir::{ExternalName, JumpTable, SourceLoc, TrapCode};
-use std::ptr::write_unaligned;
+use super::{Addend, CodeOffset, CodeSink, PatchPoint, Reloc};
+use ir::{ExternalName, Inst, JumpTable, SourceLoc, TrapCode};
+use std::ptr::{self, write_unaligned};
+use std::vec::Vec;
 
 /// A `CodeSink` that writes binary machine code directly into memory.
 ///
 /// A `MemoryCodeSink` object should be used when emitting a Cranelift IR function into executable
 /// memory. It writes machine code directly to a raw pointer without any bounds checking, so make
-/// sure to allocate enough memory for the whole function. The number of bytes required is returned
-/// by the `Context::compile()` function.
+/// sure to allocate enough memory for the whole function. The number of bytes required is the
+/// `total_size` returned by the `Context::compile()` function.
 ///
 /// Any relocations in the function are forwarded to the `RelocSink` trait object.
 ///
+/// The read-only data of the function follows the machine code, unless the sink is created with
+/// `with_rodata`, which writes it to a separate region.
+///
 /// Note that `MemoryCodeSink` writes multi-byte values in the native byte order of the host. This
 /// is not the right thing to do for cross compilation.
 pub struct MemoryCodeSink<'a> {
     data: *mut u8,
+    /// Offset from the start of the function of the byte at `data`.
+    data_offset: isize,
     offset: isize,
     /// Size of the machine code portion of output
     pub code_size: isize,
+    /// Patch points of the patchable instructions, in code order
+    pub patch_points: Vec<PatchPoint>,
+    rodata: *mut u8,
+    rodata_displacement: i64,
     relocs: &'a mut RelocSink,
     traps: &'a mut TrapSink,
 }
@@ -46,12 +56,39 @@ impl<'a> MemoryCodeSink<'a> {
     pub unsafe fn new(data: *mut u8, relocs: &'a mut RelocSink, traps: &'a mut TrapSink) -> Self {
         Self {
             data,
+            data_offset: 0,
             offset: 0,
             code_size: 0,
+            patch_points: Vec::new(),
+            rodata: ptr::null_mut(),
+            rodata_displacement: 0,
             relocs,
             traps,
         }
     }
+
+    /// Create a new memory code sink that writes the `code_size` bytes of machine code of a
+    /// function to `code`, and its read-only data to `rodata`.
+    ///
+    /// The code refers to the data with 32-bit displacements, so this fails when `rodata` is too
+    /// far from `code`. This function is unsafe for the same reasons as `new`.
+    pub unsafe fn with_rodata(
+        code: *mut u8,
+        rodata: *mut u8,
+        code_size: CodeOffset,
+        relocs: &'a mut RelocSink,
+        traps: &'a mut TrapSink,
+    ) -> Self {
+        let mut sink = Self::new(code, relocs, traps);
+        sink.rodata = rodata;
+        sink.rodata_displacement = rodata as i64 - (code as i64 + i64::from(code_size));
+        sink
+    }
+
+    /// Get a pointer to the byte at the current offset.
+    fn position(&self) -> *mut u8 {
+        unsafe { self.data.offset(self.offset - self.data_offset) }
+    }
 }
 
 /// A trait for receiving relocations for code that is emitted directly into memory.
@@ -72,7 +109,10 @@ pub trait RelocSink {
 /// [`NullTrapSink`](binemit/trait.TrapSink.html) implementation.
 pub trait TrapSink {
     /// Add trap information for a specific offset.
-    fn trap(&mut self, CodeOffset, SourceLoc, TrapCode);
+    ///
+    /// The last argument is the payload of the trap instruction, or 0 if the trap has no payload
+    /// or comes from an instruction that traps implicitly.
+    fn trap(&mut self, CodeOffset, SourceLoc, TrapCode, u32);
 }
 
 impl<'a> CodeSink for MemoryCodeSink<'a> {
@@ -82,7 +122,7 @@ impl<'a> CodeSink for MemoryCodeSink<'a> {
 
     fn put1(&mut self, x: u8) {
         unsafe {
-            write_unaligned(self.data.offset(self.offset), x);
+            write_unaligned(self.position(), x);
         }
         self.offset += 1;
     }
@@ -90,7 +130,7 @@ impl<'a> CodeSink for MemoryCodeSink<'a> {
     fn put2(&mut self, x: u16) {
         unsafe {
             #[cfg_attr(feature = "cargo-clippy", allow(cast_ptr_alignment))]
-            write_unaligned(self.data.offset(self.offset) as *mut u16, x);
+            write_unaligned(self.position() as *mut u16, x.to_le());
         }
         self.offset += 2;
     }
@@ -98,7 +138,7 @@ impl<'a> CodeSink for MemoryCodeSink<'a> {
     fn put4(&mut self, x: u32) {
         unsafe {
             #[cfg_attr(feature = "cargo-clippy", allow(cast_ptr_alignment))]
-            write_unaligned(self.data.offset(self.offset) as *mut u32, x);
+            write_unaligned(self.position() as *mut u32, x.to_le());
         }
         self.offset += 4;
     }
@@ -106,7 +146,7 @@ impl<'a> CodeSink for MemoryCodeSink<'a> {
     fn put8(&mut self, x: u64) {
         unsafe {
             #[cfg_attr(feature = "cargo-clippy", allow(cast_ptr_alignment))]
-            write_unaligned(self.data.offset(self.offset) as *mut u64, x);
+            write_unaligned(self.position() as *mut u64, x.to_le());
         }
         self.offset += 8;
     }
@@ -126,13 +166,26 @@ impl<'a> CodeSink for MemoryCodeSink<'a> {
         self.relocs.reloc_jt(ofs, rel, jt);
     }
 
-    fn trap(&mut self, code: TrapCode, srcloc: SourceLoc) {
+    fn trap(&mut self, code: TrapCode, srcloc: SourceLoc, payload: u32) {
         let ofs = self.offset();
-        self.traps.trap(ofs, srcloc, code);
+        self.traps.trap(ofs, srcloc, code, payload);
+    }
+
+    fn patch_point(&mut self, inst: Inst, size: u8) {
+        let offset = self.offset();
+        self.patch_points.push(PatchPoint { inst, offset, size });
     }
 
     fn begin_rodata(&mut self) {
         self.code_size = self.offset;
+        if !self.rodata.is_null() {
+            self.data = self.rodata;
+            self.data_offset = self.offset;
+        }
+    }
+
+    fn rodata_displacement(&self) -> i64 {
+        self.rodata_displacement
     }
 }
 
@@ -141,5 +194,5 @@ impl<'a> CodeSink for MemoryCodeSink<'a> {
 pub struct NullTrapSink {}
 
 impl TrapSink for NullTrapSink {
-    fn trap(&mut self, _offset: CodeOffset, _srcloc: SourceLoc, _code: TrapCode) {}
+    fn trap(&mut self, _offset: CodeOffset, _srcloc: SourceLoc, _code: TrapCode, _payload: u32) {}
 }
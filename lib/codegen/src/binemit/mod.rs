@@ -3,17 +3,27 @@
 //! The `binemit` module contains code for translating Cranelift's intermediate representation into
 //! binary machine code.
 
+mod addrmap;
+mod collected;
 mod memorysink;
 mod relaxation;
 mod shrink;
 
+pub use self::addrmap::{emit_address_map, AddrMapSink};
+pub use self::collected::{
+    CollectedRelocs, CollectedTraps, NullRelocSink, RelocRecord, RelocTarget, TeeSink, TrapSite,
+};
 pub use self::memorysink::{MemoryCodeSink, NullTrapSink, RelocSink, TrapSink};
 pub use self::relaxation::relax_branches;
 pub use self::shrink::shrink_instructions;
 pub use regalloc::RegDiversions;
 
-use ir::{ExternalName, Function, Inst, JumpTable, SourceLoc, TrapCode};
+use ir::{Ebb, ExternalName, Function, Inst, JumpTable, SourceLoc, TrapCode};
+use predicates::is_signed_int;
+use result::{CodegenError, CodegenResult};
+use stats::CodegenStats;
 use std::fmt;
+use target_lexicon::Endianness;
 
 /// Offset in bytes from the beginning of the function.
 ///
@@ -24,8 +34,64 @@ pub type CodeOffset = u32;
 /// Addend to add to the symbol value.
 pub type Addend = i64;
 
+/// The sizes of the sections of a compiled function.
+///
+/// The machine code comes first, followed by the jump tables, followed by any other read-only
+/// data such as the constant pool. An embedder can use the section boundaries to apply different memory protections to the
+/// code and the data, or emit the data into a separate region with
+/// `Context::emit_to_memory_with_rodata`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CodeInfo {
+    /// Number of bytes of machine code.
+    pub code_size: CodeOffset,
+
+    /// Number of bytes of jump tables.
+    pub jumptables_size: CodeOffset,
+
+    /// Number of bytes of read-only data.
+    pub rodata_size: CodeOffset,
+
+    /// Total number of bytes of code and data.
+    pub total_size: CodeOffset,
+
+    /// Statistics about the generated code.
+    pub stats: CodegenStats,
+}
+
+impl CodeInfo {
+    /// Offset of the jump tables from the start of the function.
+    pub fn jumptables(&self) -> CodeOffset {
+        self.code_size
+    }
+
+    /// Offset of the read-only data from the start of the function.
+    pub fn rodata(&self) -> CodeOffset {
+        self.code_size + self.jumptables_size
+    }
+
+    /// Number of bytes of jump tables and other read-only data following the machine code.
+    pub fn data_size(&self) -> CodeOffset {
+        self.jumptables_size + self.rodata_size
+    }
+}
+
+/// The location of the patchable field of an instruction in the emitted code.
+///
+/// See `Function::patchable`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PatchPoint {
+    /// The patchable instruction.
+    pub inst: Inst,
+
+    /// Offset of the immediate or displacement field from the start of the function.
+    pub offset: CodeOffset,
+
+    /// Size of the field in bytes.
+    pub size: u8,
+}
+
 /// Relocation kinds for every ISA
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum Reloc {
     /// absolute 4-byte
     Abs4,
@@ -74,13 +140,13 @@ pub trait CodeSink {
     /// Add 1 byte to the code section.
     fn put1(&mut self, u8);
 
-    /// Add 2 bytes to the code section.
+    /// Add 2 bytes to the code section, in little-endian byte order.
     fn put2(&mut self, u16);
 
-    /// Add 4 bytes to the code section.
+    /// Add 4 bytes to the code section, in little-endian byte order.
     fn put4(&mut self, u32);
 
-    /// Add 8 bytes to the code section.
+    /// Add 8 bytes to the code section, in little-endian byte order.
     fn put8(&mut self, u64);
 
     /// Add a relocation referencing an EBB at the current offset.
@@ -92,11 +158,23 @@ pub trait CodeSink {
     /// Add a relocation referencing a jump table.
     fn reloc_jt(&mut self, Reloc, JumpTable);
 
-    /// Add trap information for the current offset.
-    fn trap(&mut self, TrapCode, SourceLoc);
+    /// Add trap information for the current offset, with the payload of the trap instruction or 0.
+    fn trap(&mut self, TrapCode, SourceLoc, u32);
+
+    /// Mark the next `u8` bytes as the patchable field of the patchable instruction `Inst`.
+    fn patch_point(&mut self, Inst, u8);
 
     /// Code output is complete, read-only data may follow.
     fn begin_rodata(&mut self);
+
+    /// Get the distance from the position of the read-only data right after the code to its
+    /// actual position.
+    ///
+    /// The code refers to the jump tables and the constant pool with displacements, which include
+    /// this distance. It is 0 unless the data is emitted into a separate region.
+    fn rodata_displacement(&self) -> i64 {
+        0
+    }
 }
 
 /// Report a bad encoding error.
@@ -109,21 +187,91 @@ pub fn bad_encoding(func: &Function, inst: Inst) -> ! {
     );
 }
 
+/// Create an error for an offset from `from` to `to` that doesn't fit in its encoding.
+#[cold]
+pub fn offset_out_of_range<F, T>(from: F, to: T, disp: i64) -> CodegenError
+where
+    F: fmt::Display,
+    T: fmt::Display,
+{
+    CodegenError::OffsetOutOfRange(format!("{} to {} (displacement {})", from, to, disp))
+}
+
+/// Compute the displacement of the branch `inst` to `destination`, relative to `origin`.
+///
+/// The `in_range` predicate checks that the displacement fits in the encoding of the branch. When
+/// it doesn't, an error naming the branch is returned instead of a truncated displacement.
+pub fn branch_disp<P>(
+    func: &Function,
+    inst: Inst,
+    destination: Ebb,
+    origin: CodeOffset,
+    in_range: P,
+) -> CodegenResult<i64>
+where
+    P: FnOnce(i64) -> bool,
+{
+    let disp = i64::from(func.offsets[destination]) - i64::from(origin);
+    if in_range(disp) {
+        Ok(disp)
+    } else {
+        Err(offset_out_of_range(
+            func.dfg.display_inst(inst, None),
+            destination,
+            disp,
+        ))
+    }
+}
+
+/// Write the low `bytes.len()` bytes of `value` to `bytes` in the byte order `endianness`.
+///
+/// This is used for the data that is emitted with a function and for the pointer-sized values
+/// that relocations write, which are in the byte order of the target rather than the host.
+pub fn write_data(bytes: &mut [u8], value: u64, endianness: Endianness) {
+    let size = bytes.len();
+    debug_assert!(size <= 8, "can't write {} bytes", size);
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        let shift = match endianness {
+            Endianness::Little => i,
+            Endianness::Big => size - 1 - i,
+        };
+        *byte = (value >> (8 * shift)) as u8;
+    }
+}
+
+/// Add the low `size` bytes of `value` to `sink` in the byte order `endianness`.
+fn put_data<CS: CodeSink>(sink: &mut CS, value: u64, size: usize, endianness: Endianness) {
+    let mut bytes = [0; 8];
+    write_data(&mut bytes[..size], value, endianness);
+    for &byte in &bytes[..size] {
+        sink.put1(byte);
+    }
+}
+
 /// Emit a function to `sink`, given an instruction emitter function.
 ///
 /// This function is called from the `TargetIsa::emit_function()` implementations with the
-/// appropriate instruction emitter.
-pub fn emit_function<CS, EI>(func: &Function, emit_inst: EI, sink: &mut CS)
+/// appropriate instruction emitter. The jump tables and constants that follow the code are
+/// written in the byte order `endianness`.
+///
+/// Returns an error if a branch or jump table displacement doesn't fit in its encoding. The
+/// contents of `sink` are incomplete in that case.
+pub fn emit_function<CS, EI>(
+    func: &Function,
+    emit_inst: EI,
+    sink: &mut CS,
+    endianness: Endianness,
+) -> CodegenResult<()>
 where
     CS: CodeSink,
-    EI: Fn(&Function, Inst, &mut RegDiversions, &mut CS),
+    EI: Fn(&Function, Inst, &mut RegDiversions, &mut CS) -> CodegenResult<()>,
 {
     let mut divert = RegDiversions::new();
     for ebb in func.layout.ebbs() {
         divert.clear();
         debug_assert_eq!(func.offsets[ebb], sink.offset());
         for inst in func.layout.ebb_insts(ebb) {
-            emit_inst(func, inst, &mut divert, sink);
+            emit_inst(func, inst, &mut divert, sink)?;
         }
     }
 
@@ -131,10 +279,99 @@ where
 
     // output jump tables
     for (jt, jt_data) in func.jump_tables.iter() {
-        let jt_offset = func.jt_offsets[jt];
-        for ebb in jt_data.iter() {
-            let rel_offset: i32 = func.offsets[*ebb] as i32 - jt_offset as i32;
-            sink.put4(rel_offset as u32)
+        let jt_offset = i64::from(func.jt_offsets[jt]) + sink.rodata_displacement();
+        for entry in jt_data.as_slice() {
+            // The legalizer fills the holes of the tables used by indirect branches with the
+            // default destination, so the remaining holes are never loaded.
+            let rel_offset = match entry.expand() {
+                Some(ebb) => {
+                    let rel_offset = i64::from(func.offsets[ebb]) - jt_offset;
+                    if !is_signed_int(rel_offset, 32, 0) {
+                        return Err(offset_out_of_range(jt, ebb, rel_offset));
+                    }
+                    rel_offset
+                }
+                None => 0,
+            };
+            put_data(sink, u64::from(rel_offset as u32), 4, endianness);
+        }
+    }
+
+    // output the constant pool
+    for entry in func.constants.iter() {
+        while sink.offset() < entry.offset {
+            sink.put1(0);
+        }
+        match entry.size {
+            4 | 8 => put_data(sink, entry.bits, entry.size as usize, endianness),
+            _ => panic!("Bad constant pool entry size {}", entry.size),
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ir::{JumpTableData, SourceLoc, TrapCode};
+    use std::string::ToString;
+    use std::vec::Vec;
+
+    /// A code sink that only collects the emitted bytes.
+    struct BytesSink(Vec<u8>);
+
+    impl CodeSink for BytesSink {
+        fn offset(&self) -> CodeOffset {
+            self.0.len() as CodeOffset
+        }
+        fn put1(&mut self, x: u8) {
+            self.0.push(x);
+        }
+        fn put2(&mut self, x: u16) {
+            self.0.extend_from_slice(&[x as u8, (x >> 8) as u8]);
         }
+        fn put4(&mut self, x: u32) {
+            self.put2(x as u16);
+            self.put2((x >> 16) as u16);
+        }
+        fn put8(&mut self, x: u64) {
+            self.put4(x as u32);
+            self.put4((x >> 32) as u32);
+        }
+        fn reloc_ebb(&mut self, _: Reloc, _: CodeOffset) {}
+        fn reloc_external(&mut self, _: Reloc, _: &ExternalName, _: Addend) {}
+        fn reloc_jt(&mut self, _: Reloc, _: JumpTable) {}
+        fn trap(&mut self, _: TrapCode, _: SourceLoc, _: u32) {}
+        fn patch_point(&mut self, _: Inst, _: u8) {}
+        fn begin_rodata(&mut self) {}
+    }
+
+    #[test]
+    fn data() {
+        let mut bytes = [0; 8];
+        write_data(&mut bytes, 0x1122_3344_5566_7788, Endianness::Big);
+        assert_eq!(bytes, [0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88]);
+        write_data(&mut bytes[..4], 0x1122_3344_5566_7788, Endianness::Little);
+        assert_eq!(bytes, [0x88, 0x77, 0x66, 0x55, 0x55, 0x66, 0x77, 0x88]);
+    }
+
+    #[test]
+    fn jump_table_out_of_range() {
+        let mut func = Function::new();
+        let ebb = func.dfg.make_ebb();
+        let mut jt_data = JumpTableData::new();
+        jt_data.push_entry(ebb);
+        let jt = func.create_jump_table(jt_data);
+        func.offsets[ebb] = 0x8000_0010;
+        func.jt_offsets[jt] = 0x10;
+
+        let mut sink = BytesSink(Vec::new());
+        assert_eq!(
+            emit_function(&func, |_, _, _, _| Ok(()), &mut sink, Endianness::Little),
+            Err(CodegenError::OffsetOutOfRange(
+                "jt0 to ebb0 (displacement 2147483648)".to_string()
+            ))
+        );
     }
 }
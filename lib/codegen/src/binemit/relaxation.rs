@@ -6,6 +6,13 @@
 //! offsets of all the EBB headers in the function. This information is encoded in the
 //! `func.offsets` table.
 //!
+//! # Fall-throughs
+//!
+//! A `jump` to the EBB that follows it in the layout is turned into a `fallthrough` that emits no
+//! code. When a conditional branch to the next EBB is followed by a `jump`, the branch condition
+//! is inverted first so the `jump` can fall through instead. This is redone every time branches
+//! are relaxed, since the EBBs may have been reordered in the meantime.
+//!
 //! # Branch relaxation
 //!
 //! Branch relaxation is the process of ensuring that all branches in the function have enough
@@ -27,19 +34,23 @@
 //! ebb23:
 //! ```
 
-use binemit::CodeOffset;
+use binemit::{CodeInfo, CodeOffset};
 use cursor::{Cursor, FuncCursor};
-use ir::{Function, InstructionData, Opcode};
+use ir::condcodes::CondCode;
+use ir::{BranchHint, Ebb, Function, Inst, InstructionData, Opcode};
 use isa::{EncInfo, TargetIsa};
-use iterators::IteratorExtras;
 use regalloc::RegDiversions;
+use stats::CodegenStats;
+use std::vec::Vec;
 use timing;
 use CodegenResult;
 
 /// Relax branches and compute the final layout of EBB headers in `func`.
 ///
 /// Fill in the `func.offsets` table so the function is ready for binary emission.
-pub fn relax_branches(func: &mut Function, isa: &TargetIsa) -> CodegenResult<CodeOffset> {
+///
+/// Returns the sizes of the sections of the function.
+pub fn relax_branches(func: &mut Function, isa: &TargetIsa) -> CodegenResult<CodeInfo> {
     let _tt = timing::relax_branches();
 
     let encinfo = isa.encoding_info();
@@ -49,7 +60,7 @@ pub fn relax_branches(func: &mut Function, isa: &TargetIsa) -> CodegenResult<Cod
     func.offsets.resize(func.dfg.num_ebbs());
 
     // Start by inserting fall through instructions.
-    fallthroughs(func);
+    fallthroughs(func, isa);
 
     let mut offset = 0;
     let mut divert = RegDiversions::new();
@@ -101,6 +112,8 @@ pub fn relax_branches(func: &mut Function, isa: &TargetIsa) -> CodegenResult<Cod
         }
     }
 
+    let code_size = offset;
+
     for (jt, jt_data) in func.jump_tables.iter() {
         func.jt_offsets[jt] = offset;
         // TODO: this should be computed based on the min size needed to hold
@@ -108,39 +121,199 @@ pub fn relax_branches(func: &mut Function, isa: &TargetIsa) -> CodegenResult<Cod
         offset += jt_data.len() as u32 * 4;
     }
 
-    Ok(offset)
+    let jumptables_size = offset - code_size;
+
+    collect_constants(func);
+    offset = func.constants.layout(offset);
+
+    let rodata_size = offset - code_size - jumptables_size;
+
+    let mut stats = count_emitted_insts(func, &encinfo);
+    stats.code_size = offset;
+
+    Ok(CodeInfo {
+        code_size,
+        jumptables_size,
+        rodata_size,
+        total_size: offset,
+        stats,
+    })
+}
+
+/// Fill in the constant pool of `func` with the constants loaded by its encoded instructions.
+///
+/// Floating point zeros are always materialized in a register, so the pool holds the other
+/// floating point constants that have an encoding.
+fn collect_constants(func: &mut Function) {
+    func.constants.clear();
+    for ebb in func.layout.ebbs() {
+        for inst in func.layout.ebb_insts(ebb) {
+            if !func.encodings[inst].is_legal() {
+                continue;
+            }
+            match func.dfg[inst] {
+                InstructionData::UnaryIeee32 {
+                    opcode: Opcode::F32const,
+                    imm,
+                } if imm.bits() != 0 => func.constants.insert(u64::from(imm.bits()), 4),
+                InstructionData::UnaryIeee64 {
+                    opcode: Opcode::F64const,
+                    imm,
+                } if imm.bits() != 0 => func.constants.insert(imm.bits(), 8),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Count the instructions, branches and moves in `func` that are emitted as machine code.
+fn count_emitted_insts(func: &Function, encinfo: &EncInfo) -> CodegenStats {
+    let mut stats = CodegenStats::default();
+    let mut divert = RegDiversions::new();
+    for ebb in func.layout.ebbs() {
+        divert.clear();
+        for inst in func.layout.ebb_insts(ebb) {
+            divert.apply(&func.dfg[inst]);
+            if encinfo.byte_size(func.encodings[inst], inst, &divert, func) == 0 {
+                continue;
+            }
+            stats.insts += 1;
+            match func.dfg[inst].opcode() {
+                opcode if opcode.is_branch() => stats.branches += 1,
+                Opcode::Copy | Opcode::Regmove => stats.moves += 1,
+                _ => {}
+            }
+        }
+    }
+    stats
 }
 
-/// Convert `jump` instructions to `fallthrough` instructions where possible and verify that any
-/// existing `fallthrough` instructions are correct.
-fn fallthroughs(func: &mut Function) {
-    for (ebb, succ) in func.layout.ebbs().adjacent_pairs() {
+/// Convert `jump` instructions to `fallthrough` instructions where possible.
+///
+/// The EBBs may have been reordered since the `fallthrough` instructions were inserted, so any
+/// `fallthrough` that no longer goes to the layout successor is turned back into an encoded
+/// `jump`.
+fn fallthroughs(func: &mut Function, isa: &TargetIsa) {
+    let ebbs: Vec<Ebb> = func.layout.ebbs().collect();
+    for (i, &ebb) in ebbs.iter().enumerate() {
+        let succ = ebbs.get(i + 1).cloned();
         let term = func.layout.last_inst(ebb).expect("EBB has no terminator.");
+
+        // The layout may have changed after a fall-through was inserted. Make it a jump again.
         if let InstructionData::Jump {
             ref mut opcode,
             destination,
             ..
         } = func.dfg[term]
         {
-            match *opcode {
-                Opcode::Fallthrough => {
-                    // Somebody used a fall-through instruction before the branch relaxation pass.
-                    // Make sure it is correct, i.e. the destination is the layout successor.
-                    debug_assert_eq!(destination, succ, "Illegal fall-through in {}", ebb)
-                }
-                Opcode::Jump => {
-                    // If this is a jump to the successor EBB, change it to a fall-through.
-                    if destination == succ {
-                        *opcode = Opcode::Fallthrough;
-                        func.encodings[term] = Default::default();
-                    }
-                }
-                _ => {}
+            if *opcode == Opcode::Fallthrough && Some(destination) != succ {
+                *opcode = Opcode::Jump;
             }
         }
+
+        if let Some(succ) = succ {
+            invert_branch_over_jump(func, isa, term, succ);
+        }
+
+        // If this is a jump to the successor EBB, change it to a fall-through.
+        if let InstructionData::Jump {
+            ref mut opcode,
+            destination,
+            ..
+        } = func.dfg[term]
+        {
+            if *opcode == Opcode::Jump && Some(destination) == succ {
+                *opcode = Opcode::Fallthrough;
+                func.encodings[term] = Default::default();
+            }
+        }
+
+        if func.dfg[term].opcode() == Opcode::Jump && !func.encodings[term].is_legal() {
+            func.update_encoding(term, isa)
+                .expect("Can't encode jump instruction");
+        }
     }
 }
 
+/// Make a jump to `succ` out of a conditional branch to `succ` followed by a `jump` terminator.
+///
+/// The branch condition is inverted and the destinations are swapped, so:
+///
+/// ```clif
+///     brz v1, ebb2
+///     jump ebb3
+/// ebb2:
+/// ```
+///
+/// becomes a `brnz v1, ebb3` followed by a `jump ebb2` that can fall through. The branch keeps its
+/// encoding recipe, so its size and register constraints don't change. If there is no encoding
+/// with the same recipe for the inverted branch, it is left alone.
+///
+/// Any branch hint is inverted along with the branch.
+fn invert_branch_over_jump(func: &mut Function, isa: &TargetIsa, term: Inst, succ: Ebb) {
+    let jump_dest = match func.dfg[term] {
+        InstructionData::Jump {
+            opcode: Opcode::Jump,
+            destination,
+            ..
+        } if destination != succ => destination,
+        _ => return,
+    };
+    let branch = match func.layout.prev_inst(term) {
+        Some(inst) if func.dfg[inst].branch_destination() == Some(succ) => inst,
+        _ => return,
+    };
+
+    let mut data = func.dfg[branch].clone();
+    match data {
+        InstructionData::Branch { ref mut opcode, .. } => {
+            *opcode = match *opcode {
+                Opcode::Brz => Opcode::Brnz,
+                Opcode::Brnz => Opcode::Brz,
+                _ => return,
+            }
+        }
+        InstructionData::BranchInt { ref mut cond, .. } => *cond = cond.inverse(),
+        InstructionData::BranchFloat { ref mut cond, .. } => *cond = cond.inverse(),
+        InstructionData::BranchIcmp { ref mut cond, .. } => *cond = cond.inverse(),
+        _ => return,
+    }
+    *data.branch_destination_mut().unwrap() = jump_dest;
+
+    let recipe = func.encodings[branch].recipe();
+    let ctrl_type = func.dfg.ctrl_typevar(branch);
+    let enc = match isa
+        .legal_encodings(func, &data, ctrl_type)
+        .find(|enc| enc.recipe() == recipe)
+    {
+        Some(enc) => enc,
+        None => return,
+    };
+
+    // Swap the EBB arguments along with the destinations.
+    let fixed = func.dfg.inst_fixed_args(branch).to_vec();
+    let branch_args = func.dfg.inst_variable_args(branch).to_vec();
+    let jump_args = func.dfg.inst_variable_args(term).to_vec();
+    let pool = &mut func.dfg.value_lists;
+    let mut branch_list = data
+        .take_value_list()
+        .expect("Branch must have a value list");
+    branch_list.clear(pool);
+    branch_list.extend(fixed.into_iter().chain(jump_args), pool);
+    data.put_value_list(branch_list);
+    func.dfg[branch] = data;
+    func.encodings[branch] = enc;
+    func.branch_hints[branch] = func.branch_hints[branch].map(BranchHint::inverse);
+
+    let mut jump_list = func.dfg[term]
+        .take_value_list()
+        .expect("Jump must have a value list");
+    jump_list.clear(&mut func.dfg.value_lists);
+    jump_list.extend(branch_args, &mut func.dfg.value_lists);
+    func.dfg[term].put_value_list(jump_list);
+    *func.dfg[term].branch_destination_mut().unwrap() = succ;
+}
+
 /// Relax the branch instruction at `cur` so it can cover the range `offset - dest_offset`.
 ///
 /// Return the size of the replacement instructions up to and including the location where `cur` is
@@ -162,31 +335,31 @@ fn relax_branch(
         dest_offset
     );
 
-    // Pick the first encoding that can handle the branch range.
+    // Pick the smallest encoding that can handle the branch range.
     let dfg = &cur.func.dfg;
     let ctrl_type = dfg.ctrl_typevar(inst);
     if let Some(enc) = isa
         .legal_encodings(cur.func, &dfg[inst], ctrl_type)
-        .find(|&enc| {
+        .filter(|&enc| {
             let range = encinfo.branch_range(enc).expect("Branch with no range");
             if !range.contains(offset, dest_offset) {
                 debug!("  trying [{}]: out of range", encinfo.display(enc));
                 false
-            } else if encinfo.operand_constraints(enc)
-                != encinfo.operand_constraints(cur.func.encodings[inst])
-            {
-                // Conservatively give up if the encoding has different constraints
-                // than the original, so that we don't risk picking a new encoding
-                // which the existing operands don't satisfy. We can't check for
-                // validity directly because we don't have a RegDiversions active so
-                // we don't know which registers are actually in use.
-                debug!("  trying [{}]: constraints differ", encinfo.display(enc));
+            } else if !encinfo.constraints[enc.recipe()].satisfied(inst, divert, cur.func) {
+                // The allocated registers may not fit a different encoding. This happens when a
+                // compressed branch picked by the shrinking pass turns out to be out of range.
+                debug!(
+                    "  trying [{}]: constraints not satisfied",
+                    encinfo.display(enc)
+                );
                 false
             } else {
                 debug!("  trying [{}]: OK", encinfo.display(enc));
                 true
             }
-        }) {
+        })
+        .min_by_key(|&enc| encinfo.byte_size(enc, inst, divert, cur.func))
+    {
         cur.func.encodings[inst] = enc;
         return encinfo.byte_size(enc, inst, &divert, &cur.func);
     }
@@ -4,8 +4,14 @@
 //! chooses the largest one, because this typically provides the register allocator the most
 //! flexibility. However, once register allocation is done, this is no longer important, and we
 //! can switch to smaller encodings when possible.
+//!
+//! This pass runs after the stack frame is laid out, so the displacements used to address stack
+//! slots are known too. Encodings with narrow stack displacements, like the 8-bit displacement
+//! forms of x86 spills, fills, and `stack_addr`, are only valid when the displacement fits, which
+//! the recipe constraints check.
 
-use ir::instructions::InstructionData;
+use flags_liveness::flags_live_insts;
+use ir::instructions::{InstructionData, Opcode};
 use ir::Function;
 use isa::TargetIsa;
 use regalloc::RegDiversions;
@@ -18,11 +24,21 @@ pub fn shrink_instructions(func: &mut Function, isa: &TargetIsa) {
     let encinfo = isa.encoding_info();
     let mut divert = RegDiversions::new();
 
+    // Instructions with CPU flags live across them can't switch to an encoding that clobbers the
+    // flags.
+    let flags_live = flags_live_insts(func);
+    let clobbers_flags = |enc| {
+        encinfo
+            .operand_constraints(enc)
+            .map_or(false, |c| c.clobbers_flags)
+    };
+
     for ebb in func.layout.ebbs() {
         divert.clear();
         for inst in func.layout.ebb_insts(ebb) {
             let enc = func.encodings[inst];
-            if enc.is_legal() {
+            // Patchable instructions keep their patchable encoding.
+            if enc.is_legal() && !func.is_patchable(inst) {
                 // regmove/regfill/regspill are special instructions with register immediates
                 // that represented as normal operands, so the normal predicates below don't
                 // handle them correctly.
@@ -43,11 +59,26 @@ pub fn shrink_instructions(func: &mut Function, isa: &TargetIsa) {
                 }
 
                 let ctrl_type = func.dfg.ctrl_typevar(inst);
+                let may_clobber_flags = clobbers_flags(enc) || flags_live
+                    .as_ref()
+                    .map_or(false, |live| !live.contains(inst));
+
+                // A `uextend` with its result tied to the operand is only valid when the operand
+                // is already zero-extended, which the post-opt pass checks when it picks it. The
+                // operand and the result sharing a register doesn't make it valid.
+                let is_uextend = func.dfg[inst].opcode() == Opcode::Uextend;
+                let tied_ops = |enc| {
+                    encinfo
+                        .operand_constraints(enc)
+                        .map_or(false, |c| c.tied_ops)
+                };
 
                 // Pick the last encoding with constraints that are satisfied.
                 let best_enc = isa
                     .legal_encodings(func, &func.dfg[inst], ctrl_type)
                     .filter(|e| encinfo.constraints[e.recipe()].satisfied(inst, &divert, &func))
+                    .filter(|&e| may_clobber_flags || !clobbers_flags(e))
+                    .filter(|&e| e == enc || !is_uextend || !tied_ops(e))
                     .min_by_key(|e| encinfo.byte_size(*e, inst, &divert, &func))
                     .unwrap();
 
@@ -0,0 +1,117 @@
+//! Compaction of the entity numbers of a function.
+//!
+//! Passes that remove instructions and EBBs only take them out of the layout, and values that are
+//! replaced become aliases. The removed entities keep their slots in the data flow graph, so
+//! after many passes the entity maps of a function are mostly holes, which makes every
+//! `SecondaryMap` larger than it needs to be and bloats the serialized function.
+//!
+//! This pass rebuilds the data flow graph and the layout with only the EBBs, instructions and
+//! values in the layout, numbered densely in layout order. Aliases are resolved, and all the
+//! references to the renumbered entities are rewritten.
+
+use entity::{EntitySet, PrimaryMap, SecondaryMap};
+use ir::{DataFlowGraph, Ebb, Function, Layout, Value, ValueList};
+use packed_option::PackedOption;
+use std::mem;
+use std::vec::Vec;
+
+/// Renumber the EBBs, instructions and values of `func` densely in layout order.
+///
+/// The function must be valid: every value used by an instruction in the layout, or by a jump
+/// table, must be defined in the layout.
+pub fn do_compact(func: &mut Function) {
+    let mut old_dfg = mem::replace(&mut func.dfg, DataFlowGraph::new());
+    let old_layout = mem::replace(&mut func.layout, Layout::new());
+    func.dfg.signatures = mem::replace(&mut old_dfg.signatures, PrimaryMap::new());
+    func.dfg.ext_funcs = mem::replace(&mut old_dfg.ext_funcs, PrimaryMap::new());
+
+    // Create the new entities in layout order, so they are numbered densely. The instructions are
+    // created with their old data, which is rewritten once all the values are known, since an
+    // instruction can use values defined later in the layout.
+    let mut ebbs = SecondaryMap::with_default(PackedOption::<Ebb>::default());
+    let mut values = SecondaryMap::with_default(PackedOption::<Value>::default());
+    let mut insts = Vec::new();
+    for old_ebb in old_layout.ebbs() {
+        let ebb = func.dfg.make_ebb();
+        func.layout.append_ebb(ebb);
+        ebbs[old_ebb] = ebb.into();
+        for &param in old_dfg.ebb_params(old_ebb) {
+            values[param] = func
+                .dfg
+                .append_ebb_param(ebb, old_dfg.value_type(param))
+                .into();
+        }
+        for old_inst in old_layout.ebb_insts(old_ebb) {
+            let inst = func.dfg.make_inst(old_dfg[old_inst].clone());
+            func.layout.append_inst(inst, ebb);
+            for &result in old_dfg.inst_results(old_inst) {
+                values[result] = func
+                    .dfg
+                    .append_result(inst, old_dfg.value_type(result))
+                    .into();
+            }
+            insts.push((old_inst, inst));
+        }
+    }
+
+    let new_value = |value: Value| {
+        values[old_dfg.resolve_aliases(value)].expect("Value not defined in the layout")
+    };
+    let new_ebb = |ebb: Ebb| ebbs[ebb].expect("EBB not in the layout");
+
+    let mut encodings = SecondaryMap::new();
+    let mut srclocs = SecondaryMap::new();
+    let mut branch_hints = SecondaryMap::new();
+    let mut patchable = EntitySet::new();
+    for &(old_inst, inst) in &insts {
+        let mut data = old_dfg[old_inst].clone();
+        if let Some(list) = data.take_value_list() {
+            let args = list.as_slice(&old_dfg.value_lists);
+            data.put_value_list(ValueList::from_slice(args, &mut func.dfg.value_lists));
+        }
+        for arg in data.arguments_mut(&mut func.dfg.value_lists) {
+            *arg = new_value(*arg);
+        }
+        if let Some(dest) = data.branch_destination_mut() {
+            *dest = new_ebb(*dest);
+        }
+        func.dfg[inst] = data;
+
+        encodings[inst] = func.encodings[old_inst];
+        srclocs[inst] = func.srclocs[old_inst];
+        branch_hints[inst] = func.branch_hints[old_inst];
+        if func.patchable.contains(old_inst) {
+            patchable.insert(inst);
+        }
+    }
+    func.encodings = encodings;
+    func.srclocs = srclocs;
+    func.branch_hints = branch_hints;
+    func.patchable = patchable;
+
+    let mut locations = SecondaryMap::new();
+    for old_value in old_dfg.values() {
+        if let Some(value) = values[old_value].expand() {
+            locations[value] = func.locations[old_value];
+        }
+    }
+    func.locations = locations;
+
+    let mut offsets = SecondaryMap::new();
+    let mut ebb_frequencies = SecondaryMap::new();
+    for old_ebb in old_layout.ebbs() {
+        let ebb = new_ebb(old_ebb);
+        offsets[ebb] = func.offsets[old_ebb];
+        ebb_frequencies[ebb] = func.ebb_frequencies[old_ebb];
+    }
+    func.offsets = offsets;
+    func.ebb_frequencies = ebb_frequencies;
+
+    for jt in func.jump_tables.values_mut() {
+        for entry in jt.as_mut_slice() {
+            if let Some(ebb) = entry.expand() {
+                *entry = new_ebb(ebb).into();
+            }
+        }
+    }
+}
@@ -9,24 +9,34 @@
 //! contexts concurrently. Typically, you would have one context per compilation thread and only a
 //! single ISA instance.
 
+use bce::do_bce;
 use binemit::{
-    relax_branches, shrink_instructions, CodeOffset, MemoryCodeSink, RelocSink, TrapSink,
+    relax_branches, shrink_instructions, CodeInfo, MemoryCodeSink, PatchPoint, RelocSink, TrapSink,
 };
 use dce::do_dce;
+use dse::do_dse;
 use dominator_tree::DominatorTree;
+use ebb_order::do_reorder_ebbs;
 use flowgraph::ControlFlowGraph;
-use ir::Function;
+use gv_hoisting::do_hoist_global_value_loads;
+use ir::{AbiParam, ArgumentLoc, Function};
 use isa::TargetIsa;
 use legalize_function;
 use licm::do_licm;
+use limits::{CompileLimits, Deadline};
 use loop_analysis::LoopAnalysis;
 use nan_canonicalization::do_nan_canonicalization;
 use postopt::do_postopt;
 use preopt::do_preopt;
 use regalloc;
-use result::CodegenResult;
-use settings::{FlagsOrIsa, OptLevel};
+use result::{CodegenError, CodegenResult};
+use return_merging::do_merge_returns;
+use scheduling::do_schedule;
+use settings::{CallConv, FlagsOrIsa, OptLevel};
 use simple_gvn::do_simple_gvn;
+use sink::do_sink;
+use stats::count_saved_regs;
+use std::string::ToString;
 use std::vec::Vec;
 use timing;
 use unreachable_code::eliminate_unreachable_code;
@@ -48,6 +58,18 @@ pub struct Context {
 
     /// Loop analysis of `func`.
     pub loop_analysis: LoopAnalysis,
+
+    /// The locations of the patchable fields of the instructions in `func.patchable`.
+    ///
+    /// This is filled in by `compile_and_emit`, with offsets relative to the start of the
+    /// function.
+    pub patch_points: Vec<PatchPoint>,
+
+    /// The limits checked by `compile`.
+    ///
+    /// These are unset by default, and `clear` keeps them, so they apply to all the functions
+    /// compiled with this context.
+    pub limits: CompileLimits,
 }
 
 impl Context {
@@ -70,16 +92,22 @@ impl Context {
             domtree: DominatorTree::new(),
             regalloc: regalloc::Context::new(),
             loop_analysis: LoopAnalysis::new(),
+            patch_points: Vec::new(),
+            limits: CompileLimits::default(),
         }
     }
 
     /// Clear all data structures in this context.
+    ///
+    /// The allocations are kept, so compiling the next function doesn't need to allocate them
+    /// again.
     pub fn clear(&mut self) {
         self.func.clear();
         self.cfg.clear();
         self.domtree.clear();
         self.regalloc.clear();
         self.loop_analysis.clear();
+        self.patch_points.clear();
     }
 
     /// Compile the function, and emit machine code into a `Vec<u8>`.
@@ -90,26 +118,28 @@ impl Context {
     /// into `relocs`.
     ///
     /// This function calls `compile` and `emit_to_memory`, taking care to resize `mem` as
-    /// needed, so it provides a safe interface.
+    /// needed, so it provides a safe interface. The function is appended to `mem`, and the
+    /// offsets of relocations and traps are relative to the start of the function.
+    ///
+    /// The locations of the patchable fields of the instructions in `func.patchable` are stored
+    /// in `patch_points`.
+    ///
+    /// Returns the sizes of the sections of the emitted function.
     pub fn compile_and_emit(
         &mut self,
         isa: &TargetIsa,
         mem: &mut Vec<u8>,
         relocs: &mut RelocSink,
         traps: &mut TrapSink,
-    ) -> CodegenResult<()> {
-        let code_size = self.compile(isa)?;
+    ) -> CodegenResult<CodeInfo> {
+        let info = self.compile(isa)?;
         let old_len = mem.len();
-        mem.resize(old_len + code_size as usize, 0);
-        unsafe {
-            self.emit_to_memory(
-                isa,
-                mem.as_mut_ptr().offset(old_len as isize),
-                relocs,
-                traps,
-            )
+        mem.resize(old_len + info.total_size as usize, 0);
+        let sink = unsafe {
+            MemoryCodeSink::new(mem.as_mut_ptr().offset(old_len as isize), relocs, traps)
         };
-        Ok(())
+        self.patch_points = self.emit_with_patch_points(isa, sink)?;
+        Ok(info)
     }
 
     /// Compile the function.
@@ -118,10 +148,14 @@ impl Context {
     /// represented by `isa`. This does not include the final step of emitting machine code into a
     /// code sink.
     ///
-    /// Returns the size of the function's code.
-    pub fn compile(&mut self, isa: &TargetIsa) -> CodegenResult<CodeOffset> {
+    /// Returns the sizes of the sections of the function's code and data, and statistics about the
+    /// generated code.
+    pub fn compile(&mut self, isa: &TargetIsa) -> CodegenResult<CodeInfo> {
         let _tt = timing::compile();
+        let deadline = Deadline::start(&self.limits);
+        self.check_pointer_type(isa)?;
         self.verify_if(isa)?;
+        self.limits.check_function_size(&self.func)?;
 
         self.compute_cfg();
         if isa.flags().opt_level() != OptLevel::Fastest {
@@ -130,7 +164,21 @@ impl Context {
         if isa.flags().enable_nan_canonicalization() {
             self.canonicalize_nans(isa)?;
         }
+        if isa.flags().opt_level() == OptLevel::Best {
+            self.dse(isa)?;
+            self.compute_domtree();
+            self.bce(isa)?;
+        }
+        if isa.flags().hoist_global_value_loads() {
+            self.hoist_global_value_loads(isa)?;
+        }
+        if isa.flags().merge_returns() {
+            self.merge_returns(isa)?;
+        }
+        deadline.check("preopt")?;
         self.legalize(isa)?;
+        self.limits.check_function_size(&self.func)?;
+        deadline.check("legalize")?;
         if isa.flags().opt_level() != OptLevel::Fastest {
             self.postopt(isa)?;
         }
@@ -145,32 +193,107 @@ impl Context {
         if isa.flags().opt_level() != OptLevel::Fastest {
             self.dce(isa)?;
         }
+        if isa.flags().opt_level() == OptLevel::Best {
+            self.compute_loop_analysis();
+            self.sink(isa)?;
+            self.schedule(isa)?;
+        }
+        self.reorder_ebbs(isa)?;
+        deadline.check("optimization")?;
         self.regalloc(isa)?;
+        self.limits.check_spills(self.regalloc.stats().spills)?;
+        deadline.check("regalloc")?;
         self.prologue_epilogue(isa)?;
         if isa.flags().opt_level() == OptLevel::Best {
             self.shrink_instructions(isa)?;
         }
-        self.relax_branches(isa)
+        let mut info = self.relax_branches(isa)?;
+        deadline.check("relax_branches")?;
+        info.stats += self.regalloc.stats();
+        info.stats.saved_regs = count_saved_regs(&self.func, isa);
+        Ok(info)
     }
 
     /// Emit machine code directly into raw memory.
     ///
     /// Write all of the function's machine code to the memory at `mem`. The size of the machine
-    /// code is returned by `compile` above.
+    /// code and data is the `total_size` returned by `compile` above.
     ///
     /// The machine code is not relocated. Instead, any relocations are emitted into `relocs`.
     ///
     /// This function is unsafe since it does not perform bounds checking on the memory buffer,
     /// and it can't guarantee that the `mem` pointer is valid.
+    ///
+    /// Returns an error if a branch or jump table displacement doesn't fit in its encoding. This
+    /// can only happen when the function was changed after `compile` or `relax_branches`.
     pub unsafe fn emit_to_memory(
         &self,
         isa: &TargetIsa,
         mem: *mut u8,
         relocs: &mut RelocSink,
         traps: &mut TrapSink,
-    ) {
+    ) -> CodegenResult<()> {
+        let sink = MemoryCodeSink::new(mem, relocs, traps);
+        self.emit_with_patch_points(isa, sink).map(|_| ())
+    }
+
+    /// Emit machine code directly into raw memory, with the read-only data in a separate region.
+    ///
+    /// Write the function's machine code to the `info.code_size` bytes at `code`, and its jump
+    /// tables and constant pool to the `info.data_size()` bytes at `rodata`, where `info` is the
+    /// `CodeInfo` returned by `compile`. This lets an embedder keep the data out of executable
+    /// memory.
+    ///
+    /// The code refers to the data with 32-bit PC-relative displacements, so `rodata` must be
+    /// within 2 GB of `code`. Constants are aligned relative to the start of the function, so they
+    /// are only aligned in memory when `rodata` is congruent to `code + info.code_size` modulo 8.
+    ///
+    /// The locations of the patchable fields of the instructions in `func.patchable` are stored
+    /// in `patch_points`, like `compile_and_emit` does.
+    ///
+    /// This function is unsafe for the same reasons as `emit_to_memory`.
+    ///
+    /// Returns an error if a displacement doesn't fit in its encoding, including the ones from
+    /// the code to the data.
+    pub unsafe fn emit_to_memory_with_rodata(
+        &mut self,
+        isa: &TargetIsa,
+        code: *mut u8,
+        rodata: *mut u8,
+        info: &CodeInfo,
+        relocs: &mut RelocSink,
+        traps: &mut TrapSink,
+    ) -> CodegenResult<()> {
+        let sink = MemoryCodeSink::with_rodata(code, rodata, info.code_size, relocs, traps);
+        self.patch_points = self.emit_with_patch_points(isa, sink)?;
+        Ok(())
+    }
+
+    /// Emit machine code into `sink`, and return the patch points.
+    fn emit_with_patch_points(
+        &self,
+        isa: &TargetIsa,
+        mut sink: MemoryCodeSink,
+    ) -> CodegenResult<Vec<PatchPoint>> {
         let _tt = timing::binemit();
-        isa.emit_function_to_memory(&self.func, &mut MemoryCodeSink::new(mem, relocs, traps));
+        isa.emit_function_to_memory(&self.func, &mut sink)?;
+        Ok(sink.patch_points)
+    }
+
+    /// Check that the function was built for the pointer type of `isa`.
+    ///
+    /// Functions that don't declare a pointer type get the one of `isa`.
+    pub fn check_pointer_type(&mut self, isa: &TargetIsa) -> CodegenResult<()> {
+        let isa_pointer_type = isa.pointer_type();
+        match self.func.pointer_type {
+            Some(pointer_type) if pointer_type != isa_pointer_type => Err(
+                CodegenError::PointerTypeMismatch(pointer_type, isa_pointer_type),
+            ),
+            _ => {
+                self.func.pointer_type = Some(isa_pointer_type);
+                Ok(())
+            }
+        }
     }
 
     /// Run the verifier on the function.
@@ -223,9 +346,25 @@ impl Context {
         Ok(())
     }
 
+    /// Remove dead stores to explicit stack slots, and share the storage of the slots that are
+    /// never live at the same time.
+    pub fn dse<'a, FOI: Into<FlagsOrIsa<'a>>>(&mut self, fisa: FOI) -> CodegenResult<()> {
+        do_dse(&mut self.func);
+        self.verify_if(fisa)
+    }
+
+    /// Eliminate the bounds checks of `heap_addr` instructions that are proven to be in bounds.
+    pub fn bce<'a, FOI: Into<FlagsOrIsa<'a>>>(&mut self, fisa: FOI) -> CodegenResult<()> {
+        do_bce(&mut self.func, &self.cfg, &self.domtree);
+        self.verify_if(fisa)
+    }
+
     /// Perform pre-legalization rewrites on the function.
     pub fn preopt(&mut self, isa: &TargetIsa) -> CodegenResult<()> {
-        do_preopt(&mut self.func);
+        // Folding branches invalidates the domtree and loop_analysis by mutating the CFG.
+        self.domtree.clear();
+        self.loop_analysis.clear();
+        do_preopt(&mut self.func, &mut self.cfg);
         self.verify_if(isa)?;
         Ok(())
     }
@@ -236,14 +375,169 @@ impl Context {
         self.verify_if(isa)
     }
 
+    /// Replace the `global_value` instructions of each `readonly` load global value with one
+    /// instruction at the common dominator of their uses.
+    pub fn hoist_global_value_loads<'a, FOI: Into<FlagsOrIsa<'a>>>(
+        &mut self,
+        fisa: FOI,
+    ) -> CodegenResult<()> {
+        self.compute_domtree();
+        do_hoist_global_value_loads(&mut self.func, &self.domtree);
+        self.verify_if(fisa)
+    }
+
+    /// Renumber the entities of the function densely, and recompute the control flow graph.
+    ///
+    /// The dominator tree and loop analysis are cleared, since they refer to the old EBBs and
+    /// instructions.
+    pub fn compact<'a, FOI: Into<FlagsOrIsa<'a>>>(&mut self, fisa: FOI) -> CodegenResult<()> {
+        self.func.compact();
+        self.domtree.clear();
+        self.loop_analysis.clear();
+        self.flowgraph();
+        self.verify_if(fisa)
+    }
+
+    /// Replace the returns of the function with jumps to a single return, so it gets a single
+    /// epilogue.
+    pub fn merge_returns<'a, FOI: Into<FlagsOrIsa<'a>>>(&mut self, fisa: FOI) -> CodegenResult<()> {
+        // The new EBB invalidates the domtree and loop_analysis.
+        self.domtree.clear();
+        self.loop_analysis.clear();
+        do_merge_returns(&mut self.func, &mut self.cfg);
+        self.verify_if(fisa)
+    }
+
     /// Run the legalizer for `isa` on the function.
     pub fn legalize(&mut self, isa: &TargetIsa) -> CodegenResult<()> {
         // Legalization invalidates the domtree and loop_analysis by mutating the CFG.
         // TODO: Avoid doing this when legalization doesn't actually mutate the CFG.
         self.domtree.clear();
         self.loop_analysis.clear();
+        self.check_enabled_types(isa)?;
+        self.check_argument_locations(isa)?;
+        self.check_call_convs(isa)?;
         legalize_function(&mut self.func, &mut self.cfg, isa);
-        self.verify_if(isa)
+        self.verify_if(isa)?;
+        self.check_patchable(isa)
+    }
+
+    /// Check that the settings of `isa` allow the types of all the values and all the instructions
+    /// in the function.
+    ///
+    /// The verifier checks this too, but the legalizer must not expand disabled types or
+    /// instructions even when the verifier is disabled.
+    fn check_enabled_types(&self, isa: &TargetIsa) -> CodegenResult<()> {
+        let flags = isa.flags();
+        let dfg = &self.func.dfg;
+        for ebb in self.func.layout.ebbs() {
+            for &param in dfg.ebb_params(ebb) {
+                if let Some(setting) = flags.disabling_setting(dfg.value_type(param)) {
+                    return Err(CodegenError::DisabledType(format!(
+                        "{} parameter {}: {} needs {}",
+                        ebb,
+                        param,
+                        dfg.value_type(param),
+                        setting
+                    )));
+                }
+            }
+            for inst in self.func.layout.ebb_insts(ebb) {
+                if let Some(setting) = flags.disabling_opcode_setting(dfg[inst].opcode()) {
+                    return Err(CodegenError::DisabledInstruction(format!(
+                        "{}: {} needs {}",
+                        inst,
+                        dfg[inst].opcode(),
+                        setting
+                    )));
+                }
+                for &result in dfg.inst_results(inst) {
+                    if let Some(setting) = flags.disabling_setting(dfg.value_type(result)) {
+                        return Err(CodegenError::DisabledType(format!(
+                            "{}: {} needs {}",
+                            dfg.display_inst(inst, isa),
+                            dfg.value_type(result),
+                            setting
+                        )));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that the locations assigned to the parameters and return values in the signature of
+    /// the function are the ones its calling convention uses.
+    ///
+    /// The legalizer keeps the locations that are already assigned, so a function read from a
+    /// text file can spell out its ABI. With the `raw` calling convention, they can be anywhere.
+    fn check_argument_locations(&self, isa: &TargetIsa) -> CodegenResult<()> {
+        let sig = &self.func.signature;
+        if sig.call_conv == CallConv::Raw {
+            return Ok(());
+        }
+        let mut unassigned = sig.clone();
+        for arg in unassigned
+            .params
+            .iter_mut()
+            .chain(unassigned.returns.iter_mut())
+        {
+            arg.location = ArgumentLoc::Unassigned;
+        }
+        check_locations(
+            "parameter",
+            &sig.params,
+            &isa.compute_argument_locations(&unassigned),
+            sig.call_conv,
+            isa,
+        )?;
+        check_locations(
+            "return value",
+            &sig.returns,
+            &isa.compute_return_locations(&unassigned),
+            sig.call_conv,
+            isa,
+        )
+    }
+
+    /// Check that `isa` implements the calling convention of the function and the conventions of
+    /// all its calls.
+    fn check_call_convs(&self, isa: &TargetIsa) -> CodegenResult<()> {
+        let caller = self.func.signature.call_conv;
+        if !isa.supports_call_conv(caller, caller) {
+            return Err(CodegenError::UnsupportedCallConv(caller, caller));
+        }
+        let dfg = &self.func.dfg;
+        for ebb in self.func.layout.ebbs() {
+            for inst in self.func.layout.ebb_insts(ebb) {
+                if let Some(sig) = dfg.call_signature(inst) {
+                    let callee = dfg.signatures[sig].call_conv;
+                    if !isa.supports_call_conv(caller, callee) {
+                        return Err(CodegenError::UnsupportedCallConv(caller, callee));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that all the patchable instructions got a patchable encoding from the legalizer.
+    fn check_patchable(&self, isa: &TargetIsa) -> CodegenResult<()> {
+        if self.func.patchable.is_empty() {
+            return Ok(());
+        }
+        for ebb in self.func.layout.ebbs() {
+            for inst in self.func.layout.ebb_insts(ebb) {
+                if self.func.is_patchable(inst)
+                    && isa.patchable_encoding(&self.func, inst) != Some(self.func.encodings[inst])
+                {
+                    return Err(CodegenError::Unpatchable(
+                        self.func.dfg.display_inst(inst, isa).to_string(),
+                    ));
+                }
+            }
+        }
+        Ok(())
     }
 
     /// Perform post-legalization rewrites on the function.
@@ -292,6 +586,19 @@ impl Context {
         self.verify_if(fisa)
     }
 
+    /// Sink instructions towards their uses.
+    pub fn sink<'a, FOI: Into<FlagsOrIsa<'a>>>(&mut self, fisa: FOI) -> CodegenResult<()> {
+        do_sink(&mut self.func, &self.domtree, &self.loop_analysis);
+        self.verify_if(fisa)
+    }
+
+    /// Schedule the instructions in each basic block to hide latencies, if `isa` has in-order
+    /// cores that benefit from it.
+    pub fn schedule(&mut self, isa: &TargetIsa) -> CodegenResult<()> {
+        do_schedule(&mut self.func, isa);
+        self.verify_if(isa)
+    }
+
     /// Perform unreachable code elimination.
     pub fn eliminate_unreachable_code<'a, FOI>(&mut self, fisa: FOI) -> CodegenResult<()>
     where
@@ -301,6 +608,13 @@ impl Context {
         self.verify_if(fisa)
     }
 
+    /// Move rarely executed EBBs towards the end of the function, according to the frequency
+    /// hints in `func.ebb_frequencies` and the branch hints in `func.branch_hints`.
+    pub fn reorder_ebbs<'a, FOI: Into<FlagsOrIsa<'a>>>(&mut self, fisa: FOI) -> CodegenResult<()> {
+        do_reorder_ebbs(&mut self.func);
+        self.verify_if(fisa)
+    }
+
     /// Run the register allocator.
     pub fn regalloc(&mut self, isa: &TargetIsa) -> CodegenResult<()> {
         self.regalloc
@@ -323,11 +637,1516 @@ impl Context {
         Ok(())
     }
 
-    /// Run the branch relaxation pass and return the final code size.
-    pub fn relax_branches(&mut self, isa: &TargetIsa) -> CodegenResult<CodeOffset> {
-        let code_size = relax_branches(&mut self.func, isa)?;
+    /// Run the branch relaxation pass and return the sizes of the sections of the function.
+    pub fn relax_branches(&mut self, isa: &TargetIsa) -> CodegenResult<CodeInfo> {
+        let info = relax_branches(&mut self.func, isa)?;
+        // Branches may have been inverted, which moves the control flow edges to other
+        // instructions.
+        self.compute_cfg();
+        self.compute_domtree();
         self.verify_if(isa)?;
         self.verify_locations_if(isa)?;
-        Ok(code_size)
+        Ok(info)
+    }
+}
+
+/// Check the assigned locations of `args` against the `expected` locations of `call_conv`.
+///
+/// When the calling convention splits or adds arguments, they don't correspond one-to-one to the
+/// expected locations, and nothing is checked.
+fn check_locations(
+    kind: &str,
+    args: &[AbiParam],
+    expected: &[ArgumentLoc],
+    call_conv: CallConv,
+    isa: &TargetIsa,
+) -> CodegenResult<()> {
+    if args.len() != expected.len() {
+        return Ok(());
+    }
+    let regs = isa.register_info();
+    for (i, (arg, &loc)) in args.iter().zip(expected).enumerate() {
+        if arg.location.is_assigned() && arg.location != loc {
+            return Err(CodegenError::ArgumentLocation(format!(
+                "{} {} is in [{}], but {} uses [{}]",
+                kind,
+                i,
+                arg.location.display(&regs),
+                call_conv,
+                loc.display(&regs)
+            )));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+#[cfg(build_x86)]
+mod tests {
+    use super::Context;
+    use binemit::{
+        emit_address_map, AddrMapSink, CodeOffset, CollectedRelocs, CollectedTraps, NullRelocSink,
+        NullTrapSink, Reloc, RelocTarget,
+    };
+    use cursor::{Cursor, FuncCursor};
+    use ir::condcodes::IntCC;
+    use ir::immediates::{Ieee32, Ieee64};
+    use ir::{
+        types, AbiParam, ArgumentLoc, ArgumentPurpose, Cfa, Ebb, EbbOrderError, ExtFuncData,
+        ExternalName, Function, GlobalValueData, HeapData, HeapStyle, InstBuilder, JumpTableData,
+        LibCall, LibcallNames, MemFlags, Opcode, Signature, SourceLoc, StackSlotKind, TrapCode,
+        Value, ValueLoc,
+    };
+    use isa;
+    use result::CodegenError;
+    use settings::{self, CallConv, Configurable};
+    use stats::{opcode_histogram, CodegenStats};
+    use std::str::FromStr;
+    use std::string::ToString;
+    use std::vec::Vec;
+    use target_lexicon;
+
+    #[test]
+    fn code_info_with_jump_table() {
+        let isa = isa::lookup(triple!("x86_64"))
+            .unwrap()
+            .finish(settings::Flags::new(settings::builder()));
+
+        let mut func = Function::new();
+        func.signature.params.push(AbiParam::new(types::I32));
+        func.signature.returns.push(AbiParam::new(types::I32));
+        let ebb0 = func.dfg.make_ebb();
+        let ebb1 = func.dfg.make_ebb();
+        let ebb2 = func.dfg.make_ebb();
+        let ebb3 = func.dfg.make_ebb();
+        let mut jt_data = JumpTableData::new();
+        jt_data.push_entry(ebb1);
+        jt_data.push_entry(ebb2);
+        jt_data.push_entry(ebb1);
+        let jt = func.create_jump_table(jt_data);
+        {
+            let mut pos = FuncCursor::new(&mut func);
+            pos.insert_ebb(ebb0);
+            let arg = pos.func.dfg.append_ebb_param(ebb0, types::I32);
+            pos.ins().br_table(arg, ebb3, jt);
+            for (ebb, imm) in [(ebb3, 0), (ebb1, 1), (ebb2, 2)].iter().cloned() {
+                pos.insert_ebb(ebb);
+                let v = pos.ins().iconst(types::I32, imm);
+                pos.ins().return_(&[v]);
+            }
+        }
+
+        let mut ctx = Context::for_function(func);
+        let mut mem = Vec::new();
+        let info = ctx
+            .compile_and_emit(&*isa, &mut mem, &mut NullRelocSink {}, &mut NullTrapSink {})
+            .unwrap();
+
+        assert!(info.code_size > 0);
+        assert_eq!(info.jumptables_size, 3 * 4);
+        assert_eq!(info.rodata_size, 0);
+        assert_eq!(info.jumptables(), info.code_size);
+        assert_eq!(ctx.func.jt_offsets[jt], info.jumptables());
+        assert_eq!(info.rodata(), info.jumptables() + info.jumptables_size);
+        assert_eq!(
+            info.total_size,
+            info.code_size + info.jumptables_size + info.rodata_size
+        );
+        assert_eq!(mem.len(), info.total_size as usize);
+
+        // The jump table entries are emitted at the start of the jump table section, relative to
+        // the start of the table.
+        let jt_mem = &mem[info.jumptables() as usize..info.rodata() as usize];
+        for (i, &ebb) in [ebb1, ebb2, ebb1].iter().enumerate() {
+            let entry = jt_mem[i * 4..i * 4 + 4]
+                .iter()
+                .rev()
+                .fold(0, |word, &byte| (word << 8) | u32::from(byte));
+            let rel_offset = ctx.func.offsets[ebb] as i32 - info.jumptables() as i32;
+            assert_eq!(entry, rel_offset as u32);
+        }
+    }
+
+    #[test]
+    fn code_info_with_constant_pool() {
+        let isa = isa::lookup(triple!("x86_64"))
+            .unwrap()
+            .finish(settings::Flags::new(settings::builder()));
+
+        let mut func = Function::new();
+        func.signature.params.push(AbiParam::new(types::F32));
+        func.signature.params.push(AbiParam::new(types::F32));
+        func.signature.returns.push(AbiParam::new(types::F32));
+        let ebb0 = func.dfg.make_ebb();
+        {
+            let mut pos = FuncCursor::new(&mut func);
+            pos.insert_ebb(ebb0);
+            let x = pos.func.dfg.append_ebb_param(ebb0, types::F32);
+            let y = pos.func.dfg.append_ebb_param(ebb0, types::F32);
+            let v = pos.ins().fcopysign(x, y);
+            pos.ins().return_(&[v]);
+        }
+
+        let mut ctx = Context::for_function(func);
+        let mut mem = Vec::new();
+        let info = ctx
+            .compile_and_emit(&*isa, &mut mem, &mut NullRelocSink {}, &mut NullTrapSink {})
+            .unwrap();
+
+        // The sign mask is loaded from the constant pool, which follows the code. The constant is
+        // aligned, so there may be some padding before it.
+        assert_eq!(info.jumptables_size, 0);
+        assert_eq!(info.rodata(), info.code_size);
+        assert!(info.rodata_size >= 4 && info.rodata_size < 8);
+        assert_eq!(info.total_size % 4, 0);
+        assert_eq!(mem.len(), info.total_size as usize);
+        let start = (info.total_size - 4) as usize;
+        assert_eq!(&mem[start..], &[0x00, 0x00, 0x00, 0x80]);
+    }
+
+    #[test]
+    fn emit_with_separate_rodata() {
+        let isa = isa::lookup(triple!("x86_64"))
+            .unwrap()
+            .finish(settings::Flags::new(settings::builder()));
+
+        let mut func = Function::new();
+        func.signature.params.push(AbiParam::new(types::I32));
+        func.signature.returns.push(AbiParam::new(types::F64));
+        let ebb0 = func.dfg.make_ebb();
+        let ebb1 = func.dfg.make_ebb();
+        let ebb2 = func.dfg.make_ebb();
+        let mut jt_data = JumpTableData::new();
+        jt_data.push_entry(ebb1);
+        jt_data.push_entry(ebb2);
+        let jt = func.create_jump_table(jt_data);
+        {
+            let mut pos = FuncCursor::new(&mut func);
+            pos.insert_ebb(ebb0);
+            let arg = pos.func.dfg.append_ebb_param(ebb0, types::I32);
+            pos.ins().br_table(arg, ebb2, jt);
+            for (ebb, imm) in [(ebb1, 1.5), (ebb2, 2.5)].iter().cloned() {
+                pos.insert_ebb(ebb);
+                let v = pos.ins().f64const(Ieee64::with_float(imm));
+                pos.ins().return_(&[v]);
+            }
+        }
+
+        let mut ctx = Context::for_function(func);
+        let info = ctx.compile(&*isa).unwrap();
+        assert_eq!(info.jumptables_size, 2 * 4);
+        assert!(info.rodata_size >= 16);
+        assert_eq!(info.data_size(), info.jumptables_size + info.rodata_size);
+
+        // Put the data 64 bytes after the code, which keeps the constants aligned.
+        let gap = 64;
+        let mut mem = vec![0u8; (info.code_size + gap + info.data_size()) as usize];
+        unsafe {
+            let code = mem.as_mut_ptr();
+            let rodata = code.offset((info.code_size + gap) as isize);
+            ctx.emit_to_memory_with_rodata(
+                &*isa,
+                code,
+                rodata,
+                &info,
+                &mut NullRelocSink {},
+                &mut NullTrapSink {},
+            )
+        }
+        .unwrap();
+
+        // Nothing is written between the code and the data.
+        let data_start = (info.code_size + gap) as usize;
+        assert!(mem[info.code_size as usize..data_start]
+            .iter()
+            .all(|&byte| byte == 0));
+
+        // The jump table entries are relative to the moved table.
+        let jt_start = data_start + (ctx.func.jt_offsets[jt] - info.code_size) as usize;
+        for (i, &ebb) in [ebb1, ebb2].iter().enumerate() {
+            let entry = mem[jt_start + i * 4..jt_start + i * 4 + 4]
+                .iter()
+                .rev()
+                .fold(0, |word, &byte| (word << 8) | u32::from(byte));
+            let rel_offset = ctx.func.offsets[ebb] as i32 - jt_start as i32;
+            assert_eq!(entry, rel_offset as u32);
+        }
+
+        // Both constants are in the data.
+        let rodata = &mem[data_start + info.jumptables_size as usize..];
+        for imm in [1.5f64, 2.5].iter() {
+            let bits = imm.to_bits().to_le_bytes();
+            assert!(rodata.windows(8).any(|window| window == bits));
+        }
+    }
+
+    #[test]
+    fn stats_with_spills() {
+        let isa = isa::lookup(triple!("x86_64"))
+            .unwrap()
+            .finish(settings::Flags::new(settings::builder()));
+
+        // Load more values than there are registers, and keep them all live until they are added.
+        let mut func = Function::new();
+        func.signature.params.push(AbiParam::new(types::I64));
+        func.signature.returns.push(AbiParam::new(types::I64));
+        let ebb0 = func.dfg.make_ebb();
+        {
+            let mut pos = FuncCursor::new(&mut func);
+            pos.insert_ebb(ebb0);
+            let addr = pos.func.dfg.append_ebb_param(ebb0, types::I64);
+            let values: Vec<_> = (0..24)
+                .map(|i| pos.ins().load(types::I64, MemFlags::new(), addr, i * 8))
+                .collect();
+            let sum = values[1..]
+                .iter()
+                .fold(values[0], |sum, &v| pos.ins().iadd(sum, v));
+            pos.ins().return_(&[sum]);
+        }
+
+        let mut ctx = Context::for_function(func);
+        let info = ctx.compile(&*isa).unwrap();
+        let stats = info.stats;
+
+        let spilled = ctx
+            .func
+            .dfg
+            .values()
+            .filter(|&v| match ctx.func.locations[v] {
+                ValueLoc::Stack(ss) => ctx.func.stack_slots[ss].kind == StackSlotKind::SpillSlot,
+                _ => false,
+            })
+            .count();
+        let fills = opcode_histogram(&ctx.func)
+            .into_iter()
+            .find(|&(opcode, _)| opcode == Opcode::Fill)
+            .map_or(0, |(_, count)| count);
+
+        assert!(stats.spills > 0);
+        assert_eq!(stats.spills as usize, spilled);
+        assert_eq!(stats.reloads as usize, fills);
+        assert_eq!(stats.branches, 0);
+        assert!(stats.insts >= 24 + 23);
+        assert_eq!(stats.code_size, info.total_size);
+    }
+
+    /// Build a function that computes values before a call, and only uses them after it.
+    fn values_across_call() -> Function {
+        let mut func = Function::new();
+        func.signature.params.push(AbiParam::new(types::I64));
+        func.signature.returns.push(AbiParam::new(types::I64));
+        let signature = func.import_signature(Signature::new(CallConv::SystemV));
+        let callee = func.import_function(ExtFuncData {
+            name: ExternalName::testcase("g"),
+            signature,
+            colocated: false,
+        });
+        let ebb0 = func.dfg.make_ebb();
+        {
+            let mut pos = FuncCursor::new(&mut func);
+            pos.insert_ebb(ebb0);
+            let arg = pos.func.dfg.append_ebb_param(ebb0, types::I64);
+            let values: Vec<_> = (0..16).map(|i| pos.ins().iadd_imm(arg, i + 1)).collect();
+            pos.ins().call(callee, &[]);
+            let sum = values.iter().fold(arg, |sum, &v| pos.ins().iadd(sum, v));
+            pos.ins().return_(&[sum]);
+        }
+        func
+    }
+
+    /// Compile `values_across_call` with `opt_level`.
+    fn compile_values_across_call(opt_level: &str) -> CodegenStats {
+        let mut flag_builder = settings::builder();
+        flag_builder.set("opt_level", opt_level).unwrap();
+        let isa = isa::lookup(triple!("x86_64"))
+            .unwrap()
+            .finish(settings::Flags::new(flag_builder));
+
+        let mut ctx = Context::for_function(values_across_call());
+        ctx.compile(&*isa).unwrap().stats
+    }
+
+    #[test]
+    fn sinking_reduces_spills() {
+        // Values that are live across a call are spilled. Without sinking, that's all the values
+        // and the argument. With sinking, only the argument is needed after the call.
+        assert_eq!(compile_values_across_call("default").spills, 17);
+        assert_eq!(compile_values_across_call("best").spills, 1);
+    }
+
+    #[test]
+    fn spill_limit() {
+        let isa = isa::lookup(triple!("x86_64"))
+            .unwrap()
+            .finish(settings::Flags::new(settings::builder()));
+
+        let mut ctx = Context::for_function(values_across_call());
+        ctx.limits.max_spills = Some(17);
+        assert!(ctx.compile(&*isa).is_ok());
+
+        ctx.func = values_across_call();
+        ctx.limits.max_spills = Some(16);
+        assert_eq!(
+            ctx.compile(&*isa),
+            Err(CodegenError::ImplLimitExceeded(
+                "max_spills is 16, got 17".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn ebb_limit() {
+        let isa = isa::lookup(triple!("x86_64"))
+            .unwrap()
+            .finish(settings::Flags::new(settings::builder()));
+
+        let mut ctx = Context::for_function(diamond().0);
+        ctx.limits.max_ebbs = Some(3);
+        assert_eq!(
+            ctx.compile(&*isa),
+            Err(CodegenError::ImplLimitExceeded(
+                "max_ebbs is 3, got 4".to_string()
+            ))
+        );
+    }
+
+    /// Compile a leaf function that needs two registers besides its arguments.
+    fn compile_leaf(callee_saved_strategy: &str) -> CodegenStats {
+        let mut flag_builder = settings::builder();
+        flag_builder
+            .set("callee_saved_strategy", callee_saved_strategy)
+            .unwrap();
+        let isa = isa::lookup(triple!("x86_64"))
+            .unwrap()
+            .finish(settings::Flags::new(flag_builder));
+
+        let mut func = Function::new();
+        for _ in 0..4 {
+            func.signature.params.push(AbiParam::new(types::I64));
+        }
+        func.signature.returns.push(AbiParam::new(types::I64));
+        let ebb0 = func.dfg.make_ebb();
+        {
+            let mut pos = FuncCursor::new(&mut func);
+            pos.insert_ebb(ebb0);
+            let args: Vec<_> = (0..4)
+                .map(|_| pos.func.dfg.append_ebb_param(ebb0, types::I64))
+                .collect();
+            let a = pos.ins().iadd(args[0], args[1]);
+            let b = pos.ins().iadd(args[2], args[3]);
+            let product = pos.ins().imul(a, b);
+            let sum = args.iter().fold(product, |sum, &v| pos.ins().iadd(sum, v));
+            pos.ins().return_(&[sum]);
+        }
+
+        let mut ctx = Context::for_function(func);
+        ctx.compile(&*isa).unwrap().stats
+    }
+
+    #[test]
+    fn callee_saved_strategy() {
+        // The second temporary gets `%rbx` in the default register order.
+        assert_eq!(compile_leaf("auto").saved_regs, 1);
+        assert_eq!(compile_leaf("minimize").saved_regs, 0);
+        assert_eq!(compile_leaf("prefer").saved_regs, 0);
+    }
+
+    #[test]
+    fn loop_params_without_moves() {
+        let isa = isa::lookup(triple!("x86_64"))
+            .unwrap()
+            .finish(settings::Flags::new(settings::builder()));
+
+        // A loop that carries six values in EBB parameters. The back-edge passes four of them
+        // back unchanged.
+        let param_types = [
+            types::I64,
+            types::I32,
+            types::I32,
+            types::I32,
+            types::I32,
+            types::I32,
+        ];
+        let mut func = Function::new();
+        for &ty in &param_types[..5] {
+            func.signature.params.push(AbiParam::new(ty));
+        }
+        func.signature.returns.push(AbiParam::new(types::I32));
+        let ebb0 = func.dfg.make_ebb();
+        let ebb1 = func.dfg.make_ebb();
+        {
+            let mut pos = FuncCursor::new(&mut func);
+            pos.insert_ebb(ebb0);
+            let mut args: Vec<_> = param_types[..5]
+                .iter()
+                .map(|&ty| pos.func.dfg.append_ebb_param(ebb0, ty))
+                .collect();
+            args.push(pos.ins().iconst(types::I32, 0));
+            pos.ins().jump(ebb1, &args);
+
+            pos.insert_ebb(ebb1);
+            let params: Vec<_> = param_types
+                .iter()
+                .map(|&ty| pos.func.dfg.append_ebb_param(ebb1, ty))
+                .collect();
+            let (base, count, scale, bias) = (params[0], params[1], params[2], params[3]);
+            let v = pos.ins().load(types::I32, MemFlags::new(), base, 0);
+            let v = pos.ins().imul(v, scale);
+            let sum = pos.ins().iadd(params[4], v);
+            let i = pos.ins().iadd_imm(params[5], 1);
+            let cond = pos.ins().icmp(IntCC::UnsignedLessThan, i, count);
+            pos.ins()
+                .brnz(cond, ebb1, &[base, count, scale, bias, sum, i]);
+            let result = pos.ins().iadd(sum, bias);
+            pos.ins().return_(&[result]);
+        }
+
+        let mut ctx = Context::for_function(func);
+        let stats = ctx.compile(&*isa).unwrap().stats;
+
+        // The parameters don't need any copies, and the only move is for the return value.
+        assert_eq!(stats.copies, 0);
+        assert_eq!(stats.moves, 1);
+    }
+
+    /// Compile heap accesses that are all marked `notrap`, and get the `heap_oob` trap sites and
+    /// the code size.
+    fn compile_heap_accesses(trap_tables: bool) -> (Vec<(CodeOffset, SourceLoc)>, u32) {
+        let mut flag_builder = settings::builder();
+        if trap_tables {
+            flag_builder
+                .enable("emit_trap_tables_for_guarded_heaps")
+                .unwrap();
+        }
+        let isa = isa::lookup(triple!("x86_64"))
+            .unwrap()
+            .finish(settings::Flags::new(flag_builder));
+
+        let mut func = Function::new();
+        func.signature.params.push(AbiParam::new(types::I32));
+        func.signature
+            .params
+            .push(AbiParam::special(types::I64, ArgumentPurpose::VMContext));
+        let vmctx = func.create_global_value(GlobalValueData::VMContext);
+        let base = func.create_global_value(GlobalValueData::IAddImm {
+            base: vmctx,
+            offset: 64.into(),
+            global_type: types::I64,
+        });
+        // The bounds checks of a 32-bit offset are elided for a static heap with an 8 GB bound.
+        let heap = func.create_heap(HeapData {
+            base,
+            min_size: 0x1_0000.into(),
+            guard_size: 0x8000_0000.into(),
+            style: HeapStyle::Static {
+                bound: 0x2_0000_0000.into(),
+            },
+            index_type: types::I32,
+            check_alignment: false,
+        });
+        let ebb0 = func.dfg.make_ebb();
+        {
+            let mut pos = FuncCursor::new(&mut func);
+            pos.insert_ebb(ebb0);
+            let offset = pos.func.dfg.append_ebb_param(ebb0, types::I32);
+            let ctx = pos.func.dfg.append_ebb_param(ebb0, types::I64);
+            let mut notrap = MemFlags::new();
+            notrap.set_notrap();
+
+            // Only the first byte is known to be in bounds, so these rely on the guard pages.
+            pos.set_srcloc(SourceLoc::new(1));
+            let addr = pos.ins().heap_addr(types::I64, heap, offset, 1);
+            pos.set_srcloc(SourceLoc::new(2));
+            let v = pos.ins().load(types::I32, notrap, addr, 0);
+            pos.set_srcloc(SourceLoc::new(3));
+            pos.ins().store(notrap, v, addr, 0x1000);
+
+            // A load from the vmctx, and a heap access within the checked bytes.
+            pos.set_srcloc(SourceLoc::new(4));
+            let v = pos.ins().load(types::I32, notrap, ctx, 0);
+            pos.set_srcloc(SourceLoc::new(5));
+            let addr = pos.ins().heap_addr(types::I64, heap, offset, 8);
+            pos.set_srcloc(SourceLoc::new(6));
+            pos.ins().store(notrap, v, addr, 4);
+            pos.ins().return_(&[]);
+        }
+
+        let mut ctx = Context::for_function(func);
+        let mut mem = Vec::new();
+        let mut traps = CollectedTraps::new();
+        let info = ctx
+            .compile_and_emit(&*isa, &mut mem, &mut NullRelocSink {}, &mut traps)
+            .unwrap();
+        let sites = traps
+            .sites
+            .into_iter()
+            .filter(|site| site.code == TrapCode::HeapOutOfBounds)
+            .map(|site| (site.offset, site.srcloc))
+            .collect();
+        (sites, info.code_size)
+    }
+
+    #[test]
+    fn trap_tables_for_guarded_heaps() {
+        assert_eq!(compile_heap_accesses(false).0, []);
+
+        // Only the accesses beyond the checked bytes are listed, and they are within the code.
+        let (sites, code_size) = compile_heap_accesses(true);
+        let srclocs: Vec<_> = sites.iter().map(|&(_, srcloc)| srcloc).collect();
+        assert_eq!(srclocs, [SourceLoc::new(2), SourceLoc::new(3)]);
+        assert!(sites[0].0 < sites[1].0);
+        assert!(sites[1].0 < code_size);
+    }
+
+    #[test]
+    fn trap_site_per_bounds_check() {
+        let isa = isa::lookup(triple!("x86_64"))
+            .unwrap()
+            .finish(settings::Flags::new(settings::builder()));
+
+        let mut func = Function::new();
+        func.signature.params.push(AbiParam::new(types::I32));
+        func.signature.params.push(AbiParam::new(types::I32));
+        func.signature
+            .params
+            .push(AbiParam::special(types::I64, ArgumentPurpose::VMContext));
+        let vmctx = func.create_global_value(GlobalValueData::VMContext);
+        let bound_gv = func.create_global_value(GlobalValueData::Load {
+            base: vmctx,
+            offset: 8.into(),
+            global_type: types::I32,
+            readonly: false,
+        });
+        let heap = func.create_heap(HeapData {
+            base: vmctx,
+            min_size: 0x1_0000.into(),
+            guard_size: 0.into(),
+            style: HeapStyle::Dynamic { bound_gv },
+            index_type: types::I32,
+            check_alignment: false,
+        });
+        let ebb0 = func.dfg.make_ebb();
+        {
+            let mut pos = FuncCursor::new(&mut func);
+            pos.insert_ebb(ebb0);
+            let x = pos.func.dfg.append_ebb_param(ebb0, types::I32);
+            let y = pos.func.dfg.append_ebb_param(ebb0, types::I32);
+            pos.func.dfg.append_ebb_param(ebb0, types::I64);
+            let mut notrap = MemFlags::new();
+            notrap.set_notrap();
+            for (i, &offset) in [x, y].iter().enumerate() {
+                pos.set_srcloc(SourceLoc::new(10 * (i as u32 + 1)));
+                let addr = pos.ins().heap_addr(types::I64, heap, offset, 4);
+                pos.ins().store(notrap, offset, addr, 0);
+            }
+            pos.ins().return_(&[]);
+        }
+
+        // Each bounds check traps at a site of its own, with the source location of its heap
+        // access, so an out-of-bounds access can be attributed to the access that made it.
+        let mut ctx = Context::for_function(func);
+        let mut mem = Vec::new();
+        let mut traps = CollectedTraps::new();
+        ctx.compile_and_emit(&*isa, &mut mem, &mut NullRelocSink {}, &mut traps)
+            .unwrap();
+        let checks: Vec<_> = traps
+            .sites
+            .iter()
+            .filter(|site| site.code == TrapCode::HeapOutOfBounds)
+            .map(|site| (site.offset, site.srcloc))
+            .collect();
+        assert_eq!(checks.len(), 2);
+        assert_ne!(checks[0].0, checks[1].0);
+        assert_eq!(checks[0].1, SourceLoc::new(10));
+        assert_eq!(checks[1].1, SourceLoc::new(20));
+    }
+
+    /// Compile a function that keeps more values live than there are registers, and passes them
+    /// to a call that takes `call_args` arguments if that isn't zero.
+    fn compile_frame(call_conv: CallConv, call_args: usize) -> (Function, isa::RegInfo) {
+        let mut flag_builder = settings::builder();
+        flag_builder.set("baldrdash_prologue_words", "3").unwrap();
+        flag_builder.enable("probestack_enabled").unwrap();
+        if call_conv == CallConv::Baldrdash {
+            flag_builder.set("probestack_enabled", "false").unwrap();
+        }
+        let isa = isa::lookup(triple!("x86_64"))
+            .unwrap()
+            .finish(settings::Flags::new(flag_builder));
+
+        let mut func = Function::new();
+        func.signature.call_conv = call_conv;
+        func.signature.params.push(AbiParam::new(types::I64));
+        func.signature.returns.push(AbiParam::new(types::I64));
+        let mut sig = Signature::new(call_conv);
+        for _ in 0..call_args {
+            sig.params.push(AbiParam::new(types::I64));
+        }
+        let signature = func.import_signature(sig);
+        let callee = func.import_function(ExtFuncData {
+            name: ExternalName::testcase("g"),
+            signature,
+            colocated: false,
+        });
+        let ebb0 = func.dfg.make_ebb();
+        {
+            let mut pos = FuncCursor::new(&mut func);
+            pos.insert_ebb(ebb0);
+            let addr = pos.func.dfg.append_ebb_param(ebb0, types::I64);
+            let values: Vec<_> = (0..24)
+                .map(|i| pos.ins().load(types::I64, MemFlags::new(), addr, i * 8))
+                .collect();
+            if call_args > 0 {
+                let args: Vec<_> = (0..call_args).map(|i| values[i % 24]).collect();
+                pos.ins().call(callee, &args);
+            }
+            let sum = values[1..]
+                .iter()
+                .fold(values[0], |sum, &v| pos.ins().iadd(sum, v));
+            pos.ins().return_(&[sum]);
+        }
+
+        let mut ctx = Context::for_function(func);
+        ctx.compile(&*isa).unwrap();
+        (ctx.func, isa.register_info())
+    }
+
+    #[test]
+    fn frame_layout_with_frame_pointer() {
+        let (func, regs) = compile_frame(CallConv::SystemV, 0);
+        let layout = func.frame_layout.as_ref().unwrap();
+        let rbp = regs.parse_regunit("rbp").unwrap();
+
+        assert_eq!(Some(layout.frame_size), func.stack_slots.frame_size);
+        assert_eq!(
+            layout.cfa,
+            Cfa::FramePointer {
+                reg: rbp,
+                offset: 16
+            }
+        );
+        assert_eq!(layout.saved_offset(rbp), Some(-16));
+
+        // The callee-saved registers are pushed below the frame pointer, and the spill slots are
+        // below them.
+        let saved_end = -8 * (layout.saved_registers.len() as i32 + 1);
+        for (i, &(_, offset)) in layout.saved_registers.iter().enumerate() {
+            assert_eq!(offset, -8 * (i as i32 + 2));
+        }
+        assert!(layout.saved_registers.len() > 1);
+        for (ss, slot) in func.stack_slots.iter() {
+            if slot.kind == StackSlotKind::SpillSlot {
+                let offset = layout.slot_offset(ss).unwrap();
+                assert_eq!(Some(offset), slot.offset);
+                assert!(offset + slot.size as i32 <= saved_end);
+                assert!(offset >= -(layout.frame_size as i32));
+            }
+        }
+    }
+
+    #[test]
+    fn frame_layout_without_frame_pointer() {
+        let (func, _) = compile_frame(CallConv::Baldrdash, 0);
+        let layout = func.frame_layout.as_ref().unwrap();
+
+        // The SpiderMonkey prologue sets up the frame, so only the stack pointer is known.
+        assert_eq!(layout.cfa, Cfa::StackPointer);
+        assert!(layout.saved_registers.is_empty());
+        assert_eq!(layout.frame_size % 16, 0);
+        for (ss, slot) in func.stack_slots.iter() {
+            let offset = layout.slot_offset(ss).unwrap();
+            assert_eq!(Some(offset), slot.offset);
+            if slot.kind == StackSlotKind::IncomingArg {
+                assert_eq!(offset, -24);
+            } else {
+                assert!(offset >= -(layout.frame_size as i32));
+            }
+        }
+    }
+
+    #[test]
+    fn frame_layout_with_outgoing_args() {
+        // Six of the arguments are passed in registers, and the rest on the stack.
+        let (func, _) = compile_frame(CallConv::SystemV, 40);
+        let layout = func.frame_layout.as_ref().unwrap();
+        let frame_size = layout.frame_size as i32;
+
+        let mut outgoing = 0;
+        for (ss, slot) in func.stack_slots.iter() {
+            let offset = layout.slot_offset(ss).unwrap();
+            if slot.kind == StackSlotKind::OutgoingArg {
+                outgoing += 1;
+                assert_eq!(offset, slot.offset.unwrap() - frame_size);
+            } else {
+                assert_eq!(Some(offset), slot.offset);
+            }
+            assert!(offset >= -frame_size);
+        }
+        assert_eq!(outgoing, 34);
+        assert!(frame_size >= 34 * 8 + 16);
+    }
+
+    /// Build a diamond that branches to `ebb1` when the parameter is zero, and `ebb2` otherwise.
+    fn diamond() -> (Function, [Ebb; 4]) {
+        let mut func = Function::new();
+        func.signature.params.push(AbiParam::new(types::I32));
+        func.signature.returns.push(AbiParam::new(types::I32));
+        let ebbs = [
+            func.dfg.make_ebb(),
+            func.dfg.make_ebb(),
+            func.dfg.make_ebb(),
+            func.dfg.make_ebb(),
+        ];
+        {
+            let mut pos = FuncCursor::new(&mut func);
+            pos.insert_ebb(ebbs[0]);
+            let arg = pos.func.dfg.append_ebb_param(ebbs[0], types::I32);
+            pos.ins().brz(arg, ebbs[1], &[]);
+            pos.ins().jump(ebbs[2], &[]);
+            for &(ebb, imm) in &[(ebbs[1], 10), (ebbs[2], 20)] {
+                pos.insert_ebb(ebb);
+                let v = pos.ins().iconst(types::I32, imm);
+                pos.ins().jump(ebbs[3], &[v]);
+            }
+            pos.insert_ebb(ebbs[3]);
+            let v = pos.func.dfg.append_ebb_param(ebbs[3], types::I32);
+            pos.ins().return_(&[v]);
+        }
+        (func, ebbs)
+    }
+
+    /// Get the opcodes of the branches at the end of `ebb`.
+    fn branch_opcodes(func: &Function, ebb: Ebb) -> Vec<Opcode> {
+        func.layout
+            .ebb_insts(ebb)
+            .map(|inst| func.dfg[inst].opcode())
+            .filter(|opcode| opcode.is_branch())
+            .collect()
+    }
+
+    #[test]
+    fn cold_ebb_moved_last() {
+        let isa = isa::lookup(triple!("x86_64"))
+            .unwrap()
+            .finish(settings::Flags::new(settings::builder()));
+
+        // The zero side follows the branch, so the branch is inverted to fall through into it.
+        let (func, ebbs) = diamond();
+        let mut ctx = Context::for_function(func);
+        ctx.compile(&*isa).unwrap();
+        let order: Vec<Ebb> = ctx.func.layout.ebbs().collect();
+        assert_eq!(order, ebbs);
+        assert_eq!(
+            branch_opcodes(&ctx.func, ebbs[0]),
+            [Opcode::Brnz, Opcode::Fallthrough]
+        );
+        assert_eq!(branch_opcodes(&ctx.func, ebbs[1]), [Opcode::Jump]);
+        assert_eq!(branch_opcodes(&ctx.func, ebbs[2]), [Opcode::Fallthrough]);
+
+        // When the zero side is unlikely, it goes last, and the branch goes the other way.
+        let (mut func, ebbs) = diamond();
+        func.ebb_frequencies[ebbs[1]] = Some(0);
+        let mut ctx = Context::for_function(func);
+        ctx.compile(&*isa).unwrap();
+        let order: Vec<Ebb> = ctx.func.layout.ebbs().collect();
+        assert_eq!(order, [ebbs[0], ebbs[2], ebbs[3], ebbs[1]]);
+        assert_eq!(
+            branch_opcodes(&ctx.func, ebbs[0]),
+            [Opcode::Brz, Opcode::Fallthrough]
+        );
+        assert_eq!(branch_opcodes(&ctx.func, ebbs[1]), [Opcode::Jump]);
+        assert_eq!(branch_opcodes(&ctx.func, ebbs[2]), [Opcode::Fallthrough]);
+
+        // Reordering again moves the fall-throughs.
+        let order = [ebbs[0], ebbs[1], ebbs[3], ebbs[2]];
+        ctx.func.set_ebb_order(&order).unwrap();
+        let info = ctx.relax_branches(&*isa).unwrap();
+        assert_eq!(
+            branch_opcodes(&ctx.func, ebbs[0]),
+            [Opcode::Brnz, Opcode::Fallthrough]
+        );
+        assert_eq!(branch_opcodes(&ctx.func, ebbs[1]), [Opcode::Fallthrough]);
+        assert_eq!(branch_opcodes(&ctx.func, ebbs[2]), [Opcode::Jump]);
+        let mut mem = vec![0; info.total_size as usize];
+        unsafe {
+            ctx.emit_to_memory(
+                &*isa,
+                mem.as_mut_ptr(),
+                &mut NullRelocSink {},
+                &mut NullTrapSink {},
+            )
+        }
+        .unwrap();
+    }
+
+    struct AddrMapRecorder {
+        ebbs: Vec<(Ebb, CodeOffset)>,
+        insts: Vec<(CodeOffset, CodeOffset, SourceLoc)>,
+    }
+
+    impl AddrMapSink for AddrMapRecorder {
+        fn ebb(&mut self, offset: CodeOffset, ebb: Ebb) {
+            self.ebbs.push((ebb, offset));
+        }
+        fn inst(&mut self, offset: CodeOffset, size: CodeOffset, srcloc: SourceLoc) {
+            self.insts.push((offset, size, srcloc));
+        }
+    }
+
+    #[test]
+    fn ebb_offsets() {
+        let isa = isa::lookup(triple!("x86_64"))
+            .unwrap()
+            .finish(settings::Flags::new(settings::builder()));
+
+        let (mut func, ebbs) = diamond();
+        for (i, &ebb) in ebbs.iter().enumerate() {
+            let insts: Vec<_> = func.layout.ebb_insts(ebb).collect();
+            for inst in insts {
+                func.srclocs[inst] = SourceLoc::new(i as u32 + 1);
+            }
+        }
+        let mut ctx = Context::for_function(func);
+        let mut mem = Vec::new();
+        let info = ctx
+            .compile_and_emit(&*isa, &mut mem, &mut NullRelocSink {}, &mut NullTrapSink {})
+            .unwrap();
+
+        // Every EBB has some code, so the offsets are strictly increasing.
+        let offsets: Vec<(Ebb, CodeOffset)> = ctx.func.ebb_offsets().collect();
+        assert_eq!(offsets.len(), 4);
+        assert_eq!(offsets[0].1, 0);
+        for pair in offsets.windows(2) {
+            assert!(pair[0].1 < pair[1].1);
+        }
+        assert!(offsets[3].1 < info.code_size);
+
+        // The displacement of every branch leads to the offset of its destination. Short branches
+        // end in an 8-bit displacement, and long branches in a 32-bit one.
+        let encinfo = isa.encoding_info();
+        let mut branches = 0;
+        for &(ebb, _) in &offsets {
+            for (offset, inst, size) in ctx.func.inst_offsets(ebb, &encinfo) {
+                let dest = match ctx.func.dfg[inst].branch_destination() {
+                    Some(dest) if size > 0 => dest,
+                    _ => continue,
+                };
+                let end = (offset + size) as usize;
+                let recipe = encinfo.display(ctx.func.encodings[inst]).to_string();
+                let disp = if recipe.contains("b#") {
+                    i64::from(mem[end - 1] as i8)
+                } else {
+                    let word = mem[end - 4..end]
+                        .iter()
+                        .rev()
+                        .fold(0, |word, &byte| (word << 8) | u32::from(byte));
+                    i64::from(word as i32)
+                };
+                assert_eq!(end as i64 + disp, i64::from(ctx.func.offsets[dest]));
+                branches += 1;
+            }
+        }
+        assert_eq!(branches, 2);
+
+        // The address map marks the start of each EBB before its instructions, and the
+        // instructions cover all of the code.
+        let mut sink = AddrMapRecorder {
+            ebbs: Vec::new(),
+            insts: Vec::new(),
+        };
+        emit_address_map(&ctx.func, &*isa, &mut sink);
+        assert_eq!(sink.ebbs, offsets);
+        let mut next = 0;
+        for &(offset, size, _) in &sink.insts {
+            assert_eq!(offset, next);
+            next += size;
+        }
+        assert_eq!(next, info.code_size);
+
+        // Each EBB has code from its own source location, but the register allocator and the
+        // prologue and epilogue add instructions without one.
+        for (i, &(ebb, start)) in offsets.iter().enumerate() {
+            let end = offsets
+                .get(i + 1)
+                .map_or(info.code_size, |&(_, offset)| offset);
+            let srcloc = SourceLoc::new(ebbs.iter().position(|&e| e == ebb).unwrap() as u32 + 1);
+            let srclocs: Vec<SourceLoc> = sink
+                .insts
+                .iter()
+                .filter(|&&(offset, _, _)| start <= offset && offset < end)
+                .map(|&(_, _, srcloc)| srcloc)
+                .collect();
+            assert!(srclocs.contains(&srcloc));
+            assert!(srclocs.iter().all(|&s| s == srcloc || s.is_default()));
+        }
+    }
+
+    #[test]
+    fn branch_out_of_range() {
+        let isa = isa::lookup(triple!("x86_64"))
+            .unwrap()
+            .finish(settings::Flags::new(settings::builder()));
+
+        // The branch to `ebb2` skips over more than 128 bytes of code in `ebb1`.
+        let mut func = Function::new();
+        func.signature.params.push(AbiParam::new(types::I32));
+        func.signature.returns.push(AbiParam::new(types::I32));
+        let ebb0 = func.dfg.make_ebb();
+        let ebb1 = func.dfg.make_ebb();
+        let ebb2 = func.dfg.make_ebb();
+        {
+            let mut pos = FuncCursor::new(&mut func);
+            pos.insert_ebb(ebb0);
+            let v0 = pos.func.dfg.append_ebb_param(ebb0, types::I32);
+            pos.ins().brz(v0, ebb2, &[]);
+            pos.ins().jump(ebb1, &[]);
+            pos.insert_ebb(ebb1);
+            let mut v = v0;
+            for _ in 0..40 {
+                v = pos.ins().imul_imm(v, 0x1234);
+            }
+            pos.ins().return_(&[v]);
+            pos.insert_ebb(ebb2);
+            pos.ins().return_(&[v0]);
+        }
+        let mut ctx = Context::for_function(func);
+        let info = ctx.compile(&*isa).unwrap();
+
+        // Switch the long branch to the short encoding without relaxing branches again.
+        let encinfo = isa.encoding_info();
+        let is_short = |enc| {
+            encinfo
+                .branch_range(enc)
+                .map_or(false, |range| range.bits == 8)
+        };
+        let inst = ctx
+            .func
+            .layout
+            .ebb_insts(ebb0)
+            .find(|&inst| ctx.func.dfg[inst].branch_destination() == Some(ebb2))
+            .unwrap();
+        let data = ctx.func.dfg[inst].clone();
+        let ctrl_type = ctx.func.dfg.ctrl_typevar(inst);
+        let short = isa
+            .legal_encodings(&ctx.func, &data, ctrl_type)
+            .find(|&enc| is_short(enc))
+            .unwrap();
+        assert_ne!(ctx.func.encodings[inst], short);
+        ctx.func.encodings[inst] = short;
+
+        let mut mem = vec![0; info.total_size as usize];
+        let result = unsafe {
+            ctx.emit_to_memory(
+                &*isa,
+                mem.as_mut_ptr(),
+                &mut NullRelocSink {},
+                &mut NullTrapSink {},
+            )
+        };
+        assert_eq!(
+            result,
+            Err(CodegenError::OffsetOutOfRange(
+                "brz.i32 v0, ebb2 to ebb2 (displacement 409)".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn ebb_order_errors() {
+        let (mut func, ebbs) = diamond();
+        let unused = func.dfg.make_ebb();
+        assert_eq!(
+            func.set_ebb_order(&[ebbs[0], ebbs[1], ebbs[2]]),
+            Err(EbbOrderError::Missing(ebbs[3]))
+        );
+        assert_eq!(
+            func.set_ebb_order(&[ebbs[0], ebbs[1], ebbs[1], ebbs[2], ebbs[3]]),
+            Err(EbbOrderError::Duplicate(ebbs[1]))
+        );
+        assert_eq!(
+            func.set_ebb_order(&[ebbs[0], unused, ebbs[1], ebbs[2], ebbs[3]]),
+            Err(EbbOrderError::NotInserted(unused))
+        );
+        assert_eq!(
+            func.set_ebb_order(&[ebbs[1], ebbs[0], ebbs[2], ebbs[3]]),
+            Err(EbbOrderError::EntryMoved(ebbs[1]))
+        );
+        let order: Vec<Ebb> = func.layout.ebbs().collect();
+        assert_eq!(order, ebbs);
+    }
+
+    #[test]
+    fn compact_after_compile() {
+        let mut flag_builder = settings::builder();
+        flag_builder.set("opt_level", "best").unwrap();
+        let isa = isa::lookup(triple!("x86_64"))
+            .unwrap()
+            .finish(settings::Flags::new(flag_builder));
+
+        // A loop with redundant instructions for the optimizer to remove.
+        let mut func = Function::new();
+        func.signature.params.push(AbiParam::new(types::I64));
+        func.signature.params.push(AbiParam::new(types::I32));
+        func.signature.returns.push(AbiParam::new(types::I64));
+        let ebb0 = func.dfg.make_ebb();
+        let ebb1 = func.dfg.make_ebb();
+        let ebb2 = func.dfg.make_ebb();
+        {
+            let mut pos = FuncCursor::new(&mut func);
+            pos.insert_ebb(ebb0);
+            let addr = pos.func.dfg.append_ebb_param(ebb0, types::I64);
+            let count = pos.func.dfg.append_ebb_param(ebb0, types::I32);
+            let zero = pos.ins().iconst(types::I64, 0);
+            pos.ins().jump(ebb1, &[addr, count, zero]);
+
+            pos.insert_ebb(ebb1);
+            let ptr = pos.func.dfg.append_ebb_param(ebb1, types::I64);
+            let n = pos.func.dfg.append_ebb_param(ebb1, types::I32);
+            let sum = pos.func.dfg.append_ebb_param(ebb1, types::I64);
+            let x = pos.ins().load(types::I64, MemFlags::new(), ptr, 0);
+            let y = pos.ins().iadd(sum, x);
+            let z = pos.ins().iadd(sum, x);
+            let total = pos.ins().iadd(y, z);
+            let stride = pos.ins().iconst(types::I64, 8);
+            let next = pos.ins().iadd(ptr, stride);
+            let n1 = pos.ins().iadd_imm(n, -1);
+            pos.ins().brnz(n1, ebb1, &[next, n1, total]);
+            pos.ins().jump(ebb2, &[]);
+
+            pos.insert_ebb(ebb2);
+            pos.ins().return_(&[total]);
+        }
+
+        let mut ctx = Context::for_function(func);
+        let info = ctx.compile(&*isa).unwrap();
+        let emit = |ctx: &Context| {
+            let mut mem = vec![0; info.total_size as usize];
+            unsafe {
+                ctx.emit_to_memory(
+                    &*isa,
+                    mem.as_mut_ptr(),
+                    &mut NullRelocSink {},
+                    &mut NullTrapSink {},
+                )
+            }
+            .unwrap();
+            mem
+        };
+        let code = emit(&ctx);
+        let num_insts = ctx.func.dfg.num_insts();
+        let mut before = Vec::new();
+        ctx.func.write_binary(&mut before);
+
+        ctx.func.compact();
+        ctx.flowgraph();
+        ctx.verify(&*isa).unwrap();
+        ctx.verify_locations(&*isa).unwrap();
+        assert!(ctx.func.dfg.num_insts() < num_insts);
+        let mut after = Vec::new();
+        ctx.func.write_binary(&mut after);
+        assert!(after.len() < before.len());
+        assert_eq!(emit(&ctx), code);
+
+        // Compacting again doesn't change anything.
+        let text = ctx.func.display(&*isa).to_string();
+        ctx.func.compact();
+        assert_eq!(ctx.func.display(&*isa).to_string(), text);
+    }
+
+    #[test]
+    fn disabled_float() {
+        let mut flag_builder = settings::builder();
+        flag_builder.set("enable_float", "false").unwrap();
+        flag_builder.set("enable_verifier", "false").unwrap();
+        let isa = isa::lookup(triple!("x86_64"))
+            .unwrap()
+            .finish(settings::Flags::new(flag_builder));
+
+        let mut func = Function::new();
+        func.signature.params.push(AbiParam::new(types::I32));
+        func.signature.returns.push(AbiParam::new(types::I32));
+        let ebb0 = func.dfg.make_ebb();
+        {
+            let mut pos = FuncCursor::new(&mut func);
+            pos.insert_ebb(ebb0);
+            let arg = pos.func.dfg.append_ebb_param(ebb0, types::I32);
+            let f = pos.ins().bitcast(types::F32, arg);
+            let v = pos.ins().bitcast(types::I32, f);
+            pos.ins().return_(&[v]);
+        }
+
+        // The legalizer rejects the float even without the verifier.
+        let mut ctx = Context::for_function(func);
+        assert_eq!(
+            ctx.compile(&*isa),
+            Err(CodegenError::DisabledType(
+                "v1 = bitcast.f32 v0: f32 needs enable_float".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn disabled_cycle_counter() {
+        let mut flag_builder = settings::builder();
+        flag_builder.set("enable_verifier", "false").unwrap();
+        let isa = isa::lookup(triple!("x86_64"))
+            .unwrap()
+            .finish(settings::Flags::new(flag_builder));
+
+        let mut func = Function::new();
+        func.signature.returns.push(AbiParam::new(types::I64));
+        let ebb0 = func.dfg.make_ebb();
+        {
+            let mut pos = FuncCursor::new(&mut func);
+            pos.insert_ebb(ebb0);
+            let v = pos.ins().read_cycle_counter();
+            pos.ins().return_(&[v]);
+        }
+
+        let mut ctx = Context::for_function(func);
+        assert_eq!(
+            ctx.compile(&*isa),
+            Err(CodegenError::DisabledInstruction(
+                "inst0: read_cycle_counter needs enable_cycle_counter".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn preassigned_argument_locations() {
+        let isa = isa::lookup(triple!("x86_64"))
+            .unwrap()
+            .finish(settings::Flags::new(settings::builder()));
+        let rdi = isa.register_info().parse_regunit("rdi").unwrap();
+
+        // The second parameter is passed in %rdi instead of %rsi.
+        let compile = |call_conv| {
+            let mut func = Function::new();
+            func.signature.call_conv = call_conv;
+            func.signature.params.push(AbiParam::new(types::I64));
+            func.signature.params.push(AbiParam::new(types::I64));
+            func.signature.params[1].location = ArgumentLoc::Reg(rdi);
+            let ebb0 = func.dfg.make_ebb();
+            {
+                let mut pos = FuncCursor::new(&mut func);
+                pos.insert_ebb(ebb0);
+                pos.func.dfg.append_ebb_param(ebb0, types::I64);
+                pos.func.dfg.append_ebb_param(ebb0, types::I64);
+                pos.ins().return_(&[]);
+            }
+            let mut ctx = Context::for_function(func);
+            ctx.compile(&*isa)
+                .map(|_| ctx.func.signature.params[1].location)
+        };
+
+        assert_eq!(
+            compile(CallConv::SystemV),
+            Err(CodegenError::ArgumentLocation(
+                "parameter 1 is in [%rdi], but system_v uses [%rsi]".to_string()
+            ))
+        );
+        assert_eq!(compile(CallConv::Raw), Ok(ArgumentLoc::Reg(rdi)));
+    }
+
+    #[test]
+    fn unsupported_call_conv() {
+        let compile = |triple, caller, callee| {
+            let isa = isa::lookup(triple)
+                .unwrap()
+                .finish(settings::Flags::new(settings::builder()));
+            let mut func = Function::new();
+            func.signature.call_conv = caller;
+            let signature = func.import_signature(Signature::new(callee));
+            let callee = func.import_function(ExtFuncData {
+                name: ExternalName::testcase("callee"),
+                signature,
+                colocated: true,
+            });
+            let ebb0 = func.dfg.make_ebb();
+            {
+                let mut pos = FuncCursor::new(&mut func);
+                pos.insert_ebb(ebb0);
+                pos.ins().call(callee, &[]);
+                pos.ins().return_(&[]);
+            }
+            let mut ctx = Context::for_function(func);
+            ctx.compile(&*isa).map(|_| ())
+        };
+
+        assert_eq!(
+            compile(
+                triple!("x86_64"),
+                CallConv::SystemV,
+                CallConv::WindowsFastcall
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            compile(
+                triple!("i686"),
+                CallConv::SystemV,
+                CallConv::WindowsFastcall
+            ),
+            Err(CodegenError::UnsupportedCallConv(
+                CallConv::SystemV,
+                CallConv::WindowsFastcall
+            ))
+        );
+        assert_eq!(
+            compile(triple!("x86_64"), CallConv::Probestack, CallConv::SystemV),
+            Err(CodegenError::UnsupportedCallConv(
+                CallConv::Probestack,
+                CallConv::Probestack
+            ))
+        );
+    }
+
+    #[test]
+    fn renamed_libcall() {
+        let mut names = LibcallNames::new();
+        names.set_name(LibCall::FloorF32, ExternalName::testcase("wasm_rt_floorf"));
+        names.set_colocated(LibCall::FloorF32, true);
+        let mut isa_builder = isa::lookup(triple!("x86_64")).unwrap();
+        isa_builder.set_libcall_names(names);
+        let isa = isa_builder.finish(settings::Flags::new(settings::builder()));
+
+        // Without SSE 4.1, `floor` is expanded as a libcall.
+        let mut func = Function::new();
+        func.signature.params.push(AbiParam::new(types::F32));
+        func.signature.returns.push(AbiParam::new(types::F32));
+        let ebb0 = func.dfg.make_ebb();
+        {
+            let mut pos = FuncCursor::new(&mut func);
+            pos.insert_ebb(ebb0);
+            let arg = pos.func.dfg.append_ebb_param(ebb0, types::F32);
+            let v = pos.ins().floor(arg);
+            pos.ins().return_(&[v]);
+        }
+
+        let mut ctx = Context::for_function(func);
+        let mut mem = Vec::new();
+        let mut relocs = CollectedRelocs::new();
+        ctx.compile_and_emit(&*isa, &mut mem, &mut relocs, &mut NullTrapSink {})
+            .unwrap();
+
+        let callees: Vec<_> = ctx
+            .func
+            .dfg
+            .ext_funcs
+            .values()
+            .map(|ext_func| (ext_func.name.clone(), ext_func.colocated))
+            .collect();
+        assert_eq!(callees, [(ExternalName::testcase("wasm_rt_floorf"), true)]);
+        assert_eq!(relocs.relocs.len(), 1);
+        assert_eq!(relocs.relocs[0].reloc, Reloc::X86CallPCRel4);
+        assert_eq!(
+            relocs.relocs[0].target,
+            RelocTarget::External(ExternalName::testcase("wasm_rt_floorf"))
+        );
+    }
+
+    #[test]
+    fn patch_points() {
+        let isa = isa::lookup(triple!("x86_64"))
+            .unwrap()
+            .finish(settings::Flags::new(settings::builder()));
+
+        let mut func = Function::new();
+        func.signature.returns.push(AbiParam::new(types::I64));
+        let ebb0 = func.dfg.make_ebb();
+        let v = {
+            let mut pos = FuncCursor::new(&mut func);
+            pos.insert_ebb(ebb0);
+            let v = pos.ins().iconst(types::I64, 0x1122_3344_5566_7788);
+            pos.ins().return_(&[v]);
+            v
+        };
+        let inst = func.dfg.value_def(v).unwrap_inst();
+        func.patchable.insert(inst);
+
+        let mut ctx = Context::for_function(func);
+        let mut mem = Vec::new();
+        ctx.compile_and_emit(&*isa, &mut mem, &mut NullRelocSink {}, &mut NullTrapSink {})
+            .unwrap();
+
+        assert_eq!(ctx.patch_points.len(), 1);
+        let point = ctx.patch_points[0];
+        assert_eq!(point.inst, inst);
+        assert_eq!(point.size, 8);
+        let start = point.offset as usize;
+        assert_eq!(
+            &mem[start..start + 8],
+            &[0x88, 0x77, 0x66, 0x55, 0x44, 0x33, 0x22, 0x11]
+        );
+    }
+
+    #[test]
+    fn unpatchable_instruction() {
+        let isa = isa::lookup(triple!("x86_64"))
+            .unwrap()
+            .finish(settings::Flags::new(settings::builder()));
+
+        let mut func = Function::new();
+        func.signature.params.push(AbiParam::new(types::I64));
+        func.signature.returns.push(AbiParam::new(types::I64));
+        let ebb0 = func.dfg.make_ebb();
+        {
+            let mut pos = FuncCursor::new(&mut func);
+            pos.insert_ebb(ebb0);
+            let arg = pos.func.dfg.append_ebb_param(ebb0, types::I64);
+            let v = pos.ins().iadd_imm(arg, 1);
+            let inst = pos.func.dfg.value_def(v).unwrap_inst();
+            pos.func.patchable.insert(inst);
+            pos.ins().return_(&[v]);
+        }
+
+        let mut ctx = Context::for_function(func);
+        match ctx.compile(&*isa) {
+            Err(CodegenError::Unpatchable(inst)) => assert_eq!(inst, "v1 = iadd_imm.i64 v0, 1"),
+            result => panic!("unexpected result {:?}", result.map(|info| info.code_size)),
+        }
+    }
+
+    /// Get the ABI location of the function argument or return value `value` in compiled code.
+    fn abi_location(func: &Function, value: Value) -> ArgumentLoc {
+        match func.locations[value] {
+            ValueLoc::Reg(reg) => ArgumentLoc::Reg(reg),
+            ValueLoc::Stack(ss) => {
+                assert_eq!(func.stack_slots[ss].kind, StackSlotKind::IncomingArg);
+                ArgumentLoc::Stack(func.stack_slots[ss].offset.unwrap())
+            }
+            ValueLoc::Unassigned => ArgumentLoc::Unassigned,
+        }
+    }
+
+    /// Compile a callee with the signature `params -> returns` and a caller that calls it
+    /// indirectly, and check that both agree with the locations computed by the ISA.
+    fn check_argument_locations(isa: &isa::TargetIsa, params: &[AbiParam], returns: &[AbiParam]) {
+        let mut sig = Signature::new(CallConv::SystemV);
+        sig.params.extend_from_slice(params);
+        sig.returns.extend_from_slice(returns);
+        let arg_locs = isa.compute_argument_locations(&sig);
+        let ret_locs = isa.compute_return_locations(&sig);
+        assert!(arg_locs.iter().all(|loc| loc.is_assigned()));
+        assert!(ret_locs.iter().all(|loc| loc.is_assigned()));
+
+        let consts = |pos: &mut FuncCursor, abi_params: &[AbiParam]| -> Vec<Value> {
+            abi_params
+                .iter()
+                .map(|param| match param.value_type {
+                    types::F32 => pos.ins().f32const(Ieee32::with_float(1.0)),
+                    types::F64 => pos.ins().f64const(Ieee64::with_float(1.0)),
+                    types::B1 => pos.ins().bconst(types::B1, true),
+                    ty => pos.ins().iconst(ty, 1),
+                })
+                .collect()
+        };
+
+        // The prologue appends the frame pointer and callee-saved registers to the legalized
+        // signature, so only compare the leading values.
+        let mut callee =
+            Function::with_name_signature(ExternalName::testcase("callee"), sig.clone());
+        let ebb0 = callee.dfg.make_ebb();
+        {
+            let mut pos = FuncCursor::new(&mut callee);
+            pos.insert_ebb(ebb0);
+            for param in params {
+                pos.func.dfg.append_ebb_param(ebb0, param.value_type);
+            }
+            let values = consts(&mut pos, returns);
+            pos.ins().return_(&values);
+        }
+        let mut ctx = Context::for_function(callee);
+        ctx.compile(isa).unwrap();
+        let func = &ctx.func;
+        let entry_params = func.dfg.ebb_params(ebb0);
+        assert!(entry_params.len() >= arg_locs.len());
+        for (&value, &loc) in entry_params.iter().zip(&arg_locs) {
+            assert_eq!(abi_location(func, value), loc, "argument of {}", sig);
+        }
+        // Return values may be moved into place by `regmove` instructions right before the
+        // return, so check the legalized signature that the register allocator follows instead.
+        assert!(func.signature.returns.len() >= ret_locs.len());
+        for (ret, &loc) in func.signature.returns.iter().zip(&ret_locs) {
+            assert_eq!(ret.location, loc, "return value of {}", sig);
+        }
+
+        let mut caller_sig = Signature::new(CallConv::SystemV);
+        caller_sig.params.push(AbiParam::new(types::I64));
+        let mut caller =
+            Function::with_name_signature(ExternalName::testcase("caller"), caller_sig);
+        let sig_ref = caller.import_signature(sig.clone());
+        let ebb0 = caller.dfg.make_ebb();
+        {
+            let mut pos = FuncCursor::new(&mut caller);
+            pos.insert_ebb(ebb0);
+            let callee = pos.func.dfg.append_ebb_param(ebb0, types::I64);
+            let args = consts(&mut pos, params);
+            pos.ins().call_indirect(sig_ref, callee, &args);
+            pos.ins().return_(&[]);
+        }
+        let mut ctx = Context::for_function(caller);
+        ctx.compile(isa).unwrap();
+        let legalized = &ctx.func.dfg.signatures[sig_ref];
+        let caller_arg_locs: Vec<_> = legalized.params.iter().map(|p| p.location).collect();
+        let caller_ret_locs: Vec<_> = legalized.returns.iter().map(|r| r.location).collect();
+        assert_eq!(caller_arg_locs, arg_locs);
+        assert_eq!(caller_ret_locs, ret_locs);
+    }
+
+    #[test]
+    fn argument_locations() {
+        let isa = isa::lookup(triple!("x86_64"))
+            .unwrap()
+            .finish(settings::Flags::new(settings::builder()));
+        let reg = |name| ArgumentLoc::Reg(isa.register_info().parse_regunit(name).unwrap());
+        let int = AbiParam::new(types::I64);
+        let float = AbiParam::new(types::F64);
+
+        // Register arguments.
+        let params = [
+            AbiParam::new(types::I32),
+            int,
+            AbiParam::new(types::F32),
+            float,
+            AbiParam::new(types::B1),
+        ];
+        check_argument_locations(&*isa, &params, &[AbiParam::new(types::I32)]);
+
+        // Arguments that don't fit in registers are passed on the stack.
+        let mut params = vec![int; 8];
+        params.extend_from_slice(&[float; 10]);
+        check_argument_locations(&*isa, &params, &[float]);
+        let mut sig = Signature::new(CallConv::SystemV);
+        sig.params = params;
+        let locs = isa.compute_argument_locations(&sig);
+        assert_eq!(locs[0], reg("rdi"));
+        assert_eq!(locs[5], reg("r9"));
+        assert_eq!(locs[6], ArgumentLoc::Stack(0));
+        assert_eq!(locs[7], ArgumentLoc::Stack(8));
+        assert_eq!(locs[8], reg("xmm0"));
+        assert_eq!(locs[16], ArgumentLoc::Stack(16));
+        assert_eq!(locs[17], ArgumentLoc::Stack(24));
+
+        // Special purpose arguments are assigned like any other argument.
+        let params = [
+            AbiParam::special(types::I64, ArgumentPurpose::VMContext),
+            AbiParam::new(types::I32),
+        ];
+        check_argument_locations(&*isa, &params, &[int, float]);
+
+        // Return values that don't fit in registers are returned in memory, through a pointer
+        // that is passed as the first argument and returned in `rax`.
+        let params = [AbiParam::new(types::I32)];
+        let returns = [int; 4];
+        check_argument_locations(&*isa, &params, &returns);
+        let mut sig = Signature::new(CallConv::SystemV);
+        sig.params.extend_from_slice(&params);
+        sig.returns.extend_from_slice(&returns);
+        assert_eq!(
+            isa.compute_argument_locations(&sig),
+            [reg("rdi"), reg("rsi")]
+        );
+        assert_eq!(isa.compute_return_locations(&sig), [reg("rax")]);
     }
 }
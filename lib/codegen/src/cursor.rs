@@ -597,6 +597,43 @@ impl<'f> FuncCursor<'f> {
     pub fn ins(&mut self) -> ir::InsertBuilder<&mut FuncCursor<'f>> {
         ir::InsertBuilder::new(self)
     }
+
+    /// Replace the uses of the single result of `inst` with `value`, and remove `inst` from the
+    /// layout.
+    ///
+    /// The result becomes an alias of `value`, so the instruction must not be used again. If the
+    /// cursor points at `inst`, it is left pointing at the position preceding it, so iterating
+    /// with `next_inst()` continues after `inst`.
+    pub fn remove_and_replace_with_alias(&mut self, inst: ir::Inst, value: ir::Value) {
+        debug_assert_eq!(
+            self.func.dfg.inst_results(inst).len(),
+            1,
+            "{} must have a single result",
+            inst
+        );
+        let result = self.func.dfg.first_result(inst);
+        self.func.dfg.clear_results(inst);
+        self.func.dfg.change_to_alias(result, value);
+        self.remove_detached(inst);
+    }
+
+    /// Replace the uses of the results of `inst` with the results of `src_inst`, and remove
+    /// `inst` from the layout.
+    ///
+    /// The cursor is moved like with `remove_and_replace_with_alias()`.
+    pub fn remove_and_replace_with_aliases(&mut self, inst: ir::Inst, src_inst: ir::Inst) {
+        self.func.dfg.replace_with_aliases(inst, src_inst);
+        self.remove_detached(inst);
+    }
+
+    /// Remove `inst`, whose results have been cleared, from the layout.
+    fn remove_detached(&mut self, inst: ir::Inst) {
+        if self.current_inst() == Some(inst) {
+            self.remove_inst_and_step_back();
+        } else {
+            self.func.layout.remove_inst(inst);
+        }
+    }
 }
 
 impl<'f> Cursor for FuncCursor<'f> {
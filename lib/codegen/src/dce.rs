@@ -36,7 +36,9 @@ fn is_load_with_defined_trapping(opcode: Opcode, data: &InstructionData) -> bool
     }
     match *data {
         InstructionData::StackLoad { .. } => false,
-        InstructionData::Load { flags, .. } => !flags.notrap(),
+        InstructionData::Load { flags, .. }
+        | InstructionData::LoadSymbol { flags, .. }
+        | InstructionData::LoadGlobal { flags, .. } => !flags.notrap(),
         _ => true,
     }
 }
@@ -57,6 +59,7 @@ pub fn do_dce(func: &mut Function, domtree: &mut DominatorTree) {
                 let opcode = data.opcode();
                 if trivially_unsafe_for_dce(opcode)
                     || is_load_with_defined_trapping(opcode, &data)
+                    || pos.func.is_patchable(inst)
                     || any_inst_results_used(inst, &live, &pos.func.dfg)
                 {
                     for arg in pos.func.dfg.inst_args(inst) {
@@ -703,7 +703,7 @@ mod tests {
 
         cur.insert_ebb(ebb0);
         cur.ins().brnz(v0, ebb2, &[]);
-        cur.ins().trap(TrapCode::User(0));
+        cur.ins().trap(TrapCode::User(0), 0);
 
         cur.insert_ebb(ebb1);
         let v1 = cur.ins().iconst(I32, 1);
@@ -825,7 +825,7 @@ mod tests {
         let jmp02 = cur.ins().jump(ebb2, &[]);
 
         cur.insert_ebb(ebb1);
-        let trap = cur.ins().trap(TrapCode::User(5));
+        let trap = cur.ins().trap(TrapCode::User(5), 0);
 
         cur.insert_ebb(ebb2);
         let jmp21 = cur.ins().jump(ebb1, &[]);
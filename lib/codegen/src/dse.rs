@@ -0,0 +1,259 @@
+//! Dead store elimination for explicit stack slots.
+//!
+//! Frontends often store values to explicit stack slots that are never loaded again, and use
+//! several stack slots that are never needed at the same time. This pass computes which bytes of
+//! each explicit stack slot may be loaded later at every point of the function, and uses it to:
+//!
+//! - Remove `stack_store` instructions whose bytes are overwritten or go out of scope before any
+//!   load of them.
+//! - Let explicit stack slots whose contents are never live at the same time share their storage
+//!   when the stack frame is laid out.
+//!
+//! Only stack slots that are exclusively accessed with `stack_load` and `stack_store` can be
+//! tracked. A slot whose address is taken by `stack_addr` can be accessed through that pointer
+//! anywhere, including in other functions, so the pass leaves it alone.
+
+use entity::{EntityRef, SecondaryMap};
+use ir::instructions::BranchInfo;
+use ir::{Ebb, Function, Inst, InstructionData, Opcode, StackSlot, StackSlotKind};
+use std::cmp::{max, min};
+use std::vec::Vec;
+use timing;
+
+/// A half-open range of bytes in a stack slot.
+type ByteRange = (i64, i64);
+
+/// The bytes of a stack slot that may be loaded before they are overwritten.
+///
+/// The ranges are sorted, and they neither overlap nor touch each other.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct LiveBytes(Vec<ByteRange>);
+
+impl LiveBytes {
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Does `range` overlap any live bytes?
+    fn overlaps(&self, range: ByteRange) -> bool {
+        self.0.iter().any(|&(lo, hi)| lo < range.1 && range.0 < hi)
+    }
+
+    /// Make the bytes in `range` live.
+    fn insert(&mut self, range: ByteRange) {
+        let mut merged = range;
+        let mut ranges = Vec::with_capacity(self.0.len() + 1);
+        for &(lo, hi) in &self.0 {
+            if hi < merged.0 || merged.1 < lo {
+                ranges.push((lo, hi));
+            } else {
+                merged = (min(lo, merged.0), max(hi, merged.1));
+            }
+        }
+        ranges.push(merged);
+        ranges.sort();
+        self.0 = ranges;
+    }
+
+    /// Make the bytes in `range` dead.
+    fn remove(&mut self, range: ByteRange) {
+        let mut ranges = Vec::with_capacity(self.0.len() + 1);
+        for &(lo, hi) in &self.0 {
+            if lo < range.0 {
+                ranges.push((lo, min(hi, range.0)));
+            }
+            if range.1 < hi {
+                ranges.push((max(lo, range.1), hi));
+            }
+        }
+        self.0 = ranges;
+    }
+
+    /// Make all the bytes that are live in `other` live in `self` too.
+    fn union(&mut self, other: &Self) {
+        for &range in &other.0 {
+            self.insert(range);
+        }
+    }
+}
+
+/// The live bytes of all stack slots, indexed by stack slot number.
+type LiveSlots = Vec<LiveBytes>;
+
+/// A stack slot access by a `stack_load` or `stack_store` instruction.
+enum Access {
+    Load(StackSlot, ByteRange),
+    Store(StackSlot, ByteRange),
+}
+
+/// Get the stack slot access made by `inst`, if it is a `stack_load` or a `stack_store`.
+fn slot_access(func: &Function, inst: Inst) -> Option<Access> {
+    match func.dfg[inst] {
+        InstructionData::StackLoad {
+            opcode: Opcode::StackLoad,
+            stack_slot,
+            offset,
+        } => {
+            let start: i64 = offset.into();
+            let size = func.dfg.value_type(func.dfg.first_result(inst)).bytes();
+            Some(Access::Load(stack_slot, (start, start + i64::from(size))))
+        }
+        InstructionData::StackStore {
+            opcode: Opcode::StackStore,
+            arg,
+            stack_slot,
+            offset,
+        } => {
+            let start: i64 = offset.into();
+            let size = func.dfg.value_type(arg).bytes();
+            Some(Access::Store(stack_slot, (start, start + i64::from(size))))
+        }
+        _ => None,
+    }
+}
+
+/// Find the explicit stack slots which are only accessed by `stack_load` and `stack_store`.
+fn tracked_slots(func: &Function) -> SecondaryMap<StackSlot, bool> {
+    let mut tracked = SecondaryMap::new();
+    for (ss, slot) in func.stack_slots.iter() {
+        tracked[ss] = slot.kind == StackSlotKind::ExplicitSlot;
+    }
+    for ebb in func.layout.ebbs() {
+        for inst in func.layout.ebb_insts(ebb) {
+            if slot_access(func, inst).is_some() {
+                continue;
+            }
+            match func.dfg[inst] {
+                InstructionData::StackLoad { stack_slot, .. }
+                | InstructionData::StackStore { stack_slot, .. }
+                | InstructionData::RegSpill {
+                    dst: stack_slot, ..
+                }
+                | InstructionData::RegFill {
+                    src: stack_slot, ..
+                } => tracked[stack_slot] = false,
+                _ => {}
+            }
+        }
+    }
+    tracked
+}
+
+/// Update `live` from the live bytes after `inst` to the live bytes before it.
+fn transfer(
+    func: &Function,
+    inst: Inst,
+    live: &mut LiveSlots,
+    live_in: &SecondaryMap<Ebb, LiveSlots>,
+    tracked: &SecondaryMap<StackSlot, bool>,
+) {
+    let enter = |ebb: Ebb, live: &mut LiveSlots| {
+        for (slot, other) in live.iter_mut().zip(&live_in[ebb]) {
+            slot.union(other);
+        }
+    };
+    match func.dfg.analyze_branch(inst) {
+        BranchInfo::NotABranch => {}
+        BranchInfo::SingleDest(dest, _) => enter(dest, live),
+        BranchInfo::Table(jt, default) => {
+            if let Some(ebb) = default {
+                enter(ebb, live);
+            }
+            for &ebb in func.jump_tables[jt].iter() {
+                enter(ebb, live);
+            }
+        }
+    }
+    match slot_access(func, inst) {
+        Some(Access::Load(ss, range)) if tracked[ss] => live[ss.index()].insert(range),
+        Some(Access::Store(ss, range)) if tracked[ss] => live[ss.index()].remove(range),
+        _ => {}
+    }
+}
+
+/// Compute the live bytes of the stack slots on entry to each EBB.
+fn compute_live_in(
+    func: &Function,
+    tracked: &SecondaryMap<StackSlot, bool>,
+) -> SecondaryMap<Ebb, LiveSlots> {
+    let num_slots = func.stack_slots.keys().len();
+    let empty: LiveSlots = vec![LiveBytes::default(); num_slots];
+    let mut live_in = SecondaryMap::with_default(empty);
+    let mut changed = true;
+    while changed {
+        changed = false;
+        let mut next = func.layout.last_ebb();
+        while let Some(ebb) = next {
+            next = func.layout.prev_ebb(ebb);
+            let mut live = vec![LiveBytes::default(); num_slots];
+            let mut cur = func.layout.last_inst(ebb);
+            while let Some(inst) = cur {
+                transfer(func, inst, &mut live, &live_in, tracked);
+                cur = func.layout.prev_inst(inst);
+            }
+            if live != live_in[ebb] {
+                live_in[ebb] = live;
+                changed = true;
+            }
+        }
+    }
+    live_in
+}
+
+/// Remove dead stores to explicit stack slots, and share the storage of slots that are never
+/// live at the same time.
+pub fn do_dse(func: &mut Function) {
+    let _tt = timing::dse();
+    let tracked = tracked_slots(func);
+    let live_in = compute_live_in(func, &tracked);
+
+    // Two slots interfere if one of them is stored to while the other one is live.
+    let num_slots = func.stack_slots.keys().len();
+    let mut interferes = vec![false; num_slots * num_slots];
+    let mut dead_stores = Vec::new();
+    for ebb in func.layout.ebbs() {
+        let mut live = vec![LiveBytes::default(); num_slots];
+        let mut cur = func.layout.last_inst(ebb);
+        while let Some(inst) = cur {
+            if let Some(Access::Store(ss, range)) = slot_access(func, inst) {
+                if tracked[ss] && !live[ss.index()].overlaps(range) {
+                    dead_stores.push(inst);
+                    cur = func.layout.prev_inst(inst);
+                    continue;
+                }
+                for (other, bytes) in live.iter().enumerate() {
+                    if !bytes.is_empty() {
+                        interferes[ss.index() * num_slots + other] = true;
+                        interferes[other * num_slots + ss.index()] = true;
+                    }
+                }
+            }
+            transfer(func, inst, &mut live, &live_in, &tracked);
+            cur = func.layout.prev_inst(inst);
+        }
+    }
+
+    for inst in dead_stores {
+        debug!("Removing dead store {}", func.dfg.display_inst(inst, None));
+        func.layout.remove_inst(inst);
+    }
+
+    // Put each tracked slot in the first group of slots that it doesn't interfere with.
+    let mut groups: Vec<Vec<StackSlot>> = Vec::new();
+    for ss in func.stack_slots.keys().filter(|&ss| tracked[ss]) {
+        let compatible = groups.iter().position(|group| {
+            group
+                .iter()
+                .all(|other| !interferes[ss.index() * num_slots + other.index()])
+        });
+        match compatible {
+            Some(i) => groups[i].push(ss),
+            None => groups.push(vec![ss]),
+        }
+    }
+    for group in groups {
+        for &ss in &group[1..] {
+            func.stack_slots.share(ss, group[0]);
+        }
+    }
+}
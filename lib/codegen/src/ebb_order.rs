@@ -0,0 +1,181 @@
+//! Reordering of EBBs by their frequency hints.
+//!
+//! Frontends can tell Cranelift how often each EBB is executed by setting
+//! `Function::ebb_frequencies`, for example from a profile or from knowing that an EBB handles a
+//! rare error. This pass uses the hints to lay out the frequently executed EBBs first, so the
+//! common paths through the function fall through and the rarely executed code is moved out of
+//! the way, towards the end of the function.
+//!
+//! Branch hints in `Function::branch_hints` also mark EBBs as rarely executed: the destination of
+//! an `unlikely` branch, or the destination of the jump following a `likely` branch, is given a
+//! frequency of 0 unless it has a hint of its own. This only happens when every branch to the EBB
+//! agrees that it is cold.
+//!
+//! The entry block always stays first. The other EBBs are sorted by decreasing frequency, and EBBs
+//! without a hint are assumed to be executed more often than any hinted EBB. The sort is stable,
+//! so EBBs of equal frequency keep their relative order, and a function without any hints is left
+//! alone.
+
+use entity::SecondaryMap;
+use ir::instructions::BranchInfo;
+use ir::{BranchHint, Ebb, EbbFrequencies, Function, Inst, Opcode};
+use std::cmp::Reverse;
+use std::u32;
+use std::vec::Vec;
+use timing;
+
+/// Reorder the EBBs in `func` according to `func.ebb_frequencies` and `func.branch_hints`.
+pub fn do_reorder_ebbs(func: &mut Function) {
+    let _tt = timing::reorder_ebbs();
+
+    let mut ebbs: Vec<Ebb> = func.layout.ebbs().collect();
+    let frequencies = ebb_frequencies(func);
+    if ebbs.iter().all(|&ebb| frequencies[ebb].is_none()) {
+        return;
+    }
+
+    ebbs[1..].sort_by_key(|&ebb| Reverse(frequencies[ebb].unwrap_or(u32::MAX)));
+    func.set_ebb_order(&ebbs)
+        .expect("The EBB order is a permutation of the layout");
+}
+
+/// Get the EBB frequency hints of `func`, including the ones implied by its branch hints.
+fn ebb_frequencies(func: &Function) -> EbbFrequencies {
+    let mut frequencies = func.ebb_frequencies.clone();
+    if func.branch_hints.values().all(Option::is_none) {
+        return frequencies;
+    }
+
+    // Classify all the control flow edges as cold or not.
+    let mut hot = SecondaryMap::<Ebb, bool>::new();
+    let mut cold = SecondaryMap::<Ebb, bool>::new();
+    for ebb in func.layout.ebbs() {
+        for inst in func.layout.ebb_insts(ebb) {
+            match func.dfg.analyze_branch(inst) {
+                BranchInfo::NotABranch => {}
+                BranchInfo::SingleDest(dest, _) => {
+                    if is_cold_edge(func, inst) {
+                        cold[dest] = true;
+                    } else {
+                        hot[dest] = true;
+                    }
+                }
+                BranchInfo::Table(jt, dest) => {
+                    for &dest in dest.iter().chain(func.jump_tables[jt].iter()) {
+                        hot[dest] = true;
+                    }
+                }
+            }
+        }
+    }
+
+    for ebb in func.layout.ebbs() {
+        if cold[ebb] && !hot[ebb] && frequencies[ebb].is_none() {
+            frequencies[ebb] = Some(0);
+        }
+    }
+    frequencies
+}
+
+/// Is the branch `inst` hinted to rarely go to its destination?
+fn is_cold_edge(func: &Function, inst: Inst) -> bool {
+    if func.branch_hints[inst] == Some(BranchHint::Unlikely) {
+        return true;
+    }
+
+    // A jump is the other side of a likely branch right before it.
+    match func.dfg[inst].opcode() {
+        Opcode::Jump | Opcode::Fallthrough => func.layout.prev_inst(inst).map_or(false, |prev| {
+            func.branch_hints[prev] == Some(BranchHint::Likely)
+        }),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::do_reorder_ebbs;
+    use cursor::{Cursor, FuncCursor};
+    use ir::{types, BranchHint, Ebb, Function, InstBuilder};
+    use std::vec::Vec;
+
+    #[test]
+    fn cold_ebbs_last() {
+        let mut func = Function::new();
+        let ebb0 = func.dfg.make_ebb();
+        let ebb1 = func.dfg.make_ebb();
+        let ebb2 = func.dfg.make_ebb();
+        let ebb3 = func.dfg.make_ebb();
+        let cond = func.dfg.append_ebb_param(ebb0, types::I32);
+        {
+            let mut cur = FuncCursor::new(&mut func);
+            cur.insert_ebb(ebb0);
+            cur.ins().brnz(cond, ebb2, &[]);
+            cur.ins().jump(ebb1, &[]);
+            cur.insert_ebb(ebb1);
+            cur.ins().jump(ebb3, &[]);
+            cur.insert_ebb(ebb2);
+            cur.ins().jump(ebb3, &[]);
+            cur.insert_ebb(ebb3);
+            cur.ins().return_(&[]);
+        }
+
+        // Without hints, nothing moves.
+        do_reorder_ebbs(&mut func);
+        let order: Vec<Ebb> = func.layout.ebbs().collect();
+        assert_eq!(order, [ebb0, ebb1, ebb2, ebb3]);
+
+        func.ebb_frequencies[ebb1] = Some(1);
+        func.ebb_frequencies[ebb2] = Some(100);
+        do_reorder_ebbs(&mut func);
+        let order: Vec<Ebb> = func.layout.ebbs().collect();
+        assert_eq!(order, [ebb0, ebb3, ebb2, ebb1]);
+        let insts: Vec<_> = func.layout.ebb_insts(ebb1).collect();
+        assert_eq!(insts.len(), 1);
+    }
+
+    #[test]
+    fn cold_branch_destinations() {
+        let mut func = Function::new();
+        let ebb0 = func.dfg.make_ebb();
+        let ebb1 = func.dfg.make_ebb();
+        let ebb2 = func.dfg.make_ebb();
+        let ebb3 = func.dfg.make_ebb();
+        let cond = func.dfg.append_ebb_param(ebb0, types::I32);
+        let br0;
+        let br1;
+        {
+            let mut cur = FuncCursor::new(&mut func);
+            cur.insert_ebb(ebb0);
+            br0 = cur.ins().brz(cond, ebb1, &[]);
+            cur.ins().jump(ebb2, &[]);
+            cur.insert_ebb(ebb1);
+            br1 = cur.ins().brnz(cond, ebb2, &[]);
+            cur.ins().jump(ebb3, &[]);
+            cur.insert_ebb(ebb2);
+            cur.ins().jump(ebb3, &[]);
+            cur.insert_ebb(ebb3);
+            cur.ins().return_(&[]);
+        }
+
+        // `ebb1` is only reached through an unlikely branch.
+        func.branch_hints[br0] = Some(BranchHint::Unlikely);
+        do_reorder_ebbs(&mut func);
+        let order: Vec<Ebb> = func.layout.ebbs().collect();
+        assert_eq!(order, [ebb0, ebb2, ebb3, ebb1]);
+
+        // `ebb2` is reached through an unlikely branch, but the jump from `ebb0` is hot.
+        func.branch_hints[br0] = None;
+        func.branch_hints[br1] = Some(BranchHint::Unlikely);
+        func.set_ebb_order(&[ebb0, ebb1, ebb2, ebb3]).unwrap();
+        do_reorder_ebbs(&mut func);
+        let order: Vec<Ebb> = func.layout.ebbs().collect();
+        assert_eq!(order, [ebb0, ebb1, ebb2, ebb3]);
+
+        // A likely branch makes the jump after it cold.
+        func.branch_hints[br0] = Some(BranchHint::Likely);
+        do_reorder_ebbs(&mut func);
+        let order: Vec<Ebb> = func.layout.ebbs().collect();
+        assert_eq!(order, [ebb0, ebb1, ebb3, ebb2]);
+    }
+}
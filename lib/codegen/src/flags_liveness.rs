@@ -0,0 +1,47 @@
+//! Liveness of CPU flags values.
+//!
+//! The value types `iflags` and `fflags` represent CPU flags, and at most one flags value can be
+//! live at a time. Passes that want to switch an instruction to an encoding that clobbers the
+//! flags need to know if a flags value is live across that instruction.
+
+use entity::EntitySet;
+use ir::{Function, Inst};
+use std::vec::Vec;
+
+/// Find the instructions that have a CPU flags value live across them.
+///
+/// These are the instructions that are not allowed to clobber the flags. An instruction that
+/// defines the flags value itself is not included.
+///
+/// Returns `None` when a flags value is live across EBBs. This is rare, and the callers don't
+/// bother handling it.
+pub fn flags_live_insts(func: &Function) -> Option<EntitySet<Inst>> {
+    let mut live_insts = EntitySet::new();
+
+    for ebb in func.layout.ebbs() {
+        // The flags values that are used further down in `ebb`.
+        let mut live = Vec::new();
+
+        // Visit instructions backwards so we can track liveness accurately.
+        for inst in func.layout.ebb_insts(ebb).rev() {
+            for res in func.dfg.inst_results(inst) {
+                live.retain(|v| v != res);
+            }
+            if !live.is_empty() {
+                live_insts.insert(inst);
+            }
+            for &arg in func.dfg.inst_args(inst) {
+                if func.dfg.value_type(arg).is_flags() && !live.contains(&arg) {
+                    live.push(arg);
+                }
+            }
+        }
+
+        // Any flags values that are still live were defined in another EBB.
+        if !live.is_empty() {
+            return None;
+        }
+    }
+
+    Some(live_insts)
+}
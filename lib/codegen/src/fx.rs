@@ -15,6 +15,9 @@ use std::default::Default;
 use std::hash::{BuildHasherDefault, Hash, Hasher};
 use std::ops::BitXor;
 
+// The Fx hasher isn't randomly seeded, but it hashes `usize` values differently on 32-bit and
+// 64-bit hosts. The generated code must not depend on the iteration order of these maps. Test
+// harnesses can change the seed with `set_hash_seed` to check that it doesn't.
 pub type FxHashMap<K, V> = HashMap<K, V, BuildHasherDefault<FxHasher>>;
 pub type FxHashSet<V> = HashSet<V, BuildHasherDefault<FxHasher>>;
 
@@ -51,10 +54,38 @@ const K: usize = 0x517cc1b727220a95;
 impl Default for FxHasher {
     #[inline]
     fn default() -> Self {
-        Self { hash: 0 }
+        Self { hash: hash_seed() }
     }
 }
 
+#[cfg(all(feature = "testing_hooks", feature = "std"))]
+thread_local! {
+    static HASH_SEED: ::std::cell::Cell<usize> = ::std::cell::Cell::new(0);
+}
+
+#[cfg(all(feature = "testing_hooks", feature = "std"))]
+#[inline]
+fn hash_seed() -> usize {
+    HASH_SEED.with(|seed| seed.get())
+}
+
+#[cfg(not(all(feature = "testing_hooks", feature = "std")))]
+#[inline]
+fn hash_seed() -> usize {
+    0
+}
+
+/// Set the initial state of the hashers used by the hash maps and sets that the current thread
+/// creates, which changes their iteration order.
+///
+/// Maps hash their keys again on every lookup, so a map must only be used while the seed it was
+/// created with is set. This function is under the "testing_hooks" feature, and is only suitable
+/// for use by test harnesses between compilations.
+#[cfg(all(feature = "testing_hooks", feature = "std"))]
+pub fn set_hash_seed(seed: usize) {
+    HASH_SEED.with(|cell| cell.set(seed));
+}
+
 impl FxHasher {
     #[inline]
     fn add_to_hash(&mut self, i: usize) {
@@ -0,0 +1,299 @@
+//! Canonicalization of global values.
+//!
+//! Frontends often declare chains of `iadd_imm` global values, and they may declare the same
+//! global value several times. Each `global_value` instruction is expanded into the whole chain
+//! of instructions computing its global value by the legalizer, so this pass simplifies the
+//! global values of a function first:
+//!
+//! - The offsets of `iadd_imm` global values are folded into the global values using them, when
+//!   the sum fits in the offset of the user.
+//! - Global values with the same definition are merged, and `global_value` instructions, heaps
+//!   and tables are rewritten to use the remaining one.
+//! - Global values that are no longer used are removed, and the remaining ones are renumbered.
+
+use entity::{PrimaryMap, SecondaryMap};
+use fx::FxHashMap;
+use ir::immediates::{Imm64, Offset32};
+use ir::{Function, GlobalValue, GlobalValueData, HeapStyle, InstructionData};
+use packed_option::PackedOption;
+use std::mem;
+use std::vec::Vec;
+
+/// Canonicalize the global values of `func`.
+///
+/// The function is assumed to be valid, in particular the type of the base of an `iadd_imm`
+/// global value is assumed to be the type of its result.
+pub fn do_canonicalize_global_values(func: &mut Function) {
+    if func.global_values.is_empty() {
+        return;
+    }
+
+    // `replacements[gv]` is a global value with the same value as `gv`.
+    let mut replacements = SecondaryMap::with_default(PackedOption::default());
+    loop {
+        let mut changed = false;
+
+        for gv in func.global_values.keys() {
+            if replacements[gv].is_some() {
+                continue;
+            }
+            match fold(func, gv, &replacements) {
+                Folded::Same(base) => {
+                    replacements[gv] = base.into();
+                    changed = true;
+                }
+                Folded::Data(data) => {
+                    if data != func.global_values[gv] {
+                        func.global_values[gv] = data;
+                        changed = true;
+                    }
+                }
+            }
+        }
+
+        // Merge the global values with the same definition into the first one.
+        let mut defs = FxHashMap::default();
+        for gv in func.global_values.keys() {
+            if replacements[gv].is_some() {
+                continue;
+            }
+            let data = with_resolved_base(&func.global_values[gv], &replacements);
+            match defs.get(&data).cloned() {
+                Some(first) => {
+                    replacements[gv] = PackedOption::from(first);
+                    changed = true;
+                }
+                None => {
+                    defs.insert(data, gv);
+                }
+            }
+        }
+
+        if !changed {
+            break;
+        }
+    }
+
+    for gv in func.global_values.keys() {
+        if replacements[gv].is_some() {
+            continue;
+        }
+        let data = with_resolved_base(&func.global_values[gv], &replacements);
+        func.global_values[gv] = data;
+    }
+    rewrite_uses(func, |gv| resolve(gv, &replacements));
+    remove_unused(func);
+}
+
+/// The result of folding the definition of a global value.
+enum Folded {
+    /// The global value has the same value as another global value.
+    Same(GlobalValue),
+
+    /// The global value has this definition.
+    Data(GlobalValueData),
+}
+
+/// Fold the offset of the base of `gv` into its definition, if possible.
+fn fold(
+    func: &Function,
+    gv: GlobalValue,
+    replacements: &SecondaryMap<GlobalValue, PackedOption<GlobalValue>>,
+) -> Folded {
+    let data = with_resolved_base(&func.global_values[gv], replacements);
+    match data {
+        GlobalValueData::IAddImm {
+            base,
+            offset,
+            global_type,
+        } => {
+            let offset: i64 = offset.into();
+            if offset == 0 {
+                return Folded::Same(base);
+            }
+            match func.global_values[base] {
+                GlobalValueData::IAddImm {
+                    base: base_base,
+                    offset: base_offset,
+                    ..
+                } => {
+                    if let Some(sum) = offset.checked_add(base_offset.into()) {
+                        return Folded::Data(GlobalValueData::IAddImm {
+                            base: base_base,
+                            offset: Imm64::new(sum),
+                            global_type,
+                        });
+                    }
+                }
+                // The offset of a symbol that isn't colocated can't be used when its address is
+                // loaded from the GOT.
+                GlobalValueData::Symbol {
+                    ref name,
+                    offset: base_offset,
+                    colocated: true,
+                } => {
+                    if let Some(sum) = offset.checked_add(base_offset.into()) {
+                        return Folded::Data(GlobalValueData::Symbol {
+                            name: name.clone(),
+                            offset: Imm64::new(sum),
+                            colocated: true,
+                        });
+                    }
+                }
+                _ => {}
+            }
+            Folded::Data(data)
+        }
+        GlobalValueData::Load {
+            base,
+            offset,
+            global_type,
+            readonly,
+        } => {
+            if let GlobalValueData::IAddImm {
+                base: base_base,
+                offset: base_offset,
+                ..
+            } = func.global_values[base]
+            {
+                let offset: i64 = offset.into();
+                match offset.checked_add(base_offset.into()) {
+                    Some(sum) if sum as i32 as i64 == sum => {
+                        return Folded::Data(GlobalValueData::Load {
+                            base: base_base,
+                            offset: Offset32::new(sum as i32),
+                            global_type,
+                            readonly,
+                        })
+                    }
+                    _ => {}
+                }
+            }
+            Folded::Data(data)
+        }
+        GlobalValueData::IAddScaled { .. }
+        | GlobalValueData::VMContext
+        | GlobalValueData::Symbol { .. } => Folded::Data(data),
+    }
+}
+
+/// Get the global value that `gv` is replaced with.
+fn resolve(
+    mut gv: GlobalValue,
+    replacements: &SecondaryMap<GlobalValue, PackedOption<GlobalValue>>,
+) -> GlobalValue {
+    while let Some(replacement) = replacements[gv].expand() {
+        gv = replacement;
+    }
+    gv
+}
+
+/// Get `data` with its base replaced by the global value it is replaced with.
+fn with_resolved_base(
+    data: &GlobalValueData,
+    replacements: &SecondaryMap<GlobalValue, PackedOption<GlobalValue>>,
+) -> GlobalValueData {
+    let mut data = data.clone();
+    match data {
+        GlobalValueData::IAddImm { ref mut base, .. }
+        | GlobalValueData::Load { ref mut base, .. } => *base = resolve(*base, replacements),
+        GlobalValueData::IAddScaled {
+            ref mut base,
+            ref mut index,
+            ..
+        } => {
+            *base = resolve(*base, replacements);
+            *index = resolve(*index, replacements);
+        }
+        GlobalValueData::VMContext | GlobalValueData::Symbol { .. } => {}
+    }
+    data
+}
+
+/// Replace each use of a global value `gv` in the instructions, heaps and tables of `func` with
+/// `map(gv)`.
+fn rewrite_uses<F: FnMut(GlobalValue) -> GlobalValue>(func: &mut Function, mut map: F) {
+    for ebb in func.layout.ebbs() {
+        for inst in func.layout.ebb_insts(ebb) {
+            match func.dfg[inst] {
+                InstructionData::UnaryGlobalValue {
+                    ref mut global_value,
+                    ..
+                }
+                | InstructionData::LoadSymbol {
+                    ref mut global_value,
+                    ..
+                }
+                | InstructionData::StoreSymbol {
+                    ref mut global_value,
+                    ..
+                }
+                | InstructionData::LoadGlobal {
+                    ref mut global_value,
+                    ..
+                } => *global_value = map(*global_value),
+                _ => {}
+            }
+        }
+    }
+    for heap in func.heaps.values_mut() {
+        heap.base = map(heap.base);
+        if let HeapStyle::Dynamic { ref mut bound_gv } = heap.style {
+            *bound_gv = map(*bound_gv);
+        }
+    }
+    for table in func.tables.values_mut() {
+        table.base_gv = map(table.base_gv);
+        table.bound_gv = map(table.bound_gv);
+    }
+}
+
+/// Remove the global values of `func` that aren't used, and renumber the other ones.
+fn remove_unused(func: &mut Function) {
+    let mut used = SecondaryMap::with_default(false);
+    let mut worklist = Vec::new();
+    rewrite_uses(func, |gv| {
+        worklist.push(gv);
+        gv
+    });
+    while let Some(gv) = worklist.pop() {
+        if used[gv] {
+            continue;
+        }
+        used[gv] = true;
+        match func.global_values[gv] {
+            GlobalValueData::IAddImm { base, .. } | GlobalValueData::Load { base, .. } => {
+                worklist.push(base)
+            }
+            GlobalValueData::IAddScaled { base, index, .. } => {
+                worklist.push(base);
+                worklist.push(index);
+            }
+            GlobalValueData::VMContext | GlobalValueData::Symbol { .. } => {}
+        }
+    }
+
+    let old_global_values = mem::replace(&mut func.global_values, PrimaryMap::new());
+    let mut renumbered = SecondaryMap::with_default(PackedOption::default());
+    for (gv, data) in old_global_values.iter() {
+        if used[gv] {
+            renumbered[gv] = func.global_values.push(data.clone()).into();
+        }
+    }
+    for data in func.global_values.values_mut() {
+        match *data {
+            GlobalValueData::IAddImm { ref mut base, .. }
+            | GlobalValueData::Load { ref mut base, .. } => *base = renumbered[*base].unwrap(),
+            GlobalValueData::IAddScaled {
+                ref mut base,
+                ref mut index,
+                ..
+            } => {
+                *base = renumbered[*base].unwrap();
+                *index = renumbered[*index].unwrap();
+            }
+            GlobalValueData::VMContext | GlobalValueData::Symbol { .. } => {}
+        }
+    }
+    rewrite_uses(func, |gv| renumbered[gv].unwrap());
+}
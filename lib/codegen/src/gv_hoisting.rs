@@ -0,0 +1,104 @@
+//! Hoisting of global value loads.
+//!
+//! The legalizer expands each `global_value` instruction of a `load` global value into the whole
+//! chain of loads computing it, at the position of the instruction. GVN only merges the chains
+//! that dominate each other, so a global value used in several blocks is loaded again in each of
+//! them.
+//!
+//! This pass runs before legalization and replaces all the `global_value` instructions of a
+//! `readonly` load global value with a single one, placed at the common dominator of the uses.
+//! The legalizer then expands the chain of loads once, and LICM can move it out of loops. A load
+//! global value that isn't `readonly`, like the bound of a dynamic heap that may grow, can change
+//! between its uses and is left alone.
+
+use cursor::{Cursor, FuncCursor};
+use dominator_tree::DominatorTree;
+use entity::SecondaryMap;
+use flowgraph::BasicBlock;
+use ir::{Function, GlobalValue, GlobalValueData, Inst, InstBuilder, InstructionData, Opcode};
+use std::vec::Vec;
+use timing;
+
+/// Hoist the loads of the `readonly` load global values of `func` to the common dominator of
+/// their uses.
+pub fn do_hoist_global_value_loads(func: &mut Function, domtree: &DominatorTree) {
+    let _tt = timing::hoist_global_value_loads();
+    debug_assert!(domtree.is_valid());
+
+    // The `global_value` instructions using each global value, in layout order.
+    let mut uses: SecondaryMap<GlobalValue, Vec<Inst>> = SecondaryMap::new();
+    for ebb in func.layout.ebbs() {
+        if !domtree.is_reachable(ebb) {
+            continue;
+        }
+        for inst in func.layout.ebb_insts(ebb) {
+            if let InstructionData::UnaryGlobalValue {
+                opcode: Opcode::GlobalValue,
+                global_value,
+            } = func.dfg[inst]
+            {
+                uses[global_value].push(inst);
+            }
+        }
+    }
+
+    for (gv, insts) in uses.iter() {
+        if insts.len() < 2 || !is_hoistable_load(func, gv) {
+            continue;
+        }
+
+        let dom = insts[1..]
+            .iter()
+            .fold(basic_block(func, insts[0]), |dom, &inst| {
+                domtree.common_dominator(dom, basic_block(func, inst), &func.layout)
+            });
+
+        // Reuse the dominating use when there is one, otherwise insert a new instruction.
+        let hoisted = if insts.contains(&dom.inst) {
+            dom.inst
+        } else {
+            let ty = func.dfg.ctrl_typevar(insts[0]);
+            let mut pos = FuncCursor::new(func).at_inst(dom.inst);
+            let value = pos.ins().global_value(ty, gv);
+            pos.func.dfg.value_def(value).unwrap_inst()
+        };
+        let value = func.dfg.first_result(hoisted);
+
+        let mut pos = FuncCursor::new(func);
+        for &inst in insts {
+            if inst != hoisted {
+                pos.remove_and_replace_with_alias(inst, value);
+            }
+        }
+    }
+}
+
+/// Get the basic block containing `inst`.
+fn basic_block(func: &Function, inst: Inst) -> BasicBlock {
+    BasicBlock::new(
+        func.layout
+            .inst_ebb(inst)
+            .expect("Instruction not in layout"),
+        inst,
+    )
+}
+
+/// Is `gv` a load global value computed with `readonly` loads only?
+fn is_hoistable_load(func: &Function, gv: GlobalValue) -> bool {
+    match func.global_values[gv] {
+        GlobalValueData::Load { .. } => is_invariant(func, gv),
+        _ => false,
+    }
+}
+
+/// Does the value of `gv` stay the same during the execution of the function?
+fn is_invariant(func: &Function, gv: GlobalValue) -> bool {
+    match func.global_values[gv] {
+        GlobalValueData::Load { base, readonly, .. } => readonly && is_invariant(func, base),
+        GlobalValueData::IAddImm { base, .. } => is_invariant(func, base),
+        GlobalValueData::IAddScaled { base, index, .. } => {
+            is_invariant(func, base) && is_invariant(func, index)
+        }
+        GlobalValueData::VMContext | GlobalValueData::Symbol { .. } => true,
+    }
+}
@@ -0,0 +1,2088 @@
+//! Compact binary encoding of Cranelift IR functions.
+//!
+//! The binary format is an alternative to the textual `.clif` format which is much faster to read
+//! back. It is intended for embedders that cache IR between runs, so it makes no attempt at being
+//! stable across Cranelift versions: every encoded function starts with a format version and a hash
+//! of the instruction set descriptor, and data written by a different version is rejected instead
+//! of being misinterpreted.
+//!
+//! The encoding preserves all entity numbers, so a decoded function prints exactly like the
+//! original. Only the target-independent parts of the function are encoded. Instruction
+//! encodings, value locations, and code offsets are computed during compilation and are not
+//! preserved.
+//!
+//! Integers are encoded as LEB128 varints, with signed integers zig-zag encoded first. Entity
+//! references are encoded as their index. External names are collected in a table at the start of
+//! the function and referenced by index.
+
+use entity::EntityRef;
+use ir::condcodes::{FloatCC, IntCC};
+use ir::immediates::{Ieee32, Ieee64, Imm64, Offset32, Uimm32};
+use ir::instructions::{InstructionFormat, INSTRUCTION_SET_HASH};
+use ir::stackslot::{StackOffset, StackSize};
+use ir::{
+    types, AbiParam, ArgumentExtension, ArgumentLoc, ArgumentPurpose, BranchHint, Ebb, ExtFuncData,
+    ExternalName, FuncRef, Function, GlobalValue, GlobalValueData, Heap, HeapData, HeapStyle, Inst,
+    InstructionData, JumpTable, JumpTableData, LibCall, MemFlags, Opcode, SigRef, Signature,
+    SourceLoc, StackSlot, StackSlotData, StackSlotKind, Table, TableData, TrapCode, Type, Value,
+    ValueList,
+};
+use packed_option::ReservedValue;
+use settings::CallConv;
+use std::str;
+use std::string::ToString;
+use std::vec::Vec;
+
+/// Magic bytes at the start of every encoded function.
+const MAGIC: &[u8; 4] = b"CLIR";
+
+/// Version of the binary IR format.
+///
+/// This must be bumped whenever the encoding changes in a way that isn't captured by the
+/// instruction set hash.
+pub const BINARY_FORMAT_VERSION: u32 = 11;
+
+/// An error encountered while decoding a function in the binary IR format.
+#[derive(Fail, Debug, PartialEq, Eq)]
+pub enum BinaryError {
+    /// The data doesn't start with the binary IR magic bytes.
+    #[fail(display = "Not a binary IR function")]
+    BadMagic,
+
+    /// The data was written with an unsupported version of the binary IR format.
+    #[fail(display = "Unsupported binary IR format version {}", _0)]
+    UnsupportedVersion(u32),
+
+    /// The data was written with a different instruction set.
+    #[fail(display = "Binary IR was written for a different instruction set")]
+    InstructionSetMismatch,
+
+    /// The data ended prematurely.
+    #[fail(display = "Unexpected end of binary IR data")]
+    UnexpectedEof,
+
+    /// The data is malformed.
+    #[fail(display = "Malformed binary IR: {}", _0)]
+    Malformed(&'static str),
+}
+
+/// A convenient alias for a `Result` that uses `BinaryError` as the error type.
+pub type BinaryResult<T> = Result<T, BinaryError>;
+
+// Tables for decoding enums that are encoded as `x as u8`.
+
+const INT_CCS: [IntCC; 10] = [
+    IntCC::Equal,
+    IntCC::NotEqual,
+    IntCC::SignedLessThan,
+    IntCC::SignedGreaterThanOrEqual,
+    IntCC::SignedGreaterThan,
+    IntCC::SignedLessThanOrEqual,
+    IntCC::UnsignedLessThan,
+    IntCC::UnsignedGreaterThanOrEqual,
+    IntCC::UnsignedGreaterThan,
+    IntCC::UnsignedLessThanOrEqual,
+];
+
+const FLOAT_CCS: [FloatCC; 14] = [
+    FloatCC::Ordered,
+    FloatCC::Unordered,
+    FloatCC::Equal,
+    FloatCC::NotEqual,
+    FloatCC::OrderedNotEqual,
+    FloatCC::UnorderedOrEqual,
+    FloatCC::LessThan,
+    FloatCC::LessThanOrEqual,
+    FloatCC::GreaterThan,
+    FloatCC::GreaterThanOrEqual,
+    FloatCC::UnorderedOrLessThan,
+    FloatCC::UnorderedOrLessThanOrEqual,
+    FloatCC::UnorderedOrGreaterThan,
+    FloatCC::UnorderedOrGreaterThanOrEqual,
+];
+
+const CALL_CONVS: [CallConv; 7] = [
+    CallConv::Fast,
+    CallConv::Cold,
+    CallConv::SystemV,
+    CallConv::WindowsFastcall,
+    CallConv::Baldrdash,
+    CallConv::Probestack,
+    CallConv::Raw,
+];
+
+const PURPOSES: [ArgumentPurpose; 8] = [
+    ArgumentPurpose::Normal,
+    ArgumentPurpose::StructReturn,
+    ArgumentPurpose::Link,
+    ArgumentPurpose::FramePointer,
+    ArgumentPurpose::CalleeSaved,
+    ArgumentPurpose::VMContext,
+    ArgumentPurpose::SignatureId,
+    ArgumentPurpose::StackLimit,
+];
+const STRUCT_ARGUMENT_PURPOSE: u8 = 0xff;
+
+const EXTENSIONS: [ArgumentExtension; 3] = [
+    ArgumentExtension::None,
+    ArgumentExtension::Uext,
+    ArgumentExtension::Sext,
+];
+
+const STACK_SLOT_KINDS: [StackSlotKind; 5] = [
+    StackSlotKind::SpillSlot,
+    StackSlotKind::ExplicitSlot,
+    StackSlotKind::IncomingArg,
+    StackSlotKind::OutgoingArg,
+    StackSlotKind::EmergencySlot,
+];
+
+/// Trap codes other than `TrapCode::User`, which is encoded as `USER_TRAP_CODE` followed by
+/// the user code.
+const TRAP_CODES: [TrapCode; 11] = [
+    TrapCode::StackOverflow,
+    TrapCode::HeapOutOfBounds,
+    TrapCode::TableOutOfBounds,
+    TrapCode::OutOfBounds,
+    TrapCode::IndirectCallToNull,
+    TrapCode::BadSignature,
+    TrapCode::IntegerOverflow,
+    TrapCode::IntegerDivisionByZero,
+    TrapCode::BadConversionToInteger,
+    TrapCode::Interrupt,
+    TrapCode::HeapMisaligned,
+];
+const USER_TRAP_CODE: u8 = 0xff;
+
+/// Scalar lane types in the order of their type numbers.
+const LANE_TYPES: [Type; 11] = [
+    types::B1,
+    types::B8,
+    types::B16,
+    types::B32,
+    types::B64,
+    types::I8,
+    types::I16,
+    types::I32,
+    types::I64,
+    types::F32,
+    types::F64,
+];
+
+// Tags for tagged unions.
+const NAME_USER: u8 = 0;
+const NAME_TESTCASE: u8 = 1;
+const NAME_LIBCALL: u8 = 2;
+const NAME_SYMBOL: u8 = 3;
+
+const LOC_UNASSIGNED: u8 = 0;
+const LOC_REG: u8 = 1;
+const LOC_STACK: u8 = 2;
+
+const GV_VMCTX: u8 = 0;
+const GV_LOAD: u8 = 1;
+const GV_IADD_IMM: u8 = 2;
+const GV_SYMBOL: u8 = 3;
+const GV_IADD_SCALED: u8 = 4;
+
+const HEAP_DYNAMIC: u8 = 0;
+const HEAP_STATIC: u8 = 1;
+
+const VALUE_UNUSED: u8 = 0;
+const VALUE_DEFINED: u8 = 1;
+const VALUE_ALIAS: u8 = 2;
+
+/// Encode `func` in the binary IR format, appending the bytes to `out`.
+pub fn write_function(func: &Function, out: &mut Vec<u8>) {
+    let mut w = Writer {
+        out,
+        names: Vec::new(),
+    };
+    w.bytes(MAGIC);
+    w.varint(u64::from(BINARY_FORMAT_VERSION));
+    w.u64(INSTRUCTION_SET_HASH);
+    w.name_table(func);
+    w.function(func);
+}
+
+/// Decode a function in the binary IR format from `data`.
+///
+/// This never panics, even if `data` is garbage. The decoded function is structurally sound, so
+/// it can be printed and verified, but it is not guaranteed to pass the verifier.
+pub fn read_function(data: &[u8]) -> BinaryResult<Function> {
+    let mut r = Reader {
+        data,
+        pos: 0,
+        names: Vec::new(),
+    };
+    if r.bytes(MAGIC.len())? != MAGIC {
+        return Err(BinaryError::BadMagic);
+    }
+    let version = r.u32()?;
+    if version != BINARY_FORMAT_VERSION {
+        return Err(BinaryError::UnsupportedVersion(version));
+    }
+    if r.u64()? != INSTRUCTION_SET_HASH {
+        return Err(BinaryError::InstructionSetMismatch);
+    }
+    r.name_table()?;
+    let func = r.function()?;
+    if r.pos != r.data.len() {
+        return Err(BinaryError::Malformed("trailing data"));
+    }
+    Ok(func)
+}
+
+/// Encoding state.
+struct Writer<'a> {
+    out: &'a mut Vec<u8>,
+    /// External names in the order they appear in the name table.
+    names: Vec<ExternalName>,
+}
+
+impl<'a> Writer<'a> {
+    fn byte(&mut self, b: u8) {
+        self.out.push(b);
+    }
+
+    fn bytes(&mut self, b: &[u8]) {
+        self.out.extend_from_slice(b);
+    }
+
+    fn varint(&mut self, mut x: u64) {
+        while x >= 0x80 {
+            self.out.push(x as u8 | 0x80);
+            x >>= 7;
+        }
+        self.out.push(x as u8);
+    }
+
+    fn zigzag(&mut self, x: i64) {
+        self.varint(((x << 1) ^ (x >> 63)) as u64);
+    }
+
+    fn u64(&mut self, x: u64) {
+        for i in 0..8 {
+            self.out.push((x >> (8 * i)) as u8);
+        }
+    }
+
+    fn count(&mut self, n: usize) {
+        self.varint(n as u64);
+    }
+
+    fn bool(&mut self, b: bool) {
+        self.byte(b as u8);
+    }
+
+    fn entity<E: EntityRef>(&mut self, e: E) {
+        self.count(e.index());
+    }
+
+    /// Write an entity reference which may be the reserved value.
+    ///
+    /// The parser uses the reserved value in the placeholder entities it creates to pad out the
+    /// entity numbering.
+    fn packed_entity<E: EntityRef + ReservedValue>(&mut self, e: E) {
+        if e == E::reserved_value() {
+            self.byte(0);
+        } else {
+            self.count(e.index() + 1);
+        }
+    }
+
+    fn ty(&mut self, ty: Type) {
+        self.byte(ty.index() as u8);
+    }
+
+    fn imm64(&mut self, imm: Imm64) {
+        self.zigzag(imm.into());
+    }
+
+    fn offset32(&mut self, offset: Offset32) {
+        let x: i32 = offset.into();
+        self.zigzag(i64::from(x));
+    }
+
+    fn memflags(&mut self, flags: MemFlags) {
+        self.byte(flags.notrap() as u8 | (flags.aligned() as u8) << 1);
+    }
+
+    fn trap_code(&mut self, code: TrapCode) {
+        match code {
+            TrapCode::User(x) => {
+                self.byte(USER_TRAP_CODE);
+                self.varint(u64::from(x));
+            }
+            _ => {
+                let idx = TRAP_CODES
+                    .iter()
+                    .position(|&c| c == code)
+                    .expect("missing trap code in binary IR table");
+                self.byte(idx as u8);
+            }
+        }
+    }
+
+    fn trap_payload(&mut self, payload: Uimm32) {
+        let payload: u32 = payload.into();
+        self.varint(u64::from(payload));
+    }
+
+    fn value_list(&mut self, func: &Function, list: &ValueList) {
+        let values = list.as_slice(&func.dfg.value_lists);
+        self.count(values.len());
+        for &v in values {
+            self.entity(v);
+        }
+    }
+
+    /// Collect all the external names referenced by `func` and write the name table.
+    fn name_table(&mut self, func: &Function) {
+        self.intern(&func.name);
+        for gv in func.global_values.values() {
+            if let GlobalValueData::Symbol { ref name, .. } = *gv {
+                self.intern(name);
+            }
+        }
+        for ext in func.dfg.ext_funcs.values() {
+            self.intern(&ext.name);
+        }
+
+        let names = ::std::mem::replace(&mut self.names, Vec::new());
+        self.count(names.len());
+        for name in &names {
+            match *name {
+                ExternalName::User { namespace, index } => {
+                    self.byte(NAME_USER);
+                    self.varint(u64::from(namespace));
+                    self.varint(u64::from(index));
+                }
+                ExternalName::TestCase { length, ref ascii } => {
+                    self.byte(NAME_TESTCASE);
+                    self.count(usize::from(length));
+                    self.bytes(&ascii[0..usize::from(length)]);
+                }
+                ExternalName::LibCall(lc) => {
+                    let s = lc.to_string();
+                    self.byte(NAME_LIBCALL);
+                    self.count(s.len());
+                    self.bytes(s.as_bytes());
+                }
+                ExternalName::Symbol(ref s) => {
+                    self.byte(NAME_SYMBOL);
+                    self.count(s.len());
+                    self.bytes(s.as_bytes());
+                }
+            }
+        }
+        self.names = names;
+    }
+
+    fn intern(&mut self, name: &ExternalName) {
+        if !self.names.contains(name) {
+            self.names.push(name.clone());
+        }
+    }
+
+    fn name(&mut self, name: &ExternalName) {
+        let idx = self
+            .names
+            .iter()
+            .position(|n| n == name)
+            .expect("name missing from the name table");
+        self.count(idx);
+    }
+
+    fn signature(&mut self, sig: &Signature) {
+        self.byte(sig.call_conv as u8);
+        self.count(sig.params.len());
+        for param in &sig.params {
+            self.abi_param(param);
+        }
+        self.count(sig.returns.len());
+        for ret in &sig.returns {
+            self.abi_param(ret);
+        }
+    }
+
+    fn abi_param(&mut self, param: &AbiParam) {
+        self.ty(param.value_type);
+        match param.purpose {
+            ArgumentPurpose::StructArgument(size) => {
+                self.byte(STRUCT_ARGUMENT_PURPOSE);
+                self.varint(u64::from(size));
+            }
+            purpose => {
+                let idx = PURPOSES
+                    .iter()
+                    .position(|&p| p == purpose)
+                    .expect("missing argument purpose in binary IR table");
+                self.byte(idx as u8);
+            }
+        }
+        self.byte(param.extension as u8);
+        match param.location {
+            ArgumentLoc::Unassigned => self.byte(LOC_UNASSIGNED),
+            ArgumentLoc::Reg(reg) => {
+                self.byte(LOC_REG);
+                self.varint(u64::from(reg));
+            }
+            ArgumentLoc::Stack(offset) => {
+                self.byte(LOC_STACK);
+                self.zigzag(i64::from(offset));
+            }
+        }
+        self.bool(param.legalized_to_pointer);
+    }
+
+    fn function(&mut self, func: &Function) {
+        self.name(&func.name);
+        self.signature(&func.signature);
+        self.ty(func.pointer_type.unwrap_or(types::INVALID));
+
+        self.count(func.dfg.signatures.len());
+        for sig in func.dfg.signatures.values() {
+            self.signature(sig);
+        }
+
+        self.count(func.stack_slots.keys().len());
+        for ss in func.stack_slots.values() {
+            self.byte(ss.kind as u8);
+            self.varint(u64::from(ss.size));
+            self.opt_zigzag(ss.offset.map(i64::from));
+        }
+        self.opt_zigzag(func.stack_slots.frame_size.map(i64::from));
+
+        self.count(func.global_values.len());
+        for gv in func.global_values.values() {
+            self.global_value(gv);
+        }
+
+        self.count(func.heaps.len());
+        for heap in func.heaps.values() {
+            self.packed_entity(heap.base);
+            self.imm64(heap.min_size);
+            self.imm64(heap.guard_size);
+            match heap.style {
+                HeapStyle::Dynamic { bound_gv } => {
+                    self.byte(HEAP_DYNAMIC);
+                    self.packed_entity(bound_gv);
+                }
+                HeapStyle::Static { bound } => {
+                    self.byte(HEAP_STATIC);
+                    self.imm64(bound);
+                }
+            }
+            self.ty(heap.index_type);
+            self.bool(heap.check_alignment);
+        }
+
+        self.count(func.tables.len());
+        for table in func.tables.values() {
+            self.packed_entity(table.base_gv);
+            self.imm64(table.min_size);
+            self.packed_entity(table.bound_gv);
+            self.imm64(table.element_size);
+            self.ty(table.index_type);
+        }
+
+        self.count(func.dfg.ext_funcs.len());
+        for ext in func.dfg.ext_funcs.values() {
+            self.name(&ext.name);
+            self.packed_entity(ext.signature);
+            self.bool(ext.colocated);
+        }
+
+        self.values(func);
+
+        let num_ebbs = func.dfg.num_ebbs();
+        self.count(num_ebbs);
+        for ebb in (0..num_ebbs).map(Ebb::new) {
+            let params = func.dfg.ebb_params(ebb);
+            self.count(params.len());
+            for &v in params {
+                self.entity(v);
+            }
+        }
+
+        self.count(func.jump_tables.len());
+        for jt in func.jump_tables.values() {
+            self.count(jt.len());
+            for &entry in jt.as_slice() {
+                self.packed_entity(entry.expand().unwrap_or_else(Ebb::reserved_value));
+            }
+        }
+
+        let num_insts = func.dfg.num_insts();
+        self.count(num_insts);
+        for inst in (0..num_insts).map(Inst::new) {
+            self.inst(func, inst);
+        }
+
+        self.count(func.layout.ebbs().count());
+        for ebb in func.layout.ebbs() {
+            self.entity(ebb);
+            self.count(func.layout.ebb_insts(ebb).count());
+            for inst in func.layout.ebb_insts(ebb) {
+                self.entity(inst);
+            }
+        }
+
+        let srclocs = (0..num_insts)
+            .map(Inst::new)
+            .filter(|&inst| !func.srclocs[inst].is_default());
+        self.count(srclocs.clone().count());
+        for inst in srclocs {
+            self.entity(inst);
+            self.varint(u64::from(func.srclocs[inst].bits()));
+        }
+
+        let hints = (0..num_insts)
+            .map(Inst::new)
+            .filter(|&inst| func.branch_hints[inst].is_some());
+        self.count(hints.clone().count());
+        for inst in hints {
+            self.entity(inst);
+            self.bool(func.branch_hints[inst] == Some(BranchHint::Likely));
+        }
+
+        let patchable = (0..num_insts)
+            .map(Inst::new)
+            .filter(|&inst| func.is_patchable(inst));
+        self.count(patchable.clone().count());
+        for inst in patchable {
+            self.entity(inst);
+        }
+    }
+
+    fn opt_zigzag(&mut self, x: Option<i64>) {
+        match x {
+            None => self.byte(0),
+            Some(x) => {
+                self.byte(1);
+                self.zigzag(x);
+            }
+        }
+    }
+
+    fn global_value(&mut self, gv: &GlobalValueData) {
+        match *gv {
+            GlobalValueData::VMContext => self.byte(GV_VMCTX),
+            GlobalValueData::Load {
+                base,
+                offset,
+                global_type,
+                readonly,
+            } => {
+                self.byte(GV_LOAD);
+                self.entity(base);
+                self.offset32(offset);
+                self.ty(global_type);
+                self.bool(readonly);
+            }
+            GlobalValueData::IAddImm {
+                base,
+                offset,
+                global_type,
+            } => {
+                self.byte(GV_IADD_IMM);
+                self.entity(base);
+                self.imm64(offset);
+                self.ty(global_type);
+            }
+            GlobalValueData::IAddScaled {
+                base,
+                index,
+                scale,
+                global_type,
+            } => {
+                self.byte(GV_IADD_SCALED);
+                self.entity(base);
+                self.entity(index);
+                self.imm64(scale);
+                self.ty(global_type);
+            }
+            GlobalValueData::Symbol {
+                ref name,
+                offset,
+                colocated,
+            } => {
+                self.byte(GV_SYMBOL);
+                self.name(name);
+                self.imm64(offset);
+                self.bool(colocated);
+            }
+        }
+    }
+
+    /// Write the value table.
+    ///
+    /// Each value is either unused, defined as an attached instruction result or EBB parameter,
+    /// or an alias. The instruction and EBB records below say where defined values are attached.
+    fn values(&mut self, func: &Function) {
+        let dfg = &func.dfg;
+        self.count(dfg.num_values());
+        for v in (0..dfg.num_values()).map(Value::new) {
+            match dfg.value_alias_dest_for_serialization(v) {
+                // The parser pads the value numbering with aliases of the reserved value.
+                Some(original) if original == Value::reserved_value() => self.byte(VALUE_UNUSED),
+                Some(original) => {
+                    self.byte(VALUE_ALIAS);
+                    self.entity(original);
+                }
+                None if dfg.value_is_attached(v) => {
+                    self.byte(VALUE_DEFINED);
+                    self.ty(dfg.value_type(v));
+                }
+                None => self.byte(VALUE_UNUSED),
+            }
+        }
+    }
+
+    fn inst(&mut self, func: &Function, inst: Inst) {
+        let dfg = &func.dfg;
+        let data = &dfg[inst];
+        let opcode = data.opcode();
+        self.byte(opcode as u8);
+
+        // Compute the controlling type variable without assuming the instruction is well formed,
+        // unlike `dfg.ctrl_typevar()`. Detached instructions may have lost their results.
+        let constraints = opcode.constraints();
+        let ctrl_typevar = if !constraints.is_polymorphic() {
+            types::INVALID
+        } else if constraints.requires_typevar_operand() {
+            data.typevar_operand(&dfg.value_lists)
+                .map_or(types::INVALID, |v| dfg.value_type(v))
+        } else {
+            dfg.inst_results(inst)
+                .first()
+                .map_or(types::INVALID, |&v| dfg.value_type(v))
+        };
+        self.ty(ctrl_typevar);
+
+        match *data {
+            InstructionData::Unary { arg, .. } => self.entity(arg),
+            InstructionData::UnaryImm { imm, .. } => self.imm64(imm),
+            InstructionData::UnaryIeee32 { imm, .. } => self.varint(u64::from(imm.bits())),
+            InstructionData::UnaryIeee64 { imm, .. } => self.u64(imm.bits()),
+            InstructionData::UnaryBool { imm, .. } => self.bool(imm),
+            InstructionData::UnaryGlobalValue { global_value, .. } => self.entity(global_value),
+            InstructionData::Binary { args, .. } => self.values_array(&args),
+            InstructionData::BinaryImm { arg, imm, .. } => {
+                self.entity(arg);
+                self.imm64(imm);
+            }
+            InstructionData::Ternary { args, .. } => self.values_array(&args),
+            InstructionData::MultiAry { ref args, .. } => self.value_list(func, args),
+            InstructionData::NullAry { .. } => {}
+            InstructionData::InsertLane { args, lane, .. } => {
+                self.values_array(&args);
+                self.byte(lane);
+            }
+            InstructionData::ExtractLane { arg, lane, .. } => {
+                self.entity(arg);
+                self.byte(lane);
+            }
+            InstructionData::IntCompare { args, cond, .. } => {
+                self.values_array(&args);
+                self.byte(cond as u8);
+            }
+            InstructionData::IntCompareImm { arg, cond, imm, .. } => {
+                self.entity(arg);
+                self.byte(cond as u8);
+                self.imm64(imm);
+            }
+            InstructionData::IntCond { arg, cond, .. } => {
+                self.entity(arg);
+                self.byte(cond as u8);
+            }
+            InstructionData::FloatCompare { args, cond, .. } => {
+                self.values_array(&args);
+                self.byte(cond as u8);
+            }
+            InstructionData::FloatCond { arg, cond, .. } => {
+                self.entity(arg);
+                self.byte(cond as u8);
+            }
+            InstructionData::IntSelect { args, cond, .. } => {
+                self.values_array(&args);
+                self.byte(cond as u8);
+            }
+            InstructionData::Jump {
+                ref args,
+                destination,
+                ..
+            }
+            | InstructionData::Branch {
+                ref args,
+                destination,
+                ..
+            } => {
+                self.value_list(func, args);
+                self.entity(destination);
+            }
+            InstructionData::BranchInt {
+                ref args,
+                cond,
+                destination,
+                ..
+            }
+            | InstructionData::BranchIcmp {
+                ref args,
+                cond,
+                destination,
+                ..
+            } => {
+                self.value_list(func, args);
+                self.byte(cond as u8);
+                self.entity(destination);
+            }
+            InstructionData::BranchFloat {
+                ref args,
+                cond,
+                destination,
+                ..
+            } => {
+                self.value_list(func, args);
+                self.byte(cond as u8);
+                self.entity(destination);
+            }
+            InstructionData::BranchTable {
+                arg,
+                destination,
+                table,
+                ..
+            } => {
+                self.entity(arg);
+                self.entity(destination);
+                self.entity(table);
+            }
+            InstructionData::BranchTableEntry {
+                args, imm, table, ..
+            } => {
+                self.values_array(&args);
+                self.byte(imm);
+                self.entity(table);
+            }
+            InstructionData::BranchTableBase { table, .. } => self.entity(table),
+            InstructionData::IndirectJump { arg, table, .. } => {
+                self.entity(arg);
+                self.entity(table);
+            }
+            InstructionData::Call {
+                ref args, func_ref, ..
+            } => {
+                self.value_list(func, args);
+                self.entity(func_ref);
+            }
+            InstructionData::CallIndirect {
+                ref args, sig_ref, ..
+            } => {
+                self.value_list(func, args);
+                self.entity(sig_ref);
+            }
+            InstructionData::FuncAddr { func_ref, .. } => self.entity(func_ref),
+            InstructionData::Load {
+                arg, flags, offset, ..
+            } => {
+                self.entity(arg);
+                self.memflags(flags);
+                self.offset32(offset);
+            }
+            InstructionData::LoadComplex {
+                ref args,
+                flags,
+                offset,
+                ..
+            }
+            | InstructionData::StoreComplex {
+                ref args,
+                flags,
+                offset,
+                ..
+            } => {
+                self.value_list(func, args);
+                self.memflags(flags);
+                self.offset32(offset);
+            }
+            InstructionData::Store {
+                args,
+                flags,
+                offset,
+                ..
+            } => {
+                self.values_array(&args);
+                self.memflags(flags);
+                self.offset32(offset);
+            }
+            InstructionData::LoadSymbol {
+                arg,
+                flags,
+                global_value,
+                offset,
+                ..
+            } => {
+                self.entity(arg);
+                self.memflags(flags);
+                self.entity(global_value);
+                self.offset32(offset);
+            }
+            InstructionData::LoadGlobal {
+                flags,
+                global_value,
+                offset,
+                ..
+            } => {
+                self.memflags(flags);
+                self.entity(global_value);
+                self.offset32(offset);
+            }
+            InstructionData::StoreSymbol {
+                ref args,
+                flags,
+                global_value,
+                offset,
+                ..
+            } => {
+                self.value_list(func, args);
+                self.memflags(flags);
+                self.entity(global_value);
+                self.offset32(offset);
+            }
+            InstructionData::StackLoad {
+                stack_slot, offset, ..
+            } => {
+                self.entity(stack_slot);
+                self.offset32(offset);
+            }
+            InstructionData::StackStore {
+                arg,
+                stack_slot,
+                offset,
+                ..
+            } => {
+                self.entity(arg);
+                self.entity(stack_slot);
+                self.offset32(offset);
+            }
+            InstructionData::HeapAddr { arg, heap, imm, .. } => {
+                self.entity(arg);
+                self.entity(heap);
+                let imm: u32 = imm.into();
+                self.varint(u64::from(imm));
+            }
+            InstructionData::TableAddr {
+                arg, table, offset, ..
+            } => {
+                self.entity(arg);
+                self.entity(table);
+                self.offset32(offset);
+            }
+            InstructionData::RegMove { arg, src, dst, .. } => {
+                self.entity(arg);
+                self.varint(u64::from(src));
+                self.varint(u64::from(dst));
+            }
+            InstructionData::CopySpecial { src, dst, .. } => {
+                self.varint(u64::from(src));
+                self.varint(u64::from(dst));
+            }
+            InstructionData::RegSpill { arg, src, dst, .. } => {
+                self.entity(arg);
+                self.varint(u64::from(src));
+                self.entity(dst);
+            }
+            InstructionData::RegFill { arg, src, dst, .. } => {
+                self.entity(arg);
+                self.entity(src);
+                self.varint(u64::from(dst));
+            }
+            InstructionData::Trap { code, payload, .. } => {
+                self.trap_code(code);
+                self.trap_payload(payload);
+            }
+            InstructionData::CondTrap {
+                arg, code, payload, ..
+            } => {
+                self.entity(arg);
+                self.trap_code(code);
+                self.trap_payload(payload);
+            }
+            InstructionData::IntCondTrap {
+                arg,
+                cond,
+                code,
+                payload,
+                ..
+            } => {
+                self.entity(arg);
+                self.byte(cond as u8);
+                self.trap_code(code);
+                self.trap_payload(payload);
+            }
+            InstructionData::FloatCondTrap {
+                arg,
+                cond,
+                code,
+                payload,
+                ..
+            } => {
+                self.entity(arg);
+                self.byte(cond as u8);
+                self.trap_code(code);
+                self.trap_payload(payload);
+            }
+        }
+
+        let results = dfg.inst_results(inst);
+        self.count(results.len());
+        for &v in results {
+            self.entity(v);
+        }
+    }
+
+    fn values_array(&mut self, values: &[Value]) {
+        for &v in values {
+            self.entity(v);
+        }
+    }
+}
+
+/// Decoding state.
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    /// The decoded name table.
+    names: Vec<ExternalName>,
+}
+
+/// Entity counts of the function being decoded, used to validate entity references.
+struct Limits {
+    values: usize,
+    ebbs: usize,
+    sigs: usize,
+    funcs: usize,
+    gvs: usize,
+    stack_slots: usize,
+    heaps: usize,
+    tables: usize,
+    jump_tables: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn byte(&mut self) -> BinaryResult<u8> {
+        match self.data.get(self.pos) {
+            Some(&b) => {
+                self.pos += 1;
+                Ok(b)
+            }
+            None => Err(BinaryError::UnexpectedEof),
+        }
+    }
+
+    fn bytes(&mut self, n: usize) -> BinaryResult<&'a [u8]> {
+        if n > self.data.len() - self.pos {
+            return Err(BinaryError::UnexpectedEof);
+        }
+        let b = &self.data[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(b)
+    }
+
+    fn varint(&mut self) -> BinaryResult<u64> {
+        let mut x = 0u64;
+        let mut shift = 0;
+        loop {
+            let b = self.byte()?;
+            if shift == 63 && b > 1 {
+                return Err(BinaryError::Malformed("varint overflow"));
+            }
+            x |= u64::from(b & 0x7f) << shift;
+            if b & 0x80 == 0 {
+                return Ok(x);
+            }
+            shift += 7;
+        }
+    }
+
+    fn zigzag(&mut self) -> BinaryResult<i64> {
+        let x = self.varint()?;
+        Ok((x >> 1) as i64 ^ -((x & 1) as i64))
+    }
+
+    fn u16(&mut self) -> BinaryResult<u16> {
+        let x = self.varint()?;
+        if x > u64::from(u16::max_value()) {
+            return Err(BinaryError::Malformed("integer out of range"));
+        }
+        Ok(x as u16)
+    }
+
+    fn u32(&mut self) -> BinaryResult<u32> {
+        let x = self.varint()?;
+        if x > u64::from(u32::max_value()) {
+            return Err(BinaryError::Malformed("integer out of range"));
+        }
+        Ok(x as u32)
+    }
+
+    fn i32(&mut self) -> BinaryResult<i32> {
+        let x = self.zigzag()?;
+        if x < i64::from(i32::min_value()) || x > i64::from(i32::max_value()) {
+            return Err(BinaryError::Malformed("integer out of range"));
+        }
+        Ok(x as i32)
+    }
+
+    fn u64(&mut self) -> BinaryResult<u64> {
+        let b = self.bytes(8)?;
+        Ok(b.iter()
+            .rev()
+            .fold(0, |acc, &byte| (acc << 8) | u64::from(byte)))
+    }
+
+    /// Read the number of items in a list.
+    ///
+    /// Every item in a list occupies at least one byte, so this rejects counts that can't possibly
+    /// fit in the remaining data. This prevents huge allocations for garbage inputs.
+    fn count(&mut self) -> BinaryResult<usize> {
+        let n = self.varint()?;
+        if n > (self.data.len() - self.pos) as u64 {
+            return Err(BinaryError::Malformed(
+                "list is longer than the remaining data",
+            ));
+        }
+        Ok(n as usize)
+    }
+
+    fn bool(&mut self) -> BinaryResult<bool> {
+        match self.byte()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(BinaryError::Malformed("invalid boolean")),
+        }
+    }
+
+    /// Read an entity reference, checking that it is less than `limit`.
+    fn entity<E: EntityRef>(&mut self, limit: usize) -> BinaryResult<E> {
+        let idx = self.varint()?;
+        if idx >= limit as u64 {
+            return Err(BinaryError::Malformed("entity reference out of range"));
+        }
+        Ok(E::new(idx as usize))
+    }
+
+    /// Read an entity reference written by `Writer::packed_entity()`.
+    fn packed_entity<E: EntityRef + ReservedValue>(&mut self, limit: usize) -> BinaryResult<E> {
+        match self.varint()? {
+            0 => Ok(E::reserved_value()),
+            idx if idx <= limit as u64 => Ok(E::new(idx as usize - 1)),
+            _ => Err(BinaryError::Malformed("entity reference out of range")),
+        }
+    }
+
+    /// Read an element from `table`.
+    fn table<T: Copy>(&mut self, table: &[T], what: &'static str) -> BinaryResult<T> {
+        let idx = self.byte()?;
+        table
+            .get(usize::from(idx))
+            .cloned()
+            .ok_or(BinaryError::Malformed(what))
+    }
+
+    fn ty(&mut self) -> BinaryResult<Type> {
+        let b = self.byte()?;
+        let ty = match b {
+            0 => Some(types::INVALID),
+            1 => Some(types::IFLAGS),
+            2 => Some(types::FFLAGS),
+            _ if b >= 0x70 => LANE_TYPES
+                .get(usize::from(b & 0xf))
+                .and_then(|lane| lane.by(1 << ((b - 0x70) >> 4))),
+            _ => None,
+        };
+        match ty {
+            Some(ty) if ty.index() == usize::from(b) => Ok(ty),
+            _ => Err(BinaryError::Malformed("invalid type")),
+        }
+    }
+
+    /// Read a type that isn't `INVALID`.
+    fn valid_ty(&mut self) -> BinaryResult<Type> {
+        match self.ty()? {
+            types::INVALID => Err(BinaryError::Malformed("invalid type")),
+            ty => Ok(ty),
+        }
+    }
+
+    fn imm64(&mut self) -> BinaryResult<Imm64> {
+        self.zigzag().map(Imm64::new)
+    }
+
+    fn offset32(&mut self) -> BinaryResult<Offset32> {
+        self.i32().map(Offset32::new)
+    }
+
+    fn memflags(&mut self) -> BinaryResult<MemFlags> {
+        let bits = self.byte()?;
+        if bits > 3 {
+            return Err(BinaryError::Malformed("invalid memory flags"));
+        }
+        let mut flags = MemFlags::new();
+        if bits & 1 != 0 {
+            flags.set_notrap();
+        }
+        if bits & 2 != 0 {
+            flags.set_aligned();
+        }
+        Ok(flags)
+    }
+
+    fn trap_code(&mut self) -> BinaryResult<TrapCode> {
+        match self.byte()? {
+            USER_TRAP_CODE => self.u16().map(TrapCode::User),
+            idx => TRAP_CODES
+                .get(usize::from(idx))
+                .cloned()
+                .ok_or(BinaryError::Malformed("invalid trap code")),
+        }
+    }
+
+    fn intcc(&mut self) -> BinaryResult<IntCC> {
+        self.table(&INT_CCS, "invalid integer condition code")
+    }
+
+    fn floatcc(&mut self) -> BinaryResult<FloatCC> {
+        self.table(&FLOAT_CCS, "invalid float condition code")
+    }
+
+    fn name_table(&mut self) -> BinaryResult<()> {
+        let n = self.count()?;
+        for _ in 0..n {
+            let name = match self.byte()? {
+                NAME_USER => ExternalName::user(self.u32()?, self.u32()?),
+                NAME_TESTCASE => {
+                    let len = self.count()?;
+                    if len > 16 {
+                        return Err(BinaryError::Malformed("test case name too long"));
+                    }
+                    ExternalName::testcase(self.bytes(len)?)
+                }
+                NAME_LIBCALL => {
+                    let len = self.count()?;
+                    let lc = str::from_utf8(self.bytes(len)?)
+                        .ok()
+                        .and_then(|s| s.parse::<LibCall>().ok())
+                        .ok_or(BinaryError::Malformed("unknown libcall"))?;
+                    ExternalName::LibCall(lc)
+                }
+                NAME_SYMBOL => {
+                    let len = self.count()?;
+                    let s = str::from_utf8(self.bytes(len)?)
+                        .map_err(|_| BinaryError::Malformed("symbol name isn't UTF-8"))?;
+                    ExternalName::symbol(s)
+                }
+                _ => return Err(BinaryError::Malformed("invalid external name")),
+            };
+            self.names.push(name);
+        }
+        Ok(())
+    }
+
+    fn name(&mut self) -> BinaryResult<ExternalName> {
+        let idx = self.varint()?;
+        self.names
+            .get(idx as usize)
+            .cloned()
+            .ok_or(BinaryError::Malformed("name reference out of range"))
+    }
+
+    fn signature(&mut self) -> BinaryResult<Signature> {
+        let mut sig = Signature::new(self.table(&CALL_CONVS, "invalid calling convention")?);
+        let n = self.count()?;
+        for _ in 0..n {
+            let param = self.abi_param()?;
+            sig.params.push(param);
+        }
+        let n = self.count()?;
+        for _ in 0..n {
+            let ret = self.abi_param()?;
+            sig.returns.push(ret);
+        }
+        Ok(sig)
+    }
+
+    fn abi_param(&mut self) -> BinaryResult<AbiParam> {
+        let mut param = AbiParam::new(self.valid_ty()?);
+        param.purpose = match self.byte()? {
+            STRUCT_ARGUMENT_PURPOSE => ArgumentPurpose::StructArgument(self.u32()?),
+            idx => PURPOSES
+                .get(usize::from(idx))
+                .cloned()
+                .ok_or(BinaryError::Malformed("invalid argument purpose"))?,
+        };
+        param.extension = self.table(&EXTENSIONS, "invalid argument extension")?;
+        param.location = match self.byte()? {
+            LOC_UNASSIGNED => ArgumentLoc::Unassigned,
+            LOC_REG => ArgumentLoc::Reg(self.u16()?),
+            LOC_STACK => ArgumentLoc::Stack(self.i32()?),
+            _ => return Err(BinaryError::Malformed("invalid argument location")),
+        };
+        param.legalized_to_pointer = self.bool()?;
+        Ok(param)
+    }
+
+    fn opt_zigzag(&mut self) -> BinaryResult<Option<i64>> {
+        match self.byte()? {
+            0 => Ok(None),
+            1 => self.zigzag().map(Some),
+            _ => Err(BinaryError::Malformed("invalid option")),
+        }
+    }
+
+    fn function(&mut self) -> BinaryResult<Function> {
+        let name = self.name()?;
+        let sig = self.signature()?;
+        let mut func = Function::with_name_signature(name, sig);
+        func.pointer_type = match self.ty()? {
+            types::INVALID => None,
+            ty => Some(ty),
+        };
+
+        let n = self.count()?;
+        for _ in 0..n {
+            let sig = self.signature()?;
+            func.import_signature(sig);
+        }
+
+        let n = self.count()?;
+        for _ in 0..n {
+            let kind = self.table(&STACK_SLOT_KINDS, "invalid stack slot kind")?;
+            let mut ss = StackSlotData::new(kind, self.u32()?);
+            ss.offset = match self.opt_zigzag()? {
+                Some(x)
+                    if x < i64::from(StackOffset::min_value())
+                        || x > i64::from(StackOffset::max_value()) =>
+                {
+                    return Err(BinaryError::Malformed("stack slot offset out of range"))
+                }
+                x => x.map(|x| x as StackOffset),
+            };
+            func.create_stack_slot(ss);
+        }
+        func.stack_slots.frame_size = match self.opt_zigzag()? {
+            Some(x) if x < 0 || x > i64::from(StackSize::max_value()) => {
+                return Err(BinaryError::Malformed("frame size out of range"))
+            }
+            x => x.map(|x| x as StackSize),
+        };
+
+        let num_gvs = self.count()?;
+        for _ in 0..num_gvs {
+            let gv = self.global_value(num_gvs)?;
+            func.create_global_value(gv);
+        }
+
+        let n = self.count()?;
+        for _ in 0..n {
+            let base = self.packed_entity::<GlobalValue>(num_gvs)?;
+            let min_size = self.imm64()?;
+            let guard_size = self.imm64()?;
+            let style = match self.byte()? {
+                HEAP_DYNAMIC => HeapStyle::Dynamic {
+                    bound_gv: self.packed_entity(num_gvs)?,
+                },
+                HEAP_STATIC => HeapStyle::Static {
+                    bound: self.imm64()?,
+                },
+                _ => return Err(BinaryError::Malformed("invalid heap style")),
+            };
+            let index_type = self.ty()?;
+            let check_alignment = self.bool()?;
+            func.create_heap(HeapData {
+                base,
+                min_size,
+                guard_size,
+                style,
+                index_type,
+                check_alignment,
+            });
+        }
+
+        let n = self.count()?;
+        for _ in 0..n {
+            let base_gv = self.packed_entity(num_gvs)?;
+            let min_size = self.imm64()?;
+            let bound_gv = self.packed_entity(num_gvs)?;
+            let element_size = self.imm64()?;
+            let index_type = self.ty()?;
+            func.create_table(TableData {
+                base_gv,
+                min_size,
+                bound_gv,
+                element_size,
+                index_type,
+            });
+        }
+
+        let n = self.count()?;
+        for _ in 0..n {
+            let name = self.name()?;
+            let signature = self.packed_entity(func.dfg.signatures.len())?;
+            let colocated = self.bool()?;
+            func.import_function(ExtFuncData {
+                name,
+                signature,
+                colocated,
+            });
+        }
+
+        // Value table. Start out with every value as an invalid placeholder, like the parser does.
+        let num_values = self.count()?;
+        let mut value_types = Vec::with_capacity(num_values);
+        let mut aliases = Vec::new();
+        for i in 0..num_values {
+            func.dfg.make_invalid_value_for_parser();
+            value_types.push(match self.byte()? {
+                VALUE_UNUSED => None,
+                VALUE_DEFINED => Some(self.valid_ty()?),
+                VALUE_ALIAS => {
+                    aliases.push((Value::new(i), self.entity::<Value>(num_values)?));
+                    None
+                }
+                _ => return Err(BinaryError::Malformed("invalid value kind")),
+            });
+        }
+        // Values that are defined but not yet attached.
+        let mut unattached = value_types.clone();
+
+        let num_ebbs = self.count()?;
+        for _ in 0..num_ebbs {
+            let ebb = func.dfg.make_ebb();
+            let n = self.count()?;
+            for _ in 0..n {
+                let v = self.entity::<Value>(num_values)?;
+                let ty = unattached[v.index()].take().ok_or(BinaryError::Malformed(
+                    "EBB parameter is not a defined value",
+                ))?;
+                func.dfg.append_ebb_param_for_parser(ebb, ty, v);
+            }
+        }
+
+        let n = self.count()?;
+        for _ in 0..n {
+            let len = self.count()?;
+            let mut jt = JumpTableData::with_capacity(len);
+            for _ in 0..len {
+                let ebb: Ebb = self.packed_entity(num_ebbs)?;
+                if ebb == Ebb::reserved_value() {
+                    jt.push_hole();
+                } else {
+                    jt.push_entry(ebb);
+                }
+            }
+            func.create_jump_table(jt);
+        }
+
+        let limits = Limits {
+            values: num_values,
+            ebbs: num_ebbs,
+            sigs: func.dfg.signatures.len(),
+            funcs: func.dfg.ext_funcs.len(),
+            gvs: num_gvs,
+            stack_slots: func.stack_slots.keys().len(),
+            heaps: func.heaps.len(),
+            tables: func.tables.len(),
+            jump_tables: func.jump_tables.len(),
+        };
+        let num_insts = self.count()?;
+        let mut incomplete = Vec::new();
+        for inst in (0..num_insts).map(Inst::new) {
+            if !self.inst(&mut func, &limits, &mut unattached)? {
+                incomplete.push(inst);
+            }
+        }
+
+        if unattached.iter().any(Option::is_some) {
+            return Err(BinaryError::Malformed("defined value is not attached"));
+        }
+        for &(alias, original) in &aliases {
+            if value_types[original.index()].is_none()
+                && aliases.iter().all(|&(a, _)| a != original)
+            {
+                return Err(BinaryError::Malformed("alias of an unused value"));
+            }
+            func.dfg.make_value_alias_for_serialization(original, alias);
+        }
+        for &(alias, _) in &aliases {
+            if !func.dfg.set_alias_type_for_parser(alias) {
+                return Err(BinaryError::Malformed("alias cycle"));
+            }
+        }
+
+        // All instruction operands must be real values.
+        for inst in (0..num_insts).map(Inst::new) {
+            for &arg in func.dfg.inst_args(inst) {
+                if !func.dfg.value_is_valid_for_parser(arg) {
+                    return Err(BinaryError::Malformed("instruction uses an unused value"));
+                }
+            }
+        }
+
+        let n = self.count()?;
+        for _ in 0..n {
+            let ebb = self.entity(num_ebbs)?;
+            if func.layout.is_ebb_inserted(ebb) {
+                return Err(BinaryError::Malformed("EBB appears twice in the layout"));
+            }
+            func.layout.append_ebb(ebb);
+            let len = self.count()?;
+            for _ in 0..len {
+                let inst = self.entity(num_insts)?;
+                if func.layout.inst_ebb(inst).is_some() {
+                    return Err(BinaryError::Malformed(
+                        "instruction appears twice in the layout",
+                    ));
+                }
+                func.layout.append_inst(inst, ebb);
+            }
+        }
+        if incomplete
+            .iter()
+            .any(|&inst| func.layout.inst_ebb(inst).is_some())
+        {
+            return Err(BinaryError::Malformed(
+                "instruction in the layout has no results",
+            ));
+        }
+
+        let n = self.count()?;
+        for _ in 0..n {
+            let inst = self.entity::<Inst>(num_insts)?;
+            func.srclocs[inst] = SourceLoc::new(self.u32()?);
+        }
+
+        let n = self.count()?;
+        for _ in 0..n {
+            let inst = self.entity::<Inst>(num_insts)?;
+            func.branch_hints[inst] = Some(if self.bool()? {
+                BranchHint::Likely
+            } else {
+                BranchHint::Unlikely
+            });
+        }
+
+        let n = self.count()?;
+        for _ in 0..n {
+            let inst = self.entity::<Inst>(num_insts)?;
+            func.patchable.insert(inst);
+        }
+
+        Ok(func)
+    }
+
+    fn global_value(&mut self, num_gvs: usize) -> BinaryResult<GlobalValueData> {
+        Ok(match self.byte()? {
+            GV_VMCTX => GlobalValueData::VMContext,
+            GV_LOAD => GlobalValueData::Load {
+                base: self.entity(num_gvs)?,
+                offset: self.offset32()?,
+                global_type: self.valid_ty()?,
+                readonly: self.bool()?,
+            },
+            GV_IADD_IMM => GlobalValueData::IAddImm {
+                base: self.entity(num_gvs)?,
+                offset: self.imm64()?,
+                global_type: self.valid_ty()?,
+            },
+            GV_IADD_SCALED => GlobalValueData::IAddScaled {
+                base: self.entity(num_gvs)?,
+                index: self.entity(num_gvs)?,
+                scale: self.imm64()?,
+                global_type: self.valid_ty()?,
+            },
+            GV_SYMBOL => GlobalValueData::Symbol {
+                name: self.name()?,
+                offset: self.imm64()?,
+                colocated: self.bool()?,
+            },
+            _ => return Err(BinaryError::Malformed("invalid global value")),
+        })
+    }
+
+    fn value(&mut self, limits: &Limits) -> BinaryResult<Value> {
+        self.entity(limits.values)
+    }
+
+    fn value_array2(&mut self, limits: &Limits) -> BinaryResult<[Value; 2]> {
+        Ok([self.value(limits)?, self.value(limits)?])
+    }
+
+    fn value_array3(&mut self, limits: &Limits) -> BinaryResult<[Value; 3]> {
+        Ok([
+            self.value(limits)?,
+            self.value(limits)?,
+            self.value(limits)?,
+        ])
+    }
+
+    fn value_list(&mut self, func: &mut Function, limits: &Limits) -> BinaryResult<ValueList> {
+        let n = self.count()?;
+        let mut list = ValueList::new();
+        for _ in 0..n {
+            let v = self.value(limits)?;
+            list.push(v, &mut func.dfg.value_lists);
+        }
+        Ok(list)
+    }
+
+    /// Read an instruction and create its results.
+    ///
+    /// Returns false if the instruction is missing its results. This is only allowed for
+    /// instructions that aren't in the layout.
+    fn inst(
+        &mut self,
+        func: &mut Function,
+        limits: &Limits,
+        unattached: &mut [Option<Type>],
+    ) -> BinaryResult<bool> {
+        let opcode =
+            Opcode::from_u8(self.byte()?).ok_or(BinaryError::Malformed("invalid opcode"))?;
+        let ctrl_typevar = self.ty()?;
+        let data = self.inst_data(func, limits, opcode)?;
+        let inst = func.dfg.make_inst(data);
+
+        let constraints = opcode.constraints();
+        if func.dfg.inst_args(inst).len() < constraints.fixed_value_arguments()
+            || (constraints.use_typevar_operand()
+                && func.dfg[inst]
+                    .typevar_operand(&func.dfg.value_lists)
+                    .is_none())
+        {
+            return Err(BinaryError::Malformed("too few instruction arguments"));
+        }
+
+        let expected = match func.dfg.call_signature(inst) {
+            Some(sig) if sig.index() >= limits.sigs => {
+                return Err(BinaryError::Malformed("call to a placeholder function"))
+            }
+            Some(sig) => func.dfg.signatures[sig].returns.len(),
+            None => constraints.fixed_results(),
+        };
+        let n = self.count()?;
+        if n == 0 {
+            // Instructions that are no longer in the layout may have had their results detached.
+            return Ok(expected == 0);
+        }
+        if n != expected {
+            return Err(BinaryError::Malformed(
+                "wrong number of instruction results",
+            ));
+        }
+        if let Some(typeset) = constraints.ctrl_typeset() {
+            if !typeset.contains(ctrl_typevar) {
+                return Err(BinaryError::Malformed("invalid controlling type variable"));
+            }
+        }
+        let mut results = Vec::with_capacity(n);
+        let mut result_types = Vec::with_capacity(n);
+        for _ in 0..n {
+            let v = self.value(limits)?;
+            let ty = unattached[v.index()]
+                .take()
+                .ok_or(BinaryError::Malformed("result is not a defined value"))?;
+            results.push(v);
+            result_types.push(ty);
+        }
+        func.dfg
+            .make_inst_results_for_parser(inst, ctrl_typevar, &results);
+        if results
+            .iter()
+            .zip(&result_types)
+            .any(|(&v, &ty)| func.dfg.value_type(v) != ty)
+        {
+            return Err(BinaryError::Malformed("wrong instruction result type"));
+        }
+        Ok(true)
+    }
+
+    fn inst_data(
+        &mut self,
+        func: &mut Function,
+        l: &Limits,
+        opcode: Opcode,
+    ) -> BinaryResult<InstructionData> {
+        Ok(match opcode.format() {
+            InstructionFormat::Unary => InstructionData::Unary {
+                opcode,
+                arg: self.value(l)?,
+            },
+            InstructionFormat::UnaryImm => InstructionData::UnaryImm {
+                opcode,
+                imm: self.imm64()?,
+            },
+            InstructionFormat::UnaryIeee32 => InstructionData::UnaryIeee32 {
+                opcode,
+                imm: Ieee32::with_bits(self.u32()?),
+            },
+            InstructionFormat::UnaryIeee64 => InstructionData::UnaryIeee64 {
+                opcode,
+                imm: Ieee64::with_bits(self.u64()?),
+            },
+            InstructionFormat::UnaryBool => InstructionData::UnaryBool {
+                opcode,
+                imm: self.bool()?,
+            },
+            InstructionFormat::UnaryGlobalValue => InstructionData::UnaryGlobalValue {
+                opcode,
+                global_value: self.entity(l.gvs)?,
+            },
+            InstructionFormat::Binary => InstructionData::Binary {
+                opcode,
+                args: self.value_array2(l)?,
+            },
+            InstructionFormat::BinaryImm => InstructionData::BinaryImm {
+                opcode,
+                arg: self.value(l)?,
+                imm: self.imm64()?,
+            },
+            InstructionFormat::Ternary => InstructionData::Ternary {
+                opcode,
+                args: self.value_array3(l)?,
+            },
+            InstructionFormat::MultiAry => InstructionData::MultiAry {
+                opcode,
+                args: self.value_list(func, l)?,
+            },
+            InstructionFormat::NullAry => InstructionData::NullAry { opcode },
+            InstructionFormat::InsertLane => InstructionData::InsertLane {
+                opcode,
+                args: self.value_array2(l)?,
+                lane: self.byte()?,
+            },
+            InstructionFormat::ExtractLane => InstructionData::ExtractLane {
+                opcode,
+                arg: self.value(l)?,
+                lane: self.byte()?,
+            },
+            InstructionFormat::IntCompare => InstructionData::IntCompare {
+                opcode,
+                args: self.value_array2(l)?,
+                cond: self.intcc()?,
+            },
+            InstructionFormat::IntCompareImm => InstructionData::IntCompareImm {
+                opcode,
+                arg: self.value(l)?,
+                cond: self.intcc()?,
+                imm: self.imm64()?,
+            },
+            InstructionFormat::IntCond => InstructionData::IntCond {
+                opcode,
+                arg: self.value(l)?,
+                cond: self.intcc()?,
+            },
+            InstructionFormat::FloatCompare => InstructionData::FloatCompare {
+                opcode,
+                args: self.value_array2(l)?,
+                cond: self.floatcc()?,
+            },
+            InstructionFormat::FloatCond => InstructionData::FloatCond {
+                opcode,
+                arg: self.value(l)?,
+                cond: self.floatcc()?,
+            },
+            InstructionFormat::IntSelect => InstructionData::IntSelect {
+                opcode,
+                args: self.value_array3(l)?,
+                cond: self.intcc()?,
+            },
+            InstructionFormat::Jump => InstructionData::Jump {
+                opcode,
+                args: self.value_list(func, l)?,
+                destination: self.entity(l.ebbs)?,
+            },
+            InstructionFormat::Branch => InstructionData::Branch {
+                opcode,
+                args: self.value_list(func, l)?,
+                destination: self.entity(l.ebbs)?,
+            },
+            InstructionFormat::BranchInt => InstructionData::BranchInt {
+                opcode,
+                args: self.value_list(func, l)?,
+                cond: self.intcc()?,
+                destination: self.entity(l.ebbs)?,
+            },
+            InstructionFormat::BranchFloat => InstructionData::BranchFloat {
+                opcode,
+                args: self.value_list(func, l)?,
+                cond: self.floatcc()?,
+                destination: self.entity(l.ebbs)?,
+            },
+            InstructionFormat::BranchIcmp => InstructionData::BranchIcmp {
+                opcode,
+                args: self.value_list(func, l)?,
+                cond: self.intcc()?,
+                destination: self.entity(l.ebbs)?,
+            },
+            InstructionFormat::BranchTable => InstructionData::BranchTable {
+                opcode,
+                arg: self.value(l)?,
+                destination: self.entity(l.ebbs)?,
+                table: self.entity::<JumpTable>(l.jump_tables)?,
+            },
+            InstructionFormat::BranchTableEntry => InstructionData::BranchTableEntry {
+                opcode,
+                args: self.value_array2(l)?,
+                imm: self.byte()?,
+                table: self.entity(l.jump_tables)?,
+            },
+            InstructionFormat::BranchTableBase => InstructionData::BranchTableBase {
+                opcode,
+                table: self.entity(l.jump_tables)?,
+            },
+            InstructionFormat::IndirectJump => InstructionData::IndirectJump {
+                opcode,
+                arg: self.value(l)?,
+                table: self.entity(l.jump_tables)?,
+            },
+            InstructionFormat::Call => InstructionData::Call {
+                opcode,
+                args: self.value_list(func, l)?,
+                func_ref: self.entity::<FuncRef>(l.funcs)?,
+            },
+            InstructionFormat::CallIndirect => InstructionData::CallIndirect {
+                opcode,
+                args: self.value_list(func, l)?,
+                sig_ref: self.entity::<SigRef>(l.sigs)?,
+            },
+            InstructionFormat::FuncAddr => InstructionData::FuncAddr {
+                opcode,
+                func_ref: self.entity(l.funcs)?,
+            },
+            InstructionFormat::Load => InstructionData::Load {
+                opcode,
+                arg: self.value(l)?,
+                flags: self.memflags()?,
+                offset: self.offset32()?,
+            },
+            InstructionFormat::LoadComplex => InstructionData::LoadComplex {
+                opcode,
+                args: self.value_list(func, l)?,
+                flags: self.memflags()?,
+                offset: self.offset32()?,
+            },
+            InstructionFormat::Store => InstructionData::Store {
+                opcode,
+                args: self.value_array2(l)?,
+                flags: self.memflags()?,
+                offset: self.offset32()?,
+            },
+            InstructionFormat::StoreComplex => InstructionData::StoreComplex {
+                opcode,
+                args: self.value_list(func, l)?,
+                flags: self.memflags()?,
+                offset: self.offset32()?,
+            },
+            InstructionFormat::LoadSymbol => InstructionData::LoadSymbol {
+                opcode,
+                arg: self.value(l)?,
+                flags: self.memflags()?,
+                global_value: self.entity(l.gvs)?,
+                offset: self.offset32()?,
+            },
+            InstructionFormat::LoadGlobal => InstructionData::LoadGlobal {
+                opcode,
+                flags: self.memflags()?,
+                global_value: self.entity(l.gvs)?,
+                offset: self.offset32()?,
+            },
+            InstructionFormat::StoreSymbol => InstructionData::StoreSymbol {
+                opcode,
+                args: self.value_list(func, l)?,
+                flags: self.memflags()?,
+                global_value: self.entity(l.gvs)?,
+                offset: self.offset32()?,
+            },
+            InstructionFormat::StackLoad => InstructionData::StackLoad {
+                opcode,
+                stack_slot: self.entity::<StackSlot>(l.stack_slots)?,
+                offset: self.offset32()?,
+            },
+            InstructionFormat::StackStore => InstructionData::StackStore {
+                opcode,
+                arg: self.value(l)?,
+                stack_slot: self.entity(l.stack_slots)?,
+                offset: self.offset32()?,
+            },
+            InstructionFormat::HeapAddr => InstructionData::HeapAddr {
+                opcode,
+                arg: self.value(l)?,
+                heap: self.entity::<Heap>(l.heaps)?,
+                imm: Uimm32::from(self.u32()?),
+            },
+            InstructionFormat::TableAddr => InstructionData::TableAddr {
+                opcode,
+                arg: self.value(l)?,
+                table: self.entity::<Table>(l.tables)?,
+                offset: self.offset32()?,
+            },
+            InstructionFormat::RegMove => InstructionData::RegMove {
+                opcode,
+                arg: self.value(l)?,
+                src: self.u16()?,
+                dst: self.u16()?,
+            },
+            InstructionFormat::CopySpecial => InstructionData::CopySpecial {
+                opcode,
+                src: self.u16()?,
+                dst: self.u16()?,
+            },
+            InstructionFormat::RegSpill => InstructionData::RegSpill {
+                opcode,
+                arg: self.value(l)?,
+                src: self.u16()?,
+                dst: self.entity(l.stack_slots)?,
+            },
+            InstructionFormat::RegFill => InstructionData::RegFill {
+                opcode,
+                arg: self.value(l)?,
+                src: self.entity(l.stack_slots)?,
+                dst: self.u16()?,
+            },
+            InstructionFormat::Trap => InstructionData::Trap {
+                opcode,
+                code: self.trap_code()?,
+                payload: Uimm32::from(self.u32()?),
+            },
+            InstructionFormat::CondTrap => InstructionData::CondTrap {
+                opcode,
+                arg: self.value(l)?,
+                code: self.trap_code()?,
+                payload: Uimm32::from(self.u32()?),
+            },
+            InstructionFormat::IntCondTrap => InstructionData::IntCondTrap {
+                opcode,
+                arg: self.value(l)?,
+                cond: self.intcc()?,
+                code: self.trap_code()?,
+                payload: Uimm32::from(self.u32()?),
+            },
+            InstructionFormat::FloatCondTrap => InstructionData::FloatCondTrap {
+                opcode,
+                arg: self.value(l)?,
+                cond: self.floatcc()?,
+                code: self.trap_code()?,
+                payload: Uimm32::from(self.u32()?),
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cursor::{Cursor, FuncCursor};
+    use ir::{InstBuilder, StackSlotData};
+    use std::string::ToString;
+
+    #[test]
+    fn enum_tables() {
+        for (i, &cc) in INT_CCS.iter().enumerate() {
+            assert_eq!(cc as usize, i);
+        }
+        for (i, &cc) in FLOAT_CCS.iter().enumerate() {
+            assert_eq!(cc as usize, i);
+        }
+        for (i, &cc) in CALL_CONVS.iter().enumerate() {
+            assert_eq!(cc as usize, i);
+        }
+        for (i, &e) in EXTENSIONS.iter().enumerate() {
+            assert_eq!(e as usize, i);
+        }
+        for (i, &k) in STACK_SLOT_KINDS.iter().enumerate() {
+            assert_eq!(k as usize, i);
+        }
+        for (i, &ty) in LANE_TYPES.iter().enumerate() {
+            assert_eq!(ty.index(), 0x70 + i);
+        }
+    }
+
+    #[test]
+    fn opcodes() {
+        for &opcode in Opcode::all() {
+            assert_eq!(Opcode::from_u8(opcode as u8), Some(opcode));
+        }
+        assert_eq!(Opcode::from_u8(0), None);
+        assert_eq!(Opcode::from_u8(Opcode::all().len() as u8 + 1), None);
+    }
+
+    #[test]
+    fn all_types() {
+        let mut out = Vec::new();
+        for b in 0..=255u8 {
+            out.clear();
+            let mut r = Reader {
+                data: &[b],
+                pos: 0,
+                names: Vec::new(),
+            };
+            if let Ok(ty) = r.ty() {
+                Writer {
+                    out: &mut out,
+                    names: Vec::new(),
+                }
+                .ty(ty);
+                assert_eq!(out, [b]);
+            }
+        }
+        for &lane in &LANE_TYPES {
+            for &n in &[1, 2, 4, 8, 16, 32, 64, 128, 256] {
+                if let Some(ty) = lane.by(n) {
+                    out.clear();
+                    Writer {
+                        out: &mut out,
+                        names: Vec::new(),
+                    }
+                    .ty(ty);
+                    let mut r = Reader {
+                        data: &out,
+                        pos: 0,
+                        names: Vec::new(),
+                    };
+                    assert_eq!(r.ty(), Ok(ty));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn varints() {
+        let mut out = Vec::new();
+        let ints = [
+            0,
+            1,
+            -1,
+            63,
+            -64,
+            64,
+            1 << 40,
+            i64::min_value(),
+            i64::max_value(),
+        ];
+        {
+            let mut w = Writer {
+                out: &mut out,
+                names: Vec::new(),
+            };
+            for &x in &ints {
+                w.zigzag(x);
+            }
+            w.varint(u64::max_value());
+        }
+        let mut r = Reader {
+            data: &out,
+            pos: 0,
+            names: Vec::new(),
+        };
+        for &x in &ints {
+            assert_eq!(r.zigzag(), Ok(x));
+        }
+        assert_eq!(r.varint(), Ok(u64::max_value()));
+        assert_eq!(r.byte(), Err(BinaryError::UnexpectedEof));
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut func = Function::new();
+        func.name = ExternalName::testcase("round_trip");
+        func.signature.params.push(AbiParam::new(types::I32));
+        func.signature.params.push(AbiParam::special(
+            types::I64,
+            ArgumentPurpose::StructArgument(300),
+        ));
+        func.signature.returns.push(AbiParam::new(types::I32));
+        let ss = func.create_stack_slot(StackSlotData::new(StackSlotKind::ExplicitSlot, 8));
+        let symbol = ExternalName::symbol("a \"long\" symbol name");
+        let callee_sig = func.import_signature(Signature::new(CallConv::SystemV));
+        let callee = func.import_function(ExtFuncData {
+            name: symbol.clone(),
+            signature: callee_sig,
+            colocated: false,
+        });
+        let gv = func.create_global_value(GlobalValueData::Symbol {
+            name: symbol,
+            offset: Imm64::new(8),
+            colocated: true,
+        });
+        func.create_global_value(GlobalValueData::IAddScaled {
+            base: gv,
+            index: gv,
+            scale: Imm64::new(12),
+            global_type: types::I64,
+        });
+        let ebb0 = func.dfg.make_ebb();
+        let ebb1 = func.dfg.make_ebb();
+        let arg = func.dfg.append_ebb_param(ebb0, types::I32);
+        func.dfg.append_ebb_param(ebb0, types::I64);
+        let param = func.dfg.append_ebb_param(ebb1, types::I32);
+        {
+            let mut pos = FuncCursor::new(&mut func);
+            pos.insert_ebb(ebb0);
+            let v0 = pos.ins().iconst(types::I32, -5);
+            pos.func
+                .patchable
+                .insert(pos.func.dfg.value_def(v0).unwrap_inst());
+            let v1 = pos.ins().iadd(arg, v0);
+            pos.ins().stack_store(v1, ss, 4);
+            pos.ins().call(callee, &[]);
+            let br = pos.ins().brnz(v1, ebb1, &[v0]);
+            pos.func.branch_hints[br] = Some(BranchHint::Likely);
+            pos.ins().trap(TrapCode::User(7), 42);
+            pos.insert_ebb(ebb1);
+            let v2 = pos.ins().f64const(Ieee64::with_float(-0.0));
+            let v3 = pos.ins().fcvt_to_sint(types::I32, v2);
+            let v4 = pos.ins().icmp(IntCC::UnsignedLessThan, param, v3);
+            let v5 = pos.ins().bint(types::I32, v4);
+            pos.ins().return_(&[v5]);
+        }
+
+        let mut out = Vec::new();
+        func.write_binary(&mut out);
+        let copy = Function::read_binary(&out).unwrap();
+        assert_eq!(copy.to_string(), func.to_string());
+
+        // Every truncated prefix must be rejected without panicking.
+        for len in 0..out.len() {
+            assert!(Function::read_binary(&out[0..len]).is_err());
+        }
+    }
+
+    #[test]
+    fn bad_header() {
+        let mut out = Vec::new();
+        Function::new().write_binary(&mut out);
+        assert!(Function::read_binary(&out).is_ok());
+
+        let mut bad = out.clone();
+        bad[0] = b'X';
+        assert_eq!(
+            Function::read_binary(&bad).err(),
+            Some(BinaryError::BadMagic)
+        );
+
+        let mut bad = out.clone();
+        bad[4] = 99;
+        assert_eq!(
+            Function::read_binary(&bad).err(),
+            Some(BinaryError::UnsupportedVersion(99))
+        );
+
+        let mut bad = out.clone();
+        bad[5] ^= 1;
+        assert_eq!(
+            Function::read_binary(&bad).err(),
+            Some(BinaryError::InstructionSetMismatch)
+        );
+    }
+}
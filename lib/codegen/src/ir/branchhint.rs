@@ -0,0 +1,70 @@
+//! Hints about the direction of conditional branches.
+
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+/// A hint about how likely a conditional branch is to be taken.
+///
+/// Branch hints are written after the operands of the branch instruction:
+///
+/// ```clif
+///     brz v1, ebb3 unlikely
+/// ```
+///
+/// They don't affect the semantics of the program. Cranelift uses them to lay out the EBBs so
+/// the likely paths through the function fall through.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub enum BranchHint {
+    /// The branch is usually taken.
+    Likely,
+
+    /// The branch is rarely taken.
+    Unlikely,
+}
+
+impl BranchHint {
+    /// Get the hint for the branch with the inverse condition.
+    pub fn inverse(self) -> Self {
+        match self {
+            BranchHint::Likely => BranchHint::Unlikely,
+            BranchHint::Unlikely => BranchHint::Likely,
+        }
+    }
+}
+
+impl Display for BranchHint {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            BranchHint::Likely => "likely",
+            BranchHint::Unlikely => "unlikely",
+        })
+    }
+}
+
+impl FromStr for BranchHint {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "likely" => Ok(BranchHint::Likely),
+            "unlikely" => Ok(BranchHint::Unlikely),
+            _ => Err(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::string::ToString;
+
+    #[test]
+    fn display() {
+        for &hint in &[BranchHint::Likely, BranchHint::Unlikely] {
+            assert_eq!(hint.to_string().parse(), Ok(hint));
+            assert_eq!(hint.inverse().inverse(), hint);
+        }
+        assert_eq!(BranchHint::Likely.inverse(), BranchHint::Unlikely);
+        assert_eq!("probably".parse::<BranchHint>(), Err(()));
+    }
+}
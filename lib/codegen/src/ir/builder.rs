@@ -217,8 +217,9 @@ impl<'f> InstBuilderBase<'f> for ReplaceBuilder<'f> {
 mod tests {
     use cursor::{Cursor, FuncCursor};
     use ir::condcodes::*;
+    use ir::immediates::Imm64;
     use ir::types::*;
-    use ir::{Function, InstBuilder, ValueDef};
+    use ir::{Function, InstBuilder, InstructionData, Value, ValueDef};
 
     #[test]
     fn types() {
@@ -241,6 +242,68 @@ mod tests {
         assert_eq!(pos.func.dfg.value_type(cmp), B1);
     }
 
+    /// Get the immediate of the `iconst` instruction defining `v`.
+    fn iconst_imm(func: &Function, v: Value) -> i64 {
+        let inst = func.dfg.value_def(v).unwrap_inst();
+        match func.dfg[inst] {
+            InstructionData::UnaryImm { imm, .. } => imm.into(),
+            _ => panic!("not an iconst"),
+        }
+    }
+
+    #[test]
+    fn native_constants() {
+        let mut func = Function::new();
+        let ebb0 = func.dfg.make_ebb();
+        let arg0 = func.dfg.append_ebb_param(ebb0, I32);
+        let mut pos = FuncCursor::new(&mut func);
+        pos.insert_ebb(ebb0);
+
+        // The type of the constant comes from the Rust type, and the immediate is sign-extended
+        // from it whatever the signedness of the number.
+        let v0 = pos.ins().iconst_u32(0xffff_ffff);
+        assert_eq!(pos.func.dfg.value_type(v0), I32);
+        assert_eq!(iconst_imm(pos.func, v0), -1i64);
+        let v1 = pos.ins().iconst_i32(-1);
+        assert_eq!(iconst_imm(pos.func, v1), -1i64);
+        let v2 = pos.ins().iconst_u8(0x80);
+        assert_eq!(pos.func.dfg.value_type(v2), I8);
+        assert_eq!(iconst_imm(pos.func, v2), -128i64);
+        let v3 = pos.ins().iconst_i16(i16::min_value());
+        assert_eq!(iconst_imm(pos.func, v3), -32768i64);
+        let v4 = pos.ins().iconst_u64(u64::max_value());
+        assert_eq!(pos.func.dfg.value_type(v4), I64);
+        assert_eq!(iconst_imm(pos.func, v4), -1i64);
+
+        // The generic builders sign-extend the same way.
+        let v5 = pos.ins().iconst(I32, 0x8000_0000);
+        assert_eq!(iconst_imm(pos.func, v5), -0x8000_0000i64);
+        let v6 = pos.ins().iconst(I64, 0x8000_0000);
+        assert_eq!(iconst_imm(pos.func, v6), 0x8000_0000i64);
+        pos.ins().band_imm(arg0, 0xffff_ff00);
+        let band = pos.prev_inst().unwrap();
+        match pos.func.dfg[band] {
+            InstructionData::BinaryImm { imm, .. } => assert_eq!(imm, Imm64::new(-256)),
+            _ => panic!("not a band_imm"),
+        }
+
+        let f0 = pos.ins().f32const_from(1.5);
+        assert_eq!(pos.func.dfg.value_type(f0), F32);
+        let f1 = pos.ins().f64const_from(-0.0);
+        assert_eq!(pos.func.dfg.value_type(f1), F64);
+    }
+
+    #[test]
+    #[should_panic(expected = "immediate out of range for i32")]
+    #[cfg(debug_assertions)]
+    fn constant_out_of_range() {
+        let mut func = Function::new();
+        let ebb0 = func.dfg.make_ebb();
+        let mut pos = FuncCursor::new(&mut func);
+        pos.insert_ebb(ebb0);
+        pos.ins().iconst(I32, 0x1_0000_0000);
+    }
+
     #[test]
     fn reuse_results() {
         let mut func = Function::new();
@@ -0,0 +1,134 @@
+//! Constant pool representation.
+//!
+//! Some ISAs can't materialize every floating point constant in a register with a single
+//! instruction, so they load them from memory instead. The constant pool of a function holds
+//! those constants. It is emitted after the jump tables, in the read-only data of the function,
+//! so the code can address it relative to the program counter without any relocations.
+
+use binemit::CodeOffset;
+use std::slice::Iter;
+use std::vec::Vec;
+
+/// A constant in the pool.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ConstantPoolEntry {
+    /// The bits of the constant, in the low `size` bytes.
+    pub bits: u64,
+
+    /// The size of the constant in bytes. This is also its alignment.
+    pub size: u8,
+
+    /// Offset of the constant from the start of the function.
+    pub offset: CodeOffset,
+}
+
+/// The constants used by a function, with their offsets.
+///
+/// Each constant appears only once, no matter how many instructions use it.
+#[derive(Clone, Debug, Default)]
+pub struct ConstantPool {
+    entries: Vec<ConstantPoolEntry>,
+}
+
+impl ConstantPool {
+    /// Create a new empty constant pool.
+    pub fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+        }
+    }
+
+    /// Remove all the constants.
+    pub fn clear(&mut self) {
+        self.entries.clear()
+    }
+
+    /// Is the pool empty?
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Add a constant of `size` bytes to the pool, unless it is already there.
+    ///
+    /// The constant doesn't get an offset until the pool is laid out.
+    pub fn insert(&mut self, bits: u64, size: u8) {
+        debug_assert!(size == 4 || size == 8, "Bad constant size {}", size);
+        if self.find(bits, size).is_none() {
+            self.entries.push(ConstantPoolEntry {
+                bits,
+                size,
+                offset: 0,
+            });
+        }
+    }
+
+    /// Assign offsets to the constants, starting at `offset`.
+    ///
+    /// Every constant is aligned to its size. The larger constants are placed first so there is
+    /// at most one padding gap, before the first constant.
+    ///
+    /// Returns the offset of the end of the pool.
+    pub fn layout(&mut self, mut offset: CodeOffset) -> CodeOffset {
+        self.entries.sort_by(|a, b| b.size.cmp(&a.size));
+        for entry in &mut self.entries {
+            let align = CodeOffset::from(entry.size);
+            offset = (offset + align - 1) & !(align - 1);
+            entry.offset = offset;
+            offset += align;
+        }
+        offset
+    }
+
+    /// Get the offset of a constant from the start of the function.
+    ///
+    /// Panics if the constant is not in the pool.
+    pub fn offset(&self, bits: u64, size: u8) -> CodeOffset {
+        match self.find(bits, size) {
+            Some(entry) => entry.offset,
+            None => panic!("Constant {:#x} is not in the constant pool", bits),
+        }
+    }
+
+    /// Find the entry for a constant.
+    fn find(&self, bits: u64, size: u8) -> Option<&ConstantPoolEntry> {
+        self.entries
+            .iter()
+            .find(|e| e.bits == bits && e.size == size)
+    }
+
+    /// Iterate over the constants in layout order.
+    pub fn iter(&self) -> Iter<ConstantPoolEntry> {
+        self.entries.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ConstantPool;
+
+    #[test]
+    fn empty() {
+        let mut pool = ConstantPool::new();
+        assert!(pool.is_empty());
+        assert_eq!(pool.layout(13), 13);
+    }
+
+    #[test]
+    fn layout() {
+        let mut pool = ConstantPool::new();
+        pool.insert(0x8000_0000, 4);
+        pool.insert(0x8000_0000_0000_0000, 8);
+        pool.insert(0x8000_0000, 4);
+        pool.insert(0x3f80_0000, 4);
+        assert_eq!(pool.iter().count(), 3);
+
+        // The 8-byte constant comes first, aligned.
+        assert_eq!(pool.layout(13), 32);
+        assert_eq!(pool.offset(0x8000_0000_0000_0000, 8), 16);
+        assert_eq!(pool.offset(0x8000_0000, 4), 24);
+        assert_eq!(pool.offset(0x3f80_0000, 4), 28);
+
+        pool.clear();
+        assert!(pool.is_empty());
+    }
+}
@@ -1098,6 +1098,7 @@ mod tests {
         let idata = InstructionData::Trap {
             opcode: Opcode::Trap,
             code: TrapCode::User(0),
+            payload: 0.into(),
         };
         let inst = dfg.make_inst(idata);
         assert_eq!(dfg.display_inst(inst, None).to_string(), "trap user0");
@@ -109,6 +109,8 @@ pub struct AbiParam {
     /// ABI-specific location of this argument, or `Unassigned` for arguments that have not yet
     /// been legalized.
     pub location: ArgumentLoc,
+    /// Was the argument converted to a pointer to a copy of its value during legalization?
+    pub legalized_to_pointer: bool,
 }
 
 impl AbiParam {
@@ -119,6 +121,7 @@ impl AbiParam {
             extension: ArgumentExtension::None,
             purpose: ArgumentPurpose::Normal,
             location: Default::default(),
+            legalized_to_pointer: false,
         }
     }
 
@@ -129,6 +132,7 @@ impl AbiParam {
             extension: ArgumentExtension::None,
             purpose,
             location: Default::default(),
+            legalized_to_pointer: false,
         }
     }
 
@@ -139,6 +143,7 @@ impl AbiParam {
             extension: ArgumentExtension::None,
             purpose,
             location: ArgumentLoc::Reg(regunit),
+            legalized_to_pointer: false,
         }
     }
 
@@ -180,6 +185,9 @@ impl<'a> fmt::Display for DisplayAbiParam<'a> {
         if self.0.purpose != ArgumentPurpose::Normal {
             write!(f, " {}", self.0.purpose)?;
         }
+        if self.0.legalized_to_pointer {
+            write!(f, " ptr")?;
+        }
 
         if self.0.location.is_assigned() {
             write!(f, " [{}]", self.0.location.display(self.1))?;
@@ -271,9 +279,19 @@ pub enum ArgumentPurpose {
     /// This is a pointer to a stack limit. It is used to check the current stack pointer
     /// against. Can only appear once in a signature.
     StackLimit,
+
+    /// A struct passed by value.
+    ///
+    /// The argument is a pointer to the given number of bytes, which are passed to the callee by
+    /// value. ABI legalization copies the bytes into registers or the outgoing argument area as
+    /// the calling convention requires, and the callee sees a pointer to its own copy of them.
+    ///
+    /// After legalization, the struct is represented by consecutive pointer-sized parameters
+    /// holding its bytes, or by a single pointer to a copy if it's passed by reference.
+    StructArgument(u32),
 }
 
-/// Text format names of the `ArgumentPurpose` variants.
+/// Text format names of the `ArgumentPurpose` variants without data.
 static PURPOSE_NAMES: [&str; 8] = [
     "normal",
     "sret",
@@ -287,13 +305,29 @@ static PURPOSE_NAMES: [&str; 8] = [
 
 impl fmt::Display for ArgumentPurpose {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        f.write_str(PURPOSE_NAMES[*self as usize])
+        f.write_str(match *self {
+            ArgumentPurpose::Normal => PURPOSE_NAMES[0],
+            ArgumentPurpose::StructReturn => PURPOSE_NAMES[1],
+            ArgumentPurpose::Link => PURPOSE_NAMES[2],
+            ArgumentPurpose::FramePointer => PURPOSE_NAMES[3],
+            ArgumentPurpose::CalleeSaved => PURPOSE_NAMES[4],
+            ArgumentPurpose::VMContext => PURPOSE_NAMES[5],
+            ArgumentPurpose::SignatureId => PURPOSE_NAMES[6],
+            ArgumentPurpose::StackLimit => PURPOSE_NAMES[7],
+            ArgumentPurpose::StructArgument(size) => return write!(f, "sarg({})", size),
+        })
     }
 }
 
 impl FromStr for ArgumentPurpose {
     type Err = ();
     fn from_str(s: &str) -> Result<Self, ()> {
+        if s.starts_with("sarg(") && s.ends_with(')') {
+            return s[5..s.len() - 1]
+                .parse()
+                .map(ArgumentPurpose::StructArgument)
+                .map_err(|_| ());
+        }
         match s {
             "normal" => Ok(ArgumentPurpose::Normal),
             "sret" => Ok(ArgumentPurpose::StructReturn),
@@ -347,6 +381,10 @@ mod tests {
         assert_eq!(t.sext().to_string(), "i32 sext");
         t.purpose = ArgumentPurpose::StructReturn;
         assert_eq!(t.to_string(), "i32 uext sret");
+        let mut t = AbiParam::special(I32, ArgumentPurpose::StructArgument(12));
+        assert_eq!(t.to_string(), "i32 sarg(12)");
+        t.legalized_to_pointer = true;
+        assert_eq!(t.to_string(), "i32 sarg(12) ptr");
     }
 
     #[test]
@@ -365,6 +403,10 @@ mod tests {
             assert_eq!(e.to_string(), n);
             assert_eq!(Ok(e), n.parse());
         }
+        let sarg = ArgumentPurpose::StructArgument(12);
+        assert_eq!(sarg.to_string(), "sarg(12)");
+        assert_eq!(Ok(sarg), "sarg(12)".parse());
+        assert_eq!(Err(()), "sarg(x)".parse::<ArgumentPurpose>());
     }
 
     #[test]
@@ -8,12 +8,13 @@ use ir::LibCall;
 use std::cmp;
 use std::fmt::{self, Write};
 use std::str::FromStr;
+use std::sync::Arc;
 
 const TESTCASE_NAME_LENGTH: usize = 16;
 
 /// The name of an external is either a reference to a user-defined symbol
-/// table, or a short sequence of ascii bytes so that test cases do not have
-/// to keep track of a sy mbol table.
+/// table, a symbol string, or a short sequence of ascii bytes so that test
+/// cases do not have to keep track of a symbol table.
 ///
 /// External names are primarily used as keys by code using Cranelift to map
 /// from a `cranelift_codegen::ir::FuncRef` or similar to additional associated
@@ -22,7 +23,7 @@ const TESTCASE_NAME_LENGTH: usize = 16;
 /// External names can also serve as a primitive testing and debugging tool.
 /// In particular, many `.clif` test files use function names to identify
 /// functions.
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum ExternalName {
     /// A name in a user-defined symbol table. Cranelift does not interpret
     /// these numbers in any way.
@@ -42,6 +43,12 @@ pub enum ExternalName {
     },
     /// A well-known runtime library function.
     LibCall(LibCall),
+    /// A symbol name of any length, like the name of a function in an
+    /// object file. Cranelift does not interpret the string in any way.
+    ///
+    /// The string is reference counted, so all the clones of a name share a
+    /// single copy of it.
+    Symbol(Arc<str>),
 }
 
 impl ExternalName {
@@ -80,6 +87,36 @@ impl ExternalName {
     pub fn user(namespace: u32, index: u32) -> Self {
         ExternalName::User { namespace, index }
     }
+
+    /// Create a new external name from a symbol string.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use cranelift_codegen::ir::ExternalName;
+    /// // Create `ExternalName` from a string that isn't a valid test case name.
+    /// let name = ExternalName::symbol("hello world");
+    /// assert_eq!(name.to_string(), "\"hello world\"");
+    /// ```
+    pub fn symbol<T: Into<Arc<str>>>(name: T) -> Self {
+        ExternalName::Symbol(name.into())
+    }
+
+    /// Get the string of a `Symbol` name.
+    pub fn as_symbol(&self) -> Option<&str> {
+        match *self {
+            ExternalName::Symbol(ref name) => Some(name),
+            _ => None,
+        }
+    }
+}
+
+/// Resolves external names to the symbol names they stand for.
+///
+/// This allows displaying a function with human-readable names in place of the user-defined
+/// names, which only the code using Cranelift can interpret.
+pub trait NameResolver {
+    /// Get the symbol name for `name`, or `None` to display `name` as is.
+    fn symbol_name(&self, name: &ExternalName) -> Option<&str>;
 }
 
 impl Default for ExternalName {
@@ -100,8 +137,26 @@ impl fmt::Display for ExternalName {
                 Ok(())
             }
             ExternalName::LibCall(lc) => write!(f, "%{}", lc),
+            ExternalName::Symbol(ref name) => write_symbol(f, name),
+        }
+    }
+}
+
+/// Write `name` as a quoted string with the same escapes as Rust string literals.
+fn write_symbol(f: &mut fmt::Formatter, name: &str) -> fmt::Result {
+    f.write_char('"')?;
+    for c in name.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if c.is_control() => write!(f, "\\u{{{:x}}}", u32::from(c))?,
+            c => f.write_char(c)?,
         }
     }
+    f.write_char('"')
 }
 
 impl FromStr for ExternalName {
@@ -149,6 +204,41 @@ mod tests {
         );
     }
 
+    #[test]
+    fn display_symbol() {
+        assert_eq!(ExternalName::symbol("").to_string(), "\"\"");
+        assert_eq!(ExternalName::symbol("printf").to_string(), "\"printf\"");
+        assert_eq!(
+            ExternalName::symbol("a \"b\" \\ c").to_string(),
+            "\"a \\\"b\\\" \\\\ c\""
+        );
+        assert_eq!(
+            ExternalName::symbol("\t\r\n\0\u{7f}").to_string(),
+            "\"\\t\\r\\n\\u{0}\\u{7f}\""
+        );
+        assert_eq!(ExternalName::symbol("π").to_string(), "\"π\"");
+        // Longer than a test case name.
+        assert_eq!(
+            ExternalName::symbol("_ZN4core3fmt5write17h").to_string(),
+            "\"_ZN4core3fmt5write17h\""
+        );
+    }
+
+    #[test]
+    fn symbol_collisions() {
+        // Symbols never compare equal to the other kinds of names, even when they display the
+        // same text inside the quotes.
+        assert_ne!(ExternalName::symbol("foo"), ExternalName::testcase("foo"));
+        assert_ne!(ExternalName::symbol("u0:1"), ExternalName::user(0, 1));
+        assert_ne!(
+            ExternalName::symbol("FloorF32"),
+            ExternalName::LibCall(LibCall::FloorF32)
+        );
+        assert_eq!(ExternalName::symbol("foo"), ExternalName::symbol("foo"));
+        assert_eq!(ExternalName::symbol("foo").as_symbol(), Some("foo"));
+        assert_eq!(ExternalName::testcase("foo").as_symbol(), None);
+    }
+
     #[test]
     fn parsing() {
         assert_eq!(
@@ -0,0 +1,163 @@
+//! Stack frame layout.
+//!
+//! When the prologue and epilogue are inserted, the final layout of the stack frame is recorded in
+//! a `FrameLayout` object. It describes the frame for debuggers and unwinders: how to find the
+//! canonical frame address (CFA), where the callee-saved registers were saved, and where the stack
+//! slots ended up.
+
+use ir::stackslot::{StackOffset, StackSize, StackSlotKind, StackSlots};
+use ir::StackSlot;
+use isa::{RegInfo, RegUnit};
+use std::fmt;
+use std::vec::Vec;
+
+/// How to compute the canonical frame address in the body of a function.
+///
+/// The CFA is the base address that stack slot offsets are relative to. On x86, it is the stack
+/// pointer in the caller *before* the return address was pushed. On RISC ISAs, it is the value of
+/// the stack pointer on entry to the function.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Cfa {
+    /// The CFA is at a fixed offset from the frame pointer register.
+    FramePointer {
+        /// The frame pointer register.
+        reg: RegUnit,
+        /// The offset to add to the frame pointer to get the CFA.
+        offset: StackOffset,
+    },
+    /// There is no frame pointer. The CFA is the stack pointer plus the frame size.
+    StackPointer,
+}
+
+/// The final layout of the stack frame of a function.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FrameLayout {
+    /// The total size of the stack frame, including the return address and saved registers.
+    pub frame_size: StackSize,
+
+    /// How to find the CFA.
+    pub cfa: Cfa,
+
+    /// Registers saved by the prologue, in the order they were saved, with the offsets of their
+    /// save locations relative to the CFA.
+    ///
+    /// This includes the frame pointer and the link register on ISAs that have one. On x86, the
+    /// return address is always saved at `-pointer_bytes`.
+    pub saved_registers: Vec<(RegUnit, StackOffset)>,
+
+    /// The offsets of all the stack slots relative to the CFA.
+    ///
+    /// Unlike `StackSlotData::offset`, the offsets of `OutgoingArg` slots are also relative to the
+    /// CFA here.
+    pub slots: Vec<(StackSlot, StackOffset)>,
+}
+
+impl FrameLayout {
+    /// Create a frame layout for the laid out `stack_slots`.
+    pub fn new(
+        stack_slots: &StackSlots,
+        cfa: Cfa,
+        saved_registers: Vec<(RegUnit, StackOffset)>,
+    ) -> Self {
+        let frame_size = stack_slots
+            .frame_size
+            .expect("stack slots must be laid out first");
+        let slots = stack_slots
+            .iter()
+            .map(|(ss, slot)| {
+                let offset = slot.offset.expect("stack slot has no offset");
+                match slot.kind {
+                    StackSlotKind::OutgoingArg => (ss, offset - frame_size as StackOffset),
+                    _ => (ss, offset),
+                }
+            })
+            .collect();
+        Self {
+            frame_size,
+            cfa,
+            saved_registers,
+            slots,
+        }
+    }
+
+    /// Get the offset of `ss` relative to the CFA.
+    pub fn slot_offset(&self, ss: StackSlot) -> Option<StackOffset> {
+        self.slots
+            .iter()
+            .find(|&&(slot, _)| slot == ss)
+            .map(|&(_, offset)| offset)
+    }
+
+    /// Get the offset relative to the CFA where the prologue saved `reg`.
+    pub fn saved_offset(&self, reg: RegUnit) -> Option<StackOffset> {
+        self.saved_registers
+            .iter()
+            .find(|&&(r, _)| r == reg)
+            .map(|&(_, offset)| offset)
+    }
+
+    /// Return an object that can display this frame layout, using the register info from the
+    /// target ISA.
+    pub fn display<'a, R: Into<Option<&'a RegInfo>>>(&'a self, regs: R) -> DisplayFrameLayout<'a> {
+        DisplayFrameLayout(self, regs.into())
+    }
+}
+
+/// Displaying a `FrameLayout` correctly requires the associated `RegInfo` from the target ISA.
+pub struct DisplayFrameLayout<'a>(&'a FrameLayout, Option<&'a RegInfo>);
+
+impl<'a> DisplayFrameLayout<'a> {
+    fn write_reg(&self, f: &mut fmt::Formatter, reg: RegUnit) -> fmt::Result {
+        match self.1 {
+            Some(regs) => write!(f, "{}", regs.display_regunit(reg)),
+            None => write!(f, "%{}", reg),
+        }
+    }
+}
+
+impl<'a> fmt::Display for DisplayFrameLayout<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let layout = self.0;
+        write!(f, "size {}, cfa = ", layout.frame_size)?;
+        match layout.cfa {
+            Cfa::FramePointer { reg, offset } => {
+                self.write_reg(f, reg)?;
+                write!(f, "{:+}", offset)?;
+            }
+            Cfa::StackPointer => write!(f, "sp+{}", layout.frame_size)?,
+        }
+        for &(reg, offset) in &layout.saved_registers {
+            write!(f, ", ")?;
+            self.write_reg(f, reg)?;
+            write!(f, " at cfa{:+}", offset)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ir::types;
+    use ir::{StackSlotData, StackSlotKind, StackSlots};
+    use std::string::ToString;
+
+    #[test]
+    fn outgoing_args() {
+        let mut sss = StackSlots::new();
+        let ss0 = sss.push(StackSlotData::new(StackSlotKind::SpillSlot, 4));
+        let ss1 = sss.get_outgoing_arg(types::I32, 8);
+        sss[ss0].offset = Some(-20);
+        sss.frame_size = Some(32);
+
+        let layout = FrameLayout::new(&sss, Cfa::StackPointer, vec![(5, -8)]);
+        assert_eq!(layout.slot_offset(ss0), Some(-20));
+        assert_eq!(layout.slot_offset(ss1), Some(-24));
+        assert_eq!(layout.saved_offset(5), Some(-8));
+        assert_eq!(layout.saved_offset(6), None);
+        assert_eq!(
+            layout.display(None).to_string(),
+            "size 32, cfa = sp+32, %5 at cfa-8"
+        );
+    }
+}
@@ -4,20 +4,29 @@
 //! instructions.
 
 use binemit::CodeOffset;
-use entity::{PrimaryMap, SecondaryMap};
+use compaction::do_compact;
+use entity::{EntitySet, PrimaryMap, SecondaryMap};
+use gv_canonicalization::do_canonicalize_global_values;
 use ir;
-use ir::{DataFlowGraph, ExternalName, Layout, Signature};
+use ir::binary::{self, BinaryError};
+use ir::{ConstantPool, DataFlowGraph, ExternalName, FrameLayout, Layout, NameResolver, Signature};
 use ir::{
     Ebb, ExtFuncData, FuncRef, GlobalValue, GlobalValueData, Heap, HeapData, JumpTable,
     JumpTableData, SigRef, StackSlot, StackSlotData, Table, TableData,
 };
-use ir::{EbbOffsets, InstEncodings, SourceLocs, StackSlots, ValueLocations};
+use ir::{
+    BranchHints, EbbFrequencies, EbbOffsets, InstEncodings, PatchableInsts, SourceLocs, StackSlots,
+    ValueLoc, ValueLocations,
+};
 use ir::{JumpTableOffsets, JumpTables};
+use ir::immediates::{Imm64, Offset32};
+use ir::Type;
 use isa::{EncInfo, Encoding, Legalize, TargetIsa};
 use regalloc::RegDiversions;
 use settings::CallConv;
 use std::fmt;
-use write::write_function;
+use std::vec::Vec;
+use write::{write_function, write_function_with_resolver};
 
 /// A function.
 ///
@@ -31,6 +40,13 @@ pub struct Function {
     /// Signature of this function.
     pub signature: Signature,
 
+    /// The pointer type of the target this function is built for.
+    ///
+    /// This allows pointer-sized global values and heaps to be built and verified without a
+    /// `TargetIsa`. When it is `None`, the pointer type of the target ISA is assumed, and
+    /// `Context::compile` fills it in.
+    pub pointer_type: Option<Type>,
+
     /// Stack slots allocated in this function.
     pub stack_slots: StackSlots,
 
@@ -52,6 +68,18 @@ pub struct Function {
     /// Layout of EBBs and instructions in the function body.
     pub layout: Layout,
 
+    /// Optional hints about the direction of conditional branches.
+    ///
+    /// Like `ebb_frequencies`, these are used to decide which EBBs to move out of the way.
+    pub branch_hints: BranchHints,
+
+    /// Optional hints about how often each EBB is executed, for example from a profile.
+    ///
+    /// The numbers are only meaningful relative to each other. When any hints are given,
+    /// `Context::compile` moves the rarely executed EBBs towards the end of the function. The
+    /// hints are not included in the textual IR format.
+    pub ebb_frequencies: EbbFrequencies,
+
     /// Encoding recipe and bits for the legal instructions.
     /// Illegal instructions have the `Encoding::default()` value.
     pub encodings: InstEncodings,
@@ -61,19 +89,39 @@ pub struct Function {
 
     /// Code offsets of the EBB headers.
     ///
-    /// This information is only transiently available after the `binemit::relax_branches` function
-    /// computes it, and it can easily be recomputed by calling that function. It is not included
-    /// in the textual IR format.
+    /// This information is computed by the `binemit::relax_branches` function, which is the last
+    /// step of `Context::compile()`, and it stays valid until the function is changed. It is not
+    /// included in the textual IR format. Use `ebb_offsets()` to read it.
     pub offsets: EbbOffsets,
 
     /// Code offsets of Jump Table headers.
     pub jt_offsets: JumpTableOffsets,
 
+    /// Constants that are loaded from memory, laid out after the jump tables.
+    ///
+    /// Like `offsets`, this is computed by `binemit::relax_branches` and not included in the
+    /// textual IR format.
+    pub constants: ConstantPool,
+
     /// Source locations.
     ///
     /// Track the original source location for each instruction. The source locations are not
     /// interpreted by Cranelift, only preserved.
     pub srclocs: SourceLocs,
+
+    /// Instructions whose immediate or displacement field the embedder wants to patch in the
+    /// emitted code.
+    ///
+    /// The optimizer doesn't fold, merge, or remove patchable instructions, and they are encoded
+    /// with a full-width immediate field that is reported in `Context::patch_points`. Only the
+    /// instructions supported by `TargetIsa::patchable_encoding` can be marked.
+    pub patchable: PatchableInsts,
+
+    /// The final layout of the stack frame.
+    ///
+    /// This is computed when the prologue and epilogue are inserted, and is not included in the
+    /// textual IR format.
+    pub frame_layout: Option<FrameLayout>,
 }
 
 impl Function {
@@ -82,6 +130,7 @@ impl Function {
         Self {
             name,
             signature: sig,
+            pointer_type: None,
             stack_slots: StackSlots::new(),
             global_values: PrimaryMap::new(),
             heaps: PrimaryMap::new(),
@@ -89,17 +138,24 @@ impl Function {
             jump_tables: PrimaryMap::new(),
             dfg: DataFlowGraph::new(),
             layout: Layout::new(),
+            ebb_frequencies: SecondaryMap::new(),
+            branch_hints: SecondaryMap::new(),
             encodings: SecondaryMap::new(),
             locations: SecondaryMap::new(),
             offsets: SecondaryMap::new(),
             jt_offsets: SecondaryMap::new(),
+            constants: ConstantPool::new(),
             srclocs: SecondaryMap::new(),
+            patchable: EntitySet::new(),
+            frame_layout: None,
         }
     }
 
     /// Clear all data structures in this function.
     pub fn clear(&mut self) {
+        self.name = ExternalName::default();
         self.signature.clear(CallConv::Fast);
+        self.pointer_type = None;
         self.stack_slots.clear();
         self.global_values.clear();
         self.heaps.clear();
@@ -107,10 +163,16 @@ impl Function {
         self.jump_tables.clear();
         self.dfg.clear();
         self.layout.clear();
+        self.ebb_frequencies.clear();
+        self.branch_hints.clear();
         self.encodings.clear();
         self.locations.clear();
         self.offsets.clear();
+        self.jt_offsets.clear();
+        self.constants.clear();
         self.srclocs.clear();
+        self.patchable.clear();
+        self.frame_layout = None;
     }
 
     /// Create a new empty, anonymous function with a Fast calling convention.
@@ -118,6 +180,37 @@ impl Function {
         Self::with_name_signature(ExternalName::default(), Signature::new(CallConv::Fast))
     }
 
+    /// Rearrange the EBBs in the layout to follow `order`.
+    ///
+    /// The EBBs keep their instructions. `order` must contain every EBB in the layout exactly
+    /// once, and it must start with the entry block since that determines the function's entry
+    /// point.
+    pub fn set_ebb_order(&mut self, order: &[Ebb]) -> Result<(), EbbOrderError> {
+        let mut seen = EntitySet::new();
+        for &ebb in order {
+            if !self.layout.is_ebb_inserted(ebb) {
+                return Err(EbbOrderError::NotInserted(ebb));
+            }
+            if !seen.insert(ebb) {
+                return Err(EbbOrderError::Duplicate(ebb));
+            }
+        }
+        if let Some(ebb) = self.layout.ebbs().find(|&ebb| !seen.contains(ebb)) {
+            return Err(EbbOrderError::Missing(ebb));
+        }
+        if let Some(&first) = order.first() {
+            if Some(first) != self.layout.entry_block() {
+                return Err(EbbOrderError::EntryMoved(first));
+            }
+        }
+
+        for &ebb in order {
+            self.layout.remove_ebb(ebb);
+            self.layout.append_ebb(ebb);
+        }
+        Ok(())
+    }
+
     /// Creates a jump table in the function, to be used by `br_table` instructions.
     pub fn create_jump_table(&mut self, data: JumpTableData) -> JumpTable {
         self.jump_tables.push(data)
@@ -144,6 +237,49 @@ impl Function {
         self.global_values.push(data)
     }
 
+    /// Returns a global value with the definition `data`, declaring it if there is none yet.
+    pub fn get_or_create_global_value(&mut self, data: GlobalValueData) -> GlobalValue {
+        match self
+            .global_values
+            .iter()
+            .find(|&(_, existing)| *existing == data)
+        {
+            Some((gv, _)) => gv,
+            None => self.global_values.push(data),
+        }
+    }
+
+    /// Returns a global value that loads a `global_type` field at `offset` bytes from the VM
+    /// context, declaring it and the VM context global value if they don't exist yet.
+    ///
+    /// An offset that doesn't fit in the `Offset32` of a `load` global value is added to the VM
+    /// context pointer by an `iadd_imm` global value first. The function must have a `vmctx`
+    /// parameter, it provides the type of the pointer.
+    pub fn vmctx_load(&mut self, offset: i64, global_type: Type, readonly: bool) -> GlobalValue {
+        let addr_type = self
+            .signature
+            .special_param_index(ir::ArgumentPurpose::VMContext)
+            .map(|i| self.signature.params[i].value_type)
+            .expect("Missing vmctx parameter");
+        let vmctx = self.get_or_create_global_value(GlobalValueData::VMContext);
+        let (base, offset) = if offset as i32 as i64 == offset {
+            (vmctx, offset as i32)
+        } else {
+            let base = self.get_or_create_global_value(GlobalValueData::IAddImm {
+                base: vmctx,
+                offset: Imm64::new(offset),
+                global_type: addr_type,
+            });
+            (base, 0)
+        };
+        self.get_or_create_global_value(GlobalValueData::Load {
+            base,
+            offset: Offset32::new(offset),
+            global_type,
+            readonly,
+        })
+    }
+
     /// Declares a heap accessible to the function.
     pub fn create_heap(&mut self, data: HeapData) -> Heap {
         self.heaps.push(data)
@@ -156,7 +292,41 @@ impl Function {
 
     /// Return an object that can display this function with correct ISA-specific annotations.
     pub fn display<'a, I: Into<Option<&'a TargetIsa>>>(&'a self, isa: I) -> DisplayFunction<'a> {
-        DisplayFunction(self, isa.into())
+        DisplayFunction(self, isa.into(), None)
+    }
+
+    /// Return an object that can display this function like `display`, but with the external
+    /// names known to `resolver` replaced by their symbol names.
+    pub fn display_with_resolver<'a, I: Into<Option<&'a TargetIsa>>>(
+        &'a self,
+        isa: I,
+        resolver: &'a NameResolver,
+    ) -> DisplayFunction<'a> {
+        DisplayFunction(self, isa.into(), Some(resolver))
+    }
+
+    /// Encode this function in the compact binary IR format, appending the bytes to `out`.
+    ///
+    /// Only the target-independent parts of the function are encoded. See the `ir::binary`
+    /// module for details.
+    pub fn write_binary(&self, out: &mut Vec<u8>) {
+        binary::write_function(self, out)
+    }
+
+    /// Decode a function that was encoded by `write_binary()`.
+    ///
+    /// Returns an error if `data` is malformed or was written by a different version of
+    /// Cranelift.
+    pub fn read_binary(data: &[u8]) -> Result<Self, BinaryError> {
+        binary::read_function(data)
+    }
+
+    /// Get the pointer type of this function.
+    ///
+    /// This is the declared `pointer_type` if there is one, and the pointer type of `isa`
+    /// otherwise.
+    pub fn pointer_type_or(&self, isa: Option<&TargetIsa>) -> Option<Type> {
+        self.pointer_type.or_else(|| isa.map(|isa| isa.pointer_type()))
     }
 
     /// Find a presumed unique special-purpose function parameter value.
@@ -169,6 +339,22 @@ impl Function {
             .map(|i| self.dfg.ebb_params(entry)[i])
     }
 
+    /// Get an iterator over the EBBs in layout order, with the offsets of their code.
+    ///
+    /// The offset of an EBB is the offset in bytes from the beginning of the function to its first
+    /// instruction, which is also the target of any branch to the EBB. This can be used to map a
+    /// sampled program counter back to the EBB containing it.
+    ///
+    /// This function can only be used after the code layout has been computed by the
+    /// `binemit::relax_branches()` function. The offsets are valid after `Context::compile()`.
+    pub fn ebb_offsets<'a>(&'a self) -> impl Iterator<Item = (Ebb, CodeOffset)> + 'a {
+        assert!(
+            !self.offsets.is_empty(),
+            "Code layout must be computed first"
+        );
+        self.layout.ebbs().map(move |ebb| (ebb, self.offsets[ebb]))
+    }
+
     /// Get an iterator over the instructions in `ebb`, including offsets and encoded instruction
     /// sizes.
     ///
@@ -193,6 +379,82 @@ impl Function {
         }
     }
 
+    /// Get an iterator over all the instructions in layout order, with their encodings, offsets
+    /// and operand locations.
+    ///
+    /// The iterator returns `(offset, encoded, size)` tuples like `inst_offsets()`, where
+    /// `encoded` is the `EncodedInst` returned by `encoded_inst()` for the instruction.
+    /// Instructions without an encoding are skipped.
+    ///
+    /// This function can only be used after the code layout has been computed by the
+    /// `binemit::relax_branches()` function.
+    pub fn encoded_insts<'a>(&'a self, encinfo: &EncInfo) -> EncodedInstIter<'a> {
+        assert!(
+            !self.offsets.is_empty(),
+            "Code layout must be computed first"
+        );
+        EncodedInstIter {
+            encinfo: encinfo.clone(),
+            func: self,
+            divert: RegDiversions::new(),
+            ebbs: self.layout.ebbs(),
+            insts: None,
+            offset: 0,
+        }
+    }
+
+    /// Get the encoding of `inst` and the locations of its operands.
+    ///
+    /// The locations of the arguments take the register diversions in `divert` into account. The
+    /// locations are empty if no locations have been assigned to the values of the function yet.
+    ///
+    /// Returns `None` if `inst` doesn't have an encoding.
+    pub fn encoded_inst(&self, inst: ir::Inst, divert: &RegDiversions) -> Option<EncodedInst> {
+        let encoding = self.encodings.get(inst).cloned()?;
+        let (args, results) = if self.locations.is_empty() {
+            (Vec::new(), Vec::new())
+        } else {
+            (
+                self.dfg
+                    .inst_args(inst)
+                    .iter()
+                    .map(|&arg| divert.get(arg, &self.locations))
+                    .collect(),
+                self.dfg
+                    .inst_results(inst)
+                    .iter()
+                    .map(|&result| self.locations[result])
+                    .collect(),
+            )
+        };
+        Some(EncodedInst {
+            inst,
+            encoding,
+            args,
+            results,
+        })
+    }
+
+    /// Simplify the global values of the function.
+    ///
+    /// Chains of `iadd_imm` global values are folded into their users where the offsets fit,
+    /// global values with the same definition are merged, and the global values that are no longer
+    /// used are removed. The remaining global values are renumbered.
+    pub fn canonicalize_global_values(&mut self) {
+        do_canonicalize_global_values(self)
+    }
+
+    /// Renumber the EBBs, instructions and values of the function densely, in layout order.
+    ///
+    /// The EBBs and instructions that aren't in the layout are dropped, and aliases are
+    /// resolved. The references in instructions, jump tables, encodings, value locations, source
+    /// locations and the other per-entity tables are rewritten, so the function means the same
+    /// thing with smaller entity maps. Analyses like the control flow graph refer to the old
+    /// numbers and must be recomputed.
+    pub fn compact(&mut self) {
+        do_compact(self)
+    }
+
     /// Wrapper around `encode` which assigns `inst` the resulting encoding.
     pub fn update_encoding(&mut self, inst: ir::Inst, isa: &TargetIsa) -> Result<(), Legalize> {
         self.encode(inst, isa).map(|e| self.encodings[inst] = e)
@@ -200,17 +462,56 @@ impl Function {
 
     /// Wrapper around `TargetIsa::encode` for encoding an existing instruction
     /// in the `Function`.
+    ///
+    /// Patchable instructions get their `TargetIsa::patchable_encoding` when there is one.
     pub fn encode(&self, inst: ir::Inst, isa: &TargetIsa) -> Result<Encoding, Legalize> {
+        if self.is_patchable(inst) {
+            if let Some(enc) = isa.patchable_encoding(self, inst) {
+                return Ok(enc);
+            }
+        }
         isa.encode(&self, &self.dfg[inst], self.dfg.ctrl_typevar(inst))
     }
+
+    /// Is `inst` marked as patchable?
+    pub fn is_patchable(&self, inst: ir::Inst) -> bool {
+        self.patchable.contains(inst)
+    }
+}
+
+/// An error in the EBB order passed to `Function::set_ebb_order`.
+#[derive(Fail, Debug, PartialEq, Eq)]
+pub enum EbbOrderError {
+    /// The EBB is not inserted in the layout.
+    #[fail(display = "{} is not in the layout", _0)]
+    NotInserted(Ebb),
+
+    /// The EBB appears more than once.
+    #[fail(display = "{} appears more than once", _0)]
+    Duplicate(Ebb),
+
+    /// The EBB is in the layout, but not in the new order.
+    #[fail(display = "{} is missing", _0)]
+    Missing(Ebb),
+
+    /// The new order starts with an EBB that isn't the entry block.
+    #[fail(display = "{} would replace the entry block", _0)]
+    EntryMoved(Ebb),
 }
 
 /// Wrapper type capable of displaying a `Function` with correct ISA annotations.
-pub struct DisplayFunction<'a>(&'a Function, Option<&'a TargetIsa>);
+pub struct DisplayFunction<'a>(
+    &'a Function,
+    Option<&'a TargetIsa>,
+    Option<&'a NameResolver>,
+);
 
 impl<'a> fmt::Display for DisplayFunction<'a> {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
-        write_function(fmt, self.0, self.1)
+        match self.2 {
+            Some(resolver) => write_function_with_resolver(fmt, self.0, self.1, resolver),
+            None => write_function(fmt, self.0, self.1),
+        }
     }
 }
 
@@ -226,6 +527,88 @@ impl fmt::Debug for Function {
     }
 }
 
+/// The encoding of an instruction and the locations of its operands.
+///
+/// This is the information shown in the annotations of instructions in the textual IR.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EncodedInst {
+    /// The instruction.
+    pub inst: ir::Inst,
+
+    /// The encoding of the instruction.
+    pub encoding: Encoding,
+
+    /// The locations of the value arguments, in the order of `DataFlowGraph::inst_args()`.
+    pub args: Vec<ValueLoc>,
+
+    /// The locations of the results.
+    pub results: Vec<ValueLoc>,
+}
+
+impl EncodedInst {
+    /// Create an object that displays the annotation of the instruction: the encoding followed by
+    /// the locations of the results, like `[RexOp1rr#01,%rax]`.
+    pub fn display<'a>(&'a self, isa: &'a TargetIsa) -> DisplayEncodedInst<'a> {
+        DisplayEncodedInst(self, isa)
+    }
+}
+
+/// Wrapper type capable of displaying an `EncodedInst` with ISA-specific names.
+pub struct DisplayEncodedInst<'a>(&'a EncodedInst, &'a TargetIsa);
+
+impl<'a> fmt::Display for DisplayEncodedInst<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let DisplayEncodedInst(encoded, isa) = *self;
+        write!(f, "[{}", isa.encoding_info().display(encoded.encoding))?;
+        let regs = isa.register_info();
+        for loc in &encoded.results {
+            write!(f, ",{}", loc.display(&regs))?;
+        }
+        write!(f, "]")
+    }
+}
+
+/// Iterator over the encoded instructions of a function: `(offset, encoded, size)`.
+pub struct EncodedInstIter<'a> {
+    encinfo: EncInfo,
+    func: &'a Function,
+    divert: RegDiversions,
+    ebbs: ir::layout::Ebbs<'a>,
+    insts: Option<ir::layout::Insts<'a>>,
+    offset: CodeOffset,
+}
+
+impl<'a> Iterator for EncodedInstIter<'a> {
+    type Item = (CodeOffset, EncodedInst, CodeOffset);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let inst = match self.insts.as_mut().and_then(Iterator::next) {
+                Some(inst) => inst,
+                None => {
+                    let ebb = self.ebbs.next()?;
+                    self.divert.clear();
+                    self.offset = self.func.offsets[ebb];
+                    self.insts = Some(self.func.layout.ebb_insts(ebb));
+                    continue;
+                }
+            };
+
+            // The arguments are read before the diversions made by `inst` itself.
+            let encoded = self.func.encoded_inst(inst, &self.divert);
+            self.divert.apply(&self.func.dfg[inst]);
+            let size =
+                self.encinfo
+                    .byte_size(self.func.encodings[inst], inst, &self.divert, self.func);
+            let offset = self.offset;
+            self.offset += size;
+            if let Some(encoded) = encoded {
+                return Some((offset, encoded, size));
+            }
+        }
+    }
+}
+
 /// Iterator returning instruction offsets and sizes: `(offset, inst, size)`.
 pub struct InstOffsetIter<'a> {
     encinfo: EncInfo,
@@ -251,3 +634,57 @@ impl<'a> Iterator for InstOffsetIter<'a> {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ir::types::{I32, I64};
+    use ir::{AbiParam, ArgumentPurpose};
+    use std::string::ToString;
+
+    fn vmctx_function() -> Function {
+        let mut func = Function::new();
+        func.signature
+            .params
+            .push(AbiParam::special(I64, ArgumentPurpose::VMContext));
+        func
+    }
+
+    #[test]
+    fn vmctx_load() {
+        let mut func = vmctx_function();
+        let gv0 = func.vmctx_load(16, I64, true);
+        let gv1 = func.vmctx_load(24, I32, false);
+        assert_eq!(func.vmctx_load(16, I64, true), gv0);
+        assert_eq!(func.vmctx_load(24, I32, false), gv1);
+        assert_ne!(func.vmctx_load(16, I64, false), gv0);
+        assert_eq!(func.global_values.len(), 4);
+        assert_eq!(
+            func.global_values[gv0].to_string(),
+            "load.i64 notrap aligned readonly gv0+16"
+        );
+        assert_eq!(
+            func.global_values[gv1].to_string(),
+            "load.i32 notrap aligned gv0+24"
+        );
+    }
+
+    #[test]
+    fn vmctx_load_large_offset() {
+        let mut func = vmctx_function();
+        let gv = func.vmctx_load(3 << 30, I64, true);
+        assert_eq!(func.vmctx_load(3 << 30, I64, true), gv);
+        assert_eq!(func.global_values.len(), 3);
+        let base = match func.global_values[gv] {
+            GlobalValueData::Load { base, offset, .. } => {
+                assert_eq!(offset, Offset32::new(0));
+                base
+            }
+            ref data => panic!("Unexpected global value {}", data),
+        };
+        assert_eq!(
+            func.global_values[base].to_string(),
+            "iadd_imm.i64 gv0, 0xc000_0000"
+        );
+    }
+}
@@ -2,11 +2,10 @@
 
 use ir::immediates::{Imm64, Offset32};
 use ir::{ExternalName, GlobalValue, Type};
-use isa::TargetIsa;
 use std::fmt;
 
 /// Information about a global value declaration.
-#[derive(Clone)]
+#[derive(Clone, PartialEq, Eq, Hash)]
 pub enum GlobalValueData {
     /// Value is the address of the VM context struct.
     VMContext,
@@ -25,6 +24,12 @@ pub enum GlobalValueData {
 
         /// Type of the loaded value.
         global_type: Type,
+
+        /// Is the loaded value known to be the same for the whole execution of the function?
+        ///
+        /// Calls may change the values of other loads, for example the bound of a heap that the
+        /// callee resized.
+        readonly: bool,
     },
 
     /// Value is an offset from another global value.
@@ -39,6 +44,24 @@ pub enum GlobalValueData {
         global_type: Type,
     },
 
+    /// Value is another global value plus a scaled index global value.
+    ///
+    /// This can compute the address of an entry in a table indexed by a value that is only known
+    /// at run time, such as the record describing one of the memories of an instance.
+    IAddScaled {
+        /// The base pointer global value.
+        base: GlobalValue,
+
+        /// The index global value. It is zero-extended to the type of the iadd if it is narrower.
+        index: GlobalValue,
+
+        /// Factor the index is multiplied by before it is added to the base.
+        scale: Imm64,
+
+        /// Type of the iadd.
+        global_type: Type,
+    },
+
     /// Value is symbolic, meaning it's a name which will be resolved to an
     /// actual value later (eg. by linking). Cranelift itself does not interpret
     /// this name; it's used by embedders to link with other data structures.
@@ -70,13 +93,22 @@ impl GlobalValueData {
         }
     }
 
-    /// Return the type of this global.
-    pub fn global_type(&self, isa: &TargetIsa) -> Type {
+    /// Assume that `self` is an `GlobalValueData::Symbol` and return its offset.
+    pub fn symbol_offset(&self) -> i64 {
         match *self {
-            GlobalValueData::VMContext { .. } | GlobalValueData::Symbol { .. } => {
-                isa.pointer_type()
-            }
+            GlobalValueData::Symbol { offset, .. } => offset.into(),
+            _ => panic!("only symbols have offsets"),
+        }
+    }
+
+    /// Return the type of this global, given the pointer type of the function using it.
+    ///
+    /// Use `Function::pointer_type_or` to get the pointer type of a function.
+    pub fn global_type(&self, pointer_type: Type) -> Type {
+        match *self {
+            GlobalValueData::VMContext { .. } | GlobalValueData::Symbol { .. } => pointer_type,
             GlobalValueData::IAddImm { global_type, .. }
+            | GlobalValueData::IAddScaled { global_type, .. }
             | GlobalValueData::Load { global_type, .. } => global_type,
         }
     }
@@ -90,27 +122,47 @@ impl fmt::Display for GlobalValueData {
                 base,
                 offset,
                 global_type,
-            } => write!(f, "load.{} notrap aligned {}{}", global_type, base, offset),
+                readonly,
+            } => write!(
+                f,
+                "load.{} notrap aligned {}{}{}",
+                global_type,
+                if readonly { "readonly " } else { "" },
+                base,
+                offset
+            ),
             GlobalValueData::IAddImm {
                 global_type,
                 base,
                 offset,
             } => write!(f, "iadd_imm.{} {}, {}", global_type, base, offset),
+            GlobalValueData::IAddScaled {
+                base,
+                index,
+                scale,
+                global_type,
+            } => write!(
+                f,
+                "iadd_scaled.{} {}, {}, {}",
+                global_type, base, index, scale
+            ),
             GlobalValueData::Symbol {
                 ref name,
                 offset,
                 colocated,
             } => {
+                write!(f, "symbol ")?;
                 if colocated {
                     write!(f, "colocated ")?;
                 }
-                write!(f, "symbol {}", name)?;
+                write!(f, "{}", name)?;
+                // The offset is parsed like an `Offset32`, so it always needs a sign. Large
+                // negative numbers display as unsigned hexadecimal, so print the magnitude.
                 let offset_val: i64 = offset.into();
                 if offset_val > 0 {
-                    write!(f, "+")?;
-                }
-                if offset_val != 0 {
-                    write!(f, "{}", offset)?;
+                    write!(f, "+{}", offset)?;
+                } else if offset_val < 0 {
+                    write!(f, "-{}", Imm64::new(offset_val.wrapping_neg()))?;
                 }
                 Ok(())
             }
@@ -15,6 +15,8 @@ pub struct HeapData {
     pub min_size: Imm64,
 
     /// Size in bytes of the guard pages following the heap.
+    ///
+    /// For a dynamic heap, the guard pages follow the current bound and move with it.
     pub guard_size: Imm64,
 
     /// Heap style, with additional style-specific info.
@@ -22,6 +24,12 @@ pub struct HeapData {
 
     /// The index type for the heap.
     pub index_type: Type,
+
+    /// Check that heap accesses are aligned.
+    ///
+    /// When set, a `heap_addr` instruction also traps with `heap_misaligned` unless its offset is
+    /// a multiple of the largest power of two dividing its access size.
+    pub check_alignment: bool,
 }
 
 /// Style of heap including style-specific information.
@@ -57,6 +65,10 @@ impl fmt::Display for HeapData {
             f,
             ", guard {}, index_type {}",
             self.guard_size, self.index_type
-        )
+        )?;
+        if self.check_alignment {
+            f.write_str(", check_alignment")?;
+        }
+        Ok(())
     }
 }
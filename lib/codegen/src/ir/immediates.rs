@@ -26,6 +26,33 @@ impl Imm64 {
     pub fn wrapping_neg(self) -> Self {
         Imm64(self.0.wrapping_neg())
     }
+
+    /// Sign-extend the low `bit_width` bits of self to 64 bits.
+    ///
+    /// This is the canonical representation of an immediate operand for an integer type with
+    /// `bit_width` bits, so `0xffff_ffff` and `-1` are the same 32-bit immediate.
+    pub fn sign_extend_from_width(self, bit_width: u16) -> Self {
+        if bit_width >= 64 {
+            return self;
+        }
+        let shift = 64 - u32::from(bit_width);
+        Imm64((self.0 << shift) >> shift)
+    }
+
+    /// Zero-extend the low `bit_width` bits of self to 64 bits.
+    pub fn zero_extend_from_width(self, bit_width: u16) -> Self {
+        if bit_width >= 64 {
+            return self;
+        }
+        Imm64(self.0 & ((1 << bit_width) - 1))
+    }
+
+    /// Does self fit in `bit_width` bits as either a signed or an unsigned number?
+    pub fn fits_in_width(self, bit_width: u16) -> bool {
+        bit_width >= 64
+            || self.sign_extend_from_width(bit_width) == self
+            || (self.0 as u64) >> bit_width == 0
+    }
 }
 
 impl Into<i64> for Imm64 {
@@ -670,6 +697,7 @@ mod tests {
     use std::fmt::Display;
     use std::str::FromStr;
     use std::string::ToString;
+    use std::vec::Vec;
     use std::{f32, f64};
 
     #[test]
@@ -683,6 +711,40 @@ mod tests {
         assert_eq!(Imm64(0x10000).to_string(), "0x0001_0000");
     }
 
+    #[test]
+    fn imm64_width() {
+        // The same 32-bit immediate as a signed and an unsigned number.
+        assert_eq!(Imm64(0xffff_ffff).sign_extend_from_width(32), Imm64(-1));
+        assert_eq!(Imm64(-1).sign_extend_from_width(32), Imm64(-1));
+        assert_eq!(Imm64(-1).zero_extend_from_width(32), Imm64(0xffff_ffff));
+        assert_eq!(
+            Imm64(0x8000_0000).sign_extend_from_width(32),
+            Imm64(-0x8000_0000)
+        );
+        assert_eq!(
+            Imm64(0x7fff_ffff).sign_extend_from_width(32),
+            Imm64(0x7fff_ffff)
+        );
+        assert_eq!(Imm64(0x80).sign_extend_from_width(8), Imm64(-128));
+        assert_eq!(Imm64(0x7f).sign_extend_from_width(8), Imm64(127));
+        assert_eq!(Imm64(-128).zero_extend_from_width(8), Imm64(0x80));
+        assert_eq!(Imm64(0xffff).sign_extend_from_width(16), Imm64(-1));
+        assert_eq!(Imm64(-1).sign_extend_from_width(64), Imm64(-1));
+        assert_eq!(Imm64(-1).zero_extend_from_width(64), Imm64(-1));
+
+        // Probe the limits of the signed and unsigned ranges.
+        assert!(Imm64(0xffff_ffff).fits_in_width(32));
+        assert!(Imm64(-0x8000_0000).fits_in_width(32));
+        assert!(!Imm64(0x1_0000_0000).fits_in_width(32));
+        assert!(!Imm64(-0x8000_0001).fits_in_width(32));
+        assert!(Imm64(255).fits_in_width(8));
+        assert!(Imm64(-128).fits_in_width(8));
+        assert!(!Imm64(256).fits_in_width(8));
+        assert!(!Imm64(-129).fits_in_width(8));
+        assert!(Imm64(i64::min_value()).fits_in_width(64));
+        assert!(Imm64(-1).fits_in_width(64));
+    }
+
     // Verify that `text` can be parsed as a `T` into a value that displays as `want`.
     fn parse_ok<T: FromStr + Display>(text: &str, want: &str)
     where
@@ -1036,6 +1098,103 @@ mod tests {
         assert_eq!(Ieee64::pow2(1).neg().to_string(), "-0x1.0000000000000p1");
     }
 
+    /// A small xorshift generator, so the round-trip tests are reproducible.
+    struct XorShift(u64);
+
+    impl XorShift {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+    }
+
+    /// Bit patterns that probe the boundaries of the `bits`-bit integers and of the IEEE formats
+    /// with `t` trailing significand bits, followed by random bit patterns.
+    fn test_bits(bits: u8, t: u8) -> Vec<u64> {
+        let mask = if bits == 64 { !0 } else { (1u64 << bits) - 1 };
+        let sign = 1u64 << (bits - 1);
+        let exp = mask & !sign & !((1u64 << t) - 1);
+        let quiet = 1u64 << (t - 1);
+        let mut values = vec![
+            0,
+            1,
+            mask,
+            sign,
+            sign - 1,
+            sign + 1,
+            9999,
+            10000,
+            mask - 9998,
+            mask - 9999,
+            exp,
+            exp | sign,
+            exp | 1,
+            exp | sign | 1,
+            exp | quiet,
+            exp | sign | quiet,
+            exp | quiet | 1,
+            exp | sign | (quiet - 1),
+            exp - 1,
+            (1 << t) - 1,
+            1 << t,
+        ];
+        let mut rng = XorShift(0x2545_f491_4f6c_dd1d);
+        for _ in 0..100_000 {
+            let x = rng.next();
+            values.push(x & mask);
+            // Also probe the special exponents with random payloads.
+            values.push((x & mask) | exp);
+            values.push((x & mask) & !exp);
+        }
+        values
+    }
+
+    #[test]
+    fn round_trip_imm64() {
+        for bits in test_bits(64, 52) {
+            let imm = Imm64(bits as i64);
+            assert_eq!(imm.to_string().parse(), Ok(imm), "{}", imm);
+        }
+    }
+
+    #[test]
+    fn round_trip_uimm32() {
+        for bits in test_bits(32, 23) {
+            let imm = Uimm32(bits as u32);
+            assert_eq!(imm.to_string().parse(), Ok(imm), "{}", imm);
+        }
+    }
+
+    #[test]
+    fn round_trip_offset32() {
+        for bits in test_bits(32, 23) {
+            let imm = Offset32(bits as i32);
+            let text = imm.to_string();
+            // The empty zero offset is parsed by its absence.
+            if !text.is_empty() {
+                assert_eq!(text.parse(), Ok(imm), "{}", imm);
+            }
+        }
+    }
+
+    #[test]
+    fn round_trip_ieee32() {
+        for bits in test_bits(32, 23) {
+            let imm = Ieee32(bits as u32);
+            assert_eq!(imm.to_string().parse(), Ok(imm), "{}", imm);
+        }
+    }
+
+    #[test]
+    fn round_trip_ieee64() {
+        for bits in test_bits(64, 52) {
+            let imm = Ieee64(bits);
+            assert_eq!(imm.to_string().parse(), Ok(imm), "{}", imm);
+        }
+    }
+
     #[test]
     fn fcvt_to_sint_negative_overflow_ieee64() {
         for n in &[8, 16, 32] {
@@ -12,6 +12,7 @@ use std::str::FromStr;
 use std::vec::Vec;
 
 use ir;
+use ir::entities::AnyEntity;
 use ir::types;
 use ir::{Ebb, FuncRef, JumpTable, SigRef, Type, Value};
 use isa;
@@ -34,6 +35,8 @@ pub type ValueListPool = entity::ListPool<Value>;
 // - The `pub enum InstructionData` enum with all the instruction data fields.
 // - The `pub enum Opcode` definition with all known opcodes,
 // - The `const OPCODE_FORMAT: [InstructionFormat; N]` table.
+// - The `const OPCODE_LIST: [Opcode; N]` table of all opcodes in numerical order.
+// - The `INSTRUCTION_SET_HASH` fingerprint of the instruction set.
 // - The private `fn opcode_name(Opcode) -> &'static str` function, and
 // - The hash table `const OPCODE_HASH_TABLE: [Opcode; N]`.
 //
@@ -62,6 +65,18 @@ impl Opcode {
     pub fn constraints(self) -> OpcodeConstraints {
         OPCODE_CONSTRAINTS[self as usize - 1]
     }
+
+    /// Get the opcode whose numerical value is `n`, the inverse of `opcode as u8`.
+    ///
+    /// Returns `None` if `n` doesn't correspond to any opcode.
+    pub fn from_u8(n: u8) -> Option<Self> {
+        OPCODE_LIST.get((n as usize).wrapping_sub(1)).cloned()
+    }
+
+    /// Get a slice of all the opcodes, in numerical order.
+    pub fn all() -> &'static [Self] {
+        &OPCODE_LIST
+    }
 }
 
 // This trait really belongs in lib/reader where it is used by the `.clif` file parser, but since
@@ -275,6 +290,94 @@ impl InstructionData {
             }
         }
     }
+
+    /// Get the entity declared in the function preamble that this instruction refers to.
+    ///
+    /// This is the heap, table, global value, jump table, stack slot, signature, or external
+    /// function used by the instruction. Values and EBBs are not preamble entities.
+    pub fn referenced_entity(&self) -> Option<AnyEntity> {
+        match *self {
+            InstructionData::UnaryGlobalValue { global_value, .. }
+            | InstructionData::LoadSymbol { global_value, .. }
+            | InstructionData::StoreSymbol { global_value, .. }
+            | InstructionData::LoadGlobal { global_value, .. } => Some(global_value.into()),
+            InstructionData::HeapAddr { heap, .. } => Some(heap.into()),
+            InstructionData::TableAddr { table, .. } => Some(table.into()),
+            InstructionData::BranchTable { table, .. }
+            | InstructionData::BranchTableEntry { table, .. }
+            | InstructionData::BranchTableBase { table, .. }
+            | InstructionData::IndirectJump { table, .. } => Some(table.into()),
+            InstructionData::StackLoad { stack_slot, .. }
+            | InstructionData::StackStore { stack_slot, .. }
+            | InstructionData::RegSpill {
+                dst: stack_slot, ..
+            }
+            | InstructionData::RegFill {
+                src: stack_slot, ..
+            } => Some(stack_slot.into()),
+            InstructionData::Call { func_ref, .. } | InstructionData::FuncAddr { func_ref, .. } => {
+                Some(func_ref.into())
+            }
+            InstructionData::CallIndirect { sig_ref, .. } => Some(sig_ref.into()),
+            _ => None,
+        }
+    }
+
+    /// Sign-extend the integer immediate operand of this instruction from the width of the
+    /// controlling type variable `ctrl_typevar`, which is its canonical representation.
+    ///
+    /// Shift and rotate amounts are taken modulo the type width, so they are reduced to that range
+    /// instead of being range checked.
+    ///
+    /// Returns false and leaves the immediate unchanged if it doesn't fit in the type as either a
+    /// signed or an unsigned number.
+    pub fn sign_extend_imm(&mut self, ctrl_typevar: Type) -> bool {
+        if !ctrl_typevar.is_int() {
+            return true;
+        }
+        let bits = ctrl_typevar.bits();
+        match *self {
+            InstructionData::BinaryImm {
+                opcode: Opcode::IshlImm,
+                ref mut imm,
+                ..
+            }
+            | InstructionData::BinaryImm {
+                opcode: Opcode::UshrImm,
+                ref mut imm,
+                ..
+            }
+            | InstructionData::BinaryImm {
+                opcode: Opcode::SshrImm,
+                ref mut imm,
+                ..
+            }
+            | InstructionData::BinaryImm {
+                opcode: Opcode::RotlImm,
+                ref mut imm,
+                ..
+            }
+            | InstructionData::BinaryImm {
+                opcode: Opcode::RotrImm,
+                ref mut imm,
+                ..
+            } => {
+                let amount: i64 = (*imm).into();
+                *imm = (amount & i64::from(bits - 1)).into();
+                true
+            }
+            InstructionData::UnaryImm { ref mut imm, .. }
+            | InstructionData::BinaryImm { ref mut imm, .. }
+            | InstructionData::IntCompareImm { ref mut imm, .. } => {
+                if !imm.fits_in_width(bits) {
+                    return false;
+                }
+                *imm = imm.sign_extend_from_width(bits);
+                true
+            }
+            _ => true,
+        }
+    }
 }
 
 /// Information about branch and jump instructions.
@@ -4,17 +4,20 @@
 //! The actual table of destinations is stored in a `JumpTableData` struct defined in this module.
 
 use ir::entities::Ebb;
+use packed_option::PackedOption;
 use std::fmt::{self, Display, Formatter};
-use std::slice::{Iter, IterMut};
+use std::iter::FilterMap;
+use std::slice::Iter;
 use std::vec::Vec;
 
 /// Contents of a jump table.
 ///
-/// All jump tables use 0-based indexing and densely populated.
+/// All jump tables use 0-based indexing. An entry can be a hole, which branches to the default
+/// destination of the `br_table` instruction using the table, like an out-of-bounds index does.
 #[derive(Clone)]
 pub struct JumpTableData {
-    // Table entries.
-    table: Vec<Ebb>,
+    // Table entries, with `None` for the holes.
+    table: Vec<PackedOption<Ebb>>,
 }
 
 impl JumpTableData {
@@ -30,51 +33,78 @@ impl JumpTableData {
         }
     }
 
-    /// Get the number of table entries.
+    /// Get the number of table entries, including the holes.
     pub fn len(&self) -> usize {
         self.table.len()
     }
 
     /// Append a table entry.
     pub fn push_entry(&mut self, dest: Ebb) {
-        self.table.push(dest)
+        self.table.push(dest.into())
+    }
+
+    /// Append a hole, which branches to the default destination.
+    pub fn push_hole(&mut self) {
+        self.table.push(None.into())
+    }
+
+    /// Get the destination of entry `idx`, or `None` if it is a hole or out of bounds.
+    pub fn get_entry(&self, idx: usize) -> Option<Ebb> {
+        self.table.get(idx).and_then(|dest| dest.expand())
+    }
+
+    /// Does the table have any holes?
+    pub fn has_holes(&self) -> bool {
+        self.table.iter().any(|dest| dest.is_none())
+    }
+
+    /// Replace all the holes with `dest`.
+    pub fn fill_holes(&mut self, dest: Ebb) {
+        for entry in &mut self.table {
+            if entry.is_none() {
+                *entry = dest.into();
+            }
+        }
     }
 
     /// Checks if any of the entries branch to `ebb`.
     pub fn branches_to(&self, ebb: Ebb) -> bool {
-        self.table.iter().any(|target_ebb| *target_ebb == ebb)
+        self.iter().any(|&target_ebb| target_ebb == ebb)
     }
 
-    /// Access the whole table as a slice.
-    pub fn as_slice(&self) -> &[Ebb] {
+    /// Access the whole table as a slice, with `None` for the holes.
+    pub fn as_slice(&self) -> &[PackedOption<Ebb>] {
         self.table.as_slice()
     }
 
-    /// Access the whole table as a mutable slice.
-    pub fn as_mut_slice(&mut self) -> &mut [Ebb] {
+    /// Access the whole table as a mutable slice, with `None` for the holes.
+    pub fn as_mut_slice(&mut self) -> &mut [PackedOption<Ebb>] {
         self.table.as_mut_slice()
     }
 
-    /// Returns an iterator over the table.
-    pub fn iter(&self) -> Iter<Ebb> {
-        self.table.iter()
-    }
-
-    /// Returns an iterator that allows modifying each value.
-    pub fn iter_mut(&mut self) -> IterMut<Ebb> {
-        self.table.iter_mut()
+    /// Returns an iterator over the destinations of the table, skipping the holes.
+    pub fn iter(&self) -> Entries {
+        self.table
+            .iter()
+            .filter_map(PackedOption::as_ref as fn(&PackedOption<Ebb>) -> Option<&Ebb>)
     }
 }
 
+/// Iterator over the destinations of a jump table, skipping the holes.
+pub type Entries<'a> =
+    FilterMap<Iter<'a, PackedOption<Ebb>>, fn(&PackedOption<Ebb>) -> Option<&Ebb>>;
+
 impl Display for JumpTableData {
     fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
         write!(fmt, "jump_table [")?;
-        match self.table.first() {
-            None => (),
-            Some(first) => write!(fmt, "{}", first)?,
-        }
-        for ebb in self.table.iter().skip(1) {
-            write!(fmt, ", {}", ebb)?;
+        for (i, dest) in self.table.iter().enumerate() {
+            if i > 0 {
+                write!(fmt, ", ")?;
+            }
+            match dest.expand() {
+                Some(ebb) => write!(fmt, "{}", ebb)?,
+                None => write!(fmt, "_")?,
+            }
         }
         write!(fmt, "]")
     }
@@ -86,6 +116,7 @@ mod tests {
     use entity::EntityRef;
     use ir::Ebb;
     use std::string::ToString;
+    use std::vec::Vec;
 
     #[test]
     fn empty() {
@@ -114,6 +145,31 @@ mod tests {
         assert_eq!(jt.to_string(), "jump_table [ebb1, ebb2, ebb1]");
 
         let v = jt.as_slice();
-        assert_eq!(v, [e1, e2, e1]);
+        assert_eq!(v, [e1.into(), e2.into(), e1.into()]);
+    }
+
+    #[test]
+    fn holes() {
+        let e1 = Ebb::new(1);
+        let e2 = Ebb::new(2);
+
+        let mut jt = JumpTableData::new();
+
+        jt.push_entry(e1);
+        jt.push_hole();
+        jt.push_entry(e2);
+        jt.push_hole();
+
+        assert_eq!(jt.to_string(), "jump_table [ebb1, _, ebb2, _]");
+        assert_eq!(jt.len(), 4);
+        assert!(jt.has_holes());
+        assert_eq!(jt.get_entry(0), Some(e1));
+        assert_eq!(jt.get_entry(1), None);
+        assert_eq!(jt.get_entry(4), None);
+        assert_eq!(jt.iter().cloned().collect::<Vec<_>>(), [e1, e2]);
+
+        jt.fill_holes(e2);
+        assert!(!jt.has_holes());
+        assert_eq!(jt.to_string(), "jump_table [ebb1, ebb2, ebb2, ebb2]");
     }
 }
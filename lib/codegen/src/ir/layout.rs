@@ -179,6 +179,41 @@ impl Layout {
         }
     }
 
+    /// Assign valid sequence numbers to a non-empty `ebb` that was moved to a new position in the
+    /// layout, along with all of its instructions. This may require renumbering.
+    fn assign_moved_ebb_seq(&mut self, ebb: Ebb) {
+        let first_inst = match self.ebbs[ebb].first_inst.expand() {
+            Some(inst) => inst,
+            None => return self.assign_ebb_seq(ebb),
+        };
+
+        // Get the sequence number immediately before `ebb`, or 0.
+        let prev_seq = self.ebbs[ebb]
+            .prev
+            .map(|prev_ebb| self.last_ebb_seq(prev_ebb))
+            .unwrap_or(0);
+
+        // Spread the EBB header and its instructions evenly over the available numbers.
+        let count = 1 + self.ebb_insts(ebb).count() as SequenceNumber;
+        let stride = match self.ebbs[ebb].next.expand() {
+            Some(next_ebb) => (self.ebbs[next_ebb].seq - prev_seq) / (count + 1),
+            None => MAJOR_STRIDE,
+        };
+        if stride == 0 {
+            self.full_renumber();
+            return;
+        }
+
+        let mut seq = prev_seq + stride;
+        self.ebbs[ebb].seq = seq;
+        let mut next_inst = Some(first_inst);
+        while let Some(inst) = next_inst {
+            seq += stride;
+            self.insts[inst].seq = seq;
+            next_inst = self.insts[inst].next.expand();
+        }
+    }
+
     /// Assign a valid sequence number to `inst` such that the numbers are still monotonic. This may
     /// require renumbering.
     fn assign_inst_seq(&mut self, inst: Inst) {
@@ -323,8 +358,10 @@ impl Layout {
 /// Methods for laying out EBBs.
 ///
 /// An unknown EBB starts out as *not inserted* in the EBB layout. The layout is a linear order of
-/// inserted EBBs. Once an EBB has been inserted in the layout, instructions can be added. An EBB
-/// can only be removed from the layout when it is empty.
+/// inserted EBBs. Once an EBB has been inserted in the layout, instructions can be added.
+///
+/// An EBB that is removed from the layout keeps its instructions, so it can be inserted again at
+/// a different position. This is how EBBs are reordered.
 ///
 /// Since every EBB must end with a terminator instruction which cannot fall through, the layout of
 /// EBBs do not affect the semantics of the program.
@@ -343,7 +380,6 @@ impl Layout {
         );
         {
             let node = &mut self.ebbs[ebb];
-            debug_assert!(node.first_inst.is_none() && node.last_inst.is_none());
             node.prev = self.last_ebb.into();
             node.next = None.into();
         }
@@ -353,7 +389,7 @@ impl Layout {
             self.first_ebb = Some(ebb);
         }
         self.last_ebb = Some(ebb);
-        self.assign_ebb_seq(ebb);
+        self.assign_moved_ebb_seq(ebb);
     }
 
     /// Insert `ebb` in the layout before the existing EBB `before`.
@@ -377,7 +413,7 @@ impl Layout {
             None => self.first_ebb = Some(ebb),
             Some(a) => self.ebbs[a].next = ebb.into(),
         }
-        self.assign_ebb_seq(ebb);
+        self.assign_moved_ebb_seq(ebb);
     }
 
     /// Insert `ebb` in the layout *after* the existing EBB `after`.
@@ -401,13 +437,14 @@ impl Layout {
             None => self.last_ebb = Some(ebb),
             Some(b) => self.ebbs[b].prev = ebb.into(),
         }
-        self.assign_ebb_seq(ebb);
+        self.assign_moved_ebb_seq(ebb);
     }
 
     /// Remove `ebb` from the layout.
+    ///
+    /// The instructions in `ebb` stay with it, and they come along when `ebb` is inserted again.
     pub fn remove_ebb(&mut self, ebb: Ebb) {
         debug_assert!(self.is_ebb_inserted(ebb), "EBB not in the layout");
-        debug_assert!(self.first_inst(ebb).is_none(), "EBB must be empty.");
 
         // Clear the `ebb` node and extract links.
         let prev;
@@ -743,7 +780,7 @@ mod tests {
     use super::Layout;
     use cursor::{Cursor, CursorPosition};
     use entity::EntityRef;
-    use ir::{Ebb, Inst, ProgramOrder, SourceLoc};
+    use ir::{Ebb, ExpandedProgramPoint, Inst, ProgramOrder, SourceLoc};
     use std::cmp::Ordering;
     use std::vec::Vec;
 
@@ -951,6 +988,61 @@ mod tests {
         verify(&mut layout, &[(e1, &[]), (e0, &[]), (e2, &[])]);
     }
 
+    #[test]
+    fn move_ebb() {
+        let mut layout = Layout::new();
+        let e0 = Ebb::new(0);
+        let e1 = Ebb::new(1);
+        let e2 = Ebb::new(2);
+        let i0 = Inst::new(0);
+        let i1 = Inst::new(1);
+        let i2 = Inst::new(2);
+        let i3 = Inst::new(3);
+
+        layout.append_ebb(e0);
+        layout.append_inst(i0, e0);
+        layout.append_ebb(e1);
+        layout.append_inst(i1, e1);
+        layout.append_inst(i2, e1);
+        layout.append_ebb(e2);
+        layout.append_inst(i3, e2);
+        verify(&mut layout, &[(e0, &[i0]), (e1, &[i1, i2]), (e2, &[i3])]);
+
+        // Removed EBBs keep their instructions.
+        layout.remove_ebb(e1);
+        assert!(!layout.is_ebb_inserted(e1));
+        assert_eq!(layout.inst_ebb(i1), Some(e1));
+        verify(&mut layout, &[(e0, &[i0]), (e2, &[i3])]);
+
+        layout.append_ebb(e1);
+        verify(&mut layout, &[(e0, &[i0]), (e2, &[i3]), (e1, &[i1, i2])]);
+
+        layout.remove_ebb(e2);
+        layout.insert_ebb(e2, e0);
+        verify(&mut layout, &[(e2, &[i3]), (e0, &[i0]), (e1, &[i1, i2])]);
+
+        layout.remove_ebb(e1);
+        layout.insert_ebb_after(e1, e2);
+        verify(&mut layout, &[(e2, &[i3]), (e1, &[i1, i2]), (e0, &[i0])]);
+
+        // Squeeze an EBB between two EBBs that leave no room for its instructions.
+        layout.remove_ebb(e0);
+        for i in 4..10 {
+            layout.insert_inst(Inst::new(i), i1);
+        }
+        layout.insert_ebb_after(e0, e2);
+        let mut points: Vec<ExpandedProgramPoint> = Vec::new();
+        for ebb in layout.ebbs() {
+            points.push(ebb.into());
+            points.extend(layout.ebb_insts(ebb).map(ExpandedProgramPoint::from));
+        }
+        assert_eq!(points.len(), 13);
+        assert_eq!(points[2], e0.into());
+        for pair in points.windows(2) {
+            assert!(layout.seq(pair[0]) < layout.seq(pair[1]));
+        }
+    }
+
     #[test]
     fn append_inst() {
         let mut layout = Layout::new();
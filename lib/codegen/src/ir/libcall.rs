@@ -5,9 +5,10 @@ use ir::{
     Signature, Type,
 };
 use isa::{RegUnit, TargetIsa};
-use settings::CallConv;
+use settings::{self, CallConv};
 use std::fmt;
 use std::str::FromStr;
+use std::vec::Vec;
 
 /// The name of a runtime library routine.
 ///
@@ -17,7 +18,7 @@ use std::str::FromStr;
 /// convention in the embedding VM's runtime library.
 ///
 /// This list is likely to grow over time.
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum LibCall {
     /// probe for stack overflow. These are emitted for functions which need
     /// when the `probestack_enabled` setting is true.
@@ -44,6 +45,8 @@ pub enum LibCall {
     Memset,
     /// libc.memmove
     Memmove,
+    /// read_cycle_counter
+    CycleCounter,
 }
 
 impl fmt::Display for LibCall {
@@ -69,17 +72,41 @@ impl FromStr for LibCall {
             "Memcpy" => Ok(LibCall::Memcpy),
             "Memset" => Ok(LibCall::Memset),
             "Memmove" => Ok(LibCall::Memmove),
+            "CycleCounter" => Ok(LibCall::CycleCounter),
             _ => Err(()),
         }
     }
 }
 
 impl LibCall {
+    /// Get all the library calls.
+    pub fn all_libcalls() -> &'static [Self] {
+        use self::LibCall::*;
+        &[
+            Probestack,
+            CeilF32,
+            CeilF64,
+            FloorF32,
+            FloorF64,
+            TruncF32,
+            TruncF64,
+            NearestF32,
+            NearestF64,
+            Memcpy,
+            Memset,
+            Memmove,
+            CycleCounter,
+        ]
+    }
+
     /// Get the well-known library call name to use as a replacement for an instruction with the
     /// given opcode and controlling type variable.
     ///
     /// Returns `None` if no well-known library routine name exists for that instruction.
     pub fn for_inst(opcode: Opcode, ctrl_type: Type) -> Option<Self> {
+        if opcode == Opcode::ReadCycleCounter {
+            return Some(LibCall::CycleCounter);
+        }
         Some(match ctrl_type {
             types::F32 => match opcode {
                 Opcode::Ceil => LibCall::CeilF32,
@@ -98,6 +125,114 @@ impl LibCall {
             _ => return None,
         })
     }
+
+    /// Get the signature of this library routine on `isa`.
+    ///
+    /// The argument of `Probestack` is passed in a register chosen by the ISA, so its signature
+    /// is only accurate up to the argument locations.
+    pub fn signature(self, isa: &TargetIsa) -> Signature {
+        let pointer_type = isa.pointer_type();
+        let (params, returns): (&[Type], &[Type]) = match self {
+            LibCall::Probestack => {
+                let mut sig = Signature::new(CallConv::Probestack);
+                sig.params.push(AbiParam::new(pointer_type));
+                if !isa.flags().probestack_func_adjusts_sp() {
+                    sig.returns.push(AbiParam::new(pointer_type));
+                }
+                return sig;
+            }
+            LibCall::CeilF32 | LibCall::FloorF32 | LibCall::TruncF32 | LibCall::NearestF32 => {
+                (&[types::F32], &[types::F32])
+            }
+            LibCall::CeilF64 | LibCall::FloorF64 | LibCall::TruncF64 | LibCall::NearestF64 => {
+                (&[types::F64], &[types::F64])
+            }
+            LibCall::Memcpy | LibCall::Memmove => {
+                (&[pointer_type, pointer_type, pointer_type], &[])
+            }
+            LibCall::Memset => (&[pointer_type, types::I32, pointer_type], &[]),
+            LibCall::CycleCounter => (&[], &[types::I64]),
+        };
+        let mut sig = Signature::new(isa.flags().call_conv());
+        sig.params
+            .extend(params.iter().map(|&ty| AbiParam::new(ty)));
+        sig.returns
+            .extend(returns.iter().map(|&ty| AbiParam::new(ty)));
+        sig
+    }
+}
+
+/// The names of the runtime library routines, and whether they are colocated.
+///
+/// By default, a `LibCall` is referred to by an `ExternalName::LibCall` name and the
+/// `colocated_libcalls` setting decides whether it is colocated. An embedding whose runtime
+/// library uses other names or relocations can override that for individual library calls, and
+/// configure the `TargetIsa` to use them with `isa::Builder::set_libcall_names`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LibcallNames {
+    names: Vec<(LibCall, ExternalName)>,
+    colocated: Vec<(LibCall, bool)>,
+}
+
+impl LibcallNames {
+    /// Create a configuration that uses the default names for all library calls.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Refer to `libcall` as `name`.
+    pub fn set_name(&mut self, libcall: LibCall, name: ExternalName) {
+        self.names.retain(|&(lc, _)| lc != libcall);
+        self.names.push((libcall, name));
+    }
+
+    /// Decide whether `libcall` is colocated, regardless of the `colocated_libcalls` setting.
+    pub fn set_colocated(&mut self, libcall: LibCall, colocated: bool) {
+        self.colocated.retain(|&(lc, _)| lc != libcall);
+        self.colocated.push((libcall, colocated));
+    }
+
+    /// Get the name to use for `libcall`.
+    pub fn name(&self, libcall: LibCall) -> ExternalName {
+        self.names
+            .iter()
+            .find(|&&(lc, _)| lc == libcall)
+            .map_or(ExternalName::LibCall(libcall), |&(_, ref name)| {
+                name.clone()
+            })
+    }
+
+    /// Is `libcall` colocated with the functions that call it?
+    pub fn colocated(&self, libcall: LibCall, flags: &settings::Flags) -> bool {
+        self.colocated
+            .iter()
+            .find(|&&(lc, _)| lc == libcall)
+            .map_or(flags.colocated_libcalls(), |&(_, colocated)| colocated)
+    }
+
+    /// Get the library call that `name` refers to, if any.
+    pub fn libcall(&self, name: &ExternalName) -> Option<LibCall> {
+        LibCall::all_libcalls()
+            .iter()
+            .cloned()
+            .find(|&lc| self.name(lc) == *name)
+    }
+}
+
+/// Import `libcall` with the signature `sig` into `func`, using the names configured in `isa`.
+pub fn import_libcall(
+    libcall: LibCall,
+    func: &mut Function,
+    sig: Signature,
+    isa: &TargetIsa,
+) -> FuncRef {
+    let sigref = func.import_signature(sig);
+    let names = isa.libcall_names();
+    func.import_function(ExtFuncData {
+        name: names.name(libcall),
+        signature: sigref,
+        colocated: names.colocated(libcall, isa.flags()),
+    })
 }
 
 /// Get a function reference for `libcall` in `func`, following the signature
@@ -110,7 +245,8 @@ pub fn get_libcall_funcref(
     inst: Inst,
     isa: &TargetIsa,
 ) -> FuncRef {
-    find_funcref(libcall, func).unwrap_or_else(|| make_funcref_for_inst(libcall, func, inst, isa))
+    find_funcref(libcall, func, isa)
+        .unwrap_or_else(|| make_funcref_for_inst(libcall, func, inst, isa))
 }
 
 /// Get a function reference for the probestack function in `func`.
@@ -122,22 +258,22 @@ pub fn get_probestack_funcref(
     arg_reg: RegUnit,
     isa: &TargetIsa,
 ) -> FuncRef {
-    find_funcref(LibCall::Probestack, func)
+    find_funcref(LibCall::Probestack, func, isa)
         .unwrap_or_else(|| make_funcref_for_probestack(func, reg_type, arg_reg, isa))
 }
 
 /// Get the existing function reference for `libcall` in `func` if it exists.
-fn find_funcref(libcall: LibCall, func: &Function) -> Option<FuncRef> {
+fn find_funcref(libcall: LibCall, func: &Function, isa: &TargetIsa) -> Option<FuncRef> {
     // We're assuming that all libcall function decls are at the end.
     // If we get this wrong, worst case we'll have duplicate libcall decls which is harmless.
+    let names = isa.libcall_names();
+    let name = names.name(libcall);
     for (fref, func_data) in func.dfg.ext_funcs.iter().rev() {
-        match func_data.name {
-            ExternalName::LibCall(lc) => {
-                if lc == libcall {
-                    return Some(fref);
-                }
-            }
-            _ => break,
+        if func_data.name == name {
+            return Some(fref);
+        }
+        if names.libcall(&func_data.name).is_none() {
+            break;
         }
     }
     None
@@ -156,7 +292,7 @@ fn make_funcref_for_probestack(
     if !isa.flags().probestack_func_adjusts_sp() {
         sig.returns.push(rax);
     }
-    make_funcref(LibCall::Probestack, func, sig, isa)
+    import_libcall(LibCall::Probestack, func, sig, isa)
 }
 
 /// Create a funcref for `libcall` with a signature matching `inst`.
@@ -175,18 +311,7 @@ fn make_funcref_for_inst(
         sig.returns.push(AbiParam::new(func.dfg.value_type(v)));
     }
 
-    make_funcref(libcall, func, sig, isa)
-}
-
-/// Create a funcref for `libcall`.
-fn make_funcref(libcall: LibCall, func: &mut Function, sig: Signature, isa: &TargetIsa) -> FuncRef {
-    let sigref = func.import_signature(sig);
-
-    func.import_function(ExtFuncData {
-        name: ExternalName::LibCall(libcall),
-        signature: sigref,
-        colocated: isa.flags().colocated_libcalls(),
-    })
+    import_libcall(libcall, func, sig, isa)
 }
 
 #[cfg(test)]
@@ -204,4 +329,38 @@ mod tests {
     fn parsing() {
         assert_eq!("FloorF32".parse(), Ok(LibCall::FloorF32));
     }
+
+    #[test]
+    fn names() {
+        let flags = settings::Flags::new(settings::builder());
+        let mut names = LibcallNames::new();
+        names.set_name(LibCall::FloorF32, ExternalName::testcase("wasm_rt_floorf"));
+        names.set_colocated(LibCall::Memcpy, true);
+
+        assert_eq!(
+            names.name(LibCall::FloorF32),
+            ExternalName::testcase("wasm_rt_floorf")
+        );
+        assert_eq!(
+            names.name(LibCall::FloorF64),
+            ExternalName::LibCall(LibCall::FloorF64)
+        );
+        assert_eq!(
+            names.libcall(&ExternalName::testcase("wasm_rt_floorf")),
+            Some(LibCall::FloorF32)
+        );
+        assert_eq!(
+            names.libcall(&ExternalName::LibCall(LibCall::FloorF32)),
+            None
+        );
+        assert!(names.colocated(LibCall::Memcpy, &flags));
+        assert!(!names.colocated(LibCall::Memset, &flags));
+    }
+
+    #[test]
+    fn all_libcalls() {
+        for &lc in LibCall::all_libcalls() {
+            assert_eq!(lc.to_string().parse(), Ok(lc));
+        }
+    }
 }
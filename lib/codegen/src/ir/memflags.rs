@@ -35,6 +35,11 @@ impl MemFlags {
         self.bits |= 1 << bit as usize
     }
 
+    /// Clear a flag bit.
+    fn clear(&mut self, bit: FlagBit) {
+        self.bits &= !(1 << bit as usize)
+    }
+
     /// Set a flag bit by name.
     ///
     /// Returns true if the flag was found and set, false for an unknown flag name.
@@ -66,6 +71,11 @@ impl MemFlags {
         self.set(FlagBit::Notrap)
     }
 
+    /// Clear the `notrap` flag.
+    pub fn clear_notrap(&mut self) {
+        self.clear(FlagBit::Notrap)
+    }
+
     /// Test if the `aligned` flag is set.
     ///
     /// By default, Cranelift memory instructions work with any unaligned effective address. If the
@@ -1,11 +1,15 @@
 //! Representation of Cranelift IR functions.
 
+mod binary;
+mod branchhint;
 mod builder;
 pub mod condcodes;
+mod constantpool;
 pub mod dfg;
 pub mod entities;
 mod extfunc;
 mod extname;
+mod framelayout;
 pub mod function;
 mod globalvalue;
 mod heap;
@@ -23,20 +27,26 @@ mod trapcode;
 pub mod types;
 mod valueloc;
 
+pub use ir::binary::{BinaryError, BinaryResult, BINARY_FORMAT_VERSION};
+pub use ir::branchhint::BranchHint;
 pub use ir::builder::{InsertBuilder, InstBuilder, InstBuilderBase, InstInserterBase};
+pub use ir::constantpool::{ConstantPool, ConstantPoolEntry};
 pub use ir::dfg::{DataFlowGraph, ValueDef};
 pub use ir::entities::{
     Ebb, FuncRef, GlobalValue, Heap, Inst, JumpTable, SigRef, StackSlot, Table, Value,
 };
 pub use ir::extfunc::{AbiParam, ArgumentExtension, ArgumentPurpose, ExtFuncData, Signature};
-pub use ir::extname::ExternalName;
-pub use ir::function::Function;
+pub use ir::extname::{ExternalName, NameResolver};
+pub use ir::framelayout::{Cfa, FrameLayout};
+pub use ir::function::{EbbOrderError, Function};
 pub use ir::globalvalue::GlobalValueData;
 pub use ir::heap::{HeapData, HeapStyle};
 pub use ir::instructions::{InstructionData, Opcode, ValueList, ValueListPool, VariableArgs};
 pub use ir::jumptable::JumpTableData;
 pub use ir::layout::Layout;
-pub use ir::libcall::{get_libcall_funcref, get_probestack_funcref, LibCall};
+pub use ir::libcall::{
+    get_libcall_funcref, get_probestack_funcref, import_libcall, LibCall, LibcallNames,
+};
 pub use ir::memflags::MemFlags;
 pub use ir::progpoint::{ExpandedProgramPoint, ProgramOrder, ProgramPoint};
 pub use ir::sourceloc::SourceLoc;
@@ -47,7 +57,7 @@ pub use ir::types::Type;
 pub use ir::valueloc::{ArgumentLoc, ValueLoc};
 
 use binemit;
-use entity::{PrimaryMap, SecondaryMap};
+use entity::{EntitySet, PrimaryMap, SecondaryMap};
 use isa;
 
 /// Map of value locations.
@@ -62,6 +72,15 @@ pub type InstEncodings = SecondaryMap<Inst, isa::Encoding>;
 /// Code offsets for EBBs.
 pub type EbbOffsets = SecondaryMap<Ebb, binemit::CodeOffset>;
 
+/// Hints about the direction of conditional branches.
+pub type BranchHints = SecondaryMap<Inst, Option<BranchHint>>;
+
+/// Instructions whose immediate field may be patched after compilation.
+pub type PatchableInsts = EntitySet<Inst>;
+
+/// Execution frequency hints for EBBs.
+pub type EbbFrequencies = SecondaryMap<Ebb, Option<u32>>;
+
 /// Code offsets for Jump Tables.
 pub type JumpTableOffsets = SecondaryMap<JumpTable, binemit::CodeOffset>;
 
@@ -3,7 +3,7 @@
 //! The `StackSlotData` struct keeps track of a single stack slot in a function.
 //!
 
-use entity::{Iter, IterMut, Keys, PrimaryMap};
+use entity::{Iter, IterMut, Keys, PrimaryMap, SecondaryMap};
 use ir::{StackSlot, Type};
 use packed_option::PackedOption;
 use std::cmp;
@@ -160,6 +160,9 @@ pub struct StackSlots {
     /// All the emergency slots.
     emergency: Vec<StackSlot>,
 
+    /// The slot that each explicit slot shares its storage with, if any.
+    shared: SecondaryMap<StackSlot, PackedOption<StackSlot>>,
+
     /// The total size of the stack frame.
     ///
     /// This is the distance from the stack pointer in the current function to the stack pointer in
@@ -178,6 +181,7 @@ impl StackSlots {
             slots: PrimaryMap::new(),
             outgoing: Vec::new(),
             emergency: Vec::new(),
+            shared: SecondaryMap::new(),
             frame_size: None,
         }
     }
@@ -187,6 +191,7 @@ impl StackSlots {
         self.slots.clear();
         self.outgoing.clear();
         self.emergency.clear();
+        self.shared.clear();
         self.frame_size = None;
     }
 
@@ -233,6 +238,22 @@ impl StackSlots {
         self.slots.keys()
     }
 
+    /// Let the explicit slot `ss` share its storage with the explicit slot `with`.
+    ///
+    /// Both slots get the same offset when the stack frame is laid out, so this must only be used
+    /// for slots whose contents are never needed at the same time.
+    pub fn share(&mut self, ss: StackSlot, with: StackSlot) {
+        debug_assert_eq!(self.slots[ss].kind, StackSlotKind::ExplicitSlot);
+        debug_assert_eq!(self.slots[with].kind, StackSlotKind::ExplicitSlot);
+        debug_assert!(self.shared_with(with).is_none(), "{} is already shared", with);
+        self.shared[ss] = with.into();
+    }
+
+    /// Get the slot that `ss` shares its storage with, if any.
+    pub fn shared_with(&self, ss: StackSlot) -> Option<StackSlot> {
+        self.shared.get(ss).and_then(|with| with.expand())
+    }
+
     /// Get a reference to the next stack slot that would be created by `push()`.
     ///
     /// This should just be used by the parser.
@@ -1,18 +1,128 @@
 //! ARM ABI implementation.
+//!
+//! This module implements the base AAPCS calling convention described in the "Procedure Call
+//! Standard for the ARM Architecture", where floating point values are passed in the integer
+//! registers like with `-mfloat-abi=softfp`.
+//!
+//! The prologue saves the link register `lr` so that it can be allocated in the function body, and
+//! links the frames through the frame pointer `r7` like the Thumb code of other compilers.
 
-use super::registers::{D, GPR, Q, S};
+use super::registers::{D, GPR, Q, RU, S};
+use abi::{
+    bool_abi_type, expand_struct_args, legalize_args, ArgAction, ArgAssigner, ValueConversion,
+};
+use cursor::{Cursor, CursorPosition, EncCursor};
 use ir;
-use isa::RegClass;
+use ir::immediates::Imm64;
+use ir::{AbiParam, ArgumentExtension, ArgumentLoc, ArgumentPurpose, InstBuilder, ValueLoc};
+use isa::{RegClass, RegUnit, TargetIsa};
 use regalloc::RegisterSet;
+use result::CodegenResult;
 use settings as shared_settings;
+use settings::CallConv;
+use stack_layout::layout_stack;
+use std::i32;
+use std::vec::Vec;
+
+/// Callee-saved general purpose registers, excluding the frame pointer and the platform register.
+static CALLEE_SAVED_GPRS: [RU; 6] = [RU::r4, RU::r5, RU::r6, RU::r8, RU::r10, RU::r11];
+
+struct Args {
+    pointer_type: ir::Type,
+    gpr_used: usize,
+    offset: u32,
+    call_conv: CallConv,
+}
+
+impl Args {
+    fn new(call_conv: CallConv) -> Self {
+        Self {
+            pointer_type: ir::types::I32,
+            gpr_used: 0,
+            offset: 0,
+            call_conv,
+        }
+    }
+}
+
+impl ArgAssigner for Args {
+    fn assign(&mut self, arg: &AbiParam) -> ArgAction {
+        let ty = arg.value_type;
+
+        // Check for a legal type.
+        // We don't support SIMD yet, so break all vectors down.
+        if ty.is_vector() {
+            return ValueConversion::VectorSplit.into();
+        }
+
+        // Booleans are passed as integers, zero-extended to the size of a register.
+        if ty.is_bool() {
+            let mut int_ty = bool_abi_type(ty, self.call_conv);
+            if int_ty.bits() < self.pointer_type.bits() {
+                int_ty = self.pointer_type;
+            }
+            return ValueConversion::BoolToInt(int_ty).into();
+        }
+
+        // Floating point values are passed as their bits in the integer registers.
+        if ty.is_float() {
+            return ValueConversion::IntBits.into();
+        }
+
+        // A 64-bit value is passed in an even-numbered register and the next one, or in an 8-byte
+        // aligned stack slot. It is never split between the registers and the stack.
+        if ty.bits() == 64 {
+            self.gpr_used += self.gpr_used & 1;
+            if self.gpr_used > 2 {
+                self.gpr_used = 4;
+                self.offset = (self.offset + 7) & !7;
+            }
+            return ValueConversion::IntSplit.into();
+        }
+
+        // Large integers are broken down to fit in a register.
+        if ty.bits() > 32 {
+            return ValueConversion::IntSplit.into();
+        }
+
+        // Small integers are extended to the size of a pointer register.
+        if ty.is_int() && ty.bits() < 32 {
+            match arg.extension {
+                ArgumentExtension::None => {}
+                ArgumentExtension::Uext => return ValueConversion::Uext(self.pointer_type).into(),
+                ArgumentExtension::Sext => return ValueConversion::Sext(self.pointer_type).into(),
+            }
+        }
+
+        // The first four words are passed in `r0-r3`.
+        if self.gpr_used < 4 {
+            let reg = GPR.unit(self.gpr_used);
+            self.gpr_used += 1;
+            return ArgumentLoc::Reg(reg).into();
+        }
+
+        // Assign a stack location. Each word takes a 4-byte slot.
+        let loc = ArgumentLoc::Stack(self.offset as i32);
+        self.offset += 4;
+        debug_assert!(self.offset <= i32::MAX as u32);
+        loc.into()
+    }
+}
 
 /// Legalize `sig`.
 pub fn legalize_signature(
-    _sig: &mut ir::Signature,
+    sig: &mut ir::Signature,
     _flags: &shared_settings::Flags,
     _current: bool,
 ) {
-    unimplemented!()
+    let mut args = Args::new(sig.call_conv);
+    // Struct arguments are passed by reference to a copy.
+    let word = args.pointer_type;
+    expand_struct_args(&mut sig.params, word, |_| true);
+    legalize_args(&mut sig.params, &mut args);
+
+    let mut rets = Args::new(sig.call_conv);
+    legalize_args(&mut sig.returns, &mut rets);
 }
 
 /// Get register class for a type appearing in a legalized signature.
@@ -31,5 +141,216 @@ pub fn regclass_for_abi_type(ty: ir::Type) -> RegClass {
 
 /// Get the set of allocatable registers for `func`.
 pub fn allocatable_registers(_func: &ir::Function) -> RegisterSet {
-    unimplemented!()
+    let mut regs = RegisterSet::new();
+    regs.take(GPR, RU::r7 as RegUnit); // Frame pointer.
+    regs.take(GPR, RU::r9 as RegUnit); // Platform register.
+    regs.take(GPR, RU::r13 as RegUnit); // Stack pointer.
+    regs.take(GPR, RU::r15 as RegUnit); // Program counter.
+
+    // `d8-d15` are callee-saved, which the prologue doesn't handle yet, and `d16-d31` may not
+    // exist.
+    for d in 8..32 {
+        regs.take(D, D.unit(d));
+    }
+
+    // `lr` is saved by the prologue and is available for allocation.
+    regs
+}
+
+/// Get the set of callee-saved registers that are allocatable in `func`.
+pub fn callee_saved_registers(_func: &ir::Function) -> RegisterSet {
+    let mut regs = RegisterSet::empty();
+    for reg in &CALLEE_SAVED_GPRS {
+        regs.free(GPR, *reg as RegUnit);
+    }
+    regs
+}
+
+/// Get the set of callee-saved registers that are used.
+fn callee_saved_gprs_used(func: &ir::Function) -> RegisterSet {
+    let all_callee_saved = callee_saved_registers(func);
+
+    let mut used = RegisterSet::empty();
+    for value_loc in func.locations.values() {
+        if let ValueLoc::Reg(ru) = *value_loc {
+            if GPR.contains(ru) && !used.is_avail(GPR, ru) {
+                used.free(GPR, ru);
+            }
+        }
+    }
+
+    // regmove and regfill instructions may temporarily divert values into other registers,
+    // and these are not reflected in `func.locations`. Scan the function for such instructions
+    // and note which callee-saved registers they use.
+    for ebb in &func.layout {
+        for inst in func.layout.ebb_insts(ebb) {
+            match func.dfg[inst] {
+                ir::instructions::InstructionData::RegMove { dst, .. }
+                | ir::instructions::InstructionData::RegFill { dst, .. } => {
+                    if GPR.contains(dst) && !used.is_avail(GPR, dst) {
+                        used.free(GPR, dst);
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+
+    used.intersect(&all_callee_saved);
+    used
+}
+
+/// Is `call_conv` implemented by `prologue_epilogue` and the argument assignment?
+pub fn supports_call_conv(call_conv: CallConv) -> bool {
+    match call_conv {
+        CallConv::Fast | CallConv::Cold | CallConv::SystemV | CallConv::Raw => true,
+        CallConv::WindowsFastcall | CallConv::Baldrdash | CallConv::Probestack => false,
+    }
+}
+
+pub fn prologue_epilogue(func: &mut ir::Function, isa: &TargetIsa) -> CodegenResult<()> {
+    match func.signature.call_conv {
+        CallConv::Fast | CallConv::Cold | CallConv::SystemV | CallConv::Raw => {
+            aapcs_prologue_epilogue(func, isa)
+        }
+        CallConv::WindowsFastcall => unimplemented!("windows fastcall calling convention"),
+        CallConv::Baldrdash => unimplemented!("baldrdash calling convention"),
+        CallConv::Probestack => unimplemented!("probestack calling convention"),
+    }
+}
+
+/// Insert an AAPCS prologue and epilogue.
+fn aapcs_prologue_epilogue(func: &mut ir::Function, isa: &TargetIsa) -> CodegenResult<()> {
+    // The stack pointer must be 8-byte aligned at public interfaces.
+    let stack_align = 8;
+    let reg_type = ir::types::I32;
+
+    let csrs: Vec<RegUnit> = callee_saved_gprs_used(func).iter(GPR).collect();
+
+    // The reserved stack area is composed of:
+    //   link register + frame pointer + all callee-saved registers
+    let csr_stack_size = ((2 + csrs.len()) * 4) as i32;
+    func.create_stack_slot(ir::StackSlotData {
+        kind: ir::StackSlotKind::IncomingArg,
+        size: csr_stack_size as u32,
+        offset: Some(-csr_stack_size),
+    });
+
+    let total_stack_size = layout_stack(&mut func.stack_slots, stack_align)? as i32;
+    let local_stack_size = i64::from(total_stack_size - csr_stack_size);
+
+    // The prologue pushes the link register, the frame pointer and then the callee-saved
+    // registers, and points the frame pointer at the saved frame pointer.
+    let fp = RU::r7 as RegUnit;
+    let mut saved = vec![(RU::r14 as RegUnit, -4), (fp, -8)];
+    for (i, &csr) in csrs.iter().enumerate() {
+        saved.push((csr, -4 * (3 + i as i32)));
+    }
+    let cfa = ir::Cfa::FramePointer { reg: fp, offset: 8 };
+    func.frame_layout = Some(ir::FrameLayout::new(&func.stack_slots, cfa, saved));
+
+    // Add the link register, frame pointer and CSRs to function signature
+    let lr_arg = AbiParam::special_reg(reg_type, ArgumentPurpose::Link, RU::r14 as RegUnit);
+    func.signature.params.push(lr_arg);
+    func.signature.returns.push(lr_arg);
+
+    let fp_arg = AbiParam::special_reg(reg_type, ArgumentPurpose::FramePointer, RU::r7 as RegUnit);
+    func.signature.params.push(fp_arg);
+    func.signature.returns.push(fp_arg);
+
+    for &csr in &csrs {
+        let csr_arg = AbiParam::special_reg(reg_type, ArgumentPurpose::CalleeSaved, csr);
+        func.signature.params.push(csr_arg);
+        func.signature.returns.push(csr_arg);
+    }
+
+    // Set up the cursor and insert the prologue
+    let entry_ebb = func.layout.entry_block().expect("missing entry block");
+    let mut pos = EncCursor::new(func, isa).at_first_insertion_point(entry_ebb);
+    insert_prologue(&mut pos, local_stack_size, reg_type, &csrs);
+
+    // Reset the cursor and insert the epilogue
+    let mut pos = pos.at_position(CursorPosition::Nowhere);
+    insert_epilogues(&mut pos, local_stack_size, &csrs);
+
+    Ok(())
+}
+
+/// Split a stack adjustment into immediates that `add` and `sub` can encode: a 12-bit immediate
+/// and shifted 8-bit immediates for the higher bits.
+fn stack_adjustments(stack_size: i64) -> Vec<i64> {
+    [0xfff, 0xff000, 0xff0_0000, 0xf000_0000]
+        .iter()
+        .map(|mask| stack_size & mask)
+        .filter(|&imm| imm != 0)
+        .collect()
+}
+
+/// Insert the prologue for a given function.
+fn insert_prologue(pos: &mut EncCursor, stack_size: i64, reg_type: ir::Type, csrs: &[RegUnit]) {
+    // Append params to entry EBB
+    let ebb = pos.current_ebb().expect("missing ebb under cursor");
+    let lr = pos.func.dfg.append_ebb_param(ebb, reg_type);
+    pos.func.locations[lr] = ValueLoc::Reg(RU::r14 as RegUnit);
+    let fp = pos.func.dfg.append_ebb_param(ebb, reg_type);
+    pos.func.locations[fp] = ValueLoc::Reg(RU::r7 as RegUnit);
+
+    // Push the link register and the frame pointer, and point the frame pointer at them.
+    pos.ins().arm32_push(lr);
+    pos.ins().arm32_push(fp);
+    pos.ins()
+        .copy_special(RU::r13 as RegUnit, RU::r7 as RegUnit);
+
+    for &reg in csrs {
+        // Append param to entry EBB
+        let csr_arg = pos.func.dfg.append_ebb_param(ebb, reg_type);
+
+        // Assign it a location
+        pos.func.locations[csr_arg] = ValueLoc::Reg(reg);
+        pos.ins().arm32_push(csr_arg);
+    }
+
+    // Allocate stack frame storage.
+    for imm in stack_adjustments(stack_size) {
+        pos.ins().adjust_sp_down_imm(Imm64::new(imm));
+    }
+}
+
+/// Find all `return` instructions and insert epilogues before them.
+fn insert_epilogues(pos: &mut EncCursor, stack_size: i64, csrs: &[RegUnit]) {
+    while let Some(ebb) = pos.next_ebb() {
+        pos.goto_last_inst(ebb);
+        if let Some(inst) = pos.current_inst() {
+            if pos.func.dfg[inst].opcode().is_return() {
+                insert_epilogue(inst, stack_size, pos, csrs);
+            }
+        }
+    }
+}
+
+/// Insert an epilogue given a specific `return` instruction.
+fn insert_epilogue(inst: ir::Inst, stack_size: i64, pos: &mut EncCursor, csrs: &[RegUnit]) {
+    for imm in stack_adjustments(stack_size) {
+        pos.ins().adjust_sp_up_imm(Imm64::new(imm));
+    }
+
+    // Pop the link register, the frame pointer and the callee-saved registers, stepping backward
+    // each time to preserve the correct order.
+    let lr_ret = pos.ins().arm32_pop();
+    pos.prev_inst();
+    let fp_ret = pos.ins().arm32_pop();
+    pos.prev_inst();
+
+    pos.func.locations[fp_ret] = ValueLoc::Reg(RU::r7 as RegUnit);
+    pos.func.locations[lr_ret] = ValueLoc::Reg(RU::r14 as RegUnit);
+    pos.func.dfg.append_inst_arg(inst, lr_ret);
+    pos.func.dfg.append_inst_arg(inst, fp_ret);
+
+    for &reg in csrs {
+        let csr_ret = pos.ins().arm32_pop();
+        pos.prev_inst();
+
+        pos.func.locations[csr_ret] = ValueLoc::Reg(reg);
+        pos.func.dfg.append_inst_arg(inst, csr_ret);
+    }
 }
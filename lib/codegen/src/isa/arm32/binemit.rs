@@ -1,7 +1,560 @@
 //! Emitting binary ARM32 machine code.
 
-use binemit::{bad_encoding, CodeSink};
-use ir::{Function, Inst};
+use binemit::{bad_encoding, branch_disp, CodeSink, Reloc};
+use ir::condcodes::{CondCode, FloatCC, IntCC};
+use ir::{Function, Inst, InstructionData, TrapCode};
+use isa::{RegUnit, StackBase, StackBaseMask, StackRef};
+use predicates::{is_signed_int, is_unsigned_int};
 use regalloc::RegDiversions;
+use result::CodegenResult;
 
 include!(concat!(env!("OUT_DIR"), "/binemit-arm32.rs"));
+
+/// The program counter. Used as the `Rn` or `Rd` field of the data processing instructions that
+/// have no such operand.
+const PC: RegUnit = 15;
+
+/// The stack pointer.
+const SP: RegUnit = 13;
+
+/// The 16-bit `mov rd, rm`, used for conditional moves.
+const MOV: u16 = 0x4600;
+
+/// Encoding bits of `mov.w rd, #imm`, used for `cset`.
+const MOV_IMM: u16 = 0xf04f;
+
+/// Encoding bits of `movt rd, #imm16`.
+const MOVT: u16 = 0xf2c0;
+
+/// Encoding bits of `cmp.w rn, #imm`, used for comparing a register with zero.
+const CMP_IMM: u16 = 0xf1b0;
+
+/// The first halfword of `b<c>.w` and the 16-bit `b<c>`, without the condition and displacement.
+const BCOND_W: u16 = 0xf000;
+const BCOND_N: u16 = 0xd000;
+
+/// The permanently undefined instruction `udf #0`, used for traps.
+const UDF: u16 = 0xde00;
+
+/// `vmrs APSR_nzcv, fpscr`, copying the VFP comparison flags to the `nzcv` flags.
+const VMRS_HW1: u16 = 0xeef1;
+const VMRS_HW2: u16 = 0xfa10;
+
+/// The `ne` condition.
+const COND_NE: u16 = 0b0001;
+
+fn reg(r: RegUnit) -> u16 {
+    r & 0xf
+}
+
+/// The fields encoding a VFP register.
+///
+/// A register is encoded as a 4-bit field and an extra bit elsewhere in the instruction, and it
+/// determines the `sz` bit selecting the precision of the instruction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct VReg {
+    /// The 4-bit field.
+    field: u16,
+    /// The extra bit.
+    bit: u16,
+    /// The `sz` bit in position, 0 for single precision.
+    sz: u16,
+}
+
+/// Get the fields encoding a double-precision register.
+///
+/// The register units correspond to the single-precision registers, two per double-precision
+/// register. The extra bit is the high bit of the register number.
+fn dreg(r: RegUnit) -> VReg {
+    let d = (r >> 1) & 0x1f;
+    VReg {
+        field: d & 0xf,
+        bit: d >> 4,
+        sz: 0x100,
+    }
+}
+
+/// Get the fields encoding a single-precision register. The extra bit is the low bit of the
+/// register number.
+fn sreg(r: RegUnit) -> VReg {
+    let s = r & 0x1f;
+    VReg {
+        field: s >> 1,
+        bit: s & 1,
+        sz: 0,
+    }
+}
+
+/// Get the register number to use for `base` when addressing a stack slot.
+fn stk_base(base: StackBase) -> RegUnit {
+    match base {
+        StackBase::SP => SP,
+        StackBase::FP => 7,
+        StackBase::Zone => unimplemented!(),
+    }
+}
+
+/// Convert an integer condition code to the ARM condition encoding.
+fn icc2cond(cond: IntCC) -> u16 {
+    use ir::condcodes::IntCC::*;
+    match cond {
+        Equal => 0b0000,
+        NotEqual => 0b0001,
+        UnsignedGreaterThanOrEqual => 0b0010,
+        UnsignedLessThan => 0b0011,
+        UnsignedGreaterThan => 0b1000,
+        UnsignedLessThanOrEqual => 0b1001,
+        SignedGreaterThanOrEqual => 0b1010,
+        SignedLessThan => 0b1011,
+        SignedGreaterThan => 0b1100,
+        SignedLessThanOrEqual => 0b1101,
+    }
+}
+
+/// Convert a floating point condition code to the ARM condition encoding, after the `vcmp` flags
+/// have been copied by `vmrs`.
+///
+/// An unordered comparison sets the `C` and `V` flags. The `one` and `ueq` conditions need two
+/// tests, they are legalized.
+fn fcc2cond(cond: FloatCC) -> u16 {
+    use ir::condcodes::FloatCC::*;
+    match cond {
+        Ordered => 0b0111,
+        Unordered => 0b0110,
+        Equal => 0b0000,
+        NotEqual => 0b0001,
+        LessThan => 0b0100,
+        LessThanOrEqual => 0b1001,
+        GreaterThan => 0b1100,
+        GreaterThanOrEqual => 0b1010,
+        UnorderedOrLessThan => 0b1011,
+        UnorderedOrLessThanOrEqual => 0b1101,
+        UnorderedOrGreaterThan => 0b1000,
+        UnorderedOrGreaterThanOrEqual => 0b0101,
+        OrderedNotEqual | UnorderedOrEqual => panic!("{} needs two conditions", cond),
+    }
+}
+
+/// Encode `imm` as the 12-bit `i:imm3:imm8` field of a modified immediate.
+///
+/// The field is either an 8-bit value repeated according to the top 4 bits, or an 8-bit value
+/// with the top bit set rotated right by the top 5 bits.
+fn thumb_imm(imm: u32) -> Option<u16> {
+    let b0 = imm & 0xff;
+    let b1 = (imm >> 8) & 0xff;
+    if imm <= 0xff {
+        return Some(imm as u16);
+    }
+    if imm == b0 * 0x0001_0001 {
+        return Some(0x100 | b0 as u16);
+    }
+    if imm == b1 * 0x0100_0100 {
+        return Some(0x200 | b1 as u16);
+    }
+    if imm == b0 * 0x0101_0101 {
+        return Some(0x300 | b0 as u16);
+    }
+
+    // Rotate the highest set bit into bit 7.
+    let rot = 8 + imm.leading_zeros();
+    let imm8 = imm.rotate_left(rot);
+    if imm8 > 0xff {
+        return None;
+    }
+    Some((rot << 7) as u16 | (imm8 & 0x7f) as u16)
+}
+
+/// Emit a 32-bit instruction as two halfwords.
+fn put_t32<CS: CodeSink + ?Sized>(hw1: u16, hw2: u16, sink: &mut CS) {
+    sink.put2(hw1);
+    sink.put2(hw2);
+}
+
+/// Data processing with a register operand and a zero shift.
+///
+///   15     3     15 14   11 7    5    3
+///   opcode Rn    0  imm3 Rd imm2 type Rm
+///        4  0    15   12  8    6    4  0
+fn put_dpr<CS: CodeSink + ?Sized>(bits: u16, rn: RegUnit, rm: RegUnit, rd: RegUnit, sink: &mut CS) {
+    put_t32(bits | reg(rn), (reg(rd) << 8) | reg(rm), sink);
+}
+
+/// Data processing with a modified immediate.
+///
+///   15     10 9      3     15 14   11 7
+///   opcode i  opcode Rn    0  imm3 Rd imm8
+///        11 10      4  0    15   12  8    0
+fn put_dpi<CS: CodeSink + ?Sized>(bits: u16, rn: RegUnit, imm: i64, rd: RegUnit, sink: &mut CS) {
+    let field = match thumb_imm(imm as u32) {
+        Some(field) => field,
+        None => panic!("{:#x} isn't a modified immediate", imm),
+    };
+    put_t32(
+        bits | ((field >> 11) << 10) | reg(rn),
+        (((field >> 8) & 7) << 12) | (reg(rd) << 8) | (field & 0xff),
+        sink,
+    );
+}
+
+/// Add or subtract a plain 12-bit immediate: `addw` or `subw`.
+///
+/// This has the same layout as `put_dpi()`, but the `i:imm3:imm8` field holds the immediate.
+fn put_dpi12<CS: CodeSink + ?Sized>(bits: u16, rn: RegUnit, imm: i64, rd: RegUnit, sink: &mut CS) {
+    debug_assert!(is_unsigned_int(imm, 12, 0), "imm12 out of range {:#x}", imm);
+    let imm = imm as u16;
+    put_t32(
+        bits | ((imm >> 11) << 10) | reg(rn),
+        (((imm >> 8) & 7) << 12) | (reg(rd) << 8) | (imm & 0xff),
+        sink,
+    );
+}
+
+/// Move a 16-bit immediate: `movw` or `movt`.
+///
+///   15     10 9      3     15 14   11 7
+///   opcode i  opcode imm4  0  imm3 Rd imm8
+///        11 10      4    0    15   12  8    0
+fn put_movw<CS: CodeSink + ?Sized>(bits: u16, imm: u32, rd: RegUnit, sink: &mut CS) {
+    debug_assert!(imm <= 0xffff, "imm16 out of range {:#x}", imm);
+    let imm = imm as u16;
+    put_t32(
+        bits | (((imm >> 11) & 1) << 10) | (imm >> 12),
+        (((imm >> 8) & 7) << 12) | (reg(rd) << 8) | (imm & 0xff),
+        sink,
+    );
+}
+
+/// Register-controlled shifts and multiplications.
+///
+///   15     3     15     11 7      3
+///   opcode Rn    1111   Rd opcode Rm
+///        4  0    12      8      4  0
+fn put_rrr<CS: CodeSink + ?Sized>(bits: u16, rn: RegUnit, rm: RegUnit, rd: RegUnit, sink: &mut CS) {
+    put_t32(bits | reg(rn), 0xf000 | (reg(rd) << 8) | reg(rm), sink);
+}
+
+/// Long multiplication.
+///
+///   15     3     15   11   7      3
+///   opcode Rn    RdLo RdHi opcode Rm
+///        4  0    12    8      4  0
+fn put_umull<CS: CodeSink + ?Sized>(
+    bits: u16,
+    rn: RegUnit,
+    rm: RegUnit,
+    rdlo: RegUnit,
+    rdhi: RegUnit,
+    sink: &mut CS,
+) {
+    put_t32(
+        bits | reg(rn),
+        (reg(rdlo) << 12) | (reg(rdhi) << 8) | reg(rm),
+        sink,
+    );
+}
+
+/// Shift by an immediate, as an alias of `mov.w` with a shifted register.
+///
+///   15            15 14   11 7    5    3
+///   opcode        0  imm3 Rd imm2 type Rm
+///                 15   12  8    6    4  0
+///
+/// Encoding bits: the shift type.
+fn put_shi<CS: CodeSink + ?Sized>(bits: u16, rm: RegUnit, imm: i64, rd: RegUnit, sink: &mut CS) {
+    let amount = imm as u16 & 0x1f;
+    // A zero shift amount means 32 for `lsr` and `asr`, and `rrx` for `ror`. Shifting by 0 is a
+    // plain move anyway.
+    let ty = if amount == 0 { 0 } else { bits };
+    put_t32(
+        0xea4f,
+        ((amount >> 2) << 12) | (reg(rd) << 8) | ((amount & 3) << 6) | (ty << 4) | reg(rm),
+        sink,
+    );
+}
+
+/// Sign or zero extension.
+///
+///   15            15   11 7      5      3
+///   opcode        1111 Rd opcode rotate Rm
+///                 12    8      6      4  0
+fn put_ext<CS: CodeSink + ?Sized>(bits: u16, rm: RegUnit, rd: RegUnit, sink: &mut CS) {
+    put_t32(bits, 0xf080 | (reg(rd) << 8) | reg(rm), sink);
+}
+
+/// The 16-bit register move, which can use all the registers.
+///
+///   15     7 6  3  2
+///   opcode D Rm Rd
+///        8 7  3  0
+///
+/// The `D` bit is the high bit of `Rd`.
+fn put_mov<CS: CodeSink + ?Sized>(bits: u16, rm: RegUnit, rd: RegUnit, sink: &mut CS) {
+    sink.put2(bits | ((reg(rd) & 8) << 4) | (reg(rm) << 3) | (reg(rd) & 7));
+}
+
+/// An `ite cond` instruction, making the next instruction conditional on `cond` and the one after
+/// it conditional on the inverse condition.
+fn put_ite<CS: CodeSink + ?Sized>(cond: u16, sink: &mut CS) {
+    // The mask gives the condition of the second instruction as its inverted low bit, and the
+    // end of the block.
+    sink.put2(0xbf00 | (cond << 4) | ((!cond & 1) << 3) | 0b100);
+}
+
+/// Set a register to 1 if a condition holds and 0 otherwise: `ite cond; mov rd, #1; mov rd, #0`.
+///
+/// The instructions in an `it` block don't set the flags.
+fn put_cset<CS: CodeSink + ?Sized>(cond: u16, rd: RegUnit, sink: &mut CS) {
+    put_ite(cond, sink);
+    put_dpi(MOV_IMM, PC, 1, rd, sink);
+    put_dpi(MOV_IMM, PC, 0, rd, sink);
+}
+
+/// The 16-bit `cmp rn, #0`.
+fn put_cmp16<CS: CodeSink + ?Sized>(rn: RegUnit, sink: &mut CS) {
+    debug_assert!(reg(rn) < 8, "cmp16 needs a low register");
+    sink.put2(0x2800 | (reg(rn) << 8));
+}
+
+/// Unconditional branches and calls: `b.w` and `bl`.
+///
+///   15     10 9         15     13 12     11 10
+///   opcode S  imm10     opcode J1 opcode J2 imm11
+///        11 10     0        14 13     12 11     0
+///
+/// The displacement is `S:I1:I2:imm10:imm11:0` where `I1 = !(J1 ^ S)` and `I2 = !(J2 ^ S)`.
+///
+/// Encoding bits: the second halfword.
+fn put_bw<CS: CodeSink + ?Sized>(bits: u16, disp: i64, sink: &mut CS) {
+    debug_assert!(is_signed_int(disp, 25, 1), "b.w out of range {:#x}", disp);
+    let imm = (disp >> 1) as u32;
+    let s = ((imm >> 23) & 1) as u16;
+    let j1 = !((imm >> 22) as u16 ^ s) & 1;
+    let j2 = !((imm >> 21) as u16 ^ s) & 1;
+    put_t32(
+        0xf000 | (s << 10) | ((imm >> 11) as u16 & 0x3ff),
+        bits | (j1 << 13) | (j2 << 11) | (imm as u16 & 0x7ff),
+        sink,
+    );
+}
+
+/// The 16-bit unconditional branch.
+///
+///   15     10
+///   opcode imm11
+///       11     0
+fn put_bn<CS: CodeSink + ?Sized>(bits: u16, disp: i64, sink: &mut CS) {
+    debug_assert!(is_signed_int(disp, 12, 1), "b.n out of range {:#x}", disp);
+    sink.put2(bits | ((disp >> 1) as u16 & 0x7ff));
+}
+
+/// Conditional branches: `b<c>.w`.
+///
+///   15     10 9    5         15     13 12     11 10
+///   opcode S  cond imm6      opcode J1 opcode J2 imm11
+///        11 10   6    0          14 13     12 11     0
+///
+/// The displacement is `S:J2:J1:imm6:imm11:0`.
+fn put_bcondw<CS: CodeSink + ?Sized>(bits: u16, cond: u16, disp: i64, sink: &mut CS) {
+    debug_assert!(
+        is_signed_int(disp, 21, 1),
+        "b<c>.w out of range {:#x}",
+        disp
+    );
+    let imm = (disp >> 1) as u32;
+    let s = ((imm >> 19) & 1) as u16;
+    let j2 = ((imm >> 18) & 1) as u16;
+    let j1 = ((imm >> 17) & 1) as u16;
+    put_t32(
+        bits | (s << 10) | (cond << 6) | ((imm >> 11) as u16 & 0x3f),
+        0x8000 | (j1 << 13) | (j2 << 11) | (imm as u16 & 0x7ff),
+        sink,
+    );
+}
+
+/// The 16-bit conditional branch.
+///
+///   15     11   7
+///   opcode cond imm8
+///        8    8    0
+fn put_bcondn<CS: CodeSink + ?Sized>(bits: u16, cond: u16, disp: i64, sink: &mut CS) {
+    debug_assert!(is_signed_int(disp, 9, 1), "b<c>.n out of range {:#x}", disp);
+    sink.put2(bits | (cond << 8) | ((disp >> 1) as u16 & 0xff));
+}
+
+/// Compare and branch on zero: `cbz` and `cbnz`.
+///
+///   15     9 8      7    2
+///   opcode i opcode imm5 Rn
+///        10 9         3  0
+///
+/// The displacement is `i:imm5:0`, it can't be negative.
+fn put_cb<CS: CodeSink + ?Sized>(bits: u16, rn: RegUnit, disp: i64, sink: &mut CS) {
+    debug_assert!(is_unsigned_int(disp, 7, 1), "cbz out of range {:#x}", disp);
+    debug_assert!(reg(rn) < 8, "cbz needs a low register");
+    let imm = (disp >> 1) as u16;
+    sink.put2(bits | ((imm >> 5) << 9) | ((imm & 0x1f) << 3) | reg(rn));
+}
+
+/// Loads and stores with a positive 12-bit offset.
+///
+///   15     3     15 11
+///   opcode Rn    Rt imm12
+///        4  0    12     0
+fn put_ldst12<CS: CodeSink + ?Sized>(
+    bits: u16,
+    rt: RegUnit,
+    rn: RegUnit,
+    offset: i64,
+    sink: &mut CS,
+) {
+    debug_assert!(
+        is_unsigned_int(offset, 12, 0),
+        "offset out of range {:#x}",
+        offset
+    );
+    put_t32(
+        bits | reg(rn),
+        (reg(rt) << 12) | (offset as u16 & 0xfff),
+        sink,
+    );
+}
+
+/// Loads and stores with an 8-bit offset that is added or subtracted.
+///
+///   15     3     15 11     10 9 8 7
+///   opcode Rn    Rt opcode P  U W imm8
+///        4  0    12     11 10 9 8    0
+///
+/// This uses the offset addressing form with `P = 1` and `W = 0`.
+fn put_ldst8<CS: CodeSink + ?Sized>(
+    bits: u16,
+    rt: RegUnit,
+    rn: RegUnit,
+    offset: i64,
+    sink: &mut CS,
+) {
+    debug_assert!(
+        offset > -256 && offset < 256,
+        "offset out of range {:#x}",
+        offset
+    );
+    let u = if offset >= 0 { 0x200 } else { 0 };
+    put_t32(
+        bits | reg(rn),
+        (reg(rt) << 12) | 0x0c00 | u | (offset.abs() as u16 & 0xff),
+        sink,
+    );
+}
+
+/// VFP data processing with three registers.
+///
+///   15     6 5      3     15 11     8  7 6  5 4 3
+///   opcode D opcode Vn    Vd opcode sz N op M 0 Vm
+///        7 6      4  0    12      9  8 7  6 5 4  0
+///
+/// Encoding bits: the low bytes of both halfwords, see `VFP()` in `recipes.py`.
+fn put_vfp<CS: CodeSink + ?Sized>(bits: u16, vd: VReg, vn: VReg, vm: VReg, sink: &mut CS) {
+    put_t32(
+        0xee00 | (bits & 0xff) | (vd.bit << 6) | vn.field,
+        (vd.field << 12) | 0x0a00 | vd.sz | (bits >> 8) | (vn.bit << 7) | (vm.bit << 5) | vm.field,
+        sink,
+    );
+}
+
+/// VFP data processing with two registers, where the `Vn` field holds more opcode bits.
+fn put_vfp2<CS: CodeSink + ?Sized>(bits: u16, vd: VReg, vm: VReg, sink: &mut CS) {
+    put_t32(
+        0xee00 | (bits & 0xff) | (vd.bit << 6),
+        (vd.field << 12) | 0x0a00 | vd.sz | (bits >> 8) | (vm.bit << 5) | vm.field,
+        sink,
+    );
+}
+
+/// Moves between a double-precision register and two integer registers.
+///
+///   15     3      15 11        5 4 3
+///   opcode Rt2    Rt opcode    M 1 Vm
+///        4   0    12      6    5 4  0
+fn put_vmov<CS: CodeSink + ?Sized>(bits: u16, dm: VReg, rt: RegUnit, rt2: RegUnit, sink: &mut CS) {
+    put_t32(
+        bits | reg(rt2),
+        (reg(rt) << 12) | 0x0b10 | (dm.bit << 5) | dm.field,
+        sink,
+    );
+}
+
+/// Moves between a single-precision register and an integer register.
+///
+///   15     3      15 11     7
+///   opcode Vn     Rt opcode N 0010000
+///        4  0     12      8 7       0
+fn put_vmovs<CS: CodeSink + ?Sized>(bits: u16, sn: VReg, rt: RegUnit, sink: &mut CS) {
+    put_t32(
+        bits | sn.field,
+        (reg(rt) << 12) | 0x0a10 | (sn.bit << 7),
+        sink,
+    );
+}
+
+/// VFP loads and stores with an 8-bit offset scaled by 4, which is added or subtracted.
+///
+///   15     7 6 5      3     15 11     8  7
+///   opcode U D opcode Rn    Vd opcode sz imm8
+///        8 7 6      4  0    12      9  8    0
+fn put_vldst<CS: CodeSink + ?Sized>(bits: u16, vd: VReg, rn: RegUnit, offset: i64, sink: &mut CS) {
+    debug_assert!(
+        offset & 3 == 0 && offset.abs() <= 1020,
+        "offset out of range {:#x}",
+        offset
+    );
+    let u = if offset >= 0 { 0x80 } else { 0 };
+    put_t32(
+        bits | u | (vd.bit << 6) | reg(rn),
+        (vd.field << 12) | 0x0a00 | vd.sz | ((offset.abs() >> 2) as u16 & 0xff),
+        sink,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn modified_imm() {
+        assert_eq!(thumb_imm(0), Some(0));
+        assert_eq!(thumb_imm(0xab), Some(0xab));
+        assert_eq!(thumb_imm(0x00ab_00ab), Some(0x1ab));
+        assert_eq!(thumb_imm(0xab00_ab00), Some(0x2ab));
+        assert_eq!(thumb_imm(0xabab_abab), Some(0x3ab));
+        // 0x100 is 0x80 rotated right by 31.
+        assert_eq!(thumb_imm(0x100), Some(0xf80));
+        // 0xff00_0000 is 0xff rotated right by 8.
+        assert_eq!(thumb_imm(0xff00_0000), Some(0x47f));
+        assert_eq!(thumb_imm(0x8000_0000), Some(0x400));
+        assert_eq!(thumb_imm(0x3fc), Some(0xf7f));
+        assert_eq!(thumb_imm(0x1ff), None);
+        assert_eq!(thumb_imm(0xffff_ffff), Some(0x3ff));
+        assert_eq!(thumb_imm(0xffff_fffe), None);
+    }
+
+    #[test]
+    fn vfp_regs() {
+        // d17 is the units of s34 and s35.
+        assert_eq!(
+            dreg(34),
+            VReg {
+                field: 1,
+                bit: 1,
+                sz: 0x100,
+            }
+        );
+        assert_eq!(
+            sreg(3),
+            VReg {
+                field: 1,
+                bit: 1,
+                sz: 0,
+            }
+        );
+    }
+}
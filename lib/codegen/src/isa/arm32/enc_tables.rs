@@ -1,10 +1,145 @@
 //! Encoding tables for ARM32 ISA.
 
-use ir;
+use super::registers::*;
+use cursor::{Cursor, FuncCursor};
+use flowgraph::ControlFlowGraph;
+use ir::{self, InstBuilder};
 use isa;
 use isa::constraints::*;
 use isa::enc_tables::*;
-use isa::encoding::RecipeSizing;
+use isa::encoding::{base_size, RecipeSizing};
+use legalizer::split;
 
 include!(concat!(env!("OUT_DIR"), "/encoding-arm32.rs"));
 include!(concat!(env!("OUT_DIR"), "/legalize-arm32.rs"));
+
+/// Split an `i64` constant into two `i32` constants.
+fn narrow_iconst(
+    inst: ir::Inst,
+    func: &mut ir::Function,
+    _cfg: &mut ControlFlowGraph,
+    _isa: &isa::TargetIsa,
+) {
+    let imm: i64 = match func.dfg[inst] {
+        ir::InstructionData::UnaryImm {
+            opcode: ir::Opcode::Iconst,
+            imm,
+        } => imm.into(),
+        _ => panic!("Need iconst: {}", func.dfg.display_inst(inst, None)),
+    };
+
+    let mut pos = FuncCursor::new(func).at_inst(inst);
+    pos.use_srcloc(inst);
+    let lo = pos.ins().iconst(ir::types::I32, i64::from(imm as i32));
+    let hi = pos.ins().iconst(ir::types::I32, imm >> 32);
+    pos.func.dfg.replace(inst).iconcat(lo, hi);
+}
+
+/// Get the address and offset for accessing the high half of an `i64` in memory.
+///
+/// The low half comes first in memory. When the offset of the high half doesn't fit, the address
+/// is adjusted instead.
+fn high_half_address(
+    pos: &mut FuncCursor,
+    ptr: ir::Value,
+    offset: ir::immediates::Offset32,
+) -> (ir::Value, ir::immediates::Offset32) {
+    match offset.try_add_i64(4) {
+        Some(offset) => (ptr, offset),
+        None => (pos.ins().iadd_imm(ptr, 4), offset),
+    }
+}
+
+/// Split an `i64` load into two `i32` loads.
+fn narrow_load(
+    inst: ir::Inst,
+    func: &mut ir::Function,
+    _cfg: &mut ControlFlowGraph,
+    _isa: &isa::TargetIsa,
+) {
+    let (ptr, flags, offset) = match func.dfg[inst] {
+        ir::InstructionData::Load {
+            opcode: ir::Opcode::Load,
+            arg,
+            flags,
+            offset,
+        } => (arg, flags, offset),
+        _ => panic!("Need load: {}", func.dfg.display_inst(inst, None)),
+    };
+
+    let mut pos = FuncCursor::new(func).at_inst(inst);
+    pos.use_srcloc(inst);
+    let lo = pos.ins().load(ir::types::I32, flags, ptr, offset);
+    let (hi_ptr, hi_offset) = high_half_address(&mut pos, ptr, offset);
+    let hi = pos.ins().load(ir::types::I32, flags, hi_ptr, hi_offset);
+    pos.func.dfg.replace(inst).iconcat(lo, hi);
+}
+
+/// Split an `i64` store into two `i32` stores.
+fn narrow_store(
+    inst: ir::Inst,
+    func: &mut ir::Function,
+    _cfg: &mut ControlFlowGraph,
+    _isa: &isa::TargetIsa,
+) {
+    let (val, ptr, flags, offset) = match func.dfg[inst] {
+        ir::InstructionData::Store {
+            opcode: ir::Opcode::Store,
+            args,
+            flags,
+            offset,
+        } => (args[0], args[1], flags, offset),
+        _ => panic!("Need store: {}", func.dfg.display_inst(inst, None)),
+    };
+
+    let mut pos = FuncCursor::new(func).at_inst(inst);
+    pos.use_srcloc(inst);
+    let (lo, hi) = pos.ins().isplit(val);
+    pos.ins().store(flags, lo, ptr, offset);
+    let (hi_ptr, hi_offset) = high_half_address(&mut pos, ptr, offset);
+    pos.ins().store(flags, hi, hi_ptr, hi_offset);
+    pos.remove_inst();
+}
+
+/// Add a memory offset that doesn't fit the load and store encodings to the address.
+fn expand_mem_offset(
+    inst: ir::Inst,
+    func: &mut ir::Function,
+    _cfg: &mut ControlFlowGraph,
+    _isa: &isa::TargetIsa,
+) {
+    let (ptr, offset) = match func.dfg[inst] {
+        ir::InstructionData::Load { arg, offset, .. } => (arg, offset),
+        ir::InstructionData::Store { args, offset, .. } => (args[1], offset),
+        _ => panic!("Need load or store: {}", func.dfg.display_inst(inst, None)),
+    };
+    let offset: i32 = offset.into();
+    assert!(
+        offset != 0,
+        "No encoding for {}",
+        func.dfg.display_inst(inst, None)
+    );
+
+    let mut pos = FuncCursor::new(func).at_inst(inst);
+    pos.use_srcloc(inst);
+    let addr = pos.ins().iadd_imm(ptr, i64::from(offset));
+    match pos.func.dfg[inst] {
+        ir::InstructionData::Load {
+            ref mut arg,
+            ref mut offset,
+            ..
+        } => {
+            *arg = addr;
+            *offset = 0.into();
+        }
+        ir::InstructionData::Store {
+            ref mut args,
+            ref mut offset,
+            ..
+        } => {
+            args[1] = addr;
+            *offset = 0.into();
+        }
+        _ => unreachable!(),
+    }
+}
@@ -12,26 +12,40 @@ use binemit::CodeSink;
 use binemit::{emit_function, MemoryCodeSink};
 use ir;
 use isa::enc_tables::{self as shared_enc_tables, lookup_enclist, Encodings};
+use isa::support::inst_support;
 use isa::Builder as IsaBuilder;
-use isa::{EncInfo, RegClass, RegInfo, TargetIsa};
+use isa::{EncInfo, InstSupport, Latencies, RegClass, RegInfo, TargetIsa};
 use regalloc;
+use result::CodegenResult;
 use std::boxed::Box;
 use std::fmt;
 use target_lexicon::{Architecture, Triple};
+use timing;
 
 #[allow(dead_code)]
 struct Isa {
     triple: Triple,
     shared_flags: shared_settings::Flags,
     isa_flags: settings::Flags,
+    libcall_names: ir::LibcallNames,
     cpumode: &'static [shared_enc_tables::Level1Entry<u16>],
 }
 
+/// Instruction latencies of a typical in-order ARM core.
+static LATENCIES: Latencies = Latencies {
+    load: 2,
+    imul: 3,
+    idiv: 12,
+    float: 4,
+    other: 1,
+};
+
 /// Get an ISA builder for creating ARM32 targets.
 pub fn isa_builder(triple: Triple) -> IsaBuilder {
     IsaBuilder {
         triple,
         setup: settings::builder(),
+        libcall_names: ir::LibcallNames::new(),
         constructor: isa_constructor,
     }
 }
@@ -40,6 +54,7 @@ fn isa_constructor(
     triple: Triple,
     shared_flags: shared_settings::Flags,
     builder: shared_settings::Builder,
+    libcall_names: ir::LibcallNames,
 ) -> Box<TargetIsa> {
     let level1 = match triple.architecture {
         Architecture::Thumbv6m | Architecture::Thumbv7em | Architecture::Thumbv7m => {
@@ -56,6 +71,7 @@ fn isa_constructor(
         triple,
         isa_flags: settings::Flags::new(&shared_flags, builder),
         shared_flags,
+        libcall_names,
         cpumode: level1,
     })
 }
@@ -73,14 +89,30 @@ impl TargetIsa for Isa {
         &self.shared_flags
     }
 
+    fn libcall_names(&self) -> &ir::LibcallNames {
+        &self.libcall_names
+    }
+
+    fn uses_cpu_flags(&self) -> bool {
+        true
+    }
+
     fn register_info(&self) -> RegInfo {
         registers::INFO.clone()
     }
 
+    fn supports(&self, opcode: ir::Opcode, ctrl_type: ir::Type) -> InstSupport {
+        inst_support(self, opcode, ctrl_type)
+    }
+
     fn encoding_info(&self) -> EncInfo {
         enc_tables::INFO.clone()
     }
 
+    fn latencies(&self) -> Option<&Latencies> {
+        Some(&LATENCIES)
+    }
+
     fn legal_encodings<'a>(
         &'a self,
         func: &'a ir::Function,
@@ -113,6 +145,18 @@ impl TargetIsa for Isa {
         abi::allocatable_registers(func)
     }
 
+    fn callee_saved_registers(&self, func: &ir::Function) -> regalloc::RegisterSet {
+        abi::callee_saved_registers(func)
+    }
+
+    fn supports_call_conv(
+        &self,
+        caller: shared_settings::CallConv,
+        callee: shared_settings::CallConv,
+    ) -> bool {
+        abi::supports_call_conv(caller) && abi::supports_call_conv(callee)
+    }
+
     #[cfg(feature = "testing_hooks")]
     fn emit_inst(
         &self,
@@ -120,12 +164,21 @@ impl TargetIsa for Isa {
         inst: ir::Inst,
         divert: &mut regalloc::RegDiversions,
         sink: &mut CodeSink,
-    ) {
+    ) -> CodegenResult<()> {
         binemit::emit_inst(func, inst, divert, sink)
     }
 
-    fn emit_function_to_memory(&self, func: &ir::Function, sink: &mut MemoryCodeSink) {
-        emit_function(func, binemit::emit_inst, sink)
+    fn emit_function_to_memory(
+        &self,
+        func: &ir::Function,
+        sink: &mut MemoryCodeSink,
+    ) -> CodegenResult<()> {
+        emit_function(func, binemit::emit_inst, sink, self.endianness())
+    }
+
+    fn prologue_epilogue(&self, func: &mut ir::Function) -> CodegenResult<()> {
+        let _tt = timing::prologue_epilogue();
+        abi::prologue_epilogue(func, self)
     }
 }
 
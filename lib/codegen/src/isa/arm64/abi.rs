@@ -1,23 +1,131 @@
 //! ARM 64 ABI implementation.
+//!
+//! This module implements the AAPCS64 calling convention described in the "Procedure Call
+//! Standard for the ARM 64-bit Architecture".
+//!
+//! The frame record of the prologue links the frames through the frame pointer `x29`, and also
+//! saves the link register `x30` so that it can be allocated in the function body.
 
-use super::registers::{FPR, GPR};
+use super::registers::{FPR, GPR, RU};
+use abi::{
+    bool_abi_type, expand_struct_args, legalize_args, ArgAction, ArgAssigner, ValueConversion,
+};
+use cursor::{Cursor, CursorPosition, EncCursor};
 use ir;
-use isa::RegClass;
+use ir::immediates::Imm64;
+use ir::{AbiParam, ArgumentExtension, ArgumentLoc, ArgumentPurpose, InstBuilder, ValueLoc};
+use isa::{RegClass, RegUnit, TargetIsa};
 use regalloc::RegisterSet;
-use settings as shared_settings;
+use result::{CodegenError, CodegenResult};
+use settings::CallConv;
+use stack_layout::layout_stack;
+use std::i32;
+use std::string::ToString;
+use std::vec::Vec;
+
+/// Callee-saved general purpose registers, excluding the frame pointer.
+static CALLEE_SAVED_GPRS: [RU; 10] = [
+    RU::x19,
+    RU::x20,
+    RU::x21,
+    RU::x22,
+    RU::x23,
+    RU::x24,
+    RU::x25,
+    RU::x26,
+    RU::x27,
+    RU::x28,
+];
+
+struct Args {
+    pointer_type: ir::Type,
+    gpr_used: usize,
+    fpr_used: usize,
+    offset: u32,
+    call_conv: CallConv,
+}
+
+impl Args {
+    fn new(call_conv: CallConv) -> Self {
+        Self {
+            pointer_type: ir::types::I64,
+            gpr_used: 0,
+            fpr_used: 0,
+            offset: 0,
+            call_conv,
+        }
+    }
+}
+
+impl ArgAssigner for Args {
+    fn assign(&mut self, arg: &AbiParam) -> ArgAction {
+        let ty = arg.value_type;
+
+        // Check for a legal type.
+        // We don't support SIMD yet, so break all vectors down.
+        if ty.is_vector() {
+            return ValueConversion::VectorSplit.into();
+        }
+
+        // Booleans are passed as integers.
+        if ty.is_bool() {
+            return ValueConversion::BoolToInt(bool_abi_type(ty, self.call_conv)).into();
+        }
+
+        // Large integers are broken down to fit in a register.
+        if !ty.is_float() && ty.bits() > 64 {
+            return ValueConversion::IntSplit.into();
+        }
+
+        // Small integers are extended to the size of a pointer register.
+        if ty.is_int() && ty.bits() < 64 {
+            match arg.extension {
+                ArgumentExtension::None => {}
+                ArgumentExtension::Uext => return ValueConversion::Uext(self.pointer_type).into(),
+                ArgumentExtension::Sext => return ValueConversion::Sext(self.pointer_type).into(),
+            }
+        }
+
+        // The address of an indirect result is passed in `x8`.
+        if arg.purpose == ArgumentPurpose::StructReturn {
+            return ArgumentLoc::Reg(RU::x8 as RegUnit).into();
+        }
+
+        // The first eight values of each class are passed in `x0-x7` and `v0-v7`.
+        if !ty.is_float() && self.gpr_used < 8 {
+            let reg = GPR.unit(self.gpr_used);
+            self.gpr_used += 1;
+            return ArgumentLoc::Reg(reg).into();
+        }
+        if ty.is_float() && self.fpr_used < 8 {
+            let reg = FPR.unit(self.fpr_used);
+            self.fpr_used += 1;
+            return ArgumentLoc::Reg(reg).into();
+        }
+
+        // Assign a stack location. Each value takes an 8-byte slot.
+        let loc = ArgumentLoc::Stack(self.offset as i32);
+        self.offset += 8;
+        debug_assert!(self.offset <= i32::MAX as u32);
+        loc.into()
+    }
+}
 
 /// Legalize `sig`.
-pub fn legalize_signature(
-    _sig: &mut ir::Signature,
-    _flags: &shared_settings::Flags,
-    _current: bool,
-) {
-    unimplemented!()
+pub fn legalize_signature(sig: &mut ir::Signature, _current: bool) {
+    let mut args = Args::new(sig.call_conv);
+    // Struct arguments are passed by reference to a copy.
+    let word = args.pointer_type;
+    expand_struct_args(&mut sig.params, word, |_| true);
+    legalize_args(&mut sig.params, &mut args);
+
+    let mut rets = Args::new(sig.call_conv);
+    legalize_args(&mut sig.returns, &mut rets);
 }
 
 /// Get register class for a type appearing in a legalized signature.
 pub fn regclass_for_abi_type(ty: ir::Type) -> RegClass {
-    if ty.is_int() {
+    if ty.is_int() || ty.is_bool() {
         GPR
     } else {
         FPR
@@ -26,5 +134,247 @@ pub fn regclass_for_abi_type(ty: ir::Type) -> RegClass {
 
 /// Get the set of allocatable registers for `func`.
 pub fn allocatable_registers(_func: &ir::Function) -> RegisterSet {
-    unimplemented!()
+    let mut regs = RegisterSet::new();
+    regs.take(GPR, RU::x18 as RegUnit); // Platform register.
+    regs.take(GPR, RU::x29 as RegUnit); // Frame pointer.
+    regs.take(GPR, RU::x31 as RegUnit); // Stack pointer or zero register.
+
+    // `x30` is the link register, which is saved in the frame record by the prologue and is
+    // available for allocation.
+    regs
+}
+
+/// Get the set of callee-saved registers that are allocatable in `func`.
+pub fn callee_saved_registers(_func: &ir::Function) -> RegisterSet {
+    let mut regs = RegisterSet::empty();
+    for reg in &CALLEE_SAVED_GPRS {
+        regs.free(GPR, *reg as RegUnit);
+    }
+    regs
+}
+
+/// Get the set of callee-saved registers that are used.
+fn callee_saved_gprs_used(func: &ir::Function) -> RegisterSet {
+    let all_callee_saved = callee_saved_registers(func);
+
+    let mut used = RegisterSet::empty();
+    for value_loc in func.locations.values() {
+        if let ValueLoc::Reg(ru) = *value_loc {
+            if GPR.contains(ru) && !used.is_avail(GPR, ru) {
+                used.free(GPR, ru);
+            }
+        }
+    }
+
+    // regmove and regfill instructions may temporarily divert values into other registers,
+    // and these are not reflected in `func.locations`. Scan the function for such instructions
+    // and note which callee-saved registers they use.
+    for ebb in &func.layout {
+        for inst in func.layout.ebb_insts(ebb) {
+            match func.dfg[inst] {
+                ir::instructions::InstructionData::RegMove { dst, .. }
+                | ir::instructions::InstructionData::RegFill { dst, .. } => {
+                    if GPR.contains(dst) && !used.is_avail(GPR, dst) {
+                        used.free(GPR, dst);
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+
+    used.intersect(&all_callee_saved);
+    used
+}
+
+/// Is `call_conv` implemented by `prologue_epilogue` and the argument assignment?
+pub fn supports_call_conv(call_conv: CallConv) -> bool {
+    match call_conv {
+        CallConv::Fast | CallConv::Cold | CallConv::SystemV | CallConv::Raw => true,
+        CallConv::WindowsFastcall | CallConv::Baldrdash | CallConv::Probestack => false,
+    }
+}
+
+pub fn prologue_epilogue(func: &mut ir::Function, isa: &TargetIsa) -> CodegenResult<()> {
+    match func.signature.call_conv {
+        CallConv::Fast | CallConv::Cold | CallConv::SystemV | CallConv::Raw => {
+            aapcs_prologue_epilogue(func, isa)
+        }
+        CallConv::WindowsFastcall => unimplemented!("windows fastcall calling convention"),
+        CallConv::Baldrdash => unimplemented!("baldrdash calling convention"),
+        CallConv::Probestack => unimplemented!("probestack calling convention"),
+    }
+}
+
+/// Insert an AAPCS64 prologue and epilogue.
+fn aapcs_prologue_epilogue(func: &mut ir::Function, isa: &TargetIsa) -> CodegenResult<()> {
+    // The stack pointer must be 16-byte aligned when it is used to access memory.
+    let stack_align = 16;
+    let reg_type = ir::types::I64;
+
+    let csrs: Vec<RegUnit> = callee_saved_gprs_used(func).iter(GPR).collect();
+
+    // The reserved stack area is composed of:
+    //   frame record + all callee-saved registers
+    //
+    // The frame record holds the frame pointer and the link register. The callee-saved
+    // registers are pushed in pairs, and an odd register takes a 16-byte slot by itself to keep
+    // the stack pointer aligned.
+    let csr_stack_size = (16 + (csrs.len() + 1) / 2 * 16) as i32;
+    func.create_stack_slot(ir::StackSlotData {
+        kind: ir::StackSlotKind::IncomingArg,
+        size: csr_stack_size as u32,
+        offset: Some(-csr_stack_size),
+    });
+
+    let total_stack_size = layout_stack(&mut func.stack_slots, stack_align)? as i32;
+    let local_stack_size = i64::from(total_stack_size - csr_stack_size);
+
+    // The frame record is at the top of the frame, and the callee-saved registers are pushed in
+    // pairs below it. The frame pointer points at the frame record.
+    let fp = RU::x29 as RegUnit;
+    let mut saved = vec![(fp, -16), (RU::x30 as RegUnit, -8)];
+    for (i, &csr) in csrs.iter().enumerate() {
+        let pair = -16 * (2 + i as i32 / 2);
+        saved.push((csr, pair + 8 * (i as i32 % 2)));
+    }
+    let cfa = ir::Cfa::FramePointer {
+        reg: fp,
+        offset: 16,
+    };
+    func.frame_layout = Some(ir::FrameLayout::new(&func.stack_slots, cfa, saved));
+
+    // The stack pointer is adjusted with at most two 12-bit immediates.
+    if local_stack_size >= 1 << 24 {
+        return Err(CodegenError::ImplLimitExceeded(
+            "stack frame of 16 MB or more".to_string(),
+        ));
+    }
+
+    // Add the frame record and CSRs to function signature
+    let fp_arg = AbiParam::special_reg(reg_type, ArgumentPurpose::FramePointer, RU::x29 as RegUnit);
+    func.signature.params.push(fp_arg);
+    func.signature.returns.push(fp_arg);
+
+    let lr_arg = AbiParam::special_reg(reg_type, ArgumentPurpose::Link, RU::x30 as RegUnit);
+    func.signature.params.push(lr_arg);
+    func.signature.returns.push(lr_arg);
+
+    for &csr in &csrs {
+        let csr_arg = AbiParam::special_reg(reg_type, ArgumentPurpose::CalleeSaved, csr);
+        func.signature.params.push(csr_arg);
+        func.signature.returns.push(csr_arg);
+    }
+
+    // Set up the cursor and insert the prologue
+    let entry_ebb = func.layout.entry_block().expect("missing entry block");
+    let mut pos = EncCursor::new(func, isa).at_first_insertion_point(entry_ebb);
+    insert_prologue(&mut pos, local_stack_size, reg_type, &csrs);
+
+    // Reset the cursor and insert the epilogue
+    let mut pos = pos.at_position(CursorPosition::Nowhere);
+    insert_epilogues(&mut pos, local_stack_size, reg_type, &csrs);
+
+    Ok(())
+}
+
+/// Insert the prologue for a given function.
+fn insert_prologue(pos: &mut EncCursor, stack_size: i64, reg_type: ir::Type, csrs: &[RegUnit]) {
+    // Append params to entry EBB
+    let ebb = pos.current_ebb().expect("missing ebb under cursor");
+    let fp = pos.func.dfg.append_ebb_param(ebb, reg_type);
+    pos.func.locations[fp] = ValueLoc::Reg(RU::x29 as RegUnit);
+    let lr = pos.func.dfg.append_ebb_param(ebb, reg_type);
+    pos.func.locations[lr] = ValueLoc::Reg(RU::x30 as RegUnit);
+
+    // Push the frame record and point the frame pointer at it.
+    pos.ins().arm64_push2(fp, lr);
+    pos.ins()
+        .copy_special(RU::x31 as RegUnit, RU::x29 as RegUnit);
+
+    let mut csr_args = Vec::with_capacity(csrs.len());
+    for &reg in csrs {
+        // Append param to entry EBB
+        let csr_arg = pos.func.dfg.append_ebb_param(ebb, reg_type);
+
+        // Assign it a location
+        pos.func.locations[csr_arg] = ValueLoc::Reg(reg);
+        csr_args.push(csr_arg);
+    }
+    for pair in csr_args.chunks(2) {
+        if pair.len() == 2 {
+            pos.ins().arm64_push2(pair[0], pair[1]);
+        } else {
+            pos.ins().arm64_push(pair[0]);
+        }
+    }
+
+    // Allocate stack frame storage.
+    if stack_size > 0 {
+        let high = stack_size & !0xfff;
+        let low = stack_size & 0xfff;
+        if high != 0 {
+            pos.ins().adjust_sp_down_imm(Imm64::new(high));
+        }
+        if low != 0 {
+            pos.ins().adjust_sp_down_imm(Imm64::new(low));
+        }
+    }
+}
+
+/// Find all `return` instructions and insert epilogues before them.
+fn insert_epilogues(pos: &mut EncCursor, stack_size: i64, reg_type: ir::Type, csrs: &[RegUnit]) {
+    while let Some(ebb) = pos.next_ebb() {
+        pos.goto_last_inst(ebb);
+        if let Some(inst) = pos.current_inst() {
+            if pos.func.dfg[inst].opcode().is_return() {
+                insert_epilogue(inst, stack_size, pos, reg_type, csrs);
+            }
+        }
+    }
+}
+
+/// Insert an epilogue given a specific `return` instruction.
+fn insert_epilogue(
+    inst: ir::Inst,
+    stack_size: i64,
+    pos: &mut EncCursor,
+    reg_type: ir::Type,
+    csrs: &[RegUnit],
+) {
+    if stack_size > 0 {
+        let high = stack_size & !0xfff;
+        let low = stack_size & 0xfff;
+        if high != 0 {
+            pos.ins().adjust_sp_up_imm(Imm64::new(high));
+        }
+        if low != 0 {
+            pos.ins().adjust_sp_up_imm(Imm64::new(low));
+        }
+    }
+
+    // Pop the frame record and the callee-saved registers, stepping backward each time to
+    // preserve the correct order.
+    let (fp_ret, lr_ret) = pos.ins().arm64_pop2(reg_type);
+    pos.prev_inst();
+    pos.func.locations[fp_ret] = ValueLoc::Reg(RU::x29 as RegUnit);
+    pos.func.locations[lr_ret] = ValueLoc::Reg(RU::x30 as RegUnit);
+    pos.func.dfg.append_inst_arg(inst, fp_ret);
+    pos.func.dfg.append_inst_arg(inst, lr_ret);
+
+    for pair in csrs.chunks(2) {
+        if pair.len() == 2 {
+            let (first, second) = pos.ins().arm64_pop2(reg_type);
+            pos.prev_inst();
+            pos.func.locations[first] = ValueLoc::Reg(pair[0]);
+            pos.func.locations[second] = ValueLoc::Reg(pair[1]);
+            pos.func.dfg.append_inst_arg(inst, first);
+            pos.func.dfg.append_inst_arg(inst, second);
+        } else {
+            let csr_ret = pos.ins().arm64_pop(reg_type);
+            pos.prev_inst();
+            pos.func.locations[csr_ret] = ValueLoc::Reg(pair[0]);
+            pos.func.dfg.append_inst_arg(inst, csr_ret);
+        }
+    }
 }
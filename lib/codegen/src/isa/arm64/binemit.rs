@@ -1,7 +1,481 @@
 //! Emitting binary ARM64 machine code.
 
-use binemit::{bad_encoding, CodeSink};
-use ir::{Function, Inst};
+use binemit::{bad_encoding, branch_disp, CodeSink, Reloc};
+use ir::condcodes::{CondCode, IntCC};
+use ir::{Function, Inst, InstructionData, TrapCode};
+use isa::{RegUnit, StackBase, StackBaseMask, StackRef};
+use predicates::{is_signed_int, is_unsigned_int};
 use regalloc::RegDiversions;
+use result::CodegenResult;
 
 include!(concat!(env!("OUT_DIR"), "/binemit-arm64.rs"));
+
+/// Encoding bits of `csinc wd, wzr, wzr, cond`, used for `cset` after a comparison.
+const CSET_BITS: u16 = 0x08d4;
+
+/// Encoding bits of `subs wzr, wn, #imm`, used for comparing a `b1` value with zero.
+const CMP_W_IMM_BITS: u16 = 0x0388;
+
+/// The breakpoint instruction `brk #0`, used for traps.
+const BRK: u32 = 0xd420_0000;
+
+/// The system register field of `mrs` selecting the virtual count register `cntvct_el0`.
+const CNTVCT_EL0: u32 = 0x001b_e040;
+
+/// Get the instruction template encoded in `bits` by the `OP()` function in `recipes.py`.
+///
+/// The encoding bits are `inst[31:21] | (inst[14:10] << 11)`.
+fn op(bits: u16) -> u32 {
+    let bits = u32::from(bits);
+    ((bits & 0x7ff) << 21) | ((bits >> 11) << 10)
+}
+
+/// Get the register size in bits of the instruction encoded in `bits` from its `sf` bit.
+fn reg_size(bits: u16) -> u32 {
+    if op(bits) & 0x8000_0000 != 0 {
+        64
+    } else {
+        32
+    }
+}
+
+fn reg(r: RegUnit) -> u32 {
+    u32::from(r) & 0x1f
+}
+
+/// Get the register number to use for `base` when addressing a stack slot.
+fn stk_base(base: StackBase) -> RegUnit {
+    match base {
+        StackBase::SP => 31,
+        StackBase::FP => 29,
+        StackBase::Zone => unimplemented!(),
+    }
+}
+
+/// Convert an integer condition code to the A64 condition encoding.
+fn icc2cond(cond: IntCC) -> u32 {
+    use ir::condcodes::IntCC::*;
+    match cond {
+        Equal => 0b0000,
+        NotEqual => 0b0001,
+        UnsignedGreaterThanOrEqual => 0b0010,
+        UnsignedLessThan => 0b0011,
+        UnsignedGreaterThan => 0b1000,
+        UnsignedLessThanOrEqual => 0b1001,
+        SignedGreaterThanOrEqual => 0b1010,
+        SignedLessThan => 0b1011,
+        SignedGreaterThan => 0b1100,
+        SignedLessThanOrEqual => 0b1101,
+    }
+}
+
+/// Data processing instructions with three registers.
+///
+///   31     20 15        9  4
+///   opcode rm opcode/ra rn rd
+///       21 16        10  5  0
+///
+/// This covers the add/sub and logical (shifted register) classes, and the data processing
+/// classes with two and three sources.
+fn put_rrr<CS: CodeSink + ?Sized>(bits: u16, rn: RegUnit, rm: RegUnit, rd: RegUnit, sink: &mut CS) {
+    let mut i = op(bits);
+    i |= reg(rm) << 16;
+    i |= reg(rn) << 5;
+    i |= reg(rd);
+    sink.put4(i);
+}
+
+/// Add/subtract with an unsigned 12-bit immediate.
+///
+///   31     21    9  4
+///   opcode imm12 rn rd
+///       22    10  5  0
+///
+/// The shift of the immediate in bit 22 is part of the opcode.
+fn put_rri12<CS: CodeSink + ?Sized>(bits: u16, rn: RegUnit, imm: i64, rd: RegUnit, sink: &mut CS) {
+    debug_assert!(is_unsigned_int(imm, 12, 0), "imm12 out of range {:#x}", imm);
+    let mut i = op(bits);
+    i |= (imm as u32 & 0xfff) << 10;
+    i |= reg(rn) << 5;
+    i |= reg(rd);
+    sink.put4(i);
+}
+
+/// Logical with a bitmask immediate.
+///
+///   31     22 21   15   9  4
+///   opcode N  immr imms rn rd
+///       23 22   16   10  5  0
+fn put_logical_imm<CS: CodeSink + ?Sized>(
+    bits: u16,
+    rn: RegUnit,
+    imm: i64,
+    rd: RegUnit,
+    sink: &mut CS,
+) {
+    let (n, immr, imms) = match bitmask_imm(imm as u64, reg_size(bits)) {
+        Some(fields) => fields,
+        None => panic!("{:#x} isn't a logical immediate", imm),
+    };
+    let mut i = op(bits);
+    i |= n << 22;
+    i |= immr << 16;
+    i |= imms << 10;
+    i |= reg(rn) << 5;
+    i |= reg(rd);
+    sink.put4(i);
+}
+
+/// Encode the low `size` bits of `imm` as the `N`, `immr`, and `imms` fields of a logical
+/// immediate.
+///
+/// The value consists of repeated elements of 2 to 64 bits. Each element is a run of ones rotated
+/// right by `immr`, and `imms` encodes both the element size and the number of ones.
+fn bitmask_imm(imm: u64, size: u32) -> Option<(u32, u32, u32)> {
+    if !::predicates::is_bitmask_imm(imm as i64, size as u8) {
+        return None;
+    }
+    let v = if size == 32 {
+        (imm & 0xffff_ffff) | (imm << 32)
+    } else {
+        imm
+    };
+
+    // Find the smallest element size that the value repeats.
+    let mut esize = 64;
+    while esize > 2 {
+        let half = esize / 2;
+        let mask = (1u64 << half) - 1;
+        if v & mask != (v >> half) & mask {
+            break;
+        }
+        esize = half;
+    }
+    let emask = if esize == 64 { !0 } else { (1u64 << esize) - 1 };
+    let elem = v & emask;
+    let ones = elem.count_ones();
+    let run = if ones == 64 { !0 } else { (1u64 << ones) - 1 };
+
+    // Find the rotation of the run of ones that gives the element.
+    let immr = (0..esize)
+        .find(|&r| {
+            let rotated = if r == 0 {
+                run
+            } else {
+                ((run >> r) | (run << (esize - r))) & emask
+            };
+            rotated == elem
+        })
+        .expect("not a rotated run of ones");
+
+    let n = if esize == 64 { 1 } else { 0 };
+    let imms = ((!(esize - 1) << 1) & 0x3f) | (ones - 1);
+    Some((n, immr, imms))
+}
+
+/// Bitfield instructions.
+///
+///   31     21   15   9  4
+///   opcode immr imms rn rd
+///       22   16   10  5  0
+///
+/// The `N` bit in bit 22 is part of the opcode.
+fn put_bitfield<CS: CodeSink + ?Sized>(
+    bits: u16,
+    rn: RegUnit,
+    immr: u32,
+    imms: u32,
+    rd: RegUnit,
+    sink: &mut CS,
+) {
+    let mut i = op(bits);
+    i |= (immr & 0x3f) << 16;
+    i |= (imms & 0x3f) << 10;
+    i |= reg(rn) << 5;
+    i |= reg(rd);
+    sink.put4(i);
+}
+
+/// Extract a register from a pair of registers.
+///
+///   31     20 15   9  4
+///   opcode rm imms rn rd
+///       21 16   10  5  0
+fn put_extr<CS: CodeSink + ?Sized>(
+    bits: u16,
+    rn: RegUnit,
+    rm: RegUnit,
+    imms: u32,
+    rd: RegUnit,
+    sink: &mut CS,
+) {
+    let mut i = op(bits);
+    i |= reg(rm) << 16;
+    i |= (imms & 0x3f) << 10;
+    i |= reg(rn) << 5;
+    i |= reg(rd);
+    sink.put4(i);
+}
+
+/// Move wide immediate instructions.
+///
+///   31     22 20    4
+///   opcode hw imm16 rd
+///       23 21     5  0
+fn put_movewide<CS: CodeSink + ?Sized>(bits: u16, hw: u32, imm16: u32, rd: RegUnit, sink: &mut CS) {
+    let mut i = op(bits);
+    i |= (hw & 0x3) << 21;
+    i |= (imm16 & 0xffff) << 5;
+    i |= reg(rd);
+    sink.put4(i);
+}
+
+/// Get the move wide instructions materializing `imm` in a register.
+///
+/// Returns the fixed bits of each instruction as its `opc` field and the `hw` and `imm16`
+/// fields, with the number of instructions. The first instruction is a `movz` or `movn`, and the
+/// remaining ones are `movk` instructions setting the other halfwords.
+pub fn movimm_insts(imm: i64, is64: bool) -> ([(u32, u32, u32); 4], usize) {
+    const MOVN: u32 = 0b00;
+    const MOVZ: u32 = 0b10;
+    const MOVK: u32 = 0b11;
+
+    let halfwords = if is64 { 4 } else { 2 };
+    let hw = |i: u32| (imm as u64 >> (16 * i)) as u32 & 0xffff;
+    let zeros = (0..halfwords).filter(|&i| hw(i) == 0).count();
+    let ones = (0..halfwords).filter(|&i| hw(i) == 0xffff).count();
+
+    // Use `movn` when it leaves fewer halfwords to be set by `movk`.
+    let (first, fill) = if ones > zeros {
+        (MOVN, 0xffff)
+    } else {
+        (MOVZ, 0)
+    };
+
+    let mut insts = [(0, 0, 0); 4];
+    let mut len = 0;
+    for i in 0..halfwords {
+        let value = hw(i);
+        if value == fill {
+            continue;
+        }
+        insts[len] = if len == 0 {
+            if first == MOVN {
+                (MOVN, i, !value & 0xffff)
+            } else {
+                (MOVZ, i, value)
+            }
+        } else {
+            (MOVK, i, value)
+        };
+        len += 1;
+    }
+    if len == 0 {
+        // All the halfwords are equal to `fill`.
+        insts[0] = (first, 0, 0);
+        len = 1;
+    }
+    (insts, len)
+}
+
+/// Materialize the constant `imm` using `movz` or `movn` followed by `movk` instructions.
+///
+/// Encoding bits: the `movz` instruction for the register size.
+fn put_movimm<CS: CodeSink + ?Sized>(bits: u16, imm: i64, rd: RegUnit, sink: &mut CS) {
+    let (insts, len) = movimm_insts(imm, reg_size(bits) == 64);
+    for &(opc, hw, imm16) in &insts[0..len] {
+        // Replace the `opc` field in bits 30-29.
+        let bits = (bits & !0x300) | (opc << 8) as u16;
+        put_movewide(bits, hw, imm16, rd, sink);
+    }
+}
+
+/// Set a register to 1 if a condition holds and 0 otherwise: `cset rd, cond`.
+///
+/// This is an alias of `csinc rd, zr, zr, !cond`.
+fn put_cset<CS: CodeSink + ?Sized>(bits: u16, cond: IntCC, rd: RegUnit, sink: &mut CS) {
+    put_csel(bits, 31, 31, cond.inverse(), rd, sink);
+}
+
+/// Conditional select instructions.
+///
+///   31     20 15   11  9  4
+///   opcode rm cond op2 rn rd
+///       21 16   12  10  5  0
+fn put_csel<CS: CodeSink + ?Sized>(
+    bits: u16,
+    rn: RegUnit,
+    rm: RegUnit,
+    cond: IntCC,
+    rd: RegUnit,
+    sink: &mut CS,
+) {
+    let mut i = op(bits);
+    i |= reg(rm) << 16;
+    i |= icc2cond(cond) << 12;
+    i |= reg(rn) << 5;
+    i |= reg(rd);
+    sink.put4(i);
+}
+
+/// Conditional branches.
+///
+///   31     23    4
+///   opcode imm19 cond
+///       24     5    0
+fn put_bcond<CS: CodeSink + ?Sized>(bits: u16, cond: IntCC, disp: i64, sink: &mut CS) {
+    debug_assert!(
+        is_signed_int(disp, 21, 2),
+        "b.cond out of range {:#x}",
+        disp
+    );
+    let mut i = op(bits);
+    i |= ((disp >> 2) as u32 & 0x7ffff) << 5;
+    i |= icc2cond(cond);
+    sink.put4(i);
+}
+
+/// Compare and branch instructions.
+///
+///   31     23    4
+///   opcode imm19 rt
+///       24     5  0
+fn put_cb<CS: CodeSink + ?Sized>(bits: u16, rt: RegUnit, disp: i64, sink: &mut CS) {
+    debug_assert!(is_signed_int(disp, 21, 2), "cbz out of range {:#x}", disp);
+    let mut i = op(bits);
+    i |= ((disp >> 2) as u32 & 0x7ffff) << 5;
+    i |= reg(rt);
+    sink.put4(i);
+}
+
+/// Unconditional branches with an immediate.
+///
+///   31     25
+///   opcode imm26
+///       26     0
+fn put_b<CS: CodeSink + ?Sized>(bits: u16, disp: i64, sink: &mut CS) {
+    debug_assert!(is_signed_int(disp, 28, 2), "b out of range {:#x}", disp);
+    let mut i = op(bits);
+    i |= (disp >> 2) as u32 & 0x3ff_ffff;
+    sink.put4(i);
+}
+
+/// Loads and stores with an unsigned offset scaled by the access size.
+///
+///   31     21    9  4
+///   opcode imm12 rn rt
+///       22    10  5  0
+///
+/// The access size is the `size` field in bits 31-30.
+fn put_ldst_uimm<CS: CodeSink + ?Sized>(
+    bits: u16,
+    rt: RegUnit,
+    rn: RegUnit,
+    offset: i64,
+    sink: &mut CS,
+) {
+    let scale = op(bits) >> 30;
+    debug_assert!(
+        is_unsigned_int(offset, 12 + scale as u8, scale as u8),
+        "scaled offset out of range {:#x}",
+        offset
+    );
+    let mut i = op(bits);
+    i |= ((offset >> scale) as u32 & 0xfff) << 10;
+    i |= reg(rn) << 5;
+    i |= reg(rt);
+    sink.put4(i);
+}
+
+/// Loads and stores with a signed unscaled offset, including the pre-indexed and post-indexed
+/// forms.
+///
+///   31     20   11     9  4
+///   opcode imm9 opcode rn rt
+///       21   12     10  5  0
+fn put_ldst_simm9<CS: CodeSink + ?Sized>(
+    bits: u16,
+    rt: RegUnit,
+    rn: RegUnit,
+    offset: i64,
+    sink: &mut CS,
+) {
+    debug_assert!(
+        is_signed_int(offset, 9, 0),
+        "offset out of range {:#x}",
+        offset
+    );
+    let mut i = op(bits);
+    i |= (offset as u32 & 0x1ff) << 12;
+    i |= reg(rn) << 5;
+    i |= reg(rt);
+    sink.put4(i);
+}
+
+/// Load and store pair instructions.
+///
+///   31     21   14  9  4
+///   opcode imm7 rt2 rn rt
+///       22   15  10  5  0
+///
+/// The offset is scaled by the register size.
+fn put_ldst_pair<CS: CodeSink + ?Sized>(
+    bits: u16,
+    rt: RegUnit,
+    rt2: RegUnit,
+    rn: RegUnit,
+    offset: i64,
+    sink: &mut CS,
+) {
+    let scale = if reg_size(bits) == 64 { 3 } else { 2 };
+    debug_assert!(
+        is_signed_int(offset, 7 + scale, scale),
+        "pair offset out of range {:#x}",
+        offset
+    );
+    let mut i = op(bits);
+    i |= ((offset >> scale) as u32 & 0x7f) << 15;
+    i |= reg(rt2) << 10;
+    i |= reg(rn) << 5;
+    i |= reg(rt);
+    sink.put4(i);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn logical_imm() {
+        assert_eq!(bitmask_imm(1, 32), Some((0, 0, 0)));
+        assert_eq!(bitmask_imm(0xff00, 32), Some((0, 24, 7)));
+        assert_eq!(bitmask_imm(0x8000_0001, 32), Some((0, 1, 1)));
+        assert_eq!(bitmask_imm(0x5555_5555_5555_5555, 64), Some((0, 0, 0x3c)));
+        assert_eq!(bitmask_imm(0x00ff_00ff_00ff_00ff, 64), Some((0, 0, 0x27)));
+        assert_eq!(bitmask_imm(0x7fff_ffff_ffff_ffff, 64), Some((1, 0, 62)));
+        assert_eq!(bitmask_imm(!1, 64), Some((1, 63, 62)));
+        assert_eq!(bitmask_imm(0x1234, 32), None);
+        assert_eq!(bitmask_imm(0, 64), None);
+    }
+
+    #[test]
+    fn movimm() {
+        // movz w0, #0
+        assert_eq!(
+            movimm_insts(0, false),
+            ([(2, 0, 0), (0, 0, 0), (0, 0, 0), (0, 0, 0)], 1)
+        );
+        // movn w0, #0
+        assert_eq!(movimm_insts(-1, false).1, 1);
+        assert_eq!(movimm_insts(-1, false).0[0], (0, 0, 0));
+        // movn x0, #0x1233, lsl #16
+        assert_eq!(movimm_insts(-0x1233_0001, true).0[0], (0, 1, 0x1233));
+        assert_eq!(movimm_insts(-0x1233_0001, true).1, 1);
+        // movz x0, #0x1234, lsl #48; movk x0, #0x5678
+        let (insts, len) = movimm_insts(0x1234_0000_0000_5678, true);
+        assert_eq!(&insts[0..len], &[(2, 0, 0x5678), (3, 3, 0x1234)]);
+        // The high halfwords of a 32-bit constant are ignored.
+        let (insts, len) = movimm_insts(-0x8000_0000, false);
+        assert_eq!(&insts[0..len], &[(2, 1, 0x8000)]);
+    }
+}
@@ -1,10 +1,25 @@
 //! Encoding tables for ARM64 ISA.
 
-use ir;
+use super::binemit::movimm_insts;
+use super::registers::*;
+use ir::{self, Function, Inst, InstructionData};
 use isa;
 use isa::constraints::*;
 use isa::enc_tables::*;
-use isa::encoding::RecipeSizing;
+use isa::encoding::{base_size, RecipeSizing};
+use regalloc::RegDiversions;
 
 include!(concat!(env!("OUT_DIR"), "/encoding-arm64.rs"));
 include!(concat!(env!("OUT_DIR"), "/legalize-arm64.rs"));
+
+/// Compute the size of an `iconst` materialized by a `movz` or `movn` instruction followed by
+/// `movk` instructions.
+fn size_movimm(_sizing: &RecipeSizing, inst: Inst, _divert: &RegDiversions, func: &Function) -> u8 {
+    match func.dfg[inst] {
+        InstructionData::UnaryImm { imm, .. } => {
+            let is64 = func.dfg.ctrl_typevar(inst) == ir::types::I64;
+            4 * movimm_insts(imm.into(), is64).1 as u8
+        }
+        _ => panic!("Expected iconst: {}", func.dfg.display_inst(inst, None)),
+    }
+}
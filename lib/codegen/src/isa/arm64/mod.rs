@@ -12,18 +12,22 @@ use binemit::CodeSink;
 use binemit::{emit_function, MemoryCodeSink};
 use ir;
 use isa::enc_tables::{lookup_enclist, Encodings};
+use isa::support::inst_support;
 use isa::Builder as IsaBuilder;
-use isa::{EncInfo, RegClass, RegInfo, TargetIsa};
+use isa::{EncInfo, InstSupport, RegClass, RegInfo, TargetIsa};
 use regalloc;
+use result::CodegenResult;
 use std::boxed::Box;
 use std::fmt;
 use target_lexicon::Triple;
+use timing;
 
 #[allow(dead_code)]
 struct Isa {
     triple: Triple,
     shared_flags: shared_settings::Flags,
     isa_flags: settings::Flags,
+    libcall_names: ir::LibcallNames,
 }
 
 /// Get an ISA builder for creating ARM64 targets.
@@ -31,6 +35,7 @@ pub fn isa_builder(triple: Triple) -> IsaBuilder {
     IsaBuilder {
         triple,
         setup: settings::builder(),
+        libcall_names: ir::LibcallNames::new(),
         constructor: isa_constructor,
     }
 }
@@ -39,11 +44,13 @@ fn isa_constructor(
     triple: Triple,
     shared_flags: shared_settings::Flags,
     builder: shared_settings::Builder,
+    libcall_names: ir::LibcallNames,
 ) -> Box<TargetIsa> {
     Box::new(Isa {
         triple,
         isa_flags: settings::Flags::new(&shared_flags, builder),
         shared_flags,
+        libcall_names,
     })
 }
 
@@ -60,10 +67,22 @@ impl TargetIsa for Isa {
         &self.shared_flags
     }
 
+    fn libcall_names(&self) -> &ir::LibcallNames {
+        &self.libcall_names
+    }
+
+    fn uses_cpu_flags(&self) -> bool {
+        true
+    }
+
     fn register_info(&self) -> RegInfo {
         registers::INFO.clone()
     }
 
+    fn supports(&self, opcode: ir::Opcode, ctrl_type: ir::Type) -> InstSupport {
+        inst_support(self, opcode, ctrl_type)
+    }
+
     fn encoding_info(&self) -> EncInfo {
         enc_tables::INFO.clone()
     }
@@ -89,7 +108,7 @@ impl TargetIsa for Isa {
     }
 
     fn legalize_signature(&self, sig: &mut ir::Signature, current: bool) {
-        abi::legalize_signature(sig, &self.shared_flags, current)
+        abi::legalize_signature(sig, current)
     }
 
     fn regclass_for_abi_type(&self, ty: ir::Type) -> RegClass {
@@ -100,6 +119,18 @@ impl TargetIsa for Isa {
         abi::allocatable_registers(func)
     }
 
+    fn callee_saved_registers(&self, func: &ir::Function) -> regalloc::RegisterSet {
+        abi::callee_saved_registers(func)
+    }
+
+    fn supports_call_conv(
+        &self,
+        caller: shared_settings::CallConv,
+        callee: shared_settings::CallConv,
+    ) -> bool {
+        abi::supports_call_conv(caller) && abi::supports_call_conv(callee)
+    }
+
     #[cfg(feature = "testing_hooks")]
     fn emit_inst(
         &self,
@@ -107,12 +138,21 @@ impl TargetIsa for Isa {
         inst: ir::Inst,
         divert: &mut regalloc::RegDiversions,
         sink: &mut CodeSink,
-    ) {
+    ) -> CodegenResult<()> {
         binemit::emit_inst(func, inst, divert, sink)
     }
 
-    fn emit_function_to_memory(&self, func: &ir::Function, sink: &mut MemoryCodeSink) {
-        emit_function(func, binemit::emit_inst, sink)
+    fn emit_function_to_memory(
+        &self,
+        func: &ir::Function,
+        sink: &mut MemoryCodeSink,
+    ) -> CodegenResult<()> {
+        emit_function(func, binemit::emit_inst, sink, self.endianness())
+    }
+
+    fn prologue_epilogue(&self, func: &mut ir::Function) -> CodegenResult<()> {
+        let _tt = timing::prologue_epilogue();
+        abi::prologue_epilogue(func, self)
     }
 }
 
@@ -8,8 +8,8 @@
 //! are satisfied.
 
 use binemit::CodeOffset;
-use ir::{Function, Inst, ValueLoc};
-use isa::{RegClass, RegUnit};
+use ir::{Function, Inst, InstructionData, StackSlot, ValueLoc};
+use isa::{RegClass, RegUnit, StackRef};
 use regalloc::RegDiversions;
 
 /// Register constraint for a single value operand or instruction result.
@@ -118,6 +118,19 @@ pub struct RecipeConstraints {
     ///
     /// When true, SSA values of type `iflags` or `fflags` can not be live across the instruction.
     pub clobbers_flags: bool,
+
+    /// Does this instruction zero-extend its 32-bit results?
+    ///
+    /// When true, the instruction writes the whole 64-bit register of a 32-bit result, with the
+    /// upper half cleared, so a `uextend` of the result is a no-op.
+    pub zero_extends: bool,
+
+    /// Number of bits in the signed displacement used to address stack slots, if it is narrower
+    /// than 32 bits.
+    ///
+    /// The displacements are only known once the stack frame is laid out, so an encoding with
+    /// narrow stack displacements doesn't satisfy its constraints before that.
+    pub stack_disp_bits: Option<u8>,
 }
 
 impl RecipeConstraints {
@@ -146,8 +159,43 @@ impl RecipeConstraints {
             }
         }
 
-        true
+        match self.stack_disp_bits {
+            Some(bits) => stack_disps_fit(bits, inst, divert, func),
+            None => true,
+        }
+    }
+}
+
+/// Check that the displacements of the stack slots accessed by `inst` fit in a signed `bits`-bit
+/// field.
+fn stack_disps_fit(bits: u8, inst: Inst, divert: &RegDiversions, func: &Function) -> bool {
+    if func.stack_slots.frame_size.is_none() {
+        return false;
+    }
+    let fits = |ss: StackSlot, offset: i32| {
+        let limit = 1i64 << (bits - 1);
+        let disp = i64::from(StackRef::sp(ss, &func.stack_slots).offset) + i64::from(offset);
+        -limit <= disp && disp < limit
+    };
+
+    if let InstructionData::StackLoad {
+        stack_slot, offset, ..
     }
+    | InstructionData::StackStore {
+        stack_slot, offset, ..
+    } = func.dfg[inst]
+    {
+        if !fits(stack_slot, offset.into()) {
+            return false;
+        }
+    }
+
+    let args = func.dfg.inst_args(inst).iter();
+    args.chain(func.dfg.inst_results(inst))
+        .all(|&value| match divert.get(value, &func.locations) {
+            ValueLoc::Stack(ss) => fits(ss, 0),
+            _ => true,
+        })
 }
 
 /// Constraints on the range of a branch instruction.
@@ -124,6 +124,11 @@ impl EncInfo {
         self.constraints.get(enc.recipe())
     }
 
+    /// Get the name of the recipe of `enc`, or `None` if it isn't a legal encoding.
+    pub fn recipe_name(&self, enc: Encoding) -> Option<&'static str> {
+        self.names.get(enc.recipe()).cloned()
+    }
+
     /// Create an object that can display an ISA-dependent encoding properly.
     pub fn display(&self, enc: Encoding) -> DisplayEncoding {
         DisplayEncoding {
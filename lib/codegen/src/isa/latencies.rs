@@ -0,0 +1,64 @@
+//! Instruction latencies for scheduling.
+
+use ir::Opcode;
+
+/// The latencies in cycles of the classes of instructions on an in-order core.
+///
+/// The latency of an instruction is the number of cycles from when it is issued until its results
+/// can be used by another instruction without stalling the pipeline.
+#[derive(Clone, Copy, Debug)]
+pub struct Latencies {
+    /// Loads from memory.
+    pub load: u8,
+    /// Integer multiplications.
+    pub imul: u8,
+    /// Integer divisions and remainders.
+    pub idiv: u8,
+    /// Floating point arithmetic, comparisons, and conversions.
+    pub float: u8,
+    /// All other instructions.
+    pub other: u8,
+}
+
+impl Latencies {
+    /// Get the latency of an instruction with `opcode`.
+    pub fn latency(&self, opcode: Opcode) -> u8 {
+        match opcode {
+            _ if opcode.can_load() => self.load,
+            Opcode::Imul | Opcode::ImulImm | Opcode::Umulhi | Opcode::Smulhi => self.imul,
+            Opcode::Udiv
+            | Opcode::UdivImm
+            | Opcode::Sdiv
+            | Opcode::SdivImm
+            | Opcode::Urem
+            | Opcode::UremImm
+            | Opcode::Srem
+            | Opcode::SremImm => self.idiv,
+            Opcode::Fadd
+            | Opcode::Fsub
+            | Opcode::Fmul
+            | Opcode::Fdiv
+            | Opcode::Sqrt
+            | Opcode::Fma
+            | Opcode::Fmin
+            | Opcode::Fmax
+            | Opcode::FminPseudo
+            | Opcode::FmaxPseudo
+            | Opcode::Ceil
+            | Opcode::Floor
+            | Opcode::Trunc
+            | Opcode::Nearest
+            | Opcode::Fcmp
+            | Opcode::Ffcmp
+            | Opcode::Fpromote
+            | Opcode::Fdemote
+            | Opcode::FcvtToUint
+            | Opcode::FcvtToUintSat
+            | Opcode::FcvtToSint
+            | Opcode::FcvtToSintSat
+            | Opcode::FcvtFromUint
+            | Opcode::FcvtFromSint => self.float,
+            _ => self.other,
+        }
+    }
+}
@@ -48,20 +48,24 @@
 
 pub use isa::constraints::{BranchRange, ConstraintKind, OperandConstraint, RecipeConstraints};
 pub use isa::encoding::{base_size, EncInfo, Encoding};
+pub use isa::latencies::Latencies;
 pub use isa::registers::{regs_overlap, RegClass, RegClassIndex, RegInfo, RegUnit};
 pub use isa::stack::{StackBase, StackBaseMask, StackRef};
+pub use isa::support::InstSupport;
 
 use binemit;
 use flowgraph;
 use ir;
 use isa::enc_tables::Encodings;
+use legalizer::legalize_call_signature;
 use regalloc;
 use result::CodegenResult;
 use settings;
 use settings::{CallConv, SetResult};
 use std::boxed::Box;
 use std::fmt;
-use target_lexicon::{Architecture, Triple};
+use std::vec::Vec;
+use target_lexicon::{Architecture, Endianness, Triple};
 use timing;
 
 #[cfg(build_riscv)]
@@ -79,8 +83,10 @@ mod arm64;
 mod constraints;
 mod enc_tables;
 mod encoding;
+mod latencies;
 pub mod registers;
 mod stack;
+mod support;
 
 /// Returns a builder that can create a corresponding `TargetIsa`
 /// or `Err(LookupError::Unsupported)` if not enabled.
@@ -136,14 +142,20 @@ pub enum LookupError {
 pub struct Builder {
     triple: Triple,
     setup: settings::Builder,
-    constructor: fn(Triple, settings::Flags, settings::Builder) -> Box<TargetIsa>,
+    libcall_names: ir::LibcallNames,
+    constructor: fn(Triple, settings::Flags, settings::Builder, ir::LibcallNames) -> Box<TargetIsa>,
 }
 
 impl Builder {
+    /// Configure the names of the runtime library routines.
+    pub fn set_libcall_names(&mut self, libcall_names: ir::LibcallNames) {
+        self.libcall_names = libcall_names;
+    }
+
     /// Combine the ISA-specific settings with the provided ISA-independent settings and allocate a
     /// fully configured `TargetIsa` trait object.
     pub fn finish(self, shared_flags: settings::Flags) -> Box<TargetIsa> {
-        (self.constructor)(self.triple, shared_flags, self.setup)
+        (self.constructor)(self.triple, shared_flags, self.setup, self.libcall_names)
     }
 }
 
@@ -166,7 +178,10 @@ pub type Legalize =
 
 /// Methods that are specialized to a target ISA. Implies a Display trait that shows the
 /// shared flags, as well as any isa-specific flags.
-pub trait TargetIsa: fmt::Display {
+///
+/// An ISA is immutable, and it is `Send` and `Sync` so a single instance can be shared by several
+/// compilation threads, for example in an `Arc<TargetIsa>`.
+pub trait TargetIsa: fmt::Display + Send + Sync {
     /// Get the name of this ISA.
     fn name(&self) -> &'static str;
 
@@ -176,6 +191,9 @@ pub trait TargetIsa: fmt::Display {
     /// Get the ISA-independent flags that were used to make this trait object.
     fn flags(&self) -> &settings::Flags;
 
+    /// Get the names of the runtime library routines.
+    fn libcall_names(&self) -> &ir::LibcallNames;
+
     /// Get the pointer type of this ISA.
     fn pointer_type(&self) -> ir::Type {
         ir::Type::int(u16::from(self.pointer_bits())).unwrap()
@@ -191,6 +209,11 @@ pub trait TargetIsa: fmt::Display {
         self.triple().pointer_width().unwrap().bytes()
     }
 
+    /// Get the byte order of data in memory on this ISA.
+    fn endianness(&self) -> Endianness {
+        self.triple().endianness().unwrap()
+    }
+
     /// Does the CPU implement scalar comparisons using a CPU flags register?
     fn uses_cpu_flags(&self) -> bool {
         false
@@ -228,9 +251,39 @@ pub trait TargetIsa: fmt::Display {
         iter.next().ok_or_else(|| iter.legalize())
     }
 
+    /// Determine how an instruction with `opcode` and controlling type `ctrl_type` is compiled
+    /// with the current settings.
+    ///
+    /// A frontend can use this to choose between lowerings of an operation before it emits any
+    /// IR, for example to call a runtime helper instead of an instruction that can't be compiled.
+    /// The answer is found by legalizing a function containing the instruction with small
+    /// constant operands, so it doesn't account for legalizations that depend on the values of
+    /// the operands. Only instructions whose operands are values, immediates, condition codes,
+    /// and memory flags can be queried, and the others are reported as `Unsupported`.
+    fn supports(&self, opcode: ir::Opcode, ctrl_type: ir::Type) -> InstSupport;
+
+    /// Get the encoding of the patchable instruction `inst`, if this ISA supports patching it.
+    ///
+    /// A patchable encoding has an immediate or displacement field wide enough for any value of
+    /// the instruction's type, and the code emitter reports the location of that field to
+    /// `CodeSink::patch_point`. The default implementation doesn't support any patchable
+    /// instructions.
+    fn patchable_encoding(&self, _func: &ir::Function, _inst: ir::Inst) -> Option<Encoding> {
+        None
+    }
+
     /// Get a data structure describing the instruction encodings in this ISA.
     fn encoding_info(&self) -> EncInfo;
 
+    /// Get the instruction latencies to schedule instructions for, if any.
+    ///
+    /// Instructions are only scheduled for ISAs whose cores execute instructions in order, where
+    /// using the result of a slow instruction right away stalls the pipeline. The default
+    /// implementation doesn't schedule instructions.
+    fn latencies(&self) -> Option<&Latencies> {
+        None
+    }
+
     /// Legalize a function signature.
     ///
     /// This is used to legalize both the signature of the function being compiled and any called
@@ -266,6 +319,30 @@ pub trait TargetIsa: fmt::Display {
     /// allocation.
     fn legalize_signature(&self, sig: &mut ir::Signature, current: bool);
 
+    /// Compute the locations of the arguments passed to a function with signature `sig`.
+    ///
+    /// The locations are those of the parameters of the legalized signature that compiled code
+    /// uses on both sides of a call. They don't correspond one-to-one to the parameters of `sig`:
+    /// arguments may be split into several parts, and a `StructReturn` argument is added first
+    /// when the return values are returned in memory. Special purpose arguments like `VMContext`
+    /// keep their position.
+    fn compute_argument_locations(&self, sig: &ir::Signature) -> Vec<ir::ArgumentLoc> {
+        let mut sig = sig.clone();
+        legalize_call_signature(&mut sig, self);
+        sig.params.iter().map(|param| param.location).collect()
+    }
+
+    /// Compute the locations of the values returned from a function with signature `sig`.
+    ///
+    /// Like `compute_argument_locations()`, these are the locations of the return values of the
+    /// legalized signature. When the return values are returned in memory, the only return value
+    /// is the `StructReturn` pointer.
+    fn compute_return_locations(&self, sig: &ir::Signature) -> Vec<ir::ArgumentLoc> {
+        let mut sig = sig.clone();
+        legalize_call_signature(&mut sig, self);
+        sig.returns.iter().map(|ret| ret.location).collect()
+    }
+
     /// Get the register class that should be used to represent an ABI argument or return value of
     /// type `ty`. This should be the top-level register class that contains the argument
     /// registers.
@@ -280,6 +357,25 @@ pub trait TargetIsa: fmt::Display {
     /// registers.
     fn allocatable_registers(&self, func: &ir::Function) -> regalloc::RegisterSet;
 
+    /// Get the set of allocatable registers that the prologue must save when `func` uses them.
+    ///
+    /// The register allocator consults this set to implement the `callee_saved_strategy` setting.
+    /// ISAs that don't save any registers in their prologue can use the default empty set.
+    fn callee_saved_registers(&self, _func: &ir::Function) -> regalloc::RegisterSet {
+        regalloc::RegisterSet::empty()
+    }
+
+    /// Can a function with the `caller` calling convention call a function with the `callee`
+    /// calling convention?
+    ///
+    /// The legalizer places the arguments of a call where the calling convention of the callee
+    /// expects them, so calls between different conventions work as long as the ISA implements
+    /// both of them. The convention of a function itself is checked as a call from the function
+    /// to itself.
+    fn supports_call_conv(&self, _caller: CallConv, _callee: CallConv) -> bool {
+        true
+    }
+
     /// Compute the stack layout and insert prologue and epilogue code into `func`.
     ///
     /// Return an error if the stack frame is too large.
@@ -300,6 +396,11 @@ pub trait TargetIsa: fmt::Display {
         }
 
         layout_stack(&mut func.stack_slots, word_size)?;
+        func.frame_layout = Some(ir::FrameLayout::new(
+            &func.stack_slots,
+            ir::Cfa::StackPointer,
+            Vec::new(),
+        ));
         Ok(())
     }
 
@@ -310,6 +411,8 @@ pub trait TargetIsa: fmt::Display {
     ///
     /// This function is under the "testing_hooks" feature, and is only suitable for use by
     /// test harnesses. It increases code size, and is inefficient.
+    ///
+    /// Returns an error if a displacement doesn't fit in the encoding of `inst`.
     #[cfg(feature = "testing_hooks")]
     fn emit_inst(
         &self,
@@ -317,8 +420,117 @@ pub trait TargetIsa: fmt::Display {
         inst: ir::Inst,
         divert: &mut regalloc::RegDiversions,
         sink: &mut binemit::CodeSink,
-    );
+    ) -> CodegenResult<()>;
 
     /// Emit a whole function into memory.
-    fn emit_function_to_memory(&self, func: &ir::Function, sink: &mut binemit::MemoryCodeSink);
+    ///
+    /// Returns an error if a branch or jump table displacement doesn't fit in its encoding.
+    fn emit_function_to_memory(
+        &self,
+        func: &ir::Function,
+        sink: &mut binemit::MemoryCodeSink,
+    ) -> CodegenResult<()>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TargetIsa;
+    use settings::Flags;
+
+    fn assert_send_sync<T: Send + Sync + ?Sized>() {}
+
+    #[test]
+    fn send_sync() {
+        assert_send_sync::<TargetIsa>();
+        assert_send_sync::<Flags>();
+    }
+
+    /// Compile a function with a jump table and a constant for the x86 encodings with the data
+    /// layout of `triple`. Returns the bytes of the jump table entry, the value of the entry, and
+    /// the bytes of the constant 1.5.
+    #[cfg(build_x86)]
+    fn compile_rodata(triple: &str) -> (Vec<u8>, u32, Vec<u8>) {
+        use super::x86;
+        use binemit::{NullRelocSink, NullTrapSink};
+        use cursor::{Cursor, FuncCursor};
+        use ir::immediates::Ieee64;
+        use ir::{types, AbiParam, ExternalName, Function, InstBuilder, JumpTableData, Signature};
+        use settings::{self, CallConv};
+        use std::str::FromStr;
+        use target_lexicon::Triple;
+        use Context;
+
+        let isa = x86::isa_builder(Triple::from_str(triple).unwrap())
+            .finish(Flags::new(settings::builder()));
+
+        let mut sig = Signature::new(CallConv::SystemV);
+        sig.params.push(AbiParam::new(types::I32));
+        sig.returns.push(AbiParam::new(types::F64));
+        let mut func = Function::with_name_signature(ExternalName::testcase("rodata"), sig);
+        let ebb0 = func.dfg.make_ebb();
+        let ebb1 = func.dfg.make_ebb();
+        let arg = func.dfg.append_ebb_param(ebb0, types::I32);
+        let mut jt_data = JumpTableData::new();
+        jt_data.push_entry(ebb1);
+        let jt = func.create_jump_table(jt_data);
+        {
+            let mut pos = FuncCursor::new(&mut func);
+            pos.insert_ebb(ebb0);
+            pos.ins().br_table(arg, ebb1, jt);
+            pos.insert_ebb(ebb1);
+            let value = pos.ins().f64const(Ieee64::with_float(1.5));
+            pos.ins().return_(&[value]);
+        }
+
+        let mut ctx = Context::for_function(func);
+        let info = ctx.compile(&*isa).unwrap();
+        let mut bytes = vec![0; info.total_size as usize];
+        unsafe {
+            ctx.emit_to_memory(
+                &*isa,
+                bytes.as_mut_ptr(),
+                &mut NullRelocSink {},
+                &mut NullTrapSink {},
+            ).unwrap();
+        }
+
+        let func = &ctx.func;
+        let jt_offset = func.jt_offsets[jt] as usize;
+        let entry = func.offsets[ebb1] as i32 - jt_offset as i32;
+        let constant_offset = func.constants.offset(1.5f64.to_bits(), 8) as usize;
+        (
+            bytes[jt_offset..jt_offset + 4].to_vec(),
+            entry as u32,
+            bytes[constant_offset..constant_offset + 8].to_vec(),
+        )
+    }
+
+    #[test]
+    #[cfg(build_x86)]
+    fn big_endian_rodata() {
+        // MIPS64 is a big-endian target with 64-bit pointers.
+        let (entry_bytes, entry, constant_bytes) = compile_rodata("mips64-unknown-unknown");
+        assert_eq!(
+            entry_bytes,
+            [
+                (entry >> 24) as u8,
+                (entry >> 16) as u8,
+                (entry >> 8) as u8,
+                entry as u8
+            ]
+        );
+        assert_eq!(constant_bytes, [0x3f, 0xf8, 0, 0, 0, 0, 0, 0]);
+
+        let (entry_bytes, entry, constant_bytes) = compile_rodata("x86_64-unknown-unknown");
+        assert_eq!(
+            entry_bytes,
+            [
+                entry as u8,
+                (entry >> 8) as u8,
+                (entry >> 16) as u8,
+                (entry >> 24) as u8
+            ]
+        );
+        assert_eq!(constant_bytes, [0, 0, 0, 0, 0, 0, 0xf8, 0x3f]);
+    }
 }
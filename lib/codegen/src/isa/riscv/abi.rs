@@ -7,10 +7,13 @@
 
 use super::registers::{FPR, GPR};
 use super::settings;
-use abi::{legalize_args, ArgAction, ArgAssigner, ValueConversion};
+use abi::{
+    bool_abi_type, expand_struct_args, legalize_args, ArgAction, ArgAssigner, ValueConversion,
+};
 use ir::{self, AbiParam, ArgumentExtension, ArgumentLoc, ArgumentPurpose, Type};
 use isa::RegClass;
 use regalloc::RegisterSet;
+use settings::CallConv;
 use std::i32;
 use target_lexicon::Triple;
 
@@ -21,10 +24,11 @@ struct Args {
     regs: u32,
     reg_limit: u32,
     offset: u32,
+    call_conv: CallConv,
 }
 
 impl Args {
-    fn new(bits: u8, enable_e: bool) -> Self {
+    fn new(bits: u8, enable_e: bool, call_conv: CallConv) -> Self {
         Self {
             pointer_bits: bits,
             pointer_bytes: bits / 8,
@@ -32,6 +36,7 @@ impl Args {
             regs: 0,
             reg_limit: if enable_e { 6 } else { 8 },
             offset: 0,
+            call_conv,
         }
     }
 }
@@ -50,7 +55,16 @@ impl ArgAssigner for Args {
             return ValueConversion::VectorSplit.into();
         }
 
-        // Large integers and booleans are broken down to fit in a register.
+        // Booleans are passed as integers, zero-extended to the size of a register.
+        if ty.is_bool() {
+            let mut int_ty = bool_abi_type(ty, self.call_conv);
+            if int_ty.bits() < self.pointer_type.bits() {
+                int_ty = self.pointer_type;
+            }
+            return ValueConversion::BoolToInt(int_ty).into();
+        }
+
+        // Large integers are broken down to fit in a register.
         if !ty.is_float() && ty.bits() > u16::from(self.pointer_bits) {
             // Align registers and stack to a multiple of two pointers.
             self.regs = align(self.regs, 2);
@@ -95,10 +109,13 @@ pub fn legalize_signature(
 ) {
     let bits = triple.pointer_width().unwrap().bits();
 
-    let mut args = Args::new(bits, isa_flags.enable_e());
+    let mut args = Args::new(bits, isa_flags.enable_e(), sig.call_conv);
+    // Struct arguments are passed by reference to a copy.
+    let word = args.pointer_type;
+    expand_struct_args(&mut sig.params, word, |_| true);
     legalize_args(&mut sig.params, &mut args);
 
-    let mut rets = Args::new(bits, isa_flags.enable_e());
+    let mut rets = Args::new(bits, isa_flags.enable_e(), sig.call_conv);
     legalize_args(&mut sig.returns, &mut rets);
 
     if current {
@@ -1,10 +1,11 @@
 //! Emitting binary RISC-V machine code.
 
-use binemit::{bad_encoding, CodeSink, Reloc};
-use ir::{Function, Inst, InstructionData};
+use binemit::{bad_encoding, branch_disp, CodeSink, Reloc};
+use ir::{Function, Inst, InstructionData, TrapCode, Type};
 use isa::{RegUnit, StackBaseMask, StackRef};
 use predicates::is_signed_int;
 use regalloc::RegDiversions;
+use result::CodegenResult;
 use std::u32;
 
 include!(concat!(env!("OUT_DIR"), "/binemit-riscv.rs"));
@@ -119,6 +120,107 @@ fn put_u<CS: CodeSink + ?Sized>(bits: u16, imm: i64, rd: RegUnit, sink: &mut CS)
     sink.put4(i);
 }
 
+// Encoding bits of the instructions used to materialize constants.
+const LUI: u16 = 0b01101;
+const ADDI: u16 = 0b00100;
+const SLLI: u16 = 0b00100 | (0b001 << 5);
+
+/// Compute the instructions materializing the constant `imm` of type `ty`.
+///
+/// Returns the encoding bits and the immediate of each instruction, with the number of
+/// instructions. The first instruction is a `lui` or an `addi` from `%x0`, and the remaining ones
+/// update the destination register. The `addi` following a `lui` uses the encoding `bits`.
+///
+/// A constant that doesn't fit in 32 bits is built from its high bits, shifted into place by an
+/// `slli`, followed by an `addi` of its low 12 bits. This takes at most 8 instructions.
+pub fn li_insts(bits: u16, imm: i64, ty: Type) -> ([(u16, i64); 8], usize) {
+    let imm = if ty.bits() == 64 {
+        imm
+    } else {
+        i64::from(imm as i32)
+    };
+    let mut insts = [(0, 0); 8];
+    let mut len = 0;
+    push_li(bits, imm, &mut insts, &mut len);
+    (insts, len)
+}
+
+fn push_li(bits: u16, imm: i64, insts: &mut [(u16, i64); 8], len: &mut usize) {
+    // The low 12 bits, sign-extended like the `addi` immediate.
+    let lo12 = (imm << 52) >> 52;
+
+    if is_signed_int(imm, 32, 0) {
+        // Round the upper 20 bits to compensate for the sign of `lo12`.
+        let hi20 = ((imm + 0x800) >> 12) & 0xfffff;
+        if hi20 == 0 {
+            insts[*len] = (ADDI, lo12);
+            *len += 1;
+        } else {
+            insts[*len] = (LUI, hi20 << 12);
+            *len += 1;
+            if lo12 != 0 {
+                insts[*len] = (bits, lo12);
+                *len += 1;
+            }
+        }
+        return;
+    }
+
+    // Strip the trailing zeros of the high bits to shift by as much as possible.
+    let hi52 = (imm as u64).wrapping_add(0x800) >> 12;
+    let shift = 12 + hi52.trailing_zeros();
+    let hi = ((hi52 >> (shift - 12)) << shift) as i64 >> shift;
+    push_li(bits, hi, insts, len);
+    insts[*len] = (SLLI, i64::from(shift));
+    *len += 1;
+    if lo12 != 0 {
+        insts[*len] = (ADDI, lo12);
+        *len += 1;
+    }
+}
+
+/// Materialize the constant `imm` of type `ty` in `rd`.
+///
+/// Encoding bits: `addiw` in RV64, `addi` in RV32.
+fn put_li<CS: CodeSink + ?Sized>(bits: u16, imm: i64, ty: Type, rd: RegUnit, sink: &mut CS) {
+    let (insts, len) = li_insts(bits, imm, ty);
+    for (i, &(bits, imm)) in insts[0..len].iter().enumerate() {
+        match bits {
+            LUI => put_u(bits, imm, rd, sink),
+            SLLI => put_rshamt(bits, rd, imm, rd, sink),
+            // Only the first instruction doesn't read `rd`.
+            _ => put_i(bits, if i == 0 { 0 } else { rd }, imm, rd, sink),
+        }
+    }
+}
+
+/// S-type store instructions.
+///
+///   31  24  19  14     11  6
+///   imm rs2 rs1 funct3 imm opcode
+///    25  20  15     12   7      0
+///
+/// Encoding bits: `opcode[6:2] | (funct3 << 5)`
+fn put_s<CS: CodeSink + ?Sized>(bits: u16, rs1: RegUnit, rs2: RegUnit, imm: i64, sink: &mut CS) {
+    let bits = u32::from(bits);
+    let opcode5 = bits & 0x1f;
+    let funct3 = (bits >> 5) & 0x7;
+    let rs1 = u32::from(rs1) & 0x1f;
+    let rs2 = u32::from(rs2) & 0x1f;
+    let imm = imm as u32;
+
+    // 0-6: opcode
+    let mut i = 0x3;
+    i |= opcode5 << 2;
+    i |= (imm & 0x1f) << 7;
+    i |= funct3 << 12;
+    i |= rs1 << 15;
+    i |= rs2 << 20;
+    i |= ((imm >> 5) & 0x7f) << 25;
+
+    sink.put4(i);
+}
+
 /// SB-type branch instructions.
 ///
 ///   31  24  19  14     11  6
@@ -180,3 +282,152 @@ fn put_uj<CS: CodeSink + ?Sized>(bits: u16, imm: i64, rd: RegUnit, sink: &mut CS
 
     sink.put4(i);
 }
+
+/// CI-type compressed instructions with a 6-bit immediate.
+///
+///   15     12  11 6   1
+///   funct3 imm rd imm op
+///       13  12  7  2  0
+///
+/// Encoding bits: the fixed bits of the instruction.
+fn put_ci<CS: CodeSink + ?Sized>(bits: u16, rd: RegUnit, imm: i64, sink: &mut CS) {
+    let rd = u16::from(rd) & 0x1f;
+    let imm = imm as u16;
+
+    let mut i = bits;
+    i |= (imm & 0x1f) << 2;
+    i |= rd << 7;
+    i |= ((imm >> 5) & 0x1) << 12;
+
+    sink.put2(i);
+}
+
+/// CR-type compressed instructions.
+///
+///   15     11 6   1
+///   funct4 rd rs2 op
+///       12  7   2  0
+///
+/// Encoding bits: the fixed bits of the instruction.
+fn put_cr<CS: CodeSink + ?Sized>(bits: u16, rd: RegUnit, rs2: RegUnit, sink: &mut CS) {
+    let rd = u16::from(rd) & 0x1f;
+    let rs2 = u16::from(rs2) & 0x1f;
+
+    let mut i = bits;
+    i |= rs2 << 2;
+    i |= rd << 7;
+
+    sink.put2(i);
+}
+
+/// CL-type compressed loads and CS-type compressed stores.
+///
+///   15     12  9   6   4   1
+///   funct3 imm rs1 imm rd  op
+///       13  10   7   5  2  0
+///
+/// The registers are `%x8`-`%x15`. The offset is scaled by the access size, 4 or 8 bytes.
+///
+/// Encoding bits: the fixed bits of the instruction.
+fn put_cls<CS: CodeSink + ?Sized>(bits: u16, rs1: RegUnit, rd: RegUnit, imm: i64, sink: &mut CS) {
+    let rs1 = u16::from(rs1) & 0x7;
+    let rd = u16::from(rd) & 0x7;
+    let imm = imm as u16;
+
+    let mut i = bits;
+    i |= rd << 2;
+    i |= rs1 << 7;
+    i |= ((imm >> 3) & 0x7) << 10;
+    // Bit 13 of `funct3` selects the doubleword instructions.
+    if bits & 0x2000 != 0 {
+        i |= ((imm >> 6) & 0x3) << 5;
+    } else {
+        i |= ((imm >> 2) & 0x1) << 6;
+        i |= ((imm >> 6) & 0x1) << 5;
+    }
+
+    sink.put2(i);
+}
+
+/// CJ-type compressed jump instructions.
+///
+///   15     12  1
+///   funct3 imm op
+///       13   2  0
+///
+/// Encoding bits: the fixed bits of the instruction.
+fn put_cj<CS: CodeSink + ?Sized>(bits: u16, imm: i64, sink: &mut CS) {
+    debug_assert!(is_signed_int(imm, 12, 1), "CJ out of range {:#x}", imm);
+    let imm = imm as u16;
+
+    // The displacement is completely hashed up.
+    let mut i = bits;
+    i |= ((imm >> 5) & 0x1) << 2;
+    i |= ((imm >> 1) & 0x7) << 3;
+    i |= ((imm >> 7) & 0x1) << 6;
+    i |= ((imm >> 6) & 0x1) << 7;
+    i |= ((imm >> 10) & 0x1) << 8;
+    i |= ((imm >> 8) & 0x3) << 9;
+    i |= ((imm >> 4) & 0x1) << 11;
+    i |= ((imm >> 11) & 0x1) << 12;
+
+    sink.put2(i);
+}
+
+/// CB-type compressed branch instructions.
+///
+///   15     12  9   6   1
+///   funct3 imm rs1 imm op
+///       13  10   7   2  0
+///
+/// The register is `%x8`-`%x15`.
+///
+/// Encoding bits: the fixed bits of the instruction.
+fn put_cb<CS: CodeSink + ?Sized>(bits: u16, imm: i64, rs1: RegUnit, sink: &mut CS) {
+    debug_assert!(is_signed_int(imm, 9, 1), "CB out of range {:#x}", imm);
+    let rs1 = u16::from(rs1) & 0x7;
+    let imm = imm as u16;
+
+    // The displacement is completely hashed up.
+    let mut i = bits;
+    i |= ((imm >> 5) & 0x1) << 2;
+    i |= ((imm >> 1) & 0x3) << 3;
+    i |= ((imm >> 6) & 0x3) << 5;
+    i |= rs1 << 7;
+    i |= ((imm >> 3) & 0x3) << 10;
+    i |= ((imm >> 8) & 0x1) << 12;
+
+    sink.put2(i);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ir::types;
+
+    const ADDIW: u16 = 0b00110;
+
+    #[test]
+    fn li() {
+        // addi x0, 1000
+        let (insts, len) = li_insts(ADDIW, 1000, types::I64);
+        assert_eq!(&insts[0..len], &[(ADDI, 1000)]);
+        // lui 0x12345; addiw 0x678
+        let (insts, len) = li_insts(ADDIW, 0x1234_5678, types::I64);
+        assert_eq!(&insts[0..len], &[(LUI, 0x1234_5000), (ADDIW, 0x678)]);
+        // The `lui` is rounded up for a negative low part: lui 0x80000; addiw -1
+        let (insts, len) = li_insts(ADDIW, 0x7fff_ffff, types::I64);
+        assert_eq!(&insts[0..len], &[(LUI, 0x8000_0000), (ADDIW, -1)]);
+        // The high bits of a 32-bit constant are ignored.
+        let (insts, len) = li_insts(ADDI, 0xffff_f800, types::I32);
+        assert_eq!(&insts[0..len], &[(ADDI, -0x800)]);
+        // addi x0, -1; slli 63
+        let (insts, len) = li_insts(ADDIW, -1 << 63, types::I64);
+        assert_eq!(&insts[0..len], &[(ADDI, -1), (SLLI, 63)]);
+        // The worst case needs 8 instructions.
+        assert_eq!(
+            li_insts(ADDIW, 0xdead_beef_dead_beef_u64 as i64, types::I64).1,
+            8
+        );
+    }
+}
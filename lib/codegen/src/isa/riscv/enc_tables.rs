@@ -1,11 +1,16 @@
 //! Encoding tables for RISC-V.
 
+use super::binemit::li_insts;
 use super::registers::*;
-use ir;
+use bitset::BitSet;
+use cursor::{Cursor, FuncCursor};
+use flowgraph::ControlFlowGraph;
+use ir::{self, Function, Inst, InstBuilder, InstructionData};
 use isa;
 use isa::constraints::*;
 use isa::enc_tables::*;
 use isa::encoding::{base_size, RecipeSizing};
+use regalloc::RegDiversions;
 
 // Include the generated encoding tables:
 // - `LEVEL1_RV32`
@@ -15,3 +20,94 @@ use isa::encoding::{base_size, RecipeSizing};
 // - `INFO`
 include!(concat!(env!("OUT_DIR"), "/encoding-riscv.rs"));
 include!(concat!(env!("OUT_DIR"), "/legalize-riscv.rs"));
+
+/// Compute the size of an `iconst` materialized by a sequence of instructions.
+fn size_li(_sizing: &RecipeSizing, inst: Inst, _divert: &RegDiversions, func: &Function) -> u8 {
+    match func.dfg[inst] {
+        InstructionData::UnaryImm { imm, .. } => {
+            let bits = func.encodings[inst].bits();
+            let ty = func.dfg.ctrl_typevar(inst);
+            4 * li_insts(bits, imm.into(), ty).1 as u8
+        }
+        _ => panic!("Expected iconst: {}", func.dfg.display_inst(inst, None)),
+    }
+}
+
+/// Expand the `sdiv` and `srem` instructions using `riscv_div` and `riscv_rem`.
+fn expand_sdivrem(
+    inst: ir::Inst,
+    func: &mut ir::Function,
+    _cfg: &mut ControlFlowGraph,
+    _isa: &isa::TargetIsa,
+) {
+    let (x, y, is_srem) = match func.dfg[inst] {
+        ir::InstructionData::Binary {
+            opcode: ir::Opcode::Sdiv,
+            args,
+        } => (args[0], args[1], false),
+        ir::InstructionData::Binary {
+            opcode: ir::Opcode::Srem,
+            args,
+        } => (args[0], args[1], true),
+        _ => panic!("Need sdiv/srem: {}", func.dfg.display_inst(inst, None)),
+    };
+    let result = func.dfg.first_result(inst);
+    let ty = func.dfg.value_type(result);
+
+    let mut pos = FuncCursor::new(func).at_inst(inst);
+    pos.use_srcloc(inst);
+    pos.func.dfg.clear_results(inst);
+
+    // The RISC-V division instructions don't trap, so check the divisor explicitly.
+    pos.ins().trapz(y, ir::TrapCode::IntegerDivisionByZero, 0);
+
+    if is_srem {
+        // The `rem` instruction already computes `INT_MIN % -1 = 0`.
+        pos.ins().with_result(result).riscv_rem(x, y);
+    } else {
+        // Trap when `x == INT_MIN` and `y == -1`, which is exactly when both `x ^ INT_MIN` and
+        // `y + 1` are zero.
+        let min = pos.ins().iconst(ty, -1 << (ty.lane_bits() - 1));
+        let xdiff = pos.ins().bxor(x, min);
+        let ydiff = pos.ins().iadd_imm(y, 1);
+        let ovf = pos.ins().bor(xdiff, ydiff);
+        pos.ins().trapz(ovf, ir::TrapCode::IntegerOverflow, 0);
+        pos.ins().with_result(result).riscv_div(x, y);
+    }
+    pos.remove_inst();
+}
+
+/// Expand the `udiv` and `urem` instructions using `riscv_divu` and `riscv_remu`.
+fn expand_udivrem(
+    inst: ir::Inst,
+    func: &mut ir::Function,
+    _cfg: &mut ControlFlowGraph,
+    _isa: &isa::TargetIsa,
+) {
+    let (x, y, is_urem) = match func.dfg[inst] {
+        ir::InstructionData::Binary {
+            opcode: ir::Opcode::Udiv,
+            args,
+        } => (args[0], args[1], false),
+        ir::InstructionData::Binary {
+            opcode: ir::Opcode::Urem,
+            args,
+        } => (args[0], args[1], true),
+        _ => panic!("Need udiv/urem: {}", func.dfg.display_inst(inst, None)),
+    };
+    let result = func.dfg.first_result(inst);
+
+    let mut pos = FuncCursor::new(func).at_inst(inst);
+    pos.use_srcloc(inst);
+    pos.func.dfg.clear_results(inst);
+
+    // The RISC-V division instructions don't trap, so check the divisor explicitly.
+    pos.ins().trapz(y, ir::TrapCode::IntegerDivisionByZero, 0);
+
+    if is_urem {
+        pos.ins().with_result(result).riscv_remu(x, y);
+    } else {
+        pos.ins().with_result(result).riscv_divu(x, y);
+    }
+    pos.remove_inst();
+}
@@ -12,9 +12,11 @@ use binemit::CodeSink;
 use binemit::{emit_function, MemoryCodeSink};
 use ir;
 use isa::enc_tables::{self as shared_enc_tables, lookup_enclist, Encodings};
+use isa::support::inst_support;
 use isa::Builder as IsaBuilder;
-use isa::{EncInfo, RegClass, RegInfo, TargetIsa};
+use isa::{EncInfo, InstSupport, Latencies, RegClass, RegInfo, TargetIsa};
 use regalloc;
+use result::CodegenResult;
 use std::boxed::Box;
 use std::fmt;
 use target_lexicon::{PointerWidth, Triple};
@@ -24,14 +26,25 @@ struct Isa {
     triple: Triple,
     shared_flags: shared_settings::Flags,
     isa_flags: settings::Flags,
+    libcall_names: ir::LibcallNames,
     cpumode: &'static [shared_enc_tables::Level1Entry<u16>],
 }
 
+/// Instruction latencies of a typical in-order RISC-V core.
+static LATENCIES: Latencies = Latencies {
+    load: 3,
+    imul: 3,
+    idiv: 20,
+    float: 5,
+    other: 1,
+};
+
 /// Get an ISA builder for creating RISC-V targets.
 pub fn isa_builder(triple: Triple) -> IsaBuilder {
     IsaBuilder {
         triple,
         setup: settings::builder(),
+        libcall_names: ir::LibcallNames::new(),
         constructor: isa_constructor,
     }
 }
@@ -40,6 +53,7 @@ fn isa_constructor(
     triple: Triple,
     shared_flags: shared_settings::Flags,
     builder: shared_settings::Builder,
+    libcall_names: ir::LibcallNames,
 ) -> Box<TargetIsa> {
     let level1 = match triple.pointer_width().unwrap() {
         PointerWidth::U16 => panic!("16-bit RISC-V unrecognized"),
@@ -50,6 +64,7 @@ fn isa_constructor(
         triple,
         isa_flags: settings::Flags::new(&shared_flags, builder),
         shared_flags,
+        libcall_names,
         cpumode: level1,
     })
 }
@@ -67,14 +82,26 @@ impl TargetIsa for Isa {
         &self.shared_flags
     }
 
+    fn libcall_names(&self) -> &ir::LibcallNames {
+        &self.libcall_names
+    }
+
     fn register_info(&self) -> RegInfo {
         registers::INFO.clone()
     }
 
+    fn supports(&self, opcode: ir::Opcode, ctrl_type: ir::Type) -> InstSupport {
+        inst_support(self, opcode, ctrl_type)
+    }
+
     fn encoding_info(&self) -> EncInfo {
         enc_tables::INFO.clone()
     }
 
+    fn latencies(&self) -> Option<&Latencies> {
+        Some(&LATENCIES)
+    }
+
     fn legal_encodings<'a>(
         &'a self,
         func: &'a ir::Function,
@@ -114,12 +141,16 @@ impl TargetIsa for Isa {
         inst: ir::Inst,
         divert: &mut regalloc::RegDiversions,
         sink: &mut CodeSink,
-    ) {
+    ) -> CodegenResult<()> {
         binemit::emit_inst(func, inst, divert, sink)
     }
 
-    fn emit_function_to_memory(&self, func: &ir::Function, sink: &mut MemoryCodeSink) {
-        emit_function(func, binemit::emit_inst, sink)
+    fn emit_function_to_memory(
+        &self,
+        func: &ir::Function,
+        sink: &mut MemoryCodeSink,
+    ) -> CodegenResult<()> {
+        emit_function(func, binemit::emit_inst, sink, self.endianness())
     }
 }
 
@@ -0,0 +1,319 @@
+//! Queries about how an ISA supports instructions.
+//!
+//! A frontend can often express an operation in more than one way, like a saturating float
+//! conversion that is either a single `fcvt_to_sint_sat` or a call to a runtime helper with the
+//! semantics it needs. The best choice depends on what the ISA does with the instruction, which is
+//! found here by legalizing a small function containing the instruction.
+
+use cursor::{Cursor, FuncCursor};
+use flowgraph::ControlFlowGraph;
+use ir::condcodes::{FloatCC, IntCC};
+use ir::immediates::{Ieee32, Ieee64};
+use ir::instructions::{InstructionFormat, ResolvedConstraint};
+use ir::{Function, InstBuilder, InstructionData, MemFlags, Opcode, Type, Value};
+use isa::TargetIsa;
+use legalizer::legalize_insts;
+use std::vec::Vec;
+
+/// How an ISA compiles an instruction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InstSupport {
+    /// The instruction has an encoding, so it is compiled to a machine instruction.
+    Native,
+
+    /// The instruction is legalized into a sequence of other instructions with encodings.
+    Legalized,
+
+    /// The instruction is legalized into a call to a runtime library routine.
+    Libcall,
+
+    /// The instruction can't be compiled.
+    Unsupported,
+}
+
+/// Determine how `isa` compiles an instruction with `opcode` and controlling type `ctrl_type`.
+///
+/// The `ctrl_type` is ignored for instructions that aren't polymorphic. Value operands whose type
+/// isn't determined by `ctrl_type` get an example type from their type set, and immediate
+/// operands are small constants.
+///
+/// Only instructions whose operands are values, immediates, condition codes, and memory flags can
+/// be queried. The other instructions, like branches, calls, and heap accesses, are reported as
+/// `Unsupported`.
+pub fn inst_support(isa: &TargetIsa, opcode: Opcode, ctrl_type: Type) -> InstSupport {
+    let constraints = opcode.constraints();
+    if let Some(typeset) = constraints.ctrl_typeset() {
+        if !typeset.contains(ctrl_type) {
+            return InstSupport::Unsupported;
+        }
+    }
+
+    let mut func = Function::new();
+    let ebb = func.dfg.make_ebb();
+    func.layout.append_ebb(ebb);
+    let args: Vec<Value> = (0..constraints.fixed_value_arguments())
+        .map(|n| {
+            let ty = match constraints.value_argument_constraint(n, ctrl_type) {
+                ResolvedConstraint::Bound(ty) => ty,
+                ResolvedConstraint::Free(typeset) => typeset.example(),
+            };
+            func.dfg.append_ebb_param(ebb, ty)
+        })
+        .collect();
+
+    let data = match make_inst_data(opcode, &args) {
+        Some(data) => data,
+        None => return InstSupport::Unsupported,
+    };
+    let inst = func.dfg.make_inst(data);
+    func.dfg.make_inst_results(inst, ctrl_type);
+    func.layout.append_inst(inst, ebb);
+    FuncCursor::new(&mut func).at_bottom(ebb).ins().return_(&[]);
+
+    if isa.encode(&func, &func.dfg[inst], ctrl_type).is_ok() {
+        return InstSupport::Native;
+    }
+
+    let mut cfg = ControlFlowGraph::with_function(&func);
+    legalize_insts(&mut func, &mut cfg, isa);
+
+    let mut support = InstSupport::Legalized;
+    for ebb in func.layout.ebbs() {
+        for inst in func.layout.ebb_insts(ebb) {
+            if !func.encodings[inst].is_legal() {
+                return InstSupport::Unsupported;
+            }
+            if func.dfg[inst].opcode().is_call() {
+                support = InstSupport::Libcall;
+            }
+        }
+    }
+    support
+}
+
+/// Make the data of an instruction with `opcode` and the value operands `args`, if its format
+/// has no other operands than values, immediates, condition codes, and memory flags.
+fn make_inst_data(opcode: Opcode, args: &[Value]) -> Option<InstructionData> {
+    Some(match opcode.format() {
+        InstructionFormat::NullAry => InstructionData::NullAry { opcode },
+        InstructionFormat::Unary => InstructionData::Unary {
+            opcode,
+            arg: args[0],
+        },
+        InstructionFormat::UnaryImm => InstructionData::UnaryImm {
+            opcode,
+            imm: 1.into(),
+        },
+        InstructionFormat::UnaryIeee32 => InstructionData::UnaryIeee32 {
+            opcode,
+            imm: Ieee32::with_float(1.0),
+        },
+        InstructionFormat::UnaryIeee64 => InstructionData::UnaryIeee64 {
+            opcode,
+            imm: Ieee64::with_float(1.0),
+        },
+        InstructionFormat::UnaryBool => InstructionData::UnaryBool { opcode, imm: true },
+        InstructionFormat::Binary => InstructionData::Binary {
+            opcode,
+            args: [args[0], args[1]],
+        },
+        InstructionFormat::BinaryImm => InstructionData::BinaryImm {
+            opcode,
+            arg: args[0],
+            imm: 1.into(),
+        },
+        InstructionFormat::Ternary => InstructionData::Ternary {
+            opcode,
+            args: [args[0], args[1], args[2]],
+        },
+        InstructionFormat::InsertLane => InstructionData::InsertLane {
+            opcode,
+            args: [args[0], args[1]],
+            lane: 0,
+        },
+        InstructionFormat::ExtractLane => InstructionData::ExtractLane {
+            opcode,
+            arg: args[0],
+            lane: 0,
+        },
+        InstructionFormat::IntCompare => InstructionData::IntCompare {
+            opcode,
+            args: [args[0], args[1]],
+            cond: IntCC::Equal,
+        },
+        InstructionFormat::IntCompareImm => InstructionData::IntCompareImm {
+            opcode,
+            arg: args[0],
+            cond: IntCC::Equal,
+            imm: 1.into(),
+        },
+        InstructionFormat::IntCond => InstructionData::IntCond {
+            opcode,
+            arg: args[0],
+            cond: IntCC::Equal,
+        },
+        InstructionFormat::FloatCompare => InstructionData::FloatCompare {
+            opcode,
+            args: [args[0], args[1]],
+            cond: FloatCC::Equal,
+        },
+        InstructionFormat::FloatCond => InstructionData::FloatCond {
+            opcode,
+            arg: args[0],
+            cond: FloatCC::Equal,
+        },
+        InstructionFormat::IntSelect => InstructionData::IntSelect {
+            opcode,
+            args: [args[0], args[1], args[2]],
+            cond: IntCC::Equal,
+        },
+        InstructionFormat::Load => InstructionData::Load {
+            opcode,
+            arg: args[0],
+            flags: MemFlags::new(),
+            offset: 0.into(),
+        },
+        InstructionFormat::Store => InstructionData::Store {
+            opcode,
+            args: [args[0], args[1]],
+            flags: MemFlags::new(),
+            offset: 0.into(),
+        },
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{make_inst_data, InstSupport};
+    use context::Context;
+    use cursor::{Cursor, FuncCursor};
+    use ir::instructions::ResolvedConstraint;
+    use ir::types::{F64, I32, I64, I8};
+    use ir::{AbiParam, Function, InstBuilder, Opcode, Type, Value};
+    use isa::{self, TargetIsa};
+    use settings::{self, Configurable};
+    use std::boxed::Box;
+    use std::str::FromStr;
+    use std::vec::Vec;
+    use target_lexicon::Triple;
+
+    /// Make an ISA for `triple` with the ISA-specific settings `enabled`.
+    fn make_isa(triple: &str, enabled: &[&str]) -> Box<TargetIsa> {
+        let mut builder = isa::lookup(Triple::from_str(triple).unwrap()).unwrap();
+        for name in enabled {
+            builder.enable(name).unwrap();
+        }
+        builder.finish(settings::Flags::new(settings::builder()))
+    }
+
+    /// Compile a function that passes its parameters to an instruction with `opcode` and returns
+    /// its results, and find out how the instruction was compiled.
+    fn compiled_support(isa: &TargetIsa, opcode: Opcode, ctrl_type: Type) -> InstSupport {
+        let constraints = opcode.constraints();
+        let mut func = Function::new();
+        let ebb = func.dfg.make_ebb();
+        func.layout.append_ebb(ebb);
+        let args: Vec<Value> = (0..constraints.fixed_value_arguments())
+            .map(|n| {
+                let ty = match constraints.value_argument_constraint(n, ctrl_type) {
+                    ResolvedConstraint::Bound(ty) => ty,
+                    ResolvedConstraint::Free(typeset) => typeset.example(),
+                };
+                func.signature.params.push(AbiParam::new(ty));
+                func.dfg.append_ebb_param(ebb, ty)
+            })
+            .collect();
+        let inst = func.dfg.make_inst(make_inst_data(opcode, &args).unwrap());
+        func.dfg.make_inst_results(inst, ctrl_type);
+        func.layout.append_inst(inst, ebb);
+        let results = func.dfg.inst_results(inst).to_vec();
+        for &result in &results {
+            let ty = func.dfg.value_type(result);
+            func.signature.returns.push(AbiParam::new(ty));
+        }
+        FuncCursor::new(&mut func).at_bottom(ebb).ins().return_(&results);
+
+        let mut ctx = Context::for_function(func);
+        if ctx.compile(isa).is_err() {
+            return InstSupport::Unsupported;
+        }
+        let func = &ctx.func;
+        let insts: Vec<_> = func
+            .layout
+            .ebbs()
+            .flat_map(|ebb| func.layout.ebb_insts(ebb))
+            .collect();
+        if insts.iter().any(|&inst| {
+            func.dfg[inst].opcode() == opcode && func.dfg.ctrl_typevar(inst) == ctrl_type
+        }) {
+            InstSupport::Native
+        } else if insts.iter().any(|&inst| func.dfg[inst].opcode().is_call()) {
+            InstSupport::Libcall
+        } else {
+            InstSupport::Legalized
+        }
+    }
+
+    /// Check that `isa` reports the `expected` support for each of `insts`, and that it matches
+    /// the way the instruction is compiled.
+    fn check(isa: &TargetIsa, insts: &[(Opcode, Type, InstSupport)]) {
+        for &(opcode, ctrl_type, expected) in insts {
+            let support = isa.supports(opcode, ctrl_type);
+            assert_eq!(
+                (opcode, ctrl_type, support),
+                (opcode, ctrl_type, expected),
+                "{} reports the wrong support",
+                isa
+            );
+            assert_eq!(
+                (opcode, ctrl_type, compiled_support(isa, opcode, ctrl_type)),
+                (opcode, ctrl_type, expected),
+                "{} compiles differently",
+                isa
+            );
+        }
+    }
+
+    #[test]
+    #[cfg(build_x86)]
+    fn x86_64() {
+        check(
+            &*make_isa("x86_64", &[]),
+            &[
+                (Opcode::Iadd, I64, InstSupport::Native),
+                (Opcode::Clz, I8, InstSupport::Legalized),
+                (Opcode::Popcnt, I64, InstSupport::Legalized),
+                (Opcode::FcvtToUintSat, I64, InstSupport::Legalized),
+                (Opcode::FcvtToSintSat, I32, InstSupport::Legalized),
+                (Opcode::Ceil, F64, InstSupport::Libcall),
+            ],
+        );
+        check(
+            &*make_isa("x86_64", &["has_sse41", "has_sse42", "has_popcnt"]),
+            &[
+                (Opcode::Popcnt, I64, InstSupport::Native),
+                (Opcode::Popcnt, I32, InstSupport::Native),
+                (Opcode::Ceil, F64, InstSupport::Native),
+            ],
+        );
+    }
+
+    #[test]
+    #[cfg(build_riscv)]
+    fn riscv64() {
+        check(
+            &*make_isa("riscv64", &[]),
+            &[
+                (Opcode::Iadd, I64, InstSupport::Native),
+                (Opcode::Bnot, I64, InstSupport::Legalized),
+                (Opcode::Imul, I64, InstSupport::Unsupported),
+                (Opcode::Popcnt, I64, InstSupport::Unsupported),
+            ],
+        );
+        check(
+            &*make_isa("riscv64", &["supports_m"]),
+            &[(Opcode::Imul, I64, InstSupport::Native)],
+        );
+    }
+}
@@ -1,7 +1,10 @@
 //! x86 ABI implementation.
 
 use super::registers::{FPR, GPR, RU};
-use abi::{legalize_args, ArgAction, ArgAssigner, ValueConversion};
+use abi::{
+    bool_abi_type, expand_struct_args, legalize_args, struct_words, ArgAction, ArgAssigner,
+    ValueConversion,
+};
 use cursor::{Cursor, CursorPosition, EncCursor};
 use ir;
 use ir::immediates::Imm64;
@@ -13,9 +16,10 @@ use ir::{
 use isa::{RegClass, RegUnit, TargetIsa};
 use regalloc::RegisterSet;
 use result::CodegenResult;
-use settings::CallConv;
+use settings::{CallConv, CodeModel};
 use stack_layout::layout_stack;
 use std::i32;
+use std::vec::Vec;
 use target_lexicon::{PointerWidth, Triple};
 
 /// Argument registers for x86-64
@@ -30,6 +34,10 @@ static ARG_GPRS_WIN_FASTCALL_X64: [RU; 4] = [RU::rcx, RU::rdx, RU::r8, RU::r9];
 /// Return value registers for x86-64, when using windows fastcall
 static RET_GPRS_WIN_FASTCALL_X64: [RU; 1] = [RU::rax];
 
+/// The size of the shadow store that the caller of a windows fastcall function allocates for the
+/// callee's register parameters.
+const SHADOW_STORE_SIZE: i32 = 32;
+
 struct Args {
     pointer_bytes: u8,
     pointer_bits: u8,
@@ -38,6 +46,13 @@ struct Args {
     gpr_used: usize,
     fpr_limit: usize,
     fpr_used: usize,
+    /// Assign registers by argument position, so an argument passed in an FPR also uses up the
+    /// GPR for its position, and vice versa. This is how Windows fastcall passes parameters.
+    by_position: bool,
+    /// Number of parts of the current struct argument that haven't been assigned yet.
+    struct_parts_left: usize,
+    /// Are the parts of the current struct argument passed in registers?
+    struct_in_regs: bool,
     offset: u32,
     call_conv: CallConv,
 }
@@ -47,7 +62,7 @@ impl Args {
         let offset = if let CallConv::WindowsFastcall = call_conv {
             // [1] "The caller is responsible for allocating space for parameters to the callee,
             // and must always allocate sufficient space to store four register parameters"
-            32
+            SHADOW_STORE_SIZE as u32
         } else {
             0
         };
@@ -60,10 +75,55 @@ impl Args {
             gpr_used: 0,
             fpr_limit,
             fpr_used: 0,
+            by_position: false,
+            struct_parts_left: 0,
+            struct_in_regs: false,
             offset,
             call_conv,
         }
     }
+
+    /// Take the next GPR.
+    fn next_gpr(&mut self) -> ArgumentLoc {
+        let reg = self.gpr[self.gpr_used] as RegUnit;
+        self.gpr_used += 1;
+        if self.by_position {
+            self.fpr_used += 1;
+        }
+        ArgumentLoc::Reg(reg)
+    }
+
+    /// Assign a stack location to an argument of type `ty`.
+    fn next_stack(&mut self, ty: ir::Type) -> ArgumentLoc {
+        // Stack arguments occupy a whole number of pointer-sized words, so an `f64` takes up two
+        // words on 32-bit x86.
+        let loc = ArgumentLoc::Stack(self.offset as i32);
+        let word = u32::from(self.pointer_bytes);
+        self.offset += (ty.bytes() + word - 1) / word * word;
+        debug_assert!(self.offset <= i32::MAX as u32);
+        loc
+    }
+
+    /// Assign a location to the next part of a struct argument of `size` bytes.
+    ///
+    /// A struct of up to two words is passed in registers if there are enough of them left for
+    /// all of its parts. Otherwise the whole struct is copied to the stack.
+    fn assign_struct_part(&mut self, size: u32) -> ArgumentLoc {
+        if self.struct_parts_left == 0 {
+            let words = struct_words(size, self.pointer_type);
+            self.struct_parts_left = words;
+            self.struct_in_regs = size <= 2 * u32::from(self.pointer_bytes)
+                && self.gpr_used + words <= self.gpr.len();
+        }
+        self.struct_parts_left -= 1;
+
+        if self.struct_in_regs {
+            self.next_gpr()
+        } else {
+            let ty = self.pointer_type;
+            self.next_stack(ty)
+        }
+    }
 }
 
 impl ArgAssigner for Args {
@@ -76,7 +136,12 @@ impl ArgAssigner for Args {
             return ValueConversion::VectorSplit.into();
         }
 
-        // Large integers and booleans are broken down to fit in a register.
+        // Booleans are passed as integers.
+        if ty.is_bool() {
+            return ValueConversion::BoolToInt(bool_abi_type(ty, self.call_conv)).into();
+        }
+
+        // Large integers are broken down to fit in a register.
         if !ty.is_float() && ty.bits() > u16::from(self.pointer_bits) {
             return ValueConversion::IntSplit.into();
         }
@@ -99,7 +164,8 @@ impl ArgAssigner for Args {
                         RU::r14
                     } else {
                         RU::rsi
-                    } as RegUnit).into()
+                    } as RegUnit)
+                    .into()
                 }
                 // This is SpiderMonkey's `WasmTableCallSigReg`.
                 ArgumentPurpose::SignatureId => return ArgumentLoc::Reg(RU::r10 as RegUnit).into(),
@@ -107,25 +173,30 @@ impl ArgAssigner for Args {
             }
         }
 
+        // The parts of a struct argument are kept together.
+        if let ArgumentPurpose::StructArgument(size) = arg.purpose {
+            if !arg.legalized_to_pointer {
+                return self.assign_struct_part(size).into();
+            }
+        }
+
         // Try to use a GPR.
         if !ty.is_float() && self.gpr_used < self.gpr.len() {
-            let reg = self.gpr[self.gpr_used] as RegUnit;
-            self.gpr_used += 1;
-            return ArgumentLoc::Reg(reg).into();
+            return self.next_gpr().into();
         }
 
         // Try to use an FPR.
         if ty.is_float() && self.fpr_used < self.fpr_limit {
             let reg = FPR.unit(self.fpr_used);
             self.fpr_used += 1;
+            if self.by_position {
+                self.gpr_used += 1;
+            }
             return ArgumentLoc::Reg(reg).into();
         }
 
         // Assign a stack location.
-        let loc = ArgumentLoc::Stack(self.offset as i32);
-        self.offset += u32::from(self.pointer_bytes);
-        debug_assert!(self.offset <= i32::MAX as u32);
-        loc.into()
+        self.next_stack(ty).into()
     }
 }
 
@@ -143,13 +214,22 @@ pub fn legalize_signature(sig: &mut ir::Signature, triple: &Triple, _current: bo
         PointerWidth::U64 => {
             bits = 64;
             args = if sig.call_conv == CallConv::WindowsFastcall {
-                Args::new(bits, &ARG_GPRS_WIN_FASTCALL_X64[..], 4, sig.call_conv)
+                let mut args = Args::new(bits, &ARG_GPRS_WIN_FASTCALL_X64[..], 4, sig.call_conv);
+                args.by_position = true;
+                args
             } else {
                 Args::new(bits, &ARG_GPRS[..], 8, sig.call_conv)
             };
         }
     }
 
+    // Windows fastcall passes structs that don't have the size of an integer by reference. The
+    // other calling conventions pass all structs by value.
+    let fastcall = sig.call_conv == CallConv::WindowsFastcall;
+    let word = args.pointer_type;
+    expand_struct_args(&mut sig.params, word, |size| {
+        fastcall && !size_is_integer(size)
+    });
     legalize_args(&mut sig.params, &mut args);
 
     let regs = if sig.call_conv == CallConv::WindowsFastcall {
@@ -162,6 +242,14 @@ pub fn legalize_signature(sig: &mut ir::Signature, triple: &Triple, _current: bo
     legalize_args(&mut sig.returns, &mut rets);
 }
 
+/// Does a struct of `size` bytes have the size of an integer type?
+fn size_is_integer(size: u32) -> bool {
+    match size {
+        1 | 2 | 4 | 8 => true,
+        _ => false,
+    }
+}
+
 /// Get register class for a type appearing in a legalized signature.
 pub fn regclass_for_abi_type(ty: ir::Type) -> RegClass {
     if ty.is_int() || ty.is_bool() {
@@ -188,13 +276,13 @@ pub fn allocatable_registers(_func: &ir::Function, triple: &Triple) -> RegisterS
     regs
 }
 
-/// Get the set of callee-saved registers.
-fn callee_saved_gprs(isa: &TargetIsa) -> &'static [RU] {
+/// Get the set of callee-saved registers of a function with the `call_conv` calling convention.
+fn callee_saved_gprs(isa: &TargetIsa, call_conv: CallConv) -> &'static [RU] {
     match isa.triple().pointer_width().unwrap() {
         PointerWidth::U16 => panic!(),
         PointerWidth::U32 => &[RU::rbx, RU::rsi, RU::rdi],
         PointerWidth::U64 => {
-            if isa.flags().call_conv() == CallConv::WindowsFastcall {
+            if call_conv == CallConv::WindowsFastcall {
                 // "registers RBX, RBP, RDI, RSI, RSP, R12, R13, R14, R15 are considered nonvolatile
                 //  and must be saved and restored by a function that uses them."
                 // as per https://msdn.microsoft.com/en-us/library/6t169e9c.aspx
@@ -216,12 +304,62 @@ fn callee_saved_gprs(isa: &TargetIsa) -> &'static [RU] {
     }
 }
 
+/// Get the set of registers that a function with the `call_conv` calling convention preserves
+/// for its callers.
+///
+/// The code generated for a baldrdash function doesn't save any registers, since SpiderMonkey
+/// treats all registers as clobbered by calls.
+fn preserved_gprs(isa: &TargetIsa, call_conv: CallConv) -> &'static [RU] {
+    match call_conv {
+        CallConv::Baldrdash => &[],
+        _ => callee_saved_gprs(isa, call_conv),
+    }
+}
+
+/// Does the ABI implementation support a `caller` or callee with the `call_conv` calling
+/// convention on `triple`?
+///
+/// A probestack function can only be called, since there is no prologue for it.
+pub fn supports_call_conv(triple: &Triple, call_conv: CallConv, caller: bool) -> bool {
+    match call_conv {
+        CallConv::WindowsFastcall => triple.pointer_width().unwrap() == PointerWidth::U64,
+        CallConv::Probestack => !caller,
+        CallConv::Fast
+        | CallConv::Cold
+        | CallConv::SystemV
+        | CallConv::Raw
+        | CallConv::Baldrdash => true,
+    }
+}
+
+/// Get the calling conventions of the functions that `func` calls.
+fn callee_call_convs(func: &ir::Function) -> Vec<CallConv> {
+    let mut call_convs = Vec::new();
+    for ebb in &func.layout {
+        for inst in func.layout.ebb_insts(ebb) {
+            if let Some(sig) = func.dfg.call_signature(inst) {
+                let call_conv = func.dfg.signatures[sig].call_conv;
+                if !call_convs.contains(&call_conv) {
+                    call_convs.push(call_conv);
+                }
+            }
+        }
+    }
+    call_convs
+}
+
+/// Get the set of callee-saved registers of `func` that are allocatable.
+pub fn callee_saved_registers(isa: &TargetIsa, func: &ir::Function) -> RegisterSet {
+    let mut regs = RegisterSet::empty();
+    for reg in callee_saved_gprs(isa, func.signature.call_conv) {
+        regs.free(GPR, *reg as RegUnit);
+    }
+    regs
+}
+
 /// Get the set of callee-saved registers that are used.
 fn callee_saved_gprs_used(isa: &TargetIsa, func: &ir::Function) -> RegisterSet {
-    let mut all_callee_saved = RegisterSet::empty();
-    for reg in callee_saved_gprs(isa) {
-        all_callee_saved.free(GPR, *reg as RegUnit);
-    }
+    let all_callee_saved = callee_saved_registers(isa, func);
 
     let mut used = RegisterSet::empty();
     for value_loc in func.locations.values() {
@@ -229,7 +367,7 @@ fn callee_saved_gprs_used(isa: &TargetIsa, func: &ir::Function) -> RegisterSet {
         // register. We don't use registers that overlap each other in the x86 ISA, but in others
         // we do. So this should not be blindly reused.
         if let ValueLoc::Reg(ru) = *value_loc {
-            if !used.is_avail(GPR, ru) {
+            if GPR.contains(ru) && !used.is_avail(GPR, ru) {
                 used.free(GPR, ru);
             }
         }
@@ -246,7 +384,7 @@ fn callee_saved_gprs_used(isa: &TargetIsa, func: &ir::Function) -> RegisterSet {
             match func.dfg[inst] {
                 ir::instructions::InstructionData::RegMove { dst, .. }
                 | ir::instructions::InstructionData::RegFill { dst, .. } => {
-                    if !used.is_avail(GPR, dst) {
+                    if GPR.contains(dst) && !used.is_avail(GPR, dst) {
                         used.free(GPR, dst);
                     }
                 }
@@ -255,14 +393,40 @@ fn callee_saved_gprs_used(isa: &TargetIsa, func: &ir::Function) -> RegisterSet {
         }
     }
 
+    // A callee with another calling convention may clobber registers that the convention of
+    // `func` preserves. Save them as if `func` used them itself.
+    for call_conv in callee_call_convs(func) {
+        let preserved = preserved_gprs(isa, call_conv);
+        for &reg in callee_saved_gprs(isa, func.signature.call_conv) {
+            let ru = reg as RegUnit;
+            if !preserved.iter().any(|&p| p as RegUnit == ru) && !used.is_avail(GPR, ru) {
+                used.free(GPR, ru);
+            }
+        }
+    }
+
     used.intersect(&all_callee_saved);
     used
 }
 
+/// Reserve the shadow store of the windows fastcall functions that `func` calls.
+///
+/// The stack arguments of a windows fastcall callee start above its shadow store, so the outgoing
+/// arguments only cover the shadow store when some call passes arguments on the stack.
+fn reserve_shadow_store(func: &mut ir::Function) {
+    if callee_call_convs(func).contains(&CallConv::WindowsFastcall) {
+        let mut ss =
+            ir::StackSlotData::new(ir::StackSlotKind::OutgoingArg, SHADOW_STORE_SIZE as u32);
+        ss.offset = Some(0);
+        func.stack_slots.push(ss);
+    }
+}
+
 pub fn prologue_epilogue(func: &mut ir::Function, isa: &TargetIsa) -> CodegenResult<()> {
+    reserve_shadow_store(func);
     match func.signature.call_conv {
-        // For now, just translate fast and cold as system_v.
-        CallConv::Fast | CallConv::Cold | CallConv::SystemV => {
+        // For now, just translate fast, cold, and raw as system_v.
+        CallConv::Fast | CallConv::Cold | CallConv::SystemV | CallConv::Raw => {
             system_v_prologue_epilogue(func, isa)
         }
         CallConv::WindowsFastcall => fastcall_prologue_epilogue(func, isa),
@@ -287,6 +451,13 @@ pub fn baldrdash_prologue_epilogue(func: &mut ir::Function, isa: &TargetIsa) ->
     func.stack_slots.push(ss);
 
     layout_stack(&mut func.stack_slots, stack_align)?;
+
+    // The registers saved by the SpiderMonkey prologue are not known here.
+    func.frame_layout = Some(ir::FrameLayout::new(
+        &func.stack_slots,
+        ir::Cfa::StackPointer,
+        Vec::new(),
+    ));
     Ok(())
 }
 
@@ -321,7 +492,6 @@ pub fn fastcall_prologue_epilogue(func: &mut ir::Function, isa: &TargetIsa) -> C
     // instruction. Each of the others we will then push explicitly. Then we
     // will adjust the stack pointer to make room for the rest of the required
     // space for this frame.
-    const SHADOW_STORE_SIZE: i32 = 32;
     let csr_stack_size = ((csrs.iter(GPR).len() + 2) * word_size) as i32;
 
     // TODO: eventually use the 32 bytes (shadow store) as spill slot. This currently doesn't work
@@ -335,6 +505,7 @@ pub fn fastcall_prologue_epilogue(func: &mut ir::Function, isa: &TargetIsa) -> C
 
     let total_stack_size = layout_stack(&mut func.stack_slots, stack_align)? as i32;
     let local_stack_size = i64::from(total_stack_size - csr_stack_size);
+    func.frame_layout = Some(common_frame_layout(func, word_size, &csrs));
 
     // Add CSRs to function signature
     let fp_arg = ir::AbiParam::special_reg(
@@ -390,6 +561,7 @@ pub fn system_v_prologue_epilogue(func: &mut ir::Function, isa: &TargetIsa) -> C
 
     let total_stack_size = layout_stack(&mut func.stack_slots, stack_align)? as i32;
     let local_stack_size = i64::from(total_stack_size - csr_stack_size);
+    func.frame_layout = Some(common_frame_layout(func, word_size, &csrs));
 
     // Add CSRs to function signature
     let fp_arg = ir::AbiParam::special_reg(
@@ -418,6 +590,28 @@ pub fn system_v_prologue_epilogue(func: &mut ir::Function, isa: &TargetIsa) -> C
     Ok(())
 }
 
+/// Describe the stack frame set up by `insert_common_prologue`.
+///
+/// The frame pointer is pushed right after the return address, followed by the callee-saved
+/// registers.
+fn common_frame_layout(
+    func: &ir::Function,
+    word_size: usize,
+    csrs: &RegisterSet,
+) -> ir::FrameLayout {
+    let word_size = word_size as StackOffset;
+    let fp = RU::rbp as RegUnit;
+    let mut saved = vec![(fp, -2 * word_size)];
+    for (i, reg) in csrs.iter(GPR).enumerate() {
+        saved.push((reg, -(3 + i as StackOffset) * word_size));
+    }
+    let cfa = ir::Cfa::FramePointer {
+        reg: fp,
+        offset: 2 * word_size,
+    };
+    ir::FrameLayout::new(&func.stack_slots, cfa, saved)
+}
+
 /// Insert the prologue for a given function.
 /// This is used by common calling conventions such as System V.
 fn insert_common_prologue(
@@ -480,10 +674,12 @@ fn insert_common_prologue(
 
             // Make the call.
             let call = if !isa.flags().is_pic()
+                && isa.flags().code_model() == CodeModel::Large
                 && isa.triple().pointer_width().unwrap() == PointerWidth::U64
                 && !pos.func.dfg.ext_funcs[callee].colocated
             {
-                // 64-bit non-PIC non-colocated calls need to be legalized to call_indirect.
+                // 64-bit non-PIC non-colocated calls in the large code model need to be legalized
+                // to call_indirect.
                 // Use r11 as it may be clobbered under all supported calling conventions.
                 let r11 = RU::r11 as RegUnit;
                 let sig = pos.func.dfg.ext_funcs[callee].signature;
@@ -528,6 +724,7 @@ fn insert_stack_check(pos: &mut EncCursor, stack_size: i64, stack_limit_arg: ir:
         IntCC::UnsignedGreaterThanOrEqual,
         cflags,
         ir::TrapCode::StackOverflow,
+        0,
     );
 }
 
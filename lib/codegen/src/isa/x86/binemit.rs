@@ -2,11 +2,13 @@
 
 use super::enc_tables::{needs_offset, needs_sib_byte};
 use super::registers::RU;
-use binemit::{bad_encoding, CodeSink, Reloc};
+use binemit::{bad_encoding, branch_disp, offset_out_of_range, CodeSink, Reloc};
 use ir::condcodes::{CondCode, FloatCC, IntCC};
 use ir::{Ebb, Function, Inst, InstructionData, JumpTable, Opcode, TrapCode};
 use isa::{RegUnit, StackBase, StackBaseMask, StackRef};
+use predicates::is_signed_int;
 use regalloc::RegDiversions;
+use result::CodegenResult;
 
 include!(concat!(env!("OUT_DIR"), "/binemit-x86.rs"));
 
@@ -161,6 +163,22 @@ fn put_rexmp3<CS: CodeSink + ?Sized>(bits: u16, rex: u8, sink: &mut CS) {
     sink.put1(bits as u8);
 }
 
+// Emit a three-byte VEX prefix (C4 XX XX) followed by the opcode byte.
+//
+// The mandatory prefix and the opcode map of `bits` are encoded in the VEX prefix along with the
+// inverted R, X, and B bits of `rex`, and the inverted register `vvvv`. The vector length is 0.
+fn put_vex<CS: CodeSink + ?Sized>(bits: u16, rex: u8, vvvv: RegUnit, sink: &mut CS) {
+    debug_assert_ne!(bits & 0x0c00, 0, "Invalid encoding bits for Vex*");
+    debug_assert_eq!(rex & 0xf0, 0x40, "Invalid REX bits for Vex*");
+    let pp = ((bits >> 8) & 3) as u8;
+    let mm = ((bits >> 10) & 3) as u8;
+    let w = ((bits >> 15) & 1) as u8;
+    sink.put1(0xc4);
+    sink.put1((!rex & 0b111) << 5 | mm);
+    sink.put1(w << 7 | (!(vvvv as u8) & 0xf) << 3 | pp);
+    sink.put1(bits as u8);
+}
+
 /// Emit a ModR/M byte for reg-reg operands.
 fn modrm_rr<CS: CodeSink + ?Sized>(rm: RegUnit, reg: RegUnit, sink: &mut CS) {
     let reg = reg as u8 & 7;
@@ -324,19 +342,77 @@ fn fcc2opc(cond: FloatCC) -> u16 {
 }
 
 /// Emit a single-byte branch displacement to `destination`.
-fn disp1<CS: CodeSink + ?Sized>(destination: Ebb, func: &Function, sink: &mut CS) {
-    let delta = func.offsets[destination].wrapping_sub(sink.offset() + 1);
-    sink.put1(delta as u8);
+fn disp1<CS: CodeSink + ?Sized>(
+    destination: Ebb,
+    func: &Function,
+    inst: Inst,
+    sink: &mut CS,
+) -> CodegenResult<()> {
+    let disp = branch_disp(func, inst, destination, sink.offset() + 1, |d| {
+        is_signed_int(d, 8, 0)
+    })?;
+    sink.put1(disp as u8);
+    Ok(())
 }
 
 /// Emit a four-byte branch displacement to `destination`.
-fn disp4<CS: CodeSink + ?Sized>(destination: Ebb, func: &Function, sink: &mut CS) {
-    let delta = func.offsets[destination].wrapping_sub(sink.offset() + 4);
-    sink.put4(delta);
+fn disp4<CS: CodeSink + ?Sized>(
+    destination: Ebb,
+    func: &Function,
+    inst: Inst,
+    sink: &mut CS,
+) -> CodegenResult<()> {
+    let disp = branch_disp(func, inst, destination, sink.offset() + 4, |d| {
+        is_signed_int(d, 32, 0)
+    })?;
+    sink.put4(disp as u32);
+    Ok(())
 }
 
 /// Emit a four-byte displacement to jump table `jt`.
-fn jt_disp4<CS: CodeSink + ?Sized>(jt: JumpTable, func: &Function, sink: &mut CS) {
-    let delta = func.jt_offsets[jt].wrapping_sub(sink.offset() + 4);
-    sink.put4(delta);
+fn jt_disp4<CS: CodeSink + ?Sized>(
+    jt: JumpTable,
+    func: &Function,
+    inst: Inst,
+    sink: &mut CS,
+) -> CodegenResult<()> {
+    let disp =
+        i64::from(func.jt_offsets[jt]) + sink.rodata_displacement() - i64::from(sink.offset() + 4);
+    if !is_signed_int(disp, 32, 0) {
+        return Err(offset_out_of_range(
+            func.dfg.display_inst(inst, None),
+            jt,
+            disp,
+        ));
+    }
+    sink.put4(disp as u32);
+    Ok(())
+}
+
+/// Emit a four-byte displacement to the constant pool entry holding the `size` bytes of `bits`.
+fn const_disp4<CS: CodeSink + ?Sized>(
+    bits: u64,
+    size: u8,
+    func: &Function,
+    inst: Inst,
+    sink: &mut CS,
+) -> CodegenResult<()> {
+    let disp = i64::from(func.constants.offset(bits, size)) + sink.rodata_displacement()
+        - i64::from(sink.offset() + 4);
+    if !is_signed_int(disp, 32, 0) {
+        return Err(offset_out_of_range(
+            func.dfg.display_inst(inst, None),
+            "constant pool",
+            disp,
+        ));
+    }
+    sink.put4(disp as u32);
+    Ok(())
+}
+
+/// Report the next `size` bytes as the patch point of `inst` if it is patchable.
+fn patch_point<CS: CodeSink + ?Sized>(func: &Function, inst: Inst, size: u8, sink: &mut CS) {
+    if func.is_patchable(inst) {
+        sink.patch_point(inst, size);
+    }
 }
@@ -1,6 +1,7 @@
 //! Encoding tables for x86 ISAs.
 
 use super::registers::*;
+use bce::divisor_facts;
 use bitset::BitSet;
 use cursor::{Cursor, FuncCursor};
 use flowgraph::ControlFlowGraph;
@@ -12,7 +13,9 @@ use isa::enc_tables::*;
 use isa::encoding::base_size;
 use isa::encoding::RecipeSizing;
 use isa::RegUnit;
+use legalizer::expand_as_libcall;
 use regalloc::RegDiversions;
+use settings::OptLevel;
 
 include!(concat!(env!("OUT_DIR"), "/encoding-x86.rs"));
 include!(concat!(env!("OUT_DIR"), "/legalize-x86.rs"));
@@ -23,6 +26,9 @@ pub fn needs_sib_byte(reg: RegUnit) -> bool {
 pub fn needs_offset(reg: RegUnit) -> bool {
     reg == RU::r13 as RegUnit || reg == RU::rbp as RegUnit
 }
+fn needs_sib_byte_or_offset(reg: RegUnit) -> bool {
+    needs_sib_byte(reg) || needs_offset(reg)
+}
 
 fn additional_size_if(
     op_index: usize,
@@ -71,6 +77,14 @@ fn size_plus_maybe_sib_for_in_reg_1(
 ) -> u8 {
     sizing.base_size + additional_size_if(1, inst, divert, func, needs_sib_byte)
 }
+fn size_plus_maybe_sib_or_offset_for_in_reg_1(
+    sizing: &RecipeSizing,
+    inst: Inst,
+    divert: &RegDiversions,
+    func: &Function,
+) -> u8 {
+    sizing.base_size + additional_size_if(1, inst, divert, func, needs_sib_byte_or_offset)
+}
 
 /// Expand the `sdiv` and `srem` instructions using `x86_sdivmodx`.
 fn expand_sdivrem(
@@ -91,6 +105,7 @@ fn expand_sdivrem(
         _ => panic!("Need sdiv/srem: {}", func.dfg.display_inst(inst, None)),
     };
     let avoid_div_traps = isa.flags().avoid_div_traps();
+    let divisor = divisor_facts(func, cfg, inst, y);
     let old_ebb = func.layout.pp_ebb(inst);
     let result = func.dfg.first_result(inst);
     let ty = func.dfg.value_type(result);
@@ -99,10 +114,19 @@ fn expand_sdivrem(
     pos.use_srcloc(inst);
     pos.func.dfg.clear_results(inst);
 
-    // If we can tolerate native division traps, sdiv doesn't need branching.
-    if !avoid_div_traps && !is_srem {
+    // If we can tolerate native division traps, sdiv doesn't need branching. Neither does a
+    // division by a divisor that can't be -1, since it can't overflow.
+    if (!avoid_div_traps && !is_srem) || divisor.not_minus_one {
+        if avoid_div_traps && !divisor.nonzero {
+            pos.ins().trapz(y, ir::TrapCode::IntegerDivisionByZero, 0);
+        }
         let xhi = pos.ins().sshr_imm(x, i64::from(ty.lane_bits()) - 1);
-        pos.ins().with_result(result).x86_sdivmodx(x, xhi, y);
+        let reuse = if is_srem {
+            [None, Some(result)]
+        } else {
+            [Some(result), None]
+        };
+        pos.ins().with_results(reuse).x86_sdivmodx(x, xhi, y);
         pos.remove_inst();
         return;
     }
@@ -120,9 +144,10 @@ fn expand_sdivrem(
     let is_m1 = pos.ins().ifcmp_imm(y, -1);
     pos.ins().brif(IntCC::Equal, is_m1, minus_one, &[]);
 
-    // Put in an explicit division-by-zero trap if the environment requires it.
-    if avoid_div_traps {
-        pos.ins().trapz(y, ir::TrapCode::IntegerDivisionByZero);
+    // Put in an explicit division-by-zero trap if the environment requires it, unless the divisor
+    // is known not to be zero.
+    if avoid_div_traps && !divisor.nonzero {
+        pos.ins().trapz(y, ir::TrapCode::IntegerDivisionByZero, 0);
     }
 
     // Now it is safe to execute the `x86_sdivmodx` instruction which will still trap on division
@@ -142,7 +167,7 @@ fn expand_sdivrem(
         debug_assert!(avoid_div_traps, "Native trapping divide handled above");
         let f = pos.ins().ifcmp_imm(x, -1 << (ty.lane_bits() - 1));
         pos.ins()
-            .trapif(IntCC::Equal, f, ir::TrapCode::IntegerOverflow);
+            .trapif(IntCC::Equal, f, ir::TrapCode::IntegerOverflow, 0);
         // x / -1 = -x.
         pos.ins().irsub_imm(x, 0)
     };
@@ -163,7 +188,7 @@ fn expand_sdivrem(
 fn expand_udivrem(
     inst: ir::Inst,
     func: &mut ir::Function,
-    _cfg: &mut ControlFlowGraph,
+    cfg: &mut ControlFlowGraph,
     isa: &isa::TargetIsa,
 ) {
     let (x, y, is_urem) = match func.dfg[inst] {
@@ -177,7 +202,9 @@ fn expand_udivrem(
         } => (args[0], args[1], true),
         _ => panic!("Need udiv/urem: {}", func.dfg.display_inst(inst, None)),
     };
-    let avoid_div_traps = isa.flags().avoid_div_traps();
+    // Put in an explicit division-by-zero trap if the environment requires it, unless the divisor
+    // is known not to be zero.
+    let needs_guard = isa.flags().avoid_div_traps() && !divisor_facts(func, cfg, inst, y).nonzero;
     let result = func.dfg.first_result(inst);
     let ty = func.dfg.value_type(result);
 
@@ -185,9 +212,8 @@ fn expand_udivrem(
     pos.use_srcloc(inst);
     pos.func.dfg.clear_results(inst);
 
-    // Put in an explicit division-by-zero trap if the environment requires it.
-    if avoid_div_traps {
-        pos.ins().trapz(y, ir::TrapCode::IntegerDivisionByZero);
+    if needs_guard {
+        pos.ins().trapz(y, ir::TrapCode::IntegerDivisionByZero, 0);
     }
 
     // Now it is safe to execute the `x86_udivmodx` instruction.
@@ -201,98 +227,160 @@ fn expand_udivrem(
     pos.remove_inst();
 }
 
-/// Expand the `fmin` and `fmax` instructions using the x86 `x86_fmin` and `x86_fmax`
-/// instructions.
-fn expand_minmax(
+/// Expand the `ceil`, `floor`, `trunc` and `nearest` instructions without SSE 4.1.
+///
+/// When optimizing for the best code, the rounding is done inline. Adding and subtracting 2^52
+/// (2^23 for `f32`) rounds a smaller magnitude to the nearest integer, with ties to even, in the
+/// default rounding mode. The other rounding directions adjust that by one. Larger magnitudes,
+/// infinities and NaNs are already integral and are returned unchanged, and the sign of the input
+/// is copied to the result so negative zero is preserved.
+///
+/// Otherwise, the instructions are expanded as library calls.
+fn expand_round(
     inst: ir::Inst,
     func: &mut ir::Function,
-    cfg: &mut ControlFlowGraph,
-    _isa: &isa::TargetIsa,
+    _cfg: &mut ControlFlowGraph,
+    isa: &isa::TargetIsa,
 ) {
     use ir::condcodes::FloatCC;
 
-    let (x, y, x86_opc, bitwise_opc) = match func.dfg[inst] {
-        ir::InstructionData::Binary {
-            opcode: ir::Opcode::Fmin,
-            args,
-        } => (args[0], args[1], ir::Opcode::X86Fmin, ir::Opcode::Bor),
-        ir::InstructionData::Binary {
-            opcode: ir::Opcode::Fmax,
-            args,
-        } => (args[0], args[1], ir::Opcode::X86Fmax, ir::Opcode::Band),
-        _ => panic!("Expected fmin/fmax: {}", func.dfg.display_inst(inst, None)),
-    };
-    let old_ebb = func.layout.pp_ebb(inst);
+    if isa.flags().opt_level() != OptLevel::Best {
+        let expanded = expand_as_libcall(inst, func, isa);
+        debug_assert!(
+            expanded,
+            "No libcall for {}",
+            func.dfg.display_inst(inst, None)
+        );
+        return;
+    }
 
-    // We need to handle the following conditions, depending on how x and y compare:
-    //
-    // 1. LT or GT: The native `x86_opc` min/max instruction does what we need.
-    // 2. EQ: We need to use `bitwise_opc` to make sure that
-    //    fmin(0.0, -0.0) -> -0.0 and fmax(0.0, -0.0) -> 0.0.
-    // 3. UN: We need to produce a quiet NaN that is canonical if the inputs are canonical.
+    let (opcode, x) = match func.dfg[inst] {
+        ir::InstructionData::Unary { opcode, arg } => (opcode, arg),
+        _ => panic!("Expected rounding: {}", func.dfg.display_inst(inst, None)),
+    };
+    let ty = func.dfg.value_type(x);
 
-    // EBB handling case 3) where one operand is NaN.
-    let uno_ebb = func.dfg.make_ebb();
+    let mut pos = FuncCursor::new(func).at_inst(inst);
+    pos.use_srcloc(inst);
 
-    // EBB that handles the unordered or equal cases 2) and 3).
-    let ueq_ebb = func.dfg.make_ebb();
+    // The smallest magnitude where all values are integral.
+    let limit = fconst(&mut pos, ty, 0x4b00_0000, 0x4330_0000_0000_0000);
+    let abs = pos.ins().fabs(x);
+    let is_small = pos.ins().fcmp(FloatCC::LessThan, abs, limit);
+    let biased = pos.ins().fadd(abs, limit);
+    let nearest = pos.ins().fsub(biased, limit);
+
+    let result = match opcode {
+        ir::Opcode::Nearest => pos.ins().fcopysign(nearest, x),
+        ir::Opcode::Trunc => {
+            // Round the magnitude down.
+            let one = fconst(&mut pos, ty, 0x3f80_0000, 0x3ff0_0000_0000_0000);
+            let is_up = pos.ins().fcmp(FloatCC::GreaterThan, nearest, abs);
+            let down = pos.ins().fsub(nearest, one);
+            let rounded = pos.ins().select(is_up, down, nearest);
+            pos.ins().fcopysign(rounded, x)
+        }
+        ir::Opcode::Floor => {
+            // Rounding down never changes the sign, since it can't produce a zero from a negative
+            // value.
+            let one = fconst(&mut pos, ty, 0x3f80_0000, 0x3ff0_0000_0000_0000);
+            let signed = pos.ins().fcopysign(nearest, x);
+            let is_up = pos.ins().fcmp(FloatCC::GreaterThan, signed, x);
+            let down = pos.ins().fsub(signed, one);
+            pos.ins().select(is_up, down, signed)
+        }
+        ir::Opcode::Ceil => {
+            // Rounding a value between -1.0 and 0.0 up produces +0.0, so the sign is copied
+            // again.
+            let one = fconst(&mut pos, ty, 0x3f80_0000, 0x3ff0_0000_0000_0000);
+            let signed = pos.ins().fcopysign(nearest, x);
+            let is_down = pos.ins().fcmp(FloatCC::LessThan, signed, x);
+            let up = pos.ins().fadd(signed, one);
+            let rounded = pos.ins().select(is_down, up, signed);
+            pos.ins().fcopysign(rounded, x)
+        }
+        _ => panic!(
+            "Expected rounding: {}",
+            pos.func.dfg.display_inst(inst, None)
+        ),
+    };
 
-    // Final EBB with one argument representing the final result value.
-    let done = func.dfg.make_ebb();
+    pos.func.dfg.replace(inst).select(is_small, result, x);
+}
 
-    // The basic blocks are laid out to minimize branching for the common cases:
-    //
-    // 1) One branch not taken, one jump.
-    // 2) One branch taken.
-    // 3) Two branches taken, one jump.
+/// Expand a `brff` with a condition code that can't be tested with a single `jCC` instruction.
+///
+/// The `ucomiss` and `ucomisd` instructions set the parity flag when the operands are unordered,
+/// so these conditions are a supported condition combined with `uno` or `ord`:
+///
+/// - `ne`, `ugt`, and `uge` branch to the destination with `jp`, and then with the condition for
+///   `one`, `gt`, or `ge`.
+/// - `eq`, `lt`, and `le` skip over the branch for `ueq`, `ult`, or `ule` with `jp`.
+fn expand_brff(
+    inst: ir::Inst,
+    func: &mut ir::Function,
+    cfg: &mut ControlFlowGraph,
+    _isa: &isa::TargetIsa,
+) {
+    use ir::condcodes::FloatCC::*;
+
+    let (cond, flags, destination) = match func.dfg[inst] {
+        ir::InstructionData::BranchFloat {
+            opcode: ir::Opcode::Brff,
+            cond,
+            destination,
+            ref args,
+        } => (
+            cond,
+            args.first(&func.dfg.value_lists).unwrap(),
+            destination,
+        ),
+        _ => panic!("Need brff: {}", func.dfg.display_inst(inst, None)),
+    };
+    let (supported_cond, or_unordered) = match cond {
+        NotEqual => (OrderedNotEqual, true),
+        UnorderedOrGreaterThan => (GreaterThan, true),
+        UnorderedOrGreaterThanOrEqual => (GreaterThanOrEqual, true),
+        Equal => (UnorderedOrEqual, false),
+        LessThan => (UnorderedOrLessThan, false),
+        LessThanOrEqual => (UnorderedOrLessThanOrEqual, false),
+        _ => panic!("Unexpected {}", func.dfg.display_inst(inst, None)),
+    };
+    let ebb_args = func.dfg.inst_variable_args(inst).to_vec();
+    let old_ebb = func.layout.pp_ebb(inst);
 
-    // Move the `inst` result value onto the `done` EBB.
-    let result = func.dfg.first_result(inst);
-    let ty = func.dfg.value_type(result);
-    func.dfg.clear_results(inst);
-    func.dfg.attach_ebb_param(done, result);
+    if let ir::InstructionData::BranchFloat { ref mut cond, .. } = func.dfg[inst] {
+        *cond = supported_cond;
+    }
 
-    // Test for case 1) ordered and not equal.
     let mut pos = FuncCursor::new(func).at_inst(inst);
     pos.use_srcloc(inst);
-    let cmp_ueq = pos.ins().fcmp(FloatCC::UnorderedOrEqual, x, y);
-    pos.ins().brnz(cmp_ueq, ueq_ebb, &[]);
-
-    // Handle the common ordered, not equal (LT|GT) case.
-    let one_inst = pos.ins().Binary(x86_opc, ty, x, y).0;
-    let one_result = pos.func.dfg.first_result(one_inst);
-    pos.ins().jump(done, &[one_result]);
-
-    // Case 3) Unordered.
-    // We know that at least one operand is a NaN that needs to be propagated. We simply use an
-    // `fadd` instruction which has the same NaN propagation semantics.
-    pos.insert_ebb(uno_ebb);
-    let uno_result = pos.ins().fadd(x, y);
-    pos.ins().jump(done, &[uno_result]);
-
-    // Case 2) or 3).
-    pos.insert_ebb(ueq_ebb);
-    // Test for case 3) (UN) one value is NaN.
-    // TODO: When we get support for flag values, we can reuse the above comparison.
-    let cmp_uno = pos.ins().fcmp(FloatCC::Unordered, x, y);
-    pos.ins().brnz(cmp_uno, uno_ebb, &[]);
-
-    // We are now in case 2) where x and y compare EQ.
-    // We need a bitwise operation to get the sign right.
-    let bw_inst = pos.ins().Binary(bitwise_opc, ty, x, y).0;
-    let bw_result = pos.func.dfg.first_result(bw_inst);
-    // This should become a fall-through for this second most common case.
-    // Recycle the original instruction as a jump.
-    pos.func.dfg.replace(inst).jump(done, &[bw_result]);
 
-    // Finally insert a label for the completion.
-    pos.next_inst();
-    pos.insert_ebb(done);
+    if or_unordered {
+        pos.ins().brff(Unordered, flags, destination, &ebb_args);
+        cfg.recompute_ebb(pos.func, old_ebb);
+    } else {
+        // The instructions after the branch are moved to a new EBB, so the unordered case can
+        // skip the branch.
+        let skip = pos.func.dfg.make_ebb();
+        pos.ins().brff(Unordered, flags, skip, &[]);
+        pos.next_inst();
+        pos.ins().jump(skip, &[]);
+        pos.insert_ebb(skip);
+        cfg.recompute_ebb(pos.func, old_ebb);
+        cfg.recompute_ebb(pos.func, skip);
+    }
+}
 
-    cfg.recompute_ebb(pos.func, old_ebb);
-    cfg.recompute_ebb(pos.func, ueq_ebb);
-    cfg.recompute_ebb(pos.func, uno_ebb);
-    cfg.recompute_ebb(pos.func, done);
+/// Insert a floating point constant of type `ty`, given its bits as an `f32` and as an `f64`.
+fn fconst(pos: &mut FuncCursor, ty: ir::Type, bits32: u32, bits64: u64) -> ir::Value {
+    use ir::immediates::{Ieee32, Ieee64};
+
+    match ty {
+        ir::types::F32 => pos.ins().f32const(Ieee32::with_bits(bits32)),
+        ir::types::F64 => pos.ins().f64const(Ieee64::with_bits(bits64)),
+        _ => panic!("Expected a float type: {}", ty),
+    }
 }
 
 /// x86 has no unsigned-to-float conversions. We handle the easy case of zero-extending i32 to
@@ -416,7 +504,7 @@ fn expand_fcvt_to_sint(
     // Check for NaN.
     let is_nan = pos.ins().fcmp(FloatCC::Unordered, x, x);
     pos.ins()
-        .trapnz(is_nan, ir::TrapCode::BadConversionToInteger);
+        .trapnz(is_nan, ir::TrapCode::BadConversionToInteger, 0);
 
     // Check for case 1: INT_MIN is the correct result.
     // Determine the smallest floating point number that would convert to INT_MIN.
@@ -448,7 +536,7 @@ fn expand_fcvt_to_sint(
         _ => panic!("Can't convert {}", xty),
     };
     let overflow = pos.ins().fcmp(overflow_cc, x, flimit);
-    pos.ins().trapnz(overflow, ir::TrapCode::IntegerOverflow);
+    pos.ins().trapnz(overflow, ir::TrapCode::IntegerOverflow, 0);
 
     // Finally, we could have a positive value that is too large.
     let fzero = match xty {
@@ -457,7 +545,7 @@ fn expand_fcvt_to_sint(
         _ => panic!("Can't convert {}", xty),
     };
     let overflow = pos.ins().fcmp(FloatCC::GreaterThanOrEqual, x, fzero);
-    pos.ins().trapnz(overflow, ir::TrapCode::IntegerOverflow);
+    pos.ins().trapnz(overflow, ir::TrapCode::IntegerOverflow, 0);
 
     pos.ins().jump(done, &[]);
     pos.insert_ebb(done);
@@ -638,6 +726,7 @@ fn expand_fcvt_to_uint(
         FloatCC::Unordered,
         is_large,
         ir::TrapCode::BadConversionToInteger,
+        0,
     );
 
     // Now we know that x < 2^(N-1) and not NaN.
@@ -645,15 +734,19 @@ fn expand_fcvt_to_uint(
     let is_neg = pos.ins().ifcmp_imm(sres, 0);
     pos.ins()
         .brif(IntCC::SignedGreaterThanOrEqual, is_neg, done, &[sres]);
-    pos.ins().trap(ir::TrapCode::IntegerOverflow);
+    pos.ins().trap(ir::TrapCode::IntegerOverflow, 0);
 
     // Handle the case where x >= 2^(N-1) and not NaN.
     pos.insert_ebb(large);
     let adjx = pos.ins().fsub(x, pow2nm1);
     let lres = pos.ins().x86_cvtt2si(ty, adjx);
     let is_neg = pos.ins().ifcmp_imm(lres, 0);
-    pos.ins()
-        .trapif(IntCC::SignedLessThan, is_neg, ir::TrapCode::IntegerOverflow);
+    pos.ins().trapif(
+        IntCC::SignedLessThan,
+        is_neg,
+        ir::TrapCode::IntegerOverflow,
+        0,
+    );
     let lfinal = pos.ins().iadd_imm(lres, 1 << (ty.lane_bits() - 1));
 
     // Recycle the original instruction as a jump.
@@ -757,3 +850,26 @@ fn expand_fcvt_to_uint_sat(
     cfg.recompute_ebb(pos.func, large);
     cfg.recompute_ebb(pos.func, done);
 }
+
+/// Expand `read_cycle_counter` as `x86_rdtsc`, which returns the two halves of the counter.
+///
+/// In 64-bit mode, the halves are combined with a shift and an `or`. In 32-bit mode, the `i64`
+/// result is an `iconcat` of the halves, which the legalizer splits again.
+fn expand_read_cycle_counter(
+    inst: ir::Inst,
+    func: &mut ir::Function,
+    _cfg: &mut ControlFlowGraph,
+    isa: &isa::TargetIsa,
+) {
+    let mut pos = FuncCursor::new(func).at_inst(inst);
+    pos.use_srcloc(inst);
+
+    if isa.pointer_bits() == 64 {
+        let (lo, hi) = pos.ins().x86_rdtsc(ir::types::I64);
+        let hi = pos.ins().ishl_imm(hi, 32);
+        pos.func.dfg.replace(inst).bor(lo, hi);
+    } else {
+        let (lo, hi) = pos.ins().x86_rdtsc(ir::types::I32);
+        pos.func.dfg.replace(inst).iconcat(lo, hi);
+    }
+}
@@ -12,8 +12,9 @@ use binemit::CodeSink;
 use binemit::{emit_function, MemoryCodeSink};
 use ir;
 use isa::enc_tables::{self as shared_enc_tables, lookup_enclist, Encodings};
+use isa::support::inst_support;
 use isa::Builder as IsaBuilder;
-use isa::{EncInfo, RegClass, RegInfo, TargetIsa};
+use isa::{EncInfo, Encoding, InstSupport, RegClass, RegInfo, TargetIsa};
 use regalloc;
 use result::CodegenResult;
 use std::boxed::Box;
@@ -26,6 +27,7 @@ struct Isa {
     triple: Triple,
     shared_flags: shared_settings::Flags,
     isa_flags: settings::Flags,
+    libcall_names: ir::LibcallNames,
     cpumode: &'static [shared_enc_tables::Level1Entry<u16>],
 }
 
@@ -34,6 +36,7 @@ pub fn isa_builder(triple: Triple) -> IsaBuilder {
     IsaBuilder {
         triple,
         setup: settings::builder(),
+        libcall_names: ir::LibcallNames::new(),
         constructor: isa_constructor,
     }
 }
@@ -42,6 +45,7 @@ fn isa_constructor(
     triple: Triple,
     shared_flags: shared_settings::Flags,
     builder: shared_settings::Builder,
+    libcall_names: ir::LibcallNames,
 ) -> Box<TargetIsa> {
     let level1 = match triple.pointer_width().unwrap() {
         PointerWidth::U16 => unimplemented!("x86-16"),
@@ -52,6 +56,7 @@ fn isa_constructor(
         triple,
         isa_flags: settings::Flags::new(&shared_flags, builder),
         shared_flags,
+        libcall_names,
         cpumode: level1,
     })
 }
@@ -69,6 +74,10 @@ impl TargetIsa for Isa {
         &self.shared_flags
     }
 
+    fn libcall_names(&self) -> &ir::LibcallNames {
+        &self.libcall_names
+    }
+
     fn uses_cpu_flags(&self) -> bool {
         true
     }
@@ -81,6 +90,10 @@ impl TargetIsa for Isa {
         registers::INFO.clone()
     }
 
+    fn supports(&self, opcode: ir::Opcode, ctrl_type: ir::Type) -> InstSupport {
+        inst_support(self, opcode, ctrl_type)
+    }
+
     fn encoding_info(&self) -> EncInfo {
         enc_tables::INFO.clone()
     }
@@ -105,6 +118,36 @@ impl TargetIsa for Isa {
         )
     }
 
+    /// On x86-64, these instructions can be patched:
+    ///
+    /// - `iconst.i32` is encoded as `mov r32, imm32`, with a 4-byte immediate.
+    /// - `iconst.i64` is encoded as `movabs r64, imm64`, with an 8-byte immediate.
+    /// - A direct `call` is encoded as `call rel32`, with a 4-byte displacement relative to the
+    ///   end of the instruction. Calls that need to be legalized into a `call_indirect`, as in the
+    ///   large code model, can't be patched.
+    ///
+    /// In all cases, the patchable field ends the instruction.
+    fn patchable_encoding(&self, func: &ir::Function, inst: ir::Inst) -> Option<Encoding> {
+        if self.pointer_bits() != 64 {
+            return None;
+        }
+        let ctrl_type = func.dfg.ctrl_typevar(inst);
+        // The recipes with an immediate that ends the instruction, with and without a REX prefix.
+        let recipes: &[&str] = match (func.dfg[inst].opcode(), ctrl_type) {
+            (ir::Opcode::Iconst, ir::types::I32) => &["Op1pu_id", "RexOp1pu_id"],
+            (ir::Opcode::Iconst, ir::types::I64) => &["RexOp1pu_iq"],
+            (ir::Opcode::Call, _) => return self.encode(func, &func.dfg[inst], ctrl_type).ok(),
+            _ => return None,
+        };
+        let encinfo = self.encoding_info();
+        self.legal_encodings(func, &func.dfg[inst], ctrl_type)
+            .find(|&enc| {
+                encinfo
+                    .recipe_name(enc)
+                    .map_or(false, |name| recipes.contains(&name))
+            })
+    }
+
     fn legalize_signature(&self, sig: &mut ir::Signature, current: bool) {
         abi::legalize_signature(sig, &self.triple, current)
     }
@@ -117,6 +160,19 @@ impl TargetIsa for Isa {
         abi::allocatable_registers(func, &self.triple)
     }
 
+    fn callee_saved_registers(&self, func: &ir::Function) -> regalloc::RegisterSet {
+        abi::callee_saved_registers(self, func)
+    }
+
+    fn supports_call_conv(
+        &self,
+        caller: shared_settings::CallConv,
+        callee: shared_settings::CallConv,
+    ) -> bool {
+        abi::supports_call_conv(&self.triple, caller, true)
+            && abi::supports_call_conv(&self.triple, callee, false)
+    }
+
     #[cfg(feature = "testing_hooks")]
     fn emit_inst(
         &self,
@@ -124,12 +180,16 @@ impl TargetIsa for Isa {
         inst: ir::Inst,
         divert: &mut regalloc::RegDiversions,
         sink: &mut CodeSink,
-    ) {
+    ) -> CodegenResult<()> {
         binemit::emit_inst(func, inst, divert, sink)
     }
 
-    fn emit_function_to_memory(&self, func: &ir::Function, sink: &mut MemoryCodeSink) {
-        emit_function(func, binemit::emit_inst, sink)
+    fn emit_function_to_memory(
+        &self,
+        func: &ir::Function,
+        sink: &mut MemoryCodeSink,
+    ) -> CodegenResult<()> {
+        emit_function(func, binemit::emit_inst, sink, self.endianness())
     }
 
     fn prologue_epilogue(&self, func: &mut ir::Function) -> CodegenResult<()> {
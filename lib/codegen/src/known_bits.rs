@@ -0,0 +1,160 @@
+//! Known-zero bits analysis.
+//!
+//! Many integer values have bits that are zero no matter what the inputs are: a `uextend` or a
+//! `uload8` clears the high bits, a left shift clears the low bits, and a `band_imm` clears the
+//! bits that aren't in its mask. Knowing about them lets the optimizer remove masks and extensions
+//! that don't change a value.
+//!
+//! The analysis follows the instructions that define a value and its arguments, up to a small
+//! depth. It doesn't keep any state, so any pass can use it on demand.
+
+use ir::{Function, InstructionData, Opcode, Type, Value, ValueDef};
+
+/// How many instructions deep the analysis looks for known bits.
+const MAX_DEPTH: u32 = 6;
+
+/// Get the mask of the bits of an integer type `ty`.
+pub fn type_mask(ty: Type) -> u64 {
+    match ty.bits() {
+        64 => !0,
+        bits => (1 << bits) - 1,
+    }
+}
+
+/// Get the bits of `value` that are known to be zero.
+///
+/// The result has a bit set for each bit of `value` that is always zero. The bits beyond the
+/// width of the value's type are never set, and values that aren't scalar integers have no known
+/// bits. Nothing is known about the results of patchable instructions.
+pub fn known_zero_bits(func: &Function, value: Value) -> u64 {
+    zero_bits(func, value, MAX_DEPTH)
+}
+
+fn zero_bits(func: &Function, value: Value, depth: u32) -> u64 {
+    let dfg = &func.dfg;
+    let ty = dfg.value_type(value);
+    if !ty.is_int() || depth == 0 {
+        return 0;
+    }
+    let inst = match dfg.value_def(value) {
+        ValueDef::Result(inst, _) if !func.is_patchable(inst) => inst,
+        _ => return 0,
+    };
+    let bits = u64::from(ty.bits());
+    let depth = depth - 1;
+
+    let known = match dfg[inst] {
+        InstructionData::UnaryImm {
+            opcode: Opcode::Iconst,
+            imm,
+        } => {
+            let imm: i64 = imm.into();
+            !imm as u64
+        }
+        InstructionData::Unary { opcode, arg } => match opcode {
+            Opcode::Uextend => zero_bits(func, arg, depth) | !type_mask(dfg.value_type(arg)),
+            Opcode::Sextend => {
+                // The high bits are copies of the sign bit.
+                let arg_ty = dfg.value_type(arg);
+                let known = zero_bits(func, arg, depth);
+                if known & (1 << (arg_ty.bits() - 1)) != 0 {
+                    known | !type_mask(arg_ty)
+                } else {
+                    known
+                }
+            }
+            Opcode::Ireduce => zero_bits(func, arg, depth),
+            Opcode::Bint => !1,
+            _ => 0,
+        },
+        InstructionData::Load { opcode, .. } | InstructionData::LoadComplex { opcode, .. } => {
+            match opcode {
+                Opcode::Uload8 | Opcode::Uload8Complex => !0xff,
+                Opcode::Uload16 | Opcode::Uload16Complex => !0xffff,
+                Opcode::Uload32 | Opcode::Uload32Complex => !0xffff_ffff,
+                _ => 0,
+            }
+        }
+        InstructionData::BinaryImm { opcode, arg, imm } => {
+            let imm: i64 = imm.into();
+            // Shift amounts are taken modulo the type width.
+            let shift = imm as u64 & (bits - 1);
+            match opcode {
+                Opcode::BandImm => zero_bits(func, arg, depth) | !imm as u64,
+                Opcode::BorImm => zero_bits(func, arg, depth) & !imm as u64,
+                Opcode::UshrImm => {
+                    let mask = type_mask(ty);
+                    ((zero_bits(func, arg, depth) & mask) >> shift) | !(mask >> shift)
+                }
+                Opcode::IshlImm => (zero_bits(func, arg, depth) << shift) | ((1 << shift) - 1),
+                _ => 0,
+            }
+        }
+        InstructionData::Binary { opcode, args } => match opcode {
+            Opcode::Band => zero_bits(func, args[0], depth) | zero_bits(func, args[1], depth),
+            Opcode::Bor | Opcode::Bxor => {
+                zero_bits(func, args[0], depth) & zero_bits(func, args[1], depth)
+            }
+            _ => 0,
+        },
+        InstructionData::Ternary {
+            opcode: Opcode::Select,
+            args,
+        } => zero_bits(func, args[1], depth) & zero_bits(func, args[2], depth),
+        _ => 0,
+    };
+
+    known & type_mask(ty)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cursor::{Cursor, FuncCursor};
+    use ir::types::{I16, I32, I64, I8};
+    use ir::{Function, InstBuilder, MemFlags};
+
+    #[test]
+    fn masks() {
+        assert_eq!(type_mask(I8), 0xff);
+        assert_eq!(type_mask(I32), 0xffff_ffff);
+        assert_eq!(type_mask(I64), !0);
+    }
+
+    #[test]
+    fn known_bits() {
+        let mut func = Function::new();
+        let ebb0 = func.dfg.make_ebb();
+        let arg = func.dfg.append_ebb_param(ebb0, I64);
+        let mut pos = FuncCursor::new(&mut func);
+        pos.insert_ebb(ebb0);
+
+        let byte = pos.ins().uload8(I32, MemFlags::new(), arg, 0);
+        let ext = pos.ins().uextend(I64, byte);
+        let shl = pos.ins().ishl_imm(ext, 4);
+        let shr = pos.ins().ushr_imm(arg, 60);
+        let and = pos.ins().band_imm(arg, 0xf0);
+        let half = pos.ins().ireduce(I16, arg);
+        let sext = pos.ins().sextend(I32, half);
+        let small = pos.ins().ireduce(I16, byte);
+        let small_sext = pos.ins().sextend(I32, small);
+
+        let patched = pos.ins().iconst(I32, 0);
+        pos.func
+            .patchable
+            .insert(pos.func.dfg.value_def(patched).unwrap_inst());
+
+        let func = &*pos.func;
+        assert_eq!(known_zero_bits(func, arg), 0);
+        assert_eq!(known_zero_bits(func, byte), 0xffff_ff00);
+        assert_eq!(known_zero_bits(func, ext), !0xff);
+        assert_eq!(known_zero_bits(func, shl), !0xff0);
+        assert_eq!(known_zero_bits(func, shr), !0xf);
+        assert_eq!(known_zero_bits(func, and), !0xf0);
+        assert_eq!(known_zero_bits(func, half), 0);
+        assert_eq!(known_zero_bits(func, sext), 0);
+        assert_eq!(known_zero_bits(func, small), 0xff00);
+        assert_eq!(known_zero_bits(func, small_sext), 0xffff_ff00);
+        assert_eq!(known_zero_bits(func, patched), 0);
+    }
+}
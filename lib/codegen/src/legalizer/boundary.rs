@@ -16,17 +16,25 @@
 //!
 //! Between the two phases, preamble signatures and call/return arguments don't match. This
 //! intermediate state doesn't type check.
+//!
+//! Struct arguments and return values returned in memory are an exception. They are rewritten by
+//! `legalize_signatures` directly, because a struct passed in a single register has the same type
+//! as the pointer it was loaded from, so a call can't tell if its struct arguments have been
+//! expanded already.
 
-use abi::{legalize_abi_value, ValueConversion};
+use abi::{legalize_abi_value, struct_words, ValueConversion};
 use cursor::{Cursor, FuncCursor};
 use flowgraph::ControlFlowGraph;
+use ir::condcodes::IntCC;
 use ir::instructions::CallInfo;
 use ir::{
-    AbiParam, ArgumentLoc, ArgumentPurpose, DataFlowGraph, Ebb, Function, Inst, InstBuilder,
-    SigRef, Signature, Type, Value, ValueLoc,
+    types, AbiParam, ArgumentLoc, ArgumentPurpose, DataFlowGraph, Ebb, Function, Inst, InstBuilder,
+    MemFlags, SigRef, Signature, StackSlot, StackSlotData, StackSlotKind, Type, Value, ValueLoc,
 };
 use isa::TargetIsa;
 use legalizer::split::{isplit, vsplit};
+use std::cmp;
+use std::mem;
 use std::vec::Vec;
 
 /// Legalize all the function signatures in `func`.
@@ -35,15 +43,38 @@ use std::vec::Vec;
 /// change the entry block arguments, calls, or return instructions, so this can leave the function
 /// in a state with type discrepancies.
 pub fn legalize_signatures(func: &mut Function, isa: &TargetIsa) {
-    legalize_signature(&mut func.signature, true, isa);
-    for sig_data in func.dfg.signatures.values_mut() {
-        legalize_signature(sig_data, false, isa);
+    let original_signature = func.signature.clone();
+    let sret = legalize_signature(&mut func.signature, true, isa);
+    let entry_rewrite = SigRewrite {
+        structs: struct_args(&original_signature.params, &func.signature.params),
+        sret,
+    };
+
+    let mut call_rewrites = Vec::new();
+    for (sig_ref, sig_data) in func.dfg.signatures.iter_mut() {
+        let original_params = sig_data.params.clone();
+        let sret = legalize_signature(sig_data, false, isa);
+        let rewrite = SigRewrite {
+            structs: struct_args(&original_params, &sig_data.params),
+            sret,
+        };
+        if !rewrite.is_empty() {
+            call_rewrites.push((sig_ref, rewrite));
+        }
     }
 
+    let word = isa.pointer_type();
     if let Some(entry) = func.layout.entry_block() {
+        if !entry_rewrite.is_empty() {
+            rewrite_entry_params(func, entry, &entry_rewrite, word);
+        }
         legalize_entry_params(func, entry);
         spill_entry_params(func, entry);
     }
+
+    if !call_rewrites.is_empty() {
+        rewrite_calls(func, &call_rewrites, word);
+    }
 }
 
 /// Legalize the libcall signature, which we may generate on the fly after
@@ -52,11 +83,364 @@ pub fn legalize_libcall_signature(signature: &mut Signature, isa: &TargetIsa) {
     legalize_signature(signature, false, isa);
 }
 
+/// Legalize the signature of a function called through `signature`.
+///
+/// This is the signature that compiled code uses on both sides of the call, including the `sret`
+/// argument that is added when the return values don't fit in registers.
+pub fn legalize_call_signature<I: TargetIsa + ?Sized>(signature: &mut Signature, isa: &I) {
+    legalize_signature(signature, false, isa);
+}
+
 /// Legalize the given signature.
 ///
 /// `current` is true if this is the signature for the current function.
-fn legalize_signature(signature: &mut Signature, current: bool, isa: &TargetIsa) {
+///
+/// When the ISA can't fit the return values in registers, they are returned in memory instead.
+/// The caller passes a hidden `StructReturn` pointer as the first argument, and the callee returns
+/// the same pointer. In that case, the original return values are returned.
+fn legalize_signature<I: TargetIsa + ?Sized>(
+    signature: &mut Signature,
+    current: bool,
+    isa: &I,
+) -> Option<Vec<AbiParam>> {
+    let original = signature.clone();
+    let preassigned = original
+        .returns
+        .iter()
+        .any(|ret| ret.location.is_assigned());
     isa.legalize_signature(signature, current);
+
+    let in_memory = signature.returns.iter().any(|ret| match ret.location {
+        ArgumentLoc::Stack(_) => true,
+        _ => false,
+    });
+    if preassigned || !in_memory {
+        return None;
+    }
+
+    *signature = original;
+    let returns = mem::replace(&mut signature.returns, Vec::new());
+    let sret = AbiParam::special(isa.pointer_type(), ArgumentPurpose::StructReturn);
+    signature.params.insert(0, sret);
+    signature.returns.push(sret);
+    isa.legalize_signature(signature, current);
+    Some(returns)
+}
+
+/// The parts of a signature that are rewritten by `legalize_signatures` directly.
+struct SigRewrite {
+    /// The struct arguments that were expanded.
+    structs: Vec<StructArg>,
+    /// The original return values, if they are returned in memory.
+    sret: Option<Vec<AbiParam>>,
+}
+
+impl SigRewrite {
+    fn is_empty(&self) -> bool {
+        self.structs.is_empty() && self.sret.is_none()
+    }
+}
+
+/// A struct argument in a signature that has just been legalized.
+#[derive(Clone, Copy, Debug)]
+struct StructArg {
+    /// The index of the argument in the original signature.
+    index: usize,
+    /// The size of the struct in bytes.
+    size: u32,
+    /// Is a pointer to a copy of the struct passed instead of its parts?
+    by_reference: bool,
+}
+
+/// Find the struct arguments that were expanded when legalizing `original` into `legalized`.
+///
+/// Signatures that were legalized already don't have any struct arguments left to expand.
+fn struct_args(original: &[AbiParam], legalized: &[AbiParam]) -> Vec<StructArg> {
+    let indexes = original
+        .iter()
+        .enumerate()
+        .filter_map(|(index, param)| match param.purpose {
+            ArgumentPurpose::StructArgument(size)
+                if !param.location.is_assigned() && !param.legalized_to_pointer =>
+            {
+                Some((index, size))
+            }
+            _ => None,
+        });
+
+    // The legalized signature has either a single pointer parameter or a run of parts for each of
+    // the struct arguments.
+    let mut passing = Vec::new();
+    let mut abi_arg = 0;
+    while let Some(param) = legalized.get(abi_arg) {
+        abi_arg += 1;
+        if let ArgumentPurpose::StructArgument(size) = param.purpose {
+            if !param.legalized_to_pointer {
+                abi_arg += struct_words(size, param.value_type) - 1;
+            }
+            passing.push(param.legalized_to_pointer);
+        }
+    }
+
+    indexes
+        .zip(passing)
+        .map(|((index, size), by_reference)| StructArg {
+            index,
+            size,
+            by_reference,
+        })
+        .collect()
+}
+
+/// Rewrite the entry block parameters and the return instructions of `func`.
+///
+/// Struct parameters that are passed in parts are stored to a new stack slot, and the original
+/// parameter becomes the address of the stack slot. Structs that are passed by reference already
+/// have the right parameter.
+///
+/// When the return values are returned in memory, a parameter is added for the `StructReturn`
+/// pointer, and the return instructions store their arguments through it.
+fn rewrite_entry_params(func: &mut Function, entry: Ebb, rewrite: &SigRewrite, word: Type) {
+    let mut pos = FuncCursor::new(func).at_first_inst(entry);
+    let ebb_params = pos.func.dfg.detach_ebb_params(entry);
+
+    let sret = if rewrite.sret.is_some() {
+        Some(pos.func.dfg.append_ebb_param(entry, word))
+    } else {
+        None
+    };
+
+    let mut old_arg = 0;
+    while let Some(arg) = ebb_params.get(old_arg, &pos.func.dfg.value_lists) {
+        match rewrite.structs.iter().find(|s| s.index == old_arg) {
+            Some(s) if !s.by_reference => {
+                let words = struct_words(s.size, word);
+                let ss = create_struct_slot(pos.func, words, word);
+                for part in 0..words {
+                    let value = pos.func.dfg.append_ebb_param(entry, word);
+                    pos.ins().stack_store(value, ss, part_offset(part, word));
+                }
+                pos.ins().with_result(arg).stack_addr(word, ss, 0);
+            }
+            _ => pos.func.dfg.attach_ebb_param(entry, arg),
+        }
+        old_arg += 1;
+    }
+
+    if let (Some(sret), &Some(ref returns)) = (sret, &rewrite.sret) {
+        let offsets = sret_layout(returns).0;
+        let mut pos = FuncCursor::new(pos.func);
+        while let Some(_ebb) = pos.next_ebb() {
+            while let Some(inst) = pos.next_inst() {
+                if !pos.func.dfg[inst].opcode().is_return() {
+                    continue;
+                }
+                pos.use_srcloc(inst);
+
+                let values = pos.func.dfg.inst_variable_args(inst).to_vec();
+                for (&value, &offset) in values.iter().zip(&offsets) {
+                    let value = match pos.func.dfg.value_type(value) {
+                        ty if ty.is_bool() => pos.ins().bint(sret_type(ty), value),
+                        _ => value,
+                    };
+                    pos.ins().store(sret_flags(), value, sret, offset);
+                }
+
+                let mut vlist = pos.func.dfg[inst]
+                    .take_value_list()
+                    .expect("Return must have a value list");
+                vlist.clear(&mut pos.func.dfg.value_lists);
+                pos.func.dfg[inst].put_value_list(vlist);
+            }
+        }
+    }
+}
+
+/// Rewrite all the calls using the signatures in `call_rewrites`.
+///
+/// Structs passed in parts are loaded from memory. Structs passed by reference are copied to a new
+/// stack slot, so the callee can't modify the caller's struct.
+///
+/// Return values that are returned in memory are loaded from a new stack slot after the call. The
+/// address of the stack slot is passed as the `StructReturn` pointer.
+fn rewrite_calls(func: &mut Function, call_rewrites: &[(SigRef, SigRewrite)], word: Type) {
+    let mut pos = FuncCursor::new(func);
+    while let Some(_ebb) = pos.next_ebb() {
+        while let Some(inst) = pos.next_inst() {
+            let rewrite = match pos
+                .func
+                .dfg
+                .call_signature(inst)
+                .and_then(|sig_ref| call_rewrites.iter().find(|&&(s, _)| s == sig_ref))
+            {
+                Some(&(_, ref rewrite)) => rewrite,
+                None => continue,
+            };
+            pos.use_srcloc(inst);
+
+            let old_args = pos.func.dfg.inst_variable_args(inst).to_vec();
+            let mut new_args = Vec::with_capacity(old_args.len() + 1);
+
+            if let Some(ref returns) = rewrite.sret {
+                let size = sret_layout(returns).1;
+                let ss = pos
+                    .func
+                    .create_stack_slot(StackSlotData::new(StackSlotKind::ExplicitSlot, size));
+                new_args.push(pos.ins().stack_addr(word, ss, 0));
+            }
+
+            for (index, &arg) in old_args.iter().enumerate() {
+                match rewrite.structs.iter().find(|s| s.index == index) {
+                    None => new_args.push(arg),
+                    Some(s) if s.by_reference => {
+                        let words = struct_words(s.size, word);
+                        let ss = create_struct_slot(pos.func, words, word);
+                        for part in 0..words {
+                            let value = load_struct_part(&mut pos, arg, s.size, part, word);
+                            pos.ins().stack_store(value, ss, part_offset(part, word));
+                        }
+                        new_args.push(pos.ins().stack_addr(word, ss, 0));
+                    }
+                    Some(s) => {
+                        for part in 0..struct_words(s.size, word) {
+                            new_args.push(load_struct_part(&mut pos, arg, s.size, part, word));
+                        }
+                    }
+                }
+            }
+
+            // Keep the callee of an indirect call in front of the new arguments.
+            let mut vlist = pos.func.dfg[inst]
+                .take_value_list()
+                .expect("Call must have a value list");
+            let fixed_values = vlist.len(&pos.func.dfg.value_lists) - old_args.len();
+            let mut args = vlist.as_slice(&pos.func.dfg.value_lists)[..fixed_values].to_vec();
+            args.extend(new_args);
+            vlist.clear(&mut pos.func.dfg.value_lists);
+            vlist.extend(args, &mut pos.func.dfg.value_lists);
+            pos.func.dfg[inst].put_value_list(vlist);
+
+            // The call now returns the `StructReturn` pointer. The original results are loaded
+            // from the memory it points to.
+            if let Some(ref returns) = rewrite.sret {
+                let offsets = sret_layout(returns).0;
+                let results = pos.func.dfg.detach_results(inst);
+                let sret = pos.func.dfg.append_result(inst, word);
+                pos.goto_after_inst(inst);
+                let mut index = 0;
+                while let Some(result) = results.get(index, &pos.func.dfg.value_lists) {
+                    let ty = pos.func.dfg.value_type(result);
+                    let offset = offsets[index];
+                    if ty.is_bool() {
+                        let bits = pos.ins().load(sret_type(ty), sret_flags(), sret, offset);
+                        if ty == types::B1 {
+                            pos.ins()
+                                .with_result(result)
+                                .icmp_imm(IntCC::NotEqual, bits, 0);
+                        } else {
+                            let b = pos.ins().icmp_imm(IntCC::NotEqual, bits, 0);
+                            pos.ins().with_result(result).bextend(ty, b);
+                        }
+                    } else {
+                        pos.ins()
+                            .with_result(result)
+                            .load(ty, sret_flags(), sret, offset);
+                    }
+                    index += 1;
+                }
+            }
+        }
+    }
+}
+
+/// Get the type used to store a return value of type `ty` in memory.
+///
+/// Booleans are stored as integers, like they are passed in registers.
+fn sret_type(ty: Type) -> Type {
+    if ty.is_bool() {
+        Type::int(cmp::max(ty.bits(), 8)).expect("Bad boolean size")
+    } else {
+        ty
+    }
+}
+
+/// Get the memory flags for accessing return values in memory.
+fn sret_flags() -> MemFlags {
+    let mut flags = MemFlags::new();
+    flags.set_notrap();
+    flags.set_aligned();
+    flags
+}
+
+/// Compute the memory layout of return values that are returned in memory.
+///
+/// Each value is naturally aligned, and the total size is padded to a multiple of the largest
+/// alignment, so a stack slot of that size is aligned enough for all the values. Returns the
+/// offsets of the values and the total size.
+fn sret_layout(returns: &[AbiParam]) -> (Vec<i32>, u32) {
+    let mut offsets = Vec::with_capacity(returns.len());
+    let mut size = 0;
+    let mut align = 1;
+    for ret in returns {
+        let bytes = sret_type(ret.value_type).bytes();
+        size = (size + bytes - 1) / bytes * bytes;
+        offsets.push(size as i32);
+        size += bytes;
+        align = cmp::max(align, bytes);
+    }
+    (offsets, (size + align - 1) / align * align)
+}
+
+/// Create a stack slot for a struct of `words` parts.
+fn create_struct_slot(func: &mut Function, words: usize, word: Type) -> StackSlot {
+    let size = words as u32 * word.bytes();
+    func.create_stack_slot(StackSlotData::new(StackSlotKind::ExplicitSlot, size))
+}
+
+/// Get the byte offset of a part of a struct.
+fn part_offset(part: usize, word: Type) -> i32 {
+    (part as u32 * word.bytes()) as i32
+}
+
+/// Load part `part` of the struct of `size` bytes at `addr`.
+///
+/// The last part of the struct may be smaller than a word. It is loaded piece by piece, so no
+/// bytes after the end of the struct are read.
+fn load_struct_part(
+    pos: &mut FuncCursor,
+    addr: Value,
+    size: u32,
+    part: usize,
+    word: Type,
+) -> Value {
+    let offset = part_offset(part, word);
+    let bytes = (size - offset as u32).min(word.bytes());
+    if bytes == word.bytes() {
+        return pos.ins().load(word, MemFlags::new(), addr, offset);
+    }
+
+    let mut value = None;
+    let mut loaded = 0;
+    for &piece in &[4, 2, 1] {
+        if bytes - loaded < piece {
+            continue;
+        }
+        let piece_offset = offset + loaded as i32;
+        let mut bits = match piece {
+            4 => pos.ins().uload32(MemFlags::new(), addr, piece_offset),
+            2 => pos.ins().uload16(word, MemFlags::new(), addr, piece_offset),
+            _ => pos.ins().uload8(word, MemFlags::new(), addr, piece_offset),
+        };
+        if loaded > 0 {
+            bits = pos.ins().ishl_imm(bits, i64::from(loaded * 8));
+        }
+        value = Some(match value {
+            Some(v) => pos.ins().bor(v, bits),
+            None => bits,
+        });
+        loaded += piece;
+    }
+    value.expect("Empty struct part")
 }
 
 /// Legalize the entry block parameters after `func`'s signature has been legalized.
@@ -115,6 +499,7 @@ fn legalize_entry_params(func: &mut Function, entry: Ebb) {
                     debug_assert!(!has_stack_limit, "Multiple stack_limit arguments found");
                     has_stack_limit = true;
                 }
+                ArgumentPurpose::StructArgument(_) => {}
                 _ => panic!("Unexpected special-purpose arg {}", abi_type),
             }
             abi_arg += 1;
@@ -146,7 +531,7 @@ fn legalize_entry_params(func: &mut Function, entry: Ebb) {
     for &arg in &pos.func.signature.params[abi_arg..] {
         match arg.purpose {
             // Any normal parameters should have been processed above.
-            ArgumentPurpose::Normal => {
+            ArgumentPurpose::Normal | ArgumentPurpose::StructArgument(_) => {
                 panic!("Leftover arg: {}", arg);
             }
             // The callee-save parameters should not appear until after register allocation is
@@ -316,6 +701,18 @@ where
             // this value.
             pos.ins().with_results([into_result]).ireduce(ty, arg)
         }
+        // ABI argument is an integer that is non-zero when the boolean we want is true.
+        ValueConversion::BoolToInt(abi_ty) => {
+            let arg = convert_from_abi(pos, abi_ty, None, get_arg);
+            if ty == types::B1 {
+                pos.ins()
+                    .with_results([into_result])
+                    .icmp_imm(IntCC::NotEqual, arg, 0)
+            } else {
+                let b = pos.ins().icmp_imm(IntCC::NotEqual, arg, 0);
+                pos.ins().with_results([into_result]).bextend(ty, b)
+            }
+        }
     }
 }
 
@@ -375,6 +772,10 @@ fn convert_to_abi<PutArg>(
             let arg = pos.ins().uextend(abi_ty, value);
             convert_to_abi(pos, cfg, arg, put_arg);
         }
+        ValueConversion::BoolToInt(abi_ty) => {
+            let arg = pos.ins().bint(abi_ty, value);
+            convert_to_abi(pos, cfg, arg, put_arg);
+        }
     }
 }
 
@@ -34,10 +34,17 @@ pub fn expand_global_value(
             offset,
             global_type,
         } => iadd_imm_addr(inst, func, base, offset.into(), global_type),
+        ir::GlobalValueData::IAddScaled {
+            base,
+            index,
+            scale,
+            global_type,
+        } => iadd_scaled_addr(inst, func, base, index, scale.into(), global_type, isa),
         ir::GlobalValueData::Load {
             base,
             offset,
             global_type,
+            ..
         } => load_addr(inst, func, base, offset, global_type, isa),
         ir::GlobalValueData::Symbol { .. } => symbol(inst, func, gv, isa),
     }
@@ -51,10 +58,7 @@ fn vmctx_addr(inst: ir::Inst, func: &mut ir::Function) {
         .expect("Missing vmctx parameter");
 
     // Replace the `global_value` instruction's value with an alias to the vmctx arg.
-    let result = func.dfg.first_result(inst);
-    func.dfg.clear_results(inst);
-    func.dfg.change_to_alias(result, vmctx);
-    func.layout.remove_inst(inst);
+    FuncCursor::new(func).remove_and_replace_with_alias(inst, vmctx);
 }
 
 /// Expand a `global_value` instruction for an iadd_imm global.
@@ -66,19 +70,60 @@ fn iadd_imm_addr(
     global_type: ir::Type,
 ) {
     let mut pos = FuncCursor::new(func).at_inst(inst);
+    let lhs = operand(&mut pos, base, global_type);
+
+    // Simply replace the `global_value` instruction with an `iadd_imm`, reusing the result value.
+    pos.func.dfg.replace(inst).iadd_imm(lhs, offset);
+}
+
+/// Expand a `global_value` instruction for an iadd_scaled global.
+fn iadd_scaled_addr(
+    inst: ir::Inst,
+    func: &mut ir::Function,
+    base: ir::GlobalValue,
+    index: ir::GlobalValue,
+    scale: i64,
+    global_type: ir::Type,
+    isa: &TargetIsa,
+) {
+    let index_type = func.global_values[index].global_type(isa.pointer_type());
+    let mut pos = FuncCursor::new(func).at_inst(inst);
+    pos.use_srcloc(inst);
 
-    // Get the value for the lhs. For tidiness, expand VMContext here so that we avoid
-    // `vmctx_addr` which creates an otherwise unneeded value alias.
-    let lhs = if let ir::GlobalValueData::VMContext = pos.func.global_values[base] {
+    let lhs = operand(&mut pos, base, global_type);
+    let mut rhs = operand(&mut pos, index, index_type);
+    if index_type != global_type {
+        rhs = pos.ins().uextend(global_type, rhs);
+    }
+    let rhs = scale_index(&mut pos, rhs, scale);
+
+    pos.func.dfg.replace(inst).iadd(lhs, rhs);
+}
+
+/// Multiply `index` by `scale`, using a shift when `scale` is a power of two.
+pub fn scale_index(pos: &mut FuncCursor, index: ir::Value, scale: i64) -> ir::Value {
+    if scale == 1 {
+        index
+    } else if scale > 0 && scale & (scale - 1) == 0 {
+        pos.ins().ishl_imm(index, i64::from(scale.trailing_zeros()))
+    } else {
+        pos.ins().imul_imm(index, scale)
+    }
+}
+
+/// Get the value of the global value `gv` of type `ty` as an operand for an instruction inserted
+/// at `pos`.
+///
+/// For tidiness, expand VMContext here so that we avoid `vmctx_addr` which creates an otherwise
+/// unneeded value alias.
+fn operand(pos: &mut FuncCursor, gv: ir::GlobalValue, ty: ir::Type) -> ir::Value {
+    if let ir::GlobalValueData::VMContext = pos.func.global_values[gv] {
         pos.func
             .special_param(ir::ArgumentPurpose::VMContext)
             .expect("Missing vmctx parameter")
     } else {
-        pos.ins().global_value(global_type, base)
-    };
-
-    // Simply replace the `global_value` instruction with an `iadd_imm`, reusing the result value.
-    pos.func.dfg.replace(inst).iadd_imm(lhs, offset);
+        pos.ins().global_value(ty, gv)
+    }
 }
 
 /// Expand a `global_value` instruction for a load global.
@@ -90,6 +135,14 @@ fn load_addr(
     global_type: ir::Type,
     isa: &TargetIsa,
 ) {
+    // A value stored at a symbol can often be loaded by a single instruction that addresses the
+    // symbol, without materializing its address first.
+    if let ir::GlobalValueData::Symbol { .. } = func.global_values[base] {
+        if symbol_load(inst, func, base, offset, global_type, isa) {
+            return;
+        }
+    }
+
     // We need to load a pointer from the `base` global value, so insert a new `global_value`
     // instruction. This depends on the iterative legalization loop. Note that the IR verifier
     // detects any cycles in the `load` globals.
@@ -97,26 +150,59 @@ fn load_addr(
     let mut pos = FuncCursor::new(func).at_inst(inst);
     pos.use_srcloc(inst);
 
-    // Get the value for the base. For tidiness, expand VMContext here so that we avoid
-    // `vmctx_addr` which creates an otherwise unneeded value alias.
-    let base_addr = if let ir::GlobalValueData::VMContext = pos.func.global_values[base] {
-        pos.func
-            .special_param(ir::ArgumentPurpose::VMContext)
-            .expect("Missing vmctx parameter")
-    } else {
-        pos.ins().global_value(ptr_ty, base)
-    };
-
-    // Global-value loads are always notrap and aligned.
-    let mut mflags = ir::MemFlags::new();
-    mflags.set_notrap();
-    mflags.set_aligned();
+    let base_addr = operand(&mut pos, base, ptr_ty);
 
     // Perform the load.
     pos.func
         .dfg
         .replace(inst)
-        .load(global_type, mflags, base_addr, offset);
+        .load(global_type, load_flags(), base_addr, offset);
+}
+
+/// Replace a `global_value` instruction for a load global whose base is the symbol `base` with a
+/// load that addresses the symbol directly, like `x86_load_pcrel` on x86-64.
+///
+/// Return false if the ISA has no such load for the symbol, which happens when the symbol may be
+/// too far from the code to be addressed relative to it.
+fn symbol_load(
+    inst: ir::Inst,
+    func: &mut ir::Function,
+    base: ir::GlobalValue,
+    offset: ir::immediates::Offset32,
+    global_type: ir::Type,
+    isa: &TargetIsa,
+) -> bool {
+    if offset
+        .try_add_i64(func.global_values[base].symbol_offset())
+        .is_none()
+    {
+        return false;
+    }
+
+    let data = ir::InstructionData::LoadGlobal {
+        opcode: ir::Opcode::X86LoadPcrel,
+        flags: load_flags(),
+        global_value: base,
+        offset,
+    };
+    match isa.encode(func, &data, global_type) {
+        Ok(enc) => {
+            func.dfg[inst] = data;
+            func.encodings[inst] = enc;
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+/// Get the flags of the load expanded from a load global value.
+///
+/// Global-value loads are always notrap and aligned.
+pub fn load_flags() -> ir::MemFlags {
+    let mut mflags = ir::MemFlags::new();
+    mflags.set_notrap();
+    mflags.set_aligned();
+    mflags
 }
 
 /// Expand a `global_value` instruction for a symbolic name global.
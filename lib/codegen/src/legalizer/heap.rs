@@ -1,23 +1,317 @@
 //! Legalization of heaps.
 //!
 //! This module exports the `expand_heap_addr` function which transforms a `heap_addr`
-//! instruction into code that depends on the kind of heap referenced.
+//! instruction into code that depends on the kind of heap referenced, and the
+//! `expand_unchecked_heap_addr` function which leaves out the bounds check. Both check the
+//! alignment of the offset after the bounds check for heaps with `check_alignment`.
+//!
+//! The `mark_guarded_heap_accesses` function prepares the loads and stores that will rely on the
+//! guard pages of their heap for trap table emission.
+//!
+//! The `expand_indexed_heap_addrs` function expands the `heap_addr` instructions of dynamic heaps
+//! whose base or bound is found through an `iadd_scaled` global value ahead of the other
+//! instructions, sharing the computation of the global values between them.
 
+use super::globalvalue::{load_flags, scale_index};
 use cursor::{Cursor, FuncCursor};
+use entity::SecondaryMap;
 use flowgraph::ControlFlowGraph;
 use ir::condcodes::IntCC;
 use ir::{self, InstBuilder};
 use isa::TargetIsa;
+use known_bits::known_zero_bits;
+use packed_option::PackedOption;
+use std::vec::Vec;
 
 /// Expand a `heap_addr` instruction according to the definition of the heap.
 pub fn expand_heap_addr(
     inst: ir::Inst,
     func: &mut ir::Function,
     cfg: &mut ControlFlowGraph,
-    _isa: &TargetIsa,
+    isa: &TargetIsa,
 ) {
-    // Unpack the instruction.
-    let (heap, offset, access_size) = match func.dfg[inst] {
+    let (heap, offset, access_size) = unpack_heap_addr(inst, func);
+    match func.heaps[heap].style {
+        ir::HeapStyle::Dynamic { .. } => {
+            dynamic_addr(inst, heap, offset, access_size, None, func, isa)
+        }
+        ir::HeapStyle::Static { bound } => {
+            static_addr(inst, heap, offset, access_size, bound.into(), func, cfg)
+        }
+    }
+}
+
+/// Expand a `heap_addr` instruction that is known to be in bounds into the address computation
+/// and the alignment check.
+pub fn expand_unchecked_heap_addr(inst: ir::Inst, func: &mut ir::Function) {
+    let (heap, offset, access_size) = unpack_heap_addr(inst, func);
+    let offset_ty = func.dfg.value_type(offset);
+    let addr_ty = func.dfg.value_type(func.dfg.first_result(inst));
+    let mut pos = FuncCursor::new(func).at_inst(inst);
+    pos.use_srcloc(inst);
+    check_alignment(&mut pos, heap, offset, access_size);
+    compute_addr(inst, heap, addr_ty, offset, offset_ty, None, pos.func);
+}
+
+/// Expand the `heap_addr` instructions of the dynamic heaps whose base or bound depends on an
+/// `iadd_scaled` global value.
+///
+/// Such a chain of global values usually loads the address of a per-instance table from the VM
+/// context, and then the base and bound of a heap from the entry selected by an index. Expanding
+/// each `global_value` instruction separately would repeat the whole chain for every heap access,
+/// so the global values in the chains that can't change during the execution of the function are
+/// computed once at the start of the entry EBB instead, and shared by all the heap accesses.
+/// Loads that aren't `readonly` are still performed at each heap access, from the shared address.
+pub fn expand_indexed_heap_addrs(func: &mut ir::Function, isa: &TargetIsa) {
+    let mut indexed = Vec::new();
+    for ebb in func.layout.ebbs() {
+        for inst in func.layout.ebb_insts(ebb) {
+            if let ir::InstructionData::HeapAddr { heap, .. } = func.dfg[inst] {
+                let heap_data = &func.heaps[heap];
+                if let ir::HeapStyle::Dynamic { bound_gv } = heap_data.style {
+                    if is_indexed(func, heap_data.base) || is_indexed(func, bound_gv) {
+                        indexed.push(inst);
+                    }
+                }
+            }
+        }
+    }
+    if indexed.is_empty() {
+        return;
+    }
+
+    let entry = func.layout.entry_block().expect("Function has no entry EBB");
+    let entry_inst = func
+        .layout
+        .first_inst(entry)
+        .expect("Entry EBB has no instructions");
+    let mut chains = GlobalValueChains {
+        entry_inst,
+        pointer_type: isa.pointer_type(),
+        values: SecondaryMap::new(),
+    };
+    for inst in indexed {
+        let (heap, offset, access_size) = unpack_heap_addr(inst, func);
+        let bound_gv = match func.heaps[heap].style {
+            ir::HeapStyle::Dynamic { bound_gv } => bound_gv,
+            ir::HeapStyle::Static { .. } => unreachable!(),
+        };
+        let base_gv = func.heaps[heap].base;
+        let values = HeapValues {
+            bound: chains.value(func, bound_gv, inst),
+            base: chains.value(func, base_gv, inst),
+        };
+        dynamic_addr(inst, heap, offset, access_size, Some(values), func, isa);
+    }
+}
+
+/// Does the value of `gv` depend on an `iadd_scaled` global value?
+fn is_indexed(func: &ir::Function, gv: ir::GlobalValue) -> bool {
+    match func.global_values[gv] {
+        ir::GlobalValueData::VMContext | ir::GlobalValueData::Symbol { .. } => false,
+        ir::GlobalValueData::IAddScaled { .. } => true,
+        ir::GlobalValueData::IAddImm { base, .. } | ir::GlobalValueData::Load { base, .. } => {
+            is_indexed(func, base)
+        }
+    }
+}
+
+/// Is the value of `gv` the same for the whole execution of the function?
+fn is_invariant(func: &ir::Function, gv: ir::GlobalValue) -> bool {
+    match func.global_values[gv] {
+        ir::GlobalValueData::VMContext | ir::GlobalValueData::Symbol { .. } => true,
+        ir::GlobalValueData::IAddImm { base, .. } => is_invariant(func, base),
+        ir::GlobalValueData::IAddScaled { base, index, .. } => {
+            is_invariant(func, base) && is_invariant(func, index)
+        }
+        ir::GlobalValueData::Load { base, readonly, .. } => readonly && is_invariant(func, base),
+    }
+}
+
+/// The values of the global values computed by `expand_indexed_heap_addrs`.
+struct GlobalValueChains {
+    /// The first instruction of the entry EBB, where the invariant global values are computed.
+    entry_inst: ir::Inst,
+
+    /// The pointer type of the target ISA.
+    pointer_type: ir::Type,
+
+    /// The values of the invariant global values computed so far.
+    values: SecondaryMap<ir::GlobalValue, PackedOption<ir::Value>>,
+}
+
+impl GlobalValueChains {
+    /// Get the value of `gv` for the heap access `inst`.
+    ///
+    /// Invariant global values are computed at the start of the entry EBB the first time they are
+    /// needed, and the other ones are computed before `inst`.
+    fn value(&mut self, func: &mut ir::Function, gv: ir::GlobalValue, inst: ir::Inst) -> ir::Value {
+        if let Some(value) = self.values[gv].expand() {
+            return value;
+        }
+
+        let invariant = is_invariant(func, gv);
+        let value = match func.global_values[gv].clone() {
+            ir::GlobalValueData::VMContext => func
+                .special_param(ir::ArgumentPurpose::VMContext)
+                .expect("Missing vmctx parameter"),
+            ir::GlobalValueData::Symbol { .. } => {
+                let pointer_type = self.pointer_type;
+                self.cursor(func, invariant, inst)
+                    .ins()
+                    .symbol_value(pointer_type, gv)
+            }
+            ir::GlobalValueData::IAddImm { base, offset, .. } => {
+                let base = self.value(func, base, inst);
+                self.cursor(func, invariant, inst)
+                    .ins()
+                    .iadd_imm(base, offset)
+            }
+            ir::GlobalValueData::IAddScaled {
+                base,
+                index,
+                scale,
+                global_type,
+            } => {
+                let base = self.value(func, base, inst);
+                let index = self.value(func, index, inst);
+                let mut pos = self.cursor(func, invariant, inst);
+                let index = if pos.func.dfg.value_type(index) != global_type {
+                    pos.ins().uextend(global_type, index)
+                } else {
+                    index
+                };
+                let index = scale_index(&mut pos, index, scale.into());
+                pos.ins().iadd(base, index)
+            }
+            ir::GlobalValueData::Load {
+                base,
+                offset,
+                global_type,
+                ..
+            } => {
+                let base = self.value(func, base, inst);
+                self.cursor(func, invariant, inst)
+                    .ins()
+                    .load(global_type, load_flags(), base, offset)
+            }
+        };
+
+        if invariant {
+            self.values[gv] = value.into();
+        }
+        value
+    }
+
+    /// Get a cursor for inserting the computation of a global value for the heap access `inst`.
+    fn cursor<'f>(
+        &self,
+        func: &'f mut ir::Function,
+        invariant: bool,
+        inst: ir::Inst,
+    ) -> FuncCursor<'f> {
+        if invariant {
+            FuncCursor::new(func).at_inst(self.entry_inst)
+        } else {
+            let mut pos = FuncCursor::new(func).at_inst(inst);
+            pos.use_srcloc(inst);
+            pos
+        }
+    }
+}
+
+/// Clear the `notrap` flag of the loads and stores that rely on guard pages to catch out-of-bounds
+/// heap accesses.
+///
+/// This must be done before the `heap_addr` instructions are expanded. Afterwards, the code
+/// emitted for these loads and stores is reported to the `TrapSink` with the `heap_oob` trap code.
+pub fn mark_guarded_heap_accesses(func: &mut ir::Function) {
+    let mut guarded = Vec::new();
+    for ebb in func.layout.ebbs() {
+        for inst in func.layout.ebb_insts(ebb) {
+            let (flags, addr, offset) = match func.dfg[inst] {
+                ir::InstructionData::Load {
+                    flags, arg, offset, ..
+                } => (flags, arg, offset),
+                ir::InstructionData::Store {
+                    flags,
+                    args,
+                    offset,
+                    ..
+                } => (flags, args[1], offset),
+                _ => continue,
+            };
+            if flags.notrap() && is_guarded_access(func, inst, addr, offset.into()) {
+                guarded.push(inst);
+            }
+        }
+    }
+
+    for inst in guarded {
+        match func.dfg[inst] {
+            ir::InstructionData::Load { ref mut flags, .. }
+            | ir::InstructionData::Store { ref mut flags, .. } => flags.clear_notrap(),
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// Does the load or store `inst` of `addr + offset` access bytes that a `heap_addr` doesn't
+/// check explicitly?
+fn is_guarded_access(func: &ir::Function, inst: ir::Inst, addr: ir::Value, offset: i64) -> bool {
+    // Look through the `iadd_imm` instructions that adjust a heap address for large offsets.
+    let mut addr = func.dfg.resolve_aliases(addr);
+    let mut offset = offset;
+    let heap_addr = loop {
+        let def = match func.dfg.value_def(addr) {
+            ir::ValueDef::Result(def, _) => def,
+            ir::ValueDef::Param(_, _) => return false,
+        };
+        match func.dfg[def] {
+            ir::InstructionData::BinaryImm {
+                opcode: ir::Opcode::IaddImm,
+                arg,
+                imm,
+            } => {
+                offset = match offset.checked_add(imm.into()) {
+                    Some(offset) => offset,
+                    None => return true,
+                };
+                addr = func.dfg.resolve_aliases(arg);
+            }
+            ir::InstructionData::HeapAddr { .. } => break def,
+            _ => return false,
+        }
+    };
+
+    let (heap, _, access_size) = unpack_heap_addr(heap_addr, func);
+    let checked_size = match func.heaps[heap].style {
+        // A dynamic heap with a big enough guard only checks the offset itself.
+        ir::HeapStyle::Dynamic { .. }
+            if i64::from(access_size) <= func.heaps[heap].guard_size.into() =>
+        {
+            0
+        }
+        _ => i64::from(access_size),
+    };
+    let size = i64::from(memory_access_size(func, inst));
+    offset < 0 || offset + size > checked_size
+}
+
+/// Get the number of bytes in memory accessed by the load or store `inst`.
+fn memory_access_size(func: &ir::Function, inst: ir::Inst) -> u32 {
+    match func.dfg[inst].opcode() {
+        ir::Opcode::Uload8 | ir::Opcode::Sload8 | ir::Opcode::Istore8 => 1,
+        ir::Opcode::Uload16 | ir::Opcode::Sload16 | ir::Opcode::Istore16 => 2,
+        ir::Opcode::Uload32 | ir::Opcode::Sload32 | ir::Opcode::Istore32 => 4,
+        ir::Opcode::Store => func.dfg.value_type(func.dfg.inst_args(inst)[0]).bytes(),
+        _ => func.dfg.value_type(func.dfg.first_result(inst)).bytes(),
+    }
+}
+
+/// Unpack the heap, offset, and access size of a `heap_addr` instruction.
+fn unpack_heap_addr(inst: ir::Inst, func: &ir::Function) -> (ir::Heap, ir::Value, u32) {
+    match func.dfg[inst] {
         ir::InstructionData::HeapAddr {
             opcode,
             heap,
@@ -28,38 +322,51 @@ pub fn expand_heap_addr(
             (heap, arg, imm.into())
         }
         _ => panic!("Wanted heap_addr: {}", func.dfg.display_inst(inst, None)),
-    };
-
-    match func.heaps[heap].style {
-        ir::HeapStyle::Dynamic { bound_gv } => {
-            dynamic_addr(inst, heap, offset, access_size, bound_gv, func)
-        }
-        ir::HeapStyle::Static { bound } => {
-            static_addr(inst, heap, offset, access_size, bound.into(), func, cfg)
-        }
     }
 }
 
+/// The base and bound of a heap, computed ahead of a `heap_addr` instruction.
+#[derive(Clone, Copy)]
+struct HeapValues {
+    base: ir::Value,
+    bound: ir::Value,
+}
+
 /// Expand a `heap_addr` for a dynamic heap.
+///
+/// The base and bound are taken from `values` if they were computed already, and are computed from
+/// the global values of the heap otherwise.
 fn dynamic_addr(
     inst: ir::Inst,
     heap: ir::Heap,
     offset: ir::Value,
     access_size: u32,
-    bound_gv: ir::GlobalValue,
+    values: Option<HeapValues>,
     func: &mut ir::Function,
+    isa: &TargetIsa,
 ) {
     let access_size = i64::from(access_size);
     let offset_ty = func.dfg.value_type(offset);
     let addr_ty = func.dfg.value_type(func.dfg.first_result(inst));
     let min_size = func.heaps[heap].min_size.into();
+    let guard_size = func.heaps[heap].guard_size.into();
     let mut pos = FuncCursor::new(func).at_inst(inst);
     pos.use_srcloc(inst);
 
     // Start with the bounds check. Trap if `offset + access_size > bound`.
-    let bound = pos.ins().global_value(offset_ty, bound_gv);
+    let bound = match values {
+        Some(values) => values.bound,
+        None => match pos.func.heaps[heap].style {
+            ir::HeapStyle::Dynamic { bound_gv } => pos.ins().global_value(offset_ty, bound_gv),
+            ir::HeapStyle::Static { .. } => panic!("Wanted a dynamic heap"),
+        },
+    };
     let oob;
-    if access_size == 1 {
+    if access_size <= guard_size {
+        // The guard pages follow the bound, so an access starting at `offset <= bound` either
+        // hits the heap or traps in the guard pages. We only need to check `offset > bound`.
+        oob = pos.ins().icmp(IntCC::UnsignedGreaterThan, offset, bound);
+    } else if access_size == 1 {
         // `offset > bound - 1` is the same as `offset >= bound`.
         oob = pos
             .ins()
@@ -74,15 +381,40 @@ fn dynamic_addr(
     } else {
         // We need an overflow check for the adjusted offset.
         let access_size_val = pos.ins().iconst(offset_ty, access_size);
-        let (adj_offset, overflow) = pos.ins().iadd_cout(offset, access_size_val);
-        pos.ins().trapnz(overflow, ir::TrapCode::HeapOutOfBounds);
+        let adj_offset = if has_carry_flags(isa, pos.func, offset) {
+            // Trap on the carry in the CPU flags, without materializing it as a boolean first.
+            let (adj_offset, carry) = pos.ins().iadd_ifcout(offset, access_size_val);
+            pos.ins().trapif(
+                IntCC::UnsignedLessThan,
+                carry,
+                ir::TrapCode::HeapOutOfBounds,
+                0,
+            );
+            adj_offset
+        } else {
+            let (adj_offset, overflow) = pos.ins().iadd_cout(offset, access_size_val);
+            pos.ins().trapnz(overflow, ir::TrapCode::HeapOutOfBounds, 0);
+            adj_offset
+        };
         oob = pos
             .ins()
             .icmp(IntCC::UnsignedGreaterThan, adj_offset, bound);
     }
-    pos.ins().trapnz(oob, ir::TrapCode::HeapOutOfBounds);
+    pos.ins().trapnz(oob, ir::TrapCode::HeapOutOfBounds, 0);
+    check_alignment(&mut pos, heap, offset, access_size as u32);
 
-    compute_addr(inst, heap, addr_ty, offset, offset_ty, pos.func);
+    let base = values.map(|values| values.base);
+    compute_addr(inst, heap, addr_ty, offset, offset_ty, base, pos.func);
+}
+
+/// Can `isa` encode an `iadd_ifcout` that adds to `x`, so the carry of the add can be tested in
+/// the CPU flags directly?
+fn has_carry_flags(isa: &TargetIsa, func: &ir::Function, x: ir::Value) -> bool {
+    let data = ir::InstructionData::Binary {
+        opcode: ir::Opcode::IaddIfcout,
+        args: [x, x],
+    };
+    isa.encode(func, &data, func.dfg.value_type(x)).is_ok()
 }
 
 /// Expand a `heap_addr` for a static heap.
@@ -104,7 +436,7 @@ fn static_addr(
     // Start with the bounds check. Trap if `offset + access_size > bound`.
     if access_size > bound {
         // This will simply always trap since `offset >= 0`.
-        pos.ins().trap(ir::TrapCode::HeapOutOfBounds);
+        pos.ins().trap(ir::TrapCode::HeapOutOfBounds, 0);
         pos.func.dfg.replace(inst).iconst(addr_ty, 0);
 
         // Split Ebb, as the trap is a terminator instruction.
@@ -131,19 +463,42 @@ fn static_addr(
             pos.ins()
                 .icmp_imm(IntCC::UnsignedGreaterThan, offset, limit)
         };
-        pos.ins().trapnz(oob, ir::TrapCode::HeapOutOfBounds);
+        pos.ins().trapnz(oob, ir::TrapCode::HeapOutOfBounds, 0);
     }
+    check_alignment(&mut pos, heap, offset, access_size as u32);
 
-    compute_addr(inst, heap, addr_ty, offset, offset_ty, pos.func);
+    compute_addr(inst, heap, addr_ty, offset, offset_ty, None, pos.func);
+}
+
+/// Emit a check that `offset` is aligned for an access of `access_size` bytes to `heap`, if the
+/// heap checks alignment.
+///
+/// The offset must be a multiple of the largest power of two dividing the access size, which is
+/// the natural alignment of a load or store of that size. Nothing is emitted when the offset is
+/// known to be aligned.
+fn check_alignment(pos: &mut FuncCursor, heap: ir::Heap, offset: ir::Value, access_size: u32) {
+    if !pos.func.heaps[heap].check_alignment {
+        return;
+    }
+    let align_mask = u64::from(access_size & access_size.wrapping_neg()).saturating_sub(1);
+    if align_mask & !known_zero_bits(pos.func, offset) == 0 {
+        return;
+    }
+    let misaligned = pos.ins().band_imm(offset, align_mask as i64);
+    pos.ins()
+        .trapnz(misaligned, ir::TrapCode::HeapMisaligned, 0);
 }
 
 /// Emit code for the base address computation of a `heap_addr` instruction.
+///
+/// The heap base is `base` if it was computed already.
 fn compute_addr(
     inst: ir::Inst,
     heap: ir::Heap,
     addr_ty: ir::Type,
     mut offset: ir::Value,
     offset_ty: ir::Type,
+    base: Option<ir::Value>,
     func: &mut ir::Function,
 ) {
     let mut pos = FuncCursor::new(func).at_inst(inst);
@@ -155,7 +510,12 @@ fn compute_addr(
     }
 
     // Add the heap base address base
-    let base_gv = pos.func.heaps[heap].base;
-    let base = pos.ins().global_value(addr_ty, base_gv);
+    let base = match base {
+        Some(base) => base,
+        None => {
+            let base_gv = pos.func.heaps[heap].base;
+            pos.ins().global_value(addr_ty, base_gv)
+        }
+    };
     pos.func.dfg.replace(inst).iadd(base, offset);
 }
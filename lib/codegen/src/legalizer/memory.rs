@@ -0,0 +1,176 @@
+//! Inline expansion of small memory library calls.
+//!
+//! This module exports the `expand_small_memory_call` function which replaces a call to the
+//! `memcpy`, `memmove`, or `memset` library routines with a constant size by a straight-line
+//! sequence of loads and stores. The largest size that is expanded is configured by the
+//! `inline_memory_ops_limit` setting.
+
+use cursor::{Cursor, FuncCursor};
+use ir::{self, InstBuilder, LibCall, MemFlags, Type};
+use isa::TargetIsa;
+use known_bits::known_zero_bits;
+use std::vec::Vec;
+
+/// Expand `inst` inline if it is a small constant-size call to `memcpy`, `memmove`, or `memset`.
+///
+/// Return true if the call was replaced.
+pub fn expand_small_memory_call(inst: ir::Inst, func: &mut ir::Function, isa: &TargetIsa) -> bool {
+    if func.is_patchable(inst) {
+        return false;
+    }
+    let libcall = match func.dfg[inst] {
+        ir::InstructionData::Call {
+            opcode: ir::Opcode::Call,
+            func_ref,
+            ..
+        } => isa
+            .libcall_names()
+            .libcall(&func.dfg.ext_funcs[func_ref].name),
+        _ => return false,
+    };
+    match libcall {
+        Some(LibCall::Memcpy) | Some(LibCall::Memmove) | Some(LibCall::Memset) => {}
+        _ => return false,
+    }
+
+    // The C routines return their first argument, but the signatures we use for them don't.
+    let (dest, src, size) = {
+        let args = func.dfg.inst_args(inst);
+        if args.len() != 3 || !func.dfg.inst_results(inst).is_empty() {
+            return false;
+        }
+        (args[0], args[1], args[2])
+    };
+    let size = match constant_value(func, size) {
+        Some(size) if size >= 0 && size <= i64::from(isa.flags().inline_memory_ops_limit()) => {
+            size as u64
+        }
+        _ => return false,
+    };
+
+    let accesses = split_accesses(size, isa.pointer_bytes());
+    let dest_align = known_alignment(func, dest);
+    let src_align = known_alignment(func, src);
+
+    let mut pos = FuncCursor::new(func).at_inst(inst);
+    pos.use_srcloc(inst);
+
+    match libcall {
+        Some(LibCall::Memcpy) => {
+            for &(ty, offset) in &accesses {
+                let value = pos.ins().load(ty, access_flags(ty, src_align), src, offset);
+                pos.ins()
+                    .store(access_flags(ty, dest_align), value, dest, offset);
+            }
+        }
+        Some(LibCall::Memmove) => {
+            // The source and destination may overlap, so read everything before writing anything.
+            let values: Vec<_> = accesses
+                .iter()
+                .map(|&(ty, offset)| pos.ins().load(ty, access_flags(ty, src_align), src, offset))
+                .collect();
+            for (&(ty, offset), value) in accesses.iter().zip(values) {
+                pos.ins()
+                    .store(access_flags(ty, dest_align), value, dest, offset);
+            }
+        }
+        Some(LibCall::Memset) => {
+            // The accesses are in decreasing size order, so the narrower ones can use the low
+            // bits of the fill value of the first one.
+            let wide_ty = accesses.first().map_or(ir::types::I8, |&(ty, _)| ty);
+            let wide_fill = splat_byte(&mut pos, src, wide_ty);
+            for &(ty, offset) in &accesses {
+                let fill = if ty == wide_ty {
+                    wide_fill
+                } else {
+                    pos.ins().ireduce(ty, wide_fill)
+                };
+                pos.ins()
+                    .store(access_flags(ty, dest_align), fill, dest, offset);
+            }
+        }
+        _ => unreachable!(),
+    }
+
+    pos.remove_inst();
+    true
+}
+
+/// Get the value of `value` if it is defined by an `iconst` that isn't patchable.
+fn constant_value(func: &ir::Function, value: ir::Value) -> Option<i64> {
+    match func.dfg.value_def(value) {
+        ir::ValueDef::Result(def, _) if func.is_patchable(def) => None,
+        ir::ValueDef::Result(def, _) => match func.dfg[def] {
+            ir::InstructionData::UnaryImm {
+                opcode: ir::Opcode::Iconst,
+                imm,
+            } => Some(imm.into()),
+            _ => None,
+        },
+        ir::ValueDef::Param(_, _) => None,
+    }
+}
+
+/// Split `size` bytes into accesses of the widest integer types up to `max_bytes`.
+///
+/// Returns the type and offset of each access, in increasing address order.
+fn split_accesses(size: u64, max_bytes: u8) -> Vec<(Type, i32)> {
+    let mut accesses = Vec::new();
+    let mut offset = 0;
+    let mut access_bytes = u64::from(max_bytes);
+    while offset < size {
+        while size - offset < access_bytes {
+            access_bytes /= 2;
+        }
+        let ty = Type::int(access_bytes as u16 * 8).unwrap();
+        accesses.push((ty, offset as i32));
+        offset += access_bytes;
+    }
+    accesses
+}
+
+/// Get the alignment in bytes that `addr` is known to have.
+fn known_alignment(func: &ir::Function, addr: ir::Value) -> u64 {
+    let zeros = (!known_zero_bits(func, addr)).trailing_zeros().min(63);
+    1 << zeros
+}
+
+/// Get the flags for an access of type `ty` to an address with alignment `align`.
+///
+/// The accesses start at offsets that are multiples of their size, so they are aligned when the
+/// address is.
+fn access_flags(ty: Type, align: u64) -> MemFlags {
+    let mut flags = MemFlags::new();
+    if align >= u64::from(ty.bytes()) {
+        flags.set_aligned();
+    }
+    flags
+}
+
+/// Repeat the low byte of the `i32` value `byte` in every byte of a value of type `ty`.
+///
+/// The fill byte of `memset` is passed as an `i32`, and only its low 8 bits are used.
+fn splat_byte(pos: &mut FuncCursor, byte: ir::Value, ty: Type) -> ir::Value {
+    if let Some(fill) = constant_value(pos.func, byte) {
+        let fill = fill as u64 & 0xff;
+        let pattern = (0..ty.bytes()).fold(0, |acc, _| acc << 8 | fill);
+        return pos.ins().iconst(ty, pattern as i64);
+    }
+
+    let byte = pos.ins().band_imm(byte, 0xff);
+    let byte = match ty.bits() {
+        8 | 16 | 32 => byte,
+        _ => pos.ins().uextend(ty, byte),
+    };
+    let pattern = (0..ty.bytes()).fold(0u64, |acc, _| acc << 8 | 1);
+    let fill = if pattern == 1 {
+        byte
+    } else {
+        pos.ins().imul_imm(byte, pattern as i64)
+    };
+    if ty.bits() < 32 {
+        pos.ins().ireduce(ty, fill)
+    } else {
+        fill
+    }
+}
@@ -16,6 +16,7 @@
 use bitset::BitSet;
 use cursor::{Cursor, FuncCursor};
 use flowgraph::ControlFlowGraph;
+use ir::condcodes::IntCC;
 use ir::types::I32;
 use ir::{self, InstBuilder, MemFlags};
 use isa::TargetIsa;
@@ -26,13 +27,17 @@ mod call;
 mod globalvalue;
 mod heap;
 mod libcall;
-mod split;
+mod memory;
+pub mod split;
 mod table;
 
+pub use self::boundary::legalize_call_signature;
 use self::call::expand_call;
 use self::globalvalue::expand_global_value;
-use self::heap::expand_heap_addr;
-use self::libcall::expand_as_libcall;
+use self::heap::{expand_heap_addr, expand_indexed_heap_addrs, mark_guarded_heap_accesses};
+pub use self::heap::expand_unchecked_heap_addr;
+pub use self::libcall::expand_as_libcall;
+use self::memory::expand_small_memory_call;
 use self::table::expand_table_addr;
 
 /// Legalize `inst` for `isa`. Return true if any changes to the code were
@@ -47,6 +52,9 @@ fn legalize_inst(
 
     // Check for ABI boundaries that need to be converted to the legalized signature.
     if opcode.is_call() {
+        if expand_small_memory_call(inst, pos.func, isa) {
+            return true;
+        }
         if boundary::handle_call_abi(inst, pos.func, cfg) {
             return true;
         }
@@ -56,6 +64,10 @@ fn legalize_inst(
         }
     } else if opcode.is_branch() {
         split::simplify_branch_arguments(&mut pos.func.dfg, inst);
+    } else if opcode == ir::Opcode::Isplit {
+        if resolve_isplit(inst, pos) {
+            return true;
+        }
     }
 
     match pos.func.update_encoding(inst, isa) {
@@ -89,6 +101,19 @@ pub fn legalize_function(func: &mut ir::Function, cfg: &mut ControlFlowGraph, is
 
     boundary::legalize_signatures(func, isa);
 
+    if isa.flags().emit_trap_tables_for_guarded_heaps() {
+        mark_guarded_heap_accesses(func);
+    }
+    expand_indexed_heap_addrs(func, isa);
+
+    legalize_insts(func, cfg, isa);
+}
+
+/// Legalize the instructions of `func` for `isa`, leaving its signature alone.
+///
+/// This is the part of `legalize_function` that transforms the illegal instructions and fills out
+/// `func.encodings`.
+pub fn legalize_insts(func: &mut ir::Function, cfg: &mut ControlFlowGraph, isa: &TargetIsa) {
     func.encodings.resize(func.dfg.num_insts());
 
     let mut pos = FuncCursor::new(func);
@@ -118,6 +143,38 @@ pub fn legalize_function(func: &mut ir::Function, cfg: &mut ControlFlowGraph, is
 // Concretely, this defines private functions `narrow()`, and `expand()`.
 include!(concat!(env!("OUT_DIR"), "/legalizer.rs"));
 
+/// Resolve an `isplit` of a value that has been narrowed to an `iconcat` after the `isplit` was
+/// inserted.
+///
+/// This happens when an ABI boundary splits the result of an instruction that is only legalized
+/// later, as with the `bitcast` used to pass floating point values in integer registers.
+fn resolve_isplit(inst: ir::Inst, pos: &mut FuncCursor) -> bool {
+    let arg = pos
+        .func
+        .dfg
+        .resolve_aliases(pos.func.dfg.inst_args(inst)[0]);
+    let (lo, hi) = match pos.func.dfg.value_def(arg) {
+        ir::ValueDef::Result(def, _) => match pos.func.dfg[def] {
+            ir::InstructionData::Binary {
+                opcode: ir::Opcode::Iconcat,
+                args,
+            } => (args[0], args[1]),
+            _ => return false,
+        },
+        ir::ValueDef::Param(_, _) => return false,
+    };
+
+    let (res_lo, res_hi) = {
+        let res = pos.func.dfg.inst_results(inst);
+        (res[0], res[1])
+    };
+    pos.func.dfg.clear_results(inst);
+    pos.remove_inst();
+    pos.func.dfg.change_to_alias(res_lo, lo);
+    pos.func.dfg.change_to_alias(res_hi, hi);
+    true
+}
+
 /// Custom expansion for conditional trap instructions.
 /// TODO: Add CFG support to the Python patterns so we won't have to do this.
 fn expand_cond_trap(
@@ -128,15 +185,20 @@ fn expand_cond_trap(
 ) {
     // Parse the instruction.
     let trapz;
-    let (arg, code) = match func.dfg[inst] {
-        ir::InstructionData::CondTrap { opcode, arg, code } => {
+    let (arg, code, payload) = match func.dfg[inst] {
+        ir::InstructionData::CondTrap {
+            opcode,
+            arg,
+            code,
+            payload,
+        } => {
             // We want to branch *over* an unconditional trap.
             trapz = match opcode {
                 ir::Opcode::Trapz => true,
                 ir::Opcode::Trapnz => false,
                 _ => panic!("Expected cond trap: {}", func.dfg.display_inst(inst, None)),
             };
-            (arg, code)
+            (arg, code, payload)
         }
         _ => panic!("Expected cond trap: {}", func.dfg.display_inst(inst, None)),
     };
@@ -161,7 +223,7 @@ fn expand_cond_trap(
 
     let mut pos = FuncCursor::new(func).after_inst(inst);
     pos.use_srcloc(inst);
-    pos.ins().trap(code);
+    pos.ins().trap(code, payload);
     pos.insert_ebb(new_ebb);
 
     // Finally update the CFG.
@@ -169,6 +231,43 @@ fn expand_cond_trap(
     cfg.recompute_ebb(pos.func, new_ebb);
 }
 
+/// Custom expansion for `brz` and `brnz` on integer types without encodings.
+///
+/// A value that is narrower than 32 bits is zero-extended, and a value that is wider is split
+/// into halves that are combined with `bor`. In both cases, the new value is zero if and only if
+/// the original value is zero, so the branch only needs a new argument.
+fn expand_cond_branch(
+    inst: ir::Inst,
+    func: &mut ir::Function,
+    _cfg: &mut ControlFlowGraph,
+    _isa: &TargetIsa,
+) {
+    let arg = match func.dfg[inst] {
+        ir::InstructionData::Branch {
+            opcode: ir::Opcode::Brz,
+            ref args,
+            ..
+        }
+        | ir::InstructionData::Branch {
+            opcode: ir::Opcode::Brnz,
+            ref args,
+            ..
+        } => args.first(&func.dfg.value_lists).unwrap(),
+        _ => panic!("Expected brz/brnz: {}", func.dfg.display_inst(inst, None)),
+    };
+
+    let ty = func.dfg.value_type(arg);
+    let mut pos = FuncCursor::new(func).at_inst(inst);
+    pos.use_srcloc(inst);
+    let new_arg = if ty.bits() < 32 {
+        pos.ins().uextend(I32, arg)
+    } else {
+        let (lo, hi) = pos.ins().isplit(arg);
+        pos.ins().bor(lo, hi)
+    };
+    pos.func.dfg.inst_args_mut(inst)[0] = new_arg;
+}
+
 /// Jump tables.
 fn expand_br_table(
     inst: ir::Inst,
@@ -202,6 +301,7 @@ fn expand_br_table_jt(
         _ => panic!("Expected br_table: {}", func.dfg.display_inst(inst, None)),
     };
 
+    let table = fill_jump_table_holes(func, inst, table, default_ebb);
     let table_size = func.jump_tables[table].len();
     let addr_ty = isa.pointer_type();
     let entry_ty = I32;
@@ -229,6 +329,45 @@ fn expand_br_table_jt(
     cfg.recompute_ebb(pos.func, ebb);
 }
 
+/// Get a jump table with the entries of `table` and its holes replaced with `default_ebb`, for
+/// the expansion of the `br_table` instruction `inst`.
+///
+/// The entries of the table are loaded by an indirect branch, so its holes must hold the default
+/// destination. The table is filled in place unless another instruction uses it, since the other
+/// users may have a different default destination.
+fn fill_jump_table_holes(
+    func: &mut ir::Function,
+    inst: ir::Inst,
+    table: ir::JumpTable,
+    default_ebb: ir::Ebb,
+) -> ir::JumpTable {
+    if !func.jump_tables[table].has_holes() {
+        return table;
+    }
+
+    let shared = func.layout.ebbs().any(|ebb| {
+        func.layout.ebb_insts(ebb).any(|other| {
+            other != inst
+                && match func.dfg[other] {
+                    ir::InstructionData::BranchTable { table: t, .. }
+                    | ir::InstructionData::BranchTableEntry { table: t, .. }
+                    | ir::InstructionData::BranchTableBase { table: t, .. }
+                    | ir::InstructionData::IndirectJump { table: t, .. } => t == table,
+                    _ => false,
+                }
+        })
+    });
+
+    if shared {
+        let mut data = func.jump_tables[table].clone();
+        data.fill_holes(default_ebb);
+        func.create_jump_table(data)
+    } else {
+        func.jump_tables[table].fill_holes(default_ebb);
+        table
+    }
+}
+
 /// Expand br_table to series of conditionals.
 fn expand_br_table_conds(
     inst: ir::Inst,
@@ -254,9 +393,11 @@ fn expand_br_table_conds(
     pos.use_srcloc(inst);
 
     for i in 0..table_size {
-        let dest = pos.func.jump_tables[table].as_slice()[i];
-        let t = pos.ins().icmp_imm(IntCC::Equal, arg, i as i64);
-        pos.ins().brnz(t, dest, &[]);
+        // The holes go to the default destination, like the indices that don't match.
+        if let Some(dest) = pos.func.jump_tables[table].get_entry(i) {
+            let t = pos.ins().icmp_imm(IntCC::Equal, arg, i as i64);
+            pos.ins().brnz(t, dest, &[]);
+        }
     }
 
     // `br_table` jumps to the default destination if nothing matches
@@ -306,6 +447,42 @@ fn expand_select(
     cfg.recompute_ebb(pos.func, old_ebb);
 }
 
+/// Expand the vselect instruction into a bitselect.
+///
+/// The lanes of a boolean vector are all ones or all zeros, so they can select the bits of the
+/// lanes once they have been converted to a mask of the same type as the selected values.
+fn expand_vselect(
+    inst: ir::Inst,
+    func: &mut ir::Function,
+    _cfg: &mut ControlFlowGraph,
+    _isa: &TargetIsa,
+) {
+    let (ctrl, tval, fval) = match func.dfg[inst] {
+        ir::InstructionData::Ternary {
+            opcode: ir::Opcode::Vselect,
+            args,
+        } => (args[0], args[1], args[2]),
+        _ => panic!("Expected vselect: {}", func.dfg.display_inst(inst, None)),
+    };
+
+    let ty = func.dfg.value_type(tval);
+    let mut pos = FuncCursor::new(func).at_inst(inst);
+    pos.use_srcloc(inst);
+    let mask = if ty.lane_type().is_bool() {
+        ctrl
+    } else {
+        let int_lane = ir::Type::int(u16::from(ty.lane_bits())).expect("Invalid vselect lanes");
+        let int_ty = int_lane.by(ty.lane_count()).expect("Invalid vselect type");
+        let mask = pos.ins().bmask(int_ty, ctrl);
+        if int_ty == ty {
+            mask
+        } else {
+            pos.ins().bitcast(ty, mask)
+        }
+    };
+    pos.func.dfg.replace(inst).bitselect(mask, tval, fval);
+}
+
 fn expand_br_icmp(
     inst: ir::Inst,
     func: &mut ir::Function,
@@ -350,8 +527,8 @@ fn expand_fconst(
     let ty = func.dfg.value_type(func.dfg.first_result(inst));
     debug_assert!(!ty.is_vector(), "Only scalar fconst supported: {}", ty);
 
-    // In the future, we may want to generate constant pool entries for these constants, but for
-    // now use an `iconst` and a bit cast.
+    // ISAs that can load constants from the constant pool have encodings for them. The others
+    // use an `iconst` and a bit cast.
     let mut pos = FuncCursor::new(func).at_inst(inst);
     pos.use_srcloc(inst);
     let ival = match pos.func.dfg[inst] {
@@ -368,6 +545,60 @@ fn expand_fconst(
     pos.func.dfg.replace(inst).bitcast(ty, ival);
 }
 
+/// Widen an `i8` or `i16` instruction with an immediate operand to `i32`, when it doesn't matter
+/// or it is unsigned that the argument is zero-extended.
+///
+/// The immediate is stored sign-extended from the narrow type. When the argument is zero-extended,
+/// the immediate is zero-extended too, so an unsigned operation sees the same numbers.
+fn widen_imm(
+    inst: ir::Inst,
+    func: &mut ir::Function,
+    _cfg: &mut ControlFlowGraph,
+    _isa: &TargetIsa,
+) {
+    let mut pos = FuncCursor::new(func).at_inst(inst);
+    pos.use_srcloc(inst);
+
+    let (arg, imm, signed) = match pos.func.dfg[inst] {
+        ir::InstructionData::IntCompareImm { cond, arg, imm, .. } => {
+            let signed = match cond {
+                IntCC::SignedLessThan
+                | IntCC::SignedGreaterThanOrEqual
+                | IntCC::SignedGreaterThan
+                | IntCC::SignedLessThanOrEqual => true,
+                _ => false,
+            };
+            (arg, imm, signed)
+        }
+        ir::InstructionData::BinaryImm { arg, imm, .. } => (arg, imm, false),
+        _ => panic!(
+            "Expected an immediate: {}",
+            pos.func.dfg.display_inst(inst, None)
+        ),
+    };
+    let ty = pos.func.dfg.value_type(arg);
+    let (x, imm) = if signed {
+        (pos.ins().sextend(I32, arg), imm)
+    } else {
+        (
+            pos.ins().uextend(I32, arg),
+            imm.zero_extend_from_width(ty.bits()),
+        )
+    };
+
+    match pos.func.dfg[inst] {
+        ir::InstructionData::IntCompareImm { cond, .. } => {
+            pos.func.dfg.replace(inst).icmp_imm(cond, x, imm);
+        }
+        ir::InstructionData::BinaryImm { opcode, .. } => {
+            let (z_inst, dfg) = pos.ins().BinaryImm(opcode, I32, imm, x);
+            let z = dfg.first_result(z_inst);
+            pos.func.dfg.replace(inst).ireduce(ty, z);
+        }
+        _ => unreachable!(),
+    }
+}
+
 /// Expand illegal `stack_load` instructions.
 fn expand_stack_load(
     inst: ir::Inst,
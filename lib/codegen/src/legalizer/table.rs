@@ -55,7 +55,7 @@ fn dynamic_addr(
     let oob = pos
         .ins()
         .icmp(IntCC::UnsignedGreaterThanOrEqual, index, bound);
-    pos.ins().trapnz(oob, ir::TrapCode::TableOutOfBounds);
+    pos.ins().trapnz(oob, ir::TrapCode::TableOutOfBounds, 0);
 
     compute_addr(
         inst,
@@ -48,8 +48,6 @@
 // Turns on no_std and alloc features if std is not available.
 #![cfg_attr(not(feature = "std"), no_std)]
 #![cfg_attr(not(feature = "std"), feature(alloc))]
-// TODO: Remove this workaround once https://github.com/rust-lang/rust/issues/27747 is done.
-#![cfg_attr(not(feature = "std"), feature(slice_concat_ext))]
 
 #[cfg(not(feature = "std"))]
 #[macro_use]
@@ -83,9 +81,11 @@ pub mod dominator_tree;
 pub mod flowgraph;
 pub mod ir;
 pub mod isa;
+pub mod limits;
 pub mod loop_analysis;
 pub mod print_errors;
 pub mod settings;
+pub mod stats;
 pub mod timing;
 pub mod verifier;
 pub mod write;
@@ -93,13 +93,21 @@ pub mod write;
 pub use entity::packed_option;
 
 mod abi;
+mod bce;
 mod bitset;
 mod constant_hash;
+mod compaction;
 mod context;
 mod dce;
 mod divconst_magic_numbers;
+mod dse;
+mod ebb_order;
+mod flags_liveness;
 mod fx;
+mod gv_canonicalization;
+mod gv_hoisting;
 mod iterators;
+mod known_bits;
 mod legalizer;
 mod licm;
 mod nan_canonicalization;
@@ -110,25 +118,31 @@ mod preopt;
 mod ref_slice;
 mod regalloc;
 mod result;
+mod return_merging;
+mod scheduling;
 mod scoped_hash_map;
 mod simple_gvn;
+mod sink;
 mod stack_layout;
 mod topo_order;
 mod unreachable_code;
 
 pub use result::{CodegenError, CodegenResult};
 
+#[cfg(all(feature = "testing_hooks", feature = "std"))]
+pub use fx::set_hash_seed;
+
 /// This replaces `std` in builds with `core`.
 #[cfg(not(feature = "std"))]
 mod std {
-    pub use alloc::{boxed, slice, string, vec};
+    pub use alloc::{boxed, slice, string, sync, vec};
     pub use core::*;
     pub mod collections {
         #[allow(unused_extern_crates)]
-        extern crate hashmap_core;
+        extern crate hashbrown;
 
-        pub use self::hashmap_core::map as hash_map;
-        pub use self::hashmap_core::{HashMap, HashSet};
+        pub use self::hashbrown::hash_map;
+        pub use self::hashbrown::{HashMap, HashSet};
         pub use alloc::collections::BTreeSet;
     }
 }
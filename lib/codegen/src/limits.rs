@@ -0,0 +1,186 @@
+//! Limits on the functions compiled by a `Context`.
+//!
+//! Cranelift can compile very large functions, but a pathological function, like a WebAssembly
+//! function with 100,000 locals in a single block, can make the register allocator take a very
+//! long time and use a lot of memory. An embedding that compiles untrusted code can set limits in
+//! `Context::limits`, so that such a function fails to compile with an `ImplLimitExceeded` error
+//! naming the limit, instead of taking down the whole process.
+
+use ir::Function;
+use result::{CodegenError, CodegenResult};
+#[cfg(feature = "std")]
+use std::time::{Duration, Instant};
+
+/// Limits checked by `Context::compile`.
+///
+/// All the limits are unset by default, and unset limits aren't checked.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CompileLimits {
+    /// The largest number of EBBs in the function.
+    ///
+    /// This is checked before compiling the function and again after legalization, which can add
+    /// EBBs.
+    pub max_ebbs: Option<usize>,
+
+    /// The largest number of values in the function.
+    ///
+    /// This is checked with the EBBs, and counts all the values created in the function, including
+    /// the aliases and the values of removed instructions.
+    pub max_values: Option<usize>,
+
+    /// The largest number of values that the register allocator can spill.
+    ///
+    /// This is checked after register allocation, before the spill slots are laid out.
+    pub max_spills: Option<u32>,
+
+    /// The longest time that `Context::compile` can take.
+    ///
+    /// This is checked between passes, so the compilation can exceed the budget by the time of a
+    /// single pass.
+    #[cfg(feature = "std")]
+    pub time_budget: Option<Duration>,
+}
+
+impl CompileLimits {
+    /// Check that `func` has no more EBBs and values than the limits allow.
+    pub fn check_function_size(&self, func: &Function) -> CodegenResult<()> {
+        check("max_ebbs", func.dfg.num_ebbs(), self.max_ebbs)?;
+        check("max_values", func.dfg.num_values(), self.max_values)
+    }
+
+    /// Check that the register allocator spilled no more than `spills` values.
+    pub fn check_spills(&self, spills: u32) -> CodegenResult<()> {
+        check(
+            "max_spills",
+            spills as usize,
+            self.max_spills.map(|max| max as usize),
+        )
+    }
+}
+
+/// Check that `count` doesn't exceed the limit `max` named `limit`.
+fn check(limit: &str, count: usize, max: Option<usize>) -> CodegenResult<()> {
+    match max {
+        Some(max) if count > max => Err(CodegenError::ImplLimitExceeded(format!(
+            "{} is {}, got {}",
+            limit, max, count
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// The time at which the time budget of a compilation runs out.
+#[cfg(feature = "std")]
+pub struct Deadline {
+    start: Instant,
+    budget: Option<Duration>,
+}
+
+#[cfg(feature = "std")]
+impl Deadline {
+    /// Start the time budget of `limits`.
+    pub fn start(limits: &CompileLimits) -> Self {
+        Self {
+            start: Instant::now(),
+            budget: limits.time_budget,
+        }
+    }
+
+    /// Check that the time budget hasn't run out after the pass named `pass`.
+    pub fn check(&self, pass: &str) -> CodegenResult<()> {
+        match self.budget {
+            Some(budget) if self.start.elapsed() > budget => Err(CodegenError::ImplLimitExceeded(
+                format!("time_budget is {:?}, exceeded by {}", budget, pass),
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Dummy deadline for builds without `std`, where there is no `Instant` to measure time with.
+#[cfg(not(feature = "std"))]
+pub struct Deadline;
+
+#[cfg(not(feature = "std"))]
+impl Deadline {
+    /// Start the time budget of `limits`.
+    pub fn start(_limits: &CompileLimits) -> Self {
+        Deadline
+    }
+
+    /// Check that the time budget hasn't run out after the pass named `pass`.
+    pub fn check(&self, _pass: &str) -> CodegenResult<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ir::types;
+    use std::string::ToString;
+
+    #[test]
+    fn function_size() {
+        let mut func = Function::new();
+        let ebb = func.dfg.make_ebb();
+        func.dfg.append_ebb_param(ebb, types::I32);
+        func.dfg.append_ebb_param(ebb, types::I32);
+
+        let mut limits = CompileLimits::default();
+        assert_eq!(limits.check_function_size(&func), Ok(()));
+
+        limits.max_ebbs = Some(1);
+        limits.max_values = Some(2);
+        assert_eq!(limits.check_function_size(&func), Ok(()));
+
+        func.dfg.make_ebb();
+        assert_eq!(
+            limits.check_function_size(&func),
+            Err(CodegenError::ImplLimitExceeded(
+                "max_ebbs is 1, got 2".to_string()
+            ))
+        );
+
+        limits.max_ebbs = None;
+        func.dfg.append_ebb_param(ebb, types::I32);
+        assert_eq!(
+            limits.check_function_size(&func),
+            Err(CodegenError::ImplLimitExceeded(
+                "max_values is 2, got 3".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn spills() {
+        let limits = CompileLimits {
+            max_spills: Some(10),
+            ..CompileLimits::default()
+        };
+        assert_eq!(limits.check_spills(10), Ok(()));
+        assert_eq!(
+            limits.check_spills(11),
+            Err(CodegenError::ImplLimitExceeded(
+                "max_spills is 10, got 11".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn time_budget() {
+        let mut limits = CompileLimits::default();
+        assert_eq!(Deadline::start(&limits).check("nothing"), Ok(()));
+
+        limits.time_budget = Some(Duration::from_secs(0));
+        let deadline = Deadline::start(&limits);
+        ::std::thread::sleep(Duration::from_millis(1));
+        assert_eq!(
+            deadline.check("sleeping"),
+            Err(CodegenError::ImplLimitExceeded(
+                "time_budget is 0ns, exceeded by sleeping".to_string()
+            ))
+        );
+    }
+}
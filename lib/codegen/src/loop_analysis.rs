@@ -70,6 +70,11 @@ impl LoopAnalysis {
         self.loops[lp].parent.expand()
     }
 
+    /// Get the innermost loop containing `ebb`, if any.
+    pub fn innermost_loop(&self, ebb: Ebb) -> Option<Loop> {
+        self.ebb_loop_map[ebb].expand()
+    }
+
     /// Determine if an Ebb belongs to a loop by running a finger along the loop tree.
     ///
     /// Returns `true` if `ebb` is in loop `lp`.
@@ -3,11 +3,15 @@
 #![allow(non_snake_case)]
 
 use cursor::{Cursor, EncCursor};
+use entity::SecondaryMap;
+use flags_liveness::flags_live_insts;
 use ir::condcodes::{CondCode, FloatCC, IntCC};
 use ir::dfg::ValueDef;
 use ir::immediates::{Imm64, Offset32};
 use ir::instructions::{Opcode, ValueList};
-use ir::{Ebb, Function, Inst, InstBuilder, InstructionData, MemFlags, Type, Value};
+use ir::{
+    types, Ebb, Function, GlobalValue, Inst, InstBuilder, InstructionData, MemFlags, Type, Value,
+};
 use isa::TargetIsa;
 use timing;
 
@@ -169,6 +173,66 @@ fn optimize_cpu_flags(
     debug_assert!(ok);
 }
 
+/// Optimize icmp select sequences into ifcmp selectif sequences, to avoid materializing the
+/// condition in an integer register.
+fn optimize_select_flags(
+    pos: &mut EncCursor,
+    inst: Inst,
+    last_flags_clobber: Option<Inst>,
+    isa: &TargetIsa,
+) {
+    let (sel_args, cmp_inst) = match pos.func.dfg[inst] {
+        InstructionData::Ternary {
+            opcode: Opcode::Select,
+            args,
+        } => match pos.func.dfg.value_def(args[0]) {
+            ValueDef::Result(cmp_inst, _) => (args, cmp_inst),
+            _ => return,
+        },
+        _ => return,
+    };
+
+    // There is no `selectif` for floating point or boolean values.
+    let ty = pos.func.dfg.ctrl_typevar(inst);
+    if !ty.is_int() {
+        return;
+    }
+
+    // If any instructions clobber the flags between the comparison and the select,
+    // don't optimize them.
+    if last_flags_clobber != Some(cmp_inst) {
+        return;
+    }
+
+    let (cmp_arg, kind) = match pos.func.dfg[cmp_inst] {
+        InstructionData::IntCompare { cond, args, .. } => {
+            (args[0], CmpBrKind::Icmp { cond, arg: args[1] })
+        }
+        InstructionData::IntCompareImm { cond, arg, imm, .. } => {
+            (arg, CmpBrKind::IcmpImm { cond, imm })
+        }
+        _ => return,
+    };
+
+    // We found a compare+select pattern. Transform it to use flags.
+    pos.goto_inst(cmp_inst);
+    let (cond, flags) = match kind {
+        CmpBrKind::Icmp { cond, arg } => (cond, pos.ins().ifcmp(cmp_arg, arg)),
+        CmpBrKind::IcmpImm { cond, imm } => (cond, pos.ins().ifcmp_imm(cmp_arg, imm)),
+        CmpBrKind::Fcmp { .. } => unreachable!(),
+    };
+    pos.func.dfg.replace(cmp_inst).trueif(cond, flags);
+    pos.func
+        .dfg
+        .replace(inst)
+        .selectif(ty, cond, flags, sel_args[1], sel_args[2]);
+
+    let ok = pos.func.update_encoding(cmp_inst, isa).is_ok();
+    debug_assert!(ok);
+    let ok = pos.func.update_encoding(inst, isa).is_ok();
+    debug_assert!(ok);
+}
+
 struct MemOpInfo {
     opcode: Opcode,
     itype: Type,
@@ -352,12 +416,421 @@ fn optimize_complex_addresses(pos: &mut EncCursor, inst: Inst, isa: &TargetIsa)
     debug_assert!(ok);
 }
 
+/// Fold the address of a symbol into a `load` or `store` at an index from it.
+///
+/// The heap legalization computes the address of an access to a heap whose base is a symbol as
+/// `iadd (symbol_value gv), index`, possibly with `iadd_imm` instructions after the
+/// `symbol_value`, which materializes the address of the symbol in a register for every access.
+/// On x86, `x86_load_symbol` and `x86_store_symbol` add the address of the symbol to the
+/// displacement of the access with a relocation instead.
+fn optimize_symbol_addresses(pos: &mut EncCursor, inst: Inst, isa: &TargetIsa) {
+    let (addr, offset) = match pos.func.dfg[inst] {
+        InstructionData::Load {
+            opcode: Opcode::Load,
+            arg,
+            offset,
+            ..
+        } => (arg, offset),
+        InstructionData::Store {
+            opcode: Opcode::Store,
+            args,
+            offset,
+            ..
+        } => (args[1], offset),
+        _ => return,
+    };
+    if pos.func.is_patchable(inst) {
+        return;
+    }
+
+    let (index, global_value, offset) = match symbol_address(pos.func, addr, offset) {
+        Some(address) => address,
+        None => return,
+    };
+    let data = match pos.func.dfg[inst] {
+        InstructionData::Load { flags, .. } => InstructionData::LoadSymbol {
+            opcode: Opcode::X86LoadSymbol,
+            arg: index,
+            flags,
+            global_value,
+            offset,
+        },
+        InstructionData::Store { args, flags, .. } => InstructionData::StoreSymbol {
+            opcode: Opcode::X86StoreSymbol,
+            args: ValueList::from_slice(&[args[0], index], &mut pos.func.dfg.value_lists),
+            flags,
+            global_value,
+            offset,
+        },
+        _ => unreachable!(),
+    };
+    let ctrl_type = pos.func.dfg.ctrl_typevar(inst);
+    let enc = match isa.encode(pos.func, &data, ctrl_type) {
+        Ok(enc) => enc,
+        Err(_) => return,
+    };
+
+    pos.func.dfg[inst] = data;
+    pos.func.encodings[inst] = enc;
+}
+
+/// Fold the address of a symbol into a `load` or `store` relative to the instruction pointer.
+///
+/// The legalized `global_value` of a symbol is a `symbol_value`, which materializes the address of
+/// the symbol in a register with a `lea` on x86-64, so a `load` or `store` at it takes two
+/// instructions. When the address has no other uses and the symbol is within 2 GB of the code,
+/// `x86_load_pcrel` and `x86_store_pcrel` access the symbol relative to the instruction pointer
+/// instead, and the `symbol_value` becomes dead.
+fn optimize_pcrel_addresses(
+    pos: &mut EncCursor,
+    inst: Inst,
+    uses: &SecondaryMap<Value, u32>,
+    isa: &TargetIsa,
+) {
+    let (addr, offset) = match pos.func.dfg[inst] {
+        InstructionData::Load {
+            opcode: Opcode::Load,
+            arg,
+            offset,
+            ..
+        } => (arg, offset),
+        InstructionData::Store {
+            opcode: Opcode::Store,
+            args,
+            offset,
+            ..
+        } => (args[1], offset),
+        _ => return,
+    };
+    let addr = pos.func.dfg.resolve_aliases(addr);
+    if uses[addr] != 1 || pos.func.is_patchable(inst) {
+        return;
+    }
+
+    let (global_value, imm) = match symbol_base(pos.func, addr) {
+        Some(base) => base,
+        None => return,
+    };
+    let offset = match offset.try_add_i64(imm) {
+        Some(offset) => offset,
+        None => return,
+    };
+    if offset
+        .try_add_i64(pos.func.global_values[global_value].symbol_offset())
+        .is_none()
+    {
+        return;
+    }
+
+    let data = match pos.func.dfg[inst] {
+        InstructionData::Load { flags, .. } => InstructionData::LoadGlobal {
+            opcode: Opcode::X86LoadPcrel,
+            flags,
+            global_value,
+            offset,
+        },
+        InstructionData::Store { args, flags, .. } => InstructionData::LoadSymbol {
+            opcode: Opcode::X86StorePcrel,
+            arg: args[0],
+            flags,
+            global_value,
+            offset,
+        },
+        _ => unreachable!(),
+    };
+    let ctrl_type = pos.func.dfg.ctrl_typevar(inst);
+    let enc = match isa.encode(pos.func, &data, ctrl_type) {
+        Ok(enc) => enc,
+        Err(_) => return,
+    };
+
+    pos.func.dfg[inst] = data;
+    pos.func.encodings[inst] = enc;
+}
+
+/// Split the address `addr + offset` into an index value and an offset from a symbol.
+///
+/// The offset from the symbol includes the offset of the symbol global value, so it fits in the
+/// 32-bit displacement of a memory access.
+fn symbol_address(
+    func: &Function,
+    addr: Value,
+    offset: Offset32,
+) -> Option<(Value, GlobalValue, Offset32)> {
+    let args = match func.dfg.value_def(addr) {
+        ValueDef::Result(inst, _) => match func.dfg[inst] {
+            InstructionData::Binary {
+                opcode: Opcode::Iadd,
+                args,
+            } => args,
+            _ => return None,
+        },
+        ValueDef::Param(..) => return None,
+    };
+    for &(base, index) in &[(args[0], args[1]), (args[1], args[0])] {
+        if let Some((gv, imm)) = symbol_base(func, base) {
+            let offset = offset.try_add_i64(imm)?;
+            offset.try_add_i64(func.global_values[gv].symbol_offset())?;
+            return Some((index, gv, offset));
+        }
+    }
+    None
+}
+
+/// Get the symbol global value and the offset from it that `value` is computed from, if it is a
+/// `symbol_value` instruction followed by `iadd_imm` instructions.
+fn symbol_base(func: &Function, value: Value) -> Option<(GlobalValue, i64)> {
+    let inst = match func.dfg.value_def(value) {
+        ValueDef::Result(inst, _) => inst,
+        ValueDef::Param(..) => return None,
+    };
+    match func.dfg[inst] {
+        InstructionData::UnaryGlobalValue {
+            opcode: Opcode::SymbolValue,
+            global_value,
+        } => Some((global_value, 0)),
+        InstructionData::BinaryImm {
+            opcode: Opcode::IaddImm,
+            arg,
+            imm,
+        } => {
+            let (gv, offset) = symbol_base(func, arg)?;
+            Some((gv, offset.checked_add(imm.into())?))
+        }
+        _ => None,
+    }
+}
+
+/// Count the uses of each value by the instructions in the layout.
+fn count_value_uses(func: &Function) -> SecondaryMap<Value, u32> {
+    let mut uses = SecondaryMap::new();
+    for ebb in func.layout.ebbs() {
+        for inst in func.layout.ebb_insts(ebb) {
+            for &arg in func.dfg.inst_args(inst) {
+                uses[func.dfg.resolve_aliases(arg)] += 1;
+            }
+        }
+    }
+    uses
+}
+
+/// Get the version of an arithmetic opcode that takes its second operand from memory.
+fn load_op_opcode(opcode: Opcode) -> Option<Opcode> {
+    match opcode {
+        Opcode::Iadd => Some(Opcode::X86IaddLoad),
+        Opcode::Isub => Some(Opcode::X86IsubLoad),
+        Opcode::Band => Some(Opcode::X86BandLoad),
+        Opcode::Bor => Some(Opcode::X86BorLoad),
+        Opcode::Bxor => Some(Opcode::X86BxorLoad),
+        _ => None,
+    }
+}
+
+/// Get the `load` instruction defining `value` if `inst` is its only use, and it can be moved
+/// down to `inst`.
+fn single_use_load(
+    func: &Function,
+    inst: Inst,
+    value: Value,
+    uses: &SecondaryMap<Value, u32>,
+) -> Option<Inst> {
+    let value = func.dfg.resolve_aliases(value);
+    if uses[value] != 1 {
+        return None;
+    }
+    let load = match func.dfg.value_def(value) {
+        ValueDef::Result(load, _) => load,
+        ValueDef::Param(..) => return None,
+    };
+    let may_trap = match func.dfg[load] {
+        InstructionData::Load {
+            opcode: Opcode::Load,
+            flags,
+            ..
+        } => !flags.notrap(),
+        _ => return None,
+    };
+    if func.is_patchable(load) || func.layout.inst_ebb(load) != func.layout.inst_ebb(inst) {
+        return None;
+    }
+
+    // Moving the load past a store, call or trap could change what it reads or which trap
+    // happens first. A load that can trap can't move past other memory accesses either.
+    let mut next = func.layout.next_inst(load);
+    while let Some(between) = next {
+        if between == inst {
+            return Some(load);
+        }
+        let opcode = func.dfg[between].opcode();
+        if opcode.can_store()
+            || opcode.is_call()
+            || opcode.can_trap()
+            || opcode.other_side_effects()
+            || (may_trap && opcode.can_load())
+        {
+            return None;
+        }
+        next = func.layout.next_inst(between);
+    }
+    None
+}
+
+/// Fold a single-use `load` into the arithmetic instruction using it.
+///
+/// On x86, `iadd v1, v2` where `v2 = load v0+16` has no other uses becomes `add r, [r+16]`,
+/// which saves an instruction and a register. The folded instruction does the memory access, so
+/// it gets the source location of the load when the load can trap.
+fn optimize_load_operands(
+    pos: &mut EncCursor,
+    inst: Inst,
+    uses: &SecondaryMap<Value, u32>,
+    isa: &TargetIsa,
+) {
+    let (opcode, args) = match pos.func.dfg[inst] {
+        InstructionData::Binary { opcode, args } => match load_op_opcode(opcode) {
+            Some(opcode) => (opcode, args),
+            None => return,
+        },
+        _ => return,
+    };
+    if pos.func.is_patchable(inst) {
+        return;
+    }
+
+    // Only the second operand can come from memory, so the operands of commutative
+    // instructions may be swapped.
+    let (x, load) = if let Some(load) = single_use_load(pos.func, inst, args[1], uses) {
+        (args[0], load)
+    } else if opcode == Opcode::X86IsubLoad {
+        return;
+    } else if let Some(load) = single_use_load(pos.func, inst, args[0], uses) {
+        (args[1], load)
+    } else {
+        return;
+    };
+
+    let (flags, p, offset) = match pos.func.dfg[load] {
+        InstructionData::Load {
+            flags, arg, offset, ..
+        } => (flags, arg, offset),
+        _ => unreachable!(),
+    };
+    let data = InstructionData::Store {
+        opcode,
+        args: [x, p],
+        flags,
+        offset,
+    };
+    let ctrl_type = pos.func.dfg.ctrl_typevar(inst);
+    let enc = match isa.encode(pos.func, &data, ctrl_type) {
+        Ok(enc) => enc,
+        Err(_) => return,
+    };
+
+    pos.func.dfg[inst] = data;
+    pos.func.encodings[inst] = enc;
+    if !flags.notrap() {
+        pos.func.srclocs[inst] = pos.func.srclocs[load];
+    }
+    pos.func.layout.remove_inst(load);
+}
+
+/// Use the zeroing idiom encodings for zero constants where no CPU flags are live.
+///
+/// The encodings of `iconst` that clobber the flags are the zeroing idioms like `xor r, r` on
+/// x86, which are smaller than moving an immediate and break the dependency on the old register
+/// value. The legalizer never picks them, because it doesn't know where flags are live.
+fn optimize_zero_constants(func: &mut Function, isa: &TargetIsa) {
+    let flags_live = match flags_live_insts(func) {
+        Some(insts) => insts,
+        None => return,
+    };
+    let encinfo = isa.encoding_info();
+
+    for ebb in func.layout.ebbs() {
+        for inst in func.layout.ebb_insts(ebb) {
+            match func.dfg[inst] {
+                InstructionData::UnaryImm {
+                    opcode: Opcode::Iconst,
+                    imm,
+                } if imm == Imm64::new(0) => {}
+                _ => continue,
+            }
+            if flags_live.contains(inst) || func.is_patchable(inst) {
+                continue;
+            }
+
+            let ctrl_type = func.dfg.ctrl_typevar(inst);
+            let zeroing_enc = isa
+                .legal_encodings(func, &func.dfg[inst], ctrl_type)
+                .find(|&e| {
+                    encinfo
+                        .operand_constraints(e)
+                        .map_or(false, |c| c.clobbers_flags)
+                });
+            if let Some(enc) = zeroing_enc {
+                func.encodings[inst] = enc;
+            }
+        }
+    }
+}
+
+/// Remove the `uextend` of 32-bit values that are already zero-extended.
+///
+/// On x86-64, an instruction that writes a 32-bit register clears the upper half of the 64-bit
+/// register, so extending its result, like a WebAssembly heap index, only needs the register to
+/// be reused. The legalizer encodes every `uextend` as a copy, since the value may come from an
+/// EBB parameter or an `ireduce`, which leave the upper half alone. This switches the extension of
+/// a value defined by a `zero_extends` recipe to an encoding with the result tied to the operand,
+/// which emits no code.
+fn optimize_zero_extensions(func: &mut Function, isa: &TargetIsa) {
+    let flags_live = flags_live_insts(func);
+    let encinfo = isa.encoding_info();
+
+    for ebb in func.layout.ebbs() {
+        for inst in func.layout.ebb_insts(ebb) {
+            let arg = match func.dfg[inst] {
+                InstructionData::Unary {
+                    opcode: Opcode::Uextend,
+                    arg,
+                } => func.dfg.resolve_aliases(arg),
+                _ => continue,
+            };
+            if func.dfg.value_type(arg) != types::I32 || func.is_patchable(inst) {
+                continue;
+            }
+            if flags_live
+                .as_ref()
+                .map_or(false, |live| live.contains(inst))
+            {
+                continue;
+            }
+            match func.dfg.value_def(arg) {
+                ValueDef::Result(def, _)
+                    if encinfo
+                        .operand_constraints(func.encodings[def])
+                        .map_or(false, |c| c.zero_extends) => {}
+                _ => continue,
+            }
+
+            let ctrl_type = func.dfg.ctrl_typevar(inst);
+            let tied_enc = isa
+                .legal_encodings(func, &func.dfg[inst], ctrl_type)
+                .find(|&e| encinfo.operand_constraints(e).map_or(false, |c| c.tied_ops));
+            if let Some(enc) = tied_enc {
+                func.encodings[inst] = enc;
+            }
+        }
+    }
+}
+
 //----------------------------------------------------------------------
 //
 // The main post-opt pass.
 
 pub fn do_postopt(func: &mut Function, isa: &TargetIsa) {
     let _tt = timing::postopt();
+    let uses = count_value_uses(func);
     let mut pos = EncCursor::new(func, isa);
     while let Some(_ebb) = pos.next_ebb() {
         let mut last_flags_clobber = None;
@@ -365,6 +838,7 @@ pub fn do_postopt(func: &mut Function, isa: &TargetIsa) {
             if isa.uses_cpu_flags() {
                 // Optimize instructions to make use of flags.
                 optimize_cpu_flags(&mut pos, inst, last_flags_clobber, isa);
+                optimize_select_flags(&mut pos, inst, last_flags_clobber, isa);
 
                 // Track the most recent seen instruction that clobbers the flags.
                 if let Some(constraints) = isa
@@ -377,9 +851,18 @@ pub fn do_postopt(func: &mut Function, isa: &TargetIsa) {
                 }
             }
 
+            optimize_symbol_addresses(&mut pos, inst, isa);
+            optimize_pcrel_addresses(&mut pos, inst, &uses, isa);
             if isa.uses_complex_addresses() {
                 optimize_complex_addresses(&mut pos, inst, isa);
             }
+
+            optimize_load_operands(&mut pos, inst, &uses, isa);
         }
     }
+
+    if isa.uses_cpu_flags() {
+        optimize_zero_constants(pos.func, isa);
+    }
+    optimize_zero_extensions(pos.func, isa);
 }
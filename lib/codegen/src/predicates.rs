@@ -47,6 +47,77 @@ pub fn is_unsigned_int<T: Into<i64>>(x: T, wd: u8, sc: u8) -> bool {
     u == (u & m)
 }
 
+/// Check that the low `wd` bits of `x` can be encoded as an ARM64 logical immediate.
+///
+/// These immediates are a `wd`-bit value made of repeated 2, 4, 8, 16, 32, or 64-bit elements,
+/// where each element is a rotated run of ones. Zero and all ones can't be encoded.
+#[allow(dead_code)]
+pub fn is_bitmask_imm<T: Into<i64>>(x: T, wd: u8) -> bool {
+    let mut v = x.into() as u64;
+    if wd == 32 {
+        v = (v & 0xffff_ffff) | (v << 32);
+    }
+    if v == 0 || v == !0 {
+        return false;
+    }
+
+    // Find the smallest element size that the value repeats.
+    let mut size = 64;
+    while size > 2 {
+        let half = size / 2;
+        let mask = (1u64 << half) - 1;
+        if v & mask != (v >> half) & mask {
+            break;
+        }
+        size = half;
+    }
+
+    // A rotated run of ones changes between zero and one exactly twice when going around the
+    // element.
+    let elem = if size == 64 {
+        v
+    } else {
+        v & ((1u64 << size) - 1)
+    };
+    let rotated = if size == 64 {
+        elem.rotate_right(1)
+    } else {
+        (elem >> 1) | ((elem & 1) << (size - 1))
+    };
+    (elem ^ rotated).count_ones() == 2
+}
+
+/// Check that the low 32 bits of `x` can be encoded as a Thumb-2 modified immediate, after
+/// negating it or inverting its bits if requested.
+///
+/// These immediates are an 8-bit value shifted left by any amount, or an 8-bit value repeated in
+/// every byte, every even byte, or every odd byte.
+#[allow(dead_code)]
+pub fn is_thumb_imm<T: Into<i64>>(x: T, negated: bool, inverted: bool) -> bool {
+    let mut v = x.into();
+    if negated {
+        v = v.wrapping_neg();
+    }
+    if inverted {
+        v = !v;
+    }
+    let v = v as u32;
+    if v <= 0xff {
+        return true;
+    }
+
+    // The repeated patterns.
+    let b0 = v & 0xff;
+    let b1 = (v >> 8) & 0xff;
+    if v == b0 * 0x0001_0001 || v == b1 * 0x0100_0100 || v == b0 * 0x0101_0101 {
+        return true;
+    }
+
+    // All the set bits must be in the 8 bits starting at the highest set bit.
+    let shift = 24 - v.leading_zeros();
+    v >> shift << shift == v
+}
+
 #[allow(dead_code)]
 pub fn is_colocated_func(func_ref: ir::FuncRef, func: &ir::Function) -> bool {
     func.dfg.ext_funcs[func_ref].colocated
@@ -103,4 +174,59 @@ mod tests {
         assert!(!is_signed_int(x1, 16, 4));
         assert!(!is_signed_int(x2, 16, 4));
     }
+
+    #[test]
+    fn bitmask_imm() {
+        assert!(is_bitmask_imm(1, 32));
+        assert!(is_bitmask_imm(0xff00, 32));
+        assert!(is_bitmask_imm(0x8000_0001u32, 32));
+        assert!(is_bitmask_imm(0x5555_5555u32, 32));
+        assert!(is_bitmask_imm(0x00ff_00ff_00ff_00ffu64 as i64, 64));
+        assert!(is_bitmask_imm(0x7fff_ffff_ffff_ffffi64, 64));
+        assert!(is_bitmask_imm(-2, 64));
+
+        // Only the low 32 bits matter in 32-bit operations.
+        assert!(is_bitmask_imm(-2, 32));
+        assert!(is_bitmask_imm(0x1234_5678_0000_00ffi64, 32));
+        assert!(!is_bitmask_imm(0x1234_5678_0000_00ffi64, 64));
+
+        assert!(!is_bitmask_imm(0, 32));
+        assert!(!is_bitmask_imm(-1, 32));
+        assert!(!is_bitmask_imm(0xffff_ffffu32, 32));
+        assert!(!is_bitmask_imm(0, 64));
+        assert!(!is_bitmask_imm(-1, 64));
+        assert!(!is_bitmask_imm(0x1234, 32));
+        assert!(!is_bitmask_imm(0xff00_ff00_00ff_00ffu64 as i64, 64));
+        assert!(!is_bitmask_imm(0x0000_0000_ffff_0001i64, 64));
+    }
+
+    #[test]
+    fn thumb_imm() {
+        assert!(is_thumb_imm(0, false, false));
+        assert!(is_thumb_imm(0xff, false, false));
+        assert!(is_thumb_imm(0x00ab_00ab, false, false));
+        assert!(is_thumb_imm(0xab00_ab00u32, false, false));
+        assert!(is_thumb_imm(0xabab_abab_u32, false, false));
+        assert!(is_thumb_imm(0x3fc, false, false));
+        assert!(is_thumb_imm(0xff00_0000u32, false, false));
+        assert!(is_thumb_imm(0x8000_0000u32, false, false));
+
+        // Only the low 32 bits matter.
+        assert!(is_thumb_imm(-0x8000_0000i64, false, false));
+        assert!(is_thumb_imm(0x1234_5678_0000_00ffi64, false, false));
+
+        assert!(!is_thumb_imm(0x1ff, false, false));
+        assert!(!is_thumb_imm(0x0001_0002, false, false));
+        assert!(!is_thumb_imm(0xf000_000fu32, false, false));
+        assert!(!is_thumb_imm(-2, false, false));
+        assert!(!is_thumb_imm(-4, false, false));
+
+        // All ones is `0xff` in every byte.
+        assert!(is_thumb_imm(-1, false, false));
+
+        assert!(is_thumb_imm(-1, false, true));
+        assert!(is_thumb_imm(-4, true, false));
+        assert!(is_thumb_imm(0xffff_ff00u32, false, true));
+        assert!(!is_thumb_imm(0x1234, true, false));
+    }
 }
@@ -5,11 +5,17 @@
 use cursor::{Cursor, FuncCursor};
 use divconst_magic_numbers::{magicS32, magicS64, magicU32, magicU64};
 use divconst_magic_numbers::{MS32, MS64, MU32, MU64};
+use flowgraph::ControlFlowGraph;
+use gv_canonicalization::do_canonicalize_global_values;
+use ir::condcodes::{CondCode, FloatCC, IntCC};
 use ir::dfg::ValueDef;
+use ir::immediates::{Ieee32, Ieee64, Imm64};
 use ir::instructions::Opcode;
-use ir::types::{I32, I64};
+use ir::types::{B1, F32, I32, I64};
 use ir::Inst;
 use ir::{DataFlowGraph, Function, InstBuilder, InstructionData, Type, Value};
+use known_bits::{known_zero_bits, type_mask};
+use std::vec::Vec;
 use timing;
 
 //----------------------------------------------------------------------
@@ -70,7 +76,8 @@ fn package_up_divrem_info(
     isRem: bool,
 ) -> Option<DivRemByConstInfo> {
     let argRu: u64 = argRs as u64;
-    if !isSigned && argL_ty == I32 && argRu < 0x1_0000_0000 {
+    // An `i32` immediate is sign-extended, so `0x8000_0000` is `0xffff_ffff_8000_0000`.
+    if !isSigned && argL_ty == I32 && (argRu < 0x1_0000_0000 || argRu >= 0xffff_ffff_8000_0000) {
         let con = if isRem {
             DivRemByConstInfo::RemU32
         } else {
@@ -440,6 +447,17 @@ fn do_divrem_transformation(divrem_info: &DivRemByConstInfo, pos: &mut FuncCurso
     }
 }
 
+/// Get the instruction that defines `value`, unless it is patchable.
+///
+/// The immediate of a patchable instruction can change after compilation, so it must not be
+/// folded into its users.
+fn foldable_def(func: &Function, value: Value) -> Option<Inst> {
+    match func.dfg.value_def(value) {
+        ValueDef::Result(inst, _) if !func.is_patchable(inst) => Some(inst),
+        _ => None,
+    }
+}
+
 /// Apply basic simplifications.
 ///
 /// This folds constants with arithmetic to form `_imm` instructions, and other
@@ -447,7 +465,7 @@ fn do_divrem_transformation(divrem_info: &DivRemByConstInfo, pos: &mut FuncCurso
 fn simplify(pos: &mut FuncCursor, inst: Inst) {
     match pos.func.dfg[inst] {
         InstructionData::Binary { opcode, args } => {
-            if let ValueDef::Result(iconst_inst, _) = pos.func.dfg.value_def(args[1]) {
+            if let Some(iconst_inst) = foldable_def(pos.func, args[1]) {
                 if let InstructionData::UnaryImm {
                     opcode: Opcode::Iconst,
                     mut imm,
@@ -480,7 +498,7 @@ fn simplify(pos: &mut FuncCursor, inst: Inst) {
                         .replace(inst)
                         .BinaryImm(new_opcode, ty, imm, args[0]);
                 }
-            } else if let ValueDef::Result(iconst_inst, _) = pos.func.dfg.value_def(args[0]) {
+            } else if let Some(iconst_inst) = foldable_def(pos.func, args[0]) {
                 if let InstructionData::UnaryImm {
                     opcode: Opcode::Iconst,
                     imm,
@@ -500,7 +518,7 @@ fn simplify(pos: &mut FuncCursor, inst: Inst) {
         }
         InstructionData::IntCompare { opcode, cond, args } => {
             debug_assert_eq!(opcode, Opcode::Icmp);
-            if let ValueDef::Result(iconst_inst, _) = pos.func.dfg.value_def(args[1]) {
+            if let Some(iconst_inst) = foldable_def(pos.func, args[1]) {
                 if let InstructionData::UnaryImm {
                     opcode: Opcode::Iconst,
                     imm,
@@ -536,14 +554,879 @@ fn simplify(pos: &mut FuncCursor, inst: Inst) {
     }
 }
 
+/// Get the opcode and argument of the `Unary` instruction defining `value`, if any.
+fn unary_def(dfg: &DataFlowGraph, value: Value) -> Option<(Opcode, Value)> {
+    if let ValueDef::Result(inst, _) = dfg.value_def(value) {
+        if let InstructionData::Unary { opcode, arg } = dfg[inst] {
+            return Some((opcode, arg));
+        }
+    }
+    None
+}
+
+/// Fold integer extensions and reductions of values that are themselves extended, reduced, or
+/// produced by `bint`.
+///
+/// A round trip through a type that is at least as wide as the source is folded unconditionally.
+/// A round trip through a narrower type is only removed when the bits it would clear or replace
+/// are known to be zero already.
+///
+/// Returns true if `inst` was removed.
+fn simplify_conversion(pos: &mut FuncCursor, inst: Inst) -> bool {
+    let opcode = pos.func.dfg[inst].opcode();
+    let (inner, x) = match opcode {
+        Opcode::Uextend | Opcode::Sextend | Opcode::Ireduce => {
+            match unary_def(&pos.func.dfg, pos.func.dfg.inst_args(inst)[0]) {
+                Some(def) => def,
+                None => return false,
+            }
+        }
+        _ => return false,
+    };
+    let ty = pos.func.dfg.ctrl_typevar(inst);
+    let x_ty = pos.func.dfg.value_type(x);
+
+    match (opcode, inner) {
+        // A `bint` result is either 0 or 1, so extending it is the same as a wider `bint`.
+        (Opcode::Uextend, Opcode::Bint) | (Opcode::Sextend, Opcode::Bint) => {
+            pos.func.dfg.replace(inst).bint(ty, x);
+        }
+        (Opcode::Uextend, Opcode::Uextend) | (Opcode::Sextend, Opcode::Sextend) => {
+            pos.func.dfg.replace(inst).Unary(opcode, ty, x);
+        }
+        // The sign bit of a zero-extended value is zero.
+        (Opcode::Sextend, Opcode::Uextend) => {
+            pos.func.dfg.replace(inst).uextend(ty, x);
+        }
+        (Opcode::Ireduce, Opcode::Uextend) | (Opcode::Ireduce, Opcode::Sextend) => {
+            if ty == x_ty {
+                pos.remove_and_replace_with_alias(inst, x);
+                return true;
+            } else if ty.bits() > x_ty.bits() {
+                pos.func.dfg.replace(inst).Unary(inner, ty, x);
+            } else {
+                pos.func.dfg.replace(inst).ireduce(ty, x);
+            }
+        }
+        (Opcode::Uextend, Opcode::Ireduce) | (Opcode::Sextend, Opcode::Ireduce) if ty == x_ty => {
+            // The bits above the narrow type must be zero for `uextend`. For `sextend`, the sign
+            // bit of the narrow type must be zero as well.
+            let narrow_ty = pos.func.dfg.value_type(pos.func.dfg.inst_args(inst)[0]);
+            let low_bits = match opcode {
+                Opcode::Uextend => narrow_ty.bits(),
+                _ => narrow_ty.bits() - 1,
+            };
+            let high = type_mask(x_ty) & !((1 << low_bits) - 1);
+            if known_zero_bits(pos.func, x) & high == high {
+                pos.remove_and_replace_with_alias(inst, x);
+                return true;
+            }
+        }
+        _ => {}
+    }
+    false
+}
+
+/// Remove a `band_imm` whose mask keeps all the bits of its argument that aren't known to be zero.
+///
+/// Returns true if `inst` was removed.
+fn simplify_mask(pos: &mut FuncCursor, inst: Inst) -> bool {
+    if let InstructionData::BinaryImm {
+        opcode: Opcode::BandImm,
+        arg,
+        imm,
+    } = pos.func.dfg[inst]
+    {
+        let imm: i64 = imm.into();
+        let mask = type_mask(pos.func.dfg.value_type(arg));
+        if (known_zero_bits(pos.func, arg) | imm as u64) & mask == mask {
+            pos.remove_and_replace_with_alias(inst, arg);
+            return true;
+        }
+    }
+    false
+}
+
+/// Fold the extraction of a lane from a vector that was made from a single scalar.
+///
+/// All the lanes of a `splat` are its argument, and so is lane 0 of a `scalar_to_vector`.
+///
+/// Returns true if `inst` was removed.
+fn simplify_extractlane(pos: &mut FuncCursor, inst: Inst) -> bool {
+    if let InstructionData::ExtractLane {
+        opcode: Opcode::Extractlane,
+        arg,
+        lane,
+    } = pos.func.dfg[inst]
+    {
+        match unary_def(&pos.func.dfg, arg) {
+            Some((Opcode::Splat, x)) => {
+                pos.remove_and_replace_with_alias(inst, x);
+                return true;
+            }
+            Some((Opcode::ScalarToVector, x)) if lane == 0 => {
+                pos.remove_and_replace_with_alias(inst, x);
+                return true;
+            }
+            _ => {}
+        }
+    }
+    false
+}
+
+/// Fold a comparison of a `bint` result with 0 or 1 into the boolean it was made from.
+///
+/// When the comparison inverts the boolean, an `icmp` or `fcmp` producing it is replaced by the
+/// inverse comparison, and any other boolean gets a `bnot`.
+///
+/// Returns true if `inst` was removed.
+fn simplify_bint_compare(pos: &mut FuncCursor, inst: Inst) -> bool {
+    let (cond, arg, imm) = match pos.func.dfg[inst] {
+        InstructionData::IntCompareImm {
+            opcode: Opcode::IcmpImm,
+            cond,
+            arg,
+            imm,
+        } => (cond, arg, imm.into()),
+        _ => return false,
+    };
+    let same = match (cond, imm) {
+        (IntCC::NotEqual, 0) | (IntCC::Equal, 1) => true,
+        (IntCC::Equal, 0) | (IntCC::NotEqual, 1) => false,
+        _ => return false,
+    };
+    let bool_val = match unary_def(&pos.func.dfg, arg) {
+        Some((Opcode::Bint, bool_val)) if pos.func.dfg.value_type(bool_val) == B1 => bool_val,
+        _ => return false,
+    };
+
+    if same {
+        pos.remove_and_replace_with_alias(inst, bool_val);
+        return true;
+    }
+    if let ValueDef::Result(def_inst, _) = pos.func.dfg.value_def(bool_val) {
+        match pos.func.dfg[def_inst] {
+            InstructionData::IntCompare { cond, args, .. } => {
+                pos.func
+                    .dfg
+                    .replace(inst)
+                    .icmp(cond.inverse(), args[0], args[1]);
+                return false;
+            }
+            InstructionData::IntCompareImm { cond, arg, imm, .. } => {
+                pos.func
+                    .dfg
+                    .replace(inst)
+                    .icmp_imm(cond.inverse(), arg, imm);
+                return false;
+            }
+            InstructionData::FloatCompare { cond, args, .. } => {
+                pos.func
+                    .dfg
+                    .replace(inst)
+                    .fcmp(cond.inverse(), args[0], args[1]);
+                return false;
+            }
+            _ => {}
+        }
+    }
+    pos.func.dfg.replace(inst).bnot(bool_val);
+    false
+}
+
+//----------------------------------------------------------------------
+//
+// Minimum, maximum, and absolute value idioms.
+
+/// The right-hand side of an integer comparison.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum CompareRhs {
+    Value(Value),
+    /// An immediate, zero-extended from the type of the compared values.
+    Imm(u64),
+}
+
+/// Get the condition code and the operands of the integer comparison that defines `value`.
+fn int_compare_def(func: &Function, value: Value) -> Option<(IntCC, Value, CompareRhs)> {
+    match func.dfg[foldable_def(func, value)?] {
+        InstructionData::IntCompare {
+            opcode: Opcode::Icmp,
+            cond,
+            args,
+        } => Some((cond, args[0], CompareRhs::Value(args[1]))),
+        InstructionData::IntCompareImm {
+            opcode: Opcode::IcmpImm,
+            cond,
+            arg,
+            imm,
+        } => {
+            let imm: i64 = imm.into();
+            let mask = type_mask(func.dfg.value_type(arg));
+            Some((cond, arg, CompareRhs::Imm(imm as u64 & mask)))
+        }
+        _ => None,
+    }
+}
+
+/// Is `value` the right-hand side `rhs` of a comparison?
+fn is_compare_rhs(func: &Function, value: Value, rhs: CompareRhs) -> bool {
+    match rhs {
+        CompareRhs::Value(y) => value == y,
+        CompareRhs::Imm(imm) => int_def(func, value) == Some(imm),
+    }
+}
+
+/// Is `value` the negation of `x`, computed by subtracting it from 0?
+fn is_negation(func: &Function, value: Value, x: Value) -> bool {
+    let inst = match foldable_def(func, value) {
+        Some(inst) => inst,
+        None => return false,
+    };
+    match func.dfg[inst] {
+        InstructionData::BinaryImm {
+            opcode: Opcode::IrsubImm,
+            arg,
+            imm,
+        } => arg == x && imm == Imm64::new(0),
+        InstructionData::Binary {
+            opcode: Opcode::Isub,
+            args,
+        } => args[1] == x && int_def(func, args[0]) == Some(0),
+        _ => false,
+    }
+}
+
+/// Get the minimum or maximum instruction that `select(x cond y, x, y)` computes.
+fn int_min_max_opcode(cond: IntCC) -> Option<Opcode> {
+    match cond {
+        IntCC::SignedLessThan | IntCC::SignedLessThanOrEqual => Some(Opcode::Imin),
+        IntCC::SignedGreaterThan | IntCC::SignedGreaterThanOrEqual => Some(Opcode::Imax),
+        IntCC::UnsignedLessThan | IntCC::UnsignedLessThanOrEqual => Some(Opcode::Umin),
+        IntCC::UnsignedGreaterThan | IntCC::UnsignedGreaterThanOrEqual => Some(Opcode::Umax),
+        _ => None,
+    }
+}
+
+/// Replace a `select` between the operands of a comparison with the minimum, maximum, or absolute
+/// value that it computes.
+///
+/// Integer selects become `imin`, `imax`, `umin`, `umax`, or `iabs`. Float selects only become
+/// `fmin_pseudo` or `fmax_pseudo` for the `lt` and `gt` conditions, since the other conditions
+/// treat zeroes or NaNs differently.
+fn simplify_select_idiom(pos: &mut FuncCursor, inst: Inst) {
+    let (c, t, f) = match pos.func.dfg[inst] {
+        InstructionData::Ternary {
+            opcode: Opcode::Select,
+            args,
+        } => (args[0], args[1], args[2]),
+        _ => return,
+    };
+    let ty = pos.func.dfg.value_type(t);
+    if ty.is_vector() {
+        return;
+    }
+
+    if ty.is_int() {
+        let (cond, x, rhs) = match int_compare_def(pos.func, c) {
+            Some(def) => def,
+            None => return,
+        };
+
+        // `select(x cond y, y, x)` is `select(x !cond y, x, y)`.
+        let min_max = if t == x && is_compare_rhs(pos.func, f, rhs) {
+            Some((cond, f))
+        } else if f == x && is_compare_rhs(pos.func, t, rhs) {
+            Some((cond.inverse(), t))
+        } else {
+            None
+        };
+        if let Some((cond, y)) = min_max {
+            if let Some(opcode) = int_min_max_opcode(cond) {
+                pos.func.dfg.replace(inst).Binary(opcode, ty, x, y);
+            }
+            return;
+        }
+
+        if rhs == CompareRhs::Imm(0) {
+            let abs_cond = if f == x && is_negation(pos.func, t, x) {
+                Some(cond)
+            } else if t == x && is_negation(pos.func, f, x) {
+                Some(cond.inverse())
+            } else {
+                None
+            };
+            match abs_cond {
+                Some(IntCC::SignedLessThan) | Some(IntCC::SignedLessThanOrEqual) => {
+                    pos.func.dfg.replace(inst).iabs(x);
+                    return;
+                }
+                _ => {}
+            }
+        }
+
+        simplify_clamp(pos, inst, cond, x, rhs, t, f);
+    } else if ty.is_float() {
+        let (cond, x, y) = match foldable_def(pos.func, c).map(|def| &pos.func.dfg[def]) {
+            Some(&InstructionData::FloatCompare {
+                opcode: Opcode::Fcmp,
+                cond,
+                args,
+            }) => (cond, args[0], args[1]),
+            _ => return,
+        };
+        let (opcode, a, b) = match (cond, t == x && f == y, t == y && f == x) {
+            (FloatCC::LessThan, true, _) => (Opcode::FminPseudo, x, y),
+            (FloatCC::GreaterThan, true, _) => (Opcode::FmaxPseudo, x, y),
+            (FloatCC::LessThan, _, true) => (Opcode::FmaxPseudo, y, x),
+            (FloatCC::GreaterThan, _, true) => (Opcode::FminPseudo, y, x),
+            _ => return,
+        };
+        pos.func.dfg.replace(inst).Binary(opcode, ty, a, b);
+    }
+}
+
+/// Replace the second half of a clamp that compares the clamped value `x` instead of the result
+/// `m` of the first half.
+///
+/// `select(x < lo, lo, imin(x, hi))` is `imax(imin(x, hi), lo)` when the constants satisfy
+/// `lo <= hi`, and the same goes for the other minimums and maximums.
+fn simplify_clamp(
+    pos: &mut FuncCursor,
+    inst: Inst,
+    cond: IntCC,
+    x: Value,
+    rhs: CompareRhs,
+    t: Value,
+    f: Value,
+) {
+    let outer = match rhs {
+        CompareRhs::Imm(imm) => imm,
+        CompareRhs::Value(_) => return,
+    };
+    // Normalize to `select(x cond k, k, m)`.
+    let (cond, k, m) = if is_compare_rhs(pos.func, t, rhs) {
+        (cond, t, f)
+    } else if is_compare_rhs(pos.func, f, rhs) {
+        (cond.inverse(), f, t)
+    } else {
+        return;
+    };
+    let (inner_opcode, inner) = match foldable_def(pos.func, m).map(|def| &pos.func.dfg[def]) {
+        Some(&InstructionData::Binary { opcode, args }) if args[0] == x => {
+            match int_def(pos.func, args[1]) {
+                Some(inner) => (opcode, inner),
+                None => return,
+            }
+        }
+        _ => return,
+    };
+
+    let ty = pos.func.dfg.value_type(x);
+    let signed = |imm| sign_extend(imm, ty.bits());
+    let opcode = match (int_min_max_opcode(cond), inner_opcode) {
+        (Some(Opcode::Imin), Opcode::Imin) if signed(outer) <= signed(inner) => Opcode::Imax,
+        (Some(Opcode::Imax), Opcode::Imax) if signed(outer) >= signed(inner) => Opcode::Imin,
+        (Some(Opcode::Umin), Opcode::Umin) if outer <= inner => Opcode::Umax,
+        (Some(Opcode::Umax), Opcode::Umax) if outer >= inner => Opcode::Umin,
+        _ => return,
+    };
+    pos.func.dfg.replace(inst).Binary(opcode, ty, m, k);
+}
+
+//----------------------------------------------------------------------
+//
+// Constant folding.
+
+/// A constant defined by a `bconst`, `iconst`, `f32const`, or `f64const` instruction.
+#[derive(Clone, Copy)]
+enum Constant {
+    Bool(bool),
+    /// The bits of an integer constant, zero-extended from its type.
+    Int(u64),
+    F32(Ieee32),
+    F64(Ieee64),
+}
+
+/// Get the constant that defines `value`, if any.
+fn constant_def(func: &Function, value: Value) -> Option<Constant> {
+    let ty = func.dfg.value_type(value);
+    if ty.is_vector() {
+        return None;
+    }
+    match func.dfg[foldable_def(func, value)?] {
+        InstructionData::UnaryBool {
+            opcode: Opcode::Bconst,
+            imm,
+        } => Some(Constant::Bool(imm)),
+        InstructionData::UnaryImm {
+            opcode: Opcode::Iconst,
+            imm,
+        } => {
+            let imm: i64 = imm.into();
+            Some(Constant::Int(imm as u64 & type_mask(ty)))
+        }
+        InstructionData::UnaryIeee32 {
+            opcode: Opcode::F32const,
+            imm,
+        } => Some(Constant::F32(imm)),
+        InstructionData::UnaryIeee64 {
+            opcode: Opcode::F64const,
+            imm,
+        } => Some(Constant::F64(imm)),
+        _ => None,
+    }
+}
+
+/// Get the integer or boolean constant that defines `value` as an integer, if any.
+fn int_def(func: &Function, value: Value) -> Option<u64> {
+    match constant_def(func, value) {
+        Some(Constant::Int(x)) => Some(x),
+        Some(Constant::Bool(b)) => Some(b as u64),
+        _ => None,
+    }
+}
+
+/// Sign-extend the low `bits` bits of `x`.
+fn sign_extend(x: u64, bits: u16) -> i64 {
+    let shift = 64 - u32::from(bits);
+    ((x << shift) as i64) >> shift
+}
+
+/// Evaluate an integer comparison of `x` and `y`, which have type `ty`.
+fn int_compare(cond: IntCC, ty: Type, x: u64, y: u64) -> bool {
+    let (sx, sy) = (sign_extend(x, ty.bits()), sign_extend(y, ty.bits()));
+    match cond {
+        IntCC::Equal => x == y,
+        IntCC::NotEqual => x != y,
+        IntCC::SignedLessThan => sx < sy,
+        IntCC::SignedGreaterThanOrEqual => sx >= sy,
+        IntCC::SignedGreaterThan => sx > sy,
+        IntCC::SignedLessThanOrEqual => sx <= sy,
+        IntCC::UnsignedLessThan => x < y,
+        IntCC::UnsignedGreaterThanOrEqual => x >= y,
+        IntCC::UnsignedGreaterThan => x > y,
+        IntCC::UnsignedLessThanOrEqual => x <= y,
+    }
+}
+
+/// Evaluate a floating point comparison of `x` and `y`.
+fn float_compare(cond: FloatCC, x: f64, y: f64) -> bool {
+    let unordered = x.is_nan() || y.is_nan();
+    match cond {
+        FloatCC::Ordered => !unordered,
+        FloatCC::Unordered => unordered,
+        FloatCC::Equal => x == y,
+        FloatCC::NotEqual => x != y,
+        FloatCC::OrderedNotEqual => !unordered && x != y,
+        FloatCC::UnorderedOrEqual => unordered || x == y,
+        FloatCC::LessThan => x < y,
+        FloatCC::LessThanOrEqual => x <= y,
+        FloatCC::GreaterThan => x > y,
+        FloatCC::GreaterThanOrEqual => x >= y,
+        FloatCC::UnorderedOrLessThan => unordered || x < y,
+        FloatCC::UnorderedOrLessThanOrEqual => unordered || x <= y,
+        FloatCC::UnorderedOrGreaterThan => unordered || x > y,
+        FloatCC::UnorderedOrGreaterThanOrEqual => unordered || x >= y,
+    }
+}
+
+/// Evaluate a binary integer instruction with arguments `x` and `y` of type `ty`.
+///
+/// Returns `None` for a division that traps, which must be left for the target to execute.
+fn fold_int_binary(opcode: Opcode, ty: Type, x: u64, y: u64) -> Option<u64> {
+    let bits = ty.bits();
+    let (sx, sy) = (sign_extend(x, bits), sign_extend(y, bits));
+    let min = i64::min_value() >> (64 - bits);
+    let shift = (y % u64::from(bits)) as u32;
+    let result = match opcode {
+        Opcode::Iadd | Opcode::IaddImm => x.wrapping_add(y),
+        Opcode::Isub => x.wrapping_sub(y),
+        Opcode::IrsubImm => y.wrapping_sub(x),
+        Opcode::Imul | Opcode::ImulImm => x.wrapping_mul(y),
+        Opcode::Udiv | Opcode::UdivImm if y != 0 => x / y,
+        Opcode::Urem | Opcode::UremImm if y != 0 => x % y,
+        Opcode::Sdiv | Opcode::SdivImm if y != 0 && (sx, sy) != (min, -1) => (sx / sy) as u64,
+        Opcode::Srem | Opcode::SremImm if y != 0 => sx.wrapping_rem(sy) as u64,
+        Opcode::Band | Opcode::BandImm => x & y,
+        Opcode::Bor | Opcode::BorImm => x | y,
+        Opcode::Bxor | Opcode::BxorImm => x ^ y,
+        Opcode::BandNot => x & !y,
+        Opcode::BorNot => x | !y,
+        Opcode::BxorNot => x ^ !y,
+        Opcode::Ishl | Opcode::IshlImm => x << shift,
+        Opcode::Ushr | Opcode::UshrImm => x >> shift,
+        Opcode::Sshr | Opcode::SshrImm => (sx >> shift) as u64,
+        Opcode::Rotl | Opcode::RotlImm if shift != 0 => x << shift | x >> (u32::from(bits) - shift),
+        Opcode::Rotr | Opcode::RotrImm if shift != 0 => x >> shift | x << (u32::from(bits) - shift),
+        Opcode::Rotl | Opcode::RotlImm | Opcode::Rotr | Opcode::RotrImm => x,
+        _ => return None,
+    };
+    Some(result & type_mask(ty))
+}
+
+/// Evaluate a unary integer instruction with argument `x` of type `ty`.
+fn fold_int_unary(opcode: Opcode, ty: Type, x: u64) -> Option<u64> {
+    let bits = u32::from(ty.bits());
+    let result = match opcode {
+        Opcode::Bnot => !x,
+        Opcode::Popcnt => x.count_ones().into(),
+        Opcode::Clz => (x.leading_zeros() - (64 - bits)).into(),
+        Opcode::Ctz => x.trailing_zeros().min(bits).into(),
+        _ => return None,
+    };
+    Some(result & type_mask(ty))
+}
+
+/// Get the sign bit of the float type `ty`.
+fn float_sign_bit(ty: Type) -> u64 {
+    1 << (ty.bits() - 1)
+}
+
+/// Get the bits of a float constant, or `None` if `c` isn't a float.
+fn float_bits(c: Constant) -> Option<u64> {
+    match c {
+        Constant::F32(x) => Some(x.bits().into()),
+        Constant::F64(x) => Some(x.bits()),
+        _ => None,
+    }
+}
+
+/// Make a float constant of type `ty` from its bits.
+fn float_with_bits(ty: Type, bits: u64) -> Constant {
+    if ty == F32 {
+        Constant::F32(Ieee32::with_bits(bits as u32))
+    } else {
+        Constant::F64(Ieee64::with_bits(bits))
+    }
+}
+
+/// Get the value of a float constant. All `f32` values are represented exactly by an `f64`.
+fn float_value(c: Constant) -> Option<f64> {
+    match c {
+        Constant::F32(x) => Some(f32::from_bits(x.bits()).into()),
+        Constant::F64(x) => Some(f64::from_bits(x.bits())),
+        _ => None,
+    }
+}
+
+/// Is `c` a NaN with all the bits of its trailing significand other than the MSB cleared?
+fn is_canonical_nan(c: Constant) -> bool {
+    match c {
+        Constant::F32(x) => x.bits() & 0x7fff_ffff == 0x7fc0_0000,
+        Constant::F64(x) => x.bits() & 0x7fff_ffff_ffff_ffff == 0x7ff8_0000_0000_0000,
+        _ => false,
+    }
+}
+
+/// Get the canonical NaN of type `ty`, with a positive sign.
+fn canonical_nan(ty: Type) -> Constant {
+    if ty == F32 {
+        Constant::F32(Ieee32::with_bits(0x7fc0_0000))
+    } else {
+        Constant::F64(Ieee64::with_bits(0x7ff8_0000_0000_0000))
+    }
+}
+
+/// Evaluate an arithmetic floating point instruction with a result of type `ty`.
+///
+/// The result is computed by `op` from the argument values and rounded to `ty`. Computing the
+/// basic arithmetic operations on `f32` values in `f64` and rounding the result gives the
+/// correctly rounded `f32` result.
+///
+/// NaN results follow the rules for arithmetic instructions: when all NaN inputs are canonical,
+/// the result is the canonical NaN. Otherwise the payload of the result depends on the target, so
+/// the instruction isn't folded.
+fn fold_float_arith<F>(ty: Type, args: &[Constant], op: F) -> Option<Constant>
+where
+    F: FnOnce(&[f64]) -> f64,
+{
+    let values = args
+        .iter()
+        .map(|&arg| float_value(arg))
+        .collect::<Option<Vec<_>>>()?;
+    if values.iter().any(|x| x.is_nan()) {
+        let canonical = args
+            .iter()
+            .zip(&values)
+            .all(|(&arg, x)| !x.is_nan() || is_canonical_nan(arg));
+        return if canonical {
+            Some(canonical_nan(ty))
+        } else {
+            None
+        };
+    }
+    let result = op(&values);
+    Some(if result.is_nan() {
+        canonical_nan(ty)
+    } else if ty == F32 {
+        Constant::F32(Ieee32::with_float(result as f32))
+    } else {
+        Constant::F64(Ieee64::with_float(result))
+    })
+}
+
+/// Evaluate `inst`, which has a single result of type `ty`, if its arguments are constants.
+///
+/// Returns `None` if the instruction can't be evaluated at compile time, or if it must be left
+/// for the target to execute because it traps.
+fn evaluate(func: &Function, inst: Inst, ty: Type) -> Option<Constant> {
+    match func.dfg[inst] {
+        InstructionData::Unary { opcode, arg } => {
+            let arg_ty = func.dfg.value_type(arg);
+            match (opcode, constant_def(func, arg)?) {
+                (Opcode::Bnot, Constant::Bool(b)) => Some(Constant::Bool(!b)),
+                (Opcode::Bint, Constant::Bool(b)) => Some(Constant::Int(b as u64)),
+                (Opcode::Uextend, Constant::Int(x)) | (Opcode::Ireduce, Constant::Int(x)) => {
+                    Some(Constant::Int(x & type_mask(ty)))
+                }
+                (Opcode::Sextend, Constant::Int(x)) => Some(Constant::Int(
+                    sign_extend(x, arg_ty.bits()) as u64 & type_mask(ty),
+                )),
+                (Opcode::FcvtFromSint, Constant::Int(x)) => {
+                    let x = sign_extend(x, arg_ty.bits());
+                    Some(if ty == F32 {
+                        Constant::F32(Ieee32::with_float(x as f32))
+                    } else {
+                        Constant::F64(Ieee64::with_float(x as f64))
+                    })
+                }
+                (Opcode::FcvtFromUint, Constant::Int(x)) => Some(if ty == F32 {
+                    Constant::F32(Ieee32::with_float(x as f32))
+                } else {
+                    Constant::F64(Ieee64::with_float(x as f64))
+                }),
+                // NaN and values that are out of range after truncation trap.
+                (Opcode::FcvtToSint, c) => {
+                    let x = float_value(c)?;
+                    let limit = (1u64 << (ty.bits() - 1)) as f64;
+                    if x >= -limit && x < limit {
+                        Some(Constant::Int(x as i64 as u64 & type_mask(ty)))
+                    } else {
+                        None
+                    }
+                }
+                (Opcode::FcvtToUint, c) => {
+                    let x = float_value(c)?;
+                    let limit = 2.0 * (1u64 << (ty.bits() - 1)) as f64;
+                    if x > -1.0 && x < limit {
+                        Some(Constant::Int(x as u64))
+                    } else {
+                        None
+                    }
+                }
+                (Opcode::Fpromote, c) | (Opcode::Fdemote, c) => {
+                    fold_float_arith(ty, &[c], |v| v[0])
+                }
+                (Opcode::Fneg, c) => Some(float_with_bits(ty, float_bits(c)? ^ float_sign_bit(ty))),
+                (Opcode::Fabs, c) => {
+                    Some(float_with_bits(ty, float_bits(c)? & !float_sign_bit(ty)))
+                }
+                (opcode, Constant::Int(x)) => fold_int_unary(opcode, ty, x).map(Constant::Int),
+                _ => None,
+            }
+        }
+        InstructionData::Binary { opcode, args } => {
+            match (constant_def(func, args[0])?, constant_def(func, args[1])?) {
+                (Constant::Int(x), Constant::Int(y)) => {
+                    fold_int_binary(opcode, ty, x, y).map(Constant::Int)
+                }
+                (Constant::Bool(x), Constant::Bool(y)) => match opcode {
+                    Opcode::Band => Some(Constant::Bool(x & y)),
+                    Opcode::Bor => Some(Constant::Bool(x | y)),
+                    Opcode::Bxor => Some(Constant::Bool(x ^ y)),
+                    _ => None,
+                },
+                (x, y) => match opcode {
+                    Opcode::Fadd => fold_float_arith(ty, &[x, y], |v| v[0] + v[1]),
+                    Opcode::Fsub => fold_float_arith(ty, &[x, y], |v| v[0] - v[1]),
+                    Opcode::Fmul => fold_float_arith(ty, &[x, y], |v| v[0] * v[1]),
+                    Opcode::Fdiv => fold_float_arith(ty, &[x, y], |v| v[0] / v[1]),
+                    // The minimum of -0.0 and +0.0 is -0.0.
+                    Opcode::Fmin => fold_float_arith(ty, &[x, y], |v| {
+                        if v[0] < v[1] || (v[0] == v[1] && v[0].is_sign_negative()) {
+                            v[0]
+                        } else {
+                            v[1]
+                        }
+                    }),
+                    Opcode::Fmax => fold_float_arith(ty, &[x, y], |v| {
+                        if v[0] > v[1] || (v[0] == v[1] && v[0].is_sign_positive()) {
+                            v[0]
+                        } else {
+                            v[1]
+                        }
+                    }),
+                    Opcode::Fcopysign => {
+                        let sign = float_sign_bit(ty);
+                        let bits = float_bits(x)? & !sign | float_bits(y)? & sign;
+                        Some(float_with_bits(ty, bits))
+                    }
+                    _ => None,
+                },
+            }
+        }
+        InstructionData::BinaryImm { opcode, arg, imm } => {
+            let x = int_def(func, arg)?;
+            let imm: i64 = imm.into();
+            let y = imm as u64 & type_mask(ty);
+            fold_int_binary(opcode, ty, x, y).map(Constant::Int)
+        }
+        InstructionData::IntCompare { cond, args, .. } => {
+            let (x, y) = (int_def(func, args[0])?, int_def(func, args[1])?);
+            let arg_ty = func.dfg.value_type(args[0]);
+            Some(Constant::Bool(int_compare(cond, arg_ty, x, y)))
+        }
+        InstructionData::IntCompareImm { cond, arg, imm, .. } => {
+            let x = int_def(func, arg)?;
+            let arg_ty = func.dfg.value_type(arg);
+            let imm: i64 = imm.into();
+            let y = imm as u64 & type_mask(arg_ty);
+            Some(Constant::Bool(int_compare(cond, arg_ty, x, y)))
+        }
+        InstructionData::FloatCompare { cond, args, .. } => {
+            let x = float_value(constant_def(func, args[0])?)?;
+            let y = float_value(constant_def(func, args[1])?)?;
+            Some(Constant::Bool(float_compare(cond, x, y)))
+        }
+        _ => None,
+    }
+}
+
+/// Fold a conditional branch or a `br_table` whose condition or index is a constant.
+///
+/// A branch that is never taken is removed, and one that is always taken becomes a `jump`. The
+/// instructions after a `jump` are moved to a new EBB, which is unreachable and left for the
+/// unreachable code elimination pass to remove.
+///
+/// Returns true if `inst` was folded.
+fn fold_branch(pos: &mut FuncCursor, cfg: &mut ControlFlowGraph, inst: Inst) -> bool {
+    let (taken, destination) = match pos.func.dfg[inst] {
+        InstructionData::Branch {
+            opcode,
+            destination,
+            ..
+        } => match int_def(pos.func, pos.func.dfg.inst_args(inst)[0]) {
+            Some(x) => ((x != 0) == (opcode == Opcode::Brnz), destination),
+            None => return false,
+        },
+        InstructionData::BranchIcmp {
+            cond, destination, ..
+        } => {
+            let args = pos.func.dfg.inst_args(inst);
+            match (int_def(pos.func, args[0]), int_def(pos.func, args[1])) {
+                (Some(x), Some(y)) => {
+                    let ty = pos.func.dfg.value_type(args[0]);
+                    (int_compare(cond, ty, x, y), destination)
+                }
+                _ => return false,
+            }
+        }
+        InstructionData::BranchTable {
+            arg,
+            destination,
+            table,
+            ..
+        } => match int_def(pos.func, arg) {
+            Some(index) => {
+                let entry = if index < pos.func.jump_tables[table].len() as u64 {
+                    pos.func.jump_tables[table].get_entry(index as usize)
+                } else {
+                    None
+                };
+                (true, entry.unwrap_or(destination))
+            }
+            None => return false,
+        },
+        _ => return false,
+    };
+
+    let ebb = pos.func.layout.pp_ebb(inst);
+    if taken {
+        let args = pos.func.dfg.inst_variable_args(inst).to_vec();
+        pos.func.dfg.replace(inst).jump(destination, &args);
+        if let Some(next) = pos.func.layout.next_inst(inst) {
+            let unreachable_ebb = pos.func.dfg.make_ebb();
+            pos.func.layout.split_ebb(unreachable_ebb, next);
+            cfg.recompute_ebb(pos.func, unreachable_ebb);
+        }
+    } else {
+        pos.remove_inst_and_step_back();
+    }
+    cfg.recompute_ebb(pos.func, ebb);
+    true
+}
+
+/// Replace a `select` whose condition is a constant with the selected value.
+///
+/// Returns true if `inst` was removed.
+fn fold_select(pos: &mut FuncCursor, inst: Inst) -> bool {
+    if let InstructionData::Ternary {
+        opcode: Opcode::Select,
+        args,
+    } = pos.func.dfg[inst]
+    {
+        if let Some(x) = int_def(pos.func, args[0]) {
+            let value = if x != 0 { args[1] } else { args[2] };
+            pos.remove_and_replace_with_alias(inst, value);
+            return true;
+        }
+    }
+    false
+}
+
+/// Replace `inst` with a constant if all its arguments are constants.
+///
+/// Returns true if `inst` was folded.
+fn fold_constants(pos: &mut FuncCursor, cfg: &mut ControlFlowGraph, inst: Inst) -> bool {
+    if pos.func.is_patchable(inst) {
+        return false;
+    }
+    if fold_branch(pos, cfg, inst) || fold_select(pos, inst) {
+        return true;
+    }
+    if pos.func.dfg.inst_results(inst).len() != 1 {
+        return false;
+    }
+    let ty = pos.func.dfg.value_type(pos.func.dfg.first_result(inst));
+    if ty.is_flags() || ty.is_vector() {
+        return false;
+    }
+    match evaluate(pos.func, inst, ty) {
+        Some(Constant::Bool(b)) => pos.func.dfg.replace(inst).bconst(ty, b),
+        Some(Constant::Int(x)) => pos
+            .func
+            .dfg
+            .replace(inst)
+            .iconst(ty, sign_extend(x, ty.bits())),
+        Some(Constant::F32(x)) => pos.func.dfg.replace(inst).f32const(x),
+        Some(Constant::F64(x)) => pos.func.dfg.replace(inst).f64const(x),
+        None => return false,
+    };
+    true
+}
+
 /// The main pre-opt pass.
-pub fn do_preopt(func: &mut Function) {
+pub fn do_preopt(func: &mut Function, cfg: &mut ControlFlowGraph) {
     let _tt = timing::preopt();
+    do_canonicalize_global_values(func);
     let mut pos = FuncCursor::new(func);
     while let Some(_ebb) = pos.next_ebb() {
         while let Some(inst) = pos.next_inst() {
+            // Fold instructions with constant arguments.
+            if fold_constants(&mut pos, cfg, inst) {
+                continue;
+            }
+
             // Apply basic simplifications.
             simplify(&mut pos, inst);
+            if simplify_conversion(&mut pos, inst)
+                || simplify_mask(&mut pos, inst)
+                || simplify_bint_compare(&mut pos, inst)
+                || simplify_extractlane(&mut pos, inst)
+            {
+                continue;
+            }
+            simplify_select_idiom(&mut pos, inst);
 
             //-- BEGIN -- division by constants ----------------
 
@@ -4,6 +4,7 @@ use entity::SecondaryMap;
 use ir;
 use ir::entities::{AnyEntity, Inst, Value};
 use ir::function::Function;
+use ir::ExternalName;
 use isa::TargetIsa;
 use result::CodegenError;
 use std::boxed::Box;
@@ -57,6 +58,10 @@ impl<'a> FuncWriter for PrettyVerifierError<'a> {
     ) -> fmt::Result {
         pretty_preamble_error(w, func, entity, value, &mut *self.0, self.1)
     }
+
+    fn resolve_name(&self, name: &ExternalName) -> Option<ExternalName> {
+        self.0.resolve_name(name)
+    }
 }
 
 /// Pretty-print a function verifier error.
@@ -21,6 +21,7 @@ use std::fmt;
 use std::iter;
 use std::slice;
 use std::vec::Vec;
+use stats::CodegenStats;
 use timing;
 
 // # Implementation
@@ -80,6 +81,8 @@ struct Context<'a> {
     values: &'a mut Vec<Value>,
     predecessors: &'a mut Vec<Inst>,
     backedges: &'a mut Vec<Inst>,
+
+    stats: &'a mut CodegenStats,
 }
 
 impl Coalescing {
@@ -113,6 +116,7 @@ impl Coalescing {
         domtree: &DominatorTree,
         liveness: &mut Liveness,
         virtregs: &mut VirtRegs,
+        stats: &mut CodegenStats,
     ) {
         let _tt = timing::ra_cssa();
         debug!("Coalescing for:\n{}", func.display(isa));
@@ -131,6 +135,7 @@ impl Coalescing {
             values: &mut self.values,
             predecessors: &mut self.predecessors,
             backedges: &mut self.backedges,
+            stats,
         };
 
         // Run phase 1 (union-find) of the coalescing algorithm on the current function.
@@ -199,7 +204,8 @@ impl<'a> Context<'a> {
                     pred_inst,
                     pred_ebb,
                     self.liveness.context(&self.func.layout),
-                ) {
+                ) && !is_passed_unchanged(&self.func.dfg, pred_inst, i, param)
+                {
                     self.isolate_param(ebb, param);
                 }
             }
@@ -221,6 +227,11 @@ impl<'a> Context<'a> {
         {
             let arg = self.func.dfg.inst_variable_args(pred_inst)[argnum];
 
+            // A parameter that a back-edge passes back to itself is already in the right place.
+            if arg == param {
+                continue;
+            }
+
             // Never coalesce incoming function parameters on the stack. These parameters are
             // pre-spilled, and the rest of the virtual register would be forced to spill to the
             // `incoming_arg` stack slot too.
@@ -293,6 +304,7 @@ impl<'a> Context<'a> {
         let mut pos = EncCursor::new(self.func, self.isa).at_first_inst(ebb);
         pos.ins().with_result(param).copy(new_val);
         let inst = pos.built_inst();
+        self.stats.copies += 1;
         self.liveness.move_def_locally(param, inst);
 
         debug!(
@@ -348,6 +360,7 @@ impl<'a> Context<'a> {
         let mut pos = EncCursor::new(self.func, self.isa).at_inst(pred_inst);
         let copy = pos.ins().copy(pred_val);
         let inst = pos.built_inst();
+        self.stats.copies += 1;
 
         // Create a live range for the new value.
         // TODO: Handle affinity for ghost values.
@@ -384,6 +397,22 @@ impl<'a> Context<'a> {
     }
 }
 
+/// Is `param` passed unchanged as argument number `argnum` by the branch `pred_inst`, and not as
+/// any other argument?
+///
+/// Loops often carry values in EBB parameters that are passed back to the loop header unchanged.
+/// The parameter is live at the back-edge, but only because of its use as its own argument, so it
+/// doesn't interfere with the other argument values for the parameter.
+fn is_passed_unchanged(
+    dfg: &ir::DataFlowGraph,
+    pred_inst: Inst,
+    argnum: usize,
+    param: Value,
+) -> bool {
+    let args = dfg.inst_variable_args(pred_inst);
+    args[argnum] == param && args.iter().filter(|&&arg| arg == param).count() == 1
+}
+
 /// Phase 2: Dominator forests.
 ///
 /// The main entry point is `process_vregs()`.
@@ -56,6 +56,7 @@ use regalloc::liverange::{LiveRange, LiveRangeContext};
 use regalloc::register_set::RegisterSet;
 use regalloc::solver::{Solver, SolverError};
 use regalloc::RegDiversions;
+use settings::CalleeSavedStrategy;
 use std::mem;
 use timing;
 
@@ -125,6 +126,7 @@ impl Coloring {
     ) {
         let _tt = timing::ra_coloring();
         debug!("Coloring for:\n{}", func.display(isa));
+        self.solver.set_deferred_regs(deferred_regs(isa, func));
         let mut ctx = Context {
             usable_regs: isa.allocatable_registers(func),
             cur: EncCursor::new(func, isa),
@@ -139,6 +141,28 @@ impl Coloring {
     }
 }
 
+/// Get the registers that should be assigned last in `func`, as configured by the
+/// `callee_saved_strategy` setting.
+fn deferred_regs(isa: &TargetIsa, func: &Function) -> RegisterSet {
+    let callee_saved = isa.callee_saved_registers(func);
+    match isa.flags().callee_saved_strategy() {
+        CalleeSavedStrategy::Auto => RegisterSet::empty(),
+        CalleeSavedStrategy::Minimize => callee_saved,
+        CalleeSavedStrategy::Prefer => {
+            let has_calls = func.layout.ebbs().any(|ebb| {
+                func.layout
+                    .ebb_insts(ebb)
+                    .any(|inst| func.dfg[inst].opcode().is_call())
+            });
+            if has_calls {
+                callee_saved.complement()
+            } else {
+                callee_saved
+            }
+        }
+    }
+}
+
 impl<'a> Context<'a> {
     /// Run the coloring algorithm.
     fn run(&mut self, tracker: &mut LiveValueTracker) {
@@ -16,6 +16,7 @@ use regalloc::reload::Reload;
 use regalloc::spilling::Spilling;
 use regalloc::virtregs::VirtRegs;
 use result::CodegenResult;
+use stats::CodegenStats;
 use timing;
 use topo_order::TopoOrder;
 use verifier::{verify_context, verify_cssa, verify_liveness, verify_locations, VerifierErrors};
@@ -30,6 +31,7 @@ pub struct Context {
     spilling: Spilling,
     reload: Reload,
     coloring: Coloring,
+    stats: CodegenStats,
 }
 
 impl Context {
@@ -47,6 +49,7 @@ impl Context {
             spilling: Spilling::new(),
             reload: Reload::new(),
             coloring: Coloring::new(),
+            stats: CodegenStats::default(),
         }
     }
 
@@ -60,6 +63,14 @@ impl Context {
         self.spilling.clear();
         self.reload.clear();
         self.coloring.clear();
+        self.stats = CodegenStats::default();
+    }
+
+    /// Get the statistics about the code inserted by the last run of the register allocator.
+    ///
+    /// Only the spill, reload, and copy counters are set.
+    pub fn stats(&self) -> CodegenStats {
+        self.stats
     }
 
     /// Allocate registers in `func`.
@@ -80,6 +91,7 @@ impl Context {
 
         // `Liveness` and `Coloring` are self-clearing.
         self.virtregs.clear();
+        self.stats = CodegenStats::default();
 
         // Tracker state (dominator live sets) is actually reused between the spilling and coloring
         // phases.
@@ -104,6 +116,7 @@ impl Context {
             domtree,
             &mut self.liveness,
             &mut self.virtregs,
+            &mut self.stats,
         );
 
         if isa.flags().enable_verifier() {
@@ -132,6 +145,7 @@ impl Context {
             &self.virtregs,
             &mut self.topo,
             &mut self.tracker,
+            &mut self.stats,
         );
 
         if isa.flags().enable_verifier() {
@@ -159,6 +173,7 @@ impl Context {
             &mut self.liveness,
             &mut self.topo,
             &mut self.tracker,
+            &mut self.stats,
         );
 
         if isa.flags().enable_verifier() {
@@ -118,6 +118,15 @@ impl RegisterSet {
         }
     }
 
+    /// Get the set of register units that are not in this set.
+    pub fn complement(&self) -> Self {
+        let mut avail = self.avail;
+        for x in &mut avail {
+            *x = !*x;
+        }
+        Self { avail }
+    }
+
     /// Return an object that can display this register set, using the register info from the
     /// target ISA.
     pub fn display<'a, R: Into<Option<&'a RegInfo>>>(&self, regs: R) -> DisplayRegisterSet<'a> {
@@ -320,4 +329,17 @@ mod tests {
         regs1.intersect(&regs2);
         assert!(regs1.interferes_with(&regs2));
     }
+
+    #[test]
+    fn complement() {
+        let mut regs = RegisterSet::empty();
+        regs.free(&GPR, 30);
+        regs.free(&DPR, 33);
+
+        let others = regs.complement();
+        assert_eq!(others.iter(GPR).count(), 5);
+        assert!(!others.is_avail(GPR, 30));
+        assert!(!others.is_avail(GPR, 34));
+        assert_eq!(others.iter(DPR).collect::<Vec<_>>(), [28, 35]);
+    }
 }
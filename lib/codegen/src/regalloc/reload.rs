@@ -20,6 +20,7 @@ use regalloc::affinity::Affinity;
 use regalloc::live_value_tracker::{LiveValue, LiveValueTracker};
 use regalloc::liveness::Liveness;
 use std::vec::Vec;
+use stats::CodegenStats;
 use timing;
 use topo_order::TopoOrder;
 
@@ -44,6 +45,9 @@ struct Context<'a> {
 
     candidates: &'a mut Vec<ReloadCandidate>,
     reloads: &'a mut SparseMap<Value, ReloadedValue>,
+
+    // Statistics to update.
+    stats: &'a mut CodegenStats,
 }
 
 impl Reload {
@@ -70,6 +74,7 @@ impl Reload {
         liveness: &mut Liveness,
         topo: &mut TopoOrder,
         tracker: &mut LiveValueTracker,
+        stats: &mut CodegenStats,
     ) {
         let _tt = timing::ra_reload();
         debug!("Reload for:\n{}", func.display(isa));
@@ -81,6 +86,7 @@ impl Reload {
             topo,
             candidates: &mut self.candidates,
             reloads: &mut self.reloads,
+            stats,
         };
         ctx.run(tracker)
     }
@@ -296,6 +302,7 @@ impl<'a> Context<'a> {
 
             let reg = self.cur.ins().fill(cand.value);
             let fill = self.cur.built_inst();
+            self.stats.reloads += 1;
 
             self.reloads.insert(ReloadedValue {
                 stack: cand.value,
@@ -336,6 +343,7 @@ impl<'a> Context<'a> {
             self.cur.func.dfg.replace(inst).fill(cand.value);
             let ok = self.cur.func.update_encoding(inst, self.cur.isa).is_ok();
             debug_assert!(ok);
+            self.stats.reloads += 1;
         }
     }
 
@@ -92,7 +92,8 @@
 //!    its register class constraint.
 //! 2. Sort the variables in order of increasing domain size.
 //! 3. Search for a solution that assigns each variable a register from its domain without
-//!    interference between variables.
+//!    interference between variables. Registers in the *deferred* set are only picked when no
+//!    other register in the domain is available.
 //!
 //! If the search fails to find a solution, we may need to reassign more registers. Find an
 //! appropriate candidate among the set of live register values, add it as a variable and start
@@ -474,6 +475,12 @@ pub struct Solver {
     ///
     regs_out: RegisterSet,
 
+    /// Registers that should only be assigned to variables when no other register is available.
+    ///
+    /// This is configured once per function by `set_deferred_regs()`, and it is not affected by
+    /// `reset()`.
+    deferred_regs: RegisterSet,
+
     /// List of register moves scheduled to avoid conflicts.
     ///
     /// This is used as working space by the `schedule_moves()` function.
@@ -493,6 +500,7 @@ impl Solver {
             inputs_done: false,
             regs_in: RegisterSet::new(),
             regs_out: RegisterSet::new(),
+            deferred_regs: RegisterSet::empty(),
             moves: Vec::new(),
             fills: Vec::new(),
         }
@@ -505,10 +513,19 @@ impl Solver {
         self.inputs_done = false;
         self.regs_in = RegisterSet::new();
         self.regs_out = RegisterSet::new();
+        self.deferred_regs = RegisterSet::empty();
         self.moves.clear();
         self.fills.clear();
     }
 
+    /// Set the registers that are assigned to variables last.
+    ///
+    /// When choosing a register for a variable, the solver prefers any available register that is
+    /// not in `regs`. This is used to control how callee-saved registers are allocated.
+    pub fn set_deferred_regs(&mut self, regs: RegisterSet) {
+        self.deferred_regs = regs;
+    }
+
     /// Reset the solver state and prepare solving for a new instruction with an initial set of
     /// allocatable registers.
     ///
@@ -629,22 +646,23 @@ impl Solver {
         from: RegUnit,
         live_through: bool,
     ) {
-        // Check for existing entries for this value.
-        if self.regs_in.is_avail(constraint, from) {
-            // There could be an existing variable entry.
-            if let Some(v) = self.vars.iter_mut().find(|v| v.value == value) {
-                // We have an existing variable entry for `value`. Combine the constraints.
-                if let Some(rc) = v.constraint.intersect(constraint) {
-                    debug!("-> combining constraint with {} yields {}", v, rc);
-                    v.constraint = rc;
-                    return;
-                } else {
-                    // The spiller should have made sure the same value is not used with disjoint
-                    // constraints.
-                    panic!("Incompatible constraints: {} + {}", constraint, v)
-                }
+        // Check for an existing variable entry for this value. The `from` register is not
+        // necessarily available on the input side, since a fixed input assignment may be moving
+        // another value into it.
+        if let Some(v) = self.vars.iter_mut().find(|v| v.value == value) {
+            // We have an existing variable entry for `value`. Combine the constraints.
+            if let Some(rc) = v.constraint.intersect(constraint) {
+                debug!("-> combining constraint with {} yields {}", v, rc);
+                v.constraint = rc;
+                return;
+            } else {
+                // The spiller should have made sure the same value is not used with disjoint
+                // constraints.
+                panic!("Incompatible constraints: {} + {}", constraint, v)
             }
+        }
 
+        if self.regs_in.is_avail(constraint, from) {
             // No variable, then it must be a fixed reassignment.
             if let Some(a) = self.assignments.get(value) {
                 debug!("-> already fixed assignment {}", a);
@@ -916,7 +934,7 @@ impl Solver {
 
         for v in &mut self.vars {
             let rc = v.constraint;
-            let reg = match v.iter(&iregs, &oregs, &gregs).next() {
+            let reg = match choose_reg(v.iter(&iregs, &oregs, &gregs), rc, &self.deferred_regs) {
                 Some(reg) => reg,
                 None => {
                     // If `v` must avoid global interference, there is not point in requesting
@@ -950,9 +968,30 @@ impl Solver {
     }
 
     /// Check if `value` can be added as a variable to help find a solution.
-    pub fn can_add_var(&mut self, _value: Value, constraint: RegClass, from: RegUnit) -> bool {
+    ///
+    /// A fixed input assignment can make `from` unavailable on the input side even when `value`
+    /// is already a variable or has a fixed assignment. Such a value must not be added twice.
+    pub fn can_add_var(&mut self, value: Value, constraint: RegClass, from: RegUnit) -> bool {
         !self.regs_in.is_avail(constraint, from)
+            && !self.vars.iter().any(|v| v.value == value)
+            && !self.assignments.contains_key(value)
+    }
+}
+
+/// Choose the first register from `regs` that isn't in `deferred`.
+///
+/// If all the registers are deferred, choose the first deferred register.
+fn choose_reg(regs: RegSetIter, rc: RegClass, deferred: &RegisterSet) -> Option<RegUnit> {
+    let mut first_deferred = None;
+    for reg in regs {
+        if !deferred.is_avail(rc, reg) {
+            return Some(reg);
+        }
+        if first_deferred.is_none() {
+            first_deferred = Some(reg);
+        }
     }
+    first_deferred
 }
 
 /// Interface for working with parallel copies once a solution has been found.
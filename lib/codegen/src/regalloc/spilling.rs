@@ -27,6 +27,7 @@ use regalloc::pressure::Pressure;
 use regalloc::virtregs::VirtRegs;
 use std::fmt;
 use std::vec::Vec;
+use stats::CodegenStats;
 use timing;
 use topo_order::TopoOrder;
 
@@ -61,6 +62,9 @@ struct Context<'a> {
 
     // Uses of register values in the current instruction.
     reg_uses: &'a mut Vec<RegUse>,
+
+    // Statistics to update.
+    stats: &'a mut CodegenStats,
 }
 
 impl Spilling {
@@ -88,6 +92,7 @@ impl Spilling {
         virtregs: &VirtRegs,
         topo: &mut TopoOrder,
         tracker: &mut LiveValueTracker,
+        stats: &mut CodegenStats,
     ) {
         let _tt = timing::ra_spilling();
         debug!("Spilling for:\n{}", func.display(isa));
@@ -104,6 +109,7 @@ impl Spilling {
             pressure: Pressure::new(&reginfo, &usable_regs),
             spills: &mut self.spills,
             reg_uses: &mut self.reg_uses,
+            stats,
         };
         ctx.run(tracker)
     }
@@ -243,10 +249,10 @@ impl<'a> Context<'a> {
         debug_assert!(self.reg_uses.is_empty());
         self.collect_reg_uses(inst, ebb, constraints);
 
-        // Calls usually have fixed register uses.
+        // Calls and returns usually have fixed register uses.
         let call_sig = self.cur.func.dfg.call_signature(inst);
-        if let Some(sig) = call_sig {
-            self.collect_abi_reg_uses(inst, sig);
+        if call_sig.is_some() || self.cur.func.dfg[inst].opcode().is_return() {
+            self.collect_abi_reg_uses(inst, call_sig);
         }
 
         if !self.reg_uses.is_empty() {
@@ -338,18 +344,20 @@ impl<'a> Context<'a> {
     }
 
     // Collect register uses from the ABI input constraints.
-    fn collect_abi_reg_uses(&mut self, inst: Inst, sig: SigRef) {
+    //
+    // These are the parameters of `sig` for a call, or the function's own return values for a
+    // return instruction when `sig` is `None`.
+    fn collect_abi_reg_uses(&mut self, inst: Inst, sig: Option<SigRef>) {
         let fixed_args = self.cur.func.dfg[inst]
             .opcode()
             .constraints()
             .fixed_value_arguments();
         let args = self.cur.func.dfg.inst_variable_args(inst);
-        for (idx, (abi, &arg)) in self.cur.func.dfg.signatures[sig]
-            .params
-            .iter()
-            .zip(args)
-            .enumerate()
-        {
+        let abi_params = match sig {
+            Some(sig) => &self.cur.func.dfg.signatures[sig].params,
+            None => &self.cur.func.signature.returns,
+        };
+        for (idx, (abi, &arg)) in abi_params.iter().zip(args).enumerate() {
             if abi.location.is_reg() {
                 let (rci, spilled) = match self.liveness[arg].affinity {
                     Affinity::Reg(rci) => (rci, false),
@@ -486,6 +494,7 @@ impl<'a> Context<'a> {
             let rc = self.reginfo.rc(rci);
             self.pressure.free(rc);
             self.spills.push(value);
+            self.stats.spills += 1;
             debug!("Spilled {}:{} -> {}", value, rc, self.pressure);
         } else {
             panic!("Cannot spill {} that was already on the stack", value);
@@ -524,6 +533,7 @@ impl<'a> Context<'a> {
     fn insert_copy(&mut self, value: Value, rci: RegClassIndex) -> Value {
         let copy = self.cur.ins().copy(value);
         let inst = self.cur.built_inst();
+        self.stats.copies += 1;
 
         // Update live ranges.
         self.liveness.create_dead(copy, inst, Affinity::Reg(rci));
@@ -1,5 +1,8 @@
 //! Result and error types representing the outcome of compiling a function.
 
+use ir::Type;
+use settings::CallConv;
+use std::string::String;
 use verifier::VerifierErrors;
 
 /// A compilation error.
@@ -17,11 +20,14 @@ pub enum CodegenError {
     /// An implementation limit was exceeded.
     ///
     /// Cranelift can compile very large and complicated functions, but the [implementation has
-    /// limits][limits] that cause compilation to fail when they are exceeded.
+    /// limits][limits] that cause compilation to fail when they are exceeded. The embedder can set
+    /// tighter limits in `Context::limits`.
+    ///
+    /// The string names the limit that was exceeded.
     ///
     /// [limits]: https://cranelift.readthedocs.io/en/latest/ir.html#implementation-limits
-    #[fail(display = "Implementation limit exceeded")]
-    ImplLimitExceeded,
+    #[fail(display = "Implementation limit exceeded: {}", _0)]
+    ImplLimitExceeded(String),
 
     /// The code size for the function is too large.
     ///
@@ -29,6 +35,63 @@ pub enum CodegenError {
     /// is exceeded, compilation fails.
     #[fail(display = "Code for function is too large")]
     CodeTooLarge,
+
+    /// A branch displacement or another PC-relative offset doesn't fit in its encoding.
+    ///
+    /// The string names the instruction or jump table and its target. This always represents a
+    /// bug in Cranelift, since branch relaxation is supposed to pick encodings with a sufficient
+    /// range.
+    #[fail(display = "Offset out of range: {}", _0)]
+    OffsetOutOfRange(String),
+
+    /// An instruction marked as patchable can't be encoded with a patchable field.
+    ///
+    /// The string is the instruction. See `TargetIsa::patchable_encoding` for the instructions
+    /// that can be patched.
+    #[fail(display = "Instruction can't be patched: {}", _0)]
+    Unpatchable(String),
+
+    /// The function was built for a different pointer type than the target ISA uses.
+    ///
+    /// The first type is the `pointer_type` of the function, and the second one is the pointer
+    /// type of the target ISA.
+    #[fail(
+        display = "Function pointer type {} doesn't match the target pointer type {}",
+        _0,
+        _1
+    )]
+    PointerTypeMismatch(Type, Type),
+
+    /// The function uses a type that the settings disable.
+    ///
+    /// The string names the value, its type, and the setting that disables it, which is
+    /// `enable_float` for floating point types and `enable_simd` for vector types.
+    #[fail(display = "Disabled type: {}", _0)]
+    DisabledType(String),
+
+    /// The function uses an instruction that the settings disable.
+    ///
+    /// The string names the instruction, its opcode, and the setting that disables it, which is
+    /// `enable_cycle_counter` for `read_cycle_counter`.
+    #[fail(display = "Disabled instruction: {}", _0)]
+    DisabledInstruction(String),
+
+    /// A parameter or return value of the function is assigned to a location that its calling
+    /// convention doesn't use.
+    ///
+    /// The string names the argument, its location, and the location that the calling convention
+    /// assigns to it. Only functions with the `raw` calling convention can put their arguments in
+    /// arbitrary locations.
+    #[fail(display = "Argument location doesn't match the calling convention: {}", _0)]
+    ArgumentLocation(String),
+
+    /// The function or one of its calls uses a calling convention that the target ISA doesn't
+    /// implement.
+    ///
+    /// The first calling convention is the one of the function, and the second one is the one of
+    /// the callee. See `TargetIsa::supports_call_conv`.
+    #[fail(display = "A {} function can't call a {} function", _0, _1)]
+    UnsupportedCallConv(CallConv, CallConv),
 }
 
 /// A convenient alias for a `Result` that uses `CodegenError` as the error type.
@@ -0,0 +1,86 @@
+//! Merging of the returns of a function.
+//!
+//! The prologue and epilogue insertion gives each `return` instruction an epilogue of its own,
+//! which restores the callee-saved registers and the stack pointer. A function with many early
+//! returns, like a typical WebAssembly function, pays for an epilogue at each of them.
+//!
+//! This pass replaces the `return` instructions with jumps to a single EBB at the end of the
+//! function, which receives the return values as EBB parameters and holds the only `return`. The
+//! function gets a single epilogue, at the cost of a jump at each of the old returns, and of the
+//! copies into the return registers that the register allocator may need in the shared EBB.
+
+use cursor::{Cursor, FuncCursor};
+use flowgraph::ControlFlowGraph;
+use ir::{Ebb, Function, Inst, InstBuilder, Opcode, Type, Value};
+use std::vec::Vec;
+use timing;
+
+/// The largest number of return values of a function whose returns are merged.
+///
+/// All the return values are passed to the shared EBB, so a large number of them puts too much
+/// pressure on the registers at the jumps.
+const MAX_RETURN_VALUES: usize = 2;
+
+/// Replace the `return` instructions of `func` with jumps to a shared EBB with a single `return`.
+///
+/// This must run before legalization, so the special purpose return values are still added by the
+/// legalizer to the single `return`. Functions with a `fallthrough_return`, with fewer than two
+/// returns, or with more than `MAX_RETURN_VALUES` return values are left alone.
+pub fn do_merge_returns(func: &mut Function, cfg: &mut ControlFlowGraph) {
+    let _tt = timing::merge_returns();
+
+    let mut returns: Vec<Inst> = Vec::new();
+    for ebb in func.layout.ebbs() {
+        for inst in func.layout.ebb_insts(ebb) {
+            match func.dfg[inst].opcode() {
+                Opcode::Return => returns.push(inst),
+                Opcode::FallthroughReturn => return,
+                _ => {}
+            }
+        }
+    }
+    if returns.len() < 2 {
+        return;
+    }
+
+    let types: Vec<Type> = func
+        .dfg
+        .inst_args(returns[0])
+        .iter()
+        .map(|&arg| func.dfg.value_type(arg))
+        .collect();
+    if types.len() > MAX_RETURN_VALUES {
+        return;
+    }
+    if returns
+        .iter()
+        .any(|&inst| func.dfg.inst_args(inst).len() != types.len())
+    {
+        return;
+    }
+
+    let epilogue = func.dfg.make_ebb();
+    let params: Vec<Value> = types
+        .iter()
+        .map(|&ty| func.dfg.append_ebb_param(epilogue, ty))
+        .collect();
+
+    for &inst in &returns {
+        debug!("Merging {}", func.dfg.display_inst(inst, None));
+        let args = func.dfg.inst_args(inst).to_vec();
+        func.dfg.replace(inst).jump(epilogue, &args);
+    }
+
+    func.layout.append_ebb(epilogue);
+    let mut pos = FuncCursor::new(func).at_bottom(epilogue);
+    pos.ins().return_(&params);
+
+    let ebbs: Vec<Ebb> = returns
+        .iter()
+        .map(|&inst| pos.func.layout.inst_ebb(inst).unwrap())
+        .collect();
+    for ebb in ebbs {
+        cfg.recompute_ebb(pos.func, ebb);
+    }
+    cfg.recompute_ebb(pos.func, epilogue);
+}
@@ -0,0 +1,326 @@
+//! An instruction scheduler for in-order cores.
+//!
+//! An in-order core stalls when an instruction uses the result of a slow instruction, like a load,
+//! that was issued just before it. This pass reorders the instructions in each basic block so
+//! independent instructions are placed between a slow instruction and its uses, using the
+//! instruction latencies of the target ISA.
+//!
+//! The instructions of a basic block form a dependency DAG:
+//!
+//! - An instruction depends on the instructions defining its arguments.
+//! - Memory accesses and instructions with side effects keep their relative order when one of
+//!   them can write to memory or trap.
+//! - The instructions that define, use, or clobber CPU flags keep their relative order, so a flags
+//!   value is never clobbered before its uses.
+//! - The branch, terminator, or call ending a basic block stays last. Calls end basic blocks so
+//!   values aren't moved to be live across them.
+//!
+//! Ready instructions are scheduled by the longest latency path from them to the end of the block.
+//! When that would make more values defined in the block live at once than in the original order,
+//! plus some slack, the instructions that increase the number of live values the least are
+//! scheduled first instead.
+
+use entity::SecondaryMap;
+use ir::{Function, Inst, InstructionData, Opcode, Value, ValueDef};
+use isa::{EncInfo, Latencies, TargetIsa};
+use std::cmp::{max, Reverse};
+use std::vec::Vec;
+use timing;
+
+/// The number of values that can be live at once in a basic block, beyond the most that are live
+/// in the original order, before reducing register pressure takes precedence over latencies.
+const PRESSURE_SLACK: i32 = 2;
+
+/// Schedule the instructions in each basic block of `func` for the latencies of `isa`.
+///
+/// Nothing is scheduled when `isa` doesn't have latencies. Instructions are never moved between
+/// basic blocks, so the CFG and the dominator tree remain valid.
+pub fn do_schedule(func: &mut Function, isa: &TargetIsa) {
+    let _tt = timing::schedule();
+    let latencies = match isa.latencies() {
+        Some(latencies) => latencies,
+        None => return,
+    };
+    let mut scheduler = Scheduler::new(func, latencies, isa.encoding_info());
+
+    let ebbs: Vec<_> = func.layout.ebbs().collect();
+    let mut order = Vec::new();
+    for ebb in ebbs {
+        let insts: Vec<_> = func.layout.ebb_insts(ebb).collect();
+        order.clear();
+        let mut start = 0;
+        for (index, &inst) in insts.iter().enumerate() {
+            if ends_block(func.dfg[inst].opcode()) || index + 1 == insts.len() {
+                scheduler.schedule_block(func, &insts[start..=index], &mut order);
+                start = index + 1;
+            }
+        }
+
+        if order != insts {
+            for &inst in &insts {
+                func.layout.remove_inst(inst);
+            }
+            for &inst in &order {
+                func.layout.append_inst(inst, ebb);
+            }
+        }
+    }
+}
+
+/// Does an instruction with `opcode` end a basic block for scheduling?
+fn ends_block(opcode: Opcode) -> bool {
+    opcode.is_branch() || opcode.is_terminator() || opcode.is_call()
+}
+
+/// Can `inst` trap?
+fn can_trap(func: &Function, inst: Inst) -> bool {
+    match func.dfg[inst] {
+        InstructionData::Load { flags, .. }
+        | InstructionData::LoadComplex { flags, .. }
+        | InstructionData::LoadSymbol { flags, .. }
+        | InstructionData::LoadGlobal { flags, .. } => !flags.notrap(),
+        ref data => data.opcode().can_trap(),
+    }
+}
+
+/// A node in the dependency DAG of a basic block.
+struct Node {
+    inst: Inst,
+    /// The latency of the instruction.
+    latency: u32,
+    /// The length of the longest latency path from this node to the end of the block.
+    height: u32,
+    /// The nodes that depend on this one.
+    succs: Vec<usize>,
+    /// The number of nodes this one depends on that haven't been scheduled yet.
+    preds_left: u32,
+    /// The earliest cycle when the results of all the nodes this one depends on are available.
+    ready_cycle: u32,
+    /// The number of results of the instruction that are used.
+    defs: i32,
+    /// The arguments of the instruction that are defined in the block, with their number of
+    /// occurrences.
+    args: Vec<(Value, u32)>,
+}
+
+struct Scheduler<'a> {
+    latencies: &'a Latencies,
+    encinfo: EncInfo,
+
+    /// The number of uses of each value that haven't been scheduled yet.
+    uses_left: SecondaryMap<Value, u32>,
+
+    /// The node of each instruction in the current block.
+    node_index: SecondaryMap<Inst, Option<usize>>,
+
+    /// The dependency DAG of the current block, in the original order.
+    nodes: Vec<Node>,
+}
+
+impl<'a> Scheduler<'a> {
+    fn new(func: &Function, latencies: &'a Latencies, encinfo: EncInfo) -> Self {
+        let mut uses_left = SecondaryMap::new();
+        for ebb in func.layout.ebbs() {
+            for inst in func.layout.ebb_insts(ebb) {
+                for &arg in func.dfg.inst_args(inst) {
+                    uses_left[func.dfg.resolve_aliases(arg)] += 1;
+                }
+            }
+        }
+        Self {
+            latencies,
+            encinfo,
+            uses_left,
+            node_index: SecondaryMap::new(),
+            nodes: Vec::new(),
+        }
+    }
+
+    /// Schedule the basic block `insts`, and append the instructions to `order`.
+    fn schedule_block(&mut self, func: &Function, insts: &[Inst], order: &mut Vec<Inst>) {
+        self.build_dag(func, insts);
+
+        // Find the most values that are live at once in the original order.
+        let mut live = 0;
+        let mut peak = 0;
+        for node in 0..self.nodes.len() {
+            live += self.retire(node);
+            peak = max(peak, live);
+        }
+        for node in &self.nodes {
+            for &(arg, count) in &node.args {
+                self.uses_left[arg] += count;
+            }
+        }
+        let limit = peak + PRESSURE_SLACK;
+
+        let mut ready: Vec<_> = (0..self.nodes.len())
+            .filter(|&node| self.nodes[node].preds_left == 0)
+            .collect();
+        let mut cycle = 0;
+        live = 0;
+        while !ready.is_empty() {
+            let node = ready.swap_remove(self.pick(&ready, cycle, live, limit));
+            live += self.retire(node);
+            let issue = max(cycle, self.nodes[node].ready_cycle);
+            let done = issue + self.nodes[node].latency;
+            cycle = issue + 1;
+            for index in 0..self.nodes[node].succs.len() {
+                let succ = self.nodes[node].succs[index];
+                let succ_node = &mut self.nodes[succ];
+                succ_node.ready_cycle = max(succ_node.ready_cycle, done);
+                succ_node.preds_left -= 1;
+                if succ_node.preds_left == 0 {
+                    ready.push(succ);
+                }
+            }
+            order.push(self.nodes[node].inst);
+        }
+
+        for &inst in insts {
+            self.node_index[inst] = None;
+        }
+    }
+
+    /// Build the dependency DAG of the basic block `insts` in `self.nodes`.
+    fn build_dag(&mut self, func: &Function, insts: &[Inst]) {
+        self.nodes.clear();
+        let mut last_write = None;
+        let mut reads_since_write = Vec::new();
+        let mut last_trap = None;
+        let mut last_flags = None;
+
+        for (index, &inst) in insts.iter().enumerate() {
+            let opcode = func.dfg[inst].opcode();
+            self.node_index[inst] = Some(index);
+            self.nodes.push(Node {
+                inst,
+                latency: u32::from(self.latencies.latency(opcode)),
+                height: 0,
+                succs: Vec::new(),
+                preds_left: 0,
+                ready_cycle: 0,
+                defs: func
+                    .dfg
+                    .inst_results(inst)
+                    .iter()
+                    .filter(|&&result| self.uses_left[result] > 0)
+                    .count() as i32,
+                args: Vec::new(),
+            });
+
+            for &arg in func.dfg.inst_args(inst) {
+                let arg = func.dfg.resolve_aliases(arg);
+                if let ValueDef::Result(def, _) = func.dfg.value_def(arg) {
+                    if let Some(pred) = self.node_index[def] {
+                        self.add_edge(pred, index);
+                        let args = &mut self.nodes[index].args;
+                        match args.iter().position(|&(value, _)| value == arg) {
+                            Some(pos) => args[pos].1 += 1,
+                            None => args.push((arg, 1)),
+                        }
+                    }
+                }
+            }
+
+            let writes = opcode.can_store() || opcode.is_call() || opcode.other_side_effects();
+            let reads = opcode.can_load();
+            let traps = can_trap(func, inst);
+            if writes || reads || traps {
+                for pred in last_write.into_iter().chain(last_trap) {
+                    self.add_edge(pred, index);
+                }
+            }
+            if writes {
+                for &pred in &reads_since_write {
+                    self.add_edge(pred, index);
+                }
+                reads_since_write.clear();
+                last_write = Some(index);
+            } else if reads {
+                reads_since_write.push(index);
+            }
+            if traps {
+                last_trap = Some(index);
+            }
+
+            if self.uses_flags(func, inst) {
+                if let Some(pred) = last_flags {
+                    self.add_edge(pred, index);
+                }
+                last_flags = Some(index);
+            }
+        }
+
+        // The instruction ending the block stays last.
+        let last = self.nodes.len() - 1;
+        for pred in 0..last {
+            self.add_edge(pred, last);
+        }
+
+        for node in (0..self.nodes.len()).rev() {
+            let succs_height = self.nodes[node]
+                .succs
+                .iter()
+                .map(|&succ| self.nodes[succ].height)
+                .max()
+                .unwrap_or(0);
+            self.nodes[node].height = self.nodes[node].latency + succs_height;
+        }
+    }
+
+    fn add_edge(&mut self, pred: usize, succ: usize) {
+        self.nodes[pred].succs.push(succ);
+        self.nodes[succ].preds_left += 1;
+    }
+
+    /// Does `inst` define, use, or clobber CPU flags?
+    fn uses_flags(&self, func: &Function, inst: Inst) -> bool {
+        let dfg = &func.dfg;
+        dfg.inst_results(inst)
+            .iter()
+            .chain(dfg.inst_args(inst))
+            .any(|&value| dfg.value_type(value).is_flags())
+            || dfg[inst].opcode().writes_cpu_flags()
+            || self
+                .encinfo
+                .operand_constraints(func.encodings[inst])
+                .map_or(false, |c| c.clobbers_flags)
+    }
+
+    /// Update the uses left of the arguments of `node`, which is scheduled.
+    ///
+    /// Returns the change in the number of live values defined in the block.
+    fn retire(&mut self, node: usize) -> i32 {
+        let mut delta = self.nodes[node].defs;
+        for &(arg, count) in &self.nodes[node].args {
+            self.uses_left[arg] -= count;
+            if self.uses_left[arg] == 0 {
+                delta -= 1;
+            }
+        }
+        delta
+    }
+
+    /// Get the change in the number of live values defined in the block if `node` is scheduled.
+    fn pressure_delta(&self, node: usize) -> i32 {
+        let kills = self.nodes[node]
+            .args
+            .iter()
+            .filter(|&&(arg, count)| self.uses_left[arg] == count)
+            .count();
+        self.nodes[node].defs - kills as i32
+    }
+
+    /// Pick the next node to schedule from `ready`, and return its index in `ready`.
+    fn pick(&self, ready: &[usize], cycle: u32, live: i32, limit: i32) -> usize {
+        (0..ready.len())
+            .min_by_key(|&index| {
+                let node = ready[index];
+                let excess = max(0, live + self.pressure_delta(node) - limit);
+                let issue = max(cycle, self.nodes[node].ready_cycle);
+                (excess, issue, Reverse(self.nodes[node].height), node)
+            })
+            .expect("no ready instructions")
+    }
+}
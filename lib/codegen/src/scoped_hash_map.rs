@@ -5,10 +5,24 @@
 //! values inserted while inside a scope aren't visible outside the scope.
 
 use fx::FxHashMap;
+#[cfg(not(feature = "std"))]
+use fx::FxHasher;
 use std::collections::hash_map;
+#[cfg(not(feature = "std"))]
+use std::hash::BuildHasherDefault;
 use std::hash::Hash;
 use std::mem;
 
+// The entries of `hashbrown` maps are also parameterized by the hasher.
+#[cfg(feature = "std")]
+type MapOccupiedEntry<'a, K, V> = hash_map::OccupiedEntry<'a, K, V>;
+#[cfg(not(feature = "std"))]
+type MapOccupiedEntry<'a, K, V> = hash_map::OccupiedEntry<'a, K, V, BuildHasherDefault<FxHasher>>;
+#[cfg(feature = "std")]
+type MapVacantEntry<'a, K, V> = hash_map::VacantEntry<'a, K, V>;
+#[cfg(not(feature = "std"))]
+type MapVacantEntry<'a, K, V> = hash_map::VacantEntry<'a, K, V, BuildHasherDefault<FxHasher>>;
+
 struct Val<K, V> {
     value: V,
     next_key: Option<K>,
@@ -17,7 +31,7 @@ struct Val<K, V> {
 
 /// A view into an occupied entry in a `ScopedHashMap`. It is part of the `Entry` enum.
 pub struct OccupiedEntry<'a, K: 'a, V: 'a> {
-    entry: hash_map::OccupiedEntry<'a, K, Val<K, V>>,
+    entry: MapOccupiedEntry<'a, K, Val<K, V>>,
 }
 
 impl<'a, K, V> OccupiedEntry<'a, K, V> {
@@ -29,12 +43,12 @@ impl<'a, K, V> OccupiedEntry<'a, K, V> {
 
 /// A view into a vacant entry in a `ScopedHashMap`. It is part of the `Entry` enum.
 pub struct VacantEntry<'a, K: 'a, V: 'a> {
-    entry: hash_map::VacantEntry<'a, K, Val<K, V>>,
+    entry: MapVacantEntry<'a, K, Val<K, V>>,
     next_key: Option<K>,
     depth: usize,
 }
 
-impl<'a, K, V> VacantEntry<'a, K, V> {
+impl<'a, K: Hash, V> VacantEntry<'a, K, V> {
     /// Sets the value of the entry with the `VacantEntry`'s key.
     pub fn insert(self, value: V) {
         self.entry.insert(Val {
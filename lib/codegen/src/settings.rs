@@ -21,16 +21,13 @@
 //! ```
 
 use constant_hash::{probe, simple_hash};
+use ir::{Opcode, Type};
 use isa::TargetIsa;
 use std::boxed::Box;
 use std::fmt;
 use std::str;
 use std::string::{String, ToString};
 
-// TODO: Remove this workaround once https://github.com/rust-lang/rust/issues/27747 is done.
-#[cfg(not(feature = "std"))]
-use std::slice::SliceConcatExt;
-
 /// A string-based configurator for settings groups.
 ///
 /// The `Configurable` protocol allows settings to be modified by name before a finished `Flags`
@@ -328,6 +325,35 @@ pub mod detail {
 // `lib/codegen/meta-python/base/settings.py`.
 include!(concat!(env!("OUT_DIR"), "/settings.rs"));
 
+impl Flags {
+    /// Get the name of the setting that disables values of type `ty`, if they are disabled.
+    ///
+    /// Vector types need `enable_simd`, and floating point types and vectors of them need
+    /// `enable_float`.
+    pub fn disabling_setting(&self, ty: Type) -> Option<&'static str> {
+        if ty.is_vector() && !self.enable_simd() {
+            Some("enable_simd")
+        } else if ty.lane_type().is_float() && !self.enable_float() {
+            Some("enable_float")
+        } else {
+            None
+        }
+    }
+
+    /// Get the name of the setting that disables instructions with `opcode`, if they are
+    /// disabled.
+    ///
+    /// The non-deterministic `read_cycle_counter` instruction needs `enable_cycle_counter`.
+    pub fn disabling_opcode_setting(&self, opcode: Opcode) -> Option<&'static str> {
+        match opcode {
+            Opcode::ReadCycleCounter if !self.enable_cycle_counter() => {
+                Some("enable_cycle_counter")
+            }
+            _ => None,
+        }
+    }
+}
+
 /// Wrapper containing flags and optionally a `TargetIsa` trait object.
 ///
 /// A few passes need to access the flags but only optionally a target ISA. The `FlagsOrIsa`
@@ -361,6 +387,7 @@ mod tests {
     use super::Configurable;
     use super::SetError::*;
     use super::{builder, Flags};
+    use ir::{types, Opcode};
     use std::string::ToString;
 
     #[test]
@@ -374,20 +401,29 @@ mod tests {
              enable_verifier = true\n\
              call_conv = \"fast\"\n\
              is_pic = false\n\
+             code_model = \"large\"\n\
              colocated_libcalls = false\n\
              avoid_div_traps = false\n\
              enable_float = true\n\
              enable_nan_canonicalization = false\n\
              enable_simd = true\n\
              enable_atomics = true\n\
+             enable_cycle_counter = false\n\
              baldrdash_prologue_words = 0\n\
              allones_funcaddrs = false\n\
              probestack_enabled = true\n\
              probestack_func_adjusts_sp = false\n\
              probestack_size_log2 = 12\n\
-             jump_tables_enabled = true\n"
+             jump_tables_enabled = true\n\
+             emit_trap_tables_for_guarded_heaps = false\n\
+             check_heap_alignment = false\n\
+             hoist_global_value_loads = false\n\
+             merge_returns = false\n\
+             inline_memory_ops_limit = 64\n\
+             callee_saved_strategy = \"auto\"\n"
         );
         assert_eq!(f.opt_level(), super::OptLevel::Default);
+        assert_eq!(f.code_model(), super::CodeModel::Large);
         assert_eq!(f.enable_simd(), true);
         assert_eq!(f.baldrdash_prologue_words(), 0);
     }
@@ -426,4 +462,40 @@ mod tests {
         assert_eq!(f.enable_simd(), false);
         assert_eq!(f.opt_level(), super::OptLevel::Best);
     }
+
+    #[test]
+    fn disabling_setting() {
+        let f = Flags::new(builder());
+        assert_eq!(f.disabling_setting(types::F32X4), None);
+
+        let mut b = builder();
+        b.set("enable_float", "false").unwrap();
+        let f = Flags::new(b);
+        assert_eq!(f.disabling_setting(types::I32), None);
+        assert_eq!(f.disabling_setting(types::I32X4), None);
+        assert_eq!(f.disabling_setting(types::F64), Some("enable_float"));
+        assert_eq!(f.disabling_setting(types::F32X4), Some("enable_float"));
+
+        let mut b = builder();
+        b.set("enable_simd", "false").unwrap();
+        let f = Flags::new(b);
+        assert_eq!(f.disabling_setting(types::F64), None);
+        assert_eq!(f.disabling_setting(types::I32X4), Some("enable_simd"));
+        assert_eq!(f.disabling_setting(types::B8X16), Some("enable_simd"));
+    }
+
+    #[test]
+    fn disabling_opcode_setting() {
+        let f = Flags::new(builder());
+        assert_eq!(f.disabling_opcode_setting(Opcode::Iadd), None);
+        assert_eq!(
+            f.disabling_opcode_setting(Opcode::ReadCycleCounter),
+            Some("enable_cycle_counter")
+        );
+
+        let mut b = builder();
+        b.enable("enable_cycle_counter").unwrap();
+        let f = Flags::new(b);
+        assert_eq!(f.disabling_opcode_setting(Opcode::ReadCycleCounter), None);
+    }
 }
@@ -95,7 +95,8 @@ pub fn do_simple_gvn(func: &mut Function, domtree: &mut DominatorTree) {
                 scope_stack.push(func.layout.next_inst(inst).unwrap());
                 visible_values.increment_depth();
             }
-            if trivially_unsafe_for_gvn(opcode) {
+            // Patchable instructions may be given different values later.
+            if trivially_unsafe_for_gvn(opcode) || func.is_patchable(inst) {
                 continue;
             }
 
@@ -118,9 +119,8 @@ pub fn do_simple_gvn(func: &mut Function, domtree: &mut DominatorTree) {
                     }
                     // Replace the redundant instruction and remove it.
                     drop(func);
-                    let mut pos = pos.borrow_mut();
-                    pos.func.dfg.replace_with_aliases(inst, *entry.get());
-                    pos.remove_inst_and_step_back();
+                    pos.borrow_mut()
+                        .remove_and_replace_with_aliases(inst, *entry.get());
                 }
                 Vacant(entry) => {
                     entry.insert(inst);
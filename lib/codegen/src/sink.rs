@@ -0,0 +1,210 @@
+//! An instruction sinking pass.
+//!
+//! Computations are often placed earlier than they are needed, either by the frontend or by LICM
+//! and GVN hoisting them. A value computed before a branch but only used on one side of it, or
+//! computed before a call but only used after it, is live for longer than necessary and increases
+//! register pressure.
+//!
+//! This pass moves pure instructions down to the latest point that still dominates all their
+//! uses: just before the first use when all the uses are in the same EBB, or before the branch
+//! that leads to all of them otherwise. Instructions are never moved into a loop they weren't
+//! already in, since that would repeat the computation on every iteration. Within an EBB, only
+//! instructions with at most one argument are moved, so moving them never increases the number of
+//! live values.
+
+use dominator_tree::DominatorTree;
+use entity::SecondaryMap;
+use flowgraph::BasicBlock;
+use ir::{Ebb, Function, Inst, Opcode, Value};
+use loop_analysis::LoopAnalysis;
+use packed_option::PackedOption;
+use std::vec::Vec;
+use timing;
+
+/// Sink instructions towards their uses.
+///
+/// The CFG is not changed, so the dominator tree and loop analysis remain valid.
+pub fn do_sink(func: &mut Function, domtree: &DominatorTree, loop_analysis: &LoopAnalysis) {
+    let _tt = timing::sink();
+    debug_assert!(domtree.is_valid());
+    debug_assert!(loop_analysis.is_valid());
+
+    // The earliest point that uses each value, among the instructions visited so far.
+    let mut use_points = SecondaryMap::<Value, PackedOption<Inst>>::new();
+
+    // Values used in unreachable code must stay where they are, since the dominator tree doesn't
+    // know about their uses.
+    let mut pinned = SecondaryMap::<Value, bool>::new();
+    for ebb in func.layout.ebbs() {
+        if !domtree.is_reachable(ebb) {
+            for inst in func.layout.ebb_insts(ebb) {
+                for &arg in func.dfg.inst_args(inst) {
+                    pinned[func.dfg.resolve_aliases(arg)] = true;
+                }
+            }
+        }
+    }
+
+    // Visit the uses of a value before its definition: a dominated EBB comes before its
+    // dominators in the post-order, and the instructions in an EBB are visited bottom-up. The
+    // instructions are moved to points that have already been visited, so all the uses of a value
+    // are in their final position when its definition is visited.
+    for &ebb in domtree.cfg_postorder() {
+        let mut next = func.layout.last_inst(ebb);
+        while let Some(inst) = next {
+            next = func.layout.prev_inst(inst);
+
+            if let Some(point) =
+                sink_point(inst, func, domtree, loop_analysis, &use_points, &pinned)
+            {
+                debug!(
+                    "Sinking {} before {}",
+                    func.dfg.display_inst(inst, None),
+                    point
+                );
+                func.layout.remove_inst(inst);
+                func.layout.insert_inst(inst, point);
+            }
+
+            // The instruction is now in its final position.
+            let inst_ebb = func.layout.inst_ebb(inst).expect("sunk out of the layout");
+            for &arg in func.dfg.inst_args(inst) {
+                let arg = func.dfg.resolve_aliases(arg);
+                let point = match use_points[arg].expand() {
+                    None => inst,
+                    Some(other) => {
+                        let other_ebb = func.layout.inst_ebb(other).expect("use not in layout");
+                        domtree
+                            .common_dominator(
+                                BasicBlock::new(inst_ebb, inst),
+                                BasicBlock::new(other_ebb, other),
+                                &func.layout,
+                            )
+                            .inst
+                    }
+                };
+                use_points[arg] = point.into();
+            }
+        }
+    }
+}
+
+/// Test whether the given opcode is unsafe to move at all.
+///
+/// Loads are never moved, since a store or a call between their original position and the new one
+/// could change the loaded value.
+fn trivially_unsafe_for_sinking(opcode: Opcode) -> bool {
+    opcode.can_load()
+        || opcode.can_store()
+        || opcode.is_call()
+        || opcode.is_branch()
+        || opcode.is_terminator()
+        || opcode.is_return()
+        || opcode.can_trap()
+        || opcode.other_side_effects()
+        || opcode.writes_cpu_flags()
+}
+
+/// Find the instruction that `inst` should be moved in front of, if it should be moved.
+fn sink_point(
+    inst: Inst,
+    func: &Function,
+    domtree: &DominatorTree,
+    loop_analysis: &LoopAnalysis,
+    use_points: &SecondaryMap<Value, PackedOption<Inst>>,
+    pinned: &SecondaryMap<Value, bool>,
+) -> Option<Inst> {
+    let dfg = &func.dfg;
+    let layout = &func.layout;
+    if trivially_unsafe_for_sinking(dfg[inst].opcode())
+        || dfg
+            .inst_args(inst)
+            .iter()
+            .any(|&arg| dfg.value_type(arg).is_flags())
+    {
+        return None;
+    }
+
+    // Find the common dominator of the uses of all the results. Unused instructions are left for
+    // DCE.
+    let ebb = layout.inst_ebb(inst).expect("instruction not in layout");
+    let mut point: Option<BasicBlock> = None;
+    for &result in dfg.inst_results(inst) {
+        if pinned[result] {
+            return None;
+        }
+        if let Some(use_inst) = use_points[result].expand() {
+            let use_bb = BasicBlock::new(layout.inst_ebb(use_inst).expect("use"), use_inst);
+            point = Some(match point {
+                None => use_bb,
+                Some(bb) => domtree.common_dominator(bb, use_bb, layout),
+            });
+        }
+    }
+    let mut point = point?;
+
+    // Don't move into a loop. Climb the dominator tree until we're in the same loop as `inst`.
+    while !in_same_or_outer_loop(point.ebb, ebb, loop_analysis) {
+        let idom = domtree
+            .idom(point.ebb)
+            .expect("use is not dominated by its definition");
+        point = BasicBlock::new(layout.inst_ebb(idom).expect("idom"), idom);
+    }
+
+    // Moving an instruction down within its EBB extends the live ranges of its arguments. That
+    // only pays off when there is at most one of them, since the live range of the result gets
+    // shorter.
+    if point.ebb == ebb && dfg.inst_args(inst).len() > 1 {
+        return None;
+    }
+
+    let point = flags_safe_point(func, point.inst, inst)?;
+    if layout.next_inst(inst) == Some(point) {
+        None
+    } else {
+        Some(point)
+    }
+}
+
+/// Is `ebb` outside of any loops that `outer` isn't in?
+fn in_same_or_outer_loop(ebb: Ebb, outer: Ebb, loop_analysis: &LoopAnalysis) -> bool {
+    match loop_analysis.innermost_loop(ebb) {
+        None => true,
+        Some(lp) => loop_analysis.is_in_loop(outer, lp),
+    }
+}
+
+/// Find the latest point at or before `point` where an instruction can be inserted without
+/// separating the definition of a CPU flags value from its uses.
+///
+/// The search stops at `stop`, which is the instruction being moved, and returns `None` if no
+/// point can be found before reaching it or the top of the EBB.
+fn flags_safe_point(func: &Function, point: Inst, stop: Inst) -> Option<Inst> {
+    let dfg = &func.dfg;
+    let layout = &func.layout;
+    let ebb = layout.inst_ebb(point).expect("point not in layout");
+
+    // Compute the flags values that are live before each instruction, going backwards from the
+    // end of the EBB.
+    let mut live: Vec<Value> = Vec::new();
+    let mut cur = layout.last_inst(ebb);
+    let mut reached_point = false;
+    while let Some(inst) = cur {
+        if inst == stop {
+            return None;
+        }
+        live.retain(|v| !dfg.inst_results(inst).contains(v));
+        for &arg in dfg.inst_args(inst) {
+            let arg = dfg.resolve_aliases(arg);
+            if dfg.value_type(arg).is_flags() && !live.contains(&arg) {
+                live.push(arg);
+            }
+        }
+        reached_point |= inst == point;
+        if reached_point && live.is_empty() {
+            return Some(inst);
+        }
+        cur = layout.prev_inst(inst);
+    }
+    None
+}
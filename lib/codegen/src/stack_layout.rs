@@ -1,9 +1,12 @@
 //! Computing stack layout.
 
+use entity::SecondaryMap;
 use ir::stackslot::{StackOffset, StackSize, StackSlotKind};
-use ir::StackSlots;
+use ir::{StackSlot, StackSlots};
 use result::{CodegenError, CodegenResult};
 use std::cmp::{max, min};
+use std::string::ToString;
+use std::vec::Vec;
 
 /// Compute the stack frame layout.
 ///
@@ -12,6 +15,8 @@ use std::cmp::{max, min};
 ///
 /// The total frame size will be a multiple of `alignment` which must be a power of two.
 ///
+/// Explicit slots that share their storage with another slot get the offset of that slot.
+///
 /// Returns the total stack frame size which is also saved in `frame.frame_size`.
 ///
 /// If the stack frame is too big, returns an `ImplLimitExceeded` error.
@@ -41,7 +46,7 @@ pub fn layout_stack(frame: &mut StackSlots, alignment: StackSize) -> CodegenResu
 
     for slot in frame.values() {
         if slot.size > max_size {
-            return Err(CodegenError::ImplLimitExceeded);
+            return Err(frame_too_large());
         }
 
         match slot.kind {
@@ -53,7 +58,7 @@ pub fn layout_stack(frame: &mut StackSlots, alignment: StackSize) -> CodegenResu
                     .offset
                     .unwrap()
                     .checked_add(slot.size as StackOffset)
-                    .ok_or(CodegenError::ImplLimitExceeded)?;
+                    .ok_or_else(frame_too_large)?;
                 outgoing_max = max(outgoing_max, offset);
             }
             StackSlotKind::SpillSlot
@@ -65,19 +70,29 @@ pub fn layout_stack(frame: &mut StackSlots, alignment: StackSize) -> CodegenResu
         }
     }
 
+    // The size and alignment of the storage for each slot. Storage that is shared by several
+    // slots must be big enough and aligned enough for all of them.
+    let mut storage = SecondaryMap::<StackSlot, (StackSize, StackSize)>::new();
+    for (ss, slot) in frame.iter() {
+        let owner = frame.shared_with(ss).unwrap_or(ss);
+        let (size, align) = storage[owner];
+        storage[owner] = (max(size, slot.size), max(align, slot.alignment(alignment)));
+    }
+
     // Lay out spill slots and explicit slots below the incoming arguments.
     // The offset is negative, growing downwards.
     // Start with the smallest alignments for better packing.
     let mut offset = incoming_min;
+    let slots: Vec<StackSlot> = frame.keys().collect();
     debug_assert!(min_align.is_power_of_two());
     while min_align <= alignment {
-        for slot in frame.values_mut() {
+        for &ss in &slots {
             // Pick out explicit and spill slots with exact alignment `min_align`.
-            match slot.kind {
+            match frame[ss].kind {
                 StackSlotKind::SpillSlot
                 | StackSlotKind::ExplicitSlot
                 | StackSlotKind::EmergencySlot => {
-                    if slot.alignment(alignment) != min_align {
+                    if frame.shared_with(ss).is_some() || storage[ss].1 != min_align {
                         continue;
                     }
                 }
@@ -85,22 +100,28 @@ pub fn layout_stack(frame: &mut StackSlots, alignment: StackSize) -> CodegenResu
             }
 
             offset = offset
-                .checked_sub(slot.size as StackOffset)
-                .ok_or(CodegenError::ImplLimitExceeded)?;
+                .checked_sub(storage[ss].0 as StackOffset)
+                .ok_or_else(frame_too_large)?;
 
             // Aligning the negative offset can never cause overflow. We're only clearing bits.
             offset &= -(min_align as StackOffset);
-            slot.offset = Some(offset);
+            frame.set_offset(ss, offset);
         }
 
         // Move on to the next higher alignment.
         min_align *= 2;
     }
+    for &ss in &slots {
+        if let Some(owner) = frame.shared_with(ss) {
+            let owner_offset = frame[owner].offset;
+            frame[ss].offset = owner_offset;
+        }
+    }
 
     // Finally, make room for the outgoing arguments.
     offset = offset
         .checked_sub(outgoing_max)
-        .ok_or(CodegenError::ImplLimitExceeded)?;
+        .ok_or_else(frame_too_large)?;
     offset &= -(alignment as StackOffset);
 
     let frame_size = (offset as StackSize).wrapping_neg();
@@ -108,6 +129,11 @@ pub fn layout_stack(frame: &mut StackSlots, alignment: StackSize) -> CodegenResu
     Ok(frame_size)
 }
 
+/// The error returned when the stack frame doesn't fit in the range of a `StackOffset`.
+fn frame_too_large() -> CodegenError {
+    CodegenError::ImplLimitExceeded("stack frame larger than 2 GB".to_string())
+}
+
 #[cfg(test)]
 mod tests {
     use super::layout_stack;
@@ -115,6 +141,7 @@ mod tests {
     use ir::types;
     use ir::{StackSlotData, StackSlotKind, StackSlots};
     use result::CodegenError;
+    use std::string::ToString;
 
     #[test]
     fn layout() {
@@ -188,7 +215,12 @@ mod tests {
 
         // Also test that an unsupported offset is rejected.
         sss.get_outgoing_arg(types::I8, StackOffset::max_value() - 1);
-        assert_eq!(layout_stack(sss, 1), Err(CodegenError::ImplLimitExceeded));
+        assert_eq!(
+            layout_stack(sss, 1),
+            Err(CodegenError::ImplLimitExceeded(
+                "stack frame larger than 2 GB".to_string()
+            ))
+        );
     }
 
     #[test]
@@ -208,4 +240,24 @@ mod tests {
         assert_eq!(sss[ss1].offset, Some(-8));
         assert_eq!(sss[ss2].offset, Some(-12));
     }
+
+    #[test]
+    fn shared_slots() {
+        let sss = &mut StackSlots::new();
+
+        let ss0 = sss.push(StackSlotData::new(StackSlotKind::ExplicitSlot, 4));
+        let ss1 = sss.push(StackSlotData::new(StackSlotKind::ExplicitSlot, 8));
+        let ss2 = sss.push(StackSlotData::new(StackSlotKind::ExplicitSlot, 12));
+        assert_eq!(layout_stack(sss, 16), Ok(32));
+
+        // The shared storage is as big as the biggest slot, and aligned for the 8-byte slot.
+        sss.share(ss1, ss0);
+        sss.share(ss2, ss0);
+        assert_eq!(sss.shared_with(ss0), None);
+        assert_eq!(sss.shared_with(ss2), Some(ss0));
+        assert_eq!(layout_stack(sss, 16), Ok(16));
+        assert_eq!(sss[ss0].offset, Some(-16));
+        assert_eq!(sss[ss1].offset, Some(-16));
+        assert_eq!(sss[ss2].offset, Some(-16));
+    }
 }
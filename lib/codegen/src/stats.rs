@@ -0,0 +1,166 @@
+//! Code generation statistics.
+//!
+//! The statistics gathered while compiling a function are returned in the `CodeInfo` from
+//! `Context::compile`. They can be used to track the quality of the generated code over time
+//! without having to disassemble it.
+
+use binemit::CodeOffset;
+use ir::{Function, Opcode};
+use isa::TargetIsa;
+use std::fmt;
+use std::ops::AddAssign;
+use std::string::ToString;
+use std::vec::Vec;
+
+/// Counters describing the code generated for a function.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CodegenStats {
+    /// Number of instructions emitted as machine code.
+    ///
+    /// Instructions that don't emit any code, such as fall-throughs, aren't counted.
+    pub insts: u32,
+
+    /// Number of emitted branch instructions.
+    pub branches: u32,
+
+    /// Number of emitted register moves, from `copy` and `regmove` instructions.
+    pub moves: u32,
+
+    /// Number of values spilled to the stack by the register allocator.
+    pub spills: u32,
+
+    /// Number of `fill` instructions inserted by the register allocator to reload spilled values.
+    pub reloads: u32,
+
+    /// Number of `copy` instructions inserted by the coalescing and spilling passes of the register
+    /// allocator.
+    pub copies: u32,
+
+    /// Number of callee-saved registers that the prologue saves because the register allocator
+    /// used them.
+    ///
+    /// Registers that are always saved, like the frame pointer, aren't counted.
+    pub saved_regs: u32,
+
+    /// Total number of bytes of code and data.
+    pub code_size: CodeOffset,
+}
+
+impl AddAssign for CodegenStats {
+    fn add_assign(&mut self, other: Self) {
+        self.insts += other.insts;
+        self.branches += other.branches;
+        self.moves += other.moves;
+        self.spills += other.spills;
+        self.reloads += other.reloads;
+        self.copies += other.copies;
+        self.saved_regs += other.saved_regs;
+        self.code_size += other.code_size;
+    }
+}
+
+impl fmt::Display for CodegenStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "insts={} branches={} moves={} spills={} reloads={} copies={} saved_regs={} size={}",
+            self.insts,
+            self.branches,
+            self.moves,
+            self.spills,
+            self.reloads,
+            self.copies,
+            self.saved_regs,
+            self.code_size
+        )
+    }
+}
+
+/// Count the callee-saved registers that are saved by the prologue of `func`.
+///
+/// This only counts the registers in `TargetIsa::callee_saved_registers()`, so it doesn't include
+/// the frame pointer or the link register.
+pub fn count_saved_regs(func: &Function, isa: &TargetIsa) -> u32 {
+    let layout = match func.frame_layout {
+        Some(ref layout) => layout,
+        None => return 0,
+    };
+    let reginfo = isa.register_info();
+    let callee_saved = isa.callee_saved_registers(func);
+    layout
+        .saved_registers
+        .iter()
+        .filter(|&&(reg, _)| {
+            reginfo
+                .classes
+                .iter()
+                .any(|&rc| rc.contains(reg) && callee_saved.is_avail(rc, reg))
+        }).count() as u32
+}
+
+/// Count the instructions in the layout of `func` by opcode.
+///
+/// Returns the opcodes that appear in `func` with their counts, in order of decreasing count.
+/// Opcodes with the same count are ordered by name.
+pub fn opcode_histogram(func: &Function) -> Vec<(Opcode, usize)> {
+    let mut histogram: Vec<(Opcode, usize)> = Vec::new();
+    for ebb in func.layout.ebbs() {
+        for inst in func.layout.ebb_insts(ebb) {
+            let opcode = func.dfg[inst].opcode();
+            match histogram.iter().position(|&(op, _)| op == opcode) {
+                Some(i) => histogram[i].1 += 1,
+                None => histogram.push((opcode, 1)),
+            }
+        }
+    }
+    histogram.sort_by(|&(a, a_count), &(b, b_count)| {
+        b_count
+            .cmp(&a_count)
+            .then_with(|| a.to_string().cmp(&b.to_string()))
+    });
+    histogram
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cursor::{Cursor, FuncCursor};
+    use ir::types::I32;
+    use ir::InstBuilder;
+
+    #[test]
+    fn histogram() {
+        let mut func = Function::new();
+        let ebb0 = func.dfg.make_ebb();
+        {
+            let mut pos = FuncCursor::new(&mut func);
+            pos.insert_ebb(ebb0);
+            let v0 = pos.ins().iconst(I32, 1);
+            let v1 = pos.ins().iconst(I32, 2);
+            let v2 = pos.ins().iadd(v0, v1);
+            pos.ins().return_(&[v2]);
+        }
+        assert_eq!(
+            opcode_histogram(&func),
+            [(Opcode::Iconst, 2), (Opcode::Iadd, 1), (Opcode::Return, 1)]
+        );
+    }
+
+    #[test]
+    fn display() {
+        let stats = CodegenStats {
+            insts: 4,
+            branches: 1,
+            moves: 2,
+            spills: 2,
+            reloads: 3,
+            copies: 0,
+            saved_regs: 1,
+            code_size: 20,
+        };
+        assert_eq!(
+            stats.to_string(),
+            "insts=4 branches=1 moves=2 spills=2 reloads=3 copies=0 saved_regs=1 size=20"
+        );
+    }
+}
@@ -58,10 +58,17 @@ define_passes!{
     postopt: "Post-legalization rewriting",
     preopt: "Pre-legalization rewriting",
     dce: "Dead code elimination",
+    dse: "Dead stack store elimination",
+    bce: "Bounds check elimination",
+    hoist_global_value_loads: "Hoisting of global value loads",
+    merge_returns: "Merging of returns",
     legalize: "Legalization",
     gvn: "Global value numbering",
     licm: "Loop invariant code motion",
+    sink: "Instruction sinking",
+    schedule: "Instruction scheduling",
     unreachable_code: "Remove unreachable blocks",
+    reorder_ebbs: "EBB reordering",
 
     regalloc: "Register allocation",
     ra_liveness: "RA liveness analysis",
@@ -124,7 +131,7 @@ mod details {
     }
 
     /// Accumulated timing information for a single pass.
-    #[derive(Default)]
+    #[derive(Default, Copy, Clone)]
     struct PassTime {
         /// Total time spent running this pass including children.
         total: Duration,
@@ -134,11 +141,19 @@ mod details {
     }
 
     /// Accumulated timing for all passes.
-    #[derive(Default)]
     pub struct PassTimes {
         pass: [PassTime; NUM_PASSES],
     }
 
+    // Arrays only implement `Default` up to 32 elements.
+    impl Default for PassTimes {
+        fn default() -> Self {
+            Self {
+                pass: [Default::default(); NUM_PASSES],
+            }
+        }
+    }
+
     impl fmt::Display for PassTimes {
         fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
             writeln!(f, "======== ========  ==================================")?;
@@ -220,20 +235,30 @@ mod details {
     }
 }
 
-/// Dummy `debug` implementation
+/// Dummy implementation for builds without `std`, where there is no `Instant` to time passes with.
 #[cfg(not(feature = "std"))]
 mod details {
     use super::Pass;
+    use std::fmt;
+
     /// Dummy `TimingToken`
     pub struct TimingToken;
     /// Dummy `PassTimes`
+    #[derive(Default)]
     pub struct PassTimes;
+
+    impl fmt::Display for PassTimes {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            writeln!(f, "Pass timings are not available without std")
+        }
+    }
+
     /// Returns dummy `PassTimes`
     pub fn take_current() -> PassTimes {
         PassTimes
     }
     /// does nothing
-    pub fn add_to_current(_times: PassTimes) {}
+    pub fn add_to_current(_times: &PassTimes) {}
 
     /// does nothing
     pub(super) fn start_pass(_pass: Pass) -> TimingToken {
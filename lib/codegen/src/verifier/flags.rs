@@ -3,6 +3,7 @@
 use entity::{SecondaryMap, SparseSet};
 use flowgraph::{BasicBlock, ControlFlowGraph};
 use ir;
+use ir::condcodes::IntCC;
 use ir::instructions::BranchInfo;
 use isa;
 use packed_option::PackedOption;
@@ -19,7 +20,8 @@ use verifier::{VerifierErrors, VerifierStepResult};
 ///
 /// - At most one flags value can be live at a time.
 /// - A flags value can not be live across an instruction that clobbers the flags.
-///
+/// - The carry or borrow flags of `iadd_ifcout` and `isub_ifbout` are only tested with the `ult`
+///   and `uge` condition codes.
 ///
 pub fn verify_flags(
     func: &ir::Function,
@@ -124,6 +126,7 @@ impl<'a> FlagsVerifier<'a> {
             for &arg in self.func.dfg.inst_args(inst) {
                 if self.func.dfg.value_type(arg).is_flags() {
                     merge(&mut live_val, arg, inst, errors)?;
+                    self.check_carry_cond(inst, arg, errors)?;
                 }
             }
 
@@ -153,6 +156,41 @@ impl<'a> FlagsVerifier<'a> {
         // Return the required live-in flags value.
         Ok(live_val)
     }
+
+    /// Check that `inst` tests the flags value `flags` with a meaningful condition code when it
+    /// is the carry or borrow of an add or subtract.
+    fn check_carry_cond(
+        &self,
+        inst: ir::Inst,
+        flags: ir::Value,
+        errors: &mut VerifierErrors,
+    ) -> VerifierStepResult<()> {
+        let def = match self.func.dfg.value_def(flags) {
+            ir::ValueDef::Result(def, _) => def,
+            ir::ValueDef::Param(..) => return Ok(()),
+        };
+        match self.func.dfg[def].opcode() {
+            ir::Opcode::IaddIfcout | ir::Opcode::IsubIfbout => {}
+            _ => return Ok(()),
+        }
+        let cond = match self.func.dfg[inst] {
+            ir::InstructionData::IntCond { cond, .. }
+            | ir::InstructionData::BranchInt { cond, .. }
+            | ir::InstructionData::IntCondTrap { cond, .. }
+            | ir::InstructionData::IntSelect { cond, .. } => cond,
+            _ => return Ok(()),
+        };
+        match cond {
+            IntCC::UnsignedLessThan | IntCC::UnsignedGreaterThanOrEqual => Ok(()),
+            _ => fatal!(
+                errors,
+                inst,
+                "{} doesn't test the carry or borrow in {}",
+                cond,
+                flags
+            ),
+        }
+    }
 }
 
 // Merge live flags values, or return an error on conflicting values.
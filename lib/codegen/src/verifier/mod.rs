@@ -20,7 +20,14 @@
 //!
 //! - Values must be defined by an instruction that exists and that is inserted in
 //!   an EBB, or be an argument of an existing EBB.
-//! - Values used by an instruction must dominate the instruction.
+//! - Values used by an instruction must dominate the instruction. This includes the arguments
+//!   passed to the parameters of a branch destination, and aliases are resolved first. The
+//!   error names the value, its definition, and the instruction using it.
+//!
+//!   The verifier computes its own dominator tree once per function. A use in the EBB of the
+//!   definition is then checked in constant time, and other uses walk up the dominator tree
+//!   from the use, so the cost of the check grows with the number of uses times the distance
+//!   between definitions and uses in the dominator tree.
 //!
 //! Control flow graph and dominator tree integrity:
 //!
@@ -41,6 +48,11 @@
 //! - All return instructions must have return value operands matching the current
 //!   function signature.
 //!
+//! Signatures
+//!
+//! - Struct arguments must be parameters with a non-zero size and an integer type. Before ABI
+//!   legalization, their type must be the pointer type.
+//!
 //! Global values
 //!
 //! - Detect cycles in global values.
@@ -65,8 +77,9 @@ use ir;
 use ir::entities::AnyEntity;
 use ir::instructions::{BranchInfo, CallInfo, InstructionFormat, ResolvedConstraint};
 use ir::{
-    types, ArgumentLoc, Ebb, FuncRef, Function, GlobalValue, Inst, JumpTable, Opcode, SigRef,
-    StackSlot, StackSlotKind, Type, Value, ValueDef, ValueList, ValueLoc,
+    types, ArgumentLoc, ArgumentPurpose, Ebb, FuncRef, Function, GlobalValue, Inst, JumpTable,
+    Opcode, SigRef, Signature, StackSlot, StackSlotKind, Type, Value, ValueDef, ValueList,
+    ValueLoc,
 };
 use isa::TargetIsa;
 use iterators::IteratorExtras;
@@ -224,6 +237,91 @@ impl Display for VerifierErrors {
     }
 }
 
+/// Append the declarations of the preamble entities involved in each of `errors` to its message.
+///
+/// An error on an instruction shows the declaration of the heap, table, global value, jump table,
+/// stack slot, signature, or function that the instruction uses, and an error on a preamble entity
+/// shows its own declaration. The declarations they are based on follow, down to the end of the
+/// global value chain, so a bad declaration can be diagnosed without dumping the whole function.
+fn describe_declarations(func: &Function, errors: &mut [VerifierError]) {
+    for err in errors {
+        let entity = match err.location {
+            AnyEntity::Inst(inst) if func.dfg.inst_is_valid(inst) => {
+                func.dfg[inst].referenced_entity()
+            }
+            AnyEntity::Function | AnyEntity::Ebb(_) | AnyEntity::Inst(_) | AnyEntity::Value(_) => {
+                None
+            }
+            entity => Some(entity),
+        };
+        if let Some(entity) = entity {
+            write_declarations(&mut err.message, func, entity).unwrap();
+        }
+    }
+}
+
+/// Write the declaration of `entity` and of all the entities it is based on, one per line.
+fn write_declarations(w: &mut Write, func: &Function, entity: AnyEntity) -> fmt::Result {
+    // The entities are visited in the order they are discovered, and each one only once, so a
+    // cycle of global values is written once.
+    let mut entities = vec![entity];
+    let mut i = 0;
+    while let Some(&entity) = entities.get(i) {
+        i += 1;
+        let mut bases: Vec<AnyEntity> = Vec::new();
+        match entity {
+            AnyEntity::GlobalValue(gv) if func.global_values.is_valid(gv) => {
+                let data = &func.global_values[gv];
+                write!(w, "\n    {} = {}", gv, data)?;
+                match *data {
+                    ir::GlobalValueData::Load { base, .. }
+                    | ir::GlobalValueData::IAddImm { base, .. } => bases.push(base.into()),
+                    ir::GlobalValueData::IAddScaled { base, index, .. } => {
+                        bases.push(base.into());
+                        bases.push(index.into());
+                    }
+                    ir::GlobalValueData::VMContext | ir::GlobalValueData::Symbol { .. } => {}
+                }
+            }
+            AnyEntity::Heap(heap) if func.heaps.is_valid(heap) => {
+                let data = &func.heaps[heap];
+                write!(w, "\n    {} = {}", heap, data)?;
+                bases.push(data.base.into());
+                if let ir::HeapStyle::Dynamic { bound_gv } = data.style {
+                    bases.push(bound_gv.into());
+                }
+            }
+            AnyEntity::Table(table) if func.tables.is_valid(table) => {
+                let data = &func.tables[table];
+                write!(w, "\n    {} = {}", table, data)?;
+                bases.push(data.base_gv.into());
+                bases.push(data.bound_gv.into());
+            }
+            AnyEntity::FuncRef(func_ref) if func.dfg.ext_funcs.is_valid(func_ref) => {
+                let data = &func.dfg.ext_funcs[func_ref];
+                write!(w, "\n    {} = {}", func_ref, data)?;
+                bases.push(data.signature.into());
+            }
+            AnyEntity::SigRef(sig_ref) if func.dfg.signatures.is_valid(sig_ref) => {
+                write!(w, "\n    {} = {}", sig_ref, func.dfg.signatures[sig_ref])?;
+            }
+            AnyEntity::JumpTable(jt) if func.jump_tables.is_valid(jt) => {
+                write!(w, "\n    {} = {}", jt, func.jump_tables[jt])?;
+            }
+            AnyEntity::StackSlot(ss) if func.stack_slots.is_valid(ss) => {
+                write!(w, "\n    {} = {}", ss, func.stack_slots[ss])?;
+            }
+            _ => {}
+        }
+        for base in bases {
+            if !entities.contains(&base) {
+                entities.push(base);
+            }
+        }
+    }
+    Ok(())
+}
+
 /// Verify `func`.
 pub fn verify_function<'a, FOI: Into<FlagsOrIsa<'a>>>(
     func: &Function,
@@ -237,6 +335,7 @@ pub fn verify_function<'a, FOI: Into<FlagsOrIsa<'a>>>(
         result.unwrap();
         Ok(())
     } else {
+        describe_declarations(func, &mut errors.0);
         Err(errors)
     }
 }
@@ -252,13 +351,10 @@ pub fn verify_context<'a, FOI: Into<FlagsOrIsa<'a>>>(
 ) -> VerifierStepResult<()> {
     let _tt = timing::verifier();
     let verifier = Verifier::new(func, fisa.into());
-    if cfg.is_valid() {
-        verifier.cfg_integrity(cfg, errors)?;
-    }
-    if domtree.is_valid() {
-        verifier.domtree_integrity(domtree, errors)?;
-    }
-    verifier.run(errors)
+    let first_error = errors.0.len();
+    let result = verifier.run_with_context(cfg, domtree, errors);
+    describe_declarations(func, &mut errors.0[first_error..]);
+    result
 }
 
 struct Verifier<'a> {
@@ -283,6 +379,87 @@ impl<'a> Verifier<'a> {
     // Check for:
     //  - cycles in the global value declarations.
     //  - use of 'vmctx' when no special parameter declares it.
+    /// Get the pointer type of the function, if it is known.
+    fn pointer_type(&self) -> Option<Type> {
+        self.func.pointer_type_or(self.isa)
+    }
+
+    fn verify_pointer_type(&self, errors: &mut VerifierErrors) -> VerifierStepResult<()> {
+        if let Some(pointer_type) = self.func.pointer_type {
+            if !pointer_type.is_int() || pointer_type.is_vector() {
+                return fatal!(
+                    errors,
+                    AnyEntity::Function,
+                    "pointer type {} is not a scalar integer type",
+                    pointer_type
+                );
+            }
+            if let Some(isa) = self.isa {
+                if pointer_type != isa.pointer_type() {
+                    return fatal!(
+                        errors,
+                        AnyEntity::Function,
+                        "pointer type {} differs from the target pointer type {}",
+                        pointer_type,
+                        isa.pointer_type()
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn verify_signatures(&self, errors: &mut VerifierErrors) -> VerifierStepResult<()> {
+        self.verify_signature(&self.func.signature, AnyEntity::Function, errors);
+        for (sig_ref, sig) in self.func.dfg.signatures.iter() {
+            self.verify_signature(sig, sig_ref.into(), errors);
+        }
+
+        errors.as_result()
+    }
+
+    fn verify_signature(&self, sig: &Signature, entity: AnyEntity, errors: &mut VerifierErrors) {
+        for param in &sig.params {
+            if let ArgumentPurpose::StructArgument(size) = param.purpose {
+                if size == 0 {
+                    report!(errors, entity, "struct argument {} has zero size", param);
+                }
+                if !param.value_type.is_int() || param.value_type.is_vector() {
+                    report!(
+                        errors,
+                        entity,
+                        "struct argument {} doesn't have a scalar integer type",
+                        param
+                    );
+                } else if !param.location.is_assigned() && !param.legalized_to_pointer {
+                    if let Some(pointer_type) = self.pointer_type() {
+                        if param.value_type != pointer_type {
+                            report!(
+                                errors,
+                                entity,
+                                "struct argument {} is not a pointer of type {}",
+                                param,
+                                pointer_type
+                            );
+                        }
+                    }
+                }
+            }
+        }
+
+        for ret in &sig.returns {
+            if let ArgumentPurpose::StructArgument(_) = ret.purpose {
+                report!(
+                    errors,
+                    entity,
+                    "struct argument {} can't be a return value",
+                    ret
+                );
+            }
+        }
+    }
+
     fn verify_global_values(&self, errors: &mut VerifierErrors) -> VerifierStepResult<()> {
         let mut cycle_seen = false;
         let mut seen = SparseSet::new();
@@ -295,7 +472,8 @@ impl<'a> Verifier<'a> {
             loop {
                 match self.func.global_values[cur] {
                     ir::GlobalValueData::Load { base, .. }
-                    | ir::GlobalValueData::IAddImm { base, .. } => {
+                    | ir::GlobalValueData::IAddImm { base, .. }
+                    | ir::GlobalValueData::IAddScaled { base, .. } => {
                         if seen.insert(base).is_some() {
                             if !cycle_seen {
                                 report!(
@@ -316,6 +494,16 @@ impl<'a> Verifier<'a> {
                 }
             }
 
+            if let ir::GlobalValueData::IAddScaled { index, .. } = self.func.global_values[gv] {
+                if self.global_value_depends_on(index, gv) {
+                    if !cycle_seen {
+                        report!(errors, gv, "global value cycle through index {}", index);
+                        cycle_seen = true;
+                    }
+                    continue 'gvs;
+                }
+            }
+
             match self.func.global_values[gv] {
                 ir::GlobalValueData::VMContext { .. } => {
                     if self
@@ -336,8 +524,8 @@ impl<'a> Verifier<'a> {
                             "iadd_imm global value with non-int type {}",
                             global_type
                         );
-                    } else if let Some(isa) = self.isa {
-                        let base_type = self.func.global_values[base].global_type(isa);
+                    } else if let Some(pointer_type) = self.pointer_type() {
+                        let base_type = self.func.global_values[base].global_type(pointer_type);
                         if global_type != base_type {
                             report!(
                                 errors,
@@ -349,10 +537,44 @@ impl<'a> Verifier<'a> {
                         }
                     }
                 }
+                ir::GlobalValueData::IAddScaled {
+                    base,
+                    index,
+                    global_type,
+                    ..
+                } => {
+                    if !global_type.is_int() {
+                        report!(
+                            errors,
+                            gv,
+                            "iadd_scaled global value with non-int type {}",
+                            global_type
+                        );
+                    } else if let Some(pointer_type) = self.pointer_type() {
+                        let base_type = self.func.global_values[base].global_type(pointer_type);
+                        let index_type = self.func.global_values[index].global_type(pointer_type);
+                        if global_type != base_type {
+                            report!(
+                                errors,
+                                gv,
+                                "iadd_scaled type {} differs from base type {}",
+                                global_type,
+                                base_type
+                            );
+                        } else if !index_type.is_int() || index_type.bits() > global_type.bits() {
+                            report!(
+                                errors,
+                                gv,
+                                "iadd_scaled index has type {}, which doesn't extend to {}",
+                                index_type,
+                                global_type
+                            );
+                        }
+                    }
+                }
                 ir::GlobalValueData::Load { base, .. } => {
-                    if let Some(isa) = self.isa {
-                        let base_type = self.func.global_values[base].global_type(isa);
-                        let pointer_type = isa.pointer_type();
+                    if let Some(pointer_type) = self.pointer_type() {
+                        let base_type = self.func.global_values[base].global_type(pointer_type);
                         if base_type != pointer_type {
                             report!(
                                 errors,
@@ -373,16 +595,56 @@ impl<'a> Verifier<'a> {
         Ok(())
     }
 
+    /// Does the value of the global value `gv` depend on the global value `target`?
+    fn global_value_depends_on(&self, gv: ir::GlobalValue, target: ir::GlobalValue) -> bool {
+        let mut visited = SparseSet::new();
+        let mut worklist = vec![gv];
+        while let Some(cur) = worklist.pop() {
+            if cur == target {
+                return true;
+            }
+            if visited.insert(cur).is_some() {
+                continue;
+            }
+            match self.func.global_values[cur] {
+                ir::GlobalValueData::Load { base, .. }
+                | ir::GlobalValueData::IAddImm { base, .. } => worklist.push(base),
+                ir::GlobalValueData::IAddScaled { base, index, .. } => {
+                    worklist.push(base);
+                    worklist.push(index);
+                }
+                ir::GlobalValueData::VMContext | ir::GlobalValueData::Symbol { .. } => {}
+            }
+        }
+        false
+    }
+
+    /// Is the value of the global value `gv` the address of a symbol plus an offset?
+    ///
+    /// A `load` global value whose base is a symbol is the value stored at the symbol, which a
+    /// `symbol_value` instruction can't compute.
+    fn is_symbolic_address(&self, gv: ir::GlobalValue) -> bool {
+        let mut cur = gv;
+        // Bound the walk, since the global values may have a cycle.
+        for _ in 0..self.func.global_values.len() {
+            match self.func.global_values[cur] {
+                ir::GlobalValueData::Symbol { .. } => return true,
+                ir::GlobalValueData::IAddImm { base, .. } => cur = base,
+                _ => return false,
+            }
+        }
+        false
+    }
+
     fn verify_heaps(&self, errors: &mut VerifierErrors) -> VerifierStepResult<()> {
-        if let Some(isa) = self.isa {
+        if let Some(pointer_type) = self.pointer_type() {
             for (heap, heap_data) in &self.func.heaps {
                 let base = heap_data.base;
                 if !self.func.global_values.is_valid(base) {
                     return nonfatal!(errors, heap, "invalid base global value {}", base);
                 }
 
-                let pointer_type = isa.pointer_type();
-                let base_type = self.func.global_values[base].global_type(isa);
+                let base_type = self.func.global_values[base].global_type(pointer_type);
                 if base_type != pointer_type {
                     report!(
                         errors,
@@ -399,7 +661,7 @@ impl<'a> Verifier<'a> {
                     }
 
                     let index_type = heap_data.index_type;
-                    let bound_type = self.func.global_values[bound_gv].global_type(isa);
+                    let bound_type = self.func.global_values[bound_gv].global_type(pointer_type);
                     if index_type != bound_type {
                         report!(
                             errors,
@@ -417,15 +679,14 @@ impl<'a> Verifier<'a> {
     }
 
     fn verify_tables(&self, errors: &mut VerifierErrors) -> VerifierStepResult<()> {
-        if let Some(isa) = self.isa {
+        if let Some(pointer_type) = self.pointer_type() {
             for (table, table_data) in &self.func.tables {
                 let base = table_data.base_gv;
                 if !self.func.global_values.is_valid(base) {
                     return nonfatal!(errors, table, "invalid base global value {}", base);
                 }
 
-                let pointer_type = isa.pointer_type();
-                let base_type = self.func.global_values[base].global_type(isa);
+                let base_type = self.func.global_values[base].global_type(pointer_type);
                 if base_type != pointer_type {
                     report!(
                         errors,
@@ -442,7 +703,7 @@ impl<'a> Verifier<'a> {
                 }
 
                 let index_type = table_data.index_type;
-                let bound_type = self.func.global_values[bound_gv].global_type(isa);
+                let bound_type = self.func.global_values[bound_gv].global_type(pointer_type);
                 if index_type != bound_type {
                     report!(
                         errors,
@@ -607,10 +868,23 @@ impl<'a> Verifier<'a> {
             }
             BranchTable { table, .. }
             | BranchTableBase { table, .. }
-            | BranchTableEntry { table, .. }
-            | IndirectJump { table, .. } => {
+            | BranchTableEntry { table, .. } => {
                 self.verify_jump_table(inst, table, errors)?;
             }
+            IndirectJump { table, .. } => {
+                self.verify_jump_table(inst, table, errors)?;
+                // The holes only have a meaning for `br_table`, which knows the default
+                // destination. The legalizer fills them in when expanding it.
+                if self.func.jump_tables.is_valid(table) && self.func.jump_tables[table].has_holes()
+                {
+                    return nonfatal!(
+                        errors,
+                        inst,
+                        "indirect branch through {}, which has holes",
+                        table
+                    );
+                }
+            }
             Call {
                 func_ref, ref args, ..
             } => {
@@ -629,7 +903,9 @@ impl<'a> Verifier<'a> {
             StackLoad { stack_slot, .. } | StackStore { stack_slot, .. } => {
                 self.verify_stack_slot(inst, stack_slot, errors)?;
             }
-            UnaryGlobalValue { global_value, .. } => {
+            UnaryGlobalValue { global_value, .. }
+            | LoadSymbol { global_value, .. }
+            | LoadGlobal { global_value, .. } => {
                 self.verify_global_value(inst, global_value, errors)?;
             }
             HeapAddr { heap, .. } => {
@@ -647,7 +923,7 @@ impl<'a> Verifier<'a> {
             LoadComplex { ref args, .. } => {
                 self.verify_value_list(inst, args, errors)?;
             }
-            StoreComplex { ref args, .. } => {
+            StoreComplex { ref args, .. } | StoreSymbol { ref args, .. } => {
                 self.verify_value_list(inst, args, errors)?;
             }
 
@@ -843,15 +1119,18 @@ impl<'a> Verifier<'a> {
                     );
                 }
                 // Defining instruction is inserted in an EBB.
-                if self.func.layout.inst_ebb(def_inst) == None {
-                    return fatal!(
-                        errors,
-                        loc_inst,
-                        "{} is defined by {} which has no EBB",
-                        v,
-                        def_inst
-                    );
-                }
+                let def_ebb = match self.func.layout.inst_ebb(def_inst) {
+                    Some(ebb) => ebb,
+                    None => {
+                        return fatal!(
+                            errors,
+                            loc_inst,
+                            "{} is defined by {} which has no EBB",
+                            v,
+                            def_inst
+                        )
+                    }
+                };
                 // Defining instruction dominates the instruction that uses the value.
                 if is_reachable {
                     if !self
@@ -861,8 +1140,10 @@ impl<'a> Verifier<'a> {
                         return fatal!(
                             errors,
                             loc_inst,
-                            "uses value from non-dominating {}",
-                            def_inst
+                            "uses value {} from non-dominating {} in {}",
+                            v,
+                            def_inst,
+                            def_ebb
                         );
                     }
                     if def_inst == loc_inst {
@@ -900,7 +1181,8 @@ impl<'a> Verifier<'a> {
                     return fatal!(
                         errors,
                         loc_inst,
-                        "uses value arg from non-dominating {}",
+                        "uses value arg {} from non-dominating {}",
+                        v,
                         ebb
                     );
                 }
@@ -1154,44 +1436,7 @@ impl<'a> Verifier<'a> {
         inst: Inst,
         errors: &mut VerifierErrors,
     ) -> VerifierStepResult<()> {
-        match self.func.dfg.analyze_branch(inst) {
-            BranchInfo::SingleDest(ebb, _) => {
-                let iter = self
-                    .func
-                    .dfg
-                    .ebb_params(ebb)
-                    .iter()
-                    .map(|&v| self.func.dfg.value_type(v));
-                self.typecheck_variable_args_iterator(inst, iter, errors)?;
-            }
-            BranchInfo::Table(table, ebb) => {
-                if let Some(ebb) = ebb {
-                    let arg_count = self.func.dfg.num_ebb_params(ebb);
-                    if arg_count != 0 {
-                        return nonfatal!(
-                            errors,
-                            inst,
-                            "takes no arguments, but had target {} with {} arguments",
-                            ebb,
-                            arg_count
-                        );
-                    }
-                }
-                for ebb in self.func.jump_tables[table].iter() {
-                    let arg_count = self.func.dfg.num_ebb_params(*ebb);
-                    if arg_count != 0 {
-                        return nonfatal!(
-                            errors,
-                            inst,
-                            "takes no arguments, but had target {} with {} arguments",
-                            ebb,
-                            arg_count
-                        );
-                    }
-                }
-            }
-            BranchInfo::NotABranch => {}
-        }
+        self.typecheck_branch_args(inst, errors)?;
 
         match self.func.dfg[inst].analyze_call(&self.func.dfg.value_lists) {
             CallInfo::Direct(func_ref, _) => {
@@ -1216,6 +1461,81 @@ impl<'a> Verifier<'a> {
         Ok(())
     }
 
+    /// Check that the arguments passed by the branch `inst` match the parameters of every EBB it
+    /// can branch to.
+    ///
+    /// Jump tables can only target EBBs without parameters, and a `fallthrough` must target the
+    /// next EBB in the layout.
+    fn typecheck_branch_args(
+        &self,
+        inst: Inst,
+        errors: &mut VerifierErrors,
+    ) -> VerifierStepResult<()> {
+        match self.func.dfg.analyze_branch(inst) {
+            BranchInfo::SingleDest(ebb, args) => {
+                self.typecheck_ebb_args(inst, ebb, args, errors)?;
+                if self.func.dfg[inst].opcode() == Opcode::Fallthrough {
+                    let next = self
+                        .func
+                        .layout
+                        .inst_ebb(inst)
+                        .and_then(|cur| self.func.layout.next_ebb(cur));
+                    if next != Some(ebb) {
+                        return nonfatal!(
+                            errors,
+                            inst,
+                            "fallthrough target {} is not the next EBB in the layout",
+                            ebb
+                        );
+                    }
+                }
+            }
+            BranchInfo::Table(table, default) => {
+                let mut targets = BTreeSet::new();
+                targets.extend(default);
+                targets.extend(self.func.jump_tables[table].iter().cloned());
+                for ebb in targets {
+                    self.typecheck_ebb_args(inst, ebb, &[], errors)?;
+                }
+            }
+            BranchInfo::NotABranch => {}
+        }
+        Ok(())
+    }
+
+    /// Check that the arguments `args` passed by the branch `inst` to `ebb` match its parameters.
+    fn typecheck_ebb_args(
+        &self,
+        inst: Inst,
+        ebb: Ebb,
+        args: &[Value],
+        errors: &mut VerifierErrors,
+    ) -> VerifierStepResult<()> {
+        let expected: Vec<_> = self
+            .func
+            .dfg
+            .ebb_params(ebb)
+            .iter()
+            .map(|&param| self.func.dfg.value_type(param))
+            .collect();
+        let provided: Vec<_> = args
+            .iter()
+            .map(|&arg| self.func.dfg.value_type(arg))
+            .collect();
+        if expected != provided {
+            return nonfatal!(
+                errors,
+                inst,
+                "{} target {} expects arguments {}, but got {}",
+                self.func.dfg[inst].opcode(),
+                ebb,
+                DisplayList(&expected),
+                DisplayList(&provided)
+            );
+        }
+        Ok(())
+    }
+
     fn typecheck_variable_args_iterator<I: Iterator<Item = Type>>(
         &self,
         inst: Inst,
@@ -1439,10 +1759,17 @@ impl<'a> Verifier<'a> {
                     );
                 }
             }
-            ir::InstructionData::UnaryGlobalValue { global_value, .. } => {
-                if let Some(isa) = self.isa {
+            ir::InstructionData::UnaryGlobalValue {
+                opcode,
+                global_value,
+            } => {
+                if opcode == Opcode::SymbolValue && !self.is_symbolic_address(global_value) {
+                    return nonfatal!(errors, inst, "{} is not a symbolic address", global_value);
+                }
+                if let Some(pointer_type) = self.pointer_type() {
                     let inst_type = self.func.dfg.value_type(self.func.dfg.first_result(inst));
-                    let global_type = self.func.global_values[global_value].global_type(isa);
+                    let global_type =
+                        self.func.global_values[global_value].global_type(pointer_type);
                     if inst_type != global_type {
                         return nonfatal!(
                         errors,
@@ -1454,6 +1781,14 @@ impl<'a> Verifier<'a> {
                     }
                 }
             }
+            ir::InstructionData::LoadSymbol { global_value, .. }
+            | ir::InstructionData::StoreSymbol { global_value, .. }
+            | ir::InstructionData::LoadGlobal { global_value, .. } => {
+                match self.func.global_values[global_value] {
+                    ir::GlobalValueData::Symbol { .. } => {}
+                    _ => return nonfatal!(errors, inst, "{} is not a symbol", global_value),
+                }
+            }
             _ => {}
         }
         Ok(())
@@ -1532,6 +1867,55 @@ impl<'a> Verifier<'a> {
         errors.as_result()
     }
 
+    /// If the verifier has been set up with an ISA, make sure that the values defined in `ebb`
+    /// have types that the `enable_float` and `enable_simd` settings allow, and that its
+    /// instructions aren't disabled by a setting like `enable_cycle_counter`.
+    fn verify_enabled_types(&self, ebb: Ebb, errors: &mut VerifierErrors) -> VerifierStepResult<()> {
+        let flags = match self.isa {
+            Some(isa) => isa.flags(),
+            None => return Ok(()),
+        };
+        let dfg = &self.func.dfg;
+        for &param in dfg.ebb_params(ebb) {
+            let ty = dfg.value_type(param);
+            if let Some(setting) = flags.disabling_setting(ty) {
+                report!(
+                    errors,
+                    ebb,
+                    "parameter {} has type {}, but {} is disabled",
+                    param,
+                    ty,
+                    setting
+                );
+            }
+        }
+        for inst in self.func.layout.ebb_insts(ebb) {
+            if let Some(setting) = flags.disabling_opcode_setting(dfg[inst].opcode()) {
+                report!(
+                    errors,
+                    inst,
+                    "{} is disabled by {}",
+                    dfg[inst].opcode(),
+                    setting
+                );
+            }
+            for &result in dfg.inst_results(inst) {
+                let ty = dfg.value_type(result);
+                if let Some(setting) = flags.disabling_setting(ty) {
+                    report!(
+                        errors,
+                        inst,
+                        "result {} has type {}, but {} is disabled",
+                        result,
+                        ty,
+                        setting
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
     /// If the verifier has been set up with an ISA, make sure that the recorded encoding for the
     /// instruction (if any) matches how the ISA would encode it.
     fn verify_encoding(&self, inst: Inst, errors: &mut VerifierErrors) -> VerifierStepResult<()> {
@@ -1644,13 +2028,32 @@ impl<'a> Verifier<'a> {
         Ok(())
     }
 
+    /// Check the integrity of `cfg` and `domtree` if they are valid, and then run the verifier.
+    fn run_with_context(
+        &self,
+        cfg: &ControlFlowGraph,
+        domtree: &DominatorTree,
+        errors: &mut VerifierErrors,
+    ) -> VerifierStepResult<()> {
+        if cfg.is_valid() {
+            self.cfg_integrity(cfg, errors)?;
+        }
+        if domtree.is_valid() {
+            self.domtree_integrity(domtree, errors)?;
+        }
+        self.run(errors)
+    }
+
     pub fn run(&self, errors: &mut VerifierErrors) -> VerifierStepResult<()> {
+        self.verify_pointer_type(errors)?;
+        self.verify_signatures(errors)?;
         self.verify_global_values(errors)?;
         self.verify_heaps(errors)?;
         self.verify_tables(errors)?;
         self.typecheck_entry_block_params(errors)?;
 
         for ebb in self.func.layout.ebbs() {
+            self.verify_enabled_types(ebb, errors)?;
             for inst in self.func.layout.ebb_insts(ebb) {
                 self.ebb_integrity(ebb, inst, errors)?;
                 self.instruction_integrity(inst, errors)?;
@@ -1667,11 +2070,17 @@ impl<'a> Verifier<'a> {
 
 #[cfg(test)]
 mod tests {
-    use super::{Verifier, VerifierError, VerifierErrors};
+    use super::{verify_function, Verifier, VerifierError, VerifierErrors};
+    use cursor::{Cursor, FuncCursor};
     use entity::EntityList;
     use ir::instructions::{InstructionData, Opcode};
-    use ir::Function;
+    use ir::types::{I32, I64};
+    use ir::{
+        AbiParam, ArgumentPurpose, Function, GlobalValueData, HeapData, HeapStyle, InstBuilder,
+    };
+    use result::CodegenError;
     use settings;
+    use std::string::ToString;
 
     macro_rules! assert_err_with_msg {
         ($e:expr, $msg:expr) => {
@@ -1729,4 +2138,80 @@ mod tests {
 
         assert_err_with_msg!(errors, "instruction format");
     }
+
+    #[test]
+    fn bad_heap_declarations() {
+        let mut func = Function::new();
+        func.signature
+            .params
+            .push(AbiParam::special(I64, ArgumentPurpose::VMContext));
+        func.signature.params.push(AbiParam::new(I64));
+        let vmctx = func.create_global_value(GlobalValueData::VMContext);
+        let base = func.create_global_value(GlobalValueData::Load {
+            base: vmctx,
+            offset: 8.into(),
+            global_type: I64,
+            readonly: true,
+        });
+        let heap = func.create_heap(HeapData {
+            base,
+            min_size: 0x1_0000.into(),
+            guard_size: 0x8000_0000.into(),
+            style: HeapStyle::Static {
+                bound: 0x1_0000_0000.into(),
+            },
+            index_type: I32,
+            check_alignment: false,
+        });
+
+        let ebb0 = func.dfg.make_ebb();
+        func.dfg.append_ebb_param(ebb0, I64);
+        let index = func.dfg.append_ebb_param(ebb0, I64);
+        let mut pos = FuncCursor::new(&mut func);
+        pos.insert_ebb(ebb0);
+        pos.ins().heap_addr(I64, heap, index, 4);
+        pos.ins().return_(&[]);
+
+        let flags = &settings::Flags::new(settings::builder());
+        let errors = verify_function(&func, flags).unwrap_err();
+        assert_eq!(
+            CodegenError::from(errors).to_string(),
+            concat!(
+                "Verifier errors:\n",
+                "- inst0: index type i64 differs from heap index type i32\n",
+                "    heap0 = static gv1, min 0x0001_0000, bound 0x0001_0000_0000, ",
+                "guard 0x8000_0000, index_type i32\n",
+                "    gv1 = load.i64 notrap aligned readonly gv0+8\n",
+                "    gv0 = vmctx\n"
+            )
+        );
+    }
+
+    #[test]
+    fn cyclic_global_value_declarations() {
+        let mut func = Function::new();
+        let gv0 = func.create_global_value(GlobalValueData::VMContext);
+        let gv1 = func.create_global_value(GlobalValueData::IAddImm {
+            base: gv0,
+            offset: 8.into(),
+            global_type: I64,
+        });
+        func.global_values[gv0] = GlobalValueData::Load {
+            base: gv1,
+            offset: 0.into(),
+            global_type: I64,
+            readonly: false,
+        };
+
+        let flags = &settings::Flags::new(settings::builder());
+        let errors = verify_function(&func, flags).unwrap_err();
+        assert_eq!(
+            errors.to_string(),
+            concat!(
+                "- gv0: global value cycle: [gv0, gv1]\n",
+                "    gv0 = load.i64 notrap aligned gv1\n",
+                "    gv1 = iadd_imm.i64 gv0, 8\n"
+            )
+        );
+    }
 }
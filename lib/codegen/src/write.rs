@@ -5,9 +5,14 @@
 
 use entity::SecondaryMap;
 use ir::entities::AnyEntity;
-use ir::{DataFlowGraph, Ebb, Function, Inst, SigRef, Type, Value, ValueDef};
+use ir::immediates::{Imm64, Uimm32};
+use ir::{
+    DataFlowGraph, Ebb, ExtFuncData, ExternalName, Function, GlobalValueData, Inst, NameResolver,
+    SigRef, Type, Value, ValueDef,
+};
 use isa::{RegInfo, TargetIsa};
 use packed_option::ReservedValue;
+use regalloc::RegDiversions;
 use std::fmt::{self, Write};
 use std::string::String;
 use std::vec::Vec;
@@ -41,7 +46,19 @@ pub trait FuncWriter {
 
         for (gv, gv_data) in &func.global_values {
             any = true;
-            self.write_entity_definition(w, func, gv.into(), gv_data)?;
+            let renamed = match *gv_data {
+                GlobalValueData::Symbol {
+                    ref name,
+                    offset,
+                    colocated,
+                } => self.resolve_name(name).map(|name| GlobalValueData::Symbol {
+                    name,
+                    offset,
+                    colocated,
+                }),
+                _ => None,
+            };
+            self.write_entity_definition(w, func, gv.into(), renamed.as_ref().unwrap_or(gv_data))?;
         }
 
         for (heap, heap_data) in &func.heaps {
@@ -68,6 +85,11 @@ pub trait FuncWriter {
         for (fnref, ext_func) in &func.dfg.ext_funcs {
             if ext_func.signature != SigRef::reserved_value() {
                 any = true;
+                let renamed = self.resolve_name(&ext_func.name).map(|name| ExtFuncData {
+                    name,
+                    ..ext_func.clone()
+                });
+                let ext_func = renamed.as_ref().unwrap_or(ext_func);
                 self.write_entity_definition(w, func, fnref.into(), ext_func)?;
             }
         }
@@ -77,6 +99,18 @@ pub trait FuncWriter {
             self.write_entity_definition(w, func, jt.into(), jt_data)?;
         }
 
+        // The frame size is needed to emit stack accesses, so it is written once it is known.
+        if let Some(frame_size) = func.stack_slots.frame_size {
+            any = true;
+            writeln!(w, "    frame_size {}", frame_size)?;
+        }
+
+        // The frame layout is not part of the textual IR, so it is written as a comment.
+        if let Some(ref frame_layout) = func.frame_layout {
+            any = true;
+            writeln!(w, "    ; frame: {}", frame_layout.display(regs))?;
+        }
+
         Ok(any)
     }
 
@@ -91,6 +125,13 @@ pub trait FuncWriter {
     ) -> fmt::Result {
         writeln!(w, "    {} = {}", entity, value)
     }
+
+    /// Get the name to display in place of the external `name`. By default, names are displayed
+    /// as is.
+    #[allow(unused_variables)]
+    fn resolve_name(&self, name: &ExternalName) -> Option<ExternalName> {
+        None
+    }
 }
 
 /// A `PlainWriter` that doesn't decorate the function.
@@ -110,12 +151,44 @@ impl FuncWriter for PlainWriter {
     }
 }
 
+/// A `FuncWriter` that displays the symbol names supplied by a `NameResolver`.
+struct ResolvingWriter<'a>(&'a NameResolver);
+
+impl<'a> FuncWriter for ResolvingWriter<'a> {
+    fn write_instruction(
+        &mut self,
+        w: &mut Write,
+        func: &Function,
+        aliases: &SecondaryMap<Value, Vec<Value>>,
+        isa: Option<&TargetIsa>,
+        inst: Inst,
+        indent: usize,
+    ) -> fmt::Result {
+        write_instruction(w, func, aliases, isa, inst, indent)
+    }
+
+    fn resolve_name(&self, name: &ExternalName) -> Option<ExternalName> {
+        self.0.symbol_name(name).map(ExternalName::symbol)
+    }
+}
+
 /// Write `func` to `w` as equivalent text.
 /// Use `isa` to emit ISA-dependent annotations.
 pub fn write_function(w: &mut Write, func: &Function, isa: Option<&TargetIsa>) -> fmt::Result {
     decorate_function(&mut PlainWriter, w, func, isa)
 }
 
+/// Write `func` to `w` as text, with the external names known to `resolver` replaced by their
+/// symbol names.
+pub fn write_function_with_resolver(
+    w: &mut Write,
+    func: &Function,
+    isa: Option<&TargetIsa>,
+    resolver: &NameResolver,
+) -> fmt::Result {
+    decorate_function(&mut ResolvingWriter(resolver), w, func, isa)
+}
+
 /// Create a reverse-alias map from a value to all aliases having that value as a direct target
 fn alias_map(func: &Function) -> SecondaryMap<Value, Vec<Value>> {
     let mut aliases = SecondaryMap::<_, Vec<_>>::new();
@@ -141,7 +214,8 @@ pub fn decorate_function<FW: FuncWriter>(
     let regs = regs.as_ref();
 
     write!(w, "function ")?;
-    write_spec(w, func, regs)?;
+    let name = func_w.resolve_name(&func.name);
+    write_spec(w, func, name.as_ref().unwrap_or(&func.name), regs)?;
     writeln!(w, " {{")?;
     let aliases = alias_map(func);
     let mut any = func_w.write_preamble(w, func, regs)?;
@@ -159,8 +233,13 @@ pub fn decorate_function<FW: FuncWriter>(
 //
 // Function spec.
 
-fn write_spec(w: &mut Write, func: &Function, regs: Option<&RegInfo>) -> fmt::Result {
-    write!(w, "{}{}", func.name, func.signature.display(regs))
+fn write_spec(
+    w: &mut Write,
+    func: &Function,
+    name: &ExternalName,
+    regs: Option<&RegInfo>,
+) -> fmt::Result {
+    write!(w, "{}{}", name, func.signature.display(regs))
 }
 
 //----------------------------------------------------------------------
@@ -307,20 +386,12 @@ fn write_instruction(
         write!(s, "{} ", srcloc)?;
     }
 
-    // Write out encoding info.
-    if let Some(enc) = func.encodings.get(inst).cloned() {
+    // Write out encoding info, with the value locations if we have them.
+    if let Some(encoded) = func.encoded_inst(inst, &RegDiversions::new()) {
         if let Some(isa) = isa {
-            write!(s, "[{}", isa.encoding_info().display(enc))?;
-            // Write value locations, if we have them.
-            if !func.locations.is_empty() {
-                let regs = isa.register_info();
-                for &r in func.dfg.inst_results(inst) {
-                    write!(s, ",{}", func.locations[r].display(&regs))?
-                }
-            }
-            write!(s, "] ")?;
+            write!(s, "{} ", encoded.display(isa))?;
         } else {
-            write!(s, "[{}] ", enc)?;
+            write!(s, "[{}] ", encoded.encoding)?;
         }
     }
 
@@ -350,6 +421,12 @@ fn write_instruction(
     }
 
     write_operands(w, &func.dfg, isa, inst)?;
+    if let Some(hint) = func.branch_hints[inst] {
+        write!(w, " {}", hint)?;
+    }
+    if func.is_patchable(inst) {
+        write!(w, " patchable")?;
+    }
     writeln!(w)?;
 
     // Value aliases come out on lines after the instruction defining the referrent.
@@ -370,13 +447,15 @@ pub fn write_operands(
     use ir::instructions::InstructionData::*;
     match dfg[inst] {
         Unary { arg, .. } => write!(w, " {}", arg),
-        UnaryImm { imm, .. } => write!(w, " {}", imm),
+        UnaryImm { imm, .. } => write!(w, " {}", DisplayImm(imm, dfg.ctrl_typevar(inst))),
         UnaryIeee32 { imm, .. } => write!(w, " {}", imm),
         UnaryIeee64 { imm, .. } => write!(w, " {}", imm),
         UnaryBool { imm, .. } => write!(w, " {}", imm),
         UnaryGlobalValue { global_value, .. } => write!(w, " {}", global_value),
         Binary { args, .. } => write!(w, " {}, {}", args[0], args[1]),
-        BinaryImm { arg, imm, .. } => write!(w, " {}, {}", arg, imm),
+        BinaryImm { arg, imm, .. } => {
+            write!(w, " {}, {}", arg, DisplayImm(imm, dfg.value_type(arg)))
+        }
         Ternary { args, .. } => write!(w, " {}, {}, {}", args[0], args[1], args[2]),
         MultiAry { ref args, .. } => {
             if args.is_empty() {
@@ -389,7 +468,13 @@ pub fn write_operands(
         InsertLane { lane, args, .. } => write!(w, " {}, {}, {}", args[0], lane, args[1]),
         ExtractLane { lane, arg, .. } => write!(w, " {}, {}", arg, lane),
         IntCompare { cond, args, .. } => write!(w, " {} {}, {}", cond, args[0], args[1]),
-        IntCompareImm { cond, arg, imm, .. } => write!(w, " {} {}, {}", cond, arg, imm),
+        IntCompareImm { cond, arg, imm, .. } => write!(
+            w,
+            " {} {}, {}",
+            cond,
+            arg,
+            DisplayImm(imm, dfg.value_type(arg))
+        ),
         IntCond { cond, arg, .. } => write!(w, " {} {}", cond, arg),
         FloatCompare { cond, args, .. } => write!(w, " {} {}, {}", cond, args[0], args[1]),
         FloatCond { cond, arg, .. } => write!(w, " {} {}", cond, arg),
@@ -499,6 +584,19 @@ pub fn write_operands(
                 offset
             )
         }
+        LoadSymbol {
+            flags,
+            arg,
+            global_value,
+            offset,
+            ..
+        } => write!(w, "{} {}, {}{}", flags, arg, global_value, offset),
+        LoadGlobal {
+            flags,
+            global_value,
+            offset,
+            ..
+        } => write!(w, "{} {}{}", flags, global_value, offset),
         Store {
             flags,
             args,
@@ -521,6 +619,20 @@ pub fn write_operands(
                 offset
             )
         }
+        StoreSymbol {
+            flags,
+            ref args,
+            global_value,
+            offset,
+            ..
+        } => {
+            let args = args.as_slice(pool);
+            write!(
+                w,
+                "{} {}, {}, {}{}",
+                flags, args[0], args[1], global_value, offset
+            )
+        }
         RegMove { arg, src, dst, .. } => {
             if let Some(isa) = isa {
                 let regs = isa.register_info();
@@ -564,17 +676,47 @@ pub fn write_operands(
                 write!(w, " {}, {} -> %{}", arg, src, dst)
             }
         }
-        Trap { code, .. } => write!(w, " {}", code),
-        CondTrap { arg, code, .. } => write!(w, " {}, {}", arg, code),
+        Trap { code, payload, .. } => {
+            write!(w, " {}", code)?;
+            write_trap_payload(w, payload)
+        }
+        CondTrap {
+            arg, code, payload, ..
+        } => {
+            write!(w, " {}, {}", arg, code)?;
+            write_trap_payload(w, payload)
+        }
         IntCondTrap {
-            cond, arg, code, ..
-        } => write!(w, " {} {}, {}", cond, arg, code),
+            cond,
+            arg,
+            code,
+            payload,
+            ..
+        } => {
+            write!(w, " {} {}, {}", cond, arg, code)?;
+            write_trap_payload(w, payload)
+        }
         FloatCondTrap {
-            cond, arg, code, ..
-        } => write!(w, " {} {}, {}", cond, arg, code),
+            cond,
+            arg,
+            code,
+            payload,
+            ..
+        } => {
+            write!(w, " {} {}, {}", cond, arg, code)?;
+            write_trap_payload(w, payload)
+        }
     }
 }
 
+/// Write the payload of a trap instruction, which is omitted when it is 0.
+fn write_trap_payload(w: &mut Write, payload: Uimm32) -> fmt::Result {
+    if payload != Uimm32::from(0) {
+        write!(w, ", {}", payload)?;
+    }
+    Ok(())
+}
+
 /// Write EBB args using optional parantheses.
 fn write_ebb_args(w: &mut Write, args: &[Value]) -> fmt::Result {
     if args.is_empty() {
@@ -615,11 +757,33 @@ impl<'a> fmt::Display for DisplayValuesWithDelimiter<'a> {
     }
 }
 
+/// An integer immediate operand of an instruction whose controlling type is the second field.
+///
+/// Immediates for narrow integer types are stored sign-extended, but the ones too large to be
+/// written in decimal are written as the bits of the type, like `0x8000_0000` for an `i32`.
+struct DisplayImm(Imm64, Type);
+
+impl fmt::Display for DisplayImm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let x: i64 = self.0.into();
+        if x <= -10_000 && self.1.is_int() {
+            write!(f, "{}", self.0.zero_extend_from_width(self.1.bits()))
+        } else {
+            write!(f, "{}", self.0)
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use cursor::{Cursor, CursorPosition, FuncCursor};
+    use ir::immediates::Imm64;
     use ir::types;
-    use ir::{ExternalName, Function, InstBuilder, StackSlotData, StackSlotKind};
+    use ir::{
+        ExtFuncData, ExternalName, Function, GlobalValueData, InstBuilder, NameResolver, Signature,
+        StackSlotData, StackSlotKind,
+    };
+    use settings::CallConv;
     use std::string::ToString;
 
     #[test]
@@ -666,6 +830,54 @@ mod tests {
         );
     }
 
+    /// Resolves the names in user namespace 0 to made-up symbol names.
+    struct Resolver;
+
+    impl NameResolver for Resolver {
+        fn symbol_name(&self, name: &ExternalName) -> Option<&str> {
+            match *name {
+                ExternalName::User {
+                    namespace: 0,
+                    index,
+                } => ["main", "memcpy"].get(index as usize).cloned(),
+                _ => None,
+            }
+        }
+    }
+
+    #[test]
+    fn resolved_names() {
+        let mut f = Function::with_name_signature(
+            ExternalName::user(0, 0),
+            Signature::new(CallConv::SystemV),
+        );
+        let sig = f.import_signature(Signature::new(CallConv::SystemV));
+        f.import_function(ExtFuncData {
+            name: ExternalName::user(0, 1),
+            signature: sig,
+            colocated: false,
+        });
+        f.create_global_value(GlobalValueData::Symbol {
+            name: ExternalName::user(0, 2),
+            offset: Imm64::new(0),
+            colocated: true,
+        });
+        assert_eq!(
+            f.display_with_resolver(None, &Resolver).to_string(),
+            "function \"main\"() system_v {\n    \
+             gv0 = symbol colocated u0:2\n    \
+             sig0 = () system_v\n    \
+             fn0 = \"memcpy\" sig0\n}\n"
+        );
+        assert_eq!(
+            f.to_string(),
+            "function u0:0() system_v {\n    \
+             gv0 = symbol colocated u0:2\n    \
+             sig0 = () system_v\n    \
+             fn0 = u0:1 sig0\n}\n"
+        );
+    }
+
     #[test]
     fn aliases() {
         use ir::InstBuilder;
@@ -701,4 +913,83 @@ mod tests {
             "function u0:0() fast {\nebb0(v3: i32):\n    v0 -> v3\n    v2 -> v0\n    v4 = iconst.i32 42\n    v5 = iadd v0, v0\n    v1 -> v5\n    v6 = iconst.i32 23\n    v7 = iadd v1, v1\n}\n"
         );
     }
+
+    #[test]
+    #[cfg(build_x86)]
+    fn annotations() {
+        use ir::{AbiParam, Inst};
+        use isa;
+        use settings;
+        use std::str::FromStr;
+        use std::string::String;
+        use std::vec::Vec;
+        use target_lexicon;
+        use Context;
+
+        let isa = isa::lookup(triple!("x86_64"))
+            .unwrap()
+            .finish(settings::Flags::new(settings::builder()));
+
+        let mut func = Function::new();
+        func.signature.params.push(AbiParam::new(types::I32));
+        func.signature.params.push(AbiParam::new(types::I32));
+        func.signature.returns.push(AbiParam::new(types::I32));
+        {
+            let ebb0 = func.dfg.make_ebb();
+            let ebb1 = func.dfg.make_ebb();
+            let mut pos = FuncCursor::new(&mut func);
+            pos.insert_ebb(ebb0);
+            let v0 = pos.func.dfg.append_ebb_param(ebb0, types::I32);
+            let v1 = pos.func.dfg.append_ebb_param(ebb0, types::I32);
+            let v2 = pos.ins().iadd(v0, v1);
+            pos.ins().brz(v2, ebb1, &[]);
+            pos.ins().return_(&[v1]);
+            pos.insert_ebb(ebb1);
+            let v3 = pos.ins().imul(v2, v0);
+            pos.ins().return_(&[v3]);
+        }
+        let mut ctx = Context::for_function(func);
+        ctx.compile(&*isa).unwrap();
+        let func = &ctx.func;
+        let encinfo = isa.encoding_info();
+
+        // The annotations in the text are the first words of the lines starting with `[`.
+        let text = func.display(&*isa).to_string();
+        let annotations: Vec<String> = text
+            .lines()
+            .map(|line| line.trim())
+            .filter(|line| line.starts_with('['))
+            .map(|line| line.split(' ').next().unwrap().to_string())
+            .collect();
+
+        let encoded: Vec<_> = func.encoded_insts(&encinfo).collect();
+        assert_eq!(
+            encoded
+                .iter()
+                .map(|&(_, ref enc, _)| enc.display(&*isa).to_string())
+                .collect::<Vec<_>>(),
+            annotations
+        );
+
+        // The offsets and sizes are the same as computed by `inst_offsets`.
+        let offsets: Vec<(_, Inst, _)> = func
+            .layout
+            .ebbs()
+            .flat_map(|ebb| func.inst_offsets(ebb, &encinfo))
+            .collect();
+        assert_eq!(
+            encoded
+                .iter()
+                .map(|&(offset, ref enc, size)| (offset, enc.inst, size))
+                .collect::<Vec<_>>(),
+            offsets
+        );
+
+        for &(_, ref enc, _) in &encoded {
+            assert_eq!(enc.encoding, func.encodings[enc.inst]);
+            assert!(encinfo.recipe_name(enc.encoding).is_some());
+            assert_eq!(enc.args.len(), func.dfg.inst_args(enc.inst).len());
+            assert_eq!(enc.results.len(), func.dfg.inst_results(enc.inst).len());
+        }
+    }
 }
@@ -43,6 +43,18 @@ where
         }
     }
 
+    /// Create a new empty map with room for the entries of `capacity` keys before it reallocates.
+    pub fn with_capacity(capacity: usize) -> Self
+    where
+        V: Default,
+    {
+        Self {
+            elems: Vec::with_capacity(capacity),
+            default: Default::default(),
+            unused: PhantomData,
+        }
+    }
+
     /// Create a new empty map with a specified default value.
     ///
     /// This constructor does not require V to implement Default.
@@ -65,10 +77,23 @@ where
     }
 
     /// Remove all entries from this map.
+    ///
+    /// The memory of the entries is kept, so the map doesn't reallocate when it is filled again.
     pub fn clear(&mut self) {
         self.elems.clear()
     }
 
+    /// Get the number of keys the map can hold entries for without reallocating.
+    pub fn capacity(&self) -> usize {
+        self.elems.capacity()
+    }
+
+    /// Reserve room for the entries of at least `additional` more keys than the map has entries
+    /// for.
+    pub fn reserve(&mut self, additional: usize) {
+        self.elems.reserve(additional)
+    }
+
     /// Iterate over all the keys and values in this map.
     pub fn iter(&self) -> Iter<K, V> {
         Iter::new(self.elems.iter())
@@ -173,4 +198,22 @@ mod tests {
         assert_eq!(shared[r1], 5);
         assert_eq!(shared[r2], 3);
     }
+
+    #[test]
+    fn capacity() {
+        let mut m: SecondaryMap<E, u32> = SecondaryMap::with_capacity(10);
+        assert!(m.capacity() >= 10);
+        assert!(m.is_empty());
+
+        let capacity = m.capacity();
+        m[E(9)] = 1;
+        assert_eq!(m.capacity(), capacity);
+
+        m.clear();
+        assert_eq!(m.capacity(), capacity);
+        assert_eq!(m[E(9)], 0);
+
+        m.reserve(capacity + 1);
+        assert!(m.capacity() > capacity);
+    }
 }
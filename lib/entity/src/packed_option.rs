@@ -40,6 +40,15 @@ impl<T: ReservedValue> PackedOption<T> {
         }
     }
 
+    /// Converts from `&PackedOption<T>` to `Option<&T>`.
+    pub fn as_ref(&self) -> Option<&T> {
+        if self.is_none() {
+            None
+        } else {
+            Some(&self.0)
+        }
+    }
+
     /// Maps a `PackedOption<T>` to `Option<U>` by applying a function to a contained value.
     pub fn map<U, F>(self, f: F) -> Option<U>
     where
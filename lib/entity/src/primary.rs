@@ -40,6 +40,14 @@ where
         }
     }
 
+    /// Create a new empty map with room for `capacity` values before it reallocates.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            elems: Vec::with_capacity(capacity),
+            unused: PhantomData,
+        }
+    }
+
     /// Check if `k` is a valid key in the map.
     pub fn is_valid(&self, k: K) -> bool {
         k.index() < self.elems.len()
@@ -86,10 +94,22 @@ where
     }
 
     /// Remove all entries from this map.
+    ///
+    /// The memory of the entries is kept, so the map doesn't reallocate when it is filled again.
     pub fn clear(&mut self) {
         self.elems.clear()
     }
 
+    /// Get the number of values the map can hold without reallocating.
+    pub fn capacity(&self) -> usize {
+        self.elems.capacity()
+    }
+
+    /// Reserve room for at least `additional` more values.
+    pub fn reserve(&mut self, additional: usize) {
+        self.elems.reserve(additional)
+    }
+
     /// Get the key that will be assigned to the next pushed value.
     pub fn next_key(&self) -> K {
         K::new(self.elems.len())
@@ -326,4 +346,23 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn capacity() {
+        let mut m: PrimaryMap<E, usize> = PrimaryMap::with_capacity(4);
+        assert!(m.capacity() >= 4);
+
+        let capacity = m.capacity();
+        for i in 0..capacity {
+            m.push(i);
+        }
+        assert_eq!(m.capacity(), capacity);
+
+        m.clear();
+        assert_eq!(m.capacity(), capacity);
+        assert_eq!(m.next_key(), E(0));
+
+        m.reserve(capacity + 1);
+        assert!(m.capacity() > capacity);
+    }
 }
@@ -1,17 +1,18 @@
 //! Defines `FaerieBackend`.
 
 use container;
-use cranelift_codegen::binemit::{Addend, CodeOffset, NullTrapSink, Reloc, RelocSink};
+use cranelift_codegen::binemit::{Addend, CodeOffset, CollectedTraps, Reloc, RelocSink};
 use cranelift_codegen::isa::TargetIsa;
 use cranelift_codegen::{self, binemit, ir};
 use cranelift_module::{
-    Backend, DataContext, DataDescription, Init, Linkage, ModuleError, ModuleNamespace,
-    ModuleResult,
+    AppliedReloc, Backend, DataContext, DataDescription, FinalizedDataInfo, FinalizedFuncInfo,
+    FinalizedLocation, Init, Linkage, ModuleError, ModuleNamespace, ModuleResult, ObjectSection,
+    TrapSite,
 };
 use faerie;
 use failure::Error;
 use std::fs::File;
-use target_lexicon::Triple;
+use target_lexicon::{PointerWidth, Triple};
 use traps::{FaerieTrapManifest, FaerieTrapSink};
 
 #[derive(Debug)]
@@ -83,6 +84,7 @@ impl FaerieBuilder {
             ir::LibCall::Memcpy => "memcpy".to_owned(),
             ir::LibCall::Memset => "memset".to_owned(),
             ir::LibCall::Memmove => "memmove".to_owned(),
+            ir::LibCall::CycleCounter => "__cranelift_cycle_counter".to_owned(),
         })
     }
 }
@@ -97,9 +99,19 @@ pub struct FaerieBackend {
     libcall_names: Box<Fn(ir::LibCall) -> String>,
 }
 
-pub struct FaerieCompiledFunction {}
+pub struct FaerieCompiledFunction {
+    name: String,
+    size: usize,
+    traps: Vec<TrapSite>,
+    relocs: Vec<AppliedReloc>,
+}
 
-pub struct FaerieCompiledData {}
+pub struct FaerieCompiledData {
+    name: String,
+    size: usize,
+    writable: bool,
+    relocs: Vec<AppliedReloc>,
+}
 
 impl Backend for FaerieBackend {
     type Builder = FaerieBuilder;
@@ -112,6 +124,9 @@ impl Backend for FaerieBackend {
     type FinalizedFunction = ();
     type FinalizedData = ();
 
+    // Object files can't be patched once they're written, so functions can't be redefined.
+    type FunctionSlot = ();
+
     /// The returned value here provides functions for emitting object files
     /// to memory and files.
     type Product = FaerieProduct;
@@ -148,12 +163,13 @@ impl Backend for FaerieBackend {
     fn define_function(
         &mut self,
         name: &str,
-        ctx: &cranelift_codegen::Context,
+        ctx: &mut cranelift_codegen::Context,
         namespace: &ModuleNamespace<Self>,
-        code_size: u32,
     ) -> ModuleResult<FaerieCompiledFunction> {
-        let mut code: Vec<u8> = Vec::with_capacity(code_size as usize);
-        code.resize(code_size as usize, 0);
+        let mut code: Vec<u8> = Vec::new();
+        let mut traps = CollectedTraps::new();
+        let relocs;
+        let code_size;
 
         // Non-lexical lifetimes would obviate the braces here.
         {
@@ -163,42 +179,37 @@ impl Backend for FaerieBackend {
                 name,
                 namespace,
                 libcall_names: &*self.libcall_names,
+                relocs: Vec::new(),
             };
+            let info = ctx
+                .compile_and_emit(&*self.isa, &mut code, &mut reloc_sink, &mut traps)
+                .map_err(ModuleError::Compilation)?;
+            code_size = info.code_size;
+            relocs = reloc_sink.relocs;
+        }
 
-            if let Some(ref mut trap_manifest) = self.trap_manifest {
-                let mut trap_sink = FaerieTrapSink::new(name, code_size);
-                unsafe {
-                    ctx.emit_to_memory(
-                        &*self.isa,
-                        code.as_mut_ptr(),
-                        &mut reloc_sink,
-                        &mut trap_sink,
-                    )
-                };
-                trap_manifest.add_sink(trap_sink);
-            } else {
-                let mut trap_sink = NullTrapSink {};
-                unsafe {
-                    ctx.emit_to_memory(
-                        &*self.isa,
-                        code.as_mut_ptr(),
-                        &mut reloc_sink,
-                        &mut trap_sink,
-                    )
-                };
-            }
+        if let Some(ref mut trap_manifest) = self.trap_manifest {
+            let mut trap_sink = FaerieTrapSink::new(name, code_size);
+            trap_sink.sites = traps.sites.clone();
+            trap_manifest.add_sink(trap_sink);
         }
 
+        let size = code.len();
         self.artifact
             .define(name, code)
             .expect("inconsistent declaration");
-        Ok(FaerieCompiledFunction {})
+        Ok(FaerieCompiledFunction {
+            name: name.to_owned(),
+            size,
+            traps: traps.sites,
+            relocs,
+        })
     }
 
     fn define_data(
         &mut self,
         name: &str,
-        _writable: bool,
+        writable: bool,
         data_ctx: &DataContext,
         namespace: &ModuleNamespace<Self>,
     ) -> ModuleResult<FaerieCompiledData> {
@@ -224,6 +235,12 @@ impl Backend for FaerieBackend {
             }
         }
 
+        let reloc = match self.isa.triple().pointer_width().unwrap() {
+            PointerWidth::U16 => panic!(),
+            PointerWidth::U32 => Reloc::Abs4,
+            PointerWidth::U64 => Reloc::Abs8,
+        };
+        let mut relocs = Vec::new();
         for &(offset, id) in function_relocs {
             let to = &namespace.get_function_decl(&function_decls[id]).name;
             self.artifact
@@ -232,25 +249,64 @@ impl Backend for FaerieBackend {
                     to,
                     at: u64::from(offset),
                 }).map_err(|e| ModuleError::Backend(e.to_string()))?;
+            relocs.push(AppliedReloc {
+                offset,
+                reloc,
+                name: function_decls[id].clone(),
+                addend: 0,
+                target: None,
+            });
         }
         for &(offset, id, addend) in data_relocs {
-            debug_assert_eq!(
-                addend, 0,
-                "faerie doesn't support addends in data section relocations yet"
-            );
             let to = &namespace.get_data_decl(&data_decls[id]).name;
-            self.artifact
-                .link(faerie::Link {
-                    from: name,
-                    to,
-                    at: u64::from(offset),
-                }).map_err(|e| ModuleError::Backend(e.to_string()))?;
+            let link = faerie::Link {
+                from: name,
+                to,
+                at: u64::from(offset),
+            };
+            let triple = self.isa.triple();
+            let size = self.isa.pointer_bytes() as usize;
+            let linked = if container::write_implicit_addend(
+                &mut bytes,
+                offset as usize,
+                size,
+                addend,
+                triple,
+            ) {
+                self.artifact.link(link)
+            } else {
+                let (raw_reloc, raw_addend) = container::raw_relocation(reloc, triple);
+                // TODO: Handle overflow.
+                let final_addend = addend + raw_addend;
+                let addend_i32 = final_addend as i32;
+                debug_assert!(i64::from(addend_i32) == final_addend);
+                self.artifact.link_with(
+                    link,
+                    faerie::RelocOverride {
+                        reloc: raw_reloc,
+                        addend: addend_i32,
+                    },
+                )
+            };
+            linked.map_err(|e| ModuleError::Backend(e.to_string()))?;
+            relocs.push(AppliedReloc {
+                offset,
+                reloc,
+                name: data_decls[id].clone(),
+                addend,
+                target: None,
+            });
         }
 
         self.artifact
             .define(name, bytes)
             .expect("inconsistent declaration");
-        Ok(FaerieCompiledData {})
+        Ok(FaerieCompiledData {
+            name: name.to_owned(),
+            size,
+            writable,
+            relocs,
+        })
     }
 
     fn write_data_funcaddr(
@@ -284,6 +340,31 @@ impl Backend for FaerieBackend {
         // Nothing to do.
     }
 
+    fn get_finalized_function_info(&self, func: &FaerieCompiledFunction) -> FinalizedFuncInfo {
+        FinalizedFuncInfo {
+            location: FinalizedLocation::Symbol {
+                name: func.name.clone(),
+                section: ObjectSection::Text,
+            },
+            size: func.size,
+            rodata: None,
+            rodata_size: 0,
+            traps: func.traps.clone(),
+            relocs: func.relocs.clone(),
+        }
+    }
+
+    fn prepare_redefine(&mut self, name: &str) -> ModuleResult<()> {
+        Err(ModuleError::Backend(format!(
+            "faerie doesn't support redefining function {}",
+            name
+        )))
+    }
+
+    fn swap_function(&mut self, _slot: &(), _func: &FaerieCompiledFunction) {
+        unreachable!("faerie doesn't support redefining functions");
+    }
+
     fn finalize_data(&mut self, _data: &FaerieCompiledData, _namespace: &ModuleNamespace<Self>) {
         // Nothing to do.
     }
@@ -292,6 +373,21 @@ impl Backend for FaerieBackend {
         // Nothing to do.
     }
 
+    fn get_finalized_data_info(&self, data: &FaerieCompiledData) -> FinalizedDataInfo {
+        FinalizedDataInfo {
+            location: FinalizedLocation::Symbol {
+                name: data.name.clone(),
+                section: if data.writable {
+                    ObjectSection::Data
+                } else {
+                    ObjectSection::ReadOnlyData
+                },
+            },
+            size: data.size,
+            relocs: data.relocs.clone(),
+        }
+    }
+
     fn publish(&mut self) {
         // Nothing to do.
     }
@@ -363,6 +459,7 @@ struct FaerieRelocSink<'a> {
     name: &'a str,
     namespace: &'a ModuleNamespace<'a, FaerieBackend>,
     libcall_names: &'a Fn(ir::LibCall) -> String,
+    relocs: Vec<AppliedReloc>,
 }
 
 impl<'a> RelocSink for FaerieRelocSink<'a> {
@@ -378,6 +475,8 @@ impl<'a> RelocSink for FaerieRelocSink<'a> {
         addend: Addend,
     ) {
         let ref_name: String = match *name {
+            // Symbol names are the faerie symbol names already.
+            ir::ExternalName::Symbol(ref symbol) => symbol.to_string(),
             ir::ExternalName::User { .. } => {
                 if self.namespace.is_function(name) {
                     self.namespace.get_function_decl(name).name.clone()
@@ -411,6 +510,13 @@ impl<'a> RelocSink for FaerieRelocSink<'a> {
                     addend: addend_i32,
                 },
             ).expect("faerie relocation error");
+        self.relocs.push(AppliedReloc {
+            offset,
+            reloc,
+            name: name.clone(),
+            addend,
+            target: None,
+        });
     }
 
     fn reloc_jt(&mut self, _offset: CodeOffset, _reloc: Reloc, _jt: ir::JumpTable) {
@@ -1,6 +1,6 @@
 //! Utilities for working with Faerie container formats.
 
-use cranelift_codegen::binemit::Reloc;
+use cranelift_codegen::binemit::{write_data, Addend, Reloc};
 use target_lexicon::{Architecture, BinaryFormat, Triple};
 
 /// An object file format.
@@ -63,3 +63,57 @@ pub fn raw_relocation(reloc: Reloc, triple: &Triple) -> (u32, i64) {
         _ => unimplemented!("unsupported format"),
     }
 }
+
+/// Write the addend of the relocation of the `size` bytes at `offset` in `bytes`, if the object
+/// file format of `triple` takes the addends of data relocations from the relocated bytes rather
+/// than from the relocation entries. The addend is written in the byte order of the target.
+///
+/// Returns whether the addend was written.
+pub fn write_implicit_addend(
+    bytes: &mut [u8],
+    offset: usize,
+    size: usize,
+    addend: Addend,
+    triple: &Triple,
+) -> bool {
+    match triple.binary_format {
+        // ELF relocation entries have explicit addends.
+        BinaryFormat::Elf => false,
+        BinaryFormat::Macho => {
+            let endianness = triple.endianness().unwrap();
+            write_data(&mut bytes[offset..offset + size], addend as u64, endianness);
+            true
+        }
+        _ => unimplemented!("unsupported format"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn write_addend(triple: &str) -> Option<[u8; 8]> {
+        let mut bytes = [0; 8];
+        let triple = Triple::from_str(triple).unwrap();
+        if write_implicit_addend(&mut bytes, 4, 4, -0x0102_0304, &triple) {
+            Some(bytes)
+        } else {
+            None
+        }
+    }
+
+    #[test]
+    fn implicit_addends() {
+        assert_eq!(
+            write_addend("x86_64-apple-darwin"),
+            Some([0, 0, 0, 0, 0xfc, 0xfc, 0xfd, 0xfe])
+        );
+        assert_eq!(
+            write_addend("powerpc-apple-darwin"),
+            Some([0, 0, 0, 0, 0xfe, 0xfd, 0xfc, 0xfc])
+        );
+        assert_eq!(write_addend("x86_64-unknown-linux-gnu"), None);
+        assert_eq!(write_addend("powerpc-unknown-linux-gnu"), None);
+    }
+}
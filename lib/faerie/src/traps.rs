@@ -4,20 +4,15 @@
 use cranelift_codegen::{binemit, ir};
 
 /// Record of the arguments cranelift passes to `TrapSink::trap`
-pub struct FaerieTrapSite {
-    /// Offset into function
-    pub offset: binemit::CodeOffset,
-    /// Source location given to cranelift
-    pub srcloc: ir::SourceLoc,
-    /// Trap code, as determined by cranelift
-    pub code: ir::TrapCode,
-}
+pub type FaerieTrapSite = binemit::TrapSite;
 
 /// Record of the trap sites for a given function
 pub struct FaerieTrapSink {
     /// Name of function
     pub name: String,
-    /// Total code size of function
+    /// Size of the machine code of the function, not including its read-only data
+    ///
+    /// A trapping PC in `0..code_size` from the start of the function belongs to this function.
     pub code_size: u32,
     /// All trap sites collected in function
     pub sites: Vec<FaerieTrapSite>,
@@ -35,11 +30,18 @@ impl FaerieTrapSink {
 }
 
 impl binemit::TrapSink for FaerieTrapSink {
-    fn trap(&mut self, offset: binemit::CodeOffset, srcloc: ir::SourceLoc, code: ir::TrapCode) {
+    fn trap(
+        &mut self,
+        offset: binemit::CodeOffset,
+        srcloc: ir::SourceLoc,
+        code: ir::TrapCode,
+        payload: u32,
+    ) {
         self.sites.push(FaerieTrapSite {
             offset,
             srcloc,
             code,
+            payload,
         });
     }
 }
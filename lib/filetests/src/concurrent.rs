@@ -13,15 +13,22 @@ use std::sync::mpsc::{channel, Receiver, Sender};
 use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::Duration;
-use {runone, TestResult};
+use {runone, stats, TestResult};
 
 /// Request sent to worker threads contains jobid and path.
 struct Request(usize, PathBuf);
 
 /// Reply from worker thread,
 pub enum Reply {
-    Starting { jobid: usize, thread_num: usize },
-    Done { jobid: usize, result: TestResult },
+    Starting {
+        jobid: usize,
+        thread_num: usize,
+    },
+    Done {
+        jobid: usize,
+        result: TestResult,
+        stats: Vec<String>,
+    },
     Tick,
 }
 
@@ -147,7 +154,13 @@ fn worker_thread(
                     error!("FAIL: {}", msg);
                 }
 
-                replies.send(Reply::Done { jobid, result }).unwrap();
+                let stats = stats::take();
+                replies
+                    .send(Reply::Done {
+                        jobid,
+                        result,
+                        stats,
+                    }).unwrap();
             }
 
             // Timing is accumulated independently per thread.
@@ -0,0 +1,128 @@
+//! Disassembly of compiled functions for `disas:` directives.
+//!
+//! Disassembling requires capstone, which is only available with the `disas` feature. Without
+//! it, the `disas:` directives are skipped.
+
+use cranelift_codegen;
+use cranelift_codegen::binemit::CodeInfo;
+use cranelift_codegen::isa::TargetIsa;
+use subtest::{Context, SubtestResult};
+
+/// Emit the compiled function in `comp_ctx`, disassemble it, and check the disassembly against
+/// the `disas:` directives in `context`.
+#[cfg(feature = "disas")]
+pub fn check_disassembly(
+    comp_ctx: &cranelift_codegen::Context,
+    code_info: &CodeInfo,
+    isa: &TargetIsa,
+    context: &Context,
+) -> SubtestResult<()> {
+    use cranelift_codegen::binemit::{NullRelocSink, NullTrapSink};
+    use subtest::run_disas_filecheck;
+
+    let mut mem = vec![0; code_info.total_size as usize];
+    unsafe {
+        comp_ctx.emit_to_memory(
+            isa,
+            mem.as_mut_ptr(),
+            // Relocations don't matter for disassembly, so ignore them.
+            &mut NullRelocSink {},
+            &mut NullTrapSink {},
+        )
+    }.map_err(|e| e.to_string())?;
+    let code = &mem[..code_info.code_size as usize];
+
+    let text = disassemble(&comp_ctx.func, isa, code)?;
+    run_disas_filecheck(&text, context)
+}
+
+/// Disassemble the machine code for `func` in `code`.
+///
+/// Each instruction is printed on its own line, prefixed with its offset. The start of each EBB
+/// is marked with a line containing the EBB name.
+#[cfg(feature = "disas")]
+fn disassemble(
+    func: &cranelift_codegen::ir::Function,
+    isa: &TargetIsa,
+    code: &[u8],
+) -> SubtestResult<String> {
+    use capstone::prelude::*;
+    use std::fmt::Write;
+    use target_lexicon::Architecture;
+
+    let cs = match isa.triple().architecture {
+        Architecture::I386 | Architecture::I586 | Architecture::I686 => Capstone::new()
+            .x86()
+            .mode(arch::x86::ArchMode::Mode32)
+            .build(),
+        Architecture::X86_64 => Capstone::new()
+            .x86()
+            .mode(arch::x86::ArchMode::Mode64)
+            .build(),
+        Architecture::Arm
+        | Architecture::Armv4t
+        | Architecture::Armv5te
+        | Architecture::Armv7
+        | Architecture::Armv7s => Capstone::new().arm().mode(arch::arm::ArchMode::Arm).build(),
+        Architecture::Thumbv6m | Architecture::Thumbv7em | Architecture::Thumbv7m => {
+            Capstone::new()
+                .arm()
+                .mode(arch::arm::ArchMode::Thumb)
+                .build()
+        }
+        Architecture::Aarch64 => Capstone::new()
+            .arm64()
+            .mode(arch::arm64::ArchMode::Arm)
+            .build(),
+        arch => return Err(format!("No disassembler for {}", arch)),
+    };
+    let mut cs = cs.map_err(|e| e.to_string())?;
+    let insns = cs.disasm_all(code, 0).map_err(|e| e.to_string())?;
+
+    let mut ebbs: Vec<_> = func
+        .layout
+        .ebbs()
+        .map(|ebb| (func.offsets[ebb], ebb))
+        .collect();
+    ebbs.reverse();
+
+    let mut text = String::new();
+    for insn in insns.iter() {
+        while let Some(&(offset, ebb)) = ebbs.last() {
+            if offset > insn.address() as u32 {
+                break;
+            }
+            writeln!(text, "{}:", ebb).unwrap();
+            ebbs.pop();
+        }
+        write!(text, "    {:4x}: ", insn.address()).unwrap();
+        if let Some(mnemonic) = insn.mnemonic() {
+            write!(text, "{}", mnemonic).unwrap();
+        }
+        if let Some(ops) = insn.op_str() {
+            if !ops.is_empty() {
+                write!(text, " {}", ops).unwrap();
+            }
+        }
+        writeln!(text).unwrap();
+    }
+    Ok(text)
+}
+
+/// Without capstone, skip the `disas:` directives.
+#[cfg(not(feature = "disas"))]
+pub fn check_disassembly(
+    comp_ctx: &cranelift_codegen::Context,
+    _code_info: &CodeInfo,
+    _isa: &TargetIsa,
+    _context: &Context,
+) -> SubtestResult<()> {
+    use std::sync::{Once, ONCE_INIT};
+
+    static NOTICE: Once = ONCE_INIT;
+    NOTICE.call_once(|| {
+        println!("Skipping disas: directives; build with the `disas` feature to check them")
+    });
+    info!("Skipping disassembly of {}", comp_ctx.func.name);
+    Ok(())
+}
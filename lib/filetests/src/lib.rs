@@ -27,11 +27,20 @@
     )
 )]
 
+#[cfg(feature = "disas")]
+extern crate capstone;
 extern crate cranelift_codegen;
+extern crate cranelift_frontend;
+extern crate cranelift_interpreter;
+extern crate cranelift_native;
 extern crate cranelift_reader;
 extern crate file_per_thread_logger;
 extern crate filecheck;
+#[cfg(unix)]
+extern crate libc;
 extern crate num_cpus;
+#[cfg(feature = "disas")]
+extern crate target_lexicon;
 #[macro_use]
 extern crate log;
 
@@ -42,22 +51,28 @@ use std::path::Path;
 use std::time;
 
 mod concurrent;
+mod disas;
 mod match_directive;
 mod runner;
 mod runone;
+mod stats;
 mod subtest;
 
+mod test_binary;
 mod test_binemit;
 mod test_cat;
 mod test_compile;
 mod test_dce;
 mod test_domtree;
+mod test_emit;
 mod test_legalizer;
 mod test_licm;
+mod test_optimize;
 mod test_postopt;
 mod test_preopt;
 mod test_print_cfg;
 mod test_regalloc;
+mod test_run;
 mod test_shrink;
 mod test_simple_gvn;
 mod test_verifier;
@@ -74,8 +89,11 @@ type TestResult = Result<time::Duration, String>;
 /// Directories are scanned recursively for test cases ending in `.clif`. These test cases are
 /// executed on background threads.
 ///
-pub fn run(verbose: bool, report_times: bool, files: &[String]) -> TestResult {
-    let mut runner = TestRunner::new(verbose, report_times);
+/// With `print_stats`, the code generation statistics of the functions compiled by `test compile`
+/// are printed in the order of the test files, so they can be compared between runs.
+///
+pub fn run(verbose: bool, report_times: bool, print_stats: bool, files: &[String]) -> TestResult {
+    let mut runner = TestRunner::new(verbose, report_times, print_stats);
 
     for path in files.iter().map(Path::new) {
         if path.is_file() {
@@ -101,7 +119,7 @@ pub fn run_passes(
     target: &str,
     file: &str,
 ) -> TestResult {
-    let mut runner = TestRunner::new(verbose, /* report_times */ false);
+    let mut runner = TestRunner::new(verbose, /* report_times */ false, /* print_stats */ false);
 
     let path = Path::new(file);
     if path == Path::new("-") || path.is_file() {
@@ -123,17 +141,21 @@ pub fn run_passes(
 /// a `.clif` test file.
 fn new_subtest(parsed: &TestCommand) -> subtest::SubtestResult<Box<subtest::SubTest>> {
     match parsed.command {
+        "binary" => test_binary::subtest(parsed),
         "binemit" => test_binemit::subtest(parsed),
         "cat" => test_cat::subtest(parsed),
         "compile" => test_compile::subtest(parsed),
         "dce" => test_dce::subtest(parsed),
         "domtree" => test_domtree::subtest(parsed),
+        "emit" => test_emit::subtest(parsed),
         "legalizer" => test_legalizer::subtest(parsed),
         "licm" => test_licm::subtest(parsed),
+        "optimize" => test_optimize::subtest(parsed),
         "postopt" => test_postopt::subtest(parsed),
         "preopt" => test_preopt::subtest(parsed),
         "print-cfg" => test_print_cfg::subtest(parsed),
         "regalloc" => test_regalloc::subtest(parsed),
+        "run" => test_run::subtest(parsed),
         "shrink" => test_shrink::subtest(parsed),
         "simple-gvn" => test_simple_gvn::subtest(parsed),
         "verifier" => test_verifier::subtest(parsed),
@@ -10,7 +10,7 @@ use std::ffi::OsStr;
 use std::fmt::{self, Display};
 use std::path::{Path, PathBuf};
 use std::time;
-use {runone, TestResult};
+use {runone, stats, TestResult};
 
 /// Timeout in seconds when we're not making progress.
 const TIMEOUT_PANIC: usize = 10;
@@ -21,6 +21,8 @@ const TIMEOUT_SLOW: usize = 3;
 struct QueueEntry {
     path: PathBuf,
     state: State,
+    // Code generation statistics recorded while running the test.
+    stats: Vec<String>,
 }
 
 #[derive(PartialEq, Eq, Debug)]
@@ -66,6 +68,9 @@ pub struct TestRunner {
     // Should we print the timings out?
     report_times: bool,
 
+    // Should we print the code generation statistics out?
+    print_stats: bool,
+
     // Directories that have not yet been scanned.
     dir_stack: Vec<PathBuf>,
 
@@ -89,10 +94,11 @@ pub struct TestRunner {
 
 impl TestRunner {
     /// Create a new blank TrstRunner.
-    pub fn new(verbose: bool, report_times: bool) -> Self {
+    pub fn new(verbose: bool, report_times: bool, print_stats: bool) -> Self {
         Self {
             verbose,
             report_times,
+            print_stats,
             dir_stack: Vec::new(),
             tests: Vec::new(),
             new_tests: 0,
@@ -118,6 +124,7 @@ impl TestRunner {
         self.tests.push(QueueEntry {
             path: file.into(),
             state: State::New,
+            stats: Vec::new(),
         });
     }
 
@@ -201,6 +208,12 @@ impl TestRunner {
             if self.verbose || result.is_err() {
                 println!("{}", self.tests[jobid]);
             }
+            if self.print_stats {
+                let path = self.tests[jobid].path.to_string_lossy();
+                for line in &self.tests[jobid].stats {
+                    println!("{}: {}", path, line);
+                }
+            }
             true
         } else {
             false
@@ -219,7 +232,7 @@ impl TestRunner {
                 // Run test synchronously.
                 self.tests[jobid].state = State::Running;
                 let result = runone::run(self.tests[jobid].path(), None, None);
-                self.finish_job(jobid, result);
+                self.finish_job(jobid, result, stats::take());
             }
             self.new_tests = jobid + 1;
         }
@@ -241,16 +254,17 @@ impl TestRunner {
         };
 
         result = runone::run(self.tests[0].path(), Some(passes), specified_target);
-        self.finish_job(0, result);
+        self.finish_job(0, result, stats::take());
     }
 
     /// Report the end of a job.
-    fn finish_job(&mut self, jobid: usize, result: TestResult) {
+    fn finish_job(&mut self, jobid: usize, result: TestResult, stats: Vec<String>) {
         assert_eq!(self.tests[jobid].state, State::Running);
         if result.is_err() {
             self.errors += 1;
         }
         self.tests[jobid].state = State::Done(result);
+        self.tests[jobid].stats = stats;
 
         // Reports jobs in order.
         while self.report_job() {
@@ -265,9 +279,13 @@ impl TestRunner {
                 assert_eq!(self.tests[jobid].state, State::Queued);
                 self.tests[jobid].state = State::Running;
             }
-            Reply::Done { jobid, result } => {
+            Reply::Done {
+                jobid,
+                result,
+                stats,
+            } => {
                 self.ticks_since_progress = 0;
-                self.finish_job(jobid, result)
+                self.finish_job(jobid, result, stats)
             }
             Reply::Tick => {
                 self.ticks_since_progress += 1;
@@ -0,0 +1,26 @@
+//! Code generation statistics reported by the test runner.
+//!
+//! The `compile` test command records the statistics of each function it compiles. They are
+//! collected per thread, and the runner takes them after running a test file so they can be
+//! printed in the order of the test files.
+
+use cranelift_codegen::ir::ExternalName;
+use cranelift_codegen::isa::TargetIsa;
+use cranelift_codegen::stats::CodegenStats;
+use std::cell::RefCell;
+use std::mem;
+
+thread_local! {
+    static RECORDED: RefCell<Vec<String>> = RefCell::new(Vec::new());
+}
+
+/// Record the statistics of the function `name` compiled for `isa`.
+pub fn record(isa: &TargetIsa, name: &ExternalName, stats: &CodegenStats) {
+    let line = format!("{} {}: {}", isa.triple(), name, stats);
+    RECORDED.with(|recorded| recorded.borrow_mut().push(line));
+}
+
+/// Take the statistics recorded on the current thread.
+pub fn take() -> Vec<String> {
+    RECORDED.with(|recorded| mem::replace(&mut *recorded.borrow_mut(), Vec::new()))
+}
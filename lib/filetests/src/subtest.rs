@@ -5,6 +5,7 @@ use cranelift_codegen::isa::TargetIsa;
 use cranelift_codegen::settings::{Flags, FlagsOrIsa};
 use cranelift_reader::{Comment, Details};
 use filecheck::{Checker, CheckerBuilder, NO_VARIABLES};
+use match_directive::match_directive;
 use std::borrow::Cow;
 
 pub type SubtestResult<T> = Result<T, String>;
@@ -69,6 +70,32 @@ pub trait SubTest {
 /// Run filecheck on `text`, using directives extracted from `context`.
 pub fn run_filecheck(text: &str, context: &Context) -> SubtestResult<()> {
     let checker = build_filechecker(context)?;
+    check_text(&checker, text)
+}
+
+/// Run filecheck on a disassembly in `text`, using the directives following the function's
+/// `disas:` marker.
+#[cfg(feature = "disas")]
+pub fn run_disas_filecheck(text: &str, context: &Context) -> SubtestResult<()> {
+    let mut builder = CheckerBuilder::new();
+    for comment in disas_comments(context) {
+        builder
+            .directive(comment.text)
+            .map_err(|e| format!("filecheck: {}", e))?;
+    }
+    check_text(&builder.finish(), text)
+}
+
+/// Does the function have a `disas:` marker followed by directives for checking its disassembly?
+pub fn has_disas_directives(context: &Context) -> bool {
+    context
+        .details
+        .comments
+        .iter()
+        .any(|comment| is_disas_marker(comment))
+}
+
+fn check_text(checker: &Checker, text: &str) -> SubtestResult<()> {
     if checker
         .check(text, NO_VARIABLES)
         .map_err(|e| format!("filecheck: {}", e))?
@@ -84,6 +111,9 @@ pub fn run_filecheck(text: &str, context: &Context) -> SubtestResult<()> {
 }
 
 /// Build a filechecker using the directives in the file preamble and the function's comments.
+///
+/// Directives following a `disas:` marker apply to the disassembly of the function, so they are
+/// not included.
 pub fn build_filechecker(context: &Context) -> SubtestResult<Checker> {
     let mut builder = CheckerBuilder::new();
     // Preamble comments apply to all functions.
@@ -92,10 +122,31 @@ pub fn build_filechecker(context: &Context) -> SubtestResult<Checker> {
             .directive(comment.text)
             .map_err(|e| format!("filecheck: {}", e))?;
     }
-    for comment in &context.details.comments {
+    for comment in context
+        .details
+        .comments
+        .iter()
+        .take_while(|comment| !is_disas_marker(comment))
+    {
         builder
             .directive(comment.text)
             .map_err(|e| format!("filecheck: {}", e))?;
     }
     Ok(builder.finish())
 }
+
+/// Is `comment` the `; disas:` line that starts the disassembly directives?
+fn is_disas_marker(comment: &Comment) -> bool {
+    match_directive(comment.text, "disas:") == Some("")
+}
+
+/// Get the function's comments following the `disas:` marker.
+#[cfg(feature = "disas")]
+fn disas_comments<'a>(context: &'a Context) -> impl Iterator<Item = &'a Comment<'a>> {
+    context
+        .details
+        .comments
+        .iter()
+        .skip_while(|comment| !is_disas_marker(comment))
+        .skip(1)
+}
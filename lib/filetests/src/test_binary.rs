@@ -0,0 +1,57 @@
+//! The `binary` subtest.
+
+use cranelift_codegen::ir::Function;
+use cranelift_reader::TestCommand;
+use std::borrow::Cow;
+use subtest::{self, Context, SubTest, SubtestResult};
+
+/// Object implementing the `test binary` sub-test.
+///
+/// This command is used for testing the binary IR format. It encodes a function in the binary
+/// format, decodes it again, and checks that the decoded function prints exactly like the
+/// original, minus the target-specific encodings and value locations.
+///
+/// The decoded function is then verified by filecheck, like `test cat`.
+struct TestBinary;
+
+pub fn subtest(parsed: &TestCommand) -> SubtestResult<Box<SubTest>> {
+    assert_eq!(parsed.command, "binary");
+    if !parsed.options.is_empty() {
+        Err(format!("No options allowed on {}", parsed))
+    } else {
+        Ok(Box::new(TestBinary))
+    }
+}
+
+impl SubTest for TestBinary {
+    fn name(&self) -> &'static str {
+        "binary"
+    }
+
+    fn needs_verifier(&self) -> bool {
+        false
+    }
+
+    fn run(&self, func: Cow<Function>, context: &Context) -> SubtestResult<()> {
+        let mut bytes = Vec::new();
+        func.write_binary(&mut bytes);
+        let decoded = Function::read_binary(&bytes).map_err(|e| e.to_string())?;
+
+        // The binary format doesn't preserve target-specific data.
+        let mut expected = func.into_owned();
+        expected.encodings.clear();
+        expected.locations.clear();
+        expected.offsets.clear();
+        expected.jt_offsets.clear();
+
+        let original = expected.display(context.isa).to_string();
+        let text = decoded.display(context.isa).to_string();
+        if text != original {
+            return Err(format!(
+                "binary round trip changed the function:\n{}\nbecame:\n{}",
+                original, text
+            ));
+        }
+        subtest::run_filecheck(&text, context)
+    }
+}
@@ -2,6 +2,13 @@
 //!
 //! The `binemit` test command generates binary machine code for every instruction in the input
 //! functions and compares the results to the expected output.
+//!
+//! The relocations and trap codes produced by each instruction can be checked separately with
+//! `reloc:` and `trap:` directives, and the contents of each jump table can be checked with a
+//! `rodata:` directive on the jump table declaration. A `rodata:` directive on the function, after
+//! its closing brace, checks the contents of the constant pool. An empty `reloc:` or `trap:`
+//! directive checks that the instruction has no relocations or trap codes. An `offset:` directive
+//! on an EBB header checks the hexadecimal offset of the EBB's code from the start of the function.
 
 use cranelift_codegen::binemit;
 use cranelift_codegen::binemit::{CodeSink, RegDiversions};
@@ -29,10 +36,14 @@ pub fn subtest(parsed: &TestCommand) -> SubtestResult<Box<SubTest>> {
 }
 
 /// Code sink that generates text.
-struct TextSink {
+pub struct TextSink {
     code_size: binemit::CodeOffset,
-    offset: binemit::CodeOffset,
-    text: String,
+    pub offset: binemit::CodeOffset,
+    pub text: String,
+    /// Relocations added since the last `clear()`, formatted for `reloc:` directives.
+    relocs: Vec<String>,
+    /// Trap codes added since the last `clear()`, formatted for `trap:` directives.
+    traps: Vec<String>,
 }
 
 impl TextSink {
@@ -42,8 +53,17 @@ impl TextSink {
             code_size: 0,
             offset: 0,
             text: String::new(),
+            relocs: Vec::new(),
+            traps: Vec::new(),
         }
     }
+
+    /// Forget the text, relocations, and traps generated so far.
+    pub fn clear(&mut self) {
+        self.text.clear();
+        self.relocs.clear();
+        self.traps.clear();
+    }
 }
 
 impl binemit::CodeSink for TextSink {
@@ -73,6 +93,7 @@ impl binemit::CodeSink for TextSink {
 
     fn reloc_ebb(&mut self, reloc: binemit::Reloc, ebb_offset: binemit::CodeOffset) {
         write!(self.text, "{}({}) ", reloc, ebb_offset).unwrap();
+        self.relocs.push(format!("{} {}", reloc, ebb_offset));
     }
 
     fn reloc_external(
@@ -86,14 +107,29 @@ impl binemit::CodeSink for TextSink {
             write!(self.text, "{:+}", addend).unwrap();
         }
         write!(self.text, ") ").unwrap();
+        if addend != 0 {
+            self.relocs.push(format!("{} {} {}", reloc, name, addend));
+        } else {
+            self.relocs.push(format!("{} {}", reloc, name));
+        }
     }
 
     fn reloc_jt(&mut self, reloc: binemit::Reloc, jt: ir::JumpTable) {
         write!(self.text, "{}({}) ", reloc, jt).unwrap();
+        self.relocs.push(format!("{} {}", reloc, jt));
     }
 
-    fn trap(&mut self, code: ir::TrapCode, _srcloc: ir::SourceLoc) {
+    fn trap(&mut self, code: ir::TrapCode, _srcloc: ir::SourceLoc, payload: u32) {
         write!(self.text, "{} ", code).unwrap();
+        if payload != 0 {
+            self.traps.push(format!("{}, {}", code, payload));
+        } else {
+            self.traps.push(code.to_string());
+        }
+    }
+
+    fn patch_point(&mut self, _inst: ir::Inst, size: u8) {
+        write!(self.text, "patch{} ", size).unwrap();
     }
 
     fn begin_rodata(&mut self) {
@@ -121,13 +157,16 @@ impl SubTest for TestBinEmit {
         // value locations. The current error reporting is just crashing...
         let mut func = func.into_owned();
 
-        // Fix the stack frame layout so we can test spill/fill encodings.
-        let min_offset = func
-            .stack_slots
-            .values()
-            .map(|slot| slot.offset.unwrap())
-            .min();
-        func.stack_slots.frame_size = min_offset.map(|off| (-off) as u32);
+        // Fix the stack frame layout so we can test spill/fill encodings, unless the function
+        // declares its frame size.
+        if func.stack_slots.frame_size.is_none() {
+            let min_offset = func
+                .stack_slots
+                .values()
+                .map(|slot| slot.offset.unwrap())
+                .min();
+            func.stack_slots.frame_size = min_offset.map(|off| (-off) as u32);
+        }
 
         let opt_level = isa.flags().opt_level();
 
@@ -137,9 +176,14 @@ impl SubTest for TestBinEmit {
             divert.clear();
             for inst in func.layout.ebb_insts(ebb) {
                 if !func.encodings[inst].is_legal() {
-                    // Find an encoding that satisfies both immediate field and register
-                    // constraints.
-                    if let Some(enc) = {
+                    // Patchable instructions always use their patchable encoding. Otherwise, find
+                    // an encoding that satisfies both immediate field and register constraints.
+                    let patchable_enc = if func.is_patchable(inst) {
+                        isa.patchable_encoding(&func, inst)
+                    } else {
+                        None
+                    };
+                    if let Some(enc) = patchable_enc.or_else(|| {
                         let mut legal_encodings = isa
                             .legal_encodings(&func, &func.dfg[inst], func.dfg.ctrl_typevar(inst))
                             .filter(|e| {
@@ -155,7 +199,7 @@ impl SubTest for TestBinEmit {
                             // If not optimizing, just use the first encoding.
                             legal_encodings.next()
                         }
-                    } {
+                    }) {
                         func.encodings[inst] = enc;
                     }
                 }
@@ -165,11 +209,91 @@ impl SubTest for TestBinEmit {
 
         // Relax branches and compute EBB offsets based on the encodings.
         let code_size = binemit::relax_branches(&mut func, isa)
-            .map_err(|e| pretty_error(&func, context.isa, e))?;
+            .map_err(|e| pretty_error(&func, context.isa, e))?
+            .total_size;
 
         // Collect all of the 'bin:' directives on instructions.
         let mut bins = HashMap::new();
+        // Collect the 'reloc:' and 'trap:' directives, which may be repeated on an instruction.
+        let mut relocs: HashMap<ir::Inst, Vec<&str>> = HashMap::new();
+        let mut traps: HashMap<ir::Inst, Vec<&str>> = HashMap::new();
+        // Collect the 'rodata:' directives on jump tables and the function.
+        let mut rodata = HashMap::new();
+        let mut pool_rodata = None;
+        // Collect the 'offset:' directives on EBBs.
+        let mut ebb_offsets = HashMap::new();
         for comment in &context.details.comments {
+            let repeatable = if let Some(want) = match_directive(comment.text, "reloc:") {
+                Some(("reloc:", want, &mut relocs))
+            } else if let Some(want) = match_directive(comment.text, "trap:") {
+                Some(("trap:", want, &mut traps))
+            } else {
+                None
+            };
+            if let Some((directive, want, wants)) = repeatable {
+                match comment.entity {
+                    AnyEntity::Inst(inst) => {
+                        // An empty directive asserts that there are none.
+                        let wants = wants.entry(inst).or_insert_with(Vec::new);
+                        if !want.is_empty() {
+                            wants.push(want);
+                        }
+                    }
+                    _ => {
+                        return Err(format!(
+                            "'{}' directive on non-inst {}: {}",
+                            directive, comment.entity, comment.text
+                        ))
+                    }
+                }
+            }
+            if let Some(want) = match_directive(comment.text, "rodata:") {
+                match comment.entity {
+                    AnyEntity::JumpTable(jt) => {
+                        if let Some(prev) = rodata.insert(jt, want) {
+                            return Err(format!(
+                                "multiple 'rodata:' directives on {}: '{}' and '{}'",
+                                jt, prev, want
+                            ));
+                        }
+                    }
+                    AnyEntity::Function => {
+                        if let Some(prev) = pool_rodata {
+                            return Err(format!(
+                                "multiple 'rodata:' directives on the function: '{}' and '{}'",
+                                prev, want
+                            ));
+                        }
+                        pool_rodata = Some(want);
+                    }
+                    _ => {
+                        return Err(format!(
+                            "'rodata:' directive on non-jump-table {}: {}",
+                            comment.entity, comment.text
+                        ))
+                    }
+                }
+            }
+            if let Some(want) = match_directive(comment.text, "offset:") {
+                match comment.entity {
+                    AnyEntity::Ebb(ebb) => {
+                        let offset = binemit::CodeOffset::from_str_radix(want, 16)
+                            .map_err(|_| format!("bad 'offset:' directive on {}: {}", ebb, want))?;
+                        if let Some(prev) = ebb_offsets.insert(ebb, offset) {
+                            return Err(format!(
+                                "multiple 'offset:' directives on {}: '{:x}' and '{}'",
+                                ebb, prev, want
+                            ));
+                        }
+                    }
+                    _ => {
+                        return Err(format!(
+                            "'offset:' directive on non-ebb {}: {}",
+                            comment.entity, comment.text
+                        ))
+                    }
+                }
+            }
             if let Some(want) = match_directive(comment.text, "bin:") {
                 match comment.entity {
                     AnyEntity::Inst(inst) => {
@@ -191,7 +315,13 @@ impl SubTest for TestBinEmit {
                 }
             }
         }
-        if bins.is_empty() {
+        if bins.is_empty()
+            && relocs.is_empty()
+            && traps.is_empty()
+            && rodata.is_empty()
+            && pool_rodata.is_none()
+            && ebb_offsets.is_empty()
+        {
             return Err("No 'bin:' directives found".to_string());
         }
 
@@ -205,9 +335,17 @@ impl SubTest for TestBinEmit {
                 "Inconsistent {} header offset",
                 ebb
             );
+            if let Some(want) = ebb_offsets.remove(&ebb) {
+                if sink.offset != want {
+                    return Err(format!(
+                        "Bad offset for {}\nWant: {:x}\nGot:  {:x}",
+                        ebb, want, sink.offset
+                    ));
+                }
+            }
             for (offset, inst, enc_bytes) in func.inst_offsets(ebb, &encinfo) {
                 assert_eq!(sink.offset, offset);
-                sink.text.clear();
+                sink.clear();
                 let enc = func.encodings[inst];
 
                 // Send legal encodings into the emitter.
@@ -226,7 +364,8 @@ impl SubTest for TestBinEmit {
                         ));
                     }
                     let before = sink.offset;
-                    isa.emit_inst(&func, inst, &mut divert, &mut sink);
+                    isa.emit_inst(&func, inst, &mut divert, &mut sink)
+                        .map_err(|e| pretty_error(&func, context.isa, e))?;
                     let emitted = sink.offset - before;
                     // Verify the encoding recipe sizes against the ISAs emit_inst implementation.
                     assert_eq!(
@@ -239,7 +378,10 @@ impl SubTest for TestBinEmit {
                 }
 
                 // Check against bin: directives.
-                if let Some(want) = bins.remove(&inst) {
+                let want_bin = bins.remove(&inst);
+                let want_relocs = relocs.remove(&inst);
+                let want_traps = traps.remove(&inst);
+                if want_bin.is_some() || want_relocs.is_some() || want_traps.is_some() {
                     if !enc.is_legal() {
                         // A possible cause of an unencoded instruction is a missing location for
                         // one of the input operands.
@@ -274,6 +416,8 @@ impl SubTest for TestBinEmit {
                             DisplayList(&encodings),
                         ));
                     }
+                }
+                if let Some(want) = want_bin {
                     let have = sink.text.trim();
                     if have != want {
                         return Err(format!(
@@ -285,17 +429,84 @@ impl SubTest for TestBinEmit {
                         ));
                     }
                 }
+                if let Some(want) = want_relocs {
+                    if sink.relocs != want {
+                        return Err(format!(
+                            "Bad relocations for {}: {}\nWant: {}\nGot:  {}",
+                            inst,
+                            func.dfg.display_inst(inst, isa),
+                            DisplayList(&want),
+                            DisplayList(&sink.relocs)
+                        ));
+                    }
+                }
+                if let Some(want) = want_traps {
+                    if sink.traps != want {
+                        return Err(format!(
+                            "Bad trap codes for {}: {}\nWant: {}\nGot:  {}",
+                            inst,
+                            func.dfg.display_inst(inst, isa),
+                            DisplayList(&want),
+                            DisplayList(&sink.traps)
+                        ));
+                    }
+                }
             }
         }
 
         sink.begin_rodata();
 
         for (jt, jt_data) in func.jump_tables.iter() {
+            assert_eq!(
+                sink.offset, func.jt_offsets[jt],
+                "Inconsistent {} offset",
+                jt
+            );
+            sink.clear();
             let jt_offset = func.jt_offsets[jt];
-            for ebb in jt_data.iter() {
-                let rel_offset: i32 = func.offsets[*ebb] as i32 - jt_offset as i32;
+            for entry in jt_data.as_slice() {
+                // Holes are emitted as zeros.
+                let rel_offset: i32 = entry
+                    .expand()
+                    .map_or(0, |ebb| func.offsets[ebb] as i32 - jt_offset as i32);
                 sink.put4(rel_offset as u32)
             }
+
+            // Check against rodata: directives.
+            if let Some(want) = rodata.remove(&jt) {
+                let have = sink.text.trim();
+                if have != want {
+                    return Err(format!(
+                        "Bad contents for {}: {}\nWant: {}\nGot:  {}",
+                        jt, jt_data, want, have
+                    ));
+                }
+            }
+        }
+
+        // The padding before a constant isn't part of the contents.
+        let mut pool = String::new();
+        for entry in func.constants.iter() {
+            while sink.offset < entry.offset {
+                sink.put1(0);
+            }
+            sink.clear();
+            match entry.size {
+                4 => sink.put4(entry.bits as u32),
+                _ => sink.put8(entry.bits),
+            }
+            pool.push_str(&sink.text);
+        }
+
+        // Check against the rodata: directive on the function.
+        if let Some(want) = pool_rodata {
+            let have = pool.trim();
+            if have != want {
+                return Err(format!(
+                    "Bad constant pool contents\nWant: {}\nGot:  {}",
+                    want, have
+                ));
+            }
         }
 
         if sink.offset != code_size {
@@ -1,13 +1,21 @@
 //! Test command for testing the code generator pipeline
 //!
 //! The `compile` test command runs each function through the full code generator pipeline
+//!
+//! The final IR is sent to `filecheck`. The directives following a `disas:` marker are matched
+//! against the disassembled machine code instead.
 
 use cranelift_codegen;
+use cranelift_codegen::isa::TargetIsa;
 use cranelift_codegen::print_errors::pretty_error;
 use cranelift_codegen::{binemit, ir};
 use cranelift_reader::TestCommand;
+use disas::check_disassembly;
+use stats;
 use std::borrow::Cow;
-use subtest::{run_filecheck, Context, SubTest, SubtestResult};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use subtest::{has_disas_directives, run_filecheck, Context, SubTest, SubtestResult};
 
 struct TestCompile;
 
@@ -35,63 +43,108 @@ impl SubTest for TestCompile {
 
     fn run(&self, func: Cow<ir::Function>, context: &Context) -> SubtestResult<()> {
         let isa = context.isa.expect("compile needs an ISA");
-        let mut comp_ctx = cranelift_codegen::Context::for_function(func.into_owned());
+        let func = func.into_owned();
+        let mut comp_ctx = cranelift_codegen::Context::for_function(func.clone());
 
-        let code_size = comp_ctx
+        let code_info = comp_ctx
             .compile(isa)
             .map_err(|e| pretty_error(&comp_ctx.func, context.isa, e))?;
 
         info!(
             "Generated {} bytes of code:\n{}",
-            code_size,
+            code_info.total_size,
             comp_ctx.func.display(isa)
         );
+        stats::record(isa, &comp_ctx.func.name, &code_info.stats);
 
         // Verify that the returned code size matches the emitted bytes.
-        let mut sink = SizeSink { offset: 0 };
-        binemit::emit_function(
-            &comp_ctx.func,
-            |func, inst, div, sink| isa.emit_inst(func, inst, div, sink),
-            &mut sink,
-        );
-
-        if sink.offset != code_size {
+        let code = emit(&comp_ctx.func, isa)?;
+        if code.len() != code_info.total_size as usize {
             return Err(format!(
                 "Expected code size {}, got {}",
-                code_size, sink.offset
+                code_info.total_size,
+                code.len()
+            ));
+        }
+
+        // The generated code must only depend on the input function and the settings, not on the
+        // iteration order of hash maps. Compile the function again in a fresh context with a
+        // random hash seed. This only repeats the compilation in the same process, so it doesn't
+        // catch differences between hosts.
+        let seed = random_seed();
+        cranelift_codegen::set_hash_seed(seed);
+        let mut again_ctx = cranelift_codegen::Context::for_function(func);
+        let again = again_ctx
+            .compile(isa)
+            .map_err(|e| pretty_error(&again_ctx.func, context.isa, e))
+            .and_then(|_| emit(&again_ctx.func, isa));
+        let again_text = again_ctx.func.display(isa).to_string();
+        drop(again_ctx);
+        cranelift_codegen::set_hash_seed(0);
+        if again? != code {
+            return Err(format!(
+                "Compiling the function with hash seed {:#x} produced different code:\n{}\nand:\n{}",
+                seed,
+                comp_ctx.func.display(isa),
+                again_text
             ));
         }
 
         // Run final code through filecheck.
         let text = comp_ctx.func.display(Some(isa)).to_string();
-        run_filecheck(&text, context)
+        run_filecheck(&text, context)?;
+
+        if has_disas_directives(context) {
+            check_disassembly(&comp_ctx, &code_info, isa, context)?;
+        }
+        Ok(())
     }
 }
 
-/// Code sink that simply counts bytes.
-struct SizeSink {
-    offset: binemit::CodeOffset,
+/// Get a hash seed that differs between calls.
+fn random_seed() -> usize {
+    RandomState::new().build_hasher().finish() as usize
+}
+
+/// Emit the machine code for the compiled function `func`.
+fn emit(func: &ir::Function, isa: &TargetIsa) -> SubtestResult<Vec<u8>> {
+    let mut sink = BytesSink { bytes: Vec::new() };
+    binemit::emit_function(
+        func,
+        |func, inst, div, sink| isa.emit_inst(func, inst, div, sink),
+        &mut sink,
+        isa.endianness(),
+    ).map_err(|e| pretty_error(func, Some(isa), e))?;
+    Ok(sink.bytes)
+}
+
+/// Code sink that collects the emitted bytes.
+struct BytesSink {
+    bytes: Vec<u8>,
 }
 
-impl binemit::CodeSink for SizeSink {
+impl binemit::CodeSink for BytesSink {
     fn offset(&self) -> binemit::CodeOffset {
-        self.offset
+        self.bytes.len() as binemit::CodeOffset
     }
 
-    fn put1(&mut self, _: u8) {
-        self.offset += 1;
+    fn put1(&mut self, x: u8) {
+        self.bytes.push(x);
     }
 
-    fn put2(&mut self, _: u16) {
-        self.offset += 2;
+    fn put2(&mut self, x: u16) {
+        self.put1(x as u8);
+        self.put1((x >> 8) as u8);
     }
 
-    fn put4(&mut self, _: u32) {
-        self.offset += 4;
+    fn put4(&mut self, x: u32) {
+        self.put2(x as u16);
+        self.put2((x >> 16) as u16);
     }
 
-    fn put8(&mut self, _: u64) {
-        self.offset += 8;
+    fn put8(&mut self, x: u64) {
+        self.put4(x as u32);
+        self.put4((x >> 32) as u32);
     }
 
     fn reloc_ebb(&mut self, _reloc: binemit::Reloc, _ebb_offset: binemit::CodeOffset) {}
@@ -103,6 +156,7 @@ impl binemit::CodeSink for SizeSink {
     ) {
     }
     fn reloc_jt(&mut self, _reloc: binemit::Reloc, _jt: ir::JumpTable) {}
-    fn trap(&mut self, _code: ir::TrapCode, _srcloc: ir::SourceLoc) {}
+    fn trap(&mut self, _code: ir::TrapCode, _srcloc: ir::SourceLoc, _payload: u32) {}
+    fn patch_point(&mut self, _inst: ir::Inst, _size: u8) {}
     fn begin_rodata(&mut self) {}
 }
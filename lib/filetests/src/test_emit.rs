@@ -0,0 +1,164 @@
+//! Test command for testing the binary machine code emission of compiled functions.
+//!
+//! The `emit` test command takes functions that are fully annotated with encodings and value
+//! locations, like the output of `test compile`, and runs only the emission stage on them.
+//!
+//! The encodings are used as is, even if the ISA would choose different ones, so a function
+//! extracted from a failing compilation reproduces the failure. The value locations are still
+//! verified against the operand constraints of the encodings.
+//!
+//! The emitted machine code of each instruction is listed after it, with its offset, and the
+//! listing is sent to filecheck.
+
+use cranelift_codegen::binemit::{self, CodeSink, RegDiversions};
+use cranelift_codegen::ir;
+use cranelift_codegen::print_errors::{pretty_error, pretty_verifier_error};
+use cranelift_codegen::verifier::{verify_function, verify_locations, VerifierErrors};
+use cranelift_reader::TestCommand;
+use std::borrow::Cow;
+use std::fmt::Write;
+use subtest::{run_filecheck, Context, SubTest, SubtestResult};
+use test_binemit::TextSink;
+
+struct TestEmit;
+
+pub fn subtest(parsed: &TestCommand) -> SubtestResult<Box<SubTest>> {
+    assert_eq!(parsed.command, "emit");
+    if !parsed.options.is_empty() {
+        Err(format!("No options allowed on {}", parsed))
+    } else {
+        Ok(Box::new(TestEmit))
+    }
+}
+
+impl SubTest for TestEmit {
+    fn name(&self) -> &'static str {
+        "emit"
+    }
+
+    fn is_mutating(&self) -> bool {
+        true
+    }
+
+    fn needs_isa(&self) -> bool {
+        true
+    }
+
+    // The verifier with an ISA rejects encodings that the ISA wouldn't choose, so the function is
+    // verified without it instead.
+    fn needs_verifier(&self) -> bool {
+        false
+    }
+
+    fn run(&self, func: Cow<ir::Function>, context: &Context) -> SubtestResult<()> {
+        let isa = context.isa.expect("emit needs an ISA");
+        let encinfo = isa.encoding_info();
+        let mut func = func.into_owned();
+
+        verify_function(&func, context.flags)
+            .map_err(|errors| pretty_verifier_error(&func, None, None, errors))?;
+
+        for ebb in func.layout.ebbs() {
+            for inst in func.layout.ebb_insts(ebb) {
+                // A `fallthrough` has no encoding since it emits no code.
+                if !func.encodings[inst].is_legal()
+                    && func.dfg[inst].opcode() != ir::Opcode::Fallthrough
+                {
+                    return Err(format!(
+                        "Missing encoding for {}",
+                        func.dfg.display_inst(inst, isa)
+                    ));
+                }
+                if let Some(&v) = func
+                    .dfg
+                    .inst_results(inst)
+                    .iter()
+                    .find(|&&v| !func.locations[v].is_assigned())
+                {
+                    return Err(format!(
+                        "Missing register/stack slot for {} in {}",
+                        v,
+                        func.dfg.display_inst(inst, isa)
+                    ));
+                }
+            }
+        }
+        if func.stack_slots.frame_size.is_none() && func.stack_slots.keys().next().is_some() {
+            return Err("Missing frame_size for the stack slots".to_string());
+        }
+
+        let mut errors = VerifierErrors::default();
+        let _ = verify_locations(isa, &func, None, &mut errors);
+        if !errors.is_empty() {
+            return Err(pretty_verifier_error(&func, Some(isa), None, errors));
+        }
+
+        let code_size = binemit::relax_branches(&mut func, isa)
+            .map_err(|e| pretty_error(&func, context.isa, e))?
+            .total_size;
+
+        let mut listing = String::new();
+        let mut sink = TextSink::new();
+        let mut divert = RegDiversions::new();
+        for ebb in func.layout.ebbs() {
+            divert.clear();
+            writeln!(listing, "{}:", ebb).unwrap();
+            for (offset, inst, enc_bytes) in func.inst_offsets(ebb, &encinfo) {
+                sink.clear();
+                isa.emit_inst(&func, inst, &mut divert, &mut sink)
+                    .map_err(|e| pretty_error(&func, context.isa, e))?;
+                if sink.offset - offset != enc_bytes {
+                    return Err(format!(
+                        "Inconsistent size for [{}] {}: {} bytes, but {} were emitted",
+                        encinfo.display(func.encodings[inst]),
+                        func.dfg.display_inst(inst, isa),
+                        enc_bytes,
+                        sink.offset - offset
+                    ));
+                }
+                let text = func.dfg.display_inst(inst, isa).to_string();
+                write!(listing, "    {:04x} {}", offset, text.trim_end()).unwrap();
+                let bin = sink.text.trim();
+                if !bin.is_empty() {
+                    write!(listing, " ; bin: {}", bin).unwrap();
+                }
+                writeln!(listing).unwrap();
+            }
+        }
+
+        sink.begin_rodata();
+        for (jt, jt_data) in func.jump_tables.iter() {
+            sink.clear();
+            let jt_offset = func.jt_offsets[jt];
+            for entry in jt_data.as_slice() {
+                // Holes are emitted as zeros.
+                let rel_offset: i32 = entry
+                    .expand()
+                    .map_or(0, |ebb| func.offsets[ebb] as i32 - jt_offset as i32);
+                sink.put4(rel_offset as u32)
+            }
+            writeln!(listing, "{:04x} {}: {}", jt_offset, jt, sink.text.trim()).unwrap();
+        }
+        for entry in func.constants.iter() {
+            while sink.offset < entry.offset {
+                sink.put1(0);
+            }
+            sink.clear();
+            match entry.size {
+                4 => sink.put4(entry.bits as u32),
+                _ => sink.put8(entry.bits),
+            }
+            let bin = sink.text.trim();
+            writeln!(listing, "{:04x} constant: {}", entry.offset, bin).unwrap();
+        }
+
+        if sink.offset != code_size {
+            return Err(format!(
+                "Expected code size {}, got {}",
+                code_size, sink.offset
+            ));
+        }
+
+        run_filecheck(&listing, context)
+    }
+}
@@ -0,0 +1,202 @@
+//! Test command for running a sequence of optimization passes.
+//!
+//! The `optimize` test command runs each function through the passes listed in the command, in
+//! order. For example, `test optimize licm,simple_gvn,dce` runs LICM, then GVN, then dead code
+//! elimination. The analyses needed by each pass are recomputed before running it.
+//!
+//! The resulting function is sent to `filecheck`.
+
+use cranelift_codegen;
+use cranelift_codegen::ir::Function;
+use cranelift_codegen::print_errors::pretty_error;
+use cranelift_codegen::CodegenResult;
+use cranelift_reader::{TestCommand, TestOption};
+use std::borrow::Cow;
+use subtest::{run_filecheck, Context, SubTest, SubtestResult};
+
+/// A pass that can be named in a `test optimize` command.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Pass {
+    Preopt,
+    CanonicalizeNans,
+    Legalize,
+    Postopt,
+    Licm,
+    Sink,
+    Schedule,
+    SimpleGvn,
+    Dce,
+    Dse,
+    Bce,
+    UnreachableCode,
+    HoistGlobalValueLoads,
+    MergeReturns,
+    Compact,
+}
+
+/// All the passes, in the order they are listed in error messages.
+const PASSES: [Pass; 15] = [
+    Pass::Preopt,
+    Pass::CanonicalizeNans,
+    Pass::Legalize,
+    Pass::Postopt,
+    Pass::Licm,
+    Pass::Sink,
+    Pass::Schedule,
+    Pass::SimpleGvn,
+    Pass::Dce,
+    Pass::Dse,
+    Pass::Bce,
+    Pass::UnreachableCode,
+    Pass::HoistGlobalValueLoads,
+    Pass::MergeReturns,
+    Pass::Compact,
+];
+
+impl Pass {
+    fn name(self) -> &'static str {
+        match self {
+            Pass::Preopt => "preopt",
+            Pass::CanonicalizeNans => "canonicalize_nans",
+            Pass::Legalize => "legalize",
+            Pass::Postopt => "postopt",
+            Pass::Licm => "licm",
+            Pass::Sink => "sink",
+            Pass::Schedule => "schedule",
+            Pass::SimpleGvn => "simple_gvn",
+            Pass::Dce => "dce",
+            Pass::Dse => "dse",
+            Pass::Bce => "bce",
+            Pass::UnreachableCode => "unreachable_code",
+            Pass::HoistGlobalValueLoads => "hoist_global_value_loads",
+            Pass::MergeReturns => "merge_returns",
+            Pass::Compact => "compact",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        PASSES.iter().cloned().find(|pass| pass.name() == name)
+    }
+
+    /// Does this pass need a target ISA?
+    fn needs_isa(self) -> bool {
+        match self {
+            Pass::Preopt
+            | Pass::CanonicalizeNans
+            | Pass::Legalize
+            | Pass::Postopt
+            | Pass::Schedule => true,
+            Pass::Licm
+            | Pass::Sink
+            | Pass::SimpleGvn
+            | Pass::Dce
+            | Pass::Dse
+            | Pass::Bce
+            | Pass::UnreachableCode
+            | Pass::HoistGlobalValueLoads
+            | Pass::MergeReturns
+            | Pass::Compact => false,
+        }
+    }
+
+    /// Run this pass on the function in `comp_ctx`.
+    ///
+    /// The pass verifies the function afterwards if the verifier is enabled.
+    fn run(
+        self,
+        comp_ctx: &mut cranelift_codegen::Context,
+        context: &Context,
+    ) -> CodegenResult<()> {
+        comp_ctx.flowgraph();
+        match self {
+            Pass::Preopt => comp_ctx.preopt(context.isa.unwrap()),
+            Pass::CanonicalizeNans => comp_ctx.canonicalize_nans(context.isa.unwrap()),
+            Pass::Legalize => comp_ctx.legalize(context.isa.unwrap()),
+            Pass::Postopt => comp_ctx.postopt(context.isa.unwrap()),
+            Pass::Licm => {
+                comp_ctx.compute_loop_analysis();
+                comp_ctx.licm(context.flags_or_isa())
+            }
+            Pass::Sink => {
+                comp_ctx.compute_loop_analysis();
+                comp_ctx.sink(context.flags_or_isa())
+            }
+            Pass::Schedule => comp_ctx.schedule(context.isa.unwrap()),
+            Pass::SimpleGvn => comp_ctx.simple_gvn(context.flags_or_isa()),
+            Pass::Dce => comp_ctx.dce(context.flags_or_isa()),
+            Pass::Dse => comp_ctx.dse(context.flags_or_isa()),
+            Pass::Bce => comp_ctx.bce(context.flags_or_isa()),
+            Pass::UnreachableCode => comp_ctx.eliminate_unreachable_code(context.flags_or_isa()),
+            Pass::HoistGlobalValueLoads => {
+                comp_ctx.hoist_global_value_loads(context.flags_or_isa())
+            }
+            Pass::MergeReturns => comp_ctx.merge_returns(context.flags_or_isa()),
+            Pass::Compact => comp_ctx.compact(context.flags_or_isa()),
+        }
+    }
+}
+
+struct TestOptimize {
+    passes: Vec<Pass>,
+}
+
+pub fn subtest(parsed: &TestCommand) -> SubtestResult<Box<SubTest>> {
+    assert_eq!(parsed.command, "optimize");
+    let mut passes = Vec::new();
+    for option in &parsed.options {
+        let list = match *option {
+            TestOption::Flag(list) => list,
+            TestOption::Value(..) => {
+                return Err(format!("Unsupported option {} on {}", option, parsed))
+            }
+        };
+        for name in list.split(',').filter(|name| !name.is_empty()) {
+            match Pass::from_name(name) {
+                Some(pass) => passes.push(pass),
+                None => {
+                    let valid: Vec<_> = PASSES.iter().map(|pass| pass.name()).collect();
+                    return Err(format!(
+                        "Unknown pass '{}', valid passes are: {}",
+                        name,
+                        valid.join(", ")
+                    ));
+                }
+            }
+        }
+    }
+    if passes.is_empty() {
+        return Err(format!("No passes given on {}", parsed));
+    }
+    Ok(Box::new(TestOptimize { passes }))
+}
+
+impl SubTest for TestOptimize {
+    fn name(&self) -> &'static str {
+        "optimize"
+    }
+
+    fn is_mutating(&self) -> bool {
+        true
+    }
+
+    fn needs_isa(&self) -> bool {
+        self.passes.iter().any(|pass| pass.needs_isa())
+    }
+
+    fn run(&self, func: Cow<Function>, context: &Context) -> SubtestResult<()> {
+        let mut comp_ctx = cranelift_codegen::Context::for_function(func.into_owned());
+
+        for &pass in &self.passes {
+            pass.run(&mut comp_ctx, context).map_err(|e| {
+                format!(
+                    "after {}:\n{}",
+                    pass.name(),
+                    pretty_error(&comp_ctx.func, context.isa, e)
+                )
+            })?;
+        }
+
+        let text = comp_ctx.func.display(context.isa).to_string();
+        run_filecheck(&text, context)
+    }
+}
@@ -0,0 +1,572 @@
+//! Test command for running compiled functions.
+//!
+//! The `run` test command compiles each function for the host ISA, calls it with the arguments
+//! given in `run:` directives, and checks the results. The ISA-specific settings of the test file
+//! are used when the host supports all the features they enable.
+//!
+//! When the functions can't be run on the host, for example because the test file targets a
+//! different architecture, they are executed by the IR interpreter instead.
+
+use cranelift_codegen;
+use cranelift_codegen::binemit::{CollectedRelocs, NullTrapSink};
+use cranelift_codegen::ir::immediates::{Ieee32, Ieee64, Imm64};
+use cranelift_codegen::ir::{self, types, ArgumentPurpose, ExternalName, Type};
+use cranelift_codegen::isa::TargetIsa;
+use cranelift_codegen::print_errors::pretty_error;
+use cranelift_codegen::settings::{self, CallConv};
+use cranelift_frontend::make_trampoline;
+use cranelift_interpreter::{self, Interpreter};
+use cranelift_native;
+use cranelift_reader::TestCommand;
+use match_directive::match_directive;
+use std::borrow::Cow;
+use std::fmt;
+use std::str::FromStr;
+use subtest::{Context, SubTest, SubtestResult};
+
+/// Object implementing the `test run` sub-test.
+///
+/// Functions without any `run:` directives are ignored.
+struct TestRun;
+
+pub fn subtest(parsed: &TestCommand) -> SubtestResult<Box<SubTest>> {
+    assert_eq!(parsed.command, "run");
+    if !parsed.options.is_empty() {
+        Err(format!("No options allowed on {}", parsed))
+    } else {
+        Ok(Box::new(TestRun))
+    }
+}
+
+impl SubTest for TestRun {
+    fn name(&self) -> &'static str {
+        "run"
+    }
+
+    fn run(&self, func: Cow<ir::Function>, context: &Context) -> SubtestResult<()> {
+        let mut invocations = Vec::new();
+        for comment in &context.details.comments {
+            if let Some(text) = match_directive(comment.text, "run:") {
+                invocations.push(Invocation::parse(text, &func)?);
+            }
+        }
+        if invocations.is_empty() {
+            return Ok(());
+        }
+
+        let (host, call_conv) = match host_isa(context)? {
+            Some(host) => host,
+            None => {
+                info!("Interpreting {}: cannot run on this host", func.name);
+                let mut interpreter = Interpreter::new();
+                interpreter.add_function(&func);
+                interpreter.set_step_limit(Some(INTERPRETER_STEP_LIMIT));
+                for invocation in &invocations {
+                    check(invocation, interpret(&mut interpreter, &func, invocation)?)?;
+                }
+                return Ok(());
+            }
+        };
+
+        // Compiling with the test file's ISA lets tests check the code generated without some of
+        // the host's features.
+        let isa = match context.isa {
+            Some(file_isa) if host_supports(&*host, file_isa) => file_isa,
+            _ => &*host,
+        };
+
+        let trampoline = make_trampoline(
+            ExternalName::testcase("trampoline"),
+            &func.signature,
+            call_conv,
+            isa.pointer_type(),
+        );
+        let mut code = Vec::new();
+        compile_into(func.into_owned(), isa, &mut code)?;
+        let trampoline_offset = compile_into(trampoline, isa, &mut code)? as usize;
+
+        for invocation in &invocations {
+            check(invocation, execute(&code, trampoline_offset, invocation)?)?;
+        }
+        Ok(())
+    }
+}
+
+/// Check the outcome of an invocation against the expected outcome.
+fn check(invocation: &Invocation, outcome: Outcome) -> SubtestResult<()> {
+    if outcome != invocation.expected {
+        return Err(format!(
+            "run: {}: expected {}, got {}",
+            invocation, invocation.expected, outcome
+        ));
+    }
+    Ok(())
+}
+
+/// The maximum number of instructions the interpreter executes for an invocation, so functions
+/// that don't terminate fail instead of hanging the test runner.
+const INTERPRETER_STEP_LIMIT: u64 = 10_000_000;
+
+/// Call `func` with the IR interpreter.
+fn interpret(
+    interpreter: &mut Interpreter,
+    func: &ir::Function,
+    invocation: &Invocation,
+) -> SubtestResult<Outcome> {
+    let args: Vec<_> = invocation
+        .args
+        .iter()
+        .zip(&invocation.params)
+        .map(|(arg, &ty)| arg.to_interpreter(ty))
+        .collect();
+    let outcome = interpreter
+        .call(func, &args)
+        .map_err(|e| format!("run: {}: {}", invocation, e))?;
+    Ok(match outcome {
+        cranelift_interpreter::Outcome::Trap(_) => Outcome::Trapped,
+        cranelift_interpreter::Outcome::Return(_) if !invocation.check_values => {
+            Outcome::Returned(Vec::new())
+        }
+        cranelift_interpreter::Outcome::Return(values) => Outcome::Returned(
+            values
+                .into_iter()
+                .map(DataValue::from_interpreter)
+                .collect(),
+        ),
+    })
+}
+
+/// Get an ISA for the host, with the shared flags from the test file.
+///
+/// Also returns the calling convention of the host's C ABI, which is used by the trampoline.
+///
+/// Returns `None` if the functions in this test can't be run on the host, because the host isn't
+/// supported or because the test file is targeting a different architecture. The host ISA uses
+/// the shared flags from the test file, and enables all the features the host has.
+fn host_isa(context: &Context) -> SubtestResult<Option<(Box<TargetIsa>, CallConv)>> {
+    if !cfg!(unix) {
+        return Ok(None);
+    }
+    let (flag_builder, isa_builder) = match cranelift_native::builders() {
+        Ok(builders) => builders,
+        Err(_) => return Ok(None),
+    };
+    let isa = isa_builder.finish(context.flags.clone());
+    if let Some(file_isa) = context.isa {
+        if file_isa.triple().architecture != isa.triple().architecture {
+            return Ok(None);
+        }
+    }
+    let call_conv = settings::Flags::new(flag_builder).call_conv();
+    Ok(Some((isa, call_conv)))
+}
+
+/// Does the `host` ISA have all the features that are enabled in `isa`?
+///
+/// The ISA-specific boolean settings are compared by their textual form.
+fn host_supports(host: &TargetIsa, isa: &TargetIsa) -> bool {
+    let host_settings = enabled_isa_settings(host);
+    enabled_isa_settings(isa)
+        .iter()
+        .all(|setting| host_settings.contains(setting))
+}
+
+/// Get the ISA-specific boolean settings that are enabled in `isa`, like `has_sse41 = true`.
+fn enabled_isa_settings(isa: &TargetIsa) -> Vec<String> {
+    let header = format!("[{}]", isa.name());
+    isa.to_string()
+        .lines()
+        .skip_while(|&line| line != header)
+        .filter(|line| line.ends_with(" = true"))
+        .map(|line| line.to_string())
+        .collect()
+}
+
+/// Compile `func` and append its machine code to `code`, aligned to 16 bytes.
+///
+/// Returns the offset of the function in `code`.
+fn compile_into(func: ir::Function, isa: &TargetIsa, code: &mut Vec<u8>) -> SubtestResult<u32> {
+    let mut ctx = cranelift_codegen::Context::for_function(func);
+    while code.len() % 16 != 0 {
+        code.push(0);
+    }
+    let offset = code.len() as u32;
+    // The code is executed where it was emitted, so there's no way to resolve relocations.
+    let mut relocs = CollectedRelocs::new();
+    ctx.compile_and_emit(isa, code, &mut relocs, &mut NullTrapSink {})
+        .map_err(|e| pretty_error(&ctx.func, Some(isa), e))?;
+    if !relocs.relocs.is_empty() {
+        return Err("functions with relocations can't be run".to_string());
+    }
+    Ok(offset)
+}
+
+/// A scalar value passed to or returned from a function.
+#[derive(Clone, Copy, Debug)]
+enum DataValue {
+    Bool(bool),
+    /// An integer of any width, sign-extended to 64 bits.
+    Int(i64),
+    F32(Ieee32),
+    F64(Ieee64),
+}
+
+impl DataValue {
+    /// Parse a value of type `ty`.
+    fn parse(text: &str, ty: Type) -> SubtestResult<Self> {
+        let bad = || format!("invalid {} value: {}", ty, text);
+        Ok(match ty {
+            types::B1 => match text {
+                "true" => DataValue::Bool(true),
+                "false" => DataValue::Bool(false),
+                _ => return Err(bad()),
+            },
+            types::I8 | types::I16 | types::I32 | types::I64 => {
+                let imm = Imm64::from_str(text).map_err(|_| bad())?;
+                DataValue::Int(imm.into()).truncate(ty)
+            }
+            types::F32 => DataValue::F32(
+                Ieee32::from_str(text)
+                    .or_else(|_| f32::from_str(text).map(Ieee32::with_float))
+                    .map_err(|_| bad())?,
+            ),
+            types::F64 => DataValue::F64(
+                Ieee64::from_str(text)
+                    .or_else(|_| f64::from_str(text).map(Ieee64::with_float))
+                    .map_err(|_| bad())?,
+            ),
+            _ => return Err(format!("type {} is not supported by test run", ty)),
+        })
+    }
+
+    /// Truncate an integer to the width of `ty` and sign-extend it again.
+    fn truncate(self, ty: Type) -> Self {
+        match self {
+            DataValue::Int(x) => {
+                let shift = 64 - ty.bits();
+                DataValue::Int((x << shift) >> shift)
+            }
+            v => v,
+        }
+    }
+
+    /// Convert this value of type `ty` to the interpreter's representation.
+    fn to_interpreter(self, ty: Type) -> cranelift_interpreter::DataValue {
+        use cranelift_interpreter::DataValue as V;
+        match self {
+            DataValue::Bool(b) => V::B(b),
+            DataValue::Int(x) => V::from_i64(ty, x).expect("unsupported integer type"),
+            DataValue::F32(f) => V::F32(f),
+            DataValue::F64(f) => V::F64(f),
+        }
+    }
+
+    /// Convert a value returned by the interpreter.
+    fn from_interpreter(value: cranelift_interpreter::DataValue) -> Self {
+        use cranelift_interpreter::DataValue as V;
+        match value {
+            V::B(b) => DataValue::Bool(b),
+            V::F32(f) => DataValue::F32(f),
+            V::F64(f) => DataValue::F64(f),
+            v => DataValue::Int(v.to_i64()),
+        }
+    }
+
+    /// Write this value into an 8-byte slot.
+    unsafe fn write(self, slot: *mut u64, ty: Type) {
+        match self {
+            DataValue::Bool(b) => *(slot as *mut u8) = b as u8,
+            DataValue::Int(x) => match ty.bytes() {
+                1 => *(slot as *mut i8) = x as i8,
+                2 => *(slot as *mut i16) = x as i16,
+                4 => *(slot as *mut i32) = x as i32,
+                _ => *(slot as *mut i64) = x,
+            },
+            DataValue::F32(f) => *(slot as *mut u32) = f.bits(),
+            DataValue::F64(f) => *slot = f.bits(),
+        }
+    }
+
+    /// Read a value of type `ty` from an 8-byte slot.
+    unsafe fn read(slot: *const u64, ty: Type) -> Self {
+        match ty {
+            types::B1 => DataValue::Bool(*(slot as *const u8) != 0),
+            types::I8 => DataValue::Int(i64::from(*(slot as *const i8))),
+            types::I16 => DataValue::Int(i64::from(*(slot as *const i16))),
+            types::I32 => DataValue::Int(i64::from(*(slot as *const i32))),
+            types::F32 => DataValue::F32(Ieee32::with_bits(*(slot as *const u32))),
+            types::F64 => DataValue::F64(Ieee64::with_bits(*slot)),
+            _ => DataValue::Int(*(slot as *const i64)),
+        }
+    }
+}
+
+impl PartialEq for DataValue {
+    fn eq(&self, other: &Self) -> bool {
+        match (*self, *other) {
+            (DataValue::Bool(a), DataValue::Bool(b)) => a == b,
+            (DataValue::Int(a), DataValue::Int(b)) => a == b,
+            // Floats are compared bitwise, except that all NaNs are considered equal.
+            (DataValue::F32(a), DataValue::F32(b)) => {
+                a.bits() == b.bits()
+                    || (f32::from_bits(a.bits()).is_nan() && f32::from_bits(b.bits()).is_nan())
+            }
+            (DataValue::F64(a), DataValue::F64(b)) => {
+                a.bits() == b.bits()
+                    || (f64::from_bits(a.bits()).is_nan() && f64::from_bits(b.bits()).is_nan())
+            }
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for DataValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DataValue::Bool(b) => write!(f, "{}", b),
+            DataValue::Int(x) => write!(f, "{}", x),
+            DataValue::F32(x) => write!(f, "{}", x),
+            DataValue::F64(x) => write!(f, "{}", x),
+        }
+    }
+}
+
+/// The outcome of calling a function.
+#[derive(Clone, Debug, PartialEq)]
+enum Outcome {
+    /// The function returned these values.
+    Returned(Vec<DataValue>),
+    /// The function trapped.
+    Trapped,
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Outcome::Returned(ref values) if values.is_empty() => write!(f, "no values"),
+            Outcome::Returned(ref values) => {
+                for (i, v) in values.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", v)?;
+                }
+                Ok(())
+            }
+            Outcome::Trapped => write!(f, "a trap"),
+        }
+    }
+}
+
+/// A function call described by a `run:` directive.
+///
+/// The directive has one of these forms:
+///
+/// - `run: %name(args)` checks that the function returns without trapping.
+/// - `run: %name(args) == values` checks that the function returns `values`.
+/// - `run: %name(args) traps` checks that the function traps.
+struct Invocation {
+    name: String,
+    params: Vec<Type>,
+    returns: Vec<Type>,
+    args: Vec<DataValue>,
+    expected: Outcome,
+    /// Was a result given? If not, any returned values are accepted.
+    check_values: bool,
+}
+
+impl Invocation {
+    fn parse(text: &str, func: &ir::Function) -> SubtestResult<Self> {
+        let bad = |msg: &str| format!("run: {}: {}", text, msg);
+
+        for param in func.signature.params.iter().chain(&func.signature.returns) {
+            if param.purpose != ArgumentPurpose::Normal {
+                return Err(bad("special-purpose parameters are not supported"));
+            }
+        }
+        let params: Vec<Type> = func.signature.params.iter().map(|p| p.value_type).collect();
+        let returns: Vec<Type> = func
+            .signature
+            .returns
+            .iter()
+            .map(|p| p.value_type)
+            .collect();
+
+        let open = text.find('(').ok_or_else(|| bad("expected '('"))?;
+        let close = text.find(')').ok_or_else(|| bad("expected ')'"))?;
+        let name = text[..open].trim();
+        if name != func.name.to_string() {
+            return Err(bad(&format!("expected a call to {}", func.name)));
+        }
+
+        let args_text = text[open + 1..close].trim();
+        let args_text: Vec<&str> = if args_text.is_empty() {
+            Vec::new()
+        } else {
+            args_text.split(',').map(str::trim).collect()
+        };
+        if args_text.len() != params.len() {
+            return Err(bad(&format!("expected {} arguments", params.len())));
+        }
+        let args = args_text
+            .iter()
+            .zip(&params)
+            .map(|(arg, &ty)| DataValue::parse(arg, ty))
+            .collect::<SubtestResult<Vec<_>>>()
+            .map_err(|e| bad(&e))?;
+
+        let rest = text[close + 1..].trim();
+        let (expected, check_values) = if rest.is_empty() {
+            (Outcome::Returned(Vec::new()), false)
+        } else if rest == "traps" {
+            (Outcome::Trapped, true)
+        } else if rest.starts_with("==") {
+            let values: Vec<&str> = rest[2..].split(',').map(str::trim).collect();
+            if values.len() != returns.len() {
+                return Err(bad(&format!("expected {} results", returns.len())));
+            }
+            let values = values
+                .iter()
+                .zip(&returns)
+                .map(|(v, &ty)| DataValue::parse(v, ty))
+                .collect::<SubtestResult<Vec<_>>>()
+                .map_err(|e| bad(&e))?;
+            (Outcome::Returned(values), true)
+        } else {
+            return Err(bad("expected '== values' or 'traps' after the call"));
+        };
+
+        Ok(Self {
+            name: name.to_string(),
+            params,
+            returns,
+            args,
+            expected,
+            check_values,
+        })
+    }
+}
+
+impl fmt::Display for Invocation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}(", self.name)?;
+        for (i, arg) in self.args.iter().enumerate() {
+            if i != 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", arg)?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// Call the function at the start of `code` through the trampoline at `trampoline_offset`.
+///
+/// The call happens in a child process so traps can be detected without installing signal
+/// handlers in the test runner.
+#[cfg(unix)]
+fn execute(
+    code: &[u8],
+    trampoline_offset: usize,
+    invocation: &Invocation,
+) -> SubtestResult<Outcome> {
+    use libc;
+    use std::ptr;
+
+    let slots = invocation.params.len().max(invocation.returns.len()).max(1);
+    unsafe {
+        let page_size = libc::sysconf(libc::_SC_PAGESIZE) as usize;
+        let code_size = (code.len() + page_size - 1) / page_size * page_size;
+        let code_mem = libc::mmap(
+            ptr::null_mut(),
+            code_size,
+            libc::PROT_READ | libc::PROT_WRITE,
+            libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+            -1,
+            0,
+        );
+        // The values are shared so the child process can pass the results back.
+        let values_size = slots * 8;
+        let values_mem = libc::mmap(
+            ptr::null_mut(),
+            values_size,
+            libc::PROT_READ | libc::PROT_WRITE,
+            libc::MAP_SHARED | libc::MAP_ANONYMOUS,
+            -1,
+            0,
+        );
+        if code_mem == libc::MAP_FAILED || values_mem == libc::MAP_FAILED {
+            return Err("unable to allocate memory".to_string());
+        }
+        ptr::copy_nonoverlapping(code.as_ptr(), code_mem as *mut u8, code.len());
+        let protect = libc::mprotect(code_mem, code_size, libc::PROT_READ | libc::PROT_EXEC);
+        let outcome = if protect != 0 {
+            Err("unable to make memory executable".to_string())
+        } else {
+            call_in_child(
+                code_mem as *const u8,
+                trampoline_offset,
+                values_mem as *mut u64,
+                invocation,
+            )
+        };
+        libc::munmap(code_mem, code_size);
+        libc::munmap(values_mem, values_size);
+        outcome
+    }
+}
+
+#[cfg(unix)]
+unsafe fn call_in_child(
+    code: *const u8,
+    trampoline_offset: usize,
+    values: *mut u64,
+    invocation: &Invocation,
+) -> SubtestResult<Outcome> {
+    use libc;
+    use std::mem;
+
+    for (i, (arg, &ty)) in invocation.args.iter().zip(&invocation.params).enumerate() {
+        arg.write(values.offset(i as isize), ty);
+    }
+
+    let trampoline: extern "C" fn(*const u8, *mut u64) =
+        mem::transmute(code.offset(trampoline_offset as isize));
+    match libc::fork() {
+        -1 => return Err("unable to fork".to_string()),
+        0 => {
+            trampoline(code, values);
+            libc::_exit(0);
+        }
+        pid => {
+            let mut status = 0;
+            if libc::waitpid(pid, &mut status, 0) != pid {
+                return Err("unable to wait for the child process".to_string());
+            }
+            if libc::WIFSIGNALED(status) {
+                return Ok(Outcome::Trapped);
+            }
+            if !libc::WIFEXITED(status) || libc::WEXITSTATUS(status) != 0 {
+                return Err(format!("child process failed with status {}", status));
+            }
+        }
+    }
+
+    if !invocation.check_values {
+        return Ok(Outcome::Returned(Vec::new()));
+    }
+    Ok(Outcome::Returned(
+        invocation
+            .returns
+            .iter()
+            .enumerate()
+            .map(|(i, &ty)| DataValue::read(values.offset(i as isize), ty))
+            .collect(),
+    ))
+}
+
+#[cfg(not(unix))]
+fn execute(_: &[u8], _: usize, _: &Invocation) -> SubtestResult<Outcome> {
+    // `host_isa()` never returns an ISA on these hosts.
+    unreachable!()
+}
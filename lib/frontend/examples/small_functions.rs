@@ -0,0 +1,133 @@
+//! Build many small functions with a `FunctionBuilder`, like a frontend translating a large
+//! program.
+//!
+//! Usage: `cargo run --release --example small_functions -- [functions]`
+//!
+//! The functions are built with a fresh `FunctionBuilderContext` for each function, with one
+//! context that is reused for all the functions, and with one context that is reused and sized
+//! with the `builder_stats` of the previous run. The time taken by each is printed.
+
+extern crate cranelift_codegen;
+extern crate cranelift_frontend;
+
+use cranelift_codegen::entity::EntityRef;
+use cranelift_codegen::ir::condcodes::IntCC;
+use cranelift_codegen::ir::types::I32;
+use cranelift_codegen::ir::{AbiParam, ExternalName, Function, InstBuilder, Signature};
+use cranelift_codegen::settings::CallConv;
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext, Variable};
+use std::env;
+use std::time::{Duration, Instant};
+
+/// The number of times the functions are built by each method.
+const ROUNDS: usize = 5;
+
+/// The number of variables of each function.
+const VARIABLES: usize = 8;
+
+fn main() {
+    let functions = env::args()
+        .nth(1)
+        .and_then(|arg| arg.parse().ok())
+        .unwrap_or(100_000);
+    println!("Building {} functions {} times", functions, ROUNDS);
+
+    let fresh = time(|| {
+        for i in 0..functions {
+            build_function(&mut FunctionBuilderContext::new(), i);
+        }
+    });
+    println!("Fresh contexts: {:?}", fresh);
+
+    let mut ctx = FunctionBuilderContext::new();
+    let reused = time(|| {
+        for i in 0..functions {
+            build_function(&mut ctx, i);
+        }
+    });
+    println!("Reused context: {:?}", reused);
+
+    let stats = ctx.builder_stats();
+    println!("{:?}", stats);
+    let mut ctx =
+        FunctionBuilderContext::with_capacity(stats.blocks, stats.variables, stats.values);
+    let sized = time(|| {
+        for i in 0..functions {
+            build_function(&mut ctx, i);
+        }
+    });
+    println!("Sized context: {:?}", sized);
+}
+
+/// Get the shortest time `f` takes in `ROUNDS` runs.
+fn time<F: FnMut()>(mut f: F) -> Duration {
+    (0..ROUNDS)
+        .map(|_| {
+            let start = Instant::now();
+            f();
+            start.elapsed()
+        }).min()
+        .unwrap()
+}
+
+/// Build a small function with a loop that updates a few variables.
+///
+/// The number of iterations of the loop depends on `index`, so the functions aren't all the same.
+fn build_function(ctx: &mut FunctionBuilderContext, index: usize) -> Function {
+    let mut sig = Signature::new(CallConv::SystemV);
+    sig.params.push(AbiParam::new(I32));
+    sig.returns.push(AbiParam::new(I32));
+    let mut func = Function::with_name_signature(ExternalName::user(0, index as u32), sig);
+    {
+        let mut builder = FunctionBuilder::new(&mut func, ctx);
+        let vars: Vec<_> = (0..VARIABLES).map(Variable::new).collect();
+        for &var in &vars {
+            builder.declare_var(var, I32);
+        }
+
+        let entry = builder.create_ebb();
+        let header = builder.create_ebb();
+        let body = builder.create_ebb();
+        let exit = builder.create_ebb();
+
+        builder.append_ebb_params_for_function_params(entry);
+        builder.switch_to_block(entry);
+        builder.seal_block(entry);
+        let arg = builder.ebb_params(entry)[0];
+        for (i, &var) in vars.iter().enumerate() {
+            let val = builder.ins().iadd_imm(arg, i as i64);
+            builder.def_var(var, val);
+        }
+        builder.ins().jump(header, &[]);
+
+        builder.switch_to_block(header);
+        let counter = builder.use_var(vars[0]);
+        let done = builder
+            .ins()
+            .icmp_imm(IntCC::SignedGreaterThan, counter, (index % 16) as i64);
+        builder.ins().brnz(done, exit, &[]);
+        builder.ins().jump(body, &[]);
+
+        builder.switch_to_block(body);
+        builder.seal_block(body);
+        for pair in vars.windows(2) {
+            let a = builder.use_var(pair[0]);
+            let b = builder.use_var(pair[1]);
+            let sum = builder.ins().iadd(a, b);
+            builder.def_var(pair[1], sum);
+        }
+        let counter = builder.use_var(vars[0]);
+        let next = builder.ins().iadd_imm(counter, 1);
+        builder.def_var(vars[0], next);
+        builder.ins().jump(header, &[]);
+        builder.seal_block(header);
+
+        builder.switch_to_block(exit);
+        builder.seal_block(exit);
+        let result = builder.use_var(vars[VARIABLES - 1]);
+        builder.ins().return_(&[result]);
+
+        builder.finalize();
+    }
+    func
+}
@@ -4,15 +4,20 @@ use cranelift_codegen::entity::{EntitySet, SecondaryMap};
 use cranelift_codegen::ir;
 use cranelift_codegen::ir::function::DisplayFunction;
 use cranelift_codegen::ir::{
-    types, AbiParam, DataFlowGraph, Ebb, ExtFuncData, ExternalName, FuncRef, Function, GlobalValue,
-    GlobalValueData, Heap, HeapData, Inst, InstBuilder, InstBuilderBase, InstructionData,
-    JumpTable, JumpTableData, LibCall, SigRef, Signature, StackSlot, StackSlotData, Type, Value,
+    types, BranchHint, DataFlowGraph, Ebb, ExtFuncData, ExternalName, FuncRef, Function,
+    GlobalValue, GlobalValueData, Heap, HeapData, Inst, InstBuilder, InstBuilderBase,
+    InstructionData, JumpTable, JumpTableData, LibCall, MemFlags, SigRef, Signature, StackSlot,
+    StackSlotData, Type, Value,
 };
 use cranelift_codegen::isa::TargetIsa;
 use cranelift_codegen::packed_option::PackedOption;
 use ssa::{Block, SSABuilder, SideEffects};
+use std::cmp;
 use variable::Variable;
 
+/// The maximal number of loads and stores that `emit_small_memory_copy` emits inline.
+const SMALL_MEMORY_COPY_ACCESSES: u64 = 4;
+
 /// Structure used for translating a series of functions into Cranelift IR.
 ///
 /// In order to reduce memory reallocations when compiling multiple functions,
@@ -26,6 +31,26 @@ pub struct FunctionBuilderContext {
     ssa: SSABuilder,
     ebbs: SecondaryMap<Ebb, EbbData>,
     types: SecondaryMap<Variable, Type>,
+    stats: FunctionBuilderStats,
+}
+
+/// The largest sizes the data structures of a `FunctionBuilderContext` reached while building
+/// functions.
+///
+/// These are the sizes to pass to
+/// [`FunctionBuilderContext::with_capacity`](struct.FunctionBuilderContext.html#method.with_capacity)
+/// so a context never reallocates while building similar functions.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FunctionBuilderStats {
+    /// The number of functions built with the context.
+    pub functions: usize,
+    /// The largest number of basic blocks in a function. There is at least one per EBB.
+    pub blocks: usize,
+    /// The largest number of variables declared in a function.
+    pub variables: usize,
+    /// The largest number of values the lookup of a variable in the predecessors of a block kept
+    /// on its stacks.
+    pub values: usize,
 }
 
 /// Temporary object used to build a single Cranelift IR `Function`.
@@ -81,10 +106,46 @@ impl FunctionBuilderContext {
             ssa: SSABuilder::new(),
             ebbs: SecondaryMap::new(),
             types: SecondaryMap::new(),
+            stats: FunctionBuilderStats::default(),
         }
     }
 
+    /// Creates a FunctionBuilderContext structure with room for functions with `blocks` basic
+    /// blocks and `variables` variables, and for `values` values on the stacks of the variable
+    /// lookups, before it reallocates. Only the definitions of each variable in the blocks still
+    /// grow while the first functions are built, and are reused for the next ones.
+    ///
+    /// The sizes reached by a context are reported by
+    /// [`builder_stats`](#method.builder_stats).
+    pub fn with_capacity(blocks: usize, variables: usize, values: usize) -> Self {
+        let mut ctx = Self::new();
+        ctx.reserve(blocks, variables, values);
+        ctx
+    }
+
+    /// Reserves room for at least `blocks` more basic blocks, `variables` more variables, and
+    /// `values` more values on the stacks of the variable lookups than the function being built
+    /// uses.
+    ///
+    /// The reserved memory is kept when the context is cleared after each function.
+    pub fn reserve(&mut self, blocks: usize, variables: usize, values: usize) {
+        self.ssa.reserve(blocks, variables, values);
+        self.ebbs.reserve(blocks);
+        self.types.reserve(variables);
+    }
+
+    /// Returns the largest sizes the data structures of the context reached in the functions it
+    /// built.
+    pub fn builder_stats(&self) -> FunctionBuilderStats {
+        self.stats
+    }
+
     fn clear(&mut self) {
+        self.stats.functions += 1;
+        self.stats.blocks = cmp::max(self.stats.blocks, self.ssa.num_blocks());
+        self.stats.variables = cmp::max(self.stats.variables, self.types.values().len());
+        self.stats.values = cmp::max(self.stats.values, self.ssa.peak_stack_size());
+
         self.ssa.clear();
         self.ebbs.clear();
         self.types.clear();
@@ -93,6 +154,13 @@ impl FunctionBuilderContext {
     fn is_empty(&self) -> bool {
         self.ssa.is_empty() && self.ebbs.is_empty() && self.types.is_empty()
     }
+
+    #[cfg(test)]
+    fn capacities(&self) -> Vec<usize> {
+        let mut capacities = vec![self.ebbs.capacity(), self.types.capacity()];
+        capacities.extend(self.ssa.capacities());
+        capacities
+    }
 }
 
 /// Implementation of the [`InstBuilder`](../codegen/ir/builder/trait.InstBuilder.html) that has
@@ -245,6 +313,27 @@ impl<'a> FunctionBuilder<'a> {
         ebb
     }
 
+    /// Hint how often `ebb` is executed, relative to the other EBBs in the function.
+    ///
+    /// Rarely executed EBBs are moved towards the end of the function when it is compiled, so the
+    /// common paths fall through. A cold EBB like an error handler can be given a frequency of 0.
+    pub fn set_ebb_frequency(&mut self, ebb: Ebb, frequency: u32) {
+        self.func.ebb_frequencies[ebb] = Some(frequency);
+    }
+
+    /// Hint whether the conditional branch `inst` is likely to be taken.
+    ///
+    /// The EBBs on the unlikely side of the branch are moved towards the end of the function
+    /// when it is compiled.
+    pub fn set_branch_hint(&mut self, inst: Inst, hint: BranchHint) {
+        let opcode = self.func.dfg[inst].opcode();
+        debug_assert!(
+            opcode.is_branch() && !opcode.is_terminator(),
+            "branch hints only apply to conditional branches"
+        );
+        self.func.branch_hints[inst] = Some(hint);
+    }
+
     /// After the call to this function, new instructions will be inserted into the designated
     /// block, in the order they are declared. You must declare the types of the Ebb arguments
     /// you will use here.
@@ -356,6 +445,42 @@ impl<'a> FunctionBuilder<'a> {
         self.func.import_function(data)
     }
 
+    /// Declare an import of the external function `name` with `signature`.
+    ///
+    /// Unlike `import_function`, this reuses an existing import of the same function, and an
+    /// existing signature that is equal to `signature`, so it can be called for every call site.
+    pub fn import_callee(
+        &mut self,
+        name: ExternalName,
+        signature: Signature,
+        colocated: bool,
+    ) -> FuncRef {
+        let dfg = &self.func.dfg;
+        let existing = dfg.ext_funcs.keys().find(|&fref| {
+            let ext_func = &dfg.ext_funcs[fref];
+            ext_func.name == name
+                && ext_func.colocated == colocated
+                && dfg.signatures[ext_func.signature] == signature
+        });
+        if let Some(fref) = existing {
+            return fref;
+        }
+
+        let existing_sig = dfg
+            .signatures
+            .keys()
+            .find(|&sig| dfg.signatures[sig] == signature);
+        let sigref = match existing_sig {
+            Some(sigref) => sigref,
+            None => self.func.import_signature(signature),
+        };
+        self.func.import_function(ExtFuncData {
+            name,
+            signature: sigref,
+            colocated,
+        })
+    }
+
     /// Declares a global value accessible to the function.
     pub fn create_global_value(&mut self, data: GlobalValueData) -> GlobalValue {
         self.func.create_global_value(data)
@@ -546,6 +671,12 @@ impl<'a> FunctionBuilder<'a> {
 
 /// Helper functions
 impl<'a> FunctionBuilder<'a> {
+    /// Calls `callee` with `args` and returns the values returned by the call.
+    pub fn call_function(&mut self, callee: FuncRef, args: &[Value]) -> &[Value] {
+        let call = self.ins().call(callee, args);
+        self.inst_results(call)
+    }
+
     /// Calls libc.memcpy
     ///
     /// Copies the `size` bytes from `src` to `dest`, assumes that `src + size`
@@ -553,42 +684,61 @@ impl<'a> FunctionBuilder<'a> {
     /// undefined. Applications in which `dest` and `src` might overlap should
     /// use `call_memmove` instead.
     pub fn call_memcpy(&mut self, isa: &TargetIsa, dest: Value, src: Value, size: Value) {
-        let pointer_type = isa.pointer_type();
-        let signature = {
-            let mut s = Signature::new(isa.flags().call_conv());
-            s.params.push(AbiParam::new(pointer_type));
-            s.params.push(AbiParam::new(pointer_type));
-            s.params.push(AbiParam::new(pointer_type));
-            self.import_signature(s)
-        };
-
-        let libc_memcpy = self.import_function(ExtFuncData {
-            name: ExternalName::LibCall(LibCall::Memcpy),
-            signature,
-            colocated: false,
-        });
+        let libc_memcpy = self.import_libcall(isa, LibCall::Memcpy);
 
         self.ins().call(libc_memcpy, &[dest, src, size]);
     }
 
+    /// Copies the `size` bytes from `src` to `dest`, like `call_memcpy` with a constant size.
+    ///
+    /// Small copies are emitted as a sequence of loads and stores, using the largest accesses
+    /// up to the pointer size that divide `size`. The accesses are marked as aligned when
+    /// `dest_align` and `src_align` allow it, and otherwise use `flags`. Copies that need more
+    /// than four accesses call memcpy instead.
+    #[cfg_attr(feature = "cargo-clippy", allow(too_many_arguments))]
+    pub fn emit_small_memory_copy(
+        &mut self,
+        isa: &TargetIsa,
+        dest: Value,
+        src: Value,
+        size: u64,
+        dest_align: u8,
+        src_align: u8,
+        flags: MemFlags,
+    ) {
+        let mut access_size = u64::from(isa.pointer_bytes());
+        while size % access_size != 0 {
+            access_size /= 2;
+        }
+        let accesses = size / access_size;
+        if accesses > SMALL_MEMORY_COPY_ACCESSES {
+            let size = self.ins().iconst(isa.pointer_type(), size as i64);
+            self.call_memcpy(isa, dest, src, size);
+            return;
+        }
+
+        let access_flags = |align: u8| {
+            let mut access_flags = flags;
+            if u64::from(align) >= access_size {
+                access_flags.set_aligned();
+            }
+            access_flags
+        };
+        let load_flags = access_flags(src_align);
+        let store_flags = access_flags(dest_align);
+        let ty = Type::int(access_size as u16 * 8).unwrap();
+        for i in 0..accesses {
+            let offset = (i * access_size) as i32;
+            let value = self.ins().load(ty, load_flags, src, offset);
+            self.ins().store(store_flags, value, dest, offset);
+        }
+    }
+
     /// Calls libc.memset
     ///
     /// Writes `len` bytes of value `ch` to memory starting at `buffer`.
     pub fn call_memset(&mut self, isa: &TargetIsa, buffer: Value, ch: Value, len: Value) {
-        let pointer_type = isa.pointer_type();
-        let signature = {
-            let mut s = Signature::new(isa.flags().call_conv());
-            s.params.push(AbiParam::new(pointer_type));
-            s.params.push(AbiParam::new(types::I32));
-            s.params.push(AbiParam::new(pointer_type));
-            self.import_signature(s)
-        };
-
-        let libc_memset = self.import_function(ExtFuncData {
-            name: ExternalName::LibCall(LibCall::Memset),
-            signature,
-            colocated: false,
-        });
+        let libc_memset = self.import_libcall(isa, LibCall::Memset);
 
         let ch = self.ins().uextend(types::I32, ch);
         self.ins().call(libc_memset, &[buffer, ch, len]);
@@ -599,23 +749,20 @@ impl<'a> FunctionBuilder<'a> {
     /// Copies `len` bytes from memory starting at `source` to memory starting
     /// at `dest`. `source` is always read before writing to `dest`.
     pub fn call_memmove(&mut self, isa: &TargetIsa, dest: Value, source: Value, num: Value) {
-        let pointer_type = isa.pointer_type();
-        let signature = {
-            let mut s = Signature::new(isa.flags().call_conv());
-            s.params.push(AbiParam::new(pointer_type));
-            s.params.push(AbiParam::new(pointer_type));
-            s.params.push(AbiParam::new(pointer_type));
-            self.import_signature(s)
-        };
-
-        let libc_memmove = self.import_function(ExtFuncData {
-            name: ExternalName::LibCall(LibCall::Memmove),
-            signature,
-            colocated: false,
-        });
+        let libc_memmove = self.import_libcall(isa, LibCall::Memmove);
 
         self.ins().call(libc_memmove, &[dest, source, num]);
     }
+
+    /// Import `libcall` with the name and signature used by `isa`.
+    fn import_libcall(&mut self, isa: &TargetIsa, libcall: LibCall) -> FuncRef {
+        let names = isa.libcall_names();
+        self.import_callee(
+            names.name(libcall),
+            libcall.signature(isa),
+            names.colocated(libcall, isa.flags()),
+        )
+    }
 }
 
 // Helper functions
@@ -663,14 +810,24 @@ mod tests {
     use Variable;
 
     fn sample_function(lazy_seal: bool) {
+        let mut fn_ctx = FunctionBuilderContext::new();
+        let func = build_sample_function(&mut fn_ctx, lazy_seal);
+
+        let flags = settings::Flags::new(settings::builder());
+        // println!("{}", func.display(None));
+        if let Err(errors) = verify_function(&func, &flags) {
+            panic!("{}\n{}", func.display(None), errors)
+        }
+    }
+
+    fn build_sample_function(fn_ctx: &mut FunctionBuilderContext, lazy_seal: bool) -> Function {
         let mut sig = Signature::new(CallConv::SystemV);
         sig.returns.push(AbiParam::new(I32));
         sig.params.push(AbiParam::new(I32));
 
-        let mut fn_ctx = FunctionBuilderContext::new();
         let mut func = Function::with_name_signature(ExternalName::testcase("sample"), sig);
         {
-            let mut builder = FunctionBuilder::new(&mut func, &mut fn_ctx);
+            let mut builder = FunctionBuilder::new(&mut func, fn_ctx);
 
             let block0 = builder.create_ebb();
             let block1 = builder.create_ebb();
@@ -747,12 +904,7 @@ mod tests {
 
             builder.finalize();
         }
-
-        let flags = settings::Flags::new(settings::builder());
-        // println!("{}", func.display(None));
-        if let Err(errors) = verify_function(&func, &flags) {
-            panic!("{}\n{}", func.display(None), errors)
-        }
+        func
     }
 
     #[test]
@@ -765,6 +917,33 @@ mod tests {
         sample_function(true)
     }
 
+    #[test]
+    fn reuse_capacity() {
+        let mut fn_ctx = FunctionBuilderContext::new();
+        build_sample_function(&mut fn_ctx, false);
+        let capacities = fn_ctx.capacities();
+        assert!(fn_ctx.is_empty());
+
+        // The memory allocated for the first function is kept and is enough for the next ones.
+        for _ in 0..3 {
+            build_sample_function(&mut fn_ctx, true);
+            assert_eq!(fn_ctx.capacities(), capacities);
+        }
+
+        let stats = fn_ctx.builder_stats();
+        assert_eq!(stats.functions, 4);
+        assert!(stats.blocks >= 3);
+        assert_eq!(stats.variables, 3);
+        assert!(stats.values > 0);
+
+        let mut fn_ctx =
+            FunctionBuilderContext::with_capacity(stats.blocks, stats.variables, stats.values);
+        // Only the definitions of the variables are allocated by a context sized with the stats.
+        let capacities = fn_ctx.capacities();
+        build_sample_function(&mut fn_ctx, false);
+        assert_eq!(fn_ctx.capacities()[..capacities.len()], capacities[..]);
+    }
+
     #[test]
     fn memcpy() {
         use cranelift_codegen::{isa, settings};
@@ -825,4 +1004,213 @@ ebb0:
 "
         );
     }
+    fn small_memory_copy(size: u64, align: u8) -> String {
+        use cranelift_codegen::ir::MemFlags;
+        use cranelift_codegen::isa;
+        use std::str::FromStr;
+
+        let triple =
+            ::target_lexicon::Triple::from_str("x86_64").expect("Couldn't create x86_64 triple");
+        let target = isa::lookup(triple)
+            .ok()
+            .map(|b| b.finish(settings::Flags::new(settings::builder())))
+            .expect("This test requires x86 support.");
+
+        let mut sig = Signature::new(CallConv::SystemV);
+        sig.params.push(AbiParam::new(I64));
+        sig.params.push(AbiParam::new(I64));
+
+        let mut fn_ctx = FunctionBuilderContext::new();
+        let mut func = Function::with_name_signature(ExternalName::testcase("sample"), sig);
+        {
+            let mut builder = FunctionBuilder::new(&mut func, &mut fn_ctx);
+
+            let block0 = builder.create_ebb();
+            builder.append_ebb_params_for_function_params(block0);
+            builder.switch_to_block(block0);
+
+            let dest = builder.ebb_params(block0)[0];
+            let src = builder.ebb_params(block0)[1];
+            let mut flags = MemFlags::new();
+            flags.set_notrap();
+            builder.emit_small_memory_copy(&*target, dest, src, size, align, align, flags);
+            builder.ins().return_(&[]);
+
+            builder.seal_all_blocks();
+            builder.finalize();
+        }
+        func.display(None).to_string()
+    }
+
+    #[test]
+    fn small_memory_copy_inline() {
+        // Four aligned 8-byte accesses are emitted inline.
+        assert_eq!(
+            small_memory_copy(32, 8),
+            "function %sample(i64, i64) system_v {
+ebb0(v0: i64, v1: i64):
+    v2 = load.i64 notrap aligned v1
+    store notrap aligned v2, v0
+    v3 = load.i64 notrap aligned v1+8
+    store notrap aligned v3, v0+8
+    v4 = load.i64 notrap aligned v1+16
+    store notrap aligned v4, v0+16
+    v5 = load.i64 notrap aligned v1+24
+    store notrap aligned v5, v0+24
+    return
+}
+"
+        );
+
+        // The accesses are only as wide as the size allows, and they aren't aligned.
+        assert_eq!(
+            small_memory_copy(12, 1),
+            "function %sample(i64, i64) system_v {
+ebb0(v0: i64, v1: i64):
+    v2 = load.i32 notrap v1
+    store notrap v2, v0
+    v3 = load.i32 notrap v1+4
+    store notrap v3, v0+4
+    v4 = load.i32 notrap v1+8
+    store notrap v4, v0+8
+    return
+}
+"
+        );
+    }
+
+    #[test]
+    fn small_memory_copy_libcall() {
+        // Five accesses are too many.
+        assert_eq!(
+            small_memory_copy(40, 8),
+            "function %sample(i64, i64) system_v {
+    sig0 = (i64, i64, i64) fast
+    fn0 = %Memcpy sig0
+
+ebb0(v0: i64, v1: i64):
+    v2 = iconst.i64 40
+    call fn0(v0, v1, v2)
+    return
+}
+"
+        );
+    }
+
+    #[test]
+    fn import_callee() {
+        let mut fn_ctx = FunctionBuilderContext::new();
+        let mut func = Function::with_name_signature(
+            ExternalName::testcase("sample"),
+            Signature::new(CallConv::SystemV),
+        );
+        {
+            let mut builder = FunctionBuilder::new(&mut func, &mut fn_ctx);
+
+            let block0 = builder.create_ebb();
+            builder.switch_to_block(block0);
+
+            let mut sig = Signature::new(CallConv::SystemV);
+            sig.params.push(AbiParam::new(I32));
+            sig.returns.push(AbiParam::new(I32));
+            let mut arg = builder.ins().iconst(I32, 1);
+            for &name in &["f", "g", "f"] {
+                let callee =
+                    builder.import_callee(ExternalName::testcase(name), sig.clone(), false);
+                arg = builder.call_function(callee, &[arg])[0];
+            }
+            builder.ins().return_(&[]);
+
+            builder.seal_all_blocks();
+            builder.finalize();
+        }
+
+        assert_eq!(
+            func.display(None).to_string(),
+            "function %sample() system_v {
+    sig0 = (i32) -> i32 system_v
+    fn0 = %f sig0
+    fn1 = %g sig0
+
+ebb0:
+    v0 = iconst.i32 1
+    v1 = call fn0(v0)
+    v2 = call fn1(v1)
+    v3 = call fn0(v2)
+    return
+}
+"
+        );
+    }
+
+    #[test]
+    fn bool_variable() {
+        use cranelift_codegen::ir::condcodes::IntCC;
+        use cranelift_codegen::print_errors::pretty_error;
+        use cranelift_codegen::{isa, settings, Context};
+        use std::str::FromStr;
+
+        let shared_flags = settings::Flags::new(settings::builder());
+        let triple =
+            ::target_lexicon::Triple::from_str("x86_64").expect("Couldn't create x86_64 triple");
+        let target = isa::lookup(triple)
+            .ok()
+            .map(|b| b.finish(shared_flags))
+            .expect("This test requires x86 support.");
+
+        let mut sig = Signature::new(CallConv::SystemV);
+        sig.params.push(AbiParam::new(I32));
+        sig.params.push(AbiParam::new(B1));
+        sig.returns.push(AbiParam::new(B1));
+
+        let mut fn_ctx = FunctionBuilderContext::new();
+        let mut func = Function::with_name_signature(ExternalName::testcase("sample"), sig);
+        {
+            let mut builder = FunctionBuilder::new(&mut func, &mut fn_ctx);
+
+            let block0 = builder.create_ebb();
+            let block1 = builder.create_ebb();
+            let block2 = builder.create_ebb();
+            let x = Variable::new(0);
+            let flag = Variable::new(1);
+            builder.declare_var(x, I32);
+            builder.declare_var(flag, B1);
+            builder.append_ebb_params_for_function_params(block0);
+
+            builder.switch_to_block(block0);
+            let params = builder.ebb_params(block0).to_vec();
+            builder.def_var(x, params[0]);
+            builder.def_var(flag, params[1]);
+            builder.ins().jump(block1, &[]);
+
+            // Invert the flag `x` times.
+            builder.switch_to_block(block1);
+            let arg = builder.use_var(x);
+            let done = builder.ins().icmp_imm(IntCC::Equal, arg, 0);
+            builder.ins().brnz(done, block2, &[]);
+            let arg = builder.use_var(flag);
+            let inverted = builder.ins().bnot(arg);
+            builder.def_var(flag, inverted);
+            let arg = builder.use_var(x);
+            let next = builder.ins().iadd_imm(arg, -1);
+            builder.def_var(x, next);
+            builder.ins().jump(block1, &[]);
+
+            builder.switch_to_block(block2);
+            let arg = builder.use_var(flag);
+            builder.ins().return_(&[arg]);
+
+            builder.seal_all_blocks();
+            builder.finalize();
+        }
+
+        let mut ctx = Context::for_function(func);
+        if let Err(err) = ctx.compile(&*target) {
+            panic!("{}", pretty_error(&ctx.func, Some(&*target), err));
+        }
+
+        // The boolean crosses the ABI boundaries as a byte.
+        assert_eq!(ctx.func.signature.params[1].value_type, I8);
+        assert_eq!(ctx.func.signature.returns[0].value_type, I8);
+    }
 }
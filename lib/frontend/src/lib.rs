@@ -185,13 +185,15 @@ extern crate target_lexicon;
 #[macro_use]
 extern crate log;
 
-pub use frontend::{FunctionBuilder, FunctionBuilderContext};
+pub use frontend::{FunctionBuilder, FunctionBuilderContext, FunctionBuilderStats};
 pub use switch::Switch;
+pub use trampoline::{make_trampoline, TRAMPOLINE_SLOT_SIZE};
 pub use variable::Variable;
 
 mod frontend;
 mod ssa;
 mod switch;
+mod trampoline;
 mod variable;
 
 /// This replaces `std` in builds with `core`.
@@ -200,10 +202,6 @@ mod std {
     pub use alloc::{string, vec};
     pub use core::*;
     pub mod collections {
-        #[allow(unused_extern_crates)]
-        extern crate hashmap_core;
-
-        pub use self::hashmap_core::map as hash_map;
-        pub use self::hashmap_core::{HashMap, HashSet};
+        pub use alloc::collections::BTreeMap;
     }
 }
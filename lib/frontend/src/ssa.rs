@@ -13,6 +13,7 @@ use cranelift_codegen::ir::types::{F32, F64};
 use cranelift_codegen::ir::{Ebb, Function, Inst, InstBuilder, InstructionData, Type, Value};
 use cranelift_codegen::packed_option::PackedOption;
 use cranelift_codegen::packed_option::ReservedValue;
+use std::cmp;
 use std::mem;
 use std::u32;
 use std::vec::Vec;
@@ -49,6 +50,8 @@ pub struct SSABuilder {
     results: Vec<Value>,
     // Side effects accumulated in the `use_var`/`predecessors_lookup` state machine.
     side_effects: SideEffects,
+    // The largest size of the call and result stacks since the builder was last cleared.
+    peak_stack_size: usize,
 }
 
 /// Side effects of a `use_var` or a `seal_ebb_header_block` method call.
@@ -164,23 +167,64 @@ impl SSABuilder {
             calls: Vec::new(),
             results: Vec::new(),
             side_effects: SideEffects::new(),
+            peak_stack_size: 0,
         }
     }
 
+    /// Reserve room for at least `blocks` more basic blocks and `variables` more variables, and
+    /// for `values` values on the stacks of the variable lookups.
+    pub fn reserve(&mut self, blocks: usize, variables: usize, values: usize) {
+        self.blocks.reserve(blocks);
+        self.ebb_headers.reserve(blocks);
+        self.variables.reserve(variables);
+        self.calls.reserve(values);
+        self.results.reserve(values);
+    }
+
+    /// Get the number of basic blocks declared since the builder was last cleared.
+    pub fn num_blocks(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// Get the largest number of values on the stacks of the variable lookups since the builder
+    /// was last cleared.
+    pub fn peak_stack_size(&self) -> usize {
+        self.peak_stack_size
+    }
+
+    /// Get the capacities of the data structures of the builder.
+    #[cfg(test)]
+    pub fn capacities(&self) -> Vec<usize> {
+        let mut capacities = vec![
+            self.variables.capacity(),
+            self.blocks.capacity(),
+            self.ebb_headers.capacity(),
+            self.calls.capacity(),
+            self.results.capacity(),
+        ];
+        capacities.extend(self.variables.values().map(SecondaryMap::capacity));
+        capacities
+    }
+
     /// Clears a `SSABuilder` from all its data, letting it in a pristine state without
     /// deallocating memory.
     pub fn clear(&mut self) {
-        self.variables.clear();
+        // Clear the definitions of each variable rather than dropping them, so their memory is
+        // reused too.
+        for defs in self.variables.values_mut() {
+            defs.clear();
+        }
         self.blocks.clear();
         self.ebb_headers.clear();
         debug_assert!(self.calls.is_empty());
         debug_assert!(self.results.is_empty());
         debug_assert!(self.side_effects.is_empty());
+        self.peak_stack_size = 0;
     }
 
     /// Tests whether an `SSABuilder` is in a cleared state.
     pub fn is_empty(&self) -> bool {
-        self.variables.is_empty()
+        self.variables.values().all(SecondaryMap::is_empty)
             && self.blocks.is_empty()
             && self.ebb_headers.is_empty()
             && self.calls.is_empty()
@@ -672,8 +716,8 @@ impl SSABuilder {
                 }
 
                 for old_dest in func.jump_tables[jt].as_mut_slice() {
-                    if *old_dest == dest_ebb {
-                        *old_dest = middle_ebb;
+                    if *old_dest == dest_ebb.into() {
+                        *old_dest = middle_ebb.into();
                     }
                 }
                 let mut cur = FuncCursor::new(func).at_bottom(middle_ebb);
@@ -723,6 +767,10 @@ impl SSABuilder {
     fn run_state_machine(&mut self, func: &mut Function, var: Variable, ty: Type) -> Value {
         // Process the calls scheduled in `self.calls` until it is empty.
         while let Some(call) = self.calls.pop() {
+            self.peak_stack_size = cmp::max(
+                self.peak_stack_size,
+                cmp::max(self.calls.len() + 1, self.results.len()),
+            );
             match call {
                 Call::UseVar(block) => {
                     // First we lookup for the current definition of the variable in this block
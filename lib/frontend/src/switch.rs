@@ -1,7 +1,8 @@
 use cranelift_codegen::ir::condcodes::IntCC;
+use cranelift_codegen::ir::immediates::Imm64;
 use cranelift_codegen::ir::*;
 use frontend::FunctionBuilder;
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use std::vec::Vec;
 
 type EntryIndex = u64;
@@ -10,14 +11,14 @@ type EntryIndex = u64;
 /// They emit efficient code using branches, jump tables, or a combination of both.
 #[derive(Debug, Default)]
 pub struct Switch {
-    cases: HashMap<EntryIndex, Ebb>,
+    cases: BTreeMap<EntryIndex, Ebb>,
 }
 
 impl Switch {
     /// Create a new empty switch
     pub fn new() -> Self {
         Self {
-            cases: HashMap::new(),
+            cases: BTreeMap::new(),
         }
     }
 
@@ -31,23 +32,30 @@ impl Switch {
         );
     }
 
+    /// Group the cases into ranges for the jump tables.
+    ///
+    /// A gap between two cases is filled with holes when it is smaller than the number of cases
+    /// already in the range, so the jump tables stay more than half full.
     fn collect_contiguous_case_ranges(self) -> Vec<ContiguousCaseRange> {
         debug!("build_contiguous_case_ranges before: {:#?}", self.cases);
-        let mut cases = self.cases.into_iter().collect::<Vec<(_, _)>>();
-        cases.sort_by_key(|&(index, _)| index);
-
         let mut contiguous_case_ranges: Vec<ContiguousCaseRange> = vec![];
         let mut last_index = None;
-        for (index, ebb) in cases {
+        for (index, ebb) in self.cases {
             match last_index {
                 None => contiguous_case_ranges.push(ContiguousCaseRange::new(index)),
                 Some(last_index) => {
-                    if index > last_index + 1 {
+                    let gap = index - last_index - 1;
+                    let range = contiguous_case_ranges.last_mut().unwrap();
+                    if gap < range.num_cases() as u64 {
+                        for _ in 0..gap {
+                            range.ebbs.push(None);
+                        }
+                    } else {
                         contiguous_case_ranges.push(ContiguousCaseRange::new(index));
                     }
                 }
             }
-            contiguous_case_ranges.last_mut().unwrap().ebbs.push(ebb);
+            contiguous_case_ranges.last_mut().unwrap().ebbs.push(Some(ebb));
             last_index = Some(index);
         }
 
@@ -64,7 +72,7 @@ impl Switch {
         val: Value,
         otherwise: Ebb,
         contiguous_case_ranges: Vec<ContiguousCaseRange>,
-    ) -> Vec<(EntryIndex, Ebb, Vec<Ebb>)> {
+    ) -> Vec<(EntryIndex, Ebb, Vec<Option<Ebb>>)> {
         let mut cases_and_jt_ebbs = Vec::new();
 
         // Avoid allocation in the common case
@@ -124,12 +132,12 @@ impl Switch {
         val: Value,
         otherwise: Ebb,
         contiguous_case_ranges: Vec<ContiguousCaseRange>,
-        cases_and_jt_ebbs: &mut Vec<(EntryIndex, Ebb, Vec<Ebb>)>,
+        cases_and_jt_ebbs: &mut Vec<(EntryIndex, Ebb, Vec<Option<Ebb>>)>,
     ) {
         for ContiguousCaseRange { first_index, ebbs } in contiguous_case_ranges.into_iter().rev() {
             if ebbs.len() == 1 {
                 let is_good_val = bx.ins().icmp_imm(IntCC::Equal, val, first_index as i64);
-                bx.ins().brnz(is_good_val, ebbs[0], &[]);
+                bx.ins().brnz(is_good_val, ebbs[0].unwrap(), &[]);
             } else {
                 let jt_ebb = bx.create_ebb();
                 let is_good_val =
@@ -147,17 +155,25 @@ impl Switch {
         bx: &mut FunctionBuilder,
         val: Value,
         otherwise: Ebb,
-        cases_and_jt_ebbs: Vec<(EntryIndex, Ebb, Vec<Ebb>)>,
+        cases_and_jt_ebbs: Vec<(EntryIndex, Ebb, Vec<Option<Ebb>>)>,
     ) {
         for (first_index, jt_ebb, ebbs) in cases_and_jt_ebbs.into_iter().rev() {
             let mut jt_data = JumpTableData::new();
             for ebb in ebbs {
-                jt_data.push_entry(ebb);
+                match ebb {
+                    Some(ebb) => jt_data.push_entry(ebb),
+                    None => jt_data.push_hole(),
+                }
             }
             let jump_table = bx.create_jump_table(jt_data);
 
             bx.switch_to_block(jt_ebb);
-            let discr = bx.ins().iadd_imm(val, (first_index as i64).wrapping_neg());
+            // The subtraction wraps around in the type of `val`.
+            let bits = bx.func.dfg.value_type(val).bits();
+            let neg_index = Imm64::new((first_index as i64).wrapping_neg());
+            let discr = bx
+                .ins()
+                .iadd_imm(val, neg_index.sign_extend_from_width(bits));
             bx.ins().br_table(discr, otherwise, jump_table);
         }
     }
@@ -169,7 +185,13 @@ impl Switch {
     /// * The function builder to emit to
     /// * The value to switch on
     /// * The default ebb
-    pub fn emit(self, bx: &mut FunctionBuilder, val: Value, otherwise: Ebb) {
+    pub fn emit(mut self, bx: &mut FunctionBuilder, val: Value, otherwise: Ebb) {
+        // Cases with an index that isn't an unsigned value of the type of `val` are never taken.
+        let bits = bx.func.dfg.value_type(val).bits();
+        if bits < 64 {
+            self.cases.split_off(&(1 << bits));
+        }
+
         // FIXME icmp(_imm) doesn't have encodings for i8 and i16 on x86(_64) yet
         let val = match bx.func.dfg.value_type(val) {
             types::I8 | types::I16 => bx.ins().uextend(types::I32, val),
@@ -182,10 +204,11 @@ impl Switch {
     }
 }
 
+/// A range of cases starting at `first_index`, with `None` for the holes.
 #[derive(Debug)]
 struct ContiguousCaseRange {
     first_index: EntryIndex,
-    ebbs: Vec<Ebb>,
+    ebbs: Vec<Option<Ebb>>,
 }
 
 impl ContiguousCaseRange {
@@ -195,6 +218,11 @@ impl ContiguousCaseRange {
             ebbs: Vec::new(),
         }
     }
+
+    /// Get the number of cases in the range, not counting the holes.
+    fn num_cases(&self) -> usize {
+        self.ebbs.iter().filter(|ebb| ebb.is_some()).count()
+    }
 }
 
 #[cfg(test)]
@@ -205,14 +233,17 @@ mod tests {
     use std::string::ToString;
 
     macro_rules! setup {
-        ($default:expr, [$($index:expr,)*]) => {{
+        ($default:expr, [$($index:expr,)*]) => {
+            setup!($default, types::I8, [$($index,)*])
+        };
+        ($default:expr, $ty:expr, [$($index:expr,)*]) => {{
             let mut func = Function::new();
             let mut func_ctx = FunctionBuilderContext::new();
             {
                 let mut bx = FunctionBuilder::new(&mut func, &mut func_ctx);
                 let ebb = bx.create_ebb();
                 bx.switch_to_block(ebb);
-                let val = bx.ins().iconst(types::I8, 0);
+                let val = bx.ins().iconst($ty, 0);
                 let mut switch = Switch::new();
                 $(
                     let ebb = bx.create_ebb();
@@ -292,6 +323,28 @@ ebb3:
         );
     }
 
+    #[test]
+    fn switch_holes() {
+        let func = setup!(0, [0, 1, 2, 4, 5, 8, 20,]);
+        assert_eq!(
+            func,
+            "    jt0 = jump_table [ebb1, ebb2, ebb3, _, ebb4, ebb5, _, _, ebb6]
+
+ebb0:
+    v0 = iconst.i8 0
+    v1 = uextend.i32 v0
+    v2 = icmp_imm eq v1, 20
+    brnz v2, ebb7
+    v3 = icmp_imm uge v1, 0
+    brnz v3, ebb8
+    jump ebb0
+
+ebb8:
+    v4 = iadd_imm.i32 v1, 0
+    br_table v4, ebb0, jt0"
+        );
+    }
+
     #[test]
     fn switch_many() {
         let func = setup!(0, [0, 1, 5, 7, 10, 11, 12,]);
@@ -333,32 +386,46 @@ ebb10:
 
     #[test]
     fn switch_min_index_value() {
-        let func = setup!(0, [::std::i64::MIN as u64, 1,]);
+        let func = setup!(0, types::I64, [::std::i64::MIN as u64, 1,]);
         assert_eq!(
             func,
             "ebb0:
-    v0 = iconst.i8 0
-    v1 = uextend.i32 v0
-    v2 = icmp_imm eq v1, 0x8000_0000_0000_0000
-    brnz v2, ebb1
-    v3 = icmp_imm eq v1, 1
-    brnz v3, ebb2
+    v0 = iconst.i64 0
+    v1 = icmp_imm eq v0, 0x8000_0000_0000_0000
+    brnz v1, ebb1
+    v2 = icmp_imm eq v0, 1
+    brnz v2, ebb2
     jump ebb0"
         );
     }
 
     #[test]
     fn switch_max_index_value() {
-        let func = setup!(0, [::std::i64::MAX as u64, 1,]);
+        let func = setup!(0, types::I64, [::std::i64::MAX as u64, 1,]);
+        assert_eq!(
+            func,
+            "ebb0:
+    v0 = iconst.i64 0
+    v1 = icmp_imm eq v0, 0x7fff_ffff_ffff_ffff
+    brnz v1, ebb1
+    v2 = icmp_imm eq v0, 1
+    brnz v2, ebb2
+    jump ebb0"
+        )
+    }
+
+    #[test]
+    fn switch_index_out_of_range() {
+        let func = setup!(0, [0x100, 0xff, 1,]);
         assert_eq!(
             func,
             "ebb0:
     v0 = iconst.i8 0
     v1 = uextend.i32 v0
-    v2 = icmp_imm eq v1, 0x7fff_ffff_ffff_ffff
-    brnz v2, ebb1
+    v2 = icmp_imm eq v1, 255
+    brnz v2, ebb2
     v3 = icmp_imm eq v1, 1
-    brnz v3, ebb2
+    brnz v3, ebb3
     jump ebb0"
         )
     }
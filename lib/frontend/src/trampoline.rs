@@ -0,0 +1,117 @@
+//! Trampolines for calling compiled functions from the host.
+use cranelift_codegen::ir::{
+    AbiParam, ExternalName, Function, InstBuilder, MemFlags, Signature, Type,
+};
+use cranelift_codegen::settings::CallConv;
+use frontend::{FunctionBuilder, FunctionBuilderContext};
+use std::vec::Vec;
+
+/// The size of the slot that holds each argument and return value in the trampoline's buffer.
+pub const TRAMPOLINE_SLOT_SIZE: u32 = 8;
+
+/// Make a trampoline that calls a function with signature `sig` from the host.
+///
+/// The trampoline has the signature `(callee, values)` with the calling convention `call_conv`,
+/// which is normally the host's C ABI. It loads the arguments from the array of
+/// `TRAMPOLINE_SLOT_SIZE`-byte slots pointed to by `values`, calls `callee` through `sig`, and
+/// stores the return values back into the slots in order. A value smaller than a slot occupies
+/// the low-address bytes of its slot.
+///
+/// Since the trampoline calls `callee` with a normal `call_indirect` instruction, the arguments
+/// are passed the same way as when compiled code calls it, in the locations reported by
+/// `TargetIsa::compute_argument_locations()`. All the parameters of `sig`, including special
+/// purpose parameters like `VMContext`, are loaded from `values`.
+///
+/// Panics if an argument or return value doesn't fit in a slot.
+pub fn make_trampoline(
+    name: ExternalName,
+    sig: &Signature,
+    call_conv: CallConv,
+    pointer_type: Type,
+) -> Function {
+    for param in sig.params.iter().chain(&sig.returns) {
+        assert!(
+            param.value_type.bytes() <= TRAMPOLINE_SLOT_SIZE,
+            "{} doesn't fit in a trampoline slot",
+            param.value_type
+        );
+    }
+
+    let mut tsig = Signature::new(call_conv);
+    tsig.params.push(AbiParam::new(pointer_type));
+    tsig.params.push(AbiParam::new(pointer_type));
+    let mut func = Function::with_name_signature(name, tsig);
+    let mut func_ctx = FunctionBuilderContext::new();
+    {
+        let mut builder = FunctionBuilder::new(&mut func, &mut func_ctx);
+        let callee_sig = builder.import_signature(sig.clone());
+        let ebb = builder.create_ebb();
+        builder.append_ebb_params_for_function_params(ebb);
+        builder.switch_to_block(ebb);
+        builder.seal_block(ebb);
+        let callee = builder.ebb_params(ebb)[0];
+        let values = builder.ebb_params(ebb)[1];
+
+        let mut flags = MemFlags::new();
+        flags.set_notrap();
+        flags.set_aligned();
+
+        let args: Vec<_> = sig
+            .params
+            .iter()
+            .enumerate()
+            .map(|(i, param)| {
+                let offset = (i as u32 * TRAMPOLINE_SLOT_SIZE) as i32;
+                builder.ins().load(param.value_type, flags, values, offset)
+            }).collect();
+        let call = builder.ins().call_indirect(callee_sig, callee, &args);
+        let results = builder.inst_results(call).to_vec();
+        for (i, &result) in results.iter().enumerate() {
+            let offset = (i as u32 * TRAMPOLINE_SLOT_SIZE) as i32;
+            builder.ins().store(flags, result, values, offset);
+        }
+        builder.ins().return_(&[]);
+        builder.finalize();
+    }
+    func
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cranelift_codegen::ir::{types, ArgumentPurpose};
+    use cranelift_codegen::settings;
+    use cranelift_codegen::verifier::verify_function;
+    use std::string::ToString;
+
+    #[test]
+    fn trampoline() {
+        let mut sig = Signature::new(CallConv::Fast);
+        sig.params.push(AbiParam::special(types::I64, ArgumentPurpose::VMContext));
+        sig.params.push(AbiParam::new(types::F32));
+        sig.returns.push(AbiParam::new(types::I32));
+        let func = make_trampoline(
+            ExternalName::testcase("tramp"),
+            &sig,
+            CallConv::SystemV,
+            types::I64,
+        );
+
+        let flags = settings::Flags::new(settings::builder());
+        verify_function(&func, &flags).unwrap();
+        assert_eq!(
+            func.to_string(),
+            "function %tramp(i64, i64) system_v {
+    sig0 = (i64 vmctx, f32) -> i32 fast
+
+ebb0(v0: i64, v1: i64):
+    v2 = load.i64 notrap aligned v1
+    v3 = load.f32 notrap aligned v1+8
+    v4 = call_indirect sig0, v0(v2, v3)
+    store notrap aligned v4, v1
+    return
+}
+"
+        );
+    }
+}
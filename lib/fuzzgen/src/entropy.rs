@@ -0,0 +1,65 @@
+//! A source of choices for the generator.
+
+/// Supplies the choices made by the generator from a slice of bytes.
+///
+/// When the bytes run out, every choice is 0. The generator is written so that 0 selects the
+/// simplest option, which makes it wind down and finish the function.
+pub struct Entropy<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Entropy<'a> {
+    /// Create a source of choices from `data`.
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    /// Take the next byte, or 0 if there are none left.
+    pub fn byte(&mut self) -> u8 {
+        match self.data.split_first() {
+            Some((&byte, rest)) => {
+                self.data = rest;
+                byte
+            }
+            None => 0,
+        }
+    }
+
+    /// Take the next `bytes` bytes as a little-endian integer.
+    pub fn bits(&mut self, bytes: usize) -> u64 {
+        debug_assert!(bytes <= 8);
+        (0..bytes).fold(0, |word, i| word | (u64::from(self.byte()) << (i * 8)))
+    }
+
+    /// Choose a number in the range `0..n`.
+    pub fn below(&mut self, n: usize) -> usize {
+        if n <= 1 {
+            0
+        } else if n <= 256 {
+            self.byte() as usize % n
+        } else {
+            self.bits(4) as usize % n
+        }
+    }
+
+    /// Choose one of `items`, which must not be empty.
+    pub fn choose<'t, T>(&mut self, items: &'t [T]) -> &'t T {
+        &items[self.below(items.len())]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn choices() {
+        let mut entropy = Entropy::new(&[7, 0x34, 0x12, 200]);
+        assert_eq!(entropy.below(5), 2);
+        assert_eq!(entropy.bits(2), 0x1234);
+        assert_eq!(*entropy.choose(&['a', 'b', 'c']), 'c');
+        assert_eq!(entropy.below(5), 0);
+        assert_eq!(entropy.bits(8), 0);
+        assert_eq!(entropy.below(1), 0);
+    }
+}
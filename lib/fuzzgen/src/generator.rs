@@ -0,0 +1,639 @@
+//! Generation of functions.
+//!
+//! The body of a function is a region, which is a sequence of items. An item is either a
+//! straight-line sequence of instructions, or a control flow construct containing nested regions:
+//!
+//! - A conditional branches to one of two arms, which both jump to a merge EBB.
+//! - A switch uses `br_table` to branch to one of several arms, which all jump to a merge EBB.
+//! - A counted loop runs its body a fixed number of times, counting down in an EBB parameter of
+//!   the loop header.
+//!
+//! The merge EBBs, loop headers, and loop exits have parameters of random types, so values flow
+//! between EBBs through EBB arguments as well as through dominance.
+
+use cranelift_codegen::cursor::{Cursor, FuncCursor};
+use cranelift_codegen::ir::condcodes::{FloatCC, IntCC};
+use cranelift_codegen::ir::immediates::{Ieee32, Ieee64};
+use cranelift_codegen::ir::{
+    types, AbiParam, Ebb, ExternalName, Function, Inst, InstBuilder, JumpTableData, Opcode,
+    Signature, Type, Value,
+};
+use cranelift_codegen::settings::CallConv;
+use entropy::Entropy;
+
+/// The maximum number of function parameters.
+const MAX_PARAMS: usize = 4;
+
+/// The maximum number of function return values.
+///
+/// Return values can only be passed in registers, and the x86-64 System V ABI has two return
+/// registers of each class.
+const MAX_RETURNS: usize = 2;
+
+/// The maximum number of parameters of merge EBBs, loop headers, and loop exits, not counting the
+/// loop counter.
+const MAX_EBB_PARAMS: usize = 3;
+
+/// The maximum number of items in a region.
+const MAX_ITEMS: usize = 4;
+
+/// The maximum number of instructions in a straight-line sequence.
+const MAX_SEQUENCE: usize = 8;
+
+/// The maximum nesting depth of control flow constructs.
+const MAX_DEPTH: usize = 3;
+
+/// No control flow constructs are added once a function has this many EBBs.
+const MAX_EBBS: usize = 32;
+
+/// No instructions are added to sequences once a function has this many.
+const MAX_INSTS: usize = 256;
+
+/// The maximum number of arms of a switch, not counting the default.
+const MAX_ARMS: usize = 4;
+
+/// The maximum number of iterations of a counted loop.
+const MAX_ITERATIONS: usize = 4;
+
+/// The types of parameters, return values, and EBB parameters.
+const SCALAR_TYPES: [Type; 7] = [
+    types::I32,
+    types::I64,
+    types::I8,
+    types::I16,
+    types::F32,
+    types::F64,
+    types::B1,
+];
+
+/// Integer types, from narrowest to widest.
+const INT_TYPES: [Type; 4] = [types::I8, types::I16, types::I32, types::I64];
+
+/// Integer types for shifts, rotates, and high multiplications.
+///
+/// The x86 backend widens narrow shifts without reducing the shift amount modulo the original
+/// width, so narrow shifts would give different results than the interpreter.
+const WIDE_INT_TYPES: [Type; 2] = [types::I32, types::I64];
+
+const FLOAT_TYPES: [Type; 2] = [types::F32, types::F64];
+
+const INT_CONDS: [IntCC; 10] = [
+    IntCC::Equal,
+    IntCC::NotEqual,
+    IntCC::SignedLessThan,
+    IntCC::SignedGreaterThanOrEqual,
+    IntCC::SignedGreaterThan,
+    IntCC::SignedLessThanOrEqual,
+    IntCC::UnsignedLessThan,
+    IntCC::UnsignedGreaterThanOrEqual,
+    IntCC::UnsignedGreaterThan,
+    IntCC::UnsignedLessThanOrEqual,
+];
+
+const FLOAT_CONDS: [FloatCC; 14] = [
+    FloatCC::Ordered,
+    FloatCC::Unordered,
+    FloatCC::Equal,
+    FloatCC::NotEqual,
+    FloatCC::OrderedNotEqual,
+    FloatCC::UnorderedOrEqual,
+    FloatCC::LessThan,
+    FloatCC::LessThanOrEqual,
+    FloatCC::GreaterThan,
+    FloatCC::GreaterThanOrEqual,
+    FloatCC::UnorderedOrLessThan,
+    FloatCC::UnorderedOrLessThanOrEqual,
+    FloatCC::UnorderedOrGreaterThan,
+    FloatCC::UnorderedOrGreaterThanOrEqual,
+];
+
+/// Integer constants that are more likely to find bugs than random bits.
+const INTERESTING_INTS: [i64; 8] = [0, 1, -1, 2, 0x7f, 0x80, 0x7fff_ffff, i64::min_value()];
+
+const INTERESTING_FLOATS: [f64; 8] = [0.0, -0.0, 1.0, -1.5, 0.1, 1.0e10, 1.0e-40, 4.5e15];
+
+/// An instruction the generator can produce.
+///
+/// Each kind of instruction determines how the types of the operands and results are chosen.
+#[derive(Clone, Copy)]
+enum Op {
+    /// An integer instruction with two operands of the same type as the result.
+    IntBinary(Opcode),
+    /// Like `IntBinary`, but only for the `WIDE_INT_TYPES`.
+    WideIntBinary(Opcode),
+    /// An integer division or remainder, with the divisor adjusted so it doesn't trap.
+    IntDivision(Opcode),
+    /// An integer instruction with one operand of the same type as the result.
+    IntUnary(Opcode),
+    /// `icmp` with a random condition.
+    IntCompare,
+    /// A float instruction with two operands of the same type as the result.
+    FloatBinary(Opcode),
+    /// A float instruction with one operand of the same type as the result.
+    FloatUnary(Opcode),
+    /// `fcmp` with a random condition.
+    FloatCompare,
+    Select,
+    Bint,
+    /// `uextend` or `sextend` to a wider integer type.
+    Extend(Opcode),
+    Ireduce,
+    Fpromote,
+    Fdemote,
+    /// `fcvt_from_sint` or `fcvt_from_uint` from an integer in `WIDE_INT_TYPES`.
+    IntToFloat(Opcode),
+    /// `bitcast` between an integer and a float of the same width.
+    Bitcast,
+}
+
+const OPS: [Op; 49] = [
+    Op::IntBinary(Opcode::Iadd),
+    Op::IntBinary(Opcode::Isub),
+    Op::IntBinary(Opcode::Imul),
+    Op::IntBinary(Opcode::Band),
+    Op::IntBinary(Opcode::Bor),
+    Op::IntBinary(Opcode::Bxor),
+    Op::IntBinary(Opcode::BandNot),
+    Op::IntBinary(Opcode::BorNot),
+    Op::IntBinary(Opcode::BxorNot),
+    Op::IntBinary(Opcode::Imin),
+    Op::IntBinary(Opcode::Imax),
+    Op::IntBinary(Opcode::Umin),
+    Op::IntBinary(Opcode::Umax),
+    Op::WideIntBinary(Opcode::Umulhi),
+    Op::WideIntBinary(Opcode::Smulhi),
+    Op::WideIntBinary(Opcode::Ishl),
+    Op::WideIntBinary(Opcode::Ushr),
+    Op::WideIntBinary(Opcode::Sshr),
+    Op::WideIntBinary(Opcode::Rotl),
+    Op::WideIntBinary(Opcode::Rotr),
+    Op::IntDivision(Opcode::Udiv),
+    Op::IntDivision(Opcode::Sdiv),
+    Op::IntDivision(Opcode::Urem),
+    Op::IntDivision(Opcode::Srem),
+    Op::IntUnary(Opcode::Bnot),
+    Op::IntUnary(Opcode::Clz),
+    Op::IntUnary(Opcode::Ctz),
+    Op::IntUnary(Opcode::Popcnt),
+    Op::IntUnary(Opcode::Bitrev),
+    Op::IntUnary(Opcode::Iabs),
+    Op::IntCompare,
+    Op::FloatBinary(Opcode::Fadd),
+    Op::FloatBinary(Opcode::Fsub),
+    Op::FloatBinary(Opcode::Fmul),
+    Op::FloatBinary(Opcode::Fdiv),
+    Op::FloatBinary(Opcode::Fmin),
+    Op::FloatBinary(Opcode::Fmax),
+    Op::FloatBinary(Opcode::Fcopysign),
+    Op::FloatBinary(Opcode::FminPseudo),
+    Op::FloatBinary(Opcode::FmaxPseudo),
+    Op::FloatUnary(Opcode::Fneg),
+    Op::FloatUnary(Opcode::Fabs),
+    Op::FloatUnary(Opcode::Sqrt),
+    Op::FloatCompare,
+    Op::Select,
+    Op::Bint,
+    Op::Extend(Opcode::Uextend),
+    Op::Extend(Opcode::Sextend),
+    Op::Ireduce,
+];
+
+/// Instructions converting between types, which are chosen less often than the `OPS`.
+const CONVERSIONS: [Op; 6] = [
+    Op::Fpromote,
+    Op::Fdemote,
+    Op::IntToFloat(Opcode::FcvtFromSint),
+    Op::IntToFloat(Opcode::FcvtFromUint),
+    Op::Bitcast,
+    Op::Bitcast,
+];
+
+/// Generate a function from the bytes in `data`.
+///
+/// The function passes the verifier, and the same bytes always produce the same function.
+pub fn generate_function(data: &[u8]) -> Function {
+    let mut entropy = Entropy::new(data);
+    let mut sig = Signature::new(CallConv::SystemV);
+    for _ in 0..entropy.below(MAX_PARAMS + 1) {
+        sig.params.push(AbiParam::new(*entropy.choose(&SCALAR_TYPES)));
+    }
+    for _ in 0..entropy.below(MAX_RETURNS + 1) {
+        sig.returns
+            .push(AbiParam::new(*entropy.choose(&SCALAR_TYPES)));
+    }
+
+    let mut generator = Generator::new(entropy, sig);
+    generator.region(0);
+    let returns: Vec<Type> = generator
+        .func
+        .signature
+        .returns
+        .iter()
+        .map(|p| p.value_type)
+        .collect();
+    let values = generator.values(&returns);
+    generator.cursor().ins().return_(&values);
+    generator.func
+}
+
+/// The state of the generator while it builds a function.
+struct Generator<'a> {
+    entropy: Entropy<'a>,
+    func: Function,
+    /// The EBB instructions are appended to.
+    ebb: Ebb,
+    /// The values that can be used by instructions appended to `ebb`.
+    values: Vec<Value>,
+}
+
+impl<'a> Generator<'a> {
+    fn new(entropy: Entropy<'a>, sig: Signature) -> Self {
+        let mut func = Function::with_name_signature(ExternalName::testcase("fuzz"), sig);
+        let entry = func.dfg.make_ebb();
+        func.layout.append_ebb(entry);
+        let params: Vec<Type> = func.signature.params.iter().map(|p| p.value_type).collect();
+        let values = params
+            .into_iter()
+            .map(|ty| func.dfg.append_ebb_param(entry, ty))
+            .collect();
+        Self {
+            entropy,
+            func,
+            ebb: entry,
+            values,
+        }
+    }
+
+    /// Get a cursor for appending instructions to the current EBB.
+    fn cursor(&mut self) -> FuncCursor {
+        FuncCursor::new(&mut self.func).at_bottom(self.ebb)
+    }
+
+    /// Start appending instructions to `ebb`, which is added to the end of the layout.
+    fn switch_to(&mut self, ebb: Ebb) {
+        self.func.layout.append_ebb(ebb);
+        self.ebb = ebb;
+    }
+
+    /// Create an EBB with parameters of the given types.
+    fn make_ebb(&mut self, params: &[Type]) -> (Ebb, Vec<Value>) {
+        let ebb = self.func.dfg.make_ebb();
+        let values = params
+            .iter()
+            .map(|&ty| self.func.dfg.append_ebb_param(ebb, ty))
+            .collect();
+        (ebb, values)
+    }
+
+    /// Choose the types of the parameters of an EBB.
+    fn ebb_param_types(&mut self) -> Vec<Type> {
+        (0..self.entropy.below(MAX_EBB_PARAMS + 1))
+            .map(|_| *self.entropy.choose(&SCALAR_TYPES))
+            .collect()
+    }
+
+    /// Get a value of type `ty`, which is either an existing value or a new constant.
+    fn value(&mut self, ty: Type) -> Value {
+        let candidates: Vec<Value> = self
+            .values
+            .iter()
+            .cloned()
+            .filter(|&v| self.func.dfg.value_type(v) == ty)
+            .collect();
+        if !candidates.is_empty() && self.entropy.below(4) != 3 {
+            return *self.entropy.choose(&candidates);
+        }
+        let value = self.constant(ty);
+        self.values.push(value);
+        value
+    }
+
+    /// Get values of the given types.
+    fn values(&mut self, tys: &[Type]) -> Vec<Value> {
+        tys.iter().map(|&ty| self.value(ty)).collect()
+    }
+
+    /// Insert a constant of type `ty`.
+    fn constant(&mut self, ty: Type) -> Value {
+        let interesting = self.entropy.below(2) == 0;
+        match ty {
+            types::B1 => {
+                let b = self.entropy.below(2) != 0;
+                self.cursor().ins().bconst(ty, b)
+            }
+            types::F32 => {
+                let x = if interesting {
+                    Ieee32::with_float(*self.entropy.choose(&INTERESTING_FLOATS) as f32)
+                } else {
+                    Ieee32::with_bits(self.entropy.bits(4) as u32)
+                };
+                self.cursor().ins().f32const(x)
+            }
+            types::F64 => {
+                let x = if interesting {
+                    Ieee64::with_float(*self.entropy.choose(&INTERESTING_FLOATS))
+                } else {
+                    Ieee64::with_bits(self.entropy.bits(8))
+                };
+                self.cursor().ins().f64const(x)
+            }
+            _ => {
+                let x = if interesting {
+                    *self.entropy.choose(&INTERESTING_INTS)
+                } else {
+                    self.entropy.bits(ty.bytes() as usize) as i64
+                };
+                // Keep the immediate in the range of `ty`, sign-extended.
+                let shift = 64 - ty.bits();
+                self.cursor().ins().iconst(ty, (x << shift) >> shift)
+            }
+        }
+    }
+
+    /// Append the items of a region nested `depth` control flow constructs deep.
+    fn region(&mut self, depth: usize) {
+        for _ in 0..self.entropy.below(MAX_ITEMS + 1) {
+            let nest = depth < MAX_DEPTH && self.func.dfg.num_ebbs() < MAX_EBBS;
+            match if nest { self.entropy.below(4) } else { 0 } {
+                0 => self.sequence(),
+                1 => self.conditional(depth),
+                2 => self.switch(depth),
+                _ => self.counted_loop(depth),
+            }
+        }
+    }
+
+    /// Append a straight-line sequence of instructions.
+    fn sequence(&mut self) {
+        for _ in 0..=self.entropy.below(MAX_SEQUENCE) {
+            if self.func.dfg.num_insts() >= MAX_INSTS {
+                return;
+            }
+            let op = if self.entropy.below(8) == 7 {
+                *self.entropy.choose(&CONVERSIONS)
+            } else {
+                *self.entropy.choose(&OPS)
+            };
+            let inst = self.instruction(op);
+            let result = self.func.dfg.first_result(inst);
+            self.values.push(result);
+        }
+    }
+
+    /// Append an instruction for `op`.
+    fn instruction(&mut self, op: Op) -> Inst {
+        match op {
+            Op::IntBinary(opcode) | Op::WideIntBinary(opcode) => {
+                let ty = match op {
+                    Op::IntBinary(_) => *self.entropy.choose(&INT_TYPES),
+                    _ => *self.entropy.choose(&WIDE_INT_TYPES),
+                };
+                let x = self.value(ty);
+                let y = self.value(ty);
+                self.cursor().ins().Binary(opcode, ty, x, y).0
+            }
+            Op::IntDivision(opcode) => {
+                let ty = *self.entropy.choose(&INT_TYPES);
+                let x = self.value(ty);
+                let y = self.value(ty);
+                // Make the divisor positive for signed division, so `MIN / -1` can't overflow,
+                // and make it odd so it isn't zero.
+                let y = match opcode {
+                    Opcode::Sdiv | Opcode::Srem => {
+                        let max = (!0u64 >> (65 - ty.bits())) as i64;
+                        self.cursor().ins().band_imm(y, max)
+                    }
+                    _ => y,
+                };
+                let y = self.cursor().ins().bor_imm(y, 1);
+                self.cursor().ins().Binary(opcode, ty, x, y).0
+            }
+            Op::IntUnary(opcode) => {
+                let ty = *self.entropy.choose(&INT_TYPES);
+                let x = self.value(ty);
+                self.cursor().ins().Unary(opcode, ty, x).0
+            }
+            Op::IntCompare => {
+                let ty = *self.entropy.choose(&INT_TYPES);
+                let cond = *self.entropy.choose(&INT_CONDS);
+                let x = self.value(ty);
+                let y = self.value(ty);
+                self.cursor()
+                    .ins()
+                    .IntCompare(Opcode::Icmp, ty, cond, x, y)
+                    .0
+            }
+            Op::FloatBinary(opcode) => {
+                let ty = *self.entropy.choose(&FLOAT_TYPES);
+                let x = self.value(ty);
+                let y = self.value(ty);
+                self.cursor().ins().Binary(opcode, ty, x, y).0
+            }
+            Op::FloatUnary(opcode) => {
+                let ty = *self.entropy.choose(&FLOAT_TYPES);
+                let x = self.value(ty);
+                self.cursor().ins().Unary(opcode, ty, x).0
+            }
+            Op::FloatCompare => {
+                let ty = *self.entropy.choose(&FLOAT_TYPES);
+                let cond = *self.entropy.choose(&FLOAT_CONDS);
+                let x = self.value(ty);
+                let y = self.value(ty);
+                self.cursor()
+                    .ins()
+                    .FloatCompare(Opcode::Fcmp, ty, cond, x, y)
+                    .0
+            }
+            Op::Select => {
+                let ty = *self.entropy.choose(&SCALAR_TYPES);
+                let c = self.value(types::B1);
+                let x = self.value(ty);
+                let y = self.value(ty);
+                self.cursor().ins().Ternary(Opcode::Select, ty, c, x, y).0
+            }
+            Op::Bint => {
+                let ty = *self.entropy.choose(&INT_TYPES);
+                let b = self.value(types::B1);
+                self.cursor().ins().Unary(Opcode::Bint, ty, b).0
+            }
+            Op::Extend(_) | Op::Ireduce => {
+                let narrow = self.entropy.below(INT_TYPES.len() - 1);
+                let wide = narrow + 1 + self.entropy.below(INT_TYPES.len() - 1 - narrow);
+                let (from, to, opcode) = match op {
+                    Op::Extend(opcode) => (INT_TYPES[narrow], INT_TYPES[wide], opcode),
+                    _ => (INT_TYPES[wide], INT_TYPES[narrow], Opcode::Ireduce),
+                };
+                let x = self.value(from);
+                self.cursor().ins().Unary(opcode, to, x).0
+            }
+            Op::Fpromote => {
+                let x = self.value(types::F32);
+                self.cursor().ins().Unary(Opcode::Fpromote, types::F64, x).0
+            }
+            Op::Fdemote => {
+                let x = self.value(types::F64);
+                self.cursor().ins().Unary(Opcode::Fdemote, types::F32, x).0
+            }
+            Op::IntToFloat(opcode) => {
+                let from = *self.entropy.choose(&WIDE_INT_TYPES);
+                let to = *self.entropy.choose(&FLOAT_TYPES);
+                let x = self.value(from);
+                self.cursor().ins().Unary(opcode, to, x).0
+            }
+            Op::Bitcast => {
+                let (from, to) = *self.entropy.choose(&[
+                    (types::I32, types::F32),
+                    (types::F32, types::I32),
+                    (types::I64, types::F64),
+                    (types::F64, types::I64),
+                ]);
+                let x = self.value(from);
+                self.cursor().ins().Unary(Opcode::Bitcast, to, x).0
+            }
+        }
+    }
+
+    /// Append the region of an arm of a conditional or switch, starting in `ebb`, and jump to
+    /// `merge` at the end.
+    fn arm(&mut self, ebb: Ebb, merge: Ebb, merge_types: &[Type], depth: usize) {
+        let saved = self.values.len();
+        self.switch_to(ebb);
+        self.region(depth + 1);
+        let args = self.values(merge_types);
+        self.cursor().ins().jump(merge, &args);
+        self.values.truncate(saved);
+    }
+
+    /// Append a conditional with two arms.
+    fn conditional(&mut self, depth: usize) {
+        let c = self.value(types::B1);
+        let merge_types = self.ebb_param_types();
+        let (merge, merge_params) = self.make_ebb(&merge_types);
+        let (then_ebb, _) = self.make_ebb(&[]);
+        let (else_ebb, _) = self.make_ebb(&[]);
+
+        if self.entropy.below(2) == 0 {
+            self.cursor().ins().brz(c, else_ebb, &[]);
+        } else {
+            self.cursor().ins().brnz(c, else_ebb, &[]);
+        }
+        self.cursor().ins().jump(then_ebb, &[]);
+        self.arm(then_ebb, merge, &merge_types, depth);
+        self.arm(else_ebb, merge, &merge_types, depth);
+
+        self.switch_to(merge);
+        self.values.extend(merge_params);
+    }
+
+    /// Append a switch, with a `br_table` branching to a number of arms and a default arm.
+    fn switch(&mut self, depth: usize) {
+        let index_type = *self.entropy.choose(&WIDE_INT_TYPES);
+        let index = self.value(index_type);
+        let merge_types = self.ebb_param_types();
+        let (merge, merge_params) = self.make_ebb(&merge_types);
+        let arms: Vec<Ebb> = (0..=self.entropy.below(MAX_ARMS))
+            .map(|_| self.make_ebb(&[]).0)
+            .collect();
+        let (default, _) = self.make_ebb(&[]);
+
+        // Every arm has an entry, followed by a few extra entries for arms chosen at random.
+        let mut table = JumpTableData::new();
+        for &arm in &arms {
+            table.push_entry(arm);
+        }
+        for _ in 0..self.entropy.below(MAX_ARMS) {
+            table.push_entry(*self.entropy.choose(&arms));
+        }
+        let table = self.func.create_jump_table(table);
+        self.cursor().ins().br_table(index, default, table);
+
+        for &arm in arms.iter().chain(Some(&default)) {
+            self.arm(arm, merge, &merge_types, depth);
+        }
+
+        self.switch_to(merge);
+        self.values.extend(merge_params);
+    }
+
+    /// Append a loop that runs its body a fixed number of times.
+    ///
+    /// The loop header has a counter parameter in addition to the loop-carried values, which is
+    /// decremented at the end of the body. The loop exits when it reaches zero.
+    fn counted_loop(&mut self, depth: usize) {
+        let carried_types = self.ebb_param_types();
+        let initial = self.values(&carried_types);
+        let iterations = self.entropy.below(MAX_ITERATIONS) as i64 + 1;
+        let count = self.cursor().ins().iconst(types::I32, iterations);
+
+        let mut header_types = vec![types::I32];
+        header_types.extend_from_slice(&carried_types);
+        let (header, header_params) = self.make_ebb(&header_types);
+        let (exit, exit_params) = self.make_ebb(&carried_types);
+        let mut args = vec![count];
+        args.extend(initial);
+        self.cursor().ins().jump(header, &args);
+
+        let saved = self.values.len();
+        self.switch_to(header);
+        self.values.extend_from_slice(&header_params);
+        self.region(depth + 1);
+        let count = self.cursor().ins().iadd_imm(header_params[0], -1);
+        let carried = self.values(&carried_types);
+        let mut args = vec![count];
+        args.extend_from_slice(&carried);
+        self.cursor().ins().brnz(count, header, &args);
+        self.cursor().ins().jump(exit, &carried);
+        self.values.truncate(saved);
+
+        self.switch_to(exit);
+        self.values.extend(exit_params);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cranelift_codegen::settings;
+    use cranelift_codegen::verify_function;
+
+    /// Generate `len` pseudo-random bytes from `seed`.
+    fn random_bytes(seed: u64, len: usize) -> Vec<u8> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state = state
+                    .wrapping_mul(6_364_136_223_846_793_005)
+                    .wrapping_add(1_442_695_040_888_963_407);
+                (state >> 56) as u8
+            }).collect()
+    }
+
+    #[test]
+    fn empty_input() {
+        let func = generate_function(&[]);
+        assert_eq!(
+            func.to_string(),
+            "function %fuzz() system_v {\nebb0:\n    return\n}\n"
+        );
+    }
+
+    #[test]
+    fn deterministic() {
+        let data = random_bytes(1, 1000);
+        assert_eq!(
+            generate_function(&data).to_string(),
+            generate_function(&data).to_string()
+        );
+    }
+
+    #[test]
+    fn verifies() {
+        let flags = settings::Flags::new(settings::builder());
+        for seed in 0..500 {
+            let func = generate_function(&random_bytes(seed, 2000));
+            if let Err(errors) = verify_function(&func, &flags) {
+                panic!("seed {}: {}\n{}", seed, errors, func.display(None));
+            }
+        }
+    }
+}
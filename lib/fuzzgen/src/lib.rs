@@ -0,0 +1,39 @@
+//! Generator of arbitrary Cranelift IR functions for fuzzing.
+//!
+//! The functions are built from a slice of bytes, typically supplied by a fuzzer, and always pass
+//! the verifier. They take and return scalar values, and their control flow graph is built from
+//! nested conditionals, switches, and counted loops, so it is reducible and every function
+//! terminates. The bytes only steer the choices made by the generator, and running out of bytes
+//! simply ends the function, so any input produces a function.
+
+#![deny(
+    missing_docs,
+    trivial_numeric_casts,
+    unused_extern_crates,
+    unstable_features
+)]
+#![warn(unused_import_braces)]
+#![cfg_attr(feature = "clippy", plugin(clippy(conf_file = "../../clippy.toml")))]
+#![cfg_attr(
+    feature = "cargo-clippy",
+    allow(new_without_default, new_without_default_derive)
+)]
+#![cfg_attr(
+    feature = "cargo-clippy",
+    warn(
+        mut_mut,
+        nonminimal_bool,
+        option_map_unwrap_or,
+        option_map_unwrap_or_else,
+        print_stdout,
+        unicode_not_nfc,
+        use_self
+    )
+)]
+
+extern crate cranelift_codegen;
+
+mod entropy;
+mod generator;
+
+pub use generator::generate_function;
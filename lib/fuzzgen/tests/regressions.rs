@@ -0,0 +1,55 @@
+//! Inputs that made the code generator fail when compiling the generated function.
+//!
+//! Each file in the `regressions` directory is an input reduced from one found by fuzzing. The
+//! generated function is compiled for x86-64 at every optimization level, with the verifier
+//! enabled after every pass.
+
+extern crate cranelift_codegen;
+extern crate cranelift_fuzzgen;
+#[macro_use]
+extern crate target_lexicon;
+
+use cranelift_codegen::print_errors::pretty_error;
+use cranelift_codegen::settings::{self, Configurable};
+use cranelift_codegen::{isa, Context};
+use cranelift_fuzzgen::generate_function;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+#[test]
+fn regressions() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/regressions");
+    let mut paths: Vec<_> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty(), "no regression inputs");
+    for path in &paths {
+        compile(path, &fs::read(path).unwrap());
+    }
+}
+
+/// Compile the function generated from `data` at every optimization level.
+fn compile(path: &Path, data: &[u8]) {
+    let func = generate_function(data);
+    for opt_level in &["fastest", "default", "best"] {
+        let mut flag_builder = settings::builder();
+        flag_builder.enable("enable_verifier").unwrap();
+        flag_builder.set("opt_level", opt_level).unwrap();
+        let isa = isa::lookup(triple!("x86_64"))
+            .unwrap()
+            .finish(settings::Flags::new(flag_builder));
+
+        let mut ctx = Context::for_function(func.clone());
+        if let Err(err) = ctx.compile(&*isa) {
+            panic!(
+                "{}: opt_level={}: {}",
+                path.display(),
+                opt_level,
+                pretty_error(&ctx.func, Some(&*isa), err)
+            );
+        }
+    }
+}
@@ -0,0 +1,1524 @@
+//! Execution of Cranelift IR functions.
+
+use cranelift_codegen::ir::condcodes::{FloatCC, IntCC};
+use cranelift_codegen::ir::immediates::{Ieee32, Ieee64};
+use cranelift_codegen::ir::{
+    types, ArgumentPurpose, Ebb, ExternalName, Function, GlobalValue, GlobalValueData, HeapStyle,
+    Inst, InstructionData, Opcode, StackSlot, TrapCode, Type, Value,
+};
+use cranelift_entity::SecondaryMap;
+use memory::Memory;
+use std::collections::HashMap;
+use std::vec::Vec;
+use value::DataValue;
+
+/// The maximum depth of nested calls. Deeper calls trap with `TrapCode::StackOverflow`.
+const MAX_CALL_DEPTH: usize = 256;
+
+/// The address of the first function, as returned by `func_addr`.
+///
+/// Function addresses are in a part of the address space that is never used for memory, so
+/// functions can't be accessed as data.
+const FUNCTION_ADDRESS_BASE: u64 = 0xffff_0000_0000_0000;
+
+/// The result of calling a function.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// The function returned these values.
+    Return(Vec<DataValue>),
+    /// The function trapped.
+    Trap(TrapCode),
+}
+
+/// An error that prevents a function from being interpreted.
+///
+/// Traps are not errors; they are reported as `Outcome::Trap`.
+#[derive(Fail, Debug, Clone, PartialEq, Eq)]
+pub enum InterpreterError {
+    /// The function uses an instruction or type that the interpreter doesn't support.
+    #[fail(display = "Unsupported: {}", _0)]
+    Unsupported(String),
+
+    /// The function is not valid IR, for example because it uses a value before defining it.
+    #[fail(display = "Invalid function {}: {}", _0, _1)]
+    InvalidFunction(ExternalName, String),
+
+    /// A function was called with arguments that don't match its signature.
+    #[fail(display = "Bad arguments for {}: {}", _0, _1)]
+    BadArguments(ExternalName, String),
+
+    /// A called function hasn't been added to the interpreter.
+    #[fail(display = "Undefined function {}", _0)]
+    UndefinedFunction(ExternalName),
+
+    /// A symbol hasn't been defined with `define_symbol()`.
+    #[fail(display = "Undefined symbol {}", _0)]
+    UndefinedSymbol(ExternalName),
+
+    /// An indirect call to an address that isn't a function address.
+    #[fail(display = "Invalid function address {:#x}", _0)]
+    InvalidFunctionAddress(u64),
+
+    /// The number of executed instructions exceeded the limit set with `set_step_limit()`.
+    #[fail(display = "Step limit exceeded")]
+    StepLimitExceeded,
+}
+
+/// A convenient alias for a `Result` that uses `InterpreterError` as the error type.
+pub type InterpreterResult<T> = Result<T, InterpreterError>;
+
+/// The reasons for stopping the execution of a function early.
+enum Stop {
+    Error(InterpreterError),
+    Trap(TrapCode),
+}
+
+impl From<InterpreterError> for Stop {
+    fn from(e: InterpreterError) -> Self {
+        Stop::Error(e)
+    }
+}
+
+/// Where to continue after executing an instruction.
+enum Control<'f> {
+    /// Continue with the next instruction.
+    Next,
+    /// Continue at the start of an EBB, with the given values for its parameters.
+    Jump(Ebb, Vec<DataValue>),
+    /// Call a function, and continue with the next instruction when it returns.
+    Call(&'f Function, Vec<DataValue>),
+    /// Return from the function.
+    Return(Vec<DataValue>),
+}
+
+/// The state of a function being executed.
+struct Frame<'f> {
+    func: &'f Function,
+    values: SecondaryMap<Value, Option<DataValue>>,
+    stack_slots: SecondaryMap<StackSlot, u64>,
+    /// The EBB being executed.
+    ebb: Ebb,
+    /// The next instruction to execute, or `None` at the end of `ebb`.
+    next: Option<Inst>,
+    /// The call instruction waiting for a callee to return.
+    call: Option<Inst>,
+    /// The memory mark to release the stack slots when the function returns.
+    mark: usize,
+}
+
+impl<'f> Frame<'f> {
+    /// Continue execution at the start of `ebb`, passing `args` to its parameters.
+    fn jump(&mut self, ebb: Ebb, args: &[DataValue]) {
+        for (&param, &arg) in self.func.dfg.ebb_params(ebb).iter().zip(args) {
+            self.set(param, arg);
+        }
+        self.ebb = ebb;
+        self.next = self.func.layout.first_inst(ebb);
+    }
+
+    /// Assign `values` to the results of the call instruction that was waiting for them.
+    fn finish_call(&mut self, values: &[DataValue]) -> Result<(), Stop> {
+        let inst = self.call.take().expect("no call in progress");
+        let results = self.func.dfg.inst_results(inst);
+        if results.len() != values.len() {
+            return Err(self.invalid(format!(
+                "{} expects {} results, got {}",
+                inst,
+                results.len(),
+                values.len()
+            )));
+        }
+        for (&result, &v) in results.iter().zip(values) {
+            self.set(result, v);
+        }
+        Ok(())
+    }
+
+    fn invalid(&self, msg: String) -> Stop {
+        Stop::Error(InterpreterError::InvalidFunction(
+            self.func.name.clone(),
+            msg,
+        ))
+    }
+
+    fn get(&self, value: Value) -> Result<DataValue, Stop> {
+        let value = self.func.dfg.resolve_aliases(value);
+        match self.values[value] {
+            Some(v) => Ok(v),
+            None => Err(self.invalid(format!("{} is used before it is defined", value))),
+        }
+    }
+
+    fn get_all(&self, values: &[Value]) -> Result<Vec<DataValue>, Stop> {
+        values.iter().map(|&v| self.get(v)).collect()
+    }
+
+    fn set(&mut self, value: Value, v: DataValue) {
+        self.values[value] = Some(v);
+    }
+}
+
+/// An interpreter for Cranelift IR functions.
+///
+/// Functions that call other functions need the callees to be added with `add_function()`. They
+/// are looked up by their names. Memory for heaps and global values is provided with
+/// `allocate()`, and symbols referenced by global values are defined with `define_symbol()`.
+///
+/// The interpreter executes the IR as written, without legalizing it first. It supports scalar
+/// integer, boolean, and floating point types, but not vectors or CPU flags.
+pub struct Interpreter<'a> {
+    functions: Vec<&'a Function>,
+    function_indexes: HashMap<ExternalName, usize>,
+    symbols: HashMap<ExternalName, u64>,
+    memory: Memory,
+    step_limit: Option<u64>,
+}
+
+impl<'a> Default for Interpreter<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> Interpreter<'a> {
+    /// Create an interpreter without any functions or memory.
+    pub fn new() -> Self {
+        Self {
+            functions: Vec::new(),
+            function_indexes: HashMap::new(),
+            symbols: HashMap::new(),
+            memory: Memory::new(),
+            step_limit: None,
+        }
+    }
+
+    /// Make `func` available to be called by name, replacing any function with the same name.
+    pub fn add_function(&mut self, func: &'a Function) {
+        let index = self.functions.len();
+        self.functions.push(func);
+        self.function_indexes.insert(func.name.clone(), index);
+    }
+
+    /// Allocate a region of memory initialized with `data`, and return its address.
+    ///
+    /// The memory is accessible to the interpreted functions until the interpreter is dropped.
+    pub fn allocate(&mut self, data: Vec<u8>) -> u64 {
+        self.memory.allocate(data)
+    }
+
+    /// Get the contents of the `size` bytes of memory at `address`.
+    ///
+    /// Returns `None` if the bytes are not all in the same allocated region.
+    pub fn read_memory(&self, address: u64, size: usize) -> Option<&[u8]> {
+        self.memory.read(address, size)
+    }
+
+    /// Define the address of the symbol `name` used by `GlobalValueData::Symbol` global values.
+    pub fn define_symbol(&mut self, name: ExternalName, address: u64) {
+        self.symbols.insert(name, address);
+    }
+
+    /// Limit the number of instructions that a call may execute, to detect infinite loops.
+    pub fn set_step_limit(&mut self, limit: Option<u64>) {
+        self.step_limit = limit;
+    }
+
+    /// Call `func` with `args`.
+    pub fn call<'f>(&mut self, func: &'f Function, args: &[DataValue]) -> InterpreterResult<Outcome>
+    where
+        'a: 'f,
+    {
+        let mark = self.memory.mark();
+        let result = self.execute(func, args);
+        self.memory.release(mark);
+        match result {
+            Ok(values) => Ok(Outcome::Return(values)),
+            Err(Stop::Trap(code)) => Ok(Outcome::Trap(code)),
+            Err(Stop::Error(e)) => Err(e),
+        }
+    }
+
+    /// Call the function named `name`, which must have been added with `add_function()`.
+    pub fn call_by_name(
+        &mut self,
+        name: &ExternalName,
+        args: &[DataValue],
+    ) -> InterpreterResult<Outcome> {
+        let func = self.function(name)?;
+        self.call(func, args)
+    }
+
+    fn function(&self, name: &ExternalName) -> InterpreterResult<&'a Function> {
+        match self.function_indexes.get(name) {
+            Some(&index) => Ok(self.functions[index]),
+            None => Err(InterpreterError::UndefinedFunction(name.clone())),
+        }
+    }
+
+    fn function_address(&self, name: &ExternalName) -> InterpreterResult<u64> {
+        match self.function_indexes.get(name) {
+            Some(&index) => Ok(FUNCTION_ADDRESS_BASE + index as u64 * 16),
+            None => Err(InterpreterError::UndefinedFunction(name.clone())),
+        }
+    }
+
+    fn function_at(&self, address: u64) -> InterpreterResult<&'a Function> {
+        let offset = address.wrapping_sub(FUNCTION_ADDRESS_BASE);
+        let index = (offset / 16) as usize;
+        if offset % 16 != 0 || index >= self.functions.len() {
+            return Err(InterpreterError::InvalidFunctionAddress(address));
+        }
+        Ok(self.functions[index])
+    }
+
+    /// Create a frame for calling `func` with `args`.
+    fn enter<'f>(&mut self, func: &'f Function, args: &[DataValue]) -> Result<Frame<'f>, Stop> {
+        let bad_args = |msg: String| InterpreterError::BadArguments(func.name.clone(), msg);
+        let params = &func.signature.params;
+        if args.len() != params.len() {
+            return Err(bad_args(format!(
+                "expected {} arguments, got {}",
+                params.len(),
+                args.len()
+            ))
+            .into());
+        }
+        for (arg, param) in args.iter().zip(params) {
+            if !arg.has_type(param.value_type) {
+                return Err(bad_args(format!("expected {} for {}", param.value_type, arg)).into());
+            }
+        }
+        let entry = match func.layout.entry_block() {
+            Some(ebb) => ebb,
+            None => return Err(bad_args("there is no entry block".to_string()).into()),
+        };
+
+        let mut frame = Frame {
+            func,
+            values: SecondaryMap::new(),
+            stack_slots: SecondaryMap::new(),
+            ebb: entry,
+            next: None,
+            call: None,
+            mark: self.memory.mark(),
+        };
+        for (ss, data) in func.stack_slots.iter() {
+            frame.stack_slots[ss] = self.memory.allocate(vec![0; data.size as usize]);
+        }
+        frame.jump(entry, args);
+        Ok(frame)
+    }
+
+    /// Execute `func` until it returns, and get its results.
+    ///
+    /// Calls are executed with an explicit stack of frames, so deep recursion in the interpreted
+    /// code doesn't overflow the stack of the interpreter.
+    fn execute<'f>(
+        &mut self,
+        func: &'f Function,
+        args: &[DataValue],
+    ) -> Result<Vec<DataValue>, Stop>
+    where
+        'a: 'f,
+    {
+        let mut stack = vec![self.enter(func, args)?];
+        let mut steps = 0;
+        loop {
+            steps += 1;
+            if let Some(limit) = self.step_limit {
+                if steps > limit {
+                    return Err(InterpreterError::StepLimitExceeded.into());
+                }
+            }
+
+            let control = {
+                let frame = stack.last_mut().unwrap();
+                let inst = match frame.next {
+                    Some(inst) => inst,
+                    None => {
+                        let msg = format!("{} has no terminator", frame.ebb);
+                        return Err(frame.invalid(msg));
+                    }
+                };
+                frame.next = frame.func.layout.next_inst(inst);
+                self.step(frame, inst)?
+            };
+
+            match control {
+                Control::Next => {}
+                Control::Jump(ebb, args) => stack.last_mut().unwrap().jump(ebb, &args),
+                Control::Call(callee, args) => {
+                    if stack.len() >= MAX_CALL_DEPTH {
+                        return Err(Stop::Trap(TrapCode::StackOverflow));
+                    }
+                    let frame = self.enter(callee, &args)?;
+                    stack.push(frame);
+                }
+                Control::Return(values) => {
+                    let frame = stack.pop().unwrap();
+                    self.memory.release(frame.mark);
+                    match stack.last_mut() {
+                        Some(caller) => caller.finish_call(&values)?,
+                        None => return Ok(values),
+                    }
+                }
+            }
+        }
+    }
+
+    /// Execute a single instruction.
+    fn step<'f>(&mut self, frame: &mut Frame<'f>, inst: Inst) -> Result<Control<'f>, Stop>
+    where
+        'a: 'f,
+    {
+        let func = frame.func;
+        let dfg = &func.dfg;
+        let opcode = dfg[inst].opcode();
+        let results = dfg.inst_results(inst);
+        let ty = match results.first() {
+            Some(&result) => dfg.value_type(result),
+            None => types::INVALID,
+        };
+        let unsupported = || -> Stop {
+            let what = if ty == types::INVALID {
+                opcode.to_string()
+            } else {
+                format!("{}.{}", opcode, ty)
+            };
+            InterpreterError::Unsupported(what).into()
+        };
+        let make = |ty: Type, bits: u64| DataValue::from_bits(ty, bits).ok_or_else(unsupported);
+
+        let result = match dfg[inst] {
+            InstructionData::UnaryImm { imm, .. } => make(ty, imm_bits(imm))?,
+            InstructionData::UnaryIeee32 { imm, .. } => DataValue::F32(imm),
+            InstructionData::UnaryIeee64 { imm, .. } => DataValue::F64(imm),
+            InstructionData::UnaryBool { imm, .. } => DataValue::B(imm),
+            InstructionData::NullAry {
+                opcode: Opcode::Nop,
+            } => return Ok(Control::Next),
+            InstructionData::Unary { arg, .. } => {
+                let x = frame.get(arg)?;
+                match opcode {
+                    Opcode::Isplit => {
+                        let lo = make(types::I32, x.to_bits())?;
+                        let hi = make(types::I32, x.to_bits() >> 32)?;
+                        frame.set(results[0], lo);
+                        frame.set(results[1], hi);
+                        return Ok(Control::Next);
+                    }
+                    _ => unary(opcode, ty, x)?.ok_or_else(unsupported)?,
+                }
+            }
+            InstructionData::Binary { args, .. } => {
+                let x = frame.get(args[0])?;
+                let y = frame.get(args[1])?;
+                match opcode {
+                    Opcode::Iconcat => make(ty, (y.to_bits() << 32) | x.to_bits())?,
+                    _ => binary(opcode, ty, x, y)?.ok_or_else(unsupported)?,
+                }
+            }
+            InstructionData::BinaryImm { arg, imm, .. } => {
+                let x = frame.get(arg)?;
+                let y = make(ty, imm_bits(imm))?;
+                let (opcode, x, y) = match opcode {
+                    Opcode::IaddImm => (Opcode::Iadd, x, y),
+                    Opcode::ImulImm => (Opcode::Imul, x, y),
+                    Opcode::UdivImm => (Opcode::Udiv, x, y),
+                    Opcode::SdivImm => (Opcode::Sdiv, x, y),
+                    Opcode::UremImm => (Opcode::Urem, x, y),
+                    Opcode::SremImm => (Opcode::Srem, x, y),
+                    Opcode::IrsubImm => (Opcode::Isub, y, x),
+                    Opcode::BandImm => (Opcode::Band, x, y),
+                    Opcode::BorImm => (Opcode::Bor, x, y),
+                    Opcode::BxorImm => (Opcode::Bxor, x, y),
+                    Opcode::RotlImm => (Opcode::Rotl, x, y),
+                    Opcode::RotrImm => (Opcode::Rotr, x, y),
+                    Opcode::IshlImm => (Opcode::Ishl, x, y),
+                    Opcode::UshrImm => (Opcode::Ushr, x, y),
+                    Opcode::SshrImm => (Opcode::Sshr, x, y),
+                    _ => return Err(unsupported()),
+                };
+                binary(opcode, ty, x, y)?.ok_or_else(unsupported)?
+            }
+            InstructionData::Ternary { args, .. } => {
+                let x = frame.get(args[0])?;
+                let y = frame.get(args[1])?;
+                let z = frame.get(args[2])?;
+                match (opcode, x, y, z) {
+                    (Opcode::Select, c, x, y) => {
+                        if c.to_bits() != 0 {
+                            x
+                        } else {
+                            y
+                        }
+                    }
+                    (Opcode::Fma, DataValue::F32(x), DataValue::F32(y), DataValue::F32(z)) => {
+                        DataValue::F32(Ieee32::with_float(f32_of(x).mul_add(f32_of(y), f32_of(z))))
+                    }
+                    (Opcode::Fma, DataValue::F64(x), DataValue::F64(y), DataValue::F64(z)) => {
+                        DataValue::F64(Ieee64::with_float(f64_of(x).mul_add(f64_of(y), f64_of(z))))
+                    }
+                    _ => return Err(unsupported()),
+                }
+            }
+            InstructionData::IntCompare { cond, args, .. } => {
+                let x = frame.get(args[0])?;
+                let y = frame.get(args[1])?;
+                DataValue::B(icmp(cond, x, y))
+            }
+            InstructionData::IntCompareImm { cond, arg, imm, .. } => {
+                let x = frame.get(arg)?;
+                let ctrl_ty = dfg.ctrl_typevar(inst);
+                let y = make(ctrl_ty, imm_bits(imm))?;
+                DataValue::B(icmp(cond, x, y))
+            }
+            InstructionData::FloatCompare { cond, args, .. } => {
+                let x = frame.get(args[0])?;
+                let y = frame.get(args[1])?;
+                DataValue::B(fcmp(cond, x, y).ok_or_else(unsupported)?)
+            }
+
+            InstructionData::Jump { destination, .. } => {
+                let args = frame.get_all(dfg.inst_variable_args(inst))?;
+                return Ok(Control::Jump(destination, args));
+            }
+            InstructionData::Branch { destination, .. } => {
+                let c = frame.get(dfg.inst_fixed_args(inst)[0])?;
+                let taken = match opcode {
+                    Opcode::Brz => c.to_bits() == 0,
+                    Opcode::Brnz => c.to_bits() != 0,
+                    _ => return Err(unsupported()),
+                };
+                return Ok(if taken {
+                    let args = frame.get_all(dfg.inst_variable_args(inst))?;
+                    Control::Jump(destination, args)
+                } else {
+                    Control::Next
+                });
+            }
+            InstructionData::BranchIcmp {
+                cond, destination, ..
+            } => {
+                let fixed = dfg.inst_fixed_args(inst);
+                let x = frame.get(fixed[0])?;
+                let y = frame.get(fixed[1])?;
+                return Ok(if icmp(cond, x, y) {
+                    let args = frame.get_all(dfg.inst_variable_args(inst))?;
+                    Control::Jump(destination, args)
+                } else {
+                    Control::Next
+                });
+            }
+            InstructionData::BranchTable {
+                arg,
+                destination,
+                table,
+                ..
+            } => {
+                let index = frame.get(arg)?.to_bits();
+                let entry = if index < func.jump_tables[table].len() as u64 {
+                    func.jump_tables[table].get_entry(index as usize)
+                } else {
+                    None
+                };
+                let dest = entry.unwrap_or(destination);
+                return Ok(Control::Jump(dest, Vec::new()));
+            }
+            InstructionData::MultiAry { .. } => match opcode {
+                Opcode::Return | Opcode::FallthroughReturn => {
+                    return Ok(Control::Return(frame.get_all(dfg.inst_args(inst))?));
+                }
+                _ => return Err(unsupported()),
+            },
+
+            InstructionData::Trap { code, .. } => return Err(Stop::Trap(code)),
+            InstructionData::CondTrap { arg, code, .. } => {
+                let c = frame.get(arg)?;
+                let trap = match opcode {
+                    Opcode::Trapz => c.to_bits() == 0,
+                    Opcode::Trapnz => c.to_bits() != 0,
+                    _ => return Err(unsupported()),
+                };
+                if trap {
+                    return Err(Stop::Trap(code));
+                }
+                return Ok(Control::Next);
+            }
+
+            InstructionData::Call { func_ref, .. } => {
+                let callee = self.function(&dfg.ext_funcs[func_ref].name)?;
+                let args = frame.get_all(dfg.inst_args(inst))?;
+                frame.call = Some(inst);
+                return Ok(Control::Call(callee, args));
+            }
+            InstructionData::CallIndirect { .. } => {
+                let fixed = dfg.inst_fixed_args(inst);
+                let callee = self.function_at(frame.get(fixed[0])?.to_bits())?;
+                let args = frame.get_all(dfg.inst_variable_args(inst))?;
+                frame.call = Some(inst);
+                return Ok(Control::Call(callee, args));
+            }
+            InstructionData::FuncAddr { func_ref, .. } => {
+                make(ty, self.function_address(&dfg.ext_funcs[func_ref].name)?)?
+            }
+
+            InstructionData::Load { arg, offset, .. } => {
+                let address = frame.get(arg)?.to_bits();
+                self.load(opcode, ty, address.wrapping_add(imm_bits(offset)))?
+                    .ok_or_else(unsupported)?
+            }
+            InstructionData::LoadComplex { offset, .. } => {
+                let address = sum(&frame.get_all(dfg.inst_args(inst))?);
+                self.load(opcode, ty, address.wrapping_add(imm_bits(offset)))?
+                    .ok_or_else(unsupported)?
+            }
+            InstructionData::Store { args, offset, .. } => {
+                let x = frame.get(args[0])?;
+                let address = frame.get(args[1])?.to_bits();
+                let x_ty = dfg.value_type(args[0]);
+                self.store(opcode, x_ty, x, address.wrapping_add(imm_bits(offset)))?;
+                return Ok(Control::Next);
+            }
+            InstructionData::StoreComplex { offset, .. } => {
+                let args = dfg.inst_args(inst);
+                let x = frame.get(args[0])?;
+                let address = sum(&frame.get_all(&args[1..])?);
+                let x_ty = dfg.value_type(args[0]);
+                self.store(opcode, x_ty, x, address.wrapping_add(imm_bits(offset)))?;
+                return Ok(Control::Next);
+            }
+            InstructionData::StackLoad {
+                stack_slot, offset, ..
+            } => {
+                let address = frame.stack_slots[stack_slot].wrapping_add(imm_bits(offset));
+                match opcode {
+                    Opcode::StackLoad => self
+                        .load(Opcode::Load, ty, address)?
+                        .ok_or_else(unsupported)?,
+                    Opcode::StackAddr => make(ty, address)?,
+                    _ => return Err(unsupported()),
+                }
+            }
+            InstructionData::StackStore {
+                arg,
+                stack_slot,
+                offset,
+                ..
+            } => {
+                let x = frame.get(arg)?;
+                let address = frame.stack_slots[stack_slot].wrapping_add(imm_bits(offset));
+                self.store(Opcode::Store, dfg.value_type(arg), x, address)?;
+                return Ok(Control::Next);
+            }
+            InstructionData::UnaryGlobalValue { global_value, .. } => {
+                make(ty, self.global_value(frame, global_value)?)?
+            }
+            InstructionData::HeapAddr { arg, heap, imm, .. } => {
+                let heap = &func.heaps[heap];
+                let index = frame.get(arg)?.to_bits();
+                let size: u32 = imm.into();
+                let bound = match heap.style {
+                    HeapStyle::Dynamic { bound_gv } => self.global_value(frame, bound_gv)?,
+                    HeapStyle::Static { bound } => imm_bits(bound),
+                };
+                if index
+                    .checked_add(u64::from(size))
+                    .map_or(true, |end| end > bound)
+                {
+                    return Err(Stop::Trap(TrapCode::HeapOutOfBounds));
+                }
+                let align = u64::from(size & size.wrapping_neg());
+                if heap.check_alignment && align > 1 && index % align != 0 {
+                    return Err(Stop::Trap(TrapCode::HeapMisaligned));
+                }
+                make(ty, self.global_value(frame, heap.base)?.wrapping_add(index))?
+            }
+            InstructionData::TableAddr {
+                arg, table, offset, ..
+            } => {
+                let table = &func.tables[table];
+                let index = frame.get(arg)?.to_bits();
+                if index >= self.global_value(frame, table.bound_gv)? {
+                    return Err(Stop::Trap(TrapCode::TableOutOfBounds));
+                }
+                let element_size: i64 = table.element_size.into();
+                let address = self
+                    .global_value(frame, table.base_gv)?
+                    .wrapping_add(index.wrapping_mul(element_size as u64))
+                    .wrapping_add(imm_bits(offset));
+                make(ty, address)?
+            }
+            _ => return Err(unsupported()),
+        };
+
+        match results.len() {
+            1 => {
+                frame.set(results[0], result);
+                Ok(Control::Next)
+            }
+            _ => Err(unsupported()),
+        }
+    }
+
+    /// Compute the value of a global value.
+    fn global_value(&self, frame: &Frame, gv: GlobalValue) -> Result<u64, Stop> {
+        match frame.func.global_values[gv] {
+            GlobalValueData::VMContext => {
+                match frame.func.special_param(ArgumentPurpose::VMContext) {
+                    Some(vmctx) => Ok(frame.get(vmctx)?.to_bits()),
+                    None => Err(frame.invalid("there is no vmctx parameter".to_string())),
+                }
+            }
+            GlobalValueData::Load {
+                base,
+                offset,
+                global_type,
+                ..
+            } => {
+                let address = self
+                    .global_value(frame, base)?
+                    .wrapping_add(imm_bits(offset));
+                match self.memory.load(address, global_type.bytes() as usize) {
+                    Some(bits) => Ok(bits),
+                    None => Err(Stop::Trap(TrapCode::HeapOutOfBounds)),
+                }
+            }
+            GlobalValueData::IAddImm {
+                base,
+                offset,
+                global_type,
+            } => {
+                let sum = self
+                    .global_value(frame, base)?
+                    .wrapping_add(imm_bits(offset));
+                Ok(truncate(sum, global_type.bits()))
+            }
+            GlobalValueData::IAddScaled {
+                base,
+                index,
+                scale,
+                global_type,
+            } => {
+                let scaled = self
+                    .global_value(frame, index)?
+                    .wrapping_mul(imm_bits(scale));
+                let sum = self.global_value(frame, base)?.wrapping_add(scaled);
+                Ok(truncate(sum, global_type.bits()))
+            }
+            GlobalValueData::Symbol {
+                ref name, offset, ..
+            } => match self.symbols.get(name) {
+                Some(&address) => Ok(address.wrapping_add(imm_bits(offset))),
+                None => Err(InterpreterError::UndefinedSymbol(name.clone()).into()),
+            },
+        }
+    }
+
+    /// Execute the load instruction `opcode` producing a value of type `ty`.
+    ///
+    /// Returns `Ok(None)` if the load is not supported.
+    fn load(&self, opcode: Opcode, ty: Type, address: u64) -> Result<Option<DataValue>, Stop> {
+        let (size, signed) = match opcode {
+            Opcode::Load | Opcode::LoadComplex => (ty.bytes(), false),
+            Opcode::Uload8 | Opcode::Uload8Complex => (1, false),
+            Opcode::Sload8 | Opcode::Sload8Complex => (1, true),
+            Opcode::Uload16 | Opcode::Uload16Complex => (2, false),
+            Opcode::Sload16 | Opcode::Sload16Complex => (2, true),
+            Opcode::Uload32 | Opcode::Uload32Complex => (4, false),
+            Opcode::Sload32 | Opcode::Sload32Complex => (4, true),
+            _ => return Ok(None),
+        };
+        let bits = match self.memory.load(address, size as usize) {
+            Some(bits) => bits,
+            None => return Err(Stop::Trap(TrapCode::HeapOutOfBounds)),
+        };
+        let bits = if signed {
+            sign_extend(bits, size * 8) as u64
+        } else {
+            bits
+        };
+        Ok(DataValue::from_bits(ty, bits))
+    }
+
+    /// Execute the store instruction `opcode`, storing `x` of type `ty`.
+    fn store(&mut self, opcode: Opcode, ty: Type, x: DataValue, address: u64) -> Result<(), Stop> {
+        let size = match opcode {
+            Opcode::Store | Opcode::StoreComplex => ty.bytes(),
+            Opcode::Istore8 | Opcode::Istore8Complex => 1,
+            Opcode::Istore16 | Opcode::Istore16Complex => 2,
+            Opcode::Istore32 | Opcode::Istore32Complex => 4,
+            _ => return Err(InterpreterError::Unsupported(opcode.to_string()).into()),
+        };
+        match self.memory.store(address, size as usize, x.to_bits()) {
+            Some(()) => Ok(()),
+            None => Err(Stop::Trap(TrapCode::HeapOutOfBounds)),
+        }
+    }
+}
+
+/// Get the bits of an immediate, sign-extended to 64 bits.
+fn imm_bits<T: Into<i64>>(x: T) -> u64 {
+    x.into() as u64
+}
+
+/// Add up the addresses given to a complex load or store.
+fn sum(values: &[DataValue]) -> u64 {
+    values
+        .iter()
+        .fold(0, |sum, v| sum.wrapping_add(v.to_i64() as u64))
+}
+
+/// Truncate `x` to its `bits` low bits.
+fn truncate(x: u64, bits: u16) -> u64 {
+    if bits >= 64 {
+        x
+    } else {
+        x & ((1 << bits) - 1)
+    }
+}
+
+/// Sign-extend the `bits` low bits of `x`.
+fn sign_extend(x: u64, bits: u32) -> i64 {
+    let shift = 64 - bits;
+    ((x << shift) as i64) >> shift
+}
+
+fn f32_of(x: Ieee32) -> f32 {
+    f32::from_bits(x.bits())
+}
+
+fn f64_of(x: Ieee64) -> f64 {
+    f64::from_bits(x.bits())
+}
+
+/// Compare two integers.
+fn icmp(cond: IntCC, x: DataValue, y: DataValue) -> bool {
+    let (ux, uy) = (x.to_bits(), y.to_bits());
+    let (sx, sy) = (x.to_i64(), y.to_i64());
+    match cond {
+        IntCC::Equal => ux == uy,
+        IntCC::NotEqual => ux != uy,
+        IntCC::SignedLessThan => sx < sy,
+        IntCC::SignedGreaterThanOrEqual => sx >= sy,
+        IntCC::SignedGreaterThan => sx > sy,
+        IntCC::SignedLessThanOrEqual => sx <= sy,
+        IntCC::UnsignedLessThan => ux < uy,
+        IntCC::UnsignedGreaterThanOrEqual => ux >= uy,
+        IntCC::UnsignedGreaterThan => ux > uy,
+        IntCC::UnsignedLessThanOrEqual => ux <= uy,
+    }
+}
+
+/// Compare two floating point numbers.
+///
+/// Returns `None` if the values are not floats of the same type.
+fn fcmp(cond: FloatCC, x: DataValue, y: DataValue) -> Option<bool> {
+    // Every `f32` can be represented exactly as an `f64`, so all comparisons are done as `f64`.
+    let (x, y) = match (x, y) {
+        (DataValue::F32(x), DataValue::F32(y)) => (f64::from(f32_of(x)), f64::from(f32_of(y))),
+        (DataValue::F64(x), DataValue::F64(y)) => (f64_of(x), f64_of(y)),
+        _ => return None,
+    };
+    let unordered = x.is_nan() || y.is_nan();
+    Some(match cond {
+        FloatCC::Ordered => !unordered,
+        FloatCC::Unordered => unordered,
+        FloatCC::Equal => x == y,
+        FloatCC::NotEqual => x != y,
+        FloatCC::OrderedNotEqual => !unordered && x != y,
+        FloatCC::UnorderedOrEqual => unordered || x == y,
+        FloatCC::LessThan => x < y,
+        FloatCC::LessThanOrEqual => x <= y,
+        FloatCC::GreaterThan => x > y,
+        FloatCC::GreaterThanOrEqual => x >= y,
+        FloatCC::UnorderedOrLessThan => unordered || x < y,
+        FloatCC::UnorderedOrLessThanOrEqual => unordered || x <= y,
+        FloatCC::UnorderedOrGreaterThan => unordered || x > y,
+        FloatCC::UnorderedOrGreaterThanOrEqual => unordered || x >= y,
+    })
+}
+
+/// Is `ty` a scalar type that fits in 64 bits, as opposed to a vector or the CPU flags?
+fn is_scalar(ty: Type) -> bool {
+    ty.lane_count() == 1 && ty.bits() != 0 && ty.bits() <= 64
+}
+
+/// Execute the unary instruction `opcode` with the argument `x`, producing a value of type `ty`.
+///
+/// Returns `Ok(None)` if the instruction is not supported for these types.
+fn unary(opcode: Opcode, ty: Type, x: DataValue) -> Result<Option<DataValue>, Stop> {
+    if !is_scalar(ty) {
+        return Ok(None);
+    }
+    let bits = ty.bits();
+    let ux = x.to_bits();
+    let r = match (opcode, x) {
+        (Opcode::Copy, x) => return Ok(Some(x)),
+        (Opcode::Bnot, _) if ty.is_bool() => !ux & 1,
+        (Opcode::Bnot, _) => !ux,
+        (Opcode::Clz, _) => u64::from(ux.leading_zeros()) - (64 - u64::from(bits)),
+        (Opcode::Ctz, _) if ux == 0 => u64::from(bits),
+        (Opcode::Ctz, _) => u64::from(ux.trailing_zeros()),
+        (Opcode::Popcnt, _) => u64::from(ux.count_ones()),
+        (Opcode::Iabs, _) => x.to_i64().wrapping_abs() as u64,
+        (Opcode::Cls, _) => {
+            let sx = x.to_i64();
+            let same = if sx < 0 { !sx } else { sx } as u64;
+            u64::from(same.leading_zeros()) - (64 - u64::from(bits)) - 1
+        }
+        (Opcode::Bitrev, _) => (0..bits).fold(0, |r, i| (r << 1) | ((ux >> i) & 1)),
+        (Opcode::Bint, DataValue::B(b)) => u64::from(b),
+        (Opcode::Bmask, DataValue::B(b)) => {
+            if b {
+                !0
+            } else {
+                0
+            }
+        }
+        (Opcode::Breduce, DataValue::B(_)) | (Opcode::Bextend, DataValue::B(_)) => ux,
+        (Opcode::Ireduce, _) | (Opcode::Uextend, _) | (Opcode::Bitcast, _) => ux,
+        (Opcode::Sextend, _) => x.to_i64() as u64,
+        (Opcode::Fpromote, DataValue::F32(x)) => f64::from(f32_of(x)).to_bits(),
+        (Opcode::Fdemote, DataValue::F64(x)) => u64::from((f64_of(x) as f32).to_bits()),
+        (Opcode::FcvtFromUint, _) => float_bits(ty, ux as f64, ux as f32),
+        (Opcode::FcvtFromSint, _) => float_bits(ty, x.to_i64() as f64, x.to_i64() as f32),
+        (Opcode::FcvtToUint, _)
+        | (Opcode::FcvtToSint, _)
+        | (Opcode::FcvtToUintSat, _)
+        | (Opcode::FcvtToSintSat, _) => {
+            let x = match x {
+                DataValue::F32(x) => f64::from(f32_of(x)),
+                DataValue::F64(x) => f64_of(x),
+                _ => return Ok(None),
+            };
+            return fcvt_to_int(opcode, ty, x).map(|bits| DataValue::from_bits(ty, bits));
+        }
+        (_, DataValue::F32(x)) => match f32_unary(opcode, f32_of(x)) {
+            Some(r) => u64::from(r.to_bits()),
+            None => return Ok(None),
+        },
+        (_, DataValue::F64(x)) => match f64_unary(opcode, f64_of(x)) {
+            Some(r) => r.to_bits(),
+            None => return Ok(None),
+        },
+        _ => return Ok(None),
+    };
+    Ok(DataValue::from_bits(ty, r))
+}
+
+/// Get the bits of `x64` or `x32`, depending on which float type `ty` is.
+fn float_bits(ty: Type, x64: f64, x32: f32) -> u64 {
+    if ty == types::F32 {
+        u64::from(x32.to_bits())
+    } else {
+        x64.to_bits()
+    }
+}
+
+/// Convert `x` to an integer of type `ty`, as described by the `fcvt_to_*` instruction `opcode`.
+fn fcvt_to_int(opcode: Opcode, ty: Type, x: f64) -> Result<u64, Stop> {
+    let bits = i32::from(ty.bits());
+    let (min, max) = match opcode {
+        Opcode::FcvtToUint | Opcode::FcvtToUintSat => (0.0, 2f64.powi(bits)),
+        _ => (-(2f64.powi(bits - 1)), 2f64.powi(bits - 1)),
+    };
+    let t = x.trunc();
+    let saturate = opcode == Opcode::FcvtToUintSat || opcode == Opcode::FcvtToSintSat;
+    if x.is_nan() {
+        if saturate {
+            return Ok(0);
+        }
+        return Err(Stop::Trap(TrapCode::BadConversionToInteger));
+    }
+    let t = if t < min || t >= max {
+        if !saturate {
+            return Err(Stop::Trap(TrapCode::IntegerOverflow));
+        }
+        if t < min {
+            min
+        } else {
+            // The largest representable value is `max - 1`, which can't always be represented
+            // as an `f64`, so compute it as an integer below.
+            return Ok(if min == 0.0 {
+                !0
+            } else {
+                (1 << (bits - 1)) - 1
+            });
+        }
+    } else {
+        t
+    };
+    Ok(if t < 0.0 { (t as i64) as u64 } else { t as u64 })
+}
+
+/// Execute the binary instruction `opcode` with the arguments `x` and `y`, producing a value of
+/// type `ty`.
+///
+/// Returns `Ok(None)` if the instruction is not supported for these types.
+fn binary(opcode: Opcode, ty: Type, x: DataValue, y: DataValue) -> Result<Option<DataValue>, Stop> {
+    match (x, y) {
+        (DataValue::F32(x), DataValue::F32(y)) => {
+            return Ok(f32_binary(opcode, f32_of(x), f32_of(y))
+                .map(|r| DataValue::F32(Ieee32::with_float(r))))
+        }
+        (DataValue::F64(x), DataValue::F64(y)) => {
+            return Ok(f64_binary(opcode, f64_of(x), f64_of(y))
+                .map(|r| DataValue::F64(Ieee64::with_float(r))))
+        }
+        _ => {}
+    }
+    if !is_scalar(ty) {
+        return Ok(None);
+    }
+
+    let bits = u64::from(ty.bits());
+    let (ux, uy) = (x.to_bits(), y.to_bits());
+    let (sx, sy) = (x.to_i64(), y.to_i64());
+    let min = (!0u64 << (bits - 1)) as i64;
+    let r = match opcode {
+        Opcode::Iadd => ux.wrapping_add(uy),
+        Opcode::Isub => ux.wrapping_sub(uy),
+        Opcode::Imul => ux.wrapping_mul(uy),
+        Opcode::Umulhi => ((u128::from(ux) * u128::from(uy)) >> bits) as u64,
+        Opcode::Smulhi => ((i128::from(sx) * i128::from(sy)) >> bits) as u64,
+        Opcode::Udiv | Opcode::Sdiv | Opcode::Urem | Opcode::Srem if uy == 0 => {
+            return Err(Stop::Trap(TrapCode::IntegerDivisionByZero))
+        }
+        Opcode::Udiv => ux / uy,
+        Opcode::Sdiv if sx == min && sy == -1 => return Err(Stop::Trap(TrapCode::IntegerOverflow)),
+        Opcode::Sdiv => sx.wrapping_div(sy) as u64,
+        Opcode::Urem => ux % uy,
+        Opcode::Srem => sx.wrapping_rem(sy) as u64,
+        Opcode::Imin => sx.min(sy) as u64,
+        Opcode::Imax => sx.max(sy) as u64,
+        Opcode::Umin => ux.min(uy),
+        Opcode::Umax => ux.max(uy),
+        Opcode::Band => ux & uy,
+        Opcode::Bor => ux | uy,
+        Opcode::Bxor => ux ^ uy,
+        Opcode::BandNot => ux & !uy,
+        Opcode::BorNot => ux | !uy,
+        Opcode::BxorNot => ux ^ !uy,
+        Opcode::Ishl => ux << (uy % bits),
+        Opcode::Ushr => ux >> (uy % bits),
+        Opcode::Sshr => (sx >> (uy % bits)) as u64,
+        Opcode::Rotl | Opcode::Rotr => {
+            let amount = uy % bits;
+            let amount = if opcode == Opcode::Rotl {
+                amount
+            } else {
+                (bits - amount) % bits
+            };
+            if amount == 0 {
+                ux
+            } else {
+                (ux << amount) | (ux >> (bits - amount))
+            }
+        }
+        _ => return Ok(None),
+    };
+    let r = if ty.is_bool() { r & 1 } else { r };
+    Ok(DataValue::from_bits(ty, r))
+}
+
+/// Define the floating point operations for one float type.
+macro_rules! float_ops {
+    ($float:ident, $unary:ident, $binary:ident, $copysign:ident) => {
+        /// Execute a unary floating point instruction, or return `None` if it isn't one.
+        fn $unary(opcode: Opcode, x: $float) -> Option<$float> {
+            Some(match opcode {
+                Opcode::Fneg => -x,
+                Opcode::Fabs => x.abs(),
+                Opcode::Sqrt => x.sqrt(),
+                Opcode::Ceil => x.ceil(),
+                Opcode::Floor => x.floor(),
+                Opcode::Trunc => x.trunc(),
+                Opcode::Nearest => {
+                    // `round()` rounds ties away from zero, so fix up the ties that should
+                    // round towards the even neighbor instead.
+                    let r = x.round();
+                    let r = if (r - x).abs() == 0.5 && r % 2.0 != 0.0 {
+                        r - x.signum()
+                    } else {
+                        r
+                    };
+                    $copysign(r, x)
+                }
+                _ => return None,
+            })
+        }
+
+        /// Execute a binary floating point instruction, or return `None` if it isn't one.
+        fn $binary(opcode: Opcode, x: $float, y: $float) -> Option<$float> {
+            Some(match opcode {
+                Opcode::Fadd => x + y,
+                Opcode::Fsub => x - y,
+                Opcode::Fmul => x * y,
+                Opcode::Fdiv => x / y,
+                Opcode::Fcopysign => $copysign(x, y),
+                Opcode::Fmin | Opcode::Fmax => {
+                    // Unlike `min()` and `max()`, these propagate NaNs and order -0.0 before
+                    // 0.0.
+                    if x.is_nan() || y.is_nan() {
+                        x + y
+                    } else if x == y {
+                        if x.is_sign_negative() == (opcode == Opcode::Fmin) {
+                            x
+                        } else {
+                            y
+                        }
+                    } else if (x < y) == (opcode == Opcode::Fmin) {
+                        x
+                    } else {
+                        y
+                    }
+                }
+                Opcode::FminPseudo => {
+                    if x < y {
+                        x
+                    } else {
+                        y
+                    }
+                }
+                Opcode::FmaxPseudo => {
+                    if x > y {
+                        x
+                    } else {
+                        y
+                    }
+                }
+                _ => return None,
+            })
+        }
+
+        /// Get `x` with the sign of `sign`.
+        fn $copysign(x: $float, sign: $float) -> $float {
+            let negative_zero: $float = -0.0;
+            let mask = negative_zero.to_bits();
+            $float::from_bits((x.to_bits() & !mask) | (sign.to_bits() & mask))
+        }
+    };
+}
+
+float_ops!(f32, f32_unary, f32_binary, f32_copysign);
+float_ops!(f64, f64_unary, f64_binary, f64_copysign);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cranelift_codegen::ir::immediates::{Ieee32, Ieee64};
+    use cranelift_reader::parse_functions;
+
+    fn parse(text: &str) -> Vec<Function> {
+        parse_functions(text).unwrap()
+    }
+
+    /// Interpret the first function in `text`, with all the functions in `text` available.
+    fn run(text: &str, args: &[DataValue]) -> Outcome {
+        let funcs = parse(text);
+        let mut interpreter = Interpreter::new();
+        for func in &funcs {
+            interpreter.add_function(func);
+        }
+        interpreter.call(&funcs[0], args).unwrap()
+    }
+
+    fn returns(text: &str, args: &[DataValue]) -> DataValue {
+        match run(text, args) {
+            Outcome::Return(values) => {
+                assert_eq!(values.len(), 1);
+                values[0]
+            }
+            Outcome::Trap(code) => panic!("unexpected trap {}", code),
+        }
+    }
+
+    fn f32(x: f32) -> DataValue {
+        DataValue::F32(Ieee32::with_float(x))
+    }
+
+    fn f64(x: f64) -> DataValue {
+        DataValue::F64(Ieee64::with_float(x))
+    }
+
+    #[test]
+    fn integer_arithmetic() {
+        let iadd = "function %f(i8, i8) -> i8 {
+                    ebb0(v0: i8, v1: i8):
+                        v2 = iadd v0, v1
+                        return v2
+                    }";
+        assert_eq!(
+            returns(iadd, &[DataValue::I8(127), DataValue::I8(1)]),
+            DataValue::I8(-128)
+        );
+
+        let ops = "function %f(i32) -> i32 {
+                   ebb0(v0: i32):
+                       v1 = imul_imm v0, -3
+                       v2 = ushr_imm v1, 28
+                       v3 = irsub_imm v2, 100
+                       v4 = rotl_imm v3, 36
+                       v5 = clz v4
+                       v6 = iadd v4, v5
+                       return v6
+                   }";
+        // -3 has the top 4 bits set, so v2 = 15, v3 = 85, v4 = 85 << 4 = 1360, and v5 = 21.
+        assert_eq!(returns(ops, &[DataValue::I32(1)]), DataValue::I32(1381));
+
+        let mulhi = "function %f(i64, i64) -> i64, i64 {
+                     ebb0(v0: i64, v1: i64):
+                         v2 = umulhi v0, v1
+                         v3 = smulhi v0, v1
+                         return v2, v3
+                     }";
+        assert_eq!(
+            run(mulhi, &[DataValue::I64(-1), DataValue::I64(2)]),
+            Outcome::Return(vec![DataValue::I64(1), DataValue::I64(-1)])
+        );
+
+        let extend = "function %f(i8) -> i64, i64 {
+                      ebb0(v0: i8):
+                          v1 = uextend.i64 v0
+                          v2 = sextend.i64 v0
+                          return v1, v2
+                      }";
+        assert_eq!(
+            run(extend, &[DataValue::I8(-2)]),
+            Outcome::Return(vec![DataValue::I64(254), DataValue::I64(-2)])
+        );
+    }
+
+    #[test]
+    fn division_traps() {
+        let sdiv = "function %f(i32, i32) -> i32 {
+                    ebb0(v0: i32, v1: i32):
+                        v2 = sdiv v0, v1
+                        return v2
+                    }";
+        let min = DataValue::I32(i32::min_value());
+        assert_eq!(
+            returns(sdiv, &[DataValue::I32(-7), DataValue::I32(2)]),
+            DataValue::I32(-3)
+        );
+        assert_eq!(
+            run(sdiv, &[DataValue::I32(1), DataValue::I32(0)]),
+            Outcome::Trap(TrapCode::IntegerDivisionByZero)
+        );
+        assert_eq!(
+            run(sdiv, &[min, DataValue::I32(-1)]),
+            Outcome::Trap(TrapCode::IntegerOverflow)
+        );
+
+        let srem = "function %f(i32, i32) -> i32 {
+                    ebb0(v0: i32, v1: i32):
+                        v2 = srem v0, v1
+                        return v2
+                    }";
+        assert_eq!(returns(srem, &[min, DataValue::I32(-1)]), DataValue::I32(0));
+        assert_eq!(
+            returns(srem, &[DataValue::I32(-7), DataValue::I32(2)]),
+            DataValue::I32(-1)
+        );
+    }
+
+    #[test]
+    fn float_arithmetic() {
+        let fmin = "function %f(f32, f32) -> f32 {
+                    ebb0(v0: f32, v1: f32):
+                        v2 = fmin v0, v1
+                        return v2
+                    }";
+        assert!(returns(fmin, &[f32(1.0), f32(::std::f32::NAN)]).is_nan());
+        assert_eq!(
+            returns(fmin, &[f32(0.0), f32(-0.0)]).to_bits(),
+            f32(-0.0).to_bits()
+        );
+        assert_eq!(returns(fmin, &[f32(2.0), f32(-3.0)]), f32(-3.0));
+
+        let nearest = "function %f(f64) -> f64 {
+                       ebb0(v0: f64):
+                           v1 = nearest v0
+                           return v1
+                       }";
+        assert_eq!(returns(nearest, &[f64(2.5)]), f64(2.0));
+        assert_eq!(returns(nearest, &[f64(3.5)]), f64(4.0));
+        assert_eq!(returns(nearest, &[f64(-2.5)]), f64(-2.0));
+        assert_eq!(
+            returns(nearest, &[f64(-0.5)]).to_bits(),
+            f64(-0.0).to_bits()
+        );
+        assert_eq!(returns(nearest, &[f64(1.25)]), f64(1.0));
+
+        let fcvt = "function %f(f64) -> i32 {
+                    ebb0(v0: f64):
+                        v1 = fcvt_to_sint.i32 v0
+                        return v1
+                    }";
+        assert_eq!(returns(fcvt, &[f64(-3.9)]), DataValue::I32(-3));
+        assert_eq!(
+            run(fcvt, &[f64(::std::f64::NAN)]),
+            Outcome::Trap(TrapCode::BadConversionToInteger)
+        );
+        assert_eq!(
+            run(fcvt, &[f64(2147483648.0)]),
+            Outcome::Trap(TrapCode::IntegerOverflow)
+        );
+
+        let sat = "function %f(f32) -> i8 {
+                   ebb0(v0: f32):
+                       v1 = fcvt_to_uint_sat.i8 v0
+                       return v1
+                   }";
+        assert_eq!(returns(sat, &[f32(300.0)]), DataValue::I8(-1));
+        assert_eq!(returns(sat, &[f32(-5.0)]), DataValue::I8(0));
+        assert_eq!(returns(sat, &[f32(::std::f32::NAN)]), DataValue::I8(0));
+
+        let fcmp = "function %f(f32, f32) -> b1 {
+                    ebb0(v0: f32, v1: f32):
+                        v2 = fcmp ult v0, v1
+                        return v2
+                    }";
+        assert_eq!(
+            returns(fcmp, &[f32(::std::f32::NAN), f32(0.0)]),
+            DataValue::B(true)
+        );
+        assert_eq!(returns(fcmp, &[f32(1.0), f32(0.0)]), DataValue::B(false));
+    }
+
+    #[test]
+    fn control_flow() {
+        // Sum the numbers from 1 to `v0`.
+        let sum = "function %f(i32) -> i32 {
+                   ebb0(v0: i32):
+                       v1 = iconst.i32 0
+                       jump ebb1(v0, v1)
+
+                   ebb1(v2: i32, v3: i32):
+                       brz v2, ebb2(v3)
+                       v4 = iadd v3, v2
+                       v5 = iadd_imm v2, -1
+                       jump ebb1(v5, v4)
+
+                   ebb2(v6: i32):
+                       return v6
+                   }";
+        assert_eq!(returns(sum, &[DataValue::I32(10)]), DataValue::I32(55));
+
+        let table = "function %f(i32) -> i32 {
+                         jt0 = jump_table [ebb1, ebb2, _]
+
+                     ebb0(v0: i32):
+                         br_table v0, ebb3, jt0
+
+                     ebb1:
+                         v1 = iconst.i32 10
+                         return v1
+
+                     ebb2:
+                         v2 = iconst.i32 20
+                         return v2
+
+                     ebb3:
+                         v3 = iconst.i32 30
+                         return v3
+                     }";
+        assert_eq!(returns(table, &[DataValue::I32(0)]), DataValue::I32(10));
+        assert_eq!(returns(table, &[DataValue::I32(1)]), DataValue::I32(20));
+        assert_eq!(returns(table, &[DataValue::I32(2)]), DataValue::I32(30));
+        assert_eq!(returns(table, &[DataValue::I32(-1)]), DataValue::I32(30));
+
+        let trap = "function %f(i32) {
+                    ebb0(v0: i32):
+                        trapnz v0, user7
+                        return
+                    }";
+        assert_eq!(run(trap, &[DataValue::I32(0)]), Outcome::Return(vec![]));
+        assert_eq!(
+            run(trap, &[DataValue::I32(3)]),
+            Outcome::Trap(TrapCode::User(7))
+        );
+    }
+
+    #[test]
+    fn calls() {
+        let fib = "function %fib(i32) -> i32 {
+                       fn0 = %fib(i32) -> i32
+
+                   ebb0(v0: i32):
+                       v1 = icmp_imm ult v0, 2
+                       brnz v1, ebb1
+                       v2 = iadd_imm v0, -1
+                       v3 = call fn0(v2)
+                       v4 = iadd_imm v0, -2
+                       v5 = call fn0(v4)
+                       v6 = iadd v3, v5
+                       return v6
+
+                   ebb1:
+                       return v0
+                   }";
+        assert_eq!(returns(fib, &[DataValue::I32(10)]), DataValue::I32(55));
+
+        let indirect = "function %caller(i64) -> i64 {
+                            sig0 = (i64) -> i64
+                            fn0 = %double(i64) -> i64
+
+                        ebb0(v0: i64):
+                            v1 = func_addr.i64 fn0
+                            v2 = call_indirect sig0, v1(v0)
+                            return v2
+                        }
+
+                        function %double(i64) -> i64 {
+                        ebb0(v0: i64):
+                            v1 = iadd v0, v0
+                            return v1
+                        }";
+        assert_eq!(returns(indirect, &[DataValue::I64(21)]), DataValue::I64(42));
+
+        let forever = "function %forever() {
+                           fn0 = %forever()
+
+                       ebb0:
+                           call fn0()
+                           return
+                       }";
+        assert_eq!(run(forever, &[]), Outcome::Trap(TrapCode::StackOverflow));
+    }
+
+    #[test]
+    fn memory() {
+        let stack = "function %f(i64) -> i32, i64 {
+                         ss0 = explicit_slot 8
+
+                     ebb0(v0: i64):
+                         stack_store v0, ss0
+                         v1 = stack_load.i32 ss0+4
+                         v2 = stack_addr.i64 ss0
+                         v3 = sload8.i64 v2+1
+                         return v1, v3
+                     }";
+        assert_eq!(
+            run(stack, &[DataValue::I64(0x1234_5678_9abc_def0)]),
+            Outcome::Return(vec![DataValue::I32(0x1234_5678), DataValue::I64(-0x22)])
+        );
+
+        // A dynamic heap whose base and bound are stored in the VM context.
+        let heap = "function %f(i32, i64 vmctx) -> i32 {
+                        gv0 = vmctx
+                        gv1 = load.i64 notrap aligned gv0
+                        gv2 = load.i64 notrap aligned gv0+8
+                        heap0 = dynamic gv1, bound gv2, guard 0, index_type i32
+
+                    ebb0(v0: i32, v1: i64):
+                        v2 = heap_addr.i64 heap0, v0, 4
+                        v3 = load.i32 v2
+                        v4 = iadd_imm v3, 1
+                        store v4, v2
+                        return v4
+                    }";
+        let funcs = parse(heap);
+        let mut interpreter = Interpreter::new();
+        let heap_base = interpreter.allocate(vec![1, 0, 0, 0, 2, 0, 0, 0]);
+        let mut vmctx = Vec::new();
+        for word in &[heap_base, 8] {
+            vmctx.extend((0..8).map(|i| (word >> (i * 8)) as u8));
+        }
+        let vmctx = DataValue::I64(interpreter.allocate(vmctx) as i64);
+        assert_eq!(
+            interpreter
+                .call(&funcs[0], &[DataValue::I32(4), vmctx])
+                .unwrap(),
+            Outcome::Return(vec![DataValue::I32(3)])
+        );
+        assert_eq!(
+            interpreter.read_memory(heap_base, 8),
+            Some(&[1, 0, 0, 0, 3, 0, 0, 0][..])
+        );
+        assert_eq!(
+            interpreter
+                .call(&funcs[0], &[DataValue::I32(5), vmctx])
+                .unwrap(),
+            Outcome::Trap(TrapCode::HeapOutOfBounds)
+        );
+
+        let symbol = "function %f() -> i8 {
+                          gv0 = symbol %table
+                          gv1 = iadd_imm.i64 gv0, 2
+
+                      ebb0:
+                          v0 = global_value.i64 gv1
+                          v1 = load.i8 v0
+                          return v1
+                      }";
+        let funcs = parse(symbol);
+        let mut interpreter = Interpreter::new();
+        assert_eq!(
+            interpreter.call(&funcs[0], &[]),
+            Err(InterpreterError::UndefinedSymbol(ExternalName::testcase(
+                "table"
+            )))
+        );
+        let table = interpreter.allocate(vec![5, 6, 7]);
+        interpreter.define_symbol(ExternalName::testcase("table"), table);
+        assert_eq!(
+            interpreter.call(&funcs[0], &[]).unwrap(),
+            Outcome::Return(vec![DataValue::I8(7)])
+        );
+
+        let wild = "function %f(i64) -> i32 {
+                    ebb0(v0: i64):
+                        v1 = load.i32 v0
+                        return v1
+                    }";
+        assert_eq!(
+            run(wild, &[DataValue::I64(0)]),
+            Outcome::Trap(TrapCode::HeapOutOfBounds)
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let funcs = parse(
+            "function %f(i32) -> i32 {
+             ebb0(v0: i32):
+                 jump ebb0(v0)
+             }
+
+             function %g(i32) -> b1 {
+             ebb0(v0: i32):
+                 v1 = ifcmp_imm v0, 0
+                 v2 = trueif eq v1
+                 return v2
+             }",
+        );
+        let mut interpreter = Interpreter::new();
+        interpreter.set_step_limit(Some(1000));
+        assert_eq!(
+            interpreter.call(&funcs[0], &[DataValue::I32(0)]),
+            Err(InterpreterError::StepLimitExceeded)
+        );
+        assert_eq!(
+            interpreter
+                .call(&funcs[0], &[DataValue::I64(0)])
+                .unwrap_err()
+                .to_string(),
+            "Bad arguments for %f: expected i32 for 0"
+        );
+        assert_eq!(
+            interpreter
+                .call(&funcs[1], &[DataValue::I32(0)])
+                .unwrap_err()
+                .to_string(),
+            "Unsupported: ifcmp_imm.iflags"
+        );
+        assert_eq!(
+            interpreter.call_by_name(&ExternalName::testcase("g"), &[]),
+            Err(InterpreterError::UndefinedFunction(ExternalName::testcase(
+                "g"
+            )))
+        );
+    }
+}
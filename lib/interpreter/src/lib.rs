@@ -0,0 +1,44 @@
+//! Cranelift IR interpreter.
+//!
+//! This crate executes Cranelift IR functions without compiling them. This is useful for
+//! comparing the behavior of a function before and after optimization, for running tests on hosts
+//! that Cranelift can't generate code for, and for evaluating constant expressions.
+
+#![deny(
+    missing_docs,
+    trivial_numeric_casts,
+    unused_extern_crates,
+    unstable_features
+)]
+#![warn(unused_import_braces)]
+#![cfg_attr(feature = "clippy", plugin(clippy(conf_file = "../../clippy.toml")))]
+#![cfg_attr(
+    feature = "cargo-clippy",
+    allow(new_without_default, new_without_default_derive)
+)]
+#![cfg_attr(
+    feature = "cargo-clippy",
+    warn(
+        mut_mut,
+        nonminimal_bool,
+        option_map_unwrap_or,
+        option_map_unwrap_or_else,
+        print_stdout,
+        unicode_not_nfc,
+        use_self
+    )
+)]
+
+extern crate cranelift_codegen;
+extern crate cranelift_entity;
+#[cfg(test)]
+extern crate cranelift_reader;
+#[macro_use]
+extern crate failure;
+
+mod interpreter;
+mod memory;
+mod value;
+
+pub use interpreter::{Interpreter, InterpreterError, InterpreterResult, Outcome};
+pub use value::DataValue;
@@ -0,0 +1,153 @@
+//! Memory accessible to interpreted functions.
+//!
+//! The interpreter doesn't give functions access to the host's memory. Instead, memory is a set of
+//! disjoint regions in a 64-bit address space. Regions are either provided by the user, for
+//! example to back a heap or the VM context, or allocated for the stack slots of a function while
+//! it is running. An access that isn't entirely contained in a region is invalid.
+
+use std::vec::Vec;
+
+/// The address of the first region.
+///
+/// Low addresses are never mapped, so null pointers are invalid.
+const BASE_ADDRESS: u64 = 0x1_0000;
+
+/// Unmapped bytes left between consecutive regions, so an access past the end of one region
+/// doesn't hit the next one.
+const REGION_GAP: u64 = 0x1000;
+
+/// A contiguous region of memory.
+struct Region {
+    address: u64,
+    data: Vec<u8>,
+}
+
+impl Region {
+    fn end(&self) -> u64 {
+        self.address + self.data.len() as u64
+    }
+}
+
+/// The memory regions available to interpreted functions.
+///
+/// Regions are allocated at increasing addresses and are never moved. They are released in
+/// the reverse order of allocation, like a stack.
+pub struct Memory {
+    regions: Vec<Region>,
+}
+
+impl Memory {
+    /// Create a memory without any regions.
+    pub fn new() -> Self {
+        Self {
+            regions: Vec::new(),
+        }
+    }
+
+    /// Allocate a new region containing `data`, and return its address.
+    ///
+    /// Regions are aligned to 16 bytes.
+    pub fn allocate(&mut self, data: Vec<u8>) -> u64 {
+        let address = match self.regions.last() {
+            Some(region) => (region.end() + REGION_GAP + 15) & !15,
+            None => BASE_ADDRESS,
+        };
+        self.regions.push(Region { address, data });
+        address
+    }
+
+    /// Get a marker identifying the current set of regions, to be passed to `release`.
+    pub fn mark(&self) -> usize {
+        self.regions.len()
+    }
+
+    /// Release all the regions allocated since `mark` was returned by `mark()`.
+    pub fn release(&mut self, mark: usize) {
+        self.regions.truncate(mark);
+    }
+
+    /// Find the index of the region containing the `size` bytes at `address`.
+    fn region(&self, address: u64, size: usize) -> Option<usize> {
+        let end = address.checked_add(size as u64)?;
+        let index = match self.regions.binary_search_by_key(&address, |r| r.address) {
+            Ok(index) => index,
+            Err(0) => return None,
+            Err(index) => index - 1,
+        };
+        if end <= self.regions[index].end() {
+            Some(index)
+        } else {
+            None
+        }
+    }
+
+    /// Get the `size` bytes at `address`, or `None` if they are not all in the same region.
+    pub fn read(&self, address: u64, size: usize) -> Option<&[u8]> {
+        let region = &self.regions[self.region(address, size)?];
+        let start = (address - region.address) as usize;
+        Some(&region.data[start..start + size])
+    }
+
+    /// Get mutable access to the `size` bytes at `address`.
+    pub fn write(&mut self, address: u64, size: usize) -> Option<&mut [u8]> {
+        let index = self.region(address, size)?;
+        let region = &mut self.regions[index];
+        let start = (address - region.address) as usize;
+        Some(&mut region.data[start..start + size])
+    }
+
+    /// Load a little-endian integer of `size` bytes at `address`.
+    pub fn load(&self, address: u64, size: usize) -> Option<u64> {
+        let bytes = self.read(address, size)?;
+        Some(
+            bytes
+                .iter()
+                .rev()
+                .fold(0, |word, &byte| (word << 8) | u64::from(byte)),
+        )
+    }
+
+    /// Store the `size` low bytes of `bits` at `address`, in little-endian order.
+    ///
+    /// Returns `None` if the store is not valid.
+    pub fn store(&mut self, address: u64, size: usize, bits: u64) -> Option<()> {
+        let bytes = self.write(address, size)?;
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = (bits >> (i * 8)) as u8;
+        }
+        Some(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn regions() {
+        let mut mem = Memory::new();
+        let a = mem.allocate(vec![1, 2, 3, 4]);
+        let mark = mem.mark();
+        let b = mem.allocate(vec![0; 8]);
+        assert!(b >= a + 4 + REGION_GAP);
+        assert_eq!(b % 16, 0);
+
+        assert_eq!(mem.load(a, 4), Some(0x04030201));
+        assert_eq!(mem.load(a + 2, 2), Some(0x0403));
+        assert_eq!(mem.load(a + 2, 4), None);
+        assert_eq!(mem.load(a - 1, 1), None);
+        assert_eq!(mem.load(0, 1), None);
+        assert_eq!(mem.load(u64::max_value(), 2), None);
+
+        assert_eq!(mem.store(b + 4, 4, 0xdeadbeef), Some(()));
+        assert_eq!(
+            mem.read(b, 8),
+            Some(&[0, 0, 0, 0, 0xef, 0xbe, 0xad, 0xde][..])
+        );
+        assert_eq!(mem.store(b + 6, 4, 0), None);
+
+        mem.release(mark);
+        assert_eq!(mem.load(b, 1), None);
+        assert_eq!(mem.load(a, 1), Some(1));
+    }
+}
@@ -0,0 +1,150 @@
+//! Values manipulated by the interpreter.
+
+use cranelift_codegen::ir::immediates::{Ieee32, Ieee64};
+use cranelift_codegen::ir::{types, Type};
+use std::fmt;
+
+/// A scalar value held in an SSA value, passed to a function, or returned from it.
+///
+/// Booleans of all widths are represented by `B`. All other variants hold exactly one type.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DataValue {
+    /// A boolean of any width.
+    B(bool),
+    /// An `i8` integer.
+    I8(i8),
+    /// An `i16` integer.
+    I16(i16),
+    /// An `i32` integer.
+    I32(i32),
+    /// An `i64` integer.
+    I64(i64),
+    /// An `f32` floating point number.
+    F32(Ieee32),
+    /// An `f64` floating point number.
+    F64(Ieee64),
+}
+
+impl DataValue {
+    /// Create a value of type `ty` from its bit pattern.
+    ///
+    /// The bits are truncated to the width of `ty`. Booleans are true when any bit is set.
+    /// Returns `None` if the interpreter doesn't support `ty`.
+    pub fn from_bits(ty: Type, bits: u64) -> Option<Self> {
+        Some(match ty {
+            types::I8 => DataValue::I8(bits as i8),
+            types::I16 => DataValue::I16(bits as i16),
+            types::I32 => DataValue::I32(bits as i32),
+            types::I64 => DataValue::I64(bits as i64),
+            types::F32 => DataValue::F32(Ieee32::with_bits(bits as u32)),
+            types::F64 => DataValue::F64(Ieee64::with_bits(bits)),
+            ty if ty.is_bool() && ty.is_lane() => DataValue::B(bits != 0),
+            _ => return None,
+        })
+    }
+
+    /// Create an integer of type `ty` from an `i64`, truncating it to the width of `ty`.
+    pub fn from_i64(ty: Type, x: i64) -> Option<Self> {
+        Self::from_bits(ty, x as u64)
+    }
+
+    /// Get the bit pattern of this value, zero-extended to 64 bits.
+    ///
+    /// Booleans are 1 when true and 0 when false.
+    pub fn to_bits(self) -> u64 {
+        match self {
+            DataValue::B(b) => u64::from(b),
+            DataValue::I8(x) => u64::from(x as u8),
+            DataValue::I16(x) => u64::from(x as u16),
+            DataValue::I32(x) => u64::from(x as u32),
+            DataValue::I64(x) => x as u64,
+            DataValue::F32(x) => u64::from(x.bits()),
+            DataValue::F64(x) => x.bits(),
+        }
+    }
+
+    /// Get the value of an integer, sign-extended to 64 bits.
+    ///
+    /// Other values are converted as by `to_bits()`.
+    pub fn to_i64(self) -> i64 {
+        match self {
+            DataValue::I8(x) => i64::from(x),
+            DataValue::I16(x) => i64::from(x),
+            DataValue::I32(x) => i64::from(x),
+            v => v.to_bits() as i64,
+        }
+    }
+
+    /// Does this value hold a value of type `ty`?
+    pub fn has_type(self, ty: Type) -> bool {
+        match self {
+            DataValue::B(_) => ty.is_bool() && ty.is_lane(),
+            DataValue::I8(_) => ty == types::I8,
+            DataValue::I16(_) => ty == types::I16,
+            DataValue::I32(_) => ty == types::I32,
+            DataValue::I64(_) => ty == types::I64,
+            DataValue::F32(_) => ty == types::F32,
+            DataValue::F64(_) => ty == types::F64,
+        }
+    }
+
+    /// Is this a floating point NaN?
+    pub fn is_nan(self) -> bool {
+        match self {
+            DataValue::F32(x) => f32::from_bits(x.bits()).is_nan(),
+            DataValue::F64(x) => f64::from_bits(x.bits()).is_nan(),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for DataValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DataValue::B(b) => write!(f, "{}", b),
+            DataValue::I8(x) => write!(f, "{}", x),
+            DataValue::I16(x) => write!(f, "{}", x),
+            DataValue::I32(x) => write!(f, "{}", x),
+            DataValue::I64(x) => write!(f, "{}", x),
+            DataValue::F32(x) => write!(f, "{}", x),
+            DataValue::F64(x) => write!(f, "{}", x),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bits() {
+        assert_eq!(
+            DataValue::from_bits(types::I8, 0x1ff),
+            Some(DataValue::I8(-1))
+        );
+        assert_eq!(DataValue::I8(-1).to_bits(), 0xff);
+        assert_eq!(DataValue::I8(-1).to_i64(), -1);
+        assert_eq!(DataValue::I16(-2).to_bits(), 0xfffe);
+        assert_eq!(
+            DataValue::from_i64(types::I32, -1),
+            Some(DataValue::I32(-1))
+        );
+        assert_eq!(DataValue::from_bits(types::B8, 2), Some(DataValue::B(true)));
+        assert_eq!(DataValue::B(true).to_bits(), 1);
+        assert_eq!(DataValue::from_bits(types::I32X4, 0), None);
+        assert_eq!(
+            DataValue::from_bits(types::F32, 0x3f800000),
+            Some(DataValue::F32(Ieee32::with_float(1.0)))
+        );
+    }
+
+    #[test]
+    fn typing() {
+        assert!(DataValue::B(false).has_type(types::B1));
+        assert!(DataValue::B(false).has_type(types::B64));
+        assert!(!DataValue::I32(0).has_type(types::I64));
+        assert!(DataValue::F64(Ieee64::with_float(0.0)).has_type(types::F64));
+        assert!(DataValue::F32(Ieee32::with_bits(0x7fc00000)).is_nan());
+        assert!(!DataValue::I32(0).is_nan());
+    }
+}
@@ -0,0 +1,354 @@
+//! Differential testing of the interpreter against code compiled by simplejit.
+//!
+//! Every function in the filetest corpus that can be called from the host is run with a set of
+//! arguments in both the interpreter and as native code, and the results are compared.
+
+extern crate cranelift_codegen;
+extern crate cranelift_interpreter;
+extern crate cranelift_module;
+extern crate cranelift_native;
+extern crate cranelift_reader;
+extern crate cranelift_simplejit;
+
+use cranelift_codegen::cursor::{Cursor, FuncCursor};
+use cranelift_codegen::ir::condcodes::IntCC;
+use cranelift_codegen::ir::immediates::{Ieee32, Ieee64};
+use cranelift_codegen::ir::{
+    types, AbiParam, ArgumentPurpose, ExternalName, Function, Inst, InstBuilder, LibCall, MemFlags,
+    Opcode, Signature, Type,
+};
+use cranelift_codegen::settings::{self, CallConv, Configurable};
+use cranelift_codegen::{verify_function, Context};
+use cranelift_interpreter::{DataValue, Interpreter, Outcome};
+use cranelift_module::{Linkage, Module};
+use cranelift_simplejit::{SimpleJITBackend, SimpleJITBuilder};
+use std::fs;
+use std::mem;
+use std::path::{Path, PathBuf};
+
+/// The maximum number of instructions to interpret per call.
+const STEP_LIMIT: u64 = 100_000;
+
+/// The number of calls made to each function.
+const CALLS: usize = 16;
+
+#[test]
+fn filetests() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../filetests");
+    let mut files = Vec::new();
+    collect_files(&root, &mut files);
+    files.sort();
+
+    let mut tested = 0;
+    for path in &files {
+        let text = fs::read_to_string(path).unwrap();
+        let test_file = match cranelift_reader::parse_test(&text, None, None) {
+            Ok(test_file) => test_file,
+            Err(_) => continue,
+        };
+        for (func, _) in test_file.functions {
+            if !is_testable(&func) {
+                continue;
+            }
+            tested += 1;
+            compare(path, &func);
+        }
+    }
+    assert!(tested > 0, "no functions tested");
+}
+
+/// Find all the `.clif` files below `dir`.
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            collect_files(&path, files);
+        } else if path.extension().map_or(false, |ext| ext == "clif") {
+            files.push(path);
+        }
+    }
+}
+
+/// Can `func` be called through a trampoline, and does it behave the same wherever it runs?
+///
+/// Functions that reference anything outside themselves or have already been compiled are
+/// excluded, as are functions that observe the addresses of their stack slots.
+fn is_testable(func: &Function) -> bool {
+    let sig = &func.signature;
+    let supported_type = |ty: Type| match ty {
+        types::B1 | types::I8 | types::I16 | types::I32 | types::I64 | types::F32 | types::F64 => {
+            true
+        }
+        _ => false,
+    };
+    sig.params
+        .iter()
+        .chain(&sig.returns)
+        .all(|p| p.purpose == ArgumentPurpose::Normal && supported_type(p.value_type))
+        && (sig.call_conv == CallConv::SystemV || sig.call_conv == CallConv::Fast)
+        && func.dfg.ext_funcs.is_empty()
+        && func.global_values.is_empty()
+        && func.heaps.is_empty()
+        && func.tables.is_empty()
+        && func.encodings.is_empty()
+        && func.layout.entry_block().is_some()
+        && func
+            .layout
+            .ebbs()
+            .flat_map(|ebb| func.layout.ebb_insts(ebb))
+            .all(|inst| is_portable(func, inst))
+        && verify_function(func, &settings::Flags::new(settings::builder())).is_ok()
+}
+
+/// Does `inst` behave the same in the interpreter and in native code?
+fn is_portable(func: &Function, inst: Inst) -> bool {
+    let opcode = func.dfg[inst].opcode();
+    match opcode {
+        Opcode::StackAddr => false,
+        // The x86 backend widens narrow shifts without reducing the shift amount modulo the
+        // original width.
+        Opcode::Ishl | Opcode::Ushr | Opcode::Sshr | Opcode::Rotl | Opcode::Rotr => {
+            func.dfg.ctrl_typevar(inst).bits() >= 32
+        }
+        _ => true,
+    }
+}
+
+/// Interesting values of type `ty`.
+fn values(ty: Type) -> Vec<DataValue> {
+    match ty {
+        types::B1 => vec![DataValue::B(false), DataValue::B(true)],
+        types::F32 => [0.0, -0.0, 1.0, -1.5, 3.75e9, 1.0e-40]
+            .iter()
+            .map(|&x| DataValue::F32(Ieee32::with_float(x)))
+            .chain(vec![DataValue::F32(Ieee32::with_bits(0x7fc0_0000))])
+            .collect(),
+        types::F64 => [0.0, -0.0, 1.0, -1.5, 2.5, 1.0e300]
+            .iter()
+            .map(|&x| DataValue::F64(Ieee64::with_float(x)))
+            .chain(vec![DataValue::F64(Ieee64::with_bits(
+                0x7ff8_0000_0000_0000,
+            ))])
+            .collect(),
+        _ => [0, 1, -1, 2, 7, -128, 0x7fff, 0x5555_5555, i64::min_value()]
+            .iter()
+            .map(|&x| DataValue::from_i64(ty, x).unwrap())
+            .collect(),
+    }
+}
+
+/// The arguments for each call to a function with signature `sig`.
+fn arguments(sig: &Signature) -> Vec<Vec<DataValue>> {
+    let values: Vec<_> = sig.params.iter().map(|p| values(p.value_type)).collect();
+    (0..CALLS)
+        .map(|call| {
+            values
+                .iter()
+                .enumerate()
+                .map(|(i, vs)| vs[(call + i * 3) % vs.len()])
+                .collect()
+        })
+        .collect()
+}
+
+/// Is `actual` the same as `expected`, considering all NaNs to be equal?
+fn same(expected: &[DataValue], actual: &[DataValue]) -> bool {
+    expected.len() == actual.len()
+        && expected
+            .iter()
+            .zip(actual)
+            .all(|(e, a)| e == a || (e.is_nan() && a.is_nan()))
+}
+
+/// Run `func` with the interpreter and as native code, and check that the results match.
+fn compare(path: &Path, func: &Function) {
+    let mut jit = None;
+    for args in arguments(&func.signature) {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_step_limit(Some(STEP_LIMIT));
+        let expected = match interpreter.call(func, &args) {
+            Ok(Outcome::Return(values)) => values,
+            // Traps would bring down the test, and errors mean the interpreter can't run the
+            // function at all.
+            Ok(Outcome::Trap(_)) | Err(_) => continue,
+        };
+
+        if jit.is_none() {
+            jit = Some(match Jit::new(func) {
+                Some(jit) => jit,
+                None => return,
+            });
+        }
+        let actual = jit.as_ref().unwrap().call(&args);
+        assert!(
+            same(&expected, &actual),
+            "{}: {}{:?}: interpreter returned {:?}, native code returned {:?}",
+            path.display(),
+            func.name,
+            args,
+            expected,
+            actual
+        );
+    }
+}
+
+/// A function compiled with simplejit, along with a trampoline for calling it.
+struct Jit {
+    trampoline: extern "C" fn(*mut u64),
+    returns: Vec<Type>,
+    // The module owns the compiled code.
+    _module: Module<SimpleJITBackend>,
+}
+
+impl Jit {
+    /// Compile `func`, or return `None` if it can't be compiled for the host.
+    fn new(func: &Function) -> Option<Self> {
+        // Functions with large stack frames would call a probestack function, which simplejit
+        // can't resolve.
+        let (mut flag_builder, isa_builder) = cranelift_native::builders().unwrap();
+        flag_builder.set("probestack_enabled", "false").unwrap();
+        // Rounding instructions are legalized into libcalls on hosts without SSE4.1, and the C
+        // library may be too far away for a pc-relative call.
+        flag_builder.set("code_model", "large").unwrap();
+        let isa = isa_builder.finish(settings::Flags::new(flag_builder));
+        let mut jit_builder = SimpleJITBuilder::with_isa(isa);
+        jit_builder.symbols(vec![
+            ("ceilf", ceilf as *const u8),
+            ("ceil", ceil as *const u8),
+            ("floorf", floorf as *const u8),
+            ("floor", floor as *const u8),
+            ("truncf", truncf as *const u8),
+            ("trunc", trunc as *const u8),
+            ("nearbyintf", nearbyintf as *const u8),
+            ("nearbyint", nearbyint as *const u8),
+        ]);
+        let mut module: Module<SimpleJITBackend> = Module::new(jit_builder);
+        module.declare_libcalls(libcall_name).unwrap();
+        let func_id = module
+            .declare_function("func", Linkage::Local, &func.signature)
+            .unwrap();
+        let mut ctx = Context::for_function(func.clone());
+        if module.define_function(func_id, &mut ctx).is_err() {
+            return None;
+        }
+
+        let mut ctx = module.make_context();
+        ctx.func = make_trampoline(&module, func_id, &func.signature);
+        let trampoline_id = module
+            .declare_function("trampoline", Linkage::Local, &ctx.func.signature)
+            .unwrap();
+        module.define_function(trampoline_id, &mut ctx).unwrap();
+        module.finalize_definitions();
+
+        let code = module.get_finalized_function(trampoline_id);
+        Some(Self {
+            trampoline: unsafe { mem::transmute(code) },
+            returns: func
+                .signature
+                .returns
+                .iter()
+                .map(|p| p.value_type)
+                .collect(),
+            _module: module,
+        })
+    }
+
+    /// Call the function with `args`.
+    fn call(&self, args: &[DataValue]) -> Vec<DataValue> {
+        let mut slots: Vec<u64> = args.iter().map(|arg| arg.to_bits()).collect();
+        slots.resize(args.len().max(self.returns.len()).max(1), 0);
+        (self.trampoline)(slots.as_mut_ptr());
+        self.returns
+            .iter()
+            .zip(slots)
+            .map(|(&ty, slot)| {
+                let mask = !0 >> (64 - 8 * ty.bytes());
+                DataValue::from_bits(ty, slot & mask).unwrap()
+            })
+            .collect()
+    }
+}
+
+// The test doesn't otherwise use the math library, so its functions are passed to simplejit
+// explicitly instead of being looked up with `dlsym`.
+#[cfg_attr(unix, link(name = "m"))]
+extern "C" {
+    fn ceilf(x: f32) -> f32;
+    fn ceil(x: f64) -> f64;
+    fn floorf(x: f32) -> f32;
+    fn floor(x: f64) -> f64;
+    fn truncf(x: f32) -> f32;
+    fn trunc(x: f64) -> f64;
+    fn nearbyintf(x: f32) -> f32;
+    fn nearbyint(x: f64) -> f64;
+}
+
+/// Get the name of the C library function that implements `libcall`.
+fn libcall_name(libcall: LibCall) -> String {
+    match libcall {
+        LibCall::CeilF32 => "ceilf".to_owned(),
+        LibCall::CeilF64 => "ceil".to_owned(),
+        LibCall::FloorF32 => "floorf".to_owned(),
+        LibCall::FloorF64 => "floor".to_owned(),
+        LibCall::TruncF32 => "truncf".to_owned(),
+        LibCall::TruncF64 => "trunc".to_owned(),
+        LibCall::NearestF32 => "nearbyintf".to_owned(),
+        LibCall::NearestF64 => "nearbyint".to_owned(),
+        LibCall::Memcpy => "memcpy".to_owned(),
+        LibCall::Memset => "memset".to_owned(),
+        LibCall::Memmove => "memmove".to_owned(),
+        // Probes are disabled, and the other libcalls have no C library implementation.
+        _ => libcall.to_string(),
+    }
+}
+
+/// Build a trampoline with the signature `(values)` that calls `callee` with arguments loaded
+/// from the array of 8-byte slots at `values`, and stores its results back into the slots.
+fn make_trampoline(
+    module: &Module<SimpleJITBackend>,
+    callee: cranelift_module::FuncId,
+    sig: &Signature,
+) -> Function {
+    let pointer_type = module.pointer_type();
+    let mut tsig = module.make_signature();
+    tsig.params.push(AbiParam::new(pointer_type));
+    let mut func = Function::with_name_signature(ExternalName::testcase("trampoline"), tsig);
+    let callee = module.declare_func_in_func(callee, &mut func);
+
+    let ebb = func.dfg.make_ebb();
+    let values = func.dfg.append_ebb_param(ebb, pointer_type);
+    let mut pos = FuncCursor::new(&mut func);
+    pos.insert_ebb(ebb);
+
+    let mut flags = MemFlags::new();
+    flags.set_notrap();
+    flags.set_aligned();
+
+    let args: Vec<_> = sig
+        .params
+        .iter()
+        .enumerate()
+        .map(|(i, param)| {
+            let offset = (i * 8) as i32;
+            if param.value_type == types::B1 {
+                let byte = pos.ins().uload8(types::I32, flags, values, offset);
+                pos.ins().icmp_imm(IntCC::NotEqual, byte, 0)
+            } else {
+                pos.ins().load(param.value_type, flags, values, offset)
+            }
+        })
+        .collect();
+    let call = pos.ins().call(callee, &args);
+    let results = pos.func.dfg.inst_results(call).to_vec();
+    for (i, &result) in results.iter().enumerate() {
+        let offset = (i * 8) as i32;
+        if pos.func.dfg.value_type(result) == types::B1 {
+            let byte = pos.ins().bint(types::I32, result);
+            pos.ins().istore8(flags, byte, values, offset);
+        } else {
+            pos.ins().store(flags, result, values, offset);
+        }
+    }
+    pos.ins().return_(&[]);
+    func
+}
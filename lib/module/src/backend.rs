@@ -5,6 +5,8 @@ use cranelift_codegen::Context;
 use cranelift_codegen::{binemit, ir};
 use std::marker;
 use DataContext;
+use FinalizedDataInfo;
+use FinalizedFuncInfo;
 use Linkage;
 use ModuleNamespace;
 use ModuleResult;
@@ -40,6 +42,9 @@ where
     /// the `Backend`.
     type FinalizedData;
 
+    /// An indirection slot through which calls to a redefinable function are made.
+    type FunctionSlot;
+
     /// This is an object returned by `Module`'s
     /// [`finish`](struct.Module.html#method.finish) function,
     /// if the `Backend` has a purpose for this.
@@ -57,15 +62,15 @@ where
     /// Declare a data object.
     fn declare_data(&mut self, name: &str, linkage: Linkage, writable: bool);
 
-    /// Define a function, producing the function body from the given `Context`.
+    /// Define a function, compiling and emitting the function body from the given `Context`.
     ///
-    /// Functions must be declared before being defined.
+    /// Functions must be declared before being defined. Compilation errors are returned as
+    /// `ModuleError::Compilation`.
     fn define_function(
         &mut self,
         name: &str,
-        ctx: &Context,
+        ctx: &mut Context,
         namespace: &ModuleNamespace<Self>,
-        code_size: u32,
     ) -> ModuleResult<Self::CompiledFunction>;
 
     /// Define a zero-initialized data object of the given size.
@@ -109,6 +114,19 @@ where
     /// Return the finalized artifact from the backend, if relevant.
     fn get_finalized_function(&self, func: &Self::CompiledFunction) -> Self::FinalizedFunction;
 
+    /// Return the location, size, trap sites, and applied relocations of a finalized function.
+    fn get_finalized_function_info(&self, func: &Self::CompiledFunction) -> FinalizedFuncInfo;
+
+    /// Create an indirection slot for the function `name` so that it can be redefined after it
+    /// has been finalized. Relocations against the function that are performed afterwards refer
+    /// to the slot instead of to a particular definition.
+    ///
+    /// Backends that can't patch their output return `ModuleError::Backend`.
+    fn prepare_redefine(&mut self, name: &str) -> ModuleResult<Self::FunctionSlot>;
+
+    /// Atomically redirect `slot` to the finalized function `func`.
+    fn swap_function(&mut self, slot: &Self::FunctionSlot, func: &Self::CompiledFunction);
+
     /// Perform all outstanding relocations on the given data object. This requires all
     /// `Local` and `Export` entities referenced to be defined.
     fn finalize_data(
@@ -120,6 +138,9 @@ where
     /// Return the finalized artifact from the backend, if relevant.
     fn get_finalized_data(&self, data: &Self::CompiledData) -> Self::FinalizedData;
 
+    /// Return the location, size, and applied relocations of a finalized data object.
+    fn get_finalized_data_info(&self, data: &Self::CompiledData) -> FinalizedDataInfo;
+
     /// "Publish" all finalized functions and data objects to their ultimate destinations.
     fn publish(&mut self);
 
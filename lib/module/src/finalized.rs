@@ -0,0 +1,78 @@
+//! Defines the information that backends report about finalized functions and data objects.
+
+use cranelift_codegen::binemit::{self, Addend, CodeOffset, Reloc};
+use cranelift_codegen::ir;
+use std::string::String;
+use std::vec::Vec;
+
+/// Where the finalized contents of a function or data object are.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FinalizedLocation {
+    /// The contents are in memory at this address.
+    Address(*const u8),
+    /// The contents are defined by a symbol in a section of an object file.
+    ///
+    /// The address of the symbol is only known once the object file is linked and loaded.
+    Symbol {
+        /// The name of the symbol.
+        name: String,
+        /// The section that contains the symbol.
+        section: ObjectSection,
+    },
+}
+
+/// A kind of section of an object file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ObjectSection {
+    /// Executable code.
+    Text,
+    /// Writable data.
+    Data,
+    /// Read-only data.
+    ReadOnlyData,
+}
+
+/// A relocation that was applied when a function or data object was finalized.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AppliedReloc {
+    /// The offset of the relocated bytes from the start of the function or data object.
+    pub offset: CodeOffset,
+    /// The kind of relocation.
+    pub reloc: Reloc,
+    /// The name of the referenced function or data object.
+    pub name: ir::ExternalName,
+    /// The addend that was added to the address of `name`.
+    pub addend: Addend,
+    /// The address that the relocation resolved to, including the addend.
+    ///
+    /// This is `None` for backends that leave the relocations to a linker.
+    pub target: Option<u64>,
+}
+
+/// Information about a finalized function.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FinalizedFuncInfo {
+    /// Where the function is.
+    pub location: FinalizedLocation,
+    /// The size in bytes of the function, including its read-only data unless it is in `rodata`.
+    pub size: usize,
+    /// Where the read-only data of the function is, if the backend keeps it apart from the code.
+    pub rodata: Option<FinalizedLocation>,
+    /// The size in bytes of the read-only data in `rodata`.
+    pub rodata_size: usize,
+    /// The trap sites of the function, in code order.
+    pub traps: Vec<binemit::TrapSite>,
+    /// The relocations applied to the function.
+    pub relocs: Vec<AppliedReloc>,
+}
+
+/// Information about a finalized data object.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FinalizedDataInfo {
+    /// Where the data object is.
+    pub location: FinalizedLocation,
+    /// The size in bytes of the data object.
+    pub size: usize,
+    /// The relocations applied to the data object.
+    pub relocs: Vec<AppliedReloc>,
+}
@@ -42,12 +42,20 @@ extern crate log;
 
 mod backend;
 mod data_context;
+mod finalized;
+mod metadata;
 mod module;
 
 pub use backend::Backend;
+pub use cranelift_codegen::binemit::TrapSite;
 pub use data_context::{DataContext, DataDescription, Init};
+pub use finalized::{
+    AppliedReloc, FinalizedDataInfo, FinalizedFuncInfo, FinalizedLocation, ObjectSection,
+};
+pub use metadata::{CompiledMetadata, FunctionMetadata, InstAddress};
 pub use module::{
-    DataId, FuncId, FuncOrDataId, Linkage, Module, ModuleError, ModuleNamespace, ModuleResult,
+    DataId, FuncId, FuncOrDataId, Linkage, LocalNamespace, Module, ModuleError, ModuleNamespace,
+    ModuleResult,
 };
 
 /// This replaces `std` in builds with `core`.
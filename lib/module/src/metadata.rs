@@ -0,0 +1,195 @@
+//! Module-level tables of the compile artifacts of finalized functions.
+//!
+//! A runtime that catches a trap or walks the stack has a program counter, and it needs to know
+//! which function, source location, and trap code the program counter belongs to. When it is
+//! enabled with `Module::enable_compiled_metadata`, the module collects the location, trap sites,
+//! and address map of every function it finalizes, so program counters can be resolved with
+//! binary searches instead of per-function bookkeeping in the embedder.
+
+use cranelift_codegen::binemit::{emit_address_map, AddrMapSink, CodeOffset, TrapSite};
+use cranelift_codegen::entity::SecondaryMap;
+use cranelift_codegen::ir::{Ebb, Function, SourceLoc};
+use cranelift_codegen::isa::TargetIsa;
+use finalized::{FinalizedFuncInfo, FinalizedLocation};
+use module::FuncId;
+use std::borrow::ToOwned;
+use std::cmp::Ordering;
+use std::mem;
+use std::string::String;
+use std::vec::Vec;
+
+/// An instruction in the address map of a function.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InstAddress {
+    /// The offset of the instruction from the start of the function.
+    pub offset: CodeOffset,
+    /// The size in bytes of the instruction.
+    pub size: CodeOffset,
+    /// The source location of the instruction.
+    pub srcloc: SourceLoc,
+}
+
+/// An `AddrMapSink` that records the instructions of a function, and ignores the EBBs.
+#[derive(Default)]
+struct InstAddresses {
+    insts: Vec<InstAddress>,
+}
+
+impl AddrMapSink for InstAddresses {
+    fn ebb(&mut self, _offset: CodeOffset, _ebb: Ebb) {}
+
+    fn inst(&mut self, offset: CodeOffset, size: CodeOffset, srcloc: SourceLoc) {
+        self.insts.push(InstAddress {
+            offset,
+            size,
+            srcloc,
+        });
+    }
+}
+
+/// The compile artifacts of a finalized function.
+///
+/// Cranelift doesn't emit stack maps, so there are none to report.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FunctionMetadata {
+    /// The function.
+    pub func: FuncId,
+    /// The declared name of the function.
+    pub name: String,
+    /// Where the code of the function is.
+    ///
+    /// Backends that write object files report the symbol of the function, and the offsets of
+    /// the trap sites and instructions are relative to it.
+    pub location: FinalizedLocation,
+    /// The size in bytes of the function.
+    pub size: usize,
+    /// The trap sites of the function, sorted by offset.
+    pub traps: Vec<TrapSite>,
+    /// The instructions of the function that emit code, sorted by offset.
+    pub address_map: Vec<InstAddress>,
+}
+
+impl FunctionMetadata {
+    /// Get the address of the function, if the backend put it in memory.
+    pub fn address(&self) -> Option<usize> {
+        match self.location {
+            FinalizedLocation::Address(ptr) => Some(ptr as usize),
+            FinalizedLocation::Symbol { .. } => None,
+        }
+    }
+
+    /// Get the trap site of the instruction at `offset`, if it can trap.
+    pub fn trap_at(&self, offset: CodeOffset) -> Option<&TrapSite> {
+        self.traps
+            .binary_search_by_key(&offset, |site| site.offset)
+            .ok()
+            .map(|index| &self.traps[index])
+    }
+
+    /// Get the source location of the instruction whose code contains `offset`.
+    pub fn srcloc_at(&self, offset: CodeOffset) -> Option<SourceLoc> {
+        let index = match self
+            .address_map
+            .binary_search_by_key(&offset, |inst| inst.offset)
+        {
+            Ok(index) => index,
+            Err(0) => return None,
+            Err(index) => index - 1,
+        };
+        let inst = &self.address_map[index];
+        if offset - inst.offset < inst.size {
+            Some(inst.srcloc)
+        } else {
+            None
+        }
+    }
+}
+
+/// The compile artifacts of the finalized functions of a module.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CompiledMetadata {
+    /// The functions that are in memory, sorted by address, followed by the functions that are
+    /// defined by symbols, in the order they were finalized.
+    ///
+    /// A function that was redefined has an entry for each of its definitions.
+    pub functions: Vec<FunctionMetadata>,
+}
+
+impl CompiledMetadata {
+    /// Find the function whose code contains the address `pc`, and the offset of `pc` from the
+    /// start of the function.
+    pub fn lookup_function(&self, pc: usize) -> Option<(&FunctionMetadata, CodeOffset)> {
+        let index = match self
+            .functions
+            .binary_search_by(|func| match func.address() {
+                Some(address) => address.cmp(&pc),
+                None => Ordering::Greater,
+            }) {
+            Ok(index) => index,
+            Err(0) => return None,
+            Err(index) => index - 1,
+        };
+        let func = &self.functions[index];
+        let offset = pc - func.address()?;
+        if offset < func.size {
+            Some((func, offset as CodeOffset))
+        } else {
+            None
+        }
+    }
+
+    /// Find the function and the trap site of the trapping instruction at the address `pc`.
+    pub fn lookup_trap(&self, pc: usize) -> Option<(&FunctionMetadata, &TrapSite)> {
+        let (func, offset) = self.lookup_function(pc)?;
+        func.trap_at(offset).map(|site| (func, site))
+    }
+}
+
+/// Collects the compile artifacts of the functions of a module as they are defined and finalized.
+pub(crate) struct MetadataCollector {
+    /// The address maps of the functions that are defined but not yet finalized.
+    address_maps: SecondaryMap<FuncId, Vec<InstAddress>>,
+    /// The artifacts of the finalized functions.
+    functions: Vec<FunctionMetadata>,
+}
+
+impl MetadataCollector {
+    /// Create a collector that hasn't collected anything yet.
+    pub fn new() -> Self {
+        Self {
+            address_maps: SecondaryMap::new(),
+            functions: Vec::new(),
+        }
+    }
+
+    /// Record the address map of `func`, which has just been compiled from `code`.
+    pub fn define_function(&mut self, func: FuncId, code: &Function, isa: &TargetIsa) {
+        let mut sink = InstAddresses::default();
+        emit_address_map(code, isa, &mut sink);
+        self.address_maps[func] = sink.insts;
+    }
+
+    /// Record the artifacts of `func`, which has just been finalized.
+    pub fn finalize_function(&mut self, func: FuncId, name: &str, info: FinalizedFuncInfo) {
+        let mut traps = info.traps;
+        traps.sort_by_key(|site| site.offset);
+        self.functions.push(FunctionMetadata {
+            func,
+            name: name.to_owned(),
+            location: info.location,
+            size: info.size,
+            traps,
+            address_map: mem::replace(&mut self.address_maps[func], Vec::new()),
+        });
+    }
+
+    /// Take the artifacts of the functions finalized so far.
+    pub fn take(&mut self) -> CompiledMetadata {
+        let mut functions = mem::replace(&mut self.functions, Vec::new());
+        functions.sort_by_key(|func| match func.address() {
+            Some(address) => (false, address),
+            None => (true, 0),
+        });
+        CompiledMetadata { functions }
+    }
+}
@@ -8,6 +8,8 @@
 use cranelift_codegen::entity::{EntityRef, PrimaryMap};
 use cranelift_codegen::{binemit, ir, CodegenError, Context};
 use data_context::DataContext;
+use finalized::{FinalizedDataInfo, FinalizedFuncInfo};
+use metadata::{CompiledMetadata, MetadataCollector};
 use std::borrow::ToOwned;
 use std::collections::HashMap;
 use std::string::String;
@@ -44,6 +46,17 @@ impl From<DataId> for ir::ExternalName {
     }
 }
 
+/// A namespace for the local names of one unit of code in a `Module`, like one of several wasm
+/// modules translated into the same `Module`.
+///
+/// Functions and data objects declared in a namespace with `Linkage::Local` only share their
+/// names with the other local declarations in the namespace, and their symbols are prefixed with
+/// the prefix of the namespace. Names with other linkages are shared by all the namespaces, so
+/// exported names stay the same.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct LocalNamespace(u32);
+entity_impl!(LocalNamespace, "namespace");
+
 /// Linkage refers to where an entity is defined and who can see it.
 #[derive(Copy, Clone, PartialEq, Eq)]
 pub enum Linkage {
@@ -135,6 +148,9 @@ pub enum ModuleError {
         _0
     )]
     InvalidImportDefinition(String),
+    /// Indicates a function was swapped without being prepared for redefinition
+    #[fail(display = "Function not prepared for redefinition: {}", _0)]
+    NotRedefinable(String),
     /// Wraps a `cranelift-codegen` error
     #[fail(display = "Compilation error: {}", _0)]
     Compilation(CodegenError),
@@ -153,8 +169,14 @@ where
 {
     /// The function declaration.
     decl: FunctionDeclaration,
+    /// The symbol name that references to the function use. All the references share its string.
+    symbol: ir::ExternalName,
     /// The compiled artifact, once it's available.
     compiled: Option<B::CompiledFunction>,
+    /// The indirection slot, if the function can be redefined.
+    slot: Option<B::FunctionSlot>,
+    /// Whether `compiled` is a redefinition that hasn't been swapped into the slot yet.
+    pending_swap: bool,
 }
 
 impl<B> ModuleFunction<B>
@@ -184,6 +206,8 @@ where
 {
     /// The data object declaration.
     decl: DataDeclaration,
+    /// The symbol name that references to the data object use.
+    symbol: ir::ExternalName,
     /// The "compiled" artifact, once it's available.
     compiled: Option<B::CompiledData>,
 }
@@ -203,8 +227,14 @@ struct ModuleContents<B>
 where
     B: Backend,
 {
+    names: HashMap<String, FuncOrDataId>,
+    /// The prefixes of the local namespaces.
+    namespaces: PrimaryMap<LocalNamespace, String>,
+    /// The names declared with `Linkage::Local` in a local namespace.
+    local_names: HashMap<(LocalNamespace, String), FuncOrDataId>,
     functions: PrimaryMap<FuncId, ModuleFunction<B>>,
     data_objects: PrimaryMap<DataId, ModuleData<B>>,
+    libcalls: HashMap<ir::LibCall, FuncId>,
 }
 
 impl<B> ModuleContents<B>
@@ -212,23 +242,37 @@ where
     B: Backend,
 {
     fn get_function_info(&self, name: &ir::ExternalName) -> &ModuleFunction<B> {
-        if let ir::ExternalName::User { namespace, index } = *name {
-            debug_assert_eq!(namespace, 0);
-            let func = FuncId::new(index as usize);
-            &self.functions[func]
-        } else {
-            panic!("unexpected ExternalName kind {}", name)
+        match *name {
+            ir::ExternalName::User { namespace, index } => {
+                debug_assert_eq!(namespace, 0);
+                let func = FuncId::new(index as usize);
+                &self.functions[func]
+            }
+            ir::ExternalName::LibCall(libcall) => match self.libcalls.get(&libcall) {
+                Some(&func) => &self.functions[func],
+                None => panic!("undeclared libcall {}", libcall),
+            },
+            ir::ExternalName::Symbol(ref symbol) => match self.names.get(&**symbol) {
+                Some(&FuncOrDataId::Func(func)) => &self.functions[func],
+                _ => panic!("undeclared function {}", name),
+            },
+            _ => panic!("unexpected ExternalName kind {}", name),
         }
     }
 
     /// Get the `DataDeclaration` for the function named by `name`.
     fn get_data_info(&self, name: &ir::ExternalName) -> &ModuleData<B> {
-        if let ir::ExternalName::User { namespace, index } = *name {
-            debug_assert_eq!(namespace, 1);
-            let data = DataId::new(index as usize);
-            &self.data_objects[data]
-        } else {
-            panic!("unexpected ExternalName kind {}", name)
+        match *name {
+            ir::ExternalName::User { namespace, index } => {
+                debug_assert_eq!(namespace, 1);
+                let data = DataId::new(index as usize);
+                &self.data_objects[data]
+            }
+            ir::ExternalName::Symbol(ref symbol) => match self.names.get(&**symbol) {
+                Some(&FuncOrDataId::Data(data)) => &self.data_objects[data],
+                _ => panic!("undeclared data object {}", name),
+            },
+            _ => panic!("unexpected ExternalName kind {}", name),
         }
     }
 }
@@ -277,6 +321,14 @@ where
         )
     }
 
+    /// Get the indirection slot for the function named by `name`, if it can be redefined.
+    ///
+    /// Relocations against such a function must refer to the slot rather than to its current
+    /// definition.
+    pub fn get_function_slot(&self, name: &ir::ExternalName) -> Option<&B::FunctionSlot> {
+        self.contents.get_function_info(name).slot.as_ref()
+    }
+
     /// Get the definition for the data object named by `name`, along with its name
     /// and writable flag
     pub fn get_data_definition(
@@ -296,10 +348,15 @@ where
 
     /// Return whether `name` names a function, rather than a data object.
     pub fn is_function(&self, name: &ir::ExternalName) -> bool {
-        if let ir::ExternalName::User { namespace, .. } = *name {
-            namespace == 0
-        } else {
-            panic!("unexpected ExternalName kind {}", name)
+        match *name {
+            ir::ExternalName::User { namespace, .. } => namespace == 0,
+            ir::ExternalName::LibCall(_) => true,
+            ir::ExternalName::Symbol(ref symbol) => match self.contents.names.get(&**symbol) {
+                Some(&FuncOrDataId::Func(_)) => true,
+                Some(&FuncOrDataId::Data(_)) => false,
+                None => panic!("undeclared identifier {}", name),
+            },
+            _ => panic!("unexpected ExternalName kind {}", name),
         }
     }
 }
@@ -309,10 +366,11 @@ pub struct Module<B>
 where
     B: Backend,
 {
-    names: HashMap<String, FuncOrDataId>,
     contents: ModuleContents<B>,
     functions_to_finalize: Vec<FuncId>,
     data_objects_to_finalize: Vec<DataId>,
+    /// The compile artifacts of the functions, if they are being collected.
+    metadata: Option<MetadataCollector>,
     backend: B,
 }
 
@@ -323,13 +381,17 @@ where
     /// Create a new `Module`.
     pub fn new(backend_builder: B::Builder) -> Self {
         Self {
-            names: HashMap::new(),
             contents: ModuleContents {
+                names: HashMap::new(),
+                namespaces: PrimaryMap::new(),
+                local_names: HashMap::new(),
                 functions: PrimaryMap::new(),
                 data_objects: PrimaryMap::new(),
+                libcalls: HashMap::new(),
             },
             functions_to_finalize: Vec::new(),
             data_objects_to_finalize: Vec::new(),
+            metadata: None,
             backend: B::new(backend_builder),
         }
     }
@@ -337,7 +399,7 @@ where
     /// Get the module identifier for a given name, if that name
     /// has been declared.
     pub fn get_name(&self, name: &str) -> Option<FuncOrDataId> {
-        self.names.get(name).cloned()
+        self.contents.names.get(name).cloned()
     }
 
     /// Return then pointer type for the current target.
@@ -348,20 +410,22 @@ where
     /// Create a new `Context` initialized for use with this `Module`.
     ///
     /// This ensures that the `Context` is initialized with the default calling
-    /// convention for the `TargetIsa`.
+    /// convention and the pointer type of the `TargetIsa`.
     pub fn make_context(&self) -> Context {
         let mut ctx = Context::new();
         ctx.func.signature.call_conv = self.backend.isa().flags().call_conv();
+        ctx.func.pointer_type = Some(self.pointer_type());
         ctx
     }
 
     /// Clear the given `Context` and reset it for use with a new function.
     ///
     /// This ensures that the `Context` is initialized with the default calling
-    /// convention for the `TargetIsa`.
+    /// convention and the pointer type of the `TargetIsa`.
     pub fn clear_context(&self, ctx: &mut Context) {
         ctx.clear();
         ctx.func.signature.call_conv = self.backend.isa().flags().call_conv();
+        ctx.func.pointer_type = Some(self.pointer_type());
     }
 
     /// Create a new empty `Signature` with the default calling convention for
@@ -388,7 +452,7 @@ where
     ) -> ModuleResult<FuncId> {
         // TODO: Can we avoid allocating names so often?
         use std::collections::hash_map::Entry::*;
-        match self.names.entry(name.to_owned()) {
+        match self.contents.names.entry(name.to_owned()) {
             Occupied(entry) => match *entry.get() {
                 FuncOrDataId::Func(id) => {
                     let existing = &mut self.contents.functions[id];
@@ -407,7 +471,10 @@ where
                         linkage,
                         signature: signature.clone(),
                     },
+                    symbol: ir::ExternalName::symbol(name),
                     compiled: None,
+                    slot: None,
+                    pending_swap: false,
                 });
                 entry.insert(FuncOrDataId::Func(id));
                 self.backend.declare_function(name, linkage);
@@ -416,6 +483,124 @@ where
         }
     }
 
+    /// Declare a namespace for local names, whose symbols are prefixed with `prefix` and a `.`.
+    ///
+    /// Each namespace needs its own prefix.
+    pub fn declare_namespace(&mut self, prefix: &str) -> ModuleResult<LocalNamespace> {
+        if self.contents.namespaces.values().any(|p| p == prefix) {
+            return Err(ModuleError::DuplicateDefinition(prefix.to_owned()));
+        }
+        Ok(self.contents.namespaces.push(prefix.to_owned()))
+    }
+
+    /// Get the module identifier for a name declared in `namespace`, if that name has been
+    /// declared.
+    ///
+    /// The local names of the namespace hide the names that are shared by all namespaces.
+    pub fn get_name_in_namespace(
+        &self,
+        namespace: LocalNamespace,
+        name: &str,
+    ) -> Option<FuncOrDataId> {
+        self.contents
+            .local_names
+            .get(&(namespace, name.to_owned()))
+            .or_else(|| self.contents.names.get(name))
+            .cloned()
+    }
+
+    /// Declare a function in `namespace`.
+    ///
+    /// A local function is only the same function as the local declarations of `name` in the same
+    /// namespace. Functions with other linkages are declared with `declare_function`.
+    pub fn declare_function_in_namespace(
+        &mut self,
+        namespace: LocalNamespace,
+        name: &str,
+        linkage: Linkage,
+        signature: &ir::Signature,
+    ) -> ModuleResult<FuncId> {
+        let key = (namespace, name.to_owned());
+        if linkage != Linkage::Local {
+            if self.contents.local_names.contains_key(&key) {
+                return Err(ModuleError::IncompatibleDeclaration(name.to_owned()));
+            }
+            return self.declare_function(name, linkage, signature);
+        }
+        match self.contents.local_names.get(&key).cloned() {
+            Some(FuncOrDataId::Func(id)) => {
+                self.contents.functions[id].merge(linkage, signature)?;
+                Ok(id)
+            }
+            Some(FuncOrDataId::Data(..)) => {
+                Err(ModuleError::IncompatibleDeclaration(name.to_owned()))
+            }
+            None => {
+                let symbol_name = format!("{}.{}", self.contents.namespaces[namespace], name);
+                let id = self.declare_unnamed_function(symbol_name, signature);
+                self.contents
+                    .local_names
+                    .insert(key, FuncOrDataId::Func(id));
+                Ok(id)
+            }
+        }
+    }
+
+    /// Declare a local function without a name.
+    ///
+    /// Anonymous functions, like closures and thunks, can only be referred to through their
+    /// `FuncId`, so they never conflict with other functions.
+    pub fn declare_anonymous_function(
+        &mut self,
+        signature: &ir::Signature,
+    ) -> ModuleResult<FuncId> {
+        let symbol_name = format!(
+            "__cranelift_anon.{}",
+            self.contents.functions.next_key().index()
+        );
+        Ok(self.declare_unnamed_function(symbol_name, signature))
+    }
+
+    /// Declare a local function that isn't in the module's names, and has the symbol
+    /// `symbol_name` in the backend.
+    ///
+    /// References to the function use its `FuncId`, since the symbol name isn't declared.
+    fn declare_unnamed_function(
+        &mut self,
+        symbol_name: String,
+        signature: &ir::Signature,
+    ) -> FuncId {
+        let id = self.contents.functions.next_key();
+        self.backend.declare_function(&symbol_name, Linkage::Local);
+        self.contents.functions.push(ModuleFunction {
+            decl: FunctionDeclaration {
+                name: symbol_name,
+                linkage: Linkage::Local,
+                signature: signature.clone(),
+            },
+            symbol: ir::ExternalName::from(id),
+            compiled: None,
+            slot: None,
+            pending_swap: false,
+        })
+    }
+
+    /// Declare the runtime library routines as imported functions named by `names`.
+    ///
+    /// Afterwards, references to `ir::ExternalName::LibCall` names, like the ones inserted by
+    /// the legalizer, resolve to these functions.
+    pub fn declare_libcalls<F>(&mut self, names: F) -> ModuleResult<()>
+    where
+        F: Fn(ir::LibCall) -> String,
+    {
+        for &libcall in ir::LibCall::all_libcalls() {
+            let signature = libcall.signature(self.backend.isa());
+            let func = self.declare_function(&names(libcall), Linkage::Import, &signature)?;
+            self.contents.libcalls.insert(libcall, func);
+        }
+        Ok(())
+    }
+
     /// Declare a data object in this module.
     pub fn declare_data(
         &mut self,
@@ -425,7 +610,7 @@ where
     ) -> ModuleResult<DataId> {
         // TODO: Can we avoid allocating names so often?
         use std::collections::hash_map::Entry::*;
-        match self.names.entry(name.to_owned()) {
+        match self.contents.names.entry(name.to_owned()) {
             Occupied(entry) => match *entry.get() {
                 FuncOrDataId::Data(id) => {
                     let existing = &mut self.contents.data_objects[id];
@@ -446,6 +631,7 @@ where
                         linkage,
                         writable,
                     },
+                    symbol: ir::ExternalName::symbol(name),
                     compiled: None,
                 });
                 entry.insert(FuncOrDataId::Data(id));
@@ -455,16 +641,66 @@ where
         }
     }
 
+    /// Declare a data object in `namespace`.
+    ///
+    /// A local data object is only the same data object as the local declarations of `name` in
+    /// the same namespace. Data objects with other linkages are declared with `declare_data`.
+    pub fn declare_data_in_namespace(
+        &mut self,
+        namespace: LocalNamespace,
+        name: &str,
+        linkage: Linkage,
+        writable: bool,
+    ) -> ModuleResult<DataId> {
+        let key = (namespace, name.to_owned());
+        if linkage != Linkage::Local {
+            if self.contents.local_names.contains_key(&key) {
+                return Err(ModuleError::IncompatibleDeclaration(name.to_owned()));
+            }
+            return self.declare_data(name, linkage, writable);
+        }
+        match self.contents.local_names.get(&key).cloned() {
+            Some(FuncOrDataId::Data(id)) => {
+                let existing = &mut self.contents.data_objects[id];
+                existing.merge(linkage, writable);
+                self.backend
+                    .declare_data(&existing.decl.name, linkage, existing.decl.writable);
+                Ok(id)
+            }
+            Some(FuncOrDataId::Func(..)) => {
+                Err(ModuleError::IncompatibleDeclaration(name.to_owned()))
+            }
+            None => {
+                let symbol_name = format!("{}.{}", self.contents.namespaces[namespace], name);
+                let id = self.contents.data_objects.next_key();
+                self.backend.declare_data(&symbol_name, linkage, writable);
+                self.contents.data_objects.push(ModuleData {
+                    decl: DataDeclaration {
+                        name: symbol_name,
+                        linkage,
+                        writable,
+                    },
+                    symbol: ir::ExternalName::from(id),
+                    compiled: None,
+                });
+                self.contents
+                    .local_names
+                    .insert(key, FuncOrDataId::Data(id));
+                Ok(id)
+            }
+        }
+    }
+
     /// Use this when you're building the IR of a function to reference a function.
     ///
     /// TODO: Coalesce redundant decls and signatures.
     /// TODO: Look into ways to reduce the risk of using a FuncRef in the wrong function.
     pub fn declare_func_in_func(&self, func: FuncId, in_func: &mut ir::Function) -> ir::FuncRef {
-        let decl = &self.contents.functions[func].decl;
-        let signature = in_func.import_signature(decl.signature.clone());
-        let colocated = decl.linkage.is_final();
+        let info = &self.contents.functions[func];
+        let signature = in_func.import_signature(info.decl.signature.clone());
+        let colocated = info.decl.linkage.is_final();
         in_func.import_function(ir::ExtFuncData {
-            name: ir::ExternalName::user(0, func.index() as u32),
+            name: info.symbol.clone(),
             signature,
             colocated,
         })
@@ -474,10 +710,10 @@ where
     ///
     /// TODO: Same as above.
     pub fn declare_data_in_func(&self, data: DataId, func: &mut ir::Function) -> ir::GlobalValue {
-        let decl = &self.contents.data_objects[data].decl;
-        let colocated = decl.linkage.is_final();
+        let info = &self.contents.data_objects[data];
+        let colocated = info.decl.linkage.is_final();
         func.create_global_value(ir::GlobalValueData::Symbol {
-            name: ir::ExternalName::user(1, data.index() as u32),
+            name: info.symbol.clone(),
             offset: ir::immediates::Imm64::new(0),
             colocated,
         })
@@ -485,47 +721,110 @@ where
 
     /// TODO: Same as above.
     pub fn declare_func_in_data(&self, func: FuncId, ctx: &mut DataContext) -> ir::FuncRef {
-        ctx.import_function(ir::ExternalName::user(0, func.index() as u32))
+        ctx.import_function(self.contents.functions[func].symbol.clone())
     }
 
     /// TODO: Same as above.
     pub fn declare_data_in_data(&self, data: DataId, ctx: &mut DataContext) -> ir::GlobalValue {
-        ctx.import_global_value(ir::ExternalName::user(1, data.index() as u32))
+        ctx.import_global_value(self.contents.data_objects[data].symbol.clone())
+    }
+
+    /// Make `func` redefinable.
+    ///
+    /// Calls to `func` from functions finalized afterwards go through an indirection slot, which
+    /// `swap_function` can redirect to a new definition without touching the callers. Callers
+    /// that were finalized before this keep calling the definition they were linked against.
+    pub fn prepare_redefine(&mut self, func: FuncId) -> ModuleResult<()> {
+        let slot = {
+            let info = &self.contents.functions[func];
+            if info.slot.is_some() {
+                return Ok(());
+            }
+            if !info.decl.linkage.is_definable() {
+                return Err(ModuleError::InvalidImportDefinition(info.decl.name.clone()));
+            }
+            let slot = self.backend.prepare_redefine(&info.decl.name)?;
+            if let Some(ref compiled) = info.compiled {
+                if !self.functions_to_finalize.iter().any(|x| *x == func) {
+                    self.backend.swap_function(&slot, compiled);
+                }
+            }
+            slot
+        };
+        self.contents.functions[func].slot = Some(slot);
+        Ok(())
     }
 
     /// Define a function, producing the function body from the given `Context`.
+    ///
+    /// A function prepared with `prepare_redefine` may be defined again once its previous
+    /// definition has been finalized. Callers keep using the previous definition until the new
+    /// one is finalized and installed with `swap_function`.
     pub fn define_function(&mut self, func: FuncId, ctx: &mut Context) -> ModuleResult<()> {
         let compiled = {
-            let code_size = ctx.compile(self.backend.isa()).map_err(|e| {
-                info!(
-                    "defining function {}: {}",
-                    func,
-                    ctx.func.display(self.backend.isa())
-                );
-                ModuleError::Compilation(e)
-            })?;
-
             let info = &self.contents.functions[func];
-            if info.compiled.is_some() {
+            if info.compiled.is_some()
+                && (info.slot.is_none() || self.functions_to_finalize.iter().any(|x| *x == func))
+            {
                 return Err(ModuleError::DuplicateDefinition(info.decl.name.clone()));
             }
             if !info.decl.linkage.is_definable() {
                 return Err(ModuleError::InvalidImportDefinition(info.decl.name.clone()));
             }
-            Some(self.backend.define_function(
-                &info.decl.name,
-                ctx,
-                &ModuleNamespace::<B> {
-                    contents: &self.contents,
-                },
-                code_size,
-            )?)
+            let compiled = self
+                .backend
+                .define_function(
+                    &info.decl.name,
+                    ctx,
+                    &ModuleNamespace::<B> {
+                        contents: &self.contents,
+                    },
+                ).map_err(|e| {
+                    if let ModuleError::Compilation(_) = e {
+                        info!(
+                            "defining function {}: {}",
+                            func,
+                            ctx.func.display(self.backend.isa())
+                        );
+                    }
+                    e
+                })?;
+            Some(compiled)
         };
-        self.contents.functions[func].compiled = compiled;
+        if let Some(ref mut metadata) = self.metadata {
+            metadata.define_function(func, &ctx.func, self.backend.isa());
+        }
+        let info = &mut self.contents.functions[func];
+        info.pending_swap = info.compiled.is_some();
+        info.compiled = compiled;
         self.functions_to_finalize.push(func);
         Ok(())
     }
 
+    /// Redirect all calls through the indirection slot of `func` to its latest definition.
+    ///
+    /// The function must have been prepared with `prepare_redefine`, and its latest definition
+    /// must be finalized.
+    pub fn swap_function(&mut self, func: FuncId) -> ModuleResult<()> {
+        let info = &mut self.contents.functions[func];
+        debug_assert!(
+            !self.functions_to_finalize.iter().any(|x| *x == func),
+            "function not yet finalized"
+        );
+        let slot = match info.slot {
+            Some(ref slot) => slot,
+            None => return Err(ModuleError::NotRedefinable(info.decl.name.clone())),
+        };
+        self.backend.swap_function(
+            slot,
+            info.compiled
+                .as_ref()
+                .expect("function must be compiled before it can be swapped"),
+        );
+        info.pending_swap = false;
+        Ok(())
+    }
+
     /// Define a function, producing the data contents from the given `DataContext`.
     pub fn define_data(&mut self, data: DataId, data_ctx: &DataContext) -> ModuleResult<()> {
         let compiled = {
@@ -603,14 +902,27 @@ where
         for func in self.functions_to_finalize.drain(..) {
             let info = &self.contents.functions[func];
             debug_assert!(info.decl.linkage.is_definable());
+            let compiled = info
+                .compiled
+                .as_ref()
+                .expect("function must be compiled before it can be finalized");
             self.backend.finalize_function(
-                info.compiled
-                    .as_ref()
-                    .expect("function must be compiled before it can be finalized"),
+                compiled,
                 &ModuleNamespace::<B> {
                     contents: &self.contents,
                 },
             );
+            if let Some(ref mut metadata) = self.metadata {
+                let finalized = self.backend.get_finalized_function_info(compiled);
+                metadata.finalize_function(func, &info.decl.name, finalized);
+            }
+            // The first definition of a redefinable function is installed right away.
+            if let Some(ref slot) = info.slot {
+                if !info.pending_swap {
+                    self.backend
+                        .swap_function(slot, info.compiled.as_ref().unwrap());
+                }
+            }
         }
         for data in self.data_objects_to_finalize.drain(..) {
             let info = &self.contents.data_objects[data];
@@ -655,6 +967,54 @@ where
         )
     }
 
+    /// Return the location, size, trap sites, and applied relocations of a finalized function.
+    ///
+    /// This is useful for registering the function with profilers and debuggers.
+    pub fn get_finalized_function_info(&self, func: FuncId) -> FinalizedFuncInfo {
+        let info = &self.contents.functions[func];
+        debug_assert!(
+            !self.functions_to_finalize.iter().any(|x| *x == func),
+            "function not yet finalized"
+        );
+        self.backend.get_finalized_function_info(
+            info.compiled
+                .as_ref()
+                .expect("function must be compiled before it can be finalized"),
+        )
+    }
+
+    /// Return the location, size, and applied relocations of a finalized data object.
+    pub fn get_finalized_data_info(&self, data: DataId) -> FinalizedDataInfo {
+        let info = &self.contents.data_objects[data];
+        debug_assert!(
+            !self.data_objects_to_finalize.iter().any(|x| *x == data),
+            "data object not yet finalized"
+        );
+        self.backend.get_finalized_data_info(
+            info.compiled
+                .as_ref()
+                .expect("data object must be compiled before it can be finalized"),
+        )
+    }
+
+    /// Collect the compile artifacts of the functions that are defined from now on, so they can
+    /// be retrieved with `take_compiled_metadata` once they are finalized.
+    pub fn enable_compiled_metadata(&mut self) {
+        if self.metadata.is_none() {
+            self.metadata = Some(MetadataCollector::new());
+        }
+    }
+
+    /// Take the compile artifacts of the functions that were finalized since the last call.
+    ///
+    /// The result is empty unless collection was enabled with `enable_compiled_metadata`.
+    pub fn take_compiled_metadata(&mut self) -> CompiledMetadata {
+        match self.metadata {
+            Some(ref mut metadata) => metadata.take(),
+            None => CompiledMetadata::default(),
+        }
+    }
+
     /// Consume the module and return the resulting `Product`. Some `Backend`
     /// implementations may provide additional functionality available after
     /// a `Module` is complete.
@@ -662,3 +1022,37 @@ where
         self.backend.finish()
     }
 }
+
+/// Resolves the `ir::ExternalName`s of the module's functions and data objects to their declared
+/// names, for use with `ir::Function::display_with_resolver`.
+impl<B> ir::NameResolver for Module<B>
+where
+    B: Backend,
+{
+    fn symbol_name(&self, name: &ir::ExternalName) -> Option<&str> {
+        match *name {
+            ir::ExternalName::User {
+                namespace: 0,
+                index,
+            } => self
+                .contents
+                .functions
+                .get(FuncId::new(index as usize))
+                .map(|info| info.decl.name.as_str()),
+            ir::ExternalName::User {
+                namespace: 1,
+                index,
+            } => self
+                .contents
+                .data_objects
+                .get(DataId::new(index as usize))
+                .map(|info| info.decl.name.as_str()),
+            ir::ExternalName::LibCall(libcall) => self
+                .contents
+                .libcalls
+                .get(&libcall)
+                .map(|&func| self.contents.functions[func].decl.name.as_str()),
+            _ => None,
+        }
+    }
+}
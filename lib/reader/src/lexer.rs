@@ -43,6 +43,7 @@ pub enum Token<'a> {
     UserRef(u32),         // u345
     Name(&'a str),        // %9arbitrary_alphanum, %x3, %0, %function ...
     HexSequence(&'a str), // #89AF
+    String(&'a str),      // "quoted \"string\"", without the quotes or unescaping
     Identifier(&'a str),  // Unrecognized identifier (opcode, enumerator, ...)
     SourceLoc(&'a str),   // @00c7
 }
@@ -66,6 +67,7 @@ fn token(token: Token, loc: Location) -> Result<LocatedToken, LocatedError> {
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum LexError {
     InvalidChar,
+    UnterminatedString,
 }
 
 /// A `LexError` with an associated Location.
@@ -104,6 +106,47 @@ pub fn split_entity_name(name: &str) -> Option<(&str, u32)> {
     }
 }
 
+/// Replace the escape sequences in the contents of a `Token::String`.
+///
+/// This accepts the escapes of Rust string literals, except for the `\x` and line continuation
+/// escapes. Return `None` if `s` contains an invalid escape sequence.
+pub fn unescape_string(s: &str) -> Option<String> {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        out.push(match chars.next()? {
+            '"' => '"',
+            '\'' => '\'',
+            '\\' => '\\',
+            'n' => '\n',
+            'r' => '\r',
+            't' => '\t',
+            '0' => '\0',
+            'u' => {
+                if chars.next()? != '{' {
+                    return None;
+                }
+                let rest = chars.as_str();
+                let len = rest.find('}')?;
+                if len == 0 || len > 6 {
+                    return None;
+                }
+                let c = u32::from_str_radix(&rest[..len], 16)
+                    .ok()
+                    .and_then(char::from_u32)?;
+                chars = rest[len + 1..].chars();
+                c
+            }
+            _ => return None,
+        });
+    }
+    Some(out)
+}
+
 /// Lexical analysis.
 ///
 /// A `Lexer` reads text from a `&str` and provides a sequence of tokens.
@@ -414,6 +457,33 @@ impl<'a> Lexer<'a> {
         token(Token::HexSequence(&self.source[begin..end]), loc)
     }
 
+    // Scan a string literal. The escape sequences are checked by `unescape_string`, so this only
+    // needs to find the closing quote. Strings can't span multiple lines.
+    fn scan_string(&mut self) -> Result<LocatedToken<'a>, LocatedError> {
+        let loc = self.loc();
+        let begin = self.pos + 1;
+
+        assert_eq!(self.lookahead, Some('"'));
+
+        loop {
+            match self.next_ch() {
+                None | Some('\n') => return error(LexError::UnterminatedString, loc),
+                Some('"') => break,
+                Some('\\') => {
+                    // Skip the escaped character, which may be a quote.
+                    if let None | Some('\n') = self.next_ch() {
+                        return error(LexError::UnterminatedString, loc);
+                    }
+                }
+                Some(_) => {}
+            }
+        }
+
+        let end = self.pos;
+        self.next_ch();
+        token(Token::String(&self.source[begin..end]), loc)
+    }
+
     fn scan_srcloc(&mut self) -> Result<LocatedToken<'a>, LocatedError> {
         let loc = self.loc();
         let begin = self.pos + 1;
@@ -458,9 +528,10 @@ impl<'a> Lexer<'a> {
                     }
                 }
                 Some(ch) if ch.is_digit(10) => Some(self.scan_number()),
-                Some(ch) if ch.is_alphabetic() => Some(self.scan_word()),
+                Some(ch) if ch == '_' || ch.is_alphabetic() => Some(self.scan_word()),
                 Some('%') => Some(self.scan_name()),
                 Some('#') => Some(self.scan_hex_sequence()),
+                Some('"') => Some(self.scan_string()),
                 Some('@') => Some(self.scan_srcloc()),
                 Some(ch) if ch.is_whitespace() => {
                     self.next_ch();
@@ -580,7 +651,7 @@ mod tests {
         let mut lex = Lexer::new(
             "v0 v00 vx01 ebb1234567890 ebb5234567890 v1x vx1 vxvx4 \
              function0 function b1 i32x4 f32x5 \
-             iflags fflags iflagss",
+             iflags fflags iflagss _ _x1",
         );
         assert_eq!(
             lex.next(),
@@ -604,6 +675,8 @@ mod tests {
         assert_eq!(lex.next(), token(Token::Type(types::IFLAGS), 1));
         assert_eq!(lex.next(), token(Token::Type(types::FFLAGS), 1));
         assert_eq!(lex.next(), token(Token::Identifier("iflagss"), 1));
+        assert_eq!(lex.next(), token(Token::Identifier("_"), 1));
+        assert_eq!(lex.next(), token(Token::Identifier("_x1"), 1));
         assert_eq!(lex.next(), None);
     }
 
@@ -630,6 +703,44 @@ mod tests {
         assert_eq!(lex.next(), token(Token::Name("_"), 1));
     }
 
+    #[test]
+    fn lex_strings() {
+        let mut lex = Lexer::new(
+            r#""" "foo" "a \"b\" c" "\\" "π"
+            "unterminated
+            "\"#,
+        );
+
+        assert_eq!(lex.next(), token(Token::String(""), 1));
+        assert_eq!(lex.next(), token(Token::String("foo"), 1));
+        assert_eq!(lex.next(), token(Token::String(r#"a \"b\" c"#), 1));
+        assert_eq!(lex.next(), token(Token::String(r"\\"), 1));
+        assert_eq!(lex.next(), token(Token::String("π"), 1));
+        assert_eq!(lex.next(), error(LexError::UnterminatedString, 2));
+        assert_eq!(lex.next(), error(LexError::UnterminatedString, 3));
+        assert_eq!(lex.next(), None);
+    }
+
+    #[test]
+    fn unescape() {
+        assert_eq!(unescape_string(""), Some("".to_string()));
+        assert_eq!(unescape_string("foo"), Some("foo".to_string()));
+        assert_eq!(
+            unescape_string(r#"\"\'\\\n\r\t\0"#),
+            Some("\"'\\\n\r\t\0".to_string())
+        );
+        assert_eq!(
+            unescape_string(r"a\u{3c0}b\u{10ffff}"),
+            Some("aπb\u{10ffff}".to_string())
+        );
+        assert_eq!(unescape_string(r"\"), None);
+        assert_eq!(unescape_string(r"\x41"), None);
+        assert_eq!(unescape_string(r"\u{}"), None);
+        assert_eq!(unescape_string(r"\u{d800}"), None);
+        assert_eq!(unescape_string(r"\u{1234567}"), None);
+        assert_eq!(unescape_string(r"\u3c0"), None);
+    }
+
     #[test]
     fn lex_userrefs() {
         let mut lex = Lexer::new("u0 u1 u234567890 u9:8765");
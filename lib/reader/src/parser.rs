@@ -5,12 +5,12 @@ use cranelift_codegen::ir;
 use cranelift_codegen::ir::entities::AnyEntity;
 use cranelift_codegen::ir::immediates::{Ieee32, Ieee64, Imm64, Offset32, Uimm32};
 use cranelift_codegen::ir::instructions::{InstructionData, InstructionFormat, VariableArgs};
-use cranelift_codegen::ir::types::INVALID;
+use cranelift_codegen::ir::types::{self, INVALID};
 use cranelift_codegen::ir::{
-    AbiParam, ArgumentExtension, ArgumentLoc, Ebb, ExtFuncData, ExternalName, FuncRef, Function,
-    GlobalValue, GlobalValueData, Heap, HeapData, HeapStyle, JumpTable, JumpTableData, MemFlags,
-    Opcode, SigRef, Signature, StackSlot, StackSlotData, StackSlotKind, Table, TableData, Type,
-    Value, ValueLoc,
+    AbiParam, ArgumentExtension, ArgumentLoc, ArgumentPurpose, BranchHint, Ebb, ExtFuncData,
+    ExternalName, FuncRef, Function, GlobalValue, GlobalValueData, Heap, HeapData, HeapStyle,
+    JumpTable, JumpTableData, MemFlags, Opcode, SigRef, Signature, StackSlot, StackSlotData,
+    StackSlotKind, Table, TableData, Type, Value, ValueLoc,
 };
 use cranelift_codegen::isa::{self, Encoding, RegUnit, TargetIsa};
 use cranelift_codegen::packed_option::ReservedValue;
@@ -18,7 +18,7 @@ use cranelift_codegen::settings::CallConv;
 use cranelift_codegen::{settings, timing};
 use error::{Location, ParseError, ParseResult};
 use isaspec;
-use lexer::{LexError, Lexer, LocatedError, LocatedToken, Token};
+use lexer::{unescape_string, LexError, Lexer, LocatedError, LocatedToken, Token};
 use sourcemap::SourceMap;
 use std::mem;
 use std::str::FromStr;
@@ -80,6 +80,17 @@ pub fn parse_test<'a>(
     })
 }
 
+/// Parse the bit width in a `target pointer_width` command into a pointer type.
+fn parse_pointer_width(width: Option<&str>, loc: Location) -> ParseResult<Type> {
+    match width {
+        Some("16") => Ok(types::I16),
+        Some("32") => Ok(types::I32),
+        Some("64") => Ok(types::I64),
+        Some(w) => err!(loc, "invalid pointer width '{}'", w),
+        None => err!(loc, "expected pointer width"),
+    }
+}
+
 pub struct Parser<'a> {
     lex: Lexer<'a>,
 
@@ -99,6 +110,9 @@ pub struct Parser<'a> {
 
     /// Comments collected so far.
     comments: Vec<Comment<'a>>,
+
+    /// Pointer type from a `target pointer_width` command, applied to all parsed functions.
+    pointer_type: Option<Type>,
 }
 
 /// Context for resolving references when parsing a single function.
@@ -195,6 +209,7 @@ impl<'a> Context<'a> {
                     bound: Imm64::new(0),
                 },
                 index_type: INVALID,
+                check_alignment: false,
             });
         }
         self.function.heaps[heap] = data;
@@ -318,6 +333,7 @@ impl<'a> Parser<'a> {
             gathering_comments: false,
             gathered_comments: Vec::new(),
             comments: Vec::new(),
+            pointer_type: None,
         }
     }
 
@@ -585,6 +601,16 @@ impl<'a> Parser<'a> {
         }
     }
 
+    // Match and consume an optional trap payload, which follows the trap code after a comma.
+    fn optional_trap_payload(&mut self) -> ParseResult<Uimm32> {
+        if self.optional(Token::Comma) {
+            self.match_uimm32("expected trap payload")
+        } else {
+            // A trap without a payload has the payload 0.
+            Ok(Uimm32::from(0))
+        }
+    }
+
     // Match and consume an optional offset32 immediate.
     //
     // Note that this will match an empty string as an empty offset, and that if an offset is
@@ -837,6 +863,11 @@ impl<'a> Parser<'a> {
                         Some(w) => w,
                         None => return err!(loc, "expected target triple"),
                     };
+                    // Look for `target pointer_width N`, which isn't an ISA.
+                    if target_name == "pointer_width" {
+                        self.pointer_type = Some(parse_pointer_width(words.next(), loc)?);
+                        continue;
+                    }
                     let triple = match Triple::from_str(target_name) {
                         Ok(triple) => triple,
                         Err(err) => return err!(loc, err),
@@ -888,6 +919,7 @@ impl<'a> Parser<'a> {
         if let Some(err) = self.lex_error {
             return match err {
                 LexError::InvalidChar => err!(self.loc, "invalid character"),
+                LexError::UnterminatedString => err!(self.loc, "unterminated string"),
             };
         }
         Ok(list)
@@ -918,6 +950,7 @@ impl<'a> Parser<'a> {
         let sig = self.parse_signature(unique_isa)?;
 
         let mut ctx = Context::new(Function::with_name_signature(name, sig), unique_isa);
+        ctx.function.pointer_type = self.pointer_type;
 
         // function ::= "function" name signature * "{" preamble function-body "}"
         self.match_token(Token::LBrace, "expected '{' before function body")?;
@@ -959,6 +992,12 @@ impl<'a> Parser<'a> {
                 s.parse()
                     .map_err(|_| self.error("invalid test case or libcall name"))
             }
+            Some(Token::String(s)) => {
+                let name = unescape_string(s)
+                    .ok_or_else(|| self.error("invalid escape sequence in symbol name"))?;
+                self.consume();
+                Ok(ExternalName::symbol(name))
+            }
             Some(Token::UserRef(namespace)) => {
                 self.consume();
                 match self.token() {
@@ -1047,6 +1086,16 @@ impl<'a> Parser<'a> {
             match s {
                 "uext" => arg.extension = ArgumentExtension::Uext,
                 "sext" => arg.extension = ArgumentExtension::Sext,
+                "ptr" => arg.legalized_to_pointer = true,
+                "sarg" => {
+                    // flag ::= "sarg" * "(" uimm32 ")"
+                    self.consume();
+                    self.match_token(Token::LPar, "expected '(' after sarg")?;
+                    let size = self.match_uimm32("expected struct size in bytes")?;
+                    self.match_token(Token::RPar, "expected ')' after struct size")?;
+                    arg.purpose = ArgumentPurpose::StructArgument(size.into());
+                    continue;
+                }
                 _ => {
                     if let Ok(purpose) = s.parse() {
                         arg.purpose = purpose;
@@ -1109,9 +1158,11 @@ impl<'a> Parser<'a> {
     //
     // preamble      ::= * { preamble-decl }
     // preamble-decl ::= * stack-slot-decl
+    //                   * frame-size-decl
     //                   * function-decl
     //                   * signature-decl
     //                   * jump-table-decl
+    // frame-size-decl ::= "frame_size" Bytes
     //
     // The parsed decls are added to `ctx` rather than returned.
     fn parse_preamble(&mut self, ctx: &mut Context) -> ParseResult<()> {
@@ -1123,6 +1174,11 @@ impl<'a> Parser<'a> {
                     self.parse_stack_slot_decl()
                         .and_then(|(ss, dat)| ctx.add_ss(ss, dat, loc))
                 }
+                Some(Token::Identifier("frame_size")) => {
+                    self.consume();
+                    self.match_uimm32("expected frame size in bytes")
+                        .map(|size| ctx.function.stack_slots.frame_size = Some(size.into()))
+                }
                 Some(Token::GlobalValue(..)) => {
                     self.start_gathering_comments();
                     self.parse_global_value_decl()
@@ -1203,8 +1259,10 @@ impl<'a> Parser<'a> {
     //
     // global-val-decl ::= * GlobalValue(gv) "=" global-val-desc
     // global-val-desc ::= "vmctx"
-    //                   | "load" "." type "notrap" "aligned" GlobalValue(base) [offset]
+    //                   | "load" "." type "notrap" "aligned" ["readonly"] GlobalValue(base)
+    //                     [offset]
     //                   | "iadd_imm" "(" GlobalValue(base) ")" imm64
+    //                   | "iadd_scaled" "." type GlobalValue(base) "," GlobalValue(index) "," imm64
     //                   | "symbol" ["colocated"] name + imm64
     //
     fn parse_global_value_decl(&mut self) -> ParseResult<(GlobalValue, GlobalValueData)> {
@@ -1221,6 +1279,7 @@ impl<'a> Parser<'a> {
                 )?;
                 let global_type = self.match_type("expected load type")?;
                 let flags = self.optional_memflags();
+                let readonly = self.optional(Token::Identifier("readonly"));
                 let base = self.match_gv("expected global value: gv«n»")?;
                 let offset = self.optional_offset32()?;
                 let mut expected_flags = MemFlags::new();
@@ -1233,6 +1292,7 @@ impl<'a> Parser<'a> {
                     base,
                     offset,
                     global_type,
+                    readonly,
                 }
             }
             "iadd_imm" => {
@@ -1253,6 +1313,30 @@ impl<'a> Parser<'a> {
                     global_type,
                 }
             }
+            "iadd_scaled" => {
+                self.match_token(
+                    Token::Dot,
+                    "expected '.' followed by type in iadd_scaled global value decl",
+                )?;
+                let global_type = self.match_type("expected iadd type")?;
+                let base = self.match_gv("expected global value: gv«n»")?;
+                self.match_token(
+                    Token::Comma,
+                    "expected ',' followed by index in iadd_scaled global value decl",
+                )?;
+                let index = self.match_gv("expected global value: gv«n»")?;
+                self.match_token(
+                    Token::Comma,
+                    "expected ',' followed by scale in iadd_scaled global value decl",
+                )?;
+                let scale = self.match_imm64("expected iadd_scaled scale")?;
+                GlobalValueData::IAddScaled {
+                    base,
+                    index,
+                    scale,
+                    global_type,
+                }
+            }
             "symbol" => {
                 let colocated = self.optional(Token::Identifier("colocated"));
                 let name = self.parse_external_name()?;
@@ -1283,6 +1367,7 @@ impl<'a> Parser<'a> {
     //             | "bound" Imm64(bytes)
     //             | "guard" Imm64(bytes)
     //             | "index_type" type
+    //             | "check_alignment"
     //
     fn parse_heap_decl(&mut self) -> ParseResult<(Heap, HeapData)> {
         let heap = self.match_heap("expected heap number: heap«n»")?;
@@ -1307,6 +1392,7 @@ impl<'a> Parser<'a> {
             guard_size: 0.into(),
             style: HeapStyle::Static { bound: 0.into() },
             index_type: ir::types::I32,
+            check_alignment: false,
         };
 
         // heap-desc ::= heap-style heap-base * { "," heap-attr }
@@ -1332,6 +1418,9 @@ impl<'a> Parser<'a> {
                 "index_type" => {
                     data.index_type = self.match_type("expected index type")?;
                 }
+                "check_alignment" => {
+                    data.check_alignment = true;
+                }
                 t => return err!(self.loc, "unknown heap attribute '{}'", t),
             }
         }
@@ -1500,30 +1589,19 @@ impl<'a> Parser<'a> {
 
         let mut data = JumpTableData::new();
 
-        // jump-table-decl ::= JumpTable(jt) "=" "jump_table" "[" * Ebb(dest) {"," Ebb(dest)} "]"
-        match self.token() {
-            Some(Token::Ebb(dest)) => {
-                self.consume();
-                data.push_entry(dest);
-
-                loop {
-                    match self.token() {
-                        Some(Token::Comma) => {
-                            self.consume();
-                            if let Some(Token::Ebb(dest)) = self.token() {
-                                self.consume();
-                                data.push_entry(dest);
-                            } else {
-                                return err!(self.loc, "expected jump_table entry");
-                            }
-                        }
-                        Some(Token::RBracket) => break,
-                        _ => return err!(self.loc, "expected ']' after jump table contents"),
+        // jump-table-decl ::= JumpTable(jt) "=" "jump_table" "[" * jt-entry {"," jt-entry} "]"
+        if self.token() != Some(Token::RBracket) {
+            self.parse_jump_table_entry(&mut data)?;
+            loop {
+                match self.token() {
+                    Some(Token::Comma) => {
+                        self.consume();
+                        self.parse_jump_table_entry(&mut data)?;
                     }
+                    Some(Token::RBracket) => break,
+                    _ => return err!(self.loc, "expected ']' after jump table contents"),
                 }
             }
-            Some(Token::RBracket) => (),
-            _ => return err!(self.loc, "expected jump_table entry"),
         }
 
         self.consume();
@@ -1535,6 +1613,19 @@ impl<'a> Parser<'a> {
         Ok((jt, data))
     }
 
+    // Parse a jump table entry and append it to `data`.
+    //
+    // jt-entry ::= * Ebb(dest) | "_"
+    fn parse_jump_table_entry(&mut self, data: &mut JumpTableData) -> ParseResult<()> {
+        match self.token() {
+            Some(Token::Ebb(dest)) => data.push_entry(dest),
+            Some(Token::Identifier("_")) => data.push_hole(),
+            _ => return err!(self.loc, "expected jump_table entry"),
+        }
+        self.consume();
+        Ok(())
+    }
+
     // Parse a function body, add contents to `ctx`.
     //
     // function-body ::= * { extended-basic-block }
@@ -1878,7 +1969,23 @@ impl<'a> Parser<'a> {
         };
 
         // instruction ::=  [inst-results "="] Opcode(opc) ["." Type] * ...
-        let inst_data = self.parse_inst_operands(ctx, opcode)?;
+        let mut inst_data = self.parse_inst_operands(ctx, opcode)?;
+
+        // instruction ::=  [inst-results "="] Opcode(opc) ["." Type] operands * [branch-hint]
+        let branch_hint = match self.token() {
+            Some(Token::Identifier(text)) => text.parse::<BranchHint>().ok(),
+            _ => None,
+        };
+        if branch_hint.is_some() {
+            if !opcode.is_branch() || opcode.is_terminator() {
+                return err!(self.loc, "{} is not a conditional branch", opcode);
+            }
+            self.consume();
+        }
+        let patchable = self.token() == Some(Token::Identifier("patchable"));
+        if patchable {
+            self.consume();
+        }
 
         // We're done parsing the instruction now.
         //
@@ -1886,6 +1993,13 @@ impl<'a> Parser<'a> {
         // or function call signature. We also need to create values with the right type for all
         // the instruction results.
         let ctrl_typevar = self.infer_typevar(ctx, opcode, explicit_ctrl_type, &inst_data)?;
+
+        // Integer immediates like `0xffff_ffff` and `-1` are the same for an `i32`, and they are
+        // stored in the same sign-extended form.
+        if !inst_data.sign_extend_imm(ctrl_typevar) {
+            return err!(opcode_loc, "immediate out of range for {}", ctrl_typevar);
+        }
+
         let inst = ctx.function.dfg.make_inst(inst_data);
         let num_results =
             ctx.function
@@ -1904,6 +2018,11 @@ impl<'a> Parser<'a> {
             ctx.function.encodings[inst] = encoding;
         }
 
+        ctx.function.branch_hints[inst] = branch_hint;
+        if patchable {
+            ctx.function.patchable.insert(inst);
+        }
+
         if results.len() != num_results {
             return err!(
                 self.loc,
@@ -2467,6 +2586,51 @@ impl<'a> Parser<'a> {
                     offset,
                 }
             }
+            InstructionFormat::LoadSymbol => {
+                let flags = self.optional_memflags();
+                let arg = self.match_value("expected SSA value operand")?;
+                self.match_token(Token::Comma, "expected ',' between operands")?;
+                let gv = self.match_gv("expected global value")?;
+                ctx.check_gv(gv, self.loc)?;
+                let offset = self.optional_offset32()?;
+                InstructionData::LoadSymbol {
+                    opcode,
+                    flags,
+                    arg,
+                    global_value: gv,
+                    offset,
+                }
+            }
+            InstructionFormat::LoadGlobal => {
+                let flags = self.optional_memflags();
+                let gv = self.match_gv("expected global value")?;
+                ctx.check_gv(gv, self.loc)?;
+                let offset = self.optional_offset32()?;
+                InstructionData::LoadGlobal {
+                    opcode,
+                    flags,
+                    global_value: gv,
+                    offset,
+                }
+            }
+            InstructionFormat::StoreSymbol => {
+                let flags = self.optional_memflags();
+                let arg = self.match_value("expected SSA value operand")?;
+                self.match_token(Token::Comma, "expected ',' between operands")?;
+                let index = self.match_value("expected SSA value index")?;
+                self.match_token(Token::Comma, "expected ',' between operands")?;
+                let gv = self.match_gv("expected global value")?;
+                ctx.check_gv(gv, self.loc)?;
+                let offset = self.optional_offset32()?;
+                InstructionData::StoreSymbol {
+                    opcode,
+                    flags,
+                    args: VariableArgs::new()
+                        .into_value_list(&[arg, index], &mut ctx.function.dfg.value_lists),
+                    global_value: gv,
+                    offset,
+                }
+            }
             InstructionFormat::RegMove => {
                 let arg = self.match_value("expected SSA value operand")?;
                 self.match_token(Token::Comma, "expected ',' between operands")?;
@@ -2519,24 +2683,37 @@ impl<'a> Parser<'a> {
             }
             InstructionFormat::Trap => {
                 let code = self.match_enum("expected trap code")?;
-                InstructionData::Trap { opcode, code }
+                let payload = self.optional_trap_payload()?;
+                InstructionData::Trap {
+                    opcode,
+                    code,
+                    payload,
+                }
             }
             InstructionFormat::CondTrap => {
                 let arg = self.match_value("expected SSA value operand")?;
                 self.match_token(Token::Comma, "expected ',' between operands")?;
                 let code = self.match_enum("expected trap code")?;
-                InstructionData::CondTrap { opcode, arg, code }
+                let payload = self.optional_trap_payload()?;
+                InstructionData::CondTrap {
+                    opcode,
+                    arg,
+                    code,
+                    payload,
+                }
             }
             InstructionFormat::IntCondTrap => {
                 let cond = self.match_enum("expected intcc condition code")?;
                 let arg = self.match_value("expected SSA value operand")?;
                 self.match_token(Token::Comma, "expected ',' between operands")?;
                 let code = self.match_enum("expected trap code")?;
+                let payload = self.optional_trap_payload()?;
                 InstructionData::IntCondTrap {
                     opcode,
                     cond,
                     arg,
                     code,
+                    payload,
                 }
             }
             InstructionFormat::FloatCondTrap => {
@@ -2544,11 +2721,13 @@ impl<'a> Parser<'a> {
                 let arg = self.match_value("expected SSA value operand")?;
                 self.match_token(Token::Comma, "expected ',' between operands")?;
                 let code = self.match_enum("expected trap code")?;
+                let payload = self.optional_trap_payload()?;
                 InstructionData::FloatCondTrap {
                     opcode,
                     cond,
                     arg,
                     code,
+                    payload,
                 }
             }
         };
@@ -2561,8 +2740,8 @@ mod tests {
     use super::*;
     use cranelift_codegen::ir::entities::AnyEntity;
     use cranelift_codegen::ir::types;
-    use cranelift_codegen::ir::StackSlotKind;
     use cranelift_codegen::ir::{ArgumentExtension, ArgumentPurpose};
+    use cranelift_codegen::ir::{LibCall, StackSlotKind};
     use cranelift_codegen::settings::CallConv;
     use error::ParseError;
     use isaspec::IsaSpec;
@@ -2578,6 +2757,11 @@ mod tests {
         let ParseError { location, message } = p.parse_abi_param(None).unwrap_err();
         assert_eq!(location.line_number, 1);
         assert_eq!(message, "expected parameter type");
+
+        let mut p = Parser::new("i64 sarg(12) ptr");
+        let arg = p.parse_abi_param(None).unwrap();
+        assert_eq!(arg.purpose, ArgumentPurpose::StructArgument(12));
+        assert!(arg.legalized_to_pointer);
     }
 
     #[test]
@@ -2695,6 +2879,19 @@ mod tests {
         );
     }
 
+    #[test]
+    fn frame_size_decl() {
+        let (func, _) = Parser::new(
+            "function %foo() system_v {
+                                       ss0 = explicit_slot 8, offset -24
+                                       frame_size 32
+                                     }",
+        ).parse_function(None)
+        .unwrap();
+        assert_eq!(func.stack_slots.frame_size, Some(32));
+        assert!(func.to_string().contains("    frame_size 32\n"));
+    }
+
     #[test]
     fn ebb_header() {
         let (func, _) = Parser::new(
@@ -2744,6 +2941,41 @@ mod tests {
         assert_eq!(message, "duplicate entity: jt0");
     }
 
+    #[test]
+    fn jump_table_holes() {
+        let (func, _) = Parser::new(
+            "function %holes() system_v {
+                jt0 = jump_table [_, ebb1, _, _, ebb0]
+                jt1 = jump_table [_]
+            ebb0:
+                trap user0
+            ebb1:
+                trap user1
+            }",
+        ).parse_function(None)
+        .unwrap();
+        let jt0 = JumpTable::with_number(0).unwrap();
+        let jt1 = JumpTable::with_number(1).unwrap();
+        assert_eq!(
+            func.jump_tables[jt0].to_string(),
+            "jump_table [_, ebb1, _, _, ebb0]"
+        );
+        assert_eq!(func.jump_tables[jt0].len(), 5);
+        assert_eq!(
+            func.jump_tables[jt0].get_entry(1).unwrap().to_string(),
+            "ebb1"
+        );
+        assert_eq!(func.jump_tables[jt1].to_string(), "jump_table [_]");
+
+        let ParseError { location, message } = Parser::new(
+            "function %holes() system_v {
+                jt0 = jump_table [ebb0, __]",
+        ).parse_function(None)
+        .unwrap_err();
+        assert_eq!(location.line_number, 2);
+        assert_eq!(message, "expected jump_table entry");
+    }
+
     #[test]
     fn duplicate_ss() {
         let ParseError { location, message } = Parser::new(
@@ -2911,6 +3143,45 @@ mod tests {
         }
     }
 
+    #[test]
+    fn pointer_width() {
+        let tf = parse_test(
+            "test verifier
+             target pointer_width 32
+             function %foo() system_v {}
+             function %bar() system_v {}",
+            None,
+            None,
+        ).unwrap();
+        match tf.isa_spec {
+            IsaSpec::None(_) => {}
+            _ => panic!("unexpected ISAs"),
+        }
+        assert_eq!(tf.functions.len(), 2);
+        assert_eq!(tf.functions[0].0.pointer_type, Some(types::I32));
+        assert_eq!(tf.functions[1].0.pointer_type, Some(types::I32));
+
+        let tf = parse_test("function %foo() system_v {}", None, None).unwrap();
+        assert_eq!(tf.functions[0].0.pointer_type, None);
+
+        assert!(
+            parse_test(
+                "target pointer_width
+                 function %foo() system_v {}",
+                None,
+                None,
+            ).is_err()
+        );
+        assert!(
+            parse_test(
+                "target pointer_width 48
+                 function %foo() system_v {}",
+                None,
+                None,
+            ).is_err()
+        );
+    }
+
     #[test]
     fn user_function_name() {
         // Valid characters in the name:
@@ -2958,4 +3229,147 @@ mod tests {
         );
         assert!(parser.parse_function(None).is_err());
     }
+
+    #[test]
+    fn symbol_names() {
+        let text = r#"function "a \"quoted\" name\u{1}"() system_v {
+    gv0 = symbol "data.rel.ro"+8
+    sig0 = () system_v
+    fn0 = "u0:1" sig0
+    fn1 = u0:1 sig0
+    fn2 = "%FloorF32" sig0
+    fn3 = %FloorF32 sig0
+
+ebb0:
+    call fn0()
+    call fn1()
+    call fn2()
+    call fn3()
+    return
+}
+"#;
+        let func = Parser::new(text).parse_function(None).unwrap().0;
+        assert_eq!(
+            func.name,
+            ExternalName::symbol("a \"quoted\" name\u{1}")
+        );
+        assert_eq!(func.to_string(), text);
+
+        // Symbols never collide with the other kinds of names spelled the same.
+        let names: Vec<_> = func.dfg.ext_funcs.values().map(|f| &f.name).collect();
+        assert_eq!(*names[0], ExternalName::symbol("u0:1"));
+        assert_eq!(*names[1], ExternalName::user(0, 1));
+        assert_eq!(*names[2], ExternalName::symbol("%FloorF32"));
+        assert_eq!(*names[3], ExternalName::LibCall(LibCall::FloorF32));
+
+        let mut parser = Parser::new(r#"function "\x41"() system_v {}"#);
+        assert_eq!(
+            parser.parse_function(None).unwrap_err().to_string(),
+            "1: invalid escape sequence in symbol name"
+        );
+
+        let mut parser = Parser::new(
+            r#"function "foo
+               () system_v {}"#,
+        );
+        assert!(parser.parse_function(None).is_err());
+    }
+
+    #[test]
+    fn edge_immediates() {
+        // The printed function parses back to itself.
+        let text = r#"function %edges(i64) -> f64 system_v {
+    ss0 = explicit_slot 8, offset -2147483648
+    gv0 = symbol colocated "data"-0x2710
+    gv1 = symbol "data"-0x8000_0000_0000_0000
+    gv2 = symbol "data"+0x7fff_ffff_ffff_ffff
+    gv3 = iadd_imm.i64 gv0, 0x8000_0000_0000_0000
+    gv4 = load.i64 notrap aligned gv3-0x8000_0000
+
+ebb0(v0: i64):
+    v1 = iconst.i64 0x8000_0000_0000_0000
+    v2 = iconst.i8 -128
+    v3 = iconst.i32 0x8000_0000
+    v4 = icmp_imm eq v0, 0x8000_0000_0000_0000
+    v5 = load.i64 v0-0x8000_0000
+    v6 = load.i64 v0+0x7fff_ffff
+    v7 = f32const -sNaN:0x1
+    v8 = f32const +sNaN:0x3fffff
+    v9 = f64const -NaN:0x5
+    v10 = f64const -0.0
+    v11 = f64const -0x0.0000000000001p-1022
+    v12 = f64const -Inf
+    return v10
+}
+"#;
+        let func = Parser::new(text).parse_function(None).unwrap().0;
+        assert_eq!(func.to_string(), text);
+        let symbol_offsets: Vec<i64> = func
+            .global_values
+            .values()
+            .take(3)
+            .map(|gv| gv.symbol_offset())
+            .collect();
+        assert_eq!(symbol_offsets, [-10000, i64::min_value(), i64::max_value()]);
+    }
+
+    #[test]
+    fn immediate_out_of_range() {
+        let ParseError { location, message } = Parser::new(
+            "function %imm(i8) {
+                ebb0(v0: i8):
+                    v1 = iconst.i32 0x1_0000_0000
+                    v2 = iadd_imm v0, 256",
+        ).parse_function(None)
+        .unwrap_err();
+
+        assert_eq!(location.line_number, 3);
+        assert_eq!(message, "immediate out of range for i32");
+
+        let ParseError { location, message } = Parser::new(
+            "function %imm(i8) {
+                ebb0(v0: i8):
+                    v1 = iadd_imm v0, 256",
+        ).parse_function(None)
+        .unwrap_err();
+
+        assert_eq!(location.line_number, 3);
+        assert_eq!(message, "immediate out of range for i8");
+    }
+
+    #[test]
+    fn branch_hints() {
+        let (func, details) = Parser::new(
+            "function %hints(i32) {
+             ebb0(v0: i32):
+                 brz v0, ebb1 unlikely ; cold
+                 brnz v0, ebb2 likely
+                 jump ebb1
+             ebb1:
+                 return
+             ebb2:
+                 return
+             }",
+        ).parse_function(None)
+        .unwrap();
+        let insts: Vec<_> = func
+            .layout
+            .ebb_insts(func.layout.entry_block().unwrap())
+            .collect();
+        assert_eq!(func.branch_hints[insts[0]], Some(BranchHint::Unlikely));
+        assert_eq!(func.branch_hints[insts[1]], Some(BranchHint::Likely));
+        assert_eq!(func.branch_hints[insts[2]], None);
+        assert_eq!(details.comments[0].text, "; cold");
+
+        // Only conditional branches can have hints.
+        let mut parser = Parser::new(
+            "function %hints() {
+             ebb0:
+                 jump ebb1 likely
+             ebb1:
+                 return
+             }",
+        );
+        assert!(parser.parse_function(None).is_err());
+    }
 }
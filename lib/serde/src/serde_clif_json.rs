@@ -175,6 +175,26 @@ pub enum SerInstData {
         flags: String,
         offset: String,
     },
+    LoadSymbol {
+        opcode: String,
+        arg: String,
+        flags: String,
+        global_value: String,
+        offset: String,
+    },
+    LoadGlobal {
+        opcode: String,
+        flags: String,
+        global_value: String,
+        offset: String,
+    },
+    StoreSymbol {
+        opcode: String,
+        args: Vec<String>,
+        flags: String,
+        global_value: String,
+        offset: String,
+    },
     StackLoad {
         opcode: String,
         stack_slot: String,
@@ -224,23 +244,27 @@ pub enum SerInstData {
     Trap {
         opcode: String,
         code: String,
+        payload: String,
     },
     CondTrap {
         opcode: String,
         arg: String,
         code: String,
+        payload: String,
     },
     IntCondTrap {
         opcode: String,
         arg: String,
         cond: String,
         code: String,
+        payload: String,
     },
     FloatCondTrap {
         opcode: String,
         arg: String,
         cond: String,
         code: String,
+        payload: String,
     },
 }
 
@@ -592,6 +616,50 @@ pub fn get_inst_data(inst_index: Inst, func: &Function) -> SerInstData {
                 offset: offset.to_string(),
             }
         }
+        InstructionData::LoadSymbol {
+            opcode,
+            arg,
+            flags,
+            global_value,
+            offset,
+        } => SerInstData::LoadSymbol {
+            opcode: opcode.to_string(),
+            arg: arg.to_string(),
+            flags: flags.to_string(),
+            global_value: global_value.to_string(),
+            offset: offset.to_string(),
+        },
+        InstructionData::LoadGlobal {
+            opcode,
+            flags,
+            global_value,
+            offset,
+        } => SerInstData::LoadGlobal {
+            opcode: opcode.to_string(),
+            flags: flags.to_string(),
+            global_value: global_value.to_string(),
+            offset: offset.to_string(),
+        },
+        InstructionData::StoreSymbol {
+            opcode,
+            ref args,
+            flags,
+            global_value,
+            offset,
+        } => {
+            let mut hold_args = Vec::new();
+            let args_iter = args.as_slice(&func.dfg.value_lists);
+            for arg in args_iter {
+                hold_args.push(arg.to_string());
+            }
+            SerInstData::StoreSymbol {
+                opcode: opcode.to_string(),
+                args: hold_args,
+                flags: flags.to_string(),
+                global_value: global_value.to_string(),
+                offset: offset.to_string(),
+            }
+        }
         InstructionData::StackLoad {
             opcode,
             stack_slot,
@@ -672,36 +740,51 @@ pub fn get_inst_data(inst_index: Inst, func: &Function) -> SerInstData {
             src: src.to_string(),
             dst: dst.to_string(),
         },
-        InstructionData::Trap { opcode, code } => SerInstData::Trap {
+        InstructionData::Trap {
+            opcode,
+            code,
+            payload,
+        } => SerInstData::Trap {
             opcode: opcode.to_string(),
             code: code.to_string(),
+            payload: payload.to_string(),
         },
-        InstructionData::CondTrap { opcode, arg, code } => SerInstData::CondTrap {
+        InstructionData::CondTrap {
+            opcode,
+            arg,
+            code,
+            payload,
+        } => SerInstData::CondTrap {
             opcode: opcode.to_string(),
             arg: arg.to_string(),
             code: code.to_string(),
+            payload: payload.to_string(),
         },
         InstructionData::IntCondTrap {
             opcode,
             arg,
             cond,
             code,
+            payload,
         } => SerInstData::IntCondTrap {
             opcode: opcode.to_string(),
             arg: arg.to_string(),
             cond: cond.to_string(),
             code: code.to_string(),
+            payload: payload.to_string(),
         },
         InstructionData::FloatCondTrap {
             opcode,
             arg,
             cond,
             code,
+            payload,
         } => SerInstData::FloatCondTrap {
             opcode: opcode.to_string(),
             arg: arg.to_string(),
             cond: cond.to_string(),
             code: code.to_string(),
+            payload: payload.to_string(),
         },
     }
 }
@@ -1,17 +1,23 @@
 //! Defines `SimpleJITBackend`.
 
-use cranelift_codegen::binemit::{Addend, CodeOffset, NullTrapSink, Reloc, RelocSink};
+use cranelift_codegen::binemit::{
+    write_data, Addend, CodeInfo, CollectedRelocs, CollectedTraps, Reloc, RelocRecord, RelocTarget,
+};
 use cranelift_codegen::isa::TargetIsa;
 use cranelift_codegen::{self, ir, settings};
 use cranelift_module::{
-    Backend, DataContext, DataDescription, Init, Linkage, ModuleNamespace, ModuleResult,
+    AppliedReloc, Backend, DataContext, DataDescription, FinalizedDataInfo, FinalizedFuncInfo,
+    FinalizedLocation, Init, Linkage, ModuleError, ModuleNamespace, ModuleResult, TrapSite,
 };
 use cranelift_native;
 use libc;
 use memory::Memory;
 use std::collections::HashMap;
 use std::ffi::CString;
+use std::mem;
 use std::ptr;
+use std::slice;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use target_lexicon::PointerWidth;
 #[cfg(windows)]
 use winapi;
@@ -82,6 +88,9 @@ impl SimpleJITBuilder {
     }
 }
 
+/// The largest alignment of the constants in the read-only data of a function.
+const RODATA_ALIGN: usize = 8;
+
 /// A `SimpleJITBackend` implements `Backend` and emits code and data into memory where it can be
 /// directly called and accessed.
 ///
@@ -92,20 +101,28 @@ pub struct SimpleJITBackend {
     code_memory: Memory,
     readonly_memory: Memory,
     writable_memory: Memory,
-}
-
-/// A record of a relocation to perform.
-struct RelocRecord {
-    offset: CodeOffset,
-    reloc: Reloc,
-    name: ir::ExternalName,
-    addend: Addend,
+    /// The relocations applied by `finalize_function` and `finalize_data`, keyed by the address of
+    /// the function or data object.
+    applied_relocs: HashMap<usize, Vec<AppliedReloc>>,
 }
 
 pub struct SimpleJITCompiledFunction {
     code: *mut u8,
     size: usize,
+    /// The jump tables and constants of the function, in read-only memory.
+    rodata: *mut u8,
+    rodata_size: usize,
     relocs: Vec<RelocRecord>,
+    traps: Vec<TrapSite>,
+}
+
+/// The indirection slot of a redefinable function.
+pub struct SimpleJITFunctionSlot {
+    /// A stub that jumps to the address stored in `target`. Calls to the function are linked
+    /// against the stub.
+    stub: *const u8,
+    /// The address of the current definition of the function.
+    target: *mut usize,
 }
 
 pub struct SimpleJITCompiledData {
@@ -121,6 +138,64 @@ impl SimpleJITBackend {
             None => lookup_with_dlsym(name),
         }
     }
+
+    /// Get the address that references to the function `name` should be linked against.
+    fn get_function_address(
+        &self,
+        name: &ir::ExternalName,
+        namespace: &ModuleNamespace<Self>,
+    ) -> *const u8 {
+        if let Some(slot) = namespace.get_function_slot(name) {
+            return slot.stub;
+        }
+        let (def, name_str, _signature) = namespace.get_function_definition(&name);
+        match def {
+            Some(compiled) => compiled.code,
+            None => self.lookup_symbol(name_str),
+        }
+    }
+
+    /// Get the relocations applied to the finalized function or data object at `ptr`.
+    fn get_applied_relocs(&self, ptr: *const u8) -> Vec<AppliedReloc> {
+        self.applied_relocs
+            .get(&(ptr as usize))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Allocate the read-only data of the function whose code is at `code`.
+    ///
+    /// The data is placed so that the constants are as aligned as they would be right after the
+    /// code. The code refers to the data with 32-bit displacements, so when the read-only memory
+    /// is too far away, the data is placed in the code memory instead.
+    fn allocate_rodata(&mut self, code: *mut u8, info: &CodeInfo) -> *mut u8 {
+        let code_end = code as usize + info.code_size as usize;
+        let size = info.data_size() as usize + RODATA_ALIGN - 1;
+        let aligned = |ptr: *mut u8| {
+            let misalign = (code_end.wrapping_sub(ptr as usize)) % RODATA_ALIGN;
+            unsafe { ptr.offset(misalign as isize) }
+        };
+        let rodata = aligned(
+            self.readonly_memory
+                .allocate(size)
+                .expect("TODO: handle OOM etc."),
+        );
+        let reach = (rodata as isize).wrapping_sub(code_end as isize) as i64;
+        if reach.abs() + i64::from(info.data_size()) < i64::from(i32::max_value()) {
+            return rodata;
+        }
+        aligned(
+            self.code_memory
+                .allocate(size)
+                .expect("TODO: handle OOM etc."),
+        )
+    }
+
+    /// Write the low `size` bytes of `value` at `at`, in the byte order of the target.
+    fn write_reloc_value(&self, at: *mut u8, value: u64, size: usize) {
+        let bytes = unsafe { slice::from_raw_parts_mut(at, size) };
+        write_data(bytes, value, self.isa.endianness());
+    }
 }
 
 impl<'simple_jit_backend> Backend for SimpleJITBackend {
@@ -138,6 +213,10 @@ impl<'simple_jit_backend> Backend for SimpleJITBackend {
     type FinalizedFunction = *const u8;
     type FinalizedData = (*mut u8, usize);
 
+    /// SimpleJIT calls redefinable functions through a jump stub which loads the target address
+    /// from writable memory, so redirecting the calls is a single atomic store.
+    type FunctionSlot = SimpleJITFunctionSlot;
+
     /// SimpleJIT emits code and data into memory as it processes them, so it
     /// doesn't need to provide anything after the `Module` is complete.
     type Product = ();
@@ -150,6 +229,7 @@ impl<'simple_jit_backend> Backend for SimpleJITBackend {
             code_memory: Memory::new(),
             readonly_memory: Memory::new(),
             writable_memory: Memory::new(),
+            applied_relocs: HashMap::new(),
         }
     }
 
@@ -168,25 +248,44 @@ impl<'simple_jit_backend> Backend for SimpleJITBackend {
     fn define_function(
         &mut self,
         _name: &str,
-        ctx: &cranelift_codegen::Context,
+        ctx: &mut cranelift_codegen::Context,
         _namespace: &ModuleNamespace<Self>,
-        code_size: u32,
     ) -> ModuleResult<Self::CompiledFunction> {
-        let size = code_size as usize;
+        let mut reloc_sink = CollectedRelocs::new();
+        let mut trap_sink = CollectedTraps::new();
+        let info = ctx.compile(&*self.isa).map_err(ModuleError::Compilation)?;
+
+        // The jump tables and constants go in read-only memory, so they aren't executable.
+        let size = info.code_size as usize;
         let ptr = self
             .code_memory
             .allocate(size)
             .expect("TODO: handle OOM etc.");
-        let mut reloc_sink = SimpleJITRelocSink::new();
-        // Ignore traps for now. For now, frontends should just avoid generating code
-        // that traps.
-        let mut trap_sink = NullTrapSink {};
-        unsafe { ctx.emit_to_memory(&*self.isa, ptr, &mut reloc_sink, &mut trap_sink) };
+        let rodata_size = info.data_size() as usize;
+        let rodata = if rodata_size > 0 {
+            self.allocate_rodata(ptr, &info)
+        } else {
+            ptr::null_mut()
+        };
+        unsafe {
+            ctx.emit_to_memory_with_rodata(
+                &*self.isa,
+                ptr,
+                rodata,
+                &info,
+                &mut reloc_sink,
+                &mut trap_sink,
+            )
+        }
+        .map_err(ModuleError::Compilation)?;
 
         Ok(Self::CompiledFunction {
             code: ptr,
             size,
+            rodata,
+            rodata_size,
             relocs: reloc_sink.relocs,
+            traps: trap_sink.sites,
         })
     }
 
@@ -239,7 +338,7 @@ impl<'simple_jit_backend> Backend for SimpleJITBackend {
             relocs.push(RelocRecord {
                 reloc,
                 offset,
-                name: function_decls[id].clone(),
+                target: RelocTarget::External(function_decls[id].clone()),
                 addend: 0,
             });
         }
@@ -247,7 +346,7 @@ impl<'simple_jit_backend> Backend for SimpleJITBackend {
             relocs.push(RelocRecord {
                 reloc,
                 offset,
-                name: data_decls[id].clone(),
+                target: RelocTarget::External(data_decls[id].clone()),
                 addend,
             });
         }
@@ -283,24 +382,23 @@ impl<'simple_jit_backend> Backend for SimpleJITBackend {
         func: &Self::CompiledFunction,
         namespace: &ModuleNamespace<Self>,
     ) -> Self::FinalizedFunction {
-        use std::ptr::write_unaligned;
-
+        let mut applied = Vec::with_capacity(func.relocs.len());
         for &RelocRecord {
             reloc,
             offset,
-            ref name,
+            ref target,
             addend,
         } in &func.relocs
         {
+            let name = match *target {
+                RelocTarget::External(ref name) => name,
+                _ => unimplemented!(),
+            };
             let ptr = func.code;
             debug_assert!((offset as usize) < func.size);
             let at = unsafe { ptr.offset(offset as isize) };
             let base = if namespace.is_function(name) {
-                let (def, name_str, _signature) = namespace.get_function_definition(&name);
-                match def {
-                    Some(compiled) => compiled.code,
-                    None => self.lookup_symbol(name_str),
-                }
+                self.get_function_address(name, namespace)
             } else {
                 let (def, name_str, _writable) = namespace.get_data_definition(&name);
                 match def {
@@ -313,29 +411,34 @@ impl<'simple_jit_backend> Backend for SimpleJITBackend {
             match reloc {
                 Reloc::Abs4 => {
                     // TODO: Handle overflow.
-                    #[cfg_attr(feature = "cargo-clippy", allow(cast_ptr_alignment))]
-                    unsafe {
-                        write_unaligned(at as *mut u32, what as u32)
-                    };
+                    self.write_reloc_value(at, what as u64, 4);
                 }
                 Reloc::Abs8 => {
-                    #[cfg_attr(feature = "cargo-clippy", allow(cast_ptr_alignment))]
-                    unsafe {
-                        write_unaligned(at as *mut u64, what as u64)
-                    };
+                    self.write_reloc_value(at, what as u64, 8);
                 }
                 Reloc::X86PCRel4 | Reloc::X86CallPCRel4 => {
-                    // TODO: Handle overflow.
-                    let pcrel = ((what as isize) - (at as isize)) as i32;
-                    #[cfg_attr(feature = "cargo-clippy", allow(cast_ptr_alignment))]
-                    unsafe {
-                        write_unaligned(at as *mut i32, pcrel)
-                    };
+                    // The relocation is at the 4-byte displacement of the instruction, and the
+                    // addend adjusts for the rest of the instruction after it.
+                    let pcrel = (what as isize) - (at as isize);
+                    assert_eq!(
+                        pcrel as i32 as isize, pcrel,
+                        "PC-relative relocation of {} out of range",
+                        name
+                    );
+                    self.write_reloc_value(at, u64::from(pcrel as i32 as u32), 4);
                 }
                 Reloc::X86GOTPCRel4 | Reloc::X86CallPLTRel4 => panic!("unexpected PIC relocation"),
                 _ => unimplemented!(),
             }
+            applied.push(AppliedReloc {
+                offset,
+                reloc,
+                name: name.clone(),
+                addend,
+                target: Some(what as u64),
+            });
         }
+        self.applied_relocs.insert(func.code as usize, applied);
         func.code
     }
 
@@ -343,29 +446,90 @@ impl<'simple_jit_backend> Backend for SimpleJITBackend {
         func.code
     }
 
+    fn get_finalized_function_info(&self, func: &Self::CompiledFunction) -> FinalizedFuncInfo {
+        FinalizedFuncInfo {
+            location: FinalizedLocation::Address(func.code),
+            size: func.size,
+            rodata: if func.rodata.is_null() {
+                None
+            } else {
+                Some(FinalizedLocation::Address(func.rodata))
+            },
+            rodata_size: func.rodata_size,
+            traps: func.traps.clone(),
+            relocs: self.get_applied_relocs(func.code),
+        }
+    }
+
+    fn prepare_redefine(&mut self, name: &str) -> ModuleResult<Self::FunctionSlot> {
+        if self.isa.name() != "x86" {
+            return Err(ModuleError::Backend(format!(
+                "can't redefine function {}: indirection stubs are not implemented for {}",
+                name,
+                self.isa.name()
+            )));
+        }
+
+        // Allocate a naturally aligned word so that stores to it are atomic.
+        let word = mem::size_of::<usize>();
+        let storage = self
+            .writable_memory
+            .allocate(2 * word - 1)
+            .expect("TODO: handle OOM etc.");
+        let target = ((storage as usize + word - 1) & !(word - 1)) as *mut usize;
+        unsafe { ptr::write(target, 0) };
+
+        // The stub is `mov r11, target; jmp [r11]` on x86-64 and `jmp [target]` on x86-32.
+        let (opcode, suffix): (&[u8], &[u8]) = match self.isa.triple().pointer_width().unwrap() {
+            PointerWidth::U16 => panic!(),
+            PointerWidth::U32 => (&[0xff, 0x25], &[]),
+            PointerWidth::U64 => (&[0x49, 0xbb], &[0x41, 0xff, 0x23]),
+        };
+        let size = opcode.len() + word + suffix.len();
+        let stub = self
+            .code_memory
+            .allocate(size)
+            .expect("TODO: handle OOM etc.");
+        unsafe {
+            ptr::copy_nonoverlapping(opcode.as_ptr(), stub, opcode.len());
+            self.write_reloc_value(stub.offset(opcode.len() as isize), target as u64, word);
+            ptr::copy_nonoverlapping(
+                suffix.as_ptr(),
+                stub.offset((opcode.len() + word) as isize),
+                suffix.len(),
+            );
+        }
+
+        Ok(Self::FunctionSlot { stub, target })
+    }
+
+    fn swap_function(&mut self, slot: &Self::FunctionSlot, func: &Self::CompiledFunction) {
+        let target = unsafe { &*(slot.target as *const AtomicUsize) };
+        target.store(func.code as usize, Ordering::SeqCst);
+    }
+
     fn finalize_data(
         &mut self,
         data: &Self::CompiledData,
         namespace: &ModuleNamespace<Self>,
     ) -> Self::FinalizedData {
-        use std::ptr::write_unaligned;
-
+        let mut applied = Vec::with_capacity(data.relocs.len());
         for &RelocRecord {
             reloc,
             offset,
-            ref name,
+            ref target,
             addend,
         } in &data.relocs
         {
+            let name = match *target {
+                RelocTarget::External(ref name) => name,
+                _ => unimplemented!(),
+            };
             let ptr = data.storage;
             debug_assert!((offset as usize) < data.size);
             let at = unsafe { ptr.offset(offset as isize) };
             let base = if namespace.is_function(name) {
-                let (def, name_str, _signature) = namespace.get_function_definition(&name);
-                match def {
-                    Some(compiled) => compiled.code,
-                    None => self.lookup_symbol(name_str),
-                }
+                self.get_function_address(name, namespace)
             } else {
                 let (def, name_str, _writable) = namespace.get_data_definition(&name);
                 match def {
@@ -378,16 +542,10 @@ impl<'simple_jit_backend> Backend for SimpleJITBackend {
             match reloc {
                 Reloc::Abs4 => {
                     // TODO: Handle overflow.
-                    #[cfg_attr(feature = "cargo-clippy", allow(cast_ptr_alignment))]
-                    unsafe {
-                        write_unaligned(at as *mut u32, what as u32)
-                    };
+                    self.write_reloc_value(at, what as u64, 4);
                 }
                 Reloc::Abs8 => {
-                    #[cfg_attr(feature = "cargo-clippy", allow(cast_ptr_alignment))]
-                    unsafe {
-                        write_unaligned(at as *mut u64, what as u64)
-                    };
+                    self.write_reloc_value(at, what as u64, 8);
                 }
                 Reloc::X86PCRel4
                 | Reloc::X86CallPCRel4
@@ -395,7 +553,15 @@ impl<'simple_jit_backend> Backend for SimpleJITBackend {
                 | Reloc::X86CallPLTRel4 => panic!("unexpected text relocation in data"),
                 _ => unimplemented!(),
             }
+            applied.push(AppliedReloc {
+                offset,
+                reloc,
+                name: name.clone(),
+                addend,
+                target: Some(what as u64),
+            });
         }
+        self.applied_relocs.insert(data.storage as usize, applied);
         (data.storage, data.size)
     }
 
@@ -403,6 +569,14 @@ impl<'simple_jit_backend> Backend for SimpleJITBackend {
         (data.storage, data.size)
     }
 
+    fn get_finalized_data_info(&self, data: &Self::CompiledData) -> FinalizedDataInfo {
+        FinalizedDataInfo {
+            location: FinalizedLocation::Address(data.storage),
+            size: data.size,
+            relocs: self.get_applied_relocs(data.storage),
+        }
+    }
+
     fn publish(&mut self) {
         // Now that we're done patching, prepare the memory for execution!
         self.readonly_memory.set_readonly();
@@ -456,38 +630,3 @@ fn lookup_with_dlsym(name: &str) -> *const u8 {
         panic!("cannot resolve address of symbol {} {}", name, msg);
     }
 }
-
-struct SimpleJITRelocSink {
-    pub relocs: Vec<RelocRecord>,
-}
-
-impl SimpleJITRelocSink {
-    pub fn new() -> Self {
-        Self { relocs: Vec::new() }
-    }
-}
-
-impl RelocSink for SimpleJITRelocSink {
-    fn reloc_ebb(&mut self, _offset: CodeOffset, _reloc: Reloc, _ebb_offset: CodeOffset) {
-        unimplemented!();
-    }
-
-    fn reloc_external(
-        &mut self,
-        offset: CodeOffset,
-        reloc: Reloc,
-        name: &ir::ExternalName,
-        addend: Addend,
-    ) {
-        self.relocs.push(RelocRecord {
-            offset,
-            reloc,
-            name: name.clone(),
-            addend,
-        });
-    }
-
-    fn reloc_jt(&mut self, _offset: CodeOffset, _reloc: Reloc, _jt: ir::JumpTable) {
-        unimplemented!();
-    }
-}
@@ -0,0 +1,177 @@
+//! Writes perf jitdump files, so `perf` can symbolize and annotate JIT-compiled functions.
+//!
+//! Run `perf record -k mono` on a program that writes a jitdump file, then `perf inject --jit` on
+//! the recording to merge the functions into it. The file format is described in
+//! `tools/perf/Documentation/jitdump-specification.txt` in the Linux source tree.
+
+use cranelift_codegen::isa::TargetIsa;
+use cranelift_module::{FinalizedFuncInfo, FinalizedLocation};
+use libc;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::ptr;
+use std::slice;
+use target_lexicon::Architecture;
+
+/// The magic number at the start of a jitdump file, "JiTD" in the host byte order.
+const JITDUMP_MAGIC: u32 = 0x4A69_5444;
+
+/// The version of the jitdump format.
+const JITDUMP_VERSION: u32 = 1;
+
+/// The size in bytes of the jitdump file header.
+const HEADER_SIZE: u32 = 40;
+
+/// The size in bytes of the header of a record.
+const RECORD_HEADER_SIZE: u32 = 16;
+
+/// The record ID of a `JIT_CODE_LOAD` record.
+const JIT_CODE_LOAD: u32 = 0;
+
+/// A writer of a perf jitdump file.
+///
+/// The file is named `jit-<pid>.dump`, which is where `perf inject --jit` looks for it.
+pub struct JitDumpWriter {
+    file: File,
+    /// The mapping of the file that marks it for `perf record`.
+    marker: *mut libc::c_void,
+    marker_size: usize,
+    pid: u32,
+    code_index: u64,
+}
+
+impl JitDumpWriter {
+    /// Create a jitdump file in `dir` for functions compiled for `isa`, and write its header.
+    pub fn new<P: AsRef<Path>>(dir: P, isa: &TargetIsa) -> io::Result<Self> {
+        let pid = unsafe { libc::getpid() } as u32;
+        let path = dir.as_ref().join(format!("jit-{}.dump", pid));
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+
+        // `perf record` finds the jitdump file through an executable mapping of it.
+        let marker_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+        let marker = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                marker_size,
+                libc::PROT_READ | libc::PROT_EXEC,
+                libc::MAP_PRIVATE,
+                file_descriptor(&file),
+                0,
+            )
+        };
+        if marker == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut writer = Self {
+            file,
+            marker,
+            marker_size,
+            pid,
+            code_index: 0,
+        };
+        let mut header = Vec::with_capacity(HEADER_SIZE as usize);
+        push_u32(&mut header, JITDUMP_MAGIC);
+        push_u32(&mut header, JITDUMP_VERSION);
+        push_u32(&mut header, HEADER_SIZE);
+        push_u32(&mut header, elf_machine(isa.triple().architecture));
+        push_u32(&mut header, 0);
+        push_u32(&mut header, pid);
+        push_u64(&mut header, timestamp());
+        push_u64(&mut header, 0);
+        writer.file.write_all(&header)?;
+        Ok(writer)
+    }
+
+    /// Write a `JIT_CODE_LOAD` record for the finalized function `name`.
+    ///
+    /// The function must be in memory, so its code can be copied into the file.
+    pub fn write_function(&mut self, name: &str, info: &FinalizedFuncInfo) -> io::Result<()> {
+        let code = match info.location {
+            FinalizedLocation::Address(code) => code,
+            FinalizedLocation::Symbol { .. } => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "function is not in memory",
+                ))
+            }
+        };
+        let bytes = unsafe { slice::from_raw_parts(code, info.size) };
+        let tid = unsafe { libc::syscall(libc::SYS_gettid) } as u32;
+        let total_size = RECORD_HEADER_SIZE as usize + 40 + name.len() + 1 + info.size;
+
+        let mut record = Vec::with_capacity(total_size);
+        push_u32(&mut record, JIT_CODE_LOAD);
+        push_u32(&mut record, total_size as u32);
+        push_u64(&mut record, timestamp());
+        push_u32(&mut record, self.pid);
+        push_u32(&mut record, tid);
+        push_u64(&mut record, code as u64);
+        push_u64(&mut record, code as u64);
+        push_u64(&mut record, info.size as u64);
+        push_u64(&mut record, self.code_index);
+        record.extend_from_slice(name.as_bytes());
+        record.push(0);
+        record.extend_from_slice(bytes);
+        self.code_index += 1;
+        self.file.write_all(&record)
+    }
+}
+
+impl Drop for JitDumpWriter {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.marker, self.marker_size) };
+    }
+}
+
+fn file_descriptor(file: &File) -> libc::c_int {
+    use std::os::unix::io::AsRawFd;
+    file.as_raw_fd()
+}
+
+/// Get the ELF machine number of `arch`.
+fn elf_machine(arch: Architecture) -> u32 {
+    match arch {
+        Architecture::I386 | Architecture::I586 | Architecture::I686 => 3,
+        Architecture::Arm
+        | Architecture::Armv4t
+        | Architecture::Armv5te
+        | Architecture::Armv7
+        | Architecture::Armv7s
+        | Architecture::Thumbv6m
+        | Architecture::Thumbv7em
+        | Architecture::Thumbv7m => 40,
+        Architecture::X86_64 => 62,
+        Architecture::Aarch64 => 183,
+        Architecture::Riscv32 | Architecture::Riscv64 => 243,
+        _ => 0,
+    }
+}
+
+/// Get the current time of the monotonic clock that `perf` uses, in nanoseconds.
+fn timestamp() -> u64 {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts) };
+    ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64
+}
+
+fn push_u32(bytes: &mut Vec<u8>, value: u32) {
+    let start = bytes.len();
+    bytes.resize(start + 4, 0);
+    unsafe { ptr::write_unaligned(bytes[start..].as_mut_ptr() as *mut u32, value) };
+}
+
+fn push_u64(bytes: &mut Vec<u8>, value: u64) {
+    let start = bytes.len();
+    bytes.resize(start + 8, 0);
+    unsafe { ptr::write_unaligned(bytes[start..].as_mut_ptr() as *mut u64, value) };
+}
@@ -41,6 +41,10 @@ extern crate target_lexicon;
 extern crate winapi;
 
 mod backend;
+#[cfg(all(feature = "jitdump", target_os = "linux"))]
+mod jitdump;
 mod memory;
 
 pub use backend::{SimpleJITBackend, SimpleJITBuilder};
+#[cfg(all(feature = "jitdump", target_os = "linux"))]
+pub use jitdump::JitDumpWriter;
@@ -3,6 +3,9 @@ extern crate cranelift_entity;
 extern crate cranelift_frontend;
 extern crate cranelift_module;
 extern crate cranelift_simplejit;
+extern crate region;
+#[macro_use]
+extern crate target_lexicon;
 
 use cranelift_codegen::ir::*;
 use cranelift_codegen::settings::*;
@@ -11,6 +14,7 @@ use cranelift_entity::EntityRef;
 use cranelift_frontend::*;
 use cranelift_module::*;
 use cranelift_simplejit::*;
+use std::str::FromStr;
 
 #[test]
 fn error_on_incompatible_sig_in_declare_function() {
@@ -150,3 +154,774 @@ fn switch_error() {
         }
     }
 }
+
+extern "C" fn floor_plus_one(x: f32) -> f32 {
+    x.floor() + 1.0
+}
+
+#[test]
+#[cfg(target_arch = "x86_64")]
+fn declared_libcalls() {
+    use cranelift_codegen::isa;
+
+    // Calls to non-colocated functions may be too far away for a pc-relative call.
+    let mut flag_builder = builder();
+    flag_builder.set("code_model", "large").unwrap();
+    let isa = isa::lookup(triple!("x86_64"))
+        .unwrap()
+        .finish(Flags::new(flag_builder));
+    let mut jit_builder = SimpleJITBuilder::with_isa(isa);
+    jit_builder.symbol("wasm_rt_floorf", floor_plus_one as *const u8);
+    let mut module: Module<SimpleJITBackend> = Module::new(jit_builder);
+    module
+        .declare_libcalls(|libcall| match libcall {
+            LibCall::FloorF32 => "wasm_rt_floorf".to_owned(),
+            _ => libcall.to_string(),
+        }).unwrap();
+
+    let sig = Signature {
+        params: vec![AbiParam::new(types::F32)],
+        returns: vec![AbiParam::new(types::F32)],
+        call_conv: CallConv::SystemV,
+    };
+    let func_id = module
+        .declare_function("floor", Linkage::Local, &sig)
+        .unwrap();
+
+    let mut ctx = Context::new();
+    ctx.func = Function::with_name_signature(ExternalName::user(0, func_id.index() as u32), sig);
+    let mut func_ctx = FunctionBuilderContext::new();
+    {
+        let mut bcx: FunctionBuilder = FunctionBuilder::new(&mut ctx.func, &mut func_ctx);
+        let ebb = bcx.create_ebb();
+        bcx.switch_to_block(ebb);
+        let arg = bcx.append_ebb_param(ebb, types::F32);
+        let v = bcx.ins().floor(arg);
+        bcx.ins().return_(&[v]);
+        bcx.seal_all_blocks();
+        bcx.finalize();
+    }
+    module.define_function(func_id, &mut ctx).unwrap();
+    module.finalize_definitions();
+
+    let code = module.get_finalized_function(func_id);
+    let floor: extern "C" fn(f32) -> f32 = unsafe { std::mem::transmute(code) };
+    assert_eq!(floor(2.5), 3.0);
+}
+
+fn define_const_function(module: &mut Module<SimpleJITBackend>, func_id: FuncId, value: i64) {
+    let mut sig = module.make_signature();
+    sig.returns.push(AbiParam::new(types::I32));
+
+    let mut ctx = module.make_context();
+    ctx.func = Function::with_name_signature(ExternalName::user(0, func_id.index() as u32), sig);
+    let mut func_ctx = FunctionBuilderContext::new();
+    {
+        let mut bcx: FunctionBuilder = FunctionBuilder::new(&mut ctx.func, &mut func_ctx);
+        let ebb = bcx.create_ebb();
+        bcx.switch_to_block(ebb);
+        let v = bcx.ins().iconst(types::I32, value);
+        bcx.ins().return_(&[v]);
+        bcx.seal_all_blocks();
+        bcx.finalize();
+    }
+    module.define_function(func_id, &mut ctx).unwrap();
+}
+
+#[test]
+#[cfg(target_arch = "x86_64")]
+fn redefine_function() {
+    use cranelift_codegen::isa;
+
+    let isa = isa::lookup(triple!("x86_64"))
+        .unwrap()
+        .finish(Flags::new(builder()));
+    let mut module: Module<SimpleJITBackend> = Module::new(SimpleJITBuilder::with_isa(isa));
+
+    let mut sig = module.make_signature();
+    sig.returns.push(AbiParam::new(types::I32));
+    let callee = module
+        .declare_function("callee", Linkage::Local, &sig)
+        .unwrap();
+    let caller = module
+        .declare_function("caller", Linkage::Local, &sig)
+        .unwrap();
+    module.prepare_redefine(callee).unwrap();
+
+    define_const_function(&mut module, callee, 1);
+
+    let mut ctx = module.make_context();
+    ctx.func = Function::with_name_signature(ExternalName::user(0, caller.index() as u32), sig);
+    let mut func_ctx = FunctionBuilderContext::new();
+    {
+        let mut bcx: FunctionBuilder = FunctionBuilder::new(&mut ctx.func, &mut func_ctx);
+        let callee_ref = module.declare_func_in_func(callee, &mut bcx.func);
+        let ebb = bcx.create_ebb();
+        bcx.switch_to_block(ebb);
+        let call = bcx.ins().call(callee_ref, &[]);
+        let v = bcx.inst_results(call)[0];
+        bcx.ins().return_(&[v]);
+        bcx.seal_all_blocks();
+        bcx.finalize();
+    }
+    module.define_function(caller, &mut ctx).unwrap();
+    module.finalize_definitions();
+
+    let code = module.get_finalized_function(caller);
+    let caller_fn: extern "C" fn() -> i32 = unsafe { std::mem::transmute(code) };
+    assert_eq!(caller_fn(), 1);
+
+    // The new definition only takes effect once it's swapped in.
+    define_const_function(&mut module, callee, 2);
+    module.finalize_definitions();
+    assert_eq!(caller_fn(), 1);
+    module.swap_function(callee).unwrap();
+    assert_eq!(caller_fn(), 2);
+}
+
+#[test]
+#[cfg(target_arch = "x86_64")]
+fn symbol_names() {
+    use cranelift_codegen::isa;
+
+    let isa = isa::lookup(triple!("x86_64"))
+        .unwrap()
+        .finish(Flags::new(builder()));
+    let mut module: Module<SimpleJITBackend> = Module::new(SimpleJITBuilder::with_isa(isa));
+
+    // A function whose name looks like the user-defined name of the other one.
+    let mut sig = module.make_signature();
+    sig.returns.push(AbiParam::new(types::I32));
+    let look_alike = module
+        .declare_function("u0:1", Linkage::Local, &sig)
+        .unwrap();
+    let callee = module
+        .declare_function("callee", Linkage::Local, &sig)
+        .unwrap();
+    let caller = module
+        .declare_function("caller", Linkage::Local, &sig)
+        .unwrap();
+    assert_eq!(callee.index(), 1);
+    define_const_function(&mut module, look_alike, 1);
+    define_const_function(&mut module, callee, 2);
+
+    // Functions and data objects share a namespace.
+    match module.declare_data("callee", Linkage::Local, false) {
+        Err(ModuleError::IncompatibleDeclaration(ref name)) if name == "callee" => {}
+        _ => panic!("expected an IncompatibleDeclaration error"),
+    }
+
+    // Refer to the functions by symbol name without going through their `FuncId`s.
+    let mut ctx = module.make_context();
+    ctx.func = Function::with_name_signature(ExternalName::symbol("caller"), sig.clone());
+    let mut func_ctx = FunctionBuilderContext::new();
+    {
+        let mut bcx: FunctionBuilder = FunctionBuilder::new(&mut ctx.func, &mut func_ctx);
+        let sig_ref = bcx.import_signature(sig);
+        let look_alike_ref = bcx.import_function(ExtFuncData {
+            name: ExternalName::symbol("u0:1"),
+            signature: sig_ref,
+            colocated: true,
+        });
+        let callee_ref = bcx.import_function(ExtFuncData {
+            name: ExternalName::user(0, 1),
+            signature: sig_ref,
+            colocated: true,
+        });
+        let ebb = bcx.create_ebb();
+        bcx.switch_to_block(ebb);
+        let call = bcx.ins().call(look_alike_ref, &[]);
+        let a = bcx.inst_results(call)[0];
+        let call = bcx.ins().call(callee_ref, &[]);
+        let b = bcx.inst_results(call)[0];
+        let a10 = bcx.ins().imul_imm(a, 10);
+        let v = bcx.ins().iadd(a10, b);
+        bcx.ins().return_(&[v]);
+        bcx.seal_all_blocks();
+        bcx.finalize();
+    }
+
+    // The module resolves the user-defined name for display.
+    let text = ctx.func.display_with_resolver(None, &module).to_string();
+    assert!(text.starts_with("function \"caller\"() -> i32 fast {"));
+    assert!(text.contains("fn0 = colocated \"u0:1\" sig0\n"));
+    assert!(text.contains("fn1 = colocated \"callee\" sig0\n"));
+    assert!(ctx.func.to_string().contains("fn1 = colocated u0:1 sig0\n"));
+
+    module.define_function(caller, &mut ctx).unwrap();
+    module.finalize_definitions();
+
+    let code = module.get_finalized_function(caller);
+    let caller_fn: extern "C" fn() -> i32 = unsafe { std::mem::transmute(code) };
+    assert_eq!(caller_fn(), 12);
+}
+
+#[test]
+#[cfg(target_arch = "x86_64")]
+fn local_namespaces() {
+    use cranelift_codegen::isa;
+
+    let isa = isa::lookup(triple!("x86_64"))
+        .unwrap()
+        .finish(Flags::new(builder()));
+    let mut module: Module<SimpleJITBackend> = Module::new(SimpleJITBuilder::with_isa(isa));
+
+    let mut sig = module.make_signature();
+    sig.returns.push(AbiParam::new(types::I32));
+
+    // Two units that both have a local helper function by the same name.
+    let unit_a = module.declare_namespace("unit_a").unwrap();
+    let unit_b = module.declare_namespace("unit_b").unwrap();
+    match module.declare_namespace("unit_a") {
+        Err(ModuleError::DuplicateDefinition(ref name)) if name == "unit_a" => {}
+        _ => panic!("expected a DuplicateDefinition error"),
+    }
+    let helper_a = module
+        .declare_function_in_namespace(unit_a, "helper", Linkage::Local, &sig)
+        .unwrap();
+    let helper_b = module
+        .declare_function_in_namespace(unit_b, "helper", Linkage::Local, &sig)
+        .unwrap();
+    assert_ne!(helper_a, helper_b);
+    assert_eq!(
+        module
+            .declare_function_in_namespace(unit_a, "helper", Linkage::Local, &sig)
+            .unwrap(),
+        helper_a
+    );
+    assert_eq!(
+        module.get_name_in_namespace(unit_b, "helper"),
+        Some(FuncOrDataId::Func(helper_b))
+    );
+    assert_eq!(module.get_name("helper"), None);
+
+    // A local name can't also be declared with another linkage in its namespace.
+    match module.declare_function_in_namespace(unit_a, "helper", Linkage::Export, &sig) {
+        Err(ModuleError::IncompatibleDeclaration(ref name)) if name == "helper" => {}
+        _ => panic!("expected an IncompatibleDeclaration error"),
+    }
+
+    // Exported names are shared by all the namespaces.
+    let caller = module
+        .declare_function_in_namespace(unit_a, "caller", Linkage::Export, &sig)
+        .unwrap();
+    assert_eq!(module.get_name("caller"), Some(FuncOrDataId::Func(caller)));
+    assert_eq!(
+        module.get_name_in_namespace(unit_b, "caller"),
+        Some(FuncOrDataId::Func(caller))
+    );
+
+    let anon = module.declare_anonymous_function(&sig).unwrap();
+    assert_ne!(module.declare_anonymous_function(&sig).unwrap(), anon);
+
+    define_const_function(&mut module, helper_a, 1);
+    define_const_function(&mut module, helper_b, 2);
+    define_const_function(&mut module, anon, 3);
+
+    let mut ctx = module.make_context();
+    ctx.func = Function::with_name_signature(ExternalName::user(0, caller.index() as u32), sig);
+    let mut func_ctx = FunctionBuilderContext::new();
+    {
+        let mut bcx: FunctionBuilder = FunctionBuilder::new(&mut ctx.func, &mut func_ctx);
+        let ebb = bcx.create_ebb();
+        bcx.switch_to_block(ebb);
+        let mut sum = bcx.ins().iconst(types::I32, 0);
+        for &callee in &[helper_a, helper_b, anon] {
+            let callee_ref = module.declare_func_in_func(callee, &mut bcx.func);
+            let call = bcx.ins().call(callee_ref, &[]);
+            let v = bcx.inst_results(call)[0];
+            let sum10 = bcx.ins().imul_imm(sum, 10);
+            sum = bcx.ins().iadd(sum10, v);
+        }
+        bcx.ins().return_(&[sum]);
+        bcx.seal_all_blocks();
+        bcx.finalize();
+    }
+
+    // The local functions are resolved to their symbol names for display.
+    let text = ctx.func.display_with_resolver(None, &module).to_string();
+    assert!(text.contains("fn0 = colocated \"unit_a.helper\" sig0\n"));
+    assert!(text.contains("fn1 = colocated \"unit_b.helper\" sig1\n"));
+
+    module.define_function(caller, &mut ctx).unwrap();
+    module.finalize_definitions();
+
+    let code = module.get_finalized_function(caller);
+    let caller_fn: extern "C" fn() -> i32 = unsafe { std::mem::transmute(code) };
+    assert_eq!(caller_fn(), 123);
+}
+
+#[test]
+fn swap_without_prepare_redefine() {
+    let mut module: Module<SimpleJITBackend> = Module::new(SimpleJITBuilder::new());
+
+    let func_id = define_simple_function(&mut module);
+    module.finalize_definitions();
+    match module.swap_function(func_id) {
+        Err(ModuleError::NotRedefinable(ref name)) if name == "abc" => {}
+        _ => panic!("expected a NotRedefinable error"),
+    }
+}
+
+#[repr(C)]
+struct Point3 {
+    x: i32,
+    y: i32,
+    z: i32,
+}
+
+extern "C" fn weigh_point(p: Point3) -> i32 {
+    p.x + 2 * p.y + 3 * p.z
+}
+
+#[test]
+#[cfg(target_arch = "x86_64")]
+fn struct_argument_round_trip() {
+    use cranelift_codegen::isa;
+
+    // Calls to non-colocated functions may be too far away for a pc-relative call.
+    let mut flag_builder = builder();
+    flag_builder.set("code_model", "large").unwrap();
+    let isa = isa::lookup(triple!("x86_64"))
+        .unwrap()
+        .finish(Flags::new(flag_builder));
+    let mut jit_builder = SimpleJITBuilder::with_isa(isa);
+    jit_builder.symbol("weigh_point", weigh_point as *const u8);
+    let mut module: Module<SimpleJITBackend> = Module::new(jit_builder);
+
+    // Both callees take a 12-byte struct by value.
+    let mut struct_sig = module.make_signature();
+    struct_sig.params.push(AbiParam::special(
+        types::I64,
+        ArgumentPurpose::StructArgument(12),
+    ));
+    struct_sig.returns.push(AbiParam::new(types::I32));
+    let callee = module
+        .declare_function("callee", Linkage::Local, &struct_sig)
+        .unwrap();
+    let weigh = module
+        .declare_function("weigh_point", Linkage::Import, &struct_sig)
+        .unwrap();
+
+    let mut ctx = module.make_context();
+    ctx.func = Function::with_name_signature(
+        ExternalName::user(0, callee.index() as u32),
+        struct_sig.clone(),
+    );
+    let mut func_ctx = FunctionBuilderContext::new();
+    {
+        let mut bcx: FunctionBuilder = FunctionBuilder::new(&mut ctx.func, &mut func_ctx);
+        let ebb = bcx.create_ebb();
+        bcx.switch_to_block(ebb);
+        let p = bcx.append_ebb_param(ebb, types::I64);
+        let x = bcx.ins().load(types::I32, MemFlags::new(), p, 0);
+        let y = bcx.ins().load(types::I32, MemFlags::new(), p, 4);
+        let z = bcx.ins().load(types::I32, MemFlags::new(), p, 8);
+        let y = bcx.ins().imul_imm(y, 10);
+        let z = bcx.ins().imul_imm(z, 100);
+        let v = bcx.ins().iadd(x, y);
+        let v = bcx.ins().iadd(v, z);
+        bcx.ins().return_(&[v]);
+        bcx.seal_all_blocks();
+        bcx.finalize();
+    }
+    module.define_function(callee, &mut ctx).unwrap();
+    module.clear_context(&mut ctx);
+
+    // The caller passes the struct it gets a pointer to on to both callees.
+    let mut caller_sig = module.make_signature();
+    caller_sig.params.push(AbiParam::new(types::I64));
+    caller_sig.returns.push(AbiParam::new(types::I32));
+    let caller = module
+        .declare_function("caller", Linkage::Local, &caller_sig)
+        .unwrap();
+    ctx.func =
+        Function::with_name_signature(ExternalName::user(0, caller.index() as u32), caller_sig);
+    {
+        let mut bcx: FunctionBuilder = FunctionBuilder::new(&mut ctx.func, &mut func_ctx);
+        let callee_ref = module.declare_func_in_func(callee, &mut bcx.func);
+        let weigh_ref = module.declare_func_in_func(weigh, &mut bcx.func);
+        let ebb = bcx.create_ebb();
+        bcx.switch_to_block(ebb);
+        let p = bcx.append_ebb_param(ebb, types::I64);
+        let call = bcx.ins().call(callee_ref, &[p]);
+        let a = bcx.inst_results(call)[0];
+        let call = bcx.ins().call(weigh_ref, &[p]);
+        let b = bcx.inst_results(call)[0];
+        let b = bcx.ins().imul_imm(b, 1000);
+        let v = bcx.ins().iadd(a, b);
+        bcx.ins().return_(&[v]);
+        bcx.seal_all_blocks();
+        bcx.finalize();
+    }
+    module.define_function(caller, &mut ctx).unwrap();
+    module.finalize_definitions();
+
+    let point = Point3 { x: 1, y: 2, z: 3 };
+
+    let code = module.get_finalized_function(callee);
+    let callee_fn: extern "C" fn(Point3) -> i32 = unsafe { std::mem::transmute(code) };
+    assert_eq!(callee_fn(Point3 { x: 1, y: 2, z: 3 }), 321);
+
+    let code = module.get_finalized_function(caller);
+    let caller_fn: extern "C" fn(*const Point3) -> i32 = unsafe { std::mem::transmute(code) };
+    assert_eq!(caller_fn(&point), 14 * 1000 + 321);
+}
+
+#[test]
+#[cfg(target_arch = "x86_64")]
+fn patch_constant() {
+    use cranelift_codegen::isa;
+
+    let isa = isa::lookup(triple!("x86_64"))
+        .unwrap()
+        .finish(Flags::new(builder()));
+    let mut module: Module<SimpleJITBackend> = Module::new(SimpleJITBuilder::with_isa(isa));
+
+    let mut sig = module.make_signature();
+    sig.returns.push(AbiParam::new(types::I64));
+    let func_id = module
+        .declare_function("constant", Linkage::Local, &sig)
+        .unwrap();
+
+    let mut ctx = module.make_context();
+    ctx.func = Function::with_name_signature(ExternalName::user(0, func_id.index() as u32), sig);
+    let mut func_ctx = FunctionBuilderContext::new();
+    {
+        let mut bcx: FunctionBuilder = FunctionBuilder::new(&mut ctx.func, &mut func_ctx);
+        let ebb = bcx.create_ebb();
+        bcx.switch_to_block(ebb);
+        let v = bcx.ins().iconst(types::I64, 1);
+        let inst = bcx.func.dfg.value_def(v).unwrap_inst();
+        bcx.func.patchable.insert(inst);
+        bcx.ins().return_(&[v]);
+        bcx.seal_all_blocks();
+        bcx.finalize();
+    }
+    module.define_function(func_id, &mut ctx).unwrap();
+    assert_eq!(ctx.patch_points.len(), 1);
+    let point = ctx.patch_points[0];
+    assert_eq!(point.size, 8);
+    module.finalize_definitions();
+
+    let code = module.get_finalized_function(func_id);
+    let constant_fn: extern "C" fn() -> i64 = unsafe { std::mem::transmute(code) };
+    assert_eq!(constant_fn(), 1);
+
+    // Overwrite the immediate in place, and observe the new value on the next call.
+    unsafe {
+        let imm = code.offset(point.offset as isize) as *mut i64;
+        region::protect(imm as *const u8, 8, region::Protection::ReadWriteExecute).unwrap();
+        imm.write_unaligned(0x1234_5678_9abc_def0);
+    }
+    assert_eq!(constant_fn(), 0x1234_5678_9abc_def0);
+}
+
+#[test]
+#[cfg(target_arch = "x86_64")]
+fn finalized_info() {
+    use cranelift_codegen::binemit::Reloc;
+    use cranelift_codegen::isa;
+
+    let make_isa = || {
+        isa::lookup(triple!("x86_64"))
+            .unwrap()
+            .finish(Flags::new(builder()))
+    };
+    let mut module: Module<SimpleJITBackend> = Module::new(SimpleJITBuilder::with_isa(make_isa()));
+
+    let mut sig = module.make_signature();
+    sig.params.push(AbiParam::new(types::I32));
+    let func_id = module
+        .declare_function("check", Linkage::Local, &sig)
+        .unwrap();
+
+    let mut ctx = module.make_context();
+    ctx.func = Function::with_name_signature(ExternalName::user(0, func_id.index() as u32), sig);
+    let mut func_ctx = FunctionBuilderContext::new();
+    {
+        let mut bcx: FunctionBuilder = FunctionBuilder::new(&mut ctx.func, &mut func_ctx);
+        let ebb = bcx.create_ebb();
+        bcx.switch_to_block(ebb);
+        bcx.append_ebb_params_for_function_params(ebb);
+        let x = bcx.ebb_params(ebb)[0];
+        bcx.ins().trapnz(x, TrapCode::User(7), 42);
+        bcx.ins().return_(&[]);
+        bcx.seal_all_blocks();
+        bcx.finalize();
+    }
+    let code_info = Context::for_function(ctx.func.clone())
+        .compile(&*make_isa())
+        .unwrap();
+    module.define_function(func_id, &mut ctx).unwrap();
+
+    let data_id = module
+        .declare_data("check_ptr", Linkage::Local, false)
+        .unwrap();
+    let mut data_ctx = DataContext::new();
+    data_ctx.define(vec![0; 8].into_boxed_slice());
+    let func_ref = module.declare_func_in_data(func_id, &mut data_ctx);
+    data_ctx.write_function_addr(0, func_ref);
+    module.define_data(data_id, &data_ctx).unwrap();
+    module.finalize_definitions();
+
+    let code = module.get_finalized_function(func_id);
+    let func_info = module.get_finalized_function_info(func_id);
+    assert_eq!(func_info.location, FinalizedLocation::Address(code));
+    assert_eq!(func_info.size, code_info.code_size as usize);
+    assert_eq!(func_info.rodata, None);
+    assert!(func_info.relocs.is_empty());
+    let trap = func_info
+        .traps
+        .iter()
+        .find(|trap| trap.code == TrapCode::User(7))
+        .expect("trap site is missing");
+    assert!((trap.offset as usize) < func_info.size);
+    assert_eq!(trap.payload, 42);
+
+    let (storage, size) = module.get_finalized_data(data_id);
+    let data_info = module.get_finalized_data_info(data_id);
+    assert_eq!(data_info.location, FinalizedLocation::Address(storage));
+    assert_eq!(data_info.size, size);
+    assert_eq!(data_info.relocs.len(), 1);
+    assert_eq!(data_info.relocs[0].offset, 0);
+    assert_eq!(data_info.relocs[0].reloc, Reloc::Abs8);
+    assert_eq!(data_info.relocs[0].target, Some(code as u64));
+}
+
+#[test]
+#[cfg(target_arch = "x86_64")]
+fn rodata_in_readonly_memory() {
+    use cranelift_codegen::ir::immediates::Ieee64;
+    use cranelift_codegen::isa;
+
+    let isa = isa::lookup(triple!("x86_64"))
+        .unwrap()
+        .finish(Flags::new(builder()));
+    let mut module: Module<SimpleJITBackend> = Module::new(SimpleJITBuilder::with_isa(isa));
+
+    let mut sig = module.make_signature();
+    sig.params.push(AbiParam::new(types::I32));
+    sig.returns.push(AbiParam::new(types::F64));
+    let func_id = module
+        .declare_function("select", Linkage::Local, &sig)
+        .unwrap();
+
+    let mut ctx = module.make_context();
+    ctx.func = Function::with_name_signature(ExternalName::user(0, func_id.index() as u32), sig);
+    let mut func_ctx = FunctionBuilderContext::new();
+    {
+        let mut bcx: FunctionBuilder = FunctionBuilder::new(&mut ctx.func, &mut func_ctx);
+        let ebbs: Vec<Ebb> = (0..4).map(|_| bcx.create_ebb()).collect();
+        let mut jt_data = JumpTableData::new();
+        jt_data.push_entry(ebbs[1]);
+        jt_data.push_entry(ebbs[2]);
+        let jt = bcx.create_jump_table(jt_data);
+
+        bcx.switch_to_block(ebbs[0]);
+        bcx.append_ebb_params_for_function_params(ebbs[0]);
+        let x = bcx.ebb_params(ebbs[0])[0];
+        bcx.ins().br_table(x, ebbs[3], jt);
+        for (&ebb, &imm) in ebbs[1..].iter().zip(&[1.5, 2.5, 0.5]) {
+            bcx.switch_to_block(ebb);
+            let v = bcx.ins().f64const(Ieee64::with_float(imm));
+            bcx.ins().return_(&[v]);
+        }
+        bcx.seal_all_blocks();
+        bcx.finalize();
+    }
+    module.define_function(func_id, &mut ctx).unwrap();
+    module.finalize_definitions();
+
+    let code = module.get_finalized_function(func_id);
+    let select: extern "C" fn(i32) -> f64 = unsafe { std::mem::transmute(code) };
+    assert_eq!(select(0), 1.5);
+    assert_eq!(select(1), 2.5);
+    assert_eq!(select(2), 0.5);
+
+    // The jump table and the constants are in separate read-only memory.
+    let func_info = module.get_finalized_function_info(func_id);
+    let rodata = match func_info.rodata {
+        Some(FinalizedLocation::Address(rodata)) => rodata,
+        ref location => panic!("unexpected rodata location {:?}", location),
+    };
+    assert!(func_info.rodata_size >= 2 * 4 + 3 * 8);
+    let code_end = code as usize + func_info.size;
+    assert!(
+        rodata as usize >= code_end || (rodata as usize) + func_info.rodata_size <= code as usize
+    );
+    let page = region::query(rodata).unwrap();
+    assert_eq!(page.protection, region::Protection::Read);
+    let page = region::query(code).unwrap();
+    assert_eq!(page.protection, region::Protection::ReadExecute);
+}
+
+#[test]
+#[cfg(target_arch = "x86_64")]
+fn cycle_counter() {
+    use cranelift_codegen::isa;
+
+    let mut flag_builder = builder();
+    flag_builder.enable("enable_cycle_counter").unwrap();
+    let isa = isa::lookup(triple!("x86_64"))
+        .unwrap()
+        .finish(Flags::new(flag_builder));
+    let mut module: Module<SimpleJITBackend> = Module::new(SimpleJITBuilder::with_isa(isa));
+
+    // Read the counter twice and return whether the second read isn't smaller.
+    let mut sig = module.make_signature();
+    sig.returns.push(AbiParam::new(types::I32));
+    let func_id = module
+        .declare_function("cycles", Linkage::Local, &sig)
+        .unwrap();
+
+    let mut ctx = module.make_context();
+    ctx.func = Function::with_name_signature(ExternalName::user(0, func_id.index() as u32), sig);
+    let mut func_ctx = FunctionBuilderContext::new();
+    {
+        let mut bcx: FunctionBuilder = FunctionBuilder::new(&mut ctx.func, &mut func_ctx);
+        let ebb = bcx.create_ebb();
+        bcx.switch_to_block(ebb);
+        let first = bcx.ins().read_cycle_counter();
+        let second = bcx.ins().read_cycle_counter();
+        let ordered = bcx
+            .ins()
+            .icmp(condcodes::IntCC::UnsignedGreaterThanOrEqual, second, first);
+        let v = bcx.ins().bint(types::I32, ordered);
+        bcx.ins().return_(&[v]);
+        bcx.seal_all_blocks();
+        bcx.finalize();
+    }
+    module.define_function(func_id, &mut ctx).unwrap();
+    module.finalize_definitions();
+
+    let code = module.get_finalized_function(func_id);
+    let cycles: extern "C" fn() -> i32 = unsafe { std::mem::transmute(code) };
+    assert_eq!(cycles(), 1);
+}
+
+#[test]
+#[cfg(target_arch = "x86_64")]
+fn colocated_data_round_trip() {
+    use cranelift_codegen::binemit::Reloc;
+    use cranelift_codegen::isa;
+
+    let isa = isa::lookup(triple!("x86_64"))
+        .unwrap()
+        .finish(Flags::new(builder()));
+    let mut module: Module<SimpleJITBackend> = Module::new(SimpleJITBuilder::with_isa(isa));
+
+    let data_id = module
+        .declare_data("counters", Linkage::Local, true)
+        .unwrap();
+    let mut data_ctx = DataContext::new();
+    data_ctx.define(vec![0, 0, 0, 0, 42, 0, 0, 0].into_boxed_slice());
+    module.define_data(data_id, &data_ctx).unwrap();
+
+    // Load the second counter, store it plus one in the first one, and return it.
+    let mut sig = module.make_signature();
+    sig.returns.push(AbiParam::new(types::I32));
+    let func_id = module
+        .declare_function("bump", Linkage::Local, &sig)
+        .unwrap();
+
+    let mut ctx = module.make_context();
+    ctx.func = Function::with_name_signature(ExternalName::user(0, func_id.index() as u32), sig);
+    let gv = module.declare_data_in_func(data_id, &mut ctx.func);
+    let mut func_ctx = FunctionBuilderContext::new();
+    {
+        let mut bcx: FunctionBuilder = FunctionBuilder::new(&mut ctx.func, &mut func_ctx);
+        let ebb = bcx.create_ebb();
+        bcx.switch_to_block(ebb);
+        let addr = bcx.ins().global_value(types::I64, gv);
+        let v = bcx.ins().load(types::I32, MemFlags::new(), addr, 4);
+        let next = bcx.ins().iadd_imm(v, 1);
+        let addr = bcx.ins().global_value(types::I64, gv);
+        bcx.ins().store(MemFlags::new(), next, addr, 0);
+        bcx.ins().return_(&[v]);
+        bcx.seal_all_blocks();
+        bcx.finalize();
+    }
+    module.define_function(func_id, &mut ctx).unwrap();
+    module.finalize_definitions();
+
+    // The data is accessed relative to the instruction pointer, so each access has a
+    // PC-relative relocation of its 4-byte displacement.
+    let func_info = module.get_finalized_function_info(func_id);
+    let (storage, _) = module.get_finalized_data(data_id);
+    assert_eq!(func_info.relocs.len(), 2);
+    for (reloc, &offset) in func_info.relocs.iter().zip(&[4, 0]) {
+        assert_eq!(reloc.reloc, Reloc::X86PCRel4);
+        assert_eq!(reloc.addend, offset - 4);
+        assert_eq!(reloc.target, Some((storage as i64 + offset - 4) as u64));
+        assert!(reloc.offset as usize + 4 <= func_info.size);
+    }
+
+    let code = module.get_finalized_function(func_id);
+    let bump: extern "C" fn() -> i32 = unsafe { std::mem::transmute(code) };
+    assert_eq!(bump(), 42);
+    let counters = unsafe { std::slice::from_raw_parts(storage, 8) };
+    assert_eq!(counters, &[43, 0, 0, 0, 42, 0, 0, 0]);
+}
+
+#[test]
+fn patched_symbol_value() {
+    use cranelift_codegen::binemit::Reloc;
+    use cranelift_codegen::isa;
+
+    let isa = isa::lookup(triple!("x86_64"))
+        .unwrap()
+        .finish(Flags::new(builder()));
+    let mut module: Module<SimpleJITBackend> = Module::new(SimpleJITBuilder::with_isa(isa));
+
+    let data_id = module.declare_data("table", Linkage::Local, true).unwrap();
+    let mut data_ctx = DataContext::new();
+    data_ctx.define(vec![0; 16].into_boxed_slice());
+    module.define_data(data_id, &data_ctx).unwrap();
+
+    // Return the second word of the table, loaded through a load global value of the symbol.
+    let mut sig = module.make_signature();
+    sig.returns.push(AbiParam::new(types::I64));
+    let func_id = module
+        .declare_function("second", Linkage::Local, &sig)
+        .unwrap();
+
+    let mut ctx = module.make_context();
+    ctx.func = Function::with_name_signature(ExternalName::user(0, func_id.index() as u32), sig);
+    let symbol = module.declare_data_in_func(data_id, &mut ctx.func);
+    let gv = ctx.func.create_global_value(GlobalValueData::Load {
+        base: symbol,
+        offset: immediates::Offset32::new(8),
+        global_type: types::I64,
+        readonly: false,
+    });
+    let mut func_ctx = FunctionBuilderContext::new();
+    {
+        let mut bcx: FunctionBuilder = FunctionBuilder::new(&mut ctx.func, &mut func_ctx);
+        let ebb = bcx.create_ebb();
+        bcx.switch_to_block(ebb);
+        let v = bcx.ins().global_value(types::I64, gv);
+        bcx.ins().return_(&[v]);
+        bcx.seal_all_blocks();
+        bcx.finalize();
+    }
+    module.define_function(func_id, &mut ctx).unwrap();
+    module.finalize_definitions();
+
+    // The value is loaded by a single instruction relative to the instruction pointer.
+    let func_info = module.get_finalized_function_info(func_id);
+    assert_eq!(func_info.relocs.len(), 1);
+    assert_eq!(func_info.relocs[0].reloc, Reloc::X86PCRel4);
+
+    let code = module.get_finalized_function(func_id);
+    let second: extern "C" fn() -> i64 = unsafe { std::mem::transmute(code) };
+    assert_eq!(second(), 0);
+
+    // The value is loaded when the function runs, so patching the data changes the result.
+    let (storage, _) = module.get_finalized_data(data_id);
+    let table = unsafe { std::slice::from_raw_parts_mut(storage as *mut i64, 2) };
+    table[1] = 0x1234_5678_9abc;
+    assert_eq!(second(), 0x1234_5678_9abc);
+    table[1] = -1;
+    assert_eq!(second(), -1);
+}
@@ -0,0 +1,226 @@
+//! Resolve the program counter of a trap in a JIT-compiled function with the compiled metadata of
+//! its module.
+//!
+//! The function traps in a forked child process. Forking is only safe in a process that has a
+//! single thread, so this test doesn't use the libtest harness, which runs tests on worker threads.
+
+#![cfg_attr(
+    not(all(target_arch = "x86_64", target_os = "linux")),
+    allow(unused_imports)
+)]
+
+extern crate cranelift_codegen;
+extern crate cranelift_entity;
+extern crate cranelift_frontend;
+extern crate cranelift_module;
+extern crate cranelift_simplejit;
+extern crate libc;
+#[macro_use]
+extern crate target_lexicon;
+
+use cranelift_codegen::ir::*;
+use cranelift_codegen::isa;
+use cranelift_codegen::settings::*;
+use cranelift_entity::EntityRef;
+use cranelift_frontend::*;
+use cranelift_module::*;
+use cranelift_simplejit::*;
+use std::str::FromStr;
+
+#[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+fn main() {
+    resolve_trap_from_metadata();
+}
+
+#[cfg(not(all(target_arch = "x86_64", target_os = "linux")))]
+fn main() {}
+
+#[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+fn define_const_function(module: &mut Module<SimpleJITBackend>, func_id: FuncId, value: i64) {
+    let mut sig = module.make_signature();
+    sig.returns.push(AbiParam::new(types::I32));
+
+    let mut ctx = module.make_context();
+    ctx.func = Function::with_name_signature(ExternalName::user(0, func_id.index() as u32), sig);
+    let mut func_ctx = FunctionBuilderContext::new();
+    {
+        let mut bcx: FunctionBuilder = FunctionBuilder::new(&mut ctx.func, &mut func_ctx);
+        let ebb = bcx.create_ebb();
+        bcx.switch_to_block(ebb);
+        let v = bcx.ins().iconst(types::I32, value);
+        bcx.ins().return_(&[v]);
+        bcx.seal_all_blocks();
+        bcx.finalize();
+    }
+    module.define_function(func_id, &mut ctx).unwrap();
+}
+
+/// Run `f` in a child process, and return the address of the instruction that raised `SIGILL`
+/// in it.
+#[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+fn faulting_pc<F: FnOnce()>(f: F) -> usize {
+    use std::mem;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static WRITE_FD: AtomicUsize = AtomicUsize::new(0);
+
+    extern "C" fn on_sigill(_: libc::c_int, _: *mut libc::siginfo_t, ctx: *mut libc::c_void) {
+        unsafe {
+            let ctx = &*(ctx as *const libc::ucontext_t);
+            let pc = ctx.uc_mcontext.gregs[libc::REG_RIP as usize] as usize;
+            libc::write(
+                WRITE_FD.load(Ordering::SeqCst) as libc::c_int,
+                &pc as *const usize as *const libc::c_void,
+                mem::size_of::<usize>(),
+            );
+            libc::_exit(0);
+        }
+    }
+
+    unsafe {
+        let mut fds = [0; 2];
+        assert_eq!(libc::pipe(fds.as_mut_ptr()), 0);
+        let pid = libc::fork();
+        assert!(pid >= 0);
+        if pid == 0 {
+            WRITE_FD.store(fds[1] as usize, Ordering::SeqCst);
+            let mut action: libc::sigaction = mem::zeroed();
+            action.sa_sigaction = on_sigill as *const () as libc::sighandler_t;
+            action.sa_flags = libc::SA_SIGINFO;
+            libc::sigaction(libc::SIGILL, &action, std::ptr::null_mut());
+            f();
+            // Nothing trapped, so nothing is written.
+            libc::_exit(1);
+        }
+        libc::close(fds[1]);
+        let mut pc = 0usize;
+        let read = libc::read(
+            fds[0],
+            &mut pc as *mut usize as *mut libc::c_void,
+            mem::size_of::<usize>(),
+        );
+        libc::close(fds[0]);
+        let mut status = 0;
+        libc::waitpid(pid, &mut status, 0);
+        assert_eq!(
+            read as usize,
+            mem::size_of::<usize>(),
+            "no instruction trapped"
+        );
+        pc
+    }
+}
+
+#[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+fn resolve_trap_from_metadata() {
+    /// The VM context of `load`, which describes its heap.
+    #[repr(C)]
+    struct VMContext {
+        heap_base: *const i32,
+        heap_bound: u32,
+    }
+
+    let isa = isa::lookup(triple!("x86_64"))
+        .unwrap()
+        .finish(Flags::new(builder()));
+    let mut module: Module<SimpleJITBackend> = Module::new(SimpleJITBuilder::with_isa(isa));
+    module.enable_compiled_metadata();
+
+    let mut sig = module.make_signature();
+    sig.returns.push(AbiParam::new(types::I32));
+    let const_id = module
+        .declare_function("constant", Linkage::Local, &sig)
+        .unwrap();
+    define_const_function(&mut module, const_id, 42);
+
+    // `load` reads an `i32` at a byte offset in a dynamic heap, and traps if it is out of bounds.
+    let mut sig = module.make_signature();
+    sig.params.push(AbiParam::new(types::I32));
+    sig.params
+        .push(AbiParam::special(types::I64, ArgumentPurpose::VMContext));
+    sig.returns.push(AbiParam::new(types::I32));
+    let load_id = module
+        .declare_function("load", Linkage::Local, &sig)
+        .unwrap();
+
+    let mut ctx = module.make_context();
+    ctx.func = Function::with_name_signature(ExternalName::user(0, load_id.index() as u32), sig);
+    let mut func_ctx = FunctionBuilderContext::new();
+    {
+        let mut bcx: FunctionBuilder = FunctionBuilder::new(&mut ctx.func, &mut func_ctx);
+        let vmctx = bcx.create_global_value(GlobalValueData::VMContext);
+        let base = bcx.create_global_value(GlobalValueData::Load {
+            base: vmctx,
+            offset: immediates::Offset32::new(0),
+            global_type: types::I64,
+            readonly: true,
+        });
+        let bound_gv = bcx.create_global_value(GlobalValueData::Load {
+            base: vmctx,
+            offset: immediates::Offset32::new(8),
+            global_type: types::I32,
+            readonly: true,
+        });
+        let heap = bcx.create_heap(HeapData {
+            base,
+            min_size: 0.into(),
+            guard_size: 0.into(),
+            style: HeapStyle::Dynamic { bound_gv },
+            index_type: types::I32,
+            check_alignment: false,
+        });
+        let ebb = bcx.create_ebb();
+        bcx.switch_to_block(ebb);
+        bcx.append_ebb_params_for_function_params(ebb);
+        let index = bcx.ebb_params(ebb)[0];
+        bcx.set_srcloc(SourceLoc::new(10));
+        let addr = bcx.ins().heap_addr(types::I64, heap, index, 4);
+        bcx.set_srcloc(SourceLoc::new(11));
+        let v = bcx.ins().load(types::I32, MemFlags::new(), addr, 0);
+        bcx.ins().return_(&[v]);
+        bcx.seal_all_blocks();
+        bcx.finalize();
+    }
+    module.define_function(load_id, &mut ctx).unwrap();
+    module.finalize_definitions();
+
+    let metadata = module.take_compiled_metadata();
+    assert_eq!(metadata.functions.len(), 2);
+    assert!(module.take_compiled_metadata().functions.is_empty());
+    let constant = module.get_finalized_function(const_id);
+    let load = module.get_finalized_function(load_id);
+    for func in &metadata.functions {
+        let code = if func.func == const_id {
+            constant
+        } else {
+            load
+        };
+        assert_eq!(func.location, FinalizedLocation::Address(code));
+        assert_eq!(metadata.lookup_function(code as usize), Some((func, 0)));
+        let last = func.size - 1;
+        assert_eq!(
+            metadata.lookup_function(code as usize + last),
+            Some((func, last as u32))
+        );
+    }
+
+    let heap = [1, 2, 3, 4];
+    let vmctx = VMContext {
+        heap_base: heap.as_ptr(),
+        heap_bound: 16,
+    };
+    let load_fn: extern "C" fn(i32, *const VMContext) -> i32 = unsafe { std::mem::transmute(load) };
+    assert_eq!(load_fn(12, &vmctx), 4);
+
+    // Only the collected metadata is needed to resolve the trap to its function and source.
+    let pc = faulting_pc(|| {
+        load_fn(16, &vmctx);
+    });
+    let (func, site) = metadata.lookup_trap(pc).expect("trap site is missing");
+    assert_eq!(func.func, load_id);
+    assert_eq!(func.name, "load");
+    assert_eq!(site.code, TrapCode::HeapOutOfBounds);
+    assert_eq!(site.srcloc, SourceLoc::new(10));
+    let (_, offset) = metadata.lookup_function(pc).unwrap();
+    assert_eq!(func.srcloc_at(offset), Some(SourceLoc::new(10)));
+}
@@ -22,9 +22,11 @@
 //!
 //! That is why `translate_function_body` takes an object having the `WasmRuntime` trait as
 //! argument.
+use cranelift_codegen::cursor::Cursor;
 use cranelift_codegen::ir::condcodes::{FloatCC, IntCC};
 use cranelift_codegen::ir::types::*;
 use cranelift_codegen::ir::{self, InstBuilder, JumpTableData, MemFlags};
+use cranelift_codegen::isa::InstSupport;
 use cranelift_codegen::packed_option::ReservedValue;
 use cranelift_entity::EntityRef;
 use cranelift_frontend::{FunctionBuilder, Variable};
@@ -112,9 +114,10 @@ pub fn translate_operator<FE: FuncEnvironment + ?Sized>(
             // We do nothing
         }
         Operator::Unreachable => {
+            hint_branches_to_trap(builder, state);
             // We use `trap user0` to indicate a user-generated trap.
             // We could make the trap code configurable if need be.
-            builder.ins().trap(ir::TrapCode::User(0));
+            builder.ins().trap(ir::TrapCode::User(0), 0);
             state.reachable = false;
         }
         /***************************** Control flow blocks **********************************
@@ -546,7 +549,12 @@ pub fn translate_operator<FE: FuncEnvironment + ?Sized>(
         }
         Operator::I32Popcnt | Operator::I64Popcnt => {
             let arg = state.pop1();
-            state.push1(builder.ins().popcnt(arg));
+            let ty = builder.func.dfg.value_type(arg);
+            if unsupported(environ, ir::Opcode::Popcnt, ty) {
+                state.push1(translate_popcnt_bits(arg, builder));
+            } else {
+                state.push1(builder.ins().popcnt(arg));
+            }
         }
         Operator::I64ExtendSI32 => {
             let val = state.pop1();
@@ -630,18 +638,22 @@ pub fn translate_operator<FE: FuncEnvironment + ?Sized>(
         }
         Operator::I64TruncSSatF64 | Operator::I64TruncSSatF32 => {
             let val = state.pop1();
+            check_sat_conversion(environ, ir::Opcode::FcvtToSintSat, I64)?;
             state.push1(builder.ins().fcvt_to_sint_sat(I64, val));
         }
         Operator::I32TruncSSatF64 | Operator::I32TruncSSatF32 => {
             let val = state.pop1();
+            check_sat_conversion(environ, ir::Opcode::FcvtToSintSat, I32)?;
             state.push1(builder.ins().fcvt_to_sint_sat(I32, val));
         }
         Operator::I64TruncUSatF64 | Operator::I64TruncUSatF32 => {
             let val = state.pop1();
+            check_sat_conversion(environ, ir::Opcode::FcvtToUintSat, I64)?;
             state.push1(builder.ins().fcvt_to_uint_sat(I64, val));
         }
         Operator::I32TruncUSatF64 | Operator::I32TruncUSatF32 => {
             let val = state.pop1();
+            check_sat_conversion(environ, ir::Opcode::FcvtToUintSat, I32)?;
             state.push1(builder.ins().fcvt_to_uint_sat(I32, val));
         }
         Operator::F32ReinterpretI32 => {
@@ -981,11 +993,12 @@ fn translate_unreachable_operator(
     }
 }
 
-/// Get the address+offset to use for a heap access.
+/// Get the address+offset to use for a heap access of `access_size` bytes.
 fn get_heap_addr(
     heap: ir::Heap,
     addr32: ir::Value,
     offset: u32,
+    access_size: u32,
     addr_ty: Type,
     builder: &mut FunctionBuilder,
 ) -> (ir::Value, i32) {
@@ -1001,10 +1014,32 @@ fn get_heap_addr(
     // For accesses on the outer skirts of the guard pages, we expect that we get a trap
     // even if the access goes beyond the guard pages. This is because the first byte pointed to is
     // inside the guard pages.
-    let check_size = min(
-        i64::from(u32::MAX),
-        1 + (i64::from(offset) / guard_size) * guard_size,
-    ) as u32;
+    let checked_offset = (i64::from(offset) / guard_size) * guard_size;
+    let mut check_size = 1 + checked_offset;
+
+    // The `heap_addr` of a heap that checks alignment traps unless the index is a multiple of the
+    // largest power of two dividing the access size. When the offset is aligned, check the whole
+    // access instead of its first byte so the alignment is the access size. Otherwise, the
+    // effective address is checked here.
+    if builder.func.heaps[heap].check_alignment && access_size > 1 {
+        let align_mask = access_size - 1;
+        let aligned_size = checked_offset + i64::from(access_size);
+        if offset & align_mask == 0
+            && checked_offset % (2 * i64::from(access_size)) == 0
+            && aligned_size <= i64::from(u32::MAX)
+        {
+            check_size = aligned_size;
+        } else {
+            let addr = builder
+                .ins()
+                .iadd_imm(addr32, i64::from(offset & align_mask));
+            let misaligned = builder.ins().band_imm(addr, i64::from(align_mask));
+            builder
+                .ins()
+                .trapnz(misaligned, ir::TrapCode::HeapMisaligned, 0);
+        }
+    }
+    let check_size = min(i64::from(u32::MAX), check_size) as u32;
     let base = builder.ins().heap_addr(addr_ty, heap, addr32, check_size);
 
     // Native load/store instructions take a signed `Offset32` immediate, so adjust the base
@@ -1018,6 +1053,70 @@ fn get_heap_addr(
     }
 }
 
+/// Is an instruction with `opcode` and controlling type `ty` known to be unsupported by the
+/// target ISA?
+fn unsupported<FE: FuncEnvironment + ?Sized>(environ: &FE, opcode: ir::Opcode, ty: Type) -> bool {
+    environ.target_isa().map_or(false, |isa| {
+        isa.supports(opcode, ty) == InstSupport::Unsupported
+    })
+}
+
+/// Check that the target ISA can compile a saturating float conversion with `opcode` to `ty`.
+///
+/// The conversions have no simple expansion in terms of other instructions, so they are rejected
+/// up front instead of failing later in code generation.
+fn check_sat_conversion<FE: FuncEnvironment + ?Sized>(
+    environ: &FE,
+    opcode: ir::Opcode,
+    ty: Type,
+) -> WasmResult<()> {
+    if unsupported(environ, opcode, ty) {
+        return Err(WasmError::Unsupported(
+            "saturating float-to-int conversions on this target",
+        ));
+    }
+    Ok(())
+}
+
+/// Count the set bits of `arg` with shifts, masks, and additions, for targets that can't
+/// compile `popcnt`.
+fn translate_popcnt_bits(arg: ir::Value, builder: &mut FunctionBuilder) -> ir::Value {
+    let bits = builder.func.dfg.value_type(arg).bits();
+    // The masks repeat every byte, so truncating them to the width of `arg` keeps the pattern.
+    let mask = |pattern: u64| (pattern >> (64 - bits)) as i64;
+
+    // Count the bits of each pair, then each nibble, then each byte.
+    let pairs = builder.ins().ushr_imm(arg, 1);
+    let pairs = builder.ins().band_imm(pairs, mask(0x5555_5555_5555_5555));
+    let x = builder.ins().isub(arg, pairs);
+    let low = builder.ins().band_imm(x, mask(0x3333_3333_3333_3333));
+    let high = builder.ins().ushr_imm(x, 2);
+    let high = builder.ins().band_imm(high, mask(0x3333_3333_3333_3333));
+    let x = builder.ins().iadd(low, high);
+    let high = builder.ins().ushr_imm(x, 4);
+    let x = builder.ins().iadd(x, high);
+    let mut x = builder.ins().band_imm(x, mask(0x0f0f_0f0f_0f0f_0f0f));
+
+    // Sum the byte counts into the low byte. The total fits in 7 bits.
+    let mut shift = 8;
+    while shift < bits {
+        let high = builder.ins().ushr_imm(x, i64::from(shift));
+        x = builder.ins().iadd(x, high);
+        shift *= 2;
+    }
+    builder.ins().band_imm(x, 0x7f)
+}
+
+/// Get the number of bytes accessed by a load or store with `opcode` of a value of type `ty`.
+fn access_size(opcode: ir::Opcode, ty: Type) -> u32 {
+    match opcode {
+        ir::Opcode::Uload8 | ir::Opcode::Sload8 | ir::Opcode::Istore8 => 1,
+        ir::Opcode::Uload16 | ir::Opcode::Sload16 | ir::Opcode::Istore16 => 2,
+        ir::Opcode::Uload32 | ir::Opcode::Sload32 | ir::Opcode::Istore32 => 4,
+        _ => ty.bytes(),
+    }
+}
+
 /// Translate a load instruction.
 fn translate_load<FE: FuncEnvironment + ?Sized>(
     offset: u32,
@@ -1030,7 +1129,14 @@ fn translate_load<FE: FuncEnvironment + ?Sized>(
     let addr32 = state.pop1();
     // We don't yet support multiple linear memories.
     let heap = state.get_heap(builder.func, 0, environ);
-    let (base, offset) = get_heap_addr(heap, addr32, offset, environ.pointer_type(), builder);
+    let (base, offset) = get_heap_addr(
+        heap,
+        addr32,
+        offset,
+        access_size(opcode, result_ty),
+        environ.pointer_type(),
+        builder,
+    );
     // Note that we don't set `is_aligned` here, even if the load instruction's
     // alignment immediate says it's aligned, because WebAssembly's immediate
     // field is just a hint, while Cranelift's aligned flag needs a guarantee.
@@ -1054,7 +1160,14 @@ fn translate_store<FE: FuncEnvironment + ?Sized>(
 
     // We don't yet support multiple linear memories.
     let heap = state.get_heap(builder.func, 0, environ);
-    let (base, offset) = get_heap_addr(heap, addr32, offset, environ.pointer_type(), builder);
+    let (base, offset) = get_heap_addr(
+        heap,
+        addr32,
+        offset,
+        access_size(opcode, val_ty),
+        environ.pointer_type(),
+        builder,
+    );
     // See the comments in `translate_load` about the flags.
     let flags = MemFlags::new();
     builder
@@ -1074,6 +1187,28 @@ fn translate_fcmp(cc: FloatCC, builder: &mut FunctionBuilder, state: &mut Transl
     state.push1(builder.ins().bint(I32, val));
 }
 
+/// Hint that the conditional branches leading to an `unreachable` trap at the current position
+/// rarely go that way, so the trapping code is kept out of the way.
+fn hint_branches_to_trap(builder: &mut FunctionBuilder, state: &TranslationState) {
+    let pristine = builder.is_pristine();
+    let ebb = builder.cursor().current_ebb().unwrap();
+    if pristine {
+        // This is an `else` clause that traps right away. The `if` branches here when its
+        // condition is false.
+        if let Some(&ControlStackFrame::If { branch_inst, .. }) = state.control_stack.last() {
+            if builder.func.dfg[branch_inst].branch_destination() == Some(ebb) {
+                builder.set_branch_hint(branch_inst, ir::BranchHint::Unlikely);
+            }
+        }
+    } else if let Some(inst) = builder.func.layout.last_inst(ebb) {
+        // The trap directly follows a conditional branch, which jumps over it when taken.
+        let opcode = builder.func.dfg[inst].opcode();
+        if opcode.is_branch() && !opcode.is_terminator() {
+            builder.set_branch_hint(inst, ir::BranchHint::Likely);
+        }
+    }
+}
+
 fn translate_br_if(
     relative_depth: u32,
     builder: &mut FunctionBuilder,
@@ -14,7 +14,7 @@ use std::vec::Vec;
 use target_lexicon::Triple;
 use translation_utils::{
     DefinedFuncIndex, FuncIndex, Global, GlobalIndex, Memory, MemoryIndex, SignatureIndex, Table,
-    TableIndex,
+    TableIndex, WASM_PAGE_SIZE,
 };
 
 /// Compute a `ir::ExternalName` for a given wasm function index.
@@ -192,24 +192,52 @@ impl<'dummy_environment> FuncEnvironment for DummyFuncEnvironment<'dummy_environ
         }
     }
 
-    fn make_heap(&mut self, func: &mut ir::Function, _index: MemoryIndex) -> ir::Heap {
-        // Create a static heap whose base address is stored at `vmctx+0`.
-        let addr = func.create_global_value(ir::GlobalValueData::VMContext);
-        let gv = func.create_global_value(ir::GlobalValueData::Load {
-            base: addr,
-            offset: Offset32::new(0),
+    fn heap_data(&mut self, func: &mut ir::Function, index: MemoryIndex) -> ir::HeapData {
+        // The base address of each memory is stored at `vmctx+16*index`, followed by its current
+        // size in bytes.
+        let memory = self.mod_info.memories[index].entity;
+        let offset = (index.index() * 16) as i32;
+        let vmctx = func.create_global_value(ir::GlobalValueData::VMContext);
+        let min_size = (memory.pages_count as i64 * i64::from(WASM_PAGE_SIZE)).into();
+        let base = func.create_global_value(ir::GlobalValueData::Load {
+            base: vmctx,
+            offset: Offset32::new(offset),
             global_type: self.pointer_type(),
+            readonly: true,
         });
 
-        func.create_heap(ir::HeapData {
-            base: gv,
-            min_size: 0.into(),
-            guard_size: 0x8000_0000.into(),
-            style: ir::HeapStyle::Static {
-                bound: 0x1_0000_0000.into(),
+        match memory.maximum {
+            // A memory without a maximum size reserves the whole 32-bit index space and a guard
+            // region, so it never moves and its accesses need no bounds checks.
+            None => ir::HeapData {
+                base,
+                min_size,
+                guard_size: 0x8000_0000.into(),
+                style: ir::HeapStyle::Static {
+                    bound: 0x1_0000_0000.into(),
+                },
+                index_type: I32,
+                check_alignment: false,
             },
-            index_type: I32,
-        })
+            // A memory with a maximum size only reserves that size, so its accesses are checked
+            // against its current size, which changes when it grows.
+            Some(_) => {
+                let bound_gv = func.create_global_value(ir::GlobalValueData::Load {
+                    base: vmctx,
+                    offset: Offset32::new(offset + 8),
+                    global_type: I32,
+                    readonly: false,
+                });
+                ir::HeapData {
+                    base,
+                    min_size,
+                    guard_size: 0x1000.into(),
+                    style: ir::HeapStyle::Dynamic { bound_gv },
+                    index_type: I32,
+                    check_alignment: false,
+                }
+            }
+        }
     }
 
     fn make_table(&mut self, func: &mut ir::Function, _index: TableIndex) -> ir::Table {
@@ -219,11 +247,13 @@ impl<'dummy_environment> FuncEnvironment for DummyFuncEnvironment<'dummy_environ
             base: vmctx,
             offset: Offset32::new(0),
             global_type: self.pointer_type(),
+            readonly: false,
         });
         let bound_gv = func.create_global_value(ir::GlobalValueData::Load {
             base: vmctx,
             offset: Offset32::new(0),
             global_type: I32,
+            readonly: false,
         });
 
         func.create_table(ir::TableData {
@@ -2,6 +2,7 @@
 //! traits `FunctionEnvironment` and `ModuleEnvironment`.
 use cranelift_codegen::cursor::FuncCursor;
 use cranelift_codegen::ir::{self, InstBuilder};
+use cranelift_codegen::isa::TargetIsa;
 use cranelift_codegen::settings::Flags;
 use std::vec::Vec;
 use target_lexicon::Triple;
@@ -56,11 +57,44 @@ pub enum WasmError {
     /// An implementation limit was exceeded.
     ///
     /// Cranelift can compile very large and complicated functions, but the [implementation has
-    /// limits][limits] that cause compilation to fail when they are exceeded.
+    /// limits][limits] that cause compilation to fail when they are exceeded. The embedder can set
+    /// tighter limits on the translation in `FuncTranslator::limits`.
     ///
     /// [limits]: https://cranelift.readthedocs.io/en/latest/ir.html#implementation-limits
-    #[fail(display = "Implementation limit exceeded")]
-    ImplLimitExceeded,
+    #[fail(
+        display = "Implementation limit exceeded at offset {}: {} is {}, got {}",
+        offset,
+        limit,
+        max,
+        value
+    )]
+    ImplLimitExceeded {
+        /// The name of the limit, which is a field of `TranslationLimits`.
+        limit: &'static str,
+        /// The value of the limit.
+        max: usize,
+        /// The value that exceeds the limit.
+        value: usize,
+        /// The bytecode offset where the limit was exceeded.
+        offset: usize,
+    },
+
+    /// The WebAssembly code uses a feature that the Cranelift settings disable.
+    ///
+    /// Floating point types and instructions need the `enable_float` setting, and SIMD needs the
+    /// `enable_simd` setting. Modules using disabled features are rejected before any of their
+    /// functions are translated.
+    #[fail(
+        display = "WebAssembly code at offset {} needs the {} setting",
+        offset,
+        setting
+    )]
+    DisabledFeature {
+        /// The name of the setting that disables the feature.
+        setting: &'static str,
+        /// The bytecode offset of the code using the feature.
+        offset: usize,
+    },
 }
 
 impl WasmError {
@@ -95,6 +129,15 @@ pub trait FuncEnvironment {
     /// Get the flags for the current compilation.
     fn flags(&self) -> &Flags;
 
+    /// Get the ISA the function is compiled for, if it is known.
+    ///
+    /// The translator asks the ISA how it supports instructions that have a cheaper lowering on
+    /// some targets, like `popcnt` and the saturating float conversions. Without an ISA, these
+    /// instructions are always emitted as they are.
+    fn target_isa(&self) -> Option<&TargetIsa> {
+        None
+    }
+
     /// Get the Cranelift integer type to use for native pointers.
     ///
     /// This returns `I64` for 64-bit architectures and `I32` for 32-bit architectures.
@@ -116,11 +159,29 @@ pub trait FuncEnvironment {
     /// WebAssembly type of the global.
     fn make_global(&mut self, func: &mut ir::Function, index: GlobalIndex) -> GlobalVariable;
 
+    /// Describe the heap used to access the linear memory identified by `index` in `func`.
+    ///
+    /// The index space covers both imported and locally declared memories.
+    ///
+    /// The environment chooses how the memory is bounds checked, and may make a different choice
+    /// for each memory. A memory backed by a reservation covering the whole 32-bit index space
+    /// plus a guard region can use a `Static` heap style, which needs no explicit bounds checks.
+    /// A memory backed by a smaller reservation should use a `Dynamic` heap style, whose accesses
+    /// are checked against the current size of the memory.
+    ///
+    /// This should declare the global values computing the heap's base address, and the bound
+    /// of a dynamic heap, in `func`. A global value that can change when the memory grows must
+    /// not be marked `readonly`.
+    fn heap_data(&mut self, func: &mut ir::Function, index: MemoryIndex) -> ir::HeapData;
+
     /// Set up the necessary preamble definitions in `func` to access the linear memory identified
     /// by `index`.
     ///
-    /// The index space covers both imported and locally declared memories.
-    fn make_heap(&mut self, func: &mut ir::Function, index: MemoryIndex) -> ir::Heap;
+    /// The default implementation creates a heap described by `heap_data()`.
+    fn make_heap(&mut self, func: &mut ir::Function, index: MemoryIndex) -> ir::Heap {
+        let data = self.heap_data(func, index);
+        func.create_heap(data)
+    }
 
     /// Set up the necessary preamble definitions in `func` to access the table identified
     /// by `index`.
@@ -283,6 +344,9 @@ pub trait ModuleEnvironment<'data> {
         elements: Vec<FuncIndex>,
     );
     /// Declares a memory to the environment
+    ///
+    /// The function environments translating the functions of the module can use the declared
+    /// size of the memory to choose the style of its heap in `FuncEnvironment::heap_data()`.
     fn declare_memory(&mut self, memory: Memory);
     /// Fills a declared memory with bytes at module instantiation.
     fn declare_data_initialization(
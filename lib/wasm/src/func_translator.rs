@@ -21,6 +21,26 @@ use wasmparser::{self, BinaryReader};
 pub struct FuncTranslator {
     func_ctx: FunctionBuilderContext,
     state: TranslationState,
+
+    /// The limits checked when translating a function.
+    ///
+    /// These are unset by default, and apply to all the functions translated with this
+    /// translator.
+    pub limits: TranslationLimits,
+}
+
+/// Limits on the WebAssembly functions translated by a `FuncTranslator`.
+///
+/// A function exceeding a limit fails to translate with an `ImplLimitExceeded` error naming the
+/// limit, before it gets to Cranelift. All the limits are unset by default, and unset limits
+/// aren't checked.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TranslationLimits {
+    /// The largest number of locals in a function, including its parameters.
+    pub max_locals: Option<usize>,
+
+    /// The largest size of the code of a function in bytes, including the local declarations.
+    pub max_body_size: Option<usize>,
 }
 
 impl FuncTranslator {
@@ -29,6 +49,7 @@ impl FuncTranslator {
         Self {
             func_ctx: FunctionBuilderContext::new(),
             state: TranslationState::new(),
+            limits: TranslationLimits::default(),
         }
     }
 
@@ -73,8 +94,15 @@ impl FuncTranslator {
             func.name,
             func.signature
         );
+        check_limit(
+            "max_body_size",
+            self.limits.max_body_size,
+            reader.bytes_remaining(),
+            reader.current_position(),
+        )?;
         debug_assert_eq!(func.dfg.num_ebbs(), 0, "Function must be empty");
         debug_assert_eq!(func.dfg.num_insts(), 0, "Function must be empty");
+        func.pointer_type = Some(environ.pointer_type());
 
         // This clears the `FunctionBuilderContext`.
         let mut builder = FunctionBuilder::new(func, &mut self.func_ctx);
@@ -94,7 +122,7 @@ impl FuncTranslator {
         builder.append_ebb_params_for_function_returns(exit_block);
         self.state.initialize(&builder.func.signature, exit_block);
 
-        parse_local_decls(&mut reader, &mut builder, num_params)?;
+        parse_local_decls(&mut reader, &mut builder, num_params, &self.limits)?;
         parse_function_body(reader, &mut builder, &mut self.state, environ)?;
 
         builder.finalize();
@@ -133,6 +161,7 @@ fn parse_local_decls(
     reader: &mut BinaryReader,
     builder: &mut FunctionBuilder,
     num_params: usize,
+    limits: &TranslationLimits,
 ) -> WasmResult<()> {
     let mut next_local = num_params;
     let local_count = reader
@@ -141,16 +170,41 @@ fn parse_local_decls(
 
     let mut locals_total = 0;
     for _ in 0..local_count {
+        let offset = reader.current_position();
         builder.set_srcloc(cur_srcloc(reader));
         let (count, ty) = reader
             .read_local_decl(&mut locals_total)
             .map_err(WasmError::from_binary_reader_error)?;
+        check_limit(
+            "max_locals",
+            limits.max_locals,
+            next_local + count as usize,
+            offset,
+        )?;
         declare_locals(builder, count, ty, &mut next_local);
     }
 
     Ok(())
 }
 
+/// Check that `value` doesn't exceed the limit `max` named `limit`.
+fn check_limit(
+    limit: &'static str,
+    max: Option<usize>,
+    value: usize,
+    offset: usize,
+) -> WasmResult<()> {
+    match max {
+        Some(max) if value > max => Err(WasmError::ImplLimitExceeded {
+            limit,
+            max,
+            value,
+            offset,
+        }),
+        _ => Ok(()),
+    }
+}
+
 /// Declare `count` local variables of the same type, starting from `next_local`.
 ///
 /// Fail of too many locals are declared in the function, or if the type is not valid for a local.
@@ -236,10 +290,10 @@ fn cur_srcloc(reader: &BinaryReader) -> ir::SourceLoc {
 
 #[cfg(test)]
 mod tests {
-    use super::FuncTranslator;
+    use super::{FuncTranslator, TranslationLimits};
     use cranelift_codegen::ir::types::I32;
     use cranelift_codegen::{ir, Context};
-    use environ::{DummyEnvironment, FuncEnvironment};
+    use environ::{DummyEnvironment, FuncEnvironment, WasmError, WasmResult};
     use target_lexicon::Triple;
 
     #[test]
@@ -341,4 +395,130 @@ mod tests {
         debug!("{}", ctx.func.display(None));
         ctx.verify(runtime.func_env().flags()).unwrap();
     }
+
+    /// Translate `body` and get the branch hints of the `brz` instructions in the function.
+    fn brz_hints(name: &str, body: &[u8]) -> Vec<Option<ir::BranchHint>> {
+        let mut trans = FuncTranslator::new();
+        let runtime = DummyEnvironment::with_triple(Triple::default());
+        let mut ctx = Context::new();
+
+        ctx.func.name = ir::ExternalName::testcase(name);
+        ctx.func.signature.params.push(ir::AbiParam::new(I32));
+
+        trans
+            .translate(body, &mut ctx.func, &mut runtime.func_env())
+            .unwrap();
+        debug!("{}", ctx.func.display(None));
+        ctx.verify(runtime.func_env().flags()).unwrap();
+
+        let func = &ctx.func;
+        func.layout
+            .ebbs()
+            .flat_map(|ebb| func.layout.ebb_insts(ebb))
+            .filter(|&inst| func.dfg[inst].opcode() == ir::Opcode::Brz)
+            .map(|inst| func.branch_hints[inst])
+            .collect()
+    }
+
+    #[test]
+    fn unreachable_hints() {
+        // The branch over a trap is likely taken.
+        //
+        // (func $then_trap (param i32)
+        //     (if (get_local 0) (then unreachable))
+        // )
+        const THEN_TRAP: [u8; 8] = [
+            0x00, // local decl count
+            0x20, 0x00, // get_local 0
+            0x04, 0x40, // if
+            0x00, // unreachable
+            0x0b, // end
+            0x0b, // end
+        ];
+        assert_eq!(
+            brz_hints("then_trap", &THEN_TRAP),
+            [Some(ir::BranchHint::Likely)]
+        );
+
+        // The branch to a trapping `else` clause is unlikely.
+        //
+        // (func $else_trap (param i32)
+        //     (if (get_local 0) (then nop) (else unreachable))
+        // )
+        const ELSE_TRAP: [u8; 10] = [
+            0x00, // local decl count
+            0x20, 0x00, // get_local 0
+            0x04, 0x40, // if
+            0x01, // nop
+            0x05, // else
+            0x00, // unreachable
+            0x0b, // end
+            0x0b, // end
+        ];
+        assert_eq!(
+            brz_hints("else_trap", &ELSE_TRAP),
+            [Some(ir::BranchHint::Unlikely)]
+        );
+    }
+
+    /// Translate `body` with a single `i32` parameter and `limits`.
+    fn translate_with_limits(body: &[u8], limits: TranslationLimits) -> WasmResult<()> {
+        let mut trans = FuncTranslator::new();
+        trans.limits = limits;
+        let runtime = DummyEnvironment::with_triple(Triple::default());
+        let mut func = ir::Function::new();
+        func.signature.params.push(ir::AbiParam::new(I32));
+        trans.translate(body, &mut func, &mut runtime.func_env())
+    }
+
+    #[test]
+    fn locals_limit() {
+        // (func $locals (param i32) (local i32 i32))
+        const BODY: [u8; 4] = [
+            0x01, // local decl count
+            0x02, 0x7f, // 2 i32 locals
+            0x0b, // end
+        ];
+
+        let mut limits = TranslationLimits::default();
+        limits.max_locals = Some(3);
+        assert_eq!(translate_with_limits(&BODY, limits), Ok(()));
+
+        limits.max_locals = Some(2);
+        assert_eq!(
+            translate_with_limits(&BODY, limits),
+            Err(WasmError::ImplLimitExceeded {
+                limit: "max_locals",
+                max: 2,
+                value: 3,
+                offset: 1,
+            })
+        );
+    }
+
+    #[test]
+    fn body_size_limit() {
+        // (func $body (param i32) (drop (get_local 0)))
+        const BODY: [u8; 5] = [
+            0x00, // local decl count
+            0x20, 0x00, // get_local 0
+            0x1a, // drop
+            0x0b, // end
+        ];
+
+        let mut limits = TranslationLimits::default();
+        limits.max_body_size = Some(5);
+        assert_eq!(translate_with_limits(&BODY, limits), Ok(()));
+
+        limits.max_body_size = Some(4);
+        assert_eq!(
+            translate_with_limits(&BODY, limits),
+            Err(WasmError::ImplLimitExceeded {
+                limit: "max_body_size",
+                max: 4,
+                value: 5,
+                offset: 0,
+            })
+        );
+    }
 }
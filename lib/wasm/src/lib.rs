@@ -62,11 +62,11 @@ pub use environ::{
     DummyEnvironment, FuncEnvironment, GlobalVariable, ModuleEnvironment, ReturnMode, WasmError,
     WasmResult,
 };
-pub use func_translator::FuncTranslator;
+pub use func_translator::{FuncTranslator, TranslationLimits};
 pub use module_translator::translate_module;
 pub use translation_utils::{
     DefinedFuncIndex, FuncIndex, Global, GlobalIndex, GlobalInit, Memory, MemoryIndex,
-    SignatureIndex, Table, TableIndex,
+    SignatureIndex, Table, TableIndex, WASM_PAGE_SIZE,
 };
 
 #[cfg(not(feature = "std"))]
@@ -8,18 +8,19 @@
 //! is handled, according to the semantics of WebAssembly, to only specific expressions that are
 //! interpreted on the fly.
 use cranelift_codegen::ir::{self, AbiParam, Signature};
+use cranelift_codegen::settings::Flags;
 use cranelift_entity::EntityRef;
 use environ::{ModuleEnvironment, WasmError, WasmResult};
 use std::str::from_utf8;
 use std::vec::Vec;
 use translation_utils::{
-    type_to_type, FuncIndex, Global, GlobalIndex, GlobalInit, Memory, MemoryIndex, SignatureIndex,
-    Table, TableElementType, TableIndex,
+    check_type_enabled, type_to_type, FuncIndex, Global, GlobalIndex, GlobalInit, Memory,
+    MemoryIndex, SignatureIndex, Table, TableElementType, TableIndex,
 };
 use wasmparser;
 use wasmparser::{
-    ExternalKind, FuncType, ImportSectionEntryType, MemoryType, Operator, Parser, ParserState,
-    WasmDecoder,
+    BinaryReader, ExternalKind, FuncType, ImportSectionEntryType, MemoryType, Operator, Parser,
+    ParserState, WasmDecoder,
 };
 
 /// Reads the Type Section of the wasm module and returns the corresponding function signatures.
@@ -28,6 +29,7 @@ pub fn parse_function_signatures(
     environ: &mut ModuleEnvironment,
 ) -> WasmResult<()> {
     loop {
+        let offset = parser.current_position();
         match *parser.read() {
             ParserState::EndSection => break,
             ParserState::TypeSectionEntry(FuncType {
@@ -35,6 +37,9 @@ pub fn parse_function_signatures(
                 ref params,
                 ref returns,
             }) => {
+                for ty in params.iter().chain(returns.iter()) {
+                    check_type_enabled(environ.flags(), *ty, offset)?;
+                }
                 let mut sig = Signature::new(environ.flags().call_conv());
                 sig.params.extend(params.iter().map(|ty| {
                     let cret_arg: ir::Type = type_to_type(*ty)
@@ -61,6 +66,7 @@ pub fn parse_import_section<'data>(
     environ: &mut ModuleEnvironment<'data>,
 ) -> WasmResult<()> {
     loop {
+        let offset = parser.current_position();
         match *parser.read() {
             ParserState::ImportSectionEntry {
                 ty: ImportSectionEntryType::Function(sig),
@@ -96,6 +102,7 @@ pub fn parse_import_section<'data>(
                 ty: ImportSectionEntryType::Global(ref ty),
                 ..
             } => {
+                check_type_enabled(environ.flags(), ty.content_type, offset)?;
                 environ.declare_global(Global {
                     ty: type_to_type(ty.content_type).unwrap(),
                     mutability: ty.mutable,
@@ -228,6 +235,7 @@ pub fn parse_global_section(
             ParserState::Error(e) => return Err(WasmError::from_binary_reader_error(e)),
             ref s => panic!("unexpected section content: {:?}", s),
         };
+        check_type_enabled(environ.flags(), content_type, parser.current_position())?;
         match *parser.read() {
             ParserState::BeginInitExpressionBody => (),
             ParserState::Error(e) => return Err(WasmError::from_binary_reader_error(e)),
@@ -433,13 +441,138 @@ pub fn parse_code_section<'data>(
             ParserState::Error(e) => return Err(WasmError::from_binary_reader_error(e)),
             ref s => panic!("wrong content in code section: {:?}", s),
         }
+        let offset = parser.current_position();
         let mut reader = parser.create_binary_reader();
         let size = reader.bytes_remaining();
-        environ.define_function_body(
-            reader
-                .read_bytes(size)
-                .map_err(WasmError::from_binary_reader_error)?,
-        )?;
+        let body = reader
+            .read_bytes(size)
+            .map_err(WasmError::from_binary_reader_error)?;
+        if !environ.flags().enable_float() {
+            check_function_body(body, offset, environ.flags())?;
+        }
+        environ.define_function_body(body)?;
+    }
+    Ok(())
+}
+
+/// Check that the settings in `flags` enable the types and operators used by a function body.
+///
+/// The function body starts at the bytecode offset `offset`. This lets a module that uses
+/// disabled features be rejected before any of its functions are translated.
+fn check_function_body(body: &[u8], offset: usize, flags: &Flags) -> WasmResult<()> {
+    let mut reader = BinaryReader::new(body);
+    let local_count = reader
+        .read_local_count()
+        .map_err(WasmError::from_binary_reader_error)?;
+    let mut locals_total = 0;
+    for _ in 0..local_count {
+        let position = offset + reader.current_position();
+        let (_, ty) = reader
+            .read_local_decl(&mut locals_total)
+            .map_err(WasmError::from_binary_reader_error)?;
+        check_type_enabled(flags, ty, position)?;
+    }
+    while !reader.eof() {
+        let position = offset + reader.current_position();
+        let op = reader
+            .read_operator()
+            .map_err(WasmError::from_binary_reader_error)?;
+        match op {
+            Operator::Block { ty } | Operator::Loop { ty } | Operator::If { ty } => {
+                check_type_enabled(flags, ty, position)?
+            }
+            _ => {
+                if uses_float(&op) && !flags.enable_float() {
+                    return Err(WasmError::DisabledFeature {
+                        setting: "enable_float",
+                        offset: position,
+                    });
+                }
+            }
+        }
     }
     Ok(())
 }
+
+/// Does `op` operate on floating point values?
+fn uses_float(op: &Operator) -> bool {
+    match *op {
+        Operator::F32Load { .. }
+        | Operator::F64Load { .. }
+        | Operator::F32Store { .. }
+        | Operator::F64Store { .. }
+        | Operator::F32Const { .. }
+        | Operator::F64Const { .. }
+        | Operator::F32Eq
+        | Operator::F32Ne
+        | Operator::F32Lt
+        | Operator::F32Gt
+        | Operator::F32Le
+        | Operator::F32Ge
+        | Operator::F64Eq
+        | Operator::F64Ne
+        | Operator::F64Lt
+        | Operator::F64Gt
+        | Operator::F64Le
+        | Operator::F64Ge
+        | Operator::F32Abs
+        | Operator::F32Neg
+        | Operator::F32Ceil
+        | Operator::F32Floor
+        | Operator::F32Trunc
+        | Operator::F32Nearest
+        | Operator::F32Sqrt
+        | Operator::F32Add
+        | Operator::F32Sub
+        | Operator::F32Mul
+        | Operator::F32Div
+        | Operator::F32Min
+        | Operator::F32Max
+        | Operator::F32Copysign
+        | Operator::F64Abs
+        | Operator::F64Neg
+        | Operator::F64Ceil
+        | Operator::F64Floor
+        | Operator::F64Trunc
+        | Operator::F64Nearest
+        | Operator::F64Sqrt
+        | Operator::F64Add
+        | Operator::F64Sub
+        | Operator::F64Mul
+        | Operator::F64Div
+        | Operator::F64Min
+        | Operator::F64Max
+        | Operator::F64Copysign
+        | Operator::I32TruncSF32
+        | Operator::I32TruncUF32
+        | Operator::I32TruncSF64
+        | Operator::I32TruncUF64
+        | Operator::I64TruncSF32
+        | Operator::I64TruncUF32
+        | Operator::I64TruncSF64
+        | Operator::I64TruncUF64
+        | Operator::F32ConvertSI32
+        | Operator::F32ConvertUI32
+        | Operator::F32ConvertSI64
+        | Operator::F32ConvertUI64
+        | Operator::F32DemoteF64
+        | Operator::F64ConvertSI32
+        | Operator::F64ConvertUI32
+        | Operator::F64ConvertSI64
+        | Operator::F64ConvertUI64
+        | Operator::F64PromoteF32
+        | Operator::I32ReinterpretF32
+        | Operator::I64ReinterpretF64
+        | Operator::F32ReinterpretI32
+        | Operator::F64ReinterpretI64
+        | Operator::I32TruncSSatF32
+        | Operator::I32TruncUSatF32
+        | Operator::I32TruncSSatF64
+        | Operator::I32TruncUSatF64
+        | Operator::I64TruncSSatF32
+        | Operator::I64TruncUSatF32
+        | Operator::I64TruncSSatF64
+        | Operator::I64TruncUSatF64 => true,
+        _ => false,
+    }
+}
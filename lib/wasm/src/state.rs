@@ -296,6 +296,9 @@ impl TranslationState {
 
     /// Get the `Heap` reference that should be used to access linear memory `index`.
     /// Create the reference if necessary.
+    ///
+    /// The heap checks the alignment of its accesses when the `check_heap_alignment` setting is
+    /// enabled.
     pub fn get_heap<FE: FuncEnvironment + ?Sized>(
         &mut self,
         func: &mut ir::Function,
@@ -303,10 +306,13 @@ impl TranslationState {
         environ: &mut FE,
     ) -> ir::Heap {
         let index = MemoryIndex::new(index as usize);
-        *self
-            .heaps
-            .entry(index)
-            .or_insert_with(|| environ.make_heap(func, index))
+        *self.heaps.entry(index).or_insert_with(|| {
+            let heap = environ.make_heap(func, index);
+            if environ.flags().check_heap_alignment() {
+                func.heaps[heap].check_alignment = true;
+            }
+            heap
+        })
     }
 
     /// Get the `Table` reference that should be used to access table `index`.
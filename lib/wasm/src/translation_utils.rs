@@ -1,5 +1,7 @@
 //! Helper functions and structures for the translation.
 use cranelift_codegen::ir;
+use cranelift_codegen::settings::Flags;
+use environ::{WasmError, WasmResult};
 use std::u32;
 use wasmparser;
 
@@ -33,6 +35,9 @@ entity_impl!(MemoryIndex);
 pub struct SignatureIndex(u32);
 entity_impl!(SignatureIndex);
 
+/// The size of a WebAssembly page, in bytes.
+pub const WASM_PAGE_SIZE: u32 = 0x1_0000;
+
 /// WebAssembly global.
 #[derive(Debug, Clone, Copy)]
 pub struct Global {
@@ -101,6 +106,19 @@ pub fn type_to_type(ty: wasmparser::Type) -> Result<ir::Type, ()> {
     }
 }
 
+/// Check that the settings in `flags` enable the wasmparser type `ty`.
+///
+/// The `offset` is the bytecode offset reported if `ty` is disabled.
+pub fn check_type_enabled(flags: &Flags, ty: wasmparser::Type, offset: usize) -> WasmResult<()> {
+    let setting = type_to_type(ty)
+        .ok()
+        .and_then(|ty| flags.disabling_setting(ty));
+    match setting {
+        Some(setting) => Err(WasmError::DisabledFeature { setting, offset }),
+        None => Ok(()),
+    }
+}
+
 /// Turns a `wasmparser` `f32` into a `Cranelift` one.
 pub fn f32_translation(x: wasmparser::Ieee32) -> ir::immediates::Ieee32 {
     ir::immediates::Ieee32::with_bits(x.bits())
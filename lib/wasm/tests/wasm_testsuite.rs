@@ -4,18 +4,23 @@ extern crate cranelift_wasm;
 extern crate target_lexicon;
 extern crate wabt;
 
+use cranelift_codegen::cursor::{Cursor, FuncCursor};
+use cranelift_codegen::entity::EntityRef;
+use cranelift_codegen::ir::{self, types, InstBuilder};
 use cranelift_codegen::isa;
 use cranelift_codegen::print_errors::pretty_verifier_error;
 use cranelift_codegen::settings::{self, Flags};
-use cranelift_codegen::verifier;
-use cranelift_wasm::{translate_module, DummyEnvironment, ReturnMode};
+use cranelift_codegen::{verifier, Context};
+use cranelift_wasm::{
+    translate_module, DummyEnvironment, FuncEnvironment, MemoryIndex, ReturnMode, WasmError,
+};
 use std::fs;
 use std::fs::File;
 use std::io;
 use std::io::prelude::*;
 use std::path::Path;
 use std::str::FromStr;
-use wabt::wat2wasm;
+use wabt::{wat2wasm, Wat2Wasm};
 
 #[test]
 fn testsuite() {
@@ -49,6 +54,119 @@ fn use_fallthrough_return() {
     );
 }
 
+#[test]
+fn disabled_float() {
+    let mut flag_builder = settings::builder();
+    flag_builder.set("enable_float", "false").unwrap();
+    let flags = Flags::new(flag_builder);
+
+    // Integer-only modules are fine.
+    let ints = wat2wasm("(module (func (param i32) (result i32) (get_local 0)))").unwrap();
+    let mut dummy_environ = DummyEnvironment::with_triple_flags(
+        triple!("riscv64"),
+        flags.clone(),
+        ReturnMode::NormalReturns,
+    );
+    translate_module(&ints, &mut dummy_environ).unwrap();
+    assert_eq!(dummy_environ.info.function_bodies.len(), 1);
+
+    // Floats in signatures, locals, and instructions are rejected before any functions are
+    // translated.
+    for wat in &[
+        "(module (func (param f64)))",
+        "(module (func (local f32)))",
+        "(module (func (drop (f32.const 1.0))))",
+    ] {
+        let wasm = wat2wasm(wat).unwrap();
+        let mut dummy_environ = DummyEnvironment::with_triple_flags(
+            triple!("riscv64"),
+            flags.clone(),
+            ReturnMode::NormalReturns,
+        );
+        match translate_module(&wasm, &mut dummy_environ) {
+            Err(WasmError::DisabledFeature {
+                setting: "enable_float",
+                ..
+            }) => {}
+            result => panic!("unexpected result for {}: {:?}", wat, result),
+        }
+        assert!(dummy_environ.info.function_bodies.is_empty());
+    }
+}
+
+#[test]
+fn heap_styles() {
+    // wabt only accepts a single memory when validating.
+    let wasm = Wat2Wasm::new()
+        .validate(false)
+        .convert("(module (memory 1) (memory 2 4))")
+        .unwrap();
+    let mut dummy_environ = DummyEnvironment::with_triple(triple!("x86_64"));
+    translate_module(wasm.as_ref(), &mut dummy_environ).unwrap();
+    let isa = isa::lookup(dummy_environ.info.triple.clone())
+        .unwrap()
+        .finish(dummy_environ.info.flags.clone());
+
+    // Access each memory in a function, and legalize it.
+    let legalized = |index: usize| {
+        let mut func = ir::Function::new();
+        func.signature.params.push(ir::AbiParam::new(types::I32));
+        func.signature.params.push(ir::AbiParam::special(
+            types::I64,
+            ir::ArgumentPurpose::VMContext,
+        ));
+        let heap = dummy_environ
+            .func_env()
+            .make_heap(&mut func, MemoryIndex::new(index));
+        let ebb = func.dfg.make_ebb();
+        let offset = func.dfg.append_ebb_param(ebb, types::I32);
+        func.dfg.append_ebb_param(ebb, types::I64);
+        {
+            let mut pos = FuncCursor::new(&mut func);
+            pos.insert_ebb(ebb);
+            let addr = pos.ins().heap_addr(types::I64, heap, offset, 1);
+            pos.ins().load(types::I32, ir::MemFlags::new(), addr, 0);
+            pos.ins().return_(&[]);
+        }
+        let heap_decl = func.heaps[heap].to_string();
+        let mut ctx = Context::for_function(func);
+        ctx.flowgraph();
+        ctx.legalize(&*isa).unwrap();
+        let traps = ctx
+            .func
+            .layout
+            .ebbs()
+            .flat_map(|ebb| ctx.func.layout.ebb_insts(ebb))
+            .filter(|&inst| {
+                ctx.func
+                    .dfg
+                    .display_inst(inst, None)
+                    .to_string()
+                    .contains("heap_oob")
+            }).count();
+        (heap_decl, traps)
+    };
+
+    // The memory without a maximum size doesn't need bounds checks.
+    assert_eq!(
+        legalized(0),
+        (
+            "static gv1, min 0x0001_0000, bound 0x0001_0000_0000, guard 0x8000_0000, index_type i32"
+                .to_string(),
+            0
+        )
+    );
+
+    // The memory with a maximum size is checked against its current size.
+    assert_eq!(
+        legalized(1),
+        (
+            "dynamic gv1, min 0x0002_0000, bound gv2, guard 4096, index_type i32".to_string(),
+            1
+        )
+    );
+}
+
 fn read_file(path: &Path) -> io::Result<Vec<u8>> {
     let mut buf: Vec<u8> = Vec::new();
     let mut file = File::open(path)?;
@@ -38,7 +38,7 @@ echo git push
 for crate in \
     entity bforest codegen/meta codegen frontend native \
     reader wasm module \
-    faerie umbrella simplejit
+    faerie umbrella simplejit interpreter c-api
 do
     echo cargo publish --manifest-path "lib/$crate/Cargo.toml"
 done
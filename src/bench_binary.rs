@@ -0,0 +1,84 @@
+//! The `bench-binary` sub-command.
+//!
+//! Read a sequence of Cranelift IR files and compare the cost of round-tripping each function
+//! through the textual IR format and through the binary IR format.
+
+use cranelift_codegen::ir::Function;
+use cranelift_reader::parse_test;
+use std::time::{Duration, Instant};
+use utils::read_to_string;
+use CommandResult;
+
+pub fn run(files: &[String], iterations: u32) -> CommandResult {
+    if iterations == 0 {
+        return Err("The number of iterations must be positive".to_owned());
+    }
+    for f in files {
+        bench_one(f, iterations)?
+    }
+    Ok(())
+}
+
+fn bench_one(filename: &str, iterations: u32) -> CommandResult {
+    let buffer = read_to_string(&filename).map_err(|e| format!("{}: {}", filename, e))?;
+    let test_file = parse_test(&buffer, None, None).map_err(|e| format!("{}: {}", filename, e))?;
+    let funcs: Vec<Function> = test_file.functions.into_iter().map(|(f, _)| f).collect();
+
+    let mut binary = Vec::new();
+    for func in &funcs {
+        func.write_binary(&mut binary);
+    }
+
+    let text_write = time(iterations, || {
+        for func in &funcs {
+            func.to_string();
+        }
+    });
+    let text_read = time(iterations, || {
+        parse_test(&buffer, None, None).expect("reparsing the test file");
+    });
+    let binary_write = time(iterations, || {
+        let mut out = Vec::new();
+        for func in &funcs {
+            func.write_binary(&mut out);
+        }
+    });
+
+    // The binary format doesn't delimit functions, so decode from one buffer per function.
+    let buffers: Vec<Vec<u8>> = funcs
+        .iter()
+        .map(|func| {
+            let mut out = Vec::new();
+            func.write_binary(&mut out);
+            out
+        }).collect();
+    let binary_read = time(iterations, || {
+        for buf in &buffers {
+            Function::read_binary(buf).expect("decoding binary functions");
+        }
+    });
+
+    println!("{}: {} functions", filename, funcs.len());
+    println!(
+        "  text:   {:>8} bytes, write {:>10?}, read {:>10?}",
+        buffer.len(),
+        text_write,
+        text_read
+    );
+    println!(
+        "  binary: {:>8} bytes, write {:>10?}, read {:>10?}",
+        binary.len(),
+        binary_write,
+        binary_read
+    );
+    Ok(())
+}
+
+/// Run `f` `iterations` times and return the average duration of one run.
+fn time<F: FnMut()>(iterations: u32, mut f: F) -> Duration {
+    let start = Instant::now();
+    for _ in 0..iterations {
+        f();
+    }
+    start.elapsed() / iterations
+}
@@ -46,6 +46,7 @@ use std::io::{self, Write};
 use std::option::Option;
 use std::process;
 
+mod bench_binary;
 mod cat;
 mod compile;
 mod print_cfg;
@@ -87,6 +88,12 @@ fn add_time_flag<'a>() -> clap::Arg<'a, 'a> {
         .help("Print pass timing report for test")
 }
 
+fn add_stats_flag<'a>() -> clap::Arg<'a, 'a> {
+    Arg::with_name("print-stats")
+        .short("s")
+        .help("Print code generation statistics for the functions compiled by test compile")
+}
+
 fn add_set_flag<'a>() -> clap::Arg<'a, 'a> {
     Arg::with_name("set")
         .long("set")
@@ -160,6 +167,7 @@ fn main() {
                 .about("Run Cranelift tests")
                 .arg(add_verbose_flag())
                 .arg(add_time_flag())
+                .arg(add_stats_flag())
                 .arg(add_input_file_arg())
                 .arg(add_debug_flag()),
         ).subcommand(
@@ -167,6 +175,17 @@ fn main() {
                 .about("Outputs .clif file")
                 .arg(add_input_file_arg())
                 .arg(add_debug_flag()),
+        ).subcommand(
+            SubCommand::with_name("bench-binary")
+                .about("Compares text and binary IR round-trip times")
+                .arg(
+                    Arg::with_name("iterations")
+                        .short("n")
+                        .takes_value(true)
+                        .default_value("100")
+                        .help("Number of round trips to average over"),
+                ).arg(add_input_file_arg())
+                .arg(add_debug_flag()),
         ).subcommand(
             SubCommand::with_name("print-cfg")
                 .about("Prints out cfg in dot format")
@@ -197,11 +216,21 @@ fn main() {
             handle_debug_flag(rest_cmd.is_present("debug"));
             cat::run(&get_vec(rest_cmd.values_of("file")))
         }
+        ("bench-binary", Some(rest_cmd)) => {
+            handle_debug_flag(rest_cmd.is_present("debug"));
+            match rest_cmd.value_of("iterations").unwrap().parse() {
+                Ok(iterations) => {
+                    bench_binary::run(&get_vec(rest_cmd.values_of("file")), iterations)
+                }
+                Err(_) => Err("Invalid number of iterations".to_owned()),
+            }
+        }
         ("test", Some(rest_cmd)) => {
             handle_debug_flag(rest_cmd.is_present("debug"));
             cranelift_filetests::run(
                 rest_cmd.is_present("verbose"),
                 rest_cmd.is_present("time-passes"),
+                rest_cmd.is_present("print-stats"),
                 &get_vec(rest_cmd.values_of("file")),
             ).map(|_time| ())
         }
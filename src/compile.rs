@@ -51,9 +51,19 @@ struct PrintTraps {
 }
 
 impl binemit::TrapSink for PrintTraps {
-    fn trap(&mut self, offset: binemit::CodeOffset, _srcloc: ir::SourceLoc, code: ir::TrapCode) {
+    fn trap(
+        &mut self,
+        offset: binemit::CodeOffset,
+        _srcloc: ir::SourceLoc,
+        code: ir::TrapCode,
+        payload: u32,
+    ) {
         if self.flag_print {
-            println!("trap: {} at {}", code, offset);
+            if payload != 0 {
+                println!("trap: {} with payload {} at {}", code, payload, offset);
+            } else {
+                println!("trap: {} at {}", code, offset);
+            }
         }
     }
 }
@@ -106,20 +116,12 @@ fn handle_module(
         context.func = func;
 
         // Compile and encode the result to machine code.
-        let total_size = context
-            .compile(isa)
-            .map_err(|err| pretty_error(&context.func, Some(isa), err))?;
-
         let mut mem = Vec::new();
-        mem.resize(total_size as usize, 0);
-
         let mut relocs = PrintRelocs { flag_print };
         let mut traps = PrintTraps { flag_print };
-        let mut code_sink: binemit::MemoryCodeSink;
-        unsafe {
-            code_sink = binemit::MemoryCodeSink::new(mem.as_mut_ptr(), &mut relocs, &mut traps);
-        }
-        isa.emit_function_to_memory(&context.func, &mut code_sink);
+        let code_info = context
+            .compile_and_emit(isa, &mut mem, &mut relocs, &mut traps)
+            .map_err(|err| pretty_error(&context.func, Some(isa), err))?;
 
         if flag_print {
             println!("{}", context.func.display(isa));
@@ -138,8 +140,10 @@ fn handle_module(
             }
 
             println!();
-            print_disassembly(isa, &mem[0..code_sink.code_size as usize])?;
-            print_readonly_data(&mem[code_sink.code_size as usize..total_size as usize]);
+            print_disassembly(isa, &mem[0..code_info.code_size as usize])?;
+            print_readonly_data(
+                &mem[code_info.jumptables() as usize..code_info.total_size as usize],
+            );
         }
     }
 
@@ -173,7 +173,8 @@ fn handle_module(
         } else {
             let compiled_size = context
                 .compile(isa)
-                .map_err(|err| pretty_error(&context.func, fisa.isa, err))?;
+                .map_err(|err| pretty_error(&context.func, fisa.isa, err))?
+                .total_size;
             if flag_print_size {
                 println!(
                     "Function #{} code size: {} bytes",
@@ -3,6 +3,6 @@ extern crate cranelift_filetests;
 #[test]
 fn filetests() {
     // Run all the filetests in the following directories.
-    cranelift_filetests::run(false, false, &["filetests".into(), "docs".into()])
+    cranelift_filetests::run(false, false, false, &["filetests".into(), "docs".into()])
         .expect("test harness");
 }